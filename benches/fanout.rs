@@ -0,0 +1,64 @@
+//! Fan-out of one PUBLISH to many subscribers
+//!
+//! Compares framing a message for each subscriber by encoding the whole
+//! packet (payload copied into every frame) with encoding only the header
+//! and sharing the payload `Bytes`, as the connections do for payloads of
+//! at least 1 KiB on streams with vectored writes.
+//!
+//! Run with `cargo bench --bench fanout`.
+
+use std::hint::black_box;
+
+use bytes::{Bytes, BytesMut};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use vibemq::codec::Encoder;
+use vibemq::protocol::{Packet, Properties, ProtocolVersion, Publish, QoS};
+
+const SUBSCRIBERS: usize = 10_000;
+
+fn fanout(c: &mut Criterion) {
+    let encoder = Encoder::new(ProtocolVersion::V5);
+    let mut group = c.benchmark_group("fanout_10k");
+    group.throughput(Throughput::Elements(SUBSCRIBERS as u64));
+
+    for size in [64usize, 4 * 1024, 64 * 1024] {
+        let publish = Publish {
+            dup: false,
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            topic: "sensors/plant-1/line-4/vibration".to_string(),
+            packet_id: Some(1),
+            payload: Bytes::from(vec![0x5a; size]),
+            properties: Properties::default(),
+        };
+
+        group.bench_with_input(BenchmarkId::new("copy", size), &publish, |b, publish| {
+            let mut buf = BytesMut::with_capacity(size + 128);
+            b.iter(|| {
+                for _ in 0..SUBSCRIBERS {
+                    let packet = Packet::Publish(publish.clone());
+                    buf.clear();
+                    encoder.encode(&packet, &mut buf).unwrap();
+                    black_box(&buf);
+                }
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("shared", size), &publish, |b, publish| {
+            let mut buf = BytesMut::with_capacity(128);
+            b.iter(|| {
+                for _ in 0..SUBSCRIBERS {
+                    let publish = publish.clone();
+                    buf.clear();
+                    let len = encoder.encode_publish_header(&publish, &mut buf).unwrap();
+                    black_box((&buf, &publish.payload, len));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, fanout);
+criterion_main!(benches);
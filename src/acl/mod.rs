@@ -4,22 +4,43 @@
 //! - MQTT wildcards (# and +)
 //! - Variable substitution (%c = client_id, %u = username)
 //! - Role-based permissions
+//! - Per-topic limits on message expiry and retained lifetime
+//! - Per-role QoS caps
+//! - Per-role session settings (queue depth, session expiry, keep-alive,
+//!   quota), for service tiers
+//! - Per-topic required MQTT 5 user properties on published messages
+//!
+//! Checks can be dry-run with `Hooks::explain_access`, which names the
+//! pattern (and its role) that allowed the access.
+//!
+//! Rules can be replaced at runtime with `AclProvider::reload`; existing
+//! subscriptions are re-checked by `Broker::reevaluate_subscriptions`.
 
 use std::collections::HashMap;
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use parking_lot::RwLock;
 
 use crate::auth::AuthProvider;
-use crate::config::AclConfig;
-use crate::hooks::{HookResult, Hooks};
-use crate::protocol::QoS;
+use crate::config::{AclConfig, AclPropertyRule, AclTtlRule, SessionPolicy};
+use crate::hooks::{AccessAction, AccessRequest, AccessStep, HookResult, Hooks, PublishTtl};
+use crate::protocol::{Publish, QoS};
+use crate::user_properties::PropertyIndex;
 
 #[cfg(test)]
 mod tests;
 
 /// ACL provider
 pub struct AclProvider {
+    /// Current rules (replaced on reload)
+    rules: RwLock<AclRules>,
+    /// Reference to auth provider for username lookups
+    auth_provider: Arc<AuthProvider>,
+}
+
+/// ACL rules compiled from configuration
+struct AclRules {
     /// Whether ACL is enabled
     enabled: bool,
     /// Role definitions (name -> role)
@@ -27,8 +48,10 @@ pub struct AclProvider {
     /// Default permissions for users without explicit role (including anonymous)
     default_publish: Vec<String>,
     default_subscribe: Vec<String>,
-    /// Reference to auth provider for username lookups
-    auth_provider: Arc<AuthProvider>,
+    default_ttl: Vec<AclTtlRule>,
+    default_publish_properties: Vec<AclPropertyRule>,
+    default_max_qos: Option<QoS>,
+    default_session: SessionPolicy,
 }
 
 /// Internal role entry with compiled patterns
@@ -37,11 +60,18 @@ struct AclRoleEntry {
     publish: Vec<String>,
     /// Subscribe patterns
     subscribe: Vec<String>,
+    /// Message lifetime limits
+    ttl: Vec<AclTtlRule>,
+    /// Required user properties
+    publish_properties: Vec<AclPropertyRule>,
+    /// QoS cap
+    max_qos: Option<QoS>,
+    /// Session settings
+    session: SessionPolicy,
 }
 
-impl AclProvider {
-    /// Create a new ACL provider from configuration
-    pub fn new(config: &AclConfig, auth_provider: Arc<AuthProvider>) -> Self {
+impl AclRules {
+    fn new(config: &AclConfig) -> Self {
         let mut roles = HashMap::new();
 
         for role in &config.roles {
@@ -50,6 +80,10 @@ impl AclProvider {
                 AclRoleEntry {
                     publish: role.publish.clone(),
                     subscribe: role.subscribe.clone(),
+                    ttl: role.ttl.clone(),
+                    publish_properties: role.publish_properties.clone(),
+                    max_qos: role.max_qos.and_then(QoS::from_u8),
+                    session: role.session,
                 },
             );
         }
@@ -59,13 +93,35 @@ impl AclProvider {
             roles,
             default_publish: config.default.publish.clone(),
             default_subscribe: config.default.subscribe.clone(),
+            default_ttl: config.default.ttl.clone(),
+            default_publish_properties: config.default.publish_properties.clone(),
+            default_max_qos: config.default.max_qos.and_then(QoS::from_u8),
+            default_session: config.default.session,
+        }
+    }
+}
+
+impl AclProvider {
+    /// Create a new ACL provider from configuration
+    pub fn new(config: &AclConfig, auth_provider: Arc<AuthProvider>) -> Self {
+        Self {
+            rules: RwLock::new(AclRules::new(config)),
             auth_provider,
         }
     }
 
+    /// Replace the rules with a new configuration
+    ///
+    /// Only later checks are affected; call
+    /// `Broker::reevaluate_subscriptions` to apply the new rules to
+    /// existing subscriptions.
+    pub fn reload(&self, config: &AclConfig) {
+        *self.rules.write() = AclRules::new(config);
+    }
+
     /// Check if ACL is enabled
     pub fn is_enabled(&self) -> bool {
-        self.enabled
+        self.rules.read().enabled
     }
 
     /// Check if topic matches pattern with variable substitution
@@ -123,21 +179,118 @@ impl AclProvider {
         client_id: &str,
         username: Option<&str>,
     ) -> bool {
+        Self::find_pattern(patterns, topic, client_id, username).is_some()
+    }
+
+    /// First pattern in the list that matches the topic
+    fn find_pattern<'a>(
+        patterns: &'a [String],
+        topic: &str,
+        client_id: &str,
+        username: Option<&str>,
+    ) -> Option<&'a String> {
         patterns
             .iter()
-            .any(|p| Self::matches_pattern(p, topic, client_id, username))
+            .find(|p| Self::matches_pattern(p, topic, client_id, username))
+    }
+
+    /// First TTL rule whose pattern matches the topic
+    fn find_ttl_rule<'a>(
+        rules: &'a [AclTtlRule],
+        topic: &str,
+        client_id: &str,
+        username: Option<&str>,
+    ) -> Option<&'a AclTtlRule> {
+        rules
+            .iter()
+            .find(|rule| Self::matches_pattern(&rule.topic, topic, client_id, username))
+    }
+
+    /// First required-properties rule whose pattern matches the topic
+    fn find_property_rule<'a>(
+        rules: &'a [AclPropertyRule],
+        topic: &str,
+        client_id: &str,
+        username: Option<&str>,
+    ) -> Option<&'a AclPropertyRule> {
+        rules
+            .iter()
+            .find(|rule| Self::matches_pattern(&rule.topic, topic, client_id, username))
     }
 
     /// Get role permissions for a username
-    fn get_role_permissions(&self, username: Option<&str>) -> Option<&AclRoleEntry> {
+    fn get_role_permissions<'a>(
+        &self,
+        rules: &'a AclRules,
+        username: Option<&str>,
+    ) -> Option<&'a AclRoleEntry> {
         let username = username?;
         let role_name = self.auth_provider.get_user_role(username)?;
-        self.roles.get(role_name)
+        rules.roles.get(&role_name)
     }
 }
 
 #[async_trait]
 impl Hooks for AclProvider {
+    fn hook_name(&self) -> String {
+        "acl".to_string()
+    }
+
+    /// Same decision as `on_publish_check` / `on_subscribe_check`, with
+    /// the pattern that allowed it, or what was searched if nothing did
+    async fn explain_access(&self, request: &AccessRequest<'_>) -> Vec<AccessStep> {
+        let rules = self.rules.read();
+        if !rules.enabled {
+            let rule = "ACL disabled".to_string();
+            return vec![AccessStep::new(self.hook_name(), Ok(true), Some(rule))];
+        }
+
+        let client_id = request.client_id;
+        let actual_username = self.auth_provider.get_client_username(client_id);
+        let username_ref = actual_username.as_deref().or(request.username);
+        let (action, default_patterns) = match request.action {
+            AccessAction::Publish => ("publish", &rules.default_publish),
+            AccessAction::Subscribe => ("subscribe", &rules.default_subscribe),
+        };
+
+        // Role first, then the defaults, as in the checks
+        let role_name = username_ref
+            .and_then(|u| self.auth_provider.get_user_role(u))
+            .filter(|name| rules.roles.contains_key(name));
+        let role_patterns = role_name
+            .as_ref()
+            .and_then(|name| rules.roles.get(name))
+            .map(|role| match request.action {
+                AccessAction::Publish => &role.publish,
+                AccessAction::Subscribe => &role.subscribe,
+            });
+        let find = |patterns: &'_ Vec<String>| {
+            Self::find_pattern(patterns, request.topic, client_id, username_ref).cloned()
+        };
+        let matched = role_patterns
+            .and_then(find)
+            .map(|pattern| {
+                let role = role_name.as_deref().unwrap_or_default();
+                format!("role {:?}: {} {:?}", role, action, pattern)
+            })
+            .or_else(|| {
+                find(default_patterns).map(|pattern| format!("default: {} {:?}", action, pattern))
+            });
+
+        let step = match matched {
+            Some(rule) => AccessStep::new(self.hook_name(), Ok(true), Some(rule)),
+            None => {
+                let searched = match role_name {
+                    Some(ref name) => format!("role {:?} or the defaults", name),
+                    None => "the defaults".to_string(),
+                };
+                let rule = format!("no {} pattern of {} matches", action, searched);
+                AccessStep::new(self.hook_name(), Ok(false), Some(rule))
+            }
+        };
+        vec![step]
+    }
+
     async fn on_publish_check(
         &self,
         client_id: &str,
@@ -146,8 +299,10 @@ impl Hooks for AclProvider {
         _qos: QoS,
         _retain: bool,
     ) -> HookResult<bool> {
+        let rules = self.rules.read();
+
         // If ACL is disabled, allow all
-        if !self.enabled {
+        if !rules.enabled {
             return Ok(true);
         }
 
@@ -156,14 +311,14 @@ impl Hooks for AclProvider {
         let username_ref = actual_username.as_deref().or(username);
 
         // Check role-based permissions first
-        if let Some(role) = self.get_role_permissions(username_ref) {
+        if let Some(role) = self.get_role_permissions(&rules, username_ref) {
             if Self::check_patterns(&role.publish, topic, client_id, username_ref) {
                 return Ok(true);
             }
         }
 
         // Check default permissions (applies to all users without a role, including anonymous)
-        if Self::check_patterns(&self.default_publish, topic, client_id, username_ref) {
+        if Self::check_patterns(&rules.default_publish, topic, client_id, username_ref) {
             return Ok(true);
         }
 
@@ -171,6 +326,45 @@ impl Hooks for AclProvider {
         Ok(false)
     }
 
+    /// Enforce required user properties; the topic was already allowed by
+    /// `on_publish_check`
+    async fn on_publish(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        publish: &mut Publish,
+    ) -> HookResult<bool> {
+        let rules = self.rules.read();
+
+        if !rules.enabled {
+            return Ok(true);
+        }
+
+        let actual_username = self.auth_provider.get_client_username(client_id);
+        let username_ref = actual_username.as_deref().or(username);
+
+        // A role's rules take precedence over the defaults
+        let topic = &publish.topic;
+        let rule = self
+            .get_role_permissions(&rules, username_ref)
+            .and_then(|role| {
+                Self::find_property_rule(&role.publish_properties, topic, client_id, username_ref)
+            })
+            .or_else(|| {
+                Self::find_property_rule(
+                    &rules.default_publish_properties,
+                    topic,
+                    client_id,
+                    username_ref,
+                )
+            });
+
+        Ok(rule.is_none_or(|rule| {
+            PropertyIndex::new(&publish.properties.user_properties)
+                .matches_all(&rule.user_properties)
+        }))
+    }
+
     async fn on_subscribe_check(
         &self,
         client_id: &str,
@@ -178,8 +372,10 @@ impl Hooks for AclProvider {
         filter: &str,
         _qos: QoS,
     ) -> HookResult<bool> {
+        let rules = self.rules.read();
+
         // If ACL is disabled, allow all
-        if !self.enabled {
+        if !rules.enabled {
             return Ok(true);
         }
 
@@ -188,20 +384,88 @@ impl Hooks for AclProvider {
         let username_ref = actual_username.as_deref().or(username);
 
         // Check role-based permissions first
-        if let Some(role) = self.get_role_permissions(username_ref) {
+        if let Some(role) = self.get_role_permissions(&rules, username_ref) {
             if Self::check_patterns(&role.subscribe, filter, client_id, username_ref) {
                 return Ok(true);
             }
         }
 
         // Check default permissions (applies to all users without a role, including anonymous)
-        if Self::check_patterns(&self.default_subscribe, filter, client_id, username_ref) {
+        if Self::check_patterns(&rules.default_subscribe, filter, client_id, username_ref) {
             return Ok(true);
         }
 
         // Deny by default
         Ok(false)
     }
+
+    async fn on_publish_ttl(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        topic: &str,
+    ) -> HookResult<PublishTtl> {
+        let rules = self.rules.read();
+
+        if !rules.enabled {
+            return Ok(PublishTtl::default());
+        }
+
+        let actual_username = self.auth_provider.get_client_username(client_id);
+        let username_ref = actual_username.as_deref().or(username);
+
+        // A role's rules take precedence over the defaults
+        let rule = self
+            .get_role_permissions(&rules, username_ref)
+            .and_then(|role| Self::find_ttl_rule(&role.ttl, topic, client_id, username_ref))
+            .or_else(|| Self::find_ttl_rule(&rules.default_ttl, topic, client_id, username_ref));
+
+        Ok(rule
+            .map(|rule| PublishTtl {
+                max_message_expiry: rule.max_message_expiry,
+                max_retained_lifetime: rule.max_retained_lifetime,
+            })
+            .unwrap_or_default())
+    }
+
+    async fn on_max_qos(&self, client_id: &str, username: Option<&str>) -> HookResult<Option<QoS>> {
+        let rules = self.rules.read();
+
+        if !rules.enabled {
+            return Ok(None);
+        }
+
+        let actual_username = self.auth_provider.get_client_username(client_id);
+        let username_ref = actual_username.as_deref().or(username);
+
+        // A role's cap takes precedence over the default
+        Ok(self
+            .get_role_permissions(&rules, username_ref)
+            .and_then(|role| role.max_qos)
+            .or(rules.default_max_qos))
+    }
+
+    async fn on_session_policy(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+    ) -> HookResult<SessionPolicy> {
+        let rules = self.rules.read();
+
+        if !rules.enabled {
+            return Ok(SessionPolicy::default());
+        }
+
+        let actual_username = self.auth_provider.get_client_username(client_id);
+        let username_ref = actual_username.as_deref().or(username);
+
+        // A role's settings are layered over the defaults
+        let policy = rules.default_session;
+        Ok(match self.get_role_permissions(&rules, username_ref) {
+            Some(role) => policy.merge(&role.session),
+            None => policy,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -1,8 +1,13 @@
 //! ACL module tests
 
 use super::*;
-use crate::config::{AclConfig, AclPermissions, AclRole, AuthConfig, UserConfig};
+use crate::config::{
+    AclConfig, AclPermissions, AclPropertyRule, AclRole, AclTtlRule, AuthConfig, QuotaLimits,
+    UserConfig, UserPropertyMatch,
+};
+use crate::hooks::AccessDecision;
 use std::sync::Arc;
+use std::time::Duration;
 
 fn make_test_auth_provider() -> Arc<AuthProvider> {
     let auth_config = AuthConfig {
@@ -14,20 +19,24 @@ fn make_test_auth_provider() -> Arc<AuthProvider> {
                 password: Some("admin_pass".to_string()),
                 password_hash: None,
                 role: Some("admin".to_string()),
+                tenant: None,
             },
             UserConfig {
                 username: "sensor".to_string(),
                 password: Some("sensor_pass".to_string()),
                 password_hash: None,
                 role: Some("device".to_string()),
+                tenant: None,
             },
             UserConfig {
                 username: "readonly".to_string(),
                 password: Some("readonly_pass".to_string()),
                 password_hash: None,
                 role: Some("reader".to_string()),
+                tenant: None,
             },
         ],
+        ..Default::default()
     };
     Arc::new(AuthProvider::new(&auth_config))
 }
@@ -40,22 +49,39 @@ fn make_test_acl_config() -> AclConfig {
                 name: "admin".to_string(),
                 publish: vec!["#".to_string()],
                 subscribe: vec!["#".to_string()],
+                ttl: vec![],
+                publish_properties: vec![],
+                max_qos: None,
+                session: Default::default(),
             },
             AclRole {
                 name: "device".to_string(),
                 publish: vec!["sensors/%c/#".to_string()],
                 subscribe: vec!["commands/%c/#".to_string()],
+                ttl: vec![],
+                publish_properties: vec![],
+                max_qos: None,
+                session: Default::default(),
             },
             AclRole {
                 name: "reader".to_string(),
                 publish: vec![],
                 subscribe: vec!["sensors/#".to_string()],
+                ttl: vec![],
+                publish_properties: vec![],
+                max_qos: None,
+                session: Default::default(),
             },
         ],
         default: AclPermissions {
             publish: vec![],
             subscribe: vec!["$SYS/broker/+".to_string()],
+            ttl: vec![],
+            publish_properties: vec![],
+            max_qos: None,
+            session: Default::default(),
         },
+        ..Default::default()
     }
 }
 
@@ -66,6 +92,7 @@ async fn test_acl_disabled_allows_all() {
         enabled: false,
         roles: vec![],
         default: AclPermissions::default(),
+        ..Default::default()
     };
     let provider = AclProvider::new(&acl_config, auth_provider);
 
@@ -82,6 +109,61 @@ async fn test_acl_disabled_allows_all() {
     assert!(result, "Should allow when ACL is disabled");
 }
 
+#[tokio::test]
+async fn test_explain_access() {
+    let auth_provider = make_test_auth_provider();
+    auth_provider
+        .on_authenticate("sensor1", Some("sensor"), Some(b"sensor_pass"))
+        .await
+        .unwrap();
+    let provider = AclProvider::new(&make_test_acl_config(), auth_provider);
+    let request = AccessRequest {
+        client_id: "sensor1",
+        username: Some("sensor"),
+        action: AccessAction::Publish,
+        topic: "sensors/sensor1/temp",
+        qos: QoS::AtMostOnce,
+        retain: false,
+    };
+
+    let steps = provider.explain_access(&request).await;
+    assert_eq!(steps.len(), 1);
+    assert_eq!(steps[0].hook, "acl");
+    assert_eq!(steps[0].decision, AccessDecision::Allow);
+    assert_eq!(
+        steps[0].rule.as_deref(),
+        Some(r#"role "device": publish "sensors/%c/#""#)
+    );
+
+    let steps = provider
+        .explain_access(&AccessRequest {
+            topic: "sensors/other/temp",
+            ..request
+        })
+        .await;
+    assert_eq!(steps[0].decision, AccessDecision::Deny);
+    assert_eq!(
+        steps[0].rule.as_deref(),
+        Some(r#"no publish pattern of role "device" or the defaults matches"#)
+    );
+
+    // Anonymous clients fall back to the defaults
+    let steps = provider
+        .explain_access(&AccessRequest {
+            client_id: "anon",
+            username: None,
+            action: AccessAction::Subscribe,
+            topic: "$SYS/broker/uptime",
+            ..request
+        })
+        .await;
+    assert_eq!(steps[0].decision, AccessDecision::Allow);
+    assert_eq!(
+        steps[0].rule.as_deref(),
+        Some(r#"default: subscribe "$SYS/broker/+""#)
+    );
+}
+
 #[tokio::test]
 async fn test_admin_can_publish_anywhere() {
     let auth_provider = make_test_auth_provider();
@@ -207,6 +289,26 @@ async fn test_readonly_can_subscribe_to_sensors() {
     assert!(!result, "Readonly user should NOT subscribe to commands");
 }
 
+#[tokio::test]
+async fn test_reload_replaces_rules() {
+    let provider = AclProvider::new(&make_test_acl_config(), make_test_auth_provider());
+    assert!(provider
+        .on_subscribe_check("reader1", Some("readonly"), "sensors/temp", QoS::AtMostOnce)
+        .await
+        .unwrap());
+
+    let mut config = make_test_acl_config();
+    config.roles.retain(|role| role.name != "reader");
+    provider.reload(&config);
+    assert!(!provider
+        .on_subscribe_check("reader1", Some("readonly"), "sensors/temp", QoS::AtMostOnce)
+        .await
+        .unwrap());
+
+    provider.reload(&AclConfig::default());
+    assert!(!provider.is_enabled());
+}
+
 #[test]
 fn test_pattern_matching() {
     // Exact match
@@ -246,3 +348,161 @@ fn test_variable_substitution() {
         Some("admin")
     ));
 }
+
+#[tokio::test]
+async fn test_publish_ttl_role_before_default() {
+    let auth_provider = make_test_auth_provider();
+    auth_provider
+        .on_authenticate("sensor1", Some("sensor"), Some(b"sensor_pass"))
+        .await
+        .unwrap();
+
+    let mut acl_config = make_test_acl_config();
+    acl_config.roles[1].ttl = vec![AclTtlRule {
+        topic: "sensors/%c/#".to_string(),
+        max_message_expiry: Some(Duration::from_secs(60)),
+        max_retained_lifetime: Some(Duration::from_secs(3600)),
+    }];
+    acl_config.default.ttl = vec![AclTtlRule {
+        topic: "#".to_string(),
+        max_message_expiry: Some(Duration::from_secs(600)),
+        max_retained_lifetime: None,
+    }];
+    let provider = AclProvider::new(&acl_config, auth_provider);
+
+    let ttl = provider
+        .on_publish_ttl("sensor1", Some("sensor"), "sensors/sensor1/temp")
+        .await
+        .unwrap();
+    assert_eq!(ttl.max_message_expiry, Some(Duration::from_secs(60)));
+    assert_eq!(ttl.max_retained_lifetime, Some(Duration::from_secs(3600)));
+
+    // No role rule matches, so the default applies
+    let ttl = provider
+        .on_publish_ttl("sensor1", Some("sensor"), "sensors/other/temp")
+        .await
+        .unwrap();
+    assert_eq!(ttl.max_message_expiry, Some(Duration::from_secs(600)));
+    assert_eq!(ttl.max_retained_lifetime, None);
+}
+
+#[tokio::test]
+async fn test_publish_properties_role_before_default() {
+    let auth_provider = make_test_auth_provider();
+    auth_provider
+        .on_authenticate("sensor1", Some("sensor"), Some(b"sensor_pass"))
+        .await
+        .unwrap();
+
+    let mut acl_config = make_test_acl_config();
+    acl_config.roles[1].publish_properties = vec![AclPropertyRule {
+        topic: "sensors/%c/#".to_string(),
+        user_properties: vec![
+            UserPropertyMatch::exact("env", "prod"),
+            UserPropertyMatch::prefix("fw", "2."),
+        ],
+    }];
+    acl_config.default.publish_properties = vec![AclPropertyRule {
+        topic: "#".to_string(),
+        user_properties: vec![UserPropertyMatch::exact("env", "dev")],
+    }];
+    let provider = AclProvider::new(&acl_config, auth_provider);
+
+    let publish = |topic: &str, properties: &[(&str, &str)]| {
+        let mut publish = Publish {
+            topic: topic.to_string(),
+            ..Default::default()
+        };
+        publish.properties.user_properties = properties
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect();
+        let provider = &provider;
+        async move {
+            provider
+                .on_publish("sensor1", Some("sensor"), &mut publish)
+                .await
+                .unwrap()
+        }
+    };
+    assert!(publish("sensors/sensor1/t", &[("env", "prod"), ("fw", "2.1")]).await);
+    assert!(!publish("sensors/sensor1/t", &[("env", "prod"), ("fw", "1.9")]).await);
+    assert!(!publish("sensors/sensor1/t", &[]).await);
+
+    // No role rule matches, so the default applies
+    assert!(publish("sensors/other/t", &[("env", "dev")]).await);
+    assert!(!publish("sensors/other/t", &[("env", "prod")]).await);
+}
+
+#[tokio::test]
+async fn test_max_qos_role_before_default() {
+    let auth_provider = make_test_auth_provider();
+    auth_provider
+        .on_authenticate("sensor1", Some("sensor"), Some(b"sensor_pass"))
+        .await
+        .unwrap();
+    auth_provider
+        .on_authenticate("admin_client", Some("admin"), Some(b"admin_pass"))
+        .await
+        .unwrap();
+
+    let mut acl_config = make_test_acl_config();
+    acl_config.roles[1].max_qos = Some(0);
+    acl_config.default.max_qos = Some(1);
+    let provider = AclProvider::new(&acl_config, auth_provider);
+
+    let cap = provider
+        .on_max_qos("sensor1", Some("sensor"))
+        .await
+        .unwrap();
+    assert_eq!(cap, Some(QoS::AtMostOnce));
+    let cap = provider
+        .on_max_qos("admin_client", Some("admin"))
+        .await
+        .unwrap();
+    assert_eq!(cap, Some(QoS::AtLeastOnce));
+}
+
+#[tokio::test]
+async fn test_session_policy_role_over_default() {
+    let auth_provider = make_test_auth_provider();
+    auth_provider
+        .on_authenticate("sensor1", Some("sensor"), Some(b"sensor_pass"))
+        .await
+        .unwrap();
+    auth_provider
+        .on_authenticate("admin_client", Some("admin"), Some(b"admin_pass"))
+        .await
+        .unwrap();
+
+    let mut acl_config = make_test_acl_config();
+    acl_config.roles[1].session = SessionPolicy {
+        max_queued_messages: Some(10),
+        quota: QuotaLimits {
+            messages_per_sec: Some(5),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    acl_config.default.session = SessionPolicy {
+        max_queued_messages: Some(100),
+        max_keep_alive: Some(120),
+        ..Default::default()
+    };
+    let provider = AclProvider::new(&acl_config, auth_provider);
+
+    let policy = provider
+        .on_session_policy("sensor1", Some("sensor"))
+        .await
+        .unwrap();
+    assert_eq!(policy.max_queued_messages, Some(10));
+    assert_eq!(policy.max_keep_alive, Some(120));
+    assert_eq!(policy.quota.messages_per_sec, Some(5));
+
+    let policy = provider
+        .on_session_policy("admin_client", Some("admin"))
+        .await
+        .unwrap();
+    assert_eq!(policy.max_queued_messages, Some(100));
+    assert_eq!(policy.quota, QuotaLimits::default());
+}
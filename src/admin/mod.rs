@@ -0,0 +1,1173 @@
+//! Admin HTTP API
+//!
+//! JSON API for inspecting and managing a running broker, served on its own
+//! listener (`[admin]`). Every request must carry the configured token as
+//! `Authorization: Bearer <token>`.
+//!
+//! Endpoints:
+//! - `GET /api/v1/clients[?limit=<n>&after=<id>]` - sessions, connected or
+//!   not, a page at a time in client ID order
+//! - `GET /api/v1/clients/<id>` - a session's subscriptions and inflight window
+//! - `DELETE /api/v1/clients/<id>[?discard_session=true]` - disconnect a
+//!   client (reason Administrative action), optionally ending its session
+//! - `POST /api/v1/clients/<id>/handover` - hold a session for a successor
+//!   (see [`crate::config::HandoverConfig`]); returns `{"token",
+//!   "expires_in"}`
+//! - `DELETE /api/v1/clients/<id>/handover` - call a handover off
+//! - `GET /api/v1/queues` - sessions with queued messages, deepest first
+//! - `POST /api/v1/publish` - publish `{"topic", "payload" | "payload_base64",
+//!   "qos", "retain"}`
+//! - `GET /api/v1/retained[?filter=<filter>&limit=<n>&after=<topic>]` - retained
+//!   messages matching a topic filter (default `#`), in topic order, a page
+//!   of `limit` (default 100, at most 1000) at a time; pass the response's
+//!   `next` as `after` for the next page
+//! - `DELETE /api/v1/retained?topic=<topic>` - delete a retained message
+//! - `DELETE /api/v1/retained?filter=<filter>` - delete all matching ones
+//! - `GET /api/v1/retained/export[?filter=<filter>]` - matching retained
+//!   messages as a JSON array (see [`crate::broker::RetainedEntry`])
+//! - `POST /api/v1/retained/import` - store an exported array, replacing
+//!   messages on the same topics
+//! - `POST /api/v1/retained/seed[?dry_run=true]` - store the messages of a
+//!   seed file (JSON Lines, see [`crate::broker::parse_retained_seed`]);
+//!   returns how many were `created` and `updated`, or with `dry_run` how
+//!   many would be
+//! - `GET /api/v1/topics/tree[?format=dot&depth=<n>]` - tree of the topics
+//!   in use and tree of the subscription filters, with counts per level (see
+//!   [`crate::broker::TopicTree`]), as JSON or as Graphviz DOT; levels below
+//!   `depth` are left out but counted
+//! - `GET /api/v1/topics/schema` - the declared topic templates (see
+//!   [`crate::topic::TopicSchema`]) with how many publishes matched each,
+//!   and how many matched none
+//! - `GET /api/v1/plugins` - loaded hook plugins (see [`crate::plugin`])
+//!   with their call and failure counts
+//! - `PUT /api/v1/plugins/<name>` - add a plugin, or replace the one of that
+//!   name, from a JSON [`crate::config::PluginConfig`]; returns once calls in
+//!   flight on the previous chain finished, `{"replaced", "drained"}`
+//! - `DELETE /api/v1/plugins/<name>` - remove a plugin, draining likewise
+//! - `GET /api/v1/tunables` - settings changeable at runtime (see
+//!   [`crate::broker::Tunables`]) with their values, configured values and
+//!   bounds
+//! - `PUT /api/v1/tunables/<name>` - change one to `{"value"}`, at once;
+//!   returns `{"name", "previous", "value"}`
+//! - `DELETE /api/v1/tunables/<name>` - go back to the configured value
+//! - `GET /api/v1/limits[?identity=<identity>]` - token bucket levels of the
+//!   connection rate, publish rate and quota limiters, and the boosts in
+//!   force (see [`crate::broker::Limiters`]); identities are like
+//!   `ip:10.0.0.5`, `user:alice`, `cn:<CN>` or `client:<id>`
+//! - `POST /api/v1/limits/<identity>/reset` - refill the identity's buckets
+//!   (and lift an IP's temporary ban); returns `{"identity", "reset"}`
+//! - `PUT /api/v1/limits/<identity>/boost` - multiply the identity's rates
+//!   and bucket sizes by `{"factor", "duration"}` (duration like `"30m"`)
+//! - `DELETE /api/v1/limits/<identity>/boost` - end a boost early
+//! - `GET /api/v1/maintenance` - the maintenance windows (see
+//!   [`crate::broker::MaintenanceScheduler`]) with their policies, whether
+//!   each is open, when it closes or opens next, and the bridges paused
+//! - `POST /api/v1/acl/check` - dry-run authorizing `{"client_id",
+//!   "username", "action": "publish" | "subscribe", "topic", "qos",
+//!   "retain", "listener"}` against the live hooks chain (auth, ACL,
+//!   plugins); returns `{"allowed", "steps"}`, each step naming the hook,
+//!   its decision and the rule it matched (see
+//!   [`crate::hooks::Hooks::explain_access`]). A connected client's
+//!   authenticated username applies as in real checks. Nothing is
+//!   published or subscribed
+//! - `POST /api/v1/reload` - reload the config file (see [`crate::reload`]);
+//!   returns what changed
+//! - `POST /api/v1/traces` - start a live trace (see [`crate::broker::Tracer`])
+//!   of `{"client_id", "topic", "duration"}` (client ID and/or topic filter;
+//!   duration like `"10m"`, default 10 minutes)
+//! - `GET /api/v1/traces` - running traces
+//! - `DELETE /api/v1/traces/<id>` - stop a trace
+//! - `GET /api/v1/traces/<id>/events` - a trace's events as server-sent
+//!   events, until the trace ends
+//! - `GET /api/v1/ocpp/charge_points` - online OCPP charge points (see
+//!   [`crate::ocpp`]) with the client acting as each
+//! - `GET /api/v1/ocpp/charge_points/<id>` - a charge point's presence,
+//!   online or not
+//!
+//! Client IDs in paths and topics in queries are percent-encoded.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64ct::{Base64, Encoding};
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full, Limited, StreamBody};
+use hyper::body::{Bytes, Frame};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{header, Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tracing::{error, info};
+
+use crate::auth::constant_time_eq;
+use crate::broker::{Broker, RetainedEntry, Tracer, TunableError};
+use crate::cluster::{percent_decode, query_param};
+use crate::config::{PluginConfig, LISTENER_NAMES};
+use crate::hooks::{scope_listener, AccessAction, AccessDecision, AccessRequest, AccessStep};
+use crate::ocpp::{ChargePointPresence, OcppProvider};
+use crate::plugin::PluginHost;
+use crate::protocol::QoS;
+use crate::reload::ConfigReloader;
+use crate::session::{Qos2State, Session, SessionState};
+use crate::topic::{validate_topic_filter, validate_topic_name};
+
+/// Largest request body accepted (publish payloads included)
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+const CLIENTS_PATH: &str = "/api/v1/clients";
+
+const TRACES_PATH: &str = "/api/v1/traces";
+
+const PLUGINS_PATH: &str = "/api/v1/plugins";
+
+const TUNABLES_PATH: &str = "/api/v1/tunables";
+
+const LIMITS_PATH: &str = "/api/v1/limits";
+
+const CHARGE_POINTS_PATH: &str = "/api/v1/ocpp/charge_points";
+
+/// Largest retained import accepted
+const MAX_IMPORT_SIZE: usize = 64 * 1024 * 1024;
+
+/// Retained messages per page unless the request says otherwise
+const DEFAULT_RETAINED_PAGE: usize = 100;
+
+const MAX_RETAINED_PAGE: usize = 1000;
+
+/// Sessions per page unless the request says otherwise
+const DEFAULT_CLIENTS_PAGE: usize = 100;
+
+const MAX_CLIENTS_PAGE: usize = 1000;
+
+/// How long a trace runs unless the request says otherwise
+const DEFAULT_TRACE_DURATION: Duration = Duration::from_secs(600);
+
+/// How often an events stream checks its trace is still running
+const TRACE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One page of sessions returned by `GET /api/v1/clients`
+#[derive(Debug, Serialize)]
+struct ClientsPage {
+    clients: Vec<ClientSummary>,
+    /// Client ID to continue after, if there are more sessions
+    next: Option<String>,
+}
+
+/// Session summary returned by `GET /api/v1/clients`
+#[derive(Debug, Serialize)]
+struct ClientSummary {
+    client_id: String,
+    username: Option<String>,
+    connected: bool,
+    protocol_version: u8,
+    clean_start: bool,
+    session_expiry_interval: u32,
+    keep_alive: u16,
+    /// Seconds since the client disconnected
+    disconnected_secs: Option<u64>,
+    subscription_count: usize,
+    inflight_count: usize,
+    /// Age of the oldest unacknowledged message, in milliseconds
+    oldest_unacked_ms: Option<u64>,
+    /// Outbound messages not acknowledged within the retry interval
+    ack_timeouts: u64,
+    /// Connected but not acknowledging (see `limits.ack_alarm_after`)
+    ack_stalled: bool,
+    /// Publishes whose acknowledgement was held for congested subscribers
+    publishes_held: u64,
+    /// Publishes rejected for congested subscribers
+    publishes_rejected: u64,
+    queued_messages: usize,
+    queued_bytes: usize,
+    /// Idle session packed away (its subscriptions are still counted)
+    compressed: bool,
+}
+
+/// Session detail returned by `GET /api/v1/clients/<id>`
+#[derive(Debug, Serialize)]
+struct ClientDetail<'a> {
+    #[serde(flatten)]
+    summary: ClientSummary,
+    receive_maximum: u16,
+    send_quota: u16,
+    max_inflight: u16,
+    has_will: bool,
+    subscriptions: Vec<SubscriptionEntry<'a>>,
+    inflight: Vec<InflightEntry<'a>>,
+    /// Packet IDs of QoS 2 messages from the client awaiting PUBREL
+    awaiting_rel: Vec<u16>,
+}
+
+#[derive(Debug, Serialize)]
+struct SubscriptionEntry<'a> {
+    filter: &'a str,
+    qos: u8,
+    no_local: bool,
+    retain_as_published: bool,
+    retain_handling: u8,
+    subscription_id: Option<u32>,
+}
+
+/// A message sent to the client and not yet acknowledged
+#[derive(Debug, Serialize)]
+struct InflightEntry<'a> {
+    packet_id: u16,
+    topic: &'a str,
+    qos: u8,
+    /// Acknowledgement awaited: "puback", "pubrec" or "pubcomp"
+    awaiting: &'static str,
+    age_ms: u64,
+    retries: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct QueueEntry<'a> {
+    client_id: &'a str,
+    connected: bool,
+    messages: usize,
+    bytes: usize,
+}
+
+/// Presence returned by `GET /api/v1/ocpp/charge_points`
+#[derive(Debug, Serialize)]
+struct ChargePointEntry {
+    cp_id: String,
+    /// Client currently (or last) acting as the charge point
+    client_id: String,
+    online: bool,
+    /// Seconds since the client was first seen as the charge point
+    connected_secs: u64,
+    /// Seconds since its last uplink message or disconnect
+    last_seen_secs: u64,
+}
+
+impl ChargePointEntry {
+    fn new(cp_id: String, presence: ChargePointPresence) -> Self {
+        let secs_since = |at: std::time::SystemTime| at.elapsed().map_or(0, |d| d.as_secs());
+        Self {
+            cp_id,
+            client_id: presence.client_id,
+            online: presence.online,
+            connected_secs: secs_since(presence.connected_at),
+            last_seen_secs: secs_since(presence.last_seen),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DisconnectResult {
+    disconnected: bool,
+    session_discarded: bool,
+}
+
+/// Body of `POST /api/v1/publish`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublishRequest {
+    topic: String,
+    /// UTF-8 payload
+    #[serde(default)]
+    payload: Option<String>,
+    /// Binary payload, base64-encoded
+    #[serde(default)]
+    payload_base64: Option<String>,
+    #[serde(default)]
+    qos: u8,
+    #[serde(default)]
+    retain: bool,
+}
+
+/// Body of `POST /api/v1/traces`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TraceRequest {
+    #[serde(default)]
+    client_id: Option<String>,
+    /// Topic filter
+    #[serde(default)]
+    topic: Option<String>,
+    #[serde(default, with = "humantime_serde")]
+    duration: Option<Duration>,
+}
+
+/// Body of `PUT /api/v1/tunables/<name>`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TunableRequest {
+    value: u64,
+}
+
+/// Body of `POST /api/v1/acl/check`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AccessCheckRequest {
+    client_id: String,
+    #[serde(default)]
+    username: Option<String>,
+    action: AccessAction,
+    /// Topic, or topic filter to subscribe to
+    topic: String,
+    #[serde(default)]
+    qos: u8,
+    #[serde(default)]
+    retain: bool,
+    /// Listener the client would connect on (hooks limited to listeners
+    /// are skipped without one)
+    #[serde(default)]
+    listener: Option<String>,
+}
+
+/// Result of `POST /api/v1/acl/check`
+#[derive(Debug, Serialize)]
+struct AccessCheckResult {
+    allowed: bool,
+    steps: Vec<AccessStep>,
+}
+
+/// Body of `PUT /api/v1/limits/<identity>/boost`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoostRequest {
+    factor: f64,
+    #[serde(with = "humantime_serde")]
+    duration: Duration,
+}
+
+/// Admin HTTP API (see the module docs)
+pub struct AdminApi {
+    addr: SocketAddr,
+    token: String,
+    broker: Arc<Broker>,
+    reloader: Option<Arc<ConfigReloader>>,
+    plugins: Option<Arc<PluginHost>>,
+    ocpp: Option<Arc<OcppProvider>>,
+}
+
+impl AdminApi {
+    pub fn new(addr: SocketAddr, token: impl Into<String>, broker: Arc<Broker>) -> Self {
+        Self {
+            addr,
+            token: token.into(),
+            broker,
+            reloader: None,
+            plugins: None,
+            ocpp: None,
+        }
+    }
+
+    /// Serve `POST /api/v1/reload` with this reloader
+    pub fn with_reloader(mut self, reloader: Arc<ConfigReloader>) -> Self {
+        self.reloader = Some(reloader);
+        self
+    }
+
+    /// Serve `/api/v1/plugins` for these plugins
+    pub fn with_plugins(mut self, plugins: Arc<PluginHost>) -> Self {
+        self.plugins = Some(plugins);
+        self
+    }
+
+    /// Serve `/api/v1/ocpp/charge_points` from this provider's presence
+    pub fn with_ocpp(mut self, ocpp: Arc<OcppProvider>) -> Self {
+        self.ocpp = Some(ocpp);
+        self
+    }
+
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let listener = TcpListener::bind(self.addr).await?;
+        info!("Admin API listening on http://{}/api/v1", self.addr);
+
+        let api = Arc::new(self);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let io = TokioIo::new(stream);
+            let api = api.clone();
+
+            tokio::spawn(async move {
+                let service = service_fn(move |mut req: Request<hyper::body::Incoming>| {
+                    // Changes are logged with who made them
+                    req.extensions_mut().insert(peer);
+                    let api = api.clone();
+                    async move { Ok::<_, Infallible>(api.handle_request(req).await) }
+                });
+
+                if let Err(err) = http1::Builder::new().serve_connection(io, service).await {
+                    error!("Error serving admin API connection: {:?}", err);
+                }
+            });
+        }
+    }
+
+    async fn handle_request(
+        &self,
+        req: Request<hyper::body::Incoming>,
+    ) -> Response<BoxBody<Bytes, Infallible>> {
+        if !self.authorized(&req) {
+            return Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(header::WWW_AUTHENTICATE, "Bearer")
+                .body(Full::new(Bytes::from("Unauthorized")).boxed())
+                .unwrap();
+        }
+
+        // Event streams are the one response that isn't a single body
+        let trace_events = req
+            .uri()
+            .path()
+            .strip_prefix(TRACES_PATH)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .and_then(|rest| rest.strip_suffix("/events"))
+            .filter(|id| !id.is_empty() && !id.contains('/'));
+        match (req.method(), trace_events) {
+            (&Method::GET, Some(id)) => match self.broker.tracer().get(id) {
+                Some(trace) => trace_events_response(self.broker.tracer().clone(), trace.id),
+                None => error_response(StatusCode::NOT_FOUND, "No such trace").map(BodyExt::boxed),
+            },
+            _ => self.route(req).await.map(BodyExt::boxed),
+        }
+    }
+
+    async fn route(&self, req: Request<hyper::body::Incoming>) -> Response<Full<Bytes>> {
+        let path = req.uri().path().to_string();
+        let query = req.uri().query().map(str::to_string);
+        let client_id = path
+            .strip_prefix(CLIENTS_PATH)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|id| !id.is_empty());
+
+        let handover = client_id.and_then(|id| id.strip_suffix("/handover"));
+        let plugin = path
+            .strip_prefix(PLUGINS_PATH)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|name| !name.is_empty());
+        let tunable = path
+            .strip_prefix(TUNABLES_PATH)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|name| !name.is_empty());
+        let charge_point = path
+            .strip_prefix(CHARGE_POINTS_PATH)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|id| !id.is_empty());
+        let limits = path
+            .strip_prefix(LIMITS_PATH)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .and_then(|rest| rest.rsplit_once('/'))
+            .filter(|(identity, _)| !identity.is_empty());
+
+        match (req.method(), path.as_str(), client_id) {
+            (&Method::GET, CLIENTS_PATH, _) => self.list_clients(query.as_deref()),
+            (&Method::POST | &Method::DELETE, _, Some(_)) if handover.is_some() => {
+                match handover.and_then(percent_decode) {
+                    Some(id) => self.handover(req.method() == Method::POST, &id),
+                    None => error_response(StatusCode::BAD_REQUEST, "Invalid client ID encoding"),
+                }
+            }
+            (&Method::GET, _, Some(id)) => match percent_decode(id) {
+                Some(id) => self.client_detail(&id),
+                None => error_response(StatusCode::BAD_REQUEST, "Invalid client ID encoding"),
+            },
+            (&Method::DELETE, _, Some(id)) => {
+                let Some(id) = percent_decode(id) else {
+                    return error_response(StatusCode::BAD_REQUEST, "Invalid client ID encoding");
+                };
+                let discard = match query_param(query.as_deref(), "discard_session") {
+                    Ok(value) => value.is_some_and(|v| v == "true" || v == "1"),
+                    Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+                };
+                self.disconnect_client(&id, discard).await
+            }
+            (&Method::GET, "/api/v1/queues", _) => self.list_queues(),
+            (&Method::GET, "/api/v1/topics/tree", _) => self.topic_tree(query.as_deref()),
+            (&Method::GET, "/api/v1/topics/schema", _) => {
+                json_response(&self.broker.topic_schema().summary())
+            }
+            (&Method::POST, "/api/v1/publish", _) => self.publish(req).await,
+            (&Method::GET, PLUGINS_PATH, _) => match self.plugins {
+                Some(ref plugins) => json_response(&plugins.list()),
+                None => error_response(StatusCode::NOT_FOUND, "Plugins are not enabled"),
+            },
+            (&Method::PUT | &Method::DELETE, _, _) if plugin.is_some() => {
+                match plugin.and_then(percent_decode) {
+                    Some(name) => self.change_plugin(req, &name).await,
+                    None => error_response(StatusCode::BAD_REQUEST, "Invalid plugin name encoding"),
+                }
+            }
+            (&Method::GET, CHARGE_POINTS_PATH, _) => match self.ocpp {
+                Some(ref ocpp) => {
+                    let mut online = ocpp.online_charge_points();
+                    online.sort_unstable();
+                    let entries: Vec<_> = online
+                        .into_iter()
+                        .filter_map(|cp_id| {
+                            let presence = ocpp.presence(&cp_id)?;
+                            Some(ChargePointEntry::new(cp_id, presence))
+                        })
+                        .collect();
+                    json_response(&entries)
+                }
+                None => error_response(StatusCode::NOT_FOUND, "OCPP is not enabled"),
+            },
+            (&Method::GET, _, _) if charge_point.is_some() => {
+                let Some(ref ocpp) = self.ocpp else {
+                    return error_response(StatusCode::NOT_FOUND, "OCPP is not enabled");
+                };
+                let Some(cp_id) = charge_point.and_then(percent_decode) else {
+                    return error_response(
+                        StatusCode::BAD_REQUEST,
+                        "Invalid charge point ID encoding",
+                    );
+                };
+                match ocpp.presence(&cp_id) {
+                    Some(presence) => json_response(&ChargePointEntry::new(cp_id, presence)),
+                    None => error_response(StatusCode::NOT_FOUND, "No such charge point"),
+                }
+            }
+            (&Method::GET, TUNABLES_PATH, _) => json_response(&self.broker.tunables().list()),
+            (&Method::PUT | &Method::DELETE, _, _) if tunable.is_some() => {
+                match tunable.and_then(percent_decode) {
+                    Some(name) => self.change_tunable(req, &name).await,
+                    None => {
+                        error_response(StatusCode::BAD_REQUEST, "Invalid tunable name encoding")
+                    }
+                }
+            }
+            (&Method::GET, "/api/v1/maintenance", _) => {
+                json_response(&self.broker.maintenance_status())
+            }
+            (&Method::GET, LIMITS_PATH, _) => match query_param(query.as_deref(), "identity") {
+                Ok(identity) => json_response(&self.broker.limit_levels(identity.as_deref())),
+                Err(e) => error_response(StatusCode::BAD_REQUEST, e),
+            },
+            (_, _, _) if limits.is_some() => {
+                match limits
+                    .and_then(|(identity, action)| Some((percent_decode(identity)?, action)))
+                {
+                    Some((identity, action)) => self.change_limits(req, &identity, action).await,
+                    None => error_response(StatusCode::BAD_REQUEST, "Invalid identity encoding"),
+                }
+            }
+            (&Method::POST, "/api/v1/acl/check", _) => self.check_access(req).await,
+            (&Method::POST, "/api/v1/reload", _) => self.reload().await,
+            (&Method::GET, TRACES_PATH, _) => json_response(&self.broker.tracer().list()),
+            (&Method::POST, TRACES_PATH, _) => self.start_trace(req).await,
+            (&Method::DELETE, _, _) if path.starts_with(TRACES_PATH) => {
+                let id = path
+                    .strip_prefix(TRACES_PATH)
+                    .and_then(|rest| rest.strip_prefix('/'))
+                    .filter(|id| !id.is_empty() && !id.contains('/'));
+                match id {
+                    Some(id) if self.broker.tracer().stop(id) => {
+                        json_response(&serde_json::json!({ "stopped": id }))
+                    }
+                    Some(_) => error_response(StatusCode::NOT_FOUND, "No such trace"),
+                    None => error_response(StatusCode::BAD_REQUEST, "Invalid trace ID"),
+                }
+            }
+            (&Method::GET, "/api/v1/retained", _) => self.list_retained(query.as_deref()),
+            (&Method::DELETE, "/api/v1/retained", _) => {
+                if let Ok(Some(filter)) = query_param(query.as_deref(), "filter") {
+                    return match self.broker.delete_retained_matching(&filter) {
+                        Ok(deleted) => json_response(&serde_json::json!({ "deleted": deleted })),
+                        Err(e) => error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+                    };
+                }
+                match query_param(query.as_deref(), "topic") {
+                    Ok(Some(topic)) if self.broker.delete_retained(&topic) => {
+                        json_response(&serde_json::json!({ "deleted": topic }))
+                    }
+                    Ok(Some(_)) => error_response(StatusCode::NOT_FOUND, "No retained message"),
+                    Ok(None) => error_response(StatusCode::BAD_REQUEST, "Missing topic or filter"),
+                    Err(e) => error_response(StatusCode::BAD_REQUEST, e),
+                }
+            }
+            (&Method::GET, "/api/v1/retained/export", _) => {
+                let filter = match query_param(query.as_deref(), "filter") {
+                    Ok(filter) => filter.unwrap_or_else(|| "#".to_string()),
+                    Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+                };
+                match self.broker.export_retained(&filter) {
+                    Ok(entries) => json_response(&entries),
+                    Err(e) => error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+                }
+            }
+            (&Method::POST, "/api/v1/retained/import", _) => self.import_retained(req).await,
+            (&Method::POST, "/api/v1/retained/seed", _) => {
+                let dry_run = match query_param(query.as_deref(), "dry_run") {
+                    Ok(None) => false,
+                    Ok(Some(value)) => match value.parse::<bool>() {
+                        Ok(dry_run) => dry_run,
+                        Err(_) => {
+                            return error_response(
+                                StatusCode::BAD_REQUEST,
+                                "dry_run must be true or false",
+                            )
+                        }
+                    },
+                    Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+                };
+                self.seed_retained(req, dry_run).await
+            }
+            _ => error_response(StatusCode::NOT_FOUND, "Not Found"),
+        }
+    }
+
+    fn authorized(&self, req: &Request<hyper::body::Incoming>) -> bool {
+        req.headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| constant_time_eq(token.as_bytes(), self.token.as_bytes()))
+    }
+
+    fn list_clients(&self, query: Option<&str>) -> Response<Full<Bytes>> {
+        let param = |name| query_param(query, name);
+        let (after, limit) = match (param("after"), param("limit")) {
+            (Ok(after), Ok(limit)) => (after, limit),
+            (Err(e), _) | (_, Err(e)) => return error_response(StatusCode::BAD_REQUEST, e),
+        };
+        let limit = match limit.map(|limit| limit.parse::<usize>()) {
+            None => DEFAULT_CLIENTS_PAGE,
+            Some(Ok(limit)) if (1..=MAX_CLIENTS_PAGE).contains(&limit) => limit,
+            Some(_) => {
+                return error_response(StatusCode::BAD_REQUEST, "limit must be between 1 and 1000")
+            }
+        };
+        let sessions = self.broker.sessions();
+        let mut client_ids: Vec<_> = sessions
+            .client_ids()
+            .into_iter()
+            .filter(|id| after.as_deref().is_none_or(|after| &**id > after))
+            .collect();
+        client_ids.sort_unstable();
+
+        // Each session is locked only while its own summary is built
+        let mut page = ClientsPage {
+            clients: Vec::new(),
+            next: None,
+        };
+        for client_id in client_ids {
+            if page.clients.len() == limit {
+                page.next = page.clients.last().map(|c| c.client_id.clone());
+                break;
+            }
+            if let Some(session) = sessions.get(&client_id) {
+                let summary = self.summary(&session.read());
+                page.clients.push(summary);
+            }
+        }
+        json_response(&page)
+    }
+
+    fn client_detail(&self, client_id: &str) -> Response<Full<Bytes>> {
+        let Some(session) = self.broker.sessions().get(client_id) else {
+            return error_response(StatusCode::NOT_FOUND, "No such client");
+        };
+        let s = session.read();
+        // Read an idle compressed session's subscriptions without unpacking
+        // the session itself
+        let packed = s.packed_subscriptions();
+
+        let mut subscriptions: Vec<_> = s
+            .subscriptions
+            .values()
+            .chain(&packed)
+            .map(|sub| SubscriptionEntry {
+                filter: &sub.filter,
+                qos: sub.options.qos as u8,
+                no_local: sub.options.no_local,
+                retain_as_published: sub.options.retain_as_published,
+                retain_handling: sub.options.retain_handling as u8,
+                subscription_id: sub.subscription_id,
+            })
+            .collect();
+        subscriptions.sort_by(|a, b| a.filter.cmp(b.filter));
+
+        let mut inflight: Vec<_> = s
+            .inflight_outgoing
+            .values()
+            .map(|m| InflightEntry {
+                packet_id: m.packet_id,
+                topic: &m.publish.topic,
+                qos: m.publish.qos as u8,
+                awaiting: match m.qos2_state {
+                    None => "puback",
+                    Some(Qos2State::WaitingPubRec) => "pubrec",
+                    Some(Qos2State::WaitingPubComp) => "pubcomp",
+                },
+                age_ms: m.sent_at.elapsed().as_millis() as u64,
+                retries: m.retry_count,
+            })
+            .collect();
+        inflight.sort_by_key(|m| m.packet_id);
+
+        let mut awaiting_rel: Vec<_> = s.inflight_incoming.keys().copied().collect();
+        awaiting_rel.sort_unstable();
+
+        json_response(&ClientDetail {
+            summary: self.summary(&s),
+            receive_maximum: s.receive_maximum,
+            send_quota: s.send_quota,
+            max_inflight: s.max_inflight,
+            has_will: s.will.is_some(),
+            subscriptions,
+            inflight,
+            awaiting_rel,
+        })
+    }
+
+    fn summary(&self, s: &Session) -> ClientSummary {
+        ClientSummary {
+            client_id: s.client_id.to_string(),
+            username: s.username.as_deref().map(str::to_string),
+            connected: s.state == SessionState::Connected && self.broker.is_connected(&s.client_id),
+            protocol_version: s.protocol_version as u8,
+            clean_start: s.clean_start,
+            session_expiry_interval: s.session_expiry_interval,
+            keep_alive: s.keep_alive,
+            disconnected_secs: s.disconnected_at.map(|at| at.elapsed().as_secs()),
+            subscription_count: s.subscription_count(),
+            inflight_count: s.inflight_outgoing.len(),
+            oldest_unacked_ms: s
+                .oldest_unacked()
+                .map(|sent| sent.elapsed().as_millis() as u64),
+            ack_timeouts: s.ack_timeouts,
+            ack_stalled: s.ack_stalled,
+            publishes_held: s.publishes_held,
+            publishes_rejected: s.publishes_rejected,
+            queued_messages: s.pending_count(),
+            queued_bytes: s.pending_bytes(),
+            compressed: s.is_compressed(),
+        }
+    }
+
+    async fn disconnect_client(&self, client_id: &str, discard: bool) -> Response<Full<Bytes>> {
+        let disconnected = self.broker.disconnect_client(client_id, discard).await;
+        let session_discarded = if disconnected {
+            discard
+        } else if discard {
+            self.broker.discard_session(client_id)
+        } else {
+            false
+        };
+        if !disconnected && !session_discarded && self.broker.sessions().get(client_id).is_none() {
+            return error_response(StatusCode::NOT_FOUND, "No such client");
+        }
+        json_response(&DisconnectResult {
+            disconnected,
+            session_discarded,
+        })
+    }
+
+    fn handover(&self, prepare: bool, client_id: &str) -> Response<Full<Bytes>> {
+        if self.broker.sessions().get(client_id).is_none() {
+            return error_response(StatusCode::NOT_FOUND, "No such client");
+        }
+        if !prepare {
+            let cancelled = self.broker.cancel_handover(client_id);
+            return json_response(&serde_json::json!({ "cancelled": cancelled }));
+        }
+        match self.broker.prepare_handover(client_id) {
+            Some(handover) => json_response(&serde_json::json!({
+                "token": &*handover.token,
+                "expires_in": handover.expires_in(),
+            })),
+            None => error_response(StatusCode::CONFLICT, "Session handover is disabled"),
+        }
+    }
+
+    async fn reload(&self) -> Response<Full<Bytes>> {
+        let Some(ref reloader) = self.reloader else {
+            return error_response(StatusCode::SERVICE_UNAVAILABLE, "Reload not available");
+        };
+        match reloader.reload().await {
+            Ok(report) => json_response(&report),
+            Err(e) => error_response(StatusCode::UNPROCESSABLE_ENTITY, &e.to_string()),
+        }
+    }
+
+    async fn start_trace(&self, req: Request<hyper::body::Incoming>) -> Response<Full<Bytes>> {
+        let body = match Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(_) => return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Body too large"),
+        };
+        let request: TraceRequest = match serde_json::from_slice(&body) {
+            Ok(request) => request,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        };
+        let duration = request.duration.unwrap_or(DEFAULT_TRACE_DURATION);
+        match self
+            .broker
+            .tracer()
+            .start(request.client_id, request.topic, duration)
+        {
+            Ok(trace) => {
+                info!(
+                    "Admin started trace {} (client: {:?}, topic: {:?})",
+                    trace.id, trace.client_id, trace.filter
+                );
+                json_response(&trace)
+            }
+            Err(e) => error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        }
+    }
+
+    /// PUT: add or replace a plugin; DELETE: remove it
+    async fn change_plugin(
+        &self,
+        req: Request<hyper::body::Incoming>,
+        name: &str,
+    ) -> Response<Full<Bytes>> {
+        let Some(ref plugins) = self.plugins else {
+            return error_response(StatusCode::NOT_FOUND, "Plugins are not enabled");
+        };
+        if req.method() == Method::DELETE {
+            return match plugins.remove(name).await {
+                Some(change) => json_response(&change),
+                None => error_response(StatusCode::NOT_FOUND, "No such plugin"),
+            };
+        }
+
+        let body = match Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(_) => return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Body too large"),
+        };
+        let mut config: PluginConfig = match serde_json::from_slice(&body) {
+            Ok(config) => config,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        };
+        if !config.name.is_empty() && config.name != name {
+            return error_response(StatusCode::BAD_REQUEST, "Body name differs from the path");
+        }
+        config.name = name.to_string();
+        match plugins.upsert(config).await {
+            Ok(change) => json_response(&change),
+            Err(e) => error_response(StatusCode::BAD_REQUEST, &e),
+        }
+    }
+
+    /// PUT: override a tunable; DELETE: reset it
+    async fn change_tunable(
+        &self,
+        req: Request<hyper::body::Incoming>,
+        name: &str,
+    ) -> Response<Full<Bytes>> {
+        let by = match req.extensions().get::<SocketAddr>() {
+            Some(peer) => format!("admin API client {}", peer),
+            None => "admin API".to_string(),
+        };
+        let value = if req.method() == Method::DELETE {
+            None
+        } else {
+            let body = match Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await {
+                Ok(body) => body.to_bytes(),
+                Err(_) => return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Body too large"),
+            };
+            match serde_json::from_slice::<TunableRequest>(&body) {
+                Ok(request) => Some(request.value),
+                Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+            }
+        };
+        match self.broker.set_tunable(name, value, &by) {
+            Ok(change) => json_response(&change),
+            Err(e @ TunableError::Unknown(_)) => {
+                error_response(StatusCode::NOT_FOUND, &e.to_string())
+            }
+            Err(e) => error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        }
+    }
+
+    /// POST `reset`: refill an identity's buckets; PUT `boost`: boost its
+    /// limits; DELETE `boost`: end the boost
+    async fn change_limits(
+        &self,
+        req: Request<hyper::body::Incoming>,
+        identity: &str,
+        action: &str,
+    ) -> Response<Full<Bytes>> {
+        let by = match req.extensions().get::<SocketAddr>() {
+            Some(peer) => format!("admin API client {}", peer),
+            None => "admin API".to_string(),
+        };
+        match (req.method(), action) {
+            (&Method::POST, "reset") => {
+                let reset = self.broker.reset_limits(identity, &by);
+                json_response(&serde_json::json!({ "identity": identity, "reset": reset }))
+            }
+            (&Method::PUT, "boost") => {
+                let body = match Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await {
+                    Ok(body) => body.to_bytes(),
+                    Err(_) => {
+                        return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Body too large")
+                    }
+                };
+                let request = match serde_json::from_slice::<BoostRequest>(&body) {
+                    Ok(request) => request,
+                    Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+                };
+                match self
+                    .broker
+                    .boost_limits(identity, request.factor, request.duration, &by)
+                {
+                    Ok(boost) => json_response(&boost),
+                    Err(e) => error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+                }
+            }
+            (&Method::DELETE, "boost") if self.broker.clear_limit_boost(identity, &by) => {
+                json_response(&serde_json::json!({ "identity": identity, "cleared": true }))
+            }
+            (&Method::DELETE, "boost") => error_response(StatusCode::NOT_FOUND, "No boost"),
+            _ => error_response(StatusCode::NOT_FOUND, "Not Found"),
+        }
+    }
+
+    async fn check_access(&self, req: Request<hyper::body::Incoming>) -> Response<Full<Bytes>> {
+        let body = match Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(_) => return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Body too large"),
+        };
+        let request = match serde_json::from_slice::<AccessCheckRequest>(&body) {
+            Ok(request) => request,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        };
+        let valid = match request.action {
+            AccessAction::Publish => validate_topic_name(&request.topic),
+            AccessAction::Subscribe => validate_topic_filter(&request.topic),
+        };
+        if let Err(e) = valid {
+            return error_response(StatusCode::BAD_REQUEST, e);
+        }
+        let Some(qos) = QoS::from_u8(request.qos) else {
+            return error_response(StatusCode::BAD_REQUEST, "qos must be 0, 1 or 2");
+        };
+        let listener = match request.listener.as_deref() {
+            None => None,
+            Some(name) => match LISTENER_NAMES
+                .iter()
+                .chain(["stomp"].iter())
+                .find(|l| **l == name)
+            {
+                Some(listener) => Some(*listener),
+                None => return error_response(StatusCode::BAD_REQUEST, "Unknown listener"),
+            },
+        };
+
+        let access = AccessRequest {
+            client_id: &request.client_id,
+            username: request.username.as_deref(),
+            action: request.action,
+            topic: &request.topic,
+            qos,
+            retain: request.retain,
+        };
+        let hooks = self.broker.hooks();
+        let steps = match listener {
+            Some(listener) => scope_listener(listener, hooks.explain_access(&access)).await,
+            None => hooks.explain_access(&access).await,
+        };
+        let allowed = steps.iter().all(|s| s.decision == AccessDecision::Allow);
+        json_response(&AccessCheckResult { allowed, steps })
+    }
+
+    fn list_queues(&self) -> Response<Full<Bytes>> {
+        let depths = self.broker.queue_depths();
+        let entries: Vec<_> = depths
+            .iter()
+            .map(|d| QueueEntry {
+                client_id: &d.client_id,
+                connected: d.connected,
+                messages: d.messages,
+                bytes: d.bytes,
+            })
+            .collect();
+        json_response(&entries)
+    }
+
+    fn list_retained(&self, query: Option<&str>) -> Response<Full<Bytes>> {
+        let param = |name| query_param(query, name);
+        let (filter, after, limit) = match (param("filter"), param("after"), param("limit")) {
+            (Ok(filter), Ok(after), Ok(limit)) => (filter, after, limit),
+            (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+                return error_response(StatusCode::BAD_REQUEST, e)
+            }
+        };
+        let limit = match limit.map(|limit| limit.parse::<usize>()) {
+            None => DEFAULT_RETAINED_PAGE,
+            Some(Ok(limit)) if (1..=MAX_RETAINED_PAGE).contains(&limit) => limit,
+            Some(_) => {
+                return error_response(StatusCode::BAD_REQUEST, "limit must be between 1 and 1000")
+            }
+        };
+        let filter = filter.as_deref().unwrap_or("#");
+        match self
+            .broker
+            .retained_matching(filter, after.as_deref(), limit)
+        {
+            Ok(page) => json_response(&page),
+            Err(e) => error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        }
+    }
+
+    fn topic_tree(&self, query: Option<&str>) -> Response<Full<Bytes>> {
+        let param = |name| query_param(query, name);
+        let (format, depth) = match (param("format"), param("depth")) {
+            (Ok(format), Ok(depth)) => (format, depth),
+            (Err(e), _) | (_, Err(e)) => return error_response(StatusCode::BAD_REQUEST, e),
+        };
+        let depth = match depth.map(|depth| depth.parse::<usize>()) {
+            None => usize::MAX,
+            Some(Ok(depth)) => depth,
+            Some(Err(_)) => {
+                return error_response(StatusCode::BAD_REQUEST, "depth must be a number")
+            }
+        };
+        let tree = self.broker.topic_tree(depth);
+        match format.as_deref() {
+            None | Some("json") => json_response(&tree),
+            Some("dot") => Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "text/vnd.graphviz")
+                .body(Full::new(Bytes::from(tree.to_dot())))
+                .unwrap(),
+            Some(_) => error_response(StatusCode::BAD_REQUEST, "format must be json or dot"),
+        }
+    }
+
+    async fn import_retained(&self, req: Request<hyper::body::Incoming>) -> Response<Full<Bytes>> {
+        let body = match Limited::new(req.into_body(), MAX_IMPORT_SIZE)
+            .collect()
+            .await
+        {
+            Ok(body) => body.to_bytes(),
+            Err(_) => return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Body too large"),
+        };
+        let entries: Vec<RetainedEntry> = match serde_json::from_slice(&body) {
+            Ok(entries) => entries,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        };
+        match self.broker.import_retained(&entries) {
+            Ok(imported) => json_response(&serde_json::json!({ "imported": imported })),
+            Err(e) => error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        }
+    }
+
+    async fn seed_retained(
+        &self,
+        req: Request<hyper::body::Incoming>,
+        dry_run: bool,
+    ) -> Response<Full<Bytes>> {
+        let body = match Limited::new(req.into_body(), MAX_IMPORT_SIZE)
+            .collect()
+            .await
+        {
+            Ok(body) => body.to_bytes(),
+            Err(_) => return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Body too large"),
+        };
+        let Ok(seed) = std::str::from_utf8(&body) else {
+            return error_response(StatusCode::BAD_REQUEST, "Seed is not UTF-8");
+        };
+        match self.broker.seed_retained(seed, dry_run).await {
+            Ok(report) => json_response(&report),
+            Err(e) => error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        }
+    }
+
+    async fn publish(&self, req: Request<hyper::body::Incoming>) -> Response<Full<Bytes>> {
+        let body = match Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(_) => return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Body too large"),
+        };
+        let request: PublishRequest = match serde_json::from_slice(&body) {
+            Ok(request) => request,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        };
+        let payload = match (request.payload, request.payload_base64) {
+            (Some(text), None) => Bytes::from(text),
+            (None, Some(encoded)) => match Base64::decode_vec(&encoded) {
+                Ok(decoded) => Bytes::from(decoded),
+                Err(_) => return error_response(StatusCode::BAD_REQUEST, "Invalid base64 payload"),
+            },
+            (None, None) => Bytes::new(),
+            (Some(_), Some(_)) => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    "Set only one of payload and payload_base64",
+                )
+            }
+        };
+        let Some(qos) = QoS::from_u8(request.qos) else {
+            return error_response(StatusCode::BAD_REQUEST, "qos must be 0, 1, or 2");
+        };
+        if let Err(e) = validate_topic_name(&request.topic) {
+            return error_response(StatusCode::BAD_REQUEST, e);
+        }
+
+        info!("Admin publish to {}", request.topic);
+        self.broker
+            .publish_and_announce(request.topic.clone(), payload, qos, request.retain)
+            .await;
+        json_response(&serde_json::json!({ "published": request.topic }))
+    }
+}
+
+/// Server-sent events of a trace, ending once it is stopped or expires
+fn trace_events_response(tracer: Arc<Tracer>, id: String) -> Response<BoxBody<Bytes, Infallible>> {
+    let events = tracer.subscribe();
+    let state = (tracer, events, id);
+    let stream = futures_util::stream::unfold(state, |(tracer, mut events, id)| {
+        async move {
+            loop {
+                match tokio::time::timeout(TRACE_POLL_INTERVAL, events.recv()).await {
+                    Ok(Ok(event)) if event.trace == id => {
+                        let json = serde_json::to_string(&event).ok()?;
+                        let frame = Frame::data(Bytes::from(format!("data: {}\n\n", json)));
+                        return Some((Ok::<_, Infallible>(frame), (tracer, events, id)));
+                    }
+                    Ok(Ok(_)) => {}
+                    // Missed events; carry on with the next ones
+                    Ok(Err(broadcast::error::RecvError::Lagged(_))) => {}
+                    Ok(Err(broadcast::error::RecvError::Closed)) => return None,
+                    Err(_) => {
+                        tracer.get(&id)?;
+                    }
+                }
+            }
+        }
+    });
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(BodyExt::boxed(StreamBody::new(stream)))
+        .unwrap()
+}
+
+fn json_response<T: Serialize>(value: &T) -> Response<Full<Bytes>> {
+    match serde_json::to_string(value) {
+        Ok(body) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .unwrap(),
+        Err(e) => {
+            error!("Failed to encode admin API response: {}", e);
+            error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to encode response",
+            )
+        }
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Full<Bytes>> {
+    let body = serde_json::json!({ "error": message }).to_string();
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
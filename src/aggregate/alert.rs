@@ -0,0 +1,286 @@
+//! Anomaly Alerts
+//!
+//! Each key of an aggregation is either normal or alerting. A result
+//! crossing a threshold, or exceeding a multiple of the key's baseline,
+//! raises the alert; the first result back in range clears it. Only the
+//! transitions are reported, so an alerting device doesn't repeat its
+//! alert every window:
+//!
+//! ```json
+//! {"aggregate": "rate", "key": "dev1", "state": "raised", "reason": "baseline",
+//!  "value": 120, "limit": 100, "baseline": 10, "window_end": 1714566900}
+//! ```
+//!
+//! A cleared alert carries the same fields without `reason` and `limit`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::auth::HttpEndpoint;
+use crate::config::AggregateAlertConfig;
+use crate::protocol::QoS;
+use crate::topic::validate_topic_name;
+
+use super::AggregateError;
+
+/// Why an alert was raised
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Breach {
+    Above(f64),
+    Below(f64),
+    /// Exceeded `baseline_factor` times this baseline
+    Baseline(f64),
+}
+
+impl Breach {
+    fn reason(&self) -> &'static str {
+        match self {
+            Breach::Above(_) => "above",
+            Breach::Below(_) => "below",
+            Breach::Baseline(_) => "baseline",
+        }
+    }
+}
+
+/// A raised (with its breach) or cleared alert for one key
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub key: String,
+    pub breach: Option<Breach>,
+    pub value: f64,
+    /// The key's baseline before this result, once warmed up
+    pub baseline: Option<f64>,
+}
+
+/// A validated `[aggregate.alert]` section
+#[derive(Debug, Clone)]
+pub struct AlertRule {
+    above: Option<f64>,
+    below: Option<f64>,
+    baseline_factor: Option<f64>,
+    alpha: f64,
+    warmup: u32,
+    pub(super) topic: Option<String>,
+    pub(super) qos: QoS,
+    pub(super) webhook: Option<HttpEndpoint>,
+    pub(super) webhook_timeout: Duration,
+}
+
+impl AlertRule {
+    /// `topic` renders the alert topic for a key, for validation
+    pub fn new(
+        config: &AggregateAlertConfig,
+        topic: impl Fn(&str) -> String,
+    ) -> Result<Self, AggregateError> {
+        let invalid = |msg: String| AggregateError::InvalidAlert(msg);
+        if config.above.is_none() && config.below.is_none() && config.baseline_factor.is_none() {
+            return Err(invalid("needs above, below or baseline_factor".to_string()));
+        }
+        if config.topic.is_none() && config.webhook.is_none() {
+            return Err(invalid("needs a topic or a webhook".to_string()));
+        }
+        if !(config.baseline_alpha > 0.0 && config.baseline_alpha <= 1.0) {
+            return Err(invalid("baseline_alpha must be in (0, 1]".to_string()));
+        }
+        if let Some(ref template) = config.topic {
+            validate_topic_name(&topic(template)).map_err(|e| invalid(format!("topic: {}", e)))?;
+        }
+        let qos = QoS::from_u8(config.qos).ok_or(AggregateError::InvalidQos(config.qos))?;
+        let webhook = match config.webhook {
+            Some(ref url) => Some(
+                HttpEndpoint::parse(url, &config.webhook_headers)
+                    .map_err(|e| invalid(format!("webhook '{}': {}", url, e)))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            above: config.above,
+            below: config.below,
+            baseline_factor: config.baseline_factor,
+            alpha: config.baseline_alpha,
+            warmup: config.baseline_warmup,
+            topic: config.topic.clone(),
+            qos,
+            webhook,
+            webhook_timeout: config.webhook_timeout,
+        })
+    }
+}
+
+/// A key's alert state
+#[derive(Debug, Default)]
+struct KeyState {
+    /// Exponentially weighted moving average of the results
+    baseline: f64,
+    /// Results folded into the baseline
+    results: u32,
+    raised: bool,
+}
+
+/// Tracks the alert state of every key of an aggregation
+#[derive(Debug)]
+pub struct Alerts {
+    rule: AlertRule,
+    keys: HashMap<String, KeyState>,
+}
+
+impl Alerts {
+    pub fn new(rule: AlertRule) -> Self {
+        Self {
+            rule,
+            keys: HashMap::new(),
+        }
+    }
+
+    pub fn rule(&self) -> &AlertRule {
+        &self.rule
+    }
+
+    /// Check a key's result; returns the alert if the key's state changed
+    pub fn check(&mut self, key: &str, value: f64) -> Option<Alert> {
+        let rule = &self.rule;
+        let state = self.keys.entry(key.to_string()).or_default();
+        let baseline = (state.results >= rule.warmup.max(1)).then_some(state.baseline);
+
+        let breach = if rule.above.is_some_and(|limit| value > limit) {
+            rule.above.map(Breach::Above)
+        } else if rule.below.is_some_and(|limit| value < limit) {
+            rule.below.map(Breach::Below)
+        } else {
+            match (rule.baseline_factor, baseline) {
+                // A zero baseline would make any result anomalous
+                (Some(factor), Some(b)) if b > 0.0 && value > factor * b => {
+                    Some(Breach::Baseline(b))
+                }
+                _ => None,
+            }
+        };
+
+        // The baseline follows every result, anomalous ones included
+        state.baseline = match state.results {
+            0 => value,
+            _ => rule.alpha * value + (1.0 - rule.alpha) * state.baseline,
+        };
+        state.results = state.results.saturating_add(1);
+
+        if breach.is_some() == state.raised {
+            return None;
+        }
+        state.raised = breach.is_some();
+        Some(Alert {
+            key: key.to_string(),
+            breach,
+            value,
+            baseline,
+        })
+    }
+
+    /// JSON body of an alert for the window ending at `end`
+    pub fn payload(&self, name: &str, alert: &Alert, end: u64) -> serde_json::Value {
+        let mut payload = serde_json::json!({
+            "aggregate": name,
+            "key": alert.key,
+            "state": if alert.breach.is_some() { "raised" } else { "cleared" },
+            "value": alert.value,
+            "window_end": end,
+        });
+        if let Some(breach) = alert.breach {
+            payload["reason"] = breach.reason().into();
+            payload["limit"] = match breach {
+                Breach::Above(limit) | Breach::Below(limit) => limit,
+                Breach::Baseline(b) => self.rule.baseline_factor.unwrap_or(1.0) * b,
+            }
+            .into();
+        }
+        if let Some(baseline) = alert.baseline {
+            payload["baseline"] = baseline.into();
+        }
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alerts(config: AggregateAlertConfig) -> Alerts {
+        Alerts::new(AlertRule::new(&config, |t| t.to_string()).unwrap())
+    }
+
+    #[test]
+    fn test_threshold_raises_and_clears_once() {
+        let mut alerts = alerts(AggregateAlertConfig {
+            above: Some(30.0),
+            below: Some(0.0),
+            topic: Some("alerts".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(alerts.check("a", 25.0), None);
+        let raised = alerts.check("a", 31.0).unwrap();
+        assert_eq!(raised.breach, Some(Breach::Above(30.0)));
+        // Still alerting: no repeat
+        assert_eq!(alerts.check("a", 35.0), None);
+        // Other keys are independent
+        assert_eq!(
+            alerts.check("b", -1.0).unwrap().breach,
+            Some(Breach::Below(0.0))
+        );
+        let cleared = alerts.check("a", 20.0).unwrap();
+        assert_eq!(cleared.breach, None);
+
+        let payload = alerts.payload("temp", &raised, 60);
+        assert_eq!(payload["state"], "raised");
+        assert_eq!(payload["reason"], "above");
+        assert_eq!(payload["limit"], 30.0);
+        assert_eq!(alerts.payload("temp", &cleared, 120)["state"], "cleared");
+    }
+
+    #[test]
+    fn test_baseline() {
+        let mut alerts = alerts(AggregateAlertConfig {
+            baseline_factor: Some(10.0),
+            baseline_alpha: 0.5,
+            baseline_warmup: 3,
+            webhook: Some("http://127.0.0.1:9000/alerts".to_string()),
+            ..Default::default()
+        });
+        // No alert while the baseline warms up, however odd the values
+        assert_eq!(alerts.check("dev", 10.0), None);
+        assert_eq!(alerts.check("dev", 500.0), None);
+        assert_eq!(alerts.check("dev", 10.0), None);
+
+        // Baseline: 10, then 255, then 132.5
+        assert_eq!(alerts.check("dev", 1000.0), None);
+        let raised = alerts.check("dev", 6000.0).unwrap();
+        assert_eq!(raised.breach, Some(Breach::Baseline(566.25)));
+        let payload = alerts.payload("rate", &raised, 0);
+        assert_eq!(payload["limit"], 5662.5);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let new = |config: AggregateAlertConfig| AlertRule::new(&config, |t| t.to_string());
+        assert!(new(AggregateAlertConfig {
+            topic: Some("alerts".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(new(AggregateAlertConfig {
+            above: Some(1.0),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(new(AggregateAlertConfig {
+            above: Some(1.0),
+            webhook: Some("https://alerts.example.com".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(new(AggregateAlertConfig {
+            above: Some(1.0),
+            topic: Some("alerts/#".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,513 @@
+//! Aggregation Windows
+//!
+//! Edge analytics without a stream processor next to the broker: each
+//! `[[aggregate]]` entry subscribes to a topic filter, extracts a number
+//! from every message (the payload itself or a JSON field), and keeps
+//! count, sum, min and max per key (a topic level, e.g. the device ID) over
+//! the window. Every `slide` the result for each key that saw messages is
+//! published to the output topic as JSON (or CBOR or MessagePack, see
+//! [`crate::payload`], which also decodes binary payloads):
+//!
+//! ```json
+//! {"key": "dev1", "function": "avg", "value": 21.5, "count": 12,
+//!  "window_start": 1714566840, "window_end": 1714566900}
+//! ```
+//!
+//! Windows advance on wall-clock multiples of `slide`. A sliding window is
+//! kept as `window / slide` buckets, so advancing drops the oldest bucket
+//! instead of re-reading messages.
+//!
+//! With an `[aggregate.alert]` section, results are also checked for
+//! anomalies (see [`alert`]) and alerts published and/or POSTed to a
+//! webhook.
+
+pub mod alert;
+
+pub use alert::{Alert, AlertRule, Alerts, Breach};
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tracing::{debug, warn};
+
+use crate::broker::{Broker, LocalPublish, LocalPublisher};
+use crate::config::{AggregateConfig, AggregateFunction, PayloadFormat};
+use crate::payload::{self, PayloadCodec, PayloadError};
+use crate::protocol::QoS;
+use crate::topic::{validate_topic_filter, validate_topic_name};
+
+/// Why an aggregation is invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AggregateError {
+    InvalidFilter(&'static str),
+    InvalidOutput(&'static str),
+    /// `{key}` in the output without a `key_level`
+    MissingKeyLevel,
+    /// Window or slide is zero, or the slide doesn't divide the window
+    InvalidWindow,
+    InvalidQos(u8),
+    InvalidAlert(String),
+    InvalidPayload(PayloadError),
+}
+
+impl fmt::Display for AggregateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AggregateError::InvalidFilter(e) => write!(f, "invalid filter: {}", e),
+            AggregateError::InvalidOutput(e) => write!(f, "invalid output topic: {}", e),
+            AggregateError::MissingKeyLevel => write!(f, "output uses {{key}} without key_level"),
+            AggregateError::InvalidWindow => write!(
+                f,
+                "window and slide must be at least 1s and slide must divide window"
+            ),
+            AggregateError::InvalidQos(qos) => write!(f, "qos must be 0, 1, or 2, got {}", qos),
+            AggregateError::InvalidAlert(e) => write!(f, "invalid alert: {}", e),
+            AggregateError::InvalidPayload(e) => write!(f, "invalid payload: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AggregateError {}
+
+/// Running summary of one bucket or window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    /// Messages seen
+    pub count: u64,
+    /// Numeric values seen (the rest count towards `count` only)
+    pub values: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            values: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Stats {
+    fn add(&mut self, value: Option<f64>) {
+        self.count += 1;
+        if let Some(v) = value {
+            self.values += 1;
+            self.sum += v;
+            self.min = self.min.min(v);
+            self.max = self.max.max(v);
+        }
+    }
+
+    fn merge(&mut self, other: &Stats) {
+        self.count += other.count;
+        self.values += other.values;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// The function's result (`None` if there was no numeric value)
+    pub fn result(&self, function: AggregateFunction) -> Option<f64> {
+        match function {
+            AggregateFunction::Count => Some(self.count as f64),
+            _ if self.values == 0 => None,
+            AggregateFunction::Sum => Some(self.sum),
+            AggregateFunction::Avg => Some(self.sum / self.values as f64),
+            AggregateFunction::Min => Some(self.min),
+            AggregateFunction::Max => Some(self.max),
+        }
+    }
+}
+
+/// Per-key buckets of a tumbling (1 bucket) or sliding window
+#[derive(Debug)]
+pub struct Windows {
+    buckets: usize,
+    keys: HashMap<String, VecDeque<Stats>>,
+}
+
+impl Windows {
+    pub fn new(buckets: usize) -> Self {
+        Self {
+            buckets: buckets.max(1),
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Count a message in the current bucket of `key`
+    pub fn record(&mut self, key: &str, value: Option<f64>) {
+        if !self.keys.contains_key(key) {
+            self.keys
+                .insert(key.to_string(), VecDeque::from([Stats::default()]));
+        }
+        if let Some(current) = self.keys.get_mut(key).and_then(|b| b.back_mut()) {
+            current.add(value);
+        }
+    }
+
+    /// Summarize each key's window, then start a new bucket
+    ///
+    /// Keys without messages in the whole window are forgotten.
+    pub fn advance(&mut self) -> Vec<(String, Stats)> {
+        let mut results = Vec::new();
+        let buckets = self.buckets;
+        self.keys.retain(|key, window| {
+            let mut total = Stats::default();
+            for bucket in window.iter() {
+                total.merge(bucket);
+            }
+            if total.count > 0 {
+                results.push((key.clone(), total));
+            }
+            window.push_back(Stats::default());
+            while window.len() > buckets {
+                window.pop_front();
+            }
+            window.iter().any(|b| b.count > 0)
+        });
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        results
+    }
+}
+
+/// A validated `[[aggregate]]` entry
+#[derive(Debug, Clone)]
+pub struct Aggregation {
+    name: String,
+    filter: String,
+    key_level: Option<usize>,
+    field: Option<Vec<String>>,
+    payload: PayloadCodec,
+    function: AggregateFunction,
+    window: Duration,
+    slide: Duration,
+    output: String,
+    qos: QoS,
+    retain: bool,
+    username: Option<String>,
+    alert: Option<AlertRule>,
+}
+
+impl Aggregation {
+    pub fn new(config: &AggregateConfig) -> Result<Self, AggregateError> {
+        validate_topic_filter(&config.filter).map_err(AggregateError::InvalidFilter)?;
+        if config.output.contains("{key}") && config.key_level.is_none() {
+            return Err(AggregateError::MissingKeyLevel);
+        }
+        let slide = config.slide();
+        if config.window.as_secs() == 0
+            || slide.as_secs() == 0
+            || !config.window.as_millis().is_multiple_of(slide.as_millis())
+        {
+            return Err(AggregateError::InvalidWindow);
+        }
+        let qos = QoS::from_u8(config.qos).ok_or(AggregateError::InvalidQos(config.qos))?;
+        let mut aggregation = Self {
+            name: config.name.clone(),
+            filter: config.filter.clone(),
+            key_level: config.key_level,
+            field: config
+                .field
+                .as_ref()
+                .map(|f| f.split('.').map(String::from).collect()),
+            payload: PayloadCodec::new(&config.payload).map_err(AggregateError::InvalidPayload)?,
+            function: config.function,
+            window: config.window,
+            slide,
+            output: config.output.clone(),
+            qos,
+            retain: config.retain,
+            username: config.username.clone(),
+            alert: None,
+        };
+        validate_topic_name(&aggregation.output_topic("key"))
+            .map_err(AggregateError::InvalidOutput)?;
+        if let Some(ref alert) = config.alert {
+            if alert.topic.as_ref().is_some_and(|t| t.contains("{key}"))
+                && config.key_level.is_none()
+            {
+                return Err(AggregateError::MissingKeyLevel);
+            }
+            let topic = |template: &str| aggregation.render(template, "key");
+            aggregation.alert = Some(AlertRule::new(alert, topic)?);
+        }
+        Ok(aggregation)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Window key of a topic (`None` if it has too few levels)
+    fn key<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        match self.key_level {
+            Some(level) => topic.split('/').nth(level),
+            None => Some(""),
+        }
+    }
+
+    /// Numeric value of a payload, if it has one
+    fn value(&self, payload: &[u8]) -> Option<f64> {
+        match self.field {
+            None if self.payload.format() == PayloadFormat::Json => {
+                std::str::from_utf8(payload).ok()?.trim().parse().ok()
+            }
+            ref path => {
+                let decoded = self.payload.decode(payload)?;
+                payload::number(&decoded, path.as_deref().unwrap_or_default())
+            }
+        }
+    }
+
+    fn output_topic(&self, key: &str) -> String {
+        self.render(&self.output, key)
+    }
+
+    /// Expand `{key}` and `{name}` in a topic template
+    fn render(&self, template: &str, key: &str) -> String {
+        template.replace("{key}", key).replace("{name}", &self.name)
+    }
+
+    /// Result message for one key's window ending at `end` (Unix seconds)
+    fn message(&self, key: &str, stats: &Stats, end: u64) -> Option<LocalPublish> {
+        let payload = serde_json::json!({
+            "key": key,
+            "function": self.function.as_str(),
+            "value": stats.result(self.function)?,
+            "count": stats.count,
+            "window_start": end.saturating_sub(self.window.as_secs()),
+            "window_end": end,
+        });
+        Some(
+            LocalPublish::new(self.output_topic(key), self.payload.encode(&payload))
+                .with_qos(self.qos)
+                .with_retain(self.retain),
+        )
+    }
+
+    /// Subscribe and publish results until the broker shuts down
+    pub async fn run(self, broker: Arc<Broker>) {
+        let mut publisher = broker.local_publisher(&format!("aggregate:{}", self.name));
+        if let Some(ref username) = self.username {
+            publisher = publisher.with_username(username.clone());
+        }
+        let mut subscription = match publisher.subscribe(&self.filter, QoS::AtMostOnce).await {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                warn!("Aggregation '{}' cannot subscribe: {}", self.name, e);
+                return;
+            }
+        };
+        let mut shutdown = broker.subscribe_shutdown();
+
+        // Advance on wall-clock multiples of the slide
+        let slide_ms = self.slide.as_millis() as u64;
+        let since_boundary = unix_ms() % slide_ms;
+        let start = tokio::time::Instant::now() + Duration::from_millis(slide_ms - since_boundary);
+        let mut ticks = tokio::time::interval_at(start, self.slide);
+        let mut windows = Windows::new((self.window.as_millis() / self.slide.as_millis()) as usize);
+        let mut alerts = self.alert.clone().map(Alerts::new);
+
+        loop {
+            tokio::select! {
+                message = subscription.recv() => {
+                    let Some(publish) = message else {
+                        warn!("Aggregation '{}' lost its subscription", self.name);
+                        return;
+                    };
+                    match self.key(&publish.topic) {
+                        Some(key) => windows.record(key, self.value(&publish.payload)),
+                        None => debug!("Aggregation '{}': {} has no key level", self.name, publish.topic),
+                    }
+                }
+                _ = ticks.tick() => {
+                    let end = (unix_ms() + slide_ms / 2) / slide_ms * slide_ms / 1000;
+                    for (key, stats) in windows.advance() {
+                        if let Some(message) = self.message(&key, &stats, end) {
+                            self.publish(&publisher, message).await;
+                        }
+                        let (Some(alerts), Some(value)) = (&mut alerts, stats.result(self.function)) else {
+                            continue;
+                        };
+                        if let Some(alert) = alerts.check(&key, value) {
+                            self.raise(&publisher, alerts, &alert, end).await;
+                        }
+                    }
+                }
+                _ = shutdown.recv() => return,
+            }
+        }
+    }
+
+    async fn publish(&self, publisher: &LocalPublisher, message: LocalPublish) {
+        if let Err(e) = publisher.publish(message).await {
+            warn!("Aggregation '{}' failed to publish: {}", self.name, e);
+        }
+    }
+
+    /// Publish an alert and POST it to the webhook
+    async fn raise(&self, publisher: &LocalPublisher, alerts: &Alerts, alert: &Alert, end: u64) {
+        let payload = alerts.payload(&self.name, alert, end);
+        debug!("Aggregation '{}' alert: {}", self.name, payload);
+        let rule = alerts.rule();
+        if let Some(ref topic) = rule.topic {
+            let message = LocalPublish::new(
+                self.render(topic, &alert.key),
+                self.payload.encode(&payload),
+            )
+            .with_qos(rule.qos);
+            self.publish(publisher, message).await;
+        }
+        if let Some(ref webhook) = rule.webhook {
+            // Don't hold up the window for a slow webhook; always JSON
+            let payload = payload.to_string();
+            let (webhook, timeout, name) =
+                (webhook.clone(), rule.webhook_timeout, self.name.clone());
+            tokio::spawn(async move {
+                match tokio::time::timeout(timeout, webhook.post_json(payload.as_bytes())).await {
+                    Ok(Ok((status, _))) if (200..300).contains(&status) => {}
+                    Ok(Ok((status, _))) => {
+                        warn!("Aggregation '{}' alert webhook returned {}", name, status)
+                    }
+                    Ok(Err(e)) => warn!("Aggregation '{}' alert webhook failed: {}", name, e),
+                    Err(_) => warn!("Aggregation '{}' alert webhook timed out", name),
+                }
+            });
+        }
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aggregation(field: Option<&str>) -> Aggregation {
+        Aggregation::new(&AggregateConfig {
+            name: "temp".to_string(),
+            filter: "sensors/+/temp".to_string(),
+            key_level: Some(1),
+            field: field.map(String::from),
+            function: AggregateFunction::Avg,
+            output: "analytics/{key}/{name}".to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_values_and_keys() {
+        let a = aggregation(None);
+        assert_eq!(a.value(b" 21.5\n"), Some(21.5));
+        assert_eq!(a.value(b"warm"), None);
+        assert_eq!(a.key("sensors/dev1/temp"), Some("dev1"));
+        assert_eq!(a.output_topic("dev1"), "analytics/dev1/temp");
+
+        let a = aggregation(Some("reading.celsius"));
+        assert_eq!(a.value(br#"{"reading": {"celsius": 19}}"#), Some(19.0));
+        assert_eq!(a.value(br#"{"reading": {"celsius": "19.5"}}"#), Some(19.5));
+        assert_eq!(a.value(br#"{"reading": 19}"#), None);
+        assert_eq!(a.value(b"19"), None);
+
+        let a = Aggregation::new(&AggregateConfig {
+            name: "temp".to_string(),
+            filter: "sensors/+/temp".to_string(),
+            payload: crate::config::PayloadConfig {
+                format: PayloadFormat::Msgpack,
+                ..Default::default()
+            },
+            output: "analytics/temp".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        // The whole payload: 21.5 as a float64
+        assert_eq!(
+            a.value(&[0xcb, 0x40, 0x35, 0x80, 0, 0, 0, 0, 0]),
+            Some(21.5)
+        );
+        assert_eq!(a.value(b"21.5"), None);
+    }
+
+    #[test]
+    fn test_tumbling_window() {
+        let mut windows = Windows::new(1);
+        windows.record("a", Some(1.0));
+        windows.record("a", Some(3.0));
+        windows.record("a", None);
+        windows.record("b", Some(-2.0));
+
+        let results = windows.advance();
+        assert_eq!(results.len(), 2);
+        let (ref key, stats) = results[0];
+        assert_eq!(key, "a");
+        assert_eq!(stats.result(AggregateFunction::Count), Some(3.0));
+        assert_eq!(stats.result(AggregateFunction::Sum), Some(4.0));
+        assert_eq!(stats.result(AggregateFunction::Avg), Some(2.0));
+        assert_eq!(stats.result(AggregateFunction::Min), Some(1.0));
+        assert_eq!(stats.result(AggregateFunction::Max), Some(3.0));
+
+        // Nothing new: quiet keys are dropped
+        assert!(windows.advance().is_empty());
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let mut windows = Windows::new(3);
+        windows.record("a", Some(1.0));
+        assert_eq!(windows.advance()[0].1.sum, 1.0);
+        windows.record("a", Some(2.0));
+        assert_eq!(windows.advance()[0].1.sum, 3.0);
+        assert_eq!(windows.advance()[0].1.sum, 3.0);
+        // The first bucket slides out
+        assert_eq!(windows.advance()[0].1.sum, 2.0);
+        assert!(windows.advance().is_empty());
+    }
+
+    #[test]
+    fn test_no_values() {
+        let stats = Stats {
+            count: 2,
+            ..Default::default()
+        };
+        assert_eq!(stats.result(AggregateFunction::Count), Some(2.0));
+        assert_eq!(stats.result(AggregateFunction::Avg), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let config = AggregateConfig {
+            name: "x".to_string(),
+            filter: "sensors/#".to_string(),
+            output: "out/{key}".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            Aggregation::new(&config).unwrap_err(),
+            AggregateError::MissingKeyLevel
+        );
+        let config = AggregateConfig {
+            output: "out".to_string(),
+            window: Duration::from_secs(60),
+            slide: Some(Duration::from_secs(7)),
+            ..config
+        };
+        assert_eq!(
+            Aggregation::new(&config).unwrap_err(),
+            AggregateError::InvalidWindow
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Pluggable Authentication Backends
+//!
+//! An `Authenticator` checks the credentials a client presents at CONNECT.
+//! `AuthProvider` consults its static user list first, then each configured
+//! authenticator in order until one reaches a decision.
+
+use async_trait::async_trait;
+
+use crate::hooks::{ConnectionMetadata, HookError, HookResult};
+
+/// Credentials and connection details presented at CONNECT
+#[derive(Debug, Clone, Copy)]
+pub struct AuthRequest<'a> {
+    /// Client identifier (tenant-qualified when the client has a tenant)
+    pub client_id: &'a str,
+    /// Username from the CONNECT packet
+    pub username: Option<&'a str>,
+    /// Password from the CONNECT packet
+    pub password: Option<&'a [u8]>,
+    /// Network and TLS details; `client_cert_cn` is the certificate identity
+    pub metadata: &'a ConnectionMetadata,
+}
+
+/// What an authenticator decided about a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthDecision {
+    /// Credentials accepted
+    Allow,
+    /// The client is unknown to this authenticator; ask the next one
+    Ignore,
+    /// Wrong username or password (CONNACK 0x86, v3 return code 4)
+    BadCredentials,
+    /// Known client that may not connect (CONNACK 0x87, v3 return code 5)
+    NotAuthorized,
+    /// Client is banned (CONNACK 0x8A)
+    Banned,
+}
+
+impl AuthDecision {
+    /// Hook result for a final decision
+    ///
+    /// Denials map to the `HookError` the CONNECT handler turns into the
+    /// matching CONNACK reason code.
+    pub(crate) fn into_hook_result(self) -> HookResult<bool> {
+        match self {
+            AuthDecision::Allow => Ok(true),
+            AuthDecision::Ignore | AuthDecision::NotAuthorized => Ok(false),
+            AuthDecision::BadCredentials => Err(HookError::AuthenticationFailed),
+            AuthDecision::Banned => Err(HookError::Banned),
+        }
+    }
+}
+
+/// Authentication backend
+///
+/// Return `Err(HookError::Unavailable)` when the backend can't be reached;
+/// the client is refused with "Server unavailable" rather than treated as
+/// having bad credentials.
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> &str;
+
+    /// Check a client's credentials
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> HookResult<AuthDecision>;
+}
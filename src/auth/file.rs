@@ -0,0 +1,254 @@
+//! Password File Authenticator
+//!
+//! Reads `username:hash` lines in the format written by `mosquitto_passwd`.
+//! Supported hashes are argon2 PHC strings (`$argon2id$...`) and mosquitto's
+//! `$7$` (PBKDF2-HMAC-SHA512) and `$6$` (salted SHA-512). bcrypt hashes are
+//! rejected when the file is loaded. Hashes are checked on the blocking
+//! thread pool, as argon2 and PBKDF2 are deliberately slow.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use argon2::{Argon2, PasswordVerifier};
+use async_trait::async_trait;
+use base64ct::{Base64, Encoding};
+use sha2::{Digest, Sha512};
+use tracing::warn;
+
+use super::authenticator::{AuthDecision, AuthRequest, Authenticator};
+use crate::hooks::HookResult;
+
+/// A password hash from the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StoredHash {
+    /// argon2 PHC string
+    Argon2(String),
+    /// `$7$<iterations>$<salt>$<hash>`
+    Pbkdf2Sha512 {
+        iterations: u32,
+        salt: Vec<u8>,
+        hash: Vec<u8>,
+    },
+    /// `$6$<salt>$<hash>`: SHA-512 of password then salt
+    Sha512 { salt: Vec<u8>, hash: Vec<u8> },
+}
+
+impl StoredHash {
+    /// Parse a hash field
+    fn parse(field: &str) -> Result<Self, String> {
+        let decode = |s: &str| Base64::decode_vec(s).map_err(|_| "invalid base64".to_string());
+        // An empty or truncated hash would compare equal to a truncated
+        // derivation, so anything shorter than a SHA-512 digest is refused
+        let digest = |s: &str| {
+            let hash = decode(s)?;
+            if hash.len() < Sha512::output_size() {
+                return Err("hash is shorter than a SHA-512 digest".to_string());
+            }
+            Ok(hash)
+        };
+
+        if field.starts_with("$argon2") {
+            argon2::PasswordHash::new(field).map_err(|e| e.to_string())?;
+            return Ok(StoredHash::Argon2(field.to_string()));
+        }
+        if field.starts_with("$2a$") || field.starts_with("$2b$") || field.starts_with("$2y$") {
+            return Err("bcrypt hashes are not supported (use argon2 or mosquitto_passwd)".into());
+        }
+
+        let parts: Vec<&str> = field.split('$').collect();
+        match parts.as_slice() {
+            ["", "7", iterations, salt, hash] => Ok(StoredHash::Pbkdf2Sha512 {
+                iterations: iterations
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or("invalid iteration count")?,
+                salt: decode(salt)?,
+                hash: digest(hash)?,
+            }),
+            ["", "6", salt, hash] => Ok(StoredHash::Sha512 {
+                salt: decode(salt)?,
+                hash: digest(hash)?,
+            }),
+            _ => Err("unrecognized password hash".into()),
+        }
+    }
+
+    /// Check a password against the hash
+    fn verify(&self, password: &[u8]) -> bool {
+        match self {
+            StoredHash::Argon2(phc) => argon2::PasswordHash::new(phc)
+                .map(|hash| Argon2::default().verify_password(password, &hash).is_ok())
+                .unwrap_or(false),
+            StoredHash::Pbkdf2Sha512 {
+                iterations,
+                salt,
+                hash,
+            } => {
+                let mut derived = vec![0u8; hash.len()];
+                pbkdf2::pbkdf2_hmac::<Sha512>(password, salt, *iterations, &mut derived);
+                constant_time_eq(&derived, hash)
+            }
+            StoredHash::Sha512 { salt, hash } => {
+                let digest = Sha512::new()
+                    .chain_update(password)
+                    .chain_update(salt)
+                    .finalize();
+                constant_time_eq(&digest, hash)
+            }
+        }
+    }
+}
+
+/// Compare without an early exit on the first differing byte
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Authenticates users listed in a password file
+pub struct PasswordFileAuthenticator {
+    /// File the users were loaded from (for logs)
+    path: PathBuf,
+    /// Username -> password hash
+    users: HashMap<String, StoredHash>,
+}
+
+impl PasswordFileAuthenticator {
+    /// Load a password file; a malformed line fails the whole file
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        let users = Self::parse(&contents).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            users,
+        })
+    }
+
+    /// Parse `username:hash` lines, skipping blank lines and `#` comments
+    fn parse(contents: &str) -> Result<HashMap<String, StoredHash>, String> {
+        let mut users = HashMap::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (username, hash) = line
+                .split_once(':')
+                .filter(|(username, _)| !username.is_empty())
+                .ok_or_else(|| format!("line {}: expected 'username:hash'", number + 1))?;
+            let hash =
+                StoredHash::parse(hash).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            users.insert(username.to_string(), hash);
+        }
+        Ok(users)
+    }
+
+    /// Number of users in the file
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    /// Whether the file lists no users
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+}
+
+#[async_trait]
+impl Authenticator for PasswordFileAuthenticator {
+    fn name(&self) -> &str {
+        self.path.to_str().unwrap_or("password_file")
+    }
+
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> HookResult<AuthDecision> {
+        let Some(hash) = request.username.and_then(|u| self.users.get(u)) else {
+            return Ok(AuthDecision::Ignore);
+        };
+        let (hash, password) = (hash.clone(), request.password.unwrap_or(&[]).to_vec());
+        let verified = tokio::task::spawn_blocking(move || hash.verify(&password))
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Password check failed: {}", e);
+                false
+            });
+        Ok(if verified {
+            AuthDecision::Allow
+        } else {
+            AuthDecision::BadCredentials
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::ConnectionMetadata;
+
+    // mosquitto_passwd-style hashes of "secret" with salt "0123456789ab"
+    const PBKDF2_LINE: &str = "carol:$7$101$MDEyMzQ1Njc4OWFi$EO/lLlkeUgIiBaS8G8UK0ZMP1u508TA7Tl+AdJ1cEsmlbGyEPAERErpfq84j1kepISs0UzmcdL4ucgZ2uodxfQ==";
+    const SHA512_LINE: &str = "dave:$6$MDEyMzQ1Njc4OWFi$qEXipeLbgxRlwd06QHfY5WITkUZg0jLg9SZbXzq3ifXjfj+v3GbJGrSfC5PAg3UNCS+UFfbhUIZX4bmIAs330w==";
+
+    #[tokio::test]
+    async fn test_password_file() {
+        let contents = format!(
+            "# users\n\nalice:{}\n{}\n{}\n",
+            "$argon2id$v=19$m=19456,t=2,p=1$3QUugnyLZGsTrETNoga03Q$Tnmpw8w1t/PzI36MTps259IB7ntGAb4NA0KlYD9Yzlw",
+            PBKDF2_LINE,
+            SHA512_LINE
+        );
+        let auth = PasswordFileAuthenticator {
+            path: PathBuf::from("passwd"),
+            users: PasswordFileAuthenticator::parse(&contents).unwrap(),
+        };
+        assert_eq!(auth.len(), 3);
+
+        let metadata = ConnectionMetadata::default();
+        let check = |username: &'static str, password: &'static [u8]| {
+            let auth = &auth;
+            let metadata = &metadata;
+            async move {
+                auth.authenticate(&AuthRequest {
+                    client_id: "c1",
+                    username: Some(username),
+                    password: Some(password),
+                    metadata,
+                })
+                .await
+                .unwrap()
+            }
+        };
+        for user in ["alice", "carol", "dave"] {
+            assert_eq!(check(user, b"secret").await, AuthDecision::Allow);
+            assert_eq!(check(user, b"wrong").await, AuthDecision::BadCredentials);
+        }
+        assert_eq!(check("eve", b"secret").await, AuthDecision::Ignore);
+    }
+
+    #[test]
+    fn test_password_file_errors() {
+        let err =
+            PasswordFileAuthenticator::parse("alice:$2b$12$abcdefghijklmnopqrstuv").unwrap_err();
+        assert!(err.contains("line 1") && err.contains("bcrypt"), "{}", err);
+        assert!(PasswordFileAuthenticator::parse("\nno-separator")
+            .unwrap_err()
+            .contains("line 2"));
+        assert!(PasswordFileAuthenticator::parse("bob:plaintext").is_err());
+        assert!(PasswordFileAuthenticator::parse("bob:$7$0$MDEy$MDEy").is_err());
+        // An empty or truncated hash would accept any password
+        for line in [
+            "eve:$7$101$c2FsdA==$",
+            "eve:$7$101$c2FsdA==$MDEy",
+            "eve:$6$c2FsdA==$",
+        ] {
+            let err = PasswordFileAuthenticator::parse(line).unwrap_err();
+            assert!(err.contains("shorter than a SHA-512 digest"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,446 @@
+//! HTTP Webhook Authenticator
+//!
+//! POSTs each CONNECT as JSON to an external service:
+//!
+//! ```json
+//! {"client_id": "sensor-1", "username": "alice", "password": "secret",
+//!  "client_ip": "203.0.113.7", "listener": "tls", "client_cert_cn": "sensor-1"}
+//! ```
+//!
+//! The connection metadata fields are those selected by
+//! `auth.metadata_fields`. The reply status decides:
+//! - 200 or 204: allow; a 200 body of `{"result": "..."}` can instead answer
+//!   `deny`, `not_authorized`, `banned` or `ignore` (ask the next backend)
+//! - 401: bad credentials; 403: not authorized
+//! - anything else, a timeout or a connection failure: the service is
+//!   unavailable and the client is refused with "Server unavailable"
+//!
+//! Answers are cached per distinct request body for `cache_ttl`.
+
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::authenticator::{AuthDecision, AuthRequest, Authenticator};
+use crate::config::HttpAuthConfig;
+use crate::hooks::{ConnectionMetadata, HookError, HookResult};
+
+/// Largest reply read from the service
+const MAX_RESPONSE_SIZE: usize = 64 * 1024;
+
+/// Maximum response headers parsed
+const MAX_HEADERS: usize = 32;
+
+/// Request body sent to the service
+#[derive(Serialize)]
+struct AuthPayload<'a> {
+    client_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<&'a str>,
+    /// Omitted when absent or not valid UTF-8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<&'a str>,
+    #[serde(flatten)]
+    metadata: &'a ConnectionMetadata,
+}
+
+/// Optional decision in a 200 reply body
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ReplyResult {
+    Allow,
+    Deny,
+    NotAuthorized,
+    Banned,
+    Ignore,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    result: Option<ReplyResult>,
+}
+
+/// An http:// URL that JSON bodies are POSTed to
+#[derive(Debug, Clone)]
+pub(crate) struct HttpEndpoint {
+    /// Host and port to connect to
+    host: String,
+    port: u16,
+    /// Host header value
+    authority: String,
+    /// Request path and query
+    path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpEndpoint {
+    pub(crate) fn parse(
+        url: &str,
+        headers: &HashMap<String, String>,
+    ) -> Result<Self, &'static str> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or("only http:// is supported")?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        // Bracketed IPv6 literals carry colons of their own
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                (host, port.parse().map_err(|_| "invalid port")?)
+            }
+            _ => (authority, 80),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err("missing host");
+        }
+
+        let mut headers: Vec<_> = headers
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        headers.sort();
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            authority: authority.to_string(),
+            path: path.to_string(),
+            headers,
+        })
+    }
+
+    /// POST a JSON body; returns the reply's status and body
+    pub(crate) async fn post_json(&self, body: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n",
+            self.path,
+            self.authority,
+            body.len()
+        );
+        for (name, value) in &self.headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        stream.write_all(request.as_bytes()).await?;
+        stream.write_all(body).await?;
+
+        let mut response = Vec::new();
+        (&mut stream)
+            .take(MAX_RESPONSE_SIZE as u64 + 1)
+            .read_to_end(&mut response)
+            .await?;
+        if response.len() > MAX_RESPONSE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "response too large",
+            ));
+        }
+        parse_response(&response)
+    }
+}
+
+/// Authenticates clients against an HTTP service
+pub struct HttpAuthenticator {
+    /// Configured URL (for logs)
+    url: String,
+    endpoint: HttpEndpoint,
+    timeout: Duration,
+    cache_ttl: Duration,
+    cache_size: usize,
+    /// SHA-256 of the request body -> (decision, when it was received)
+    cache: Mutex<HashMap<[u8; 32], (AuthDecision, Instant)>>,
+}
+
+impl HttpAuthenticator {
+    /// Create an authenticator for the configured endpoint
+    pub fn new(config: &HttpAuthConfig) -> io::Result<Self> {
+        let endpoint = HttpEndpoint::parse(&config.url, &config.headers).map_err(|msg| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("auth.http.url '{}': {}", config.url, msg),
+            )
+        })?;
+        Ok(Self {
+            url: config.url.clone(),
+            endpoint,
+            timeout: config.timeout,
+            cache_ttl: config.cache_ttl,
+            cache_size: config.cache_size,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Cached decision for a request body, if still fresh
+    fn cached(&self, key: &[u8; 32]) -> Option<AuthDecision> {
+        let cache = self.cache.lock();
+        cache
+            .get(key)
+            .filter(|(_, at)| at.elapsed() < self.cache_ttl)
+            .map(|(decision, _)| *decision)
+    }
+
+    /// Remember a decision, dropping stale entries when the cache is full
+    fn remember(&self, key: [u8; 32], decision: AuthDecision) {
+        if self.cache_ttl.is_zero() || self.cache_size == 0 {
+            return;
+        }
+        let mut cache = self.cache.lock();
+        if cache.len() >= self.cache_size {
+            cache.retain(|_, (_, at)| at.elapsed() < self.cache_ttl);
+            if cache.len() >= self.cache_size {
+                cache.clear();
+            }
+        }
+        cache.insert(key, (decision, Instant::now()));
+    }
+
+    /// POST the body and map the reply to a decision
+    async fn call(&self, body: &[u8]) -> io::Result<AuthDecision> {
+        let (status, body) = self.endpoint.post_json(body).await?;
+        Ok(match status {
+            200 => match serde_json::from_slice::<Reply>(&body)
+                .ok()
+                .and_then(|r| r.result)
+            {
+                None | Some(ReplyResult::Allow) => AuthDecision::Allow,
+                Some(ReplyResult::Deny) => AuthDecision::BadCredentials,
+                Some(ReplyResult::NotAuthorized) => AuthDecision::NotAuthorized,
+                Some(ReplyResult::Banned) => AuthDecision::Banned,
+                Some(ReplyResult::Ignore) => AuthDecision::Ignore,
+            },
+            204 => AuthDecision::Allow,
+            401 => AuthDecision::BadCredentials,
+            403 => AuthDecision::NotAuthorized,
+            status => {
+                return Err(io::Error::other(format!("unexpected status {}", status)));
+            }
+        })
+    }
+}
+
+/// Status code and (de-chunked) body of an HTTP/1.1 response
+fn parse_response(raw: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut response = httparse::Response::new(&mut headers);
+    let header_len = match response.parse(raw) {
+        Ok(httparse::Status::Complete(len)) => len,
+        Ok(httparse::Status::Partial) => return Err(invalid("truncated response")),
+        Err(e) => return Err(invalid(&e.to_string())),
+    };
+    let status = response.code.ok_or_else(|| invalid("missing status"))?;
+    let chunked = response.headers.iter().any(|h| {
+        h.name.eq_ignore_ascii_case("transfer-encoding")
+            && String::from_utf8_lossy(h.value)
+                .to_ascii_lowercase()
+                .contains("chunked")
+    });
+
+    let mut body = &raw[header_len..];
+    if !chunked {
+        return Ok((status, body.to_vec()));
+    }
+
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| invalid("truncated chunk"))?;
+        let size = std::str::from_utf8(&body[..line_end])
+            .ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok())
+            .ok_or_else(|| invalid("invalid chunk size"))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok((status, decoded));
+        }
+        if body.len() < size {
+            return Err(invalid("truncated chunk"));
+        }
+        decoded.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
+
+#[async_trait]
+impl Authenticator for HttpAuthenticator {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> HookResult<AuthDecision> {
+        let payload = AuthPayload {
+            client_id: request.client_id,
+            username: request.username,
+            password: request.password.and_then(|p| std::str::from_utf8(p).ok()),
+            metadata: request.metadata,
+        };
+        let body = serde_json::to_vec(&payload).map_err(|e| HookError::Internal(e.to_string()))?;
+
+        // Key on a digest so cached entries don't hold passwords
+        let key: [u8; 32] = Sha256::digest(&body).into();
+        if let Some(decision) = self.cached(&key) {
+            return Ok(decision);
+        }
+
+        let decision = match tokio::time::timeout(self.timeout, self.call(&body)).await {
+            Ok(Ok(decision)) => decision,
+            Ok(Err(e)) => return Err(HookError::Unavailable(format!("{}: {}", self.url, e))),
+            Err(_) => return Err(HookError::Unavailable(format!("{}: timed out", self.url))),
+        };
+        self.remember(key, decision);
+        Ok(decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Serve canned replies, keyed by username, and count requests
+    async fn auth_service(hits: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                hits.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    // Headers and body arrive in separate writes
+                    let mut buf = Vec::new();
+                    while !buf.ends_with(b"}") {
+                        let mut chunk = [0u8; 4096];
+                        match stream.read(&mut chunk).await.unwrap() {
+                            0 => return,
+                            n => buf.extend_from_slice(&chunk[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&buf).to_string();
+                    let reply = if request.contains("\"username\":\"alice\"") {
+                        assert!(request.contains("\"client_ip\":\"10.0.0.5\""));
+                        assert!(request.contains("X-Token: t0k3n"));
+                        "HTTP/1.1 204 No Content\r\n\r\n".to_string()
+                    } else if request.contains("\"username\":\"mallory\"") {
+                        let body = "{\"result\":\"banned\"}";
+                        // Chunked, as many frameworks send
+                        format!(
+                            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                            body.len(),
+                            body
+                        )
+                    } else if request.contains("\"username\":\"slow\"") {
+                        tokio::time::sleep(Duration::from_secs(2)).await;
+                        "HTTP/1.1 204 No Content\r\n\r\n".to_string()
+                    } else if request.contains("\"username\":\"broken\"") {
+                        "HTTP/1.1 500 Internal Server Error\r\n\r\n".to_string()
+                    } else {
+                        "HTTP/1.1 401 Unauthorized\r\n\r\n".to_string()
+                    };
+                    let _ = stream.write_all(reply.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}/mqtt/auth", addr)
+    }
+
+    #[tokio::test]
+    async fn test_http_authenticator() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = auth_service(hits.clone()).await;
+        let auth = HttpAuthenticator::new(&HttpAuthConfig {
+            url,
+            timeout: Duration::from_millis(300),
+            headers: [("X-Token".to_string(), "t0k3n".to_string())].into(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let metadata = ConnectionMetadata {
+            client_ip: Some("10.0.0.5".parse().unwrap()),
+            ..Default::default()
+        };
+        let request = |username| AuthRequest {
+            client_id: "c1",
+            username: Some(username),
+            password: Some(b"secret"),
+            metadata: &metadata,
+        };
+
+        assert_eq!(
+            auth.authenticate(&request("alice")).await.unwrap(),
+            AuthDecision::Allow
+        );
+        assert_eq!(
+            auth.authenticate(&request("eve")).await.unwrap(),
+            AuthDecision::BadCredentials
+        );
+        assert_eq!(
+            auth.authenticate(&request("mallory")).await.unwrap(),
+            AuthDecision::Banned
+        );
+        assert!(matches!(
+            auth.authenticate(&request("broken")).await,
+            Err(HookError::Unavailable(_))
+        ));
+        assert!(matches!(
+            auth.authenticate(&request("slow")).await,
+            Err(HookError::Unavailable(_))
+        ));
+
+        // Answers are cached; failures are not
+        let before = hits.load(Ordering::SeqCst);
+        auth.authenticate(&request("alice")).await.unwrap();
+        auth.authenticate(&request("eve")).await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), before);
+        let _ = auth.authenticate(&request("broken")).await;
+        assert_eq!(hits.load(Ordering::SeqCst), before + 1);
+    }
+
+    #[test]
+    fn test_http_authenticator_url() {
+        let new = |url: &str| {
+            HttpAuthenticator::new(&HttpAuthConfig {
+                url: url.to_string(),
+                ..Default::default()
+            })
+        };
+        let auth = new("http://auth.internal:8080/check?v=1").unwrap();
+        let endpoint = &auth.endpoint;
+        assert_eq!(
+            (endpoint.host.as_str(), endpoint.port),
+            ("auth.internal", 8080)
+        );
+        assert_eq!(endpoint.path, "/check?v=1");
+
+        let auth = new("http://[::1]").unwrap();
+        let endpoint = &auth.endpoint;
+        assert_eq!((endpoint.host.as_str(), endpoint.port), ("::1", 80));
+        assert_eq!(
+            (endpoint.authority.as_str(), endpoint.path.as_str()),
+            ("[::1]", "/")
+        );
+
+        assert!(new("https://auth.example.com/").is_err());
+        assert!(new("http://host:notaport/").is_err());
+    }
+}
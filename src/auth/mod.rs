@@ -3,30 +3,54 @@
 //! Provides username/password authentication with support for:
 //! - Plaintext passwords (for development/testing)
 //! - Argon2 password hashes (recommended for production)
+//! - Per-user tenants that scope client ID uniqueness
+//! - Pluggable backends (password files, HTTP webhooks) consulted after the
+//!   static user list
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use async_trait::async_trait;
 use parking_lot::RwLock;
+use tracing::debug;
 
 use crate::config::AuthConfig;
-use crate::hooks::{HookResult, Hooks};
+use crate::hooks::{ConnectionMetadata, HookResult, Hooks};
+use crate::session::split_tenant;
 
+mod authenticator;
+mod file;
+mod http;
 #[cfg(test)]
 mod tests;
 
+pub use authenticator::{AuthDecision, AuthRequest, Authenticator};
+pub(crate) use file::constant_time_eq;
+pub use file::PasswordFileAuthenticator;
+pub use http::HttpAuthenticator;
+pub(crate) use http::HttpEndpoint;
+
 /// Authentication provider
 pub struct AuthProvider {
+    /// Users and backends, replaced as a whole on reload
+    state: RwLock<AuthState>,
+    /// Connected client usernames (for ACL lookups)
+    client_usernames: Arc<RwLock<HashMap<String, Option<String>>>>,
+}
+
+/// Reloadable authentication settings
+struct AuthState {
     /// Whether auth is enabled
     enabled: bool,
     /// Allow anonymous connections
     allow_anonymous: bool,
     /// User credentials map (username -> UserEntry)
     users: HashMap<String, UserEntry>,
-    /// Connected client usernames (for ACL lookups)
-    client_usernames: Arc<RwLock<HashMap<String, Option<String>>>>,
+    /// Tenants named by any user
+    tenants: HashSet<String>,
+    /// Backends consulted, in order, for users not in the static list
+    authenticators: Vec<Arc<dyn Authenticator>>,
 }
 
 /// Credential storage type
@@ -43,11 +67,12 @@ struct UserEntry {
     credential: Credential,
     /// ACL role (if any)
     role: Option<String>,
+    /// Tenant (if any)
+    tenant: Option<String>,
 }
 
-impl AuthProvider {
-    /// Create a new auth provider from configuration
-    pub fn new(config: &AuthConfig) -> Self {
+impl AuthState {
+    fn new(config: &AuthConfig, authenticators: Vec<Arc<dyn Authenticator>>) -> Self {
         let mut users = HashMap::new();
 
         for user in &config.users {
@@ -65,26 +90,94 @@ impl AuthProvider {
                 UserEntry {
                     credential,
                     role: user.role.clone(),
+                    tenant: user.tenant.clone(),
                 },
             );
         }
 
+        let tenants = users
+            .values()
+            .filter_map(|user| user.tenant.clone())
+            .collect();
+
         Self {
             enabled: config.enabled,
             allow_anonymous: config.allow_anonymous,
             users,
+            tenants,
+            authenticators,
+        }
+    }
+
+    fn user_tenant(&self, username: &str) -> Option<&str> {
+        self.users.get(username).and_then(|u| u.tenant.as_deref())
+    }
+
+    /// Whether a client ID lies in the namespace of a tenant other than the
+    /// user's own (e.g. an untenanted client claiming `acme/sensor-1`)
+    fn is_foreign_tenant_id(&self, client_id: &str, username: Option<&str>) -> bool {
+        let Some((prefix, _)) = split_tenant(client_id) else {
+            return false;
+        };
+        let own = username.and_then(|u| self.user_tenant(u));
+        self.tenants.contains(prefix) && own != Some(prefix)
+    }
+}
+
+/// Build the backends an `[auth]` section configures (password file, then
+/// HTTP webhook)
+pub fn config_authenticators(config: &AuthConfig) -> Result<Vec<Arc<dyn Authenticator>>, String> {
+    let mut authenticators: Vec<Arc<dyn Authenticator>> = Vec::new();
+    if let Some(ref path) = config.password_file {
+        let passwords = PasswordFileAuthenticator::load(path)
+            .map_err(|e| format!("Error loading password file: {}", e))?;
+        authenticators.push(Arc::new(passwords));
+    }
+    if let Some(ref http) = config.http {
+        let webhook = HttpAuthenticator::new(http)
+            .map_err(|e| format!("Error configuring HTTP authentication: {}", e))?;
+        authenticators.push(Arc::new(webhook));
+    }
+    Ok(authenticators)
+}
+
+impl AuthProvider {
+    /// Create a new auth provider from configuration
+    pub fn new(config: &AuthConfig) -> Self {
+        Self {
+            state: RwLock::new(AuthState::new(config, Vec::new())),
             client_usernames: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
+    /// Add an authentication backend, consulted after earlier ones
+    pub fn with_authenticator(mut self, authenticator: Arc<dyn Authenticator>) -> Self {
+        self.state.get_mut().authenticators.push(authenticator);
+        self
+    }
+
+    /// Replace the users and backends (config reload)
+    ///
+    /// Connected clients stay connected; the new settings apply from their
+    /// next authentication and to ACL role lookups right away.
+    pub fn reload(&self, config: &AuthConfig, authenticators: Vec<Arc<dyn Authenticator>>) {
+        *self.state.write() = AuthState::new(config, authenticators);
+    }
+
     /// Check if auth is enabled
     pub fn is_enabled(&self) -> bool {
-        self.enabled
+        self.state.read().enabled
     }
 
     /// Get the ACL role for a username
-    pub fn get_user_role(&self, username: &str) -> Option<&str> {
-        self.users.get(username).and_then(|u| u.role.as_deref())
+    pub fn get_user_role(&self, username: &str) -> Option<String> {
+        let state = self.state.read();
+        state.users.get(username).and_then(|u| u.role.clone())
+    }
+
+    /// Get the tenant for a username
+    pub fn get_user_tenant(&self, username: &str) -> Option<String> {
+        self.state.read().user_tenant(username).map(str::to_string)
     }
 
     /// Get the username for a connected client
@@ -96,7 +189,7 @@ impl AuthProvider {
     }
 
     /// Verify a password against stored credential
-    fn verify_password(&self, password: &[u8], credential: &Credential) -> bool {
+    fn verify_password(password: &[u8], credential: &Credential) -> bool {
         match credential {
             Credential::Plaintext(stored) => {
                 // Compare plaintext password
@@ -128,48 +221,121 @@ impl AuthProvider {
     pub fn remove_client_username(&self, client_id: &str) {
         self.client_usernames.write().remove(client_id);
     }
+
+    /// Decide whether a client may connect
+    ///
+    /// Static users are checked first, then each authenticator in order; the
+    /// first that doesn't answer `Ignore` decides. Clients nobody recognizes
+    /// are anonymous (allowed if `allow_anonymous`) or have bad credentials.
+    pub async fn authenticate(&self, request: &AuthRequest<'_>) -> HookResult<AuthDecision> {
+        let mut decision = AuthDecision::Ignore;
+        let (allow_anonymous, authenticators) = {
+            let state = self.state.read();
+
+            // If auth is disabled, allow all
+            if !state.enabled {
+                self.store_client_username(request.client_id, request.username);
+                return Ok(AuthDecision::Allow);
+            }
+
+            // Tenant namespaces are reserved for the tenant's own users
+            if state.is_foreign_tenant_id(request.client_id, request.username) {
+                return Ok(AuthDecision::NotAuthorized);
+            }
+
+            if let Some(user) = request.username.and_then(|u| state.users.get(u)) {
+                decision =
+                    if Self::verify_password(request.password.unwrap_or(&[]), &user.credential) {
+                        AuthDecision::Allow
+                    } else {
+                        AuthDecision::BadCredentials
+                    };
+            }
+            // Backends are awaited without holding the lock
+            (state.allow_anonymous, state.authenticators.clone())
+        };
+        for authenticator in &authenticators {
+            if decision != AuthDecision::Ignore {
+                break;
+            }
+            decision = authenticator.authenticate(request).await?;
+            if decision != AuthDecision::Ignore {
+                debug!(
+                    "Authenticator {} decided {:?} for {}",
+                    authenticator.name(),
+                    decision,
+                    request.client_id
+                );
+            }
+        }
+
+        // Check for anonymous connection
+        if decision == AuthDecision::Ignore {
+            decision = match (request.username, allow_anonymous) {
+                (None, true) => AuthDecision::Allow,
+                (None, false) => AuthDecision::NotAuthorized,
+                (Some(_), _) => AuthDecision::BadCredentials,
+            };
+        }
+
+        if decision == AuthDecision::Allow {
+            self.store_client_username(request.client_id, request.username);
+        }
+        Ok(decision)
+    }
 }
 
 #[async_trait]
 impl Hooks for AuthProvider {
+    fn hook_name(&self) -> String {
+        "auth".to_string()
+    }
+
     async fn on_authenticate(
         &self,
         client_id: &str,
         username: Option<&str>,
         password: Option<&[u8]>,
     ) -> HookResult<bool> {
-        // If auth is disabled, allow all
-        if !self.enabled {
-            self.store_client_username(client_id, username);
-            return Ok(true);
-        }
-
-        // Check for anonymous connection
-        if username.is_none() {
-            if self.allow_anonymous {
-                self.store_client_username(client_id, None);
-                return Ok(true);
-            } else {
-                return Ok(false);
-            }
-        }
-
-        let username = username.unwrap();
-        let password = password.unwrap_or(&[]);
+        let metadata = ConnectionMetadata::default();
+        let decision = self
+            .authenticate(&AuthRequest {
+                client_id,
+                username,
+                password,
+                metadata: &metadata,
+            })
+            .await?;
+        Ok(decision == AuthDecision::Allow)
+    }
 
-        // Look up user
-        let user = match self.users.get(username) {
-            Some(u) => u,
-            None => return Ok(false),
-        };
+    async fn on_authenticate_with_metadata(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        password: Option<&[u8]>,
+        metadata: &ConnectionMetadata,
+    ) -> HookResult<bool> {
+        self.authenticate(&AuthRequest {
+            client_id,
+            username,
+            password,
+            metadata,
+        })
+        .await?
+        .into_hook_result()
+    }
 
-        // Verify password
-        if self.verify_password(password, &user.credential) {
-            self.store_client_username(client_id, Some(username));
-            Ok(true)
-        } else {
-            Ok(false)
+    async fn on_client_tenant(
+        &self,
+        _client_id: &str,
+        username: Option<&str>,
+    ) -> HookResult<Option<String>> {
+        // Tenants come from the user list, which only applies with auth enabled
+        if !self.is_enabled() {
+            return Ok(None);
         }
+        Ok(username.and_then(|u| self.get_user_tenant(u)))
     }
 
     async fn on_client_disconnected(&self, client_id: &str, _graceful: bool) {
@@ -8,6 +8,7 @@ fn make_auth_config(enabled: bool, allow_anonymous: bool, users: Vec<UserConfig>
         enabled,
         allow_anonymous,
         users,
+        ..Default::default()
     }
 }
 
@@ -17,6 +18,7 @@ fn make_user_plaintext(username: &str, password: &str, role: Option<&str>) -> Us
         password: Some(password.to_string()),
         password_hash: None,
         role: role.map(|s| s.to_string()),
+        tenant: None,
     }
 }
 
@@ -26,6 +28,7 @@ fn make_user_hashed(username: &str, password_hash: &str, role: Option<&str>) ->
         password: None,
         password_hash: Some(password_hash.to_string()),
         role: role.map(|s| s.to_string()),
+        tenant: None,
     }
 }
 
@@ -150,7 +153,10 @@ fn test_get_user_role() {
     );
     let provider = AuthProvider::new(&config);
 
-    assert_eq!(provider.get_user_role("admin"), Some("admin_role"));
+    assert_eq!(
+        provider.get_user_role("admin").as_deref(),
+        Some("admin_role")
+    );
     assert_eq!(provider.get_user_role("unknown"), None);
 }
 
@@ -215,3 +221,185 @@ async fn test_mixed_auth_methods() {
         .unwrap();
     assert!(result, "Hashed user should authenticate");
 }
+
+#[tokio::test]
+async fn test_tenant_namespace() {
+    let mut alice = make_user_plaintext("alice", "secret", None);
+    alice.tenant = Some("acme".to_string());
+    let config = make_auth_config(
+        true,
+        true,
+        vec![alice, make_user_plaintext("admin", "secret", None)],
+    );
+    let provider = AuthProvider::new(&config);
+
+    assert_eq!(
+        provider
+            .on_client_tenant("sensor-1", Some("alice"))
+            .await
+            .unwrap()
+            .as_deref(),
+        Some("acme")
+    );
+    assert_eq!(
+        provider
+            .on_client_tenant("sensor-1", Some("admin"))
+            .await
+            .unwrap(),
+        None
+    );
+
+    // Only the tenant's own users may use its namespace
+    assert!(provider
+        .on_authenticate("acme/sensor-1", Some("alice"), Some(b"secret"))
+        .await
+        .unwrap());
+    assert!(!provider
+        .on_authenticate("acme/sensor-1", Some("admin"), Some(b"secret"))
+        .await
+        .unwrap());
+    assert!(!provider
+        .on_authenticate("acme/sensor-1", None, None)
+        .await
+        .unwrap());
+    assert!(provider
+        .on_authenticate("other/sensor-1", None, None)
+        .await
+        .unwrap());
+}
+
+/// Authenticator answering from a fixed username -> decision table
+struct StubAuthenticator(HashMap<&'static str, HookResult<AuthDecision>>);
+
+#[async_trait]
+impl Authenticator for StubAuthenticator {
+    fn name(&self) -> &str {
+        "stub"
+    }
+
+    async fn authenticate(&self, request: &AuthRequest<'_>) -> HookResult<AuthDecision> {
+        match request.username.and_then(|u| self.0.get(u)) {
+            Some(Ok(decision)) => Ok(*decision),
+            Some(Err(_)) => Err(crate::hooks::HookError::Unavailable("down".into())),
+            None => Ok(AuthDecision::Ignore),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_authenticator_chain() {
+    let config = make_auth_config(
+        true,
+        false,
+        vec![make_user_plaintext("admin", "secret", None)],
+    );
+    let first = StubAuthenticator(HashMap::from([
+        ("admin", Ok(AuthDecision::Allow)),
+        ("alice", Ok(AuthDecision::Allow)),
+        ("mallory", Ok(AuthDecision::Banned)),
+        ("bob", Ok(AuthDecision::Ignore)),
+        (
+            "flaky",
+            Err(crate::hooks::HookError::Unavailable(String::new())),
+        ),
+    ]));
+    let second = StubAuthenticator(HashMap::from([
+        ("alice", Ok(AuthDecision::BadCredentials)),
+        ("bob", Ok(AuthDecision::NotAuthorized)),
+    ]));
+    let provider = AuthProvider::new(&config)
+        .with_authenticator(Arc::new(first))
+        .with_authenticator(Arc::new(second));
+
+    let metadata = ConnectionMetadata::default();
+    let decide = |username: Option<&'static str>, password: &'static [u8]| {
+        let provider = &provider;
+        let metadata = &metadata;
+        async move {
+            provider
+                .authenticate(&AuthRequest {
+                    client_id: "c1",
+                    username,
+                    password: Some(password),
+                    metadata,
+                })
+                .await
+        }
+    };
+
+    // The static list wins, even over a backend that would allow
+    assert_eq!(
+        decide(Some("admin"), b"wrong").await.unwrap(),
+        AuthDecision::BadCredentials
+    );
+    // The first backend to decide wins
+    assert_eq!(
+        decide(Some("alice"), b"x").await.unwrap(),
+        AuthDecision::Allow
+    );
+    assert_eq!(
+        provider.get_client_username("c1"),
+        Some("alice".to_string())
+    );
+    assert_eq!(
+        decide(Some("bob"), b"x").await.unwrap(),
+        AuthDecision::NotAuthorized
+    );
+    assert_eq!(
+        decide(Some("mallory"), b"x").await.unwrap(),
+        AuthDecision::Banned
+    );
+    assert!(decide(Some("flaky"), b"x").await.is_err());
+    // Nobody knows the user, and anonymous access is off
+    assert_eq!(
+        decide(Some("eve"), b"x").await.unwrap(),
+        AuthDecision::BadCredentials
+    );
+    assert_eq!(
+        decide(None, b"").await.unwrap(),
+        AuthDecision::NotAuthorized
+    );
+
+    // Denials surface as the errors the CONNECT handler maps to reason codes
+    let hook =
+        |username| provider.on_authenticate_with_metadata("c2", Some(username), None, &metadata);
+    assert!(matches!(
+        hook("mallory").await,
+        Err(crate::hooks::HookError::Banned)
+    ));
+    assert!(matches!(
+        hook("eve").await,
+        Err(crate::hooks::HookError::AuthenticationFailed)
+    ));
+    assert!(!hook("bob").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_reload_replaces_users() {
+    let config = make_auth_config(
+        true,
+        false,
+        vec![make_user_plaintext("alice", "old", Some("reader"))],
+    );
+    let provider = AuthProvider::new(&config);
+    assert!(provider
+        .on_authenticate("c1", Some("alice"), Some(b"old"))
+        .await
+        .unwrap());
+
+    let config = make_auth_config(
+        true,
+        false,
+        vec![make_user_plaintext("alice", "new", Some("writer"))],
+    );
+    provider.reload(&config, Vec::new());
+    assert!(!provider
+        .on_authenticate("c1", Some("alice"), Some(b"old"))
+        .await
+        .unwrap());
+    assert!(provider
+        .on_authenticate("c1", Some("alice"), Some(b"new"))
+        .await
+        .unwrap());
+    assert_eq!(provider.get_user_role("alice").as_deref(), Some("writer"));
+}
@@ -3,28 +3,42 @@
 //! Implements a client that connects to a remote MQTT broker and forwards
 //! messages according to configured rules.
 
-use std::sync::atomic::{AtomicU16, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
-use bytes::{Bytes, BytesMut};
+use bytes::{Buf, Bytes, BytesMut};
 use parking_lot::RwLock;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio::time::timeout;
 use tracing::{debug, error, info, warn};
 
+use crate::broker::ROUTING_ID_PROPERTY;
 use crate::codec::{Decoder, Encoder};
 use crate::protocol::{
     Connect, Disconnect, Packet, Properties, ProtocolVersion, Publish, QoS, ReasonCode, Subscribe,
     Subscription, SubscriptionOptions,
 };
-use crate::remote::{RemoteError, RemotePeer, RemotePeerStatus};
+use crate::proxy::{
+    write_proxy_header_v1, write_proxy_header_v2, ProxyInfo, ProxyTlsInfo, ProxyVersion,
+    PP2_TYPE_UNIQUE_ID,
+};
+use crate::remote::compression::{codec_names, negotiate};
+use crate::remote::{
+    CompressionMetrics, LinkCompressor, PublishOrigin, RemoteError, RemotePeer, RemotePeerStatus,
+};
 
+use super::egress;
+use super::endpoint::{BridgeEndpoint, EndpointResolver};
+use super::health::{BridgeHealth, BridgeMetrics, HealthMonitor};
+use super::session::BridgeSession;
 use super::topic_mapper::TopicMapper;
-use crate::config::BridgeConfig;
+use super::{forwarded_properties, BRIDGE_COMPRESSION_PROPERTY, BRIDGE_ENCODING_PROPERTY};
+use crate::config::{BridgeConfig, BridgeProxyConfig, BridgeProxyVersion};
+use crate::user_properties::PropertyIndex;
 
 /// Message to send to the bridge client task
 #[derive(Debug)]
@@ -35,6 +49,8 @@ enum BridgeCommand {
         payload: Bytes,
         qos: QoS,
         retain: bool,
+        /// Extra user properties (e.g. forwarded origin)
+        user_properties: Vec<(String, String)>,
     },
     /// Subscribe to a topic on the remote broker
     Subscribe { filter: String, qos: QoS },
@@ -58,13 +74,20 @@ pub struct BridgeClient {
     topic_mapper: TopicMapper,
     /// Current connection status
     status: Arc<RwLock<RemotePeerStatus>>,
+    /// Remote address of the current connection
+    endpoint: Arc<RwLock<Option<BridgeEndpoint>>>,
+    /// Latest health probe results
+    health: Arc<RwLock<BridgeHealth>>,
+    /// Collectors the health probes report to
+    metrics: Option<BridgeMetrics>,
+    /// Collectors compressed traffic is reported to
+    compression_metrics: Option<CompressionMetrics>,
     /// Command channel for sending operations to the connection task
     command_tx: Option<mpsc::Sender<BridgeCommand>>,
     /// Callback for inbound messages
     inbound_callback: Option<InboundCallback>,
-    /// Next packet ID (for future QoS 1/2 tracking)
-    #[allow(dead_code)]
-    next_packet_id: AtomicU16,
+    /// Set while a maintenance window holds the bridge disconnected
+    paused: watch::Sender<bool>,
 }
 
 impl BridgeClient {
@@ -76,9 +99,13 @@ impl BridgeClient {
             config,
             topic_mapper,
             status: Arc::new(RwLock::new(RemotePeerStatus::Disconnected)),
+            endpoint: Arc::new(RwLock::new(None)),
+            health: Arc::new(RwLock::new(BridgeHealth::default())),
+            metrics: None,
+            compression_metrics: None,
             command_tx: None,
             inbound_callback: None,
-            next_packet_id: AtomicU16::new(1),
+            paused: watch::channel(false).0,
         }
     }
 
@@ -87,41 +114,119 @@ impl BridgeClient {
         self.inbound_callback = Some(callback);
     }
 
-    /// Get the next packet ID (for future QoS 1/2 tracking)
-    #[allow(dead_code)]
-    fn next_packet_id(&self) -> u16 {
-        let id = self.next_packet_id.fetch_add(1, Ordering::SeqCst);
-        if id == 0 {
-            self.next_packet_id.fetch_add(1, Ordering::SeqCst)
-        } else {
-            id
-        }
+    /// Remote address the bridge is currently connected to
+    pub fn endpoint(&self) -> Option<BridgeEndpoint> {
+        self.endpoint.read().clone()
+    }
+
+    /// Latest health probe results
+    pub fn health(&self) -> BridgeHealth {
+        self.health.read().clone()
+    }
+
+    /// Whether the bridge keeps running through maintenance windows
+    pub fn is_critical(&self) -> bool {
+        self.config.critical
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Disconnect from the remote and stay down until resumed, or resume;
+    /// false if the bridge already was in that state
+    pub fn set_paused(&self, paused: bool) -> bool {
+        self.paused.send_if_modified(|current| {
+            let changed = *current != paused;
+            *current = paused;
+            changed
+        })
+    }
+
+    /// Report health probes to these collectors (before `spawn`)
+    pub fn set_metrics(&mut self, metrics: BridgeMetrics) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Report compressed traffic to these collectors (before `spawn`)
+    pub fn set_compression_metrics(&mut self, metrics: CompressionMetrics) {
+        self.compression_metrics = Some(metrics);
     }
 
     /// Run the connection loop
+    #[allow(clippy::too_many_arguments)]
     async fn connection_loop(
         config: BridgeConfig,
         topic_mapper: TopicMapper,
         status: Arc<RwLock<RemotePeerStatus>>,
+        endpoint: Arc<RwLock<Option<BridgeEndpoint>>>,
+        mut monitor: HealthMonitor,
         mut command_rx: mpsc::Receiver<BridgeCommand>,
         inbound_callback: Option<InboundCallback>,
+        compression_metrics: Option<CompressionMetrics>,
+        mut paused: watch::Receiver<bool>,
     ) {
         let mut retry_interval = config.reconnect_interval;
         let max_retry = config.max_reconnect_interval;
+        let mut resolver = EndpointResolver::new(&config);
+        let mut session = BridgeSession::new(config.max_inflight);
 
         loop {
+            // Nothing is forwarded to a paused bridge; only a shutdown is
+            // acted on until it resumes
+            while *paused.borrow_and_update() {
+                *status.write() = RemotePeerStatus::Paused;
+                tokio::select! {
+                    changed = paused.changed() => {
+                        if changed.is_err() {
+                            return;
+                        }
+                    }
+                    command = command_rx.recv() => {
+                        if matches!(command, Some(BridgeCommand::Shutdown) | None) {
+                            info!("Bridge '{}': Shutdown requested", config.name);
+                            *status.write() = RemotePeerStatus::Disconnected;
+                            return;
+                        }
+                    }
+                }
+            }
+
             *status.write() = RemotePeerStatus::Connecting;
             debug!("Bridge '{}': Connecting to {}", config.name, config.address);
 
-            match Self::connect_and_run(
+            let result = Self::connect_and_run(
                 &config,
                 &topic_mapper,
                 &status,
+                &mut resolver,
+                &endpoint,
+                &mut session,
+                &mut monitor,
                 &mut command_rx,
                 &inbound_callback,
+                compression_metrics.as_ref(),
+                &mut paused,
             )
-            .await
-            {
+            .await;
+            let last_endpoint = endpoint.write().take();
+
+            // Try the other endpoints first after failed health checks
+            if let (true, Some(last)) = (monitor.disconnected(), last_endpoint) {
+                warn!(
+                    "Bridge '{}': {} failed health checks, holding it down for {:?}",
+                    config.name, last.address, config.health.hold_down
+                );
+                resolver.hold_down(&last.address, config.health.hold_down);
+                retry_interval = config.reconnect_interval;
+            }
+
+            match result {
+                Ok(()) if *paused.borrow() => {
+                    info!("Bridge '{}': Disconnected for a pause", config.name);
+                    retry_interval = config.reconnect_interval;
+                    continue;
+                }
                 Ok(()) => {
                     info!("Bridge '{}': Disconnected gracefully", config.name);
                     *status.write() = RemotePeerStatus::Disconnected;
@@ -154,26 +259,129 @@ impl BridgeClient {
         }
     }
 
+    /// Send the configured PROXY header ahead of CONNECT
+    async fn send_proxy_header(
+        stream: &mut TcpStream,
+        proxy: &BridgeProxyConfig,
+    ) -> Result<(), RemoteError> {
+        let local_addr = stream
+            .local_addr()
+            .map_err(|e| RemoteError::ConnectionLost(e.to_string()))?;
+        let tls_info =
+            (proxy.sni.is_some() || proxy.client_cert_cn.is_some()).then(|| ProxyTlsInfo {
+                sni: proxy.sni.clone(),
+                client_cert_cn: proxy.client_cert_cn.clone(),
+                client_cert_verified: proxy.client_cert_cn.is_some(),
+                ..Default::default()
+            });
+        let tlvs = proxy
+            .unique_id
+            .iter()
+            .map(|id| (PP2_TYPE_UNIQUE_ID, Bytes::from(id.clone())))
+            .collect();
+        let info = ProxyInfo {
+            client_addr: proxy.source_address.unwrap_or(local_addr).into(),
+            server_addr: stream.peer_addr().ok().map(Into::into),
+            tls_info,
+            version: match proxy.version {
+                BridgeProxyVersion::V1 => ProxyVersion::V1,
+                BridgeProxyVersion::V2 => ProxyVersion::V2,
+            },
+            tlvs,
+        };
+
+        let result = match proxy.version {
+            BridgeProxyVersion::V1 => write_proxy_header_v1(stream, &info).await,
+            BridgeProxyVersion::V2 => write_proxy_header_v2(stream, &info).await,
+        };
+        result.map_err(|e| RemoteError::ConnectionLost(e.to_string()))
+    }
+
+    /// Open a TCP connection to the first reachable candidate address
+    async fn connect_tcp(
+        config: &BridgeConfig,
+        resolver: &mut EndpointResolver,
+    ) -> Result<(TcpStream, BridgeEndpoint), RemoteError> {
+        let mut last_error = RemoteError::ConnectionLost(format!(
+            "No addresses resolved for {}",
+            config.endpoints().collect::<Vec<_>>().join(", ")
+        ));
+
+        if let Some(ref proxy) = config.egress_proxy {
+            for (address, host, port) in resolver.unresolved() {
+                match timeout(config.connect_timeout, egress::connect(proxy, &host, port)).await {
+                    Ok(Ok((stream, addr))) => {
+                        return Ok((stream, BridgeEndpoint { address, addr }))
+                    }
+                    Ok(Err(e)) => {
+                        debug!(
+                            "Bridge '{}': {} unreachable via {}: {}",
+                            config.name, address, proxy.address, e
+                        );
+                        last_error = RemoteError::ConnectionLost(e.to_string());
+                    }
+                    Err(_) => {
+                        debug!(
+                            "Bridge '{}': {} timed out via {}",
+                            config.name, address, proxy.address
+                        );
+                        last_error = RemoteError::Timeout;
+                    }
+                }
+            }
+            return Err(last_error);
+        }
+
+        for candidate in resolver.candidates().await {
+            match timeout(config.connect_timeout, TcpStream::connect(candidate.addr)).await {
+                Ok(Ok(stream)) => return Ok((stream, candidate)),
+                Ok(Err(e)) => {
+                    debug!(
+                        "Bridge '{}': {} ({}) unreachable: {}",
+                        config.name, candidate.address, candidate.addr, e
+                    );
+                    last_error = RemoteError::ConnectionLost(e.to_string());
+                }
+                Err(_) => {
+                    debug!(
+                        "Bridge '{}': {} ({}) timed out",
+                        config.name, candidate.address, candidate.addr
+                    );
+                    last_error = RemoteError::Timeout;
+                }
+            }
+            resolver.invalidate(&candidate.address);
+        }
+
+        Err(last_error)
+    }
+
     /// Connect to the remote broker and run the message loop
+    #[allow(clippy::too_many_arguments)]
     async fn connect_and_run(
         config: &BridgeConfig,
         topic_mapper: &TopicMapper,
         status: &Arc<RwLock<RemotePeerStatus>>,
+        resolver: &mut EndpointResolver,
+        endpoint: &Arc<RwLock<Option<BridgeEndpoint>>>,
+        session: &mut BridgeSession,
+        monitor: &mut HealthMonitor,
         command_rx: &mut mpsc::Receiver<BridgeCommand>,
         inbound_callback: &Option<InboundCallback>,
+        compression_metrics: Option<&CompressionMetrics>,
+        paused: &mut watch::Receiver<bool>,
     ) -> Result<(), RemoteError> {
-        let (host, port) = config.parse_address();
+        let (mut stream, connected) = Self::connect_tcp(config, resolver).await?;
 
-        // Connect with timeout
-        let stream = timeout(
-            config.connect_timeout,
-            TcpStream::connect(format!("{}:{}", host, port)),
-        )
-        .await
-        .map_err(|_| RemoteError::Timeout)?
-        .map_err(|e| RemoteError::ConnectionLost(e.to_string()))?;
+        debug!(
+            "Bridge '{}': TCP connected to {} ({})",
+            config.name, connected.address, connected.addr
+        );
 
-        debug!("Bridge '{}': TCP connected", config.name);
+        if let Some(ref proxy) = config.proxy_protocol {
+            Self::send_proxy_header(&mut stream, proxy).await?;
+            debug!("Bridge '{}': PROXY header sent", config.name);
+        }
 
         // Set up encoder/decoder
         let encoder = Encoder::new(ProtocolVersion::V5);
@@ -181,8 +389,17 @@ impl BridgeClient {
         decoder.set_protocol_version(ProtocolVersion::V5);
 
         let (mut read_half, mut write_half) = stream.into_split();
+        let mut buf = BytesMut::new();
 
-        // Send CONNECT packet
+        // Send CONNECT packet, offering compression
+        let session_expiry = config.session_expiry.as_secs().min(u32::MAX as u64) as u32;
+        let mut user_properties = Vec::new();
+        if config.compression.enabled() {
+            user_properties.push((
+                BRIDGE_COMPRESSION_PROPERTY.to_string(),
+                codec_names(&config.compression.codecs).join(","),
+            ));
+        }
         let connect = Packet::Connect(Box::new(Connect {
             protocol_version: ProtocolVersion::V5,
             client_id: config.client_id.clone(),
@@ -191,37 +408,26 @@ impl BridgeClient {
             username: config.username.clone(),
             password: config.password.as_ref().map(|p| Bytes::from(p.clone())),
             will: None,
-            properties: Properties::default(),
+            properties: Properties {
+                session_expiry_interval: (session_expiry > 0).then_some(session_expiry),
+                user_properties,
+                ..Default::default()
+            },
         }));
-
-        let mut buf = BytesMut::new();
-        encoder
-            .encode(&connect, &mut buf)
-            .map_err(|e| RemoteError::Other(format!("Encode error: {}", e)))?;
-        write_half
-            .write_all(&buf)
-            .await
-            .map_err(|e| RemoteError::ConnectionLost(e.to_string()))?;
+        Self::send_packet(&mut write_half, &encoder, &mut buf, &connect).await?;
 
         debug!("Bridge '{}': CONNECT sent", config.name);
 
         // Wait for CONNACK
-        let mut read_buf = vec![0u8; 4096];
-        let n = timeout(config.connect_timeout, read_half.read(&mut read_buf))
-            .await
-            .map_err(|_| RemoteError::Timeout)?
-            .map_err(|e| RemoteError::ConnectionLost(e.to_string()))?;
-
-        if n == 0 {
-            return Err(RemoteError::ConnectionLost("Connection closed".to_string()));
-        }
-
-        let (packet, _) = decoder
-            .decode(&read_buf[..n])
-            .map_err(|e| RemoteError::Other(format!("Decode error: {}", e)))?
-            .ok_or_else(|| RemoteError::Other("Incomplete packet".to_string()))?;
+        let mut read_buf = BytesMut::with_capacity(4096);
+        let packet = timeout(
+            config.connect_timeout,
+            Self::read_packet(&mut read_half, &mut decoder, &mut read_buf),
+        )
+        .await
+        .map_err(|_| RemoteError::Timeout)??;
 
-        match packet {
+        let (resend, compressor) = match packet {
             Packet::ConnAck(connack) => {
                 if connack.reason_code != ReasonCode::Success {
                     return Err(RemoteError::Rejected(format!(
@@ -230,16 +436,42 @@ impl BridgeClient {
                     )));
                 }
                 info!(
-                    "Bridge '{}': Connected (session_present={})",
-                    config.name, connack.session_present
+                    "Bridge '{}': Connected to {} ({}) (session_present={})",
+                    config.name, connected.address, connected.addr, connack.session_present
                 );
+                // The codec the remote took, if any
+                let codec = connack
+                    .properties
+                    .user_properties
+                    .iter()
+                    .find(|(name, _)| name == BRIDGE_COMPRESSION_PROPERTY)
+                    .and_then(|(_, codec)| negotiate([codec.as_str()], &config.compression.codecs));
+                let compressor = codec.map(|codec| {
+                    info!(
+                        "Bridge '{}': Compressing with {}",
+                        config.name,
+                        codec.as_str()
+                    );
+                    LinkCompressor::new(
+                        codec,
+                        &config.compression,
+                        &format!("bridge:{}", config.name),
+                        compression_metrics,
+                    )
+                });
+                (
+                    session.resume(connack.session_present, connack.properties.receive_maximum),
+                    compressor,
+                )
             }
             _ => {
                 return Err(RemoteError::Other("Expected CONNACK".to_string()));
             }
-        }
+        };
 
+        *endpoint.write() = Some(connected);
         *status.write() = RemotePeerStatus::Connected;
+        monitor.connected();
 
         // Subscribe to inbound topics with loop prevention
         let use_no_local = config.use_no_local();
@@ -258,19 +490,11 @@ impl BridgeClient {
                 .collect();
 
             let subscribe = Packet::Subscribe(Subscribe {
-                packet_id: 1,
+                packet_id: session.next_packet_id(),
                 subscriptions,
                 properties: Properties::default(),
             });
-
-            buf.clear();
-            encoder
-                .encode(&subscribe, &mut buf)
-                .map_err(|e| RemoteError::Other(format!("Encode error: {}", e)))?;
-            write_half
-                .write_all(&buf)
-                .await
-                .map_err(|e| RemoteError::ConnectionLost(e.to_string()))?;
+            Self::send_packet(&mut write_half, &encoder, &mut buf, &subscribe).await?;
 
             debug!(
                 "Bridge '{}': Subscribed to {} inbound topics",
@@ -279,66 +503,75 @@ impl BridgeClient {
             );
         }
 
-        // Message loop
-        let keepalive_interval = Duration::from_secs(config.keepalive as u64);
+        // Retransmit whatever the previous connection left unacknowledged
+        if !resend.is_empty() {
+            debug!(
+                "Bridge '{}': Resending {} in-flight packets",
+                config.name,
+                resend.len()
+            );
+        }
+        for packet in &resend {
+            Self::send_packet(&mut write_half, &encoder, &mut buf, packet).await?;
+        }
+
+        // Message loop; PINGREQ doubles as the health probe
+        let keepalive_interval =
+            monitor.ping_interval(Duration::from_secs(config.keepalive as u64));
         let mut keepalive_timer = tokio::time::interval(keepalive_interval);
         keepalive_timer.reset();
 
         loop {
+            let probe_deadline = monitor.deadline();
             tokio::select! {
                 // Handle commands from the broker
                 Some(cmd) = command_rx.recv() => {
                     match cmd {
-                        BridgeCommand::Publish { topic, payload, qos, retain } => {
-                            let packet_id = if qos != QoS::AtMostOnce {
-                                Some(1) // Simplified - real impl would track packet IDs
-                            } else {
-                                None
-                            };
-
-                            let publish = Packet::Publish(Publish {
+                        BridgeCommand::Publish { topic, mut payload, qos, retain, mut user_properties } => {
+                            if let Some(ref compressor) = compressor {
+                                if let Some(compressed) = compressor.compress(&payload) {
+                                    payload = Bytes::from(compressed);
+                                    user_properties.push((
+                                        BRIDGE_ENCODING_PROPERTY.to_string(),
+                                        compressor.codec().as_str().to_string(),
+                                    ));
+                                }
+                            }
+                            let publish = Publish {
                                 dup: false,
                                 qos,
                                 retain,
                                 topic,
-                                packet_id,
+                                packet_id: None,
                                 payload,
-                                properties: Properties::default(),
-                            });
+                                properties: Properties {
+                                    user_properties,
+                                    ..Default::default()
+                                },
+                            };
 
-                            buf.clear();
-                            if encoder.encode(&publish, &mut buf).is_ok() {
-                                if let Err(e) = write_half.write_all(&buf).await {
-                                    return Err(RemoteError::ConnectionLost(e.to_string()));
-                                }
+                            if let Some(packet) = session.publish(publish) {
+                                Self::send_packet(&mut write_half, &encoder, &mut buf, &packet).await?;
                             }
                         }
                         BridgeCommand::Subscribe { filter, qos } => {
                             let subscribe = Packet::Subscribe(Subscribe {
-                                packet_id: 2,
+                                packet_id: session.next_packet_id(),
                                 subscriptions: vec![Subscription {
                                     filter,
                                     options: SubscriptionOptions { qos, ..Default::default() },
                                 }],
                                 properties: Properties::default(),
                             });
-
-                            buf.clear();
-                            if encoder.encode(&subscribe, &mut buf).is_ok() {
-                                let _ = write_half.write_all(&buf).await;
-                            }
+                            Self::send_packet(&mut write_half, &encoder, &mut buf, &subscribe).await?;
                         }
                         BridgeCommand::Unsubscribe { filter } => {
                             let unsubscribe = Packet::Unsubscribe(crate::protocol::Unsubscribe {
-                                packet_id: 3,
+                                packet_id: session.next_packet_id(),
                                 filters: vec![filter],
                                 properties: Properties::default(),
                             });
-
-                            buf.clear();
-                            if encoder.encode(&unsubscribe, &mut buf).is_ok() {
-                                let _ = write_half.write_all(&buf).await;
-                            }
+                            Self::send_packet(&mut write_half, &encoder, &mut buf, &unsubscribe).await?;
                         }
                         BridgeCommand::Shutdown => {
                             // Send DISCONNECT
@@ -346,88 +579,189 @@ impl BridgeClient {
                                 reason_code: ReasonCode::Success,
                                 properties: Properties::default(),
                             });
-
-                            buf.clear();
-                            if encoder.encode(&disconnect, &mut buf).is_ok() {
-                                let _ = write_half.write_all(&buf).await;
-                            }
+                            let _ = Self::send_packet(&mut write_half, &encoder, &mut buf, &disconnect).await;
                             return Ok(());
                         }
                     }
                 }
 
+                // Paused: leave cleanly, the connection loop waits
+                Ok(()) = async { paused.wait_for(|paused| *paused).await.map(drop) } => {
+                    let disconnect = Packet::Disconnect(Disconnect {
+                        reason_code: ReasonCode::Success,
+                        properties: Properties::default(),
+                    });
+                    let _ = Self::send_packet(&mut write_half, &encoder, &mut buf, &disconnect).await;
+                    return Ok(());
+                }
+
                 // Handle incoming packets from remote broker
-                result = read_half.read(&mut read_buf) => {
+                result = read_half.read_buf(&mut read_buf) => {
                     let n = result.map_err(|e| RemoteError::ConnectionLost(e.to_string()))?;
                     if n == 0 {
                         return Err(RemoteError::ConnectionLost("Connection closed".to_string()));
                     }
 
-                    if let Ok(Some((packet, _))) = decoder.decode(&read_buf[..n]) {
-                        match packet {
-                            Packet::Publish(publish) => {
-                                // Forward to local broker via callback
-                                if let Some(ref callback) = inbound_callback {
-                                    if let Some((local_topic, qos, retain)) = topic_mapper.map_inbound(
-                                        &publish.topic,
-                                        publish.qos,
-                                        publish.retain,
-                                    ) {
-                                        debug!(
-                                            "Bridge '{}': Forwarding {} -> {}",
-                                            config.name, publish.topic, local_topic
-                                        );
-                                        callback(local_topic, publish.payload, qos, retain);
-                                    }
-                                }
-
-                                // Send PUBACK for QoS 1
-                                if publish.qos == QoS::AtLeastOnce {
-                                    if let Some(packet_id) = publish.packet_id {
-                                        let puback = Packet::PubAck(crate::protocol::PubAck {
-                                            packet_id,
-                                            reason_code: ReasonCode::Success,
-                                            properties: Properties::default(),
-                                        });
-                                        buf.clear();
-                                        if encoder.encode(&puback, &mut buf).is_ok() {
-                                            let _ = write_half.write_all(&buf).await;
-                                        }
-                                    }
-                                }
-                            }
-                            Packet::PingResp => {
-                                debug!("Bridge '{}': PINGRESP received", config.name);
-                            }
-                            Packet::SubAck(_) => {
-                                debug!("Bridge '{}': SUBACK received", config.name);
-                            }
-                            Packet::PubAck(_) => {
-                                debug!("Bridge '{}': PUBACK received", config.name);
-                            }
-                            Packet::Disconnect(disconnect) => {
-                                warn!(
-                                    "Bridge '{}': Received DISCONNECT: {:?}",
-                                    config.name, disconnect.reason_code
-                                );
-                                return Err(RemoteError::ConnectionLost("Remote disconnected".to_string()));
-                            }
-                            _ => {}
+                    while let Some((packet, consumed)) = decoder
+                        .decode(&read_buf)
+                        .map_err(|e| RemoteError::Other(format!("Decode error: {}", e)))?
+                    {
+                        read_buf.advance(consumed);
+                        if matches!(packet, Packet::PingResp) && monitor.pong_received() {
+                            return Err(RemoteError::Other("Health check failed: link too slow".to_string()));
+                        }
+                        let replies = Self::handle_packet(
+                            config,
+                            topic_mapper,
+                            session,
+                            inbound_callback,
+                            packet,
+                        )?;
+                        for reply in &replies {
+                            Self::send_packet(&mut write_half, &encoder, &mut buf, reply).await?;
                         }
                     }
                 }
 
                 // Send PINGREQ to keep connection alive
                 _ = keepalive_timer.tick() => {
-                    let pingreq = Packet::PingReq;
-                    buf.clear();
-                    if encoder.encode(&pingreq, &mut buf).is_ok() {
-                        if let Err(e) = write_half.write_all(&buf).await {
-                            return Err(RemoteError::ConnectionLost(e.to_string()));
-                        }
+                    Self::send_packet(&mut write_half, &encoder, &mut buf, &Packet::PingReq).await?;
+                    monitor.ping_sent();
+                }
+
+                // Outstanding probe went unanswered
+                _ = tokio::time::sleep_until(
+                    probe_deadline.map(tokio::time::Instant::from_std).unwrap_or_else(tokio::time::Instant::now)
+                ), if probe_deadline.is_some() => {
+                    if monitor.check_timeout() {
+                        return Err(RemoteError::Other("Health check failed: no PINGRESP".to_string()));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handle a packet from the remote broker, returning the replies to send
+    fn handle_packet(
+        config: &BridgeConfig,
+        topic_mapper: &TopicMapper,
+        session: &mut BridgeSession,
+        inbound_callback: &Option<InboundCallback>,
+        packet: Packet,
+    ) -> Result<Vec<Packet>, RemoteError> {
+        let replies = match packet {
+            Packet::Publish(publish) => {
+                // A retransmitted QoS 2 message was already delivered
+                let deliver = match (publish.qos, publish.packet_id) {
+                    (QoS::ExactlyOnce, Some(packet_id)) => session.receive_qos2(packet_id),
+                    _ => true,
+                };
+
+                // Forward to local broker via callback
+                if let (true, Some(callback)) = (deliver, inbound_callback) {
+                    if let Some((local_topic, qos, retain)) =
+                        topic_mapper.map_inbound(&publish.topic, publish.qos, publish.retain)
+                    {
+                        debug!(
+                            "Bridge '{}': Forwarding {} -> {}",
+                            config.name, publish.topic, local_topic
+                        );
+                        callback(local_topic, publish.payload, qos, retain);
+                    }
+                }
+
+                match (publish.qos, publish.packet_id) {
+                    (QoS::AtLeastOnce, Some(packet_id)) => {
+                        vec![Packet::PubAck(crate::protocol::PubAck {
+                            packet_id,
+                            reason_code: ReasonCode::Success,
+                            properties: Properties::default(),
+                        })]
+                    }
+                    (QoS::ExactlyOnce, Some(packet_id)) => {
+                        vec![Packet::PubRec(crate::protocol::PubRec {
+                            packet_id,
+                            reason_code: ReasonCode::Success,
+                            properties: Properties::default(),
+                        })]
                     }
+                    _ => Vec::new(),
                 }
             }
+            Packet::PubRel(pubrel) => {
+                session.release(pubrel.packet_id);
+                vec![Packet::PubComp(crate::protocol::PubComp {
+                    packet_id: pubrel.packet_id,
+                    reason_code: ReasonCode::Success,
+                    properties: Properties::default(),
+                })]
+            }
+            Packet::PubAck(puback) => {
+                debug!("Bridge '{}': PUBACK received", config.name);
+                session.on_puback(puback.packet_id)
+            }
+            Packet::PubRec(pubrec) => session.on_pubrec(pubrec.packet_id, pubrec.reason_code),
+            Packet::PubComp(pubcomp) => session.on_pubcomp(pubcomp.packet_id),
+            Packet::PingResp => {
+                debug!("Bridge '{}': PINGRESP received", config.name);
+                Vec::new()
+            }
+            Packet::SubAck(_) => {
+                debug!("Bridge '{}': SUBACK received", config.name);
+                Vec::new()
+            }
+            Packet::Disconnect(disconnect) => {
+                warn!(
+                    "Bridge '{}': Received DISCONNECT: {:?}",
+                    config.name, disconnect.reason_code
+                );
+                return Err(RemoteError::ConnectionLost(
+                    "Remote disconnected".to_string(),
+                ));
+            }
+            _ => Vec::new(),
+        };
+        Ok(replies)
+    }
+
+    /// Encode and write a packet to the remote broker
+    async fn send_packet(
+        write_half: &mut OwnedWriteHalf,
+        encoder: &Encoder,
+        buf: &mut BytesMut,
+        packet: &Packet,
+    ) -> Result<(), RemoteError> {
+        buf.clear();
+        encoder
+            .encode(packet, buf)
+            .map_err(|e| RemoteError::Other(format!("Encode error: {}", e)))?;
+        write_half
+            .write_all(buf)
+            .await
+            .map_err(|e| RemoteError::ConnectionLost(e.to_string()))
+    }
+
+    /// Read until a complete packet has arrived
+    async fn read_packet(
+        read_half: &mut OwnedReadHalf,
+        decoder: &mut Decoder,
+        read_buf: &mut BytesMut,
+    ) -> Result<Packet, RemoteError> {
+        loop {
+            if let Some((packet, consumed)) = decoder
+                .decode(read_buf)
+                .map_err(|e| RemoteError::Other(format!("Decode error: {}", e)))?
+            {
+                read_buf.advance(consumed);
+                return Ok(packet);
+            }
+            let n = read_half
+                .read_buf(read_buf)
+                .await
+                .map_err(|e| RemoteError::ConnectionLost(e.to_string()))?;
+            if n == 0 {
+                return Err(RemoteError::ConnectionLost("Connection closed".to_string()));
+            }
         }
     }
 }
@@ -449,26 +783,8 @@ impl RemotePeer for BridgeClient {
         qos: QoS,
         retain: bool,
     ) -> Result<(), RemoteError> {
-        // Map the topic and check if we should forward
-        let (remote_topic, effective_qos, effective_retain) =
-            match self.topic_mapper.map_outbound(topic, qos, retain) {
-                Some(mapping) => mapping,
-                None => return Ok(()), // Topic doesn't match any rules
-            };
-
-        // Send via command channel
-        if let Some(ref tx) = self.command_tx {
-            tx.send(BridgeCommand::Publish {
-                topic: remote_topic,
-                payload,
-                qos: effective_qos,
-                retain: effective_retain,
-            })
+        self.forward_publish_from(topic, payload, qos, retain, None, &PropertyIndex::default())
             .await
-            .map_err(|_| RemoteError::ConnectionLost("Command channel closed".to_string()))?;
-        }
-
-        Ok(())
     }
 
     async fn notify_subscribe(&self, filter: &str, qos: QoS) -> Result<(), RemoteError> {
@@ -520,6 +836,54 @@ impl RemotePeer for BridgeClient {
 }
 
 impl BridgeClient {
+    /// Forward a published message, tagging it with its local publisher
+    /// when `forward_origin` is enabled
+    ///
+    /// `properties` indexes the message's user properties for the forward
+    /// rules' `user_properties` conditions.
+    pub async fn forward_publish_from(
+        &self,
+        topic: &str,
+        payload: Bytes,
+        qos: QoS,
+        retain: bool,
+        origin: Option<&PublishOrigin>,
+        properties: &PropertyIndex<'_>,
+    ) -> Result<(), RemoteError> {
+        // Map the topic and check if we should forward
+        let (remote_topic, effective_qos, effective_retain) = match self
+            .topic_mapper
+            .map_outbound_tagged(topic, qos, retain, properties)
+        {
+            Some(mapping) => mapping,
+            None => return Ok(()), // Topic doesn't match any rules
+        };
+
+        let mut user_properties = match origin {
+            Some(origin) if self.config.forward_origin => forwarded_properties(origin),
+            _ => Vec::new(),
+        };
+        // The remote side can follow the message under the same ID
+        if let Some(id) = properties.values(ROUTING_ID_PROPERTY).next() {
+            user_properties.push((ROUTING_ID_PROPERTY.to_string(), id.to_string()));
+        }
+
+        // Send via command channel
+        if let Some(ref tx) = self.command_tx {
+            tx.send(BridgeCommand::Publish {
+                topic: remote_topic,
+                payload,
+                qos: effective_qos,
+                retain: effective_retain,
+                user_properties,
+            })
+            .await
+            .map_err(|_| RemoteError::ConnectionLost("Command channel closed".to_string()))?;
+        }
+
+        Ok(())
+    }
+
     /// Spawn the connection task and return the bridge client ready to use
     pub fn spawn(mut self, inbound_callback: InboundCallback) -> Arc<Self> {
         let (tx, rx) = mpsc::channel(1000);
@@ -529,10 +893,30 @@ impl BridgeClient {
         let config = self.config.clone();
         let topic_mapper = TopicMapper::new(&config.forwards);
         let status = self.status.clone();
+        let endpoint = self.endpoint.clone();
         let callback = self.inbound_callback.clone();
+        let compression_metrics = self.compression_metrics.clone();
+        let paused = self.paused.subscribe();
+        let monitor = HealthMonitor::new(
+            &config.name,
+            config.health.clone(),
+            self.health.clone(),
+            self.metrics.as_ref(),
+        );
 
         tokio::spawn(async move {
-            Self::connection_loop(config, topic_mapper, status, rx, callback).await;
+            Self::connection_loop(
+                config,
+                topic_mapper,
+                status,
+                endpoint,
+                monitor,
+                rx,
+                callback,
+                compression_metrics,
+                paused,
+            )
+            .await;
         });
 
         Arc::new(self)
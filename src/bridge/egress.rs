@@ -0,0 +1,373 @@
+//! Bridge Egress Proxies
+//!
+//! Opens a bridge's TCP connection through a SOCKS5 (RFC 1928/1929) or
+//! HTTP CONNECT proxy, for networks where direct outbound connections are
+//! blocked. The remote hostname is handed to the proxy unresolved; once the
+//! tunnel is up the stream carries MQTT as if connected directly.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use base64ct::{Base64, Encoding};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::config::{EgressProxyConfig, EgressProxyType};
+
+/// Largest HTTP CONNECT response header accepted
+const MAX_RESPONSE_HEADER: usize = 8192;
+
+/// Connect to `host:port` through the proxy, returning the tunnelled stream
+/// and the proxy's address
+pub async fn connect(
+    proxy: &EgressProxyConfig,
+    host: &str,
+    port: u16,
+) -> io::Result<(TcpStream, SocketAddr)> {
+    let (proxy_host, proxy_port) = proxy.parse_address();
+    let mut stream = TcpStream::connect((proxy_host.as_str(), proxy_port)).await?;
+    let proxy_addr = stream.peer_addr()?;
+
+    match proxy.proxy_type {
+        EgressProxyType::Socks5 => socks5_handshake(&mut stream, proxy, host, port).await?,
+        EgressProxyType::Http => http_connect(&mut stream, proxy, host, port).await?,
+    }
+    Ok((stream, proxy_addr))
+}
+
+fn proxy_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionRefused, message.into())
+}
+
+/// SOCKS5 method negotiation, optional authentication and CONNECT
+async fn socks5_handshake(
+    stream: &mut TcpStream,
+    proxy: &EgressProxyConfig,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    let credentials = proxy.username.as_deref().map(|user| {
+        (
+            user.as_bytes(),
+            proxy.password.as_deref().unwrap_or("").as_bytes(),
+        )
+    });
+
+    // Offer no-auth, plus username/password when configured
+    let greeting: &[u8] = if credentials.is_some() {
+        &[0x05, 0x02, 0x00, 0x02]
+    } else {
+        &[0x05, 0x01, 0x00]
+    };
+    stream.write_all(greeting).await?;
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != 0x05 {
+        return Err(proxy_error("SOCKS5 proxy sent an invalid reply"));
+    }
+    match (reply[1], credentials) {
+        (0x00, _) => {}
+        (0x02, Some((user, pass))) => {
+            if user.len() > 255 || pass.len() > 255 {
+                return Err(proxy_error("SOCKS5 credentials longer than 255 bytes"));
+            }
+            let mut auth = Vec::with_capacity(3 + user.len() + pass.len());
+            auth.push(0x01);
+            auth.push(user.len() as u8);
+            auth.extend_from_slice(user);
+            auth.push(pass.len() as u8);
+            auth.extend_from_slice(pass);
+            stream.write_all(&auth).await?;
+
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0x00 {
+                return Err(proxy_error("SOCKS5 proxy rejected the credentials"));
+            }
+        }
+        _ => {
+            return Err(proxy_error(
+                "SOCKS5 proxy requires an authentication method that isn't configured",
+            ))
+        }
+    }
+
+    // CONNECT request: version, command, reserved, address
+    let mut request = vec![0x05, 0x01, 0x00];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(0x01);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(0x04);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                return Err(proxy_error("hostname too long for SOCKS5"));
+            }
+            request.push(0x03);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[1] != 0x00 {
+        return Err(proxy_error(format!(
+            "SOCKS5 proxy refused the connection: {}",
+            socks5_reply_message(head[1])
+        )));
+    }
+
+    // Skip the bound address
+    let bound_len = match head[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        _ => return Err(proxy_error("SOCKS5 proxy sent an invalid address type")),
+    };
+    let mut bound = vec![0u8; bound_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+fn socks5_reply_message(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// HTTP CONNECT request and response
+async fn http_connect(
+    stream: &mut TcpStream,
+    proxy: &EgressProxyConfig,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    let authority = if host.parse::<std::net::Ipv6Addr>().is_ok() {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+
+    let mut request = format!(
+        "CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n",
+        authority = authority
+    );
+    if let Some(ref user) = proxy.username {
+        let credentials = format!("{}:{}", user, proxy.password.as_deref().unwrap_or(""));
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            Base64::encode_string(credentials.as_bytes())
+        ));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte so nothing after the header is consumed
+    let mut response = Vec::with_capacity(256);
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_RESPONSE_HEADER {
+            return Err(proxy_error("HTTP proxy response header too large"));
+        }
+        let mut byte = [0u8; 1];
+        if stream.read(&mut byte).await? == 0 {
+            return Err(proxy_error("HTTP proxy closed the connection"));
+        }
+        response.push(byte[0]);
+    }
+
+    let mut headers = [httparse::EMPTY_HEADER; 32];
+    let mut parsed = httparse::Response::new(&mut headers);
+    parsed
+        .parse(&response)
+        .map_err(|e| proxy_error(format!("invalid HTTP proxy response: {}", e)))?;
+    match parsed.code {
+        Some(code) if (200..300).contains(&code) => Ok(()),
+        Some(407) => Err(proxy_error("HTTP proxy requires authentication (407)")),
+        Some(code) => Err(proxy_error(format!(
+            "HTTP proxy refused the connection: {} {}",
+            code,
+            parsed.reason.unwrap_or("")
+        ))),
+        None => Err(proxy_error("invalid HTTP proxy response")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn proxy(proxy_type: EgressProxyType, addr: SocketAddr, auth: bool) -> EgressProxyConfig {
+        EgressProxyConfig {
+            proxy_type,
+            address: addr.to_string(),
+            username: auth.then(|| "edge".to_string()),
+            password: auth.then(|| "s3cret".to_string()),
+        }
+    }
+
+    /// Minimal SOCKS5 server: checks the handshake, then echoes
+    async fn socks5_server(listener: TcpListener, require_auth: bool) -> Vec<u8> {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut greeting = [0u8; 2];
+        stream.read_exact(&mut greeting).await.unwrap();
+        let mut methods = vec![0u8; greeting[1] as usize];
+        stream.read_exact(&mut methods).await.unwrap();
+
+        if require_auth {
+            assert!(methods.contains(&0x02));
+            stream.write_all(&[0x05, 0x02]).await.unwrap();
+            let mut ver_len = [0u8; 2];
+            stream.read_exact(&mut ver_len).await.unwrap();
+            let mut user = vec![0u8; ver_len[1] as usize];
+            stream.read_exact(&mut user).await.unwrap();
+            let mut plen = [0u8; 1];
+            stream.read_exact(&mut plen).await.unwrap();
+            let mut pass = vec![0u8; plen[0] as usize];
+            stream.read_exact(&mut pass).await.unwrap();
+            let ok = user == b"edge" && pass == b"s3cret";
+            stream
+                .write_all(&[0x01, if ok { 0x00 } else { 0x01 }])
+                .await
+                .unwrap();
+        } else {
+            stream.write_all(&[0x05, 0x00]).await.unwrap();
+        }
+
+        // CONNECT with a domain name
+        let mut head = [0u8; 5];
+        stream.read_exact(&mut head).await.unwrap();
+        assert_eq!(head[..4], [0x05, 0x01, 0x00, 0x03]);
+        let mut target = vec![0u8; head[4] as usize + 2];
+        stream.read_exact(&mut target).await.unwrap();
+        stream
+            .write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0x07, 0x5b])
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(&buf).await.unwrap();
+        target
+    }
+
+    #[tokio::test]
+    async fn test_socks5_tunnel() {
+        for auth in [false, true] {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = tokio::spawn(socks5_server(listener, auth));
+
+            let config = proxy(EgressProxyType::Socks5, addr, auth);
+            let (mut stream, proxy_addr) = connect(&config, "broker.internal", 1883).await.unwrap();
+            assert_eq!(proxy_addr, addr);
+
+            stream.write_all(b"ping").await.unwrap();
+            let mut echoed = [0u8; 4];
+            stream.read_exact(&mut echoed).await.unwrap();
+            assert_eq!(&echoed, b"ping");
+
+            let target = server.await.unwrap();
+            assert_eq!(&target[..target.len() - 2], b"broker.internal");
+            assert_eq!(target[target.len() - 2..], 1883u16.to_be_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_socks5_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            stream.write_all(&[0x05, 0x00]).await.unwrap();
+            let mut request = [0u8; 10];
+            stream.read_exact(&mut request).await.unwrap();
+            stream
+                .write_all(&[0x05, 0x02, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+        });
+
+        let config = proxy(EgressProxyType::Socks5, addr, false);
+        let err = connect(&config, "10.0.0.1", 1883).await.unwrap_err();
+        assert!(err.to_string().contains("not allowed by ruleset"));
+    }
+
+    /// Minimal HTTP proxy: answers with `status` and returns the request
+    async fn http_server(listener: TcpListener, status: &'static str) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8; 1];
+            stream.read_exact(&mut byte).await.unwrap();
+            request.push(byte[0]);
+        }
+        stream
+            .write_all(format!("HTTP/1.1 {}\r\nVia: test\r\n\r\n", status).as_bytes())
+            .await
+            .unwrap();
+        if status.starts_with("200") {
+            // First tunnelled bytes follow the header immediately
+            stream.write_all(b"MQTT").await.unwrap();
+        }
+        String::from_utf8(request).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_tunnel() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(http_server(listener, "200 Connection established"));
+
+        let config = proxy(EgressProxyType::Http, addr, true);
+        let (mut stream, _) = connect(&config, "broker.internal", 8883).await.unwrap();
+
+        let mut first = [0u8; 4];
+        stream.read_exact(&mut first).await.unwrap();
+        assert_eq!(&first, b"MQTT");
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("CONNECT broker.internal:8883 HTTP/1.1\r\n"));
+        assert!(request.contains("Host: broker.internal:8883\r\n"));
+        assert!(request.contains(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            Base64::encode_string(b"edge:s3cret")
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(http_server(listener, "407 Proxy Authentication Required"));
+
+        let config = proxy(EgressProxyType::Http, addr, false);
+        let err = connect(&config, "::1", 1883).await.unwrap_err();
+        assert!(err.to_string().contains("407"));
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("CONNECT [::1]:1883 HTTP/1.1\r\n"));
+        assert!(!request.contains("Proxy-Authorization"));
+    }
+}
@@ -0,0 +1,270 @@
+//! Bridge Endpoint Resolution
+//!
+//! Turns a bridge's configured addresses into the ordered list of socket
+//! addresses to try on each connection attempt. Hostnames are looked up
+//! again once their cached result is older than `dns_ttl`, so a changed
+//! DNS record is picked up on the next reconnect.
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use crate::config::{BridgeConfig, EndpointSelection};
+
+/// A resolved remote address together with the configured address it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgeEndpoint {
+    /// Address as written in the configuration (e.g. "cloud.example.com:8883")
+    pub address: String,
+    /// Socket address connected to (the egress proxy's, when one is used)
+    pub addr: SocketAddr,
+}
+
+/// One configured address and its last lookup
+struct Target {
+    address: String,
+    host: String,
+    port: u16,
+    resolved: Option<(Instant, Vec<SocketAddr>)>,
+    /// Failed health checks: tried after the others until then
+    held_until: Option<Instant>,
+}
+
+/// Resolves and orders a bridge's remote addresses
+pub struct EndpointResolver {
+    targets: Vec<Target>,
+    selection: EndpointSelection,
+    ttl: Duration,
+    /// Offset of the first candidate for round-robin selection
+    next: usize,
+}
+
+impl EndpointResolver {
+    /// Create a resolver for the bridge's `address` and `failover_addresses`
+    pub fn new(config: &BridgeConfig) -> Self {
+        let targets = config
+            .endpoints()
+            .map(|address| {
+                let (host, port) = config.parse_endpoint(address);
+                Target {
+                    address: address.to_string(),
+                    host,
+                    port,
+                    resolved: None,
+                    held_until: None,
+                }
+            })
+            .collect();
+
+        Self {
+            targets,
+            selection: config.endpoint_selection,
+            ttl: config.dns_ttl,
+            next: 0,
+        }
+    }
+
+    /// Addresses to try for the next connection attempt, in order
+    ///
+    /// Every resolved address of every configured address is included, so a
+    /// hostname with several records fails over between them as well.
+    pub async fn candidates(&mut self) -> Vec<BridgeEndpoint> {
+        let mut candidates = Vec::new();
+        for target in &mut self.targets {
+            for addr in Self::resolve(target, self.ttl).await {
+                candidates.push(BridgeEndpoint {
+                    address: target.address.clone(),
+                    addr,
+                });
+            }
+        }
+
+        self.rotate(&mut candidates);
+        candidates.sort_by_key(|c| self.is_held(&c.address));
+        candidates
+    }
+
+    /// Configured addresses for the next attempt through an egress proxy,
+    /// which resolves names itself: `(address, host, port)` in order
+    pub fn unresolved(&mut self) -> Vec<(String, String, u16)> {
+        let mut targets: Vec<_> = self
+            .targets
+            .iter()
+            .map(|t| (t.address.clone(), t.host.clone(), t.port))
+            .collect();
+        self.rotate(&mut targets);
+        targets.sort_by_key(|(address, _, _)| self.is_held(address));
+        targets
+    }
+
+    /// Try an address that failed its health checks after the others for
+    /// `duration`
+    pub fn hold_down(&mut self, address: &str, duration: Duration) {
+        let until = Instant::now() + duration;
+        for target in &mut self.targets {
+            if target.address == address {
+                target.held_until = Some(until);
+            }
+        }
+    }
+
+    fn is_held(&self, address: &str) -> bool {
+        self.targets.iter().any(|t| {
+            t.address == address && t.held_until.is_some_and(|until| Instant::now() < until)
+        })
+    }
+
+    /// Apply round-robin selection to this attempt's candidates
+    fn rotate<T>(&mut self, items: &mut [T]) {
+        if self.selection == EndpointSelection::RoundRobin && !items.is_empty() {
+            let offset = self.next % items.len();
+            items.rotate_left(offset);
+            self.next = offset + 1;
+        }
+    }
+
+    /// Drop the cached lookup for an address that could not be reached, so
+    /// the next attempt asks DNS again
+    pub fn invalidate(&mut self, address: &str) {
+        for target in &mut self.targets {
+            if target.address == address {
+                target.resolved = None;
+            }
+        }
+    }
+
+    /// Look up a target, reusing a result younger than `ttl`
+    ///
+    /// A failed lookup falls back to the previous result, if any: a DNS
+    /// outage shouldn't take down the bridge while the upstream is fine.
+    async fn resolve(target: &mut Target, ttl: Duration) -> Vec<SocketAddr> {
+        if let Some((at, ref addrs)) = target.resolved {
+            if at.elapsed() < ttl {
+                return addrs.clone();
+            }
+        }
+
+        match tokio::net::lookup_host((target.host.as_str(), target.port)).await {
+            Ok(addrs) => {
+                let addrs: Vec<SocketAddr> = addrs.collect();
+                target.resolved = Some((Instant::now(), addrs.clone()));
+                addrs
+            }
+            Err(e) => {
+                warn!("Failed to resolve {}: {}", target.address, e);
+                target
+                    .resolved
+                    .as_ref()
+                    .map(|(_, addrs)| addrs.clone())
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(selection: EndpointSelection) -> BridgeConfig {
+        BridgeConfig {
+            address: "127.0.0.1:1883".to_string(),
+            failover_addresses: vec!["127.0.0.2:1883".to_string(), "[::1]:1884".to_string()],
+            endpoint_selection: selection,
+            ..Default::default()
+        }
+    }
+
+    fn ports_and_ips(candidates: &[BridgeEndpoint]) -> Vec<String> {
+        candidates.iter().map(|c| c.addr.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_failover_order() {
+        let mut resolver = EndpointResolver::new(&config(EndpointSelection::Failover));
+
+        for _ in 0..2 {
+            let candidates = resolver.candidates().await;
+            assert_eq!(
+                ports_and_ips(&candidates),
+                vec!["127.0.0.1:1883", "127.0.0.2:1883", "[::1]:1884"]
+            );
+            assert_eq!(candidates[2].address, "[::1]:1884");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_round_robin_order() {
+        let mut resolver = EndpointResolver::new(&config(EndpointSelection::RoundRobin));
+
+        let first: Vec<_> = [
+            resolver.candidates().await,
+            resolver.candidates().await,
+            resolver.candidates().await,
+            resolver.candidates().await,
+        ]
+        .iter()
+        .map(|c| c[0].addr.to_string())
+        .collect();
+        assert_eq!(
+            first,
+            vec![
+                "127.0.0.1:1883",
+                "127.0.0.2:1883",
+                "[::1]:1884",
+                "127.0.0.1:1883"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hold_down_moves_endpoint_last() {
+        let mut resolver = EndpointResolver::new(&config(EndpointSelection::Failover));
+        resolver.hold_down("127.0.0.1:1883", Duration::from_secs(60));
+        assert_eq!(
+            ports_and_ips(&resolver.candidates().await),
+            vec!["127.0.0.2:1883", "[::1]:1884", "127.0.0.1:1883"]
+        );
+        assert_eq!(resolver.unresolved()[2].0, "127.0.0.1:1883");
+
+        // Once the hold expires the configured order is restored
+        resolver.hold_down("127.0.0.1:1883", Duration::ZERO);
+        assert_eq!(resolver.candidates().await[0].address, "127.0.0.1:1883");
+    }
+
+    #[tokio::test]
+    async fn test_lookup_cache() {
+        let mut resolver = EndpointResolver::new(&BridgeConfig {
+            address: "localhost:1883".to_string(),
+            dns_ttl: Duration::from_secs(3600),
+            ..Default::default()
+        });
+
+        assert!(!resolver.candidates().await.is_empty());
+        let (cached_at, _) = resolver.targets[0].resolved.clone().unwrap();
+
+        // Within the TTL the previous lookup is reused
+        resolver.candidates().await;
+        assert_eq!(resolver.targets[0].resolved.as_ref().unwrap().0, cached_at);
+
+        // An unreachable address is looked up again on the next attempt
+        resolver.invalidate("localhost:1883");
+        assert!(resolver.targets[0].resolved.is_none());
+        resolver.candidates().await;
+        assert!(resolver.targets[0].resolved.as_ref().unwrap().0 > cached_at);
+    }
+
+    #[tokio::test]
+    async fn test_zero_ttl_resolves_every_time() {
+        let mut resolver = EndpointResolver::new(&BridgeConfig {
+            address: "localhost:1883".to_string(),
+            ..Default::default()
+        });
+
+        resolver.candidates().await;
+        let (cached_at, _) = resolver.targets[0].resolved.clone().unwrap();
+        resolver.candidates().await;
+        assert!(resolver.targets[0].resolved.as_ref().unwrap().0 > cached_at);
+    }
+}
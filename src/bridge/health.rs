@@ -0,0 +1,348 @@
+//! Bridge Health Probes
+//!
+//! Times the PINGREQ/PINGRESP round trips on a bridge connection and
+//! decides when a slow or silent link should be abandoned for the next
+//! endpoint. Results are kept per bridge for `BridgeManager::health` and
+//! exported as Prometheus metrics labelled by bridge name.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::RwLock;
+use prometheus::{Gauge, GaugeVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts};
+
+use crate::config::BridgeHealthConfig;
+use crate::metrics::Metrics;
+
+/// Weight of the newest sample in the smoothed round trip
+const RTT_SMOOTHING: f64 = 0.2;
+
+/// Latest probe results for a bridge
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BridgeHealth {
+    /// Round trip of the last answered probe
+    pub last_rtt: Option<Duration>,
+    /// Exponentially smoothed round trip
+    pub smoothed_rtt: Option<Duration>,
+    /// Consecutive slow or unanswered probes
+    pub consecutive_failures: u32,
+    /// Connections dropped for failing health checks
+    pub unhealthy_disconnects: u64,
+}
+
+/// Prometheus collectors for all bridges of a manager
+#[derive(Clone)]
+pub struct BridgeMetrics {
+    rtt: GaugeVec,
+    probe_failures: IntCounterVec,
+    unhealthy: IntCounterVec,
+    connected: IntGaugeVec,
+}
+
+impl BridgeMetrics {
+    /// Create the (unregistered) collectors
+    pub fn new() -> Self {
+        Self {
+            rtt: GaugeVec::new(
+                Opts::new(
+                    "vibemq_bridge_rtt_seconds",
+                    "Round trip of the last answered bridge PINGREQ",
+                ),
+                &["bridge"],
+            )
+            .unwrap(),
+            probe_failures: IntCounterVec::new(
+                Opts::new(
+                    "vibemq_bridge_probe_failures_total",
+                    "Bridge health probes that were too slow or went unanswered",
+                ),
+                &["bridge"],
+            )
+            .unwrap(),
+            unhealthy: IntCounterVec::new(
+                Opts::new(
+                    "vibemq_bridge_unhealthy_total",
+                    "Bridge connections dropped for failing health checks",
+                ),
+                &["bridge"],
+            )
+            .unwrap(),
+            connected: IntGaugeVec::new(
+                Opts::new(
+                    "vibemq_bridge_connected",
+                    "Whether the bridge is connected to its remote broker",
+                ),
+                &["bridge"],
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Add the collectors to the crate-wide registry
+    pub fn register(&self, metrics: &Metrics) -> prometheus::Result<()> {
+        metrics.register(Box::new(self.rtt.clone()))?;
+        metrics.register(Box::new(self.probe_failures.clone()))?;
+        metrics.register(Box::new(self.unhealthy.clone()))?;
+        metrics.register(Box::new(self.connected.clone()))
+    }
+
+    fn for_bridge(&self, name: &str) -> BridgeLinkMetrics {
+        BridgeLinkMetrics {
+            rtt: self.rtt.with_label_values(&[name]),
+            probe_failures: self.probe_failures.with_label_values(&[name]),
+            unhealthy: self.unhealthy.with_label_values(&[name]),
+            connected: self.connected.with_label_values(&[name]),
+        }
+    }
+}
+
+impl Default for BridgeMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One bridge's children of the `BridgeMetrics` collectors
+struct BridgeLinkMetrics {
+    rtt: Gauge,
+    probe_failures: IntCounter,
+    unhealthy: IntCounter,
+    connected: IntGauge,
+}
+
+/// Probe state of one bridge, kept across its connections
+pub(super) struct HealthMonitor {
+    config: BridgeHealthConfig,
+    shared: Arc<RwLock<BridgeHealth>>,
+    metrics: Option<BridgeLinkMetrics>,
+    /// When the outstanding PINGREQ was sent
+    outstanding: Option<Instant>,
+    /// Failure threshold reached on the current connection
+    tripped: bool,
+}
+
+impl HealthMonitor {
+    pub fn new(
+        name: &str,
+        config: BridgeHealthConfig,
+        shared: Arc<RwLock<BridgeHealth>>,
+        metrics: Option<&BridgeMetrics>,
+    ) -> Self {
+        Self {
+            config,
+            shared,
+            metrics: metrics.map(|m| m.for_bridge(name)),
+            outstanding: None,
+            tripped: false,
+        }
+    }
+
+    /// How often to send PINGREQ given the connection's keepalive
+    pub fn ping_interval(&self, keepalive: Duration) -> Duration {
+        [keepalive, self.config.interval]
+            .into_iter()
+            .filter(|d| !d.is_zero())
+            .min()
+            // Neither keepalive nor probes: tick rarely and never ping
+            .unwrap_or(Duration::from_secs(24 * 3600))
+    }
+
+    /// A new connection is up
+    pub fn connected(&mut self) {
+        self.outstanding = None;
+        self.tripped = false;
+        self.shared.write().consecutive_failures = 0;
+        if let Some(ref m) = self.metrics {
+            m.connected.set(1);
+        }
+    }
+
+    /// The connection ended; true when health checks ended it
+    pub fn disconnected(&mut self) -> bool {
+        self.outstanding = None;
+        if let Some(ref m) = self.metrics {
+            m.connected.set(0);
+        }
+        let unhealthy = std::mem::take(&mut self.tripped);
+        if unhealthy {
+            self.shared.write().unhealthy_disconnects += 1;
+            if let Some(ref m) = self.metrics {
+                m.unhealthy.inc();
+            }
+        }
+        unhealthy
+    }
+
+    /// A PINGREQ went out (one probe is timed at a time)
+    pub fn ping_sent(&mut self) {
+        self.outstanding.get_or_insert_with(Instant::now);
+    }
+
+    /// When the outstanding probe times out
+    pub fn deadline(&self) -> Option<Instant> {
+        self.outstanding.map(|sent| sent + self.config.timeout)
+    }
+
+    /// A PINGRESP arrived; true when the link should be abandoned
+    pub fn pong_received(&mut self) -> bool {
+        let Some(sent) = self.outstanding.take() else {
+            return false;
+        };
+        let rtt = sent.elapsed();
+
+        {
+            let mut health = self.shared.write();
+            health.last_rtt = Some(rtt);
+            health.smoothed_rtt = Some(match health.smoothed_rtt {
+                Some(smoothed) => {
+                    smoothed.mul_f64(1.0 - RTT_SMOOTHING) + rtt.mul_f64(RTT_SMOOTHING)
+                }
+                None => rtt,
+            });
+        }
+        if let Some(ref m) = self.metrics {
+            m.rtt.set(rtt.as_secs_f64());
+        }
+
+        if !self.config.max_latency.is_zero() && rtt > self.config.max_latency {
+            self.probe_failed()
+        } else {
+            self.shared.write().consecutive_failures = 0;
+            false
+        }
+    }
+
+    /// Check the outstanding probe against its deadline; true when the
+    /// link should be abandoned
+    pub fn check_timeout(&mut self) -> bool {
+        match self.deadline() {
+            Some(deadline) if Instant::now() >= deadline => {
+                self.outstanding = None;
+                self.probe_failed()
+            }
+            _ => false,
+        }
+    }
+
+    fn probe_failed(&mut self) -> bool {
+        if let Some(ref m) = self.metrics {
+            m.probe_failures.inc();
+        }
+        let mut health = self.shared.write();
+        health.consecutive_failures += 1;
+        self.tripped |= self.config.failure_threshold > 0
+            && health.consecutive_failures >= self.config.failure_threshold;
+        self.tripped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(config: BridgeHealthConfig) -> (HealthMonitor, Arc<RwLock<BridgeHealth>>) {
+        let shared = Arc::new(RwLock::new(BridgeHealth::default()));
+        let metrics = BridgeMetrics::new();
+        (
+            HealthMonitor::new("test", config, shared.clone(), Some(&metrics)),
+            shared,
+        )
+    }
+
+    #[test]
+    fn test_ping_interval() {
+        let (m, _) = monitor(BridgeHealthConfig {
+            interval: Duration::from_secs(5),
+            ..Default::default()
+        });
+        assert_eq!(
+            m.ping_interval(Duration::from_secs(60)),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            m.ping_interval(Duration::from_secs(2)),
+            Duration::from_secs(2)
+        );
+        assert_eq!(m.ping_interval(Duration::ZERO), Duration::from_secs(5));
+
+        let (m, _) = monitor(BridgeHealthConfig::default());
+        assert_eq!(
+            m.ping_interval(Duration::from_secs(60)),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn test_rtt_recorded() {
+        let (mut m, shared) = monitor(BridgeHealthConfig::default());
+        assert!(!m.pong_received(), "unsolicited PINGRESP is ignored");
+
+        m.ping_sent();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(!m.pong_received());
+        let health = shared.read().clone();
+        assert!(health.last_rtt.unwrap() >= Duration::from_millis(5));
+        assert_eq!(health.smoothed_rtt, health.last_rtt);
+        assert_eq!(
+            m.metrics.as_ref().unwrap().rtt.get(),
+            health.last_rtt.unwrap().as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn test_slow_probes_trip_threshold() {
+        let (mut m, shared) = monitor(BridgeHealthConfig {
+            max_latency: Duration::from_millis(1),
+            failure_threshold: 2,
+            ..Default::default()
+        });
+
+        m.ping_sent();
+        std::thread::sleep(Duration::from_millis(3));
+        assert!(!m.pong_received());
+        assert_eq!(shared.read().consecutive_failures, 1);
+
+        m.ping_sent();
+        std::thread::sleep(Duration::from_millis(3));
+        assert!(m.pong_received());
+
+        assert!(m.disconnected());
+        assert!(!m.disconnected());
+        m.connected();
+        let health = shared.read().clone();
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(health.unhealthy_disconnects, 1);
+        assert_eq!(m.metrics.as_ref().unwrap().unhealthy.get(), 1);
+        assert_eq!(m.metrics.as_ref().unwrap().probe_failures.get(), 2);
+    }
+
+    #[test]
+    fn test_unanswered_probe_times_out() {
+        let (mut m, shared) = monitor(BridgeHealthConfig {
+            timeout: Duration::from_millis(2),
+            failure_threshold: 1,
+            ..Default::default()
+        });
+        assert!(!m.check_timeout());
+
+        m.ping_sent();
+        assert!(!m.check_timeout());
+        std::thread::sleep(Duration::from_millis(3));
+        assert!(m.check_timeout());
+        assert!(m.deadline().is_none());
+        assert_eq!(shared.read().consecutive_failures, 1);
+    }
+
+    #[test]
+    fn test_zero_threshold_never_fails_over() {
+        let (mut m, _) = monitor(BridgeHealthConfig {
+            timeout: Duration::ZERO,
+            failure_threshold: 0,
+            ..Default::default()
+        });
+        for _ in 0..5 {
+            m.ping_sent();
+            assert!(!m.check_timeout());
+        }
+    }
+}
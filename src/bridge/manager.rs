@@ -9,16 +9,25 @@ use bytes::Bytes;
 use parking_lot::RwLock;
 use tracing::{debug, error, info};
 
+use crate::broker::ROUTING_ID_PROPERTY;
 use crate::protocol::QoS;
-use crate::remote::{RemotePeer, RemotePeerStatus};
+use crate::remote::{CompressionMetrics, PublishOrigin, RemotePeer, RemotePeerStatus};
 
 use super::client::{BridgeClient, InboundCallback};
+use super::endpoint::BridgeEndpoint;
+use super::health::{BridgeHealth, BridgeMetrics};
 use crate::config::BridgeConfig;
+use crate::metrics::Metrics;
+use crate::user_properties::PropertyIndex;
 
 /// Manages all bridge connections for a broker
 pub struct BridgeManager {
     /// All bridge connections
     bridges: RwLock<Vec<Arc<BridgeClient>>>,
+    /// Health probe collectors shared by all bridges
+    metrics: BridgeMetrics,
+    /// Compression collectors, shared with the rest of the broker
+    compression_metrics: CompressionMetrics,
 }
 
 impl BridgeManager {
@@ -26,12 +35,20 @@ impl BridgeManager {
     pub fn new() -> Self {
         Self {
             bridges: RwLock::new(Vec::new()),
+            metrics: BridgeMetrics::new(),
+            compression_metrics: CompressionMetrics::new(),
         }
     }
 
-    /// Create a bridge manager from configuration
-    pub fn from_configs(configs: Vec<BridgeConfig>, inbound_callback: InboundCallback) -> Self {
-        let manager = Self::new();
+    /// Create a bridge manager from configuration, reporting compressed
+    /// traffic to `compression_metrics`
+    pub fn from_configs(
+        configs: Vec<BridgeConfig>,
+        inbound_callback: InboundCallback,
+        compression_metrics: CompressionMetrics,
+    ) -> Self {
+        let mut manager = Self::new();
+        manager.compression_metrics = compression_metrics;
 
         for config in configs {
             if config.enabled {
@@ -45,7 +62,9 @@ impl BridgeManager {
     /// Add a new bridge connection
     pub fn add_bridge(&self, config: BridgeConfig, inbound_callback: InboundCallback) {
         let name = config.name.clone();
-        let client = BridgeClient::new(config);
+        let mut client = BridgeClient::new(config);
+        client.set_metrics(self.metrics.clone());
+        client.set_compression_metrics(self.compression_metrics.clone());
         let client = client.spawn(inbound_callback);
 
         info!("Bridge manager: Added bridge '{}'", name);
@@ -54,17 +73,35 @@ impl BridgeManager {
     }
 
     /// Forward a published message to all matching bridges
-    pub async fn forward_publish(&self, topic: &str, payload: Bytes, qos: QoS, retain: bool) {
+    pub async fn forward_publish(
+        &self,
+        topic: &str,
+        payload: Bytes,
+        qos: QoS,
+        retain: bool,
+        origin: Option<&PublishOrigin>,
+        user_properties: &[(String, String)],
+    ) {
         // Collect bridges first to avoid holding lock across await
         let bridges: Vec<_> = self.bridges.read().iter().cloned().collect();
+        // Indexed once for every bridge's forward rules
+        let properties = PropertyIndex::new(user_properties);
 
         for bridge in bridges {
             if bridge.should_forward(topic) && bridge.status() == RemotePeerStatus::Connected {
                 if let Err(e) = bridge
-                    .forward_publish(topic, payload.clone(), qos, retain)
+                    .forward_publish_from(topic, payload.clone(), qos, retain, origin, &properties)
                     .await
                 {
-                    debug!("Bridge '{}': Forward failed: {}", bridge.name(), e);
+                    debug!(
+                        routing_id = properties
+                            .values(ROUTING_ID_PROPERTY)
+                            .next()
+                            .unwrap_or_default(),
+                        "Bridge '{}': Forward failed: {}",
+                        bridge.name(),
+                        e
+                    );
                 }
             }
         }
@@ -98,6 +135,57 @@ impl BridgeManager {
             .collect()
     }
 
+    /// Get the remote address each bridge is connected to (None while
+    /// disconnected)
+    pub fn endpoints(&self) -> Vec<(String, Option<BridgeEndpoint>)> {
+        self.bridges
+            .read()
+            .iter()
+            .map(|b| (b.name().to_string(), b.endpoint()))
+            .collect()
+    }
+
+    /// Get the latest health probe results of each bridge
+    pub fn health(&self) -> Vec<(String, BridgeHealth)> {
+        self.bridges
+            .read()
+            .iter()
+            .map(|b| (b.name().to_string(), b.health()))
+            .collect()
+    }
+
+    /// Pause or resume every bridge not marked `critical`, returning the
+    /// names of those that changed
+    pub fn set_noncritical_paused(&self, paused: bool) -> Vec<String> {
+        let mut changed = Vec::new();
+        for bridge in self.bridges.read().iter().filter(|b| !b.is_critical()) {
+            if bridge.set_paused(paused) {
+                info!(
+                    "Bridge '{}': {}",
+                    bridge.name(),
+                    if paused { "Paused" } else { "Resumed" }
+                );
+                changed.push(bridge.name().to_string());
+            }
+        }
+        changed
+    }
+
+    /// Names of the paused bridges
+    pub fn paused(&self) -> Vec<String> {
+        self.bridges
+            .read()
+            .iter()
+            .filter(|b| b.is_paused())
+            .map(|b| b.name().to_string())
+            .collect()
+    }
+
+    /// Export bridge health metrics through the broker's registry
+    pub fn register_metrics(&self, metrics: &Metrics) -> prometheus::Result<()> {
+        self.metrics.register(metrics)
+    }
+
     /// Start all bridges
     pub async fn start_all(&self) {
         // Collect bridges first to avoid holding lock across await
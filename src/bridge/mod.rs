@@ -9,12 +9,34 @@
 //! - **no_local**: MQTT v5.0 subscription option that prevents receiving own messages
 //! - **User Property**: Tags messages with origin broker ID to detect loops
 //!
+//! # Endpoint Failover
+//!
+//! Hostnames are resolved again on each reconnect (see `dns_ttl`) and every
+//! address of `address` and `failover_addresses` is tried in turn, so
+//! DNS-based upstream failover takes effect without a restart.
+//!
+//! The keepalive PINGREQs (or more frequent probes, see `[bridge.health]`)
+//! are timed; a link whose probes go unanswered or exceed `max_latency`
+//! too often is dropped and its address tried after the others for a
+//! while. Round trips are exported as `vibemq_bridge_rtt_seconds`.
+//!
+//! # Compression
+//!
+//! With `[bridge.compression]`, the bridge offers its codecs in CONNECT
+//! (`x-vibemq-compression`). A VibeMQ remote that accepts one of them (see
+//! `mqtt.bridge_compression`) names it in CONNACK, and from then on
+//! forwarded payloads of at least `min_size` bytes are sent compressed and
+//! tagged `x-vibemq-content-encoding`. The remote unpacks them on arrival,
+//! so its subscribers see the original message. Messages the remote
+//! sends to the bridge are not compressed.
+//!
 //! # Example Configuration
 //!
 //! ```toml
 //! [[bridge]]
 //! name = "cloud"
 //! address = "cloud.example.com:8883"
+//! failover_addresses = ["cloud-dr.example.com:8883"]
 //! protocol = "mqtts"
 //! client_id = "edge-bridge-01"
 //! loop_prevention = "both"  # Uses no_local AND user property
@@ -27,20 +49,70 @@
 //! ```
 
 mod client;
+mod egress;
+mod endpoint;
+mod health;
 mod manager;
+mod session;
 mod topic_mapper;
 
 #[cfg(test)]
 mod tests;
 
 pub use client::BridgeClient;
+pub use endpoint::{BridgeEndpoint, EndpointResolver};
+pub use health::{BridgeHealth, BridgeMetrics};
 pub use manager::BridgeManager;
 pub use topic_mapper::TopicMapper;
 
+use crate::remote::PublishOrigin;
+
 // Re-export config types from the config module for convenience
 pub use crate::config::{
-    BridgeConfig, BridgeProtocol, ForwardDirection, ForwardRule, LoopPrevention,
+    BridgeConfig, BridgeHealthConfig, BridgeProtocol, BridgeProxyConfig, BridgeProxyVersion,
+    EgressProxyConfig, EgressProxyType, EndpointSelection, ForwardDirection, ForwardRule,
+    LoopPrevention,
 };
 
 /// User property key for bridge origin tracking (loop prevention)
 pub const BRIDGE_ORIGIN_PROPERTY: &str = "x-vibemq-origin";
+
+/// CONNECT/CONNACK user property negotiating payload compression: the
+/// codecs a bridge offers (comma-separated), and the one the remote takes
+pub const BRIDGE_COMPRESSION_PROPERTY: &str = "x-vibemq-compression";
+
+/// User property naming the codec a forwarded payload is compressed with
+pub const BRIDGE_ENCODING_PROPERTY: &str = "x-vibemq-content-encoding";
+
+/// User property carrying the publishing client's IP (`forward_origin`)
+pub const BRIDGE_FORWARDED_FOR_PROPERTY: &str = "x-forwarded-for";
+
+/// User property carrying the publishing client's source port
+pub const BRIDGE_FORWARDED_PORT_PROPERTY: &str = "x-forwarded-port";
+
+/// User property carrying the publishing client's ID
+pub const BRIDGE_FORWARDED_CLIENT_PROPERTY: &str = "x-forwarded-client-id";
+
+/// User properties describing where a forwarded message came from
+///
+/// The MQTT counterpart of `X-Forwarded-For`: a bridge multiplexes many
+/// publishers over one connection, so a per-connection PROXY header can't
+/// identify them. Unix socket clients have no address to report.
+pub fn forwarded_properties(origin: &PublishOrigin) -> Vec<(String, String)> {
+    let mut properties = Vec::with_capacity(3);
+    if let Some(addr) = origin.addr.socket_addr() {
+        properties.push((
+            BRIDGE_FORWARDED_FOR_PROPERTY.to_string(),
+            addr.ip().to_string(),
+        ));
+        properties.push((
+            BRIDGE_FORWARDED_PORT_PROPERTY.to_string(),
+            addr.port().to_string(),
+        ));
+    }
+    properties.push((
+        BRIDGE_FORWARDED_CLIENT_PROPERTY.to_string(),
+        origin.client_id.to_string(),
+    ));
+    properties
+}
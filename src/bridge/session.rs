@@ -0,0 +1,320 @@
+//! Bridge Session State
+//!
+//! Delivery state of a bridge's session with its remote broker, kept across
+//! reconnects: outbound QoS 1/2 publishes awaiting acknowledgement, publishes
+//! queued behind the in-flight window, and inbound QoS 2 packet IDs awaiting
+//! PUBREL. After a reconnect the unacknowledged publishes are sent again, so
+//! a dropped connection doesn't drop messages.
+
+use std::collections::{HashSet, VecDeque};
+
+use tracing::warn;
+
+use crate::protocol::{Packet, Properties, PubRel, Publish, QoS, ReasonCode};
+
+/// Publishes held back while the in-flight window is full
+const MAX_QUEUED: usize = 10_000;
+
+/// An outbound QoS 1/2 exchange
+enum Inflight {
+    /// Sent, awaiting PUBACK (QoS 1) or PUBREC (QoS 2)
+    Publish(Box<Publish>),
+    /// PUBREL sent, awaiting PUBCOMP
+    Release,
+}
+
+/// Delivery state of a bridge session
+pub(super) struct BridgeSession {
+    next_packet_id: u16,
+    /// Outbound exchanges in send order
+    inflight: VecDeque<(u16, Inflight)>,
+    /// Publishes waiting for a free in-flight slot
+    queued: VecDeque<Publish>,
+    /// Inbound QoS 2 packet IDs delivered locally but not yet released
+    inbound_qos2: HashSet<u16>,
+    /// Configured in-flight limit
+    max_inflight: usize,
+    /// Effective limit for the current connection
+    window: usize,
+}
+
+impl BridgeSession {
+    /// Create an empty session
+    pub fn new(max_inflight: u16) -> Self {
+        let max_inflight = usize::from(max_inflight.max(1));
+        Self {
+            next_packet_id: 1,
+            inflight: VecDeque::new(),
+            queued: VecDeque::new(),
+            inbound_qos2: HashSet::new(),
+            max_inflight,
+            window: max_inflight,
+        }
+    }
+
+    /// Start a new connection, returning the packets to send after CONNACK
+    ///
+    /// With `session_present` the remote still knows the exchanges in
+    /// flight, so publishes are retransmitted with DUP set and pending
+    /// releases resent. Otherwise publishes are redelivered as new, and
+    /// exchanges the remote already acknowledged with PUBREC are complete.
+    pub fn resume(&mut self, session_present: bool, receive_maximum: Option<u16>) -> Vec<Packet> {
+        self.window = self
+            .max_inflight
+            .min(usize::from(receive_maximum.unwrap_or(u16::MAX)).max(1));
+        if !session_present {
+            self.inbound_qos2.clear();
+            self.inflight
+                .retain(|(_, exchange)| matches!(exchange, Inflight::Publish(_)));
+        }
+
+        let mut packets: Vec<Packet> = self
+            .inflight
+            .iter()
+            .map(|(packet_id, exchange)| match exchange {
+                Inflight::Publish(publish) => Packet::Publish(Publish {
+                    dup: session_present,
+                    ..(**publish).clone()
+                }),
+                Inflight::Release => pubrel(*packet_id),
+            })
+            .collect();
+        packets.extend(self.fill());
+        packets
+    }
+
+    /// Prepare a publish for sending
+    ///
+    /// QoS 1/2 publishes get a packet ID and are tracked until acknowledged;
+    /// when the in-flight window is full they're queued and `None` returned.
+    pub fn publish(&mut self, publish: Publish) -> Option<Packet> {
+        if publish.qos == QoS::AtMostOnce {
+            return Some(Packet::Publish(publish));
+        }
+        if self.inflight.len() >= self.window {
+            if self.queued.len() >= MAX_QUEUED {
+                warn!("Bridge queue full, dropping message on {}", publish.topic);
+            } else {
+                self.queued.push_back(publish);
+            }
+            return None;
+        }
+        Some(self.send(publish))
+    }
+
+    /// Handle PUBACK, returning queued publishes that can now be sent
+    pub fn on_puback(&mut self, packet_id: u16) -> Vec<Packet> {
+        self.complete(packet_id);
+        self.fill()
+    }
+
+    /// Handle PUBREC, returning the PUBREL (and any queued publishes when
+    /// the remote refused the message)
+    pub fn on_pubrec(&mut self, packet_id: u16, reason_code: ReasonCode) -> Vec<Packet> {
+        let Some(exchange) = self.find(packet_id) else {
+            return Vec::new();
+        };
+        if reason_code.is_error() {
+            self.complete(packet_id);
+            return self.fill();
+        }
+        *exchange = Inflight::Release;
+        vec![pubrel(packet_id)]
+    }
+
+    /// Handle PUBCOMP, returning queued publishes that can now be sent
+    pub fn on_pubcomp(&mut self, packet_id: u16) -> Vec<Packet> {
+        self.complete(packet_id);
+        self.fill()
+    }
+
+    /// Record an inbound QoS 2 publish; false if it's a retransmission that
+    /// was already delivered
+    pub fn receive_qos2(&mut self, packet_id: u16) -> bool {
+        self.inbound_qos2.insert(packet_id)
+    }
+
+    /// Handle an inbound PUBREL
+    pub fn release(&mut self, packet_id: u16) {
+        self.inbound_qos2.remove(&packet_id);
+    }
+
+    /// Allocate a packet ID not used by an exchange in flight
+    pub fn next_packet_id(&mut self) -> u16 {
+        loop {
+            let packet_id = self.next_packet_id;
+            self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+            if !self.inflight.iter().any(|(id, _)| *id == packet_id) {
+                return packet_id;
+            }
+        }
+    }
+
+    /// Number of outbound exchanges awaiting acknowledgement
+    #[cfg(test)]
+    fn inflight_count(&self) -> usize {
+        self.inflight.len()
+    }
+
+    fn send(&mut self, mut publish: Publish) -> Packet {
+        let packet_id = self.next_packet_id();
+        publish.packet_id = Some(packet_id);
+        publish.dup = false;
+        self.inflight
+            .push_back((packet_id, Inflight::Publish(Box::new(publish.clone()))));
+        Packet::Publish(publish)
+    }
+
+    /// Send queued publishes while the window has room
+    fn fill(&mut self) -> Vec<Packet> {
+        let mut packets = Vec::new();
+        while self.inflight.len() < self.window {
+            let Some(publish) = self.queued.pop_front() else {
+                break;
+            };
+            packets.push(self.send(publish));
+        }
+        packets
+    }
+
+    fn find(&mut self, packet_id: u16) -> Option<&mut Inflight> {
+        self.inflight
+            .iter_mut()
+            .find(|(id, _)| *id == packet_id)
+            .map(|(_, exchange)| exchange)
+    }
+
+    fn complete(&mut self, packet_id: u16) {
+        self.inflight.retain(|(id, _)| *id != packet_id);
+    }
+}
+
+fn pubrel(packet_id: u16) -> Packet {
+    Packet::PubRel(PubRel {
+        packet_id,
+        reason_code: ReasonCode::Success,
+        properties: Properties::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn publish(topic: &str, qos: QoS) -> Publish {
+        Publish {
+            dup: false,
+            qos,
+            retain: false,
+            topic: topic.to_string(),
+            packet_id: None,
+            payload: Bytes::from_static(b"x"),
+            properties: Properties::default(),
+        }
+    }
+
+    fn sent(packet: &Packet) -> (u16, &str, bool) {
+        match packet {
+            Packet::Publish(p) => (p.packet_id.unwrap_or(0), p.topic.as_str(), p.dup),
+            other => panic!("expected PUBLISH, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_qos0_untracked() {
+        let mut session = BridgeSession::new(10);
+        let packet = session.publish(publish("a", QoS::AtMostOnce)).unwrap();
+        assert_eq!(sent(&packet), (0, "a", false));
+        assert_eq!(session.inflight_count(), 0);
+    }
+
+    #[test]
+    fn test_window_queues_and_drains() {
+        let mut session = BridgeSession::new(2);
+        assert_eq!(session.resume(false, None).len(), 0);
+
+        let first = session.publish(publish("a", QoS::AtLeastOnce)).unwrap();
+        let second = session.publish(publish("b", QoS::AtLeastOnce)).unwrap();
+        assert!(session.publish(publish("c", QoS::AtLeastOnce)).is_none());
+        assert_eq!(sent(&first).0, 1);
+        assert_eq!(sent(&second).0, 2);
+
+        let next = session.on_puback(1);
+        assert_eq!(next.len(), 1);
+        assert_eq!(sent(&next[0]), (3, "c", false));
+        assert_eq!(session.inflight_count(), 2);
+    }
+
+    #[test]
+    fn test_receive_maximum_limits_window() {
+        let mut session = BridgeSession::new(100);
+        session.resume(false, Some(1));
+        assert!(session.publish(publish("a", QoS::AtLeastOnce)).is_some());
+        assert!(session.publish(publish("b", QoS::AtLeastOnce)).is_none());
+    }
+
+    #[test]
+    fn test_qos2_exchange() {
+        let mut session = BridgeSession::new(10);
+        session.publish(publish("a", QoS::ExactlyOnce)).unwrap();
+
+        let rel = session.on_pubrec(1, ReasonCode::Success);
+        assert!(matches!(rel.as_slice(), [Packet::PubRel(r)] if r.packet_id == 1));
+        assert_eq!(session.inflight_count(), 1);
+
+        // Resumed session resends the release, not the publish
+        let resent = session.resume(true, None);
+        assert!(matches!(resent.as_slice(), [Packet::PubRel(r)] if r.packet_id == 1));
+
+        session.on_pubcomp(1);
+        assert_eq!(session.inflight_count(), 0);
+    }
+
+    #[test]
+    fn test_refused_pubrec_ends_exchange() {
+        let mut session = BridgeSession::new(10);
+        session.publish(publish("a", QoS::ExactlyOnce)).unwrap();
+        assert!(session.on_pubrec(1, ReasonCode::NotAuthorized).is_empty());
+        assert_eq!(session.inflight_count(), 0);
+    }
+
+    #[test]
+    fn test_resume_retransmits() {
+        let mut session = BridgeSession::new(10);
+        session.publish(publish("a", QoS::AtLeastOnce)).unwrap();
+        session.publish(publish("b", QoS::ExactlyOnce)).unwrap();
+        session.on_pubrec(2, ReasonCode::Success);
+
+        // Session kept: publish is a duplicate, release is resent
+        let resent = session.resume(true, None);
+        assert_eq!(resent.len(), 2);
+        assert_eq!(sent(&resent[0]), (1, "a", true));
+        assert!(matches!(resent[1], Packet::PubRel(_)));
+
+        // Session lost: publish is redelivered as new, release is dropped
+        let resent = session.resume(false, None);
+        assert_eq!(resent.len(), 1);
+        assert_eq!(sent(&resent[0]), (1, "a", false));
+    }
+
+    #[test]
+    fn test_packet_ids_skip_inflight() {
+        let mut session = BridgeSession::new(10);
+        session.next_packet_id = u16::MAX;
+        session.publish(publish("a", QoS::AtLeastOnce)).unwrap();
+        assert_eq!(session.next_packet_id(), 1);
+
+        session.next_packet_id = u16::MAX;
+        assert_eq!(session.next_packet_id(), 1);
+    }
+
+    #[test]
+    fn test_inbound_qos2_dedup() {
+        let mut session = BridgeSession::new(10);
+        assert!(session.receive_qos2(7));
+        assert!(!session.receive_qos2(7));
+        session.release(7);
+        assert!(session.receive_qos2(7));
+    }
+}
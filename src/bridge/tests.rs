@@ -71,6 +71,7 @@ fn test_forward_rule_directions() {
         direction: ForwardDirection::Out,
         qos: 1,
         retain: true,
+        user_properties: vec![],
     };
     assert!(out_rule.is_outbound());
     assert!(!out_rule.is_inbound());
@@ -149,6 +150,7 @@ fn make_rule(local: &str, remote: &str, direction: ForwardDirection, qos: u8) ->
         direction,
         qos,
         retain: true,
+        user_properties: vec![],
     }
 }
 
@@ -209,6 +211,7 @@ fn test_topic_mapper_retain_filtering() {
         direction: ForwardDirection::Out,
         qos: 1,
         retain: false,
+        user_properties: vec![],
     }];
     let mapper = TopicMapper::new(&rules);
 
@@ -390,3 +393,39 @@ fn test_bridge_config_toml_minimal() {
     assert_eq!(config.forwards[0].direction, ForwardDirection::Out); // Default
     assert_eq!(config.forwards[0].qos, 1); // Default
 }
+
+// =============================================================================
+// Forwarded Origin Tests
+// =============================================================================
+
+#[test]
+fn test_forwarded_properties() {
+    use crate::remote::PublishOrigin;
+    use crate::transport::PeerAddr;
+
+    let origin = PublishOrigin {
+        client_id: "sensor-1".into(),
+        addr: "192.0.2.10:50123"
+            .parse::<std::net::SocketAddr>()
+            .unwrap()
+            .into(),
+    };
+    assert_eq!(
+        super::forwarded_properties(&origin),
+        vec![
+            ("x-forwarded-for".to_string(), "192.0.2.10".to_string()),
+            ("x-forwarded-port".to_string(), "50123".to_string()),
+            ("x-forwarded-client-id".to_string(), "sensor-1".to_string()),
+        ]
+    );
+
+    // Unix socket clients only carry their ID
+    let unix = PublishOrigin {
+        client_id: "local".into(),
+        addr: PeerAddr::Unix(None),
+    };
+    assert_eq!(
+        super::forwarded_properties(&unix),
+        vec![("x-forwarded-client-id".to_string(), "local".to_string())]
+    );
+}
@@ -2,9 +2,10 @@
 //!
 //! Handles topic pattern matching and transformation between local and remote brokers.
 
-use crate::config::ForwardRule;
+use crate::config::{ForwardRule, UserPropertyMatch};
 use crate::protocol::QoS;
 use crate::topic::validation::topic_matches_filter;
+use crate::user_properties::PropertyIndex;
 
 /// Maps topics between local and remote brokers based on forwarding rules
 pub struct TopicMapper {
@@ -29,6 +30,8 @@ struct CompiledRule {
     strip_prefix: Option<String>,
     /// Prefix to add to destination topic
     add_prefix: Option<String>,
+    /// User properties outbound messages must carry
+    user_properties: Vec<UserPropertyMatch>,
 }
 
 impl CompiledRule {
@@ -59,6 +62,11 @@ impl CompiledRule {
             retain: rule.retain,
             strip_prefix,
             add_prefix,
+            user_properties: if outbound {
+                rule.user_properties.clone()
+            } else {
+                Vec::new()
+            },
         }
     }
 
@@ -169,6 +177,7 @@ impl TopicMapper {
     }
 
     /// Check if a local topic should be forwarded to the remote broker
+    /// (before `user_properties` conditions)
     pub fn should_forward_outbound(&self, topic: &str) -> bool {
         self.outbound_rules.iter().any(|r| r.matches(topic, true))
     }
@@ -176,8 +185,20 @@ impl TopicMapper {
     /// Map a local topic to remote topic for outbound forwarding
     /// Returns (remote_topic, qos, retain) if the topic should be forwarded
     pub fn map_outbound(&self, topic: &str, qos: QoS, retain: bool) -> Option<(String, QoS, bool)> {
+        self.map_outbound_tagged(topic, qos, retain, &PropertyIndex::default())
+    }
+
+    /// Map a local message to remote topic for outbound forwarding, taking
+    /// rules' `user_properties` conditions into account
+    pub fn map_outbound_tagged(
+        &self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        properties: &PropertyIndex,
+    ) -> Option<(String, QoS, bool)> {
         for rule in &self.outbound_rules {
-            if rule.matches(topic, true) {
+            if rule.matches(topic, true) && properties.matches_all(&rule.user_properties) {
                 let remote_topic = rule.transform(topic, true);
                 let effective_qos = qos.min(rule.qos);
                 let effective_retain = retain && rule.retain;
@@ -235,6 +256,7 @@ mod tests {
             direction,
             qos: 1,
             retain: true,
+            user_properties: vec![],
         }
     }
 
@@ -296,6 +318,31 @@ mod tests {
         assert!(!retain);
     }
 
+    #[test]
+    fn test_user_property_conditions() {
+        let mut tagged = make_rule("sensors/#", "prod/sensors/#", ForwardDirection::Out);
+        tagged.user_properties = vec![UserPropertyMatch::exact("env", "prod")];
+        let fallback = make_rule("sensors/#", "other/sensors/#", ForwardDirection::Out);
+        let mapper = TopicMapper::new(&[tagged, fallback]);
+
+        let prod = vec![("env".to_string(), "prod".to_string())];
+        let staging = vec![("env".to_string(), "staging".to_string())];
+        let map = |properties: &[(String, String)]| {
+            mapper
+                .map_outbound_tagged(
+                    "sensors/t",
+                    QoS::AtLeastOnce,
+                    false,
+                    &PropertyIndex::new(properties),
+                )
+                .unwrap()
+                .0
+        };
+        assert_eq!(map(&prod), "prod/sensors/t");
+        assert_eq!(map(&staging), "other/sensors/t");
+        assert_eq!(map(&[]), "other/sensors/t");
+    }
+
     #[test]
     fn test_inbound_filters() {
         let rules = vec![
@@ -0,0 +1,174 @@
+//! Per-Listener Connection Counts and Slow-Subscriber Backpressure
+//!
+//! Every connection holds a [`ListenerSlot`] from CONNECT on, counting it
+//! against its listener's `max_connections`. A subscriber whose backlog
+//! is over its listener's limits under `slow_client = "pause_publishers"`
+//! is marked congested here; publishers routing a message to it wait until
+//! it catches up (or the pause times out) instead of dropping the message,
+//! and since a waiting publisher's connection reads nothing more, TCP flow
+//! control slows the publisher down in turn.
+//!
+//! With `limits.publisher_backpressure` enabled, MQTT v5 publishers of QoS
+//! 1/2 messages are told instead: the acknowledgement is held while the
+//! subscribers catch up, which uses up the publisher's Receive Maximum,
+//! and past `max_delay` the message is rejected with Quota exceeded and
+//! the pause to take advised in the [`BACKPRESSURE_PROPERTY`] user property.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+/// User property on a publish rejected for congested subscribers: how long
+/// the publisher should pause before sending more, in milliseconds
+pub const BACKPRESSURE_PROPERTY: &str = "x-vibemq-backpressure";
+
+/// Connections per listener and congested subscribers
+#[derive(Default)]
+pub struct ListenerLoad {
+    connections: Mutex<HashMap<&'static str, usize>>,
+    /// Congested subscribers, each with the publishers waiting for it and
+    /// how long they wait at most
+    congested: DashMap<Arc<str>, (Arc<Notify>, Duration)>,
+}
+
+impl ListenerLoad {
+    /// Clients connected through `listener`
+    pub fn connections(&self, listener: &str) -> usize {
+        self.connections.lock().get(listener).copied().unwrap_or(0)
+    }
+
+    /// Count a connection against `listener`, unless `max` (0 = unlimited)
+    /// connections are already there; a session takeover always gets in,
+    /// as the connection it replaces is about to go
+    pub(crate) fn connect(
+        self: &Arc<Self>,
+        listener: &'static str,
+        client_id: &Arc<str>,
+        max: usize,
+        takeover: bool,
+    ) -> Option<ListenerSlot> {
+        let mut connections = self.connections.lock();
+        let count = connections.entry(listener).or_default();
+        if max > 0 && *count >= max && !takeover {
+            return None;
+        }
+        *count += 1;
+        Some(ListenerSlot {
+            load: self.clone(),
+            listener,
+            client_id: client_id.clone(),
+            congested: false,
+        })
+    }
+
+    /// Whether publishers wait for `client_id`
+    pub fn is_congested(&self, client_id: &str) -> bool {
+        self.congested.contains_key(client_id)
+    }
+
+    /// Wait until `client_id` is no longer congested, at most its
+    /// listener's `pause_timeout`
+    pub(crate) async fn wait_for(&self, client_id: &str) {
+        let Some((notify, limit)) = self.congested.get(client_id).map(|n| n.value().clone()) else {
+            return;
+        };
+        let relieved = notify.notified();
+        tokio::pin!(relieved);
+        relieved.as_mut().enable();
+        // Relieved between the lookup and enable()
+        if !self.congested.contains_key(client_id) {
+            return;
+        }
+        let _ = tokio::time::timeout(limit, relieved).await;
+    }
+}
+
+/// A connection counted against its listener (released on drop)
+pub(crate) struct ListenerSlot {
+    load: Arc<ListenerLoad>,
+    listener: &'static str,
+    client_id: Arc<str>,
+    congested: bool,
+}
+
+impl ListenerSlot {
+    /// Make publishers wait for the client, up to `pause_timeout` per
+    /// message
+    pub(crate) fn congest(&mut self, pause_timeout: Duration) {
+        if !self.congested {
+            self.congested = true;
+            self.load.congested.insert(
+                self.client_id.clone(),
+                (Arc::new(Notify::new()), pause_timeout),
+            );
+        }
+    }
+
+    pub(crate) fn is_congested(&self) -> bool {
+        self.congested
+    }
+
+    /// Resume the publishers waiting for the client
+    pub(crate) fn relieve(&mut self) {
+        if std::mem::take(&mut self.congested) {
+            if let Some((_, (notify, _))) = self.load.congested.remove(&self.client_id) {
+                notify.notify_waiters();
+            }
+        }
+    }
+}
+
+impl Drop for ListenerSlot {
+    fn drop(&mut self) {
+        self.relieve();
+        if let Some(count) = self.load.connections.lock().get_mut(self.listener) {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_listener_load() {
+        let load = Arc::new(ListenerLoad::default());
+        let (a, b): (Arc<str>, Arc<str>) = ("a".into(), "b".into());
+        let first = load.connect("ws", &a, 1, false).unwrap();
+        assert!(load.connect("ws", &b, 1, false).is_none());
+        let takeover = load.connect("ws", &a, 1, true).unwrap();
+        assert_eq!(load.connections("ws"), 2);
+        assert!(load.connect("tcp", &b, 0, false).is_some());
+        drop(first);
+        drop(takeover);
+        assert_eq!(load.connections("ws"), 0);
+
+        // Waiters resume when the subscriber is relieved or time out
+        let mut slot = load.connect("tcp", &a, 0, false).unwrap();
+        load.wait_for("a").await;
+        slot.congest(Duration::from_secs(5));
+        assert!(load.is_congested("a"));
+        let waiter = {
+            let load = load.clone();
+            tokio::spawn(async move { load.wait_for("a").await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        slot.relieve();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter resumed")
+            .unwrap();
+
+        slot.congest(Duration::from_millis(50));
+        let started = std::time::Instant::now();
+        load.wait_for("a").await;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        drop(slot);
+        assert!(!load.is_congested("a"));
+    }
+}
@@ -0,0 +1,251 @@
+//! End-to-End Publish Confirmation
+//!
+//! A QoS 1 PUBACK normally means the broker has the message. An in-process
+//! consumer writing messages on to a backend (a rule's `ack_action`) can
+//! register a topic filter here to hold back the PUBACKs of matching
+//! messages until it has handled them, so a publisher only lets go of a
+//! message once the backend has it. Messages not confirmed in time get the
+//! registration's fallback answer.
+//!
+//! Held messages are recognized by topic and payload buffer: the copies
+//! routed to subscribers share the publisher's `Bytes`, so nothing has to
+//! travel with the message.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::oneshot;
+
+use crate::config::AckFallback;
+use crate::protocol::Publish;
+use crate::topic::topic_matches_filter;
+
+/// Identity of a routed message
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    topic: String,
+    payload: usize,
+    len: usize,
+}
+
+impl Key {
+    fn of(publish: &Publish) -> Self {
+        Self {
+            topic: publish.topic.clone(),
+            payload: publish.payload.as_ptr() as usize,
+            len: publish.payload.len(),
+        }
+    }
+}
+
+/// Held copies of one message: waiter ID and its confirmation
+type Waiters = VecDeque<(u64, oneshot::Sender<bool>)>;
+
+/// One registered filter and its held messages
+struct Gate {
+    filter: String,
+    timeout: Duration,
+    fallback: AckFallback,
+    /// Oldest first, so identical messages are confirmed in order
+    pending: Mutex<HashMap<Key, Waiters>>,
+}
+
+impl Gate {
+    fn take(&self, key: &Key) -> Option<oneshot::Sender<bool>> {
+        let mut pending = self.pending.lock();
+        let waiters = pending.get_mut(key)?;
+        let (_, tx) = waiters.pop_front()?;
+        if waiters.is_empty() {
+            pending.remove(key);
+        }
+        Some(tx)
+    }
+
+    fn cancel(&self, key: &Key, id: u64) {
+        let mut pending = self.pending.lock();
+        if let Some(waiters) = pending.get_mut(key) {
+            waiters.retain(|(waiter, _)| *waiter != id);
+            if waiters.is_empty() {
+                pending.remove(key);
+            }
+        }
+    }
+}
+
+/// Filters whose QoS 1 PUBACKs wait for a confirmation (see the module docs)
+#[derive(Default)]
+pub struct Confirmations {
+    gates: RwLock<Vec<Arc<Gate>>>,
+    next_id: AtomicU64,
+}
+
+impl Confirmations {
+    /// Hold PUBACKs of messages matching `filter` until the returned handle
+    /// confirms them (or `timeout` passes); dropping it releases them all
+    /// with `fallback`
+    pub fn register(
+        self: &Arc<Self>,
+        filter: &str,
+        timeout: Duration,
+        fallback: AckFallback,
+    ) -> ConfirmFilter {
+        let gate = Arc::new(Gate {
+            filter: filter.to_string(),
+            timeout,
+            fallback,
+            pending: Mutex::new(HashMap::new()),
+        });
+        self.gates.write().push(gate.clone());
+        ConfirmFilter {
+            confirmations: self.clone(),
+            gate,
+        }
+    }
+
+    /// Start holding a message's PUBACK, if a filter matches it; call
+    /// before routing the message
+    pub(crate) fn hold(&self, publish: &Publish) -> Option<HeldPublish> {
+        let gates = self.gates.read();
+        if gates.is_empty() {
+            return None;
+        }
+        let key = Key::of(publish);
+        let waits: Vec<_> = gates
+            .iter()
+            .filter(|gate| topic_matches_filter(&publish.topic, &gate.filter))
+            .map(|gate| {
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                let (tx, rx) = oneshot::channel();
+                gate.pending
+                    .lock()
+                    .entry(key.clone())
+                    .or_default()
+                    .push_back((id, tx));
+                (gate.clone(), id, rx)
+            })
+            .collect();
+        if waits.is_empty() {
+            return None;
+        }
+        Some(HeldPublish { key, waits })
+    }
+}
+
+/// A registered filter (see `Confirmations::register`)
+pub struct ConfirmFilter {
+    confirmations: Arc<Confirmations>,
+    gate: Arc<Gate>,
+}
+
+impl ConfirmFilter {
+    /// The confirmation a delivered message's publisher waits for, if it
+    /// waits for one
+    pub fn take(&self, publish: &Publish) -> Option<Confirm> {
+        self.gate.take(&Key::of(publish)).map(Confirm)
+    }
+}
+
+impl Drop for ConfirmFilter {
+    fn drop(&mut self) {
+        self.confirmations
+            .gates
+            .write()
+            .retain(|gate| !Arc::ptr_eq(gate, &self.gate));
+        self.gate.pending.lock().clear();
+    }
+}
+
+/// Tells a held message's publisher how its message fared; dropping it
+/// counts as a failure
+pub struct Confirm(oneshot::Sender<bool>);
+
+impl Confirm {
+    pub fn confirm(self, success: bool) {
+        let _ = self.0.send(success);
+    }
+}
+
+/// A message whose PUBACK is held
+pub(crate) struct HeldPublish {
+    key: Key,
+    waits: Vec<(Arc<Gate>, u64, oneshot::Receiver<bool>)>,
+}
+
+impl HeldPublish {
+    /// Wait for every matching filter; false if the message should be
+    /// rejected
+    pub(crate) async fn wait(self) -> bool {
+        let key = &self.key;
+        let results = join_all(self.waits.into_iter().map(|(gate, id, rx)| async move {
+            match tokio::time::timeout(gate.timeout, rx).await {
+                Ok(Ok(true)) => true,
+                result => {
+                    if result.is_err() {
+                        gate.cancel(key, id);
+                    }
+                    gate.fallback == AckFallback::Puback
+                }
+            }
+        }))
+        .await;
+        results.into_iter().all(|ack| ack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn publish(topic: &str, payload: &Bytes) -> Publish {
+        Publish {
+            topic: topic.to_string(),
+            payload: payload.clone(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_confirmations() {
+        let confirmations = Arc::new(Confirmations::default());
+        let payload = Bytes::from_static(b"21.5");
+        assert!(confirmations.hold(&publish("orders/1", &payload)).is_none());
+
+        let filter =
+            confirmations.register("orders/#", Duration::from_millis(50), AckFallback::Reject);
+        assert!(confirmations
+            .hold(&publish("sensors/1", &payload))
+            .is_none());
+
+        // Confirmed by the consumer, which sees a routed copy
+        let held = confirmations.hold(&publish("orders/1", &payload)).unwrap();
+        let routed = publish("orders/1", &payload);
+        // Same content in another buffer is a different message
+        assert!(filter
+            .take(&publish("orders/1", &Bytes::copy_from_slice(b"21.5")))
+            .is_none());
+        filter.take(&routed).unwrap().confirm(true);
+        assert!(held.wait().await);
+
+        // Failed, timed out and dropped confirmations fall back
+        let held = confirmations.hold(&publish("orders/1", &payload)).unwrap();
+        filter.take(&routed).unwrap().confirm(false);
+        assert!(!held.wait().await);
+        let held = confirmations.hold(&publish("orders/1", &payload)).unwrap();
+        assert!(!held.wait().await);
+        assert!(filter.take(&routed).is_none());
+        let held = confirmations.hold(&publish("orders/1", &payload)).unwrap();
+        drop(filter);
+        assert!(!held.wait().await);
+        assert!(confirmations.hold(&publish("orders/1", &payload)).is_none());
+
+        let _lenient =
+            confirmations.register("orders/#", Duration::from_millis(10), AckFallback::Puback);
+        let held = confirmations.hold(&publish("orders/1", &payload)).unwrap();
+        assert!(held.wait().await);
+    }
+}
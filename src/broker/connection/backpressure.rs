@@ -0,0 +1,326 @@
+//! Per-listener limits (`limits.listeners`)
+//!
+//! At CONNECT the client takes a slot on its listener, or is refused with
+//! Server unavailable when the listener's `max_connections` are taken.
+//! Before each message goes out, the subscriber's backlog is checked
+//! against the listener's limits and `slow_client` applies: QoS 0 messages
+//! are dropped, publishers are paused (see [`crate::broker::ListenerLoad`])
+//! or the client is disconnected. Messages on priority topics are exempt.
+//! Backlog limits changed at runtime (see [`crate::broker::Tunables`])
+//! apply from the next message on.
+//!
+//! Under `limits.publisher_backpressure`, QoS 1/2 messages from MQTT v5
+//! clients that would wait for congested subscribers are held before they
+//! are acknowledged, while the connection keeps reading, then rejected with
+//! Quota exceeded (see [`crate::broker::BACKPRESSURE_PROPERTY`]) if the
+//! subscribers haven't caught up within `max_delay`.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::RwLock;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use super::{Connection, ConnectionError, Diagnostic};
+use crate::broker::BACKPRESSURE_PROPERTY;
+use crate::config::{ListenerLimits, SlowClientPolicy};
+use crate::protocol::{ProtocolError, ProtocolVersion, Publish, QoS, ReasonCode};
+use crate::session::Session;
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Count the client against its listener; false if the listener is
+    /// full
+    pub(crate) fn take_listener_slot(&mut self, client_id: &Arc<str>, takeover: bool) -> bool {
+        self.listener_limits = self.config.listener_limits.get(self.listener).copied();
+        let Some(ref load) = self.listener_load else {
+            return true;
+        };
+        let max = self
+            .listener_limits
+            .map_or(0, |limits| limits.max_connections);
+        self.listener_slot = load.connect(self.listener, client_id, max, takeover);
+        self.listener_slot.is_some()
+    }
+
+    /// The listener's limits, with backlog limits changed at runtime
+    fn backlog_limits(&self) -> Option<ListenerLimits> {
+        match self.tunables {
+            Some(ref tunables) => tunables.listener_limits(self.listener, self.listener_limits),
+            None => self.listener_limits,
+        }
+    }
+
+    /// Resume paused publishers once the client has caught up without
+    /// another message going out
+    pub(crate) fn relieve_backpressure(&mut self, session: &Arc<RwLock<Session>>) {
+        let limits = self.backlog_limits();
+        let (Some(limits), Some(slot)) = (limits, self.listener_slot.as_mut()) else {
+            return;
+        };
+        if slot.is_congested() {
+            let s = session.read();
+            let messages = s.pending_count() + self.packet_rx.len();
+            if !limits.is_backlogged(messages, s.pending_bytes()) {
+                slot.relieve();
+            }
+        }
+    }
+
+    /// Apply the listener's `slow_client` policy before delivering
+    /// `publish`; false if the message is dropped
+    pub(crate) async fn apply_backpressure(
+        &mut self,
+        session: &Arc<RwLock<Session>>,
+        publish: &Publish,
+    ) -> Result<bool, ConnectionError> {
+        let Some(limits) = self.backlog_limits().filter(|l| l.limits_backlog()) else {
+            return Ok(true);
+        };
+        // Priority messages aren't shed
+        if self.is_priority(&publish.topic) {
+            return Ok(true);
+        }
+        let (client_id, messages, bytes) = {
+            let s = session.read();
+            (
+                s.client_id.clone(),
+                s.pending_count() + self.packet_rx.len(),
+                s.pending_bytes(),
+            )
+        };
+        let backlogged = limits.is_backlogged(messages, bytes);
+
+        match limits.slow_client {
+            SlowClientPolicy::DropQos0 => {
+                if backlogged && publish.qos == QoS::AtMostOnce {
+                    self.trace_held(publish, "slow client");
+                    if let Some(ref metrics) = self.metrics {
+                        metrics.message_dropped("slow_client");
+                    }
+                    return Ok(false);
+                }
+            }
+            SlowClientPolicy::PausePublishers => {
+                if let Some(ref mut slot) = self.listener_slot {
+                    if backlogged {
+                        if !slot.is_congested() {
+                            debug!(
+                                "{} is behind ({} messages), pausing its publishers",
+                                client_id, messages
+                            );
+                        }
+                        slot.congest(limits.pause_timeout);
+                    } else {
+                        slot.relieve();
+                    }
+                }
+            }
+            SlowClientPolicy::Disconnect => {
+                if backlogged {
+                    warn!(
+                        "Disconnecting {}: too far behind ({} messages, {} bytes queued)",
+                        client_id, messages, bytes
+                    );
+                    let diagnostic = if messages > limits.max_outbound_messages
+                        && limits.max_outbound_messages > 0
+                    {
+                        Diagnostic::limit("max_outbound_messages", limits.max_outbound_messages)
+                    } else {
+                        Diagnostic::limit("max_outbound_bytes", limits.max_outbound_bytes)
+                    };
+                    self.send_disconnect(ReasonCode::QuotaExceeded, diagnostic)
+                        .await;
+                    return Err(ConnectionError::Protocol(ProtocolError::QuotaExceeded));
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Hold a QoS 1/2 message from an MQTT v5 client while subscribers it
+    /// is routed to are congested
+    ///
+    /// Returns the message if it can be acknowledged and routed now. A held
+    /// message waits off the connection, which keeps reading, and is settled
+    /// by [`Self::release_throttled`]. Until it is acknowledged it counts
+    /// against the client's Receive Maximum, so a publisher that outpaces
+    /// its subscribers runs out of quota and slows down. Messages arriving
+    /// while others are held queue behind them, so they are routed in order.
+    pub(crate) async fn throttle_publisher(
+        &mut self,
+        client_id: &Arc<str>,
+        session: &Arc<RwLock<Session>>,
+        publish: Publish,
+        retained_lifetime: Option<u32>,
+    ) -> Result<Option<Publish>, ConnectionError> {
+        let config = &self.config.publisher_backpressure;
+        let max_delay = config.max_delay;
+        if !config.enabled
+            || publish.qos == QoS::AtMostOnce
+            || self.decoder.protocol_version() != Some(ProtocolVersion::V5)
+            || self.is_priority(&publish.topic)
+        {
+            return Ok(Some(publish));
+        }
+        let Some(load) = self.listener_load.clone() else {
+            return Ok(Some(publish));
+        };
+        // A QoS 2 message already received is only acknowledged again
+        let stored = publish
+            .packet_id
+            .is_some_and(|id| session.read().inflight_incoming.contains_key(&id));
+        if stored {
+            return Ok(Some(publish));
+        }
+        // A resend of a message still held is answered when it's settled
+        if self
+            .throttled
+            .iter()
+            .any(|held| held.publish.packet_id == publish.packet_id)
+        {
+            return Ok(None);
+        }
+
+        let congested = self
+            .subscriptions
+            .find_subscriber(&publish.topic, client_id, |id| load.is_congested(id));
+        if congested.is_none() && self.throttled.is_empty() {
+            return Ok(Some(publish));
+        }
+        // [MQTT-3.3.4-9] Held messages are unacknowledged, so a client
+        // sending more than the Receive Maximum is in breach
+        let receive_maximum = self.config.receive_maximum as usize;
+        if self.throttled.len() >= receive_maximum {
+            self.send_disconnect(
+                ReasonCode::ReceiveMaxExceeded,
+                Diagnostic::limit("receive_maximum", receive_maximum),
+            )
+            .await;
+            return Err(ConnectionError::Protocol(ProtocolError::ProtocolViolation(
+                "receive maximum exceeded",
+            )));
+        }
+
+        let wait = congested.map(|subscriber| {
+            let subscriptions = self.subscriptions.clone();
+            let (topic, client_id) = (publish.topic.clone(), client_id.clone());
+            let deadline = tokio::time::Instant::now() + max_delay;
+            tokio::spawn(async move {
+                let mut subscriber = subscriber;
+                loop {
+                    if tokio::time::Instant::now() >= deadline {
+                        return Some(subscriber);
+                    }
+                    let _ = tokio::time::timeout_at(deadline, load.wait_for(&subscriber)).await;
+                    match subscriptions
+                        .find_subscriber(&topic, &client_id, |id| load.is_congested(id))
+                    {
+                        Some(behind) => subscriber = behind,
+                        None => return None,
+                    }
+                }
+            })
+        });
+        self.throttled.push_back(Throttled {
+            publish,
+            retained_lifetime,
+            wait,
+        });
+        Ok(None)
+    }
+
+    /// Settle the oldest held message once its subscribers caught up or
+    /// `publisher_backpressure.max_delay` passed
+    ///
+    /// `behind` is the subscriber still congested at the deadline, if any;
+    /// the message is then rejected with Quota exceeded.
+    pub(crate) async fn release_throttled(
+        &mut self,
+        client_id: &Arc<str>,
+        session: &Arc<RwLock<Session>>,
+        behind: Option<Arc<str>>,
+    ) -> Result<(), ConnectionError> {
+        let Some(held) = self.throttled.pop_front() else {
+            return Ok(());
+        };
+        self.wake_from_hibernation();
+        let publish = held.publish;
+        if let Some(subscriber) = behind {
+            debug!(
+                "Rejecting publish from {} to {}: {} is behind",
+                client_id, publish.topic, subscriber
+            );
+            session.write().publishes_rejected += 1;
+            if let Some(ref metrics) = self.metrics {
+                metrics.publisher_backpressure("rejected");
+            }
+            let retry_after = self.config.publisher_backpressure.retry_after;
+            return self.reject_throttled(&publish, retry_after).await;
+        }
+        if held.wait.is_some() {
+            session.write().publishes_held += 1;
+            if let Some(ref metrics) = self.metrics {
+                metrics.publisher_backpressure("held");
+            }
+        }
+        self.settle_publish(client_id, session, publish, held.retained_lifetime)
+            .await
+    }
+
+    /// Reject a message for congested subscribers with Quota exceeded,
+    /// advising the publisher to pause for `retry_after`
+    async fn reject_throttled(
+        &mut self,
+        publish: &Publish,
+        retry_after: Duration,
+    ) -> Result<(), ConnectionError> {
+        let diagnostic = Diagnostic {
+            retry_after: Some(retry_after),
+            ..Diagnostic::limit(
+                "publisher_backpressure.max_delay_ms",
+                self.config.publisher_backpressure.max_delay.as_millis() as usize,
+            )
+        }
+        .with_detail("subscribers are behind");
+        let (reason_code, mut properties) =
+            self.client_error(ReasonCode::QuotaExceeded, diagnostic, || {
+                format!("publish to '{}'", publish.topic)
+            });
+        // Advised whatever the error detail policy, so publishers can rely
+        // on it to pace themselves
+        properties.user_properties.push((
+            BACKPRESSURE_PROPERTY.to_string(),
+            retry_after.as_millis().to_string(),
+        ));
+        self.fit_client_diagnostics(&mut properties, 0);
+        self.send_publish_response(publish, reason_code, properties)
+            .await
+    }
+}
+
+/// A QoS 1/2 message held for congested subscribers
+pub(crate) struct Throttled {
+    publish: Publish,
+    retained_lifetime: Option<u32>,
+    /// Resolves to the subscriber still behind at the deadline, if any
+    /// (`None` for a message only queued behind held ones)
+    wait: Option<JoinHandle<Option<Arc<str>>>>,
+}
+
+/// Wait until the oldest held message can be settled (forever if none is
+/// held); see [`Connection::release_throttled`]
+pub(crate) async fn next_throttled(throttled: &mut VecDeque<Throttled>) -> Option<Arc<str>> {
+    match throttled.front_mut() {
+        Some(Throttled {
+            wait: Some(wait), ..
+        }) => wait.await.ok().flatten(),
+        Some(_) => None,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,251 @@
+//! Batch frame handling (message batching extension)
+//!
+//! A PUBLISH to the configured batch topic carries a batch frame (see
+//! [`crate::codec::decode_batch`]). The broker unpacks it and routes each
+//! entry as an individual message with the batch's QoS, retain flag and
+//! properties. Every entry is validated and checked against ACL before
+//! anything is routed; if any entry fails, the whole batch is rejected.
+
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::{debug, error, trace};
+
+use super::{Connection, ConnectionError, Diagnostic};
+use crate::codec::decode_batch;
+use crate::hooks::AccessAction;
+use crate::protocol::{DecodeError, Publish, QoS, ReasonCode};
+use crate::session::Session;
+use crate::topic::validate_topic_name_with_max_levels;
+
+/// Join the batch topic prefix and an entry topic
+fn entry_topic(prefix: &str, topic: &str) -> String {
+    match (prefix.is_empty(), topic.is_empty()) {
+        (true, _) => topic.to_string(),
+        (false, true) => prefix.to_string(),
+        (false, false) => format!("{}/{}", prefix, topic),
+    }
+}
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Handle a PUBLISH to the batch topic
+    pub(crate) async fn handle_batch(
+        &mut self,
+        client_id: &Arc<str>,
+        session: &Arc<RwLock<Session>>,
+        publish: Publish,
+        prefix: &str,
+    ) -> Result<(), ConnectionError> {
+        let mut messages = match self.unpack_batch(client_id, &publish, prefix).await {
+            Ok(messages) => messages,
+            Err((reason_code, diagnostic)) => {
+                if let Some(ref metrics) = self.metrics {
+                    metrics.batch_rejected();
+                }
+                self.send_publish_error(&publish, reason_code, diagnostic)
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        // Every entry counts against the publish rate limit
+        if let Err(diagnostic) = self
+            .check_publish_rate(client_id, messages.len() as u32)
+            .await
+        {
+            self.send_publish_error(&publish, ReasonCode::QuotaExceeded, diagnostic)
+                .await?;
+            return Ok(());
+        }
+
+        trace!(
+            "Batch of {} messages from {} (QoS {:?})",
+            messages.len(),
+            client_id,
+            publish.qos
+        );
+        if let Some(ref metrics) = self.metrics {
+            metrics.batch_received(messages.len());
+        }
+
+        // TTL limits apply per entry topic
+        let mut lifetimes = Vec::with_capacity(messages.len());
+        for message in &mut messages {
+            lifetimes.push(self.apply_publish_ttl(client_id, message).await);
+        }
+
+        // Held until the client commits
+        if self.transaction.is_some() {
+            return self
+                .hold_in_transaction(client_id, &publish, messages.into_iter().zip(lifetimes))
+                .await;
+        }
+
+        match publish.qos {
+            QoS::AtMostOnce => {}
+            QoS::AtLeastOnce => {
+                self.send_puback(publish.packet_id.unwrap()).await?;
+            }
+            QoS::ExactlyOnce => {
+                // The batch is unpacked again and routed on PUBREL
+                if self.await_release(session, &publish).await? {
+                    for (message, lifetime) in messages.iter().zip(lifetimes) {
+                        self.store_retained(client_id, message, lifetime);
+                    }
+                }
+                return Ok(());
+            }
+        }
+
+        for (message, lifetime) in messages.iter().zip(lifetimes) {
+            self.store_retained(client_id, message, lifetime);
+            self.route_message(client_id, message).await?;
+        }
+        Ok(())
+    }
+
+    /// Route a released QoS 2 message, unpacking it if it's a batch
+    pub(crate) async fn route_released(
+        &self,
+        client_id: &Arc<str>,
+        publish: &Publish,
+    ) -> Result<(), ConnectionError> {
+        let prefix = self
+            .config
+            .batch
+            .enabled
+            .then(|| self.config.batch.prefix(&publish.topic))
+            .flatten();
+        let Some(prefix) = prefix else {
+            return self.route_message(client_id, publish).await;
+        };
+
+        // Already validated when the PUBLISH was received
+        let entries = decode_batch(&publish.payload, 0).unwrap_or_default();
+        for entry in entries {
+            let mut message = Publish {
+                topic: entry_topic(prefix, &entry.topic),
+                payload: entry.payload,
+                ..Self::batch_template(publish)
+            };
+            // The hooks accepted the entries on PUBLISH; they rewrite them again
+            if self
+                .intercept_publish(client_id, &mut message)
+                .await
+                .is_err()
+            {
+                continue;
+            }
+            self.apply_publish_ttl(client_id, &mut message).await;
+            self.route_message(client_id, &message).await?;
+        }
+        Ok(())
+    }
+
+    /// Decode a batch frame, validating and authorizing every entry
+    async fn unpack_batch(
+        &self,
+        client_id: &Arc<str>,
+        publish: &Publish,
+        prefix: &str,
+    ) -> Result<Vec<Publish>, (ReasonCode, Diagnostic)> {
+        let max_messages = match self.tunables {
+            Some(ref tunables) => tunables.batch_max_messages(self.config.batch.max_messages),
+            None => self.config.batch.max_messages,
+        };
+        let entries = decode_batch(&publish.payload, max_messages).map_err(|e| {
+            debug!("Invalid batch frame from {}: {}", client_id, e);
+            match e {
+                DecodeError::PacketTooLarge => (
+                    ReasonCode::QuotaExceeded,
+                    Diagnostic::limit("batch.max_messages", max_messages),
+                ),
+                _ => (
+                    ReasonCode::PayloadFormatInvalid,
+                    Diagnostic::default().with_detail("invalid batch frame"),
+                ),
+            }
+        })?;
+
+        let mut template = Self::batch_template(publish);
+        if template.retain {
+            self.stamp_forwarded_for(&mut template.properties);
+        }
+        let mut messages = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let topic = entry_topic(prefix, &entry.topic);
+            if let Err(e) =
+                validate_topic_name_with_max_levels(&topic, self.config.max_topic_levels)
+            {
+                debug!("Invalid batch entry topic from {}: {}", client_id, e);
+                return Err((
+                    ReasonCode::TopicNameInvalid,
+                    self.topic_diagnostic(&topic, e),
+                ));
+            }
+
+            match self
+                .hooks
+                .on_publish_check_with_identity(
+                    client_id,
+                    self.username.as_deref(),
+                    &topic,
+                    publish.qos,
+                    publish.retain,
+                    self.proxy_identity.as_deref(),
+                )
+                .await
+            {
+                Ok(true) => {}
+                Ok(false) => {
+                    debug!(
+                        "Batch denied for {}: entry topic {} (ACL)",
+                        client_id, topic
+                    );
+                    let diagnostic = self
+                        .access_diagnostic(
+                            client_id,
+                            AccessAction::Publish,
+                            &topic,
+                            publish.qos,
+                            publish.retain,
+                        )
+                        .await;
+                    return Err((ReasonCode::NotAuthorized, diagnostic));
+                }
+                Err(e) => {
+                    error!("ACL check error for {}: {}", client_id, e);
+                    return Err((ReasonCode::UnspecifiedError, Diagnostic::default()));
+                }
+            }
+
+            let mut message = Publish {
+                topic,
+                payload: entry.payload,
+                ..template.clone()
+            };
+            self.intercept_publish(client_id, &mut message).await?;
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    /// Publish fields shared by all entries of a batch
+    fn batch_template(publish: &Publish) -> Publish {
+        let mut template = Publish {
+            dup: false,
+            qos: publish.qos,
+            retain: publish.retain,
+            topic: String::new(),
+            packet_id: None,
+            payload: Default::default(),
+            properties: publish.properties.clone(),
+        };
+        template.properties.topic_alias = None;
+        template
+    }
+}
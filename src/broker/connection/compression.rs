@@ -0,0 +1,72 @@
+//! Compressed publishes from bridges (`mqtt.bridge_compression`)
+//!
+//! A VibeMQ bridge offers its codecs in CONNECT; the first one the broker
+//! accepts is named in CONNACK. Payloads the bridge then tags with that
+//! codec are unpacked as soon as the topic is known, so rules, retained
+//! storage and subscribers all see the original payload.
+
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::{Connection, Diagnostic};
+use crate::bridge::{BRIDGE_COMPRESSION_PROPERTY, BRIDGE_ENCODING_PROPERTY};
+use crate::config::CompressionCodec;
+use crate::protocol::{Properties, Publish, ReasonCode};
+use crate::remote::compression::{decompress, negotiate};
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Take a codec from the compression a bridge offered in CONNECT
+    ///
+    /// Returns the codec to name in CONNACK, if any was accepted.
+    pub(crate) fn accept_compression(
+        &mut self,
+        properties: &Properties,
+    ) -> Option<CompressionCodec> {
+        let offer = properties
+            .user_properties
+            .iter()
+            .find(|(name, _)| name == BRIDGE_COMPRESSION_PROPERTY)?;
+        self.bridge_codec = negotiate(offer.1.split(','), &self.config.bridge_compression);
+        self.bridge_codec
+    }
+
+    /// Unpack a payload compressed with the negotiated codec
+    ///
+    /// Returns the reason code and diagnostic to reject the message with
+    /// if it can't be unpacked.
+    pub(crate) fn take_compression(
+        &self,
+        publish: &mut Publish,
+    ) -> Result<(), (ReasonCode, Diagnostic)> {
+        let Some(codec) = self.bridge_codec else {
+            return Ok(());
+        };
+        let properties = &mut publish.properties.user_properties;
+        let Some(index) = properties
+            .iter()
+            .position(|(name, _)| name == BRIDGE_ENCODING_PROPERTY)
+        else {
+            return Ok(());
+        };
+        let (_, encoding) = properties.remove(index);
+        if CompressionCodec::from_name(&encoding) != Some(codec) {
+            return Err((
+                ReasonCode::PayloadFormatInvalid,
+                Diagnostic::default().with_detail("payload compressed with another codec"),
+            ));
+        }
+        match decompress(codec, &publish.payload, self.config.max_packet_size) {
+            Ok(payload) => {
+                publish.payload = Bytes::from(payload);
+                Ok(())
+            }
+            Err(_) => Err((
+                ReasonCode::PayloadFormatInvalid,
+                Diagnostic::default().with_detail("payload could not be decompressed"),
+            )),
+        }
+    }
+}
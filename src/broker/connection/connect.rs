@@ -7,15 +7,22 @@ use parking_lot::RwLock;
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tracing::{debug, error, trace};
 
-use super::{BytesMutExt, Connection, ConnectionError, State};
-use crate::broker::BrokerEvent;
+use super::{BytesMutExt, Connection, ConnectionError, Diagnostic, State};
+use crate::auth::constant_time_eq;
+use crate::bridge::BRIDGE_COMPRESSION_PROPERTY;
+use crate::broker::{BrokerEvent, TraceDirection};
+use crate::config::SessionPolicy;
+use crate::hooks::{AccessAction, ConnectionMetadata, HookError};
 use crate::protocol::{
     ConnAck, Disconnect, Packet, Properties, ProtocolVersion, PubRel, QoS, ReasonCode,
 };
 use crate::session::{
-    InflightMessage, Qos2State, QueueResult, Session, SessionLimits, WillMessage,
+    tenant_client_id, Qos2State, Session, SessionLimits, WillMessage, HANDOVER_TOKEN_PROPERTY,
 };
 
+/// Longest client ID MQTT 3.1 allows
+const MQTT31_MAX_CLIENT_ID_LEN: usize = 23;
+
 impl<S> Connection<S>
 where
     S: AsyncRead + AsyncWrite + Unpin,
@@ -30,7 +37,7 @@ where
 
                     match packet {
                         Packet::Connect(connect) => {
-                            return self.handle_connect(*connect).await;
+                            return self.handle_connect(*connect, consumed).await;
                         }
                         _ => {
                             // Protocol violation - first packet must be CONNECT
@@ -56,12 +63,42 @@ where
                             crate::protocol::DecodeError::MalformedPacket(_) => {
                                 ReasonCode::MalformedPacket
                             }
+                            crate::protocol::DecodeError::PacketTooLarge => {
+                                ReasonCode::PacketTooLarge
+                            }
                             _ => ReasonCode::MalformedPacket,
                         };
+                        let diagnostic = match e {
+                            crate::protocol::DecodeError::PacketTooLarge => {
+                                Diagnostic::limit("max_packet_size", self.config.max_packet_size)
+                            }
+                            _ => Diagnostic::default(),
+                        };
                         self.encoder.set_protocol_version(ProtocolVersion::V5);
+                        let (reason_code, properties) =
+                            self.client_error(reason_code, diagnostic, || e.to_string());
                         let connack = ConnAck {
                             session_present: false,
                             reason_code,
+                            properties,
+                        };
+                        let mut buf = bytes::BytesMut::new();
+                        if self
+                            .encoder
+                            .encode(&Packet::ConnAck(connack), &mut buf)
+                            .is_ok()
+                        {
+                            let _ = self.stream.write_all(&buf).await;
+                            let _ = self.stream.flush().await;
+                        }
+                    } else if let crate::protocol::DecodeError::InvalidProtocolVersion(version) = &e
+                    {
+                        // [MQTT-3.1.2-2] Unacceptable protocol level gets CONNACK 0x01
+                        debug!("Unsupported protocol level {} from {}", version, self.addr);
+                        self.encoder.set_protocol_version(ProtocolVersion::V311);
+                        let connack = ConnAck {
+                            session_present: false,
+                            reason_code: ReasonCode::UnsupportedProtocolVersion,
                             properties: Properties::default(),
                         };
                         let mut buf = bytes::BytesMut::new();
@@ -94,54 +131,115 @@ where
     async fn handle_connect(
         &mut self,
         connect: crate::protocol::Connect,
+        size: usize,
     ) -> Result<(), ConnectionError> {
         let protocol_version = connect.protocol_version;
         self.decoder.set_protocol_version(protocol_version);
         self.encoder.set_protocol_version(protocol_version);
+        if let Some(max) = connect.properties.maximum_packet_size {
+            self.client_max_packet_size = max;
+        }
+
+        // MQTT 3.1 clients run on the v3.1.1 session logic
+        let mqtt31 = self.decoder.is_mqtt31();
+        if mqtt31 {
+            debug!("MQTT 3.1 (MQIsdp) client from {}", self.addr);
+        }
 
         // Per MQTT-3.1.3-8: If client supplies zero-byte ClientId with CleanSession=0,
-        // the server MUST respond with CONNACK return code 0x02 (Identifier rejected)
-        if connect.client_id.is_empty() && !connect.clean_start {
+        // the server MUST respond with CONNACK return code 0x02 (Identifier rejected).
+        // MQTT 3.1 requires a client ID regardless of CleanSession.
+        if connect.client_id.is_empty() && (!connect.clean_start || mqtt31) {
             debug!(
                 "Rejecting empty client ID with clean_start=false from {}",
                 self.addr
             );
+            let (reason_code, properties) =
+                self.client_error(ReasonCode::ClientIdNotValid, Diagnostic::default(), || {
+                    "empty client ID requires clean start".to_string()
+                });
+            let connack = ConnAck {
+                session_present: false,
+                reason_code,
+                properties,
+            };
+            self.write_packet(&Packet::ConnAck(connack)).await?;
+            return Err(ConnectionError::Protocol(
+                crate::protocol::ProtocolError::ProtocolViolation(
+                    "empty client ID with clean_start=false",
+                ),
+            ));
+        }
+
+        // MQTT 3.1 client IDs are 1 to 23 characters (CONNACK 0x02 otherwise)
+        if mqtt31 && connect.client_id.chars().count() > MQTT31_MAX_CLIENT_ID_LEN {
+            debug!(
+                "Rejecting MQTT 3.1 client ID longer than {} characters from {}",
+                MQTT31_MAX_CLIENT_ID_LEN, self.addr
+            );
             let connack = ConnAck {
                 session_present: false,
                 reason_code: ReasonCode::ClientIdNotValid,
                 properties: Properties::default(),
             };
-            self.write_buf.clear();
-            self.encoder
-                .encode(&Packet::ConnAck(connack), &mut self.write_buf)
-                .map_err(|e| ConnectionError::Protocol(e.into()))?;
-            self.stream.write_all(&self.write_buf).await?;
+            self.write_packet(&Packet::ConnAck(connack)).await?;
             return Err(ConnectionError::Protocol(
                 crate::protocol::ProtocolError::ProtocolViolation(
-                    "empty client ID with clean_start=false",
+                    "MQTT 3.1 client ID longer than 23 characters",
                 ),
             ));
         }
 
         // Validate client ID
-        let client_id: Arc<str> = if connect.client_id.is_empty() {
+        let raw_client_id: Arc<str> = if connect.client_id.is_empty() {
             // Generate client ID (only allowed when clean_start=true)
             format!("roker-{:x}", super::rand_id()).into()
         } else {
             connect.client_id.clone().into()
         };
+        let mut client_id = raw_client_id.clone();
+
+        match self.proxy_unique_id {
+            Some(ref id) => debug!(
+                "CONNECT from {} (client_id: {}, proxy id: {})",
+                self.addr, client_id, id
+            ),
+            None => debug!("CONNECT from {} (client_id: {})", self.addr, client_id),
+        }
 
-        debug!("CONNECT from {} (client_id: {})", self.addr, client_id);
-
-        // Authenticate the client
-        let auth_result = self
+        // Scope the client ID to its tenant, then authenticate the client
+        let auth_result = match self
             .hooks
-            .on_authenticate(
-                &client_id,
-                connect.username.as_deref(),
-                connect.password.as_deref(),
-            )
-            .await;
+            .on_client_tenant(&client_id, connect.username.as_deref())
+            .await
+        {
+            Ok(tenant) => {
+                if let Some(ref tenant) = tenant {
+                    client_id = tenant_client_id(tenant, &client_id).into();
+                    debug!("Client {} belongs to tenant {}", client_id, tenant);
+                }
+                let metadata = self.connection_metadata(tenant);
+                self.hooks
+                    .on_authenticate_with_metadata(
+                        &client_id,
+                        connect.username.as_deref(),
+                        connect.password.as_deref(),
+                        &metadata,
+                    )
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+
+        // Trace from the CONNECT on, under the client ID it ends up with
+        self.trace_client_id = Some(client_id.clone());
+        if self.tracer.as_ref().is_some_and(|t| t.is_active()) {
+            self.trace(
+                TraceDirection::In,
+                &Packet::Connect(Box::new(connect.clone())),
+                size,
+            );
+        }
 
         match auth_result {
             Ok(true) => {
@@ -151,32 +249,64 @@ where
             }
             Ok(false) => {
                 debug!("Authentication failed for {}", client_id);
+                let (reason_code, properties) = self.client_error(
+                    ReasonCode::NotAuthorized,
+                    Diagnostic::denied_by("authentication"),
+                    || "authentication failed".to_string(),
+                );
                 let connack = ConnAck {
                     session_present: false,
-                    reason_code: ReasonCode::NotAuthorized,
-                    properties: Properties::default(),
+                    reason_code,
+                    properties,
                 };
-                self.write_buf.clear();
-                self.encoder
-                    .encode(&Packet::ConnAck(connack), &mut self.write_buf)
-                    .map_err(|e| ConnectionError::Protocol(e.into()))?;
-                self.stream.write_all(&self.write_buf).await?;
+                self.write_packet(&Packet::ConnAck(connack)).await?;
                 return Err(ConnectionError::Protocol(
                     crate::protocol::ProtocolError::ProtocolViolation("authentication failed"),
                 ));
             }
             Err(e) => {
-                error!("Authentication error for {}: {}", client_id, e);
+                // Denials carry their own reason code; anything else is a fault
+                let (code, diagnostic, reason) = match e {
+                    HookError::AuthenticationFailed => (
+                        ReasonCode::BadUserNameOrPassword,
+                        Diagnostic::denied_by("authentication"),
+                        "bad username or password",
+                    ),
+                    HookError::AuthorizationDenied => (
+                        ReasonCode::NotAuthorized,
+                        Diagnostic::denied_by("authentication"),
+                        "authentication failed",
+                    ),
+                    HookError::Banned => (
+                        ReasonCode::Banned,
+                        Diagnostic::denied_by("authentication"),
+                        "client is banned",
+                    ),
+                    HookError::Unavailable(_) => (
+                        ReasonCode::ServerUnavailable,
+                        Diagnostic::default(),
+                        "authentication backend unavailable",
+                    ),
+                    _ => (
+                        ReasonCode::UnspecifiedError,
+                        Diagnostic::default(),
+                        "authentication error",
+                    ),
+                };
+                match e {
+                    HookError::Unavailable(_) | HookError::Internal(_) => {
+                        error!("Authentication error for {}: {}", client_id, e)
+                    }
+                    _ => debug!("Authentication failed for {}: {}", client_id, e),
+                }
+                let (reason_code, properties) =
+                    self.client_error(code, diagnostic, || reason.to_string());
                 let connack = ConnAck {
                     session_present: false,
-                    reason_code: ReasonCode::UnspecifiedError,
-                    properties: Properties::default(),
+                    reason_code,
+                    properties,
                 };
-                self.write_buf.clear();
-                self.encoder
-                    .encode(&Packet::ConnAck(connack), &mut self.write_buf)
-                    .map_err(|e| ConnectionError::Protocol(e.into()))?;
-                self.stream.write_all(&self.write_buf).await?;
+                self.write_packet(&Packet::ConnAck(connack)).await?;
                 return Err(ConnectionError::Protocol(
                     crate::protocol::ProtocolError::ProtocolViolation("authentication error"),
                 ));
@@ -191,21 +321,165 @@ where
                 "Max connections ({}) reached, rejecting {}",
                 self.config.max_connections, client_id
             );
+            let (reason_code, properties) = self.client_error(
+                ReasonCode::ServerUnavailable,
+                Diagnostic::limit("max_connections", self.config.max_connections),
+                || "connection limit reached".to_string(),
+            );
             let connack = ConnAck {
                 session_present: false,
-                reason_code: ReasonCode::ServerUnavailable,
-                properties: Properties::default(),
+                reason_code,
+                properties,
             };
-            self.write_buf.clear();
-            self.encoder
-                .encode(&Packet::ConnAck(connack), &mut self.write_buf)
-                .map_err(|e| ConnectionError::Protocol(e.into()))?;
-            self.stream.write_all(&self.write_buf).await?;
+            self.write_packet(&Packet::ConnAck(connack)).await?;
             return Err(ConnectionError::Protocol(
                 crate::protocol::ProtocolError::ProtocolViolation("max connections reached"),
             ));
         }
 
+        // Connection limit of the client's listener
+        if !self.take_listener_slot(&client_id, is_takeover) {
+            let max = self
+                .listener_limits
+                .map_or(0, |limits| limits.max_connections);
+            debug!(
+                "Listener {} is full ({} connections), rejecting {}",
+                self.listener, max, client_id
+            );
+            let (reason_code, properties) = self.client_error(
+                ReasonCode::ServerUnavailable,
+                Diagnostic::limit("listener max_connections", max),
+                || "listener connection limit reached".to_string(),
+            );
+            let connack = ConnAck {
+                session_present: false,
+                reason_code,
+                properties,
+            };
+            self.write_packet(&Packet::ConnAck(connack)).await?;
+            return Err(ConnectionError::Protocol(
+                crate::protocol::ProtocolError::ProtocolViolation("listener full"),
+            ));
+        }
+
+        // Session settings of the client's role
+        let policy = match self
+            .hooks
+            .on_session_policy(&client_id, self.username.as_deref())
+            .await
+        {
+            Ok(policy) => policy,
+            Err(e) => {
+                tracing::warn!("Session policy lookup error for {}: {}", client_id, e);
+                SessionPolicy::default()
+            }
+        };
+
+        // Quota for the client's listener, role and username
+        self.resolve_quota(&client_id, &policy.quota);
+        let quota_inflight = self.quota.as_ref().and_then(|q| q.max_inflight());
+
+        // QoS cap of the client's role
+        self.role_max_qos = match self
+            .hooks
+            .on_max_qos(&client_id, self.username.as_deref())
+            .await
+        {
+            Ok(max_qos) => max_qos,
+            Err(e) => {
+                tracing::warn!("QoS cap lookup error for {}: {}", client_id, e);
+                None
+            }
+        };
+
+        // A will the client may not publish rejects the CONNECT
+        if let Some(ref will) = connect.will {
+            let allowed = match self
+                .hooks
+                .on_publish_check_with_identity(
+                    &client_id,
+                    self.username.as_deref(),
+                    &will.topic,
+                    will.qos,
+                    will.retain,
+                    self.proxy_identity.as_deref(),
+                )
+                .await
+            {
+                Ok(allowed) => allowed,
+                Err(e) => {
+                    error!("ACL check error for {}: {}", client_id, e);
+                    false
+                }
+            };
+            if !allowed {
+                debug!(
+                    "Will denied for {} to topic {} (ACL)",
+                    client_id, will.topic
+                );
+                let diagnostic = self
+                    .access_diagnostic(
+                        &client_id,
+                        AccessAction::Publish,
+                        &will.topic,
+                        will.qos,
+                        will.retain,
+                    )
+                    .await;
+                let (reason_code, properties) =
+                    self.client_error(ReasonCode::NotAuthorized, diagnostic, || {
+                        "will topic not authorized".to_string()
+                    });
+                let connack = ConnAck {
+                    session_present: false,
+                    reason_code,
+                    properties,
+                };
+                self.write_packet(&Packet::ConnAck(connack)).await?;
+                return Err(ConnectionError::Protocol(
+                    crate::protocol::ProtocolError::ProtocolViolation("will not authorized"),
+                ));
+            }
+        }
+
+        // [MQTT-3.1.2-29] Request Problem Information = 0 limits reason
+        // strings to CONNACK, DISCONNECT and PUBLISH
+        self.problem_information = connect.properties.request_problem_information != Some(0);
+
+        // A session being handed over goes only to the successor with the
+        // token
+        if let Some(session) = self.sessions.get(&client_id) {
+            let token = session.read().pending_handover().map(|h| h.token.clone());
+            if let Some(token) = token {
+                let presented = connect
+                    .properties
+                    .user_properties
+                    .iter()
+                    .find(|(name, _)| name == HANDOVER_TOKEN_PROPERTY)
+                    .is_some_and(|(_, value)| constant_time_eq(value.as_bytes(), token.as_bytes()));
+                if !presented {
+                    debug!("Rejecting {}: session is being handed over", client_id);
+                    let (reason_code, properties) = self.client_error(
+                        ReasonCode::NotAuthorized,
+                        Diagnostic::denied_by("handover"),
+                        || "session is being handed over".to_string(),
+                    );
+                    let connack = ConnAck {
+                        session_present: false,
+                        reason_code,
+                        properties,
+                    };
+                    self.write_packet(&Packet::ConnAck(connack)).await?;
+                    return Err(ConnectionError::Protocol(
+                        crate::protocol::ProtocolError::ProtocolViolation(
+                            "session handover pending",
+                        ),
+                    ));
+                }
+                debug!("Session of {} handed over", client_id);
+            }
+        }
+
         // Check for existing connection and disconnect it
         if let Some(existing) = self.connections.get(&client_id) {
             // Send disconnect to existing connection
@@ -218,8 +492,17 @@ where
 
         // Get or create session
         let session_limits = SessionLimits {
-            max_pending_messages: self.config.max_queued_messages,
-            max_inflight: self.config.max_inflight,
+            max_pending_messages: match policy.max_queued_messages {
+                Some(0) => usize::MAX,
+                Some(max) => max,
+                None => self.config.max_queued_messages,
+            },
+            max_pending_bytes: self.config.max_queued_bytes,
+            queue_qos0: self.config.queue_qos0,
+            queue_overflow: self.config.queue_overflow,
+            max_inflight: quota_inflight.map_or(self.config.max_inflight, |max| {
+                max.min(self.config.max_inflight)
+            }),
             max_awaiting_rel: self.config.max_awaiting_rel,
         };
         let (session, session_present) = self.sessions.get_or_create(
@@ -234,15 +517,32 @@ where
             self.subscriptions.unsubscribe_all(&client_id);
         }
 
+        // Session expiry granted below the requested one (returned in CONNACK)
+        let mut granted_expiry = None;
+        // Keep-alive other than the requested one (Server Keep Alive)
+        let mut granted_keep_alive = None;
+        let max_session_expiry = policy.max_session_expiry.or(self.config.max_session_expiry);
+        let max_keep_alive = policy.max_keep_alive.unwrap_or(self.config.max_keep_alive);
+
         // Update session with connection parameters
         {
             let mut s = session.write();
             s.clean_start = connect.clean_start;
+            s.handover = None;
+            s.ack_stalled = false;
+            s.reset_topic_aliases();
+            s.username = self.username.as_deref().map(Into::into);
             s.keep_alive = if connect.keep_alive == 0 {
                 self.config.default_keep_alive
             } else {
-                connect.keep_alive.min(self.config.max_keep_alive)
+                connect.keep_alive.min(max_keep_alive)
             };
+            if let Some(min) = policy.min_keep_alive {
+                s.keep_alive = s.keep_alive.max(min);
+            }
+            if s.keep_alive != connect.keep_alive {
+                granted_keep_alive = Some(s.keep_alive);
+            }
 
             // Handle session expiry based on protocol version
             if protocol_version == ProtocolVersion::V5 {
@@ -272,18 +572,32 @@ where
                 }
             }
 
+            // Cap the interval at session.max_expiry or the role's cap
+            // (Never expires included)
+            if let Some(max) = max_session_expiry {
+                let max = max.as_secs().min(u32::MAX as u64 - 1) as u32;
+                if s.session_expiry_interval > max {
+                    s.session_expiry_interval = max;
+                    granted_expiry = Some(max);
+                }
+            }
+
             // Store will message
             if let Some(will) = connect.will {
+                let mut properties = will.properties.clone();
+                self.stamp_forwarded_for(&mut properties);
                 s.will = Some(WillMessage {
                     topic: will.topic,
                     payload: will.payload,
                     qos: will.qos,
                     retain: will.retain,
-                    properties: will.properties.clone(),
+                    properties,
+                    proxy_identity: self.proxy_identity.clone(),
                 });
-                if let Some(delay) = will.properties.will_delay_interval {
-                    s.will_delay_interval = delay;
-                }
+                s.will_delay_interval = will.properties.will_delay_interval.unwrap_or(0);
+            } else {
+                s.will = None;
+                s.will_delay_interval = 0;
             }
 
             s.touch();
@@ -292,21 +606,34 @@ where
         // Register connection
         self.connections
             .insert(client_id.clone(), self.packet_tx.clone());
+        if let (Some(priority), Some(tx)) = (&self.priority, &self.priority_tx) {
+            priority.register(client_id.clone(), tx.clone());
+        }
 
         // Send CONNACK
+        // MQTT 3.1 has no session present flag (the byte is reserved)
         let mut connack = ConnAck {
-            session_present: session_present && !connect.clean_start,
+            session_present: session_present && !connect.clean_start && !mqtt31,
             reason_code: ReasonCode::Success,
             properties: Properties::default(),
         };
 
         // Set v5.0 properties
         if protocol_version == ProtocolVersion::V5 {
-            connack.properties.receive_maximum = Some(self.config.receive_maximum);
+            // QoS 2 publishes beyond max_awaiting_rel are refused, so don't
+            // invite more than that
+            let awaiting_rel = u16::try_from(self.config.max_awaiting_rel).unwrap_or(u16::MAX);
+            let receive_maximum = self.config.receive_maximum.min(awaiting_rel);
+            connack.properties.receive_maximum = Some(
+                quota_inflight
+                    .map_or(receive_maximum, |max| max.min(receive_maximum))
+                    .max(1),
+            );
             // Per MQTT 5.0 spec 3.2.2.3.4: Maximum QoS can only be 0 or 1.
             // If server supports QoS 2, don't include this property (default is 2).
-            if self.config.max_qos != QoS::ExactlyOnce {
-                connack.properties.maximum_qos = Some(self.config.max_qos as u8);
+            let max_qos = self.max_qos();
+            if max_qos != QoS::ExactlyOnce {
+                connack.properties.maximum_qos = Some(max_qos as u8);
             }
             connack.properties.retain_available =
                 Some(if self.config.retain_available { 1 } else { 0 });
@@ -331,22 +658,57 @@ where
                     0
                 });
 
-            // Assign client ID if we generated one
+            // Publish rate limit for this identity (unknown when decided externally)
+            let publish_rate = &self.config.publish_rate;
+            if publish_rate.advertise && publish_rate.enabled() {
+                let identity = self.publish_rate_identity(&client_id);
+                let publish_rate = &self.config.publish_rate;
+                if !publish_rate.is_external(&identity) {
+                    // As changed at runtime, if it was
+                    let (messages_per_sec, burst) = self
+                        .tunables
+                        .as_ref()
+                        .and_then(|t| t.publish_rate())
+                        .unwrap_or((publish_rate.messages_per_sec, publish_rate.burst));
+                    connack.properties.user_properties.extend([
+                        (
+                            "rate-limit-messages-per-sec".to_string(),
+                            messages_per_sec.to_string(),
+                        ),
+                        ("rate-limit-burst".to_string(), burst.to_string()),
+                    ]);
+                }
+            }
+
+            // Compression offered by a bridge
+            if let Some(codec) = self.accept_compression(&connect.properties) {
+                connack.properties.user_properties.push((
+                    BRIDGE_COMPRESSION_PROPERTY.to_string(),
+                    codec.as_str().to_string(),
+                ));
+            }
+
+            connack.properties.session_expiry_interval = granted_expiry;
+            connack.properties.server_keep_alive = granted_keep_alive;
+
+            // Assign client ID if we generated one (without the tenant prefix)
             if connect.client_id.is_empty() {
-                connack.properties.assigned_client_identifier = Some(client_id.to_string());
+                connack.properties.assigned_client_identifier = Some(raw_client_id.to_string());
             }
         }
 
+        let packet = Packet::ConnAck(connack);
         self.write_buf.clear();
         debug!("Encoding CONNACK for {}", client_id);
         self.encoder
-            .encode(&Packet::ConnAck(connack), &mut self.write_buf)
+            .encode(&packet, &mut self.write_buf)
             .map_err(|e| ConnectionError::Protocol(e.into()))?;
         debug!(
             "CONNACK encoded, {} bytes: {:02x?}",
             self.write_buf.len(),
             &self.write_buf[..]
         );
+        self.trace(TraceDirection::Out, &packet, self.write_buf.len());
         self.stream.write_all(&self.write_buf).await?;
         debug!("CONNACK sent to {}", client_id);
 
@@ -362,8 +724,10 @@ where
             protocol_version,
         });
 
-        // Send pending messages
-        self.send_pending_messages(&session).await?;
+        // Send pending messages (large backlogs drain in turns from the
+        // packet loop)
+        self.send_pending_messages(&session, policy.drain_weight.unwrap_or(1))
+            .await?;
 
         // Re-send unacknowledged inflight messages on session resume [MQTT-4.4.0-1]
         if session_present {
@@ -377,82 +741,6 @@ where
         Ok(())
     }
 
-    /// Send pending messages from session queue
-    async fn send_pending_messages(
-        &mut self,
-        session: &Arc<RwLock<Session>>,
-    ) -> Result<(), ConnectionError> {
-        let (pending, max_packet_size) = {
-            let mut s = session.write();
-            (s.drain_pending_messages(), s.max_packet_size)
-        };
-
-        for mut publish in pending {
-            if publish.qos != QoS::AtMostOnce {
-                let mut s = session.write();
-                // Check send quota (MQTT v5.0 flow control)
-                if !s.decrement_send_quota() {
-                    // Quota exhausted - re-queue remaining messages
-                    if s.queue_message(publish) == QueueResult::DroppedOldest {
-                        let _ = self.events.send(BrokerEvent::MessageDropped);
-                    }
-                    continue;
-                }
-                // Check max_inflight limit
-                if s.inflight_outgoing.len() >= s.max_inflight as usize {
-                    // Inflight limit reached - re-queue and restore quota
-                    s.increment_send_quota();
-                    if s.queue_message(publish) == QueueResult::DroppedOldest {
-                        let _ = self.events.send(BrokerEvent::MessageDropped);
-                    }
-                    continue;
-                }
-                publish.packet_id = Some(s.next_packet_id());
-                // Store inflight for retry
-                if let Some(packet_id) = publish.packet_id {
-                    s.inflight_outgoing.insert(
-                        packet_id,
-                        InflightMessage {
-                            packet_id,
-                            publish: publish.clone(),
-                            qos2_state: if publish.qos == QoS::ExactlyOnce {
-                                Some(Qos2State::WaitingPubRec)
-                            } else {
-                                None
-                            },
-                            sent_at: Instant::now(),
-                            retry_count: 0,
-                        },
-                    );
-                }
-            }
-
-            self.write_buf.clear();
-            self.encoder
-                .encode(&Packet::Publish(publish), &mut self.write_buf)
-                .map_err(|e| ConnectionError::Protocol(e.into()))?;
-
-            // Per MQTT v5.0 spec [MQTT-3.1.2-24]: MUST NOT send packets
-            // exceeding client's Maximum Packet Size
-            if self.write_buf.len() > max_packet_size as usize {
-                tracing::warn!(
-                    "Dropping pending PUBLISH: encoded size {} exceeds client max {}",
-                    self.write_buf.len(),
-                    max_packet_size
-                );
-                continue;
-            }
-
-            let bytes_sent = self.write_buf.len();
-            self.stream.write_all(&self.write_buf).await?;
-            if let Some(ref metrics) = self.metrics {
-                metrics.publish_sent(bytes_sent);
-            }
-        }
-
-        Ok(())
-    }
-
     /// Re-send unacknowledged inflight messages on session resume
     ///
     /// Per [MQTT-4.4.0-1]: When a Client reconnects with CleanSession set to 0,
@@ -471,6 +759,7 @@ where
                 .map(|(packet_id, inflight)| {
                     // Update sent_at for retry tracking
                     inflight.sent_at = now;
+                    inflight.first_sent_at = now;
                     inflight.retry_count += 1;
                     (*packet_id, inflight.publish.clone(), inflight.qos2_state)
                 })
@@ -485,28 +774,29 @@ where
                     publish.dup = true;
                     publish.packet_id = Some(packet_id);
 
-                    self.write_buf.clear();
-                    self.encoder
-                        .encode(&Packet::Publish(publish), &mut self.write_buf)
-                        .map_err(|e| ConnectionError::Protocol(e.into()))?;
-
-                    if self.write_buf.len() <= max_packet_size as usize {
+                    let len = self.encode_publish_header(&publish)?;
+                    if len <= max_packet_size as usize {
                         trace!(
                             "Resending inflight PUBLISH packet_id={} with DUP=1",
                             packet_id
                         );
-                        self.stream.write_all(&self.write_buf).await?;
+                        let payload = publish.payload.clone();
+                        let packet = Packet::Publish(publish);
+                        self.trace(TraceDirection::Out, &packet, len);
+                        self.write_publish(&payload).await?;
                     }
                 }
                 Some(Qos2State::WaitingPubComp) => {
                     // QoS 2 waiting for PUBCOMP: resend PUBREL
                     let pubrel = PubRel::new(packet_id);
+                    let packet = Packet::PubRel(pubrel);
                     self.write_buf.clear();
                     self.encoder
-                        .encode(&Packet::PubRel(pubrel), &mut self.write_buf)
+                        .encode(&packet, &mut self.write_buf)
                         .map_err(|e| ConnectionError::Protocol(e.into()))?;
 
                     trace!("Resending inflight PUBREL packet_id={}", packet_id);
+                    self.trace(TraceDirection::Out, &packet, self.write_buf.len());
                     self.stream.write_all(&self.write_buf).await?;
                 }
             }
@@ -530,7 +820,7 @@ where
             self.send_retained_messages(
                 client_id,
                 &sub.filter,
-                sub.options.qos,
+                sub.options.qos.min(self.max_qos()),
                 session,
                 sub.subscription_id,
             )
@@ -539,4 +829,22 @@ where
 
         Ok(())
     }
+
+    /// Connection details for authentication hooks, limited to the
+    /// configured fields
+    fn connection_metadata(&self, tenant: Option<String>) -> ConnectionMetadata {
+        let tls = self.tls_info.as_ref();
+        ConnectionMetadata {
+            client_ip: self.addr.ip(),
+            sni: tls.and_then(|t| t.sni.clone()),
+            tls_version: tls.and_then(|t| t.version.clone()),
+            tls_cipher: tls.and_then(|t| t.cipher.clone()),
+            client_cert_cn: tls
+                .filter(|t| t.client_cert_verified)
+                .and_then(|t| t.client_cert_cn.clone()),
+            listener: Some(self.listener.to_string()),
+            tenant,
+        }
+        .retain(&self.config.auth_metadata_fields)
+    }
 }
@@ -0,0 +1,108 @@
+//! Delayed publishes (`delayed`)
+//!
+//! The delay is taken off a message for "{delayed.topic}/{seconds}/{topic}"
+//! (or one carrying `delayed.user_property`) before its topic is validated,
+//! so every check after that applies to the target topic. Instead of being
+//! routed, the message is then handed to the broker's
+//! [`crate::broker::DelayedQueue`] and acknowledged right away (QoS 2: the
+//! PUBREL has nothing to release). A delayed message published inside a
+//! transaction is not held with it.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::debug;
+
+use super::{Connection, ConnectionError, Diagnostic};
+use crate::broker::delayed::{self, DelayedMessage};
+use crate::protocol::{Publish, ReasonCode};
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Take the delay off a delayed publish, leaving it for its target topic
+    ///
+    /// Returns the delay (None to route the message now), or the reason
+    /// code and diagnostic to reject it with.
+    pub(crate) fn take_delay(
+        &self,
+        publish: &mut Publish,
+    ) -> Result<Option<Duration>, (ReasonCode, Diagnostic)> {
+        let config = &self.config.delayed;
+        if !config.enabled || self.delayed.is_none() {
+            return Ok(None);
+        }
+        let delay = match config.split(&publish.topic) {
+            Some(Some((delay, target))) => {
+                publish.topic = target.to_string();
+                delay
+            }
+            Some(None) => {
+                return Err((
+                    ReasonCode::TopicNameInvalid,
+                    Diagnostic::default().with_detail("expected a delay in seconds and a topic"),
+                ))
+            }
+            None => {
+                let Some(ref name) = config.user_property else {
+                    return Ok(None);
+                };
+                let properties = &mut publish.properties.user_properties;
+                let Some(index) = properties.iter().position(|(key, _)| key == name) else {
+                    return Ok(None);
+                };
+                let (_, seconds) = properties.remove(index);
+                match seconds.trim().parse() {
+                    Ok(seconds) => Duration::from_secs(seconds),
+                    Err(_) => {
+                        return Err((
+                            ReasonCode::ImplementationError,
+                            Diagnostic::default().with_detail("delay is not a number of seconds"),
+                        ))
+                    }
+                }
+            }
+        };
+        if delay > config.max_delay {
+            return Err((
+                ReasonCode::QuotaExceeded,
+                Diagnostic::limit("delayed.max_delay", config.max_delay.as_secs() as usize),
+            ));
+        }
+        Ok((!delay.is_zero()).then_some(delay))
+    }
+
+    /// Hold a message until its delay is up and acknowledge it
+    pub(crate) async fn hold_delayed(
+        &mut self,
+        client_id: &Arc<str>,
+        publish: &Publish,
+        delay: Duration,
+    ) -> Result<(), ConnectionError> {
+        let Some(ref queue) = self.delayed else {
+            return Ok(());
+        };
+        let max_messages = self.config.delayed.max_messages;
+        let message = DelayedMessage::new(client_id.clone(), publish.clone(), delay);
+        if !delayed::schedule(queue, self.persistence.as_deref(), message, max_messages) {
+            debug!(
+                "Delayed message from {} rejected: {} messages held",
+                client_id, max_messages
+            );
+            if let Some(ref metrics) = self.metrics {
+                metrics.message_dropped("delayed_full");
+            }
+            let diagnostic = Diagnostic::limit("delayed.max_messages", max_messages);
+            return self
+                .send_publish_error(publish, ReasonCode::QuotaExceeded, diagnostic)
+                .await;
+        }
+        debug!(
+            "Holding message from {} for {} for {:?}",
+            client_id, publish.topic, delay
+        );
+        self.acknowledge_settled(publish).await
+    }
+}
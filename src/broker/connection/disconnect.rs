@@ -12,10 +12,12 @@ use tokio::sync::{broadcast, mpsc};
 use tracing::debug;
 
 use super::{Connection, ConnectionError};
+use crate::broker::retained_feed::{self, RetainedChange};
+use crate::broker::routing_id;
 use crate::broker::{BrokerEvent, RetainedMessage};
-use crate::persistence::{PersistenceOp, StoredRetainedMessage, StoredSession};
+use crate::persistence::{PersistenceOp, StoredRetainedMessage};
 use crate::protocol::{Packet, Publish, QoS};
-use crate::session::{QueueResult, Session, SessionStore};
+use crate::session::{Session, SessionStore};
 use crate::topic::SubscriptionStore;
 
 impl<S> Connection<S>
@@ -31,11 +33,28 @@ where
     ) {
         // Remove from connections
         self.connections.remove(client_id);
+        if let (Some(priority), Some(tx)) = (&self.priority, &self.priority_tx) {
+            priority.unregister(client_id, tx);
+        }
+
+        if let Some(messages) = self.transaction.take() {
+            debug!(
+                "Transaction of {} discarded on disconnect ({} messages)",
+                client_id,
+                messages.len()
+            );
+        }
 
         // Remove subscriptions if clean start
+        // The will goes out when its delay passes or the session ends,
+        // whichever comes first [MQTT-3.1.3-9]
         let (clean_start, will, will_delay_interval) = {
             let s = session.read();
-            (s.clean_start, s.will.clone(), s.will_delay_interval)
+            (
+                s.clean_start,
+                s.will.clone(),
+                s.will_delay_interval.min(s.session_expiry_interval),
+            )
         };
 
         if clean_start {
@@ -45,8 +64,19 @@ where
         // Mark session as disconnected
         self.sessions.disconnect(client_id);
 
+        // A maintenance window may drop wills, as on a normal disconnect
+        let suppress_will = publish_will
+            && will.is_some()
+            && self
+                .maintenance
+                .as_ref()
+                .is_some_and(|m| m.suppress_wills());
+        if suppress_will {
+            debug!("Will of {} dropped during maintenance", client_id);
+        }
+
         // Publish will message if needed
-        if publish_will {
+        if publish_will && !suppress_will {
             if let Some(will) = will {
                 if will_delay_interval > 0 {
                     // Spawn delayed will publish task
@@ -59,6 +89,8 @@ where
                     let config = self.config.clone();
                     let events = self.events.clone();
                     let persistence = self.persistence.clone();
+                    let sequencer = self.sequencer.clone();
+                    let maintenance = self.maintenance.clone();
                     let delay = Duration::from_secs(will_delay_interval as u64);
 
                     // Capture the disconnect timestamp to detect reconnect+disconnect cycles
@@ -72,6 +104,7 @@ where
 
                         // Check if:
                         // 1. This session is still the active session (not replaced by clean_start=true)
+                        //    or it expired, which also triggers the will
                         // 2. Session is still disconnected from the SAME disconnect event
                         // 3. Will is still pending
                         let should_publish = {
@@ -79,7 +112,7 @@ where
                             let is_current_session = sessions
                                 .get(client_id.as_ref())
                                 .map(|s| Arc::ptr_eq(&s, &session))
-                                .unwrap_or(false);
+                                .unwrap_or(true);
 
                             if !is_current_session {
                                 false
@@ -96,7 +129,18 @@ where
                             let will = {
                                 let mut s = session.write();
                                 s.will.take()
-                            };
+                            }
+                            .filter(|_| {
+                                let suppress =
+                                    maintenance.as_ref().is_some_and(|m| m.suppress_wills());
+                                if suppress {
+                                    debug!(
+                                        "Delayed will of {} dropped during maintenance",
+                                        client_id
+                                    );
+                                }
+                                !suppress
+                            });
 
                             if let Some(will) = will {
                                 debug!(
@@ -104,7 +148,7 @@ where
                                     client_id, will.topic
                                 );
 
-                                let publish = Publish {
+                                let mut publish = Publish {
                                     dup: false,
                                     qos: will.qos,
                                     retain: will.retain,
@@ -117,7 +161,14 @@ where
                                 // Handle retained
                                 if will.retain && config.retain_available {
                                     if publish.payload.is_empty() {
-                                        retained.remove(&will.topic);
+                                        if retained.remove(&will.topic).is_some() {
+                                            retained_feed::announce(
+                                                &events,
+                                                &will.topic,
+                                                RetainedChange::Deleted,
+                                                Some(&client_id),
+                                            );
+                                        }
                                         if let Some(ref persistence) = persistence {
                                             persistence.write(PersistenceOp::DeleteRetained {
                                                 topic: will.topic.clone(),
@@ -130,8 +181,17 @@ where
                                             qos: publish.qos,
                                             properties: publish.properties.clone(),
                                             timestamp: Instant::now(),
+                                            proxy_identity: will.proxy_identity.clone(),
                                         };
-                                        retained.insert(will.topic.clone(), retained_msg.clone());
+                                        let replaced = retained
+                                            .insert(will.topic.clone(), retained_msg.clone())
+                                            .is_some();
+                                        retained_feed::announce(
+                                            &events,
+                                            &will.topic,
+                                            RetainedChange::stored(replaced),
+                                            Some(&client_id),
+                                        );
                                         if let Some(ref persistence) = persistence {
                                             persistence.write(PersistenceOp::SetRetained {
                                                 topic: will.topic.clone(),
@@ -141,6 +201,13 @@ where
                                     }
                                 }
 
+                                if config.routing_ids {
+                                    routing_id::assign(&mut publish.properties);
+                                }
+                                if let Some(ref sequencer) = sequencer {
+                                    sequencer.stamp(&mut publish, persistence.as_deref());
+                                }
+
                                 // Route will message to subscribers
                                 let _ = route_will_message(
                                     &subscriptions,
@@ -174,7 +241,14 @@ where
                     // Handle retained
                     if will.retain && self.config.retain_available {
                         if publish.payload.is_empty() {
-                            self.retained.remove(&will.topic);
+                            if self.retained.remove(&will.topic).is_some() {
+                                retained_feed::announce(
+                                    &self.events,
+                                    &will.topic,
+                                    RetainedChange::Deleted,
+                                    Some(client_id),
+                                );
+                            }
                             if let Some(ref persistence) = self.persistence {
                                 persistence.write(PersistenceOp::DeleteRetained {
                                     topic: will.topic.clone(),
@@ -187,9 +261,18 @@ where
                                 qos: publish.qos,
                                 properties: publish.properties.clone(),
                                 timestamp: Instant::now(),
+                                proxy_identity: will.proxy_identity.clone(),
                             };
-                            self.retained
-                                .insert(will.topic.clone(), retained_msg.clone());
+                            let replaced = self
+                                .retained
+                                .insert(will.topic.clone(), retained_msg.clone())
+                                .is_some();
+                            retained_feed::announce(
+                                &self.events,
+                                &will.topic,
+                                RetainedChange::stored(replaced),
+                                Some(client_id),
+                            );
                             if let Some(ref persistence) = self.persistence {
                                 persistence.write(PersistenceOp::SetRetained {
                                     topic: will.topic.clone(),
@@ -217,14 +300,13 @@ where
 
         // Persist session on disconnect if non-ephemeral
         if let Some(ref persistence) = self.persistence {
-            let s = session.read();
-            // Only persist non-clean sessions with expiry > 0
-            if !s.clean_start && s.session_expiry_interval > 0 {
-                persistence.write(PersistenceOp::SetSession {
-                    client_id: client_id.to_string(),
-                    session: StoredSession::from_session(&s),
-                });
-            } else if s.clean_start || s.session_expiry_interval == 0 {
+            let persistent = {
+                let s = session.read();
+                !s.clean_start && s.session_expiry_interval > 0
+            };
+            if persistent {
+                self.checkpoint_session(client_id, session);
+            } else {
                 // Delete any persisted session for clean start or expired
                 persistence.write(PersistenceOp::DeleteSession {
                     client_id: client_id.to_string(),
@@ -237,7 +319,10 @@ where
             client_id: client_id.clone(),
         });
 
-        debug!("Client {} disconnected", client_id);
+        match self.proxy_unique_id {
+            Some(ref id) => debug!("Client {} disconnected (proxy id: {})", client_id, id),
+            None => debug!("Client {} disconnected", client_id),
+        }
     }
 }
 
@@ -251,7 +336,7 @@ pub(crate) async fn route_will_message(
     sender_id: &Arc<str>,
     publish: &Publish,
 ) -> Result<(), ConnectionError> {
-    let matches = subscriptions.matches(&publish.topic);
+    let matches = subscriptions.matches_from(&publish.topic, Some(sender_id));
 
     // Deduplicate by client_id, keeping highest QoS and collecting ALL subscription IDs
     struct ClientSub {
@@ -318,7 +403,7 @@ pub(crate) async fn route_will_message(
             // Client disconnected, queue message if persistent session
             if let Some(session) = sessions.get(client_id.as_ref()) {
                 let mut s = session.write();
-                if !s.clean_start && s.queue_message(outgoing) == QueueResult::DroppedOldest {
+                if !s.clean_start && s.queue_message(outgoing).dropped() {
                     let _ = events.send(BrokerEvent::MessageDropped);
                 }
             }
@@ -331,6 +416,8 @@ pub(crate) async fn route_will_message(
         payload: publish.payload.clone(),
         qos: publish.qos,
         retain: publish.retain,
+        origin: None,
+        user_properties: publish.properties.user_properties.clone(),
     });
 
     Ok(())
@@ -0,0 +1,235 @@
+//! Backlog draining
+//!
+//! A reconnecting client's queued messages are sent after CONNACK. Without
+//! fair draining (see `session::DrainScheduler`), and for backlogs of one
+//! batch, they go out at once. Larger backlogs wait for their turns in the
+//! packet loop, one batch per turn, so the client's pings, acks and
+//! publishes are served in between.
+//!
+//! A turn ends after `turn_timeout` even if a write to a client that stopped
+//! reading is still blocked, and what's left of the batch waits for the next
+//! turn. QoS 1/2 messages routed to the client while it drains are queued
+//! behind the backlog, so they arrive in order.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::RwLock;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::OwnedSemaphorePermit;
+use tracing::debug;
+
+use super::{Connection, ConnectionError};
+use crate::broker::memory_pressure::report_evictions;
+use crate::broker::{BrokerEvent, TraceDirection};
+use crate::protocol::{Packet, Publish, QoS};
+use crate::session::{InflightMessage, Qos2State, Session};
+
+/// A backlog waiting for its next turn
+pub(crate) struct Drain {
+    /// Role `drain_weight` of the client
+    weight: u32,
+    turn: Pin<Box<dyn Future<Output = OwnedSemaphorePermit> + Send + Sync>>,
+}
+
+/// Wait for the connection's next drain turn (forever if not draining)
+pub(crate) async fn next_turn(drain: &mut Option<Drain>) -> OwnedSemaphorePermit {
+    match drain {
+        Some(drain) => drain.turn.as_mut().await,
+        None => std::future::pending().await,
+    }
+}
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Send pending messages from session queue
+    ///
+    /// With fair backlog draining, a backlog larger than one batch is queued
+    /// for a turn instead, `weight` times the batch size per turn.
+    pub(crate) async fn send_pending_messages(
+        &mut self,
+        session: &Arc<RwLock<Session>>,
+        weight: u32,
+    ) -> Result<(), ConnectionError> {
+        if let Some(scheduler) = self.sessions.backlog_drain() {
+            if session.read().pending_count() > scheduler.batch_size(weight) {
+                self.queue_drain_turn(weight);
+                return Ok(());
+            }
+        }
+
+        let (mut pending, max_packet_size) = {
+            let mut s = session.write();
+            (s.drain_pending_messages(), s.max_packet_size)
+        };
+        // Stop at messages the client has no room for (they stay queued)
+        let held = self
+            .send_pending_batch(session, &mut pending, max_packet_size, None)
+            .await?;
+        if !held.is_empty() {
+            session.write().requeue_front(held);
+        }
+        Ok(())
+    }
+
+    /// Wait in line for the next turn
+    fn queue_drain_turn(&mut self, weight: u32) {
+        if let Some(scheduler) = self.sessions.backlog_drain() {
+            self.drain = Some(Drain {
+                weight,
+                turn: Box::pin(scheduler.turn()),
+            });
+        }
+    }
+
+    /// Send one batch of the backlog on the connection's turn, then queue
+    /// for another if more is left
+    pub(crate) async fn drain_turn(
+        &mut self,
+        session: &Arc<RwLock<Session>>,
+        turn: OwnedSemaphorePermit,
+    ) -> Result<(), ConnectionError> {
+        let Some(Drain { weight, .. }) = self.drain.take() else {
+            return Ok(());
+        };
+        let Some(scheduler) = self.sessions.backlog_drain() else {
+            return Ok(());
+        };
+        let batch_size = scheduler.batch_size(weight);
+        let deadline = Instant::now() + scheduler.turn_timeout();
+
+        // Give the turn up at the deadline, even while a write is blocked
+        let release = tokio::spawn(async move {
+            tokio::time::sleep_until(deadline.into()).await;
+            drop(turn);
+        });
+        let (mut pending, max_packet_size) = {
+            let mut s = session.write();
+            (s.drain_pending(batch_size), s.max_packet_size)
+        };
+        let result = self
+            .send_pending_batch(session, &mut pending, max_packet_size, Some(deadline))
+            .await;
+        release.abort();
+
+        // Held messages wait for room on the client, as without fair
+        // draining; the rest of a cut-short batch waits for the next turn
+        let mut held = result?;
+        let blocked = !held.is_empty();
+        held.extend(pending);
+        let mut s = session.write();
+        if !held.is_empty() {
+            s.requeue_front(held);
+        }
+        if !blocked && s.pending_count() > 0 {
+            drop(s);
+            self.queue_drain_turn(weight);
+        }
+        Ok(())
+    }
+
+    /// Queue a QoS 1/2 message routed to the client behind its backlog
+    ///
+    /// Returns the packet if it should be sent now.
+    pub(crate) fn queue_behind_backlog(
+        &self,
+        session: &Arc<RwLock<Session>>,
+        packet: Packet,
+    ) -> Option<Packet> {
+        let publish = match packet {
+            Packet::Publish(p) if self.drain.is_some() && p.qos != QoS::AtMostOnce => p,
+            packet => return Some(packet),
+        };
+        if session.write().queue_message(publish).dropped() {
+            debug!("Session queue full while draining - message dropped");
+            let _ = self.events.send(BrokerEvent::MessageDropped);
+        }
+        None
+    }
+
+    /// Send a batch of pending messages, returning the QoS 1/2 ones held
+    /// back by the client's receive maximum or the inflight limit
+    ///
+    /// Messages not sent by `deadline` are left in `pending`.
+    pub(crate) async fn send_pending_batch(
+        &mut self,
+        session: &Arc<RwLock<Session>>,
+        pending: &mut VecDeque<Publish>,
+        max_packet_size: u32,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<Publish>, ConnectionError> {
+        let mut held = Vec::new();
+        while deadline.is_none_or(|deadline| Instant::now() < deadline) {
+            let Some(mut publish) = pending.pop_front() else {
+                break;
+            };
+            if !self.deliver_allowed(session, &publish).await {
+                continue;
+            }
+            if publish.qos != QoS::AtMostOnce {
+                let mut s = session.write();
+                // Check send quota (MQTT v5.0 flow control)
+                if !s.decrement_send_quota() {
+                    // Quota exhausted - keep the message queued
+                    held.push(publish);
+                    continue;
+                }
+                // Check max_inflight limit
+                if s.inflight_outgoing.len() >= s.max_inflight as usize {
+                    // Inflight limit reached - keep queued and restore quota
+                    s.increment_send_quota();
+                    held.push(publish);
+                    continue;
+                }
+                publish.packet_id = Some(s.next_packet_id());
+                // Store inflight for retry
+                if let Some(packet_id) = publish.packet_id {
+                    s.inflight_outgoing.insert(
+                        packet_id,
+                        InflightMessage {
+                            packet_id,
+                            publish: publish.clone(),
+                            qos2_state: if publish.qos == QoS::ExactlyOnce {
+                                Some(Qos2State::WaitingPubRec)
+                            } else {
+                                None
+                            },
+                            sent_at: Instant::now(),
+                            first_sent_at: Instant::now(),
+                            retry_count: 0,
+                        },
+                    );
+                }
+            }
+
+            report_evictions(&mut publish, &mut session.write());
+            let bytes_sent = self.encode_publish_header(&publish)?;
+
+            // Per MQTT v5.0 spec [MQTT-3.1.2-24]: MUST NOT send packets
+            // exceeding client's Maximum Packet Size
+            if bytes_sent > max_packet_size as usize {
+                tracing::warn!(
+                    "Dropping pending PUBLISH: encoded size {} exceeds client max {}",
+                    bytes_sent,
+                    max_packet_size
+                );
+                continue;
+            }
+
+            let payload = publish.payload.clone();
+            let packet = Packet::Publish(publish);
+            self.trace(TraceDirection::Out, &packet, bytes_sent);
+            self.write_publish(&payload).await?;
+            if let Some(ref metrics) = self.metrics {
+                metrics.publish_sent(bytes_sent);
+            }
+        }
+
+        Ok(held)
+    }
+}
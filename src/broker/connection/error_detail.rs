@@ -0,0 +1,289 @@
+//! Client-visible error detail
+//!
+//! Error reason codes pass through the configured `reason_map` and then the
+//! listener's [`ErrorDetail`] policy before they are sent. Under
+//! [`ErrorDetail::Full`], MQTT v5 clients also get a reason string naming
+//! the failed operation and diagnostic user properties:
+//! - `denied-by` - the check that rejected the request (e.g. "acl"); for
+//!   a publish or subscribe the hooks deny, the hook and the rule it denied
+//!   by (e.g. "acl: no publish pattern of the defaults matches")
+//! - `limit` - the limit that was exceeded (e.g. "max_connections=1000")
+//! - `retry-after` - seconds until the request may succeed
+//!
+//! Diagnostics are omitted if the client set Request Problem Information to
+//! 0, or if they would push the packet past the client's Maximum Packet Size.
+
+use std::borrow::Cow;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::Connection;
+use crate::config::ErrorDetail;
+use crate::hooks::{AccessAction, AccessDecision, AccessRequest};
+use crate::protocol::{Properties, QoS, ReasonCode};
+
+/// Bytes reserved for an ack's fixed header, packet ID and reason code when
+/// fitting diagnostics into the client's Maximum Packet Size
+const ACK_OVERHEAD: usize = 16;
+
+/// Drop user properties, then the reason string, until a packet of
+/// `payload_len` bytes besides its properties fits in `max`
+fn fit_diagnostics(properties: &mut Properties, payload_len: usize, max: usize) {
+    let size = |p: &Properties| {
+        payload_len
+            + ACK_OVERHEAD
+            + p.reason_string.as_ref().map_or(0, |s| 3 + s.len())
+            + p.user_properties
+                .iter()
+                .map(|(k, v)| 5 + k.len() + v.len())
+                .sum::<usize>()
+    };
+    if size(properties) > max {
+        properties.user_properties.clear();
+    }
+    if size(properties) > max {
+        properties.reason_string = None;
+    }
+}
+
+/// Why a request failed, reported as user properties
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Diagnostic {
+    /// Why the check failed, appended to the reason string
+    pub detail: Option<&'static str>,
+    /// The check that rejected the request
+    pub denied_by: Option<Cow<'static, str>>,
+    /// The limit that was exceeded, with its configured value
+    pub limit: Option<(&'static str, usize)>,
+    /// How long until the request may succeed
+    pub retry_after: Option<Duration>,
+}
+
+impl Diagnostic {
+    /// Rejected by the named check
+    pub fn denied_by(check: &'static str) -> Self {
+        Self {
+            denied_by: Some(Cow::Borrowed(check)),
+            ..Default::default()
+        }
+    }
+
+    /// Rejected because the named limit was reached
+    pub fn limit(name: &'static str, value: usize) -> Self {
+        Self {
+            limit: Some((name, value)),
+            ..Default::default()
+        }
+    }
+
+    /// Add why the check failed
+    pub fn with_detail(mut self, detail: &'static str) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+
+    fn user_properties(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        let denied_by = self
+            .denied_by
+            .as_ref()
+            .map(|check| ("denied-by".to_string(), check.to_string()));
+        let limit = self
+            .limit
+            .map(|(name, value)| ("limit".to_string(), format!("{}={}", name, value)));
+        let retry_after = self
+            .retry_after
+            .map(|d| ("retry-after".to_string(), d.as_secs().max(1).to_string()));
+        denied_by.into_iter().chain(limit).chain(retry_after)
+    }
+}
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Report errors to this connection's client with the given detail policy
+    pub fn with_error_detail(mut self, detail: ErrorDetail) -> Self {
+        self.error_detail = detail;
+        self
+    }
+
+    /// Diagnostic for a rejected topic name or filter
+    pub(crate) fn topic_diagnostic(&self, topic: &str, detail: &'static str) -> Diagnostic {
+        let max_levels = self.config.max_topic_levels;
+        let diagnostic = if max_levels > 0 && topic.split('/').count() > max_levels {
+            Diagnostic::limit("max_topic_levels", max_levels)
+        } else {
+            Diagnostic::default()
+        };
+        diagnostic.with_detail(detail)
+    }
+
+    /// Diagnostic for a publish or subscribe the hooks denied, naming the
+    /// hook and rule that denied it (see `Hooks::explain_access`)
+    ///
+    /// The hooks are only asked again if the client gets to see the answer.
+    pub(crate) async fn access_diagnostic(
+        &self,
+        client_id: &str,
+        action: AccessAction,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+    ) -> Diagnostic {
+        let mut diagnostic = Diagnostic::denied_by("acl");
+        if !self.error_detail.reason_strings() || !self.problem_information {
+            return diagnostic;
+        }
+        let request = AccessRequest {
+            client_id,
+            username: self.username.as_deref(),
+            action,
+            topic,
+            qos,
+            retain,
+        };
+        let steps = self.hooks.explain_access(&request).await;
+        if let Some(step) = steps
+            .into_iter()
+            .find(|step| step.decision != AccessDecision::Allow)
+        {
+            diagnostic.denied_by = Some(Cow::Owned(match step.rule {
+                Some(rule) => format!("{}: {}", step.hook, rule),
+                None => step.hook,
+            }));
+        }
+        diagnostic
+    }
+
+    /// Reason code to send for an error under the listener's policy
+    pub(crate) fn client_reason_code(&self, code: ReasonCode) -> ReasonCode {
+        let code = self.config.reason_map.get(&code).copied().unwrap_or(code);
+        self.error_detail.reason_code(code)
+    }
+
+    /// Add a reason string and diagnostics for an error, if the policy and
+    /// client allow them
+    ///
+    /// `context` describes the failed operation, e.g. "publish to 'a/b'".
+    /// Reason strings of multiple errors (SUBACK) are joined with "; ".
+    pub(crate) fn add_client_diagnostics(
+        &self,
+        properties: &mut Properties,
+        code: ReasonCode,
+        diagnostic: Diagnostic,
+        context: impl FnOnce() -> String,
+    ) {
+        if !self.error_detail.reason_strings() || !self.problem_information {
+            return;
+        }
+        let context = match (context(), diagnostic.detail) {
+            (context, None) => context,
+            (context, Some(detail)) if context.is_empty() => detail.to_string(),
+            (context, Some(detail)) => format!("{}: {}", context, detail),
+        };
+        let reason = if context.is_empty() {
+            code.to_string()
+        } else {
+            format!("{} ({})", code, context)
+        };
+        properties.reason_string = Some(match properties.reason_string.take() {
+            Some(existing) => format!("{}; {}", existing, reason),
+            None => reason,
+        });
+        for property in diagnostic.user_properties() {
+            if !properties.user_properties.contains(&property) {
+                properties.user_properties.push(property);
+            }
+        }
+    }
+
+    /// Drop diagnostics that would exceed the client's Maximum Packet Size
+    ///
+    /// [MQTT-3.2.2-19] [MQTT-3.2.2-20] User properties are dropped first,
+    /// then the reason string. `payload_len` is the size of the packet
+    /// besides its properties.
+    pub(crate) fn fit_client_diagnostics(&self, properties: &mut Properties, payload_len: usize) {
+        fit_diagnostics(
+            properties,
+            payload_len,
+            self.client_max_packet_size as usize,
+        );
+    }
+
+    /// Reason code and properties to report an error to the client
+    pub(crate) fn client_error(
+        &self,
+        code: ReasonCode,
+        diagnostic: Diagnostic,
+        context: impl FnOnce() -> String,
+    ) -> (ReasonCode, Properties) {
+        let mut properties = Properties::default();
+        self.add_client_diagnostics(&mut properties, code, diagnostic, context);
+        self.fit_client_diagnostics(&mut properties, 0);
+        (self.client_reason_code(code), properties)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnosed() -> Properties {
+        let diagnostic = Diagnostic {
+            retry_after: Some(Duration::from_millis(200)),
+            ..Diagnostic::limit("quota.messages_per_sec", 10)
+        };
+        Properties {
+            reason_string: Some("Quota exceeded".to_string()),
+            user_properties: diagnostic.user_properties().collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_user_properties() {
+        let diagnostic = Diagnostic {
+            retry_after: Some(Duration::from_millis(200)),
+            ..Diagnostic::denied_by("acl: no publish pattern of the defaults matches")
+        };
+        let properties: Vec<_> = diagnostic.user_properties().collect();
+        assert_eq!(
+            properties,
+            [
+                (
+                    "denied-by".to_string(),
+                    "acl: no publish pattern of the defaults matches".to_string()
+                ),
+                // Rounded up to a whole second
+                ("retry-after".to_string(), "1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fit_diagnostics() {
+        // Reason string: 3 + 14, limit: 5 + 5 + 25, retry-after: 5 + 11 + 1
+        let full = ACK_OVERHEAD + 17 + 35 + 17;
+
+        let mut properties = diagnosed();
+        fit_diagnostics(&mut properties, 0, full);
+        assert_eq!(properties, diagnosed());
+
+        // User properties go first
+        let mut properties = diagnosed();
+        fit_diagnostics(&mut properties, 0, full - 1);
+        assert!(properties.user_properties.is_empty());
+        assert_eq!(properties.reason_string.as_deref(), Some("Quota exceeded"));
+
+        // Then the reason string, counting the rest of the packet
+        let mut properties = diagnosed();
+        fit_diagnostics(&mut properties, 10, ACK_OVERHEAD + 17 + 9);
+        assert_eq!(properties, Properties::default());
+
+        let mut properties = diagnosed();
+        fit_diagnostics(&mut properties, 10, ACK_OVERHEAD + 17 + 10);
+        assert!(properties.user_properties.is_empty());
+        assert!(properties.reason_string.is_some());
+    }
+}
@@ -0,0 +1,140 @@
+//! Outgoing PUBLISH frames
+//!
+//! A message fanned out to N subscribers reaches each connection as a
+//! `Publish` whose payload is a handle on the same `Bytes`. Only the header
+//! (fixed header, topic, packet ID, properties) is encoded into the
+//! connection's `write_buf`; the payload goes out as the second buffer of a
+//! vectored write, so it is never copied per subscriber. Small payloads,
+//! and streams without vectored writes (TLS, WebSocket), are appended to the
+//! header instead: one contiguous write is cheaper there than a second
+//! buffer or a second write.
+
+use std::io::IoSlice;
+
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use super::{Connection, ConnectionError};
+use crate::protocol::Publish;
+
+/// Payloads at least this large are written from their own buffer
+pub(crate) const SHARED_PAYLOAD_MIN: usize = 1024;
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Encode a PUBLISH into `write_buf` up to its payload
+    ///
+    /// Returns the size of the whole packet, for the client's Maximum
+    /// Packet Size and for metrics.
+    pub(crate) fn encode_publish_header(
+        &mut self,
+        publish: &Publish,
+    ) -> Result<usize, ConnectionError> {
+        self.write_buf.clear();
+        self.encoder
+            .encode_publish_header(publish, &mut self.write_buf)
+            .map_err(|e| ConnectionError::Protocol(e.into()))
+    }
+
+    /// Write the PUBLISH whose header is in `write_buf`, then its payload
+    pub(crate) async fn write_publish(&mut self, payload: &Bytes) -> Result<(), ConnectionError> {
+        if payload.len() < SHARED_PAYLOAD_MIN || !self.stream.is_write_vectored() {
+            self.write_buf.extend_from_slice(payload);
+            self.stream.write_all(&self.write_buf).await?;
+            return Ok(());
+        }
+
+        write_all_vectored(&mut self.stream, &self.write_buf, payload).await?;
+        Ok(())
+    }
+}
+
+/// Write `header` then `payload` with vectored writes, however much of
+/// them each write takes
+async fn write_all_vectored<W: AsyncWrite + Unpin>(
+    stream: &mut W,
+    mut header: &[u8],
+    mut payload: &[u8],
+) -> std::io::Result<()> {
+    while !header.is_empty() || !payload.is_empty() {
+        let bufs = [IoSlice::new(header), IoSlice::new(payload)];
+        let written = stream.write_vectored(&bufs).await?;
+        if written == 0 {
+            return Err(std::io::ErrorKind::WriteZero.into());
+        }
+        let from_header = written.min(header.len());
+        header = &header[from_header..];
+        payload = &payload[written - from_header..];
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Takes at most `limit` bytes per write
+    struct Trickle {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let n = buf.len().min(self.limit);
+            self.written.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_write_vectored(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            bufs: &[IoSlice<'_>],
+        ) -> Poll<std::io::Result<usize>> {
+            let mut n = 0;
+            for buf in bufs {
+                let take = buf.len().min(self.limit - n);
+                self.written.extend_from_slice(&buf[..take]);
+                n += take;
+            }
+            Poll::Ready(Ok(n))
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            true
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_all_vectored() {
+        let header = b"header";
+        let payload: Vec<u8> = (0..=255).collect();
+        for limit in [1, 4, 7, 1000] {
+            let mut stream = Trickle {
+                written: Vec::new(),
+                limit,
+            };
+            write_all_vectored(&mut stream, header, &payload)
+                .await
+                .unwrap();
+            assert_eq!(&stream.written[..6], header);
+            assert_eq!(&stream.written[6..], &payload[..]);
+        }
+    }
+}
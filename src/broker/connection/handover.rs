@@ -0,0 +1,99 @@
+//! Session handover
+//!
+//! A client about to hand its work to a successor (another instance taking
+//! over during a failover or rolling update) publishes to
+//! "{topic}/prepare" (see [`crate::config::HandoverConfig`]). The broker
+//! answers with a token, sent to the request's Response Topic (with its
+//! Correlation Data) or else to "{topic}/token", to this client only. From
+//! then on the session is held for the successor: it outlives the
+//! connection, queueing messages, and a CONNECT for the client ID is
+//! refused unless it carries the token as the `handover-token` user
+//! property. The successor connects with Clean Start = 0 and resumes the
+//! session, taking over the connection if it is still open.
+//!
+//! Once the token expires, anyone may connect again and the session
+//! expires as usual. "{topic}/cancel" calls the handover off early. The
+//! admin API does the same for a client that can't (`POST` and `DELETE`
+//! `/api/v1/clients/<id>/handover`). The successor needs MQTT 5 to present
+//! the token; the client handing over may use any version.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use parking_lot::RwLock;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::debug;
+
+use super::{Connection, ConnectionError, Diagnostic};
+use crate::config::HandoverControl;
+use crate::protocol::{Packet, Properties, Publish, QoS, ReasonCode};
+use crate::session::{Handover, Session};
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Handle a PUBLISH to a handover control topic
+    pub(crate) async fn handle_handover_control(
+        &mut self,
+        client_id: &Arc<str>,
+        session: &Arc<RwLock<Session>>,
+        publish: &Publish,
+        control: Option<HandoverControl>,
+    ) -> Result<(), ConnectionError> {
+        match control {
+            Some(HandoverControl::Prepare) => {
+                let handover = session
+                    .write()
+                    .prepare_handover(self.config.handover.token_ttl);
+                debug!("Handover of {} prepared", client_id);
+                self.acknowledge_settled(publish).await?;
+                self.send_handover_token(publish, &handover).await
+            }
+            Some(HandoverControl::Cancel) => {
+                if session.write().handover.take().is_some() {
+                    debug!("Handover of {} cancelled", client_id);
+                }
+                self.acknowledge_settled(publish).await
+            }
+            None => {
+                debug!(
+                    "Handover command {} from {}: unknown command",
+                    publish.topic, client_id
+                );
+                let diagnostic = Diagnostic::default().with_detail("unknown handover command");
+                self.send_publish_error(publish, ReasonCode::ImplementationError, diagnostic)
+                    .await
+            }
+        }
+    }
+
+    /// Send the token to the client that asked for it
+    async fn send_handover_token(
+        &mut self,
+        request: &Publish,
+        handover: &Handover,
+    ) -> Result<(), ConnectionError> {
+        let topic = request
+            .properties
+            .response_topic
+            .clone()
+            .unwrap_or_else(|| self.config.handover.token_topic());
+        let payload = serde_json::json!({
+            "token": &*handover.token,
+            "expires_in": handover.expires_in(),
+        });
+        let reply = Publish {
+            qos: QoS::AtMostOnce,
+            topic,
+            payload: Bytes::from(payload.to_string()),
+            properties: Properties {
+                correlation_data: request.properties.correlation_data.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        self.write_packet(&Packet::Publish(reply)).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+//! Idle connection hibernation
+//!
+//! A connected client that is neither sending nor receiving still holds a
+//! pooled read and write buffer (4 KB each). Once a connection has been idle
+//! for `hibernate_after`, both are returned to the pool and replaced with
+//! empty buffers, and the retry ticker is paused, so the task only waits on
+//! socket readability, its outbound channel and the keep-alive deadline.
+//!
+//! The first read or outbound packet wakes the connection and restores
+//! pooled buffers. Connections with inflight QoS 1/2 state are never
+//! hibernated, since they still need retries, nor are ones draining a
+//! backlog or holding messages for congested subscribers.
+
+use std::sync::Arc;
+
+use bytes::BytesMut;
+use parking_lot::RwLock;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::trace;
+
+use super::Connection;
+use crate::buffer_pool;
+use crate::session::Session;
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Release the connection's buffers if it is safe to do so
+    ///
+    /// Returns false if the connection has buffered input, inflight or held
+    /// messages or a backlog to drain.
+    pub(crate) fn try_hibernate(&mut self, session: &Arc<RwLock<Session>>) -> bool {
+        if self.hibernated
+            || !self.read_buf.is_empty()
+            || self.drain.is_some()
+            || !self.throttled.is_empty()
+        {
+            return false;
+        }
+        {
+            let s = session.read();
+            if !s.inflight_outgoing.is_empty() || !s.inflight_incoming.is_empty() {
+                return false;
+            }
+        }
+
+        buffer_pool::put_buffer(std::mem::take(&mut self.read_buf));
+        buffer_pool::put_buffer(std::mem::take(&mut self.write_buf));
+        self.hibernated = true;
+        if let Some(ref m) = self.metrics {
+            m.connection_hibernated();
+        }
+        trace!("Hibernated idle connection from {}", self.addr);
+        true
+    }
+
+    /// Restore pooled buffers after hibernation (no-op if awake)
+    ///
+    /// Bytes already read into the small hibernation buffer are kept.
+    pub(crate) fn wake_from_hibernation(&mut self) {
+        if !self.hibernated {
+            return;
+        }
+
+        let mut read_buf = buffer_pool::get_buffer();
+        read_buf.extend_from_slice(&self.read_buf);
+        self.read_buf = read_buf;
+        self.write_buf = buffer_pool::get_buffer();
+        self.hibernated = false;
+        if let Some(ref m) = self.metrics {
+            m.connection_woken();
+        }
+        trace!("Woke hibernated connection from {}", self.addr);
+    }
+
+    /// Drop hibernation buffers without pooling them (they are too small to reuse)
+    ///
+    /// Returns true if the connection was hibernated.
+    pub(crate) fn end_hibernation(&mut self) -> bool {
+        if !std::mem::take(&mut self.hibernated) {
+            return false;
+        }
+        self.read_buf = BytesMut::new();
+        self.write_buf = BytesMut::new();
+        if let Some(ref m) = self.metrics {
+            m.connection_woken();
+        }
+        true
+    }
+}
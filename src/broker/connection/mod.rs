@@ -8,13 +8,29 @@
 //! - Uses SmallVec for subscription IDs (typically few per message)
 //! - Pre-allocates collections with reasonable capacity
 
+mod backpressure;
+mod batch;
+mod compression;
 mod connect;
+mod delayed;
 mod disconnect;
+mod drain;
+mod error_detail;
+mod frame;
+mod handover;
+mod hibernate;
 mod publish;
 mod qos;
+mod quota;
+mod replay;
 mod subscribe;
+mod transaction;
 
-use std::net::SocketAddr;
+pub(crate) use error_detail::Diagnostic;
+pub(crate) use publish::acquire_publish_rate;
+pub(crate) use quota::ClientQuota;
+
+use std::collections::VecDeque;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -26,15 +42,25 @@ use tokio::sync::{broadcast, mpsc};
 use tokio::time::timeout;
 use tracing::{debug, error, info, warn};
 
-use crate::broker::{BrokerConfig, BrokerEvent, RetainedMessage};
+use crate::broker::backpressure::ListenerSlot;
+use crate::broker::memory_pressure::report_evictions;
+use crate::broker::{
+    BrokerConfig, BrokerEvent, Confirmations, DelayedQueue, Limiters, ListenerLoad, Maintenance,
+    PriorityLanes, RetainedFrames, RetainedMessage, Sequencer, TraceDirection, Tracer, Tunables,
+};
 use crate::buffer_pool;
 use crate::codec::{Decoder, Encoder};
+use crate::config::{
+    CompressionCodec, ErrorDetail, ListenerCapabilities, ListenerLimits, SessionCheckpoint,
+};
 use crate::hooks::Hooks;
 use crate::metrics::Metrics;
-use crate::protocol::Packet;
-use crate::proxy::ProxyInfo;
-use crate::session::{Session, SessionStore};
-use crate::topic::SubscriptionStore;
+use crate::persistence::{PersistenceOp, StoredSession};
+use crate::protocol::{Packet, Properties, Publish, QoS};
+use crate::proxy::{ProxyIdentity, ProxyInfo, ProxyTlsInfo};
+use crate::session::{QueueResult, Session, SessionStore};
+use crate::topic::{SubscriptionStore, TopicSchema};
+use crate::transport::PeerAddr;
 
 /// Connection error types
 #[derive(Debug)]
@@ -84,7 +110,7 @@ pub(crate) enum State {
 /// Connection handler - generic over the stream type
 pub struct Connection<S> {
     pub(crate) stream: S,
-    pub(crate) addr: SocketAddr,
+    pub(crate) addr: PeerAddr,
     pub(crate) state: State,
     pub(crate) decoder: Decoder,
     pub(crate) encoder: Encoder,
@@ -104,9 +130,72 @@ pub struct Connection<S> {
     pub(crate) persistence: Option<Arc<crate::persistence::PersistenceManager>>,
     /// Username from CONNECT packet (for ACL checks)
     pub(crate) username: Option<String>,
-    /// PROXY protocol info (if connection came through a proxy)
-    #[allow(dead_code)]
-    pub(crate) proxy_info: Option<ProxyInfo>,
+    /// TLS details, from the local handshake or the PROXY header
+    pub(crate) tls_info: Option<ProxyTlsInfo>,
+    /// Proxy-assigned connection ID (PP2_TYPE_UNIQUE_ID), for log correlation
+    pub(crate) proxy_unique_id: Option<String>,
+    /// Who the client is behind a PROXY header, kept on its wills and
+    /// retained messages
+    pub(crate) proxy_identity: Option<Arc<ProxyIdentity>>,
+    /// Publish rate limit key, resolved on first publish
+    pub(crate) rate_identity: Option<Arc<str>>,
+    /// Session state changed since the last checkpoint
+    pub(crate) checkpoint_pending: bool,
+    /// Buffers released while idle (see `hibernate`)
+    pub(crate) hibernated: bool,
+    /// QoS cap of the listener
+    pub(crate) listener_max_qos: Option<QoS>,
+    /// QoS cap of the client's role, resolved at CONNECT
+    pub(crate) role_max_qos: Option<QoS>,
+    /// Error detail revealed to the client (per listener)
+    pub(crate) error_detail: ErrorDetail,
+    /// Client accepts reason strings on acks (Request Problem Information)
+    pub(crate) problem_information: bool,
+    /// Client's Maximum Packet Size (bounds diagnostic properties)
+    pub(crate) client_max_packet_size: u32,
+    /// Listener the connection was accepted on (reported to auth hooks)
+    pub(crate) listener: &'static str,
+    /// Quota limits, resolved at CONNECT (`None` = unlimited)
+    pub(crate) quota: Option<quota::ClientQuota>,
+    /// Live message traces packets are reported to
+    pub(crate) tracer: Option<Arc<Tracer>>,
+    /// Filters holding back QoS 1 PUBACKs (see `confirm`)
+    pub(crate) confirmations: Option<Arc<Confirmations>>,
+    /// Client ID packets are traced under, known from CONNECT on
+    pub(crate) trace_client_id: Option<Arc<str>>,
+    /// Messages held by the client's open transaction (see `transaction`)
+    pub(crate) transaction: Option<transaction::Transaction>,
+    /// Connections per listener and congested subscribers
+    pub(crate) listener_load: Option<Arc<ListenerLoad>>,
+    /// Queue of delayed publishes (`delayed.enabled`)
+    pub(crate) delayed: Option<Arc<DelayedQueue>>,
+    /// Declared topic namespace publishes are checked against
+    pub(crate) topic_schema: Option<Arc<TopicSchema>>,
+    /// Numbers routed messages (`sequence.enabled`)
+    pub(crate) sequencer: Option<Arc<Sequencer>>,
+    /// Priority channels (`priority.topics`), and this connection's
+    pub(crate) priority: Option<Arc<PriorityLanes>>,
+    pub(crate) priority_tx: Option<mpsc::Sender<Packet>>,
+    pub(crate) priority_rx: Option<mpsc::Receiver<Packet>>,
+    /// Encoded retained frames (`retained_cache.enabled`)
+    pub(crate) retained_frames: Option<Arc<RetainedFrames>>,
+    /// Settings changed at runtime
+    pub(crate) tunables: Option<Arc<Tunables>>,
+    /// Limit boosts and the registry quota buckets are listed in
+    pub(crate) limiters: Option<Arc<Limiters>>,
+    /// Maintenance window state (will suppression)
+    pub(crate) maintenance: Option<Arc<Maintenance>>,
+    /// This connection's count against its listener, from CONNECT on
+    pub(crate) listener_slot: Option<ListenerSlot>,
+    /// Limits of the client's listener, resolved at CONNECT
+    pub(crate) listener_limits: Option<ListenerLimits>,
+    /// Codec of a bridge's compressed publishes, accepted at CONNECT
+    pub(crate) bridge_codec: Option<CompressionCodec>,
+    /// Backlog waiting for its next drain turn (see `drain`)
+    pub(crate) drain: Option<drain::Drain>,
+    /// Messages held for congested subscribers, oldest first (see
+    /// `backpressure`)
+    pub(crate) throttled: VecDeque<backpressure::Throttled>,
 }
 
 impl<S> Connection<S>
@@ -116,7 +205,7 @@ where
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         stream: S,
-        addr: SocketAddr,
+        addr: PeerAddr,
         proxy_info: Option<ProxyInfo>,
         sessions: Arc<SessionStore>,
         subscriptions: Arc<SubscriptionStore>,
@@ -129,6 +218,11 @@ where
         persistence: Option<Arc<crate::persistence::PersistenceManager>>,
     ) -> Self {
         let (packet_tx, packet_rx) = mpsc::channel(config.outbound_channel_capacity);
+        let proxy_unique_id = proxy_info.as_ref().and_then(ProxyInfo::unique_id);
+        let proxy_identity = proxy_info
+            .as_ref()
+            .map(|info| Arc::new(ProxyIdentity::from_info(info)));
+        let tls_info = proxy_info.and_then(|info| info.tls_info);
 
         Self {
             stream,
@@ -150,7 +244,232 @@ where
             metrics,
             persistence,
             username: None,
-            proxy_info,
+            tls_info,
+            proxy_unique_id,
+            proxy_identity,
+            rate_identity: None,
+            checkpoint_pending: false,
+            hibernated: false,
+            listener_max_qos: None,
+            role_max_qos: None,
+            error_detail: ErrorDetail::default(),
+            problem_information: true,
+            client_max_packet_size: u32::MAX,
+            listener: "tcp",
+            quota: None,
+            tracer: None,
+            confirmations: None,
+            trace_client_id: None,
+            transaction: None,
+            listener_load: None,
+            delayed: None,
+            topic_schema: None,
+            sequencer: None,
+            priority: None,
+            priority_tx: None,
+            priority_rx: None,
+            retained_frames: None,
+            tunables: None,
+            limiters: None,
+            maintenance: None,
+            listener_slot: None,
+            listener_limits: None,
+            bridge_codec: None,
+            drain: None,
+            throttled: VecDeque::new(),
+        }
+    }
+
+    /// Accept legacy MQTT 3.1 ("MQIsdp") clients on this connection
+    pub fn with_mqtt31(mut self, allow: bool) -> Self {
+        self.decoder = std::mem::take(&mut self.decoder).with_mqtt31(allow);
+        self
+    }
+
+    /// Name the listener this connection was accepted on
+    pub fn with_listener(mut self, listener: &'static str) -> Self {
+        self.listener = listener;
+        self
+    }
+
+    /// Report this connection's packets to live traces
+    pub fn with_tracer(mut self, tracer: Arc<Tracer>) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Hold back PUBACKs of messages a consumer has to confirm
+    pub fn with_confirmations(mut self, confirmations: Arc<Confirmations>) -> Self {
+        self.confirmations = Some(confirmations);
+        self
+    }
+
+    /// Count the connection against its listener's limits
+    pub fn with_listener_load(mut self, listener_load: Arc<ListenerLoad>) -> Self {
+        self.listener_load = Some(listener_load);
+        self
+    }
+
+    /// Hold delayed publishes in `delayed`
+    pub fn with_delayed(mut self, delayed: Arc<DelayedQueue>) -> Self {
+        self.delayed = Some(delayed);
+        self
+    }
+
+    /// Check publishes against the `topic_schema` templates
+    pub fn with_topic_schema(mut self, topic_schema: Arc<TopicSchema>) -> Self {
+        self.topic_schema = Some(topic_schema);
+        self
+    }
+
+    /// Number routed messages with `sequencer`
+    pub fn with_sequencer(mut self, sequencer: Arc<Sequencer>) -> Self {
+        self.sequencer = Some(sequencer).filter(|s| s.is_enabled());
+        self
+    }
+
+    /// Deliver messages on priority topics through a channel of their own
+    pub fn with_priority(mut self, priority: Arc<PriorityLanes>) -> Self {
+        if priority.is_enabled() {
+            let (tx, rx) = mpsc::channel(priority.headroom());
+            self.priority_tx = Some(tx);
+            self.priority_rx = Some(rx);
+            self.priority = Some(priority);
+        }
+        self
+    }
+
+    /// Whether messages on `topic` take the priority path
+    pub(crate) fn is_priority(&self, topic: &str) -> bool {
+        self.priority.as_ref().is_some_and(|p| p.applies(topic))
+    }
+
+    /// Deliver retained messages from the shared frame cache
+    pub fn with_retained_frames(mut self, retained_frames: Arc<RetainedFrames>) -> Self {
+        if retained_frames.is_enabled() {
+            self.retained_frames = Some(retained_frames);
+        }
+        self
+    }
+
+    /// Apply the broker's runtime tunables
+    pub fn with_tunables(mut self, tunables: Arc<Tunables>) -> Self {
+        self.tunables = Some(tunables);
+        self
+    }
+
+    /// Apply limit boosts and list quota buckets in the broker's limiters
+    pub fn with_limiters(mut self, limiters: Arc<Limiters>) -> Self {
+        self.limiters = Some(limiters);
+        self
+    }
+
+    /// Drop wills while a maintenance window suppresses them
+    pub fn with_maintenance(mut self, maintenance: Arc<Maintenance>) -> Self {
+        self.maintenance = Some(maintenance);
+        self
+    }
+
+    /// Use the TLS details of a locally terminated handshake
+    pub fn with_tls_info(mut self, tls_info: ProxyTlsInfo) -> Self {
+        self.tls_info = Some(tls_info);
+        self
+    }
+
+    /// Cap the QoS granted and accepted on this connection
+    pub fn with_max_qos(mut self, max_qos: Option<QoS>) -> Self {
+        self.listener_max_qos = max_qos;
+        self
+    }
+
+    /// Narrow the MQTT features offered to those the listener allows
+    ///
+    /// Applied to this connection's copy of the broker config, so CONNACK
+    /// and the per-packet checks both see the listener's view.
+    pub fn with_capabilities(mut self, capabilities: ListenerCapabilities) -> Self {
+        self.config.retain_available &= capabilities.retain_available;
+        self.config.wildcard_subscription_available &= capabilities.wildcard_subscriptions;
+        self.config.subscription_identifiers_available &= capabilities.subscription_identifiers;
+        self.config.shared_subscriptions_available &= capabilities.shared_subscriptions;
+        if let Some(max) = capabilities.topic_alias_maximum {
+            self.config.max_topic_alias = self.config.max_topic_alias.min(max);
+        }
+        self
+    }
+
+    /// Report a packet to the traces matching the client
+    pub(crate) fn trace(&self, direction: TraceDirection, packet: &Packet, size: usize) {
+        if let (Some(tracer), Some(client_id)) = (&self.tracer, &self.trace_client_id) {
+            tracer.record(client_id, direction, packet, size);
+        }
+    }
+
+    /// Report an outgoing PUBLISH queued or dropped instead of written
+    pub(crate) fn trace_held(&self, publish: &Publish, why: &'static str) {
+        if let (Some(tracer), Some(client_id)) = (&self.tracer, &self.trace_client_id) {
+            if tracer.is_active() {
+                tracer.record_held(client_id, &Packet::Publish(publish.clone()), why);
+            }
+        }
+    }
+
+    /// Whether the `on_deliver` hooks let a message through to the client
+    ///
+    /// A failing hook doesn't hold messages back.
+    pub(crate) async fn deliver_allowed(
+        &self,
+        session: &Arc<RwLock<Session>>,
+        publish: &Publish,
+    ) -> bool {
+        let client_id = session.read().client_id.clone();
+        match self.hooks.on_deliver(&client_id, publish).await {
+            Ok(true) => true,
+            Ok(false) => {
+                self.trace_held(publish, "skipped by hook");
+                false
+            }
+            Err(e) => {
+                warn!("Deliver hook error for {}: {}", client_id, e);
+                true
+            }
+        }
+    }
+
+    /// Encode and write a packet to the client
+    pub(crate) async fn write_packet(&mut self, packet: &Packet) -> Result<(), ConnectionError> {
+        if let Packet::Publish(publish) = packet {
+            let len = self.encode_publish_header(publish)?;
+            self.trace(TraceDirection::Out, packet, len);
+            return self.write_publish(&publish.payload).await;
+        }
+        self.write_buf.clear();
+        self.encoder
+            .encode(packet, &mut self.write_buf)
+            .map_err(|e| ConnectionError::Protocol(e.into()))?;
+        self.trace(TraceDirection::Out, packet, self.write_buf.len());
+        self.stream.write_all(&self.write_buf).await?;
+        Ok(())
+    }
+
+    /// Highest QoS the client may use (broker, listener and role caps)
+    pub(crate) fn max_qos(&self) -> QoS {
+        [self.listener_max_qos, self.role_max_qos]
+            .into_iter()
+            .flatten()
+            .fold(self.config.max_qos, QoS::min)
+    }
+
+    /// Stamp the client's proxied identity on a will or retained message,
+    /// if the listener's PROXY config asks for it
+    pub(crate) fn stamp_forwarded_for(&self, properties: &mut Properties) {
+        let proxy = match self.listener {
+            "tls" => &self.config.tls_proxy_protocol,
+            "ws" | "wss" => &self.config.ws_proxy_protocol,
+            "unix" => &self.config.unix_proxy_protocol,
+            _ => &self.config.proxy_protocol,
+        };
+        if let (true, Some(identity)) = (proxy.forwarded_for, &self.proxy_identity) {
+            identity.stamp(properties);
         }
     }
 
@@ -202,9 +521,24 @@ where
         // Skip the first immediate tick
         retry_ticker.tick().await;
 
+        // Create checkpoint ticker for interval session checkpoints
+        let checkpoint_interval = self
+            .persistence
+            .as_ref()
+            .filter(|p| p.session_checkpoint() == SessionCheckpoint::Interval)
+            .map(|p| p.session_checkpoint_interval());
+        let mut checkpoint_ticker =
+            tokio::time::interval(checkpoint_interval.unwrap_or(Duration::from_secs(1)));
+        checkpoint_ticker.tick().await;
+
         // Track keep-alive deadline (reset when packets received)
         let mut keep_alive_deadline = tokio::time::Instant::now() + keep_alive;
 
+        // Track hibernation deadline (reset on any traffic)
+        let hibernate_after = self.config.hibernate_after;
+        let hibernate_idle = hibernate_after.unwrap_or(keep_alive);
+        let mut hibernate_deadline = tokio::time::Instant::now() + hibernate_idle;
+
         loop {
             tokio::select! {
                 // Read from socket
@@ -217,9 +551,13 @@ where
                             return Ok(());
                         }
                         Ok(_) => {
+                            self.wake_from_hibernation();
+                            hibernate_deadline = tokio::time::Instant::now() + hibernate_idle;
+
                             // Process packets
                             while let Some((packet, consumed)) = self.decoder.decode(&self.read_buf)? {
                                 self.read_buf.advance(consumed);
+                                self.trace(TraceDirection::In, &packet, consumed);
 
                                 // Update activity timestamp and reset keep-alive deadline
                                 {
@@ -228,6 +566,7 @@ where
                                 }
                                 keep_alive_deadline = tokio::time::Instant::now() + keep_alive;
 
+                                let changes_session = Self::changes_session(&packet);
                                 if let Err(e) = self.handle_packet(&client_id, &session, packet).await {
                                     match &e {
                                         ConnectionError::Shutdown => {
@@ -247,6 +586,9 @@ where
                                         }
                                     }
                                 }
+                                if changes_session {
+                                    self.session_changed(&client_id, &session);
+                                }
                             }
                         }
                         Err(e) => {
@@ -259,12 +601,58 @@ where
 
                 // Receive packets to send
                 Some(packet) = self.packet_rx.recv() => {
-                    self.handle_outgoing_packet(&session, packet).await?;
+                    hibernate_deadline = tokio::time::Instant::now() + hibernate_idle;
+                    if let Some(packet) = self.queue_behind_backlog(&session, packet) {
+                        self.send_outgoing(&client_id, &session, packet).await?;
+                    }
+                }
+
+                // Priority messages don't wait behind the ones above
+                Some(packet) = recv_priority(&mut self.priority_rx) => {
+                    hibernate_deadline = tokio::time::Instant::now() + hibernate_idle;
+                    self.send_outgoing(&client_id, &session, packet).await?;
+                }
+
+                // Settle a message held for congested subscribers
+                behind = backpressure::next_throttled(&mut self.throttled), if !self.throttled.is_empty() => {
+                    hibernate_deadline = tokio::time::Instant::now() + hibernate_idle;
+                    if let Err(e) = self.release_throttled(&client_id, &session, behind).await {
+                        if !matches!(e, ConnectionError::Shutdown) {
+                            self.handle_disconnect(&client_id, &session, true).await;
+                        }
+                        return Err(e);
+                    }
+                    self.session_changed(&client_id, &session);
+                }
+
+                // Send the next batch of the backlog
+                turn = drain::next_turn(&mut self.drain), if self.drain.is_some() => {
+                    hibernate_deadline = tokio::time::Instant::now() + hibernate_idle;
+                    if let Err(e) = self.drain_turn(&session, turn).await {
+                        self.handle_disconnect(&client_id, &session, true).await;
+                        return Err(e);
+                    }
+                    self.session_changed(&client_id, &session);
+                }
+
+                // Checkpoint session state
+                _ = checkpoint_ticker.tick(), if checkpoint_interval.is_some() => {
+                    if std::mem::take(&mut self.checkpoint_pending) {
+                        self.checkpoint_session(&client_id, &session);
+                    }
                 }
 
-                // Retry unacked messages
-                _ = retry_ticker.tick() => {
+                // Retry unacked messages (nothing is inflight while hibernated)
+                _ = retry_ticker.tick(), if !self.hibernated => {
                     self.retry_unacked_messages(&session).await?;
+                    self.relieve_backpressure(&session);
+                }
+
+                // Hibernate idle connection
+                _ = tokio::time::sleep_until(hibernate_deadline), if hibernate_after.is_some() && !self.hibernated => {
+                    if !self.try_hibernate(&session) {
+                        hibernate_deadline = tokio::time::Instant::now() + hibernate_idle;
+                    }
                 }
 
                 // Keep alive timeout
@@ -272,12 +660,20 @@ where
                     info!("Keep alive timeout for {} - disconnecting", client_id);
                     // For MQTT v5, send DISCONNECT with KeepAliveTimeout reason before closing
                     if self.decoder.protocol_version() == Some(crate::protocol::ProtocolVersion::V5) {
+                        let (reason_code, properties) =
+                            self.client_error(
+                                crate::protocol::ReasonCode::KeepAliveTimeout,
+                                Diagnostic::limit("keep_alive", keep_alive_secs as usize),
+                                String::new,
+                            );
                         let disconnect = crate::protocol::Disconnect {
-                            reason_code: crate::protocol::ReasonCode::KeepAliveTimeout,
-                            properties: crate::protocol::Properties::default(),
+                            reason_code,
+                            properties,
                         };
+                        let packet = Packet::Disconnect(disconnect);
                         self.write_buf.clear();
-                        if self.encoder.encode(&Packet::Disconnect(disconnect), &mut self.write_buf).is_ok() {
+                        if self.encoder.encode(&packet, &mut self.write_buf).is_ok() {
+                            self.trace(TraceDirection::Out, &packet, self.write_buf.len());
                             let _ = self.stream.write_all(&self.write_buf).await;
                             let _ = self.stream.flush().await;
                         }
@@ -289,59 +685,184 @@ where
         }
     }
 
+    /// Whether handling an incoming packet changes checkpointed session state
+    fn changes_session(packet: &Packet) -> bool {
+        match packet {
+            Packet::Publish(publish) => publish.qos == crate::protocol::QoS::ExactlyOnce,
+            Packet::PubAck(_)
+            | Packet::PubRec(_)
+            | Packet::PubRel(_)
+            | Packet::PubComp(_)
+            | Packet::Subscribe(_)
+            | Packet::Unsubscribe(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Record a session state change, checkpointing per the configured mode
+    fn session_changed(&mut self, client_id: &Arc<str>, session: &Arc<RwLock<Session>>) {
+        match self.persistence.as_ref().map(|p| p.session_checkpoint()) {
+            Some(SessionCheckpoint::EveryChange) => self.checkpoint_session(client_id, session),
+            Some(SessionCheckpoint::Interval) => self.checkpoint_pending = true,
+            Some(SessionCheckpoint::Disconnect) | None => {}
+        }
+    }
+
+    /// Write the session to storage if it outlives the connection
+    pub(crate) fn checkpoint_session(&self, client_id: &Arc<str>, session: &Arc<RwLock<Session>>) {
+        let Some(ref persistence) = self.persistence else {
+            return;
+        };
+        let stored = {
+            let s = session.read();
+            // Only persist non-clean sessions with expiry > 0
+            if s.clean_start || s.session_expiry_interval == 0 {
+                return;
+            }
+            StoredSession::from_session(&s)
+        };
+        persistence.write(PersistenceOp::SetSession {
+            client_id: client_id.to_string(),
+            session: stored,
+        });
+        if let Some(ref metrics) = self.metrics {
+            metrics.session_checkpointed();
+        }
+    }
+
+    /// Disconnect the client if its queue overflowed under
+    /// `queue_overflow = "disconnect"` (the session ends with it)
+    async fn check_queue_overflow(&mut self, result: QueueResult) -> Result<(), ConnectionError> {
+        if result != QueueResult::Overflow {
+            return Ok(());
+        }
+        self.send_disconnect(
+            crate::protocol::ReasonCode::QuotaExceeded,
+            Diagnostic::default(),
+        )
+        .await;
+        Err(ConnectionError::Protocol(
+            crate::protocol::ProtocolError::QuotaExceeded,
+        ))
+    }
+
+    /// Send a packet from the outbound channels, disconnecting on failure
+    async fn send_outgoing(
+        &mut self,
+        client_id: &Arc<str>,
+        session: &Arc<RwLock<Session>>,
+        packet: Packet,
+    ) -> Result<(), ConnectionError> {
+        self.wake_from_hibernation();
+        let changes_session =
+            matches!(&packet, Packet::Publish(p) if p.qos != crate::protocol::QoS::AtMostOnce);
+        if let Err(e) = self.handle_outgoing_packet(session, packet).await {
+            // Shutdown: taken over, the new connection owns the session
+            if !matches!(e, ConnectionError::Shutdown) {
+                self.handle_disconnect(client_id, session, true).await;
+            }
+            return Err(e);
+        }
+        if changes_session {
+            self.session_changed(client_id, session);
+        }
+        Ok(())
+    }
+
     /// Handle outgoing packet from the channel
     async fn handle_outgoing_packet(
         &mut self,
         session: &Arc<RwLock<Session>>,
         packet: Packet,
     ) -> Result<(), ConnectionError> {
-        use crate::protocol::QoS;
-        use crate::session::{InflightMessage, Qos2State, QueueResult};
-
         match packet {
-            Packet::Disconnect(_) => {
+            Packet::Disconnect(mut disconnect) => {
                 // We're being disconnected (session takeover)
                 // Per MQTT spec, after sending DISCONNECT, we must close the connection
+                let taken_over =
+                    disconnect.reason_code == crate::protocol::ReasonCode::SessionTakenOver;
+                if disconnect.reason_code.is_error() {
+                    let code = disconnect.reason_code;
+                    disconnect.reason_code = self.client_reason_code(code);
+                    if disconnect.properties.reason_string.is_none() {
+                        self.add_client_diagnostics(
+                            &mut disconnect.properties,
+                            code,
+                            Diagnostic::default(),
+                            String::new,
+                        );
+                        self.fit_client_diagnostics(&mut disconnect.properties, 0);
+                    }
+                }
+                let packet = Packet::Disconnect(disconnect);
                 self.write_buf.clear();
                 let _ = self.encoder.encode(&packet, &mut self.write_buf);
+                self.trace(TraceDirection::Out, &packet, self.write_buf.len());
                 let _ = self.stream.write_all(&self.write_buf).await;
+                // A new connection owns a taken over session; otherwise the
+                // server ended the connection (ACL revocation, admin) and
+                // this one cleans up like after a network error
+                if !taken_over {
+                    let client_id = session.read().client_id.clone();
+                    self.handle_disconnect(&client_id, session, true).await;
+                }
                 // Return Shutdown to terminate the connection loop
                 Err(ConnectionError::Shutdown)
             }
-            Packet::Publish(mut publish) => {
-                // Get max packet size from session
-                let max_packet_size = {
-                    let s = session.read();
-                    s.max_packet_size
-                };
+            Packet::Publish(publish) => {
+                self.send_publish(session, publish).await?;
+                Ok(())
+            }
+            _ => {
+                self.write_packet(&packet).await?;
+                Ok(())
+            }
+        }
+    }
 
-                // Per MQTT v5.0 spec [MQTT-4.9.0-2]: MUST NOT send QoS>0
-                // PUBLISH when send quota is 0
-                if publish.qos != QoS::AtMostOnce {
-                    let mut s = session.write();
-                    if !s.decrement_send_quota() {
-                        // Quota exhausted - queue message for later delivery
-                        debug!("Send quota exhausted for {}, queuing message", s.client_id);
-                        if s.queue_message(publish) == QueueResult::DroppedOldest {
-                            warn!(client_id = %s.client_id, "message dropped - queue full (quota exhausted)");
-                            let _ = self.events.send(BrokerEvent::MessageDropped);
-                        }
-                        return Ok(());
-                    }
-                    // Check max_inflight limit
-                    if s.inflight_outgoing.len() >= s.max_inflight as usize {
-                        // Inflight limit reached - queue and restore quota
-                        s.increment_send_quota();
-                        debug!(
-                            "Inflight limit ({}) reached for {}, queuing message",
-                            s.max_inflight, s.client_id
-                        );
-                        if s.queue_message(publish) == QueueResult::DroppedOldest {
-                            warn!(client_id = %s.client_id, "message dropped - queue full (inflight limit)");
-                            let _ = self.events.send(BrokerEvent::MessageDropped);
-                        }
-                        return Ok(());
-                    }
+    /// Send a PUBLISH to the client, returning whether it was written
+    ///
+    /// Messages the client may not receive, shed for backpressure or too
+    /// large for it are dropped; QoS 1/2 ones without room on the client are
+    /// queued.
+    pub(crate) async fn send_publish(
+        &mut self,
+        session: &Arc<RwLock<Session>>,
+        mut publish: Publish,
+    ) -> Result<bool, ConnectionError> {
+        use crate::session::{InflightMessage, Qos2State};
+
+        if !self.deliver_allowed(session, &publish).await {
+            return Ok(false);
+        }
+        if !self.apply_backpressure(session, &publish).await? {
+            return Ok(false);
+        }
+
+        // Get max packet size from session
+        let max_packet_size = {
+            let s = session.read();
+            s.max_packet_size
+        };
+
+        // Per MQTT v5.0 spec [MQTT-4.9.0-2]: MUST NOT send QoS>0
+        // PUBLISH when send quota is 0
+        if publish.qos != QoS::AtMostOnce {
+            let blocked = {
+                let mut s = session.write();
+                if !s.decrement_send_quota() {
+                    // Quota exhausted - queue message for later delivery
+                    debug!("Send quota exhausted for {}, queuing message", s.client_id);
+                    Some("quota exhausted")
+                } else if s.inflight_outgoing.len() >= s.max_inflight as usize {
+                    // Inflight limit reached - queue and restore quota
+                    s.increment_send_quota();
+                    debug!(
+                        "Inflight limit ({}) reached for {}, queuing message",
+                        s.max_inflight, s.client_id
+                    );
+                    Some("inflight limit")
+                } else {
                     // Assign packet ID
                     if publish.packet_id.is_none() {
                         publish.packet_id = Some(s.next_packet_id());
@@ -359,44 +880,76 @@ where
                                     None
                                 },
                                 sent_at: Instant::now(),
+                                first_sent_at: Instant::now(),
                                 retry_count: 0,
                             },
                         );
                     }
+                    None
                 }
+            };
+            if let Some(reason) = blocked {
+                self.trace_held(&publish, reason);
+                let result = {
+                    let mut s = session.write();
+                    let result = s.queue_message(publish);
+                    if result.dropped() {
+                        warn!(client_id = %s.client_id, "message dropped - queue full ({})", reason);
+                        let _ = self.events.send(BrokerEvent::MessageDropped);
+                    }
+                    result
+                };
+                self.check_queue_overflow(result).await?;
+                return Ok(false);
+            }
+        }
 
-                self.write_buf.clear();
-                self.encoder
-                    .encode(&Packet::Publish(publish), &mut self.write_buf)
-                    .map_err(|e| ConnectionError::Protocol(e.into()))?;
-
-                // Per MQTT v5.0 spec [MQTT-3.1.2-24]: MUST NOT send packets
-                // exceeding client's Maximum Packet Size
-                if self.write_buf.len() > max_packet_size as usize {
-                    warn!(
-                        "Dropping PUBLISH: encoded size {} exceeds client max {}",
-                        self.write_buf.len(),
-                        max_packet_size
-                    );
-                    return Ok(());
-                }
+        // Topic alias (v5.0), after storing the inflight copy so a
+        // retransmission carries the full topic
+        let aliased = {
+            let mut s = session.write();
+            report_evictions(&mut publish, &mut s);
+            s.get_or_create_topic_alias(&publish.topic)
+        };
+        let mut topic = None;
+        if let Some((alias, new)) = aliased {
+            publish.properties.topic_alias = Some(alias);
+            if !new {
+                topic = Some(std::mem::take(&mut publish.topic));
+            }
+        }
 
-                let bytes_sent = self.write_buf.len();
-                self.stream.write_all(&self.write_buf).await?;
-                if let Some(ref metrics) = self.metrics {
-                    metrics.publish_sent(bytes_sent);
+        let bytes_sent = self.encode_publish_header(&publish)?;
+        let payload = publish.payload.clone();
+        let packet = Packet::Publish(publish);
+
+        // Per MQTT v5.0 spec [MQTT-3.1.2-24]: MUST NOT send packets
+        // exceeding client's Maximum Packet Size
+        if bytes_sent > max_packet_size as usize {
+            warn!(
+                "Dropping PUBLISH: encoded size {} exceeds client max {}",
+                bytes_sent, max_packet_size
+            );
+            if let Packet::Publish(mut publish) = packet {
+                match topic {
+                    Some(topic) => publish.topic = topic,
+                    // The client never learns the new alias
+                    None if aliased.is_some() => {
+                        session.write().server_topic_aliases.forget(&publish.topic)
+                    }
+                    None => {}
                 }
-                Ok(())
-            }
-            _ => {
-                self.write_buf.clear();
-                self.encoder
-                    .encode(&packet, &mut self.write_buf)
-                    .map_err(|e| ConnectionError::Protocol(e.into()))?;
-                self.stream.write_all(&self.write_buf).await?;
-                Ok(())
+                self.trace_held(&publish, "too large for client");
             }
+            return Ok(false);
         }
+
+        self.trace(TraceDirection::Out, &packet, bytes_sent);
+        self.write_publish(&payload).await?;
+        if let Some(ref metrics) = self.metrics {
+            metrics.publish_sent(bytes_sent);
+        }
+        Ok(true)
     }
 
     /// Handle an incoming packet
@@ -426,11 +979,7 @@ where
                     .await
             }
             Packet::PingReq => {
-                self.write_buf.clear();
-                self.encoder
-                    .encode(&Packet::PingResp, &mut self.write_buf)
-                    .map_err(|e| ConnectionError::Protocol(e.into()))?;
-                self.stream.write_all(&self.write_buf).await?;
+                self.write_packet(&Packet::PingResp).await?;
                 Ok(())
             }
             Packet::Disconnect(disconnect) => {
@@ -443,6 +992,28 @@ where
                 // - Reason 0x04 (DisconnectWithWill): will message MUST still be published
                 let publish_will =
                     disconnect.reason_code == crate::protocol::ReasonCode::DisconnectWithWill;
+
+                // A client may change its session expiry on the way out, but
+                // not revive a session it asked to end [MQTT-3.14.2-2]
+                if let Some(interval) = disconnect.properties.session_expiry_interval {
+                    let current = session.read().session_expiry_interval;
+                    if current == 0 && interval != 0 {
+                        self.send_disconnect(
+                            crate::protocol::ReasonCode::ProtocolError,
+                            Diagnostic::default(),
+                        )
+                        .await;
+                        return Err(ConnectionError::Protocol(
+                            crate::protocol::ProtocolError::ProtocolViolation(
+                                "session expiry set on DISCONNECT after 0 in CONNECT",
+                            ),
+                        ));
+                    }
+                    let max = self.config.max_session_expiry.map_or(u32::MAX, |max| {
+                        max.as_secs().min(u32::MAX as u64 - 1) as u32
+                    });
+                    session.write().session_expiry_interval = interval.min(max);
+                }
                 self.handle_disconnect(client_id, session, publish_will)
                     .await;
                 Err(ConnectionError::Shutdown)
@@ -461,6 +1032,9 @@ where
 
     /// Return buffers to the pool for reuse by other connections
     pub fn return_buffers(&mut self) {
+        if self.end_hibernation() {
+            return;
+        }
         let read_buf = std::mem::take(&mut self.read_buf);
         let write_buf = std::mem::take(&mut self.write_buf);
         buffer_pool::put_buffer(read_buf);
@@ -468,6 +1042,14 @@ where
     }
 }
 
+/// Next packet on the priority channel (never ready without one)
+async fn recv_priority(rx: &mut Option<mpsc::Receiver<Packet>>) -> Option<Packet> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
 /// Generate a random ID
 pub(crate) fn rand_id() -> u64 {
     use std::collections::hash_map::RandomState;
@@ -1,19 +1,29 @@
 //! PUBLISH packet handling and message routing
 
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use ahash::AHashMap;
 use parking_lot::RwLock;
 use smallvec::SmallVec;
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::time::timeout;
 use tracing::{debug, error, trace, warn};
 
-use super::{Connection, ConnectionError};
-use crate::broker::{BrokerEvent, RetainedMessage};
+use super::{Connection, ConnectionError, Diagnostic};
+use crate::broker::confirm::HeldPublish;
+use crate::broker::retained_feed::{self, RetainedChange};
+use crate::broker::routing_id;
+use crate::broker::{BrokerConfig, BrokerEvent, Limiters, RetainedMessage, TraceDirection};
+use crate::config::TopicSchemaMode;
+use crate::hooks::{AccessAction, Hooks, RateLimitDecision};
+use crate::metrics::Metrics;
 use crate::persistence::{PersistenceOp, StoredRetainedMessage};
-use crate::protocol::{Packet, Properties, PubAck, PubRec, Publish, QoS, ReasonCode};
-use crate::session::{QueueResult, Session};
+use crate::protocol::{
+    Disconnect, Packet, Properties, ProtocolVersion, PubAck, PubRec, Publish, QoS, ReasonCode,
+};
+use crate::remote::PublishOrigin;
+use crate::session::{Session, SessionStore};
 use crate::topic::validate_topic_name_with_max_levels;
 
 impl<S> Connection<S>
@@ -27,45 +37,73 @@ where
         session: &Arc<RwLock<Session>>,
         mut publish: Publish,
     ) -> Result<(), ConnectionError> {
-        // Validate topic name
-        if let Err(e) =
-            validate_topic_name_with_max_levels(&publish.topic, self.config.max_topic_levels)
-        {
-            warn!("Invalid topic name from {}: {}", client_id, e);
-            // For v5.0, send PUBACK/PUBREC with error
-            if publish.qos != QoS::AtMostOnce {
-                let packet_id = publish.packet_id.unwrap();
-                let response = if publish.qos == QoS::AtLeastOnce {
-                    Packet::PubAck(PubAck {
-                        packet_id,
-                        reason_code: ReasonCode::TopicNameInvalid,
-                        properties: Properties::default(),
-                    })
-                } else {
-                    Packet::PubRec(PubRec {
-                        packet_id,
-                        reason_code: ReasonCode::TopicNameInvalid,
-                        properties: Properties::default(),
-                    })
-                };
-                self.write_buf.clear();
-                self.encoder
-                    .encode(&response, &mut self.write_buf)
-                    .map_err(|e| ConnectionError::Protocol(e.into()))?;
-                self.stream.write_all(&self.write_buf).await?;
-            }
-            return Ok(());
+        // [MQTT-3.2.2-12] A QoS above the advertised Maximum QoS is a
+        // protocol error; earlier versions can't be told the cap, so the
+        // connection is just closed
+        let max_qos = self.max_qos();
+        if publish.qos > max_qos {
+            debug!(
+                "PUBLISH from {} with QoS {:?} above cap {:?}",
+                client_id, publish.qos, max_qos
+            );
+            self.send_disconnect(
+                ReasonCode::QoSNotSupported,
+                Diagnostic::limit("max_qos", max_qos as usize),
+            )
+            .await;
+            return Err(ConnectionError::Protocol(
+                crate::protocol::ProtocolError::ProtocolViolation("QoS not supported"),
+            ));
+        }
+
+        // [MQTT-3.3.1-8] Retain when Retain Available is 0 is a protocol error
+        if publish.retain && !self.config.retain_available {
+            debug!(
+                "Retained PUBLISH from {} where retain is unavailable",
+                client_id
+            );
+            self.send_disconnect(ReasonCode::RetainNotSupported, Diagnostic::default())
+                .await;
+            return Err(ConnectionError::Protocol(
+                crate::protocol::ProtocolError::ProtocolViolation("retain not supported"),
+            ));
         }
 
-        // Handle topic alias (v5.0)
-        if let Some(alias) = publish.properties.topic_alias {
+        self.check_publish_quota(client_id, &publish).await?;
+
+        // Handle topic alias (v5.0); the alias is the client's and doesn't
+        // go any further
+        if let Some(alias) = publish.properties.topic_alias.take() {
+            // [MQTT-3.3.2-9] Aliases above our Topic Alias Maximum are invalid
+            if alias > self.config.max_topic_alias {
+                debug!(
+                    "PUBLISH from {} with topic alias {} above maximum {}",
+                    client_id, alias, self.config.max_topic_alias
+                );
+                self.send_disconnect(
+                    ReasonCode::TopicAliasInvalid,
+                    Diagnostic::limit("topic_alias_maximum", self.config.max_topic_alias as usize),
+                )
+                .await;
+                return Err(ConnectionError::Protocol(
+                    crate::protocol::ProtocolError::ProtocolViolation("topic alias invalid"),
+                ));
+            }
             if publish.topic.is_empty() {
                 // Lookup alias
-                let s = session.read();
-                if let Some(topic) = s.resolve_topic_alias(alias) {
-                    publish.topic = topic.clone();
+                let topic = session.read().resolve_topic_alias(alias).cloned();
+                if let Some(topic) = topic {
+                    publish.topic = topic;
                 } else {
-                    // Invalid alias
+                    debug!(
+                        "PUBLISH from {} with unknown topic alias {}",
+                        client_id, alias
+                    );
+                    self.send_disconnect(
+                        ReasonCode::ProtocolError,
+                        Diagnostic::default().with_detail("unknown topic alias"),
+                    )
+                    .await;
                     return Err(ConnectionError::Protocol(
                         crate::protocol::ProtocolError::ProtocolViolation("unknown topic alias"),
                     ));
@@ -77,6 +115,96 @@ where
             }
         }
 
+        // Compressed payload from a bridge
+        if let Err((reason, diagnostic)) = self.take_compression(&mut publish) {
+            self.send_publish_error(&publish, reason, diagnostic)
+                .await?;
+            return Ok(());
+        }
+
+        // Delayed publish: from here on the message is for its target topic
+        let delay = match self.take_delay(&mut publish) {
+            Ok(delay) => delay,
+            Err((reason, diagnostic)) => {
+                self.send_publish_error(&publish, reason, diagnostic)
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        // Validate topic name
+        if let Err(e) =
+            validate_topic_name_with_max_levels(&publish.topic, self.config.max_topic_levels)
+        {
+            warn!("Invalid topic name from {}: {}", client_id, e);
+            // For v5.0, send PUBACK/PUBREC with error
+            let diagnostic = self.topic_diagnostic(&publish.topic, e);
+            self.send_publish_error(&publish, ReasonCode::TopicNameInvalid, diagnostic)
+                .await?;
+            return Ok(());
+        }
+
+        // Topics outside the declared namespace
+        if let Some(ref schema) = self.topic_schema {
+            if !schema.check(&publish.topic) {
+                if schema.mode() == TopicSchemaMode::Reject {
+                    if let Some(ref metrics) = self.metrics {
+                        metrics.message_dropped("topic_schema");
+                    }
+                    debug!(
+                        "PUBLISH from {} to {} matches no topic template",
+                        client_id, publish.topic
+                    );
+                    let diagnostic = Diagnostic::denied_by("topic_schema")
+                        .with_detail("topic matches no template");
+                    self.send_publish_error(&publish, ReasonCode::TopicNameInvalid, diagnostic)
+                        .await?;
+                    return Ok(());
+                }
+                warn!(
+                    "PUBLISH from {} to {} matches no topic template",
+                    client_id, publish.topic
+                );
+            }
+        }
+
+        // Transaction commands are handled here, not routed
+        if self.config.transaction.enabled {
+            if let Some(control) = self.config.transaction.control(&publish.topic) {
+                return self
+                    .handle_transaction_control(client_id, &publish, control)
+                    .await;
+            }
+        }
+
+        // Handover commands too
+        if self.config.handover.enabled {
+            if let Some(control) = self.config.handover.control(&publish.topic) {
+                return self
+                    .handle_handover_control(client_id, session, &publish, control)
+                    .await;
+            }
+        }
+
+        // And replay requests
+        if self.sequencer.is_some() {
+            if let Some(known) = self.config.sequence.replay.control(&publish.topic) {
+                return self
+                    .handle_replay_request(client_id, session, &publish, known)
+                    .await;
+            }
+        }
+
+        // Unpack batch frames (message batching extension)
+        if self.config.batch.enabled {
+            if let Some(prefix) = self.config.batch.prefix(&publish.topic) {
+                let prefix = prefix.to_string();
+                return self
+                    .handle_batch(client_id, session, publish, &prefix)
+                    .await;
+            }
+        }
+
         trace!(
             "PUBLISH from {} to {} (QoS {:?})",
             client_id,
@@ -87,12 +215,13 @@ where
         // Check ACL for publish permission
         let acl_result = self
             .hooks
-            .on_publish_check(
+            .on_publish_check_with_identity(
                 client_id,
                 self.username.as_deref(),
                 &publish.topic,
                 publish.qos,
                 publish.retain,
+                self.proxy_identity.as_deref(),
             )
             .await;
 
@@ -105,176 +234,447 @@ where
                     "PUBLISH denied for {} to topic {} (ACL)",
                     client_id, publish.topic
                 );
-                // For QoS > 0, send acknowledgment with error reason code
-                if publish.qos != QoS::AtMostOnce {
-                    let packet_id = publish.packet_id.unwrap();
-                    let response = if publish.qos == QoS::AtLeastOnce {
-                        Packet::PubAck(PubAck {
-                            packet_id,
-                            reason_code: ReasonCode::NotAuthorized,
-                            properties: Properties::default(),
-                        })
-                    } else {
-                        Packet::PubRec(PubRec {
-                            packet_id,
-                            reason_code: ReasonCode::NotAuthorized,
-                            properties: Properties::default(),
-                        })
-                    };
-                    self.write_buf.clear();
-                    self.encoder
-                        .encode(&response, &mut self.write_buf)
-                        .map_err(|e| ConnectionError::Protocol(e.into()))?;
-                    self.stream.write_all(&self.write_buf).await?;
+                if let Some(ref metrics) = self.metrics {
+                    metrics.message_dropped("not_authorized");
                 }
+                let diagnostic = self
+                    .access_diagnostic(
+                        client_id,
+                        AccessAction::Publish,
+                        &publish.topic,
+                        publish.qos,
+                        publish.retain,
+                    )
+                    .await;
+                // For QoS > 0, send acknowledgment with error reason code
+                self.send_publish_error(&publish, ReasonCode::NotAuthorized, diagnostic)
+                    .await?;
                 return Ok(());
             }
             Err(e) => {
                 error!("ACL check error for {}: {}", client_id, e);
                 // For QoS > 0, send error acknowledgment
-                if publish.qos != QoS::AtMostOnce {
-                    let packet_id = publish.packet_id.unwrap();
-                    let response = if publish.qos == QoS::AtLeastOnce {
-                        Packet::PubAck(PubAck {
-                            packet_id,
-                            reason_code: ReasonCode::UnspecifiedError,
-                            properties: Properties::default(),
-                        })
-                    } else {
-                        Packet::PubRec(PubRec {
-                            packet_id,
-                            reason_code: ReasonCode::UnspecifiedError,
-                            properties: Properties::default(),
-                        })
-                    };
-                    self.write_buf.clear();
-                    self.encoder
-                        .encode(&response, &mut self.write_buf)
-                        .map_err(|e| ConnectionError::Protocol(e.into()))?;
-                    self.stream.write_all(&self.write_buf).await?;
-                }
+                self.send_publish_error(
+                    &publish,
+                    ReasonCode::UnspecifiedError,
+                    Diagnostic::default(),
+                )
+                .await?;
                 return Ok(());
             }
         }
 
+        if let Err(diagnostic) = self.check_publish_rate(client_id, 1).await {
+            self.send_publish_error(&publish, ReasonCode::QuotaExceeded, diagnostic)
+                .await?;
+            return Ok(());
+        }
+
+        if let Err((reason, diagnostic)) = self.intercept_publish(client_id, &mut publish).await {
+            self.send_publish_error(&publish, reason, diagnostic)
+                .await?;
+            return Ok(());
+        }
+
+        // Clamp expiry per the publisher's TTL limits (before a QoS 2 copy is kept)
+        let retained_lifetime = self.apply_publish_ttl(client_id, &mut publish).await;
+        if publish.retain {
+            self.stamp_forwarded_for(&mut publish.properties);
+        }
+
+        // Held until its delay is up
+        if let Some(delay) = delay {
+            return self.hold_delayed(client_id, &publish, delay).await;
+        }
+
+        // Held until the client commits
+        if self.transaction.is_some() {
+            let message = (publish.clone(), retained_lifetime);
+            return self
+                .hold_in_transaction(client_id, &publish, [message])
+                .await;
+        }
+
+        // Slow down a publisher that outpaces its subscribers
+        let Some(publish) = self
+            .throttle_publisher(client_id, session, publish, retained_lifetime)
+            .await?
+        else {
+            return Ok(());
+        };
+        self.settle_publish(client_id, session, publish, retained_lifetime)
+            .await
+    }
+
+    /// Acknowledge and route an accepted PUBLISH
+    pub(crate) async fn settle_publish(
+        &mut self,
+        client_id: &Arc<str>,
+        session: &Arc<RwLock<Session>>,
+        publish: Publish,
+        retained_lifetime: Option<u32>,
+    ) -> Result<(), ConnectionError> {
         // Handle QoS
         match publish.qos {
             QoS::AtMostOnce => {
                 // No acknowledgment needed
             }
             QoS::AtLeastOnce => {
-                // Send PUBACK
-                let puback = PubAck::new(publish.packet_id.unwrap());
-                self.write_buf.clear();
-                self.encoder
-                    .encode(&Packet::PubAck(puback), &mut self.write_buf)
-                    .map_err(|e| ConnectionError::Protocol(e.into()))?;
-                self.stream.write_all(&self.write_buf).await?;
+                // Send PUBACK, unless a consumer has to confirm the message
+                // first (held before routing, so it can't miss it)
+                match self.confirmations.as_ref().and_then(|c| c.hold(&publish)) {
+                    Some(held) => self.puback_when_confirmed(&publish, held),
+                    None => self.send_puback(publish.packet_id.unwrap()).await?,
+                }
             }
             QoS::ExactlyOnce => {
+                self.check_inflight_quota(client_id, session, publish.packet_id)
+                    .await?;
                 // Store message and send PUBREC - message will be routed on PUBREL
-                let packet_id = publish.packet_id.unwrap();
-
-                // Check max_awaiting_rel limit
-                let limit_exceeded = {
-                    let s = session.read();
-                    s.inflight_incoming.len() >= s.max_awaiting_rel
-                };
-
-                if limit_exceeded {
-                    // Send PUBREC with QuotaExceeded - client should retry later
-                    debug!("Max awaiting PUBREL limit reached, rejecting QoS 2 publish");
-                    let pubrec = PubRec {
-                        packet_id,
-                        reason_code: ReasonCode::QuotaExceeded,
-                        properties: Properties::default(),
-                    };
-                    self.write_buf.clear();
-                    self.encoder
-                        .encode(&Packet::PubRec(pubrec), &mut self.write_buf)
-                        .map_err(|e| ConnectionError::Protocol(e.into()))?;
-                    self.stream.write_all(&self.write_buf).await?;
-                    return Ok(());
+                if self.await_release(session, &publish).await? {
+                    // For QoS 2, we route after PUBREL (not now)
+                    // Handle retained message now, but don't route to subscribers yet
+                    self.store_retained(client_id, &publish, retained_lifetime);
                 }
+                return Ok(());
+            }
+        }
 
-                {
-                    let mut s = session.write();
-                    s.inflight_incoming.insert(packet_id, publish.clone());
-                }
+        // Handle retained message
+        self.store_retained(client_id, &publish, retained_lifetime);
 
-                let pubrec = PubRec::new(packet_id);
-                self.write_buf.clear();
-                self.encoder
-                    .encode(&Packet::PubRec(pubrec), &mut self.write_buf)
-                    .map_err(|e| ConnectionError::Protocol(e.into()))?;
-                self.stream.write_all(&self.write_buf).await?;
-
-                // For QoS 2, we route after PUBREL (not now)
-                // Handle retained message now, but don't route to subscribers yet
-                if publish.retain && self.config.retain_available {
-                    if publish.payload.is_empty() {
-                        self.retained.remove(&publish.topic);
-                        if let Some(ref persistence) = self.persistence {
-                            persistence.write(PersistenceOp::DeleteRetained {
-                                topic: publish.topic.clone(),
-                            });
-                        }
-                    } else {
-                        let retained_msg = RetainedMessage {
-                            topic: publish.topic.clone(),
-                            payload: publish.payload.clone(),
-                            qos: publish.qos,
-                            properties: publish.properties.clone(),
-                            timestamp: Instant::now(),
-                        };
-                        self.retained
-                            .insert(publish.topic.clone(), retained_msg.clone());
-                        if let Some(ref persistence) = self.persistence {
-                            persistence.write(PersistenceOp::SetRetained {
-                                topic: publish.topic.clone(),
-                                message: StoredRetainedMessage::from(&retained_msg),
-                            });
-                        }
-                    }
+        // Route message to subscribers
+        self.route_message(client_id, &publish).await?;
+
+        Ok(())
+    }
+
+    /// Let the `on_publish` hooks rewrite or reject a message
+    ///
+    /// Returns the reason code and diagnostic to reject it with. Changes to
+    /// what's already settled with the client (QoS, retain, packet ID, DUP)
+    /// are undone.
+    pub(crate) async fn intercept_publish(
+        &self,
+        client_id: &Arc<str>,
+        publish: &mut Publish,
+    ) -> Result<(), (ReasonCode, Diagnostic)> {
+        let (qos, retain, packet_id, dup) =
+            (publish.qos, publish.retain, publish.packet_id, publish.dup);
+        let result = self
+            .hooks
+            .on_publish(client_id, self.username.as_deref(), publish)
+            .await;
+        publish.qos = qos;
+        publish.retain = retain;
+        publish.packet_id = packet_id;
+        publish.dup = dup;
+
+        match result {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!(
+                    "PUBLISH from {} to topic {} rejected by hook",
+                    client_id, publish.topic
+                );
+                if let Some(ref metrics) = self.metrics {
+                    metrics.message_dropped("not_authorized");
                 }
-                return Ok(());
+                return Err((ReasonCode::NotAuthorized, Diagnostic::denied_by("hook")));
+            }
+            Err(e) => {
+                error!("Publish hook error for {}: {}", client_id, e);
+                return Err((ReasonCode::UnspecifiedError, Diagnostic::default()));
             }
         }
+        validate_topic_name_with_max_levels(&publish.topic, self.config.max_topic_levels).map_err(
+            |e| {
+                warn!(
+                    "Publish hook gave {} an invalid topic name: {}",
+                    client_id, e
+                );
+                (
+                    ReasonCode::TopicNameInvalid,
+                    self.topic_diagnostic(&publish.topic, e),
+                )
+            },
+        )
+    }
 
-        // Handle retained message
-        if publish.retain && self.config.retain_available {
-            if publish.payload.is_empty() {
-                self.retained.remove(&publish.topic);
-                if let Some(ref persistence) = self.persistence {
-                    persistence.write(PersistenceOp::DeleteRetained {
-                        topic: publish.topic.clone(),
-                    });
+    /// Clamp a message's expiry interval to the publisher's TTL limits
+    ///
+    /// A message without an expiry gets the maximum. Returns the maximum
+    /// retained lifetime in seconds, if any. If the hook fails, the message
+    /// is left as published.
+    pub(crate) async fn apply_publish_ttl(
+        &self,
+        client_id: &Arc<str>,
+        publish: &mut Publish,
+    ) -> Option<u32> {
+        let ttl = match self
+            .hooks
+            .on_publish_ttl(client_id, self.username.as_deref(), &publish.topic)
+            .await
+        {
+            Ok(ttl) => ttl,
+            Err(e) => {
+                warn!("TTL lookup error for {}: {}", client_id, e);
+                return None;
+            }
+        };
+
+        let secs = |d: Duration| u32::try_from(d.as_secs()).unwrap_or(u32::MAX);
+        if let Some(max) = ttl.max_message_expiry.map(secs) {
+            let expiry = &mut publish.properties.message_expiry_interval;
+            *expiry = Some(expiry.map_or(max, |e| e.min(max)));
+        }
+        ttl.max_retained_lifetime.map(secs)
+    }
+
+    /// Take `count` messages from the client's publish rate limit
+    ///
+    /// Buckets are keyed by username, then verified TLS certificate CN (from
+    /// the local handshake or the PROXY header), then client ID, so reconnecting doesn't reset them.
+    pub(crate) async fn check_publish_rate(
+        &mut self,
+        client_id: &Arc<str>,
+        count: u32,
+    ) -> Result<(), Diagnostic> {
+        let identity = self.publish_rate_identity(client_id);
+        acquire_publish_rate(
+            &self.config,
+            self.hooks.as_ref(),
+            &self.sessions,
+            self.limiters.as_deref(),
+            self.metrics.as_deref(),
+            &identity,
+            count,
+        )
+        .await
+    }
+
+    /// Identity the client's publish rate limit is keyed by
+    pub(crate) fn publish_rate_identity(&mut self, client_id: &Arc<str>) -> Arc<str> {
+        self.rate_identity
+            .get_or_insert_with(|| {
+                let cert_cn = self
+                    .tls_info
+                    .as_ref()
+                    .filter(|tls| tls.client_cert_verified)
+                    .and_then(|tls| tls.client_cert_cn.as_deref());
+                match (self.username.as_deref(), cert_cn) {
+                    (Some(username), _) => format!("user:{}", username).into(),
+                    (None, Some(cn)) => format!("cn:{}", cn).into(),
+                    (None, None) => format!("client:{}", client_id).into(),
                 }
+            })
+            .clone()
+    }
+
+    /// Send DISCONNECT with a reason code (MQTT v5 only)
+    pub(crate) async fn send_disconnect(&mut self, code: ReasonCode, diagnostic: Diagnostic) {
+        if self.decoder.protocol_version() != Some(ProtocolVersion::V5) {
+            return;
+        }
+        let (reason_code, properties) = self.client_error(code, diagnostic, String::new);
+        let disconnect = Disconnect {
+            reason_code,
+            properties,
+        };
+        let packet = Packet::Disconnect(disconnect);
+        self.write_buf.clear();
+        if self.encoder.encode(&packet, &mut self.write_buf).is_ok() {
+            self.trace(TraceDirection::Out, &packet, self.write_buf.len());
+            let _ = self.stream.write_all(&self.write_buf).await;
+            let _ = self.stream.flush().await;
+        }
+    }
+
+    /// Send PUBACK for a QoS 1 publish
+    pub(crate) async fn send_puback(&mut self, packet_id: u16) -> Result<(), ConnectionError> {
+        let puback = PubAck::new(packet_id);
+        self.write_packet(&Packet::PubAck(puback)).await?;
+        Ok(())
+    }
+
+    /// Send a held message's PUBACK once it is confirmed, without holding
+    /// up the connection
+    ///
+    /// Unconfirmed messages are rejected with an error PUBACK, or on 3.1.1
+    /// (which has no error PUBACK) by closing the connection, so the client
+    /// sends them again. Subscribers may then see a message twice, as QoS 1
+    /// allows.
+    pub(crate) fn puback_when_confirmed(&self, publish: &Publish, held: HeldPublish) {
+        let packet_id = publish.packet_id.unwrap_or_default();
+        let code = ReasonCode::ImplementationError;
+        let reject = if self.decoder.protocol_version() == Some(ProtocolVersion::V5) {
+            let (reason_code, properties) = self.client_error(
+                code,
+                Diagnostic::default().with_detail("not confirmed"),
+                || format!("publish to '{}'", publish.topic),
+            );
+            Packet::PubAck(PubAck {
+                packet_id,
+                reason_code,
+                properties,
+            })
+        } else {
+            Packet::Disconnect(Disconnect {
+                reason_code: code,
+                ..Default::default()
+            })
+        };
+        let tx = self.packet_tx.clone();
+        tokio::spawn(async move {
+            let packet = if held.wait().await {
+                Packet::PubAck(PubAck::new(packet_id))
             } else {
-                let retained_msg = RetainedMessage {
-                    topic: publish.topic.clone(),
-                    payload: publish.payload.clone(),
-                    qos: publish.qos,
-                    properties: publish.properties.clone(),
-                    timestamp: Instant::now(),
-                };
-                self.retained
-                    .insert(publish.topic.clone(), retained_msg.clone());
-                if let Some(ref persistence) = self.persistence {
-                    persistence.write(PersistenceOp::SetRetained {
-                        topic: publish.topic.clone(),
-                        message: StoredRetainedMessage::from(&retained_msg),
-                    });
-                }
-            }
+                reject
+            };
+            let _ = tx.send(packet).await;
+        });
+    }
+
+    /// Store a QoS 2 publish until PUBREL and send PUBREC
+    ///
+    /// Returns false (after sending PUBREC with QuotaExceeded) if the
+    /// max_awaiting_rel limit has been reached.
+    pub(crate) async fn await_release(
+        &mut self,
+        session: &Arc<RwLock<Session>>,
+        publish: &Publish,
+    ) -> Result<bool, ConnectionError> {
+        let packet_id = publish.packet_id.unwrap();
+
+        // Check max_awaiting_rel limit
+        let (limit_exceeded, max_awaiting_rel) = {
+            let s = session.read();
+            (
+                s.inflight_incoming.len() >= s.max_awaiting_rel,
+                s.max_awaiting_rel,
+            )
+        };
+
+        if limit_exceeded {
+            // Send PUBREC with QuotaExceeded - client should retry later
+            debug!("Max awaiting PUBREL limit reached, rejecting QoS 2 publish");
+            let diagnostic = Diagnostic::limit("max_awaiting_rel", max_awaiting_rel);
+            self.send_publish_error(publish, ReasonCode::QuotaExceeded, diagnostic)
+                .await?;
+            return Ok(false);
         }
 
-        // Route message to subscribers
-        self.route_message(client_id, &publish).await?;
+        {
+            let mut s = session.write();
+            s.inflight_incoming.insert(packet_id, publish.clone());
+        }
+
+        let pubrec = PubRec::new(packet_id);
+        self.write_packet(&Packet::PubRec(pubrec)).await?;
+        Ok(true)
+    }
 
+    /// Send PUBACK/PUBREC with an error reason code (no-op for QoS 0)
+    pub(crate) async fn send_publish_error(
+        &mut self,
+        publish: &Publish,
+        reason_code: ReasonCode,
+        diagnostic: Diagnostic,
+    ) -> Result<(), ConnectionError> {
+        let (reason_code, properties) = self.client_error(reason_code, diagnostic, || {
+            format!("publish to '{}'", publish.topic)
+        });
+        self.send_publish_response(publish, reason_code, properties)
+            .await
+    }
+
+    /// Send PUBACK/PUBREC with the reason code and properties reported to
+    /// the client (no-op for QoS 0)
+    pub(crate) async fn send_publish_response(
+        &mut self,
+        publish: &Publish,
+        reason_code: ReasonCode,
+        properties: Properties,
+    ) -> Result<(), ConnectionError> {
+        let Some(packet_id) = publish.packet_id else {
+            return Ok(());
+        };
+        let response = if publish.qos == QoS::AtLeastOnce {
+            Packet::PubAck(PubAck {
+                packet_id,
+                reason_code,
+                properties,
+            })
+        } else {
+            Packet::PubRec(PubRec {
+                packet_id,
+                reason_code,
+                properties,
+            })
+        };
+        self.write_packet(&response).await?;
         Ok(())
     }
 
+    /// Store or clear a retained message (if retain is set and available)
+    ///
+    /// `lifetime` caps how long (in seconds) the retained copy is kept.
+    pub(crate) fn store_retained(
+        &self,
+        publisher: &Arc<str>,
+        publish: &Publish,
+        lifetime: Option<u32>,
+    ) {
+        if !publish.retain || !self.config.retain_available {
+            return;
+        }
+        if publish.payload.is_empty() {
+            if self.retained.remove(&publish.topic).is_some() {
+                retained_feed::announce(
+                    &self.events,
+                    &publish.topic,
+                    RetainedChange::Deleted,
+                    Some(publisher),
+                );
+            }
+            if let Some(ref persistence) = self.persistence {
+                persistence.write(PersistenceOp::DeleteRetained {
+                    topic: publish.topic.clone(),
+                });
+            }
+        } else {
+            let mut properties = publish.properties.clone();
+            if let Some(max) = lifetime {
+                let expiry = &mut properties.message_expiry_interval;
+                *expiry = Some(expiry.map_or(max, |e| e.min(max)));
+            }
+            let retained_msg = RetainedMessage {
+                topic: publish.topic.clone(),
+                payload: publish.payload.clone(),
+                qos: publish.qos,
+                properties,
+                timestamp: Instant::now(),
+                proxy_identity: self.proxy_identity.clone(),
+            };
+            let replaced = self
+                .retained
+                .insert(publish.topic.clone(), retained_msg.clone())
+                .is_some();
+            retained_feed::announce(
+                &self.events,
+                &publish.topic,
+                RetainedChange::stored(replaced),
+                Some(publisher),
+            );
+            if let Some(ref persistence) = self.persistence {
+                persistence.write(PersistenceOp::SetRetained {
+                    topic: publish.topic.clone(),
+                    message: StoredRetainedMessage::from(&retained_msg),
+                });
+            }
+        }
+    }
+
     /// Route a message to subscribers
     /// Uses AHashMap for O(n) deduplication regardless of subscriber count
     pub(crate) async fn route_message(
@@ -282,7 +682,23 @@ where
         sender_id: &Arc<str>,
         publish: &Publish,
     ) -> Result<(), ConnectionError> {
-        let matches = self.subscriptions.matches(&publish.topic);
+        // Every subscriber (and the published event) sees the same number
+        // and routing ID
+        let mut stamped = self
+            .sequencer
+            .as_ref()
+            .and_then(|s| s.stamped(publish, self.persistence.as_deref()));
+        if self.config.routing_ids {
+            let stamped = stamped.get_or_insert_with(|| publish.clone());
+            routing_id::assign(&mut stamped.properties);
+        }
+        let publish = stamped.as_ref().unwrap_or(publish);
+        let id = routing_id::routing_id(&publish.properties).unwrap_or_default();
+
+        let matches = self
+            .subscriptions
+            .matches_from(&publish.topic, Some(sender_id));
+        let priority = self.is_priority(&publish.topic);
 
         // Deduplicate by client_id, keeping highest QoS and collecting ALL subscription IDs
         struct ClientSub {
@@ -329,6 +745,14 @@ where
             }
         }
 
+        trace!(
+            routing_id = id,
+            topic = %publish.topic,
+            subscribers = client_subs.len(),
+            "Routing message from {}",
+            sender_id
+        );
+
         // Send to each client
         for (client_id, sub_info) in client_subs {
             let effective_qos = publish.qos.min(sub_info.qos);
@@ -349,17 +773,35 @@ where
                 outgoing.properties.subscription_identifiers.push(id);
             }
 
+            // A slow subscriber may make its publishers wait, though not
+            // with priority messages
+            if let Some(ref load) = self.listener_load {
+                if client_id != *sender_id && !priority && load.is_congested(&client_id) {
+                    load.wait_for(&client_id).await;
+                }
+            }
+
+            // Priority messages skip the ordinary queue when there's room
+            let outgoing = match self.priority.as_ref().filter(|_| priority) {
+                Some(lanes) => match lanes.try_send(&client_id, outgoing) {
+                    Ok(()) => continue,
+                    Err(outgoing) => outgoing,
+                },
+                None => outgoing,
+            };
+
             if let Some(sender) = self.connections.get(&client_id) {
                 if let Err(tokio::sync::mpsc::error::TrySendError::Full(_)) =
                     sender.try_send(Packet::Publish(outgoing))
                 {
-                    warn!(client_id = %client_id, "channel full - dropping message");
+                    warn!(client_id = %client_id, routing_id = id, "channel full - dropping message");
                 }
             } else {
                 // Client disconnected, queue message if persistent session
                 if let Some(session) = self.sessions.get(client_id.as_ref()) {
                     let mut s = session.write();
-                    if !s.clean_start && s.queue_message(outgoing) == QueueResult::DroppedOldest {
+                    if !s.clean_start && s.queue_message(outgoing).dropped() {
+                        debug!(client_id = %client_id, routing_id = id, "session queue full - message dropped");
                         let _ = self.events.send(BrokerEvent::MessageDropped);
                     }
                 }
@@ -372,8 +814,86 @@ where
             payload: publish.payload.clone(),
             qos: publish.qos,
             retain: publish.retain,
+            origin: Some(PublishOrigin {
+                client_id: sender_id.clone(),
+                addr: self.addr.clone(),
+            }),
+            user_properties: publish.properties.user_properties.clone(),
         });
 
         Ok(())
     }
 }
+
+/// Take `count` messages from `identity`'s publish rate limit
+///
+/// Identities listed in `publish_rate.external` are decided by the
+/// `on_rate_limit` hook; if it has no answer in time, the local bucket
+/// applies. Shared by MQTT connections and STOMP sessions.
+pub(crate) async fn acquire_publish_rate(
+    config: &BrokerConfig,
+    hooks: &dyn Hooks,
+    sessions: &SessionStore,
+    limiters: Option<&Limiters>,
+    metrics: Option<&Metrics>,
+    identity: &str,
+    count: u32,
+) -> Result<(), Diagnostic> {
+    let limiter = sessions.rate_limits();
+    let publish_rate = &config.publish_rate;
+    if limiter.is_none() && publish_rate.external.is_empty() {
+        return Ok(());
+    }
+
+    if publish_rate.is_external(identity) {
+        let decision = timeout(
+            publish_rate.external_timeout,
+            hooks.on_rate_limit(identity, count),
+        )
+        .await;
+        match decision {
+            Ok(Ok(Some(RateLimitDecision::Allow))) => return Ok(()),
+            Ok(Ok(Some(RateLimitDecision::Deny { retry_after }))) => {
+                debug!("PUBLISH rate limit exceeded for {} (external)", identity);
+                if let Some(metrics) = metrics {
+                    metrics.publish_rate_limited();
+                }
+                return Err(Diagnostic {
+                    retry_after,
+                    ..Diagnostic::denied_by("rate_limit_hook")
+                });
+            }
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => {
+                debug!("Rate limit hook failed for {}: {}", identity, e);
+                if let Some(metrics) = metrics {
+                    metrics.rate_limit_fallback();
+                }
+            }
+            Err(_) => {
+                debug!("Rate limit hook timed out for {}", identity);
+                if let Some(metrics) = metrics {
+                    metrics.rate_limit_fallback();
+                }
+            }
+        }
+    }
+
+    let Some(limiter) = limiter else {
+        return Ok(());
+    };
+    let boost = limiters.map_or(1.0, |l| l.boost(identity));
+    let Err(retry_after) = limiter.try_acquire(identity, count, boost) else {
+        return Ok(());
+    };
+
+    debug!("PUBLISH rate limit exceeded for {}", identity);
+    if let Some(metrics) = metrics {
+        metrics.publish_rate_limited();
+    }
+    let messages_per_sec = publish_rate.messages_per_sec as usize;
+    Err(Diagnostic {
+        retry_after: Some(retry_after),
+        ..Diagnostic::limit("publish_rate.messages_per_sec", messages_per_sec)
+    })
+}
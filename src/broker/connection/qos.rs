@@ -5,9 +5,10 @@ use std::time::Instant;
 
 use parking_lot::RwLock;
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
-use tracing::trace;
+use tracing::{info, trace, warn};
 
 use super::{Connection, ConnectionError};
+use crate::broker::TraceDirection;
 use crate::protocol::{Packet, PubAck, PubComp, PubRec, PubRel};
 use crate::session::{Qos2State, Session};
 
@@ -42,11 +43,7 @@ where
 
         // Send PUBREL
         let pubrel = PubRel::new(pubrec.packet_id);
-        self.write_buf.clear();
-        self.encoder
-            .encode(&Packet::PubRel(pubrel), &mut self.write_buf)
-            .map_err(|e| ConnectionError::Protocol(e.into()))?;
-        self.stream.write_all(&self.write_buf).await?;
+        self.write_packet(&Packet::PubRel(pubrel)).await?;
 
         Ok(())
     }
@@ -66,15 +63,11 @@ where
 
         // Send PUBCOMP
         let pubcomp = PubComp::new(pubrel.packet_id);
-        self.write_buf.clear();
-        self.encoder
-            .encode(&Packet::PubComp(pubcomp), &mut self.write_buf)
-            .map_err(|e| ConnectionError::Protocol(e.into()))?;
-        self.stream.write_all(&self.write_buf).await?;
+        self.write_packet(&Packet::PubComp(pubcomp)).await?;
 
         // Now route the message to subscribers (QoS 2 delivery complete)
         if let Some(publish) = publish {
-            self.route_message(client_id, &publish).await?;
+            self.route_released(client_id, &publish).await?;
         }
 
         Ok(())
@@ -103,7 +96,8 @@ where
         // Collect messages that need retry (to avoid holding lock while sending)
         let to_retry: Vec<_> = {
             let mut s = session.write();
-            s.inflight_outgoing
+            let to_retry: Vec<_> = s
+                .inflight_outgoing
                 .iter_mut()
                 .filter_map(|(packet_id, inflight)| {
                     if now.duration_since(inflight.sent_at) >= retry_interval {
@@ -116,8 +110,16 @@ where
                         None
                     }
                 })
-                .collect()
+                .collect();
+            s.ack_timeouts += to_retry.len() as u64;
+            to_retry
         };
+        if let Some(ref metrics) = self.metrics {
+            for (_, publish, _) in &to_retry {
+                metrics.message_retransmitted(publish.qos);
+            }
+        }
+        self.check_ack_stall(session);
 
         // Get max packet size
         let max_packet_size = {
@@ -133,25 +135,29 @@ where
                     publish.dup = true;
                     publish.packet_id = Some(packet_id);
 
+                    let packet = Packet::Publish(publish);
                     self.write_buf.clear();
                     self.encoder
-                        .encode(&Packet::Publish(publish), &mut self.write_buf)
+                        .encode(&packet, &mut self.write_buf)
                         .map_err(|e| ConnectionError::Protocol(e.into()))?;
 
                     if self.write_buf.len() <= max_packet_size as usize {
                         trace!("Retrying PUBLISH packet_id={}", packet_id);
+                        self.trace(TraceDirection::Out, &packet, self.write_buf.len());
                         self.stream.write_all(&self.write_buf).await?;
                     }
                 }
                 Some(Qos2State::WaitingPubComp) => {
                     // QoS 2 waiting for PUBCOMP: resend PUBREL
                     let pubrel = PubRel::new(packet_id);
+                    let packet = Packet::PubRel(pubrel);
                     self.write_buf.clear();
                     self.encoder
-                        .encode(&Packet::PubRel(pubrel), &mut self.write_buf)
+                        .encode(&packet, &mut self.write_buf)
                         .map_err(|e| ConnectionError::Protocol(e.into()))?;
 
                     trace!("Retrying PUBREL packet_id={}", packet_id);
+                    self.trace(TraceDirection::Out, &packet, self.write_buf.len());
                     self.stream.write_all(&self.write_buf).await?;
                 }
             }
@@ -159,4 +165,33 @@ where
 
         Ok(())
     }
+
+    /// Raise or clear the alarm for a client that keeps sending packets but
+    /// has left a message unacknowledged past `ack_alarm_after`
+    fn check_ack_stall(&mut self, session: &Arc<RwLock<Session>>) {
+        let Some(alarm_after) = self.config.ack_alarm_after else {
+            return;
+        };
+        let mut s = session.write();
+        let oldest = s.oldest_unacked();
+        let stalled =
+            oldest.is_some_and(|sent| sent.elapsed() >= alarm_after && s.last_activity > sent);
+        if stalled == s.ack_stalled {
+            return;
+        }
+        s.ack_stalled = stalled;
+        if stalled {
+            warn!(
+                "{} is connected but has not acknowledged {} messages, the oldest sent {:?} ago",
+                s.client_id,
+                s.inflight_outgoing.len(),
+                oldest.map(|sent| sent.elapsed()).unwrap_or_default()
+            );
+            if let Some(ref metrics) = self.metrics {
+                metrics.ack_stalled();
+            }
+        } else {
+            info!("{} is acknowledging messages again", s.client_id);
+        }
+    }
 }
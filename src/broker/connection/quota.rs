@@ -0,0 +1,262 @@
+//! Per-connection quota enforcement (`limits.quota`)
+//!
+//! Limits are resolved once the client is authenticated, from the broker
+//! defaults and the overrides for its listener and username. Unlike the
+//! identity-keyed publish rate limit, which rejects single messages, a
+//! quota violation ends the connection with DISCONNECT Quota exceeded.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::{Mutex, RwLock};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::debug;
+
+use super::{Connection, ConnectionError, Diagnostic};
+use crate::broker::limiters::{Limiters, QuotaBuckets, TokenBucket};
+use crate::config::QuotaLimits;
+use crate::protocol::{ProtocolError, Publish, ReasonCode};
+use crate::session::Session;
+
+/// Resolved limits of one connection and its rate buckets
+pub(crate) struct ClientQuota {
+    limits: QuotaLimits,
+    /// Buckets holding up to one second's worth, listed by the broker's
+    /// limiters (see [`crate::broker::Limiters`])
+    buckets: Arc<QuotaBuckets>,
+}
+
+/// A limit exceeded, with its value and how long until it would pass
+pub(crate) type Exceeded = (&'static str, usize, Option<Duration>);
+
+/// A limit's value if it is set and not 0 (unlimited)
+fn active<T: Copy + Default + PartialEq>(limit: Option<T>) -> Option<T> {
+    limit.filter(|v| *v != T::default())
+}
+
+impl ClientQuota {
+    /// Quota for the resolved limits, or `None` when nothing is limited
+    pub fn new(limits: QuotaLimits, identity: Arc<str>) -> Option<Self> {
+        let bucket = |rate: u64| Mutex::new(TokenBucket::new(rate as f64, rate as f64));
+        (!limits.is_unlimited()).then(|| Self {
+            buckets: Arc::new(QuotaBuckets {
+                identity,
+                messages: active(limits.messages_per_sec).map(|r| bucket(r as u64)),
+                bytes: active(limits.bytes_per_sec).map(bucket),
+            }),
+            limits,
+        })
+    }
+
+    /// List the rate buckets with the broker's limiters
+    pub fn register(&self, client_id: &Arc<str>, limiters: &Limiters) {
+        limiters.register_quota(client_id, &self.buckets);
+    }
+
+    /// Charge a PUBLISH against the quota, scaled by its identity's boost
+    pub fn charge_publish(
+        &self,
+        payload_len: usize,
+        limiters: Option<&Limiters>,
+    ) -> Option<Exceeded> {
+        let boost = limiters.map_or(1.0, |l| l.boost(&self.buckets.identity));
+        self.check_publish(payload_len, boost)
+    }
+
+    /// In-flight cap, if limited
+    pub fn max_inflight(&self) -> Option<u16> {
+        active(self.limits.max_inflight)
+    }
+
+    /// The limit a PUBLISH exceeds, taking it from the rate buckets
+    /// (with rate and size times `boost`) otherwise
+    ///
+    /// A rate limit comes with the time until the PUBLISH would have passed.
+    fn check_publish(&self, payload_len: usize, boost: f64) -> Option<Exceeded> {
+        if let Some(max) = active(self.limits.max_payload_size) {
+            if payload_len > max {
+                return Some(("quota.max_payload_size", max, None));
+            }
+        }
+        if let Some(ref bucket) = self.buckets.messages {
+            let mut bucket = bucket.lock();
+            if !bucket.try_take(1.0, boost) {
+                let wait = bucket.time_until(1.0, boost);
+                return Some(("quota.messages_per_sec", bucket.rate() as usize, Some(wait)));
+            }
+        }
+        if let Some(ref bucket) = self.buckets.bytes {
+            let mut bucket = bucket.lock();
+            // A payload larger than the bucket could never pass
+            let size = (payload_len as f64).min(bucket.rate() * boost);
+            if !bucket.try_take(size, boost) {
+                let wait = bucket.time_until(size, boost);
+                return Some(("quota.bytes_per_sec", bucket.rate() as usize, Some(wait)));
+            }
+        }
+        None
+    }
+}
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Resolve the client's quota for its listener, role and username
+    pub(crate) fn resolve_quota(&mut self, client_id: &Arc<str>, role: &QuotaLimits) {
+        let limits =
+            self.config
+                .quota
+                .resolve_with_role(self.listener, role, self.username.as_deref());
+        let identity = self.publish_rate_identity(client_id);
+        self.quota = ClientQuota::new(limits, identity);
+        if let (Some(quota), Some(limiters)) = (&self.quota, &self.limiters) {
+            quota.register(client_id, limiters);
+        }
+    }
+
+    /// Charge a PUBLISH against the client's quota
+    pub(crate) async fn check_publish_quota(
+        &mut self,
+        client_id: &Arc<str>,
+        publish: &Publish,
+    ) -> Result<(), ConnectionError> {
+        let exceeded = self.quota.as_ref().and_then(|quota| {
+            quota.charge_publish(publish.payload.len(), self.limiters.as_deref())
+        });
+        match exceeded {
+            Some((limit, value, retry_after)) => {
+                self.quota_exceeded(client_id, limit, value, retry_after)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Check an incoming QoS 2 message against the in-flight quota
+    ///
+    /// A retransmission of a message already awaiting PUBREL is not new.
+    pub(crate) async fn check_inflight_quota(
+        &mut self,
+        client_id: &Arc<str>,
+        session: &Arc<RwLock<Session>>,
+        packet_id: Option<u16>,
+    ) -> Result<(), ConnectionError> {
+        let Some(max) = self.quota.as_ref().and_then(ClientQuota::max_inflight) else {
+            return Ok(());
+        };
+        {
+            let s = session.read();
+            let retransmit = packet_id.is_some_and(|id| s.inflight_incoming.contains_key(&id));
+            if retransmit || s.inflight_incoming.len() < max as usize {
+                return Ok(());
+            }
+        }
+        self.quota_exceeded(client_id, "quota.max_inflight", max as usize, None)
+            .await
+    }
+
+    /// Check a new subscription against the subscription quota
+    pub(crate) async fn check_subscription_quota(
+        &mut self,
+        client_id: &Arc<str>,
+        session: &Arc<RwLock<Session>>,
+    ) -> Result<(), ConnectionError> {
+        let Some(max) = self
+            .quota
+            .as_ref()
+            .and_then(|q| active(q.limits.max_subscriptions))
+        else {
+            return Ok(());
+        };
+        if session.read().subscriptions.len() < max {
+            return Ok(());
+        }
+        self.quota_exceeded(client_id, "quota.max_subscriptions", max, None)
+            .await
+    }
+
+    /// Disconnect the client for exceeding `limit`, advising it to wait
+    /// `retry_after` if known
+    async fn quota_exceeded(
+        &mut self,
+        client_id: &Arc<str>,
+        limit: &'static str,
+        value: usize,
+        retry_after: Option<Duration>,
+    ) -> Result<(), ConnectionError> {
+        debug!("{} exceeded {} ({})", client_id, limit, value);
+        if let Some(ref metrics) = self.metrics {
+            metrics.quota_exceeded(limit.trim_start_matches("quota."));
+        }
+        let diagnostic = Diagnostic {
+            retry_after,
+            ..Diagnostic::limit(limit, value)
+        };
+        self.send_disconnect(ReasonCode::QuotaExceeded, diagnostic)
+            .await;
+        Err(ConnectionError::Protocol(ProtocolError::QuotaExceeded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlimited_has_no_quota() {
+        assert!(ClientQuota::new(QuotaLimits::default(), "client:c".into()).is_none());
+        assert!(ClientQuota::new(
+            QuotaLimits {
+                messages_per_sec: Some(0),
+                ..Default::default()
+            },
+            "client:c".into()
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_publish_limits() {
+        let quota = ClientQuota::new(
+            QuotaLimits {
+                messages_per_sec: Some(2),
+                max_payload_size: Some(10),
+                ..Default::default()
+            },
+            "client:c".into(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            quota.check_publish(11, 1.0),
+            Some(("quota.max_payload_size", 10, None))
+        );
+        assert_eq!(quota.check_publish(10, 1.0), None);
+        assert_eq!(quota.check_publish(10, 1.0), None);
+        // Another token comes in half a second
+        let Some(("quota.messages_per_sec", 2, Some(wait))) = quota.check_publish(10, 1.0) else {
+            panic!("expected the message rate to be exceeded");
+        };
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_byte_rate() {
+        let quota = ClientQuota::new(
+            QuotaLimits {
+                bytes_per_sec: Some(100),
+                ..Default::default()
+            },
+            "client:c".into(),
+        )
+        .unwrap();
+
+        assert_eq!(quota.check_publish(60, 1.0), None);
+        let Some(("quota.bytes_per_sec", 100, Some(wait))) = quota.check_publish(60, 1.0) else {
+            panic!("expected the byte rate to be exceeded");
+        };
+        assert!(wait > Duration::from_millis(100) && wait <= Duration::from_millis(200));
+        assert_eq!(quota.check_publish(40, 1.0), None);
+    }
+}
@@ -0,0 +1,178 @@
+//! Replay requests
+//!
+//! A subscriber that finds a sequence number skipped (see
+//! [`crate::broker::Sequencer`]) publishes to "{topic}/request" (see
+//! [`crate::config::ReplayConfig`]) with a JSON payload naming the topic and
+//! the numbers it missed: `{"topic": "sensors/1", "from": 41, "to": 43}`
+//! (without "to", up to the latest). The messages still kept in the
+//! broker's [`crate::broker::ReplayLog`] are sent to this client only, at
+//! QoS 0 with their original numbers, followed by a status sent to the
+//! request's Response Topic (with its Correlation Data) or else to
+//! "{topic}/response":
+//! `{"topic", "from", "to", "replayed", "unavailable", "more"}`. With
+//! "more", the range was cut at `max_messages`; the client asks again
+//! from after its last replayed number.
+//!
+//! A topic may be replayed if it matches `sequence.replay.topics` and the
+//! client may subscribe to it. Requests count against `max_requests` per
+//! `window` per client ID; refused ones are answered with an error
+//! acknowledgment (v5, QoS > 0).
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use parking_lot::RwLock;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::{debug, error};
+
+use super::{Connection, ConnectionError, Diagnostic};
+use crate::hooks::AccessAction;
+use crate::protocol::{Properties, Publish, QoS, ReasonCode};
+use crate::session::Session;
+
+/// A replay request payload
+#[derive(Debug, Deserialize)]
+struct ReplayRequest {
+    topic: String,
+    from: u64,
+    to: Option<u64>,
+}
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Handle a PUBLISH to a replay control topic
+    pub(crate) async fn handle_replay_request(
+        &mut self,
+        client_id: &Arc<str>,
+        session: &Arc<RwLock<Session>>,
+        publish: &Publish,
+        known: bool,
+    ) -> Result<(), ConnectionError> {
+        let Some(sequencer) = self.sequencer.clone() else {
+            return Ok(());
+        };
+        let Some(log) = sequencer.replay() else {
+            return Ok(());
+        };
+        let max_requests = self.config.sequence.replay.max_requests;
+        let refused = if !known {
+            Some((
+                ReasonCode::ImplementationError,
+                Diagnostic::default().with_detail("unknown replay command"),
+            ))
+        } else if !log.allow_request(client_id) {
+            Some((
+                ReasonCode::QuotaExceeded,
+                Diagnostic::limit("sequence.replay.max_requests", max_requests as usize),
+            ))
+        } else {
+            None
+        };
+        if let Some((reason, diagnostic)) = refused {
+            debug!(
+                "Replay request {} from {} refused",
+                publish.topic, client_id
+            );
+            return self.send_publish_error(publish, reason, diagnostic).await;
+        }
+
+        let request = match serde_json::from_slice::<ReplayRequest>(&publish.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!("Replay request from {}: {}", client_id, e);
+                let diagnostic = Diagnostic::default()
+                    .with_detail("expected {\"topic\", \"from\"} and optionally \"to\"");
+                return self
+                    .send_publish_error(publish, ReasonCode::ImplementationError, diagnostic)
+                    .await;
+            }
+        };
+        let denied_by = if !log.applies(&request.topic) {
+            Some(Diagnostic::denied_by("replay"))
+        } else if !self.replay_readable(client_id, &request.topic).await {
+            let diagnostic = self
+                .access_diagnostic(
+                    client_id,
+                    AccessAction::Subscribe,
+                    &request.topic,
+                    QoS::AtMostOnce,
+                    false,
+                )
+                .await;
+            Some(diagnostic)
+        } else {
+            None
+        };
+        if let Some(diagnostic) = denied_by {
+            debug!("Replay of {} denied for {}", request.topic, client_id);
+            return self
+                .send_publish_error(publish, ReasonCode::NotAuthorized, diagnostic)
+                .await;
+        }
+
+        let last = sequencer.last(&request.topic).unwrap_or(0);
+        let to = request.to.unwrap_or(last).min(last);
+        let replay = log.replay(&request.topic, request.from.max(1), to);
+        self.acknowledge_settled(publish).await?;
+
+        debug!(
+            "Replaying {} messages of {} ({}..={}) to {}",
+            replay.messages.len(),
+            request.topic,
+            request.from,
+            to,
+            client_id
+        );
+        let mut replayed = 0;
+        for mut message in replay.messages {
+            message.qos = QoS::AtMostOnce;
+            message.retain = false;
+            if self.send_publish(session, message).await? {
+                replayed += 1;
+            }
+        }
+
+        let status = serde_json::json!({
+            "topic": request.topic,
+            "from": request.from,
+            "to": to,
+            "replayed": replayed,
+            "unavailable": replay.unavailable,
+            "more": replay.more,
+        });
+        let reply = Publish {
+            qos: QoS::AtMostOnce,
+            topic: publish
+                .properties
+                .response_topic
+                .clone()
+                .unwrap_or_else(|| self.config.sequence.replay.response_topic()),
+            payload: Bytes::from(status.to_string()),
+            properties: Properties {
+                correlation_data: publish.properties.correlation_data.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        self.send_publish(session, reply).await?;
+        Ok(())
+    }
+
+    /// Whether the client may read the topic it asks to replay
+    async fn replay_readable(&self, client_id: &Arc<str>, topic: &str) -> bool {
+        match self
+            .hooks
+            .on_subscribe_check(client_id, self.username.as_deref(), topic, QoS::AtMostOnce)
+            .await
+        {
+            Ok(allowed) => allowed,
+            Err(e) => {
+                error!("ACL check error for {}: {}", client_id, e);
+                false
+            }
+        }
+    }
+}
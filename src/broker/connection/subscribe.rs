@@ -3,11 +3,13 @@
 use std::sync::Arc;
 
 use parking_lot::RwLock;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::{debug, error};
 
-use super::{Connection, ConnectionError};
-use crate::broker::BrokerEvent;
+use super::{Connection, ConnectionError, Diagnostic};
+use crate::broker::retained_cache::FrameKey;
+use crate::broker::{BrokerEvent, RetainedMessage, TraceDirection};
+use crate::hooks::AccessAction;
 use crate::protocol::{
     Packet, Properties, ProtocolVersion, Publish, QoS, ReasonCode, RetainHandling, SubAck,
     Subscribe, UnsubAck, Unsubscribe,
@@ -27,6 +29,8 @@ where
         subscribe: Subscribe,
     ) -> Result<(), ConnectionError> {
         let mut reason_codes = Vec::with_capacity(subscribe.subscriptions.len());
+        // Why each failed subscription was rejected (reported per error detail policy)
+        let mut failures: Vec<(ReasonCode, Diagnostic, &str)> = Vec::new();
         let _protocol_version = self
             .decoder
             .protocol_version()
@@ -39,15 +43,35 @@ where
             .first()
             .copied();
 
+        // A Subscription Identifier when they are unavailable is a protocol error
+        if sub_id.is_some() && !self.config.subscription_identifiers_available {
+            debug!(
+                "SUBSCRIBE from {} with identifier where they are unavailable",
+                client_id
+            );
+            self.send_disconnect(ReasonCode::SubIdNotSupported, Diagnostic::default())
+                .await;
+            return Err(ConnectionError::Protocol(
+                crate::protocol::ProtocolError::ProtocolViolation(
+                    "subscription identifiers not supported",
+                ),
+            ));
+        }
+
         // Track subscription info for retained message handling
         let mut sub_info: Vec<(QoS, bool, RetainHandling, String)> = Vec::new();
 
         for sub in &subscribe.subscriptions {
             // Validate topic filter
-            if validate_topic_filter_with_max_levels(&sub.filter, self.config.max_topic_levels)
-                .is_err()
+            if let Err(e) =
+                validate_topic_filter_with_max_levels(&sub.filter, self.config.max_topic_levels)
             {
                 reason_codes.push(ReasonCode::TopicFilterInvalid);
+                failures.push((
+                    ReasonCode::TopicFilterInvalid,
+                    self.topic_diagnostic(&sub.filter, e),
+                    &sub.filter,
+                ));
                 sub_info.push((
                     QoS::AtMostOnce,
                     false,
@@ -62,6 +86,28 @@ where
                 && (sub.filter.contains('+') || sub.filter.contains('#'))
             {
                 reason_codes.push(ReasonCode::WildcardSubsNotSupported);
+                failures.push((
+                    ReasonCode::WildcardSubsNotSupported,
+                    Diagnostic::default(),
+                    &sub.filter,
+                ));
+                sub_info.push((
+                    QoS::AtMostOnce,
+                    false,
+                    RetainHandling::DoNotSend,
+                    sub.filter.clone(),
+                ));
+                continue;
+            }
+
+            // Check shared subscription support
+            if !self.config.shared_subscriptions_available && sub.filter.starts_with("$share/") {
+                reason_codes.push(ReasonCode::SharedSubsNotSupported);
+                failures.push((
+                    ReasonCode::SharedSubsNotSupported,
+                    Diagnostic::default(),
+                    &sub.filter,
+                ));
                 sub_info.push((
                     QoS::AtMostOnce,
                     false,
@@ -91,7 +137,17 @@ where
                         "SUBSCRIBE denied for {} to filter {} (ACL)",
                         client_id, sub.filter
                     );
+                    let diagnostic = self
+                        .access_diagnostic(
+                            client_id,
+                            AccessAction::Subscribe,
+                            &sub.filter,
+                            sub.options.qos,
+                            false,
+                        )
+                        .await;
                     reason_codes.push(ReasonCode::NotAuthorized);
+                    failures.push((ReasonCode::NotAuthorized, diagnostic, &sub.filter));
                     sub_info.push((
                         QoS::AtMostOnce,
                         false,
@@ -103,6 +159,11 @@ where
                 Err(e) => {
                     error!("ACL check error for {}: {}", client_id, e);
                     reason_codes.push(ReasonCode::UnspecifiedError);
+                    failures.push((
+                        ReasonCode::UnspecifiedError,
+                        Diagnostic::default(),
+                        &sub.filter,
+                    ));
                     sub_info.push((
                         QoS::AtMostOnce,
                         false,
@@ -114,13 +175,16 @@ where
             }
 
             // Check QoS support
-            let granted_qos = sub.options.qos.min(self.config.max_qos);
+            let granted_qos = sub.options.qos.min(self.max_qos());
 
             // Check if subscription already existed (for retain_handling=1)
             let subscription_existed = {
                 let s = session.read();
                 s.subscriptions.contains_key(sub.filter.as_str())
             };
+            if !subscription_existed {
+                self.check_subscription_quota(client_id, session).await?;
+            }
 
             // Add subscription (SubscriptionStore handles $share parsing internally)
             self.subscriptions.subscribe(
@@ -168,18 +232,24 @@ where
             );
         }
 
-        // Send SUBACK
+        // Send SUBACK (errors reported per the listener's error detail policy)
+        let mut properties = Properties::default();
+        for (code, diagnostic, filter) in failures {
+            self.add_client_diagnostics(&mut properties, code, diagnostic, || {
+                format!("subscribe to '{}'", filter)
+            });
+        }
+        self.fit_client_diagnostics(&mut properties, reason_codes.len());
         let suback = SubAck {
             packet_id: subscribe.packet_id,
-            reason_codes: reason_codes.clone(),
-            properties: Properties::default(),
+            reason_codes: reason_codes
+                .iter()
+                .map(|&code| self.client_reason_code(code))
+                .collect(),
+            properties,
         };
 
-        self.write_buf.clear();
-        self.encoder
-            .encode(&Packet::SubAck(suback), &mut self.write_buf)
-            .map_err(|e| ConnectionError::Protocol(e.into()))?;
-        self.stream.write_all(&self.write_buf).await?;
+        self.write_packet(&Packet::SubAck(suback)).await?;
 
         // Send retained messages based on retain_handling option
         for ((granted_qos, existed, retain_handling, filter), reason) in
@@ -206,9 +276,13 @@ where
     }
 
     /// Send retained messages for a subscription
+    ///
+    /// A wildcard filter can match retained topics the client may not read,
+    /// so each topic is checked against the hooks as an exact filter, using
+    /// the rules in effect now rather than when the message was retained.
     pub(crate) async fn send_retained_messages(
         &mut self,
-        _client_id: &Arc<str>,
+        client_id: &Arc<str>,
         filter: &str,
         qos: QoS,
         session: &Arc<RwLock<Session>>,
@@ -222,7 +296,18 @@ where
             }
         }
 
+        // A filter without wildcards was checked by the SUBSCRIBE itself
+        let check_topics = filter.contains(['+', '#']);
+
         for retained in matching_retained {
+            if check_topics
+                && !self
+                    .retained_readable(client_id, &retained.topic, qos)
+                    .await
+            {
+                continue;
+            }
+
             // Calculate elapsed time for message expiry countdown
             let elapsed_secs = retained.timestamp.elapsed().as_secs() as u32;
 
@@ -257,17 +342,20 @@ where
                 publish.properties.subscription_identifiers.push(sub_id);
             }
 
+            if !self.deliver_allowed(session, &publish).await {
+                continue;
+            }
+
             if effective_qos != QoS::AtMostOnce {
                 let mut s = session.write();
                 publish.packet_id = Some(s.next_packet_id());
             }
 
-            self.write_buf.clear();
-            self.encoder
-                .encode(&Packet::Publish(publish), &mut self.write_buf)
-                .map_err(|e| ConnectionError::Protocol(e.into()))?;
-            let bytes_sent = self.write_buf.len();
-            self.stream.write_all(&self.write_buf).await?;
+            let bytes_sent = self.encode_retained(&retained, &publish, subscription_id)?;
+            let payload = publish.payload.clone();
+            let packet = Packet::Publish(publish);
+            self.trace(TraceDirection::Out, &packet, bytes_sent);
+            self.write_publish(&payload).await?;
             if let Some(ref metrics) = self.metrics {
                 metrics.publish_sent(bytes_sent);
             }
@@ -276,6 +364,65 @@ where
         Ok(())
     }
 
+    /// Encode a retained message's PUBLISH into `write_buf` up to its
+    /// payload, from the frame cache when it has the frame
+    ///
+    /// Returns the size of the whole packet.
+    fn encode_retained(
+        &mut self,
+        retained: &RetainedMessage,
+        publish: &Publish,
+        subscription_id: Option<u32>,
+    ) -> Result<usize, ConnectionError> {
+        let version = self.encoder.protocol_version();
+        let Some(frames) = self
+            .retained_frames
+            .clone()
+            .filter(|frames| frames.cacheable(retained, version))
+        else {
+            return self.encode_publish_header(publish);
+        };
+
+        let key = FrameKey {
+            version,
+            qos: publish.qos,
+            subscription_id,
+        };
+        self.write_buf.clear();
+        if frames.header(retained, key, publish.packet_id, &mut self.write_buf) {
+            if let Some(ref metrics) = self.metrics {
+                metrics.retained_frame_hit();
+            }
+            return Ok(self.write_buf.len() + publish.payload.len());
+        }
+
+        let bytes = self.encode_publish_header(publish)?;
+        frames.insert(retained, key, &self.write_buf);
+        if let Some(ref metrics) = self.metrics {
+            metrics.retained_frame_miss();
+        }
+        Ok(bytes)
+    }
+
+    /// Whether the client may read a retained topic
+    async fn retained_readable(&self, client_id: &Arc<str>, topic: &str, qos: QoS) -> bool {
+        match self
+            .hooks
+            .on_subscribe_check(client_id, self.username.as_deref(), topic, qos)
+            .await
+        {
+            Ok(true) => true,
+            Ok(false) => {
+                debug!("Retained {} withheld from {} (ACL)", topic, client_id);
+                false
+            }
+            Err(e) => {
+                error!("ACL check error for {}: {}", client_id, e);
+                false
+            }
+        }
+    }
+
     /// Handle UNSUBSCRIBE packet
     pub(crate) async fn handle_unsubscribe(
         &mut self,
@@ -324,11 +471,7 @@ where
             properties: Properties::default(),
         };
 
-        self.write_buf.clear();
-        self.encoder
-            .encode(&Packet::UnsubAck(unsuback), &mut self.write_buf)
-            .map_err(|e| ConnectionError::Protocol(e.into()))?;
-        self.stream.write_all(&self.write_buf).await?;
+        self.write_packet(&Packet::UnsubAck(unsuback)).await?;
 
         Ok(())
     }
@@ -0,0 +1,139 @@
+//! Publish transactions
+//!
+//! Between PUBLISHes to "{topic}/begin" and "{topic}/commit" (see
+//! [`crate::config::TransactionConfig`]), a client's messages are
+//! acknowledged but held back. The commit routes them all, in order; an
+//! abort or a disconnect discards them, so subscribers never see part of a
+//! transaction. This works with any protocol version: the commands are
+//! ordinary PUBLISHes, answered with the usual acknowledgment or, on
+//! failure, an error one (v5, QoS > 0).
+//!
+//! Held QoS 2 messages are settled by the transaction: their PUBREC goes
+//! out without the message being stored, so the PUBREL routes nothing.
+//! A message that is rejected (ACL, rate limit, hooks) is not part of the
+//! transaction; a client that needs all or nothing aborts when one is.
+
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::debug;
+
+use super::{Connection, ConnectionError, Diagnostic};
+use crate::config::TransactionControl;
+use crate::protocol::{Packet, PubRec, Publish, QoS, ReasonCode};
+
+/// Messages held by an open transaction, with their retained lifetimes
+pub(crate) type Transaction = Vec<(Publish, Option<u32>)>;
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Handle a PUBLISH to a transaction control topic
+    pub(crate) async fn handle_transaction_control(
+        &mut self,
+        client_id: &Arc<str>,
+        publish: &Publish,
+        control: Option<TransactionControl>,
+    ) -> Result<(), ConnectionError> {
+        let result = match (control, self.transaction.take()) {
+            (Some(TransactionControl::Begin), None) => {
+                self.transaction = Some(Vec::new());
+                Ok(None)
+            }
+            (Some(TransactionControl::Begin), Some(open)) => {
+                self.transaction = Some(open);
+                Err("transaction already open")
+            }
+            (Some(TransactionControl::Commit), Some(messages)) => Ok(Some(messages)),
+            (Some(TransactionControl::Abort), Some(messages)) => {
+                debug!(
+                    "Transaction of {} aborted ({} messages)",
+                    client_id,
+                    messages.len()
+                );
+                Ok(None)
+            }
+            (Some(_), None) => Err("no open transaction"),
+            (None, open) => {
+                self.transaction = open;
+                Err("unknown transaction command")
+            }
+        };
+
+        let messages = match result {
+            Ok(messages) => messages,
+            Err(detail) => {
+                debug!(
+                    "Transaction command {} from {}: {}",
+                    publish.topic, client_id, detail
+                );
+                let diagnostic = Diagnostic::default().with_detail(detail);
+                return self
+                    .send_publish_error(publish, ReasonCode::ImplementationError, diagnostic)
+                    .await;
+            }
+        };
+        self.acknowledge_settled(publish).await?;
+
+        if let Some(messages) = messages {
+            debug!(
+                "Transaction of {} committed ({} messages)",
+                client_id,
+                messages.len()
+            );
+            for (message, lifetime) in &messages {
+                self.store_retained(client_id, message, *lifetime);
+                self.route_message(client_id, message).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add accepted messages to the open transaction, acknowledging
+    /// `publish` (which carried them)
+    ///
+    /// A transaction growing past `transaction.max_messages` is aborted and
+    /// the messages are rejected.
+    pub(crate) async fn hold_in_transaction(
+        &mut self,
+        client_id: &Arc<str>,
+        publish: &Publish,
+        messages: impl IntoIterator<Item = (Publish, Option<u32>)>,
+    ) -> Result<(), ConnectionError> {
+        let max_messages = self.config.transaction.max_messages;
+        let Some(transaction) = self.transaction.as_mut() else {
+            return Ok(());
+        };
+        transaction.extend(messages);
+        if max_messages > 0 && transaction.len() > max_messages {
+            debug!(
+                "Transaction of {} aborted: over {} messages",
+                client_id, max_messages
+            );
+            self.transaction = None;
+            let diagnostic = Diagnostic::limit("transaction.max_messages", max_messages);
+            return self
+                .send_publish_error(publish, ReasonCode::QuotaExceeded, diagnostic)
+                .await;
+        }
+        self.acknowledge_settled(publish).await
+    }
+
+    /// Acknowledge a PUBLISH the broker is done with (QoS 2: the PUBREL
+    /// has nothing to release)
+    pub(crate) async fn acknowledge_settled(
+        &mut self,
+        publish: &Publish,
+    ) -> Result<(), ConnectionError> {
+        match (publish.qos, publish.packet_id) {
+            (QoS::AtLeastOnce, Some(packet_id)) => self.send_puback(packet_id).await,
+            (QoS::ExactlyOnce, Some(packet_id)) => {
+                self.write_packet(&Packet::PubRec(PubRec::new(packet_id)))
+                    .await?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Delayed Publish
+//!
+//! With `delayed.enabled`, a message published to
+//! "$delayed/{seconds}/{topic}" (or to "{topic}" with the user property
+//! named by `delayed.user_property`) is checked like any other message for
+//! "{topic}" (ACL, rate limits, hooks) and acknowledged at once, but is held
+//! here and only routed when its delay is up. Useful for retries with
+//! backoff: a device command that failed is simply published again with a
+//! delay.
+//!
+//! Messages wait in a timer queue ordered by deadline; one task sleeps until
+//! the earliest is due. With persistence enabled they are stored until
+//! delivered, so a restart doesn't lose them (those due during the downtime
+//! go out right after it). A message expiry interval counts from when the
+//! message was published, so a message whose expiry is shorter than its
+//! delay is never delivered.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+use tracing::{debug, trace};
+
+use super::{Broker, BrokerEvent};
+use crate::persistence::{PersistenceOp, StoredDelayedMessage};
+use crate::protocol::Publish;
+
+/// A message held until its deadline
+#[derive(Debug, Clone)]
+pub struct DelayedMessage {
+    /// Client that published the message
+    pub publisher: Arc<str>,
+    /// The message, for its target topic
+    pub publish: Publish,
+    /// Unix timestamp in milliseconds when the message was received
+    pub received_ms: u64,
+    /// Unix timestamp in milliseconds when the message is due
+    pub deadline_ms: u64,
+}
+
+impl DelayedMessage {
+    /// A message received now, due after `delay`
+    pub fn new(publisher: Arc<str>, mut publish: Publish, delay: Duration) -> Self {
+        publish.packet_id = None;
+        publish.dup = false;
+        let received_ms = now_ms();
+        Self {
+            publisher,
+            publish,
+            received_ms,
+            deadline_ms: received_ms.saturating_add(delay.as_millis() as u64),
+        }
+    }
+
+    /// The message as it goes out at its deadline, None if it has expired
+    /// while held
+    fn release(mut self) -> Option<Publish> {
+        if let Some(expiry) = self.publish.properties.message_expiry_interval {
+            let held = self.deadline_ms.saturating_sub(self.received_ms) / 1000;
+            let remaining = u64::from(expiry).checked_sub(held).filter(|r| *r > 0)?;
+            self.publish.properties.message_expiry_interval = Some(remaining as u32);
+        }
+        Some(self.publish)
+    }
+}
+
+/// Delayed messages in order of deadline
+#[derive(Default)]
+pub struct DelayedQueue {
+    /// Messages keyed by deadline and ID
+    queue: Mutex<BTreeMap<(u64, u64), DelayedMessage>>,
+    next_id: AtomicU64,
+    /// Woken when a message is scheduled
+    scheduled: Notify,
+}
+
+impl DelayedQueue {
+    /// Number of messages held
+    pub fn len(&self) -> usize {
+        self.queue.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().is_empty()
+    }
+
+    /// Hold `message` until its deadline, unless `max` (0 = unlimited) are
+    /// held already; returns its ID
+    pub fn schedule(&self, message: DelayedMessage, max: usize) -> Option<u64> {
+        let mut queue = self.queue.lock();
+        if max > 0 && queue.len() >= max {
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        queue.insert((message.deadline_ms, id), message);
+        drop(queue);
+        self.scheduled.notify_one();
+        Some(id)
+    }
+
+    /// Hold a message loaded from persistence under its stored ID
+    pub fn restore(&self, id: u64, message: DelayedMessage) {
+        self.next_id.fetch_max(id + 1, Ordering::Relaxed);
+        self.queue.lock().insert((message.deadline_ms, id), message);
+        self.scheduled.notify_one();
+    }
+
+    /// Take the messages due by `now_ms`, earliest first
+    pub fn take_due(&self, now_ms: u64) -> Vec<(u64, DelayedMessage)> {
+        let mut queue = self.queue.lock();
+        let later = queue.split_off(&(now_ms.saturating_add(1), 0));
+        std::mem::replace(&mut *queue, later)
+            .into_iter()
+            .map(|((_, id), message)| (id, message))
+            .collect()
+    }
+
+    /// Deadline of the earliest message
+    fn next_deadline(&self) -> Option<u64> {
+        self.queue
+            .lock()
+            .first_key_value()
+            .map(|((deadline, _), _)| *deadline)
+    }
+}
+
+impl Broker {
+    /// Route delayed messages as they become due until shutdown
+    pub(crate) fn spawn_delayed_delivery(&self) {
+        let broker = self.clone_for_sys_topics();
+        let queue = self.delayed.clone();
+        let mut shutdown_rx = self.shutdown.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let wait = queue
+                    .next_deadline()
+                    .map_or(Duration::from_secs(3600), |deadline| {
+                        Duration::from_millis(deadline.saturating_sub(now_ms()))
+                    });
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = queue.scheduled.notified() => continue,
+                    _ = shutdown_rx.recv() => break,
+                }
+
+                for (id, message) in queue.take_due(now_ms()) {
+                    if let Some(ref persistence) = broker.persistence {
+                        persistence.write(PersistenceOp::DeleteDelayed { id });
+                    }
+                    let publisher = message.publisher.clone();
+                    let Some(publish) = message.release() else {
+                        debug!("Delayed message from {} expired while held", publisher);
+                        continue;
+                    };
+                    trace!(
+                        "Delayed message from {} due for {}",
+                        publisher,
+                        publish.topic
+                    );
+                    let user_properties = broker.publish_with_properties(
+                        publish.topic.clone(),
+                        publish.payload.clone(),
+                        publish.qos,
+                        publish.retain,
+                        publish.properties.clone(),
+                    );
+                    let _ = broker.events.send(BrokerEvent::MessagePublished {
+                        topic: publish.topic.clone(),
+                        payload: publish.payload.clone(),
+                        qos: publish.qos,
+                        retain: publish.retain,
+                        origin: None,
+                        user_properties,
+                    });
+                    broker
+                        .hooks
+                        .on_message_published(&publish.topic, &publish.payload, publish.qos)
+                        .await;
+                }
+            }
+        });
+    }
+}
+
+/// Hold a message in `queue` (and store it, with persistence)
+///
+/// Returns false if `max` (0 = unlimited) messages are held already.
+pub(crate) fn schedule(
+    queue: &DelayedQueue,
+    persistence: Option<&crate::persistence::PersistenceManager>,
+    message: DelayedMessage,
+    max: usize,
+) -> bool {
+    let stored = persistence.map(|_| StoredDelayedMessage::from(&message));
+    let Some(id) = queue.schedule(message, max) else {
+        return false;
+    };
+    if let (Some(persistence), Some(message)) = (persistence, stored) {
+        persistence.write(PersistenceOp::SetDelayed { id, message });
+    }
+    true
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn message(topic: &str, delay_secs: u64) -> DelayedMessage {
+        let publish = Publish {
+            qos: crate::protocol::QoS::AtLeastOnce,
+            topic: topic.to_string(),
+            packet_id: Some(7),
+            payload: Bytes::from_static(b"x"),
+            ..Default::default()
+        };
+        DelayedMessage::new("device-1".into(), publish, Duration::from_secs(delay_secs))
+    }
+
+    #[test]
+    fn test_delayed_queue() {
+        let queue = DelayedQueue::default();
+        let later = message("cmd/later", 60);
+        let soon = message("cmd/soon", 5);
+        let now = soon.received_ms;
+        assert_eq!(queue.schedule(later, 2), Some(0));
+        assert_eq!(queue.schedule(soon, 2), Some(1));
+        assert_eq!(queue.schedule(message("cmd/full", 1), 2), None);
+        assert_eq!(queue.next_deadline(), Some(now + 5_000));
+
+        assert!(queue.take_due(now + 4_999).is_empty());
+        let due = queue.take_due(now + 5_000);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, 1);
+        assert_eq!(due[0].1.publish.topic, "cmd/soon");
+        assert_eq!(queue.len(), 1);
+
+        // Restored IDs aren't handed out again
+        queue.restore(41, message("cmd/restored", 0));
+        assert_eq!(queue.schedule(message("cmd/new", 10), 0), Some(42));
+    }
+
+    #[test]
+    fn test_delayed_expiry() {
+        let mut short = message("cmd/a", 30);
+        short.publish.properties.message_expiry_interval = Some(10);
+        assert!(short.release().is_none());
+
+        let mut long = message("cmd/b", 30);
+        long.publish.properties.message_expiry_interval = Some(100);
+        let publish = long.release().unwrap();
+        assert_eq!(publish.properties.message_expiry_interval, Some(70));
+        assert_eq!(publish.packet_id, None);
+    }
+}
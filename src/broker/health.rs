@@ -0,0 +1,251 @@
+//! Health and Readiness Probes
+//!
+//! `/healthz` answers 200 as long as the broker process serves requests.
+//! `/readyz` answers 200 only when the broker can take sessions, and 503
+//! otherwise, with the checks as JSON:
+//!
+//! ```json
+//! {"ready": false, "checks": [
+//!   {"name": "listeners", "ok": true},
+//!   {"name": "storage", "ok": false, "detail": "last write failed"}]}
+//! ```
+//!
+//! - `listeners` - every configured client listener is bound
+//! - `shutdown` - the broker is not shutting down
+//! - `connections` - fewer clients than `max_connections` are connected
+//! - `storage` - with persistence, the backend takes writes
+//! - `cluster` - `health.min_cluster_peers` peers are connected (fewer
+//!   while fewer are known)
+//!
+//! Both are served on the metrics listener and on `health.bind`.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+use super::{Broker, Listener};
+use crate::remote::{RemotePeer, RemotePeerStatus};
+
+/// Result of the readiness checks
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    /// Whether every check passed
+    pub ready: bool,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+/// One readiness check
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    /// Why the check failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ReadinessCheck {
+    fn new(name: &'static str, failure: Option<String>) -> Self {
+        Self {
+            name,
+            ok: failure.is_none(),
+            detail: failure,
+        }
+    }
+}
+
+impl Broker {
+    /// Check whether the broker can take sessions
+    pub fn readiness(&self) -> Readiness {
+        let config = self.live_config.read();
+        let mut checks = Vec::new();
+
+        let wanted = if self.is_observer() {
+            Vec::new()
+        } else {
+            Listener::configured(&config)
+        };
+        let unbound: Vec<String> = {
+            let running = self.listeners.lock();
+            wanted
+                .iter()
+                .filter(|l| !running.contains_key(l))
+                .map(|l| l.to_string())
+                .collect()
+        };
+        checks.push(ReadinessCheck::new(
+            "listeners",
+            (!unbound.is_empty()).then(|| format!("not bound: {}", unbound.join(", "))),
+        ));
+
+        checks.push(ReadinessCheck::new(
+            "shutdown",
+            self.is_draining().then(|| "shutting down".to_string()),
+        ));
+
+        let connections = self.connections.len();
+        checks.push(ReadinessCheck::new(
+            "connections",
+            (connections >= config.max_connections).then(|| {
+                format!(
+                    "{} connected, max_connections is {}",
+                    connections, config.max_connections
+                )
+            }),
+        ));
+
+        if let Some(ref persistence) = self.persistence {
+            checks.push(ReadinessCheck::new(
+                "storage",
+                (!persistence.is_healthy()).then(|| "last write failed".to_string()),
+            ));
+        }
+
+        if let Some(ref cluster) = self.cluster_manager {
+            let peers = cluster.peers();
+            let connected = peers
+                .iter()
+                .filter(|peer| peer.status() == RemotePeerStatus::Connected)
+                .count();
+            let required = config.health.min_cluster_peers.min(peers.len());
+            checks.push(ReadinessCheck::new(
+                "cluster",
+                (connected < required).then(|| {
+                    format!(
+                        "{} of {} peers connected, {} required",
+                        connected,
+                        peers.len(),
+                        required
+                    )
+                }),
+            ));
+        }
+
+        Readiness {
+            ready: checks.iter().all(|check| check.ok),
+            checks,
+        }
+    }
+}
+
+/// `/healthz` response
+pub fn health_response() -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Full::new(Bytes::from("OK")))
+        .unwrap()
+}
+
+/// `/readyz` response: 200 when ready, 503 otherwise
+pub fn readiness_response(broker: &Broker) -> Response<Full<Bytes>> {
+    let readiness = broker.readiness();
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = serde_json::to_vec(&readiness).unwrap_or_default();
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+/// HTTP listener serving only the probes (`health.bind`)
+pub struct HealthServer {
+    broker: Arc<Broker>,
+    addr: SocketAddr,
+}
+
+impl HealthServer {
+    pub fn new(broker: Arc<Broker>, addr: SocketAddr) -> Self {
+        Self { broker, addr }
+    }
+
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let listener = TcpListener::bind(self.addr).await?;
+        info!("Health probes listening on http://{}/readyz", self.addr);
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let io = TokioIo::new(stream);
+            let broker = self.broker.clone();
+
+            tokio::spawn(async move {
+                let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                    let broker = broker.clone();
+                    async move {
+                        Ok::<_, Infallible>(match req.uri().path() {
+                            "/health" | "/healthz" => health_response(),
+                            "/ready" | "/readyz" => readiness_response(&broker),
+                            _ => Response::builder()
+                                .status(StatusCode::NOT_FOUND)
+                                .body(Full::new(Bytes::from("Not Found")))
+                                .unwrap(),
+                        })
+                    }
+                });
+
+                if let Err(err) = http1::Builder::new().serve_connection(io, service).await {
+                    error!("Error serving health probe connection: {:?}", err);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::BrokerConfig;
+
+    #[test]
+    fn test_readiness() {
+        let config = BrokerConfig {
+            bind_addr: "127.0.0.1:0".parse().unwrap(),
+            max_connections: 1,
+            ..Default::default()
+        };
+        let broker = Broker::new(config);
+
+        // Not bound yet
+        let readiness = broker.readiness();
+        assert!(!readiness.ready);
+        let listeners = &readiness.checks[0];
+        assert_eq!(listeners.name, "listeners");
+        assert_eq!(
+            listeners.detail.as_deref(),
+            Some("not bound: tcp://127.0.0.1:0")
+        );
+        assert!(readiness.checks.iter().all(|c| c.name != "storage"));
+
+        let (stop, _) = tokio::sync::broadcast::channel(1);
+        broker
+            .listeners
+            .lock()
+            .insert(Listener::Tcp("127.0.0.1:0".parse().unwrap()), stop);
+        assert!(broker.readiness().ready);
+
+        // Full
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        broker.connections.insert("a".into(), tx);
+        let readiness = broker.readiness();
+        assert!(!readiness.ready);
+        assert!(!readiness.checks[2].ok);
+        broker.connections.clear();
+
+        broker.draining.send_replace(true);
+        assert!(!broker.readiness().ready);
+    }
+}
@@ -0,0 +1,520 @@
+//! Limiter Levels and Overrides
+//!
+//! The broker holds clients to three kinds of token bucket:
+//!
+//! - `connect`: new connections per second from one IP
+//!   (`limits.connection_limit`), keyed `ip:<address>`
+//! - `publish`: PUBLISH messages per second (`limits.publish_rate`), keyed
+//!   `user:<username>`, `cn:<certificate CN>` or `client:<client ID>`
+//! - `quota.messages_per_sec`, `quota.bytes_per_sec`: each connection's
+//!   message and bandwidth quota (`limits.quota`), keyed by the same
+//!   identity as the publish rate
+//!
+//! During incident recovery (a fleet reconnecting, a backlog to drain) the
+//! admin API (`/api/v1/limits`) shows the current bucket levels per
+//! identity, refills an identity's buckets, and boosts its limits for a
+//! while: a boost multiplies the rate and capacity of all the identity's
+//! buckets (and, for an IP, its connection cap) until it expires.
+//!
+//! A maintenance window may relax the connection limits of every IP at
+//! once (`reconnect_rate_factor`); its factor multiplies with any boost of
+//! the IP.
+//!
+//! Only the `publish` buckets are persisted and exchanged with cluster
+//! peers (see [`crate::session::RateLimiter`]). The `connect` buckets
+//! live in the flapping detector and the quota buckets on their
+//! connection, in memory on this node: they start full after a restart,
+//! and a client reconnecting to another node gets fresh ones there. Boosts
+//! live in memory only and aren't shared with peers either. A reset
+//! refills the publish bucket on this node and persists the refilled
+//! level, but peers keep the level they had.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tracing::warn;
+
+use super::Broker;
+
+/// Largest boost factor accepted
+pub const MAX_BOOST_FACTOR: f64 = 1000.0;
+
+/// Longest boost accepted
+pub const MAX_BOOST_DURATION: Duration = Duration::from_secs(24 * 3600);
+
+/// Token bucket refilled continuously at `rate` per second up to `capacity`
+pub(crate) struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket
+    pub fn new(rate: f64, capacity: f64) -> Self {
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Configured rate per second
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Refill for the time passed, with rate and capacity times `boost`
+    fn refill(&mut self, boost: f64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate * boost).min(self.capacity * boost);
+        self.updated = now;
+    }
+
+    /// Take `count` tokens if there are enough
+    pub fn try_take(&mut self, count: f64, boost: f64) -> bool {
+        self.refill(boost);
+        if self.tokens < count {
+            return false;
+        }
+        self.tokens -= count;
+        true
+    }
+
+    /// Time until `count` tokens are available, with rate times `boost`
+    pub fn time_until(&mut self, count: f64, boost: f64) -> Duration {
+        self.refill(boost);
+        Duration::from_secs_f64(((count - self.tokens) / (self.rate * boost)).max(0.0))
+    }
+
+    /// Tokens and capacity right now
+    fn level(&mut self, boost: f64) -> (f64, f64) {
+        self.refill(boost);
+        (self.tokens, self.capacity * boost)
+    }
+
+    fn fill(&mut self, boost: f64) {
+        self.tokens = self.capacity * boost;
+        self.updated = Instant::now();
+    }
+}
+
+/// A connection's quota buckets, shared with the registry so they can be
+/// listed and refilled
+pub(crate) struct QuotaBuckets {
+    /// Identity the buckets count against (see the module docs)
+    pub identity: Arc<str>,
+    pub messages: Option<Mutex<TokenBucket>>,
+    pub bytes: Option<Mutex<TokenBucket>>,
+}
+
+impl QuotaBuckets {
+    fn buckets(&self) -> impl Iterator<Item = (&'static str, &Mutex<TokenBucket>)> {
+        let messages = self
+            .messages
+            .as_ref()
+            .map(|b| ("quota.messages_per_sec", b));
+        let bytes = self.bytes.as_ref().map(|b| ("quota.bytes_per_sec", b));
+        messages.into_iter().chain(bytes)
+    }
+}
+
+/// A bucket's level as listed by `GET /api/v1/limits`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BucketLevel {
+    /// "connect", "publish", "quota.messages_per_sec" or
+    /// "quota.bytes_per_sec"
+    pub limiter: &'static str,
+    pub identity: String,
+    /// Connection the bucket belongs to (quota buckets only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    pub tokens: f64,
+    /// Tokens a full bucket holds, boost included
+    pub capacity: f64,
+    /// Tokens added per second, boost included
+    pub rate: f64,
+}
+
+/// A boost in force, as listed by `GET /api/v1/limits`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BoostInfo {
+    pub identity: String,
+    pub factor: f64,
+    /// Unix timestamp in seconds when the boost ends
+    pub expires_at: u64,
+}
+
+/// `GET /api/v1/limits` response
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LimitLevels {
+    pub buckets: Vec<BucketLevel>,
+    pub boosts: Vec<BoostInfo>,
+}
+
+/// Why a boost was refused
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoostError {
+    Factor(f64),
+    Duration(Duration),
+}
+
+impl std::fmt::Display for BoostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BoostError::Factor(factor) => write!(
+                f,
+                "boost factor {} must be above 1 and at most {}",
+                factor, MAX_BOOST_FACTOR
+            ),
+            BoostError::Duration(duration) => write!(
+                f,
+                "boost duration {:?} must be above 0 and at most {:?}",
+                duration, MAX_BOOST_DURATION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BoostError {}
+
+struct Boost {
+    factor: f64,
+    until: Instant,
+    expires_at: u64,
+}
+
+/// Boosts and the quota buckets of connected clients (see the module docs)
+#[derive(Default)]
+pub struct Limiters {
+    boosts: DashMap<Arc<str>, Boost>,
+    /// Quota buckets by client ID; entries of closed connections are
+    /// dropped as they are found
+    quotas: DashMap<Arc<str>, Weak<QuotaBuckets>>,
+    /// Connection limit multiplier of all IPs during a maintenance window
+    /// (`f64` bits, 0 when there is none)
+    reconnect_boost: AtomicU64,
+}
+
+impl Limiters {
+    /// Rate and capacity multiplier of `identity` (1 without a boost)
+    pub fn boost(&self, identity: &str) -> f64 {
+        if self.boosts.is_empty() {
+            return 1.0;
+        }
+        let Some(boost) = self.boosts.get(identity) else {
+            return 1.0;
+        };
+        if boost.until > Instant::now() {
+            return boost.factor;
+        }
+        drop(boost);
+        self.boosts
+            .remove_if(identity, |_, boost| boost.until <= Instant::now());
+        1.0
+    }
+
+    /// Multiplier of the `ip:<address>` identity of `ip`, times the
+    /// reconnect boost
+    pub fn boost_ip(&self, ip: IpAddr) -> f64 {
+        let reconnect = self.reconnect_boost();
+        if self.boosts.is_empty() {
+            return reconnect;
+        }
+        reconnect * self.boost(&format!("ip:{}", ip.to_canonical()))
+    }
+
+    /// Connection limit multiplier applied to every IP (1 when unset)
+    pub fn reconnect_boost(&self) -> f64 {
+        match self.reconnect_boost.load(Ordering::Relaxed) {
+            0 => 1.0,
+            bits => f64::from_bits(bits),
+        }
+    }
+
+    /// Multiply every IP's connection limits by `factor` until set back
+    /// to 1
+    pub fn set_reconnect_boost(&self, factor: f64) {
+        let bits = if factor > 1.0 { factor.to_bits() } else { 0 };
+        self.reconnect_boost.store(bits, Ordering::Relaxed);
+    }
+
+    /// Multiply `identity`'s limits by `factor` for `duration`, replacing
+    /// any boost it has
+    pub fn set_boost(
+        &self,
+        identity: &str,
+        factor: f64,
+        duration: Duration,
+    ) -> Result<BoostInfo, BoostError> {
+        if !(factor > 1.0 && factor <= MAX_BOOST_FACTOR) {
+            return Err(BoostError::Factor(factor));
+        }
+        if duration.is_zero() || duration > MAX_BOOST_DURATION {
+            return Err(BoostError::Duration(duration));
+        }
+        let expires_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .saturating_add(duration)
+            .as_secs();
+        self.boosts.insert(
+            identity.into(),
+            Boost {
+                factor,
+                until: Instant::now() + duration,
+                expires_at,
+            },
+        );
+        Ok(BoostInfo {
+            identity: identity.to_string(),
+            factor,
+            expires_at,
+        })
+    }
+
+    /// End `identity`'s boost; false if it had none
+    pub fn clear_boost(&self, identity: &str) -> bool {
+        self.boosts.remove(identity).is_some()
+    }
+
+    /// Boosts in force, by identity
+    pub fn boosts(&self) -> Vec<BoostInfo> {
+        let now = Instant::now();
+        self.boosts.retain(|_, boost| boost.until > now);
+        let mut boosts: Vec<_> = self
+            .boosts
+            .iter()
+            .map(|entry| BoostInfo {
+                identity: entry.key().to_string(),
+                factor: entry.factor,
+                expires_at: entry.expires_at,
+            })
+            .collect();
+        boosts.sort_by(|a, b| a.identity.cmp(&b.identity));
+        boosts
+    }
+
+    /// List a connection's quota buckets
+    pub(crate) fn register_quota(&self, client_id: &Arc<str>, buckets: &Arc<QuotaBuckets>) {
+        self.quotas
+            .insert(client_id.clone(), Arc::downgrade(buckets));
+    }
+
+    /// Levels of the quota buckets of connected clients
+    fn quota_levels(&self) -> Vec<BucketLevel> {
+        let mut levels = Vec::new();
+        self.quotas.retain(|client_id, buckets| {
+            let Some(buckets) = buckets.upgrade() else {
+                return false;
+            };
+            let boost = self.boost(&buckets.identity);
+            for (limiter, bucket) in buckets.buckets() {
+                let mut bucket = bucket.lock();
+                let (tokens, capacity) = bucket.level(boost);
+                levels.push(BucketLevel {
+                    limiter,
+                    identity: buckets.identity.to_string(),
+                    client_id: Some(client_id.to_string()),
+                    tokens,
+                    capacity,
+                    rate: bucket.rate() * boost,
+                });
+            }
+            true
+        });
+        levels
+    }
+
+    /// Refill the quota buckets of `identity`'s connections
+    fn fill_quotas(&self, identity: &str) -> usize {
+        let boost = self.boost(identity);
+        let mut filled = 0;
+        for entry in self.quotas.iter() {
+            let Some(buckets) = entry.upgrade().filter(|b| &*b.identity == identity) else {
+                continue;
+            };
+            for (_, bucket) in buckets.buckets() {
+                bucket.lock().fill(boost);
+                filled += 1;
+            }
+        }
+        filled
+    }
+}
+
+/// The IP of an `ip:<address>` identity
+fn identity_ip(identity: &str) -> Option<IpAddr> {
+    identity.strip_prefix("ip:")?.parse().ok()
+}
+
+impl Broker {
+    /// Limiter levels and boosts (see the module docs)
+    pub fn limiters(&self) -> &Arc<Limiters> {
+        &self.limiters
+    }
+
+    /// Levels of all buckets, or only `identity`'s
+    pub fn limit_levels(&self, identity: Option<&str>) -> LimitLevels {
+        let mut buckets = Vec::new();
+        if let Some(ref detector) = self.flapping_detector {
+            for (ip, tokens, capacity, rate) in detector.bucket_levels() {
+                buckets.push(BucketLevel {
+                    limiter: "connect",
+                    identity: format!("ip:{}", ip),
+                    client_id: None,
+                    tokens,
+                    capacity,
+                    rate,
+                });
+            }
+        }
+        if let Some(limiter) = self.sessions.rate_limits() {
+            for (identity, tokens, capacity, rate) in limiter.levels(|i| self.limiters.boost(i)) {
+                buckets.push(BucketLevel {
+                    limiter: "publish",
+                    identity: identity.to_string(),
+                    client_id: None,
+                    tokens,
+                    capacity,
+                    rate,
+                });
+            }
+        }
+        buckets.extend(self.limiters.quota_levels());
+        buckets.retain(|b| identity.is_none_or(|identity| b.identity == identity));
+        buckets.sort_by(|a, b| {
+            (&a.identity, a.limiter, &a.client_id).cmp(&(&b.identity, b.limiter, &b.client_id))
+        });
+
+        let mut boosts = self.limiters.boosts();
+        boosts.retain(|b| identity.is_none_or(|identity| b.identity == identity));
+        LimitLevels { buckets, boosts }
+    }
+
+    /// Refill all of `identity`'s buckets (and lift a temporary ban of an
+    /// IP), logging it as done `by` the caller; returns how many were
+    /// refilled
+    pub fn reset_limits(&self, identity: &str, by: &str) -> usize {
+        let mut reset = self.limiters.fill_quotas(identity);
+        let boost = self.limiters.boost(identity);
+        if let Some(limiter) = self.sessions.rate_limits() {
+            if limiter.fill(identity, boost) {
+                reset += 1;
+            }
+        }
+        if let (Some(detector), Some(ip)) = (&self.flapping_detector, identity_ip(identity)) {
+            if detector.reset_ip(ip) {
+                reset += 1;
+            }
+        }
+        warn!("Limits of {} reset by {} ({} buckets)", identity, by, reset);
+        reset
+    }
+
+    /// Boost `identity`'s limits by `factor` for `duration`, logging it as
+    /// done `by` the caller
+    pub fn boost_limits(
+        &self,
+        identity: &str,
+        factor: f64,
+        duration: Duration,
+        by: &str,
+    ) -> Result<BoostInfo, BoostError> {
+        let boost = self.limiters.set_boost(identity, factor, duration)?;
+        warn!(
+            "Limits of {} boosted {}x for {:?} by {}",
+            identity, factor, duration, by
+        );
+        Ok(boost)
+    }
+
+    /// End `identity`'s boost, logging it as done `by` the caller
+    pub fn clear_limit_boost(&self, identity: &str, by: &str) -> bool {
+        let cleared = self.limiters.clear_boost(identity);
+        if cleared {
+            warn!("Limit boost of {} ended by {}", identity, by);
+        }
+        cleared
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_boost() {
+        let mut bucket = TokenBucket::new(1.0, 2.0);
+        assert!(bucket.try_take(2.0, 1.0));
+        assert!(!bucket.try_take(1.0, 1.0));
+        bucket.fill(3.0);
+        assert_eq!(bucket.level(3.0).1, 6.0);
+        assert!(bucket.try_take(6.0, 3.0));
+    }
+
+    #[test]
+    fn test_boosts() {
+        let limiters = Limiters::default();
+        assert_eq!(limiters.boost("user:a"), 1.0);
+        assert!(limiters
+            .set_boost("user:a", 1.0, Duration::from_secs(60))
+            .is_err());
+        assert!(limiters.set_boost("user:a", 2.0, Duration::ZERO).is_err());
+
+        let info = limiters
+            .set_boost("user:a", 4.0, Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(info.factor, 4.0);
+        assert_eq!(limiters.boost("user:a"), 4.0);
+        assert_eq!(limiters.boost("user:b"), 1.0);
+        assert_eq!(limiters.boosts().len(), 1);
+        assert!(limiters.clear_boost("user:a"));
+        assert_eq!(limiters.boost("user:a"), 1.0);
+
+        limiters
+            .set_boost("user:b", 2.0, Duration::from_millis(1))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(limiters.boost("user:b"), 1.0);
+        assert!(limiters.boosts().is_empty());
+    }
+
+    #[test]
+    fn test_quota_registry() {
+        let limiters = Limiters::default();
+        let buckets = Arc::new(QuotaBuckets {
+            identity: "user:a".into(),
+            messages: Some(Mutex::new(TokenBucket::new(10.0, 10.0))),
+            bytes: None,
+        });
+        limiters.register_quota(&"c1".into(), &buckets);
+        assert!(buckets
+            .messages
+            .as_ref()
+            .unwrap()
+            .lock()
+            .try_take(10.0, 1.0));
+
+        let levels = limiters.quota_levels();
+        assert_eq!(levels.len(), 1);
+        assert_eq!(levels[0].client_id.as_deref(), Some("c1"));
+        assert!(levels[0].tokens < 1.0);
+
+        assert_eq!(limiters.fill_quotas("user:b"), 0);
+        assert_eq!(limiters.fill_quotas("user:a"), 1);
+        assert_eq!(limiters.quota_levels()[0].tokens, 10.0);
+
+        // Closed connections drop out
+        drop(buckets);
+        assert!(limiters.quota_levels().is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+//! Client Listener Identity
+//!
+//! Each configured client-facing endpoint is a [`Listener`]. On a config
+//! reload the broker compares the listeners it runs against the ones the
+//! new config asks for: the ones that are gone stop (closing their
+//! connections), the new ones start, and the rest keep running untouched.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use super::BrokerConfig;
+
+/// A client-facing listener, identified by its kind and address
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Listener {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+    Ws(SocketAddr),
+    Wss(SocketAddr),
+    Tls(SocketAddr),
+    Quic(SocketAddr),
+}
+
+impl Listener {
+    /// Listeners a configuration asks for, in start order
+    ///
+    /// TLS, WebSocket/TLS and QUIC listeners need `tls_config`; without it they
+    /// are left out (config validation reports that case).
+    pub fn configured(config: &BrokerConfig) -> Vec<Listener> {
+        let mut listeners: Vec<Listener> = std::iter::once(config.bind_addr)
+            .chain(config.extra_bind_addrs.iter().copied())
+            .map(Listener::Tcp)
+            .collect();
+        listeners.extend(config.unix_bind_path.clone().map(Listener::Unix));
+        listeners.extend(config.ws_bind_addr.map(Listener::Ws));
+        if config.tls_config.is_some() {
+            listeners.extend(config.wss_bind_addr.map(Listener::Wss));
+            listeners.extend(config.tls_bind_addr.map(Listener::Tls));
+            listeners.extend(config.quic_bind_addr.map(Listener::Quic));
+        }
+        listeners
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(addr) => write!(f, "tcp://{}", addr),
+            Listener::Unix(path) => write!(f, "unix://{}", path.display()),
+            Listener::Ws(addr) => write!(f, "ws://{}", addr),
+            Listener::Wss(addr) => write!(f, "wss://{}", addr),
+            Listener::Tls(addr) => write!(f, "tls://{}", addr),
+            Listener::Quic(addr) => write!(f, "quic://{}", addr),
+        }
+    }
+}
+
+/// Listeners started and stopped by [`Broker::apply_config`](super::Broker::apply_config)
+#[derive(Debug, Clone, Default)]
+pub struct ListenerChanges {
+    pub started: Vec<String>,
+    pub stopped: Vec<String>,
+    /// Listeners that failed to start, with the error
+    pub errors: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::TlsConfig;
+    use std::time::Duration;
+
+    #[test]
+    fn test_configured_listeners() {
+        let mut config = BrokerConfig {
+            bind_addr: "127.0.0.1:1883".parse().unwrap(),
+            extra_bind_addrs: vec!["[::1]:1883".parse().unwrap()],
+            ws_bind_addr: Some("127.0.0.1:8080".parse().unwrap()),
+            tls_bind_addr: Some("127.0.0.1:8883".parse().unwrap()),
+            quic_bind_addr: Some("127.0.0.1:14567".parse().unwrap()),
+            ..Default::default()
+        };
+        let names = |config: &BrokerConfig| -> Vec<String> {
+            Listener::configured(config)
+                .iter()
+                .map(|l| l.to_string())
+                .collect()
+        };
+        // No certificate, no TLS listener
+        assert_eq!(
+            names(&config),
+            [
+                "tcp://127.0.0.1:1883",
+                "tcp://[::1]:1883",
+                "ws://127.0.0.1:8080"
+            ]
+        );
+
+        config.tls_config = Some(TlsConfig {
+            cert_path: "cert.pem".to_string(),
+            key_path: "key.pem".to_string(),
+            ca_cert_path: None,
+            require_client_cert: false,
+            handshake_threads: 0,
+            handshake_queue_size: 0,
+            handshake_timeout: Duration::from_secs(10),
+        });
+        assert_eq!(
+            names(&config)[3..],
+            ["tls://127.0.0.1:8883", "quic://127.0.0.1:14567"]
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Local Publish Interface
+//!
+//! In-process producers (rules, connectors) publish through a
+//! `LocalPublisher` rather than a client connection. Listener limits
+//! (publish rate, quotas) are meant for network clients and don't apply,
+//! but ACLs do: the publisher has its own client ID and optional username,
+//! so `%c`/`%u` namespaces confine it like any client.
+//!
+//! Every message published this way carries its hop count in the
+//! `x-vibemq-hops` user property. A producer reacting to a delivered
+//! message passes it as the cause of its own publish, which then counts one
+//! hop more; chains longer than `max_local_hops` are dropped, so rules
+//! republishing each other's output can't loop forever. The reaction also
+//! keeps its cause's routing ID, if it has one (see `routing_id`).
+
+use std::fmt;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use super::routing_id::{routing_id, ROUTING_ID_PROPERTY};
+use super::{Broker, BrokerEvent};
+use crate::metrics::Metrics;
+use crate::protocol::{Packet, Properties, Publish, QoS};
+use crate::topic::{validate_topic_filter, validate_topic_name_with_max_levels, Subscription};
+
+/// User property carrying a message's local publish hop count
+pub const LOCAL_HOPS_PROPERTY: &str = "x-vibemq-hops";
+
+/// Messages buffered for a local subscription before new ones are dropped
+const LOCAL_SUBSCRIPTION_CAPACITY: usize = 1024;
+
+/// Hop count of a message (0 if it didn't come through a `LocalPublisher`)
+pub fn local_hops(properties: &Properties) -> u8 {
+    properties
+        .user_properties
+        .iter()
+        .find(|(key, _)| key == LOCAL_HOPS_PROPERTY)
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0)
+}
+
+/// A message to publish through a `LocalPublisher`
+#[derive(Debug, Clone)]
+pub struct LocalPublish {
+    topic: String,
+    payload: Bytes,
+    qos: QoS,
+    retain: bool,
+    /// Hops of the message this one reacts to
+    hops: u8,
+    /// Routing ID of the message this one reacts to
+    routing_id: Option<String>,
+}
+
+impl LocalPublish {
+    /// A QoS 0, non-retained message
+    pub fn new(topic: impl Into<String>, payload: impl Into<Bytes>) -> Self {
+        Self {
+            topic: topic.into(),
+            payload: payload.into(),
+            qos: QoS::AtMostOnce,
+            retain: false,
+            hops: 0,
+            routing_id: None,
+        }
+    }
+
+    pub fn with_qos(mut self, qos: QoS) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    pub fn with_retain(mut self, retain: bool) -> Self {
+        self.retain = retain;
+        self
+    }
+
+    /// Publish in reaction to a delivered message, continuing its hop count
+    /// and keeping its routing ID
+    pub fn caused_by(mut self, cause: &Publish) -> Self {
+        self.hops = local_hops(&cause.properties);
+        self.routing_id = routing_id(&cause.properties).map(str::to_string);
+        self
+    }
+}
+
+/// Why a local publish or subscribe was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalPublishError {
+    /// Topic name or filter is not valid
+    InvalidTopic(&'static str),
+    /// ACL denied the publisher
+    NotAuthorized,
+    /// The message's chain exceeded `max_local_hops`
+    LoopDetected { hops: u8 },
+    /// ACL hook failed
+    Hook(String),
+}
+
+impl fmt::Display for LocalPublishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocalPublishError::InvalidTopic(e) => write!(f, "Invalid topic: {}", e),
+            LocalPublishError::NotAuthorized => write!(f, "Not authorized"),
+            LocalPublishError::LoopDetected { hops } => {
+                write!(f, "Loop detected after {} hops", hops)
+            }
+            LocalPublishError::Hook(e) => write!(f, "ACL check failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LocalPublishError {}
+
+/// In-process publish handle (see the module docs)
+pub struct LocalPublisher {
+    broker: Broker,
+    client_id: Arc<str>,
+    username: Option<String>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl LocalPublisher {
+    pub(super) fn new(broker: &Broker, client_id: &str) -> Self {
+        Self {
+            broker: broker.clone_for_sys_topics(),
+            client_id: client_id.into(),
+            username: None,
+            metrics: broker.metrics.clone(),
+        }
+    }
+
+    /// Username ACLs are checked for
+    pub fn with_username(mut self, username: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    /// Client ID ACLs are checked for
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// Publish a message to local subscribers (and bridges, via events)
+    pub async fn publish(&self, message: LocalPublish) -> Result<(), LocalPublishError> {
+        let LocalPublish {
+            topic,
+            payload,
+            qos,
+            retain,
+            hops,
+            routing_id,
+        } = message;
+
+        let hops = hops.saturating_add(1);
+        if hops > self.broker.config.max_local_hops {
+            warn!(
+                "Local publish from {} to {} dropped: loop after {} hops",
+                self.client_id, topic, hops
+            );
+            if let Some(ref metrics) = self.metrics {
+                metrics.message_dropped("loop_detected");
+            }
+            return Err(LocalPublishError::LoopDetected { hops });
+        }
+
+        validate_topic_name_with_max_levels(&topic, self.broker.config.max_topic_levels)
+            .map_err(LocalPublishError::InvalidTopic)?;
+
+        let allowed = self
+            .broker
+            .hooks
+            .on_publish_check(
+                &self.client_id,
+                self.username.as_deref(),
+                &topic,
+                qos,
+                retain,
+            )
+            .await
+            .map_err(|e| LocalPublishError::Hook(e.to_string()))?;
+        if !allowed {
+            if let Some(ref metrics) = self.metrics {
+                metrics.message_dropped("not_authorized");
+            }
+            return Err(LocalPublishError::NotAuthorized);
+        }
+
+        let mut user_properties = vec![(LOCAL_HOPS_PROPERTY.to_string(), hops.to_string())];
+        if let Some(id) = routing_id {
+            user_properties.push((ROUTING_ID_PROPERTY.to_string(), id));
+        }
+        let properties = Properties {
+            user_properties,
+            ..Default::default()
+        };
+        let user_properties = self.broker.publish_with_properties(
+            topic.clone(),
+            payload.clone(),
+            qos,
+            retain,
+            properties,
+        );
+        let _ = self.broker.events.send(BrokerEvent::MessagePublished {
+            topic: topic.clone(),
+            payload: payload.clone(),
+            qos,
+            retain,
+            origin: None,
+            user_properties,
+        });
+        self.broker
+            .hooks
+            .on_message_published(&topic, &payload, qos)
+            .await;
+        Ok(())
+    }
+
+    /// Receive messages matching `filter` (ACL-checked like a client)
+    ///
+    /// Messages arrive with their hop count, so reactions published with
+    /// `LocalPublish::caused_by` stay within the loop budget. A publisher
+    /// has one subscription at a time: subscribing again replaces it, and
+    /// it ends when the returned handle is dropped.
+    pub async fn subscribe(
+        &self,
+        filter: &str,
+        qos: QoS,
+    ) -> Result<LocalSubscription, LocalPublishError> {
+        validate_topic_filter(filter).map_err(LocalPublishError::InvalidTopic)?;
+        let allowed = self
+            .broker
+            .hooks
+            .on_subscribe_check(&self.client_id, self.username.as_deref(), filter, qos)
+            .await
+            .map_err(|e| LocalPublishError::Hook(e.to_string()))?;
+        if !allowed {
+            return Err(LocalPublishError::NotAuthorized);
+        }
+
+        let (tx, rx) = mpsc::channel(LOCAL_SUBSCRIPTION_CAPACITY);
+        self.broker.subscriptions.unsubscribe_all(&self.client_id);
+        self.broker.connections.insert(self.client_id.clone(), tx);
+        self.broker.subscriptions.subscribe(
+            filter,
+            Subscription {
+                client_id: self.client_id.clone(),
+                qos,
+                no_local: false,
+                retain_as_published: false,
+                subscription_id: None,
+                share_group: None,
+            },
+        );
+
+        Ok(LocalSubscription {
+            rx,
+            broker: self.broker.clone_for_sys_topics(),
+            client_id: self.client_id.clone(),
+        })
+    }
+}
+
+/// Messages delivered to a `LocalPublisher::subscribe` filter
+pub struct LocalSubscription {
+    rx: mpsc::Receiver<Packet>,
+    broker: Broker,
+    client_id: Arc<str>,
+}
+
+impl LocalSubscription {
+    /// Next delivered message (`None` once the broker drops the subscription)
+    pub async fn recv(&mut self) -> Option<Publish> {
+        loop {
+            match self.rx.recv().await? {
+                Packet::Publish(publish) => return Some(publish),
+                // Taken over by a client with the same ID
+                Packet::Disconnect(_) => return None,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for LocalSubscription {
+    fn drop(&mut self) {
+        // Leave a replacement subscription (or a client that took over the
+        // ID) alone: only a closed channel is ours
+        self.rx.close();
+        let removed = self
+            .broker
+            .connections
+            .remove_if(&self.client_id, |_, tx| tx.is_closed());
+        if removed.is_some() {
+            self.broker.subscriptions.unsubscribe_all(&self.client_id);
+        }
+    }
+}
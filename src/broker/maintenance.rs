@@ -0,0 +1,427 @@
+//! Maintenance Windows
+//!
+//! `[[maintenance]]` entries describe planned work recurring on a cron
+//! schedule. While a window is open the broker applies its policies, and
+//! takes them back when it closes:
+//!
+//! - `pause_bridges`: bridges not marked `critical` disconnect and stay
+//!   down, forwarding nothing
+//! - `log_level`: the log filter changes; the one in force before the
+//!   window is restored after it
+//! - `reconnect_rate_factor`: every IP's connection rate, burst, connection
+//!   cap and flapping threshold are multiplied, so a fleet reconnecting
+//!   after a restart isn't refused or banned
+//! - `suppress_wills`: will messages of clients disconnecting are dropped,
+//!   as are delayed wills coming due
+//!
+//! Policies of overlapping windows combine: bridges pause and wills are
+//! dropped if any open window says so, the largest factor applies, and the
+//! log level is that of the first open window (in config order) with one.
+//! A window already open at startup, e.g. after a restart during the work,
+//! applies at once.
+//!
+//! Window state is listed at `GET /api/v1/maintenance` and published as
+//! `$SYS/broker/maintenance/active` (open windows) and
+//! `$SYS/broker/maintenance/windows` (their names). Each node evaluates its
+//! own windows, so a cluster's nodes should be configured alike.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tracing::{debug, info, warn};
+use tracing_subscriber::EnvFilter;
+
+use super::limiters::MAX_BOOST_FACTOR;
+use super::Broker;
+use crate::config::MaintenanceConfig;
+use crate::logging::LogFilter;
+use crate::schedule::{Cron, CronError};
+
+/// Longest window accepted
+pub const MAX_WINDOW_DURATION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Why a maintenance window is invalid
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaintenanceError {
+    Cron(CronError),
+    InvalidDuration(Duration),
+    InvalidFactor(f64),
+    InvalidLogLevel(String),
+}
+
+impl fmt::Display for MaintenanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaintenanceError::Cron(e) => write!(f, "{}", e),
+            MaintenanceError::InvalidDuration(duration) => write!(
+                f,
+                "duration {:?} must be above 0 and at most {:?}",
+                duration, MAX_WINDOW_DURATION
+            ),
+            MaintenanceError::InvalidFactor(factor) => write!(
+                f,
+                "reconnect_rate_factor {} must be at least 1 and at most {}",
+                factor, MAX_BOOST_FACTOR
+            ),
+            MaintenanceError::InvalidLogLevel(e) => write!(f, "invalid log_level: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MaintenanceError {}
+
+/// A validated `[[maintenance]]` entry
+#[derive(Debug, Clone)]
+pub struct MaintenanceWindow {
+    config: MaintenanceConfig,
+    cron: Cron,
+}
+
+impl MaintenanceWindow {
+    pub fn new(config: &MaintenanceConfig) -> Result<Self, MaintenanceError> {
+        let cron = Cron::parse(&config.cron).map_err(MaintenanceError::Cron)?;
+        if config.duration.as_secs() == 0 || config.duration > MAX_WINDOW_DURATION {
+            return Err(MaintenanceError::InvalidDuration(config.duration));
+        }
+        let factor = config.reconnect_rate_factor;
+        if !(1.0..=MAX_BOOST_FACTOR).contains(&factor) {
+            return Err(MaintenanceError::InvalidFactor(factor));
+        }
+        if let Some(ref level) = config.log_level {
+            EnvFilter::try_new(level.trim())
+                .map_err(|e| MaintenanceError::InvalidLogLevel(e.to_string()))?;
+        }
+        Ok(Self {
+            config: config.clone(),
+            cron,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Start and end (Unix seconds) of the window open at `now`, if any
+    fn open_at(&self, now: u64) -> Option<(u64, u64)> {
+        let duration = self.config.duration.as_secs();
+        let mut start = self
+            .cron
+            .next_after(now.saturating_sub(duration))
+            .filter(|&at| at <= now)?;
+        // The latest start, should successive windows overlap
+        while let Some(at) = self.cron.next_after(start).filter(|&at| at <= now) {
+            start = at;
+        }
+        Some((start, start + duration))
+    }
+
+    fn status(&self, now: u64) -> WindowStatus {
+        let open = self.open_at(now);
+        WindowStatus {
+            name: self.config.name.clone(),
+            cron: self.config.cron.clone(),
+            duration_secs: self.config.duration.as_secs(),
+            active: open.is_some(),
+            started_at: open.map(|(start, _)| start),
+            ends_at: open.map(|(_, end)| end),
+            next_start: self.cron.next_after(now),
+            pause_bridges: self.config.pause_bridges,
+            log_level: self.config.log_level.clone(),
+            reconnect_rate_factor: self.config.reconnect_rate_factor,
+            suppress_wills: self.config.suppress_wills,
+        }
+    }
+}
+
+/// A window as listed by `GET /api/v1/maintenance`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WindowStatus {
+    pub name: String,
+    pub cron: String,
+    pub duration_secs: u64,
+    /// Whether the window is open
+    pub active: bool,
+    /// Unix timestamp in seconds the open window started at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<u64>,
+    /// Unix timestamp in seconds the open window closes at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<u64>,
+    /// Unix timestamp in seconds the window opens at next
+    pub next_start: Option<u64>,
+    pub pause_bridges: bool,
+    pub log_level: Option<String>,
+    pub reconnect_rate_factor: f64,
+    pub suppress_wills: bool,
+}
+
+/// `GET /api/v1/maintenance` response
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MaintenanceStatus {
+    /// Names of the open windows
+    pub active: Vec<String>,
+    pub windows: Vec<WindowStatus>,
+    /// Bridges a window holds down
+    pub paused_bridges: Vec<String>,
+}
+
+/// Window state shared with connections, `$SYS` and the admin API
+#[derive(Default)]
+pub struct Maintenance {
+    windows: Mutex<Vec<WindowStatus>>,
+    suppress_wills: AtomicBool,
+}
+
+impl Maintenance {
+    /// Whether will messages are dropped right now
+    pub fn suppress_wills(&self) -> bool {
+        self.suppress_wills.load(Ordering::Relaxed)
+    }
+
+    /// Every configured window, as of its last evaluation
+    pub fn windows(&self) -> Vec<WindowStatus> {
+        self.windows.lock().clone()
+    }
+
+    /// Names of the open windows
+    pub fn active(&self) -> Vec<String> {
+        self.windows
+            .lock()
+            .iter()
+            .filter(|w| w.active)
+            .map(|w| w.name.clone())
+            .collect()
+    }
+}
+
+impl Broker {
+    /// Maintenance window state (see `maintenance`)
+    pub fn maintenance(&self) -> &Arc<Maintenance> {
+        &self.maintenance
+    }
+
+    /// The windows and what they hold paused, for the admin API
+    pub fn maintenance_status(&self) -> MaintenanceStatus {
+        MaintenanceStatus {
+            active: self.maintenance.active(),
+            windows: self.maintenance.windows(),
+            paused_bridges: self
+                .bridge_manager
+                .as_ref()
+                .map(|bridges| bridges.paused())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Log filter changes made by open windows
+#[derive(Default)]
+struct AppliedLog {
+    /// Directives a window set
+    level: Option<String>,
+    /// Filter in force before the first window changed it
+    saved: Option<String>,
+}
+
+/// Opens and closes the configured windows against a broker
+pub struct MaintenanceScheduler {
+    broker: Arc<Broker>,
+    windows: Vec<MaintenanceWindow>,
+    log_filter: Option<Arc<LogFilter>>,
+    log: AppliedLog,
+}
+
+impl MaintenanceScheduler {
+    /// Scheduler for the enabled entries of `configs`
+    pub fn new(
+        broker: Arc<Broker>,
+        configs: &[MaintenanceConfig],
+    ) -> Result<Self, MaintenanceError> {
+        let windows = configs
+            .iter()
+            .filter(|c| c.enabled)
+            .map(MaintenanceWindow::new)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            broker,
+            windows,
+            log_filter: None,
+            log: AppliedLog::default(),
+        })
+    }
+
+    /// Change this log filter for windows with a `log_level`
+    pub fn with_log_filter(mut self, log_filter: Arc<LogFilter>) -> Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
+    /// Number of enabled windows
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// Open and close windows until the broker shuts down
+    pub async fn run(mut self) {
+        let mut shutdown = self.broker.subscribe_shutdown();
+        loop {
+            let Some(due) = self.apply(unix_now()) else {
+                debug!("No maintenance window will open again");
+                return;
+            };
+            let wait = Duration::from_secs(due.saturating_sub(unix_now()));
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = shutdown.recv() => return,
+            }
+        }
+    }
+
+    /// Apply the policies of the windows open at `now`, returning when the
+    /// next window opens or closes
+    fn apply(&mut self, now: u64) -> Option<u64> {
+        let statuses: Vec<WindowStatus> = self.windows.iter().map(|w| w.status(now)).collect();
+        let previous = std::mem::replace(
+            &mut *self.broker.maintenance.windows.lock(),
+            statuses.clone(),
+        );
+        for status in &statuses {
+            let was_active = previous.iter().any(|p| p.name == status.name && p.active);
+            match (was_active, status.active) {
+                (false, true) => info!(
+                    "Maintenance window '{}' opened, closing in {}s",
+                    status.name,
+                    status.ends_at.unwrap_or(now).saturating_sub(now)
+                ),
+                (true, false) => info!("Maintenance window '{}' closed", status.name),
+                _ => {}
+            }
+        }
+
+        let open: Vec<&WindowStatus> = statuses.iter().filter(|s| s.active).collect();
+        let suppress_wills = open.iter().any(|s| s.suppress_wills);
+        self.broker
+            .maintenance
+            .suppress_wills
+            .store(suppress_wills, Ordering::Relaxed);
+        let factor = open
+            .iter()
+            .map(|s| s.reconnect_rate_factor)
+            .fold(1.0, f64::max);
+        self.broker.limiters().set_reconnect_boost(factor);
+        if let Some(ref bridges) = self.broker.bridge_manager {
+            bridges.set_noncritical_paused(open.iter().any(|s| s.pause_bridges));
+        }
+        let level = open.iter().find_map(|s| s.log_level.clone());
+        self.apply_log_level(level);
+
+        statuses
+            .iter()
+            .flat_map(|s| [s.ends_at, s.next_start])
+            .flatten()
+            .min()
+    }
+
+    /// Switch to a window's log level, or back to the one it replaced
+    fn apply_log_level(&mut self, level: Option<String>) {
+        let Some(ref filter) = self.log_filter else {
+            return;
+        };
+        if level == self.log.level {
+            return;
+        }
+        let directives = match level {
+            Some(ref level) => {
+                if self.log.saved.is_none() {
+                    self.log.saved = Some(filter.current());
+                }
+                level.clone()
+            }
+            None => self.log.saved.take().unwrap_or_else(|| filter.current()),
+        };
+        match filter.set_directives(&directives) {
+            Ok(()) => info!("Log filter changed to '{}'", filter.current()),
+            Err(e) => warn!("Cannot change the log filter for maintenance: {}", e),
+        }
+        self.log.level = level;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(cron: &str, duration: Duration) -> MaintenanceWindow {
+        MaintenanceWindow::new(&MaintenanceConfig {
+            name: "patch".to_string(),
+            cron: cron.to_string(),
+            duration,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_open_at() {
+        // Daily at 02:00 UTC for two hours
+        let window = window("0 2 * * *", Duration::from_secs(7200));
+        let day = 86400 * 19000;
+        assert_eq!(window.open_at(day + 3600), None);
+        assert_eq!(window.open_at(day + 7200), Some((day + 7200, day + 14400)));
+        assert_eq!(window.open_at(day + 14399), Some((day + 7200, day + 14400)));
+        assert_eq!(window.open_at(day + 14400), None);
+
+        let status = window.status(day + 14400);
+        assert!(!status.active);
+        assert_eq!(status.next_start, Some(day + 86400 + 7200));
+
+        // Overlapping windows stay open from the latest start
+        let window = self::window("*/5 * * * *", Duration::from_secs(600));
+        assert_eq!(window.open_at(day + 420), Some((day + 300, day + 900)));
+    }
+
+    #[test]
+    fn test_invalid_windows() {
+        let config = |f: fn(&mut MaintenanceConfig)| {
+            let mut config = MaintenanceConfig {
+                name: "patch".to_string(),
+                cron: "@daily".to_string(),
+                ..Default::default()
+            };
+            f(&mut config);
+            MaintenanceWindow::new(&config)
+        };
+        assert!(config(|_| {}).is_ok());
+        assert!(matches!(
+            config(|c| c.cron = "61 * * * *".to_string()),
+            Err(MaintenanceError::Cron(_))
+        ));
+        assert!(matches!(
+            config(|c| c.duration = Duration::ZERO),
+            Err(MaintenanceError::InvalidDuration(_))
+        ));
+        assert!(matches!(
+            config(|c| c.reconnect_rate_factor = 0.5),
+            Err(MaintenanceError::InvalidFactor(_))
+        ));
+        assert!(matches!(
+            config(|c| c.log_level = Some("vibemq=loud".to_string())),
+            Err(MaintenanceError::InvalidLogLevel(_))
+        ));
+    }
+}
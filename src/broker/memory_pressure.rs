@@ -0,0 +1,107 @@
+//! Memory Pressure
+//!
+//! With `limits.memory_pressure.high_watermark` set, the broker checks its
+//! memory use (see `sys_topics::heap_usage`) every `check_interval`. Above
+//! the watermark it evicts queued QoS 0 messages, which the protocol lets
+//! it lose, until it has freed the queue bytes that should bring memory use
+//! back under `low_watermark`. The order is defined:
+//!
+//! 1. messages on `low_priority_topics`, then ordinary messages, then those
+//!    on `[priority]` topics;
+//! 2. within each, the largest queues (by bytes) first;
+//! 3. within a queue, the oldest messages first.
+//!
+//! QoS 1/2 messages and compressed queues are never touched. Each session
+//! counts its evicted messages, and the next message delivered to the
+//! client (MQTT v5) carries the count since it was last told in the
+//! `x-vibemq-evicted` user property. The resident set size may stay up
+//! after eviction, in which case eviction continues while it does.
+
+use tracing::warn;
+
+use super::sys_topics::heap_usage;
+use super::Broker;
+use crate::protocol::{ProtocolVersion, Publish};
+use crate::session::Session;
+use crate::topic::topic_matches_filter;
+
+/// User property telling a client how many of its queued QoS 0 messages
+/// were evicted since it was last told
+pub const EVICTED_PROPERTY: &str = "x-vibemq-evicted";
+
+/// Eviction tiers: low-priority topics, ordinary topics, priority topics
+const TIERS: u8 = 3;
+
+impl Broker {
+    /// Check memory use and evict queued QoS 0 messages above the high
+    /// watermark until shutdown
+    pub(crate) fn spawn_memory_pressure(&self) {
+        let config = self.config.memory_pressure.clone();
+        if !config.is_enabled() {
+            return;
+        }
+        let priority_topics = self.config.priority.topics.clone();
+        let sessions = self.sessions.clone();
+        let metrics = self.metrics.clone();
+        let mut shutdown_rx = self.shutdown.subscribe();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(config.check_interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = shutdown_rx.recv() => break,
+                }
+                let Some(used) = heap_usage().map(|used| used as usize) else {
+                    warn!("Memory use is unavailable on this platform; memory pressure eviction is off");
+                    break;
+                };
+                if used <= config.high_watermark {
+                    continue;
+                }
+                let rank = |topic: &str| {
+                    let matches =
+                        |filters: &[String]| filters.iter().any(|f| topic_matches_filter(topic, f));
+                    if matches(&config.low_priority_topics) {
+                        0
+                    } else if matches(&priority_topics) {
+                        2
+                    } else {
+                        1
+                    }
+                };
+                let eviction = sessions.evict_qos0(used - config.low_watermark(), TIERS, rank);
+                if eviction.messages == 0 {
+                    continue;
+                }
+                warn!(
+                    "Memory use {} bytes over the {} byte watermark: evicted {} queued QoS 0 \
+                     message(s) ({} bytes) from {} session(s)",
+                    used,
+                    config.high_watermark,
+                    eviction.messages,
+                    eviction.bytes,
+                    eviction.sessions
+                );
+                if let Some(ref metrics) = metrics {
+                    metrics
+                        .messages_dropped_total
+                        .with_label_values(&["memory_pressure"])
+                        .inc_by(eviction.messages as u64);
+                }
+            }
+        });
+    }
+}
+
+/// Tell the client of a session about to be sent `publish` how many of its
+/// messages were evicted since it was last told (MQTT v5 clients only; the
+/// count restarts either way)
+pub(crate) fn report_evictions(publish: &mut Publish, session: &mut Session) {
+    let evicted = session.take_evictions();
+    if evicted > 0 && session.protocol_version == ProtocolVersion::V5 {
+        publish
+            .properties
+            .user_properties
+            .push((EVICTED_PROPERTY.to_string(), evicted.to_string()));
+    }
+}
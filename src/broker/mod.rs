@@ -3,56 +3,150 @@
 //! The main broker implementation that handles client connections,
 //! message routing, and coordinates all components.
 
+mod backpressure;
+mod confirm;
 mod connection;
+mod delayed;
+mod health;
+pub(crate) mod limiters;
+mod listener;
+mod local;
+mod maintenance;
+mod memory_pressure;
+mod priority;
+mod replay;
+mod retained;
+mod retained_cache;
+mod retained_feed;
 mod router;
+mod routing_id;
+mod sequence;
+mod session_events;
+mod stomp;
 mod sys_topics;
 mod tls;
+mod topic_tree;
+mod trace;
+mod tunables;
 
+pub use backpressure::{ListenerLoad, BACKPRESSURE_PROPERTY};
+pub use confirm::{Confirm, ConfirmFilter, Confirmations};
 pub use connection::Connection;
+pub use delayed::{DelayedMessage, DelayedQueue};
+pub use health::{health_response, readiness_response, HealthServer, Readiness, ReadinessCheck};
+pub use limiters::{BoostError, BoostInfo, BucketLevel, LimitLevels, Limiters};
+pub use listener::{Listener, ListenerChanges};
+pub use local::{
+    local_hops, LocalPublish, LocalPublishError, LocalPublisher, LocalSubscription,
+    LOCAL_HOPS_PROPERTY,
+};
+pub use maintenance::{
+    Maintenance, MaintenanceError, MaintenanceScheduler, MaintenanceStatus, MaintenanceWindow,
+    WindowStatus, MAX_WINDOW_DURATION,
+};
+pub use memory_pressure::EVICTED_PROPERTY;
+pub use priority::PriorityLanes;
+pub use replay::{Replay, ReplayLog};
+pub use retained::{parse_retained_seed, RetainedEntry, RetainedError, RetainedPage, SeedReport};
+pub use retained_cache::RetainedFrames;
+pub use retained_feed::RetainedChange;
 pub use router::MessageRouter;
-pub use tls::load_tls_config;
-
+pub use routing_id::{routing_id, ROUTING_ID_PROPERTY};
+pub use sequence::Sequencer;
+pub use session_events::{ExpiryReason, SessionExpiryEvent};
+pub use tls::{
+    client_tls_info, load_quic_config, load_tls_config, quic_tls_info, TlsHandshakePool,
+};
+pub use topic_tree::{FilterNode, TopicActivity, TopicNode, TopicTree};
+pub use trace::{
+    Trace, TraceDirection, TraceError, TraceEvent, Tracer, MAX_TRACES, MAX_TRACE_DURATION,
+    TRACE_TOPIC_PREFIX,
+};
+pub use tunables::{TunableChange, TunableError, TunableInfo, Tunables};
+
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use ahash::AHashMap;
 use bytes::Bytes;
 use dashmap::DashMap;
+use parking_lot::{Mutex, RwLock};
 use socket2::{Domain, Protocol, Socket, Type};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{broadcast, mpsc};
-use tracing::{debug, error, info};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, error, info, warn};
 
 /// TCP listen backlog size - high value for burst connection handling
 const TCP_BACKLOG: i32 = 4096;
 
+/// How often sampled metrics (session count, publish rates) are refreshed
+const METRICS_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often draining checks whether inflight flows completed
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Time given to disconnected clients' connections to close after draining
+const DRAIN_CLOSE_GRACE: Duration = Duration::from_secs(1);
+
 use crate::bridge::BridgeManager;
 use crate::cluster::ClusterManager;
-use crate::config::ProxyProtocolConfig;
+use crate::config::{
+    AclRevocation, AuthMetadataField, BacklogDrainConfig, BatchConfig, CompressionCodec,
+    DelayedConfig, ErrorDetail, HandoverConfig, HealthConfig, ListenerCapabilities, ListenerLimits,
+    MemoryPressureConfig, PriorityConfig, ProxyProtocolConfig, PublishRateConfig,
+    PublisherBackpressureConfig, QueueOverflow, QuicConfig, QuotaConfig, RetainedCacheConfig,
+    RetainedFeedConfig, SequenceConfig, SessionExpiryEventsConfig, SharedSubscriptionStrategy,
+    ShutdownConfig, StompConfig, TopicSchemaConfig, TopicTreeConfig, TransactionConfig,
+};
 use crate::flapping::FlappingDetector;
 use crate::hooks::{DefaultHooks, Hooks};
-use crate::metrics::Metrics;
-use crate::persistence::{PersistenceManager, PersistenceOp, StoredRetainedMessage};
-use crate::protocol::{Packet, Properties, ProtocolVersion, Publish, QoS};
-use crate::proxy::{parse_proxy_header, ProxyInfo};
-use crate::session::SessionStore;
-use crate::topic::SubscriptionStore;
-use crate::transport::WsStream;
+use crate::metrics::{Metrics, ThroughputSampler};
+use crate::persistence::{
+    PersistenceManager, PersistenceOp, StoredRateBucket, StoredRetainedMessage, StoredSession,
+};
+use crate::protocol::{Disconnect, Packet, Properties, ProtocolVersion, Publish, QoS, ReasonCode};
+use crate::proxy::{
+    parse_optional_proxy_header, parse_proxy_header, ProxyError, ProxyIdentity, ProxyInfo,
+};
+use crate::remote::{CompressionMetrics, PublishOrigin};
+use crate::session::{Handover, QueueDepth, RateLimiter, SessionStore};
+use crate::topic::{SubscriptionStore, TopicSchema};
+use crate::transport::{PeerAddr, QuicStream, Rewind, WsStream};
 
 /// Broker configuration
 #[derive(Debug, Clone)]
 pub struct BrokerConfig {
     /// TCP bind address
     pub bind_addr: SocketAddr,
+    /// Additional TCP bind addresses sharing the TCP listener's settings
+    pub extra_bind_addrs: Vec<SocketAddr>,
+    /// IPV6_V6ONLY for IPv6 listeners (None = OS default, see `ipv6_only`)
+    pub ipv6_only: Option<bool>,
     /// TLS bind address (optional)
     pub tls_bind_addr: Option<SocketAddr>,
     /// TLS configuration
     pub tls_config: Option<TlsConfig>,
     /// WebSocket bind address (optional)
     pub ws_bind_addr: Option<SocketAddr>,
+    /// WebSocket-over-TLS bind address (optional, uses `tls_config`)
+    pub wss_bind_addr: Option<SocketAddr>,
+    /// MQTT-over-QUIC (UDP) bind address (optional, uses `tls_config`)
+    pub quic_bind_addr: Option<SocketAddr>,
+    /// QUIC listener settings
+    pub quic: QuicConfig,
     /// WebSocket path (default: "/mqtt")
     pub ws_path: String,
+    /// Maximum incoming WebSocket frame size (None = tungstenite default)
+    pub ws_max_frame_size: Option<usize>,
+    /// Unix domain socket path (optional)
+    pub unix_bind_path: Option<PathBuf>,
     /// Maximum connections
     pub max_connections: usize,
     /// Maximum packet size
@@ -63,6 +157,10 @@ pub struct BrokerConfig {
     pub max_keep_alive: u16,
     /// Session expiry check interval
     pub session_expiry_check_interval: Duration,
+    /// Compress idle persistent sessions after this long disconnected (None = disabled)
+    pub session_compress_idle_after: Option<Duration>,
+    /// Cap on the session expiry interval granted to clients (None = no cap)
+    pub max_session_expiry: Option<Duration>,
     /// Receive maximum (flow control)
     pub receive_maximum: u16,
     /// Maximum QoS
@@ -75,6 +173,8 @@ pub struct BrokerConfig {
     pub subscription_identifiers_available: bool,
     /// Shared subscriptions available
     pub shared_subscriptions_available: bool,
+    /// Shared subscription member selection
+    pub shared_subscription_strategy: SharedSubscriptionStrategy,
     /// Maximum topic alias
     pub max_topic_alias: u16,
     /// Number of worker tasks
@@ -83,14 +183,30 @@ pub struct BrokerConfig {
     pub sys_topics_enabled: bool,
     /// $SYS topic publish interval
     pub sys_topics_interval: Duration,
+    /// Hops a message may take through `LocalPublisher`s before it is
+    /// dropped as a loop
+    pub max_local_hops: u8,
+    /// Give messages routing IDs (see `routing_id`)
+    pub routing_ids: bool,
+    /// Compression codecs accepted from bridges of other VibeMQ brokers
+    pub bridge_compression: Vec<CompressionCodec>,
     /// Maximum in-flight messages per client (QoS 1/2)
     pub max_inflight: u16,
     /// Maximum queued messages per offline client
     pub max_queued_messages: usize,
+    /// Maximum topic and payload bytes queued per client (0 = unlimited)
+    pub max_queued_bytes: usize,
+    /// Whether QoS 0 messages are queued for offline clients
+    pub queue_qos0: bool,
+    /// Policy when a client's queue is full
+    pub queue_overflow: QueueOverflow,
     /// Maximum pending PUBREL for QoS 2
     pub max_awaiting_rel: usize,
     /// Retry interval for unacked messages
     pub retry_interval: Duration,
+    /// Alarm on clients alive but not acknowledging for this long (None =
+    /// disabled)
+    pub ack_alarm_after: Option<Duration>,
     /// Per-connection outbound message channel capacity.
     /// This buffer holds messages waiting to be written to the client socket.
     /// Higher values handle burst traffic better but use more memory per connection.
@@ -99,12 +215,82 @@ pub struct BrokerConfig {
     /// Topic levels are separated by '/'. For example, "a/b/c" has 3 levels.
     /// 0 = unlimited.
     pub max_topic_levels: usize,
+    /// Hibernate connections idle for this long (None = disabled)
+    pub hibernate_after: Option<Duration>,
     /// PROXY protocol configuration for TCP listener
     pub proxy_protocol: ProxyProtocolConfig,
     /// PROXY protocol configuration for TLS listener
     pub tls_proxy_protocol: ProxyProtocolConfig,
     /// PROXY protocol configuration for WebSocket listener
     pub ws_proxy_protocol: ProxyProtocolConfig,
+    /// PROXY protocol configuration for the Unix socket listener
+    pub unix_proxy_protocol: ProxyProtocolConfig,
+    /// Accept MQTT 3.1 ("MQIsdp") clients on the TCP listener
+    pub allow_mqtt31: bool,
+    /// Accept MQTT 3.1 ("MQIsdp") clients on the TLS listener
+    pub tls_allow_mqtt31: bool,
+    /// Accept MQTT 3.1 ("MQIsdp") clients on the WebSocket listener
+    pub ws_allow_mqtt31: bool,
+    /// QoS cap on the TCP listener (below `max_qos`)
+    pub listener_max_qos: Option<QoS>,
+    /// QoS cap on the TLS listener (below `max_qos`)
+    pub tls_listener_max_qos: Option<QoS>,
+    /// QoS cap on the WebSocket listener (below `max_qos`)
+    pub ws_listener_max_qos: Option<QoS>,
+    /// MQTT features offered on the TCP listener
+    pub capabilities: ListenerCapabilities,
+    /// MQTT features offered on the TLS listener
+    pub tls_capabilities: ListenerCapabilities,
+    /// MQTT features offered on the WebSocket listener
+    pub ws_capabilities: ListenerCapabilities,
+    /// Error detail revealed to clients on the TCP listener
+    pub error_detail: ErrorDetail,
+    /// Error detail revealed to clients on the TLS listener
+    pub tls_error_detail: ErrorDetail,
+    /// Error detail revealed to clients on the WebSocket listener
+    pub ws_error_detail: ErrorDetail,
+    /// Error reason codes remapped before they are sent to clients
+    pub reason_map: HashMap<ReasonCode, ReasonCode>,
+    /// Message batching extension
+    pub batch: BatchConfig,
+    /// Publish transactions
+    pub transaction: TransactionConfig,
+    /// Two-phase session handover
+    pub handover: HandoverConfig,
+    /// Feed of retained message changes
+    pub retained_feed: RetainedFeedConfig,
+    /// Cache of encoded retained message frames
+    pub retained_cache: RetainedCacheConfig,
+    /// Delayed publishes
+    pub delayed: DelayedConfig,
+    /// Events published when persistent sessions expire
+    pub session_expiry_events: SessionExpiryEventsConfig,
+    /// How sessions share delivery of their queued messages
+    pub backlog_drain: BacklogDrainConfig,
+    /// Readiness checks
+    pub health: HealthConfig,
+    /// Topics of recent traffic kept for the topic tree export
+    pub topic_tree: TopicTreeConfig,
+    /// Declared topic namespace
+    pub topic_schema: TopicSchemaConfig,
+    /// Per-topic sequence numbers on routed messages
+    pub sequence: SequenceConfig,
+    /// Latency-critical topics delivered ahead of other traffic
+    pub priority: PriorityConfig,
+    /// Publish rate limit per client identity
+    pub publish_rate: PublishRateConfig,
+    /// Per-connection quotas (listener and user overrides)
+    pub quota: QuotaConfig,
+    /// Eviction of queued QoS 0 messages under memory pressure
+    pub memory_pressure: MemoryPressureConfig,
+    /// Flow control of MQTT v5 publishers routing to congested subscribers
+    pub publisher_backpressure: PublisherBackpressureConfig,
+    /// Connection and slow-subscriber limits by listener name
+    pub listener_limits: HashMap<String, ListenerLimits>,
+    /// Connection details passed to authentication hooks
+    pub auth_metadata_fields: Vec<AuthMetadataField>,
+    /// Connection draining on shutdown
+    pub shutdown: ShutdownConfig,
 }
 
 /// TLS configuration for the broker
@@ -118,40 +304,98 @@ pub struct TlsConfig {
     pub ca_cert_path: Option<String>,
     /// Require client certificate authentication
     pub require_client_cert: bool,
+    /// Threads dedicated to TLS handshakes (0 = handshake on the main runtime)
+    pub handshake_threads: usize,
+    /// Maximum TLS handshakes queued or in progress on the handshake pool
+    pub handshake_queue_size: usize,
+    /// Longest a handshake on the handshake pool may take
+    pub handshake_timeout: Duration,
 }
 
 impl Default for BrokerConfig {
     fn default() -> Self {
         Self {
             bind_addr: "0.0.0.0:1883".parse().unwrap(),
+            extra_bind_addrs: Vec::new(),
+            ipv6_only: None,
             tls_bind_addr: None,
             tls_config: None,
             ws_bind_addr: None,
+            wss_bind_addr: None,
+            quic_bind_addr: None,
+            quic: QuicConfig::default(),
             ws_path: "/mqtt".to_string(),
+            ws_max_frame_size: None,
+            unix_bind_path: None,
             max_connections: 100_000,
             max_packet_size: 1024 * 1024, // 1 MB
             default_keep_alive: 60,
             max_keep_alive: 65535,
             session_expiry_check_interval: Duration::from_secs(60),
+            session_compress_idle_after: None,
+            max_session_expiry: None,
             receive_maximum: 65535,
             max_qos: QoS::ExactlyOnce,
             retain_available: true,
             wildcard_subscription_available: true,
             subscription_identifiers_available: true,
             shared_subscriptions_available: true,
+            shared_subscription_strategy: SharedSubscriptionStrategy::default(),
             max_topic_alias: 65535,
             num_workers: num_cpus::get(),
             sys_topics_enabled: true,
             sys_topics_interval: Duration::from_secs(10),
+            max_local_hops: 8,
+            routing_ids: false,
+            bridge_compression: Vec::new(),
             max_inflight: 32,
             max_queued_messages: 1000,
+            max_queued_bytes: 0,
+            queue_qos0: true,
+            queue_overflow: QueueOverflow::default(),
             max_awaiting_rel: 100,
             retry_interval: Duration::from_secs(30),
+            ack_alarm_after: None,
             outbound_channel_capacity: 1024,
             max_topic_levels: 0, // 0 = unlimited
+            hibernate_after: None,
             proxy_protocol: ProxyProtocolConfig::default(),
             tls_proxy_protocol: ProxyProtocolConfig::default(),
             ws_proxy_protocol: ProxyProtocolConfig::default(),
+            unix_proxy_protocol: ProxyProtocolConfig::default(),
+            allow_mqtt31: true,
+            tls_allow_mqtt31: true,
+            ws_allow_mqtt31: true,
+            listener_max_qos: None,
+            tls_listener_max_qos: None,
+            ws_listener_max_qos: None,
+            capabilities: ListenerCapabilities::default(),
+            tls_capabilities: ListenerCapabilities::default(),
+            ws_capabilities: ListenerCapabilities::default(),
+            error_detail: ErrorDetail::default(),
+            tls_error_detail: ErrorDetail::default(),
+            ws_error_detail: ErrorDetail::default(),
+            reason_map: HashMap::new(),
+            batch: BatchConfig::default(),
+            transaction: TransactionConfig::default(),
+            handover: HandoverConfig::default(),
+            retained_feed: RetainedFeedConfig::default(),
+            retained_cache: RetainedCacheConfig::default(),
+            delayed: DelayedConfig::default(),
+            session_expiry_events: SessionExpiryEventsConfig::default(),
+            backlog_drain: BacklogDrainConfig::default(),
+            health: HealthConfig::default(),
+            topic_tree: TopicTreeConfig::default(),
+            topic_schema: TopicSchemaConfig::default(),
+            sequence: SequenceConfig::default(),
+            priority: PriorityConfig::default(),
+            publish_rate: PublishRateConfig::default(),
+            quota: QuotaConfig::default(),
+            memory_pressure: MemoryPressureConfig::default(),
+            publisher_backpressure: PublisherBackpressureConfig::default(),
+            listener_limits: HashMap::new(),
+            auth_metadata_fields: AuthMetadataField::ALL.to_vec(),
+            shutdown: ShutdownConfig::default(),
         }
     }
 }
@@ -173,6 +417,8 @@ pub struct RetainedMessage {
     pub qos: QoS,
     pub properties: Properties,
     pub timestamp: Instant,
+    /// Publisher's identity behind a PROXY header (not persisted)
+    pub proxy_identity: Option<Arc<ProxyIdentity>>,
 }
 
 /// Broker events
@@ -191,6 +437,10 @@ pub enum BrokerEvent {
         payload: Bytes,
         qos: QoS,
         retain: bool,
+        /// Publishing client, when the message came from a local client
+        origin: Option<PublishOrigin>,
+        /// The message's MQTT 5 user properties (for bridge forward rules)
+        user_properties: Vec<(String, String)>,
     },
     /// Message dropped due to queue overflow
     MessageDropped,
@@ -198,12 +448,41 @@ pub enum BrokerEvent {
     SubscriptionAdded { filter: String, client_id: Arc<str> },
     /// Subscription removed (for cluster synchronization)
     SubscriptionRemoved { filter: String, client_id: Arc<str> },
+    /// Subscription revoked by an ACL change (also reported as removed)
+    SubscriptionRevoked { filter: String, client_id: Arc<str> },
+    /// Retained message stored or deleted (for the retained change feed)
+    RetainedChanged {
+        topic: String,
+        change: RetainedChange,
+        /// Client that published the message, if a local client did
+        publisher: Option<Arc<str>>,
+    },
+}
+
+/// TLS acceptor and optional handshake pool shared by the TLS listeners
+type TlsListenerSetup = (TlsAcceptor, Option<Arc<TlsHandshakePool>>);
+
+/// A listener's bound socket, ready for its accept loop
+enum BoundListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+    Ws(TcpListener),
+    Wss(TcpListener, TlsListenerSetup),
+    Tls(TcpListener, TlsListenerSetup),
+    Quic(quinn::Endpoint),
 }
 
 /// The MQTT Broker
 pub struct Broker {
     /// Configuration
     config: BrokerConfig,
+    /// Configuration new connections are accepted with (replaced on reload)
+    live_config: Arc<RwLock<BrokerConfig>>,
+    /// Running client listeners, each with the signal that stops it
+    listeners: Arc<Mutex<HashMap<Listener, broadcast::Sender<()>>>>,
+    /// TLS acceptor shared by the TLS and WebSocket/TLS listeners
+    tls: Arc<Mutex<Option<TlsListenerSetup>>>,
     /// Session store
     sessions: Arc<SessionStore>,
     /// Subscription store
@@ -214,6 +493,8 @@ pub struct Broker {
     connections: Arc<DashMap<Arc<str>, mpsc::Sender<Packet>>>,
     /// Shutdown signal
     shutdown: broadcast::Sender<()>,
+    /// Set once the broker stops accepting connections to shut down
+    draining: Arc<watch::Sender<bool>>,
     /// Event channel
     events: broadcast::Sender<BrokerEvent>,
     /// Hooks for auth/ACL and events
@@ -224,10 +505,41 @@ pub struct Broker {
     cluster_manager: Option<Arc<ClusterManager>>,
     /// Metrics for observability
     metrics: Option<Arc<Metrics>>,
+    /// Collectors for compressed cluster and bridge traffic
+    link_compression: CompressionMetrics,
+    /// Live message traces (see `trace`)
+    tracer: Arc<Tracer>,
+    /// Filters whose PUBACKs wait for a confirmation (see `confirm`)
+    confirmations: Arc<Confirmations>,
+    /// Connections per listener and congested subscribers
+    listener_load: Arc<ListenerLoad>,
+    /// Delayed publishes waiting for their deadline (see `delayed`)
+    delayed: Arc<DelayedQueue>,
+    /// Session expiry events restored from persistence, published when the
+    /// broker runs (see `session_events`)
+    pending_session_events: Arc<Mutex<Vec<SessionExpiryEvent>>>,
+    /// Declared topic namespace (see `topic_schema`)
+    topic_schema: Arc<TopicSchema>,
+    /// Per-topic message numbers (see `sequence`)
+    sequencer: Arc<Sequencer>,
+    /// Priority channels of connected clients
+    priority: Arc<PriorityLanes>,
+    /// Encoded frames of delivered retained messages (see `retained_cache`)
+    retained_frames: Arc<RetainedFrames>,
+    /// Settings changeable at runtime (see `tunables`)
+    tunables: Arc<Tunables>,
+    /// Limit boosts and quota buckets (see `limiters`)
+    limiters: Arc<Limiters>,
+    /// Maintenance window state (see `maintenance`)
+    maintenance: Arc<Maintenance>,
+    /// Topics of recent traffic (see `topic_tree`)
+    topic_activity: Arc<TopicActivity>,
     /// Persistence manager for durable storage
     persistence: Option<Arc<PersistenceManager>>,
     /// Flapping detector for DoS protection
     flapping_detector: Option<Arc<FlappingDetector>>,
+    /// STOMP compatibility listener configuration
+    stomp: Option<StompConfig>,
 }
 
 impl Broker {
@@ -240,27 +552,63 @@ impl Broker {
     pub fn with_hooks(config: BrokerConfig, hooks: Arc<dyn Hooks>) -> Self {
         let (shutdown, _) = broadcast::channel(1);
         let (events, _) = broadcast::channel(16384);
+        // Validated with the config file
+        let topic_schema = Arc::new(TopicSchema::new(&config.topic_schema).unwrap_or_default());
+        let sequencer = Arc::new(Sequencer::new(&config.sequence));
+        let priority = Arc::new(PriorityLanes::new(&config.priority));
+        let retained_frames = Arc::new(RetainedFrames::new(&config.retained_cache));
+        let tunables = Arc::new(Tunables::new(&config));
 
         Self {
+            sessions: Arc::new(
+                SessionStore::new()
+                    .with_rate_limits(&config.publish_rate)
+                    .with_backlog_drain(&config.backlog_drain),
+            ),
+            subscriptions: Arc::new(
+                SubscriptionStore::new().with_share_strategy(config.shared_subscription_strategy),
+            ),
+            live_config: Arc::new(RwLock::new(config.clone())),
+            listeners: Arc::new(Mutex::new(HashMap::new())),
+            tls: Arc::new(Mutex::new(None)),
             config,
-            sessions: Arc::new(SessionStore::new()),
-            subscriptions: Arc::new(SubscriptionStore::new()),
             retained: Arc::new(DashMap::new()),
             connections: Arc::new(DashMap::new()),
             shutdown,
+            draining: Arc::new(watch::channel(false).0),
             events,
             hooks,
             bridge_manager: None,
             cluster_manager: None,
             metrics: None,
+            link_compression: CompressionMetrics::new(),
+            tracer: Arc::new(Tracer::default()),
+            confirmations: Arc::new(Confirmations::default()),
+            listener_load: Arc::new(ListenerLoad::default()),
+            delayed: Arc::new(DelayedQueue::default()),
+            pending_session_events: Arc::new(Mutex::new(Vec::new())),
+            topic_schema,
+            sequencer,
+            priority,
+            retained_frames,
+            tunables,
+            limiters: Arc::new(Limiters::default()),
+            maintenance: Arc::new(Maintenance::default()),
+            topic_activity: Arc::new(TopicActivity::default()),
             persistence: None,
             flapping_detector: None,
+            stomp: None,
         }
     }
 
     /// Set flapping detector for DoS protection
     pub fn set_flapping_detector(&mut self, detector: FlappingDetector) {
-        self.flapping_detector = Some(Arc::new(detector));
+        self.flapping_detector = Some(Arc::new(detector.with_limiters(self.limiters.clone())));
+    }
+
+    /// Get the publish rate limiter (if enabled)
+    pub fn rate_limits(&self) -> Option<&RateLimiter> {
+        self.sessions.rate_limits()
     }
 
     /// Get flapping detector (if enabled)
@@ -268,8 +616,16 @@ impl Broker {
         self.flapping_detector.as_ref()
     }
 
+    /// Enable the STOMP compatibility listener
+    pub fn set_stomp(&mut self, config: StompConfig) {
+        self.stomp = Some(config);
+    }
+
     /// Set persistence manager for this broker
     pub fn set_persistence(&mut self, persistence: Arc<PersistenceManager>) {
+        if let Some(ref metrics) = self.metrics {
+            persistence.set_metrics(metrics.clone());
+        }
         self.persistence = Some(persistence);
     }
 
@@ -280,7 +636,14 @@ impl Broker {
 
     /// Set metrics for this broker
     pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        if let Err(e) = self.link_compression.register(&metrics) {
+            warn!("Failed to register link compression metrics: {}", e);
+        }
+        if let Some(ref persistence) = self.persistence {
+            persistence.set_metrics(metrics.clone());
+        }
         self.metrics = Some(metrics);
+        self.register_bridge_metrics();
     }
 
     /// Get metrics (if enabled)
@@ -288,28 +651,107 @@ impl Broker {
         self.metrics.as_ref()
     }
 
-    /// Clone broker for $SYS topics task (only needs publish capability)
+    /// Live message traces
+    pub fn tracer(&self) -> &Arc<Tracer> {
+        &self.tracer
+    }
+
+    /// Filters holding back PUBACKs until a consumer confirms the message
+    pub fn confirmations(&self) -> &Arc<Confirmations> {
+        &self.confirmations
+    }
+
+    /// Connections per listener and congested subscribers
+    pub fn listener_load(&self) -> &Arc<ListenerLoad> {
+        &self.listener_load
+    }
+
+    /// Delayed publishes waiting for their deadline
+    pub fn delayed(&self) -> &Arc<DelayedQueue> {
+        &self.delayed
+    }
+
+    /// Declared topic namespace, with how often each template matched
+    pub fn topic_schema(&self) -> &Arc<TopicSchema> {
+        &self.topic_schema
+    }
+
+    /// Per-topic message sequence counters
+    pub fn sequencer(&self) -> &Arc<Sequencer> {
+        &self.sequencer
+    }
+
+    /// Priority channels of connected clients
+    pub fn priority(&self) -> &Arc<PriorityLanes> {
+        &self.priority
+    }
+
+    /// Encoded frames of delivered retained messages
+    pub fn retained_frames(&self) -> &Arc<RetainedFrames> {
+        &self.retained_frames
+    }
+
+    /// Settings changeable at runtime
+    pub fn tunables(&self) -> &Arc<Tunables> {
+        &self.tunables
+    }
+
+    /// Hooks authenticating and authorizing clients
+    pub fn hooks(&self) -> &Arc<dyn Hooks> {
+        &self.hooks
+    }
+
+    /// Clone broker for background tasks ($SYS topics, STOMP) that only need publish/subscribe access
     fn clone_for_sys_topics(&self) -> Self {
         Self {
             config: self.config.clone(),
+            live_config: self.live_config.clone(),
+            listeners: self.listeners.clone(),
+            tls: self.tls.clone(),
             sessions: self.sessions.clone(),
             subscriptions: self.subscriptions.clone(),
             retained: self.retained.clone(),
             connections: self.connections.clone(),
             shutdown: self.shutdown.clone(),
+            draining: self.draining.clone(),
             events: self.events.clone(),
             hooks: self.hooks.clone(),
             bridge_manager: None,
             cluster_manager: None,
             metrics: None,
+            link_compression: self.link_compression.clone(),
+            tracer: self.tracer.clone(),
+            confirmations: self.confirmations.clone(),
+            listener_load: self.listener_load.clone(),
+            delayed: self.delayed.clone(),
+            pending_session_events: self.pending_session_events.clone(),
+            topic_schema: self.topic_schema.clone(),
+            sequencer: self.sequencer.clone(),
+            priority: self.priority.clone(),
+            retained_frames: self.retained_frames.clone(),
+            tunables: self.tunables.clone(),
+            limiters: self.limiters.clone(),
+            maintenance: self.maintenance.clone(),
+            topic_activity: self.topic_activity.clone(),
             persistence: self.persistence.clone(),
             flapping_detector: None,
+            stomp: None,
         }
     }
 
     /// Set the bridge manager for this broker
     pub fn set_bridge_manager(&mut self, manager: BridgeManager) {
         self.bridge_manager = Some(Arc::new(manager));
+        self.register_bridge_metrics();
+    }
+
+    /// Export bridge health metrics once both bridges and metrics are set
+    fn register_bridge_metrics(&self) {
+        if let (Some(manager), Some(metrics)) = (&self.bridge_manager, &self.metrics) {
+            if let Err(e) = manager.register_metrics(metrics) {
+                warn!("Failed to register bridge metrics: {}", e);
+            }
+        }
     }
 
     /// Set the cluster manager for this broker
@@ -317,6 +759,18 @@ impl Broker {
         self.cluster_manager = Some(Arc::new(manager));
     }
 
+    /// Get the cluster manager (if clustering is enabled)
+    pub fn cluster_manager(&self) -> Option<&Arc<ClusterManager>> {
+        self.cluster_manager.as_ref()
+    }
+
+    /// Whether this broker is a read-only cluster observer
+    pub fn is_observer(&self) -> bool {
+        self.cluster_manager
+            .as_ref()
+            .is_some_and(|c| c.is_observer())
+    }
+
     /// Create a cluster manager with inbound callback that publishes to this broker
     pub async fn create_cluster_manager(
         &self,
@@ -327,10 +781,16 @@ impl Broker {
         let subscriptions = self.subscriptions.clone();
         let connections = self.connections.clone();
         let persistence = self.persistence.clone();
+        let events = self.events.clone();
 
         // Callback for messages received from cluster peers
         let inbound_callback = Arc::new(
-            move |topic: String, payload: Bytes, qos: QoS, retain: bool, _origin_node: String| {
+            move |topic: String,
+                  payload: Bytes,
+                  qos: QoS,
+                  retain: bool,
+                  _origin_node: String,
+                  publisher: Option<String>| {
                 debug!(
                     "Cluster inbound_callback: routing '{}' to local subscribers",
                     topic
@@ -350,7 +810,9 @@ impl Broker {
                 // Handle retained message
                 if retain {
                     if payload.is_empty() {
-                        retained.remove(&topic);
+                        if retained.remove(&topic).is_some() {
+                            retained_feed::announce(&events, &topic, RetainedChange::Deleted, None);
+                        }
                         if let Some(ref persistence) = persistence {
                             persistence.write(PersistenceOp::DeleteRetained {
                                 topic: topic.clone(),
@@ -363,8 +825,17 @@ impl Broker {
                             qos,
                             properties: Properties::default(),
                             timestamp: Instant::now(),
+                            proxy_identity: None,
                         };
-                        retained.insert(topic.clone(), retained_msg.clone());
+                        let replaced = retained
+                            .insert(topic.clone(), retained_msg.clone())
+                            .is_some();
+                        retained_feed::announce(
+                            &events,
+                            &topic,
+                            RetainedChange::stored(replaced),
+                            None,
+                        );
                         if let Some(ref persistence) = persistence {
                             persistence.write(PersistenceOp::SetRetained {
                                 topic: topic.clone(),
@@ -374,8 +845,9 @@ impl Broker {
                     }
                 }
 
-                // Route to local subscribers only
-                let matches = subscriptions.matches(&topic);
+                // Route to local subscribers only (sticky share groups
+                // pick the member the publisher maps to on every node)
+                let matches = subscriptions.matches_from(&topic, publisher.as_deref());
 
                 // Deduplicate by client_id (keep highest QoS) - use AHashMap for faster lookup
                 let mut client_qos: AHashMap<Arc<str>, QoS> =
@@ -426,7 +898,61 @@ impl Broker {
             },
         );
 
-        ClusterManager::new(config, inbound_callback).await
+        // Snapshot of retained messages sent to observers when they join
+        let snapshot_retained = self.retained.clone();
+        let retained_snapshot = Arc::new(move || {
+            snapshot_retained
+                .iter()
+                .map(|m| (m.topic.clone(), m.payload.clone(), m.qos))
+                .collect()
+        });
+
+        let mut manager = ClusterManager::new(config, inbound_callback)
+            .await?
+            .with_retained_snapshot(retained_snapshot)
+            .with_compression_metrics(self.link_compression.clone());
+
+        // Share publish rate buckets so quotas hold across nodes
+        if self.sessions.rate_limits().is_some() {
+            let sessions = self.sessions.clone();
+            manager = manager.with_rate_limit_sync(Arc::new(move |buckets| {
+                if let Some(limiter) = sessions.rate_limits() {
+                    for bucket in buckets {
+                        limiter.merge(bucket);
+                    }
+                }
+            }));
+        }
+
+        // A client that reconnected to another node is disconnected here and
+        // its session dropped: the node it connected to owns the session now
+        let connections = self.connections.clone();
+        let sessions = self.sessions.clone();
+        let subscriptions = self.subscriptions.clone();
+        let persistence = self.persistence.clone();
+        manager = manager.with_session_takeover(Arc::new(move |client_id: String| {
+            let connection = connections.remove(client_id.as_str());
+            if connection.is_none() && sessions.get(&client_id).is_none() {
+                return;
+            }
+            info!(
+                "Session of {} taken over by another cluster node",
+                client_id
+            );
+            if let Some((_, sender)) = connection {
+                let _ = sender.try_send(Packet::Disconnect(Disconnect {
+                    reason_code: ReasonCode::SessionTakenOver,
+                    properties: Properties::default(),
+                }));
+            }
+            sessions.remove(&client_id);
+            subscriptions.unsubscribe_all(&client_id);
+            if let Some(ref persistence) = persistence {
+                persistence.write(PersistenceOp::DeleteSession { client_id });
+            }
+        }));
+
+        Ok(manager)
     }
 
     /// Create a bridge manager with inbound callback that publishes to this broker
@@ -439,6 +965,7 @@ impl Broker {
         let subscriptions = self.subscriptions.clone();
         let connections = self.connections.clone();
         let persistence = self.persistence.clone();
+        let events = self.events.clone();
 
         let inbound_callback = Arc::new(
             move |topic: String, payload: Bytes, qos: QoS, retain: bool| {
@@ -456,7 +983,9 @@ impl Broker {
                 // Handle retained message
                 if retain {
                     if payload.is_empty() {
-                        retained.remove(&topic);
+                        if retained.remove(&topic).is_some() {
+                            retained_feed::announce(&events, &topic, RetainedChange::Deleted, None);
+                        }
                         if let Some(ref persistence) = persistence {
                             persistence.write(PersistenceOp::DeleteRetained {
                                 topic: topic.clone(),
@@ -469,8 +998,17 @@ impl Broker {
                             qos,
                             properties: Properties::default(),
                             timestamp: Instant::now(),
+                            proxy_identity: None,
                         };
-                        retained.insert(topic.clone(), retained_msg.clone());
+                        let replaced = retained
+                            .insert(topic.clone(), retained_msg.clone())
+                            .is_some();
+                        retained_feed::announce(
+                            &events,
+                            &topic,
+                            RetainedChange::stored(replaced),
+                            None,
+                        );
                         if let Some(ref persistence) = persistence {
                             persistence.write(PersistenceOp::SetRetained {
                                 topic: topic.clone(),
@@ -480,7 +1018,8 @@ impl Broker {
                     }
                 }
 
-                // Route to subscribers
+                // Route to subscribers (the remote publisher is unknown, so
+                // sticky share groups fall back to round-robin)
                 let matches = subscriptions.matches(&topic);
 
                 // Deduplicate by client_id (keep highest QoS) - use AHashMap for faster lookup
@@ -518,355 +1057,864 @@ impl Broker {
             },
         );
 
-        BridgeManager::from_configs(configs, inbound_callback)
+        BridgeManager::from_configs(configs, inbound_callback, self.link_compression.clone())
     }
 
-    /// Run the broker
-    pub async fn run(&self) -> Result<(), std::io::Error> {
-        let listener = create_tcp_listener(self.config.bind_addr)?;
-        info!("MQTT/TCP listening on {}", self.config.bind_addr);
+    /// Spawn the client-facing MQTT listeners (TCP, Unix, WebSocket, TLS)
+    fn spawn_client_listeners(&self) -> Result<(), std::io::Error> {
+        let config = self.live_config.read().clone();
 
-        // Spawn TCP accept loop immediately to handle connection bursts
-        self.spawn_tcp_accept_loop(listener);
+        // Bind every listener before accepting on any of them
+        let mut bound = Vec::new();
+        for listener in Listener::configured(&config) {
+            let socket = self.bind_listener(&listener, &config)?;
+            bound.push((listener, socket));
+        }
 
-        // Spawn WebSocket listener if configured
-        if let Some(ws_addr) = self.config.ws_bind_addr {
-            let ws_listener = create_tcp_listener(ws_addr)?;
-            info!(
-                "MQTT/WebSocket listening on {} (path: {})",
-                ws_addr, self.config.ws_path
-            );
+        for (listener, socket) in bound {
+            self.spawn_listener(listener, socket);
+        }
+        Ok(())
+    }
 
-            let sessions = self.sessions.clone();
-            let subscriptions = self.subscriptions.clone();
-            let retained = self.retained.clone();
-            let connections = self.connections.clone();
-            let config = self.config.clone();
-            let events = self.events.clone();
-            let shutdown = self.shutdown.clone();
-            let hooks = self.hooks.clone();
-            let metrics = self.metrics.clone();
-            let persistence = self.persistence.clone();
-            let flapping_detector = self.flapping_detector.clone();
+    /// Apply a reloaded configuration
+    ///
+    /// Connections accepted from now on use `config`; open connections keep
+    /// the settings they were accepted with. Listeners `config` no longer
+    /// has stop (closing their connections), new ones start, and the rest
+    /// keep running. Settings only read at startup (workers, session expiry
+    /// interval, $SYS topics, publish rate limiter, TLS certificates) keep
+    /// their startup values.
+    pub fn apply_config(&self, config: BrokerConfig) -> ListenerChanges {
+        let wanted = if self.is_observer() {
+            Vec::new()
+        } else {
+            Listener::configured(&config)
+        };
+        self.tunables.configure(&config);
+        *self.live_config.write() = config.clone();
+
+        let mut changes = ListenerChanges::default();
+        let running: Vec<Listener> = self.listeners.lock().keys().cloned().collect();
+        for listener in running.iter().filter(|l| !wanted.contains(l)) {
+            if let Some(stop) = self.listeners.lock().remove(listener) {
+                let _ = stop.send(());
+            }
+            info!("Stopped listener {}", listener);
+            changes.stopped.push(listener.to_string());
+        }
+        for listener in wanted.into_iter().filter(|l| !running.contains(l)) {
+            match self.bind_listener(&listener, &config) {
+                Ok(socket) => {
+                    changes.started.push(listener.to_string());
+                    self.spawn_listener(listener, socket);
+                }
+                Err(e) => {
+                    error!("Failed to start listener {}: {}", listener, e);
+                    changes.errors.push(format!("{}: {}", listener, e));
+                }
+            }
+        }
+        changes
+    }
 
-            tokio::spawn(async move {
-                loop {
-                    match ws_listener.accept().await {
-                        Ok((mut stream, addr)) => {
-                            debug!("New WebSocket connection from {}", addr);
-                            let sessions = sessions.clone();
-                            let subscriptions = subscriptions.clone();
-                            let retained = retained.clone();
-                            let connections = connections.clone();
-                            let config = config.clone();
-                            let events = events.clone();
-                            let hooks = hooks.clone();
-                            let metrics = metrics.clone();
-                            let persistence = persistence.clone();
-                            let flapping_detector = flapping_detector.clone();
-                            let mut shutdown_rx = shutdown.subscribe();
-
-                            tokio::spawn(async move {
-                                // Handle PROXY protocol before WebSocket handshake if enabled
-                                let (effective_addr, proxy_info) =
-                                    if config.ws_proxy_protocol.enabled {
-                                        match parse_proxy_header(
-                                            &mut stream,
-                                            config.ws_proxy_protocol.timeout,
-                                            config.ws_proxy_protocol.tls_termination,
-                                        )
-                                        .await
-                                        {
-                                            Ok((info, _remaining)) => {
-                                                debug!(
-                                                    "PROXY protocol (WS): {} -> {} (v{:?})",
-                                                    addr, info.client_addr, info.version
-                                                );
-                                                (info.client_addr, Some(info))
-                                            }
-                                            Err(e) => {
-                                                debug!("PROXY protocol error from {}: {}", addr, e);
-                                                return;
-                                            }
-                                        }
-                                    } else {
-                                        (addr, None)
-                                    };
+    /// Bind a listener's socket (and load TLS for the TLS listeners)
+    fn bind_listener(
+        &self,
+        listener: &Listener,
+        config: &BrokerConfig,
+    ) -> Result<BoundListener, std::io::Error> {
+        Ok(match listener {
+            Listener::Tcp(addr) => {
+                let socket = create_tcp_listener(*addr, config)?;
+                info!("MQTT/TCP listening on {}", addr);
+                BoundListener::Tcp(socket)
+            }
+            #[cfg(unix)]
+            Listener::Unix(path) => {
+                let socket = create_unix_listener(path)?;
+                info!("MQTT/Unix listening on {}", path.display());
+                BoundListener::Unix(socket)
+            }
+            #[cfg(not(unix))]
+            Listener::Unix(path) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!(
+                        "Unix socket listener ({}) is not supported on this platform",
+                        path.display()
+                    ),
+                ))
+            }
+            Listener::Ws(addr) => {
+                let socket = create_tcp_listener(*addr, config)?;
+                info!(
+                    "MQTT/WebSocket listening on {} (path: {})",
+                    addr, config.ws_path
+                );
+                BoundListener::Ws(socket)
+            }
+            Listener::Wss(addr) => {
+                let tls = self.tls_setup(config)?;
+                let socket = create_tcp_listener(*addr, config)?;
+                info!(
+                    "MQTT/WebSocket/TLS listening on {} (path: {})",
+                    addr, config.ws_path
+                );
+                BoundListener::Wss(socket, tls)
+            }
+            Listener::Tls(addr) => {
+                let tls = self.tls_setup(config)?;
+                let socket = create_tcp_listener(*addr, config)?;
+                info!("MQTT/TLS listening on {}", addr);
+                BoundListener::Tls(socket, tls)
+            }
+            Listener::Quic(addr) => {
+                let tls_config = config.tls_config.as_ref().ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "QUIC listener requires a TLS configuration",
+                    )
+                })?;
+                let server_config = load_quic_config(tls_config, &config.quic).map_err(|e| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("TLS configuration error: {}", e),
+                    )
+                })?;
+                let endpoint = quinn::Endpoint::server(server_config, *addr)?;
+                info!("MQTT/QUIC listening on {} (experimental)", addr);
+                BoundListener::Quic(endpoint)
+            }
+        })
+    }
+
+    /// TLS acceptor and handshake pool, loaded by the first TLS listener
+    fn tls_setup(&self, config: &BrokerConfig) -> Result<TlsListenerSetup, std::io::Error> {
+        let mut tls = self.tls.lock();
+        if let Some(ref setup) = *tls {
+            return Ok(setup.clone());
+        }
+        let tls_config = config.tls_config.as_ref().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "TLS listener requires a TLS configuration",
+            )
+        })?;
+        let setup = self.load_tls(tls_config)?;
+        *tls = Some(setup.clone());
+        Ok(setup)
+    }
+
+    /// Register a bound listener and spawn its accept loop
+    ///
+    /// The listener's stop signal also fires on broker shutdown, so its
+    /// accept loop and connections only need to watch the one signal.
+    fn spawn_listener(&self, listener: Listener, socket: BoundListener) {
+        let (stop, _) = broadcast::channel(1);
+        let mut shutdown_rx = self.shutdown.subscribe();
+        let mut stopped_rx = stop.subscribe();
+        let forward = stop.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = shutdown_rx.recv() => {
+                    let _ = forward.send(());
+                }
+                _ = stopped_rx.recv() => {}
+            }
+        });
+        self.listeners.lock().insert(listener, stop.clone());
+
+        match socket {
+            BoundListener::Tcp(socket) => self.spawn_tcp_accept_loop(socket, stop),
+            #[cfg(unix)]
+            BoundListener::Unix(socket) => self.spawn_unix_accept_loop(socket, stop),
+            BoundListener::Ws(socket) => self.spawn_ws_accept_loop(socket, stop),
+            BoundListener::Wss(socket, (acceptor, pool)) => {
+                self.spawn_wss_accept_loop(socket, acceptor, pool, stop)
+            }
+            BoundListener::Tls(socket, (acceptor, pool)) => {
+                self.spawn_tls_accept_loop(socket, acceptor, pool, stop)
+            }
+            BoundListener::Quic(endpoint) => self.spawn_quic_accept_loop(endpoint, stop),
+        }
+    }
+
+    /// Spawn the WebSocket accept loop as a separate task
+    fn spawn_ws_accept_loop(&self, listener: TcpListener, stop: broadcast::Sender<()>) {
+        let sessions = self.sessions.clone();
+        let subscriptions = self.subscriptions.clone();
+        let retained = self.retained.clone();
+        let connections = self.connections.clone();
+        let live_config = self.live_config.clone();
+        let events = self.events.clone();
+        let hooks = self.hooks.clone();
+        let metrics = self.metrics.clone();
+        let tracer = self.tracer.clone();
+        let confirmations = self.confirmations.clone();
+        let listener_load = self.listener_load.clone();
+        let delayed = self.delayed.clone();
+        let topic_schema = self.topic_schema.clone();
+        let sequencer = self.sequencer.clone();
+        let priority = self.priority.clone();
+        let retained_frames = self.retained_frames.clone();
+        let tunables = self.tunables.clone();
+        let limiters = self.limiters.clone();
+        let maintenance = self.maintenance.clone();
+        let persistence = self.persistence.clone();
+        let flapping_detector = self.flapping_detector.clone();
+        let mut stop_rx = stop.subscribe();
+        let mut draining = self.draining.subscribe();
 
-                                // Check flapping/rate limits before WebSocket handshake
-                                let client_ip = effective_addr.ip();
-                                if let Some(ref detector) = flapping_detector {
-                                    if let Err(reason) = detector.check_connection(client_ip) {
-                                        debug!(
-                                            "Rejecting WebSocket connection from {}: {:?}",
-                                            client_ip, reason
-                                        );
-                                        return;
+        tokio::spawn(async move {
+            loop {
+                let accepted = tokio::select! {
+                    accepted = listener.accept() => accepted,
+                    _ = stop_rx.recv() => break,
+                    _ = draining.wait_for(|draining| *draining) => break,
+                };
+                match accepted {
+                    Ok((stream, addr)) => {
+                        debug!("New WebSocket connection from {}", addr);
+                        // Priority messages aren't held back to be coalesced
+                        if priority.is_enabled() {
+                            let _ = stream.set_nodelay(true);
+                        }
+                        let sessions = sessions.clone();
+                        let subscriptions = subscriptions.clone();
+                        let retained = retained.clone();
+                        let connections = connections.clone();
+                        let config = live_config.read().clone();
+                        let events = events.clone();
+                        let hooks = hooks.clone();
+                        let metrics = metrics.clone();
+                        let tracer = tracer.clone();
+                        let confirmations = confirmations.clone();
+                        let listener_load = listener_load.clone();
+                        let delayed = delayed.clone();
+                        let topic_schema = topic_schema.clone();
+                        let sequencer = sequencer.clone();
+                        let priority = priority.clone();
+                        let retained_frames = retained_frames.clone();
+                        let tunables = tunables.clone();
+                        let limiters = limiters.clone();
+                        let maintenance = maintenance.clone();
+                        let persistence = persistence.clone();
+                        let flapping_detector = flapping_detector.clone();
+                        let mut shutdown_rx = stop.subscribe();
+
+                        tokio::spawn(async move {
+                            // Handle PROXY protocol before WebSocket handshake if enabled (trusted peers only)
+                            let (effective_addr, proxy_info, stream) = match accept_proxy_protocol(
+                                stream,
+                                addr.into(),
+                                &config.ws_proxy_protocol,
+                                "PROXY protocol (WS)",
+                            )
+                            .await
+                            {
+                                Ok(accepted) => accepted,
+                                Err(e) => {
+                                    debug!("PROXY protocol error from {}: {}", addr, e);
+                                    if let Some(ref metrics) = metrics {
+                                        metrics.proxy_protocol_error("ws");
                                     }
-                                    detector.record_connection(client_ip);
+                                    return;
+                                }
+                            };
+
+                            // Check flapping/rate limits before WebSocket handshake
+                            if let (Some(detector), Some(client_ip)) =
+                                (&flapping_detector, effective_addr.ip())
+                            {
+                                if let Err(reason) = detector.check_connection(client_ip) {
+                                    debug!(
+                                        "Rejecting WebSocket connection from {}: {:?}",
+                                        client_ip, reason
+                                    );
+                                    return;
                                 }
+                                detector.record_connection(client_ip);
+                            }
 
-                                // Perform WebSocket handshake with path validation
-                                match WsStream::accept_with_path(stream, &config.ws_path).await {
-                                    Ok(ws_stream) => {
-                                        debug!(
-                                            "WebSocket handshake complete for {}",
-                                            effective_addr
-                                        );
-                                        let mut conn = Connection::new(
-                                            ws_stream,
-                                            effective_addr,
-                                            proxy_info,
-                                            sessions,
-                                            subscriptions,
-                                            retained,
-                                            connections,
-                                            config,
-                                            events,
-                                            hooks,
-                                            metrics,
-                                            persistence,
-                                        );
-
-                                        {
-                                            let conn_fut = conn.run();
-                                            tokio::pin!(conn_fut);
-
-                                            loop {
-                                                tokio::select! {
-                                                    biased;
-
-                                                    result = &mut conn_fut => {
-                                                        if let Err(e) = result {
-                                                            debug!("WebSocket connection error from {}: {}", effective_addr, e);
-                                                        }
-                                                        break;
+                            // Perform WebSocket handshake with path validation
+                            let allow_mqtt31 = config.ws_allow_mqtt31;
+                            let max_qos = config.ws_listener_max_qos;
+                            let capabilities = config.ws_capabilities;
+                            let error_detail = config.ws_error_detail;
+                            match WsStream::accept_with_limits(
+                                stream,
+                                &config.ws_path,
+                                config.ws_max_frame_size,
+                            )
+                            .await
+                            {
+                                Ok(ws_stream) => {
+                                    debug!("WebSocket handshake complete for {}", effective_addr);
+                                    let mut conn = Connection::new(
+                                        ws_stream,
+                                        effective_addr.clone(),
+                                        proxy_info,
+                                        sessions,
+                                        subscriptions,
+                                        retained,
+                                        connections,
+                                        config,
+                                        events,
+                                        hooks,
+                                        metrics,
+                                        persistence,
+                                    )
+                                    .with_mqtt31(allow_mqtt31)
+                                    .with_max_qos(max_qos)
+                                    .with_capabilities(capabilities)
+                                    .with_error_detail(error_detail)
+                                    .with_listener("ws")
+                                    .with_tracer(tracer)
+                                    .with_confirmations(confirmations)
+                                    .with_listener_load(listener_load)
+                                    .with_delayed(delayed)
+                                    .with_topic_schema(topic_schema)
+                                    .with_sequencer(sequencer)
+                                    .with_priority(priority)
+                                    .with_retained_frames(retained_frames)
+                                    .with_tunables(tunables)
+                                    .with_limiters(limiters)
+                                    .with_maintenance(maintenance);
+
+                                    {
+                                        let conn_fut = conn.run();
+                                        tokio::pin!(conn_fut);
+
+                                        loop {
+                                            tokio::select! {
+                                                biased;
+
+                                                result = &mut conn_fut => {
+                                                    if let Err(e) = result {
+                                                        debug!("WebSocket connection error from {}: {}", effective_addr, e);
                                                     }
-                                                    result = shutdown_rx.recv() => {
-                                                        match result {
-                                                            Ok(()) => break,
-                                                            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
-                                                            Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
-                                                        }
+                                                    break;
+                                                }
+                                                result = shutdown_rx.recv() => {
+                                                    match result {
+                                                        Ok(()) => break,
+                                                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                                                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
                                                     }
                                                 }
                                             }
                                         }
+                                    }
 
-                                        // Return buffers to the pool for reuse
-                                        conn.return_buffers();
+                                    // Return buffers to the pool for reuse
+                                    conn.return_buffers();
 
-                                        // Track disconnection for flapping detection
-                                        if let Some(ref detector) = flapping_detector {
-                                            detector.record_disconnection(effective_addr.ip());
-                                        }
+                                    // Track disconnection for flapping detection
+                                    if let (Some(detector), Some(ip)) =
+                                        (&flapping_detector, effective_addr.ip())
+                                    {
+                                        detector.record_disconnection(ip);
                                     }
-                                    Err(e) => {
-                                        debug!(
-                                            "WebSocket handshake failed for {}: {}",
-                                            effective_addr, e
-                                        );
-                                        // Track disconnection even on handshake failure
-                                        if let Some(ref detector) = flapping_detector {
-                                            detector.record_disconnection(effective_addr.ip());
-                                        }
+                                }
+                                Err(e) => {
+                                    debug!(
+                                        "WebSocket handshake failed for {}: {}",
+                                        effective_addr, e
+                                    );
+                                    // Track disconnection even on handshake failure
+                                    if let (Some(detector), Some(ip)) =
+                                        (&flapping_detector, effective_addr.ip())
+                                    {
+                                        detector.record_disconnection(ip);
                                     }
                                 }
-                            });
-                        }
-                        Err(e) => {
-                            error!("Failed to accept WebSocket connection: {}", e);
-                        }
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        error!("Failed to accept WebSocket connection: {}", e);
                     }
                 }
-            });
-        }
-
-        // Spawn TLS listener if configured
-        if let (Some(tls_addr), Some(tls_config)) =
-            (self.config.tls_bind_addr, &self.config.tls_config)
-        {
-            let tls_acceptor = match load_tls_config(tls_config) {
-                Ok(acceptor) => acceptor,
-                Err(e) => {
-                    error!("Failed to load TLS configuration: {}", e);
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::InvalidInput,
-                        format!("TLS configuration error: {}", e),
-                    ));
-                }
-            };
-
-            let tls_listener = create_tcp_listener(tls_addr)?;
-            info!("MQTT/TLS listening on {}", tls_addr);
-
-            let sessions = self.sessions.clone();
-            let subscriptions = self.subscriptions.clone();
-            let retained = self.retained.clone();
-            let connections = self.connections.clone();
-            let config = self.config.clone();
-            let events = self.events.clone();
-            let shutdown = self.shutdown.clone();
-            let hooks = self.hooks.clone();
-            let metrics = self.metrics.clone();
-            let persistence = self.persistence.clone();
-            let flapping_detector = self.flapping_detector.clone();
+            }
+            debug!("WebSocket accept loop stopped");
+        });
+    }
 
-            tokio::spawn(async move {
-                loop {
-                    match tls_listener.accept().await {
-                        Ok((mut stream, addr)) => {
-                            debug!("New TLS connection from {}", addr);
-                            let sessions = sessions.clone();
-                            let subscriptions = subscriptions.clone();
-                            let retained = retained.clone();
-                            let connections = connections.clone();
-                            let config = config.clone();
-                            let events = events.clone();
-                            let hooks = hooks.clone();
-                            let metrics = metrics.clone();
-                            let tls_acceptor = tls_acceptor.clone();
-                            let persistence = persistence.clone();
-                            let flapping_detector = flapping_detector.clone();
-                            let mut shutdown_rx = shutdown.subscribe();
-
-                            tokio::spawn(async move {
-                                // Handle PROXY protocol before TLS handshake if enabled
-                                let (effective_addr, proxy_info) =
-                                    if config.tls_proxy_protocol.enabled {
-                                        match parse_proxy_header(
-                                            &mut stream,
-                                            config.tls_proxy_protocol.timeout,
-                                            config.tls_proxy_protocol.tls_termination,
-                                        )
-                                        .await
-                                        {
-                                            Ok((info, _remaining)) => {
-                                                debug!(
-                                                    "PROXY protocol (TLS): {} -> {} (v{:?})",
-                                                    addr, info.client_addr, info.version
-                                                );
-                                                (info.client_addr, Some(info))
-                                            }
-                                            Err(e) => {
-                                                debug!("PROXY protocol error from {}: {}", addr, e);
-                                                return;
-                                            }
-                                        }
-                                    } else {
-                                        (addr, None)
-                                    };
+    /// Spawn the TLS accept loop as a separate task
+    fn spawn_tls_accept_loop(
+        &self,
+        listener: TcpListener,
+        tls_acceptor: TlsAcceptor,
+        handshake_pool: Option<Arc<TlsHandshakePool>>,
+        stop: broadcast::Sender<()>,
+    ) {
+        let sessions = self.sessions.clone();
+        let subscriptions = self.subscriptions.clone();
+        let retained = self.retained.clone();
+        let connections = self.connections.clone();
+        let live_config = self.live_config.clone();
+        let events = self.events.clone();
+        let hooks = self.hooks.clone();
+        let metrics = self.metrics.clone();
+        let tracer = self.tracer.clone();
+        let confirmations = self.confirmations.clone();
+        let listener_load = self.listener_load.clone();
+        let delayed = self.delayed.clone();
+        let topic_schema = self.topic_schema.clone();
+        let sequencer = self.sequencer.clone();
+        let priority = self.priority.clone();
+        let retained_frames = self.retained_frames.clone();
+        let tunables = self.tunables.clone();
+        let limiters = self.limiters.clone();
+        let maintenance = self.maintenance.clone();
+        let persistence = self.persistence.clone();
+        let flapping_detector = self.flapping_detector.clone();
+        let mut stop_rx = stop.subscribe();
+        let mut draining = self.draining.subscribe();
 
-                                // Check flapping/rate limits before TLS handshake
-                                let client_ip = effective_addr.ip();
-                                if let Some(ref detector) = flapping_detector {
-                                    if let Err(reason) = detector.check_connection(client_ip) {
-                                        debug!(
-                                            "Rejecting TLS connection from {}: {:?}",
-                                            client_ip, reason
-                                        );
-                                        return;
+        tokio::spawn(async move {
+            loop {
+                let accepted = tokio::select! {
+                    accepted = listener.accept() => accepted,
+                    _ = stop_rx.recv() => break,
+                    _ = draining.wait_for(|draining| *draining) => break,
+                };
+                match accepted {
+                    Ok((stream, addr)) => {
+                        debug!("New TLS connection from {}", addr);
+                        // Priority messages aren't held back to be coalesced
+                        if priority.is_enabled() {
+                            let _ = stream.set_nodelay(true);
+                        }
+                        let sessions = sessions.clone();
+                        let subscriptions = subscriptions.clone();
+                        let retained = retained.clone();
+                        let connections = connections.clone();
+                        let config = live_config.read().clone();
+                        let events = events.clone();
+                        let hooks = hooks.clone();
+                        let metrics = metrics.clone();
+                        let tracer = tracer.clone();
+                        let confirmations = confirmations.clone();
+                        let listener_load = listener_load.clone();
+                        let delayed = delayed.clone();
+                        let topic_schema = topic_schema.clone();
+                        let sequencer = sequencer.clone();
+                        let priority = priority.clone();
+                        let retained_frames = retained_frames.clone();
+                        let tunables = tunables.clone();
+                        let limiters = limiters.clone();
+                        let maintenance = maintenance.clone();
+                        let tls_acceptor = tls_acceptor.clone();
+                        let handshake_pool = handshake_pool.clone();
+                        let persistence = persistence.clone();
+                        let flapping_detector = flapping_detector.clone();
+                        let mut shutdown_rx = stop.subscribe();
+
+                        tokio::spawn(async move {
+                            // Handle PROXY protocol before TLS handshake if enabled (trusted peers only)
+                            let (effective_addr, proxy_info, stream) = match accept_proxy_protocol(
+                                stream,
+                                addr.into(),
+                                &config.tls_proxy_protocol,
+                                "PROXY protocol (TLS)",
+                            )
+                            .await
+                            {
+                                Ok(accepted) => accepted,
+                                Err(e) => {
+                                    debug!("PROXY protocol error from {}: {}", addr, e);
+                                    if let Some(ref metrics) = metrics {
+                                        metrics.proxy_protocol_error("tls");
                                     }
-                                    detector.record_connection(client_ip);
+                                    return;
+                                }
+                            };
+
+                            // Check flapping/rate limits before TLS handshake
+                            if let (Some(detector), Some(client_ip)) =
+                                (&flapping_detector, effective_addr.ip())
+                            {
+                                if let Err(reason) = detector.check_connection(client_ip) {
+                                    debug!(
+                                        "Rejecting TLS connection from {}: {:?}",
+                                        client_ip, reason
+                                    );
+                                    return;
                                 }
+                                detector.record_connection(client_ip);
+                            }
 
-                                // Perform TLS handshake
-                                match tls_acceptor.accept(stream).await {
-                                    Ok(tls_stream) => {
-                                        debug!("TLS handshake complete for {}", effective_addr);
-                                        let mut conn = Connection::new(
-                                            tls_stream,
-                                            effective_addr,
-                                            proxy_info,
-                                            sessions,
-                                            subscriptions,
-                                            retained,
-                                            connections,
-                                            config,
-                                            events,
-                                            hooks,
-                                            metrics,
-                                            persistence,
-                                        );
-
-                                        {
-                                            let conn_fut = conn.run();
-                                            tokio::pin!(conn_fut);
-
-                                            loop {
-                                                tokio::select! {
-                                                    biased;
-
-                                                    result = &mut conn_fut => {
-                                                        if let Err(e) = result {
-                                                            debug!("TLS connection error from {}: {}", effective_addr, e);
-                                                        }
-                                                        break;
+                            // Perform TLS handshake
+                            let allow_mqtt31 = config.tls_allow_mqtt31;
+                            let max_qos = config.tls_listener_max_qos;
+                            let capabilities = config.tls_capabilities;
+                            let error_detail = config.tls_error_detail;
+                            let handshake = match handshake_pool {
+                                Some(ref pool) => pool.accept(&tls_acceptor, stream).await,
+                                None => tls_acceptor.accept(stream).await,
+                            };
+                            match handshake {
+                                Ok(tls_stream) => {
+                                    debug!("TLS handshake complete for {}", effective_addr);
+                                    let tls_info = client_tls_info(&tls_stream);
+                                    let mut conn = Connection::new(
+                                        tls_stream,
+                                        effective_addr.clone(),
+                                        proxy_info,
+                                        sessions,
+                                        subscriptions,
+                                        retained,
+                                        connections,
+                                        config,
+                                        events,
+                                        hooks,
+                                        metrics,
+                                        persistence,
+                                    )
+                                    .with_mqtt31(allow_mqtt31)
+                                    .with_max_qos(max_qos)
+                                    .with_capabilities(capabilities)
+                                    .with_error_detail(error_detail)
+                                    .with_tls_info(tls_info)
+                                    .with_listener("tls")
+                                    .with_tracer(tracer)
+                                    .with_confirmations(confirmations)
+                                    .with_listener_load(listener_load)
+                                    .with_delayed(delayed)
+                                    .with_topic_schema(topic_schema)
+                                    .with_sequencer(sequencer)
+                                    .with_priority(priority)
+                                    .with_retained_frames(retained_frames)
+                                    .with_tunables(tunables)
+                                    .with_limiters(limiters)
+                                    .with_maintenance(maintenance);
+
+                                    {
+                                        let conn_fut = conn.run();
+                                        tokio::pin!(conn_fut);
+
+                                        loop {
+                                            tokio::select! {
+                                                biased;
+
+                                                result = &mut conn_fut => {
+                                                    if let Err(e) = result {
+                                                        debug!("TLS connection error from {}: {}", effective_addr, e);
                                                     }
-                                                    result = shutdown_rx.recv() => {
-                                                        match result {
-                                                            Ok(()) => break,
-                                                            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
-                                                            Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
-                                                        }
+                                                    break;
+                                                }
+                                                result = shutdown_rx.recv() => {
+                                                    match result {
+                                                        Ok(()) => break,
+                                                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                                                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
                                                     }
                                                 }
                                             }
                                         }
+                                    }
 
-                                        // Return buffers to the pool for reuse
-                                        conn.return_buffers();
+                                    // Return buffers to the pool for reuse
+                                    conn.return_buffers();
 
-                                        // Track disconnection for flapping detection
-                                        if let Some(ref detector) = flapping_detector {
-                                            detector.record_disconnection(effective_addr.ip());
-                                        }
+                                    // Track disconnection for flapping detection
+                                    if let (Some(detector), Some(ip)) =
+                                        (&flapping_detector, effective_addr.ip())
+                                    {
+                                        detector.record_disconnection(ip);
                                     }
-                                    Err(e) => {
-                                        debug!(
-                                            "TLS handshake failed for {}: {}",
-                                            effective_addr, e
-                                        );
-                                        // Track disconnection even on handshake failure
-                                        if let Some(ref detector) = flapping_detector {
-                                            detector.record_disconnection(effective_addr.ip());
-                                        }
+                                }
+                                Err(e) => {
+                                    debug!("TLS handshake failed for {}: {}", effective_addr, e);
+                                    // Track disconnection even on handshake failure
+                                    if let (Some(detector), Some(ip)) =
+                                        (&flapping_detector, effective_addr.ip())
+                                    {
+                                        detector.record_disconnection(ip);
                                     }
                                 }
-                            });
-                        }
-                        Err(e) => {
-                            error!("Failed to accept TLS connection: {}", e);
-                        }
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        error!("Failed to accept TLS connection: {}", e);
                     }
                 }
-            });
-        }
+            }
+            debug!("TLS accept loop stopped");
+        });
+    }
 
-        // Spawn session expiry cleanup task
+    /// Spawn the QUIC accept loop as a separate task
+    ///
+    /// Each bidirectional stream a client opens is one MQTT connection.
+    fn spawn_quic_accept_loop(&self, endpoint: quinn::Endpoint, stop: broadcast::Sender<()>) {
         let sessions = self.sessions.clone();
-        let interval = self.config.session_expiry_check_interval;
-        let mut shutdown_rx = self.shutdown.subscribe();
+        let subscriptions = self.subscriptions.clone();
+        let retained = self.retained.clone();
+        let connections = self.connections.clone();
+        let live_config = self.live_config.clone();
+        let events = self.events.clone();
+        let hooks = self.hooks.clone();
+        let metrics = self.metrics.clone();
+        let tracer = self.tracer.clone();
+        let confirmations = self.confirmations.clone();
+        let listener_load = self.listener_load.clone();
+        let delayed = self.delayed.clone();
+        let topic_schema = self.topic_schema.clone();
+        let sequencer = self.sequencer.clone();
+        let priority = self.priority.clone();
+        let retained_frames = self.retained_frames.clone();
+        let tunables = self.tunables.clone();
+        let limiters = self.limiters.clone();
+        let maintenance = self.maintenance.clone();
+        let persistence = self.persistence.clone();
+        let flapping_detector = self.flapping_detector.clone();
+        let mut stop_rx = stop.subscribe();
+        let mut draining = self.draining.subscribe();
+
         tokio::spawn(async move {
-            let mut ticker = tokio::time::interval(interval);
             loop {
-                tokio::select! {
-                    biased;
-
-                    _ = ticker.tick() => {
-                        sessions.cleanup_expired();
-                    }
-                    result = shutdown_rx.recv() => {
-                        match result {
-                            Ok(()) => break,
-                            Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
-                            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
-                        }
+                let incoming = tokio::select! {
+                    incoming = endpoint.accept() => incoming,
+                    _ = stop_rx.recv() => break,
+                    _ = draining.wait_for(|draining| *draining) => break,
+                };
+                let Some(incoming) = incoming else {
+                    break;
+                };
+                let addr = incoming.remote_address();
+                debug!("New QUIC connection from {}", addr);
+
+                // Check flapping/rate limits before the handshake
+                if let Some(ref detector) = flapping_detector {
+                    if let Err(reason) = detector.check_connection(addr.ip()) {
+                        debug!("Rejecting QUIC connection from {}: {:?}", addr, reason);
+                        incoming.refuse();
+                        continue;
+                    }
+                    detector.record_connection(addr.ip());
+                }
+
+                let sessions = sessions.clone();
+                let subscriptions = subscriptions.clone();
+                let retained = retained.clone();
+                let connections = connections.clone();
+                let config = live_config.read().clone();
+                let events = events.clone();
+                let hooks = hooks.clone();
+                let metrics = metrics.clone();
+                let tracer = tracer.clone();
+                let confirmations = confirmations.clone();
+                let listener_load = listener_load.clone();
+                let delayed = delayed.clone();
+                let topic_schema = topic_schema.clone();
+                let sequencer = sequencer.clone();
+                let priority = priority.clone();
+                let retained_frames = retained_frames.clone();
+                let tunables = tunables.clone();
+                let limiters = limiters.clone();
+                let maintenance = maintenance.clone();
+                let persistence = persistence.clone();
+                let flapping_detector = flapping_detector.clone();
+                let stop = stop.clone();
+
+                tokio::spawn(async move {
+                    let connection = match incoming.accept() {
+                        Ok(connecting) if config.quic.zero_rtt => {
+                            match connecting.into_0rtt() {
+                                Ok((connection, handshake)) => {
+                                    // A resumed session carries its client
+                                    // certificate; on a full handshake wait
+                                    // for the certificate before reading
+                                    // CONNECT
+                                    let client_auth = config
+                                        .tls_config
+                                        .as_ref()
+                                        .is_some_and(|tls| tls.ca_cert_path.is_some());
+                                    if client_auth && connection.peer_identity().is_none() {
+                                        handshake.await;
+                                    }
+                                    Ok(connection)
+                                }
+                                Err(connecting) => connecting.await,
+                            }
+                        }
+                        Ok(connecting) => connecting.await,
+                        Err(e) => Err(e),
+                    };
+                    let connection = match connection {
+                        Ok(connection) => connection,
+                        Err(e) => {
+                            debug!("QUIC handshake failed for {}: {}", addr, e);
+                            if let Some(ref detector) = flapping_detector {
+                                detector.record_disconnection(addr.ip());
+                            }
+                            return;
+                        }
+                    };
+                    debug!("QUIC handshake complete for {}", addr);
+                    let tls_info = quic_tls_info(&connection);
+                    let mut shutdown_rx = stop.subscribe();
+
+                    loop {
+                        let (send, recv) = tokio::select! {
+                            stream = connection.accept_bi() => match stream {
+                                Ok(stream) => stream,
+                                Err(e) => {
+                                    debug!("QUIC connection from {} closed: {}", addr, e);
+                                    break;
+                                }
+                            },
+                            _ = shutdown_rx.recv() => {
+                                connection.close(0u32.into(), b"shutdown");
+                                break;
+                            }
+                        };
+
+                        let allow_mqtt31 = config.tls_allow_mqtt31;
+                        let max_qos = config.tls_listener_max_qos;
+                        let capabilities = config.tls_capabilities;
+                        let error_detail = config.tls_error_detail;
+                        let mut conn = Connection::new(
+                            QuicStream::new(send, recv),
+                            addr.into(),
+                            None,
+                            sessions.clone(),
+                            subscriptions.clone(),
+                            retained.clone(),
+                            connections.clone(),
+                            config.clone(),
+                            events.clone(),
+                            hooks.clone(),
+                            metrics.clone(),
+                            persistence.clone(),
+                        )
+                        .with_mqtt31(allow_mqtt31)
+                        .with_max_qos(max_qos)
+                        .with_capabilities(capabilities)
+                        .with_error_detail(error_detail)
+                        .with_tls_info(tls_info.clone())
+                        .with_listener("quic")
+                        .with_tracer(tracer.clone())
+                        .with_confirmations(confirmations.clone())
+                        .with_listener_load(listener_load.clone())
+                        .with_delayed(delayed.clone())
+                        .with_topic_schema(topic_schema.clone())
+                        .with_sequencer(sequencer.clone())
+                        .with_priority(priority.clone())
+                        .with_retained_frames(retained_frames.clone())
+                        .with_tunables(tunables.clone())
+                        .with_limiters(limiters.clone())
+                        .with_maintenance(maintenance.clone());
+                        let mut shutdown_rx = stop.subscribe();
+
+                        tokio::spawn(async move {
+                            {
+                                let conn_fut = conn.run();
+                                tokio::pin!(conn_fut);
+
+                                loop {
+                                    tokio::select! {
+                                        biased;
+
+                                        result = &mut conn_fut => {
+                                            if let Err(e) = result {
+                                                debug!("QUIC connection error from {}: {}", addr, e);
+                                            }
+                                            break;
+                                        }
+                                        result = shutdown_rx.recv() => {
+                                            match result {
+                                                Ok(()) => break,
+                                                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                                                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+
+                            // Return buffers to the pool for reuse
+                            conn.return_buffers();
+                        });
+                    }
+
+                    // Track disconnection for flapping detection
+                    if let Some(ref detector) = flapping_detector {
+                        detector.record_disconnection(addr.ip());
+                    }
+                });
+            }
+            endpoint.close(0u32.into(), b"shutdown");
+            debug!("QUIC accept loop stopped");
+        });
+    }
+
+    /// Run the broker
+    pub async fn run(&self) -> Result<(), std::io::Error> {
+        // Observers replicate cluster state but accept no client connections
+        if self.is_observer() {
+            info!("Cluster observer mode: client listeners disabled");
+        } else {
+            self.spawn_client_listeners()?;
+        }
+
+        // Spawn session expiry cleanup task
+        let broker = self.clone_for_sys_topics();
+        let interval = self.config.session_expiry_check_interval;
+        let compress_idle_after = self.config.session_compress_idle_after;
+        let mut shutdown_rx = self.shutdown.subscribe();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    biased;
+
+                    _ = ticker.tick() => {
+                        let expired = broker.sessions.cleanup_expired();
+                        broker.reap_sessions(expired).await;
+                        if let Some(idle_after) = compress_idle_after {
+                            let compressed = broker.sessions.compress_idle(idle_after);
+                            if compressed > 0 {
+                                debug!("Compressed {} idle session(s)", compressed);
+                            }
+                        }
+                    }
+                    result = shutdown_rx.recv() => {
+                        match result {
+                            Ok(()) => break,
+                            Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                        }
                     }
                 }
             }
         });
 
+        self.spawn_memory_pressure();
+
+        // Spawn cluster metrics report task if federation is enabled
+        if let (Some(cluster_manager), Some(metrics)) =
+            (self.cluster_manager.clone(), self.metrics.clone())
+        {
+            if let Some(interval) = cluster_manager.metrics_report_interval() {
+                let mut shutdown_rx = self.shutdown.subscribe();
+                tokio::spawn(async move {
+                    let mut ticker = tokio::time::interval(interval);
+                    loop {
+                        tokio::select! {
+                            _ = ticker.tick() => {}
+                            _ = shutdown_rx.recv() => break,
+                        }
+                        cluster_manager.report_metrics(&metrics).await;
+                    }
+                });
+            }
+        }
+
         // Spawn flapping detector cleanup task if enabled
         if let Some(ref detector) = self.flapping_detector {
             let detector = detector.clone();
@@ -894,6 +1942,62 @@ impl Broker {
             });
         }
 
+        // Spawn publish rate bucket sync task if rate limiting is enabled
+        if self.sessions.rate_limits().is_some() {
+            let sessions = self.sessions.clone();
+            let persistence = self
+                .persistence
+                .clone()
+                .filter(|_| self.config.publish_rate.persist);
+            let cluster_manager = self.cluster_manager.clone();
+            let interval = self.config.publish_rate.sync_interval;
+            let mut shutdown_rx = self.shutdown.subscribe();
+
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    let stopping = tokio::select! {
+                        biased;
+
+                        _ = ticker.tick() => false,
+                        result = shutdown_rx.recv() => {
+                            match result {
+                                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                                _ => true,
+                            }
+                        }
+                    };
+
+                    let Some(limiter) = sessions.rate_limits() else {
+                        break;
+                    };
+                    let changed = limiter.take_dirty();
+                    if let Some(ref persistence) = persistence {
+                        for bucket in &changed {
+                            persistence.write(PersistenceOp::SetRateBucket {
+                                identity: bucket.identity.clone(),
+                                bucket: StoredRateBucket::from(bucket),
+                            });
+                        }
+                        for identity in limiter.evict_full() {
+                            persistence.write(PersistenceOp::DeleteRateBucket {
+                                identity: identity.to_string(),
+                            });
+                        }
+                    } else {
+                        limiter.evict_full();
+                    }
+                    if let Some(ref cluster_manager) = cluster_manager {
+                        cluster_manager.broadcast_rate_limits(changed).await;
+                    }
+
+                    if stopping {
+                        break;
+                    }
+                }
+            });
+        }
+
         // Spawn bridge forwarding task if bridges are configured
         if let Some(ref bridge_manager) = self.bridge_manager {
             let bridge_manager = bridge_manager.clone();
@@ -915,9 +2019,9 @@ impl Broker {
 
                         result = events_rx.recv() => {
                             match result {
-                                Ok(BrokerEvent::MessagePublished { topic, payload, qos, retain }) => {
+                                Ok(BrokerEvent::MessagePublished { topic, payload, qos, retain, origin, user_properties }) => {
                                     // Forward to bridges
-                                    bridge_manager.forward_publish(&topic, payload, qos, retain).await;
+                                    bridge_manager.forward_publish(&topic, payload, qos, retain, origin.as_ref(), &user_properties).await;
                                 }
                                 Ok(_) => {} // Ignore other events
                                 Err(broadcast::error::RecvError::Lagged(n)) => {
@@ -966,10 +2070,16 @@ impl Broker {
 
                         result = events_rx.recv() => {
                             match result {
-                                Ok(BrokerEvent::MessagePublished { topic, payload, qos, retain }) => {
+                                Ok(BrokerEvent::MessagePublished { topic, payload, qos, retain, origin, .. }) => {
                                     // Forward to cluster peers
                                     debug!("Cluster: forwarding publish to topic '{}' (peers={})", topic, cluster_manager.peer_count());
-                                    cluster_manager.forward_publish(&topic, payload, qos, retain).await;
+                                    let publisher = origin.as_ref().map(|o| &*o.client_id);
+                                    cluster_manager.forward_publish(&topic, payload, qos, retain, publisher).await;
+                                }
+                                Ok(BrokerEvent::ClientConnected { client_id, .. }) => {
+                                    // Take the client's session over from other nodes
+                                    // (sent in the background, so forwarding doesn't wait)
+                                    cluster_manager.announce_session(&client_id);
                                 }
                                 Ok(BrokerEvent::SubscriptionAdded { filter, client_id }) => {
                                     // Update cluster subscription state
@@ -1007,16 +2117,24 @@ impl Broker {
         // Spawn metrics collection task if metrics are enabled
         if let Some(ref metrics) = self.metrics {
             let metrics = metrics.clone();
+            let sessions = self.sessions.clone();
             let mut events_rx = self.events.subscribe();
             let mut shutdown_rx = self.shutdown.subscribe();
 
             info!("Starting metrics collection");
 
             tokio::spawn(async move {
+                // Gauges that are sampled rather than event-driven
+                let mut sampler = ThroughputSampler::new(&metrics);
+                let mut ticker = tokio::time::interval(METRICS_SAMPLE_INTERVAL);
                 loop {
                     tokio::select! {
                         biased;
 
+                        _ = ticker.tick() => {
+                            sampler.sample(&metrics);
+                            metrics.update_session_count(sessions.len());
+                        }
                         result = events_rx.recv() => {
                             match result {
                                 Ok(BrokerEvent::ClientConnected { protocol_version, .. }) => {
@@ -1043,6 +2161,10 @@ impl Broker {
                                 Ok(BrokerEvent::SubscriptionRemoved { .. }) => {
                                     metrics.subscription_removed();
                                 }
+                                Ok(BrokerEvent::SubscriptionRevoked { .. }) => {
+                                    metrics.subscription_revoked();
+                                }
+                                Ok(BrokerEvent::RetainedChanged { .. }) => {}
                                 Err(broadcast::error::RecvError::Lagged(n)) => {
                                     debug!("Metrics event listener lagged, missed {} events", n);
                                 }
@@ -1060,85 +2182,463 @@ impl Broker {
             });
         }
 
+        // Spawn STOMP listeners if configured
+        if let Some(stomp_config) = self.stomp.as_ref().filter(|_| !self.is_observer()) {
+            stomp::spawn_stomp_listeners(
+                Arc::new(self.clone_for_sys_topics()),
+                stomp_config.clone(),
+                self.metrics.clone(),
+            )?;
+        }
+
+        // Publish trace events to their $SYS/trace/<id> topics
+        let broker = self.clone_for_sys_topics();
+        let mut trace_events = self.tracer.subscribe();
+        let mut shutdown_rx = self.shutdown.subscribe();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    result = trace_events.recv() => match result {
+                        Ok(event) => {
+                            let Ok(payload) = serde_json::to_vec(&event) else {
+                                continue;
+                            };
+                            let topic = format!("{}{}", TRACE_TOPIC_PREFIX, event.trace);
+                            broker.publish(topic, Bytes::from(payload), QoS::AtMostOnce, false);
+                        }
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            debug!("Trace publisher lagged, missed {} events", n);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = shutdown_rx.recv() => break,
+                }
+            }
+        });
+
+        // Publish retained changes to the feed
+        if self.config.retained_feed.enabled {
+            self.spawn_retained_feed();
+        }
+
+        // Drop cached retained frames as their messages change
+        if self.retained_frames.is_enabled() {
+            self.spawn_retained_frame_invalidation();
+        }
+
+        // Route delayed publishes when they are due
+        if self.config.delayed.enabled {
+            self.spawn_delayed_delivery();
+        }
+
+        // Track the topics of recent traffic for the topic tree export
+        if self.config.topic_tree.recent_topics > 0 {
+            self.spawn_topic_activity();
+        }
+
         // Spawn $SYS topics publishing task if enabled
         if self.config.sys_topics_enabled {
             let broker = Arc::new(self.clone_for_sys_topics());
             let metrics = self.metrics.clone();
-            let interval_secs = self.config.sys_topics_interval.as_secs();
+            let interval = self.config.sys_topics_interval;
             let start_time = Instant::now();
             let shutdown_rx = self.shutdown.subscribe();
 
-            info!(
-                "Starting $SYS topics publisher (interval={}s)",
-                interval_secs
-            );
-            sys_topics::spawn_sys_topics_task(
-                broker,
-                metrics,
-                interval_secs,
-                start_time,
-                shutdown_rx,
-            );
+            info!("Starting $SYS topics publisher (interval={:?})", interval);
+            sys_topics::spawn_sys_topics_task(broker, metrics, interval, start_time, shutdown_rx);
         }
 
-        // Wait for Ctrl+C to trigger graceful shutdown
-        tokio::signal::ctrl_c()
-            .await
-            .expect("Failed to listen for Ctrl+C");
+        // Wait for Ctrl+C or SIGTERM to trigger graceful shutdown
+        shutdown_signal().await;
         info!("Received shutdown signal, shutting down...");
+        self.drain().await;
         self.shutdown();
         Ok(())
     }
 
-    /// Spawn the TCP accept loop as a separate task
-    fn spawn_tcp_accept_loop(&self, listener: TcpListener) {
+    /// Drain client connections ahead of a shutdown
+    ///
+    /// Stops accepting connections, then waits up to `shutdown.drain_timeout`
+    /// for connected clients' QoS 1/2 flows to complete. Clients are then
+    /// sent DISCONNECT "Server shutting down" (with the configured Server
+    /// Reference) and given a moment to close; the connections still open
+    /// afterwards close on [`shutdown`](Self::shutdown). Sessions that
+    /// outlive their connection are persisted as the clients disconnect.
+    pub async fn drain(&self) {
+        let config = self.live_config.read().shutdown.clone();
+        self.draining.send_replace(true);
+        info!(
+            "Draining {} connection(s) (timeout {:?})",
+            self.connections.len(),
+            config.drain_timeout
+        );
+
+        let deadline = Instant::now() + config.drain_timeout;
+        loop {
+            let inflight = self.connected_inflight();
+            if inflight == 0 {
+                break;
+            }
+            if Instant::now() >= deadline {
+                warn!("Drain timeout with {} message(s) still inflight", inflight);
+                break;
+            }
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+
+        if !config.disconnect {
+            return;
+        }
+        let disconnect = Disconnect {
+            reason_code: ReasonCode::ServerShuttingDown,
+            properties: Properties {
+                server_reference: config.server_reference.clone(),
+                ..Default::default()
+            },
+        };
+        for sender in self.connections.iter() {
+            let _ = sender.try_send(Packet::Disconnect(disconnect.clone()));
+        }
+        let closed_by = Instant::now() + DRAIN_CLOSE_GRACE;
+        while !self.connections.is_empty() && Instant::now() < closed_by {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Whether the broker stopped accepting connections to shut down
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// QoS 1/2 messages inflight in either direction for connected clients
+    fn connected_inflight(&self) -> usize {
+        let client_ids: Vec<Arc<str>> = self.connections.iter().map(|c| c.key().clone()).collect();
+        client_ids
+            .iter()
+            .filter_map(|client_id| self.sessions.get(client_id))
+            .map(|session| {
+                let s = session.read();
+                s.inflight_outgoing.len() + s.inflight_incoming.len()
+            })
+            .sum()
+    }
+
+    /// Build the TLS acceptor and, if configured, the handshake pool
+    fn load_tls(&self, tls_config: &TlsConfig) -> Result<TlsListenerSetup, std::io::Error> {
+        let tls_acceptor = match load_tls_config(tls_config) {
+            Ok(acceptor) => acceptor,
+            Err(e) => {
+                error!("Failed to load TLS configuration: {}", e);
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("TLS configuration error: {}", e),
+                ));
+            }
+        };
+
+        // Offload handshakes to a dedicated pool if configured
+        let handshake_pool = match tls_config.handshake_threads {
+            0 => None,
+            threads => {
+                let pool = TlsHandshakePool::new(
+                    threads,
+                    tls_config.handshake_queue_size,
+                    tls_config.handshake_timeout,
+                )?
+                .with_metrics(self.metrics.clone());
+                info!(
+                    "TLS handshake pool: {} thread(s), queue size {}",
+                    threads, tls_config.handshake_queue_size
+                );
+                Some(Arc::new(pool))
+            }
+        };
+
+        Ok((tls_acceptor, handshake_pool))
+    }
+
+    /// Spawn the WebSocket-over-TLS accept loop as a separate task
+    ///
+    /// Uses the WebSocket listener's settings; the TLS handshake runs after
+    /// the PROXY header and before the WebSocket upgrade.
+    fn spawn_wss_accept_loop(
+        &self,
+        listener: TcpListener,
+        tls_acceptor: TlsAcceptor,
+        handshake_pool: Option<Arc<TlsHandshakePool>>,
+        stop: broadcast::Sender<()>,
+    ) {
         let sessions = self.sessions.clone();
         let subscriptions = self.subscriptions.clone();
         let retained = self.retained.clone();
         let connections = self.connections.clone();
-        let config = self.config.clone();
+        let live_config = self.live_config.clone();
         let events = self.events.clone();
         let hooks = self.hooks.clone();
         let metrics = self.metrics.clone();
+        let tracer = self.tracer.clone();
+        let confirmations = self.confirmations.clone();
+        let listener_load = self.listener_load.clone();
+        let delayed = self.delayed.clone();
+        let topic_schema = self.topic_schema.clone();
+        let sequencer = self.sequencer.clone();
+        let priority = self.priority.clone();
+        let retained_frames = self.retained_frames.clone();
+        let tunables = self.tunables.clone();
+        let limiters = self.limiters.clone();
+        let maintenance = self.maintenance.clone();
         let persistence = self.persistence.clone();
-        let shutdown = self.shutdown.clone();
         let flapping_detector = self.flapping_detector.clone();
+        let mut stop_rx = stop.subscribe();
+        let mut draining = self.draining.subscribe();
 
         tokio::spawn(async move {
-            debug!("Starting TCP accept loop");
             loop {
-                match listener.accept().await {
-                    Ok((mut stream, addr)) => {
-                        debug!("New TCP connection from {}", addr);
+                let accepted = tokio::select! {
+                    accepted = listener.accept() => accepted,
+                    _ = stop_rx.recv() => break,
+                    _ = draining.wait_for(|draining| *draining) => break,
+                };
+                let (stream, addr) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("Failed to accept WebSocket/TLS connection: {}", e);
+                        continue;
+                    }
+                };
+                debug!("New WebSocket/TLS connection from {}", addr);
+                // Priority messages aren't held back to be coalesced
+                if priority.is_enabled() {
+                    let _ = stream.set_nodelay(true);
+                }
+                let sessions = sessions.clone();
+                let subscriptions = subscriptions.clone();
+                let retained = retained.clone();
+                let connections = connections.clone();
+                let config = live_config.read().clone();
+                let events = events.clone();
+                let hooks = hooks.clone();
+                let metrics = metrics.clone();
+                let tracer = tracer.clone();
+                let confirmations = confirmations.clone();
+                let listener_load = listener_load.clone();
+                let delayed = delayed.clone();
+                let topic_schema = topic_schema.clone();
+                let sequencer = sequencer.clone();
+                let priority = priority.clone();
+                let retained_frames = retained_frames.clone();
+                let tunables = tunables.clone();
+                let limiters = limiters.clone();
+                let maintenance = maintenance.clone();
+                let tls_acceptor = tls_acceptor.clone();
+                let handshake_pool = handshake_pool.clone();
+                let persistence = persistence.clone();
+                let flapping_detector = flapping_detector.clone();
+                let mut shutdown_rx = stop.subscribe();
+
+                tokio::spawn(async move {
+                    // Handle PROXY protocol before TLS handshake if enabled (trusted peers only)
+                    let (effective_addr, proxy_info, stream) = match accept_proxy_protocol(
+                        stream,
+                        addr.into(),
+                        &config.ws_proxy_protocol,
+                        "PROXY protocol (WSS)",
+                    )
+                    .await
+                    {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            debug!("PROXY protocol error from {}: {}", addr, e);
+                            if let Some(ref metrics) = metrics {
+                                metrics.proxy_protocol_error("wss");
+                            }
+                            return;
+                        }
+                    };
+
+                    // Check flapping/rate limits before any handshake
+                    if let (Some(detector), Some(client_ip)) =
+                        (&flapping_detector, effective_addr.ip())
+                    {
+                        if let Err(reason) = detector.check_connection(client_ip) {
+                            debug!(
+                                "Rejecting WebSocket/TLS connection from {}: {:?}",
+                                client_ip, reason
+                            );
+                            return;
+                        }
+                        detector.record_connection(client_ip);
+                    }
 
-                        // Handle PROXY protocol if enabled
-                        let (effective_addr, proxy_info) = if config.proxy_protocol.enabled {
-                            match parse_proxy_header(
-                                &mut stream,
-                                config.proxy_protocol.timeout,
-                                config.proxy_protocol.tls_termination,
+                    let handshake = match handshake_pool {
+                        Some(ref pool) => pool.accept(&tls_acceptor, stream).await,
+                        None => tls_acceptor.accept(stream).await,
+                    };
+                    let ws_stream = match handshake {
+                        Ok(tls_stream) => {
+                            let tls_info = client_tls_info(&tls_stream);
+                            WsStream::accept_with_limits(
+                                tls_stream,
+                                &config.ws_path,
+                                config.ws_max_frame_size,
                             )
                             .await
+                            .map(|ws_stream| (ws_stream, tls_info))
+                        }
+                        Err(e) => Err(e),
+                    };
+
+                    match ws_stream {
+                        Ok((ws_stream, tls_info)) => {
+                            debug!("WebSocket/TLS handshake complete for {}", effective_addr);
+                            let allow_mqtt31 = config.ws_allow_mqtt31;
+                            let max_qos = config.ws_listener_max_qos;
+                            let capabilities = config.ws_capabilities;
+                            let error_detail = config.ws_error_detail;
+                            let mut conn = Connection::new(
+                                ws_stream,
+                                effective_addr.clone(),
+                                proxy_info,
+                                sessions,
+                                subscriptions,
+                                retained,
+                                connections,
+                                config,
+                                events,
+                                hooks,
+                                metrics,
+                                persistence,
+                            )
+                            .with_mqtt31(allow_mqtt31)
+                            .with_max_qos(max_qos)
+                            .with_capabilities(capabilities)
+                            .with_error_detail(error_detail)
+                            .with_tls_info(tls_info)
+                            .with_listener("wss")
+                            .with_tracer(tracer)
+                            .with_confirmations(confirmations)
+                            .with_listener_load(listener_load)
+                            .with_delayed(delayed)
+                            .with_topic_schema(topic_schema)
+                            .with_sequencer(sequencer)
+                            .with_priority(priority)
+                            .with_retained_frames(retained_frames)
+                            .with_tunables(tunables)
+                            .with_limiters(limiters)
+                            .with_maintenance(maintenance);
+
                             {
-                                Ok((info, _remaining)) => {
-                                    debug!(
-                                        "PROXY protocol: {} -> {} (v{:?})",
-                                        addr, info.client_addr, info.version
-                                    );
-                                    (info.client_addr, Some(info))
+                                let conn_fut = conn.run();
+                                tokio::pin!(conn_fut);
+
+                                loop {
+                                    tokio::select! {
+                                        biased;
+
+                                        result = &mut conn_fut => {
+                                            if let Err(e) = result {
+                                                debug!("WebSocket/TLS connection error from {}: {}", effective_addr, e);
+                                            }
+                                            break;
+                                        }
+                                        result = shutdown_rx.recv() => {
+                                            match result {
+                                                Ok(()) => break,
+                                                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                                                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                                            }
+                                        }
+                                    }
                                 }
-                                Err(e) => {
-                                    debug!("PROXY protocol error from {}: {}", addr, e);
-                                    continue;
+                            }
+
+                            // Return buffers to the pool for reuse
+                            conn.return_buffers();
+                        }
+                        Err(e) => {
+                            debug!(
+                                "WebSocket/TLS handshake failed for {}: {}",
+                                effective_addr, e
+                            );
+                        }
+                    }
+
+                    // Track disconnection for flapping detection
+                    if let (Some(detector), Some(ip)) = (&flapping_detector, effective_addr.ip()) {
+                        detector.record_disconnection(ip);
+                    }
+                });
+            }
+            debug!("WebSocket/TLS accept loop stopped");
+        });
+    }
+
+    /// Spawn the TCP accept loop as a separate task
+    fn spawn_tcp_accept_loop(&self, listener: TcpListener, stop: broadcast::Sender<()>) {
+        let sessions = self.sessions.clone();
+        let subscriptions = self.subscriptions.clone();
+        let retained = self.retained.clone();
+        let connections = self.connections.clone();
+        let live_config = self.live_config.clone();
+        let events = self.events.clone();
+        let hooks = self.hooks.clone();
+        let metrics = self.metrics.clone();
+        let tracer = self.tracer.clone();
+        let confirmations = self.confirmations.clone();
+        let listener_load = self.listener_load.clone();
+        let delayed = self.delayed.clone();
+        let topic_schema = self.topic_schema.clone();
+        let sequencer = self.sequencer.clone();
+        let priority = self.priority.clone();
+        let retained_frames = self.retained_frames.clone();
+        let tunables = self.tunables.clone();
+        let limiters = self.limiters.clone();
+        let maintenance = self.maintenance.clone();
+        let persistence = self.persistence.clone();
+        let flapping_detector = self.flapping_detector.clone();
+        let mut stop_rx = stop.subscribe();
+        let mut draining = self.draining.subscribe();
+
+        tokio::spawn(async move {
+            debug!("Starting TCP accept loop");
+            loop {
+                let accepted = tokio::select! {
+                    accepted = listener.accept() => accepted,
+                    _ = stop_rx.recv() => break,
+                    _ = draining.wait_for(|draining| *draining) => break,
+                };
+                match accepted {
+                    Ok((stream, addr)) => {
+                        let config = live_config.read().clone();
+                        debug!("New TCP connection from {}", addr);
+                        // Priority messages aren't held back to be coalesced
+                        if priority.is_enabled() {
+                            let _ = stream.set_nodelay(true);
+                        }
+
+                        // Handle PROXY protocol if enabled (trusted peers only)
+                        let (effective_addr, proxy_info, stream) = match accept_proxy_protocol(
+                            stream,
+                            addr.into(),
+                            &config.proxy_protocol,
+                            "PROXY protocol",
+                        )
+                        .await
+                        {
+                            Ok(accepted) => accepted,
+                            Err(e) => {
+                                debug!("PROXY protocol error from {}: {}", addr, e);
+                                if let Some(ref metrics) = metrics {
+                                    metrics.proxy_protocol_error("tcp");
                                 }
+                                continue;
                             }
-                        } else {
-                            (addr, None)
                         };
 
                         // Check flapping/rate limits before spawning handler
-                        let client_ip = effective_addr.ip();
-                        if let Some(ref detector) = flapping_detector {
+                        if let (Some(detector), Some(client_ip)) =
+                            (&flapping_detector, effective_addr.ip())
+                        {
                             if let Err(reason) = detector.check_connection(client_ip) {
                                 debug!("Rejecting TCP connection from {}: {:?}", client_ip, reason);
                                 drop(stream);
@@ -1155,13 +2655,25 @@ impl Broker {
                             subscriptions.clone(),
                             retained.clone(),
                             connections.clone(),
-                            config.clone(),
+                            config,
                             events.clone(),
                             hooks.clone(),
                             metrics.clone(),
                             persistence.clone(),
-                            shutdown.clone(),
+                            tracer.clone(),
+                            confirmations.clone(),
+                            listener_load.clone(),
+                            delayed.clone(),
+                            topic_schema.clone(),
+                            sequencer.clone(),
+                            priority.clone(),
+                            retained_frames.clone(),
+                            tunables.clone(),
+                            limiters.clone(),
+                            maintenance.clone(),
+                            stop.clone(),
                             flapping_detector.clone(),
+                            "tcp",
                         );
                     }
                     Err(e) => {
@@ -1169,6 +2681,120 @@ impl Broker {
                     }
                 }
             }
+            debug!("TCP accept loop stopped");
+        });
+    }
+
+    /// Spawn the Unix domain socket accept loop as a separate task
+    ///
+    /// Local peers carry no IP, so they bypass the flapping detector unless
+    /// a PROXY header reports a TCP source.
+    #[cfg(unix)]
+    fn spawn_unix_accept_loop(&self, listener: UnixListener, stop: broadcast::Sender<()>) {
+        let sessions = self.sessions.clone();
+        let subscriptions = self.subscriptions.clone();
+        let retained = self.retained.clone();
+        let connections = self.connections.clone();
+        let live_config = self.live_config.clone();
+        let events = self.events.clone();
+        let hooks = self.hooks.clone();
+        let metrics = self.metrics.clone();
+        let tracer = self.tracer.clone();
+        let confirmations = self.confirmations.clone();
+        let listener_load = self.listener_load.clone();
+        let delayed = self.delayed.clone();
+        let topic_schema = self.topic_schema.clone();
+        let sequencer = self.sequencer.clone();
+        let priority = self.priority.clone();
+        let retained_frames = self.retained_frames.clone();
+        let tunables = self.tunables.clone();
+        let limiters = self.limiters.clone();
+        let maintenance = self.maintenance.clone();
+        let persistence = self.persistence.clone();
+        let flapping_detector = self.flapping_detector.clone();
+        let mut stop_rx = stop.subscribe();
+        let mut draining = self.draining.subscribe();
+
+        tokio::spawn(async move {
+            debug!("Starting Unix socket accept loop");
+            loop {
+                let accepted = tokio::select! {
+                    accepted = listener.accept() => accepted,
+                    _ = stop_rx.recv() => break,
+                    _ = draining.wait_for(|draining| *draining) => break,
+                };
+                match accepted {
+                    Ok((stream, addr)) => {
+                        let config = live_config.read().clone();
+                        let addr = PeerAddr::from(addr);
+                        debug!("New Unix socket connection from {}", addr);
+
+                        let (effective_addr, proxy_info, stream) = match accept_proxy_protocol(
+                            stream,
+                            addr.clone(),
+                            &config.unix_proxy_protocol,
+                            "PROXY protocol (Unix)",
+                        )
+                        .await
+                        {
+                            Ok(accepted) => accepted,
+                            Err(e) => {
+                                debug!("PROXY protocol error from {}: {}", addr, e);
+                                if let Some(ref metrics) = metrics {
+                                    metrics.proxy_protocol_error("unix");
+                                }
+                                continue;
+                            }
+                        };
+
+                        if let (Some(detector), Some(client_ip)) =
+                            (&flapping_detector, effective_addr.ip())
+                        {
+                            if let Err(reason) = detector.check_connection(client_ip) {
+                                debug!(
+                                    "Rejecting Unix socket connection from {}: {:?}",
+                                    client_ip, reason
+                                );
+                                continue;
+                            }
+                            detector.record_connection(client_ip);
+                        }
+
+                        spawn_connection_handler(
+                            stream,
+                            effective_addr,
+                            proxy_info,
+                            sessions.clone(),
+                            subscriptions.clone(),
+                            retained.clone(),
+                            connections.clone(),
+                            config,
+                            events.clone(),
+                            hooks.clone(),
+                            metrics.clone(),
+                            persistence.clone(),
+                            tracer.clone(),
+                            confirmations.clone(),
+                            listener_load.clone(),
+                            delayed.clone(),
+                            topic_schema.clone(),
+                            sequencer.clone(),
+                            priority.clone(),
+                            retained_frames.clone(),
+                            tunables.clone(),
+                            limiters.clone(),
+                            maintenance.clone(),
+                            stop.clone(),
+                            flapping_detector.clone(),
+                            "unix",
+                        );
+                    }
+                    Err(e) => {
+                        error!("Failed to accept Unix socket connection: {}", e);
+                    }
+                }
+            }
+            debug!("Unix socket accept loop stopped");
         });
     }
 
@@ -1177,6 +2803,11 @@ impl Broker {
         let _ = self.shutdown.send(());
     }
 
+    /// Receiver notified when the broker shuts down
+    pub fn subscribe_shutdown(&self) -> broadcast::Receiver<()> {
+        self.shutdown.subscribe()
+    }
+
     /// Subscribe to broker events
     pub fn subscribe_events(&self) -> broadcast::Receiver<BrokerEvent> {
         self.events.subscribe()
@@ -1187,6 +2818,136 @@ impl Broker {
         self.sessions.len()
     }
 
+    /// Clients with queued messages, deepest queue first
+    pub fn queue_depths(&self) -> Vec<QueueDepth> {
+        self.sessions.queue_depths()
+    }
+
+    /// Session store, for inspecting sessions (admin API)
+    pub fn sessions(&self) -> &Arc<SessionStore> {
+        &self.sessions
+    }
+
+    /// Whether a client is connected to this node
+    pub fn is_connected(&self, client_id: &str) -> bool {
+        self.connections.contains_key(client_id)
+    }
+
+    /// Disconnect a client with reason Administrative action
+    ///
+    /// With `discard_session` its session ends with the connection instead
+    /// of persisting until it expires. Returns false if it isn't connected.
+    pub async fn disconnect_client(&self, client_id: &str, discard_session: bool) -> bool {
+        let Some(sender) = self.connections.get(client_id).map(|s| s.value().clone()) else {
+            return false;
+        };
+        if discard_session {
+            if let Some(session) = self.sessions.get(client_id) {
+                // Ended on disconnect, subscriptions and stored state included
+                let mut s = session.write();
+                s.clean_start = true;
+                s.session_expiry_interval = 0;
+            }
+        }
+        info!("Disconnecting {} (administrative action)", client_id);
+        sender
+            .send(Packet::Disconnect(Disconnect {
+                reason_code: ReasonCode::AdministrativeAction,
+                properties: Properties::default(),
+            }))
+            .await
+            .is_ok()
+    }
+
+    /// End the session of a disconnected client
+    ///
+    /// Returns false if there is no such session or the client is connected.
+    pub fn discard_session(&self, client_id: &str) -> bool {
+        if self.connections.contains_key(client_id) || self.sessions.get(client_id).is_none() {
+            return false;
+        }
+        self.sessions.remove(client_id);
+        self.subscriptions.unsubscribe_all(client_id);
+        if let Some(ref persistence) = self.persistence {
+            persistence.write(PersistenceOp::DeleteSession {
+                client_id: client_id.to_string(),
+            });
+        }
+        info!("Discarded session of {}", client_id);
+        true
+    }
+
+    /// Hold a client's session for a successor (see `HandoverConfig`)
+    ///
+    /// Returns the token the successor connects with, or None if handover
+    /// is disabled or there is no such session.
+    pub fn prepare_handover(&self, client_id: &str) -> Option<Handover> {
+        let config = self.live_config.read().handover.clone();
+        if !config.enabled {
+            return None;
+        }
+        let session = self.sessions.get(client_id)?;
+        let handover = session.write().prepare_handover(config.token_ttl);
+        info!("Prepared handover of {}", client_id);
+        Some(handover)
+    }
+
+    /// Call off a session handover; returns false if none is under way
+    pub fn cancel_handover(&self, client_id: &str) -> bool {
+        let Some(session) = self.sessions.get(client_id) else {
+            return false;
+        };
+        let cancelled = {
+            let mut s = session.write();
+            s.pending_handover().is_some() && s.handover.take().is_some()
+        };
+        if cancelled {
+            info!("Cancelled handover of {}", client_id);
+        }
+        cancelled
+    }
+
+    /// Delete a retained message; returns false if the topic has none
+    pub fn delete_retained(&self, topic: &str) -> bool {
+        if self.retained.remove(topic).is_none() {
+            return false;
+        }
+        retained_feed::announce(&self.events, topic, RetainedChange::Deleted, None);
+        if let Some(ref persistence) = self.persistence {
+            persistence.write(PersistenceOp::DeleteRetained {
+                topic: topic.to_string(),
+            });
+        }
+        true
+    }
+
+    /// Publish a message from the server and announce it like a client's,
+    /// so bridges, cluster peers and `on_message_published` hooks see it
+    pub async fn publish_and_announce(
+        &self,
+        topic: String,
+        payload: Bytes,
+        qos: QoS,
+        retain: bool,
+    ) {
+        let user_properties = self.publish_with_properties(
+            topic.clone(),
+            payload.clone(),
+            qos,
+            retain,
+            Properties::default(),
+        );
+        let _ = self.events.send(BrokerEvent::MessagePublished {
+            topic: topic.clone(),
+            payload: payload.clone(),
+            qos,
+            retain,
+            origin: None,
+            user_properties,
+        });
+        self.hooks.on_message_published(&topic, &payload, qos).await;
+    }
+
     /// Get connection count
     pub fn connection_count(&self) -> usize {
         self.connections.len()
@@ -1202,23 +2963,130 @@ impl Broker {
         &self.retained
     }
 
+    /// Re-check every session's subscriptions against the hooks
+    ///
+    /// Call after ACLs change (e.g. `AclProvider::reload`); until then, only
+    /// new subscriptions see the new rules. Subscriptions that are no longer
+    /// authorized are removed and reported as `BrokerEvent::SubscriptionRevoked`.
+    /// With `AclRevocation::Disconnect`, affected clients that are online
+    /// are also disconnected with NotAuthorized.
+    ///
+    /// Returns the number of revoked subscriptions.
+    pub async fn reevaluate_subscriptions(&self, policy: AclRevocation) -> usize {
+        let mut revoked = 0;
+
+        for session in self.sessions.snapshot() {
+            let (client_id, username, filters) = {
+                let mut s = session.write();
+                s.decompress();
+                let filters: Vec<(Arc<str>, QoS)> = s
+                    .subscriptions
+                    .iter()
+                    .map(|(filter, sub)| (filter.clone(), sub.options.qos))
+                    .collect();
+                (s.client_id.clone(), s.username.clone(), filters)
+            };
+
+            let mut session_revoked = false;
+            for (filter, qos) in filters {
+                match self
+                    .hooks
+                    .on_subscribe_check(&client_id, username.as_deref(), &filter, qos)
+                    .await
+                {
+                    Ok(true) => continue,
+                    Ok(false) => {}
+                    Err(e) => {
+                        // Keep the subscription rather than revoke on a failed check
+                        warn!(
+                            "ACL re-check of '{}' for {} failed: {}",
+                            filter, client_id, e
+                        );
+                        continue;
+                    }
+                }
+
+                self.subscriptions.unsubscribe(&filter, &client_id);
+                session.write().remove_subscription(&filter);
+                info!("Revoked subscription '{}' of {}", filter, client_id);
+                let _ = self.events.send(BrokerEvent::SubscriptionRemoved {
+                    filter: filter.to_string(),
+                    client_id: client_id.clone(),
+                });
+                let _ = self.events.send(BrokerEvent::SubscriptionRevoked {
+                    filter: filter.to_string(),
+                    client_id: client_id.clone(),
+                });
+                revoked += 1;
+                session_revoked = true;
+            }
+
+            if !session_revoked {
+                continue;
+            }
+
+            if let Some(ref persistence) = self.persistence {
+                let s = session.read();
+                if !s.clean_start && s.session_expiry_interval > 0 {
+                    persistence.write(PersistenceOp::SetSession {
+                        client_id: client_id.to_string(),
+                        session: StoredSession::from_session(&s),
+                    });
+                }
+            }
+
+            if policy == AclRevocation::Disconnect {
+                if let Some(sender) = self.connections.get(&client_id) {
+                    let _ = sender.try_send(Packet::Disconnect(Disconnect {
+                        reason_code: ReasonCode::NotAuthorized,
+                        properties: Properties::default(),
+                    }));
+                }
+            }
+        }
+
+        revoked
+    }
+
+    /// In-process publish handle for rules and connectors, checked by ACLs
+    /// as `client_id` (see `LocalPublisher`)
+    pub fn local_publisher(&self, client_id: &str) -> LocalPublisher {
+        LocalPublisher::new(self, client_id)
+    }
+
     /// Publish a message from the server
     pub fn publish(&self, topic: String, payload: Bytes, qos: QoS, retain: bool) {
+        self.publish_with_properties(topic, payload, qos, retain, Properties::default());
+    }
+
+    /// Publish a message from the server with the given properties;
+    /// returns the user properties it was routed with (including its
+    /// sequence number, if numbered)
+    fn publish_with_properties(
+        &self,
+        topic: String,
+        payload: Bytes,
+        qos: QoS,
+        retain: bool,
+        properties: Properties,
+    ) -> Vec<(String, String)> {
         // Create a publish packet
-        let publish = Publish {
+        let mut publish = Publish {
             dup: false,
             qos,
             retain,
             topic: topic.clone(),
             packet_id: None,
             payload: payload.clone(),
-            properties: Properties::default(),
+            properties,
         };
 
         // Handle retained message
         if retain {
             if payload.is_empty() {
-                self.retained.remove(&topic);
+                if self.retained.remove(&topic).is_some() {
+                    retained_feed::announce(&self.events, &topic, RetainedChange::Deleted, None);
+                }
                 if let Some(ref persistence) = self.persistence {
                     persistence.write(PersistenceOp::DeleteRetained {
                         topic: topic.clone(),
@@ -1229,10 +3097,20 @@ impl Broker {
                     topic: topic.clone(),
                     payload,
                     qos,
-                    properties: Properties::default(),
+                    properties: publish.properties.clone(),
                     timestamp: Instant::now(),
+                    proxy_identity: None,
                 };
-                self.retained.insert(topic.clone(), retained_msg.clone());
+                let replaced = self
+                    .retained
+                    .insert(topic.clone(), retained_msg.clone())
+                    .is_some();
+                retained_feed::announce(
+                    &self.events,
+                    &topic,
+                    RetainedChange::stored(replaced),
+                    None,
+                );
                 if let Some(ref persistence) = self.persistence {
                     persistence.write(PersistenceOp::SetRetained {
                         topic: topic.clone(),
@@ -1242,7 +3120,16 @@ impl Broker {
             }
         }
 
-        // Route to subscribers
+        // Number the routed copy and give it a routing ID; the retained
+        // one stays unnumbered
+        if self.config.routing_ids {
+            routing_id::ensure(&mut publish.properties);
+        }
+        self.sequencer
+            .stamp(&mut publish, self.persistence.as_deref());
+
+        // Route to subscribers (no client published this, so sticky share
+        // groups fall back to round-robin)
         let matches = self.subscriptions.matches(&topic);
 
         // Deduplicate by client_id (keep highest QoS) - use AHashMap for faster lookup
@@ -1257,6 +3144,7 @@ impl Broker {
         }
 
         // Send to each client
+        let priority = self.priority.applies(&topic);
         for (client_id, sub_qos) in client_qos {
             let effective_qos = qos.min(sub_qos);
 
@@ -1264,6 +3152,14 @@ impl Broker {
                 let mut publish = publish.clone();
                 publish.qos = effective_qos;
 
+                // Priority messages skip the ordinary queue when there's room
+                if priority {
+                    match self.priority.try_send(&client_id, publish) {
+                        Ok(()) => continue,
+                        Err(rest) => publish = rest,
+                    }
+                }
+
                 // For QoS > 0, packet_id will be assigned by the connection handler
                 let _ = sender.try_send(Packet::Publish(publish));
             } else {
@@ -1278,6 +3174,8 @@ impl Broker {
                 }
             }
         }
+
+        publish.properties.user_properties
     }
 }
 
@@ -1287,11 +3185,69 @@ impl Default for Broker {
     }
 }
 
-/// Spawn a connection handler task for a new TCP connection
+/// Read the PROXY header for a new connection, if the listener expects one
+///
+/// Returns the effective client address, the parsed header, and the stream
+/// with any bytes read past the header (or, in optional mode, the bytes read
+/// while looking for one) queued for replay.
+///
+/// Unix socket peers have no IP to match against `trusted_networks`; access
+/// to the socket file is the trust boundary, so they are always trusted.
+async fn accept_proxy_protocol<S: AsyncRead + Unpin>(
+    mut stream: S,
+    addr: PeerAddr,
+    proxy_config: &ProxyProtocolConfig,
+    label: &str,
+) -> Result<(PeerAddr, Option<ProxyInfo>, Rewind<S>), ProxyError> {
+    let trusted = addr.ip().is_none_or(|ip| proxy_config.is_trusted(ip));
+    if !proxy_config.enabled || !trusted {
+        return Ok((addr, None, Rewind::new(stream)));
+    }
+
+    let (info, remaining) = if proxy_config.optional {
+        parse_optional_proxy_header(
+            &mut stream,
+            proxy_config.timeout,
+            proxy_config.tls_termination,
+        )
+        .await?
+    } else {
+        let (info, remaining) = parse_proxy_header(
+            &mut stream,
+            proxy_config.timeout,
+            proxy_config.tls_termination,
+        )
+        .await?;
+        (Some(info), remaining)
+    };
+
+    let effective_addr = match info {
+        Some(ref info) => {
+            match info.unique_id() {
+                Some(id) => debug!(
+                    "{}: {} -> {} (v{:?}, proxy id: {})",
+                    label, addr, info.client_addr, info.version, id
+                ),
+                None => debug!(
+                    "{}: {} -> {} (v{:?})",
+                    label, addr, info.client_addr, info.version
+                ),
+            }
+            info.client_addr.clone()
+        }
+        None => {
+            debug!("{}: no header from {}, treating as direct", label, addr);
+            addr
+        }
+    };
+    Ok((effective_addr, info, Rewind::with_prefix(stream, remaining)))
+}
+
+/// Spawn a connection handler task for a new TCP or Unix socket connection
 #[allow(clippy::too_many_arguments)]
-fn spawn_connection_handler(
-    stream: TcpStream,
-    addr: SocketAddr,
+fn spawn_connection_handler<S>(
+    stream: Rewind<S>,
+    addr: PeerAddr,
     proxy_info: Option<ProxyInfo>,
     sessions: Arc<SessionStore>,
     subscriptions: Arc<SubscriptionStore>,
@@ -1302,15 +3258,34 @@ fn spawn_connection_handler(
     hooks: Arc<dyn Hooks>,
     metrics: Option<Arc<Metrics>>,
     persistence: Option<Arc<crate::persistence::PersistenceManager>>,
+    tracer: Arc<Tracer>,
+    confirmations: Arc<Confirmations>,
+    listener_load: Arc<ListenerLoad>,
+    delayed: Arc<DelayedQueue>,
+    topic_schema: Arc<TopicSchema>,
+    sequencer: Arc<Sequencer>,
+    priority: Arc<PriorityLanes>,
+    retained_frames: Arc<RetainedFrames>,
+    tunables: Arc<Tunables>,
+    limiters: Arc<Limiters>,
+    maintenance: Arc<Maintenance>,
     shutdown: broadcast::Sender<()>,
     flapping_detector: Option<Arc<FlappingDetector>>,
-) {
+    listener: &'static str,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static,
+{
     let mut shutdown_rx = shutdown.subscribe();
 
+    let allow_mqtt31 = config.allow_mqtt31;
+    let max_qos = config.listener_max_qos;
+    let capabilities = config.capabilities;
+    let error_detail = config.error_detail;
+
     tokio::spawn(async move {
         let mut conn = Connection::new(
             stream,
-            addr,
+            addr.clone(),
             proxy_info,
             sessions,
             subscriptions,
@@ -1321,11 +3296,27 @@ fn spawn_connection_handler(
             hooks,
             metrics,
             persistence,
-        );
+        )
+        .with_mqtt31(allow_mqtt31)
+        .with_max_qos(max_qos)
+        .with_capabilities(capabilities)
+        .with_error_detail(error_detail)
+        .with_listener(listener)
+        .with_tracer(tracer)
+        .with_confirmations(confirmations)
+        .with_listener_load(listener_load)
+        .with_delayed(delayed)
+        .with_topic_schema(topic_schema)
+        .with_sequencer(sequencer)
+        .with_priority(priority)
+        .with_retained_frames(retained_frames)
+        .with_tunables(tunables)
+        .with_limiters(limiters)
+        .with_maintenance(maintenance);
 
         // Pin the connection future so we can poll it repeatedly
         {
-            let conn_fut = conn.run();
+            let conn_fut = crate::hooks::scope_listener(listener, conn.run());
             tokio::pin!(conn_fut);
 
             loop {
@@ -1362,18 +3353,68 @@ fn spawn_connection_handler(
         conn.return_buffers();
 
         // Track disconnection for flapping detection
-        if let Some(ref detector) = flapping_detector {
-            detector.record_disconnection(addr.ip());
+        if let (Some(detector), Some(ip)) = (&flapping_detector, addr.ip()) {
+            detector.record_disconnection(ip);
         }
     });
 }
 
+/// Wait for Ctrl+C or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    result = tokio::signal::ctrl_c() => result.expect("Failed to listen for Ctrl+C"),
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("Failed to listen for SIGTERM: {}", e),
+        }
+    }
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Failed to listen for Ctrl+C");
+}
+
+/// IPV6_V6ONLY setting for an IPv6 listener (None = leave the OS default)
+///
+/// Without an explicit `ipv6_only`, an IPv6 wildcard that shares its port
+/// with an IPv4 listener is made IPv6-only; otherwise, on dual-stack hosts,
+/// the two would conflict.
+fn ipv6_only_for(addr: SocketAddr, config: &BrokerConfig) -> Option<bool> {
+    if !addr.is_ipv6() {
+        return None;
+    }
+    config.ipv6_only.or_else(|| {
+        let shares_port = [
+            Some(config.bind_addr),
+            config.tls_bind_addr,
+            config.ws_bind_addr,
+            config.wss_bind_addr,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(config.extra_bind_addrs.iter().copied())
+        .any(|other| other.is_ipv4() && other.port() == addr.port());
+        (addr.ip().is_unspecified() && shares_port).then_some(true)
+    })
+}
+
 /// Create a TCP listener with a large backlog for burst connection handling.
 ///
 /// Uses socket2 to configure the socket before calling listen() with a backlog
 /// of 4096, allowing the kernel to queue many more pending connections during
 /// bursts of incoming connections.
-fn create_tcp_listener(addr: SocketAddr) -> Result<TcpListener, std::io::Error> {
+///
+/// IPv6 sockets get IPV6_V6ONLY per `ipv6_only_for`.
+fn create_tcp_listener(
+    addr: SocketAddr,
+    config: &BrokerConfig,
+) -> Result<TcpListener, std::io::Error> {
     let domain = if addr.is_ipv4() {
         Domain::IPV4
     } else {
@@ -1384,6 +3425,10 @@ fn create_tcp_listener(addr: SocketAddr) -> Result<TcpListener, std::io::Error>
     // Allow address reuse
     socket.set_reuse_address(true)?;
 
+    if let Some(only_v6) = ipv6_only_for(addr, config) {
+        socket.set_only_v6(only_v6)?;
+    }
+
     // Set non-blocking before converting to tokio
     socket.set_nonblocking(true)?;
 
@@ -1394,3 +3439,25 @@ fn create_tcp_listener(addr: SocketAddr) -> Result<TcpListener, std::io::Error>
     // Convert to tokio TcpListener
     TcpListener::from_std(socket.into())
 }
+
+/// Bind a Unix domain socket listener
+///
+/// A socket file left behind by a previous run is removed first; any other
+/// kind of file at the path is an error.
+#[cfg(unix)]
+fn create_unix_listener(path: &Path) -> Result<UnixListener, std::io::Error> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    UnixListener::bind(path)
+}
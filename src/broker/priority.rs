@@ -0,0 +1,130 @@
+//! Priority Topics
+//!
+//! Messages on topics matching `priority.topics` (device commands, control
+//! messages) must not wait behind telemetry. Each connection gets a second,
+//! small outbound channel besides its ordinary one, and routing puts these
+//! messages there: after at most `headroom` other priority messages instead
+//! of after `outbound_channel_capacity` ordinary ones. The connection serves
+//! both channels, so a priority message goes out as soon as the one being
+//! written is done. When the priority channel is full the message takes
+//! the ordinary channel rather than being dropped.
+//!
+//! Priority messages are also exempt from load shedding: a slow
+//! subscriber's `slow_client` policy doesn't apply to them, and their
+//! publishers aren't paused for it. TCP sockets are opened with
+//! TCP_NODELAY while priority topics are set, so a small command isn't held
+//! back to be coalesced with later writes (Nagle's algorithm).
+
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use tokio::sync::mpsc;
+
+use crate::config::PriorityConfig;
+use crate::protocol::{Packet, Publish};
+use crate::topic::topic_matches_filter;
+
+/// Priority channels of connected clients (see the module docs)
+#[derive(Default)]
+pub struct PriorityLanes {
+    config: PriorityConfig,
+    lanes: DashMap<Arc<str>, mpsc::Sender<Packet>>,
+}
+
+impl PriorityLanes {
+    pub fn new(config: &PriorityConfig) -> Self {
+        Self {
+            config: config.clone(),
+            lanes: DashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.is_enabled()
+    }
+
+    /// Messages each priority channel holds
+    pub fn headroom(&self) -> usize {
+        self.config.headroom.max(1)
+    }
+
+    /// Whether messages on `topic` take the priority path
+    pub fn applies(&self, topic: &str) -> bool {
+        self.config
+            .topics
+            .iter()
+            .any(|filter| topic_matches_filter(topic, filter))
+    }
+
+    /// Number of clients with a priority channel
+    pub fn len(&self) -> usize {
+        self.lanes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lanes.is_empty()
+    }
+
+    pub(crate) fn register(&self, client_id: Arc<str>, sender: mpsc::Sender<Packet>) {
+        self.lanes.insert(client_id, sender);
+    }
+
+    /// Remove a client's channel, unless a new connection registered its own
+    pub(crate) fn unregister(&self, client_id: &str, sender: &mpsc::Sender<Packet>) {
+        self.lanes
+            .remove_if(client_id, |_, registered| registered.same_channel(sender));
+    }
+
+    /// Queue a message on the client's priority channel; gives it back if
+    /// the client has none or it is full
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn try_send(&self, client_id: &str, publish: Publish) -> Result<(), Publish> {
+        let Some(sender) = self.lanes.get(client_id) else {
+            return Err(publish);
+        };
+        sender
+            .try_send(Packet::Publish(publish))
+            .map_err(|e| match e.into_inner() {
+                Packet::Publish(publish) => publish,
+                _ => unreachable!("only PUBLISH is sent on priority channels"),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_lanes() {
+        let lanes = PriorityLanes::new(&PriorityConfig {
+            topics: vec!["devices/+/cmd/#".to_string()],
+            headroom: 1,
+        });
+        assert!(lanes.applies("devices/7/cmd/reboot"));
+        assert!(!lanes.applies("devices/7/telemetry"));
+
+        let publish = |topic: &str| Publish {
+            topic: topic.to_string(),
+            ..Default::default()
+        };
+        let client: Arc<str> = "device-7".into();
+        assert!(lanes.try_send(&client, publish("a")).is_err());
+
+        let (tx, mut rx) = mpsc::channel(lanes.headroom());
+        lanes.register(client.clone(), tx.clone());
+        assert!(lanes.try_send(&client, publish("a")).is_ok());
+        // Full: handed back for the ordinary channel
+        assert_eq!(
+            lanes.try_send(&client, publish("b")).unwrap_err().topic,
+            "b"
+        );
+        assert!(matches!(rx.try_recv(), Ok(Packet::Publish(p)) if p.topic == "a"));
+
+        // A newer connection's channel stays registered
+        let (newer, _newer_rx) = mpsc::channel(1);
+        lanes.register(client.clone(), newer);
+        lanes.unregister(&client, &tx);
+        assert_eq!(lanes.len(), 1);
+    }
+}
@@ -0,0 +1,214 @@
+//! Replay Log
+//!
+//! With `sequence.replay.enabled`, the numbered messages on topics matching
+//! `sequence.replay.topics` are also kept here, the last `retain` per topic
+//! for up to `max_age`, so a subscriber that finds a sequence number skipped
+//! can ask for what it missed (see the connection's replay handling for the
+//! control protocol). This is what gives QoS 0 pipelines a way to recover:
+//! delivery stays fire-and-forget, and only the gaps cost extra.
+//!
+//! The log is in memory only; after a restart it starts out empty, and
+//! numbers from before it are reported as unavailable.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Instant;
+
+use dashmap::DashMap;
+
+use crate::config::ReplayConfig;
+use crate::protocol::Publish;
+use crate::topic::topic_matches_filter;
+
+/// A kept message
+#[derive(Debug, Clone)]
+struct Logged {
+    seq: u64,
+    at: Instant,
+    publish: Publish,
+}
+
+/// What a replay request gets
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    /// The kept messages in the range, oldest first, expiry counted down
+    pub messages: Vec<Publish>,
+    /// Numbers in the range no longer (or never) kept
+    pub unavailable: u64,
+    /// Whether the range was cut at `max_messages`; the rest follows the
+    /// last message replayed
+    pub more: bool,
+}
+
+/// Recent numbered messages per topic (see the module docs)
+#[derive(Default)]
+pub struct ReplayLog {
+    config: ReplayConfig,
+    topics: DashMap<String, VecDeque<Logged>>,
+    /// Start of each client's request window and the requests in it
+    requests: DashMap<Arc<str>, (Instant, u32)>,
+}
+
+impl ReplayLog {
+    pub fn new(config: &ReplayConfig) -> Self {
+        Self {
+            config: config.clone(),
+            topics: DashMap::new(),
+            requests: DashMap::new(),
+        }
+    }
+
+    /// Whether messages on `topic` may be replayed
+    pub fn applies(&self, topic: &str) -> bool {
+        self.config
+            .topics
+            .iter()
+            .any(|filter| topic_matches_filter(topic, filter))
+    }
+
+    /// Keep a numbered message, dropping the oldest past `retain` or
+    /// `max_age`
+    pub fn push(&self, seq: u64, publish: &Publish) {
+        let now = Instant::now();
+        let mut log = match self.topics.get_mut(&publish.topic) {
+            Some(log) => log,
+            None => self.topics.entry(publish.topic.clone()).or_default(),
+        };
+        while log.len() >= self.config.retain.max(1)
+            || log
+                .front()
+                .is_some_and(|oldest| now.duration_since(oldest.at) > self.config.max_age)
+        {
+            log.pop_front();
+        }
+        log.push_back(Logged {
+            seq,
+            at: now,
+            publish: publish.clone(),
+        });
+    }
+
+    /// Kept messages on `topic` numbered `from` to `to`
+    pub fn replay(&self, topic: &str, from: u64, to: u64) -> Replay {
+        let mut replay = Replay::default();
+        if from > to {
+            return replay;
+        }
+        let mut end = to;
+        if let Some(log) = self.topics.get(topic) {
+            let start = log.partition_point(|logged| logged.seq < from);
+            for logged in log.range(start..) {
+                if logged.seq > to {
+                    break;
+                }
+                let elapsed = logged.at.elapsed();
+                if elapsed > self.config.max_age {
+                    continue;
+                }
+                if replay.messages.len() >= self.config.max_messages {
+                    replay.more = true;
+                    end = logged.seq - 1;
+                    break;
+                }
+                let mut publish = logged.publish.clone();
+                if let Some(expiry) = publish.properties.message_expiry_interval {
+                    let elapsed = elapsed.as_secs() as u32;
+                    if elapsed >= expiry {
+                        continue;
+                    }
+                    publish.properties.message_expiry_interval = Some(expiry - elapsed);
+                }
+                replay.messages.push(publish);
+            }
+        }
+        replay.unavailable = (end - from + 1).saturating_sub(replay.messages.len() as u64);
+        replay
+    }
+
+    /// Count a request from `client_id`; false if it's over
+    /// `max_requests` per `window`
+    pub fn allow_request(&self, client_id: &Arc<str>) -> bool {
+        if self.config.max_requests == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        let window = self.config.window;
+        // Forget finished windows now and then
+        if self.requests.len() > 1024 {
+            self.requests
+                .retain(|_, (start, _)| now.duration_since(*start) < window);
+        }
+        let mut entry = self.requests.entry(client_id.clone()).or_insert((now, 0));
+        let (start, count) = &mut *entry;
+        if now.duration_since(*start) >= window {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= self.config.max_requests {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(retain: usize, max_messages: usize) -> ReplayLog {
+        ReplayLog::new(&ReplayConfig {
+            enabled: true,
+            retain,
+            max_messages,
+            max_requests: 2,
+            ..Default::default()
+        })
+    }
+
+    fn message(topic: &str, payload: &'static [u8]) -> Publish {
+        Publish {
+            topic: topic.to_string(),
+            payload: bytes::Bytes::from_static(payload),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_replay_range() {
+        let log = log(3, 10);
+        for (seq, payload) in [(1, b"a"), (2, b"b"), (3, b"c"), (4, b"d")] {
+            log.push(seq, &message("sensors/1", payload));
+        }
+        log.push(1, &message("sensors/2", b"x"));
+
+        // #1 fell out past `retain`
+        let replay = log.replay("sensors/1", 1, 3);
+        let payloads: Vec<_> = replay.messages.iter().map(|p| &p.payload[..]).collect();
+        assert_eq!(payloads, [b"b", b"c"]);
+        assert_eq!(replay.unavailable, 1);
+        assert!(!replay.more);
+
+        assert_eq!(log.replay("sensors/1", 4, 4).messages.len(), 1);
+        assert_eq!(log.replay("sensors/3", 1, 2).unavailable, 2);
+        assert!(log.replay("sensors/1", 5, 4).messages.is_empty());
+    }
+
+    #[test]
+    fn test_replay_limits() {
+        let log = log(100, 2);
+        for seq in 1..=5 {
+            log.push(seq, &message("sensors/1", b"x"));
+        }
+        let replay = log.replay("sensors/1", 2, 5);
+        assert_eq!(replay.messages.len(), 2);
+        assert!(replay.more);
+        assert_eq!(replay.unavailable, 0);
+
+        let client: Arc<str> = "c1".into();
+        assert!(log.allow_request(&client));
+        assert!(log.allow_request(&client));
+        assert!(!log.allow_request(&client));
+        assert!(log.allow_request(&"c2".into()));
+    }
+}
@@ -0,0 +1,457 @@
+//! Retained Store Administration
+//!
+//! Queries and bulk changes over the retained store, for managing many
+//! retained topics (device shadows, say) at once: listing the messages
+//! matching a wildcard filter page by page, deleting them, and exporting
+//! them as JSON to import into another broker (or the same one, after a
+//! reset). The admin API serves these under `/api/v1/retained`.
+//!
+//! An exported message keeps its remaining expiry interval, so it expires
+//! on the importing broker when it would have on the exporting one.
+//! Imported messages are stored, not delivered: current subscribers don't
+//! see them, new subscriptions do.
+//!
+//! Seeding loads a retained state tree (initial device states for a new
+//! environment, say) from a seed file: JSON Lines, one entry per line in
+//! the export format, with blank lines and `#` comments skipped. The whole
+//! file is validated before anything is stored, and a dry run reports what
+//! seeding would change without changing it. Seeds go through the admin API
+//! (`POST /api/v1/retained/seed`) into a running broker, or through
+//! `vibemq data seed` into a stopped broker's persistence store.
+
+use std::fmt;
+use std::time::Instant;
+
+use base64ct::{Base64, Encoding};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::retained_feed::{self, RetainedChange};
+use super::{Broker, RetainedMessage};
+use crate::persistence::{PersistenceOp, StoredRetainedMessage};
+use crate::protocol::{Properties, QoS};
+use crate::topic::{topic_matches_filter, validate_topic_filter, validate_topic_name};
+
+/// A retained message as JSON
+///
+/// The payload is `payload` if it is UTF-8 and `payload_base64` otherwise.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetainedEntry {
+    pub topic: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_base64: Option<String>,
+    #[serde(default)]
+    pub qos: u8,
+    /// Seconds left until the message expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_expiry_interval: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_format_indicator: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_topic: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_data_base64: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub user_properties: Vec<(String, String)>,
+}
+
+impl RetainedEntry {
+    /// The entry of a stored message, or None if it has expired
+    pub(crate) fn of(message: &RetainedMessage) -> Option<Self> {
+        let properties = &message.properties;
+        let elapsed = u32::try_from(message.timestamp.elapsed().as_secs()).unwrap_or(u32::MAX);
+        let message_expiry_interval = match properties.message_expiry_interval {
+            Some(expiry) if elapsed >= expiry => return None,
+            expiry => expiry.map(|e| e - elapsed),
+        };
+        let (payload, payload_base64) = match std::str::from_utf8(&message.payload) {
+            Ok(text) => (Some(text.to_string()), None),
+            Err(_) => (None, Some(Base64::encode_string(&message.payload))),
+        };
+        Some(Self {
+            topic: message.topic.clone(),
+            payload,
+            payload_base64,
+            qos: message.qos as u8,
+            message_expiry_interval,
+            payload_format_indicator: properties.payload_format_indicator,
+            content_type: properties.content_type.clone(),
+            response_topic: properties.response_topic.clone(),
+            correlation_data_base64: properties
+                .correlation_data
+                .as_ref()
+                .map(|data| Base64::encode_string(data)),
+            user_properties: properties.user_properties.clone(),
+        })
+    }
+
+    /// The message to store for an imported entry
+    pub fn to_message(&self) -> Result<RetainedMessage, &'static str> {
+        validate_topic_name(&self.topic)?;
+        let qos = QoS::from_u8(self.qos).ok_or("qos must be 0, 1, or 2")?;
+        let payload = match (&self.payload, &self.payload_base64) {
+            (Some(text), None) => Bytes::from(text.clone()),
+            (None, Some(encoded)) => {
+                Bytes::from(Base64::decode_vec(encoded).map_err(|_| "invalid payload_base64")?)
+            }
+            (None, None) => return Err("missing payload"),
+            (Some(_), Some(_)) => return Err("set only one of payload and payload_base64"),
+        };
+        if payload.is_empty() {
+            return Err("empty payload");
+        }
+        let correlation_data = match self.correlation_data_base64 {
+            Some(ref encoded) => Some(Bytes::from(
+                Base64::decode_vec(encoded).map_err(|_| "invalid correlation_data_base64")?,
+            )),
+            None => None,
+        };
+        Ok(RetainedMessage {
+            topic: self.topic.clone(),
+            payload,
+            qos,
+            properties: Properties {
+                payload_format_indicator: self.payload_format_indicator,
+                message_expiry_interval: self.message_expiry_interval,
+                content_type: self.content_type.clone(),
+                response_topic: self.response_topic.clone(),
+                correlation_data,
+                user_properties: self.user_properties.clone(),
+                ..Default::default()
+            },
+            timestamp: Instant::now(),
+            proxy_identity: None,
+        })
+    }
+}
+
+/// One page of retained messages, in topic order
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RetainedPage {
+    pub messages: Vec<RetainedEntry>,
+    /// Topic to continue after, if there are more messages
+    pub next: Option<String>,
+}
+
+/// What seeding stored (or, in a dry run, would store)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SeedReport {
+    /// Topics that had no retained message
+    pub created: usize,
+    /// Topics whose retained message was replaced
+    pub updated: usize,
+    pub dry_run: bool,
+}
+
+/// Parse and validate a seed file (JSON Lines of [`RetainedEntry`])
+pub fn parse_retained_seed(text: &str) -> Result<Vec<RetainedMessage>, RetainedError> {
+    let mut messages = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |reason: String| RetainedError::InvalidLine {
+            line: line_no,
+            reason,
+        };
+        let entry: RetainedEntry =
+            serde_json::from_str(line).map_err(|e| invalid(e.to_string()))?;
+        messages.push(entry.to_message().map_err(|e| invalid(e.to_string()))?);
+    }
+    Ok(messages)
+}
+
+/// Why a retained query or import was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetainedError {
+    InvalidFilter(&'static str),
+    /// An import entry is invalid; nothing was imported
+    InvalidEntry {
+        index: usize,
+        reason: &'static str,
+    },
+    /// A seed file line is invalid; nothing was seeded
+    InvalidLine {
+        line: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for RetainedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetainedError::InvalidFilter(e) => write!(f, "invalid filter: {}", e),
+            RetainedError::InvalidEntry { index, reason } => {
+                write!(f, "entry {}: {}", index, reason)
+            }
+            RetainedError::InvalidLine { line, reason } => {
+                write!(f, "line {}: {}", line, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RetainedError {}
+
+impl Broker {
+    /// Retained messages matching `filter`, at most `limit` of them with
+    /// topics after `after` (the previous page's `next`)
+    pub fn retained_matching(
+        &self,
+        filter: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<RetainedPage, RetainedError> {
+        validate_topic_filter(filter).map_err(RetainedError::InvalidFilter)?;
+        let mut topics: Vec<String> = self
+            .retained
+            .iter()
+            .filter(|entry| after.is_none_or(|after| entry.key().as_str() > after))
+            .filter(|entry| topic_matches_filter(entry.key(), filter))
+            .map(|entry| entry.key().clone())
+            .collect();
+        topics.sort_unstable();
+
+        // Only the page's messages are copied; expired ones are skipped
+        let mut page = RetainedPage::default();
+        for topic in topics {
+            if page.messages.len() == limit {
+                page.next = page.messages.last().map(|entry| entry.topic.clone());
+                break;
+            }
+            let entry = self.retained.get(&topic);
+            if let Some(entry) = entry.and_then(|message| RetainedEntry::of(&message)) {
+                page.messages.push(entry);
+            }
+        }
+        Ok(page)
+    }
+
+    /// All retained messages matching `filter`, in topic order
+    pub fn export_retained(&self, filter: &str) -> Result<Vec<RetainedEntry>, RetainedError> {
+        validate_topic_filter(filter).map_err(RetainedError::InvalidFilter)?;
+        let mut entries: Vec<_> = self
+            .retained
+            .iter()
+            .filter(|entry| topic_matches_filter(entry.key(), filter))
+            .filter_map(|entry| RetainedEntry::of(entry.value()))
+            .collect();
+        entries.sort_unstable_by(|a, b| a.topic.cmp(&b.topic));
+        Ok(entries)
+    }
+
+    /// Delete the retained messages matching `filter`; returns how many
+    pub fn delete_retained_matching(&self, filter: &str) -> Result<usize, RetainedError> {
+        validate_topic_filter(filter).map_err(RetainedError::InvalidFilter)?;
+        let topics: Vec<String> = self
+            .retained
+            .iter()
+            .filter(|entry| topic_matches_filter(entry.key(), filter))
+            .map(|entry| entry.key().clone())
+            .collect();
+        let deleted = topics
+            .iter()
+            .filter(|topic| self.delete_retained(topic))
+            .count();
+        info!("Deleted {} retained messages matching {}", deleted, filter);
+        Ok(deleted)
+    }
+
+    /// Store exported messages, replacing those on the same topics
+    ///
+    /// Every entry is checked first; if one is invalid, nothing is stored.
+    pub fn import_retained(&self, entries: &[RetainedEntry]) -> Result<usize, RetainedError> {
+        let messages = entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                entry
+                    .to_message()
+                    .map_err(|reason| RetainedError::InvalidEntry { index, reason })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let (report, ops) = self.store_retained_messages(messages, false);
+        if let Some(ref persistence) = self.persistence {
+            for op in ops {
+                persistence.write(op);
+            }
+        }
+        info!(
+            "Imported {} retained messages",
+            report.created + report.updated
+        );
+        Ok(report.created + report.updated)
+    }
+
+    /// Store the messages of a seed file (see [`parse_retained_seed`]), or with
+    /// `dry_run` only count what would change
+    pub async fn seed_retained(
+        &self,
+        seed: &str,
+        dry_run: bool,
+    ) -> Result<SeedReport, RetainedError> {
+        let messages = parse_retained_seed(seed)?;
+        let (report, ops) = self.store_retained_messages(messages, dry_run);
+        if let Some(ref persistence) = self.persistence {
+            persistence.write_all(ops).await;
+        }
+        if !dry_run {
+            info!(
+                "Seeded {} retained messages ({} new, {} replaced)",
+                report.created + report.updated,
+                report.created,
+                report.updated
+            );
+        }
+        Ok(report)
+    }
+
+    /// Store validated messages; returns what changed and the persistence
+    /// writes to make
+    fn store_retained_messages(
+        &self,
+        messages: Vec<RetainedMessage>,
+        dry_run: bool,
+    ) -> (SeedReport, Vec<PersistenceOp>) {
+        let mut report = SeedReport {
+            dry_run,
+            ..Default::default()
+        };
+        let mut ops = Vec::with_capacity(if dry_run { 0 } else { messages.len() });
+        for message in messages {
+            let replaced = if dry_run {
+                self.retained.contains_key(&message.topic)
+            } else {
+                if self.persistence.is_some() {
+                    ops.push(PersistenceOp::SetRetained {
+                        topic: message.topic.clone(),
+                        message: StoredRetainedMessage::from(&message),
+                    });
+                }
+                let topic = message.topic.clone();
+                let replaced = self.retained.insert(topic.clone(), message).is_some();
+                retained_feed::announce(
+                    &self.events,
+                    &topic,
+                    RetainedChange::stored(replaced),
+                    None,
+                );
+                replaced
+            };
+            if replaced {
+                report.updated += 1;
+            } else {
+                report.created += 1;
+            }
+        }
+        (report, ops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::BrokerConfig;
+
+    fn entry(topic: &str, payload: &str) -> RetainedEntry {
+        RetainedEntry {
+            topic: topic.to_string(),
+            payload: Some(payload.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_retained_administration() {
+        let broker = Broker::new(BrokerConfig::default());
+        let mut binary = entry("shadow/3", "");
+        binary.payload = None;
+        binary.payload_base64 = Some("/wA=".to_string());
+        binary.message_expiry_interval = Some(3600);
+        let entries = vec![
+            entry("shadow/2", "{}"),
+            entry("shadow/1", "{\"on\":true}"),
+            binary,
+            entry("alerts/1", "hot"),
+        ];
+        assert_eq!(broker.import_retained(&entries), Ok(4));
+
+        // Paged in topic order
+        let page = broker.retained_matching("shadow/+", None, 2).unwrap();
+        let topics: Vec<_> = page.messages.iter().map(|e| e.topic.as_str()).collect();
+        assert_eq!(topics, ["shadow/1", "shadow/2"]);
+        assert_eq!(page.next.as_deref(), Some("shadow/2"));
+        let page = broker
+            .retained_matching("shadow/+", page.next.as_deref(), 2)
+            .unwrap();
+        assert_eq!(page.messages.len(), 1);
+        assert_eq!(page.messages[0].payload_base64.as_deref(), Some("/wA="));
+        assert_eq!(page.messages[0].message_expiry_interval, Some(3600));
+        assert_eq!(page.next, None);
+
+        // Exported messages import back as they were
+        let exported = broker.export_retained("#").unwrap();
+        assert_eq!(exported.len(), 4);
+        let other = Broker::new(BrokerConfig::default());
+        assert_eq!(other.import_retained(&exported), Ok(4));
+        assert_eq!(other.export_retained("#").unwrap(), exported);
+
+        assert_eq!(broker.delete_retained_matching("shadow/#"), Ok(3));
+        assert_eq!(broker.retained_count(), 1);
+        assert!(matches!(
+            broker.retained_matching("shadow/#/x", None, 10),
+            Err(RetainedError::InvalidFilter(_))
+        ));
+
+        // One invalid entry stops the whole import
+        let invalid = vec![entry("shadow/9", "{}"), entry("shadow/+", "{}")];
+        assert!(matches!(
+            broker.import_retained(&invalid),
+            Err(RetainedError::InvalidEntry { index: 1, .. })
+        ));
+        assert_eq!(broker.retained_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retained_seed() {
+        let broker = Broker::new(BrokerConfig::default());
+        broker.import_retained(&[entry("shadow/1", "old")]).unwrap();
+        let seed = "# lamps\n\
+            {\"topic\": \"shadow/1\", \"payload\": \"{}\", \"qos\": 1}\n\
+            \n\
+            {\"topic\": \"shadow/2\", \"payload_base64\": \"/wA=\"}\n";
+        assert_eq!(parse_retained_seed(seed).unwrap().len(), 2);
+
+        // A dry run changes nothing
+        let report = broker.seed_retained(seed, true).await.unwrap();
+        assert_eq!(
+            (report.created, report.updated, report.dry_run),
+            (1, 1, true)
+        );
+        assert_eq!(broker.retained_count(), 1);
+
+        let report = broker.seed_retained(seed, false).await.unwrap();
+        assert_eq!((report.created, report.updated), (1, 1));
+        assert_eq!(broker.export_retained("shadow/1").unwrap()[0].qos, 1);
+
+        // Invalid lines are reported by number and nothing is stored
+        let invalid = "{\"topic\": \"shadow/3\", \"payload\": \"{}\"}\n{\"topic\": \"shadow/+\", \"payload\": \"x\"}\n";
+        assert!(matches!(
+            broker.seed_retained(invalid, false).await,
+            Err(RetainedError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse_retained_seed("{\"topic\": \"a\"\n"),
+            Err(RetainedError::InvalidLine { line: 1, .. })
+        ));
+        assert_eq!(broker.retained_count(), 2);
+    }
+}
@@ -0,0 +1,245 @@
+//! Retained Frame Cache
+//!
+//! A retained message on a hot topic (the configuration every device asks
+//! for when it connects) is delivered on nearly every SUBSCRIBE. Encoding
+//! it means sizing and writing its properties each time, for the same
+//! bytes. With `retained_cache.enabled` the delivered frame's header is
+//! kept per topic, protocol version, QoS and subscription identifier, and
+//! later deliveries copy it, patching in their packet identifier; the
+//! payload is written from the retained message as usual (see `frame`).
+//!
+//! A frame belongs to the retained message it was encoded from. Storing or
+//! deleting the topic's retained message drops its frames, and a frame
+//! whose message has been replaced meanwhile is never used: it is encoded
+//! again. MQTT 5 messages with an expiry interval aren't cached, since the
+//! interval they're sent with counts down.
+
+use std::time::Instant;
+
+use bytes::{Bytes, BytesMut};
+use dashmap::DashMap;
+use tokio::sync::broadcast;
+
+use super::{Broker, BrokerEvent, RetainedMessage};
+use crate::config::RetainedCacheConfig;
+use crate::protocol::{ProtocolVersion, QoS};
+
+/// How a retained message was encoded for a subscriber
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FrameKey {
+    pub version: ProtocolVersion,
+    pub qos: QoS,
+    pub subscription_id: Option<u32>,
+}
+
+/// An encoded PUBLISH header
+struct Frame {
+    key: FrameKey,
+    /// The retained message encoded, by store time and payload
+    stored: Instant,
+    payload: Bytes,
+    header: Bytes,
+    /// Where the packet identifier goes, for QoS > 0
+    packet_id_at: Option<usize>,
+}
+
+impl Frame {
+    fn encodes(&self, retained: &RetainedMessage) -> bool {
+        self.stored == retained.timestamp
+            && self.payload.len() == retained.payload.len()
+            && self.payload.as_ptr() == retained.payload.as_ptr()
+    }
+}
+
+/// Encoded headers of delivered retained messages (see the module docs)
+#[derive(Default)]
+pub struct RetainedFrames {
+    config: RetainedCacheConfig,
+    topics: DashMap<String, Vec<Frame>>,
+}
+
+impl RetainedFrames {
+    pub fn new(config: &RetainedCacheConfig) -> Self {
+        Self {
+            config: config.clone(),
+            topics: DashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Number of topics with cached frames
+    pub fn len(&self) -> usize {
+        self.topics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.topics.is_empty()
+    }
+
+    /// Whether the message's frame is the same for every delivery
+    pub(crate) fn cacheable(&self, retained: &RetainedMessage, version: ProtocolVersion) -> bool {
+        version != ProtocolVersion::V5 || retained.properties.message_expiry_interval.is_none()
+    }
+
+    /// Append the cached header of `retained` to `buf`, with `packet_id`;
+    /// false if there is none
+    pub(crate) fn header(
+        &self,
+        retained: &RetainedMessage,
+        key: FrameKey,
+        packet_id: Option<u16>,
+        buf: &mut BytesMut,
+    ) -> bool {
+        let Some(frames) = self.topics.get(&retained.topic) else {
+            return false;
+        };
+        let Some(frame) = frames
+            .iter()
+            .find(|frame| frame.key == key && frame.encodes(retained))
+        else {
+            return false;
+        };
+        let start = buf.len();
+        buf.extend_from_slice(&frame.header);
+        if let (Some(at), Some(packet_id)) = (frame.packet_id_at, packet_id) {
+            buf[start + at..start + at + 2].copy_from_slice(&packet_id.to_be_bytes());
+        }
+        true
+    }
+
+    /// Cache the header encoded for `retained`, replacing frames of any
+    /// message it has replaced
+    pub(crate) fn insert(&self, retained: &RetainedMessage, key: FrameKey, header: &[u8]) {
+        if !self.topics.contains_key(&retained.topic) && self.topics.len() >= self.config.max_topics
+        {
+            return;
+        }
+        let packet_id_at = (key.qos != QoS::AtMostOnce).then(|| {
+            // Fixed header byte, remaining length, then the topic
+            let length_bytes = header[1..].iter().take_while(|b| *b & 0x80 != 0).count() + 1;
+            1 + length_bytes + 2 + retained.topic.len()
+        });
+        let frame = Frame {
+            key,
+            stored: retained.timestamp,
+            payload: retained.payload.clone(),
+            header: Bytes::copy_from_slice(header),
+            packet_id_at,
+        };
+        let mut frames = self.topics.entry(retained.topic.clone()).or_default();
+        frames.retain(|cached| cached.key != key && cached.encodes(retained));
+        frames.push(frame);
+    }
+
+    /// Drop the frames of a topic whose retained message changed
+    pub fn invalidate(&self, topic: &str) {
+        self.topics.remove(topic);
+    }
+
+    pub fn clear(&self) {
+        self.topics.clear();
+    }
+}
+
+impl Broker {
+    /// Drop cached frames as retained messages change, until shutdown
+    pub(crate) fn spawn_retained_frame_invalidation(&self) {
+        let frames = self.retained_frames.clone();
+        let mut events = self.events.subscribe();
+        let mut shutdown_rx = self.shutdown.subscribe();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    result = events.recv() => match result {
+                        Ok(BrokerEvent::RetainedChanged { topic, .. }) => frames.invalidate(&topic),
+                        Ok(_) => {}
+                        // Frames of replaced messages aren't used anyway;
+                        // this only frees them
+                        Err(broadcast::error::RecvError::Lagged(_)) => frames.clear(),
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = shutdown_rx.recv() => break,
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Encoder;
+    use crate::protocol::{Properties, Publish};
+
+    fn retained(topic: &str, payload: &'static [u8]) -> RetainedMessage {
+        RetainedMessage {
+            topic: topic.to_string(),
+            payload: Bytes::from_static(payload),
+            qos: QoS::AtLeastOnce,
+            properties: Properties::default(),
+            timestamp: Instant::now(),
+            proxy_identity: None,
+        }
+    }
+
+    fn encode(retained: &RetainedMessage, key: FrameKey, packet_id: Option<u16>) -> BytesMut {
+        let publish = Publish {
+            dup: false,
+            qos: key.qos,
+            retain: true,
+            topic: retained.topic.clone(),
+            packet_id,
+            payload: retained.payload.clone(),
+            properties: retained.properties.clone(),
+        };
+        let mut buf = BytesMut::new();
+        Encoder::new(key.version)
+            .encode_publish_header(&publish, &mut buf)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_retained_frames() {
+        let frames = RetainedFrames::new(&RetainedCacheConfig {
+            enabled: true,
+            max_topics: 1,
+        });
+        let config = retained("devices/config", b"{\"interval\":30}");
+        let key = FrameKey {
+            version: ProtocolVersion::V5,
+            qos: QoS::AtLeastOnce,
+            subscription_id: None,
+        };
+
+        let mut buf = BytesMut::new();
+        assert!(!frames.header(&config, key, Some(1), &mut buf));
+        frames.insert(&config, key, &encode(&config, key, Some(1)));
+
+        // The cached header carries each delivery's packet identifier
+        assert!(frames.header(&config, key, Some(0x1234), &mut buf));
+        assert_eq!(buf, encode(&config, key, Some(0x1234)));
+
+        // Another encoding of the message isn't cached yet
+        let v3 = FrameKey {
+            version: ProtocolVersion::V311,
+            ..key
+        };
+        assert!(!frames.header(&config, v3, Some(1), &mut buf));
+
+        // A replacement message doesn't use the old frame
+        let replaced = retained("devices/config", b"{\"interval\":60}");
+        assert!(!frames.header(&replaced, key, Some(1), &mut buf));
+
+        // Full: other topics aren't cached
+        let other = retained("devices/firmware", b"1.2.3");
+        frames.insert(&other, key, &encode(&other, key, Some(1)));
+        assert_eq!(frames.len(), 1);
+
+        frames.invalidate("devices/config");
+        assert!(frames.is_empty());
+    }
+}
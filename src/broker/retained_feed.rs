@@ -0,0 +1,146 @@
+//! Retained Change Feed
+//!
+//! With `retained_feed.enabled`, every change to the retained store is
+//! published as JSON to "{topic}/{retained topic}" (QoS 1, not retained),
+//! so a service can mirror retained state incrementally: export the store
+//! once (`/api/v1/retained/export`), then apply changes as they come.
+//!
+//! ```json
+//! {"change": "updated", "topic": "shadow/lamp-1", "publisher": "lamp-1",
+//!  "timestamp_ms": 1714566900123,
+//!  "message": {"topic": "shadow/lamp-1", "payload": "{\"on\":true}", "qos": 1}}
+//! ```
+//!
+//! `change` is "created", "updated" or "deleted"; `message` is the stored
+//! message as in an export, left out for deletions and with
+//! `retained_feed.payload = false`. `publisher` is the client that sent the
+//! message (or whose will it was), absent when the broker itself stored it
+//! (admin API, cluster peers, server publishes). Messages that expire are
+//! not reported; their `message_expiry_interval` tells a mirror when.
+//!
+//! Changes reach the feed through the broker's event channel. If the feed
+//! falls behind and misses some, it publishes `{"lagged": <count>}` to the
+//! prefix topic itself, and mirrors should export again.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use super::{Broker, BrokerEvent, RetainedEntry};
+use crate::protocol::QoS;
+
+/// How a retained topic changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetainedChange {
+    /// A message was stored where there was none
+    Created,
+    /// A stored message was replaced
+    Updated,
+    /// The stored message was deleted
+    Deleted,
+}
+
+impl RetainedChange {
+    /// The change of storing a message, `replaced` if there was one
+    pub fn stored(replaced: bool) -> Self {
+        if replaced {
+            RetainedChange::Updated
+        } else {
+            RetainedChange::Created
+        }
+    }
+}
+
+/// A change as published on the feed
+#[derive(Debug, Serialize)]
+struct FeedMessage<'a> {
+    change: RetainedChange,
+    topic: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    publisher: Option<&'a str>,
+    timestamp_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<RetainedEntry>,
+}
+
+impl Broker {
+    /// Publish retained changes to the feed until shutdown
+    pub(crate) fn spawn_retained_feed(&self) {
+        let config = self.config.retained_feed.clone();
+        let broker = self.clone_for_sys_topics();
+        let mut events = self.events.subscribe();
+        let mut shutdown_rx = self.shutdown.subscribe();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    result = events.recv() => match result {
+                        Ok(BrokerEvent::RetainedChanged { topic, change, publisher }) => {
+                            // The message as it is now: a later change
+                            // follows if it has changed again since
+                            let message = match change {
+                                RetainedChange::Deleted => None,
+                                _ if !config.payload => None,
+                                _ => match broker.retained.get(&topic) {
+                                    Some(stored) => RetainedEntry::of(&stored),
+                                    None => continue,
+                                },
+                            };
+                            let timestamp_ms = SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .map(|d| d.as_millis() as u64)
+                                .unwrap_or(0);
+                            let feed = FeedMessage {
+                                change,
+                                topic: &topic,
+                                publisher: publisher.as_deref(),
+                                timestamp_ms,
+                                message,
+                            };
+                            let Ok(payload) = serde_json::to_vec(&feed) else {
+                                continue;
+                            };
+                            broker.publish(
+                                config.change_topic(&topic),
+                                Bytes::from(payload),
+                                QoS::AtLeastOnce,
+                                false,
+                            );
+                        }
+                        Ok(_) => {}
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            warn!("Retained change feed lagged, missed {} changes", n);
+                            let payload = serde_json::json!({ "lagged": n }).to_string();
+                            broker.publish(
+                                config.topic.clone(),
+                                Bytes::from(payload),
+                                QoS::AtLeastOnce,
+                                false,
+                            );
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = shutdown_rx.recv() => break,
+                }
+            }
+        });
+    }
+}
+
+/// Report a retained change on the broker's event channel
+pub(crate) fn announce(
+    events: &broadcast::Sender<BrokerEvent>,
+    topic: &str,
+    change: RetainedChange,
+    publisher: Option<&Arc<str>>,
+) {
+    let _ = events.send(BrokerEvent::RetainedChanged {
+        topic: topic.to_string(),
+        change,
+        publisher: publisher.cloned(),
+    });
+}
@@ -0,0 +1,72 @@
+//! Message Routing IDs
+//!
+//! Reconstructing what happened to one message during an incident means
+//! following it through subsystems that each log their own view: the
+//! connection that routed it, the subscribers it was delivered to or dropped
+//! for, the rules that exported or dead-lettered it, the bridges that
+//! forwarded it. With `mqtt.routing_ids`, every message the broker takes in
+//! gets an ID (from the broker-wide [`crate::id`] generator) in the
+//! `x-vibemq-routing-id` user property, and each of them reports it:
+//!
+//! - routing logs (`routing_id` field) and the message's outgoing trace
+//!   events;
+//! - deliveries, which carry the property (MQTT 5 subscribers see it);
+//! - rule webhook records and dead-letter entries (`routing_id` field);
+//! - bridge forwards, which pass the property on to the remote broker.
+//!
+//! A client's PUBLISH always gets a fresh ID, replacing any it set. A
+//! message published in reaction to another (a rule's republish or
+//! dead-letter entry) keeps the ID of its cause, so its whole chain shares
+//! one ID.
+
+use crate::protocol::Properties;
+
+/// User property carrying a message's routing ID
+pub const ROUTING_ID_PROPERTY: &str = "x-vibemq-routing-id";
+
+/// Routing ID of a message, if it has one
+pub fn routing_id(properties: &Properties) -> Option<&str> {
+    properties
+        .user_properties
+        .iter()
+        .find(|(key, _)| key == ROUTING_ID_PROPERTY)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Give a message a new routing ID, replacing any it has
+pub(crate) fn assign(properties: &mut Properties) {
+    properties
+        .user_properties
+        .retain(|(key, _)| key != ROUTING_ID_PROPERTY);
+    properties
+        .user_properties
+        .push((ROUTING_ID_PROPERTY.to_string(), crate::id::next_id()));
+}
+
+/// Give a message a routing ID unless it carries one
+pub(crate) fn ensure(properties: &mut Properties) {
+    if routing_id(properties).is_none() {
+        assign(properties);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routing_ids() {
+        let mut properties = Properties::default();
+        assert_eq!(routing_id(&properties), None);
+
+        ensure(&mut properties);
+        let first = routing_id(&properties).unwrap().to_string();
+        ensure(&mut properties);
+        assert_eq!(routing_id(&properties), Some(first.as_str()));
+
+        // A publisher's own ID is replaced
+        assign(&mut properties);
+        assert_ne!(routing_id(&properties), Some(first.as_str()));
+        assert_eq!(properties.user_properties.len(), 1);
+    }
+}
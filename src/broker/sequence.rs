@@ -0,0 +1,235 @@
+//! Message Sequence Numbers
+//!
+//! With `sequence.enabled`, every message routed on a topic matching
+//! `sequence.topics` carries a user property (`sequence.property`, "x-seq"
+//! by default) with a number counting up from 1 per topic. All subscribers
+//! see the same number for a message, so one that finds a number skipped
+//! knows it missed a message (e.g. a QoS 0 message dropped on a full
+//! queue), and a bridge or rule downstream can tell as well.
+//!
+//! Numbers are assigned where messages are routed, so the retained copy of
+//! a message isn't numbered: it's a snapshot a subscriber gets on
+//! subscribing, not part of the stream.
+//!
+//! Counters survive restarts with persistence enabled. Rather than writing
+//! every number, the broker reserves `sequence.reserve` numbers at a time
+//! and stores the reservation; the next one is stored when half of it is
+//! used, well before it runs out. After a restart a topic continues from
+//! its stored reservation, so numbers never repeat; an unclean shutdown
+//! shows up as a jump (a gap with nothing missing) of at most `reserve`.
+//!
+//! With `sequence.replay` enabled, numbered messages are also kept in a
+//! [`ReplayLog`] so missed ones can be asked for again.
+
+use dashmap::DashMap;
+
+use super::replay::ReplayLog;
+use crate::config::SequenceConfig;
+use crate::persistence::{PersistenceManager, PersistenceOp, StoredSequence};
+use crate::protocol::Publish;
+use crate::topic::topic_matches_filter;
+
+/// A topic's counter
+#[derive(Debug, Clone, Copy, Default)]
+struct Counter {
+    /// Last number assigned (0 = none yet)
+    last: u64,
+    /// Highest number stored as possibly assigned
+    reserved: u64,
+}
+
+/// Per-topic sequence counters (see the module docs)
+#[derive(Default)]
+pub struct Sequencer {
+    config: SequenceConfig,
+    counters: DashMap<String, Counter>,
+    replay: Option<ReplayLog>,
+}
+
+impl Sequencer {
+    pub fn new(config: &SequenceConfig) -> Self {
+        Self {
+            config: config.clone(),
+            counters: DashMap::new(),
+            replay: (config.enabled && config.replay.enabled)
+                .then(|| ReplayLog::new(&config.replay)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Log of numbered messages, if they may be replayed
+    pub fn replay(&self) -> Option<&ReplayLog> {
+        self.replay.as_ref()
+    }
+
+    /// User property carrying the numbers
+    pub fn property(&self) -> &str {
+        &self.config.property
+    }
+
+    /// Whether messages on `topic` are numbered
+    pub fn applies(&self, topic: &str) -> bool {
+        self.config.enabled
+            && self
+                .config
+                .topics
+                .iter()
+                .any(|filter| topic_matches_filter(topic, filter))
+    }
+
+    /// Continue a topic's numbering after its stored reservation
+    pub fn restore(&self, topic: String, stored: StoredSequence) {
+        let mut counter = self.counters.entry(topic).or_default();
+        if stored.reserved > counter.reserved {
+            *counter = Counter {
+                last: stored.reserved,
+                reserved: stored.reserved,
+            };
+        }
+    }
+
+    /// Last number assigned on `topic`
+    pub fn last(&self, topic: &str) -> Option<u64> {
+        self.counters
+            .get(topic)
+            .map(|counter| counter.last)
+            .filter(|last| *last > 0)
+    }
+
+    /// Assign the next number on `topic`, storing a new reservation when
+    /// half of the current one is used
+    fn next(&self, topic: &str, persistence: Option<&PersistenceManager>) -> u64 {
+        let mut counter = match self.counters.get_mut(topic) {
+            Some(counter) => counter,
+            None => self.counters.entry(topic.to_string()).or_default(),
+        };
+        counter.last += 1;
+        let last = counter.last;
+        let reserve = self.config.reserve.max(1);
+        if counter.reserved.saturating_sub(last) < reserve.div_ceil(2) {
+            counter.reserved = last + reserve;
+            if let Some(persistence) = persistence {
+                persistence.write(PersistenceOp::SetSequence {
+                    topic: topic.to_string(),
+                    sequence: StoredSequence {
+                        reserved: counter.reserved,
+                    },
+                });
+            }
+        }
+        last
+    }
+
+    /// Number a message if its topic is numbered, replacing any number the
+    /// publisher set; returns the number
+    pub fn stamp(
+        &self,
+        publish: &mut Publish,
+        persistence: Option<&PersistenceManager>,
+    ) -> Option<u64> {
+        if !self.applies(&publish.topic) {
+            return None;
+        }
+        let seq = self.next(&publish.topic, persistence);
+        let properties = &mut publish.properties.user_properties;
+        properties.retain(|(name, _)| *name != self.config.property);
+        properties.push((self.config.property.clone(), seq.to_string()));
+        if let Some(ref log) = self.replay {
+            if log.applies(&publish.topic) {
+                log.push(seq, publish);
+            }
+        }
+        Some(seq)
+    }
+
+    /// A numbered copy of a message, or None if its topic isn't numbered
+    pub(crate) fn stamped(
+        &self,
+        publish: &Publish,
+        persistence: Option<&PersistenceManager>,
+    ) -> Option<Publish> {
+        if !self.applies(&publish.topic) {
+            return None;
+        }
+        let mut publish = publish.clone();
+        self.stamp(&mut publish, persistence);
+        Some(publish)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequencer(reserve: u64) -> Sequencer {
+        Sequencer::new(&SequenceConfig {
+            enabled: true,
+            topics: vec!["sensors/#".to_string()],
+            reserve,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_stamp() {
+        let sequencer = sequencer(1000);
+        let mut publish = Publish {
+            topic: "sensors/1".to_string(),
+            ..Default::default()
+        };
+        // A number set by the publisher is replaced
+        publish
+            .properties
+            .user_properties
+            .push(("x-seq".to_string(), "77".to_string()));
+        assert_eq!(sequencer.stamp(&mut publish.clone(), None), Some(1));
+        assert_eq!(sequencer.stamp(&mut publish, None), Some(2));
+        assert_eq!(
+            publish.properties.user_properties,
+            [("x-seq".to_string(), "2".to_string())]
+        );
+
+        // Topics count separately; others aren't numbered
+        let mut other = Publish {
+            topic: "sensors/2".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(sequencer.stamp(&mut other, None), Some(1));
+        other.topic = "commands/1".to_string();
+        assert!(sequencer.stamped(&other, None).is_none());
+        assert_eq!(sequencer.last("sensors/1"), Some(2));
+        assert_eq!(sequencer.last("commands/1"), None);
+
+        let disabled = Sequencer::new(&SequenceConfig::default());
+        assert!(!disabled.applies("sensors/1"));
+    }
+
+    #[test]
+    fn test_restore_continues_after_reservation() {
+        let sequencer = sequencer(10);
+        let topic = "sensors/1";
+        for expected in 1..=3 {
+            assert_eq!(sequencer.next(topic, None), expected);
+        }
+        // Reserved up to 11 by the first number
+        assert_eq!(sequencer.counters.get(topic).unwrap().reserved, 11);
+        for _ in 4..=6 {
+            sequencer.next(topic, None);
+        }
+        assert_eq!(sequencer.counters.get(topic).unwrap().reserved, 11);
+        // Less than half of the reservation left: extended
+        sequencer.next(topic, None);
+        assert_eq!(sequencer.counters.get(topic).unwrap().reserved, 17);
+
+        let restarted = self::sequencer(10);
+        restarted.restore(topic.to_string(), StoredSequence { reserved: 17 });
+        assert_eq!(restarted.last(topic), Some(17));
+        assert_eq!(restarted.next(topic, None), 18);
+        // An older stored reservation doesn't move the counter back
+        restarted.restore(topic.to_string(), StoredSequence { reserved: 11 });
+        assert_eq!(restarted.next(topic, None), 19);
+    }
+}
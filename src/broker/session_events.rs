@@ -0,0 +1,238 @@
+//! Session Expiry Events
+//!
+//! With `session.expiry_events.enabled`, every persistent session the
+//! broker garbage collects (its expiry interval elapsed, or it overflowed
+//! its queue with `queue_overflow = "disconnect"`) is announced on
+//! `session.expiry_events.topic` as a JSON event at QoS 2:
+//!
+//! ```json
+//! {"event_id": "0190...", "client_id": "sensor-17", "username": "fleet",
+//!  "expired_at_ms": 1714564800000, "expiry_interval_secs": 3600,
+//!  "reason": "expired", "discarded_messages": 12, "discarded_inflight": 1}
+//! ```
+//!
+//! Provisioning systems subscribe to it (or have a rule forward it to a
+//! webhook, or a bridge to another broker) to deregister devices instead of
+//! inferring expiry from silence.
+//!
+//! With persistence, the event is stored along with the session's removal
+//! and deleted once published, so a crash in between publishes it again at
+//! the next start. A subscriber thus gets each event once, and after a
+//! crash possibly again under the same `event_id`: consumers dedupe on
+//! `event_id` for exactly-once handling.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use super::{Broker, BrokerEvent};
+use crate::persistence::{PersistenceOp, StoredSessionEvent};
+use crate::protocol::{Properties, QoS};
+use crate::session::ExpiredSession;
+
+/// Why a session was removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryReason {
+    /// The session expiry interval elapsed
+    Expired,
+    /// The session's queue overflowed with `queue_overflow = "disconnect"`
+    QueueOverflow,
+}
+
+/// A persistent session garbage collected by the broker
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionExpiryEvent {
+    /// Stable ID, the same if the event is published again after a crash
+    pub event_id: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    /// Unix timestamp in milliseconds when the session was removed
+    pub expired_at_ms: u64,
+    pub expiry_interval_secs: u32,
+    pub reason: ExpiryReason,
+    /// Queued messages discarded with the session
+    pub discarded_messages: u64,
+    /// Unacknowledged outgoing messages discarded with the session
+    pub discarded_inflight: u64,
+}
+
+impl SessionExpiryEvent {
+    /// The event for a session removed now
+    pub fn new(expired: &ExpiredSession) -> Self {
+        Self {
+            event_id: crate::id::next_id(),
+            client_id: expired.client_id.to_string(),
+            username: expired.username.as_deref().map(str::to_string),
+            expired_at_ms: now_ms(),
+            expiry_interval_secs: expired.session_expiry_interval,
+            reason: if expired.overflowed {
+                ExpiryReason::QueueOverflow
+            } else {
+                ExpiryReason::Expired
+            },
+            discarded_messages: expired.pending_messages as u64,
+            discarded_inflight: expired.inflight_messages as u64,
+        }
+    }
+
+    /// An event loaded from persistence
+    pub fn restore(event_id: String, stored: StoredSessionEvent) -> Self {
+        Self {
+            event_id,
+            client_id: stored.client_id,
+            username: stored.username,
+            expired_at_ms: stored.expired_at_ms,
+            expiry_interval_secs: stored.expiry_interval_secs,
+            reason: if stored.overflowed {
+                ExpiryReason::QueueOverflow
+            } else {
+                ExpiryReason::Expired
+            },
+            discarded_messages: stored.discarded_messages,
+            discarded_inflight: stored.discarded_inflight,
+        }
+    }
+
+    fn stored(&self) -> StoredSessionEvent {
+        StoredSessionEvent {
+            client_id: self.client_id.clone(),
+            username: self.username.clone(),
+            expired_at_ms: self.expired_at_ms,
+            expiry_interval_secs: self.expiry_interval_secs,
+            overflowed: self.reason == ExpiryReason::QueueOverflow,
+            discarded_messages: self.discarded_messages,
+            discarded_inflight: self.discarded_inflight,
+        }
+    }
+}
+
+impl Broker {
+    /// Hold session expiry events loaded from persistence until the broker
+    /// runs; kept in storage while events are disabled
+    pub fn restore_session_events(&self, events: Vec<(String, StoredSessionEvent)>) {
+        if events.is_empty() {
+            return;
+        }
+        if !self.config.session_expiry_events.enabled {
+            warn!(
+                "{} session expiry events are stored but expiry events are disabled; \
+                 they are kept until it is enabled",
+                events.len()
+            );
+            return;
+        }
+        self.pending_session_events.lock().extend(
+            events
+                .into_iter()
+                .map(|(event_id, stored)| SessionExpiryEvent::restore(event_id, stored)),
+        );
+    }
+
+    /// Finish removing sessions the session store found expired: drop their
+    /// subscriptions and stored state, and announce the persistent ones
+    pub(crate) async fn reap_sessions(&self, expired: Vec<ExpiredSession>) {
+        let enabled = self.config.session_expiry_events.enabled;
+        let mut events = std::mem::take(&mut *self.pending_session_events.lock());
+        for session in expired {
+            // A client may have reconnected since
+            if self.sessions.get(&session.client_id).is_none() {
+                self.subscriptions.unsubscribe_all(&session.client_id);
+            }
+            let event = (enabled && session.session_expiry_interval > 0)
+                .then(|| SessionExpiryEvent::new(&session));
+            if let Some(ref persistence) = self.persistence {
+                // Written ahead of the removal, so no removal goes unannounced
+                if let Some(ref event) = event {
+                    persistence.write(PersistenceOp::SetSessionEvent {
+                        event_id: event.event_id.clone(),
+                        event: event.stored(),
+                    });
+                }
+                persistence.write(PersistenceOp::DeleteSession {
+                    client_id: session.client_id.to_string(),
+                });
+            }
+            events.extend(event);
+        }
+        for event in events {
+            self.publish_session_event(event).await;
+        }
+    }
+
+    async fn publish_session_event(&self, event: SessionExpiryEvent) {
+        let payload = match serde_json::to_vec(&event) {
+            Ok(payload) => Bytes::from(payload),
+            Err(e) => {
+                warn!("Failed to encode session expiry event: {}", e);
+                return;
+            }
+        };
+        debug!(
+            "Session of {} expired ({} queued message(s) discarded)",
+            event.client_id, event.discarded_messages
+        );
+        let topic = self.config.session_expiry_events.topic.clone();
+        let user_properties = self.publish_with_properties(
+            topic.clone(),
+            payload.clone(),
+            QoS::ExactlyOnce,
+            false,
+            Properties::default(),
+        );
+        let _ = self.events.send(BrokerEvent::MessagePublished {
+            topic: topic.clone(),
+            payload: payload.clone(),
+            qos: QoS::ExactlyOnce,
+            retain: false,
+            origin: None,
+            user_properties,
+        });
+        self.hooks
+            .on_message_published(&topic, &payload, QoS::ExactlyOnce)
+            .await;
+        if let Some(ref persistence) = self.persistence {
+            persistence.write(PersistenceOp::DeleteSessionEvent {
+                event_id: event.event_id,
+            });
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_expiry_event() {
+        let expired = ExpiredSession {
+            client_id: "sensor-17".into(),
+            username: Some("fleet".into()),
+            session_expiry_interval: 3600,
+            overflowed: true,
+            pending_messages: 12,
+            inflight_messages: 1,
+        };
+        let event = SessionExpiryEvent::new(&expired);
+        assert_eq!(event.reason, ExpiryReason::QueueOverflow);
+        assert_eq!(event.discarded_messages, 12);
+
+        // The stored event comes back under the same ID
+        let restored = SessionExpiryEvent::restore(event.event_id.clone(), event.stored());
+        assert_eq!(restored, event);
+
+        let json: serde_json::Value = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["client_id"], "sensor-17");
+        assert_eq!(json["reason"], "queue_overflow");
+        assert_eq!(json["discarded_inflight"], 1);
+    }
+}
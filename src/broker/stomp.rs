@@ -0,0 +1,766 @@
+//! STOMP Compatibility Listener
+//!
+//! Accepts STOMP 1.2 clients over TCP and WebSocket. Each STOMP connection is
+//! registered as a broker client: SEND frames are published to the mapped
+//! MQTT topic and SUBSCRIBE frames become MQTT subscriptions whose deliveries
+//! are written back as MESSAGE frames.
+//!
+//! STOMP sessions count against `max_connections` like MQTT clients, and are
+//! reported in the connection metrics under the "stomp" protocol. SEND frames
+//! pass the same quota, topic schema, ACL, publish rate and `on_publish` hook
+//! checks as an MQTT PUBLISH, keyed by the STOMP login.
+
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, error, info, warn};
+
+use super::connection::{acquire_publish_rate, ClientQuota};
+use super::{create_tcp_listener, Broker, BrokerEvent};
+use crate::config::{SessionPolicy, StompConfig, TopicSchemaMode};
+use crate::metrics::Metrics;
+use crate::protocol::{Packet, Publish, QoS};
+use crate::remote::PublishOrigin;
+use crate::stomp::{decode_frame, DestinationMapper, Frame, StompError};
+use crate::topic::validation::{
+    topic_matches_filter, validate_topic_filter_with_max_levels,
+    validate_topic_name_with_max_levels,
+};
+use crate::topic::{parse_shared_subscription, Subscription};
+use crate::transport::{PeerAddr, WsStream};
+
+/// Protocol versions accepted in CONNECT, in order of preference
+const SUPPORTED_VERSIONS: [&str; 3] = ["1.2", "1.1", "1.0"];
+
+/// Spawn the STOMP TCP and WebSocket listeners
+pub(crate) fn spawn_stomp_listeners(
+    broker: Arc<Broker>,
+    config: StompConfig,
+    metrics: Option<Arc<Metrics>>,
+) -> Result<(), std::io::Error> {
+    let mapper = Arc::new(DestinationMapper::new(&config));
+    let config = Arc::new(config);
+
+    let listener = create_tcp_listener(config.bind, &broker.config)?;
+    info!("STOMP/TCP listening on {}", config.bind);
+    {
+        let broker = broker.clone();
+        let mapper = mapper.clone();
+        let config = config.clone();
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, addr)) => {
+                        debug!("New STOMP connection from {}", addr);
+                        let session = StompSession::new(
+                            stream,
+                            addr.into(),
+                            broker.clone(),
+                            metrics.clone(),
+                            mapper.clone(),
+                            config.max_frame_size,
+                        );
+                        tokio::spawn(crate::hooks::scope_listener(
+                            "stomp",
+                            session.run_until_shutdown(broker.shutdown.subscribe()),
+                        ));
+                    }
+                    Err(e) => error!("Failed to accept STOMP connection: {}", e),
+                }
+            }
+        });
+    }
+
+    if let Some(ws_addr) = config.ws_bind {
+        let listener = create_tcp_listener(ws_addr, &broker.config)?;
+        info!(
+            "STOMP/WebSocket listening on {} (path: {})",
+            ws_addr, config.ws_path
+        );
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, addr)) => {
+                        debug!("New STOMP WebSocket connection from {}", addr);
+                        let broker = broker.clone();
+                        let metrics = metrics.clone();
+                        let mapper = mapper.clone();
+                        let config = config.clone();
+                        tokio::spawn(async move {
+                            match WsStream::accept_stomp(stream, &config.ws_path).await {
+                                Ok(ws_stream) => {
+                                    let session = StompSession::new(
+                                        ws_stream,
+                                        addr.into(),
+                                        broker.clone(),
+                                        metrics,
+                                        mapper,
+                                        config.max_frame_size,
+                                    );
+                                    let shutdown = broker.shutdown.subscribe();
+                                    crate::hooks::scope_listener(
+                                        "stomp",
+                                        session.run_until_shutdown(shutdown),
+                                    )
+                                    .await;
+                                }
+                                Err(e) => {
+                                    debug!("STOMP WebSocket handshake failed for {}: {}", addr, e)
+                                }
+                            }
+                        });
+                    }
+                    Err(e) => error!("Failed to accept STOMP WebSocket connection: {}", e),
+                }
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// A STOMP subscription
+struct StompSubscription {
+    /// Subscription ID chosen by the client
+    id: String,
+    /// MQTT topic filter
+    filter: String,
+    /// Destination mapping used to build MESSAGE destinations
+    mapping: usize,
+}
+
+/// Why a session ended
+enum Close {
+    /// Client sent DISCONNECT (or closed the socket)
+    Graceful,
+    /// Error frame sent or I/O failure
+    Error,
+    /// The broker is shutting down
+    Shutdown,
+}
+
+/// A single STOMP client connection
+struct StompSession<S> {
+    stream: S,
+    addr: PeerAddr,
+    broker: Arc<Broker>,
+    metrics: Option<Arc<Metrics>>,
+    mapper: Arc<DestinationMapper>,
+    max_frame_size: usize,
+    client_id: Arc<str>,
+    username: Option<String>,
+    /// Identity the publish rate limit and quota are keyed by
+    rate_identity: Arc<str>,
+    /// Quota of the login's role, if limited
+    quota: Option<ClientQuota>,
+    /// Registered as a broker client (CONNECTED sent)
+    connected: bool,
+    subscriptions: Vec<StompSubscription>,
+    read_buf: BytesMut,
+    write_buf: BytesMut,
+}
+
+impl<S> StompSession<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn new(
+        stream: S,
+        addr: PeerAddr,
+        broker: Arc<Broker>,
+        metrics: Option<Arc<Metrics>>,
+        mapper: Arc<DestinationMapper>,
+        max_frame_size: usize,
+    ) -> Self {
+        let client_id: Arc<str> = format!("stomp-{}", crate::id::next_id()).into();
+        Self {
+            stream,
+            addr,
+            broker,
+            metrics,
+            mapper,
+            max_frame_size,
+            rate_identity: format!("client:{}", client_id).into(),
+            client_id,
+            username: None,
+            quota: None,
+            connected: false,
+            subscriptions: Vec::new(),
+            read_buf: BytesMut::with_capacity(4096),
+            write_buf: BytesMut::with_capacity(4096),
+        }
+    }
+
+    async fn run_until_shutdown(mut self, mut shutdown_rx: broadcast::Receiver<()>) {
+        let close = tokio::select! {
+            result = self.run() => match result {
+                Ok(close) => close,
+                Err(e) => {
+                    debug!("STOMP connection error from {}: {}", self.addr, e);
+                    Close::Error
+                }
+            },
+            _ = shutdown_rx.recv() => Close::Shutdown,
+        };
+        self.cleanup(close).await;
+    }
+
+    async fn run(&mut self) -> Result<Close, std::io::Error> {
+        let Some(connect) = self.read_frame().await? else {
+            return Ok(Close::Graceful);
+        };
+        if connect.command != "CONNECT" && connect.command != "STOMP" {
+            self.send_error("Expected CONNECT frame", None).await?;
+            return Ok(Close::Error);
+        }
+        let Some(version) = negotiate_version(connect.get("accept-version")) else {
+            self.send_error("Supported protocol versions are 1.0, 1.1, 1.2", None)
+                .await?;
+            return Ok(Close::Error);
+        };
+
+        let login = connect.get("login").map(str::to_string);
+        let passcode = connect.get("passcode").map(|p| p.as_bytes().to_vec());
+        match self
+            .broker
+            .hooks
+            .on_authenticate(&self.client_id, login.as_deref(), passcode.as_deref())
+            .await
+        {
+            Ok(true) => self.username = login,
+            Ok(false) => {
+                self.send_error("Authentication failed", None).await?;
+                return Ok(Close::Error);
+            }
+            Err(e) => {
+                error!("STOMP authentication error for {}: {}", self.client_id, e);
+                self.send_error("Authentication error", None).await?;
+                return Ok(Close::Error);
+            }
+        }
+
+        // Same connection limit as MQTT clients
+        let max_connections = self.broker.live_config.read().max_connections;
+        if self.broker.connections.len() >= max_connections {
+            debug!(
+                "Max connections ({}) reached, rejecting STOMP client {}",
+                max_connections, self.addr
+            );
+            self.send_error("Connection limit reached", None).await?;
+            return Ok(Close::Error);
+        }
+
+        let (tx, mut rx) = mpsc::channel(self.broker.config.outbound_channel_capacity);
+        self.broker.connections.insert(self.client_id.clone(), tx);
+        self.connected = true;
+        if let Some(ref metrics) = self.metrics {
+            metrics.client_connected("stomp");
+        }
+        self.resolve_limits().await;
+
+        let connected = Frame::new("CONNECTED")
+            .header("version", version)
+            .header("server", concat!("vibemq/", env!("CARGO_PKG_VERSION")))
+            .header("session", self.client_id.as_ref())
+            .header("heart-beat", "0,0");
+        self.write_frame(&connected).await?;
+        self.broker
+            .hooks
+            .on_client_connected(&self.client_id, self.username.as_deref())
+            .await;
+        debug!(
+            "STOMP CONNECT from {} (session: {})",
+            self.addr, self.client_id
+        );
+
+        let close = loop {
+            // Handle all complete frames already buffered
+            match decode_frame(&mut self.read_buf, self.max_frame_size) {
+                Ok(Some(frame)) => match self.handle_frame(frame).await? {
+                    Some(close) => break close,
+                    None => continue,
+                },
+                Ok(None) => {}
+                Err(e) => {
+                    self.send_protocol_error(e).await?;
+                    break Close::Error;
+                }
+            }
+
+            tokio::select! {
+                result = self.stream.read_buf(&mut self.read_buf) => {
+                    if result? == 0 {
+                        break Close::Graceful;
+                    }
+                }
+                packet = rx.recv() => match packet {
+                    Some(Packet::Publish(publish)) => self.deliver(&publish).await?,
+                    Some(Packet::Disconnect(_)) | None => break Close::Error,
+                    Some(_) => {}
+                },
+            }
+        };
+        Ok(close)
+    }
+
+    /// Read the first frame of the connection
+    async fn read_frame(&mut self) -> Result<Option<Frame>, std::io::Error> {
+        loop {
+            match decode_frame(&mut self.read_buf, self.max_frame_size) {
+                Ok(Some(frame)) => return Ok(Some(frame)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.send_protocol_error(e).await?;
+                    return Ok(None);
+                }
+            }
+            if self.stream.read_buf(&mut self.read_buf).await? == 0 {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Handle a frame after CONNECT; returns `Some` when the session should end
+    async fn handle_frame(&mut self, frame: Frame) -> Result<Option<Close>, std::io::Error> {
+        let receipt = frame.get("receipt").map(str::to_string);
+        let result = match frame.command.as_str() {
+            "SEND" => self.handle_send(&frame).await,
+            "SUBSCRIBE" => self.handle_subscribe(&frame).await,
+            "UNSUBSCRIBE" => self.handle_unsubscribe(&frame),
+            // Deliveries use auto acknowledgement
+            "ACK" | "NACK" => Ok(()),
+            "DISCONNECT" => {
+                if let Some(receipt) = receipt {
+                    self.write_frame(&Frame::new("RECEIPT").header("receipt-id", receipt))
+                        .await?;
+                }
+                return Ok(Some(Close::Graceful));
+            }
+            "BEGIN" | "COMMIT" | "ABORT" => Err("Transactions are not supported"),
+            "CONNECT" | "STOMP" => Err("Already connected"),
+            _ => Err("Unknown command"),
+        };
+
+        match result {
+            Ok(()) => {
+                if let Some(receipt) = receipt {
+                    self.write_frame(&Frame::new("RECEIPT").header("receipt-id", receipt))
+                        .await?;
+                }
+                Ok(None)
+            }
+            Err(message) => {
+                self.send_error(message, receipt.as_deref()).await?;
+                Ok(Some(Close::Error))
+            }
+        }
+    }
+
+    async fn handle_send(&mut self, frame: &Frame) -> Result<(), &'static str> {
+        let destination = frame
+            .get("destination")
+            .ok_or("Missing destination header")?;
+        let (_, topic) = self
+            .mapper
+            .to_mqtt(destination)
+            .ok_or("Unmapped destination")?;
+        validate_topic_name_with_max_levels(&topic, self.broker.config.max_topic_levels)
+            .map_err(|_| "Invalid destination")?;
+
+        let qos = parse_qos(frame.get("qos"))?.min(self.broker.config.max_qos);
+        let retain = frame.get("retain") == Some("true") && self.broker.config.retain_available;
+
+        if let Some((limit, value, _)) = self
+            .quota
+            .as_ref()
+            .and_then(|quota| quota.charge_publish(frame.body.len(), Some(&self.broker.limiters)))
+        {
+            debug!("{} exceeded {} ({})", self.client_id, limit, value);
+            if let Some(ref metrics) = self.metrics {
+                metrics.quota_exceeded(limit.trim_start_matches("quota."));
+            }
+            return Err("Quota exceeded");
+        }
+
+        // Topics outside the declared namespace
+        let schema = self.broker.topic_schema();
+        if !schema.check(&topic) {
+            if schema.mode() == TopicSchemaMode::Reject {
+                self.message_dropped("topic_schema");
+                return Err("Destination matches no topic template");
+            }
+            warn!(
+                "STOMP SEND from {} to {} matches no topic template",
+                self.client_id, topic
+            );
+        }
+
+        match self
+            .broker
+            .hooks
+            .on_publish_check_with_identity(
+                &self.client_id,
+                self.username.as_deref(),
+                &topic,
+                qos,
+                retain,
+                None,
+            )
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                self.message_dropped("not_authorized");
+                return Err("Not authorized to send to this destination");
+            }
+            Err(e) => {
+                error!("STOMP ACL check error for {}: {}", self.client_id, e);
+                return Err("Authorization error");
+            }
+        }
+
+        acquire_publish_rate(
+            &self.broker.config,
+            self.broker.hooks.as_ref(),
+            &self.broker.sessions,
+            Some(&self.broker.limiters),
+            self.metrics.as_deref(),
+            &self.rate_identity,
+            1,
+        )
+        .await
+        .map_err(|_| "Publish rate limit exceeded")?;
+
+        // The hooks may rewrite the topic, payload and properties only
+        let mut publish = Publish {
+            qos,
+            retain,
+            topic,
+            payload: frame.body.clone(),
+            ..Publish::default()
+        };
+        match self
+            .broker
+            .hooks
+            .on_publish(&self.client_id, self.username.as_deref(), &mut publish)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                self.message_dropped("not_authorized");
+                return Err("Not authorized to send to this destination");
+            }
+            Err(e) => {
+                error!("STOMP publish hook error for {}: {}", self.client_id, e);
+                return Err("Publish hook error");
+            }
+        }
+        let Publish {
+            topic,
+            payload,
+            properties,
+            ..
+        } = publish;
+        validate_topic_name_with_max_levels(&topic, self.broker.config.max_topic_levels)
+            .map_err(|_| "Invalid destination")?;
+
+        let user_properties = self.broker.publish_with_properties(
+            topic.clone(),
+            payload.clone(),
+            qos,
+            retain,
+            properties,
+        );
+        let _ = self.broker.events.send(BrokerEvent::MessagePublished {
+            topic: topic.clone(),
+            payload: payload.clone(),
+            qos,
+            retain,
+            origin: Some(PublishOrigin {
+                client_id: self.client_id.clone(),
+                addr: self.addr.clone(),
+            }),
+            user_properties,
+        });
+        self.broker
+            .hooks
+            .on_message_published(&topic, &payload, qos)
+            .await;
+        Ok(())
+    }
+
+    /// Key the publish limits by the login, and resolve its role's quota
+    async fn resolve_limits(&mut self) {
+        if let Some(ref username) = self.username {
+            self.rate_identity = format!("user:{}", username).into();
+        }
+        let policy = match self
+            .broker
+            .hooks
+            .on_session_policy(&self.client_id, self.username.as_deref())
+            .await
+        {
+            Ok(policy) => policy,
+            Err(e) => {
+                warn!("Session policy lookup error for {}: {}", self.client_id, e);
+                SessionPolicy::default()
+            }
+        };
+        let limits = self.broker.config.quota.resolve_with_role(
+            "stomp",
+            &policy.quota,
+            self.username.as_deref(),
+        );
+        self.quota = ClientQuota::new(limits, self.rate_identity.clone());
+        if let Some(ref quota) = self.quota {
+            quota.register(&self.client_id, &self.broker.limiters);
+        }
+    }
+
+    fn message_dropped(&self, reason: &'static str) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.message_dropped(reason);
+        }
+    }
+
+    async fn handle_subscribe(&mut self, frame: &Frame) -> Result<(), &'static str> {
+        let id = frame.get("id").ok_or("Missing id header")?.to_string();
+        let destination = frame
+            .get("destination")
+            .ok_or("Missing destination header")?;
+        if self.subscriptions.iter().any(|s| s.id == id) {
+            return Err("Duplicate subscription id");
+        }
+        let (mapping, filter) = self
+            .mapper
+            .to_mqtt(destination)
+            .ok_or("Unmapped destination")?;
+        validate_topic_filter_with_max_levels(&filter, self.broker.config.max_topic_levels)
+            .map_err(|_| "Invalid destination")?;
+        if !self.broker.config.wildcard_subscription_available && filter.contains(['+', '#']) {
+            return Err("Wildcard subscriptions are not available");
+        }
+
+        let qos = parse_qos(frame.get("qos"))?.min(self.broker.config.max_qos);
+        match self
+            .broker
+            .hooks
+            .on_subscribe_check(&self.client_id, self.username.as_deref(), &filter, qos)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => return Err("Not authorized to subscribe to this destination"),
+            Err(e) => {
+                error!("STOMP ACL check error for {}: {}", self.client_id, e);
+                return Err("Authorization error");
+            }
+        }
+
+        self.broker.subscriptions.subscribe(
+            &filter,
+            Subscription {
+                client_id: self.client_id.clone(),
+                qos,
+                no_local: false,
+                retain_as_published: false,
+                subscription_id: None,
+                share_group: None,
+            },
+        );
+        let _ = self.broker.events.send(BrokerEvent::SubscriptionAdded {
+            filter: filter.clone(),
+            client_id: self.client_id.clone(),
+        });
+        self.subscriptions.push(StompSubscription {
+            id: id.clone(),
+            filter: filter.clone(),
+            mapping,
+        });
+
+        // Retained messages go to the new subscription only (not for shared subscriptions)
+        if parse_shared_subscription(&filter).is_none() {
+            let retained: Vec<_> = self
+                .broker
+                .retained
+                .iter()
+                .filter(|entry| topic_matches_filter(entry.key(), &filter))
+                .filter(|entry| {
+                    entry
+                        .properties
+                        .message_expiry_interval
+                        .is_none_or(|expiry| entry.timestamp.elapsed().as_secs() < expiry as u64)
+                })
+                .map(|entry| (entry.topic.clone(), entry.payload.clone()))
+                .collect();
+            // Wildcard destinations can match retained topics this client may not read
+            let check_topics = filter.contains(['+', '#']);
+            for (topic, payload) in retained {
+                if check_topics
+                    && !matches!(
+                        self.broker
+                            .hooks
+                            .on_subscribe_check(
+                                &self.client_id,
+                                self.username.as_deref(),
+                                &topic,
+                                qos
+                            )
+                            .await,
+                        Ok(true)
+                    )
+                {
+                    continue;
+                }
+                self.write_message(&id, mapping, &topic, payload, None)
+                    .await
+                    .map_err(|_| "Write failed")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_unsubscribe(&mut self, frame: &Frame) -> Result<(), &'static str> {
+        let id = frame.get("id").ok_or("Missing id header")?;
+        let pos = self
+            .subscriptions
+            .iter()
+            .position(|s| s.id == id)
+            .ok_or("Unknown subscription id")?;
+        let sub = self.subscriptions.remove(pos);
+
+        // Multiple STOMP subscriptions can share one MQTT subscription
+        if !self.subscriptions.iter().any(|s| s.filter == sub.filter) {
+            self.broker
+                .subscriptions
+                .unsubscribe(&sub.filter, &self.client_id);
+            let _ = self.broker.events.send(BrokerEvent::SubscriptionRemoved {
+                filter: sub.filter,
+                client_id: self.client_id.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Write a routed publish as a MESSAGE frame for each matching subscription
+    async fn deliver(&mut self, publish: &Publish) -> Result<(), std::io::Error> {
+        let matching: Vec<(String, usize)> = self
+            .subscriptions
+            .iter()
+            .filter(|s| {
+                let filter = parse_shared_subscription(&s.filter)
+                    .map(|(_, f)| f)
+                    .unwrap_or(&s.filter);
+                topic_matches_filter(&publish.topic, filter)
+            })
+            .map(|s| (s.id.clone(), s.mapping))
+            .collect();
+        for (id, mapping) in matching {
+            self.write_message(
+                &id,
+                mapping,
+                &publish.topic,
+                publish.payload.clone(),
+                publish.properties.content_type.as_deref(),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn write_message(
+        &mut self,
+        subscription: &str,
+        mapping: usize,
+        topic: &str,
+        payload: Bytes,
+        content_type: Option<&str>,
+    ) -> Result<(), std::io::Error> {
+        let mut frame = Frame::new("MESSAGE")
+            .header("subscription", subscription)
+            .header("message-id", crate::id::next_id())
+            .header("destination", self.mapper.to_stomp(mapping, topic));
+        if let Some(content_type) = content_type {
+            frame = frame.header("content-type", content_type);
+        }
+        self.write_frame(&frame.body(payload)).await
+    }
+
+    async fn send_protocol_error(&mut self, e: StompError) -> Result<(), std::io::Error> {
+        debug!("STOMP protocol error from {}: {}", self.addr, e);
+        let message = match e {
+            StompError::FrameTooLarge => "Frame too large",
+            StompError::Malformed(_) => "Malformed frame",
+        };
+        self.send_error(message, None).await
+    }
+
+    async fn send_error(
+        &mut self,
+        message: &str,
+        receipt: Option<&str>,
+    ) -> Result<(), std::io::Error> {
+        let mut frame = Frame::new("ERROR").header("message", message);
+        if let Some(receipt) = receipt {
+            frame = frame.header("receipt-id", receipt);
+        }
+        self.write_frame(&frame).await
+    }
+
+    async fn write_frame(&mut self, frame: &Frame) -> Result<(), std::io::Error> {
+        self.write_buf.clear();
+        frame.encode(&mut self.write_buf);
+        self.stream.write_all(&self.write_buf).await?;
+        self.stream.flush().await
+    }
+
+    /// Remove the session's subscriptions and connection entry, and report
+    /// the disconnect of a connected client
+    async fn cleanup(&mut self, close: Close) {
+        if !self.connected {
+            return;
+        }
+        self.broker.connections.remove(&self.client_id);
+        if let Some(ref metrics) = self.metrics {
+            metrics.client_disconnected("stomp");
+        }
+        self.broker
+            .hooks
+            .on_client_disconnected(&self.client_id, matches!(close, Close::Graceful))
+            .await;
+        if !self.subscriptions.is_empty() {
+            self.broker.subscriptions.unsubscribe_all(&self.client_id);
+            for sub in self.subscriptions.drain(..) {
+                let _ = self.broker.events.send(BrokerEvent::SubscriptionRemoved {
+                    filter: sub.filter,
+                    client_id: self.client_id.clone(),
+                });
+            }
+        }
+    }
+}
+
+/// Pick the highest common protocol version (a missing header means 1.0)
+fn negotiate_version(accept_version: Option<&str>) -> Option<&'static str> {
+    let Some(accepted) = accept_version else {
+        return Some("1.0");
+    };
+    SUPPORTED_VERSIONS
+        .into_iter()
+        .find(|v| accepted.split(',').any(|a| a.trim() == *v))
+}
+
+/// Parse the optional `qos` header (default 0)
+fn parse_qos(value: Option<&str>) -> Result<QoS, &'static str> {
+    match value {
+        None => Ok(QoS::AtMostOnce),
+        Some(v) => v
+            .trim()
+            .parse::<u8>()
+            .ok()
+            .and_then(QoS::from_u8)
+            .ok_or("Invalid qos header"),
+    }
+}
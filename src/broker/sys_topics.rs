@@ -1,10 +1,12 @@
 //! $SYS Topics Publisher
 //!
 //! Publishes broker statistics as retained messages to standard $SYS/# topics.
-//! Topics are updated periodically based on configuration.
+//! Topics are updated periodically based on configuration. Names and value
+//! formats follow Mosquitto's, so existing dashboards and scripts work
+//! unchanged.
 
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 
@@ -15,13 +17,162 @@ use crate::protocol::QoS;
 /// Version string for $SYS/broker/version
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Load average windows in minutes
+const LOAD_WINDOWS: [u64; 3] = [1, 5, 15];
+
+/// Exponentially decaying per-minute rate of a counter, like Mosquitto's
+/// `$SYS/broker/load/...` topics
+struct LoadAverage {
+    topic: &'static str,
+    counter: fn(&Metrics) -> u64,
+    last: Option<u64>,
+    averages: [f64; 3],
+}
+
+impl LoadAverage {
+    fn new(topic: &'static str, counter: fn(&Metrics) -> u64) -> Self {
+        Self {
+            topic,
+            counter,
+            last: None,
+            averages: [0.0; 3],
+        }
+    }
+
+    /// Fold in the counter's current value, `elapsed` after the last one
+    fn update(&mut self, value: u64, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let Some(last) = self.last.replace(value) else {
+            return;
+        };
+        if secs <= 0.0 {
+            return;
+        }
+
+        let rate = value.saturating_sub(last) as f64 * 60.0 / secs;
+        for (average, minutes) in self.averages.iter_mut().zip(LOAD_WINDOWS) {
+            let decay = (-secs / (minutes as f64 * 60.0)).exp();
+            *average = rate + (*average - rate) * decay;
+        }
+    }
+}
+
+/// State carried between $SYS refreshes
+struct SysTopics {
+    start_time: Instant,
+    last_update: Instant,
+    loads: Vec<LoadAverage>,
+    heap_maximum: u64,
+}
+
+impl SysTopics {
+    fn new(start_time: Instant) -> Self {
+        let loads = vec![
+            LoadAverage::new("messages/received", |m| m.messages_total_received.get()),
+            LoadAverage::new("messages/sent", |m| m.messages_total_sent.get()),
+            LoadAverage::new("publish/received", |m| m.publish_messages_received.get()),
+            LoadAverage::new("publish/sent", |m| m.publish_messages_sent.get()),
+            LoadAverage::new("publish/dropped", |m| m.publish_messages_dropped.get()),
+            LoadAverage::new("bytes/received", |m| m.messages_bytes_received.get()),
+            LoadAverage::new("bytes/sent", |m| m.messages_bytes_sent.get()),
+            LoadAverage::new("connections", |m| m.connections_total.get()),
+        ];
+
+        Self {
+            start_time,
+            last_update: start_time,
+            loads,
+            heap_maximum: 0,
+        }
+    }
+
+    fn publish(&mut self, broker: &Broker, metrics: Option<&Metrics>) {
+        publish_sys_topics(broker, metrics, self.start_time);
+
+        if let Some(heap) = heap_usage() {
+            self.heap_maximum = self.heap_maximum.max(heap);
+            publish(broker, "$SYS/broker/heap/current", &heap.to_string());
+            publish(
+                broker,
+                "$SYS/broker/heap/maximum",
+                &self.heap_maximum.to_string(),
+            );
+        }
+
+        let Some(metrics) = metrics else {
+            return;
+        };
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update);
+        self.last_update = now;
+
+        for load in &mut self.loads {
+            load.update((load.counter)(metrics), elapsed);
+
+            for (average, minutes) in load.averages.iter().zip(LOAD_WINDOWS) {
+                publish(
+                    broker,
+                    &format!("$SYS/broker/load/{}/{}min", load.topic, minutes),
+                    &format!("{:.2}", average),
+                );
+            }
+        }
+    }
+}
+
+/// Bytes of heap in use: jemalloc's allocated count in profiling builds,
+/// the resident set size elsewhere (Linux only)
+pub(crate) fn heap_usage() -> Option<u64> {
+    #[cfg(feature = "pprof")]
+    {
+        use tikv_jemalloc_ctl::{epoch, stats};
+        if epoch::advance().is_ok() {
+            if let Ok(allocated) = stats::allocated::read() {
+                return Some(allocated as u64);
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        // Second field of statm is resident pages (assumed 4 KiB)
+        let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+        let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+        Some(pages * 4096)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
 /// Publish all $SYS topics as retained messages
 pub fn publish_sys_topics(broker: &Broker, metrics: Option<&Metrics>, start_time: Instant) {
     let uptime = start_time.elapsed().as_secs();
 
     // Broker info (always available)
-    publish(broker, "$SYS/broker/version", VERSION);
-    publish(broker, "$SYS/broker/uptime", &uptime.to_string());
+    publish(
+        broker,
+        "$SYS/broker/version",
+        &format!("vibemq version {}", VERSION),
+    );
+    publish(broker, "$SYS/broker/uptime", &format!("{} seconds", uptime));
+
+    // Maintenance windows (when configured); no names are retained while
+    // none is open
+    let windows = broker.maintenance().windows();
+    if !windows.is_empty() {
+        let open: Vec<&str> = windows
+            .iter()
+            .filter(|w| w.active)
+            .map(|w| w.name.as_str())
+            .collect();
+        publish(
+            broker,
+            "$SYS/broker/maintenance/active",
+            &open.len().to_string(),
+        );
+        publish(broker, "$SYS/broker/maintenance/windows", &open.join(","));
+    }
 
     // Session store stats (always available)
     let disconnected_count = broker.sessions.count_disconnected();
@@ -172,20 +323,19 @@ fn publish(broker: &Broker, topic: &str, value: &str) {
 pub fn spawn_sys_topics_task(
     broker: Arc<Broker>,
     metrics: Option<Arc<Metrics>>,
-    interval_secs: u64,
+    interval: Duration,
     start_time: Instant,
     mut shutdown_rx: tokio::sync::broadcast::Receiver<()>,
 ) {
     tokio::spawn(async move {
-        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
-
-        // Publish immediately on startup
-        publish_sys_topics(&broker, metrics.as_deref(), start_time);
+        let mut sys_topics = SysTopics::new(start_time);
+        // The first tick completes immediately, publishing on startup
+        let mut ticker = tokio::time::interval(interval.max(Duration::from_secs(1)));
 
         loop {
             tokio::select! {
                 _ = ticker.tick() => {
-                    publish_sys_topics(&broker, metrics.as_deref(), start_time);
+                    sys_topics.publish(&broker, metrics.as_deref());
                 }
                 _ = shutdown_rx.recv() => {
                     tracing::debug!("$SYS topics task shutting down");
@@ -195,3 +345,33 @@ pub fn spawn_sys_topics_task(
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_average_converges() {
+        let mut load = LoadAverage::new("test", |_| 0);
+
+        // The first sample only establishes the baseline
+        load.update(100, Duration::from_secs(10));
+        assert_eq!(load.averages, [0.0; 3]);
+
+        // A steady 6 per 10s is 36 per minute; shorter windows track it sooner
+        let mut value = 100;
+        for _ in 0..60 {
+            value += 6;
+            load.update(value, Duration::from_secs(10));
+        }
+        assert!((load.averages[0] - 36.0).abs() < 0.1);
+        assert!(load.averages[0] > load.averages[1]);
+        assert!(load.averages[1] > load.averages[2]);
+
+        // Idle periods decay towards zero
+        for _ in 0..60 {
+            load.update(value, Duration::from_secs(10));
+        }
+        assert!(load.averages[0] < 0.1);
+    }
+}
@@ -6,14 +6,22 @@
 use std::fs::File;
 use std::io::BufReader;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::runtime::Runtime;
+use tokio::sync::Semaphore;
 use tokio_rustls::rustls::pki_types::pem::PemObject;
 use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use tokio_rustls::rustls::server::WebPkiClientVerifier;
 use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
 use tokio_rustls::TlsAcceptor;
 
 use super::TlsConfig;
+use crate::config::QuicConfig;
+use crate::metrics::Metrics;
+use crate::proxy::ProxyTlsInfo;
 
 /// Error type for TLS configuration
 #[derive(Debug)]
@@ -90,6 +98,46 @@ fn load_ca_certs(path: &str) -> Result<RootCertStore, TlsError> {
 
 /// Load TLS configuration and create a TlsAcceptor
 pub fn load_tls_config(config: &TlsConfig) -> Result<TlsAcceptor, TlsError> {
+    Ok(TlsAcceptor::from(Arc::new(server_config(config)?)))
+}
+
+/// Load TLS configuration for the QUIC listener
+///
+/// Same certificate and client verification as the TLS listener, plus the
+/// QUIC-specific ALPN, early data and transport settings.
+pub fn load_quic_config(
+    config: &TlsConfig,
+    quic: &QuicConfig,
+) -> Result<quinn::ServerConfig, TlsError> {
+    let mut tls = server_config(config)?;
+    tls.alpn_protocols = quic.alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+    if quic.zero_rtt {
+        // QUIC allows no other non-zero value
+        tls.max_early_data_size = u32::MAX;
+    }
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls)
+        .map_err(|e| TlsError::ConfigError(format!("Failed to build QUIC config: {}", e)))?;
+
+    let mut transport = quinn::TransportConfig::default();
+    transport
+        .max_concurrent_bidi_streams(quic.max_streams.into())
+        .max_concurrent_uni_streams(0u32.into())
+        .max_idle_timeout(Some(quic.idle_timeout.try_into().map_err(|_| {
+            TlsError::ConfigError("QUIC idle timeout is too long".to_string())
+        })?))
+        .keep_alive_interval(
+            (!quic.keep_alive_interval.is_zero()).then_some(quic.keep_alive_interval),
+        );
+
+    let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    server_config
+        .transport_config(Arc::new(transport))
+        .migration(quic.migration);
+    Ok(server_config)
+}
+
+/// rustls server configuration shared by the TLS and QUIC listeners
+fn server_config(config: &TlsConfig) -> Result<ServerConfig, TlsError> {
     // Load server certificate chain
     let certs = load_certs(&config.cert_path)?;
 
@@ -138,7 +186,288 @@ pub fn load_tls_config(config: &TlsConfig) -> Result<TlsAcceptor, TlsError> {
             .map_err(|e| TlsError::ConfigError(format!("Failed to build TLS config: {}", e)))?
     };
 
-    Ok(TlsAcceptor::from(Arc::new(server_config)))
+    Ok(server_config)
+}
+
+/// TLS details of a locally terminated connection
+///
+/// Fills the same structure PROXY v2 TLVs do, so identity-based rules treat
+/// local and upstream TLS termination alike. A client certificate is only
+/// present after the configured verifier accepted it.
+pub fn client_tls_info<IO>(stream: &TlsStream<IO>) -> ProxyTlsInfo {
+    let (_, connection) = stream.get_ref();
+    let mut info = ProxyTlsInfo {
+        sni: connection.server_name().map(str::to_string),
+        // "TLSv1_3" -> "TLSv1.3", as HAProxy reports it
+        version: connection
+            .protocol_version()
+            .and_then(|v| v.as_str())
+            .map(|v| v.replace('_', ".")),
+        cipher: connection
+            .negotiated_cipher_suite()
+            .and_then(|suite| suite.suite().as_str())
+            .map(str::to_string),
+        ..Default::default()
+    };
+    if let Some(cert) = connection
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+    {
+        let (cn, sans) = cert_identity(cert).unwrap_or_default();
+        info.client_cert_cn = cn;
+        info.client_cert_sans = sans;
+        info.client_cert_verified = true;
+    }
+    info
+}
+
+/// TLS details of a QUIC connection, like [`client_tls_info`]
+///
+/// QUIC always runs TLS 1.3; the negotiated cipher suite isn't exposed.
+pub fn quic_tls_info(connection: &quinn::Connection) -> ProxyTlsInfo {
+    let handshake = connection
+        .handshake_data()
+        .and_then(|data| data.downcast::<quinn::crypto::rustls::HandshakeData>().ok());
+    let mut info = ProxyTlsInfo {
+        sni: handshake.and_then(|data| data.server_name),
+        version: Some("TLSv1.3".to_string()),
+        ..Default::default()
+    };
+    let certs = connection
+        .peer_identity()
+        .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok());
+    if let Some(cert) = certs.as_ref().and_then(|certs| certs.first()) {
+        let (cn, sans) = cert_identity(cert).unwrap_or_default();
+        info.client_cert_cn = cn;
+        info.client_cert_sans = sans;
+        info.client_cert_verified = true;
+    }
+    info
+}
+
+/// Subject CN and Subject Alternative Names of a DER certificate
+fn cert_identity(der: &[u8]) -> Option<(Option<String>, Vec<String>)> {
+    const OID_CN: &[u8] = &[0x55, 0x04, 0x03];
+    const OID_SAN: &[u8] = &[0x55, 0x1d, 0x11];
+
+    let (_, cert, _) = der_read(der, 0x30)?;
+    let (_, tbs, _) = der_read(cert, 0x30)?;
+
+    // version [0] (optional), serial, signature, issuer, validity, subject
+    let mut rest = tbs;
+    if rest.first() == Some(&0xa0) {
+        rest = der_next(rest)?.2;
+    }
+    for _ in 0..4 {
+        rest = der_next(rest)?.2;
+    }
+    let (_, subject, mut rest) = der_read(rest, 0x30)?;
+
+    // Subject: SEQUENCE OF SET OF SEQUENCE { OID, value }; the last CN wins
+    let mut cn = None;
+    let mut rdns = subject;
+    while !rdns.is_empty() {
+        let (_, set, next) = der_read(rdns, 0x31)?;
+        rdns = next;
+        let mut attrs = set;
+        while !attrs.is_empty() {
+            let (_, attr, next) = der_read(attrs, 0x30)?;
+            attrs = next;
+            let (_, oid, value) = der_read(attr, 0x06)?;
+            if oid == OID_CN {
+                let (_, value, _) = der_next(value)?;
+                cn = std::str::from_utf8(value).ok().map(str::to_string);
+            }
+        }
+    }
+
+    // subjectPublicKeyInfo, then [1]/[2] unique IDs and [3] extensions
+    rest = der_next(rest)?.2;
+    let mut sans = Vec::new();
+    while !rest.is_empty() {
+        let (tag, value, next) = der_next(rest)?;
+        rest = next;
+        if tag != 0xa3 {
+            continue;
+        }
+        let (_, mut extensions, _) = der_read(value, 0x30)?;
+        while !extensions.is_empty() {
+            let (_, extension, next) = der_read(extensions, 0x30)?;
+            extensions = next;
+            let (_, oid, mut fields) = der_read(extension, 0x06)?;
+            if oid != OID_SAN {
+                continue;
+            }
+            if fields.first() == Some(&0x01) {
+                fields = der_next(fields)?.2; // critical flag
+            }
+            let (_, octets, _) = der_read(fields, 0x04)?;
+            let (_, mut names, _) = der_read(octets, 0x30)?;
+            while !names.is_empty() {
+                let (tag, name, next) = der_next(names)?;
+                names = next;
+                if let Some(name) = general_name(tag, name) {
+                    sans.push(name);
+                }
+            }
+        }
+    }
+
+    Some((cn, sans))
+}
+
+/// A GeneralName in OpenSSL's "TYPE:value" notation (other kinds are skipped)
+fn general_name(tag: u8, value: &[u8]) -> Option<String> {
+    let text = || std::str::from_utf8(value).ok();
+    match tag {
+        0x81 => text().map(|v| format!("email:{}", v)),
+        0x82 => text().map(|v| format!("DNS:{}", v)),
+        0x86 => text().map(|v| format!("URI:{}", v)),
+        0x87 => {
+            let ip: std::net::IpAddr = match value.len() {
+                4 => <[u8; 4]>::try_from(value).ok()?.into(),
+                16 => <[u8; 16]>::try_from(value).ok()?.into(),
+                _ => return None,
+            };
+            Some(format!("IP:{}", ip))
+        }
+        _ => None,
+    }
+}
+
+/// Read one DER element: (tag, contents, remaining input)
+fn der_next(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, mut input) = input.split_first()?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || input.len() < count {
+            return None;
+        }
+        let (bytes, rest) = input.split_at(count);
+        input = rest;
+        bytes.iter().fold(0usize, |len, &b| (len << 8) | b as usize)
+    };
+    if input.len() < len {
+        return None;
+    }
+    let (contents, rest) = input.split_at(len);
+    Some((tag, contents, rest))
+}
+
+/// Read one DER element, requiring the given tag
+fn der_read(input: &[u8], expected: u8) -> Option<(u8, &[u8], &[u8])> {
+    der_next(input).filter(|(tag, _, _)| *tag == expected)
+}
+
+/// Dedicated thread pool for TLS handshakes
+///
+/// Handshakes are CPU-bound, so a burst of new TLS connections running on the
+/// main I/O runtime can starve packet processing for established sessions.
+/// The pool runs handshakes on its own runtime and hands the completed stream
+/// back to the caller. At most `queue_size` handshakes may be queued or in
+/// progress; further connections are rejected until the queue drains. A
+/// handshake not done within `timeout` fails, so peers that never send a
+/// ClientHello can't hold the queue.
+pub struct TlsHandshakePool {
+    runtime: Option<Runtime>,
+    queue: Arc<Semaphore>,
+    queue_size: usize,
+    timeout: Duration,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl TlsHandshakePool {
+    /// Create a pool with `threads` worker threads
+    pub fn new(threads: usize, queue_size: usize, timeout: Duration) -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(threads.max(1))
+            .thread_name("vibemq-tls-handshake")
+            .enable_all()
+            .build()?;
+
+        Ok(Self {
+            runtime: Some(runtime),
+            queue: Arc::new(Semaphore::new(queue_size)),
+            queue_size,
+            timeout,
+            metrics: None,
+        })
+    }
+
+    /// Record queue depth and handshake latency
+    pub fn with_metrics(mut self, metrics: Option<Arc<Metrics>>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Number of handshakes queued or in progress
+    pub fn queue_depth(&self) -> usize {
+        self.queue_size - self.queue.available_permits()
+    }
+
+    /// Perform a TLS handshake on the pool
+    ///
+    /// Fails immediately with `WouldBlock` if the handshake queue is full,
+    /// and with `TimedOut` if the handshake takes longer than the pool's
+    /// timeout.
+    pub async fn accept<IO>(
+        &self,
+        acceptor: &TlsAcceptor,
+        stream: IO,
+    ) -> std::io::Result<TlsStream<IO>>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let runtime = self.runtime.as_ref().expect("handshake pool is running");
+        let Ok(permit) = self.queue.clone().try_acquire_owned() else {
+            if let Some(ref metrics) = self.metrics {
+                metrics.tls_handshake_rejected();
+            }
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::WouldBlock,
+                "TLS handshake queue full",
+            ));
+        };
+
+        if let Some(ref metrics) = self.metrics {
+            metrics.tls_handshake_queued();
+        }
+        let start = Instant::now();
+
+        // The task releases its queue slot and records the handshake even if
+        // the caller has gone
+        let acceptor = acceptor.clone();
+        let limit = self.timeout;
+        let metrics = self.metrics.clone();
+        let handshake = runtime.spawn(async move {
+            let result = tokio::time::timeout(limit, acceptor.accept(stream))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "TLS handshake timed out",
+                    ))
+                });
+            drop(permit);
+            if let Some(ref metrics) = metrics {
+                metrics.tls_handshake_finished(start.elapsed());
+            }
+            result
+        });
+        handshake.await.map_err(std::io::Error::other)?
+    }
+}
+
+impl Drop for TlsHandshakePool {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which isn't allowed inside the main runtime
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -156,4 +485,109 @@ mod tests {
         let err = TlsError::ConfigError("config error".to_string());
         assert!(err.to_string().contains("TLS config error"));
     }
+
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        if contents.len() < 0x80 {
+            out.push(contents.len() as u8);
+        } else {
+            out.push(0x82);
+            out.extend_from_slice(&(contents.len() as u16).to_be_bytes());
+        }
+        out.extend_from_slice(contents);
+        out
+    }
+
+    fn name(cn: &str) -> Vec<u8> {
+        let attr = [der(0x06, &[0x55, 0x04, 0x03]), der(0x0c, cn.as_bytes())].concat();
+        der(0x30, &der(0x31, &der(0x30, &attr)))
+    }
+
+    #[test]
+    fn test_cert_identity() {
+        let general_names = [
+            der(0x82, b"sensor-7.example.com"),
+            der(0x81, b"ops@example.com"),
+            der(0x87, &[10, 0, 0, 7]),
+        ]
+        .concat();
+        let san = [
+            der(0x06, &[0x55, 0x1d, 0x11]),
+            der(0x04, &der(0x30, &general_names)),
+        ]
+        .concat();
+        let tbs = [
+            der(0xa0, &der(0x02, &[2])),
+            der(0x02, &[1]),
+            der(0x30, &der(0x06, &[0x2a])),
+            name("Test CA"),
+            der(0x30, &[]),
+            name("sensor-7"),
+            der(0x30, &[]),
+            der(0xa3, &der(0x30, &der(0x30, &san))),
+        ]
+        .concat();
+        let cert = der(
+            0x30,
+            &[der(0x30, &tbs), der(0x30, &[]), der(0x03, &[0])].concat(),
+        );
+
+        let (cn, sans) = cert_identity(&cert).unwrap();
+        assert_eq!(cn.as_deref(), Some("sensor-7"));
+        assert_eq!(
+            sans,
+            vec![
+                "DNS:sensor-7.example.com",
+                "email:ops@example.com",
+                "IP:10.0.0.7"
+            ]
+        );
+
+        assert!(cert_identity(&cert[..cert.len() - 4]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_pool_drop_in_runtime() {
+        let pool = TlsHandshakePool::new(1, 4, Duration::from_secs(10)).unwrap();
+        assert_eq!(pool.queue_depth(), 0);
+        // Must not panic when dropped from within an async context
+        drop(pool);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_pool_timeout() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let key = PrivateKeyDer::try_from(cert.key_pair.serialize_der()).unwrap();
+        let server = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.cert.der().clone()], key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server));
+        let metrics = Arc::new(Metrics::new());
+        let pool = Arc::new(
+            TlsHandshakePool::new(1, 1, Duration::from_millis(100))
+                .unwrap()
+                .with_metrics(Some(metrics.clone())),
+        );
+
+        // A peer that never sends a ClientHello gives up its slot
+        let (_client, server) = tokio::io::duplex(1024);
+        let err = pool.accept(&acceptor, server).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(pool.queue_depth(), 0);
+        assert_eq!(metrics.tls_handshake_queue_depth.get(), 0);
+
+        // So does one whose caller went away
+        let (_client, server) = tokio::io::duplex(1024);
+        let waiting = {
+            let (pool, acceptor) = (pool.clone(), acceptor.clone());
+            tokio::spawn(async move { pool.accept(&acceptor, server).await.map(drop) })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(pool.queue_depth(), 1);
+        waiting.abort();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(pool.queue_depth(), 0);
+        assert_eq!(metrics.tls_handshake_queue_depth.get(), 0);
+    }
 }
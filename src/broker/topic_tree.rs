@@ -0,0 +1,377 @@
+//! Topic Tree Export
+//!
+//! Builds two trees for `GET /api/v1/topics/tree`, one level per node:
+//! - the concrete topics in use: the retained ones, plus (with
+//!   `topic_tree.recent_topics`) those published to within
+//!   `topic_tree.recent_window`, with how many topics, retained messages
+//!   and recent messages are at or below each level
+//! - the subscription filters, with how many subscriptions are on each
+//!   filter and at or below it
+//!
+//! Either comes as JSON or as Graphviz DOT. Branches deeper than the
+//! requested depth are cut off but still counted in their parent, so a
+//! sprawling namespace shows up as a node with a large count.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use ahash::AHashMap;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use super::{Broker, BrokerEvent};
+
+/// Topics of recent traffic, each with its message count and last use
+#[derive(Default)]
+pub struct TopicActivity {
+    topics: Mutex<AHashMap<String, (u64, Instant)>>,
+}
+
+impl TopicActivity {
+    /// Count a message to `topic`, remembering at most `max` topics; when
+    /// full, the least recently used quarter is forgotten
+    pub fn record(&self, topic: &str, max: usize) {
+        let now = Instant::now();
+        let mut topics = self.topics.lock();
+        if let Some((count, last)) = topics.get_mut(topic) {
+            *count += 1;
+            *last = now;
+            return;
+        }
+        if !topics.is_empty() && topics.len() >= max {
+            let mut last_used: Vec<Instant> = topics.values().map(|(_, last)| *last).collect();
+            let cutoff = (max / 4).min(last_used.len() - 1);
+            let (_, &mut oldest, _) = last_used.select_nth_unstable(cutoff);
+            topics.retain(|_, (_, last)| *last > oldest);
+        }
+        topics.insert(topic.to_string(), (1, now));
+    }
+
+    /// Topics used within `window`, with their message counts
+    pub fn recent(&self, window: Duration) -> Vec<(String, u64)> {
+        let mut topics = self.topics.lock();
+        topics.retain(|_, (_, last)| last.elapsed() <= window);
+        topics
+            .iter()
+            .map(|(topic, (count, _))| (topic.clone(), *count))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.topics.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.topics.lock().is_empty()
+    }
+}
+
+/// A level of the concrete topic tree
+#[derive(Debug, Default, Serialize)]
+pub struct TopicNode {
+    /// Topic level ("" for the root)
+    pub name: String,
+    /// Concrete topics at or below this level
+    pub topics: usize,
+    /// Retained messages at or below this level
+    pub retained: usize,
+    /// Recent messages at or below this level
+    pub messages: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TopicNode>,
+}
+
+/// A level of the subscription filter tree
+#[derive(Debug, Default, Serialize)]
+pub struct FilterNode {
+    /// Filter level ("" for the root)
+    pub name: String,
+    /// Subscriptions on the filter ending at this level
+    pub subscriptions: usize,
+    /// Subscriptions on filters at or below this level
+    pub total: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<FilterNode>,
+}
+
+/// Both trees, as returned by the export
+#[derive(Debug, Serialize)]
+pub struct TopicTree {
+    pub topics: TopicNode,
+    pub subscriptions: FilterNode,
+}
+
+/// Tree of levels with per-node counts, built before conversion
+#[derive(Default)]
+struct Levels<C> {
+    counts: C,
+    children: BTreeMap<String, Levels<C>>,
+}
+
+impl<C: Default> Levels<C> {
+    /// Add counts to every level on `path`, `add(counts, is_last)`
+    fn add(&mut self, path: &str, mut add: impl FnMut(&mut C, bool)) {
+        add(&mut self.counts, false);
+        let mut node = self;
+        let mut levels = path.split('/').peekable();
+        while let Some(level) = levels.next() {
+            node = node.children.entry(level.to_string()).or_default();
+            add(&mut node.counts, levels.peek().is_none());
+        }
+    }
+}
+
+#[derive(Default)]
+struct TopicCounts {
+    topics: usize,
+    retained: usize,
+    messages: u64,
+}
+
+#[derive(Default)]
+struct FilterCounts {
+    subscriptions: usize,
+    total: usize,
+}
+
+fn topic_node(name: String, levels: Levels<TopicCounts>, depth: usize) -> TopicNode {
+    TopicNode {
+        name,
+        topics: levels.counts.topics,
+        retained: levels.counts.retained,
+        messages: levels.counts.messages,
+        children: if depth == 0 {
+            Vec::new()
+        } else {
+            levels
+                .children
+                .into_iter()
+                .map(|(name, child)| topic_node(name, child, depth - 1))
+                .collect()
+        },
+    }
+}
+
+fn filter_node(name: String, levels: Levels<FilterCounts>, depth: usize) -> FilterNode {
+    FilterNode {
+        name,
+        subscriptions: levels.counts.subscriptions,
+        total: levels.counts.total,
+        children: if depth == 0 {
+            Vec::new()
+        } else {
+            levels
+                .children
+                .into_iter()
+                .map(|(name, child)| filter_node(name, child, depth - 1))
+                .collect()
+        },
+    }
+}
+
+impl TopicTree {
+    /// Build the trees from topics (with whether they are retained and
+    /// their recent messages) and subscription counts per filter, down to
+    /// `depth` levels
+    pub fn build(
+        topics: impl IntoIterator<Item = (String, bool, u64)>,
+        filters: impl IntoIterator<Item = (String, usize)>,
+        depth: usize,
+    ) -> Self {
+        let mut topic_levels = Levels::<TopicCounts>::default();
+        for (topic, retained, messages) in topics {
+            topic_levels.add(&topic, |counts, _| {
+                counts.topics += 1;
+                counts.retained += usize::from(retained);
+                counts.messages += messages;
+            });
+        }
+
+        let mut filter_levels = Levels::<FilterCounts>::default();
+        for (filter, subscriptions) in filters {
+            filter_levels.add(&filter, |counts, last| {
+                counts.total += subscriptions;
+                if last {
+                    counts.subscriptions += subscriptions;
+                }
+            });
+        }
+
+        Self {
+            topics: topic_node(String::new(), topic_levels, depth),
+            subscriptions: filter_node(String::new(), filter_levels, depth),
+        }
+    }
+
+    /// Graphviz DOT rendering, one cluster per tree
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph topics {\n  rankdir=LR;\n  node [shape=box];\n");
+        let mut next_id = 0;
+
+        dot.push_str("  subgraph cluster_topics {\n    label=\"topics\";\n");
+        write_topic_dot(&mut dot, &self.topics, &mut next_id);
+        dot.push_str("  }\n");
+
+        dot.push_str("  subgraph cluster_subscriptions {\n    label=\"subscriptions\";\n");
+        write_filter_dot(&mut dot, &self.subscriptions, &mut next_id);
+        dot.push_str("  }\n}\n");
+        dot
+    }
+}
+
+/// Quote a level for a DOT label
+fn dot_label(name: &str) -> String {
+    let name = if name.is_empty() { "(root)" } else { name };
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn write_topic_dot(dot: &mut String, node: &TopicNode, next_id: &mut usize) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let _ = writeln!(
+        dot,
+        "    n{} [label=\"{}\\n{} topics, {} retained, {} msgs\"];",
+        id,
+        dot_label(&node.name),
+        node.topics,
+        node.retained,
+        node.messages
+    );
+    for child in &node.children {
+        let child_id = write_topic_dot(dot, child, next_id);
+        let _ = writeln!(dot, "    n{} -> n{};", id, child_id);
+    }
+    id
+}
+
+fn write_filter_dot(dot: &mut String, node: &FilterNode, next_id: &mut usize) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let _ = writeln!(
+        dot,
+        "    n{} [label=\"{}\\n{} subs ({} total)\"];",
+        id,
+        dot_label(&node.name),
+        node.subscriptions,
+        node.total
+    );
+    for child in &node.children {
+        let child_id = write_filter_dot(dot, child, next_id);
+        let _ = writeln!(dot, "    n{} -> n{};", id, child_id);
+    }
+    id
+}
+
+impl Broker {
+    /// The current topic and subscription trees, down to `depth` levels
+    pub fn topic_tree(&self, depth: usize) -> TopicTree {
+        let mut topics: BTreeMap<String, (bool, u64)> = self
+            .retained
+            .iter()
+            .map(|entry| (entry.key().clone(), (true, 0)))
+            .collect();
+        for (topic, messages) in self
+            .topic_activity
+            .recent(self.config.topic_tree.recent_window)
+        {
+            topics.entry(topic).or_default().1 = messages;
+        }
+        TopicTree::build(
+            topics
+                .into_iter()
+                .map(|(topic, (retained, messages))| (topic, retained, messages)),
+            self.subscriptions.filter_counts(),
+            depth,
+        )
+    }
+
+    /// Record the topics of published messages until shutdown
+    pub(crate) fn spawn_topic_activity(&self) {
+        let max = self.config.topic_tree.recent_topics;
+        let activity = self.topic_activity.clone();
+        let mut events = self.events.subscribe();
+        let mut shutdown_rx = self.shutdown.subscribe();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    result = events.recv() => match result {
+                        Ok(BrokerEvent::MessagePublished { topic, .. }) => {
+                            activity.record(&topic, max);
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = shutdown_rx.recv() => break,
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_tree() {
+        let tree = TopicTree::build(
+            [
+                ("sensors/a/temp".to_string(), true, 0),
+                ("sensors/b/temp".to_string(), false, 5),
+                ("status".to_string(), true, 2),
+            ],
+            [
+                ("#".to_string(), 1),
+                ("sensors/+/temp".to_string(), 3),
+                ("sensors/#".to_string(), 2),
+            ],
+            1,
+        );
+
+        assert_eq!(tree.topics.topics, 3);
+        assert_eq!(tree.topics.retained, 2);
+        assert_eq!(tree.topics.messages, 7);
+        let sensors = &tree.topics.children[0];
+        assert_eq!(sensors.name, "sensors");
+        assert_eq!(sensors.topics, 2);
+        assert_eq!(sensors.messages, 5);
+        // Cut off below the depth, but counted
+        assert!(sensors.children.is_empty());
+
+        let filters = &tree.subscriptions;
+        assert_eq!(filters.total, 6);
+        assert_eq!(filters.children[0].name, "#");
+        assert_eq!(filters.children[0].subscriptions, 1);
+        assert_eq!(filters.children[1].name, "sensors");
+        assert_eq!(filters.children[1].subscriptions, 0);
+        assert_eq!(filters.children[1].total, 5);
+
+        let dot = tree.to_dot();
+        assert!(dot.starts_with("digraph topics {"));
+        assert!(dot.contains("n0 [label=\"(root)\\n3 topics, 2 retained, 7 msgs\"];"));
+        assert!(dot.contains("n0 -> n1;"));
+    }
+
+    #[test]
+    fn test_topic_activity() {
+        let activity = TopicActivity::default();
+        for topic in ["a", "b", "a", "c", "d"] {
+            activity.record(topic, 4);
+        }
+        let mut recent = activity.recent(Duration::from_secs(60));
+        recent.sort();
+        assert_eq!(recent[0], ("a".to_string(), 2));
+        assert_eq!(recent.len(), 4);
+
+        // Full: the least recently used go
+        activity.record("e", 4);
+        assert!(activity.len() < 4 + 1);
+        assert!(activity
+            .recent(Duration::from_secs(60))
+            .iter()
+            .any(|(t, _)| t == "e"));
+        assert!(activity.recent(Duration::ZERO).is_empty());
+    }
+}
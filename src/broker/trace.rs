@@ -0,0 +1,479 @@
+//! Live Message Tracing
+//!
+//! Answers "why didn't my message arrive" on a running broker. A trace is
+//! started through the admin API for a client ID, a topic filter, or both,
+//! and runs until it is stopped or expires. While it runs, each matching
+//! packet a connection reads or writes becomes a [`TraceEvent`]. Events are
+//! published as JSON to `$SYS/trace/<id>` and streamed to the admin API's
+//! server-sent events endpoint. Trace IDs come from the broker-wide
+//! [`crate::id`] generator:
+//!
+//! ```json
+//! {"trace": "0190f1c2-3a4b-7c5d-8e6f-0123456789ab", "timestamp_ms": 1714566900123, "client_id": "sensor-1",
+//!  "direction": "in", "packet": "PUBLISH", "size": 42,
+//!  "topic": "sensors/1/temp", "qos": 1, "retain": false, "packet_id": 7}
+//! ```
+//!
+//! A client trace covers everything the client sends and receives: CONNECT
+//! and CONNACK, SUBSCRIBE and its granted reason codes, PUBLISH, acks, and
+//! DISCONNECT. A topic trace covers PUBLISH packets on matching topics, in
+//! both directions. Outgoing messages that are queued (send quota or
+//! inflight window full) or dropped (over the client's maximum packet
+//! size) are reported with a `held` reason instead of being written.
+//!
+//! The `$SYS/trace/` topics themselves are never traced, so subscribing to
+//! a trace can't feed it.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use parking_lot::RwLock;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use super::routing_id::routing_id;
+use crate::protocol::Packet;
+use crate::topic::{topic_matches_filter, validate_topic_filter};
+
+/// Topic prefix trace events are published under
+pub const TRACE_TOPIC_PREFIX: &str = "$SYS/trace/";
+
+/// Traces running at once
+pub const MAX_TRACES: usize = 16;
+
+/// Longest a trace may run
+pub const MAX_TRACE_DURATION: Duration = Duration::from_secs(3600);
+
+/// Events buffered for slow consumers before they miss some
+const EVENT_BUFFER: usize = 4096;
+
+/// Why a trace can't be started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceError {
+    /// Neither a client ID nor a topic filter
+    NoCriteria,
+    InvalidFilter(&'static str),
+    TooManyTraces,
+}
+
+impl std::fmt::Display for TraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceError::NoCriteria => write!(f, "a trace needs a client_id or a topic filter"),
+            TraceError::InvalidFilter(e) => write!(f, "invalid topic filter: {}", e),
+            TraceError::TooManyTraces => write!(f, "at most {} traces can run", MAX_TRACES),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+/// Whether a packet was read from or written to the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceDirection {
+    In,
+    Out,
+}
+
+/// A running trace
+#[derive(Debug, Clone, Serialize)]
+pub struct Trace {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// Topic the trace's events are published to
+    pub topic: String,
+    #[serde(skip)]
+    pub expires_at: Instant,
+}
+
+impl Trace {
+    fn matches(&self, client_id: &str, topic: Option<&str>) -> bool {
+        self.client_id.as_deref().is_none_or(|id| id == client_id)
+            && self
+                .filter
+                .as_deref()
+                .is_none_or(|filter| topic.is_some_and(|t| topic_matches_filter(t, filter)))
+    }
+}
+
+/// One traced packet
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEvent {
+    pub trace: String,
+    pub timestamp_ms: u64,
+    pub client_id: String,
+    pub direction: TraceDirection,
+    /// Packet type ("PUBLISH", "SUBACK", ...)
+    pub packet: &'static str,
+    /// Encoded size in bytes (0 if the packet wasn't written)
+    pub size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qos: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retain: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packet_id: Option<u16>,
+    /// Routing ID of a PUBLISH (see `routing_id`); the broker assigns it
+    /// when routing, so only outgoing PUBLISH packets have it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routing_id: Option<String>,
+    /// Reason code of acks, CONNACK and DISCONNECT
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Filters of SUBSCRIBE and UNSUBSCRIBE
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub filters: Vec<String>,
+    /// Per-filter reason codes of SUBACK and UNSUBACK
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+    /// Why an outgoing packet was queued or dropped instead of written
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub held: Option<&'static str>,
+}
+
+impl TraceEvent {
+    fn new(client_id: &str, direction: TraceDirection, packet: &Packet, size: usize) -> Self {
+        let mut event = Self {
+            trace: String::new(),
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            client_id: client_id.to_string(),
+            direction,
+            packet: packet_name(packet),
+            size,
+            topic: None,
+            qos: None,
+            retain: None,
+            packet_id: None,
+            routing_id: None,
+            reason: None,
+            filters: Vec::new(),
+            reasons: Vec::new(),
+            held: None,
+        };
+        match packet {
+            Packet::Publish(p) => {
+                event.topic = Some(p.topic.clone());
+                event.qos = Some(p.qos as u8);
+                event.retain = Some(p.retain);
+                event.packet_id = p.packet_id;
+                event.routing_id = routing_id(&p.properties).map(str::to_string);
+            }
+            Packet::ConnAck(p) => event.reason = Some(p.reason_code.to_string()),
+            Packet::PubAck(p) => {
+                event.packet_id = Some(p.packet_id);
+                event.reason = Some(p.reason_code.to_string());
+            }
+            Packet::PubRec(p) => {
+                event.packet_id = Some(p.packet_id);
+                event.reason = Some(p.reason_code.to_string());
+            }
+            Packet::PubRel(p) => {
+                event.packet_id = Some(p.packet_id);
+                event.reason = Some(p.reason_code.to_string());
+            }
+            Packet::PubComp(p) => {
+                event.packet_id = Some(p.packet_id);
+                event.reason = Some(p.reason_code.to_string());
+            }
+            Packet::Subscribe(p) => {
+                event.packet_id = Some(p.packet_id);
+                event.filters = p.subscriptions.iter().map(|s| s.filter.clone()).collect();
+            }
+            Packet::SubAck(p) => {
+                event.packet_id = Some(p.packet_id);
+                event.reasons = p.reason_codes.iter().map(|r| r.to_string()).collect();
+            }
+            Packet::Unsubscribe(p) => {
+                event.packet_id = Some(p.packet_id);
+                event.filters = p.filters.clone();
+            }
+            Packet::UnsubAck(p) => {
+                event.packet_id = Some(p.packet_id);
+                event.reasons = p.reason_codes.iter().map(|r| r.to_string()).collect();
+            }
+            Packet::Disconnect(p) => event.reason = Some(p.reason_code.to_string()),
+            Packet::Connect(_) | Packet::PingReq | Packet::PingResp | Packet::Auth(_) => {}
+        }
+        event
+    }
+}
+
+fn packet_name(packet: &Packet) -> &'static str {
+    match packet {
+        Packet::Connect(_) => "CONNECT",
+        Packet::ConnAck(_) => "CONNACK",
+        Packet::Publish(_) => "PUBLISH",
+        Packet::PubAck(_) => "PUBACK",
+        Packet::PubRec(_) => "PUBREC",
+        Packet::PubRel(_) => "PUBREL",
+        Packet::PubComp(_) => "PUBCOMP",
+        Packet::Subscribe(_) => "SUBSCRIBE",
+        Packet::SubAck(_) => "SUBACK",
+        Packet::Unsubscribe(_) => "UNSUBSCRIBE",
+        Packet::UnsubAck(_) => "UNSUBACK",
+        Packet::PingReq => "PINGREQ",
+        Packet::PingResp => "PINGRESP",
+        Packet::Disconnect(_) => "DISCONNECT",
+        Packet::Auth(_) => "AUTH",
+    }
+}
+
+/// The broker's running traces
+#[derive(Debug)]
+pub struct Tracer {
+    traces: RwLock<Vec<Trace>>,
+    /// Number of traces, checked before anything else on the packet path
+    active: AtomicUsize,
+    events: broadcast::Sender<TraceEvent>,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self {
+            traces: RwLock::new(Vec::new()),
+            active: AtomicUsize::new(0),
+            events: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+}
+
+impl Tracer {
+    /// Start tracing a client, a topic filter, or a client's publishes to
+    /// a topic filter for `duration` (capped at [`MAX_TRACE_DURATION`])
+    pub fn start(
+        &self,
+        client_id: Option<String>,
+        filter: Option<String>,
+        duration: Duration,
+    ) -> Result<Trace, TraceError> {
+        if client_id.is_none() && filter.is_none() {
+            return Err(TraceError::NoCriteria);
+        }
+        if let Some(ref filter) = filter {
+            validate_topic_filter(filter).map_err(TraceError::InvalidFilter)?;
+        }
+        let mut traces = self.traces.write();
+        let now = Instant::now();
+        traces.retain(|t| t.expires_at > now);
+        if traces.len() >= MAX_TRACES {
+            return Err(TraceError::TooManyTraces);
+        }
+        let id = crate::id::next_id();
+        let trace = Trace {
+            topic: format!("{}{}", TRACE_TOPIC_PREFIX, id),
+            id,
+            client_id,
+            filter,
+            expires_at: now + duration.min(MAX_TRACE_DURATION),
+        };
+        traces.push(trace.clone());
+        self.active.store(traces.len(), Ordering::Release);
+        Ok(trace)
+    }
+
+    /// Stop a trace; false if it isn't running
+    pub fn stop(&self, id: &str) -> bool {
+        let mut traces = self.traces.write();
+        let before = traces.len();
+        traces.retain(|t| t.id != id);
+        self.active.store(traces.len(), Ordering::Release);
+        traces.len() < before
+    }
+
+    /// Running traces (expired ones are removed)
+    pub fn list(&self) -> Vec<Trace> {
+        self.expire();
+        self.traces.read().clone()
+    }
+
+    /// A running trace
+    pub fn get(&self, id: &str) -> Option<Trace> {
+        let now = Instant::now();
+        self.traces
+            .read()
+            .iter()
+            .find(|t| t.id == id && t.expires_at > now)
+            .cloned()
+    }
+
+    /// Receive the events of every trace
+    pub fn subscribe(&self) -> broadcast::Receiver<TraceEvent> {
+        self.events.subscribe()
+    }
+
+    /// Whether any trace is running (cheap; checked per packet)
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire) > 0
+    }
+
+    /// Report a packet read from or written to a client
+    pub fn record(&self, client_id: &str, direction: TraceDirection, packet: &Packet, size: usize) {
+        self.emit(client_id, direction, packet, size, None);
+    }
+
+    /// Report an outgoing packet that was queued or dropped instead of
+    /// written
+    pub fn record_held(&self, client_id: &str, packet: &Packet, why: &'static str) {
+        self.emit(client_id, TraceDirection::Out, packet, 0, Some(why));
+    }
+
+    fn emit(
+        &self,
+        client_id: &str,
+        direction: TraceDirection,
+        packet: &Packet,
+        size: usize,
+        held: Option<&'static str>,
+    ) {
+        if !self.is_active() {
+            return;
+        }
+        let topic = match packet {
+            Packet::Publish(p) if p.topic.starts_with(TRACE_TOPIC_PREFIX) => return,
+            Packet::Publish(p) => Some(p.topic.as_str()),
+            _ => None,
+        };
+        let now = Instant::now();
+        let mut expired = false;
+        let mut event: Option<TraceEvent> = None;
+        for trace in self.traces.read().iter() {
+            if trace.expires_at <= now {
+                expired = true;
+                continue;
+            }
+            if !trace.matches(client_id, topic) {
+                continue;
+            }
+            let mut e = event
+                .get_or_insert_with(|| TraceEvent::new(client_id, direction, packet, size))
+                .clone();
+            e.trace = trace.id.clone();
+            e.held = held;
+            let _ = self.events.send(e);
+        }
+        if expired {
+            self.expire();
+        }
+    }
+
+    fn expire(&self) {
+        let now = Instant::now();
+        let mut traces = self.traces.write();
+        traces.retain(|t| t.expires_at > now);
+        self.active.store(traces.len(), Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Publish, QoS, ReasonCode, SubAck};
+    use bytes::Bytes;
+
+    fn publish(topic: &str) -> Packet {
+        Packet::Publish(Publish {
+            dup: false,
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            topic: topic.to_string(),
+            packet_id: Some(7),
+            payload: Bytes::from_static(b"21.5"),
+            properties: Default::default(),
+        })
+    }
+
+    #[test]
+    fn test_matching() {
+        let tracer = Tracer::default();
+        assert!(!tracer.is_active());
+        let mut events = tracer.subscribe();
+        let minute = Duration::from_secs(60);
+        let client = tracer.start(Some("dev1".into()), None, minute).unwrap();
+        let topic = tracer
+            .start(None, Some("sensors/+/temp".into()), minute)
+            .unwrap();
+        assert!(tracer.is_active());
+
+        tracer.record("dev1", TraceDirection::In, &publish("sensors/1/temp"), 42);
+        let suback = Packet::SubAck(SubAck {
+            packet_id: 1,
+            reason_codes: vec![ReasonCode::NotAuthorized],
+            properties: Default::default(),
+        });
+        tracer.record("dev1", TraceDirection::Out, &suback, 5);
+        tracer.record_held("dev2", &publish("sensors/2/temp"), "inflight limit");
+        tracer.record("dev2", TraceDirection::In, &publish("other"), 10);
+        // Trace topics are never traced
+        tracer.record("dev1", TraceDirection::Out, &publish("$SYS/trace/1"), 10);
+
+        let received: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        let summary: Vec<_> = received
+            .iter()
+            .map(|e| (e.trace.as_str(), e.client_id.as_str(), e.packet))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (client.id.as_str(), "dev1", "PUBLISH"),
+                (topic.id.as_str(), "dev1", "PUBLISH"),
+                (client.id.as_str(), "dev1", "SUBACK"),
+                (topic.id.as_str(), "dev2", "PUBLISH"),
+            ]
+        );
+        assert_eq!(received[0].size, 42);
+        assert_eq!(received[2].reasons, vec!["Not authorized".to_string()]);
+        assert_eq!(received[3].held, Some("inflight limit"));
+
+        let json = serde_json::to_value(&received[0]).unwrap();
+        assert_eq!(json["direction"], "in");
+        assert_eq!(json["topic"], "sensors/1/temp");
+        assert!(json.get("held").is_none());
+
+        assert!(tracer.stop(&client.id));
+        assert!(!tracer.stop(&client.id));
+        assert_eq!(tracer.list().len(), 1);
+    }
+
+    #[test]
+    fn test_start_and_expiry() {
+        let tracer = Tracer::default();
+        assert_eq!(
+            tracer
+                .start(None, None, Duration::from_secs(1))
+                .unwrap_err(),
+            TraceError::NoCriteria
+        );
+        assert!(tracer
+            .start(None, Some("a/#/b".into()), Duration::from_secs(1))
+            .is_err());
+
+        let trace = tracer
+            .start(Some("dev".into()), None, Duration::ZERO)
+            .unwrap();
+        assert_eq!(trace.topic, format!("$SYS/trace/{}", trace.id));
+        assert!(tracer.get(&trace.id).is_none());
+        assert!(tracer.list().is_empty());
+        assert!(!tracer.is_active());
+
+        for i in 0..MAX_TRACES {
+            tracer
+                .start(Some(i.to_string()), None, Duration::from_secs(60))
+                .unwrap();
+        }
+        assert_eq!(
+            tracer
+                .start(Some("one-more".into()), None, Duration::from_secs(60))
+                .unwrap_err(),
+            TraceError::TooManyTraces
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! Runtime Tunables
+//!
+//! During an incident a limit from the config file can turn out wrong: a
+//! publish rate too low to drain a backlog, a slow-client watermark that
+//! disconnects half the fleet. Selected settings can be changed on the
+//! running broker (`/api/v1/tunables` on the admin API) and take effect at
+//! once, for connected clients too:
+//!
+//! - `publish_rate.messages_per_sec`, `publish_rate.burst`: the publish
+//!   rate limit (only when `limits.publish_rate` is enabled)
+//! - `batch.max_messages`: messages per batch frame (0 = unlimited)
+//! - `listeners.<listener>.max_outbound_messages` and
+//!   `.max_outbound_bytes`: the backlog a subscriber may have before its
+//!   listener's `slow_client` policy applies (0 = unlimited)
+//!
+//! Values outside a tunable's bounds are refused, and every change is
+//! logged with who made it. A change holds until it is reset or the broker
+//! restarts, so it should be carried over to the config file; the listing
+//! shows which values differ from the config's. The log filter has its own
+//! runtime control (`/debug/log`).
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+use tracing::warn;
+
+use super::{Broker, BrokerConfig};
+use crate::config::{ListenerLimits, LISTENER_NAMES};
+
+/// Value of a tunable that isn't overridden
+const UNSET: u64 = u64::MAX;
+
+struct Tunable {
+    name: String,
+    description: &'static str,
+    min: u64,
+    max: u64,
+    configured: AtomicU64,
+    /// Runtime override, or `UNSET`
+    value: AtomicU64,
+}
+
+impl Tunable {
+    fn new(name: impl Into<String>, description: &'static str, min: u64, max: u64) -> Self {
+        Self {
+            name: name.into(),
+            description,
+            min,
+            max,
+            configured: AtomicU64::new(0),
+            value: AtomicU64::new(UNSET),
+        }
+    }
+
+    fn configure(&self, value: u64) {
+        self.configured.store(value, Ordering::Relaxed);
+    }
+
+    fn overridden(&self) -> Option<u64> {
+        let value = self.value.load(Ordering::Relaxed);
+        (value != UNSET).then_some(value)
+    }
+
+    fn value(&self) -> u64 {
+        self.overridden()
+            .unwrap_or_else(|| self.configured.load(Ordering::Relaxed))
+    }
+}
+
+/// A tunable as listed by `GET /api/v1/tunables`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TunableInfo {
+    pub name: String,
+    /// Value in force
+    pub value: u64,
+    /// Value from the config
+    pub configured: u64,
+    /// Whether `value` was set at runtime
+    pub overridden: bool,
+    pub min: u64,
+    pub max: u64,
+    pub description: &'static str,
+}
+
+/// A tunable's value before and after a change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TunableChange {
+    pub name: String,
+    pub previous: u64,
+    pub value: u64,
+}
+
+/// Why a tunable couldn't be changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TunableError {
+    /// No tunable of that name
+    Unknown(String),
+    /// The value is outside the tunable's bounds
+    OutOfRange { name: String, min: u64, max: u64 },
+}
+
+impl fmt::Display for TunableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TunableError::Unknown(name) => write!(f, "no tunable named {}", name),
+            TunableError::OutOfRange { name, min, max } => {
+                write!(f, "{} must be between {} and {}", name, min, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TunableError {}
+
+/// Settings changeable at runtime (see the module docs)
+pub struct Tunables {
+    publish_rate: Option<(Tunable, Tunable)>,
+    batch_max_messages: Tunable,
+    /// Backlog limits by listener: messages, bytes
+    outbound: Vec<(&'static str, Tunable, Tunable)>,
+}
+
+impl Tunables {
+    pub fn new(config: &BrokerConfig) -> Self {
+        // The rate limiter only exists when enabled at startup
+        let publish_rate = config.publish_rate.enabled().then(|| {
+            let rate = Tunable::new(
+                "publish_rate.messages_per_sec",
+                "Messages per second each identity may publish",
+                1,
+                1_000_000,
+            );
+            let burst = Tunable::new(
+                "publish_rate.burst",
+                "Messages each identity may publish in a burst",
+                1,
+                1_000_000,
+            );
+            rate.configure(config.publish_rate.messages_per_sec as u64);
+            burst.configure(config.publish_rate.burst.max(1) as u64);
+            (rate, burst)
+        });
+        let tunables = Self {
+            publish_rate,
+            batch_max_messages: Tunable::new(
+                "batch.max_messages",
+                "Messages per batch frame (0 = unlimited)",
+                0,
+                1_000_000,
+            ),
+            outbound: LISTENER_NAMES
+                .into_iter()
+                .map(|listener| {
+                    let messages = Tunable::new(
+                        format!("listeners.{}.max_outbound_messages", listener),
+                        "Backlog messages before slow_client applies (0 = unlimited)",
+                        0,
+                        10_000_000,
+                    );
+                    let bytes = Tunable::new(
+                        format!("listeners.{}.max_outbound_bytes", listener),
+                        "Backlog bytes before slow_client applies (0 = unlimited)",
+                        0,
+                        1 << 40,
+                    );
+                    (listener, messages, bytes)
+                })
+                .collect(),
+        };
+        tunables.configure(config);
+        tunables
+    }
+
+    /// Take the configured values of a reloaded config (overrides stay);
+    /// the publish rate is only read at startup
+    pub fn configure(&self, config: &BrokerConfig) {
+        self.batch_max_messages
+            .configure(config.batch.max_messages as u64);
+        for (listener, messages, bytes) in &self.outbound {
+            let limits = config
+                .listener_limits
+                .get(*listener)
+                .copied()
+                .unwrap_or_default();
+            messages.configure(limits.max_outbound_messages as u64);
+            bytes.configure(limits.max_outbound_bytes as u64);
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Tunable> {
+        self.publish_rate
+            .iter()
+            .flat_map(|(rate, burst)| [rate, burst])
+            .chain([&self.batch_max_messages])
+            .chain(
+                self.outbound
+                    .iter()
+                    .flat_map(|(_, messages, bytes)| [messages, bytes]),
+            )
+    }
+
+    pub fn list(&self) -> Vec<TunableInfo> {
+        self.entries()
+            .map(|tunable| TunableInfo {
+                name: tunable.name.clone(),
+                value: tunable.value(),
+                configured: tunable.configured.load(Ordering::Relaxed),
+                overridden: tunable.overridden().is_some(),
+                min: tunable.min,
+                max: tunable.max,
+                description: tunable.description,
+            })
+            .collect()
+    }
+
+    /// Override a tunable, or with `None` go back to the configured value
+    pub fn set(&self, name: &str, value: Option<u64>) -> Result<TunableChange, TunableError> {
+        let tunable = self
+            .entries()
+            .find(|tunable| tunable.name == name)
+            .ok_or_else(|| TunableError::Unknown(name.to_string()))?;
+        if let Some(value) = value {
+            if value < tunable.min || value > tunable.max {
+                return Err(TunableError::OutOfRange {
+                    name: tunable.name.clone(),
+                    min: tunable.min,
+                    max: tunable.max,
+                });
+            }
+        }
+        let previous = tunable.value();
+        tunable
+            .value
+            .store(value.unwrap_or(UNSET), Ordering::Relaxed);
+        Ok(TunableChange {
+            name: tunable.name.clone(),
+            previous,
+            value: tunable.value(),
+        })
+    }
+
+    /// Publish rate and burst in force, when rate limiting is enabled
+    pub fn publish_rate(&self) -> Option<(u32, u32)> {
+        self.publish_rate
+            .as_ref()
+            .map(|(rate, burst)| (rate.value() as u32, burst.value() as u32))
+    }
+
+    /// Messages per batch frame, `configured` unless overridden
+    pub fn batch_max_messages(&self, configured: usize) -> usize {
+        self.batch_max_messages
+            .overridden()
+            .map_or(configured, |max| max as usize)
+    }
+
+    /// A listener's limits with overridden backlog limits applied
+    pub fn listener_limits(
+        &self,
+        listener: &str,
+        limits: Option<ListenerLimits>,
+    ) -> Option<ListenerLimits> {
+        let Some((_, messages, bytes)) = self.outbound.iter().find(|(l, ..)| *l == listener) else {
+            return limits;
+        };
+        let (max_messages, max_bytes) = (messages.overridden(), bytes.overridden());
+        if max_messages.is_none() && max_bytes.is_none() {
+            return limits;
+        }
+        let mut limits = limits.unwrap_or_default();
+        if let Some(max) = max_messages {
+            limits.max_outbound_messages = max as usize;
+        }
+        if let Some(max) = max_bytes {
+            limits.max_outbound_bytes = max as usize;
+        }
+        Some(limits)
+    }
+}
+
+impl Broker {
+    /// Change a tunable (`None` resets it), logging the change as made
+    /// `by` the caller
+    pub fn set_tunable(
+        &self,
+        name: &str,
+        value: Option<u64>,
+        by: &str,
+    ) -> Result<TunableChange, TunableError> {
+        let change = self.tunables.set(name, value)?;
+        if name.starts_with("publish_rate.") {
+            if let (Some(limiter), Some((rate, burst))) =
+                (self.sessions.rate_limits(), self.tunables.publish_rate())
+            {
+                limiter.set_limits(rate, burst);
+            }
+        }
+        match value {
+            Some(_) => warn!(
+                "Tunable {} changed from {} to {} by {}",
+                change.name, change.previous, change.value, by
+            ),
+            None => warn!(
+                "Tunable {} reset from {} to its configured {} by {}",
+                change.name, change.previous, change.value, by
+            ),
+        }
+        Ok(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PublishRateConfig;
+
+    #[test]
+    fn test_tunables() {
+        let config = BrokerConfig {
+            publish_rate: PublishRateConfig {
+                messages_per_sec: 10,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let tunables = Tunables::new(&config);
+        assert_eq!(tunables.publish_rate(), Some((10, 100)));
+        assert_eq!(tunables.listener_limits("tcp", None), None);
+
+        let change = tunables
+            .set("publish_rate.messages_per_sec", Some(50))
+            .unwrap();
+        assert_eq!((change.previous, change.value), (10, 50));
+        assert_eq!(tunables.publish_rate(), Some((50, 100)));
+
+        // Bounds are enforced
+        assert_eq!(
+            tunables.set("publish_rate.burst", Some(0)),
+            Err(TunableError::OutOfRange {
+                name: "publish_rate.burst".to_string(),
+                min: 1,
+                max: 1_000_000,
+            })
+        );
+        assert!(matches!(
+            tunables.set("limits.max_connections", Some(1)),
+            Err(TunableError::Unknown(_))
+        ));
+
+        // A backlog override gives a listener limits of its own
+        tunables
+            .set("listeners.tcp.max_outbound_messages", Some(500))
+            .unwrap();
+        let limits = tunables.listener_limits("tcp", None).unwrap();
+        assert_eq!(limits.max_outbound_messages, 500);
+        assert_eq!(limits.max_outbound_bytes, 0);
+
+        // Overrides survive a reload; resetting takes the configured value
+        tunables.configure(&BrokerConfig {
+            batch: crate::config::BatchConfig {
+                max_messages: 20,
+                ..Default::default()
+            },
+            ..config
+        });
+        tunables.set("batch.max_messages", Some(5)).unwrap();
+        assert_eq!(tunables.batch_max_messages(20), 5);
+        let change = tunables.set("batch.max_messages", None).unwrap();
+        assert_eq!((change.previous, change.value), (5, 20));
+        assert_eq!(tunables.batch_max_messages(20), 20);
+
+        let listed = tunables.list();
+        assert_eq!(listed.len(), 3 + 2 * LISTENER_NAMES.len());
+        assert!(listed
+            .iter()
+            .any(|t| t.name == "publish_rate.messages_per_sec" && t.overridden));
+
+        // Without rate limiting there's no rate to tune
+        let tunables = Tunables::new(&BrokerConfig::default());
+        assert_eq!(tunables.publish_rate(), None);
+        assert!(tunables.set("publish_rate.burst", Some(10)).is_err());
+    }
+}
@@ -5,8 +5,8 @@
 
 use std::collections::HashSet;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use bytes::Bytes;
 use chitchat::transport::UdpTransport;
@@ -16,18 +16,51 @@ use parking_lot::RwLock;
 use tokio::net::TcpListener;
 use tracing::{debug, error, info, warn};
 
-use crate::config::{ClusterConfig, ProxyProtocolConfig};
+use crate::config::{ClusterConfig, ClusterRole, LinkCompressionConfig, ProxyProtocolConfig};
+use crate::metrics::Metrics;
 use crate::protocol::QoS;
 use crate::proxy::parse_proxy_header;
+use crate::remote::compression::negotiate;
 use crate::remote::RemotePeer;
 use crate::remote::RemotePeerStatus;
+use crate::remote::{CompressionMetrics, LinkCompressor};
+use crate::session::RateBucket;
+use crate::transport::PeerAddr;
 
+use super::metrics::{federate, split_families, MetricFamily, MetricsReports};
 use super::peer::{ClusterInboundCallback, ClusterPeer};
-use super::protocol::{frame_message, read_frame_length, ClusterMessage, CLUSTER_PROTOCOL_VERSION};
+use super::protocol::{
+    decode_frame, frame_message, read_frame_length, ClusterMessage, CLUSTER_PROTOCOL_VERSION,
+};
+use super::takeover::SessionOwners;
 
 /// Chitchat state keys
 const KEY_PEER_ADDR: &str = "peer_addr";
 const KEY_SUBSCRIPTIONS: &str = "subscriptions";
+const KEY_ROLE: &str = "role";
+/// Node ID of the broker-wide ID generator, for generators that embed one
+const KEY_ID_NODE: &str = "id_node";
+
+/// Subscription filter advertised by observers (receive every publish)
+const OBSERVER_FILTER: &str = "#";
+
+/// How often to check a peer's connection for retained or session resync
+const PEER_SYNC_POLL: Duration = Duration::from_millis(500);
+
+/// Encoded size at which rate limit syncs are split (peers read 64 KiB frames)
+const RATE_LIMIT_SYNC_CHUNK: usize = 32 * 1024;
+
+/// Encoded size at which metrics reports are split
+const METRICS_REPORT_CHUNK: usize = 32 * 1024;
+
+/// Snapshot of the local retained store (topic, payload, QoS) sent to observers
+pub type ClusterRetainedSnapshot = Arc<dyn Fn() -> Vec<(String, Bytes, QoS)> + Send + Sync>;
+
+/// Callback for publish rate buckets received from cluster peers
+pub type ClusterRateLimitCallback = Arc<dyn Fn(Vec<RateBucket>) + Send + Sync>;
+
+/// Callback for clients that connected to a cluster peer (by client ID)
+pub type ClusterTakeoverCallback = Arc<dyn Fn(String) + Send + Sync>;
 
 /// Cluster manager for gossip-based horizontal scaling
 pub struct ClusterManager {
@@ -43,6 +76,18 @@ pub struct ClusterManager {
     local_subscriptions: Arc<RwLock<HashSet<String>>>,
     /// Callback for inbound messages from cluster peers
     inbound_callback: ClusterInboundCallback,
+    /// Retained store snapshot for syncing observers
+    retained_snapshot: Option<ClusterRetainedSnapshot>,
+    /// Merges publish rate buckets received from peers
+    rate_limit_callback: Option<ClusterRateLimitCallback>,
+    /// Drops local sessions of clients that connected to a peer
+    takeover_callback: Option<ClusterTakeoverCallback>,
+    /// Latest known connect of each client (see `takeover`)
+    session_owners: Arc<SessionOwners>,
+    /// Metrics reports received from peers (kept by the leader)
+    metrics_reports: Arc<MetricsReports>,
+    /// Collectors compressed peer traffic is reported to
+    compression_metrics: Option<CompressionMetrics>,
 }
 
 impl ClusterManager {
@@ -89,30 +134,118 @@ impl ClusterManager {
         // Create UDP transport
         let transport = UdpTransport;
 
+        // Observers subscribe to everything so serving nodes forward all publishes
+        let local_subscriptions: HashSet<String> = if config.is_observer() {
+            HashSet::from([OBSERVER_FILTER.to_string()])
+        } else {
+            HashSet::new()
+        };
+        let subscriptions_json =
+            serde_json::to_string(&local_subscriptions).unwrap_or_else(|_| "[]".to_string());
+
         // Initial key-value pairs for our node - use advertise address for peer_addr
-        let initial_kvs = vec![
+        let mut initial_kvs = vec![
             (KEY_PEER_ADDR.to_string(), peer_advertise_addr.to_string()),
-            (KEY_SUBSCRIPTIONS.to_string(), "[]".to_string()),
+            (KEY_SUBSCRIPTIONS.to_string(), subscriptions_json),
+            (KEY_ROLE.to_string(), config.role.as_str().to_string()),
         ];
+        if let Some(id_node) = crate::id::global().node_id() {
+            initial_kvs.push((KEY_ID_NODE.to_string(), id_node.to_string()));
+        }
 
         // Spawn chitchat
         let chitchat = spawn_chitchat(chitchat_config, initial_kvs, &transport).await?;
 
+        let session_owners = Arc::new(SessionOwners::new(node_id.clone()));
         Ok(Self {
             node_id,
             config,
             chitchat,
             peers: Arc::new(DashMap::new()),
-            local_subscriptions: Arc::new(RwLock::new(HashSet::new())),
+            local_subscriptions: Arc::new(RwLock::new(local_subscriptions)),
             inbound_callback,
+            retained_snapshot: None,
+            rate_limit_callback: None,
+            takeover_callback: None,
+            session_owners,
+            metrics_reports: Arc::new(MetricsReports::default()),
+            compression_metrics: None,
         })
     }
 
+    /// Set the retained store snapshot sent to observers when they connect
+    pub fn with_retained_snapshot(mut self, snapshot: ClusterRetainedSnapshot) -> Self {
+        self.retained_snapshot = Some(snapshot);
+        self
+    }
+
+    /// Share publish rate buckets with peers, merging theirs with `callback`
+    pub fn with_rate_limit_sync(mut self, callback: ClusterRateLimitCallback) -> Self {
+        self.rate_limit_callback = Some(callback);
+        self
+    }
+
+    /// Hand sessions over to the node their client last connected to
+    ///
+    /// `callback` is called with the client ID when a client connects to a
+    /// peer; announce local connections with [`announce_session`](Self::announce_session).
+    pub fn with_session_takeover(mut self, callback: ClusterTakeoverCallback) -> Self {
+        self.takeover_callback = Some(callback);
+        self
+    }
+
+    /// Report compressed peer traffic to these collectors
+    pub fn with_compression_metrics(mut self, metrics: CompressionMetrics) -> Self {
+        self.compression_metrics = Some(metrics);
+        self
+    }
+
     /// Get our node ID
     pub fn node_id(&self) -> &str {
         &self.node_id
     }
 
+    /// Get our role in the cluster
+    pub fn role(&self) -> ClusterRole {
+        self.config.role
+    }
+
+    /// Whether this node is a read-only observer
+    pub fn is_observer(&self) -> bool {
+        self.config.is_observer()
+    }
+
+    /// Whether this node leads cluster-wide singleton tasks (scheduled publishes)
+    ///
+    /// The leader is the serving node with the lowest node ID among those
+    /// alive in gossip, so every node reaches the same answer without a vote;
+    /// when it leaves, the next lowest takes over. Observers never lead.
+    pub fn is_leader(&self) -> bool {
+        !self.is_observer()
+            && !self.peers.iter().any(|p| {
+                p.value().role() == ClusterRole::Member && p.key().as_str() < self.node_id.as_str()
+            })
+    }
+
+    /// Node ID of the leader (see [`is_leader`](Self::is_leader)), if a
+    /// serving node is known
+    pub fn leader_id(&self) -> Option<String> {
+        let peers = self
+            .peers
+            .iter()
+            .filter(|p| p.value().role() == ClusterRole::Member)
+            .map(|p| p.key().clone());
+        let local = (!self.is_observer()).then(|| self.node_id.clone());
+        peers.chain(local).min()
+    }
+
+    /// Get all known peers, sorted by node ID
+    pub fn peers(&self) -> Vec<Arc<ClusterPeer>> {
+        let mut peers: Vec<_> = self.peers.iter().map(|p| p.value().clone()).collect();
+        peers.sort_by(|a, b| a.node_id().cmp(b.node_id()));
+        peers
+    }
+
     /// Get the number of peers
     pub fn peer_count(&self) -> usize {
         self.peers.len()
@@ -155,6 +288,9 @@ impl ClusterManager {
 
     /// Add a subscription filter
     pub async fn add_subscription(&self, filter: String) {
+        if self.is_observer() {
+            return;
+        }
         debug!("Cluster: adding subscription filter '{}'", filter);
         let filters = {
             let mut subs = self.local_subscriptions.write();
@@ -166,6 +302,9 @@ impl ClusterManager {
 
     /// Remove a subscription filter
     pub async fn remove_subscription(&self, filter: &str) {
+        if self.is_observer() {
+            return;
+        }
         let filters = {
             let mut subs = self.local_subscriptions.write();
             subs.remove(filter);
@@ -175,7 +314,14 @@ impl ClusterManager {
     }
 
     /// Forward a published message to peers that have matching subscriptions
-    pub async fn forward_publish(&self, topic: &str, payload: Bytes, qos: QoS, retain: bool) {
+    pub async fn forward_publish(
+        &self,
+        topic: &str,
+        payload: Bytes,
+        qos: QoS,
+        retain: bool,
+        publisher: Option<&str>,
+    ) {
         for peer in self.peers.iter() {
             let peer_ref = peer.value();
             let status = peer_ref.status();
@@ -190,7 +336,13 @@ impl ClusterManager {
             if status == RemotePeerStatus::Connected && should_fwd {
                 debug!("Cluster: forwarding to peer '{}'", peer_ref.node_id());
                 if let Err(e) = peer_ref
-                    .forward_publish(topic, payload.clone(), qos, retain)
+                    .forward_publish_from(
+                        topic,
+                        payload.clone(),
+                        qos,
+                        retain,
+                        publisher.map(str::to_string),
+                    )
                     .await
                 {
                     warn!(
@@ -203,6 +355,128 @@ impl ClusterManager {
         }
     }
 
+    /// Send changed publish rate buckets to serving peers
+    pub async fn broadcast_rate_limits(&self, buckets: Vec<RateBucket>) {
+        if buckets.is_empty() {
+            return;
+        }
+
+        // Split so no frame outgrows the peer's read buffer
+        let mut chunks = vec![Vec::new()];
+        let mut chunk_size = 0;
+        for bucket in buckets {
+            let size = bucket.identity.len() + 24;
+            if chunk_size + size > RATE_LIMIT_SYNC_CHUNK && chunk_size > 0 {
+                chunks.push(Vec::new());
+                chunk_size = 0;
+            }
+            chunk_size += size;
+            chunks.last_mut().unwrap().push(bucket);
+        }
+
+        for peer in self.peers.iter() {
+            let peer_ref = peer.value();
+            if peer_ref.status() != RemotePeerStatus::Connected
+                || peer_ref.role() == ClusterRole::Observer
+            {
+                continue;
+            }
+            for chunk in &chunks {
+                if let Err(e) = peer_ref.send_rate_limits(chunk.clone()).await {
+                    warn!(
+                        "Failed to sync rate limits to peer '{}': {}",
+                        peer_ref.node_id(),
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Tell serving peers a client connected here, so they drop its session
+    ///
+    /// Doesn't wait for the peers. Ones not connected now are told when
+    /// their link comes up (see `spawn_session_sync`).
+    pub fn announce_session(&self, client_id: &str) {
+        let epoch = self.session_owners.connected(client_id);
+        let peers: Vec<Arc<ClusterPeer>> = self
+            .peers
+            .iter()
+            .filter(|peer| {
+                peer.status() == RemotePeerStatus::Connected && peer.role() != ClusterRole::Observer
+            })
+            .map(|peer| peer.value().clone())
+            .collect();
+        if peers.is_empty() {
+            return;
+        }
+
+        let client_id = client_id.to_string();
+        tokio::spawn(async move {
+            for peer in peers {
+                if let Err(e) = peer.send_session_takeover(client_id.clone(), epoch).await {
+                    warn!(
+                        "Failed to announce session to peer '{}': {}",
+                        peer.node_id(),
+                        e
+                    );
+                }
+            }
+        });
+    }
+
+    /// How often nodes report their metrics to the leader, if federation
+    /// is enabled
+    pub fn metrics_report_interval(&self) -> Option<Duration> {
+        self.config
+            .metrics
+            .enabled
+            .then_some(self.config.metrics.interval)
+    }
+
+    /// Send this node's metrics to the leader (which keeps its own)
+    pub async fn report_metrics(&self, metrics: &Metrics) {
+        let Some(leader) = self.leader_id().filter(|id| *id != self.node_id) else {
+            return;
+        };
+        let Some(peer) = self.peers.get(&leader).map(|p| p.value().clone()) else {
+            return;
+        };
+        if peer.status() != RemotePeerStatus::Connected {
+            return;
+        }
+
+        let collected_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let parts = split_families(MetricFamily::gather(metrics), METRICS_REPORT_CHUNK);
+        let last = parts.len() - 1;
+        for (i, families) in parts.into_iter().enumerate() {
+            if let Err(e) = peer
+                .send_metrics_report(collected_ms, families, i == last)
+                .await
+            {
+                warn!("Failed to report metrics to leader '{}': {}", leader, e);
+                break;
+            }
+        }
+    }
+
+    /// The cluster's metrics, federated from the reports of every node and
+    /// `local` (see [`metrics`](super::metrics))
+    pub fn federated_metrics(&self, local: &Metrics) -> Vec<prometheus::proto::MetricFamily> {
+        let mut nodes = self.metrics_reports.current(self.config.metrics.interval);
+        nodes.retain(|(node_id, _, _)| *node_id != self.node_id);
+        nodes.push((
+            self.node_id.clone(),
+            Duration::ZERO,
+            MetricFamily::gather(local),
+        ));
+        federate(nodes)
+    }
+
     /// Start the cluster manager background tasks
     pub async fn start(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         info!(
@@ -216,14 +490,29 @@ impl ClusterManager {
         let local_node_id = self.node_id.clone();
         let local_subs = self.local_subscriptions.clone();
         let proxy_config = self.config.proxy_protocol.clone();
+        if proxy_config.enabled && proxy_config.trusted_networks.is_empty() {
+            warn!("Cluster PROXY protocol has no trusted_networks: any peer can claim any address");
+        }
+        let rate_limit_callback = self.rate_limit_callback.clone();
+        let takeover_callback = self.takeover_callback.clone();
+        let session_owners = self.session_owners.clone();
+        let metrics_reports = self.metrics_reports.clone();
+        let compression = self.config.compression.clone();
+        let compression_metrics = self.compression_metrics.clone();
 
         tokio::spawn(async move {
             Self::peer_listener_loop(
                 listener,
                 inbound_callback,
+                rate_limit_callback,
+                takeover_callback,
+                session_owners,
+                metrics_reports,
                 local_node_id,
                 local_subs,
                 proxy_config,
+                compression,
+                compression_metrics,
             )
             .await;
         });
@@ -234,10 +523,26 @@ impl ClusterManager {
         let config = self.config.clone();
         let inbound_callback = self.inbound_callback.clone();
         let local_node_id = self.node_id.clone();
+        // Only serving nodes seed observers with retained state
+        let retained_snapshot = self
+            .retained_snapshot
+            .clone()
+            .filter(|_| !self.is_observer());
+        let compression_metrics = self.compression_metrics.clone();
+        let session_owners = self.session_owners.clone();
 
         tokio::spawn(async move {
-            Self::gossip_watcher_loop(chitchat, peers, config, inbound_callback, local_node_id)
-                .await;
+            Self::gossip_watcher_loop(
+                chitchat,
+                peers,
+                config,
+                inbound_callback,
+                local_node_id,
+                retained_snapshot,
+                compression_metrics,
+                session_owners,
+            )
+            .await;
         });
 
         Ok(())
@@ -256,12 +561,19 @@ impl ClusterManager {
     }
 
     /// Listen for incoming peer connections
+    #[allow(clippy::too_many_arguments)]
     async fn peer_listener_loop(
         listener: TcpListener,
         inbound_callback: ClusterInboundCallback,
+        rate_limit_callback: Option<ClusterRateLimitCallback>,
+        takeover_callback: Option<ClusterTakeoverCallback>,
+        session_owners: Arc<SessionOwners>,
+        metrics_reports: Arc<MetricsReports>,
         local_node_id: String,
         local_subs: Arc<RwLock<HashSet<String>>>,
         proxy_config: ProxyProtocolConfig,
+        compression: LinkCompressionConfig,
+        compression_metrics: Option<CompressionMetrics>,
     ) {
         loop {
             match listener.accept().await {
@@ -269,13 +581,20 @@ impl ClusterManager {
                     debug!("Incoming cluster peer connection from {}", addr);
 
                     let callback = inbound_callback.clone();
+                    let rate_limit_callback = rate_limit_callback.clone();
+                    let takeover_callback = takeover_callback.clone();
+                    let session_owners = session_owners.clone();
+                    let metrics_reports = metrics_reports.clone();
                     let node_id = local_node_id.clone();
                     let subs = local_subs.clone();
                     let proxy_config = proxy_config.clone();
+                    let compression = compression.clone();
+                    let compression_metrics = compression_metrics.clone();
 
                     tokio::spawn(async move {
-                        // Handle PROXY protocol if enabled
-                        let effective_addr = if proxy_config.enabled {
+                        // Handle PROXY protocol if enabled; only trusted
+                        // proxies may claim another address
+                        let effective_addr = if expects_proxy_header(&proxy_config, addr) {
                             match parse_proxy_header(
                                 &mut stream,
                                 proxy_config.timeout,
@@ -299,11 +618,22 @@ impl ClusterManager {
                                 }
                             }
                         } else {
-                            addr
+                            PeerAddr::from(addr)
                         };
 
-                        if let Err(e) =
-                            Self::handle_incoming_peer(stream, callback, node_id, subs).await
+                        if let Err(e) = Self::handle_incoming_peer(
+                            stream,
+                            callback,
+                            rate_limit_callback,
+                            takeover_callback,
+                            session_owners,
+                            metrics_reports,
+                            node_id,
+                            subs,
+                            compression,
+                            compression_metrics,
+                        )
+                        .await
                         {
                             debug!(
                                 "Incoming peer connection error from {}: {}",
@@ -320,11 +650,18 @@ impl ClusterManager {
     }
 
     /// Handle an incoming peer connection
+    #[allow(clippy::too_many_arguments)]
     async fn handle_incoming_peer(
         stream: tokio::net::TcpStream,
         inbound_callback: ClusterInboundCallback,
+        rate_limit_callback: Option<ClusterRateLimitCallback>,
+        takeover_callback: Option<ClusterTakeoverCallback>,
+        session_owners: Arc<SessionOwners>,
+        metrics_reports: Arc<MetricsReports>,
         local_node_id: String,
         local_subs: Arc<RwLock<HashSet<String>>>,
+        compression: LinkCompressionConfig,
+        compression_metrics: Option<CompressionMetrics>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
@@ -378,6 +715,7 @@ impl ClusterManager {
 
         // Message loop
         let mut buf_offset = 0usize;
+        let mut decompressor: Option<LinkCompressor> = None;
 
         loop {
             let n = read_half.read(&mut read_buf[buf_offset..]).await?;
@@ -394,7 +732,14 @@ impl ClusterManager {
                     break;
                 }
 
-                if let Ok(msg) = ClusterMessage::decode(&read_buf[4..4 + len]) {
+                let msg = decode_frame(&read_buf[4..4 + len], decompressor.as_ref());
+                if let Err(ref e) = msg {
+                    debug!(
+                        "Cluster inbound: bad frame from peer {}: {}",
+                        peer_node_id, e
+                    );
+                }
+                if let Ok(msg) = msg {
                     match msg {
                         ClusterMessage::Publish {
                             topic,
@@ -402,6 +747,7 @@ impl ClusterManager {
                             qos,
                             retain,
                             origin_node,
+                            publisher,
                         } => {
                             debug!(
                                 "Cluster inbound: received publish '{}' from peer {} (origin={})",
@@ -423,7 +769,66 @@ impl ClusterManager {
                                 qos_level,
                                 retain,
                                 origin_node,
+                                publisher,
+                            );
+                        }
+                        ClusterMessage::RateLimitSync { buckets } => {
+                            debug!(
+                                "Cluster inbound: {} rate bucket(s) from peer {}",
+                                buckets.len(),
+                                peer_node_id
+                            );
+                            if let Some(ref callback) = rate_limit_callback {
+                                callback(buckets);
+                            }
+                        }
+                        ClusterMessage::SessionTakeover { client_id, epoch } => {
+                            if !session_owners.connected_to(&client_id, epoch, &peer_node_id) {
+                                debug!(
+                                    "Cluster inbound: ignoring stale takeover of {} by peer {}",
+                                    client_id, peer_node_id
+                                );
+                            } else {
+                                debug!(
+                                    "Cluster inbound: {} connected to peer {}",
+                                    client_id, peer_node_id
+                                );
+                                if let Some(ref callback) = takeover_callback {
+                                    callback(client_id);
+                                }
+                            }
+                        }
+                        ClusterMessage::MetricsReport {
+                            collected_ms,
+                            families,
+                            last,
+                        } => {
+                            debug!(
+                                "Cluster inbound: {} metric families from peer {}",
+                                families.len(),
+                                peer_node_id
                             );
+                            metrics_reports.receive(&peer_node_id, collected_ms, families, last);
+                        }
+                        ClusterMessage::CompressionOffer { codecs } => {
+                            let codec =
+                                negotiate(codecs.iter().map(String::as_str), &compression.codecs);
+                            debug!(
+                                "Cluster inbound: peer {} offers {:?}, accepting {:?}",
+                                peer_node_id, codecs, codec
+                            );
+                            decompressor = codec.map(|codec| {
+                                LinkCompressor::new(
+                                    codec,
+                                    &compression,
+                                    &format!("cluster:{}", peer_node_id),
+                                    compression_metrics.as_ref(),
+                                )
+                            });
+                            let accept = ClusterMessage::CompressionAccept {
+                                codec: codec.map(|c| c.as_str().to_string()),
+                            };
+                            write_half.write_all(&frame_message(&accept)?).await?;
                         }
                         ClusterMessage::Ping => {
                             let pong = ClusterMessage::Pong;
@@ -446,14 +851,19 @@ impl ClusterManager {
     }
 
     /// Watch gossip state for new peers and connect to them
+    #[allow(clippy::too_many_arguments)]
     async fn gossip_watcher_loop(
         chitchat: Arc<tokio::sync::Mutex<chitchat::Chitchat>>,
         peers: Arc<DashMap<String, Arc<ClusterPeer>>>,
         config: ClusterConfig,
         inbound_callback: ClusterInboundCallback,
         local_node_id: String,
+        retained_snapshot: Option<ClusterRetainedSnapshot>,
+        compression_metrics: Option<CompressionMetrics>,
+        session_owners: Arc<SessionOwners>,
     ) {
         let mut known_nodes: HashSet<String> = HashSet::new();
+        let id_node = crate::id::global().node_id();
 
         loop {
             tokio::time::sleep(config.gossip_interval).await;
@@ -477,14 +887,36 @@ impl ClusterManager {
                 if !known_nodes.contains(&node_id_str) {
                     known_nodes.insert(node_id_str.clone());
 
+                    // Both nodes would generate the same IDs
+                    let peer_id_node = node_state
+                        .get(KEY_ID_NODE)
+                        .and_then(|id| id.parse::<u16>().ok());
+                    if id_node.is_some() && peer_id_node == id_node {
+                        error!(
+                            "Cluster peer '{}' uses ID generator node ID {} like this node; \
+                             not peering with it (set a distinct id.node_id on each node)",
+                            node_id_str,
+                            id_node.unwrap_or_default()
+                        );
+                        continue;
+                    }
+
                     // Get peer address from gossip state - this should be the advertise address
                     let gossip_addr = node_state.chitchat_id().gossip_advertise_addr;
 
+                    let role = node_state
+                        .get(KEY_ROLE)
+                        .map(ClusterRole::from_gossip)
+                        .unwrap_or_default();
+
                     if let Some(peer_addr_str) = node_state.get(KEY_PEER_ADDR) {
                         if let Ok(peer_addr) = peer_addr_str.parse::<SocketAddr>() {
                             info!(
-                                "Discovered new cluster peer: {} at peer={} gossip={}",
-                                node_id_str, peer_addr, gossip_addr
+                                "Discovered new cluster peer: {} at peer={} gossip={} (role={})",
+                                node_id_str,
+                                peer_addr,
+                                gossip_addr,
+                                role.as_str()
                             );
 
                             // Create and spawn peer connection
@@ -492,8 +924,21 @@ impl ClusterManager {
                                 node_id_str.clone(),
                                 peer_addr,
                                 local_node_id.clone(),
+                            )
+                            .with_role(role)
+                            .with_compression(
+                                config.compression.clone(),
+                                compression_metrics.clone(),
                             );
                             let peer = peer.spawn(inbound_callback.clone());
+
+                            if role == ClusterRole::Observer {
+                                if let Some(ref snapshot) = retained_snapshot {
+                                    Self::spawn_retained_sync(&peer, snapshot.clone());
+                                }
+                            } else {
+                                Self::spawn_session_sync(&peer, session_owners.clone());
+                            }
                             peers.insert(node_id_str.clone(), peer);
                         }
                     }
@@ -535,8 +980,119 @@ impl ClusterManager {
             }
         }
     }
+
+    /// Send the retained store to an observer each time its connection comes up
+    ///
+    /// The task exits once the peer has been removed from the cluster.
+    fn spawn_retained_sync(peer: &Arc<ClusterPeer>, snapshot: ClusterRetainedSnapshot) {
+        let peer: Weak<ClusterPeer> = Arc::downgrade(peer);
+
+        tokio::spawn(async move {
+            let mut synced = false;
+            loop {
+                tokio::time::sleep(PEER_SYNC_POLL).await;
+                let Some(peer) = peer.upgrade() else {
+                    return;
+                };
+
+                let connected = peer.status() == RemotePeerStatus::Connected;
+                if !connected || synced {
+                    synced = connected;
+                    continue;
+                }
+
+                let messages = snapshot();
+                info!(
+                    "Cluster: syncing {} retained message(s) to observer '{}'",
+                    messages.len(),
+                    peer.node_id()
+                );
+                for (topic, payload, qos) in messages {
+                    if let Err(e) = peer.forward_publish(&topic, payload, qos, true).await {
+                        warn!(
+                            "Failed to sync retained message to observer '{}': {}",
+                            peer.node_id(),
+                            e
+                        );
+                        break;
+                    }
+                }
+                synced = true;
+            }
+        });
+    }
+
+    /// Announce the sessions owned here to a serving peer each time its
+    /// connection comes up, so connects it missed while unreachable still
+    /// take the sessions over
+    ///
+    /// The task exits once the peer has been removed from the cluster.
+    fn spawn_session_sync(peer: &Arc<ClusterPeer>, owners: Arc<SessionOwners>) {
+        let peer: Weak<ClusterPeer> = Arc::downgrade(peer);
+
+        tokio::spawn(async move {
+            let mut synced = false;
+            loop {
+                tokio::time::sleep(PEER_SYNC_POLL).await;
+                let Some(peer) = peer.upgrade() else {
+                    return;
+                };
+
+                let connected = peer.status() == RemotePeerStatus::Connected;
+                if !connected || synced {
+                    synced = connected;
+                    continue;
+                }
+
+                let sessions = owners.local();
+                debug!(
+                    "Cluster: announcing {} session(s) to peer '{}'",
+                    sessions.len(),
+                    peer.node_id()
+                );
+                for (client_id, epoch) in sessions {
+                    if let Err(e) = peer.send_session_takeover(client_id, epoch).await {
+                        warn!(
+                            "Failed to announce session to peer '{}': {}",
+                            peer.node_id(),
+                            e
+                        );
+                        break;
+                    }
+                }
+                synced = true;
+            }
+        });
+    }
 }
 
 // ClusterManager is Send + Sync because all its fields are thread-safe
 unsafe impl Send for ClusterManager {}
 unsafe impl Sync for ClusterManager {}
+
+/// Whether a peer connection from `addr` must start with a PROXY header
+fn expects_proxy_header(proxy_config: &ProxyProtocolConfig, addr: SocketAddr) -> bool {
+    proxy_config.enabled && proxy_config.is_trusted(addr.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expects_proxy_header() {
+        let mut config = ProxyProtocolConfig {
+            enabled: true,
+            trusted_networks: vec!["10.0.0.0/8".parse().unwrap()],
+            ..Default::default()
+        };
+        let proxy: SocketAddr = "10.1.2.3:40000".parse().unwrap();
+        let peer: SocketAddr = "192.0.2.7:40000".parse().unwrap();
+        assert!(expects_proxy_header(&config, proxy));
+        // An untrusted peer's connection is taken as is, header or not
+        assert!(!expects_proxy_header(&config, peer));
+
+        config.enabled = false;
+        assert!(!expects_proxy_header(&config, proxy));
+    }
+}
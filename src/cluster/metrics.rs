@@ -0,0 +1,509 @@
+//! Cluster Metrics Federation
+//!
+//! With `[cluster.metrics] enabled`, every node sends a snapshot of its
+//! metrics registry to the cluster leader (see [`ClusterManager::is_leader`])
+//! each `interval`. The leader serves them together on `/cluster/metrics` of
+//! its metrics server, so a single scrape target backs a single dashboard:
+//!
+//! - every series of every node under its usual name, with a `node` label
+//!   (`vibemq_connections_current{node="vibemq-1"}`), so per-node panels use
+//!   the same queries as against a single broker;
+//! - each `vibemq_*` family summed over the nodes as `vibemq_cluster_*`
+//!   (`vibemq_cluster_connections_current`; histograms bucket by bucket, so
+//!   `histogram_quantile` works on them), for cluster-level SLOs;
+//! - `vibemq_cluster_nodes_reporting` and
+//!   `vibemq_cluster_report_age_seconds{node}`, to tell a quiet node from a
+//!   missing one.
+//!
+//! Families already named `vibemq_cluster_*` are one node's view of the
+//! cluster and are not summed. Summaries are left out: their quantiles can't
+//! be summed. Reports older than three intervals are dropped, so a node that
+//! left disappears from the totals. Other nodes answer `/cluster/metrics`
+//! with 503 and the leader's node ID.
+//!
+//! [`ClusterManager::is_leader`]: super::ClusterManager::is_leader
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use bincode::{Decode, Encode};
+use parking_lot::Mutex;
+use prometheus::proto;
+
+use crate::metrics::Metrics;
+
+/// Label carrying the node ID of each federated series
+pub const NODE_LABEL: &str = "node";
+
+/// Name prefix of the families summed over nodes
+const CLUSTER_PREFIX: &str = "vibemq_cluster_";
+
+/// Reports older than this many intervals are dropped
+const MAX_REPORT_AGE: u32 = 3;
+
+/// Type of a federated metric family
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+/// One labelled series of a metric family
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct MetricSeries {
+    pub labels: Vec<(String, String)>,
+    /// Counter or gauge value, or histogram sample sum
+    pub value: f64,
+    /// Histogram sample count
+    pub count: u64,
+    /// Histogram buckets (upper bound, cumulative count)
+    pub buckets: Vec<(f64, u64)>,
+}
+
+/// A metric family of one node, as sent to the leader
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct MetricFamily {
+    pub name: String,
+    pub help: String,
+    pub kind: MetricKind,
+    pub series: Vec<MetricSeries>,
+}
+
+impl MetricFamily {
+    /// Snapshot of the families in `metrics`' registry
+    pub fn gather(metrics: &Metrics) -> Vec<Self> {
+        metrics
+            .registry
+            .gather()
+            .iter()
+            .filter_map(Self::from_proto)
+            .collect()
+    }
+
+    fn from_proto(family: &proto::MetricFamily) -> Option<Self> {
+        let kind = match family.get_field_type() {
+            proto::MetricType::COUNTER => MetricKind::Counter,
+            proto::MetricType::GAUGE => MetricKind::Gauge,
+            proto::MetricType::HISTOGRAM => MetricKind::Histogram,
+            _ => return None,
+        };
+        let series = family
+            .get_metric()
+            .iter()
+            .map(|metric| {
+                let labels = metric
+                    .get_label()
+                    .iter()
+                    .map(|l| (l.name().to_string(), l.value().to_string()))
+                    .collect();
+                match kind {
+                    MetricKind::Counter => {
+                        MetricSeries::value(labels, metric.get_counter().get_value())
+                    }
+                    MetricKind::Gauge => {
+                        MetricSeries::value(labels, metric.get_gauge().get_value())
+                    }
+                    MetricKind::Histogram => {
+                        let histogram = metric.get_histogram();
+                        MetricSeries {
+                            labels,
+                            value: histogram.get_sample_sum(),
+                            count: histogram.get_sample_count(),
+                            buckets: histogram
+                                .get_bucket()
+                                .iter()
+                                .map(|b| (b.upper_bound(), b.cumulative_count()))
+                                .collect(),
+                        }
+                    }
+                }
+            })
+            .collect();
+        Some(Self {
+            name: family.name().to_string(),
+            help: family.help().to_string(),
+            kind,
+            series,
+        })
+    }
+
+    /// The same family without its series
+    fn header(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            help: self.help.clone(),
+            kind: self.kind,
+            series: Vec::new(),
+        }
+    }
+
+    /// Encoded size of the family without its series, roughly
+    fn header_size(&self) -> usize {
+        self.name.len() + self.help.len() + 8
+    }
+}
+
+impl MetricSeries {
+    fn value(labels: Vec<(String, String)>, value: f64) -> Self {
+        Self {
+            labels,
+            value,
+            count: 0,
+            buckets: Vec::new(),
+        }
+    }
+
+    /// Encoded size, roughly
+    fn size(&self) -> usize {
+        let labels: usize = self.labels.iter().map(|(n, v)| n.len() + v.len() + 2).sum();
+        labels + self.buckets.len() * 16 + 24
+    }
+
+    /// Add `other` (the same series on another node) to this one; false if
+    /// their histogram buckets differ
+    fn add(&mut self, other: &MetricSeries) -> bool {
+        if self.buckets.len() != other.buckets.len()
+            || self
+                .buckets
+                .iter()
+                .zip(&other.buckets)
+                .any(|(a, b)| a.0 != b.0)
+        {
+            return false;
+        }
+        self.value += other.value;
+        self.count += other.count;
+        for (bucket, other) in self.buckets.iter_mut().zip(&other.buckets) {
+            bucket.1 += other.1;
+        }
+        true
+    }
+}
+
+/// Split families into parts of about `limit` encoded bytes each, splitting
+/// a family's series across parts if need be
+pub fn split_families(families: Vec<MetricFamily>, limit: usize) -> Vec<Vec<MetricFamily>> {
+    let mut parts = vec![Vec::new()];
+    let mut part_size = 0;
+    for family in families {
+        let header_size = family.header_size();
+        let mut current = family.header();
+        for series in family.series {
+            let mut size = series.size();
+            if current.series.is_empty() {
+                size += header_size;
+            }
+            if part_size + size > limit && part_size > 0 {
+                if !current.series.is_empty() {
+                    let header = current.header();
+                    parts.last_mut().unwrap().push(current);
+                    current = header;
+                    size += header_size;
+                }
+                parts.push(Vec::new());
+                part_size = 0;
+            }
+            part_size += size;
+            current.series.push(series);
+        }
+        if !current.series.is_empty() {
+            parts.last_mut().unwrap().push(current);
+        }
+    }
+    parts
+}
+
+/// Join the parts of a report split by [`split_families`]
+fn join_families(parts: Vec<MetricFamily>) -> Vec<MetricFamily> {
+    let mut families: Vec<MetricFamily> = Vec::new();
+    for part in parts {
+        match families.last_mut() {
+            Some(last) if last.name == part.name => last.series.extend(part.series),
+            _ => families.push(part),
+        }
+    }
+    families
+}
+
+/// Latest report of a node
+#[derive(Default)]
+struct NodeReport {
+    /// Parts of the report being received and when it was collected
+    partial: Vec<MetricFamily>,
+    partial_ms: u64,
+    /// Last complete report and when it arrived
+    families: Vec<MetricFamily>,
+    received: Option<Instant>,
+}
+
+/// Metrics reports received from peers, by node ID
+#[derive(Default)]
+pub struct MetricsReports {
+    nodes: Mutex<HashMap<String, NodeReport>>,
+}
+
+impl MetricsReports {
+    /// Add a part of a node's report; `last` completes it
+    pub fn receive(
+        &self,
+        node_id: &str,
+        collected_ms: u64,
+        families: Vec<MetricFamily>,
+        last: bool,
+    ) {
+        let mut nodes = self.nodes.lock();
+        let report = nodes.entry(node_id.to_string()).or_default();
+        if report.partial_ms != collected_ms {
+            report.partial.clear();
+            report.partial_ms = collected_ms;
+        }
+        report.partial.extend(families);
+        if last {
+            report.families = join_families(std::mem::take(&mut report.partial));
+            report.received = Some(Instant::now());
+        }
+    }
+
+    /// Complete reports received within `MAX_REPORT_AGE` report intervals,
+    /// with their age; older ones are dropped
+    pub fn current(&self, interval: Duration) -> Vec<(String, Duration, Vec<MetricFamily>)> {
+        let max_age = interval * MAX_REPORT_AGE;
+        let mut nodes = self.nodes.lock();
+        nodes.retain(|_, report| {
+            report
+                .received
+                .is_some_and(|received| received.elapsed() <= max_age)
+                || !report.partial.is_empty()
+        });
+        nodes
+            .iter()
+            .filter_map(|(node_id, report)| {
+                let age = report.received?.elapsed();
+                Some((node_id.clone(), age, report.families.clone()))
+            })
+            .collect()
+    }
+}
+
+/// A family (as first seen) and its series on each node
+type NodeSeries<'a> = (&'a MetricFamily, Vec<(&'a str, &'a MetricSeries)>);
+
+/// The cluster's metrics: those of every node with a `node` label, the
+/// `vibemq_*` families summed as `vibemq_cluster_*`, and the age of each
+/// node's report (the local node's is 0)
+pub fn federate(mut nodes: Vec<(String, Duration, Vec<MetricFamily>)>) -> Vec<proto::MetricFamily> {
+    nodes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // Series of each family by node; a family whose type differs between
+    // nodes (different versions) keeps the first type seen
+    let mut by_name: BTreeMap<&str, NodeSeries> = BTreeMap::new();
+    for (node_id, _, families) in &nodes {
+        for family in families {
+            let (first, series) = by_name
+                .entry(family.name.as_str())
+                .or_insert_with(|| (family, Vec::new()));
+            if first.kind == family.kind {
+                series.extend(family.series.iter().map(|s| (node_id.as_str(), s)));
+            }
+        }
+    }
+
+    let mut output = Vec::new();
+    let mut totals = Vec::new();
+    for (name, (family, series)) in &by_name {
+        output.push(encode_family(
+            name,
+            &family.help,
+            family.kind,
+            series.iter().map(|(node_id, s)| (Some(*node_id), *s)),
+        ));
+
+        let Some(rest) = name.strip_prefix("vibemq_") else {
+            continue;
+        };
+        if name.starts_with(CLUSTER_PREFIX) {
+            continue;
+        }
+        let mut summed: BTreeMap<&[(String, String)], MetricSeries> = BTreeMap::new();
+        for (_, s) in series {
+            match summed.get_mut(s.labels.as_slice()) {
+                Some(total) => {
+                    total.add(s);
+                }
+                None => {
+                    summed.insert(s.labels.as_slice(), (*s).clone());
+                }
+            }
+        }
+        totals.push(encode_family(
+            &format!("{}{}", CLUSTER_PREFIX, rest),
+            &format!("{} (sum over cluster nodes)", family.help),
+            family.kind,
+            summed.values().map(|s| (None, s)),
+        ));
+    }
+    output.extend(totals);
+
+    let reporting = MetricSeries::value(Vec::new(), nodes.len() as f64);
+    output.push(encode_family(
+        "vibemq_cluster_nodes_reporting",
+        "Nodes whose metrics are in this federated view",
+        MetricKind::Gauge,
+        [(None, &reporting)],
+    ));
+    let ages: Vec<(&str, MetricSeries)> = nodes
+        .iter()
+        .map(|(node_id, age, _)| {
+            (
+                node_id.as_str(),
+                MetricSeries::value(Vec::new(), age.as_secs_f64()),
+            )
+        })
+        .collect();
+    output.push(encode_family(
+        "vibemq_cluster_report_age_seconds",
+        "Time since the leader received each node's metrics",
+        MetricKind::Gauge,
+        ages.iter().map(|(node_id, s)| (Some(*node_id), s)),
+    ));
+    output.retain(|family| !family.get_metric().is_empty());
+    output
+}
+
+/// A family in the Prometheus model, with a `node` label on series that
+/// have one
+fn encode_family<'a>(
+    name: &str,
+    help: &str,
+    kind: MetricKind,
+    series: impl IntoIterator<Item = (Option<&'a str>, &'a MetricSeries)>,
+) -> proto::MetricFamily {
+    let mut family = proto::MetricFamily::default();
+    family.set_name(name.to_string());
+    family.set_help(help.to_string());
+    family.set_field_type(match kind {
+        MetricKind::Counter => proto::MetricType::COUNTER,
+        MetricKind::Gauge => proto::MetricType::GAUGE,
+        MetricKind::Histogram => proto::MetricType::HISTOGRAM,
+    });
+    for (node_id, s) in series {
+        let labels = node_id
+            .map(|node_id| (NODE_LABEL, node_id))
+            .into_iter()
+            .chain(
+                s.labels
+                    .iter()
+                    .filter(|(name, _)| name != NODE_LABEL)
+                    .map(|(name, value)| (name.as_str(), value.as_str())),
+            )
+            .map(|(name, value)| {
+                let mut label = proto::LabelPair::default();
+                label.set_name(name.to_string());
+                label.set_value(value.to_string());
+                label
+            })
+            .collect();
+        let mut metric = proto::Metric::from_label(labels);
+        match kind {
+            MetricKind::Counter => {
+                let mut counter = proto::Counter::default();
+                counter.set_value(s.value);
+                metric.set_counter(counter);
+            }
+            MetricKind::Gauge => {
+                let mut gauge = proto::Gauge::default();
+                gauge.set_value(s.value);
+                metric.set_gauge(gauge);
+            }
+            MetricKind::Histogram => {
+                let mut histogram = proto::Histogram::default();
+                histogram.set_sample_sum(s.value);
+                histogram.set_sample_count(s.count);
+                histogram.set_bucket(
+                    s.buckets
+                        .iter()
+                        .map(|&(upper_bound, count)| {
+                            let mut bucket = proto::Bucket::default();
+                            bucket.set_upper_bound(upper_bound);
+                            bucket.set_cumulative_count(count);
+                            bucket
+                        })
+                        .collect(),
+                );
+                metric.set_histogram(histogram);
+            }
+        }
+        family.mut_metric().push(metric);
+    }
+    family
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{Encoder, TextEncoder};
+
+    fn node_metrics(connections: i64, latency: f64) -> Vec<MetricFamily> {
+        let metrics = Metrics::new();
+        metrics.connections_current.set(connections);
+        metrics.publish_latency.observe(latency);
+        metrics.proxy_protocol_error("tcp");
+        MetricFamily::gather(&metrics)
+    }
+
+    fn text(families: &[proto::MetricFamily]) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(families, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_federate() {
+        let federated = federate(vec![
+            (
+                "node-b".into(),
+                Duration::from_secs(4),
+                node_metrics(3, 0.5),
+            ),
+            ("node-a".into(), Duration::ZERO, node_metrics(2, 0.001)),
+        ]);
+        let text = text(&federated);
+
+        assert!(text.contains("vibemq_connections_current{node=\"node-a\"} 2"));
+        assert!(text.contains("vibemq_connections_current{node=\"node-b\"} 3"));
+        assert!(text.contains("vibemq_cluster_connections_current 5"));
+        assert!(text.contains("vibemq_cluster_proxy_protocol_errors_total{listener=\"tcp\"} 2"));
+        assert!(text.contains("vibemq_cluster_publish_latency_seconds_count 2"));
+        assert!(text.contains("vibemq_cluster_nodes_reporting 2"));
+        assert!(text.contains("vibemq_cluster_report_age_seconds{node=\"node-b\"} 4"));
+        // A node's view of the cluster isn't summed
+        assert!(text.contains("vibemq_cluster_peers_current{node=\"node-a\"} 0"));
+        assert!(!text.contains("vibemq_cluster_cluster_"));
+    }
+
+    #[test]
+    fn test_split_and_receive_report() {
+        let families = node_metrics(7, 0.2);
+        let parts = split_families(families.clone(), 512);
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|part| !part.is_empty()));
+
+        let reports = MetricsReports::default();
+        let last = parts.len() - 1;
+        for (i, part) in parts.into_iter().enumerate() {
+            reports.receive("node-b", 1000, part, i == last);
+        }
+        let current = reports.current(Duration::from_secs(15));
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].2, families);
+
+        // The parts of a newer report replace those of an unfinished one
+        reports.receive("node-b", 2000, families[..1].to_vec(), false);
+        reports.receive("node-b", 3000, families[1..2].to_vec(), true);
+        assert_eq!(
+            reports.current(Duration::from_secs(15))[0].2,
+            families[1..2].to_vec()
+        );
+    }
+}
@@ -8,6 +8,22 @@
 //! - **Gossip (UDP via chitchat)**: Node discovery, membership, subscription state
 //! - **Peer TCP**: Direct message forwarding between nodes
 //!
+//! Publishes reach subscribers on any node: each node gossips the filters
+//! it has subscribers for and peers forward matching publishes. A client
+//! that connects to one node takes its session over from the others: they
+//! disconnect it (`Session taken over`) and drop the session, which isn't
+//! replicated, so the new node starts the client from its own state.
+//! Announcements carry a connect epoch so late ones are ignored, and are
+//! repeated to peers whose link comes up later (see `cluster::takeover`).
+//!
+//! A node with `role = "observer"` joins as a read-only replica: it receives
+//! every publish and the retained store from serving nodes, accepts no
+//! client connections, and serves queries via [`ObserverApi`].
+//!
+//! With `[cluster.metrics]` enabled, nodes report their metrics to the
+//! leader, which serves the cluster's on `/cluster/metrics` (see
+//! [`metrics`]).
+//!
 //! # Usage
 //!
 //! ```toml
@@ -20,10 +36,17 @@
 //! ```
 
 mod manager;
+pub mod metrics;
+mod observer;
 mod peer;
 mod protocol;
+mod takeover;
 
-pub use manager::ClusterManager;
+pub use manager::{
+    ClusterManager, ClusterRateLimitCallback, ClusterRetainedSnapshot, ClusterTakeoverCallback,
+};
+pub use observer::ObserverApi;
+pub(crate) use observer::{percent_decode, query_param};
 pub use peer::{ClusterInboundCallback, ClusterPeer};
 pub use protocol::{ClusterMessage, CLUSTER_PROTOCOL_VERSION};
 
@@ -0,0 +1,367 @@
+//! Observer Query API
+//!
+//! Read-only HTTP API served by observer nodes. Observers receive every
+//! publish and the retained store from serving nodes, so reporting queries
+//! can run here instead of on nodes handling client traffic.
+//!
+//! Endpoints (GET only):
+//! - `/api/v1/cluster` - node ID, role, and peers with their status
+//! - `/api/v1/subscriptions` - topic filters advertised by each serving node
+//! - `/api/v1/retained?filter=<filter>` - retained messages (optionally
+//!   matching a URL-encoded topic filter)
+//! - `/health` - liveness
+//!
+//! Every endpoint except `/health` requires the admin API token as
+//! `Authorization: Bearer <token>`.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{header, HeaderMap, Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+use crate::auth::constant_time_eq;
+use crate::broker::RetainedMessage;
+use crate::config::ClusterRole;
+use crate::remote::RemotePeer;
+use crate::topic::{topic_matches_filter, validate_topic_filter};
+
+use super::ClusterManager;
+
+/// Retained message summary returned by the query API
+#[derive(Debug, Serialize)]
+struct RetainedEntry<'a> {
+    topic: &'a str,
+    qos: u8,
+    payload_size: usize,
+    /// Payload as text (None if it isn't valid UTF-8)
+    payload: Option<&'a str>,
+}
+
+/// Peer summary returned by the query API
+#[derive(Debug, Serialize)]
+struct PeerEntry {
+    node_id: String,
+    role: &'static str,
+    status: String,
+    subscriptions: usize,
+}
+
+/// Cluster summary returned by the query API
+#[derive(Debug, Serialize)]
+struct ClusterEntry<'a> {
+    node_id: &'a str,
+    role: &'static str,
+    retained_messages: usize,
+    peers: Vec<PeerEntry>,
+}
+
+/// Read-only HTTP API for observer nodes
+pub struct ObserverApi {
+    addr: SocketAddr,
+    token: String,
+    cluster: Arc<ClusterManager>,
+    retained: Arc<DashMap<String, RetainedMessage>>,
+}
+
+impl ObserverApi {
+    pub fn new(
+        addr: SocketAddr,
+        token: String,
+        cluster: Arc<ClusterManager>,
+        retained: Arc<DashMap<String, RetainedMessage>>,
+    ) -> Self {
+        Self {
+            addr,
+            token,
+            cluster,
+            retained,
+        }
+    }
+
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let listener = TcpListener::bind(self.addr).await?;
+        info!("Observer API listening on http://{}/api/v1", self.addr);
+
+        let api = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let io = TokioIo::new(stream);
+            let api = api.clone();
+
+            tokio::spawn(async move {
+                let service = service_fn(move |req| {
+                    let api = api.clone();
+                    async move { Ok::<_, Infallible>(api.handle_request(req)) }
+                });
+
+                if let Err(err) = http1::Builder::new().serve_connection(io, service).await {
+                    error!("Error serving observer API connection: {:?}", err);
+                }
+            });
+        }
+    }
+
+    fn handle_request(&self, req: Request<hyper::body::Incoming>) -> Response<Full<Bytes>> {
+        if req.method() != Method::GET {
+            return text_response(StatusCode::METHOD_NOT_ALLOWED, "Read-only API");
+        }
+
+        // Retained payloads and the cluster layout are not for anonymous
+        // callers; only the liveness probe stays open
+        let path = req.uri().path();
+        if !matches!(path, "/health" | "/healthz") && !authorized(req.headers(), &self.token) {
+            return Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(header::WWW_AUTHENTICATE, "Bearer")
+                .body(Full::new(Bytes::from("Unauthorized")))
+                .unwrap();
+        }
+
+        match path {
+            "/api/v1/cluster" => json_response(&self.cluster_summary()),
+            "/api/v1/subscriptions" => json_response(&self.subscriptions()),
+            "/api/v1/retained" => {
+                let filter = match query_param(req.uri().query(), "filter") {
+                    Ok(filter) => filter,
+                    Err(e) => return text_response(StatusCode::BAD_REQUEST, e),
+                };
+                match list_retained(&self.retained, filter.as_deref()) {
+                    Ok(body) => json_body(body),
+                    Err(e) => text_response(StatusCode::BAD_REQUEST, e),
+                }
+            }
+            "/health" | "/healthz" => text_response(StatusCode::OK, "OK"),
+            _ => text_response(StatusCode::NOT_FOUND, "Not Found"),
+        }
+    }
+
+    fn cluster_summary(&self) -> ClusterEntry<'_> {
+        let peers = self
+            .cluster
+            .peers()
+            .iter()
+            .map(|peer| PeerEntry {
+                node_id: peer.node_id().to_string(),
+                role: peer.role().as_str(),
+                status: format!("{:?}", peer.status()).to_lowercase(),
+                subscriptions: peer.remote_subscriptions().len(),
+            })
+            .collect();
+
+        ClusterEntry {
+            node_id: self.cluster.node_id(),
+            role: self.cluster.role().as_str(),
+            retained_messages: self.retained.len(),
+            peers,
+        }
+    }
+
+    /// Topic filters per serving node (observers' catch-all filter is omitted)
+    fn subscriptions(&self) -> BTreeMap<String, Vec<String>> {
+        self.cluster
+            .peers()
+            .iter()
+            .filter(|peer| peer.role() == ClusterRole::Member)
+            .map(|peer| (peer.node_id().to_string(), peer.remote_subscriptions()))
+            .collect()
+    }
+}
+
+/// Serialize retained messages (sorted by topic) matching an optional filter
+fn list_retained(
+    retained: &DashMap<String, RetainedMessage>,
+    filter: Option<&str>,
+) -> Result<String, &'static str> {
+    if let Some(filter) = filter {
+        validate_topic_filter(filter).map_err(|_| "Invalid topic filter")?;
+    }
+
+    let messages: Vec<_> = retained
+        .iter()
+        .filter(|m| filter.is_none_or(|f| topic_matches_filter(m.key(), f)))
+        .map(|m| m.value().clone())
+        .collect();
+
+    let mut entries: Vec<RetainedEntry> = messages
+        .iter()
+        .map(|m| RetainedEntry {
+            topic: &m.topic,
+            qos: m.qos as u8,
+            payload_size: m.payload.len(),
+            payload: std::str::from_utf8(&m.payload).ok(),
+        })
+        .collect();
+    entries.sort_by(|a, b| a.topic.cmp(b.topic));
+
+    serde_json::to_string(&entries).map_err(|_| "Failed to encode response")
+}
+
+/// Get a URL-decoded query parameter
+pub(crate) fn query_param(query: Option<&str>, name: &str) -> Result<Option<String>, &'static str> {
+    let Some(query) = query else {
+        return Ok(None);
+    };
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        if key == name {
+            return percent_decode(value)
+                .map(Some)
+                .ok_or("Invalid query parameter encoding");
+        }
+    }
+    Ok(None)
+}
+
+/// Decode a percent-encoded query value ('+' is a literal plus, as MQTT
+/// filters use it as a wildcard)
+pub(crate) fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn json_response<T: Serialize>(value: &T) -> Response<Full<Bytes>> {
+    match serde_json::to_string(value) {
+        Ok(body) => json_body(body),
+        Err(e) => {
+            error!("Failed to encode observer API response: {}", e);
+            text_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to encode response",
+            )
+        }
+    }
+}
+
+fn json_body(body: String) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+}
+
+fn text_response(status: StatusCode, body: &'static str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    use crate::protocol::{Properties, QoS};
+
+    fn retained(topics: &[(&str, &[u8])]) -> DashMap<String, RetainedMessage> {
+        let map = DashMap::new();
+        for (topic, payload) in topics {
+            map.insert(
+                topic.to_string(),
+                RetainedMessage {
+                    topic: topic.to_string(),
+                    payload: Bytes::copy_from_slice(payload),
+                    qos: QoS::AtLeastOnce,
+                    properties: Properties::default(),
+                    timestamp: Instant::now(),
+                    proxy_identity: None,
+                },
+            );
+        }
+        map
+    }
+
+    #[test]
+    fn test_authorized() {
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&headers, "secret"));
+
+        headers.insert(header::AUTHORIZATION, "Bearer wrong".parse().unwrap());
+        assert!(!authorized(&headers, "secret"));
+
+        headers.insert(header::AUTHORIZATION, "secret".parse().unwrap());
+        assert!(!authorized(&headers, "secret"));
+
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert!(authorized(&headers, "secret"));
+    }
+
+    #[test]
+    fn test_query_param() {
+        let query = Some("limit=5&filter=site%2F%2B%2Ftemp");
+        assert_eq!(
+            query_param(query, "filter").unwrap().as_deref(),
+            Some("site/+/temp")
+        );
+        assert_eq!(query_param(query, "missing").unwrap(), None);
+        assert_eq!(
+            query_param(Some("filter=a+b"), "filter")
+                .unwrap()
+                .as_deref(),
+            Some("a+b")
+        );
+        assert!(query_param(Some("filter=%2"), "filter").is_err());
+        assert_eq!(query_param(None, "filter").unwrap(), None);
+    }
+
+    #[test]
+    fn test_list_retained() {
+        let map = retained(&[
+            ("site/b/temp", b"21.5"),
+            ("site/a/temp", b"19.0"),
+            ("site/a/raw", &[0xff, 0xfe]),
+        ]);
+
+        let all: serde_json::Value =
+            serde_json::from_str(&list_retained(&map, None).unwrap()).unwrap();
+        let topics: Vec<_> = all
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["topic"].as_str().unwrap())
+            .collect();
+        assert_eq!(topics, vec!["site/a/raw", "site/a/temp", "site/b/temp"]);
+        assert!(all[0]["payload"].is_null());
+        assert_eq!(all[0]["payload_size"], 2);
+        assert_eq!(all[1]["payload"], "19.0");
+        assert_eq!(all[1]["qos"], 1);
+
+        let filtered: serde_json::Value =
+            serde_json::from_str(&list_retained(&map, Some("site/+/temp")).unwrap()).unwrap();
+        assert_eq!(filtered.as_array().unwrap().len(), 2);
+
+        assert!(list_retained(&map, Some("site/#/temp")).is_err());
+    }
+}
@@ -16,11 +16,19 @@ use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use tracing::{debug, error, info};
 
+use crate::config::{ClusterRole, CompressionCodec, LinkCompressionConfig};
 use crate::protocol::QoS;
-use crate::remote::{RemoteError, RemotePeer, RemotePeerStatus};
-use crate::topic::topic_matches_filter;
-
-use super::protocol::{frame_message, read_frame_length, ClusterMessage, CLUSTER_PROTOCOL_VERSION};
+use crate::remote::compression::codec_names;
+use crate::remote::{
+    CompressionMetrics, LinkCompressor, RemoteError, RemotePeer, RemotePeerStatus,
+};
+use crate::session::RateBucket;
+use crate::topic::{parse_shared_subscription, topic_matches_filter};
+
+use super::metrics::MetricFamily;
+use super::protocol::{
+    frame_compressed, frame_message, read_frame_length, ClusterMessage, CLUSTER_PROTOCOL_VERSION,
+};
 
 /// Commands sent to the peer connection task
 #[derive(Debug)]
@@ -32,6 +40,7 @@ pub enum ClusterCommand {
         qos: QoS,
         retain: bool,
         origin_node: String,
+        publisher: Option<String>,
     },
     /// Send subscription sync
     SyncSubscriptions { filters: Vec<String> },
@@ -40,12 +49,24 @@ pub enum ClusterCommand {
         added: Vec<String>,
         removed: Vec<String>,
     },
+    /// Send changed publish rate buckets
+    SyncRateLimits { buckets: Vec<RateBucket> },
+    /// Announce a client that connected locally
+    TakeOverSession { client_id: String, epoch: u64 },
+    /// Send part of a metrics report
+    ReportMetrics {
+        collected_ms: u64,
+        families: Vec<MetricFamily>,
+        last: bool,
+    },
     /// Shutdown the connection
     Shutdown,
 }
 
-/// Callback for messages received from a cluster peer
-pub type ClusterInboundCallback = Arc<dyn Fn(String, Bytes, QoS, bool, String) + Send + Sync>;
+/// Callback for messages received from a cluster peer: topic, payload, QoS,
+/// retain, origin node and publishing client (if known)
+pub type ClusterInboundCallback =
+    Arc<dyn Fn(String, Bytes, QoS, bool, String, Option<String>) + Send + Sync>;
 
 /// A connection to another cluster node
 pub struct ClusterPeer {
@@ -61,6 +82,12 @@ pub struct ClusterPeer {
     remote_subscriptions: Arc<RwLock<HashSet<String>>>,
     /// Our local node ID (for origin tracking)
     local_node_id: String,
+    /// Remote node's role (from gossip state)
+    role: ClusterRole,
+    /// Compression offered to the remote node
+    compression: LinkCompressionConfig,
+    /// Collectors compressed traffic is reported to
+    compression_metrics: Option<CompressionMetrics>,
 }
 
 impl ClusterPeer {
@@ -73,9 +100,29 @@ impl ClusterPeer {
             command_tx: None,
             remote_subscriptions: Arc::new(RwLock::new(HashSet::new())),
             local_node_id,
+            role: ClusterRole::Member,
+            compression: LinkCompressionConfig::default(),
+            compression_metrics: None,
         }
     }
 
+    /// Set the remote node's role
+    pub fn with_role(mut self, role: ClusterRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    /// Offer compression to the remote node
+    pub fn with_compression(
+        mut self,
+        compression: LinkCompressionConfig,
+        metrics: Option<CompressionMetrics>,
+    ) -> Self {
+        self.compression = compression;
+        self.compression_metrics = metrics;
+        self
+    }
+
     /// Get the remote node ID
     pub fn node_id(&self) -> &str {
         &self.node_id
@@ -86,6 +133,18 @@ impl ClusterPeer {
         self.peer_addr
     }
 
+    /// Get the remote node's role
+    pub fn role(&self) -> ClusterRole {
+        self.role
+    }
+
+    /// Get the remote node's subscriptions
+    pub fn remote_subscriptions(&self) -> Vec<String> {
+        let mut filters: Vec<String> = self.remote_subscriptions.read().iter().cloned().collect();
+        filters.sort();
+        filters
+    }
+
     /// Update remote subscriptions (called when gossip state changes)
     pub fn update_remote_subscriptions(&self, filters: Vec<String>) {
         let mut subs = self.remote_subscriptions.write();
@@ -117,6 +176,74 @@ impl ClusterPeer {
         Ok(())
     }
 
+    /// Send changed publish rate buckets to this peer
+    pub async fn send_rate_limits(&self, buckets: Vec<RateBucket>) -> Result<(), RemoteError> {
+        if let Some(ref tx) = self.command_tx {
+            tx.send(ClusterCommand::SyncRateLimits { buckets })
+                .await
+                .map_err(|_| RemoteError::ConnectionLost("Command channel closed".to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Forward a message published by `publisher` (sticky shared
+    /// subscriptions on the peer pick their member by it)
+    pub async fn forward_publish_from(
+        &self,
+        topic: &str,
+        payload: Bytes,
+        qos: QoS,
+        retain: bool,
+        publisher: Option<String>,
+    ) -> Result<(), RemoteError> {
+        if let Some(ref tx) = self.command_tx {
+            tx.send(ClusterCommand::Publish {
+                topic: topic.to_string(),
+                payload,
+                qos,
+                retain,
+                origin_node: self.local_node_id.clone(),
+                publisher,
+            })
+            .await
+            .map_err(|_| RemoteError::ConnectionLost("Command channel closed".to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Tell this peer a client connected here, taking over its session
+    pub async fn send_session_takeover(
+        &self,
+        client_id: String,
+        epoch: u64,
+    ) -> Result<(), RemoteError> {
+        if let Some(ref tx) = self.command_tx {
+            tx.send(ClusterCommand::TakeOverSession { client_id, epoch })
+                .await
+                .map_err(|_| RemoteError::ConnectionLost("Command channel closed".to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Send part of this node's metrics report to this peer (the leader)
+    pub async fn send_metrics_report(
+        &self,
+        collected_ms: u64,
+        families: Vec<MetricFamily>,
+        last: bool,
+    ) -> Result<(), RemoteError> {
+        if let Some(ref tx) = self.command_tx {
+            tx.send(ClusterCommand::ReportMetrics {
+                collected_ms,
+                families,
+                last,
+            })
+            .await
+            .map_err(|_| RemoteError::ConnectionLost("Command channel closed".to_string()))?;
+        }
+        Ok(())
+    }
+
     /// Spawn the connection task and return the peer ready to use
     pub fn spawn(mut self, inbound_callback: ClusterInboundCallback) -> Arc<Self> {
         let (tx, rx) = mpsc::channel(1000);
@@ -127,6 +254,8 @@ impl ClusterPeer {
         let peer_addr = self.peer_addr;
         let status = self.status.clone();
         let remote_subs = self.remote_subscriptions.clone();
+        let compression = self.compression.clone();
+        let compression_metrics = self.compression_metrics.clone();
 
         tokio::spawn(async move {
             Self::connection_loop(
@@ -137,6 +266,8 @@ impl ClusterPeer {
                 rx,
                 inbound_callback,
                 remote_subs,
+                compression,
+                compression_metrics,
             )
             .await;
         });
@@ -145,6 +276,7 @@ impl ClusterPeer {
     }
 
     /// Run the connection loop with reconnection
+    #[allow(clippy::too_many_arguments)]
     async fn connection_loop(
         node_id: String,
         local_node_id: String,
@@ -153,6 +285,8 @@ impl ClusterPeer {
         mut command_rx: mpsc::Receiver<ClusterCommand>,
         inbound_callback: ClusterInboundCallback,
         remote_subs: Arc<RwLock<HashSet<String>>>,
+        compression: LinkCompressionConfig,
+        compression_metrics: Option<CompressionMetrics>,
     ) {
         let mut retry_interval = Duration::from_secs(1);
         let max_retry = Duration::from_secs(30);
@@ -169,6 +303,8 @@ impl ClusterPeer {
                 &mut command_rx,
                 &inbound_callback,
                 &remote_subs,
+                &compression,
+                compression_metrics.as_ref(),
             )
             .await
             {
@@ -204,6 +340,7 @@ impl ClusterPeer {
     }
 
     /// Connect to the peer and run the message loop
+    #[allow(clippy::too_many_arguments)]
     async fn connect_and_run(
         node_id: &str,
         local_node_id: &str,
@@ -212,6 +349,8 @@ impl ClusterPeer {
         command_rx: &mut mpsc::Receiver<ClusterCommand>,
         inbound_callback: &ClusterInboundCallback,
         remote_subs: &Arc<RwLock<HashSet<String>>>,
+        compression: &LinkCompressionConfig,
+        compression_metrics: Option<&CompressionMetrics>,
     ) -> Result<(), RemoteError> {
         // Connect with timeout
         let stream = tokio::time::timeout(Duration::from_secs(10), TcpStream::connect(peer_addr))
@@ -278,6 +417,21 @@ impl ClusterPeer {
 
         *status.write() = RemotePeerStatus::Connected;
 
+        // Offer compression; until the peer accepts (nodes without it
+        // never do), messages go uncompressed
+        let mut compressor: Option<LinkCompressor> = None;
+        if compression.enabled() {
+            let offer = ClusterMessage::CompressionOffer {
+                codecs: codec_names(&compression.codecs),
+            };
+            let frame = frame_message(&offer)
+                .map_err(|e| RemoteError::Other(format!("Encode error: {}", e)))?;
+            write_half
+                .write_all(&frame)
+                .await
+                .map_err(|e| RemoteError::ConnectionLost(e.to_string()))?;
+        }
+
         // Message loop
         let ping_interval = Duration::from_secs(15);
         let mut ping_timer = tokio::time::interval(ping_interval);
@@ -290,7 +444,7 @@ impl ClusterPeer {
                 // Handle commands from the cluster manager
                 Some(cmd) = command_rx.recv() => {
                     match cmd {
-                        ClusterCommand::Publish { topic, payload, qos, retain, origin_node } => {
+                        ClusterCommand::Publish { topic, payload, qos, retain, origin_node, publisher } => {
                             debug!("ClusterPeer '{}': sending publish '{}' over TCP", node_id, topic);
                             let msg = ClusterMessage::Publish {
                                 topic: topic.clone(),
@@ -298,8 +452,9 @@ impl ClusterPeer {
                                 qos: qos as u8,
                                 retain,
                                 origin_node,
+                                publisher,
                             };
-                            if let Ok(frame) = frame_message(&msg) {
+                            if let Ok(frame) = frame_compressed(&msg, compressor.as_ref()) {
                                 if let Err(e) = write_half.write_all(&frame).await {
                                     error!("ClusterPeer '{}': TCP write error: {}", node_id, e);
                                     return Err(RemoteError::ConnectionLost(e.to_string()));
@@ -309,20 +464,38 @@ impl ClusterPeer {
                         }
                         ClusterCommand::SyncSubscriptions { filters } => {
                             let msg = ClusterMessage::SubscriptionSync { filters };
-                            if let Ok(frame) = frame_message(&msg) {
+                            if let Ok(frame) = frame_compressed(&msg, compressor.as_ref()) {
                                 let _ = write_half.write_all(&frame).await;
                             }
                         }
                         ClusterCommand::UpdateSubscriptions { added, removed } => {
                             let msg = ClusterMessage::SubscriptionUpdate { added, removed };
-                            if let Ok(frame) = frame_message(&msg) {
+                            if let Ok(frame) = frame_compressed(&msg, compressor.as_ref()) {
+                                let _ = write_half.write_all(&frame).await;
+                            }
+                        }
+                        ClusterCommand::SyncRateLimits { buckets } => {
+                            let msg = ClusterMessage::RateLimitSync { buckets };
+                            if let Ok(frame) = frame_compressed(&msg, compressor.as_ref()) {
+                                let _ = write_half.write_all(&frame).await;
+                            }
+                        }
+                        ClusterCommand::TakeOverSession { client_id, epoch } => {
+                            let msg = ClusterMessage::SessionTakeover { client_id, epoch };
+                            if let Ok(frame) = frame_compressed(&msg, compressor.as_ref()) {
+                                let _ = write_half.write_all(&frame).await;
+                            }
+                        }
+                        ClusterCommand::ReportMetrics { collected_ms, families, last } => {
+                            let msg = ClusterMessage::MetricsReport { collected_ms, families, last };
+                            if let Ok(frame) = frame_compressed(&msg, compressor.as_ref()) {
                                 let _ = write_half.write_all(&frame).await;
                             }
                         }
                         ClusterCommand::Shutdown => {
                             // Send Goodbye
                             let msg = ClusterMessage::Goodbye;
-                            if let Ok(frame) = frame_message(&msg) {
+                            if let Ok(frame) = frame_compressed(&msg, compressor.as_ref()) {
                                 let _ = write_half.write_all(&frame).await;
                             }
                             return Ok(());
@@ -348,7 +521,7 @@ impl ClusterPeer {
 
                         if let Ok(msg) = ClusterMessage::decode(&read_buf[4..4 + len]) {
                             match msg {
-                                ClusterMessage::Publish { topic, payload, qos, retain, origin_node } => {
+                                ClusterMessage::Publish { topic, payload, qos, retain, origin_node, publisher } => {
                                     // Always process messages from cluster peers
                                     let qos_level = match qos {
                                         0 => QoS::AtMostOnce,
@@ -365,6 +538,7 @@ impl ClusterPeer {
                                         qos_level,
                                         retain,
                                         origin_node,
+                                        publisher,
                                     );
                                 }
                                 ClusterMessage::SubscriptionSync { filters } => {
@@ -398,6 +572,27 @@ impl ClusterPeer {
                                 ClusterMessage::Pong => {
                                     debug!("ClusterPeer '{}': Pong received", node_id);
                                 }
+                                ClusterMessage::CompressionAccept { codec } => {
+                                    let codec = codec
+                                        .as_deref()
+                                        .and_then(CompressionCodec::from_name)
+                                        .filter(|c| compression.codecs.contains(c));
+                                    if let Some(codec) = codec {
+                                        info!(
+                                            "ClusterPeer '{}': Compressing with {}",
+                                            node_id,
+                                            codec.as_str()
+                                        );
+                                    }
+                                    compressor = codec.map(|codec| {
+                                        LinkCompressor::new(
+                                            codec,
+                                            compression,
+                                            &format!("cluster:{}", node_id),
+                                            compression_metrics,
+                                        )
+                                    });
+                                }
                                 ClusterMessage::Goodbye => {
                                     info!("ClusterPeer '{}': Received Goodbye", node_id);
                                     return Err(RemoteError::ConnectionLost("Peer disconnected".to_string()));
@@ -443,18 +638,8 @@ impl RemotePeer for ClusterPeer {
         qos: QoS,
         retain: bool,
     ) -> Result<(), RemoteError> {
-        if let Some(ref tx) = self.command_tx {
-            tx.send(ClusterCommand::Publish {
-                topic: topic.to_string(),
-                payload,
-                qos,
-                retain,
-                origin_node: self.local_node_id.clone(),
-            })
+        self.forward_publish_from(topic, payload, qos, retain, None)
             .await
-            .map_err(|_| RemoteError::ConnectionLost("Command channel closed".to_string()))?;
-        }
-        Ok(())
     }
 
     async fn notify_subscribe(&self, filter: &str, _qos: QoS) -> Result<(), RemoteError> {
@@ -485,11 +670,13 @@ impl RemotePeer for ClusterPeer {
 
     fn should_forward(&self, topic: &str) -> bool {
         // Check if the peer has any subscription that matches this topic
+        // (share groups by their topic filter)
         let subs = self.remote_subscriptions.read();
         let subs_list: Vec<_> = subs.iter().cloned().collect();
-        let matches = subs
-            .iter()
-            .any(|filter| topic_matches_filter(topic, filter));
+        let matches = subs.iter().any(|filter| {
+            let filter = parse_shared_subscription(filter).map_or(filter.as_str(), |(_, f)| f);
+            topic_matches_filter(topic, filter)
+        });
         tracing::debug!(
             "ClusterPeer '{}': should_forward('{}')={} remote_subs={:?}",
             self.node_id,
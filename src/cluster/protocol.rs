@@ -5,9 +5,18 @@
 
 use bincode::{Decode, Encode};
 
+use crate::config::CompressionCodec;
+use crate::remote::LinkCompressor;
+use crate::session::RateBucket;
+
+use super::metrics::MetricFamily;
+
 /// Protocol version for compatibility checking
 pub const CLUSTER_PROTOCOL_VERSION: u8 = 1;
 
+/// Largest message a compressed frame may unpack to
+pub const MAX_DECOMPRESSED_FRAME: usize = 16 * 1024 * 1024;
+
 /// Messages exchanged between cluster nodes over TCP
 #[derive(Debug, Clone, Encode, Decode)]
 pub enum ClusterMessage {
@@ -39,6 +48,8 @@ pub enum ClusterMessage {
         retain: bool,
         /// Origin node ID (to prevent loops)
         origin_node: String,
+        /// Client ID of the publisher, for sticky shared subscriptions
+        publisher: Option<String>,
     },
 
     /// Full subscription state sync
@@ -55,6 +66,52 @@ pub enum ClusterMessage {
         removed: Vec<String>,
     },
 
+    /// Publish rate buckets changed since the last sync
+    RateLimitSync {
+        /// Changed buckets; receivers keep the emptier of theirs and ours
+        buckets: Vec<RateBucket>,
+    },
+
+    /// A client connected to the sender, which now owns its session
+    SessionTakeover {
+        /// Client ID of the connected client
+        client_id: String,
+        /// Connect epoch; receivers ignore announcements older than the
+        /// connect they know of
+        epoch: u64,
+    },
+
+    /// Part of the sender's metrics, sent to the leader for federation
+    MetricsReport {
+        /// Unix timestamp in milliseconds when the metrics were collected
+        collected_ms: u64,
+        /// Metric families (a family may continue in the next part)
+        families: Vec<MetricFamily>,
+        /// Whether this is the last part of the report
+        last: bool,
+    },
+
+    /// Compression codecs the sender can use, most preferred first (sent
+    /// after the handshake; nodes without compression ignore it)
+    CompressionOffer {
+        /// Codec names
+        codecs: Vec<String>,
+    },
+
+    /// Reply to an offer: the codec the sender takes, if any
+    CompressionAccept {
+        /// Codec name (None = send uncompressed)
+        codec: Option<String>,
+    },
+
+    /// Another message, encoded and compressed with the accepted codec
+    Compressed {
+        /// Codec name
+        codec: String,
+        /// Compressed encoding of the message
+        data: Vec<u8>,
+    },
+
     /// Keep-alive ping
     Ping,
 
@@ -84,6 +141,12 @@ impl ClusterMessage {
             ClusterMessage::Publish { .. } => "Publish",
             ClusterMessage::SubscriptionSync { .. } => "SubscriptionSync",
             ClusterMessage::SubscriptionUpdate { .. } => "SubscriptionUpdate",
+            ClusterMessage::RateLimitSync { .. } => "RateLimitSync",
+            ClusterMessage::SessionTakeover { .. } => "SessionTakeover",
+            ClusterMessage::MetricsReport { .. } => "MetricsReport",
+            ClusterMessage::CompressionOffer { .. } => "CompressionOffer",
+            ClusterMessage::CompressionAccept { .. } => "CompressionAccept",
+            ClusterMessage::Compressed { .. } => "Compressed",
             ClusterMessage::Ping => "Ping",
             ClusterMessage::Pong => "Pong",
             ClusterMessage::Goodbye => "Goodbye",
@@ -103,6 +166,45 @@ pub fn frame_message(msg: &ClusterMessage) -> Result<Vec<u8>, bincode::error::En
     Ok(frame)
 }
 
+/// Frame a message, compressed if the link has a codec and the message
+/// is worth compressing
+pub fn frame_compressed(
+    msg: &ClusterMessage,
+    compressor: Option<&LinkCompressor>,
+) -> Result<Vec<u8>, bincode::error::EncodeError> {
+    let Some(compressor) = compressor else {
+        return frame_message(msg);
+    };
+    let encoded = msg.encode()?;
+    match compressor.compress(&encoded) {
+        Some(data) => frame_message(&ClusterMessage::Compressed {
+            codec: compressor.codec().as_str().to_string(),
+            data,
+        }),
+        None => frame_message(msg),
+    }
+}
+
+/// Decode a frame's message, unpacking it if compressed
+///
+/// Compressed frames are only accepted with the codec negotiated for the
+/// link (`decompressor`).
+pub fn decode_frame(
+    data: &[u8],
+    decompressor: Option<&LinkCompressor>,
+) -> Result<ClusterMessage, Box<dyn std::error::Error + Send + Sync>> {
+    match ClusterMessage::decode(data)? {
+        ClusterMessage::Compressed { codec, data } => {
+            let decompressor = decompressor
+                .filter(|d| CompressionCodec::from_name(&codec) == Some(d.codec()))
+                .ok_or_else(|| format!("Unexpected {} compressed frame", codec))?;
+            let decoded = decompressor.decompress(&data, MAX_DECOMPRESSED_FRAME)?;
+            Ok(ClusterMessage::decode(&decoded)?)
+        }
+        msg => Ok(msg),
+    }
+}
+
 /// Read frame length from bytes (returns None if not enough data)
 pub fn read_frame_length(data: &[u8]) -> Option<u32> {
     if data.len() < 4 {
@@ -114,6 +216,8 @@ pub fn read_frame_length(data: &[u8]) -> Option<u32> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cluster::metrics::{MetricKind, MetricSeries};
+    use crate::config::LinkCompressionConfig;
 
     #[test]
     fn test_encode_decode_hello() {
@@ -142,6 +246,7 @@ mod tests {
             qos: 1,
             retain: true,
             origin_node: "node1".to_string(),
+            publisher: Some("sensor-1".to_string()),
         };
 
         let encoded = msg.encode().unwrap();
@@ -154,12 +259,14 @@ mod tests {
                 qos,
                 retain,
                 origin_node,
+                publisher,
             } => {
                 assert_eq!(topic, "test/topic");
                 assert_eq!(payload, vec![1, 2, 3, 4]);
                 assert_eq!(qos, 1);
                 assert!(retain);
                 assert_eq!(origin_node, "node1");
+                assert_eq!(publisher.as_deref(), Some("sensor-1"));
             }
             _ => panic!("Wrong message type"),
         }
@@ -184,6 +291,75 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_encode_decode_rate_limit_sync() {
+        let bucket = RateBucket {
+            identity: "user:alice".to_string(),
+            tokens: 1.5,
+            updated_ms: 1_700_000_000_000,
+        };
+        let msg = ClusterMessage::RateLimitSync {
+            buckets: vec![bucket.clone()],
+        };
+
+        let encoded = msg.encode().unwrap();
+        match ClusterMessage::decode(&encoded).unwrap() {
+            ClusterMessage::RateLimitSync { buckets } => assert_eq!(buckets, vec![bucket]),
+            _ => panic!("Wrong message type"),
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_session_takeover() {
+        let msg = ClusterMessage::SessionTakeover {
+            client_id: "sensor-42".to_string(),
+            epoch: 7,
+        };
+
+        let encoded = msg.encode().unwrap();
+        match ClusterMessage::decode(&encoded).unwrap() {
+            ClusterMessage::SessionTakeover { client_id, epoch } => {
+                assert_eq!(client_id, "sensor-42");
+                assert_eq!(epoch, 7);
+            }
+            _ => panic!("Wrong message type"),
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_metrics_report() {
+        let family = MetricFamily {
+            name: "vibemq_connections_current".to_string(),
+            help: "Current number of connections".to_string(),
+            kind: MetricKind::Gauge,
+            series: vec![MetricSeries {
+                labels: vec![("listener".to_string(), "tcp".to_string())],
+                value: 12.0,
+                count: 0,
+                buckets: Vec::new(),
+            }],
+        };
+        let msg = ClusterMessage::MetricsReport {
+            collected_ms: 1_700_000_000_000,
+            families: vec![family.clone()],
+            last: true,
+        };
+
+        let encoded = msg.encode().unwrap();
+        match ClusterMessage::decode(&encoded).unwrap() {
+            ClusterMessage::MetricsReport {
+                collected_ms,
+                families,
+                last,
+            } => {
+                assert_eq!(collected_ms, 1_700_000_000_000);
+                assert_eq!(families, vec![family]);
+                assert!(last);
+            }
+            _ => panic!("Wrong message type"),
+        }
+    }
+
     #[test]
     fn test_frame_message() {
         let msg = ClusterMessage::Ping;
@@ -198,6 +374,47 @@ mod tests {
         assert!(matches!(decoded, ClusterMessage::Ping));
     }
 
+    #[test]
+    fn test_frame_compressed() {
+        let config = LinkCompressionConfig {
+            codecs: vec![CompressionCodec::Lz4],
+            min_size: 64,
+            level: 3,
+        };
+        let link = LinkCompressor::new(CompressionCodec::Lz4, &config, "cluster:node2", None);
+        let msg = ClusterMessage::Publish {
+            topic: "sensors/temp".to_string(),
+            payload: b"21.5;".repeat(100),
+            qos: 1,
+            retain: false,
+            origin_node: "node1".to_string(),
+            publisher: None,
+        };
+
+        let frame = frame_compressed(&msg, Some(&link)).unwrap();
+        let len = read_frame_length(&frame).unwrap() as usize;
+        assert!(len < msg.encode().unwrap().len());
+        let compressed = &frame[4..];
+        assert!(matches!(
+            ClusterMessage::decode(compressed).unwrap(),
+            ClusterMessage::Compressed { ref codec, .. } if codec == "lz4"
+        ));
+        match decode_frame(compressed, Some(&link)).unwrap() {
+            ClusterMessage::Publish { payload, .. } => assert_eq!(payload, b"21.5;".repeat(100)),
+            _ => panic!("Wrong message type"),
+        }
+
+        // Only with the negotiated codec
+        assert!(decode_frame(compressed, None).is_err());
+
+        // Small messages go uncompressed
+        let frame = frame_compressed(&ClusterMessage::Ping, Some(&link)).unwrap();
+        assert!(matches!(
+            decode_frame(&frame[4..], None).unwrap(),
+            ClusterMessage::Ping
+        ));
+    }
+
     #[test]
     fn test_type_name() {
         assert_eq!(ClusterMessage::Ping.type_name(), "Ping");
@@ -0,0 +1,93 @@
+//! Session ownership across cluster nodes
+//!
+//! A client that connects to a node takes its session over from the others.
+//! Each connect is stamped with an epoch: microseconds since the Unix epoch,
+//! raised past any epoch already known for the client, so a node's own
+//! connects always win over announcements it has seen. Announcements can
+//! arrive late (a peer link coming up after the client moved on), so a node
+//! only gives a session up for an announcement newer than the connect it
+//! knows of; equal epochs are ordered by node ID.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dashmap::DashMap;
+
+/// Latest known connect of each client, and the node it connected to
+#[derive(Debug)]
+pub(crate) struct SessionOwners {
+    node_id: String,
+    owners: DashMap<String, (u64, String)>,
+}
+
+impl SessionOwners {
+    pub(crate) fn new(node_id: String) -> Self {
+        Self {
+            node_id,
+            owners: DashMap::new(),
+        }
+    }
+
+    /// Record a client connecting here; returns its connect epoch
+    pub(crate) fn connected(&self, client_id: &str) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or_default();
+        let mut owner = self
+            .owners
+            .entry(client_id.to_string())
+            .or_insert_with(|| (0, String::new()));
+        let epoch = now.max(owner.0.saturating_add(1));
+        *owner = (epoch, self.node_id.clone());
+        epoch
+    }
+
+    /// Record a client connecting to `node_id`; false if a connect at least
+    /// as recent is already known (the announcement is stale)
+    pub(crate) fn connected_to(&self, client_id: &str, epoch: u64, node_id: &str) -> bool {
+        let mut owner = self
+            .owners
+            .entry(client_id.to_string())
+            .or_insert_with(|| (0, String::new()));
+        if (epoch, node_id) <= (owner.0, owner.1.as_str()) {
+            return false;
+        }
+        *owner = (epoch, node_id.to_string());
+        true
+    }
+
+    /// Clients whose latest known connect was here, with their epochs
+    pub(crate) fn local(&self) -> Vec<(String, u64)> {
+        self.owners
+            .iter()
+            .filter(|owner| owner.value().1 == self.node_id)
+            .map(|owner| (owner.key().clone(), owner.value().0))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stale_announcements_are_ignored() {
+        let a = SessionOwners::new("a".to_string());
+        let first = a.connected("sensor");
+        let second = a.connected("sensor");
+        assert!(second > first);
+
+        // An announcement older than the local connect arrives late
+        assert!(!a.connected_to("sensor", first, "b"));
+        assert_eq!(a.local(), [("sensor".to_string(), second)]);
+
+        // A newer one takes the session over, and a later local connect
+        // takes it back even if the clock is behind
+        assert!(a.connected_to("sensor", u64::MAX - 1, "b"));
+        assert!(!a.connected_to("sensor", u64::MAX - 1, "b"));
+        assert!(a.local().is_empty());
+        assert_eq!(a.connected("sensor"), u64::MAX);
+        assert!(!a.connected_to("sensor", u64::MAX, "a"));
+        assert!(a.connected_to("sensor", u64::MAX, "c"));
+    }
+}
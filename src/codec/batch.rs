@@ -0,0 +1,103 @@
+//! Batch frame codec
+//!
+//! A batch frame packs many small messages into a single PUBLISH payload so
+//! high-frequency publishers avoid per-message protocol overhead:
+//!
+//! ```text
+//! version (1 byte, 0x01)
+//! repeated:
+//!   topic    UTF-8 string (2-byte length prefix)
+//!   length   Variable Byte Integer
+//!   payload  `length` bytes
+//! ```
+//!
+//! Entry topics are relative to the batch topic's suffix: a batch published
+//! to `$batch/sensors/dev1` with entry topic `temp` routes to
+//! `sensors/dev1/temp` (an empty entry topic routes to `sensors/dev1`).
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use super::{read_string, read_variable_int, write_string, write_variable_int};
+use crate::protocol::{DecodeError, EncodeError};
+
+/// Current batch frame format version
+pub const BATCH_FORMAT_VERSION: u8 = 0x01;
+
+/// A single message in a batch frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchEntry {
+    /// Topic, relative to the batch topic's prefix
+    pub topic: String,
+    /// Message payload
+    pub payload: Bytes,
+}
+
+impl BatchEntry {
+    pub fn new(topic: impl Into<String>, payload: impl Into<Bytes>) -> Self {
+        Self {
+            topic: topic.into(),
+            payload: payload.into(),
+        }
+    }
+}
+
+/// Encode entries into a batch frame payload
+pub fn encode_batch(entries: &[BatchEntry]) -> Result<Bytes, EncodeError> {
+    let size: usize = entries
+        .iter()
+        .map(|e| 2 + e.topic.len() + 4 + e.payload.len())
+        .sum();
+    let mut buf = BytesMut::with_capacity(1 + size);
+    buf.put_u8(BATCH_FORMAT_VERSION);
+    for entry in entries {
+        write_string(&mut buf, &entry.topic)?;
+        write_variable_int(&mut buf, entry.payload.len() as u32)?;
+        buf.put_slice(&entry.payload);
+    }
+    Ok(buf.freeze())
+}
+
+/// Decode a batch frame payload
+///
+/// Entry payloads are zero-copy slices of `payload`. Fails if the frame is
+/// malformed or holds more than `max_messages` entries (0 = unlimited).
+pub fn decode_batch(payload: &Bytes, max_messages: usize) -> Result<Vec<BatchEntry>, DecodeError> {
+    let Some((&version, mut rest)) = payload.split_first() else {
+        return Err(DecodeError::MalformedPacket("empty batch frame"));
+    };
+    if version != BATCH_FORMAT_VERSION {
+        return Err(DecodeError::MalformedPacket(
+            "unsupported batch format version",
+        ));
+    }
+
+    let mut entries = Vec::new();
+    let mut offset = 1;
+    while !rest.is_empty() {
+        if max_messages > 0 && entries.len() >= max_messages {
+            return Err(DecodeError::PacketTooLarge);
+        }
+        let (topic, topic_len) = read_string(rest).map_err(truncated)?;
+        let (len, len_len) = read_variable_int(&rest[topic_len..]).map_err(truncated)?;
+        let start = topic_len + len_len;
+        let end = start + len as usize;
+        if rest.len() < end {
+            return Err(DecodeError::MalformedPacket("truncated batch entry"));
+        }
+        entries.push(BatchEntry {
+            topic: topic.to_string(),
+            payload: payload.slice(offset + start..offset + end),
+        });
+        rest = &rest[end..];
+        offset += end;
+    }
+    Ok(entries)
+}
+
+/// A complete frame has been received, so running out of data means it's malformed
+fn truncated(e: DecodeError) -> DecodeError {
+    match e {
+        DecodeError::InsufficientData => DecodeError::MalformedPacket("truncated batch entry"),
+        e => e,
+    }
+}
@@ -17,6 +17,10 @@ pub struct Decoder {
     max_packet_size: usize,
     /// Current protocol version (set after CONNECT)
     protocol_version: Option<ProtocolVersion>,
+    /// Accept legacy MQTT 3.1 ("MQIsdp", level 3) CONNECT packets
+    allow_mqtt31: bool,
+    /// Whether the decoded CONNECT was MQTT 3.1
+    mqtt31: bool,
 }
 
 impl Decoder {
@@ -24,6 +28,8 @@ impl Decoder {
         Self {
             max_packet_size: MAX_REMAINING_LENGTH,
             protocol_version: None,
+            allow_mqtt31: true,
+            mqtt31: false,
         }
     }
 
@@ -32,6 +38,17 @@ impl Decoder {
         self
     }
 
+    /// Accept MQTT 3.1 clients, decoding them as v3.1.1 (the default)
+    pub fn with_mqtt31(mut self, allow: bool) -> Self {
+        self.allow_mqtt31 = allow;
+        self
+    }
+
+    /// Whether the client connected with MQTT 3.1 ("MQIsdp")
+    pub fn is_mqtt31(&self) -> bool {
+        self.mqtt31
+    }
+
     pub fn set_protocol_version(&mut self, version: ProtocolVersion) {
         self.protocol_version = Some(version);
     }
@@ -124,9 +141,15 @@ impl Decoder {
         let version_byte = payload[pos];
         pos += 1;
 
-        let protocol_version = match version_byte {
-            3 | 4 => ProtocolVersion::V311,
-            5 => ProtocolVersion::V5,
+        // MQTT 3.1 pairs "MQIsdp" with level 3 and is otherwise identical to v3.1.1
+        // on the wire, so it is decoded as v3.1.1 where enabled
+        let protocol_version = match (protocol_name, version_byte) {
+            ("MQIsdp", 3) if self.allow_mqtt31 => {
+                self.mqtt31 = true;
+                ProtocolVersion::V311
+            }
+            ("MQTT", 4) => ProtocolVersion::V311,
+            ("MQTT", 5) => ProtocolVersion::V5,
             _ => return Err(DecodeError::InvalidProtocolVersion(version_byte)),
         };
 
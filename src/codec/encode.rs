@@ -26,6 +26,10 @@ impl Encoder {
         self.protocol_version = version;
     }
 
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
     /// Encode a packet to the buffer
     pub fn encode(&self, packet: &Packet, buf: &mut BytesMut) -> Result<(), EncodeError> {
         match packet {
@@ -199,6 +203,23 @@ impl Encoder {
     }
 
     fn encode_publish(&self, packet: &Publish, buf: &mut BytesMut) -> Result<(), EncodeError> {
+        self.encode_publish_header(packet, buf)?;
+        buf.put_slice(&packet.payload);
+        Ok(())
+    }
+
+    /// Encode a PUBLISH up to its payload, which the caller writes after it
+    ///
+    /// Returns the size of the whole packet, payload included. Writing the
+    /// payload `Bytes` as a buffer of its own lets a message fanned out to
+    /// many subscribers share one payload instead of copying it into every
+    /// frame.
+    pub fn encode_publish_header(
+        &self,
+        packet: &Publish,
+        buf: &mut BytesMut,
+    ) -> Result<usize, EncodeError> {
+        let start = buf.len();
         let is_v5 = self.protocol_version == ProtocolVersion::V5;
 
         // Calculate remaining length
@@ -240,10 +261,7 @@ impl Encoder {
             packet.properties.encode(buf)?;
         }
 
-        // Payload
-        buf.put_slice(&packet.payload);
-
-        Ok(())
+        Ok(buf.len() - start + packet.payload.len())
     }
 
     fn encode_puback(&self, packet: &PubAck, buf: &mut BytesMut) -> Result<(), EncodeError> {
@@ -3,12 +3,14 @@
 //! Provides encoding and decoding for MQTT v3.1.1 and v5.0 packets
 //! in a unified manner.
 
+mod batch;
 mod decode;
 mod encode;
 
 #[cfg(test)]
 mod tests;
 
+pub use batch::{decode_batch, encode_batch, BatchEntry, BATCH_FORMAT_VERSION};
 pub use decode::Decoder;
 pub use encode::Encoder;
 
@@ -195,6 +195,45 @@ fn test_connect_invalid_protocol_version() {
     ));
 }
 
+#[test]
+fn test_connect_mqtt31() {
+    let mqisdp = [
+        0x10, 0x0F, // CONNECT, remaining length
+        0x00, 0x06, b'M', b'Q', b'I', b's', b'd', b'p', // "MQIsdp"
+        0x03, // Protocol level 3
+        0x02, // Clean session
+        0x00, 0x3C, // Keep alive 60
+        0x00, 0x01, b'a', // Client ID "a"
+    ];
+
+    // Rejected where disabled
+    let mut decoder = Decoder::new().with_mqtt31(false);
+    assert!(matches!(
+        decoder.decode(&mqisdp),
+        Err(DecodeError::InvalidProtocolVersion(3))
+    ));
+
+    let mut decoder = Decoder::new();
+    match decoder.decode(&mqisdp).unwrap() {
+        Some((Packet::Connect(connect), _)) => {
+            assert_eq!(connect.protocol_version, ProtocolVersion::V311);
+            assert_eq!(connect.client_id, "a");
+        }
+        other => panic!("expected CONNECT, got {:?}", other),
+    }
+    assert!(decoder.is_mqtt31());
+
+    // Level 3 is only valid with the "MQIsdp" name
+    let mismatched = [
+        0x10, 0x0D, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x03, 0x02, 0x00, 0x3C, 0x00, 0x01, b'a',
+    ];
+    let mut decoder = Decoder::new().with_mqtt31(true);
+    assert!(matches!(
+        decoder.decode(&mismatched),
+        Err(DecodeError::InvalidProtocolVersion(3))
+    ));
+}
+
 #[test]
 fn test_connect_reserved_bit_set() {
     // Reserved bit in connect flags must be 0
@@ -405,6 +444,32 @@ fn test_publish_v5_with_properties() {
     assert_eq!(packet, decoded);
 }
 
+#[test]
+fn test_publish_header_then_payload() {
+    let mut props = Properties::default();
+    props.message_expiry_interval = Some(60);
+    let publish = Publish {
+        dup: false,
+        qos: QoS::AtLeastOnce,
+        retain: false,
+        topic: "fan/out".to_string(),
+        packet_id: Some(9),
+        payload: Bytes::from(vec![7u8; 300]),
+        properties: props,
+    };
+
+    for version in [ProtocolVersion::V311, ProtocolVersion::V5] {
+        let whole = encode_packet(&Packet::Publish(publish.clone()), version);
+        let mut header = BytesMut::new();
+        let len = Encoder::new(version)
+            .encode_publish_header(&publish, &mut header)
+            .unwrap();
+        assert_eq!(len, whole.len());
+        assert_eq!(&whole[..header.len()], &header[..]);
+        assert_eq!(&whole[header.len()..], &publish.payload[..]);
+    }
+}
+
 #[test]
 fn test_publish_empty_payload() {
     let packet = Packet::Publish(Publish {
@@ -1241,6 +1306,47 @@ fn test_roundtrip_all_packet_types_v5() {
     }
 }
 
+// ============================================================================
+// Batch Frame Tests
+// ============================================================================
+
+#[test]
+fn test_batch_roundtrip() {
+    use crate::codec::{decode_batch, encode_batch, BatchEntry};
+
+    let entries = vec![
+        BatchEntry::new("temp", Bytes::from_static(b"21.5")),
+        BatchEntry::new("", Bytes::new()),
+        BatchEntry::new("humidity/raw", Bytes::from(vec![0u8; 300])),
+    ];
+    let frame = encode_batch(&entries).unwrap();
+    assert_eq!(frame[0], crate::codec::BATCH_FORMAT_VERSION);
+    assert_eq!(decode_batch(&frame, 0).unwrap(), entries);
+    assert_eq!(decode_batch(&frame, 3).unwrap().len(), 3);
+
+    // Over the entry limit
+    assert_eq!(decode_batch(&frame, 2), Err(DecodeError::PacketTooLarge));
+}
+
+#[test]
+fn test_batch_malformed() {
+    use crate::codec::{decode_batch, encode_batch, BatchEntry};
+
+    assert!(decode_batch(&Bytes::new(), 0).is_err());
+    assert!(decode_batch(&Bytes::from_static(&[0x02]), 0).is_err());
+    // Version only is an empty batch
+    assert!(decode_batch(&Bytes::from_static(&[0x01]), 0)
+        .unwrap()
+        .is_empty());
+
+    let frame = encode_batch(&[BatchEntry::new("a", Bytes::from_static(b"hello"))]).unwrap();
+    let truncated = frame.slice(..frame.len() - 1);
+    assert_eq!(
+        decode_batch(&truncated, 0),
+        Err(DecodeError::MalformedPacket("truncated batch entry"))
+    );
+}
+
 // ============================================================================
 // Property-Based Tests (using proptest)
 // ============================================================================
@@ -0,0 +1,26 @@
+//! Admin API configuration
+
+use serde::Deserialize;
+use std::net::SocketAddr;
+
+/// Admin HTTP API configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// Whether the admin API is served
+    pub enabled: bool,
+    /// HTTP bind address
+    pub bind: SocketAddr,
+    /// Bearer token required on every request (required when enabled)
+    pub token: Option<String>,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1:8081".parse().unwrap(),
+            token: None,
+        }
+    }
+}
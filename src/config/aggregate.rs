@@ -0,0 +1,158 @@
+//! Aggregation Window Configuration
+//!
+//! `[[aggregate]]` entries summarize the numeric values published to a
+//! topic filter over a time window and publish the result to an output
+//! topic each time the window advances. An optional `[aggregate.alert]`
+//! raises an alert when a result crosses a threshold or departs from the
+//! key's usual level.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use super::PayloadConfig;
+
+/// How the values in a window are summarized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateFunction {
+    /// Number of messages (numeric or not)
+    #[default]
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFunction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+        }
+    }
+}
+
+/// One windowed aggregation
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AggregateConfig {
+    /// Unique name (client ID "aggregate:<name>" for ACLs)
+    pub name: String,
+    /// Enable this aggregation
+    pub enabled: bool,
+    /// Topic filter whose messages are aggregated
+    pub filter: String,
+    /// Topic level (0-based) whose value keys separate windows, e.g. 1 for
+    /// the device in "sensors/+/temperature"; unset = one window for all
+    pub key_level: Option<usize>,
+    /// Dot-separated JSON field holding the value ("reading.temp"); unset
+    /// = the whole payload is the number
+    pub field: Option<String>,
+    /// Payload encoding (JSON by default)
+    pub payload: PayloadConfig,
+    pub function: AggregateFunction,
+    /// Length of the window
+    #[serde(with = "humantime_serde")]
+    pub window: Duration,
+    /// How often the window advances and results are published; equal to
+    /// `window` (the default) for tumbling windows, a divisor of it for
+    /// sliding windows
+    #[serde(with = "humantime_serde")]
+    pub slide: Option<Duration>,
+    /// Output topic template; `{key}` is the window's key, `{name}` the
+    /// aggregation's name
+    pub output: String,
+    /// QoS of the published results (0-2)
+    pub qos: u8,
+    /// Publish results as retained messages
+    pub retain: bool,
+    /// Username ACLs are checked for
+    pub username: Option<String>,
+    /// Alert on anomalous results
+    pub alert: Option<AggregateAlertConfig>,
+}
+
+impl AggregateConfig {
+    /// Interval between results
+    pub fn slide(&self) -> Duration {
+        self.slide.unwrap_or(self.window)
+    }
+}
+
+impl Default for AggregateConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            filter: String::new(),
+            key_level: None,
+            field: None,
+            payload: PayloadConfig::default(),
+            function: AggregateFunction::Count,
+            window: Duration::from_secs(60),
+            slide: None,
+            output: String::new(),
+            qos: 0,
+            retain: false,
+            username: None,
+            alert: None,
+        }
+    }
+}
+
+/// Anomaly alerts on an aggregation's results
+///
+/// A key's alert is raised when its result crosses `above` or `below` or
+/// exceeds `baseline_factor` times its baseline (an exponentially weighted
+/// moving average of its earlier results), and cleared once a result is
+/// back in range.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AggregateAlertConfig {
+    /// Alert when a result is above this value
+    pub above: Option<f64>,
+    /// Alert when a result is below this value
+    pub below: Option<f64>,
+    /// Alert when a result exceeds this multiple of the key's baseline,
+    /// e.g. 10 with `function = "count"` for "10x its normal rate"
+    pub baseline_factor: Option<f64>,
+    /// Weight of the newest result in the baseline (0 < alpha <= 1)
+    pub baseline_alpha: f64,
+    /// Results a key's baseline is built from before it can alert
+    pub baseline_warmup: u32,
+    /// Topic template alerts are published to; `{key}` and `{name}` as for
+    /// `output`
+    pub topic: Option<String>,
+    /// QoS of published alerts (0-2)
+    pub qos: u8,
+    /// URL (http:// only) each alert is POSTed to as JSON
+    pub webhook: Option<String>,
+    /// Extra webhook request headers (e.g. an Authorization token)
+    pub webhook_headers: HashMap<String, String>,
+    /// Time allowed for each webhook request
+    #[serde(with = "humantime_serde")]
+    pub webhook_timeout: Duration,
+}
+
+impl Default for AggregateAlertConfig {
+    fn default() -> Self {
+        Self {
+            above: None,
+            below: None,
+            baseline_factor: None,
+            baseline_alpha: 0.2,
+            baseline_warmup: 5,
+            topic: None,
+            qos: 1,
+            webhook: None,
+            webhook_headers: HashMap::new(),
+            webhook_timeout: Duration::from_secs(5),
+        }
+    }
+}
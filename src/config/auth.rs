@@ -0,0 +1,42 @@
+//! HTTP Authentication Webhook Configuration
+//!
+//! Delegates credential checks to an external service: each CONNECT is
+//! POSTed as JSON and the response status (and optional `result` field)
+//! decides. Answers are cached briefly so reconnect storms don't overwhelm
+//! the service.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// HTTP authentication webhook
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HttpAuthConfig {
+    /// Endpoint to POST credentials to (http:// only, e.g. a local sidecar)
+    pub url: String,
+    /// Time allowed for the whole request; a timeout refuses the client
+    /// with "Server unavailable"
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    /// How long answers are reused for identical credentials (0 = no cache)
+    #[serde(with = "humantime_serde")]
+    pub cache_ttl: Duration,
+    /// Maximum cached answers
+    pub cache_size: usize,
+    /// Extra request headers (e.g. an Authorization token)
+    pub headers: HashMap<String, String>,
+}
+
+impl Default for HttpAuthConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            timeout: Duration::from_secs(5),
+            cache_ttl: Duration::from_secs(60),
+            cache_size: 10_000,
+            headers: HashMap::new(),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Backlog Drain Configuration
+//!
+//! Configuration for sharing delivery between sessions draining queued
+//! messages, so the first clients to reconnect after a restart can't take
+//! all of the broker's bandwidth.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Backlog drain configuration (`[session.backlog_drain]`)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct BacklogDrainConfig {
+    /// Take turns draining backlogs instead of each session sending its
+    /// whole queue on reconnect
+    pub fair: bool,
+    /// Sessions draining at the same time
+    pub concurrency: usize,
+    /// Messages a session sends per turn, times its role's `drain_weight`
+    pub batch_size: usize,
+    /// Longest a turn lasts; the rest of the batch waits for the session's
+    /// next turn (default: 1s)
+    #[serde(with = "humantime_serde")]
+    pub turn_timeout: Duration,
+}
+
+impl Default for BacklogDrainConfig {
+    fn default() -> Self {
+        Self {
+            fair: false,
+            concurrency: 4,
+            batch_size: 100,
+            turn_timeout: Duration::from_secs(1),
+        }
+    }
+}
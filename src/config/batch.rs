@@ -0,0 +1,42 @@
+//! Message Batching Configuration
+//!
+//! Configuration for the batch frame extension, which lets cooperating
+//! clients publish many small messages in a single PUBLISH.
+
+use serde::Deserialize;
+
+/// Message batching configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BatchConfig {
+    /// Unpack batch frames published to the batch topic
+    pub enabled: bool,
+    /// Batch topic. Publishing to "{topic}" or "{topic}/{prefix}" delivers a
+    /// batch frame whose entry topics are relative to the prefix (default: "$batch")
+    pub topic: String,
+    /// Maximum messages per batch frame (default: 1000, 0 = unlimited)
+    pub max_messages: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            topic: "$batch".to_string(),
+            max_messages: 1000,
+        }
+    }
+}
+
+impl BatchConfig {
+    /// Get the entry topic prefix if `topic` is a batch topic
+    ///
+    /// Returns `Some("")` for the bare batch topic.
+    pub fn prefix<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        let rest = topic.strip_prefix(self.topic.as_str())?;
+        if rest.is_empty() {
+            return Some("");
+        }
+        rest.strip_prefix('/')
+    }
+}
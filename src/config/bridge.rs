@@ -2,10 +2,13 @@
 //!
 //! Configuration structures for MQTT bridge connections.
 
+use std::net::SocketAddr;
 use std::time::Duration;
 
 use serde::Deserialize;
 
+use super::{LinkCompressionConfig, UserPropertyMatch};
+
 /// Bridge connection protocol
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -89,6 +92,23 @@ pub struct ForwardRule {
     /// Whether to forward retained messages
     #[serde(default = "default_true")]
     pub retain: bool,
+
+    /// Outbound: user properties local messages must carry (all of them)
+    /// to be forwarded by this rule, e.g. only `env=prod` reaches the
+    /// bridge
+    #[serde(default)]
+    pub user_properties: Vec<UserPropertyMatch>,
+}
+
+/// Order in which a bridge tries its remote addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointSelection {
+    /// Always start from the first address, falling back down the list
+    #[default]
+    Failover,
+    /// Start each connection attempt one address further along
+    RoundRobin,
 }
 
 /// Loop prevention strategy
@@ -141,6 +161,20 @@ pub struct BridgeConfig {
     /// Remote broker address (host:port or just host)
     pub address: String,
 
+    /// Further remote addresses, tried after `address`
+    #[serde(default)]
+    pub failover_addresses: Vec<String>,
+
+    /// How the addresses are ordered on each connection attempt
+    #[serde(default)]
+    pub endpoint_selection: EndpointSelection,
+
+    /// How long a hostname lookup is reused across reconnects (0 = look up
+    /// again on every reconnect). The system resolver doesn't expose record
+    /// TTLs, so this acts as the upper bound instead.
+    #[serde(default, with = "humantime_serde")]
+    pub dns_ttl: Duration,
+
     /// Connection protocol
     #[serde(default)]
     pub protocol: BridgeProtocol,
@@ -163,6 +197,17 @@ pub struct BridgeConfig {
     #[serde(default = "default_true")]
     pub clean_start: bool,
 
+    /// Session expiry interval requested from the remote broker (e.g.,
+    /// "1h"). With `clean_start = false` the remote keeps the bridge's
+    /// subscriptions and in-flight messages across reconnects.
+    #[serde(default, with = "humantime_serde")]
+    pub session_expiry: Duration,
+
+    /// Maximum unacknowledged QoS 1/2 messages sent to the remote broker
+    /// (further limited by its receive maximum)
+    #[serde(default = "default_max_inflight")]
+    pub max_inflight: u16,
+
     /// Reconnect interval (e.g., "5s", "1m")
     #[serde(default = "default_reconnect_interval", with = "humantime_serde")]
     pub reconnect_interval: Duration,
@@ -199,6 +244,35 @@ pub struct BridgeConfig {
     /// Defaults to the bridge name if not specified
     #[serde(default)]
     pub origin_id: Option<String>,
+
+    /// Send a PROXY protocol header when connecting (for upstreams behind
+    /// a PROXY-aware listener)
+    #[serde(default)]
+    pub proxy_protocol: Option<BridgeProxyConfig>,
+
+    /// Link health probing and failover thresholds
+    #[serde(default)]
+    pub health: BridgeHealthConfig,
+
+    /// Tunnel the connection through a SOCKS5 or HTTP CONNECT proxy
+    #[serde(default)]
+    pub egress_proxy: Option<EgressProxyConfig>,
+
+    /// Tag forwarded messages with the publishing client's address and ID
+    /// (`x-forwarded-*` user properties), so the remote side can apply
+    /// source-based policies
+    #[serde(default)]
+    pub forward_origin: bool,
+
+    /// Compression of forwarded messages, if the remote (a VibeMQ broker)
+    /// accepts it
+    #[serde(default)]
+    pub compression: LinkCompressionConfig,
+
+    /// Keep this bridge running through maintenance windows that pause
+    /// bridges
+    #[serde(default)]
+    pub critical: bool,
 }
 
 fn default_client_id() -> String {
@@ -209,6 +283,10 @@ fn default_keepalive() -> u16 {
     60
 }
 
+fn default_max_inflight() -> u16 {
+    100
+}
+
 fn default_reconnect_interval() -> Duration {
     Duration::from_secs(5)
 }
@@ -230,12 +308,17 @@ impl Default for BridgeConfig {
         Self {
             name: "default".to_string(),
             address: "localhost:1883".to_string(),
+            failover_addresses: Vec::new(),
+            endpoint_selection: EndpointSelection::default(),
+            dns_ttl: Duration::ZERO,
             protocol: BridgeProtocol::default(),
             client_id: default_client_id(),
             username: None,
             password: None,
             keepalive: default_keepalive(),
             clean_start: true,
+            session_expiry: Duration::ZERO,
+            max_inflight: default_max_inflight(),
             reconnect_interval: Duration::from_secs(5),
             max_reconnect_interval: Duration::from_secs(60),
             connect_timeout: Duration::from_secs(30),
@@ -245,6 +328,12 @@ impl Default for BridgeConfig {
             enabled: true,
             loop_prevention: LoopPrevention::default(),
             origin_id: None,
+            proxy_protocol: None,
+            health: BridgeHealthConfig::default(),
+            egress_proxy: None,
+            forward_origin: false,
+            compression: LinkCompressionConfig::default(),
+            critical: false,
         }
     }
 }
@@ -252,12 +341,24 @@ impl Default for BridgeConfig {
 impl BridgeConfig {
     /// Parse address into host and port
     pub fn parse_address(&self) -> (String, u16) {
-        if let Some((host, port_str)) = self.address.rsplit_once(':') {
+        self.parse_endpoint(&self.address)
+    }
+
+    /// Parse any of this bridge's addresses into host and port
+    pub fn parse_endpoint(&self, address: &str) -> (String, u16) {
+        if let Some((host, port_str)) = address.rsplit_once(':') {
             if let Ok(port) = port_str.parse::<u16>() {
+                let host = host.trim_start_matches('[').trim_end_matches(']');
                 return (host.to_string(), port);
             }
         }
-        (self.address.clone(), self.protocol.default_port())
+        (address.to_string(), self.protocol.default_port())
+    }
+
+    /// All remote addresses in configured order
+    pub fn endpoints(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.address.as_str())
+            .chain(self.failover_addresses.iter().map(String::as_str))
     }
 
     /// Get outbound forwarding rules (local → remote)
@@ -312,6 +413,129 @@ pub struct BridgeTlsConfig {
     pub server_name: Option<String>,
 }
 
+/// PROXY protocol version sent by a bridge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BridgeProxyVersion {
+    /// Text header (addresses only)
+    V1,
+    /// Binary header with TLVs
+    #[default]
+    V2,
+}
+
+/// PROXY header sent on bridge connections
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct BridgeProxyConfig {
+    /// Header version
+    #[serde(default)]
+    pub version: BridgeProxyVersion,
+
+    /// Client address to announce (defaults to the connection's local address)
+    pub source_address: Option<SocketAddr>,
+
+    /// SNI sent as PP2_TYPE_AUTHORITY (v2 only)
+    pub sni: Option<String>,
+
+    /// Client certificate CN sent in PP2_TYPE_SSL (v2 only)
+    pub client_cert_cn: Option<String>,
+
+    /// ID sent as PP2_TYPE_UNIQUE_ID (v2 only)
+    pub unique_id: Option<String>,
+}
+
+/// Bridge link health checks
+///
+/// Every PINGREQ is timed. A probe fails when it goes unanswered for
+/// `timeout` or its round trip exceeds `max_latency`; after
+/// `failure_threshold` consecutive failures the connection is dropped and
+/// the endpoint is tried last for `hold_down`, so the bridge fails over to
+/// its next address.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BridgeHealthConfig {
+    /// Probe interval (0 = only time the keepalive pings)
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    /// Time after which an unanswered probe counts as failed
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    /// Round trip above which a probe counts as failed (0 = no limit)
+    #[serde(with = "humantime_serde")]
+    pub max_latency: Duration,
+    /// Consecutive failed probes before failing over (0 = never)
+    pub failure_threshold: u32,
+    /// How long an unhealthy endpoint is tried after the others
+    #[serde(with = "humantime_serde")]
+    pub hold_down: Duration,
+}
+
+impl Default for BridgeHealthConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::ZERO,
+            timeout: Duration::from_secs(10),
+            max_latency: Duration::ZERO,
+            failure_threshold: 3,
+            hold_down: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Kind of outbound proxy a bridge tunnels through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EgressProxyType {
+    /// SOCKS5 (RFC 1928), with optional username/password authentication
+    #[default]
+    Socks5,
+    /// HTTP CONNECT, with optional Basic proxy authentication
+    Http,
+}
+
+impl EgressProxyType {
+    /// Port used when the proxy address doesn't name one
+    pub fn default_port(&self) -> u16 {
+        match self {
+            EgressProxyType::Socks5 => 1080,
+            EgressProxyType::Http => 8080,
+        }
+    }
+}
+
+/// Outbound proxy for bridge connections
+///
+/// The remote hostname is passed to the proxy unresolved, so the bridge
+/// works on networks without outside DNS.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EgressProxyConfig {
+    /// Proxy kind
+    #[serde(default, rename = "type")]
+    pub proxy_type: EgressProxyType,
+
+    /// Proxy address (host:port or just host)
+    pub address: String,
+
+    /// Username for proxy authentication
+    pub username: Option<String>,
+
+    /// Password for proxy authentication
+    pub password: Option<String>,
+}
+
+impl EgressProxyConfig {
+    /// Parse the proxy address into host and port
+    pub fn parse_address(&self) -> (String, u16) {
+        if let Some((host, port_str)) = self.address.rsplit_once(':') {
+            if let Ok(port) = port_str.parse::<u16>() {
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                return (host.to_string(), port);
+            }
+        }
+        (self.address.clone(), self.proxy_type.default_port())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -339,6 +563,82 @@ mod tests {
         assert_eq!(port, 8883); // Default for mqtts
     }
 
+    #[test]
+    fn test_failover_endpoints() {
+        let config: BridgeConfig = toml::from_str(
+            r#"
+            name = "cloud"
+            address = "primary.example.com:8883"
+            failover_addresses = ["[2001:db8::1]:8883", "backup.example.com"]
+            endpoint_selection = "round_robin"
+            dns_ttl = "30s"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.endpoint_selection, EndpointSelection::RoundRobin);
+        assert_eq!(config.dns_ttl, Duration::from_secs(30));
+        let endpoints: Vec<_> = config
+            .endpoints()
+            .map(|e| config.parse_endpoint(e))
+            .collect();
+        assert_eq!(
+            endpoints,
+            vec![
+                ("primary.example.com".to_string(), 8883),
+                ("2001:db8::1".to_string(), 8883),
+                ("backup.example.com".to_string(), 1883),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_egress_proxy() {
+        let config: BridgeConfig = toml::from_str(
+            r#"
+            name = "cloud"
+            address = "cloud.example.com:8883"
+
+            [egress_proxy]
+            type = "http"
+            address = "proxy.factory.local"
+            username = "edge"
+            password = "secret"
+            "#,
+        )
+        .unwrap();
+
+        let proxy = config.egress_proxy.unwrap();
+        assert_eq!(proxy.proxy_type, EgressProxyType::Http);
+        assert_eq!(
+            proxy.parse_address(),
+            ("proxy.factory.local".to_string(), 8080)
+        );
+        assert_eq!(proxy.username.as_deref(), Some("edge"));
+    }
+
+    #[test]
+    fn test_health_config() {
+        let config: BridgeConfig = toml::from_str(
+            r#"
+            name = "cloud"
+            address = "cloud.example.com:8883"
+
+            [health]
+            interval = "5s"
+            max_latency = "500ms"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.health.interval, Duration::from_secs(5));
+        assert_eq!(config.health.max_latency, Duration::from_millis(500));
+        // Unset fields keep their defaults
+        assert_eq!(config.health.timeout, Duration::from_secs(10));
+        assert_eq!(config.health.failure_threshold, 3);
+        assert_eq!(config.health.hold_down, Duration::from_secs(60));
+    }
+
     #[test]
     fn test_forward_direction() {
         let out_rule = ForwardRule {
@@ -347,6 +647,7 @@ mod tests {
             direction: ForwardDirection::Out,
             qos: 1,
             retain: true,
+            user_properties: vec![],
         };
         assert!(out_rule.is_outbound());
         assert!(!out_rule.is_inbound());
@@ -359,6 +660,30 @@ mod tests {
         assert!(both_rule.is_inbound());
     }
 
+    #[test]
+    fn test_proxy_protocol_config() {
+        let toml = r#"
+name = "upstream"
+address = "upstream:1883"
+
+[proxy_protocol]
+source_address = "203.0.113.7:1883"
+sni = "upstream.example.com"
+unique_id = "edge-1"
+"#;
+        let config: BridgeConfig = toml::from_str(toml).unwrap();
+        let proxy = config.proxy_protocol.unwrap();
+        assert_eq!(proxy.version, BridgeProxyVersion::V2);
+        assert_eq!(
+            proxy.source_address,
+            Some("203.0.113.7:1883".parse().unwrap())
+        );
+        assert_eq!(proxy.sni.as_deref(), Some("upstream.example.com"));
+        assert!(proxy.client_cert_cn.is_none());
+        assert!(BridgeConfig::default().proxy_protocol.is_none());
+        assert!(!config.forward_origin);
+    }
+
     #[test]
     fn test_protocol_defaults() {
         assert_eq!(BridgeProtocol::Mqtt.default_port(), 1883);
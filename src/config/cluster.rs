@@ -7,7 +7,7 @@ use std::time::Duration;
 
 use serde::Deserialize;
 
-use super::ProxyProtocolConfig;
+use super::{LinkCompressionConfig, ProxyProtocolConfig};
 
 /// Cluster configuration for gossip-based horizontal scaling
 #[derive(Debug, Clone, Deserialize)]
@@ -60,6 +60,76 @@ pub struct ClusterConfig {
     /// PROXY protocol configuration for peer listener
     #[serde(default)]
     pub proxy_protocol: ProxyProtocolConfig,
+
+    /// Node role: "member" (serving node) or "observer" (read-only replica)
+    /// Default: member
+    #[serde(default)]
+    pub role: ClusterRole,
+
+    /// Bind address for the observer's read-only query API
+    /// Only used when role = "observer"; requests need admin.token.
+    /// Default: 127.0.0.1:8081
+    #[serde(default = "default_observer_api_bind")]
+    pub observer_api_bind: SocketAddr,
+
+    /// Metrics federation: nodes report their metrics to the leader, which
+    /// serves the whole cluster's on `/cluster/metrics`
+    #[serde(default)]
+    pub metrics: ClusterMetricsConfig,
+
+    /// Compression of traffic to peers that accept it
+    #[serde(default)]
+    pub compression: LinkCompressionConfig,
+}
+
+/// Cluster metrics federation configuration (`[cluster.metrics]`)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ClusterMetricsConfig {
+    /// Whether nodes report their metrics to the leader
+    pub enabled: bool,
+    /// How often each node reports (default: 15s)
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+}
+
+impl Default for ClusterMetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: Duration::from_secs(15),
+        }
+    }
+}
+
+/// Role of a node within the cluster
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClusterRole {
+    /// Serving node: accepts client connections and forwards publishes
+    #[default]
+    Member,
+    /// Read-only replica: receives all publishes and retained state from
+    /// serving nodes but accepts no client connections
+    Observer,
+}
+
+impl ClusterRole {
+    /// Role name as advertised in gossip state
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClusterRole::Member => "member",
+            ClusterRole::Observer => "observer",
+        }
+    }
+
+    /// Parse a role advertised in gossip state (unknown roles are members)
+    pub fn from_gossip(value: &str) -> Self {
+        match value {
+            "observer" => ClusterRole::Observer,
+            _ => ClusterRole::Member,
+        }
+    }
 }
 
 fn default_gossip_addr() -> SocketAddr {
@@ -70,6 +140,10 @@ fn default_peer_addr() -> SocketAddr {
     "0.0.0.0:7947".parse().unwrap()
 }
 
+fn default_observer_api_bind() -> SocketAddr {
+    "127.0.0.1:8081".parse().unwrap()
+}
+
 fn default_gossip_interval() -> Duration {
     Duration::from_secs(1)
 }
@@ -96,6 +170,10 @@ impl Default for ClusterConfig {
             failure_timeout: Duration::from_secs(5),
             dead_node_grace_period: Duration::from_secs(30),
             proxy_protocol: ProxyProtocolConfig::default(),
+            role: ClusterRole::Member,
+            observer_api_bind: default_observer_api_bind(),
+            metrics: ClusterMetricsConfig::default(),
+            compression: LinkCompressionConfig::default(),
         }
     }
 }
@@ -110,6 +188,11 @@ impl ClusterConfig {
         })
     }
 
+    /// Whether this node joins the cluster as a read-only observer
+    pub fn is_observer(&self) -> bool {
+        self.role == ClusterRole::Observer
+    }
+
     /// Get the gossip advertise address (what peers use to reach us)
     /// Priority: explicit config > resolved hostname > bind address
     pub fn get_gossip_advertise_addr(&self) -> SocketAddr {
@@ -203,4 +286,16 @@ mod tests {
         assert_eq!(config.failure_timeout, Duration::from_secs(5));
         assert_eq!(config.dead_node_grace_period, Duration::from_secs(30));
     }
+
+    #[test]
+    fn test_role() {
+        let config = ClusterConfig::default();
+        assert_eq!(config.role, ClusterRole::Member);
+        assert!(!config.is_observer());
+
+        assert_eq!(ClusterRole::from_gossip("observer"), ClusterRole::Observer);
+        assert_eq!(ClusterRole::from_gossip("member"), ClusterRole::Member);
+        assert_eq!(ClusterRole::from_gossip("unknown"), ClusterRole::Member);
+        assert_eq!(ClusterRole::Observer.as_str(), "observer");
+    }
 }
@@ -0,0 +1,65 @@
+//! Link Compression Configuration
+//!
+//! Configuration for compressing traffic on cluster links and bridge
+//! connections, to cut bandwidth between sites.
+
+use serde::Deserialize;
+
+/// Compression codec for cluster links and bridges
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionCodec {
+    /// Zstandard: better ratio, more CPU
+    Zstd,
+    /// LZ4: faster, lower ratio
+    Lz4,
+}
+
+impl CompressionCodec {
+    /// Codec name as negotiated on the wire
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionCodec::Zstd => "zstd",
+            CompressionCodec::Lz4 => "lz4",
+        }
+    }
+
+    /// Parse a codec name from the wire (unknown codecs are None)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "zstd" => Some(CompressionCodec::Zstd),
+            "lz4" => Some(CompressionCodec::Lz4),
+            _ => None,
+        }
+    }
+}
+
+/// Link compression configuration (`[cluster.compression]`,
+/// `[bridge.compression]`)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct LinkCompressionConfig {
+    /// Codecs offered to the other end, most preferred first (empty = off)
+    pub codecs: Vec<CompressionCodec>,
+    /// Messages smaller than this many bytes are sent uncompressed
+    pub min_size: usize,
+    /// zstd compression level (1-22; lz4 has no levels)
+    pub level: i32,
+}
+
+impl LinkCompressionConfig {
+    /// Whether compression is offered on the link
+    pub fn enabled(&self) -> bool {
+        !self.codecs.is_empty()
+    }
+}
+
+impl Default for LinkCompressionConfig {
+    fn default() -> Self {
+        Self {
+            codecs: Vec::new(),
+            min_size: 512,
+            level: 3,
+        }
+    }
+}
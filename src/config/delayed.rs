@@ -0,0 +1,53 @@
+//! Delayed Publish Configuration
+//!
+//! Configuration for delayed publishes, which the broker acknowledges at
+//! once but only routes when their delay is up (retries with backoff,
+//! commands to run later).
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Delayed publish configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct DelayedConfig {
+    /// Hold messages published to "{topic}/{seconds}/{target topic}"
+    pub enabled: bool,
+    /// Topic prefix (default: "$delayed")
+    pub topic: String,
+    /// MQTT 5 user property whose value delays a message published to its
+    /// target topic directly, in seconds (default: none)
+    pub user_property: Option<String>,
+    /// Longest delay accepted (default: 1d)
+    #[serde(with = "humantime_serde")]
+    pub max_delay: Duration,
+    /// Most messages held at once (0 = unlimited)
+    pub max_messages: usize,
+}
+
+impl Default for DelayedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            topic: "$delayed".to_string(),
+            user_property: None,
+            max_delay: Duration::from_secs(24 * 60 * 60),
+            max_messages: 100_000,
+        }
+    }
+}
+
+impl DelayedConfig {
+    /// Split a topic under the prefix into the delay and the target topic
+    ///
+    /// Returns `Some(None)` if the topic is under the prefix but has no
+    /// valid delay or target topic.
+    pub fn split<'a>(&self, topic: &'a str) -> Option<Option<(Duration, &'a str)>> {
+        let rest = topic.strip_prefix(self.topic.as_str())?.strip_prefix('/')?;
+        Some(rest.split_once('/').and_then(|(seconds, target)| {
+            let seconds = seconds.parse().ok()?;
+            (!target.is_empty()).then_some((Duration::from_secs(seconds), target))
+        }))
+    }
+}
@@ -0,0 +1,136 @@
+//! Enrichment Configuration
+//!
+//! `[[enrich]]` entries republish the messages of a topic filter with
+//! fields added from a lookup table (a CSV or JSON file, or the output of
+//! a SQL query run through the database's command-line client), keyed by
+//! a topic level or a payload field.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use super::PayloadConfig;
+
+/// Encoding of a lookup table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableFormat {
+    /// A header row naming the columns, then one row per entry
+    Csv,
+    /// An array of objects, or an object of objects keyed by the key
+    Json,
+}
+
+/// What happens to a message whose key isn't in the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnrichMiss {
+    /// Republish it unchanged
+    #[default]
+    Forward,
+    /// Don't republish it
+    Drop,
+}
+
+/// Where a lookup table comes from
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LookupTableConfig {
+    /// Table file; reloaded when it changes
+    pub path: Option<PathBuf>,
+    /// Command printing the table, re-run every `ttl`, e.g.
+    /// `["sqlite3", "-json", "sites.db", "SELECT device_id, site FROM devices"]`
+    pub command: Vec<String>,
+    /// Table encoding; unset = from the file extension
+    pub format: Option<TableFormat>,
+    /// Column (or field) holding the key
+    pub key_column: String,
+    /// How long a loaded table is used before the file is checked for
+    /// changes or the command re-run
+    #[serde(with = "humantime_serde")]
+    pub ttl: Duration,
+    /// Time the command may take
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+impl Default for LookupTableConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            command: Vec::new(),
+            format: None,
+            key_column: "id".to_string(),
+            ttl: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl LookupTableConfig {
+    /// The table's encoding, if known
+    pub fn format(&self) -> Option<TableFormat> {
+        self.format
+            .or_else(|| match self.path.as_ref()?.extension()?.to_str()? {
+                "csv" => Some(TableFormat::Csv),
+                "json" => Some(TableFormat::Json),
+                _ => None,
+            })
+    }
+}
+
+/// One enrichment rule
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EnrichConfig {
+    /// Unique name (client ID "enrich:<name>" for ACLs)
+    pub name: String,
+    /// Enable this rule
+    pub enabled: bool,
+    /// Topic filter whose messages are enriched
+    pub filter: String,
+    /// Topic level (0-based) holding the key, e.g. 1 for the device in
+    /// "sensors/+/temperature"
+    pub key_level: Option<usize>,
+    /// Dot-separated payload field holding the key (instead of a topic
+    /// level)
+    pub key_field: Option<String>,
+    /// Payload encoding (JSON by default)
+    pub payload: PayloadConfig,
+    pub table: LookupTableConfig,
+    /// Dot-separated field the table row is added under; unset = the
+    /// row's columns are added to the payload (fields it already has win)
+    pub into: Option<String>,
+    /// Messages whose key isn't in the table
+    pub on_miss: EnrichMiss,
+    /// Output topic template; `{topic}` is the message's topic, `{key}` its
+    /// key, `{name}` the rule's name
+    pub output: String,
+    /// QoS of the enriched messages (0-2)
+    pub qos: u8,
+    /// Publish enriched messages as retained messages
+    pub retain: bool,
+    /// Username ACLs are checked for
+    pub username: Option<String>,
+}
+
+impl Default for EnrichConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            filter: String::new(),
+            key_level: None,
+            key_field: None,
+            payload: PayloadConfig::default(),
+            table: LookupTableConfig::default(),
+            into: None,
+            on_miss: EnrichMiss::Forward,
+            output: String::new(),
+            qos: 0,
+            retain: false,
+            username: None,
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Client-Visible Error Detail Configuration
+//!
+//! Controls how much error responses (CONNACK, PUBACK/PUBREC, SUBACK,
+//! DISCONNECT) reveal to clients. Public-facing listeners can hide which
+//! check failed, while internal listeners report everything.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::protocol::ReasonCode;
+
+/// How much detail error responses reveal to clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorDetail {
+    /// Specific reason codes plus human-readable reason strings (MQTT v5)
+    Full,
+    /// Specific reason codes, no reason strings
+    #[default]
+    Codes,
+    /// Errors are reported as "Unspecified error", except codes clients
+    /// need to react correctly (version, availability and flow control)
+    Generic,
+}
+
+impl ErrorDetail {
+    /// Reason code to send for an error under this policy
+    pub fn reason_code(self, code: ReasonCode) -> ReasonCode {
+        if self != ErrorDetail::Generic || !code.is_error() {
+            return code;
+        }
+        match code {
+            ReasonCode::UnsupportedProtocolVersion
+            | ReasonCode::ServerUnavailable
+            | ReasonCode::ServerBusy
+            | ReasonCode::ServerShuttingDown
+            | ReasonCode::KeepAliveTimeout
+            | ReasonCode::SessionTakenOver
+            | ReasonCode::ReceiveMaxExceeded
+            | ReasonCode::PacketTooLarge
+            | ReasonCode::MessageRateTooHigh
+            | ReasonCode::QuotaExceeded
+            | ReasonCode::UseAnotherServer
+            | ReasonCode::ServerMoved
+            | ReasonCode::ConnectionRateExceeded => code,
+            _ => ReasonCode::UnspecifiedError,
+        }
+    }
+
+    /// Whether reason strings are sent
+    pub fn reason_strings(self) -> bool {
+        self == ErrorDetail::Full
+    }
+}
+
+/// Parse a reason code given as hex ("0x87") or decimal ("135")
+fn parse_reason_code(s: &str) -> Option<ReasonCode> {
+    let s = s.trim();
+    let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16).ok()?,
+        None => s.parse().ok()?,
+    };
+    ReasonCode::from_u8(value)
+}
+
+/// Parse a `reason_map` table into reason codes
+///
+/// Only error codes (0x80 and above) can be remapped, and only to other
+/// error codes.
+pub fn parse_reason_map(
+    map: &HashMap<String, String>,
+) -> Result<HashMap<ReasonCode, ReasonCode>, String> {
+    map.iter()
+        .map(|(from, to)| {
+            let parse = |s: &str| {
+                parse_reason_code(s)
+                    .filter(|code| code.is_error())
+                    .ok_or_else(|| format!("reason_map: '{}' is not an error reason code", s))
+            };
+            Ok((parse(from)?, parse(to)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generic_reason_code() {
+        let generic = ErrorDetail::Generic;
+        assert_eq!(
+            generic.reason_code(ReasonCode::NotAuthorized),
+            ReasonCode::UnspecifiedError
+        );
+        assert_eq!(
+            generic.reason_code(ReasonCode::TopicFilterInvalid),
+            ReasonCode::UnspecifiedError
+        );
+        assert_eq!(
+            generic.reason_code(ReasonCode::ServerBusy),
+            ReasonCode::ServerBusy
+        );
+        assert_eq!(
+            generic.reason_code(ReasonCode::GrantedQoS1),
+            ReasonCode::GrantedQoS1
+        );
+        assert_eq!(
+            ErrorDetail::Codes.reason_code(ReasonCode::NotAuthorized),
+            ReasonCode::NotAuthorized
+        );
+    }
+
+    #[test]
+    fn test_parse_reason_map() {
+        let map = HashMap::from([
+            ("0x87".to_string(), "0x80".to_string()),
+            ("144".to_string(), "0x83".to_string()),
+        ]);
+        let parsed = parse_reason_map(&map).unwrap();
+        assert_eq!(
+            parsed[&ReasonCode::NotAuthorized],
+            ReasonCode::UnspecifiedError
+        );
+        assert_eq!(
+            parsed[&ReasonCode::TopicNameInvalid],
+            ReasonCode::ImplementationError
+        );
+
+        // Success codes and unknown values are rejected
+        let map = HashMap::from([("0x87".to_string(), "0x00".to_string())]);
+        assert!(parse_reason_map(&map).is_err());
+        let map = HashMap::from([("0xff".to_string(), "0x80".to_string())]);
+        assert!(parse_reason_map(&map).is_err());
+    }
+}
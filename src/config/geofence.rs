@@ -0,0 +1,61 @@
+//! Geofence Configuration
+//!
+//! `[[geofence]]` entries read positions (latitude/longitude JSON fields)
+//! published to a topic filter and publish an event each time an asset
+//! enters or leaves the fence's polygon.
+
+use serde::Deserialize;
+
+use super::PayloadConfig;
+
+/// One geofence
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GeofenceConfig {
+    /// Unique name (client ID "geofence:<name>" for ACLs)
+    pub name: String,
+    /// Enable this geofence
+    pub enabled: bool,
+    /// Topic filter positions are published to
+    pub filter: String,
+    /// Topic level (0-based) identifying the asset, e.g. 1 for the vehicle
+    /// in "fleet/+/position"; unset = one asset
+    pub key_level: Option<usize>,
+    /// Dot-separated JSON field holding the latitude (default: "lat")
+    pub lat_field: String,
+    /// Dot-separated JSON field holding the longitude (default: "lon")
+    pub lon_field: String,
+    /// Payload encoding (JSON by default)
+    pub payload: PayloadConfig,
+    /// Polygon vertices as `[latitude, longitude]` pairs (at least 3; the
+    /// polygon closes itself)
+    pub polygon: Vec<[f64; 2]>,
+    /// Event topic template; `{key}` is the asset, `{name}` the geofence's
+    /// name
+    pub output: String,
+    /// QoS of the published events (0-2)
+    pub qos: u8,
+    /// Publish events as retained messages (the last event per topic)
+    pub retain: bool,
+    /// Username ACLs are checked for
+    pub username: Option<String>,
+}
+
+impl Default for GeofenceConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            filter: String::new(),
+            key_level: None,
+            lat_field: "lat".to_string(),
+            lon_field: "lon".to_string(),
+            payload: PayloadConfig::default(),
+            polygon: Vec::new(),
+            output: String::new(),
+            qos: 1,
+            retain: false,
+            username: None,
+        }
+    }
+}
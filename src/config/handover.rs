@@ -0,0 +1,62 @@
+//! Session Handover Configuration
+//!
+//! Configuration for two-phase session handover, which lets a client hand
+//! its session to a successor (another instance of the same service) without
+//! the two racing to take it over.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Session handover configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HandoverConfig {
+    /// Handle the handover control topics and admin endpoint
+    pub enabled: bool,
+    /// Control topic prefix. Publishing to "{topic}/prepare" starts a
+    /// handover of the publisher's session and "{topic}/cancel" calls it off
+    /// (default: "$handover")
+    pub topic: String,
+    /// How long the successor has to connect with the handover token
+    /// (default: 60s)
+    #[serde(with = "humantime_serde")]
+    pub token_ttl: Duration,
+}
+
+impl Default for HandoverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            topic: "$handover".to_string(),
+            token_ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A handover control message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandoverControl {
+    Prepare,
+    Cancel,
+}
+
+impl HandoverConfig {
+    /// Get the control message if `topic` is under the control topic
+    ///
+    /// Returns `Some(None)` for an unknown command.
+    pub fn control(&self, topic: &str) -> Option<Option<HandoverControl>> {
+        let command = topic.strip_prefix(self.topic.as_str())?.strip_prefix('/')?;
+        Some(match command {
+            "prepare" => Some(HandoverControl::Prepare),
+            "cancel" => Some(HandoverControl::Cancel),
+            _ => None,
+        })
+    }
+
+    /// Topic the token is sent to when the prepare request names no
+    /// response topic
+    pub fn token_topic(&self) -> String {
+        format!("{}/token", self.topic)
+    }
+}
@@ -0,0 +1,30 @@
+//! Health Probe Configuration
+//!
+//! Configuration for the `/healthz` and `/readyz` probes used by
+//! orchestrators. Both are served on the metrics listener, and optionally
+//! on a listener of their own.
+
+use std::net::SocketAddr;
+
+use serde::Deserialize;
+
+/// Health probe configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Dedicated listener for the probes, e.g. when metrics are disabled
+    /// (default: none)
+    pub bind: Option<SocketAddr>,
+    /// Cluster peers that must be connected for the broker to be ready;
+    /// fewer are enough while fewer are known (0 = don't check)
+    pub min_cluster_peers: usize,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            bind: None,
+            min_cluster_peers: 1,
+        }
+    }
+}
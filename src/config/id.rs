@@ -0,0 +1,24 @@
+//! ID generation configuration
+
+use serde::Deserialize;
+
+/// ID generation strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdGeneratorKind {
+    /// Time-ordered UUIDv7 (no coordination required)
+    #[default]
+    Uuidv7,
+    /// Snowflake-style 64-bit IDs with a per-node ID
+    Snowflake,
+}
+
+/// ID generation configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct IdConfig {
+    /// Generator strategy
+    pub generator: IdGeneratorKind,
+    /// Snowflake node ID (0-1023). Derived from the cluster node ID if not set.
+    pub node_id: Option<u16>,
+}
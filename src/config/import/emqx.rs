@@ -0,0 +1,809 @@
+//! EMQX config import
+//!
+//! Parses the subset of HOCON used by `emqx.conf` into flattened
+//! `a.b.c = value` entries, then maps known keys onto VibeMQ options.
+//! File-based authorization sources (`acl.conf`) are imported as ACL roles.
+
+use std::path::Path;
+
+use regex::Regex;
+use toml::{Table, Value};
+
+use super::{
+    parse_bool, parse_size, resolve_path, socket_addr, ImportError, ImportReport, ImportSource,
+    RoleDraft,
+};
+
+/// A flattened HOCON value
+#[derive(Debug, Clone, PartialEq)]
+enum Flat {
+    Scalar(String),
+    List(Vec<String>),
+}
+
+impl Flat {
+    fn as_str(&self) -> &str {
+        match self {
+            Flat::Scalar(s) => s,
+            Flat::List(_) => "",
+        }
+    }
+}
+
+/// A flattened entry with its source line
+#[derive(Debug)]
+struct Entry {
+    key: String,
+    value: Flat,
+    line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Assign,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Separator,
+}
+
+/// Tokenize HOCON, keeping line numbers
+fn tokenize(content: &str) -> Vec<(Token, usize)> {
+    let mut tokens = Vec::new();
+    let mut chars = content.chars().peekable();
+    let mut line = 1;
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' => {
+                chars.next();
+                tokens.push((Token::Separator, line));
+                line += 1;
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '/' => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    while chars.peek().is_some_and(|&c| c != '\n') {
+                        chars.next();
+                    }
+                } else {
+                    tokens.push((Token::Word("/".to_string()), line));
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            if let Some(escaped) = chars.next() {
+                                s.push(escaped);
+                            }
+                        }
+                        '\n' => {
+                            line += 1;
+                            s.push(c);
+                        }
+                        _ => s.push(c),
+                    }
+                }
+                tokens.push((Token::Quoted(s), line));
+            }
+            '=' => {
+                chars.next();
+                tokens.push((Token::Assign, line));
+            }
+            '{' => {
+                chars.next();
+                tokens.push((Token::LBrace, line));
+            }
+            '}' => {
+                chars.next();
+                tokens.push((Token::RBrace, line));
+            }
+            '[' => {
+                chars.next();
+                tokens.push((Token::LBracket, line));
+            }
+            ']' => {
+                chars.next();
+                tokens.push((Token::RBracket, line));
+            }
+            ',' => {
+                chars.next();
+                tokens.push((Token::Separator, line));
+            }
+            _ => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{}[]=,\"#".contains(c) {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                // `key: value` form
+                if let Some(key) = s.strip_suffix(':') {
+                    tokens.push((Token::Word(key.to_string()), line));
+                    tokens.push((Token::Assign, line));
+                } else {
+                    tokens.push((Token::Word(s), line));
+                }
+            }
+        }
+    }
+    tokens
+}
+
+/// Recursive-descent HOCON flattener
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    entries: Vec<Entry>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map(|(_, l)| *l)
+            .unwrap_or(0)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(t, _)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn skip_separators(&mut self) {
+        while self.peek() == Some(&Token::Separator) {
+            self.pos += 1;
+        }
+    }
+
+    fn join(prefix: &str, key: &str) -> String {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    }
+
+    /// Parse object members until `}` or end of input
+    fn object(&mut self, prefix: &str) {
+        loop {
+            self.skip_separators();
+            let key = match self.peek() {
+                None | Some(Token::RBrace) => return,
+                Some(Token::Word(_)) | Some(Token::Quoted(_)) => match self.next() {
+                    Some(Token::Word(k)) | Some(Token::Quoted(k)) => k,
+                    _ => unreachable!(),
+                },
+                _ => {
+                    // Unexpected token; skip it
+                    self.pos += 1;
+                    continue;
+                }
+            };
+            let path = Self::join(prefix, &key);
+
+            if self.peek() == Some(&Token::Assign) {
+                self.pos += 1;
+            }
+            self.value(&path);
+        }
+    }
+
+    fn value(&mut self, path: &str) {
+        let line = self.line();
+        match self.peek() {
+            Some(Token::LBrace) => {
+                self.pos += 1;
+                self.object(path);
+                if self.peek() == Some(&Token::RBrace) {
+                    self.pos += 1;
+                }
+            }
+            Some(Token::LBracket) => {
+                self.pos += 1;
+                let mut scalars = Vec::new();
+                let mut index = 0;
+                loop {
+                    self.skip_separators();
+                    match self.peek() {
+                        None => break,
+                        Some(Token::RBracket) => {
+                            self.pos += 1;
+                            break;
+                        }
+                        Some(Token::LBrace) => {
+                            self.pos += 1;
+                            self.object(&format!("{}.{}", path, index));
+                            if self.peek() == Some(&Token::RBrace) {
+                                self.pos += 1;
+                            }
+                            index += 1;
+                        }
+                        _ => match self.next() {
+                            Some(Token::Word(s)) | Some(Token::Quoted(s)) => scalars.push(s),
+                            _ => {}
+                        },
+                    }
+                }
+                if index == 0 {
+                    self.entries.push(Entry {
+                        key: path.to_string(),
+                        value: Flat::List(scalars),
+                        line,
+                    });
+                }
+            }
+            _ => {
+                // Scalar: concatenate words up to the end of the line
+                let mut parts = Vec::new();
+                while let Some(Token::Word(_)) | Some(Token::Quoted(_)) = self.peek() {
+                    if let Some(Token::Word(s)) | Some(Token::Quoted(s)) = self.next() {
+                        parts.push(s);
+                    }
+                }
+                self.entries.push(Entry {
+                    key: path.to_string(),
+                    value: Flat::Scalar(parts.join(" ")),
+                    line,
+                });
+            }
+        }
+    }
+}
+
+/// Flatten HOCON content into dotted keys
+fn flatten(content: &str) -> Vec<Entry> {
+    let mut parser = Parser {
+        tokens: tokenize(content),
+        pos: 0,
+        entries: Vec::new(),
+    };
+    while parser.pos < parser.tokens.len() {
+        parser.object("");
+        // Stray closing brace at top level
+        parser.pos += 1;
+    }
+    parser.entries
+}
+
+/// Parse an EMQX duration ("30s", "1m", "500ms") into seconds
+fn duration_secs(value: &str) -> Option<i64> {
+    humantime_serde::re::humantime::parse_duration(value.trim())
+        .ok()
+        .map(|d| d.as_secs() as i64)
+}
+
+/// A listener collected from `listeners.<type>.<name>.*`
+#[derive(Debug, Default)]
+struct Listener {
+    kind: String,
+    name: String,
+    line: usize,
+    enabled: bool,
+    bind: Option<String>,
+    certfile: Option<String>,
+    keyfile: Option<String>,
+    cacertfile: Option<String>,
+    verify_peer: bool,
+    fail_if_no_peer_cert: bool,
+    proxy_protocol: bool,
+    mqtt_path: Option<String>,
+}
+
+/// A bridge collected from `bridges.mqtt.<name>.*`
+#[derive(Debug, Default)]
+struct Bridge {
+    name: String,
+    table: Table,
+    egress: Table,
+    ingress: Table,
+}
+
+/// Import an EMQX configuration
+///
+/// `path` is used for resolving referenced files and in report entries.
+/// Fails if two options translate to conflicting values.
+pub fn import_emqx(content: &str, path: &Path) -> Result<ImportReport, ImportError> {
+    let mut report = ImportReport::new(ImportSource::Emqx);
+    let mut listeners: Vec<Listener> = Vec::new();
+    let mut bridges: Vec<Bridge> = Vec::new();
+    let mut acl_files: Vec<String> = Vec::new();
+    let mut no_match_allow = None;
+
+    for entry in flatten(content) {
+        let line = Some(entry.line);
+        let key = entry.key.as_str();
+        let value = entry.value.as_str();
+        let parts: Vec<&str> = key.split('.').collect();
+
+        match parts.as_slice() {
+            ["listeners", kind, name, rest @ ..] if !rest.is_empty() => {
+                let listener = match listeners
+                    .iter_mut()
+                    .position(|l| l.kind == *kind && l.name == *name)
+                {
+                    Some(i) => &mut listeners[i],
+                    None => {
+                        listeners.push(Listener {
+                            kind: kind.to_string(),
+                            name: name.to_string(),
+                            line: entry.line,
+                            enabled: true,
+                            ..Default::default()
+                        });
+                        listeners.last_mut().unwrap()
+                    }
+                };
+                match rest {
+                    ["bind"] => listener.bind = Some(value.to_string()),
+                    ["enable"] | ["enabled"] => {
+                        listener.enabled = parse_bool(value).unwrap_or(true)
+                    }
+                    ["max_connections"] => {
+                        let n = if value == "infinity" {
+                            0
+                        } else {
+                            value.parse::<i64>().unwrap_or(0)
+                        };
+                        report.set("limits.max_connections", n);
+                    }
+                    ["proxy_protocol"] => listener.proxy_protocol = parse_bool(value) == Some(true),
+                    ["ssl_options", "certfile"] => listener.certfile = Some(value.to_string()),
+                    ["ssl_options", "keyfile"] => listener.keyfile = Some(value.to_string()),
+                    ["ssl_options", "cacertfile"] => listener.cacertfile = Some(value.to_string()),
+                    ["ssl_options", "verify"] => listener.verify_peer = value == "verify_peer",
+                    ["ssl_options", "fail_if_no_peer_cert"] => {
+                        listener.fail_if_no_peer_cert = parse_bool(value) == Some(true)
+                    }
+                    ["websocket", "mqtt_path"] => listener.mqtt_path = Some(value.to_string()),
+                    ["acceptors"] | ["zone"] => {}
+                    _ => report.note(path, line, key, "listener option not supported by VibeMQ"),
+                }
+            }
+            ["mqtt", option] => {
+                let target = match *option {
+                    "max_packet_size" => match parse_size(value) {
+                        Some(n) => {
+                            report.set("limits.max_packet_size", n);
+                            continue;
+                        }
+                        None => None,
+                    },
+                    "max_qos_allowed" => Some("mqtt.max_qos"),
+                    "max_inflight" => Some("limits.max_inflight"),
+                    "max_mqueue_len" => Some("limits.max_queued_messages"),
+                    "max_awaiting_rel" => Some("limits.max_awaiting_rel"),
+                    "max_topic_alias" => Some("session.max_topic_aliases"),
+                    "max_topic_levels" => Some("limits.max_topic_levels"),
+                    "retain_available" | "wildcard_subscription" | "shared_subscription" => {
+                        let target = match *option {
+                            "retain_available" => "mqtt.retain_available",
+                            "wildcard_subscription" => "mqtt.wildcard_subscriptions",
+                            _ => "mqtt.shared_subscriptions",
+                        };
+                        match parse_bool(value) {
+                            Some(b) => report.set(target, b),
+                            None => report.note(path, line, key, "invalid boolean"),
+                        }
+                        continue;
+                    }
+                    "retry_interval" => {
+                        report.set("limits.retry_interval", value);
+                        continue;
+                    }
+                    _ => None,
+                };
+                match target.map(|t| (t, value.parse::<i64>())) {
+                    Some((target, Ok(n))) => report.set(target, n),
+                    Some((_, Err(_))) => report.note(path, line, key, "invalid integer"),
+                    None => report.note(path, line, key, "option not supported by VibeMQ"),
+                }
+            }
+            ["authorization", "no_match"] => no_match_allow = Some(value == "allow"),
+            ["authorization", "sources", _, "type"] => {
+                if value != "file" {
+                    report.note(
+                        path,
+                        line,
+                        key,
+                        format!("authorization source '{}' is not supported; only file ACLs are imported", value),
+                    );
+                }
+            }
+            ["authorization", "sources", _, "path"] => acl_files.push(value.to_string()),
+            ["authorization", "sources", _, "rules"] => report.note(
+                path,
+                line,
+                key,
+                "inline ACL rules are not imported; move them to an acl.conf file",
+            ),
+            ["authorization", "sources", _, "enable"] => {}
+            ["authentication", ..] => {
+                if key.ends_with(".backend") || key.ends_with(".mechanism") {
+                    report.note(
+                        path,
+                        line,
+                        key,
+                        format!("authenticator '{}' is not imported; users must be configured under [auth]", value),
+                    );
+                }
+            }
+            ["bridges", "mqtt", name, rest @ ..] if !rest.is_empty() => {
+                let bridge = match bridges.iter().position(|b| b.name == *name) {
+                    Some(i) => &mut bridges[i],
+                    None => {
+                        bridges.push(Bridge {
+                            name: name.to_string(),
+                            ..Default::default()
+                        });
+                        bridges.last_mut().unwrap()
+                    }
+                };
+                match rest {
+                    ["server"] => {
+                        bridge.table.insert("address".into(), value.into());
+                    }
+                    ["username"] => {
+                        bridge.table.insert("username".into(), value.into());
+                    }
+                    ["password"] => {
+                        bridge.table.insert("password".into(), value.into());
+                    }
+                    ["clientid_prefix"] => {
+                        bridge.table.insert("client_id".into(), value.into());
+                    }
+                    ["clean_start"] => {
+                        if let Some(b) = parse_bool(value) {
+                            bridge.table.insert("clean_start".into(), b.into());
+                        }
+                    }
+                    ["keepalive"] => {
+                        if let Some(secs) = duration_secs(value) {
+                            bridge.table.insert("keepalive".into(), secs.into());
+                        }
+                    }
+                    ["enable"] => {
+                        if let Some(b) = parse_bool(value) {
+                            bridge.table.insert("enabled".into(), b.into());
+                        }
+                    }
+                    ["ssl", "enable"] => {
+                        if parse_bool(value) == Some(true) {
+                            bridge.table.insert("protocol".into(), "mqtts".into());
+                        }
+                    }
+                    ["ssl", "cacertfile"] => set_bridge_tls(bridge, "ca_cert", value),
+                    ["ssl", "certfile"] => set_bridge_tls(bridge, "client_cert", value),
+                    ["ssl", "keyfile"] => set_bridge_tls(bridge, "client_key", value),
+                    ["egress", "local", "topic"] => {
+                        bridge.egress.insert("local_topic".into(), value.into());
+                    }
+                    ["egress", "remote", "topic"] => {
+                        bridge.egress.insert("remote_topic".into(), value.into());
+                    }
+                    ["egress", "remote", "qos"] => insert_qos(&mut bridge.egress, value),
+                    ["ingress", "remote", "topic"] => {
+                        bridge.ingress.insert("remote_topic".into(), value.into());
+                    }
+                    ["ingress", "remote", "qos"] => insert_qos(&mut bridge.ingress, value),
+                    ["ingress", "local", "topic"] => {
+                        bridge.ingress.insert("local_topic".into(), value.into());
+                    }
+                    _ => report.note(path, line, key, "bridge option not supported by VibeMQ"),
+                }
+            }
+            ["log", _, "level"] => report.set("log.level", value),
+            ["node", "data_dir"] => report.set("persistence.path", value),
+            ["include"] => report.note(path, line, key, "include directives are not followed"),
+            _ => report.note(path, line, key, "option not supported by VibeMQ"),
+        }
+    }
+
+    apply_listeners(listeners, path, &mut report);
+    apply_bridges(bridges, path, &mut report);
+
+    if !acl_files.is_empty() || no_match_allow.is_some() {
+        import_acl(
+            &acl_files,
+            no_match_allow.unwrap_or(true),
+            path,
+            &mut report,
+        );
+    }
+
+    report.finish()
+}
+
+fn set_bridge_tls(bridge: &mut Bridge, key: &str, value: &str) {
+    let tls = bridge
+        .table
+        .entry("tls")
+        .or_insert_with(|| Value::Table(Table::new()));
+    if let Some(tls) = tls.as_table_mut() {
+        tls.insert(key.into(), value.into());
+    }
+}
+
+fn insert_qos(table: &mut Table, value: &str) {
+    if let Ok(qos) = value.parse::<i64>() {
+        table.insert("qos".into(), qos.into());
+    }
+}
+
+/// Map listeners onto VibeMQ's TCP, TLS and WebSocket binds
+fn apply_listeners(listeners: Vec<Listener>, path: &Path, report: &mut ImportReport) {
+    let mut seen: Vec<&'static str> = Vec::new();
+
+    for listener in listeners.into_iter().filter(|l| l.enabled) {
+        let line = Some(listener.line);
+        let name = format!("listeners.{}.{}", listener.kind, listener.name);
+        let (target, proxy_key) = match listener.kind.as_str() {
+            "tcp" => ("server.bind", "server.proxy_protocol.enabled"),
+            "ssl" => ("server.tls_bind", "server.tls_proxy_protocol.enabled"),
+            "ws" => ("server.ws_bind", "server.ws_proxy_protocol.enabled"),
+            other => {
+                report.note(
+                    path,
+                    line,
+                    &name,
+                    format!("'{}' listeners are not supported", other),
+                );
+                continue;
+            }
+        };
+        if seen.contains(&target) {
+            report.note(
+                path,
+                line,
+                &name,
+                "only one listener of each kind is supported; skipped",
+            );
+            continue;
+        }
+
+        let bind = listener.bind.as_deref().unwrap_or("0.0.0.0:1883");
+        let addr = match bind.parse::<u16>() {
+            Ok(port) => socket_addr(None, port),
+            Err(_) => bind
+                .rsplit_once(':')
+                .and_then(|(host, port)| socket_addr(Some(host), port.parse().ok()?)),
+        };
+        let Some(addr) = addr else {
+            report.note(
+                path,
+                line,
+                &name,
+                format!("cannot parse bind address '{}'", bind),
+            );
+            continue;
+        };
+        seen.push(target);
+        report.set(target, addr);
+
+        if listener.proxy_protocol {
+            report.set(proxy_key, true);
+        }
+        if listener.kind == "ws" {
+            if let Some(mqtt_path) = listener.mqtt_path {
+                report.set("server.ws_path", mqtt_path);
+            }
+        }
+        if listener.kind == "ssl" {
+            report.set("server.tls.cert", listener.certfile.unwrap_or_default());
+            report.set("server.tls.key", listener.keyfile.unwrap_or_default());
+            if let Some(ca) = listener.cacertfile {
+                report.set("server.tls.ca_cert", ca);
+            }
+            if listener.verify_peer && listener.fail_if_no_peer_cert {
+                report.set("server.tls.require_client_cert", true);
+            }
+        }
+    }
+}
+
+fn apply_bridges(bridges: Vec<Bridge>, path: &Path, report: &mut ImportReport) {
+    for bridge in bridges {
+        let mut table = bridge.table;
+        if !table.contains_key("address") {
+            report.note(
+                path,
+                None,
+                &format!("bridges.mqtt.{}", bridge.name),
+                "bridge has no server address; skipped",
+            );
+            continue;
+        }
+        table.insert("name".into(), bridge.name.into());
+
+        let mut forwards = Vec::new();
+        for (mut rule, direction) in [(bridge.egress, "out"), (bridge.ingress, "in")] {
+            if rule.is_empty() {
+                continue;
+            }
+            // EMQX uses "${topic}" to mean "same topic as the source"
+            let local = rule
+                .get("local_topic")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            let remote = rule
+                .get("remote_topic")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            let (local, remote) = match (local, remote) {
+                (Some(l), Some(r)) if r.contains("${topic}") => (l.clone(), l),
+                (Some(l), Some(r)) if l.contains("${topic}") => (r.clone(), r),
+                (Some(l), Some(r)) => (l, r),
+                (Some(t), None) | (None, Some(t)) => (t.clone(), t),
+                (None, None) => continue,
+            };
+            rule.insert("local_topic".into(), local.into());
+            rule.insert("remote_topic".into(), remote.into());
+            rule.insert("direction".into(), direction.into());
+            forwards.push(Value::Table(rule));
+        }
+        table.insert("forwards".into(), Value::Array(forwards));
+        report.push("bridge", table);
+    }
+}
+
+/// Import EMQX file-based ACL rules (`acl.conf`)
+fn import_acl(
+    files: &[String],
+    no_match_allow: bool,
+    config_path: &Path,
+    report: &mut ImportReport,
+) {
+    let rule_re = Regex::new(
+        r#"^\{\s*(allow|deny)\s*,\s*(all|\{\s*(\w+)\s*,\s*"([^"]*)"\s*\}|.+?)\s*,\s*(publish|subscribe|all)\s*,\s*\[(.*)\]\s*\}$"#,
+    )
+    .unwrap();
+    let catch_all_re = Regex::new(r"^\{\s*(allow|deny)\s*,\s*all\s*\}$").unwrap();
+    let topic_re = Regex::new(r#"\{\s*eq\s*,\s*"([^"]*)"\s*\}|"([^"]*)""#).unwrap();
+
+    report.set("acl.enabled", true);
+    let mut default = RoleDraft::default();
+    let mut default_all = no_match_allow;
+    let mut roles: Vec<(String, RoleDraft)> = Vec::new();
+
+    for file in files {
+        let file = resolve_path(config_path, file);
+        let content = match std::fs::read_to_string(&file) {
+            Ok(c) => c,
+            Err(e) => {
+                report.note(&file, None, "acl_file", format!("cannot read: {}", e));
+                continue;
+            }
+        };
+
+        // Statements are Erlang terms terminated by "." and may span lines
+        let mut statement = String::new();
+        let mut start_line = 1;
+        for (idx, raw) in content.lines().enumerate() {
+            let line = strip_erlang_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if statement.is_empty() {
+                start_line = idx + 1;
+            }
+            statement.push_str(line);
+            statement.push(' ');
+            if !line.ends_with('.') {
+                continue;
+            }
+
+            let term = statement.trim().trim_end_matches('.').trim().to_string();
+            statement.clear();
+            let line_no = Some(start_line);
+
+            if let Some(caps) = catch_all_re.captures(&term) {
+                default_all = &caps[1] == "allow";
+                continue;
+            }
+            let Some(caps) = rule_re.captures(&term) else {
+                report.note(&file, line_no, "acl", "unrecognized ACL rule");
+                continue;
+            };
+            if &caps[1] == "deny" {
+                report.note(
+                    &file,
+                    line_no,
+                    "acl",
+                    "deny rules are not supported (ACLs are allow-only)",
+                );
+                continue;
+            }
+            let (publish, subscribe) = match &caps[5] {
+                "publish" => (true, false),
+                "subscribe" => (false, true),
+                _ => (true, true),
+            };
+
+            let target = if &caps[2] == "all" {
+                &mut default
+            } else if matches!(caps.get(3).map(|m| m.as_str()), Some("user" | "username")) {
+                let name = caps[4].to_string();
+                match roles.iter().position(|(n, _)| *n == name) {
+                    Some(i) => &mut roles[i].1,
+                    None => {
+                        roles.push((name, RoleDraft::default()));
+                        &mut roles.last_mut().unwrap().1
+                    }
+                }
+            } else {
+                report.note(
+                    &file,
+                    line_no,
+                    "acl",
+                    format!(
+                        "rule subject '{}' is not supported (only all/username)",
+                        &caps[2]
+                    ),
+                );
+                continue;
+            };
+
+            for topic in topic_re.captures_iter(&caps[6]) {
+                let topic = topic.get(1).or_else(|| topic.get(2)).unwrap().as_str();
+                // Allow rules for "#" from a catch-all are implied by no_match = allow
+                target.add(topic, publish, subscribe);
+            }
+        }
+    }
+
+    if default_all {
+        default.add("#", true, true);
+    }
+    report.set("acl.default", Value::Table(default.into_table(None)));
+
+    if !roles.is_empty() {
+        report.note(
+            config_path,
+            None,
+            "authorization",
+            format!(
+                "{} per-user ACL roles imported; assign them to users under [auth] via `role`",
+                roles.len()
+            ),
+        );
+    }
+    for (name, role) in roles {
+        report.push("acl.roles", role.into_table(Some(&name)));
+    }
+}
+
+/// Strip a `%` comment, ignoring `%` inside strings (e.g. `%c` placeholders)
+fn strip_erlang_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '%' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
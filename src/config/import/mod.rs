@@ -0,0 +1,325 @@
+//! Config Import
+//!
+//! Translates configuration from other brokers into VibeMQ TOML, so users
+//! migrating from Mosquitto or EMQX don't have to rewrite everything by hand.
+//!
+//! Supported sources:
+//! - **Mosquitto**: `mosquitto.conf`, plus referenced `password_file` and `acl_file`
+//! - **EMQX**: `emqx.conf` (HOCON), plus a referenced file-based `acl.conf`
+//!
+//! Every option that cannot be translated is recorded in the [`ImportReport`]
+//! instead of being silently dropped.
+
+mod emqx;
+mod mosquitto;
+
+#[cfg(test)]
+mod tests;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+pub use emqx::import_emqx;
+pub use mosquitto::import_mosquitto;
+
+/// Source broker format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportSource {
+    /// Eclipse Mosquitto (`mosquitto.conf`)
+    Mosquitto,
+    /// EMQX 5.x (`emqx.conf`, HOCON)
+    Emqx,
+}
+
+impl ImportSource {
+    /// Guess the source format from a file name
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.contains("mosquitto") {
+            Some(ImportSource::Mosquitto)
+        } else if name.contains("emqx") || name.ends_with(".hocon") {
+            Some(ImportSource::Emqx)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ImportSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportSource::Mosquitto => write!(f, "mosquitto"),
+            ImportSource::Emqx => write!(f, "emqx"),
+        }
+    }
+}
+
+/// An option that could not be translated (or was translated lossily)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportNote {
+    /// Source file the option came from
+    pub file: PathBuf,
+    /// Line number (1-based), if known
+    pub line: Option<usize>,
+    /// Option name as written in the source config
+    pub option: String,
+    /// Why it was not imported
+    pub message: String,
+}
+
+impl fmt::Display for ImportNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}:{}: {}: {}",
+                self.file.display(),
+                line,
+                self.option,
+                self.message
+            ),
+            None => write!(
+                f,
+                "{}: {}: {}",
+                self.file.display(),
+                self.option,
+                self.message
+            ),
+        }
+    }
+}
+
+/// Result of an import
+#[derive(Debug, Clone)]
+pub struct ImportReport {
+    /// Source format
+    pub source: ImportSource,
+    /// Translated configuration
+    pub config: Table,
+    /// Options that were not (fully) imported
+    pub notes: Vec<ImportNote>,
+    /// First path set both as a value and as a table (see `finish`)
+    collision: Option<String>,
+}
+
+impl ImportReport {
+    fn new(source: ImportSource) -> Self {
+        Self {
+            source,
+            config: Table::new(),
+            notes: Vec::new(),
+            collision: None,
+        }
+    }
+
+    /// The report, unless two options collided on a config path
+    fn finish(self) -> Result<Self, ImportError> {
+        match self.collision {
+            Some(path) => Err(ImportError::Collision(path)),
+            None => Ok(self),
+        }
+    }
+
+    /// Render the translated configuration as TOML
+    pub fn to_toml(&self) -> String {
+        let mut out = format!(
+            "# Imported from {} configuration by `vibemq config import`\n\n",
+            self.source
+        );
+        out.push_str(&toml::to_string(&self.config).unwrap_or_default());
+        out
+    }
+
+    /// Set a value at a dotted path (e.g. "server.bind"), creating tables as needed
+    ///
+    /// A path through a value that isn't a table is recorded as a collision.
+    fn set(&mut self, path: &str, value: impl Into<Value>) {
+        let mut table = &mut self.config;
+        let mut parts = path.split('.').peekable();
+        while let Some(part) = parts.next() {
+            if parts.peek().is_none() {
+                table.insert(part.to_string(), value.into());
+                return;
+            }
+            match table
+                .entry(part.to_string())
+                .or_insert_with(|| Value::Table(Table::new()))
+            {
+                Value::Table(next) => table = next,
+                _ => {
+                    self.collision.get_or_insert_with(|| path.to_string());
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Append a table to an array of tables (e.g. "auth.users")
+    ///
+    /// A path through a value that isn't a table, or to one that isn't an
+    /// array, is recorded as a collision.
+    fn push(&mut self, path: &str, entry: Table) {
+        let (parent, key) = match path.rsplit_once('.') {
+            Some((parent, key)) => (Some(parent), key),
+            None => (None, path),
+        };
+        let mut table = &mut self.config;
+        for part in parent.into_iter().flat_map(|parent| parent.split('.')) {
+            match table
+                .entry(part.to_string())
+                .or_insert_with(|| Value::Table(Table::new()))
+            {
+                Value::Table(next) => table = next,
+                _ => {
+                    self.collision.get_or_insert_with(|| path.to_string());
+                    return;
+                }
+            }
+        }
+        match table
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Array(Vec::new()))
+        {
+            Value::Array(items) => items.push(Value::Table(entry)),
+            _ => {
+                self.collision.get_or_insert_with(|| path.to_string());
+            }
+        }
+    }
+
+    fn note(&mut self, file: &Path, line: Option<usize>, option: &str, message: impl Into<String>) {
+        self.notes.push(ImportNote {
+            file: file.to_path_buf(),
+            line,
+            option: option.to_string(),
+            message: message.into(),
+        });
+    }
+}
+
+/// Error type for config imports
+#[derive(Debug)]
+pub enum ImportError {
+    /// Failed to read the main configuration file
+    Io(PathBuf, std::io::Error),
+    /// Source format could not be determined
+    UnknownSource(PathBuf),
+    /// Options translated to both a value and a table at this path
+    Collision(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ImportError::UnknownSource(path) => write!(
+                f,
+                "cannot detect config format of {} (use --format)",
+                path.display()
+            ),
+            ImportError::Collision(path) => {
+                write!(f, "options translate to conflicting values for '{}'", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Import a configuration file
+///
+/// Referenced files (password files, ACL files) are resolved relative to the
+/// directory of `path`.
+pub fn import_file(path: &Path, source: Option<ImportSource>) -> Result<ImportReport, ImportError> {
+    let source = source
+        .or_else(|| ImportSource::detect(path))
+        .ok_or_else(|| ImportError::UnknownSource(path.to_path_buf()))?;
+    let content =
+        std::fs::read_to_string(path).map_err(|e| ImportError::Io(path.to_path_buf(), e))?;
+
+    match source {
+        ImportSource::Mosquitto => import_mosquitto(&content, path),
+        ImportSource::Emqx => import_emqx(&content, path),
+    }
+}
+
+/// Resolve a path referenced from a config file
+fn resolve_path(config_path: &Path, referenced: &str) -> PathBuf {
+    let referenced = Path::new(referenced);
+    if referenced.is_absolute() {
+        return referenced.to_path_buf();
+    }
+    config_path
+        .parent()
+        .map(|dir| dir.join(referenced))
+        .unwrap_or_else(|| referenced.to_path_buf())
+}
+
+/// Parse a boolean option value
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "on" | "yes" | "1" => Some(true),
+        "false" | "off" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a byte size like "1MB", "256KB" or "1048576"
+fn parse_size(value: &str) -> Option<i64> {
+    let value = value.trim().to_uppercase();
+    let (digits, multiplier) = if let Some(n) = value.strip_suffix("GB") {
+        (n, 1024 * 1024 * 1024)
+    } else if let Some(n) = value.strip_suffix("MB") {
+        (n, 1024 * 1024)
+    } else if let Some(n) = value.strip_suffix("KB") {
+        (n, 1024)
+    } else if let Some(n) = value.strip_suffix('B') {
+        (n, 1)
+    } else {
+        (value.as_str(), 1)
+    };
+    digits.trim().parse::<i64>().ok().map(|n| n * multiplier)
+}
+
+/// Build a socket address string from a host and port
+fn socket_addr(host: Option<&str>, port: u16) -> Option<String> {
+    let host = host
+        .unwrap_or("0.0.0.0")
+        .trim_matches(|c| c == '[' || c == ']');
+    let ip: std::net::IpAddr = host.parse().ok()?;
+    Some(std::net::SocketAddr::new(ip, port).to_string())
+}
+
+/// Role entry accumulated while translating ACLs
+#[derive(Debug, Default)]
+struct RoleDraft {
+    publish: Vec<String>,
+    subscribe: Vec<String>,
+}
+
+impl RoleDraft {
+    fn add(&mut self, topic: &str, publish: bool, subscribe: bool) {
+        if publish && !self.publish.iter().any(|t| t == topic) {
+            self.publish.push(topic.to_string());
+        }
+        if subscribe && !self.subscribe.iter().any(|t| t == topic) {
+            self.subscribe.push(topic.to_string());
+        }
+    }
+
+    fn into_table(self, name: Option<&str>) -> Table {
+        let mut table = Table::new();
+        if let Some(name) = name {
+            table.insert("name".into(), name.into());
+        }
+        table.insert("publish".into(), string_array(self.publish));
+        table.insert("subscribe".into(), string_array(self.subscribe));
+        table
+    }
+}
+
+fn string_array(items: Vec<String>) -> Value {
+    Value::Array(items.into_iter().map(Value::String).collect())
+}
@@ -0,0 +1,594 @@
+//! Mosquitto config import
+//!
+//! Parses `mosquitto.conf` (one `option value` per line) together with the
+//! password and ACL files it references.
+
+use std::path::Path;
+
+use toml::{Table, Value};
+
+use super::{
+    parse_bool, resolve_path, socket_addr, ImportError, ImportReport, ImportSource, RoleDraft,
+};
+
+/// A `listener` block (or the implicit default listener)
+#[derive(Debug, Default)]
+struct Listener {
+    line: Option<usize>,
+    port: u16,
+    host: Option<String>,
+    websockets: bool,
+    certfile: Option<String>,
+    keyfile: Option<String>,
+    cafile: Option<String>,
+    require_certificate: bool,
+}
+
+/// A `connection` (bridge) block
+#[derive(Debug, Default)]
+struct Bridge {
+    name: String,
+    table: Table,
+    forwards: Vec<Table>,
+    tls: Table,
+}
+
+/// Import a Mosquitto configuration
+///
+/// `path` is used for resolving referenced files and in report entries.
+/// Fails if two options translate to conflicting values.
+pub fn import_mosquitto(content: &str, path: &Path) -> Result<ImportReport, ImportError> {
+    let mut report = ImportReport::new(ImportSource::Mosquitto);
+    let mut default_listener = Listener {
+        port: 1883,
+        ..Default::default()
+    };
+    let mut default_listener_set = false;
+    let mut listeners: Vec<Listener> = Vec::new();
+    let mut bridges: Vec<Bridge> = Vec::new();
+    let mut password_file = None;
+    let mut acl_file = None;
+    let mut allow_anonymous = None;
+
+    for (idx, raw) in content.lines().enumerate() {
+        let line_no = Some(idx + 1);
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once(char::is_whitespace) {
+            Some((k, v)) => (k, v.trim()),
+            None => (line, ""),
+        };
+
+        // Bridge-scoped options apply to the most recent `connection`
+        if let Some(bridge) = bridges.last_mut() {
+            if apply_bridge_option(bridge, key, value, path, line_no, &mut report) {
+                continue;
+            }
+        }
+
+        match key {
+            "port" => match value.parse() {
+                Ok(port) => {
+                    default_listener.port = port;
+                    default_listener.line = line_no;
+                    default_listener_set = true;
+                }
+                Err(_) => report.note(path, line_no, key, "invalid port"),
+            },
+            "bind_address" => {
+                default_listener.host = Some(value.to_string());
+                default_listener_set = true;
+            }
+            "listener" => {
+                let mut parts = value.split_whitespace();
+                match parts.next().and_then(|p| p.parse().ok()) {
+                    Some(port) => listeners.push(Listener {
+                        line: line_no,
+                        port,
+                        host: parts.next().map(|s| s.to_string()),
+                        ..Default::default()
+                    }),
+                    None => report.note(path, line_no, key, "invalid listener port"),
+                }
+            }
+            "protocol" | "certfile" | "keyfile" | "cafile" | "require_certificate" => {
+                let listener = match listeners.last_mut() {
+                    Some(l) => l,
+                    None => {
+                        default_listener_set = true;
+                        &mut default_listener
+                    }
+                };
+                match key {
+                    "protocol" => match value {
+                        "mqtt" => listener.websockets = false,
+                        "websockets" => listener.websockets = true,
+                        _ => report.note(path, line_no, key, "unsupported listener protocol"),
+                    },
+                    "certfile" => listener.certfile = Some(value.to_string()),
+                    "keyfile" => listener.keyfile = Some(value.to_string()),
+                    "cafile" => listener.cafile = Some(value.to_string()),
+                    _ => listener.require_certificate = parse_bool(value).unwrap_or(false),
+                }
+            }
+            "max_connections" => match value.parse::<i64>() {
+                Ok(n) => report.set("limits.max_connections", n.max(0)),
+                Err(_) => report.note(path, line_no, key, "invalid value"),
+            },
+            "max_packet_size" => set_int(
+                &mut report,
+                "limits.max_packet_size",
+                key,
+                value,
+                path,
+                line_no,
+            ),
+            "max_inflight_messages" => set_int(
+                &mut report,
+                "limits.max_inflight",
+                key,
+                value,
+                path,
+                line_no,
+            ),
+            "max_queued_messages" => set_int(
+                &mut report,
+                "limits.max_queued_messages",
+                key,
+                value,
+                path,
+                line_no,
+            ),
+            "max_keepalive" => set_int(
+                &mut report,
+                "session.max_keep_alive",
+                key,
+                value,
+                path,
+                line_no,
+            ),
+            "max_qos" => set_int(&mut report, "mqtt.max_qos", key, value, path, line_no),
+            "retain_available" => match parse_bool(value) {
+                Some(b) => report.set("mqtt.retain_available", b),
+                None => report.note(path, line_no, key, "invalid boolean"),
+            },
+            "sys_interval" => match value.parse::<u64>() {
+                Ok(0) => report.set("mqtt.sys_topics", false),
+                Ok(n) => report.set("mqtt.sys_interval", format!("{}s", n)),
+                Err(_) => report.note(path, line_no, key, "invalid value"),
+            },
+            "allow_anonymous" => allow_anonymous = parse_bool(value),
+            "password_file" => password_file = Some(value.to_string()),
+            "acl_file" => acl_file = Some(value.to_string()),
+            "persistence" => match parse_bool(value) {
+                Some(b) => report.set("persistence.enabled", b),
+                None => report.note(path, line_no, key, "invalid boolean"),
+            },
+            "persistence_location" => report.set("persistence.path", value),
+            "connection" => bridges.push(Bridge {
+                name: value.to_string(),
+                ..Default::default()
+            }),
+            _ => report.note(path, line_no, key, "option not supported by VibeMQ"),
+        }
+    }
+
+    if default_listener_set || listeners.is_empty() {
+        listeners.insert(0, default_listener);
+    }
+    apply_listeners(listeners, path, &mut report);
+
+    let users = match password_file {
+        Some(ref file) => {
+            import_password_file(&resolve_path(path, file), &mut report, allow_anonymous)
+        }
+        None => {
+            // Mosquitto 2.x denies anonymous clients unless allowed; that
+            // takes auth enabled here, or everyone gets in
+            if allow_anonymous.is_none() {
+                report.note(
+                    path,
+                    None,
+                    "allow_anonymous",
+                    "not set; Mosquitto 2.x denies anonymous clients by default, so auth.allow_anonymous = false",
+                );
+            }
+            let anon = allow_anonymous.unwrap_or(false);
+            if !anon {
+                report.set("auth.enabled", true);
+            }
+            report.set("auth.allow_anonymous", anon);
+            Vec::new()
+        }
+    };
+
+    let users = match acl_file {
+        Some(ref file) => import_acl_file(&resolve_path(path, file), users, &mut report),
+        None => users,
+    };
+    for user in users {
+        report.push("auth.users", user);
+    }
+
+    for bridge in bridges {
+        let mut table = bridge.table;
+        table.insert("name".into(), bridge.name.into());
+        if !bridge.tls.is_empty() {
+            table.insert("protocol".into(), "mqtts".into());
+            table.insert("tls".into(), Value::Table(bridge.tls));
+        }
+        table.insert(
+            "forwards".into(),
+            Value::Array(bridge.forwards.into_iter().map(Value::Table).collect()),
+        );
+        report.push("bridge", table);
+    }
+
+    report.finish()
+}
+
+fn set_int(
+    report: &mut ImportReport,
+    target: &str,
+    key: &str,
+    value: &str,
+    path: &Path,
+    line: Option<usize>,
+) {
+    match value.parse::<i64>() {
+        Ok(n) => report.set(target, n),
+        Err(_) => report.note(path, line, key, "invalid integer"),
+    }
+}
+
+/// Map listeners onto VibeMQ's TCP, TLS and WebSocket binds
+fn apply_listeners(listeners: Vec<Listener>, path: &Path, report: &mut ImportReport) {
+    let mut have_tcp = false;
+    let mut have_tls = false;
+    let mut have_ws = false;
+
+    for listener in listeners {
+        let Some(addr) = socket_addr(listener.host.as_deref(), listener.port) else {
+            report.note(
+                path,
+                listener.line,
+                "listener",
+                format!(
+                    "bind host '{}' is not an IP address; listener skipped",
+                    listener.host.unwrap_or_default()
+                ),
+            );
+            continue;
+        };
+        let tls = listener.certfile.is_some();
+
+        let slot = match (listener.websockets, tls) {
+            (false, false) => &mut have_tcp,
+            (false, true) => &mut have_tls,
+            (true, false) => &mut have_ws,
+            (true, true) => {
+                report.note(
+                    path,
+                    listener.line,
+                    "listener",
+                    "secure websockets listeners are not supported; use a TLS-terminating proxy",
+                );
+                continue;
+            }
+        };
+        if *slot {
+            report.note(
+                path,
+                listener.line,
+                "listener",
+                format!(
+                    "only one listener of each kind is supported; {} skipped",
+                    addr
+                ),
+            );
+            continue;
+        }
+        *slot = true;
+
+        match (listener.websockets, tls) {
+            (false, false) => report.set("server.bind", addr),
+            (true, _) => report.set("server.ws_bind", addr),
+            (false, true) => {
+                report.set("server.tls_bind", addr);
+                report.set("server.tls.cert", listener.certfile.unwrap_or_default());
+                report.set("server.tls.key", listener.keyfile.unwrap_or_default());
+                if let Some(ca) = listener.cafile {
+                    report.set("server.tls.ca_cert", ca);
+                }
+                if listener.require_certificate {
+                    report.set("server.tls.require_client_cert", true);
+                }
+            }
+        }
+    }
+}
+
+/// Apply a bridge option; returns false if the option is not bridge-scoped
+fn apply_bridge_option(
+    bridge: &mut Bridge,
+    key: &str,
+    value: &str,
+    path: &Path,
+    line: Option<usize>,
+    report: &mut ImportReport,
+) -> bool {
+    match key {
+        "address" | "addresses" => {
+            let mut addrs = value.split_whitespace();
+            if let Some(first) = addrs.next() {
+                bridge.table.insert("address".into(), first.into());
+            }
+            if addrs.next().is_some() {
+                report.note(path, line, key, "only the first bridge address is used");
+            }
+        }
+        "topic" => match parse_bridge_topic(value) {
+            Some(forward) => bridge.forwards.push(forward),
+            None => report.note(path, line, key, "invalid bridge topic"),
+        },
+        "remote_username" => {
+            bridge.table.insert("username".into(), value.into());
+        }
+        "remote_password" => {
+            bridge.table.insert("password".into(), value.into());
+        }
+        "remote_clientid" | "clientid" => {
+            bridge.table.insert("client_id".into(), value.into());
+        }
+        "keepalive_interval" => match value.parse::<i64>() {
+            Ok(n) => {
+                bridge.table.insert("keepalive".into(), n.into());
+            }
+            Err(_) => report.note(path, line, key, "invalid integer"),
+        },
+        "cleansession" => {
+            if let Some(b) = parse_bool(value) {
+                bridge.table.insert("clean_start".into(), b.into());
+            }
+        }
+        "bridge_cafile" => {
+            bridge.tls.insert("ca_cert".into(), value.into());
+        }
+        "bridge_certfile" => {
+            bridge.tls.insert("client_cert".into(), value.into());
+        }
+        "bridge_keyfile" => {
+            bridge.tls.insert("client_key".into(), value.into());
+        }
+        "bridge_insecure" => {
+            if parse_bool(value) == Some(true) {
+                bridge.tls.insert("insecure".into(), true.into());
+            }
+        }
+        "bridge_protocol_version" => {
+            if value != "mqttv50" {
+                report.note(path, line, key, "bridges always connect with MQTT v5.0");
+            }
+        }
+        k if k.starts_with("bridge_")
+            || k.starts_with("local_")
+            || k.starts_with("notification")
+            || matches!(
+                k,
+                "try_private"
+                    | "start_type"
+                    | "restart_timeout"
+                    | "round_robin"
+                    | "idle_timeout"
+                    | "threshold"
+                    | "cleansession_local"
+            ) =>
+        {
+            report.note(path, line, key, "bridge option not supported by VibeMQ");
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Parse `topic <pattern> [[[out|in|both] qos] local-prefix remote-prefix]`
+fn parse_bridge_topic(value: &str) -> Option<Table> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let pattern = *parts.first()?;
+    let direction = parts.get(1).copied().unwrap_or("out");
+    if !matches!(direction, "in" | "out" | "both") {
+        return None;
+    }
+    let qos: i64 = match parts.get(2) {
+        Some(q) => q.parse().ok().filter(|q| (0..=2).contains(q))?,
+        None => 0,
+    };
+    let unquote = |s: &str| {
+        if s == "\"\"" {
+            String::new()
+        } else {
+            s.to_string()
+        }
+    };
+    let local_prefix = parts.get(3).map(|s| unquote(s)).unwrap_or_default();
+    let remote_prefix = parts.get(4).map(|s| unquote(s)).unwrap_or_default();
+
+    let mut table = Table::new();
+    table.insert(
+        "local_topic".into(),
+        format!("{}{}", local_prefix, pattern).into(),
+    );
+    table.insert(
+        "remote_topic".into(),
+        format!("{}{}", remote_prefix, pattern).into(),
+    );
+    table.insert("direction".into(), direction.into());
+    table.insert("qos".into(), qos.into());
+    Some(table)
+}
+
+/// Import `username:password` entries; hashed entries cannot be converted
+fn import_password_file(
+    file: &Path,
+    report: &mut ImportReport,
+    allow_anonymous: Option<bool>,
+) -> Vec<Table> {
+    report.set("auth.enabled", true);
+    // Mosquitto 2.x disallows anonymous access by default once a password file is set
+    report.set("auth.allow_anonymous", allow_anonymous.unwrap_or(false));
+
+    let content = match std::fs::read_to_string(file) {
+        Ok(c) => c,
+        Err(e) => {
+            report.note(file, None, "password_file", format!("cannot read: {}", e));
+            return Vec::new();
+        }
+    };
+
+    let mut users = Vec::new();
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((username, secret)) = line.split_once(':') else {
+            report.note(file, Some(idx + 1), "password_file", "malformed entry");
+            continue;
+        };
+        if secret.starts_with('$') {
+            report.note(
+                file,
+                Some(idx + 1),
+                username,
+                "mosquitto password hashes cannot be converted; add a password or argon2 \
+                 password_hash for this user",
+            );
+            continue;
+        }
+        let mut user = Table::new();
+        user.insert("username".into(), username.into());
+        user.insert("password".into(), secret.into());
+        users.push(user);
+    }
+    users
+}
+
+/// Import an ACL file, assigning per-user roles to imported users
+fn import_acl_file(file: &Path, mut users: Vec<Table>, report: &mut ImportReport) -> Vec<Table> {
+    let content = match std::fs::read_to_string(file) {
+        Ok(c) => c,
+        Err(e) => {
+            report.note(file, None, "acl_file", format!("cannot read: {}", e));
+            return users;
+        }
+    };
+    report.set("acl.enabled", true);
+
+    let mut anonymous = RoleDraft::default();
+    let mut patterns = RoleDraft::default();
+    let mut roles: Vec<(String, RoleDraft)> = Vec::new();
+
+    for (idx, line) in content.lines().enumerate() {
+        let line_no = Some(idx + 1);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once(char::is_whitespace) {
+            Some((k, v)) => (k, v.trim()),
+            None => (line, ""),
+        };
+
+        match key {
+            "user" => roles.push((value.to_string(), RoleDraft::default())),
+            "topic" | "pattern" => {
+                let (access, topic) = match value.split_once(char::is_whitespace) {
+                    Some((a, t)) if matches!(a, "read" | "write" | "readwrite" | "deny") => {
+                        (a, t.trim())
+                    }
+                    _ => ("readwrite", value),
+                };
+                let (publish, subscribe) = match access {
+                    "read" => (false, true),
+                    "write" => (true, false),
+                    "readwrite" => (true, true),
+                    _ => {
+                        report.note(
+                            file,
+                            line_no,
+                            key,
+                            "deny rules are not supported (ACLs are allow-only)",
+                        );
+                        continue;
+                    }
+                };
+                let target = if key == "pattern" {
+                    &mut patterns
+                } else if let Some((_, role)) = roles.last_mut() {
+                    role
+                } else {
+                    &mut anonymous
+                };
+                target.add(topic, publish, subscribe);
+            }
+            _ => report.note(file, line_no, key, "unknown ACL directive"),
+        }
+    }
+
+    // Patterns apply to every client; anonymous topics only to clients without a role
+    let mut default = RoleDraft::default();
+    for t in anonymous.publish.iter().chain(&patterns.publish) {
+        default.add(t, true, false);
+    }
+    for t in anonymous.subscribe.iter().chain(&patterns.subscribe) {
+        default.add(t, false, true);
+    }
+    report.set("acl.default", Value::Table(default.into_table(None)));
+
+    for (name, mut role) in roles {
+        for t in &patterns.publish {
+            role.add(t, true, false);
+        }
+        for t in &patterns.subscribe {
+            role.add(t, false, true);
+        }
+        match users
+            .iter_mut()
+            .find(|u| u.get("username").and_then(|v| v.as_str()) == Some(name.as_str()))
+        {
+            Some(user) => {
+                user.insert("role".into(), name.clone().into());
+            }
+            None => report.note(
+                file,
+                None,
+                &name,
+                "ACL user has no imported password entry; role created but not assigned",
+            ),
+        }
+        report.push("acl.roles", role.into_table(Some(&name)));
+    }
+
+    // Users without an ACL block only get pattern rules in Mosquitto
+    if !anonymous.publish.is_empty() || !anonymous.subscribe.is_empty() {
+        let mut assigned = false;
+        for user in users.iter_mut().filter(|u| !u.contains_key("role")) {
+            user.insert("role".into(), "mosquitto-patterns".into());
+            assigned = true;
+        }
+        if assigned {
+            let mut role = RoleDraft::default();
+            for t in &patterns.publish {
+                role.add(t, true, false);
+            }
+            for t in &patterns.subscribe {
+                role.add(t, false, true);
+            }
+            report.push("acl.roles", role.into_table(Some("mosquitto-patterns")));
+        }
+    }
+
+    users
+}
@@ -0,0 +1,296 @@
+//! Config import tests
+
+use super::*;
+use crate::config::{BridgeProtocol, Config, ForwardDirection};
+
+fn write(dir: &Path, name: &str, content: &str) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn test_detect_source() {
+    assert_eq!(
+        ImportSource::detect(Path::new("/etc/mosquitto/mosquitto.conf")),
+        Some(ImportSource::Mosquitto)
+    );
+    assert_eq!(
+        ImportSource::detect(Path::new("emqx.conf")),
+        Some(ImportSource::Emqx)
+    );
+    assert_eq!(ImportSource::detect(Path::new("broker.conf")), None);
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("1MB"), Some(1024 * 1024));
+    assert_eq!(parse_size("256KB"), Some(256 * 1024));
+    assert_eq!(parse_size("1000"), Some(1000));
+    assert_eq!(parse_size("lots"), None);
+}
+
+#[test]
+fn test_import_mosquitto() {
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        "passwd",
+        "admin:secret\nsensor:devicepass\nlegacy:$7$101$abc$def\n",
+    );
+    write(
+        dir.path(),
+        "acl",
+        "topic read $SYS/#\n\
+         pattern readwrite devices/%c/#\n\
+         user admin\n\
+         topic #\n\
+         user sensor\n\
+         topic write sensors/#\n\
+         topic deny secret/#\n",
+    );
+    let conf = write(
+        dir.path(),
+        "mosquitto.conf",
+        "# Main listener\n\
+         listener 1884 127.0.0.1\n\
+         listener 8883\n\
+         certfile /etc/certs/server.crt\n\
+         keyfile /etc/certs/server.key\n\
+         require_certificate true\n\
+         listener 9001\n\
+         protocol websockets\n\
+         max_connections -1\n\
+         max_inflight_messages 16\n\
+         sys_interval 30\n\
+         persistent_client_expiration 2d\n\
+         password_file passwd\n\
+         acl_file acl\n\
+         connection cloud\n\
+         address cloud.example.com:1883\n\
+         remote_username bridge\n\
+         remote_password bridgepass\n\
+         topic sensors/# out 1 \"\" edge/\n\
+         topic commands/# in 0\n\
+         try_private true\n",
+    );
+
+    let report = import_file(&conf, None).unwrap();
+    assert_eq!(report.source, ImportSource::Mosquitto);
+
+    let config = Config::parse(&report.to_toml()).unwrap();
+    assert_eq!(config.server.bind, "127.0.0.1:1884".parse().unwrap());
+    assert_eq!(
+        config.server.tls_bind,
+        Some("0.0.0.0:8883".parse().unwrap())
+    );
+    assert_eq!(config.server.ws_bind, Some("0.0.0.0:9001".parse().unwrap()));
+    let tls = config.server.tls.as_ref().unwrap();
+    assert_eq!(tls.cert, "/etc/certs/server.crt");
+    assert!(tls.require_client_cert);
+    assert_eq!(config.limits.max_connections, 0);
+    assert_eq!(config.limits.max_inflight, 16);
+    assert_eq!(config.mqtt.sys_interval, std::time::Duration::from_secs(30));
+
+    // Users with convertible passwords and their ACL roles
+    assert!(config.auth.enabled);
+    assert!(!config.auth.allow_anonymous);
+    assert_eq!(config.auth.users.len(), 2);
+    let sensor = config
+        .auth
+        .users
+        .iter()
+        .find(|u| u.username == "sensor")
+        .unwrap();
+    assert_eq!(sensor.role.as_deref(), Some("sensor"));
+
+    assert!(config.acl.enabled);
+    let role = config
+        .acl
+        .roles
+        .iter()
+        .find(|r| r.name == "sensor")
+        .unwrap();
+    assert!(role.publish.contains(&"sensors/#".to_string()));
+    assert!(role.publish.contains(&"devices/%c/#".to_string()));
+    assert!(config.acl.default.subscribe.contains(&"$SYS/#".to_string()));
+
+    // Bridge with prefix mapping
+    assert_eq!(config.bridge.len(), 1);
+    let bridge = &config.bridge[0];
+    assert_eq!(bridge.name, "cloud");
+    assert_eq!(bridge.username.as_deref(), Some("bridge"));
+    assert_eq!(bridge.forwards.len(), 2);
+    assert_eq!(bridge.forwards[0].remote_topic, "edge/sensors/#");
+    assert_eq!(bridge.forwards[0].qos, 1);
+    assert_eq!(bridge.forwards[1].direction, ForwardDirection::In);
+
+    // Unsupported options are reported, not dropped
+    let options: Vec<&str> = report.notes.iter().map(|n| n.option.as_str()).collect();
+    assert!(options.contains(&"persistent_client_expiration"));
+    assert!(options.contains(&"legacy"));
+    assert!(options.contains(&"topic"));
+    assert!(options.contains(&"try_private"));
+}
+
+#[test]
+fn test_path_collision() {
+    let mut report = ImportReport::new(ImportSource::Mosquitto);
+    report.set("server.bind", "0.0.0.0:1883");
+    report.set("server.bind.port", 1883);
+    report.push("server.bind", Table::new());
+    report.set("auth.users", true);
+    let err = report.finish().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "options translate to conflicting values for 'server.bind.port'"
+    );
+
+    let mut report = ImportReport::new(ImportSource::Mosquitto);
+    report.set("auth.enabled", true);
+    report.push("auth.enabled", Table::new());
+    assert!(matches!(report.finish(), Err(ImportError::Collision(path)) if path == "auth.enabled"));
+}
+
+#[test]
+fn test_import_mosquitto_defaults() {
+    let report = import_mosquitto("allow_anonymous true\n", Path::new("mosquitto.conf")).unwrap();
+    let config = Config::parse(&report.to_toml()).unwrap();
+    assert_eq!(config.server.bind, "0.0.0.0:1883".parse().unwrap());
+    assert!(config.auth.allow_anonymous);
+    assert!(report.notes.is_empty());
+
+    // Mosquitto 2.x denies anonymous clients when allow_anonymous is absent
+    let report = import_mosquitto("listener 1883\n", Path::new("mosquitto.conf")).unwrap();
+    let config = Config::parse(&report.to_toml()).unwrap();
+    assert!(config.auth.enabled);
+    assert!(!config.auth.allow_anonymous);
+    assert!(report.notes.iter().any(|n| n.option == "allow_anonymous"));
+}
+
+#[test]
+fn test_import_emqx() {
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        "acl.conf",
+        "%% EMQX ACL\n\
+         {allow, {username, \"dashboard\"}, subscribe, [\"$SYS/#\"]}.\n\
+         {allow, {ipaddr, \"127.0.0.1\"}, all, [\"$SYS/#\", \"#\"]}.\n\
+         {deny, all, subscribe, [\"$SYS/#\", {eq, \"#\"}]}.\n\
+         {allow, all, publish, [\"devices/%c/#\"]}.\n\
+         {allow, all}.\n",
+    );
+    let conf = write(
+        dir.path(),
+        "emqx.conf",
+        r#"
+node {
+  name = "emqx@127.0.0.1"
+  data_dir = "/var/lib/emqx"
+}
+
+listeners.tcp.default {
+  bind = "0.0.0.0:1883"
+  max_connections = 1024000
+  proxy_protocol = true
+}
+
+listeners.ssl.default {
+  bind = 8883
+  ssl_options {
+    certfile = "etc/certs/cert.pem"
+    keyfile = "etc/certs/key.pem"
+    verify = verify_peer
+    fail_if_no_peer_cert = true
+  }
+}
+
+listeners.ws.default {
+  bind = "0.0.0.0:8083"
+  websocket.mqtt_path = "/mqtt"
+}
+
+mqtt {
+  max_packet_size = 1MB
+  max_qos_allowed = 1
+  max_inflight = 64
+  wildcard_subscription = true
+  retry_interval = 20s
+  session_expiry_interval = 2h
+}
+
+authorization {
+  no_match = deny
+  sources = [
+    { type = file, path = "acl.conf" },
+    { type = built_in_database }
+  ]
+}
+
+bridges.mqtt.upstream {
+  server = "upstream.example.com:1883"
+  username = "edge"
+  keepalive = 60s
+  egress {
+    local.topic = "telemetry/#"
+    remote.topic = "${topic}"
+    remote.qos = 1
+  }
+}
+"#,
+    );
+
+    let report = import_file(&conf, None).unwrap();
+    assert_eq!(report.source, ImportSource::Emqx);
+
+    let config = Config::parse(&report.to_toml()).unwrap();
+    assert_eq!(config.server.bind, "0.0.0.0:1883".parse().unwrap());
+    assert!(config.server.proxy_protocol.enabled);
+    assert_eq!(
+        config.server.tls_bind,
+        Some("0.0.0.0:8883".parse().unwrap())
+    );
+    assert!(config.server.tls.as_ref().unwrap().require_client_cert);
+    assert_eq!(config.server.ws_bind, Some("0.0.0.0:8083".parse().unwrap()));
+    assert_eq!(config.limits.max_connections, 1_024_000);
+    assert_eq!(config.limits.max_packet_size, 1024 * 1024);
+    assert_eq!(config.limits.max_inflight, 64);
+    assert_eq!(config.mqtt.max_qos, 1);
+    assert_eq!(
+        config.limits.retry_interval,
+        std::time::Duration::from_secs(20)
+    );
+    assert_eq!(config.persistence.path, PathBuf::from("/var/lib/emqx"));
+
+    // ACLs: catch-all allow wins over no_match = deny
+    assert!(config.acl.enabled);
+    assert!(config
+        .acl
+        .default
+        .publish
+        .contains(&"devices/%c/#".to_string()));
+    assert!(config.acl.default.subscribe.contains(&"#".to_string()));
+    let role = config
+        .acl
+        .roles
+        .iter()
+        .find(|r| r.name == "dashboard")
+        .unwrap();
+    assert_eq!(role.subscribe, vec!["$SYS/#".to_string()]);
+
+    let bridge = &config.bridge[0];
+    assert_eq!(bridge.name, "upstream");
+    assert_eq!(bridge.protocol, BridgeProtocol::Mqtt);
+    assert_eq!(bridge.keepalive, 60);
+    assert_eq!(bridge.forwards[0].local_topic, "telemetry/#");
+    assert_eq!(bridge.forwards[0].remote_topic, "telemetry/#");
+
+    let messages: Vec<String> = report.notes.iter().map(|n| n.to_string()).collect();
+    assert!(messages
+        .iter()
+        .any(|m| m.contains("session_expiry_interval")));
+    assert!(messages.iter().any(|m| m.contains("built_in_database")));
+    assert!(messages.iter().any(|m| m.contains("ipaddr")));
+    assert!(messages.iter().any(|m| m.contains("deny rules")));
+}
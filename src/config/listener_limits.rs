@@ -0,0 +1,73 @@
+//! Per-Listener Limits Configuration
+//!
+//! Limits that apply to the clients of one listener: how many may connect,
+//! and what happens to a subscriber that can't keep up with its messages.
+//! A subscriber's backlog is what waits for it: messages in its delivery
+//! channel plus those queued in its session behind the inflight window.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Listener names limits can be set for
+pub(crate) const LISTENER_NAMES: [&str; 6] = ["tcp", "tls", "ws", "wss", "unix", "quic"];
+
+/// What happens to a subscriber whose backlog is over its listener's limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlowClientPolicy {
+    /// Drop QoS 0 messages for it until it catches up
+    #[default]
+    DropQos0,
+    /// Stop reading from clients publishing to it until it catches up (up to
+    /// `pause_timeout` per message)
+    PausePublishers,
+    /// Disconnect it with reason Quota exceeded; a persistent session keeps
+    /// queueing as usual
+    Disconnect,
+}
+
+/// Limits of one listener's clients (0 = unlimited)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ListenerLimits {
+    /// Clients connected through the listener (`limits.max_connections`
+    /// still applies to all of them)
+    pub max_connections: usize,
+    /// Messages a subscriber's backlog may hold before `slow_client` applies
+    pub max_outbound_messages: usize,
+    /// Topic and payload bytes queued in a subscriber's session before
+    /// `slow_client` applies
+    pub max_outbound_bytes: usize,
+    /// What happens to a subscriber over either limit
+    pub slow_client: SlowClientPolicy,
+    /// Longest a publisher waits for one slow subscriber under
+    /// `pause_publishers` (default: 5s)
+    #[serde(with = "humantime_serde")]
+    pub pause_timeout: Duration,
+}
+
+impl Default for ListenerLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: 0,
+            max_outbound_messages: 0,
+            max_outbound_bytes: 0,
+            slow_client: SlowClientPolicy::default(),
+            pause_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ListenerLimits {
+    /// Whether a backlog of `messages` holding `bytes` is over the limits
+    pub fn is_backlogged(&self, messages: usize, bytes: usize) -> bool {
+        (self.max_outbound_messages > 0 && messages > self.max_outbound_messages)
+            || (self.max_outbound_bytes > 0 && bytes > self.max_outbound_bytes)
+    }
+
+    /// Whether backlogs are limited at all
+    pub fn limits_backlog(&self) -> bool {
+        self.max_outbound_messages > 0 || self.max_outbound_bytes > 0
+    }
+}
@@ -0,0 +1,49 @@
+//! Maintenance Window Configuration
+//!
+//! `[[maintenance]]` entries open a window each time their cron expression
+//! fires and close it `duration` later, e.g. a weekly patch night. The
+//! policies of an open window apply until it closes.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// One recurring maintenance window
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    /// Unique name, shown in the admin API and `$SYS`
+    pub name: String,
+    /// Enable this window
+    pub enabled: bool,
+    /// Five-field cron expression in UTC the window opens at, or a macro
+    /// such as "@weekly"
+    pub cron: String,
+    /// How long the window stays open (e.g., "2h")
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+    /// Pause bridges not marked `critical`
+    pub pause_bridges: bool,
+    /// Log level (or `EnvFilter` directives) in force while open
+    pub log_level: Option<String>,
+    /// Multiplier of every IP's connection rate, burst, connection cap and
+    /// flapping threshold (1 = unchanged)
+    pub reconnect_rate_factor: f64,
+    /// Drop the will messages of clients disconnecting while open
+    pub suppress_wills: bool,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            cron: String::new(),
+            duration: Duration::from_secs(3600),
+            pause_bridges: false,
+            log_level: None,
+            reconnect_rate_factor: 1.0,
+            suppress_wills: false,
+        }
+    }
+}
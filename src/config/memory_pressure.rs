@@ -0,0 +1,50 @@
+//! Memory Pressure Configuration
+//!
+//! Configuration for evicting queued QoS 0 messages when the broker's
+//! memory use crosses a watermark.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Memory pressure configuration (`[limits.memory_pressure]`)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct MemoryPressureConfig {
+    /// Memory use in bytes above which queued QoS 0 messages are evicted
+    /// (0 = disabled)
+    pub high_watermark: usize,
+    /// Memory use in bytes eviction aims to get back under (0 = 80% of
+    /// `high_watermark`)
+    pub low_watermark: usize,
+    /// How often memory use is checked (default: 1s)
+    #[serde(with = "humantime_serde")]
+    pub check_interval: Duration,
+    /// Topic filters whose messages are evicted before any others
+    pub low_priority_topics: Vec<String>,
+}
+
+impl Default for MemoryPressureConfig {
+    fn default() -> Self {
+        Self {
+            high_watermark: 0,
+            low_watermark: 0,
+            check_interval: Duration::from_secs(1),
+            low_priority_topics: Vec::new(),
+        }
+    }
+}
+
+impl MemoryPressureConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.high_watermark > 0
+    }
+
+    /// Memory use eviction aims for
+    pub fn low_watermark(&self) -> usize {
+        match self.low_watermark {
+            0 => self.high_watermark / 5 * 4,
+            low => low,
+        }
+    }
+}
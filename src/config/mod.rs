@@ -8,10 +8,11 @@
 //! - Authentication and ACL
 //! - Bridge configuration
 //! - Environment variable overrides (VIBEMQ_* prefix)
+//! - Bundled deployment profiles (see [`profile`])
 
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use config::{Environment, File, FileFormat};
@@ -20,28 +21,185 @@ use serde::Deserialize;
 
 use crate::flapping::{ConnectionLimitConfig, FlappingConfig};
 
+// Re-export admin API config types
+pub use admin::AdminConfig;
+
+// Re-export aggregation window config types
+pub use aggregate::{AggregateAlertConfig, AggregateConfig, AggregateFunction};
+
+// Re-export HTTP authentication config types
+pub use auth::HttpAuthConfig;
+
+// Re-export backlog drain config types
+pub use backlog_drain::BacklogDrainConfig;
+
+// Re-export message batching config types
+pub use batch::BatchConfig;
+
 // Re-export bridge config types
 pub use bridge::{
-    BridgeConfig, BridgeProtocol, BridgeTlsConfig, ForwardDirection, ForwardRule, LoopPrevention,
+    BridgeConfig, BridgeHealthConfig, BridgeProtocol, BridgeProxyConfig, BridgeProxyVersion,
+    BridgeTlsConfig, EgressProxyConfig, EgressProxyType, EndpointSelection, ForwardDirection,
+    ForwardRule, LoopPrevention,
 };
 
 // Re-export cluster config types
-pub use cluster::ClusterConfig;
+pub use cluster::{ClusterConfig, ClusterMetricsConfig, ClusterRole};
+
+// Re-export link compression config types
+pub use compression::{CompressionCodec, LinkCompressionConfig};
+
+// Re-export enrichment config types
+pub use enrich::{EnrichConfig, EnrichMiss, LookupTableConfig, TableFormat};
+
+// Re-export client error detail config types
+pub use error_detail::{parse_reason_map, ErrorDetail};
+
+// Re-export geofence config types
+pub use geofence::GeofenceConfig;
+
+// Re-export ID generation config types
+pub use id::{IdConfig, IdGeneratorKind};
+
+// Re-export health probe config types
+pub use health::HealthConfig;
+
+// Re-export per-listener limit config types
+pub(crate) use listener_limits::LISTENER_NAMES;
+pub use listener_limits::{ListenerLimits, SlowClientPolicy};
+
+// Re-export delayed publish config types
+pub use delayed::DelayedConfig;
+
+// Re-export maintenance window config types
+pub use maintenance::MaintenanceConfig;
+
+// Re-export memory pressure config types
+pub use memory_pressure::MemoryPressureConfig;
 
 // Re-export metrics config types
 pub use metrics::MetricsConfig;
 
+// Re-export session event config types
+pub use session_events::SessionExpiryEventsConfig;
+
 // Re-export proxy protocol config types
 pub use proxy::ProxyProtocolConfig;
 
+// Re-export publisher backpressure config types
+pub use publisher_backpressure::PublisherBackpressureConfig;
+
+// Re-export OCPP config types
+pub use ocpp::OcppConfig;
+
+// Re-export payload format config types
+pub use payload::{PayloadConfig, PayloadFormat};
+
 // Re-export persistence config types
-pub use persistence::{BackendType, PersistenceConfig};
+pub use persistence::{BackendType, PersistenceConfig, SessionCheckpoint};
+
+// Re-export plugin config types
+pub use plugins::{PluginConfig, PluginEvent, PluginFailure, PluginsConfig};
+
+// Re-export profile history config types
+pub use profile_history::ProfileHistoryConfig;
+
+// Re-export QUIC listener config types
+pub use quic::QuicConfig;
+
+// Re-export client quota config types
+pub use quota::{QuotaConfig, QuotaLimits};
 
+// Re-export publish rate limit config types
+pub use rate_limit::PublishRateConfig;
+
+// Re-export priority topic config types
+pub use priority::PriorityConfig;
+
+// Re-export config reload types
+pub use reload::ConfigDiff;
+
+// Re-export retained frame cache config types
+pub use retained_cache::RetainedCacheConfig;
+
+// Re-export retained change feed config types
+pub use retained_feed::RetainedFeedConfig;
+
+// Re-export routing rule config types
+pub use rules::{AckFallback, ActionKind, RuleActionConfig, RuleConfig};
+
+// Re-export message sequence config types
+pub use sequence::{ReplayConfig, SequenceConfig};
+
+// Re-export scheduled publish config types
+pub use schedule::ScheduleConfig;
+
+// Re-export session handover config types
+pub use handover::{HandoverConfig, HandoverControl};
+
+// Re-export graceful shutdown config types
+pub use shutdown::ShutdownConfig;
+
+// Re-export STOMP listener config types
+pub use stomp::{StompConfig, StompDestination};
+
+// Re-export topic schema config types
+pub use topic_schema::{TopicSchemaConfig, TopicSchemaMode, TopicTemplateConfig};
+
+// Re-export topic tree config types
+pub use topic_tree::TopicTreeConfig;
+
+// Re-export publish transaction config types
+pub use transaction::{TransactionConfig, TransactionControl};
+
+// Re-export user property condition config types
+pub use user_properties::UserPropertyMatch;
+
+mod admin;
+mod aggregate;
+mod auth;
+mod backlog_drain;
+mod batch;
 mod bridge;
 mod cluster;
+mod compression;
+mod delayed;
+mod enrich;
+mod error_detail;
+mod geofence;
+mod handover;
+mod health;
+mod id;
+pub mod import;
+mod listener_limits;
+mod maintenance;
+mod memory_pressure;
 mod metrics;
+mod ocpp;
+mod payload;
 mod persistence;
+mod plugins;
+mod priority;
+pub mod profile;
+mod profile_history;
 mod proxy;
+mod publisher_backpressure;
+mod quic;
+mod quota;
+mod rate_limit;
+mod reload;
+mod retained_cache;
+mod retained_feed;
+mod rules;
+mod schedule;
+mod sequence;
+mod session_events;
+mod shutdown;
+mod stomp;
+mod topic_schema;
+mod topic_tree;
+mod transaction;
+mod user_properties;
 
 /// Substitute environment variables in a string.
 /// Supports `${VAR}` and `${VAR:-default}` syntax.
@@ -107,6 +265,9 @@ impl From<config::ConfigError> for ConfigError {
 #[serde(default)]
 #[derive(Default)]
 pub struct Config {
+    /// Bundled profile the settings start from ("edge", "cloud" or "test";
+    /// see [`profile`])
+    pub profile: Option<String>,
     /// Logging configuration
     pub log: LogConfig,
     /// Server configuration
@@ -130,9 +291,81 @@ pub struct Config {
     /// Metrics configuration
     #[serde(default)]
     pub metrics: MetricsConfig,
+    /// Admin HTTP API configuration
+    #[serde(default)]
+    pub admin: AdminConfig,
     /// Persistence configuration
     #[serde(default)]
     pub persistence: PersistenceConfig,
+    /// ID generation configuration
+    #[serde(default)]
+    pub id: IdConfig,
+    /// STOMP compatibility listener configuration
+    #[serde(default)]
+    pub stomp: StompConfig,
+    /// OCPP-over-MQTT topic conventions
+    #[serde(default)]
+    pub ocpp: OcppConfig,
+    /// Message batching extension
+    #[serde(default)]
+    pub batch: BatchConfig,
+    /// Publish transactions (atomic multi-message batches)
+    #[serde(default)]
+    pub transaction: TransactionConfig,
+    /// Two-phase session handover to a successor connection
+    #[serde(default)]
+    pub handover: HandoverConfig,
+    /// Feed of retained message changes
+    #[serde(default)]
+    pub retained_feed: RetainedFeedConfig,
+    /// Cache of encoded retained message frames
+    #[serde(default)]
+    pub retained_cache: RetainedCacheConfig,
+    /// Delayed publishes ("$delayed/{seconds}/{topic}")
+    #[serde(default)]
+    pub delayed: DelayedConfig,
+    /// Health and readiness probes
+    #[serde(default)]
+    pub health: HealthConfig,
+    /// Topic tree export (admin API)
+    #[serde(default)]
+    pub topic_tree: TopicTreeConfig,
+    /// External hook plugins (also managed through the admin API)
+    #[serde(default)]
+    pub plugins: PluginsConfig,
+    /// Continuous profiling (pprof builds)
+    #[serde(default)]
+    pub profile_history: ProfileHistoryConfig,
+    /// Declared topic namespace, checked on client publishes
+    #[serde(default)]
+    pub topic_schema: TopicSchemaConfig,
+    /// Per-topic sequence numbers on routed messages
+    #[serde(default)]
+    pub sequence: SequenceConfig,
+    /// Latency-critical topics delivered ahead of other traffic
+    #[serde(default)]
+    pub priority: PriorityConfig,
+    /// Scheduled (cron-triggered) publishes
+    #[serde(default)]
+    pub schedule: Vec<ScheduleConfig>,
+    /// Recurring maintenance windows and the policies they apply
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceConfig>,
+    /// Windowed aggregations published to output topics
+    #[serde(default)]
+    pub aggregate: Vec<AggregateConfig>,
+    /// Geofences publishing entry/exit events
+    #[serde(default)]
+    pub geofence: Vec<GeofenceConfig>,
+    /// Lookup-table enrichment of messages
+    #[serde(default)]
+    pub enrich: Vec<EnrichConfig>,
+    /// Rules routing messages to multiple actions
+    #[serde(default)]
+    pub rule: Vec<RuleConfig>,
+    /// Graceful shutdown and connection draining
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
 }
 
 /// Logging configuration
@@ -167,15 +400,40 @@ pub struct ServerConfig {
     pub tls_bind: Option<SocketAddr>,
     /// WebSocket bind address (optional)
     pub ws_bind: Option<SocketAddr>,
+    /// WebSocket-over-TLS bind address (optional); uses `[server.tls]` and
+    /// the WebSocket listener's settings
+    pub wss_bind: Option<SocketAddr>,
+    /// UDP bind address of the experimental MQTT-over-QUIC listener
+    /// (optional); uses `[server.tls]` and the TLS listener's settings
+    pub quic_bind: Option<SocketAddr>,
     /// WebSocket path (default: "/mqtt")
     #[serde(default = "default_ws_path")]
     pub ws_path: String,
+    /// Maximum incoming WebSocket frame size in bytes (default: 16 MiB)
+    pub ws_max_frame_size: Option<usize>,
+    /// Unix domain socket path (optional, for local sidecars); shares the
+    /// TCP listener's `allow_mqtt31`, `max_qos`, `capabilities` and
+    /// `error_detail`
+    pub unix_bind: Option<PathBuf>,
+    /// Additional MQTT/TCP bind addresses sharing the TCP listener's
+    /// settings, e.g. `"[::]:1883"` alongside `bind = "0.0.0.0:1883"`
+    #[serde(default)]
+    pub extra_binds: Vec<SocketAddr>,
+    /// IPV6_V6ONLY for IPv6 listeners: true accepts IPv6 only, false also
+    /// accepts IPv4 as v4-mapped addresses. Unset keeps the OS default,
+    /// except that an IPv6 wildcard sharing its port with an IPv4 bind is
+    /// made IPv6-only so both can bind
+    #[serde(default)]
+    pub ipv6_only: Option<bool>,
     /// Number of worker threads (0 = auto)
     #[serde(default)]
     pub workers: usize,
     /// TLS configuration (required when tls_bind is set)
     #[serde(default)]
     pub tls: Option<ServerTlsConfig>,
+    /// QUIC listener settings
+    #[serde(default)]
+    pub quic: QuicConfig,
     /// PROXY protocol configuration for TCP listener
     #[serde(default)]
     pub proxy_protocol: ProxyProtocolConfig,
@@ -185,10 +443,90 @@ pub struct ServerConfig {
     /// PROXY protocol configuration for WebSocket listener
     #[serde(default)]
     pub ws_proxy_protocol: ProxyProtocolConfig,
+    /// PROXY protocol configuration for the Unix socket listener
+    /// (`trusted_networks` doesn't apply: every local peer is trusted)
+    #[serde(default)]
+    pub unix_proxy_protocol: ProxyProtocolConfig,
+    /// Accept legacy MQTT 3.1 ("MQIsdp") clients on the TCP listener
+    /// (default: true)
+    #[serde(default = "default_true")]
+    pub allow_mqtt31: bool,
+    /// Accept legacy MQTT 3.1 ("MQIsdp") clients on the TLS listener
+    /// (default: true)
+    #[serde(default = "default_true")]
+    pub tls_allow_mqtt31: bool,
+    /// Accept legacy MQTT 3.1 ("MQIsdp") clients on the WebSocket listener
+    /// (default: true)
+    #[serde(default = "default_true")]
+    pub ws_allow_mqtt31: bool,
+    /// Highest QoS granted and accepted on the TCP listener (0, 1, or 2;
+    /// default: mqtt.max_qos)
+    #[serde(default)]
+    pub max_qos: Option<u8>,
+    /// Highest QoS granted and accepted on the TLS listener
+    #[serde(default)]
+    pub tls_max_qos: Option<u8>,
+    /// Highest QoS granted and accepted on the WebSocket listener
+    #[serde(default)]
+    pub ws_max_qos: Option<u8>,
+    /// MQTT features offered on the TCP listener
+    #[serde(default)]
+    pub capabilities: ListenerCapabilities,
+    /// MQTT features offered on the TLS listener
+    #[serde(default)]
+    pub tls_capabilities: ListenerCapabilities,
+    /// MQTT features offered on the WebSocket listener
+    #[serde(default)]
+    pub ws_capabilities: ListenerCapabilities,
+    /// Error detail revealed to clients on the TCP listener
+    #[serde(default)]
+    pub error_detail: ErrorDetail,
+    /// Error detail revealed to clients on the TLS listener
+    #[serde(default)]
+    pub tls_error_detail: ErrorDetail,
+    /// Error detail revealed to clients on the WebSocket listener
+    #[serde(default)]
+    pub ws_error_detail: ErrorDetail,
+    /// Remap error reason codes sent to clients, e.g. { "0x87" = "0x80" }
+    /// (hex or decimal, applied before the listener's error detail policy)
+    #[serde(default)]
+    pub reason_map: HashMap<String, String>,
+}
+
+/// MQTT features offered on a listener
+///
+/// A feature is only available where both this and the `[mqtt]` setting
+/// allow it, and is advertised in CONNACK accordingly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ListenerCapabilities {
+    /// Whether retained messages are available
+    pub retain_available: bool,
+    /// Whether wildcard subscriptions are available
+    pub wildcard_subscriptions: bool,
+    /// Whether subscription identifiers are available
+    pub subscription_identifiers: bool,
+    /// Whether shared subscriptions are available
+    pub shared_subscriptions: bool,
+    /// Topic Alias Maximum advertised to clients, at most
+    /// `session.max_topic_aliases` (default: that setting)
+    pub topic_alias_maximum: Option<u16>,
+}
+
+impl Default for ListenerCapabilities {
+    fn default() -> Self {
+        Self {
+            retain_available: true,
+            wildcard_subscriptions: true,
+            subscription_identifiers: true,
+            shared_subscriptions: true,
+            topic_alias_maximum: None,
+        }
+    }
 }
 
 /// TLS configuration for the server
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct ServerTlsConfig {
     /// Path to certificate file (PEM format)
     pub cert: String,
@@ -199,6 +537,38 @@ pub struct ServerTlsConfig {
     /// Require client certificate authentication
     #[serde(default)]
     pub require_client_cert: bool,
+    /// Threads dedicated to TLS handshakes (0 = handshake on the main runtime)
+    #[serde(default)]
+    pub handshake_threads: usize,
+    /// Maximum TLS handshakes queued or in progress on the handshake pool;
+    /// further connections are dropped until the queue drains (default: 1024)
+    #[serde(default = "default_handshake_queue_size")]
+    pub handshake_queue_size: usize,
+    /// Longest a handshake on the handshake pool may take (default: 10s)
+    #[serde(default = "default_handshake_timeout", with = "humantime_serde")]
+    pub handshake_timeout: Duration,
+}
+
+impl Default for ServerTlsConfig {
+    fn default() -> Self {
+        Self {
+            cert: String::new(),
+            key: String::new(),
+            ca_cert: None,
+            require_client_cert: false,
+            handshake_threads: 0,
+            handshake_queue_size: default_handshake_queue_size(),
+            handshake_timeout: default_handshake_timeout(),
+        }
+    }
+}
+
+fn default_handshake_queue_size() -> usize {
+    1024
+}
+
+fn default_handshake_timeout() -> Duration {
+    Duration::from_secs(10)
 }
 
 fn default_ws_path() -> String {
@@ -215,12 +585,33 @@ impl Default for ServerConfig {
             bind: default_bind(),
             tls_bind: None,
             ws_bind: None,
+            wss_bind: None,
+            quic_bind: None,
             ws_path: default_ws_path(),
+            ws_max_frame_size: None,
+            unix_bind: None,
+            extra_binds: Vec::new(),
+            ipv6_only: None,
             workers: 0,
             tls: None,
+            quic: QuicConfig::default(),
             proxy_protocol: ProxyProtocolConfig::default(),
             tls_proxy_protocol: ProxyProtocolConfig::default(),
             ws_proxy_protocol: ProxyProtocolConfig::default(),
+            unix_proxy_protocol: ProxyProtocolConfig::default(),
+            allow_mqtt31: true,
+            tls_allow_mqtt31: true,
+            ws_allow_mqtt31: true,
+            max_qos: None,
+            tls_max_qos: None,
+            ws_max_qos: None,
+            capabilities: ListenerCapabilities::default(),
+            tls_capabilities: ListenerCapabilities::default(),
+            ws_capabilities: ListenerCapabilities::default(),
+            error_detail: ErrorDetail::default(),
+            tls_error_detail: ErrorDetail::default(),
+            ws_error_detail: ErrorDetail::default(),
+            reason_map: HashMap::new(),
         }
     }
 }
@@ -241,12 +632,26 @@ pub struct LimitsConfig {
     /// Maximum queued messages per offline client
     #[serde(default = "default_max_queued_messages")]
     pub max_queued_messages: usize,
+    /// Maximum topic and payload bytes queued per client (0 = unlimited)
+    #[serde(default)]
+    pub max_queued_bytes: usize,
+    /// Queue QoS 0 messages for offline clients, not just QoS 1/2
+    #[serde(default = "default_true")]
+    pub queue_qos0: bool,
+    /// What happens when a client's queue is full
+    #[serde(default)]
+    pub queue_overflow: QueueOverflow,
     /// Maximum pending PUBREL for QoS 2
     #[serde(default = "default_max_awaiting_rel")]
     pub max_awaiting_rel: usize,
     /// Retry interval for unacked messages (e.g., "30s", "1m")
     #[serde(default = "default_retry_interval", with = "humantime_serde")]
     pub retry_interval: Duration,
+    /// Warn when a client still sending packets (PINGREQs included) has left
+    /// a QoS 1/2 message unacknowledged this long (e.g., "2m"; unset =
+    /// disabled). Checked every retry interval.
+    #[serde(default, with = "humantime_serde")]
+    pub ack_alarm_after: Option<Duration>,
     /// Per-connection outbound message channel capacity.
     /// This buffer holds messages waiting to be written to the client socket.
     /// Higher values handle burst traffic better but use more memory per connection.
@@ -258,12 +663,46 @@ pub struct LimitsConfig {
     /// Set to 0 for unlimited (default).
     #[serde(default)]
     pub max_topic_levels: usize,
+    /// Hibernate connections idle for this long (e.g., "30s"; unset = disabled).
+    /// Hibernated connections release their read/write buffers until the
+    /// client sends data or a message is delivered to them.
+    #[serde(default, with = "humantime_serde")]
+    pub hibernate_after: Option<Duration>,
     /// Flapping detection configuration (DoS protection)
     #[serde(default)]
     pub flapping_detect: FlappingConfig,
     /// Connection rate limiting configuration (DoS protection)
     #[serde(default)]
     pub connection_limit: ConnectionLimitConfig,
+    /// Publish rate limiting per client identity
+    #[serde(default)]
+    pub publish_rate: PublishRateConfig,
+    /// Per-connection quotas, overridable per listener and user
+    #[serde(default)]
+    pub quota: QuotaConfig,
+    /// Eviction of queued QoS 0 messages under memory pressure
+    #[serde(default)]
+    pub memory_pressure: MemoryPressureConfig,
+    /// Flow control of MQTT v5 publishers routing to congested subscribers
+    #[serde(default)]
+    pub publisher_backpressure: PublisherBackpressureConfig,
+    /// Connection and slow-subscriber limits by listener ("tcp", "tls",
+    /// "ws", "wss", "unix", "quic")
+    #[serde(default)]
+    pub listeners: HashMap<String, ListenerLimits>,
+}
+
+/// Overflow policy of a client's message queue
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueOverflow {
+    /// Make room by dropping the oldest queued messages
+    #[default]
+    DropOldest,
+    /// Keep the queue and drop the new message
+    DropNewest,
+    /// Disconnect the client (if online) and end its session
+    Disconnect,
 }
 
 fn default_max_connections() -> usize {
@@ -295,12 +734,22 @@ impl Default for LimitsConfig {
             max_packet_size: default_max_packet_size(),
             max_inflight: default_max_inflight(),
             max_queued_messages: default_max_queued_messages(),
+            max_queued_bytes: 0,
+            queue_qos0: true,
+            queue_overflow: QueueOverflow::default(),
             max_awaiting_rel: default_max_awaiting_rel(),
             retry_interval: Duration::from_secs(30),
+            ack_alarm_after: None,
             outbound_channel_capacity: default_outbound_channel_capacity(),
             max_topic_levels: 0, // 0 = unlimited
+            hibernate_after: None,
             flapping_detect: FlappingConfig::default(),
             connection_limit: ConnectionLimitConfig::default(),
+            publish_rate: PublishRateConfig::default(),
+            quota: QuotaConfig::default(),
+            memory_pressure: MemoryPressureConfig::default(),
+            publisher_backpressure: PublisherBackpressureConfig::default(),
+            listeners: HashMap::new(),
         }
     }
 }
@@ -321,6 +770,18 @@ pub struct SessionConfig {
     /// Maximum topic aliases
     #[serde(default = "default_max_topic_aliases")]
     pub max_topic_aliases: u16,
+    /// Compress subscriptions and queued messages of persistent sessions
+    /// disconnected for at least this long (e.g., "10m"; unset = disabled)
+    #[serde(default, with = "humantime_serde")]
+    pub compress_idle_after: Option<Duration>,
+    /// Longest session expiry granted to clients (e.g., "1d"; unset = as
+    /// requested). Also bounds sessions that would never expire
+    #[serde(default, with = "humantime_serde")]
+    pub max_expiry: Option<Duration>,
+    /// Events published when persistent sessions expire
+    pub expiry_events: SessionExpiryEventsConfig,
+    /// How sessions share delivery of their queued messages
+    pub backlog_drain: BacklogDrainConfig,
 }
 
 fn default_keep_alive() -> u16 {
@@ -343,6 +804,10 @@ impl Default for SessionConfig {
             max_keep_alive: default_max_keep_alive(),
             expiry_check_interval: Duration::from_secs(60),
             max_topic_aliases: default_max_topic_aliases(),
+            compress_idle_after: None,
+            max_expiry: None,
+            expiry_events: SessionExpiryEventsConfig::default(),
+            backlog_drain: BacklogDrainConfig::default(),
         }
     }
 }
@@ -366,12 +831,39 @@ pub struct MqttConfig {
     /// Whether shared subscriptions are available
     #[serde(default = "default_true")]
     pub shared_subscriptions: bool,
+    /// How messages are distributed across a share group's members
+    #[serde(default)]
+    pub shared_subscription_strategy: SharedSubscriptionStrategy,
     /// Whether $SYS topics are published
     #[serde(default = "default_true")]
     pub sys_topics: bool,
     /// $SYS topic publish interval (e.g., "10s", "1m")
     #[serde(default = "default_sys_interval", with = "humantime_serde")]
     pub sys_interval: Duration,
+    /// Republish chains allowed through the local publish interface before
+    /// a message is dropped as a loop
+    #[serde(default = "default_max_local_hops")]
+    pub max_local_hops: u8,
+    /// Give every message taken in a routing ID, reported by the logs,
+    /// deliveries, rules and bridges that handle it
+    pub routing_ids: bool,
+    /// Compression codecs accepted from bridges of other VibeMQ brokers,
+    /// most preferred first (empty = none)
+    pub bridge_compression: Vec<CompressionCodec>,
+}
+
+/// Member selection for shared subscriptions ($share/{group}/{filter})
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SharedSubscriptionStrategy {
+    /// Members take turns
+    #[default]
+    RoundRobin,
+    /// A member is picked at random for each message
+    Random,
+    /// All messages from one publishing client go to the same member while
+    /// it stays in the group (round-robin for broker-originated messages)
+    Sticky,
 }
 
 fn default_max_qos() -> u8 {
@@ -383,6 +875,9 @@ fn default_true() -> bool {
 fn default_sys_interval() -> Duration {
     Duration::from_secs(10)
 }
+fn default_max_local_hops() -> u8 {
+    8
+}
 
 impl Default for MqttConfig {
     fn default() -> Self {
@@ -392,14 +887,18 @@ impl Default for MqttConfig {
             wildcard_subscriptions: true,
             subscription_identifiers: true,
             shared_subscriptions: true,
+            shared_subscription_strategy: SharedSubscriptionStrategy::default(),
             sys_topics: true,
             sys_interval: Duration::from_secs(10),
+            max_local_hops: default_max_local_hops(),
+            routing_ids: false,
+            bridge_compression: Vec::new(),
         }
     }
 }
 
 /// Authentication configuration
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
 pub struct AuthConfig {
     /// Whether authentication is enabled
@@ -410,6 +909,64 @@ pub struct AuthConfig {
     /// Static user list
     #[serde(default)]
     pub users: Vec<UserConfig>,
+    /// Connection details passed to authentication hooks (default: all)
+    #[serde(default = "default_auth_metadata_fields")]
+    pub metadata_fields: Vec<AuthMetadataField>,
+    /// `username:hash` file checked after the static user list
+    /// (argon2, or mosquitto_passwd `$6$`/`$7$` hashes)
+    pub password_file: Option<PathBuf>,
+    /// HTTP webhook checked after the password file
+    pub http: Option<HttpAuthConfig>,
+}
+
+/// A connection detail that can be passed to authentication hooks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMetadataField {
+    /// Client IP, as reported by the PROXY header when behind a proxy
+    ClientIp,
+    /// TLS Server Name Indication
+    Sni,
+    /// Negotiated TLS version
+    TlsVersion,
+    /// Negotiated TLS cipher suite
+    TlsCipher,
+    /// Verified client certificate CN
+    ClientCertCn,
+    /// Listener the client connected to ("tcp", "tls", "ws", "wss", "unix", "quic")
+    Listener,
+    /// Tenant the client belongs to
+    Tenant,
+}
+
+impl AuthMetadataField {
+    /// Every field, in documentation order
+    pub const ALL: [AuthMetadataField; 7] = [
+        AuthMetadataField::ClientIp,
+        AuthMetadataField::Sni,
+        AuthMetadataField::TlsVersion,
+        AuthMetadataField::TlsCipher,
+        AuthMetadataField::ClientCertCn,
+        AuthMetadataField::Listener,
+        AuthMetadataField::Tenant,
+    ];
+}
+
+fn default_auth_metadata_fields() -> Vec<AuthMetadataField> {
+    AuthMetadataField::ALL.to_vec()
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allow_anonymous: false,
+            users: Vec::new(),
+            metadata_fields: default_auth_metadata_fields(),
+            password_file: None,
+            http: None,
+        }
+    }
 }
 
 /// User configuration
@@ -426,6 +983,9 @@ pub struct UserConfig {
     /// Role name for ACL permissions
     #[serde(default)]
     pub role: Option<String>,
+    /// Tenant the user's clients belong to; client IDs are unique per tenant
+    #[serde(default)]
+    pub tenant: Option<String>,
 }
 
 /// ACL configuration
@@ -440,6 +1000,64 @@ pub struct AclConfig {
     /// Default permissions for users without explicit role (including anonymous)
     #[serde(default)]
     pub default: AclPermissions,
+    /// What happens to sessions whose subscriptions are revoked by an ACL change
+    pub on_revoke: AclRevocation,
+    /// TOML file of `[[roles]]` and `[default]` rules replacing the inline
+    /// ones; re-read on SIGHUP
+    pub rule_file: Option<PathBuf>,
+}
+
+/// ACL rules loaded from `acl.rule_file`
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AclRuleFile {
+    /// ACL roles
+    pub roles: Vec<AclRole>,
+    /// Default permissions for users without explicit role (including anonymous)
+    pub default: AclPermissions,
+}
+
+impl AclConfig {
+    /// Rules in effect: the inline ones, or those in `rule_file` if set
+    ///
+    /// Environment variables are substituted as in the main config file.
+    pub fn load_rules(&self) -> Result<AclConfig, ConfigError> {
+        let Some(ref path) = self.rule_file else {
+            return Ok(self.clone());
+        };
+        let invalid = |msg: String| ConfigError::Validation(format!("{}: {}", path.display(), msg));
+
+        let content = std::fs::read_to_string(path)?;
+        let file: AclRuleFile =
+            toml::from_str(&substitute_env_vars(&content)).map_err(|e| invalid(e.to_string()))?;
+        let caps = file
+            .roles
+            .iter()
+            .map(|role| (role.name.as_str(), role.max_qos))
+            .chain(std::iter::once(("default", file.default.max_qos)));
+        for (name, max_qos) in caps {
+            if max_qos.is_some_and(|qos| qos > 2) {
+                return Err(invalid(format!("max_qos of '{}' must be 0, 1, or 2", name)));
+            }
+        }
+
+        Ok(AclConfig {
+            roles: file.roles,
+            default: file.default,
+            ..self.clone()
+        })
+    }
+}
+
+/// Action taken when an ACL change revokes an existing subscription
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AclRevocation {
+    /// Remove the revoked subscriptions and keep the client connected
+    #[default]
+    Unsubscribe,
+    /// Remove the revoked subscriptions and disconnect the client
+    Disconnect,
 }
 
 /// ACL role
@@ -453,6 +1071,19 @@ pub struct AclRole {
     /// Topic patterns this role can subscribe to
     #[serde(default)]
     pub subscribe: Vec<String>,
+    /// Lifetime limits for messages this role publishes (first match wins)
+    #[serde(default)]
+    pub ttl: Vec<AclTtlRule>,
+    /// User properties messages this role publishes must carry (first
+    /// match wins)
+    #[serde(default)]
+    pub publish_properties: Vec<AclPropertyRule>,
+    /// Highest QoS this role may subscribe or publish with (0, 1, or 2)
+    #[serde(default)]
+    pub max_qos: Option<u8>,
+    /// Session settings for this role's clients, over the defaults'
+    #[serde(default)]
+    pub session: SessionPolicy,
 }
 
 /// ACL permissions
@@ -463,6 +1094,86 @@ pub struct AclPermissions {
     pub publish: Vec<String>,
     /// Topic patterns that can be subscribed to
     pub subscribe: Vec<String>,
+    /// Lifetime limits for published messages (first match wins)
+    pub ttl: Vec<AclTtlRule>,
+    /// User properties published messages must carry (first match wins)
+    pub publish_properties: Vec<AclPropertyRule>,
+    /// Highest QoS these clients may subscribe or publish with (0, 1, or 2)
+    pub max_qos: Option<u8>,
+    /// Session settings for these clients
+    pub session: SessionPolicy,
+}
+
+/// Session settings of an ACL role, applied at CONNECT
+///
+/// Set fields replace the broker-wide setting for the role's clients, so a
+/// role can grant more than the default as well as less; unset fields
+/// inherit. Together with `max_qos` this defines a service tier in one
+/// place.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SessionPolicy {
+    /// Messages queued for an offline or slow client (0 = unlimited;
+    /// replaces `limits.max_queued_messages`)
+    pub max_queued_messages: Option<usize>,
+    /// Longest session expiry interval granted (replaces
+    /// `session.max_expiry`)
+    #[serde(with = "humantime_serde")]
+    pub max_session_expiry: Option<Duration>,
+    /// Shortest keep-alive granted, in seconds; lower requests are raised
+    pub min_keep_alive: Option<u16>,
+    /// Longest keep-alive granted, in seconds (replaces
+    /// `session.max_keep_alive`)
+    pub max_keep_alive: Option<u16>,
+    /// Quota limits, over the listener's and under the username's
+    /// (`limits.quota`)
+    pub quota: QuotaLimits,
+    /// Messages sent per backlog drain turn, in multiples of
+    /// `session.backlog_drain.batch_size` (default 1)
+    pub drain_weight: Option<u32>,
+}
+
+impl SessionPolicy {
+    /// Layer `other` over this policy (fields set in `other` win)
+    pub fn merge(self, other: &SessionPolicy) -> SessionPolicy {
+        SessionPolicy {
+            max_queued_messages: other.max_queued_messages.or(self.max_queued_messages),
+            max_session_expiry: other.max_session_expiry.or(self.max_session_expiry),
+            min_keep_alive: other.min_keep_alive.or(self.min_keep_alive),
+            max_keep_alive: other.max_keep_alive.or(self.max_keep_alive),
+            quota: self.quota.merge(&other.quota),
+            drain_weight: other.drain_weight.or(self.drain_weight),
+        }
+    }
+}
+
+/// Lifetime limits for messages published to matching topics
+///
+/// Client-provided expiry intervals are clamped to these bounds, so
+/// publishers can't keep data in the broker indefinitely.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AclTtlRule {
+    /// Topic pattern (wildcards and %c/%u as in publish patterns)
+    pub topic: String,
+    /// Maximum message expiry interval, also applied when the publisher
+    /// sets none (e.g., "1h")
+    #[serde(default, with = "humantime_serde")]
+    pub max_message_expiry: Option<Duration>,
+    /// Maximum time a retained message on the topic is kept (e.g., "1d")
+    #[serde(default, with = "humantime_serde")]
+    pub max_retained_lifetime: Option<Duration>,
+}
+
+/// User properties required of messages published to matching topics
+///
+/// A publish without them is refused like one to a forbidden topic, e.g.
+/// only messages tagged `env=prod` may be published under `prod/#`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AclPropertyRule {
+    /// Topic pattern (wildcards and %c/%u as in publish patterns)
+    pub topic: String,
+    /// Conditions the message must all satisfy
+    pub user_properties: Vec<UserPropertyMatch>,
 }
 
 impl Config {
@@ -505,15 +1216,22 @@ impl Config {
 
         // Load from file with env var substitution
         let path = path.as_ref();
-        match std::fs::read_to_string(path) {
-            Ok(content) => {
-                let substituted = substitute_env_vars(&content);
-                builder = builder.add_source(File::from_str(&substituted, FileFormat::Toml));
-            }
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => Some(substitute_env_vars(&content)),
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
                 // File doesn't exist, use defaults
+                None
             }
             Err(e) => return Err(ConfigError::Io(e)),
+        };
+
+        // The selected profile sits between the defaults and the file
+        if let Some(name) = profile::selected_by(content.as_deref()) {
+            builder =
+                builder.add_source(File::from_str(profile::profile(&name)?, FileFormat::Toml));
+        }
+        if let Some(ref content) = content {
+            builder = builder.add_source(File::from_str(content, FileFormat::Toml));
         }
 
         // Override with environment variables (VIBEMQ__SERVER__BIND, etc.)
@@ -540,7 +1258,13 @@ impl Config {
 
     /// Parse configuration from a string (for testing, no env var support)
     pub fn parse(content: &str) -> Result<Self, ConfigError> {
-        let config: Config = toml::from_str(content)?;
+        let mut table: toml::Table = toml::from_str(content)?;
+        if let Some(name) = profile::selected(&table) {
+            let mut layered: toml::Table = toml::from_str(profile::profile(name)?)?;
+            profile::merge(&mut layered, table);
+            table = layered;
+        }
+        let config: Config = table.try_into()?;
         config.validate()?;
         Ok(config)
     }
@@ -554,6 +1278,107 @@ impl Config {
             ));
         }
 
+        for name in self.limits.listeners.keys() {
+            if !listener_limits::LISTENER_NAMES.contains(&name.as_str()) {
+                return Err(ConfigError::Validation(format!(
+                    "limits.listeners.{}: unknown listener (expected one of {})",
+                    name,
+                    listener_limits::LISTENER_NAMES.join(", ")
+                )));
+            }
+        }
+
+        let qos_caps = [
+            ("server.max_qos", self.server.max_qos),
+            ("server.tls_max_qos", self.server.tls_max_qos),
+            ("server.ws_max_qos", self.server.ws_max_qos),
+            ("acl.default.max_qos", self.acl.default.max_qos),
+        ];
+        let role_caps = self
+            .acl
+            .roles
+            .iter()
+            .map(|role| ("acl.roles.max_qos", role.max_qos));
+        for (name, max_qos) in qos_caps.into_iter().chain(role_caps) {
+            if max_qos.is_some_and(|qos| qos > 2) {
+                return Err(ConfigError::Validation(format!(
+                    "{} must be 0, 1, or 2",
+                    name
+                )));
+            }
+        }
+
+        let session_policies = self
+            .acl
+            .roles
+            .iter()
+            .map(|role| &role.session)
+            .chain([&self.acl.default.session]);
+        for policy in session_policies {
+            if let (Some(min), Some(max)) = (policy.min_keep_alive, policy.max_keep_alive) {
+                if min > max {
+                    return Err(ConfigError::Validation(
+                        "acl session.min_keep_alive must not exceed session.max_keep_alive"
+                            .to_string(),
+                    ));
+                }
+            }
+        }
+
+        // Validate user property conditions (rules check their own)
+        let acl_conditions = self
+            .acl
+            .roles
+            .iter()
+            .flat_map(|role| &role.publish_properties)
+            .chain(&self.acl.default.publish_properties)
+            .flat_map(|rule| rule.user_properties.iter().map(|c| ("acl", c)));
+        let forward_conditions = self.bridge.iter().flat_map(|bridge| {
+            bridge
+                .forwards
+                .iter()
+                .flat_map(|forward| &forward.user_properties)
+                .map(|c| ("bridge", c))
+        });
+        for (section, condition) in acl_conditions.chain(forward_conditions) {
+            condition.validate().map_err(|e| {
+                ConfigError::Validation(format!("{}: {} ('{}')", section, e, condition.name))
+            })?;
+        }
+
+        // Validate TCP listener addresses
+        let binds: Vec<SocketAddr> = [
+            Some(self.server.bind),
+            self.server.tls_bind,
+            self.server.ws_bind,
+            self.server.wss_bind,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.server.extra_binds.iter().copied())
+        .collect();
+        for (i, addr) in binds.iter().enumerate() {
+            if binds[..i].contains(addr) {
+                return Err(ConfigError::Validation(format!(
+                    "{} is bound by more than one listener",
+                    addr
+                )));
+            }
+            // A dual-stack wildcard also claims the port on every IPv4 address
+            let dual_stack = self.server.ipv6_only == Some(false)
+                && addr.is_ipv6()
+                && addr.ip().is_unspecified();
+            if let Some(v4) = binds
+                .iter()
+                .find(|other| dual_stack && other.is_ipv4() && other.port() == addr.port())
+            {
+                return Err(ConfigError::Validation(format!(
+                    "dual-stack {} overlaps {}; set server.ipv6_only = true to bind both",
+                    addr, v4
+                )));
+            }
+        }
+
         // Note: 0 means unbounded for all limits
 
         // Validate user password configuration
@@ -589,8 +1414,54 @@ impl Config {
             }
         }
 
-        // Validate ACL role references
-        if self.auth.enabled && self.acl.enabled {
+        // The admin API can disconnect clients and publish, never serve it open
+        if self.admin.enabled && self.admin.token.as_deref().is_none_or(str::is_empty) {
+            return Err(ConfigError::Validation(
+                "admin.token is required when the admin API is enabled".to_string(),
+            ));
+        }
+
+        // The observer query API exposes retained payloads behind the same token
+        if self.cluster.iter().any(|c| c.enabled && c.is_observer())
+            && self.admin.token.as_deref().is_none_or(str::is_empty)
+        {
+            return Err(ConfigError::Validation(
+                "admin.token is required for the observer query API".to_string(),
+            ));
+        }
+
+        // Validate the HTTP auth webhook
+        if let Some(ref http) = self.auth.http {
+            if !http.url.starts_with("http://") {
+                return Err(ConfigError::Validation(format!(
+                    "auth.http.url must be an http:// URL, got '{}'",
+                    http.url
+                )));
+            }
+            if http.timeout.is_zero() {
+                return Err(ConfigError::Validation(
+                    "auth.http.timeout must be greater than zero".to_string(),
+                ));
+            }
+        }
+
+        // Validate tenant names
+        for user in &self.auth.users {
+            if let Some(ref tenant) = user.tenant {
+                if tenant.is_empty() || tenant.contains(crate::session::TENANT_SEPARATOR) {
+                    return Err(ConfigError::Validation(format!(
+                        "User '{}' has invalid tenant '{}' (must be non-empty without '{}')",
+                        user.username,
+                        tenant,
+                        crate::session::TENANT_SEPARATOR
+                    )));
+                }
+            }
+        }
+
+        // Validate ACL role references (a rule file's roles are only known
+        // once it's loaded)
+        if self.auth.enabled && self.acl.enabled && self.acl.rule_file.is_none() {
             let role_names: std::collections::HashSet<_> =
                 self.acl.roles.iter().map(|r| &r.name).collect();
 
@@ -606,29 +1477,487 @@ impl Config {
             }
         }
 
+        // Validate Snowflake node ID (10 bits)
+        if let Some(node_id) = self.id.node_id {
+            if node_id > crate::id::MAX_NODE_ID {
+                return Err(ConfigError::Validation(format!(
+                    "id.node_id must be between 0 and {}",
+                    crate::id::MAX_NODE_ID
+                )));
+            }
+        }
+
+        // Validate PROXY protocol trusted networks
+        for (listener, proxy) in [
+            ("proxy_protocol", &self.server.proxy_protocol),
+            ("tls_proxy_protocol", &self.server.tls_proxy_protocol),
+            ("ws_proxy_protocol", &self.server.ws_proxy_protocol),
+            ("unix_proxy_protocol", &self.server.unix_proxy_protocol),
+        ] {
+            proxy
+                .validate()
+                .map_err(|e| ConfigError::Validation(format!("server.{}: {}", listener, e)))?;
+        }
+
+        // Validate reason code remapping
+        parse_reason_map(&self.server.reason_map).map_err(ConfigError::Validation)?;
+
+        // Validate STOMP destination mappings
+        if self.stomp.enabled {
+            if self.stomp.destinations.is_empty() {
+                return Err(ConfigError::Validation(
+                    "stomp.destinations must not be empty".to_string(),
+                ));
+            }
+            for dest in &self.stomp.destinations {
+                if dest.topic.contains(['+', '#']) {
+                    return Err(ConfigError::Validation(format!(
+                        "stomp destination '{}' maps to a topic prefix with wildcards",
+                        dest.prefix
+                    )));
+                }
+            }
+        }
+
+        // Validate session checkpoint interval
+        if self.persistence.session_checkpoint == SessionCheckpoint::Interval
+            && self.persistence.session_checkpoint_interval.is_zero()
+        {
+            return Err(ConfigError::Validation(
+                "persistence.session_checkpoint_interval must be greater than 0".to_string(),
+            ));
+        }
+
+        // Validate the batch topic
+        if self.batch.enabled
+            && (self.batch.topic.is_empty() || self.batch.topic.contains(['+', '#']))
+        {
+            return Err(ConfigError::Validation(
+                "batch.topic must be a non-empty topic name without wildcards".to_string(),
+            ));
+        }
+
+        // Validate the transaction control topic
+        if self.transaction.enabled
+            && (self.transaction.topic.is_empty() || self.transaction.topic.contains(['+', '#']))
+        {
+            return Err(ConfigError::Validation(
+                "transaction.topic must be a non-empty topic name without wildcards".to_string(),
+            ));
+        }
+
+        // Validate the handover control topic and token lifetime
+        if self.handover.enabled {
+            if self.handover.topic.is_empty() || self.handover.topic.contains(['+', '#']) {
+                return Err(ConfigError::Validation(
+                    "handover.topic must be a non-empty topic name without wildcards".to_string(),
+                ));
+            }
+            if self.handover.token_ttl.is_zero() {
+                return Err(ConfigError::Validation(
+                    "handover.token_ttl must be greater than 0".to_string(),
+                ));
+            }
+        }
+
+        // Validate the retained change feed topic
+        if self.retained_feed.enabled
+            && (self.retained_feed.topic.is_empty()
+                || self.retained_feed.topic.contains(['+', '#']))
+        {
+            return Err(ConfigError::Validation(
+                "retained_feed.topic must be a non-empty topic name without wildcards".to_string(),
+            ));
+        }
+
+        // Validate the retained frame cache
+        if self.retained_cache.enabled && self.retained_cache.max_topics == 0 {
+            return Err(ConfigError::Validation(
+                "retained_cache.max_topics must be at least 1".to_string(),
+            ));
+        }
+
+        // Validate the delayed publish prefix
+        if self.delayed.enabled {
+            if self.delayed.topic.is_empty() || self.delayed.topic.contains(['+', '#']) {
+                return Err(ConfigError::Validation(
+                    "delayed.topic must be a non-empty topic name without wildcards".to_string(),
+                ));
+            }
+            if self.delayed.max_delay.is_zero() {
+                return Err(ConfigError::Validation(
+                    "delayed.max_delay must be greater than 0".to_string(),
+                ));
+            }
+        }
+
+        // Validate the session expiry event topic
+        let expiry_events = &self.session.expiry_events;
+        if expiry_events.enabled
+            && (expiry_events.topic.is_empty() || expiry_events.topic.contains(['+', '#']))
+        {
+            return Err(ConfigError::Validation(
+                "session.expiry_events.topic must be a non-empty topic name without wildcards"
+                    .to_string(),
+            ));
+        }
+
+        // Validate plugin instances
+        for (i, plugin) in self.plugins.instances.iter().enumerate() {
+            if self.plugins.instances[..i]
+                .iter()
+                .any(|p| p.name == plugin.name)
+            {
+                return Err(ConfigError::Validation(format!(
+                    "plugins: duplicate plugin name '{}'",
+                    plugin.name
+                )));
+            }
+            crate::plugin::Plugin::new(plugin.clone())
+                .map_err(|e| ConfigError::Validation(format!("plugins: {}", e)))?;
+        }
+
+        // Validate continuous profiling
+        if self.profile_history.enabled {
+            let history = &self.profile_history;
+            if history.duration.is_zero() || history.duration > history.interval {
+                return Err(ConfigError::Validation(
+                    "profile_history.duration must be greater than 0 and at most profile_history.interval"
+                        .to_string(),
+                ));
+            }
+            if history.frequency <= 0 || history.keep == 0 {
+                return Err(ConfigError::Validation(
+                    "profile_history.frequency and profile_history.keep must be greater than 0"
+                        .to_string(),
+                ));
+            }
+        }
+
+        // Validate the topic schema templates
+        if self.topic_schema.mode != TopicSchemaMode::Off && self.topic_schema.templates.is_empty()
+        {
+            return Err(ConfigError::Validation(
+                "topic_schema.templates must not be empty when topic_schema.mode is set"
+                    .to_string(),
+            ));
+        }
+        crate::topic::TopicSchema::new(&self.topic_schema)
+            .map_err(|e| ConfigError::Validation(format!("topic_schema: {}", e)))?;
+
+        // Validate message sequence numbering
+        if self.sequence.enabled {
+            if self.sequence.property.is_empty() {
+                return Err(ConfigError::Validation(
+                    "sequence.property must not be empty".to_string(),
+                ));
+            }
+            if self.sequence.reserve == 0 {
+                return Err(ConfigError::Validation(
+                    "sequence.reserve must be at least 1".to_string(),
+                ));
+            }
+            for filter in &self.sequence.topics {
+                crate::topic::validate_topic_filter(filter).map_err(|e| {
+                    ConfigError::Validation(format!("sequence.topics '{}': {}", filter, e))
+                })?;
+            }
+        }
+        // Validate priority topics
+        if self.priority.is_enabled() && self.priority.headroom == 0 {
+            return Err(ConfigError::Validation(
+                "priority.headroom must be at least 1".to_string(),
+            ));
+        }
+        for filter in &self.priority.topics {
+            crate::topic::validate_topic_filter(filter).map_err(|e| {
+                ConfigError::Validation(format!("priority.topics '{}': {}", filter, e))
+            })?;
+        }
+
+        // Validate memory pressure watermarks
+        let memory = &self.limits.memory_pressure;
+        if memory.is_enabled() {
+            if memory.low_watermark() >= memory.high_watermark {
+                return Err(ConfigError::Validation(
+                    "limits.memory_pressure.low_watermark must be below high_watermark".to_string(),
+                ));
+            }
+            if memory.check_interval.is_zero() {
+                return Err(ConfigError::Validation(
+                    "limits.memory_pressure.check_interval must be greater than 0".to_string(),
+                ));
+            }
+        }
+        for filter in &memory.low_priority_topics {
+            crate::topic::validate_topic_filter(filter).map_err(|e| {
+                ConfigError::Validation(format!(
+                    "limits.memory_pressure.low_priority_topics '{}': {}",
+                    filter, e
+                ))
+            })?;
+        }
+
+        let backpressure = &self.limits.publisher_backpressure;
+        if backpressure.enabled && backpressure.retry_after.is_zero() {
+            return Err(ConfigError::Validation(
+                "limits.publisher_backpressure.retry_after must be greater than 0".to_string(),
+            ));
+        }
+
+        // Validate backlog draining
+        let drain = &self.session.backlog_drain;
+        if drain.fair && (drain.concurrency == 0 || drain.batch_size == 0) {
+            return Err(ConfigError::Validation(
+                "session.backlog_drain.concurrency and batch_size must be greater than 0"
+                    .to_string(),
+            ));
+        }
+        if drain.fair && drain.turn_timeout.is_zero() {
+            return Err(ConfigError::Validation(
+                "session.backlog_drain.turn_timeout must be greater than 0".to_string(),
+            ));
+        }
+        let weights = self
+            .acl
+            .roles
+            .iter()
+            .map(|role| role.session.drain_weight)
+            .chain(std::iter::once(self.acl.default.session.drain_weight));
+        for weight in weights {
+            if weight == Some(0) {
+                return Err(ConfigError::Validation(
+                    "acl session drain_weight must be greater than 0".to_string(),
+                ));
+            }
+        }
+
+        // Validate cluster metrics federation
+        for cluster in &self.cluster {
+            if cluster.metrics.enabled && cluster.metrics.interval.is_zero() {
+                return Err(ConfigError::Validation(
+                    "cluster.metrics.interval must be greater than 0".to_string(),
+                ));
+            }
+        }
+
+        // Validate link compression
+        let compression = self
+            .cluster
+            .iter()
+            .map(|c| ("cluster", &c.compression))
+            .chain(self.bridge.iter().map(|b| ("bridge", &b.compression)));
+        for (link, compression) in compression {
+            if compression.enabled() && !(1..=22).contains(&compression.level) {
+                return Err(ConfigError::Validation(format!(
+                    "{}.compression.level must be between 1 and 22",
+                    link
+                )));
+            }
+        }
+
+        let replay = &self.sequence.replay;
+        if replay.enabled {
+            if !self.sequence.enabled {
+                return Err(ConfigError::Validation(
+                    "sequence.replay needs sequence.enabled".to_string(),
+                ));
+            }
+            if replay.topic.is_empty() {
+                return Err(ConfigError::Validation(
+                    "sequence.replay.topic must not be empty".to_string(),
+                ));
+            }
+            if replay.retain == 0 || replay.max_messages == 0 {
+                return Err(ConfigError::Validation(
+                    "sequence.replay.retain and max_messages must be at least 1".to_string(),
+                ));
+            }
+            for filter in &replay.topics {
+                crate::topic::validate_topic_filter(filter).map_err(|e| {
+                    ConfigError::Validation(format!("sequence.replay.topics '{}': {}", filter, e))
+                })?;
+            }
+        }
+
+        // Validate OCPP topic templates
+        if self.ocpp.enabled {
+            crate::ocpp::OcppRouter::new(&self.ocpp)
+                .map_err(|e| ConfigError::Validation(format!("ocpp: {}", e)))?;
+        }
+
+        // Validate scheduled publishes
+        for (i, schedule) in self.schedule.iter().enumerate() {
+            if schedule.name.is_empty() {
+                return Err(ConfigError::Validation(
+                    "schedule.name must not be empty".to_string(),
+                ));
+            }
+            if self.schedule[..i].iter().any(|s| s.name == schedule.name) {
+                return Err(ConfigError::Validation(format!(
+                    "schedule '{}' is defined more than once",
+                    schedule.name
+                )));
+            }
+            crate::schedule::ScheduledPublish::new(schedule).map_err(|e| {
+                ConfigError::Validation(format!("schedule '{}': {}", schedule.name, e))
+            })?;
+        }
+
+        // Validate maintenance windows
+        for (i, window) in self.maintenance.iter().enumerate() {
+            if window.name.is_empty() {
+                return Err(ConfigError::Validation(
+                    "maintenance.name must not be empty".to_string(),
+                ));
+            }
+            if self.maintenance[..i].iter().any(|w| w.name == window.name) {
+                return Err(ConfigError::Validation(format!(
+                    "maintenance window '{}' is defined more than once",
+                    window.name
+                )));
+            }
+            crate::broker::MaintenanceWindow::new(window).map_err(|e| {
+                ConfigError::Validation(format!("maintenance window '{}': {}", window.name, e))
+            })?;
+        }
+
+        // Validate aggregations
+        for (i, aggregate) in self.aggregate.iter().enumerate() {
+            if aggregate.name.is_empty() {
+                return Err(ConfigError::Validation(
+                    "aggregate.name must not be empty".to_string(),
+                ));
+            }
+            if self.aggregate[..i].iter().any(|a| a.name == aggregate.name) {
+                return Err(ConfigError::Validation(format!(
+                    "aggregate '{}' is defined more than once",
+                    aggregate.name
+                )));
+            }
+            crate::aggregate::Aggregation::new(aggregate).map_err(|e| {
+                ConfigError::Validation(format!("aggregate '{}': {}", aggregate.name, e))
+            })?;
+        }
+
+        // Validate geofences
+        for (i, geofence) in self.geofence.iter().enumerate() {
+            if geofence.name.is_empty() {
+                return Err(ConfigError::Validation(
+                    "geofence.name must not be empty".to_string(),
+                ));
+            }
+            if self.geofence[..i].iter().any(|g| g.name == geofence.name) {
+                return Err(ConfigError::Validation(format!(
+                    "geofence '{}' is defined more than once",
+                    geofence.name
+                )));
+            }
+            crate::geofence::Geofence::new(geofence).map_err(|e| {
+                ConfigError::Validation(format!("geofence '{}': {}", geofence.name, e))
+            })?;
+        }
+
+        // Validate enrichment rules
+        for (i, enrich) in self.enrich.iter().enumerate() {
+            if enrich.name.is_empty() {
+                return Err(ConfigError::Validation(
+                    "enrich.name must not be empty".to_string(),
+                ));
+            }
+            if self.enrich[..i].iter().any(|e| e.name == enrich.name) {
+                return Err(ConfigError::Validation(format!(
+                    "enrich '{}' is defined more than once",
+                    enrich.name
+                )));
+            }
+            crate::enrich::Enrichment::new(enrich)
+                .map_err(|e| ConfigError::Validation(format!("enrich '{}': {}", enrich.name, e)))?;
+        }
+
+        // Validate routing rules
+        for (i, rule) in self.rule.iter().enumerate() {
+            if rule.name.is_empty() {
+                return Err(ConfigError::Validation(
+                    "rule.name must not be empty".to_string(),
+                ));
+            }
+            if self.rule[..i].iter().any(|r| r.name == rule.name) {
+                return Err(ConfigError::Validation(format!(
+                    "rule '{}' is defined more than once",
+                    rule.name
+                )));
+            }
+            crate::rules::Rule::new(rule)
+                .map_err(|e| ConfigError::Validation(format!("rule '{}': {}", rule.name, e)))?;
+        }
+
+        if self.server.ws_max_frame_size == Some(0) {
+            return Err(ConfigError::Validation(
+                "server.ws_max_frame_size must be at least 1".to_string(),
+            ));
+        }
+
         // Validate TLS configuration
-        if self.server.tls_bind.is_some() {
+        if self.server.tls_bind.is_some()
+            || self.server.wss_bind.is_some()
+            || self.server.quic_bind.is_some()
+        {
             match &self.server.tls {
                 Some(tls) => {
                     if tls.cert.is_empty() {
                         return Err(ConfigError::Validation(
-                            "tls.cert is required when tls_bind is set".to_string(),
+                            "tls.cert is required when tls_bind, wss_bind or quic_bind is set"
+                                .to_string(),
                         ));
                     }
                     if tls.key.is_empty() {
                         return Err(ConfigError::Validation(
-                            "tls.key is required when tls_bind is set".to_string(),
+                            "tls.key is required when tls_bind, wss_bind or quic_bind is set"
+                                .to_string(),
+                        ));
+                    }
+                    if tls.handshake_threads > 0 && tls.handshake_queue_size == 0 {
+                        return Err(ConfigError::Validation(
+                            "tls.handshake_queue_size must be at least 1 when handshake_threads is set"
+                                .to_string(),
+                        ));
+                    }
+                    if tls.handshake_threads > 0 && tls.handshake_timeout.is_zero() {
+                        return Err(ConfigError::Validation(
+                            "tls.handshake_timeout must be greater than 0".to_string(),
                         ));
                     }
                 }
                 None => {
                     return Err(ConfigError::Validation(
-                        "tls configuration is required when tls_bind is set".to_string(),
+                        "tls configuration is required when tls_bind, wss_bind or quic_bind is set"
+                            .to_string(),
                     ));
                 }
             }
         }
 
+        // Validate QUIC listener
+        if self.server.quic_bind.is_some() {
+            let quic = &self.server.quic;
+            if quic.alpn.is_empty() || quic.alpn.iter().any(|p| p.is_empty() || p.len() > 255) {
+                return Err(ConfigError::Validation(
+                    "server.quic.alpn must list protocols of 1-255 bytes".to_string(),
+                ));
+            }
+            if quic.max_streams == 0 {
+                return Err(ConfigError::Validation(
+                    "server.quic.max_streams must be at least 1".to_string(),
+                ));
+            }
+            if quic.idle_timeout.is_zero() {
+                return Err(ConfigError::Validation(
+                    "server.quic.idle_timeout must be positive".to_string(),
+                ));
+            }
+        }
+
         Ok(())
     }
 
@@ -0,0 +1,41 @@
+//! OCPP Topic Conventions Configuration
+//!
+//! Configuration for the OCPP-over-MQTT helper that validates charge-point
+//! topics and tracks charge-point presence.
+
+use serde::Deserialize;
+
+/// OCPP topic conventions configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OcppConfig {
+    /// Enable OCPP topic validation and presence tracking
+    pub enabled: bool,
+    /// Topic carrying messages from a charge point to the CSMS.
+    /// Must contain `{cp_id}` as a whole topic level (default: "ocpp/{cp_id}/up")
+    pub uplink_topic: String,
+    /// Topic carrying messages from the CSMS to a charge point.
+    /// Must contain `{cp_id}` as a whole topic level (default: "ocpp/{cp_id}/down")
+    pub downlink_topic: String,
+    /// Require the charge-point ID to equal the client ID (or username) when
+    /// publishing on an uplink topic or subscribing to a downlink topic
+    pub enforce_identity: bool,
+    /// Client IDs of CSMS backends, exempt from identity checks. When non-empty,
+    /// only these clients may publish downlink or subscribe to uplink topics.
+    pub csms_clients: Vec<String>,
+    /// Maximum charge-point ID length (OCPP identities are at most 48 characters)
+    pub max_charge_point_id_len: usize,
+}
+
+impl Default for OcppConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            uplink_topic: "ocpp/{cp_id}/up".to_string(),
+            downlink_topic: "ocpp/{cp_id}/down".to_string(),
+            enforce_identity: true,
+            csms_clients: Vec::new(),
+            max_charge_point_id_len: 48,
+        }
+    }
+}
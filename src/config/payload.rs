@@ -0,0 +1,48 @@
+//! Payload Format Configuration
+//!
+//! Payload-aware features (aggregations, geofences) read fields out of
+//! message payloads. A `payload` section tells them how the payloads are
+//! encoded and how to encode what they publish.
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+/// Encoding of message payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    #[default]
+    Json,
+    Cbor,
+    Msgpack,
+    /// Needs a descriptor set and message type
+    Protobuf,
+}
+
+impl PayloadFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayloadFormat::Json => "json",
+            PayloadFormat::Cbor => "cbor",
+            PayloadFormat::Msgpack => "msgpack",
+            PayloadFormat::Protobuf => "protobuf",
+        }
+    }
+}
+
+/// How payloads are decoded and outputs encoded
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PayloadConfig {
+    /// Format of the incoming payloads
+    pub format: PayloadFormat,
+    /// Compiled descriptor set (`protoc --include_imports
+    /// --descriptor_set_out=...`), for protobuf
+    pub descriptor: Option<PathBuf>,
+    /// Fully qualified message type of the payloads ("telemetry.Reading"),
+    /// for protobuf
+    pub message: Option<String>,
+    /// Format of the published results and events (json, cbor or msgpack)
+    pub output_format: PayloadFormat,
+}
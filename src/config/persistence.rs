@@ -6,19 +6,38 @@ use std::time::Duration;
 use serde::Deserialize;
 
 /// Backend type for persistence
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum BackendType {
     /// Fjall (local LSM-tree storage)
     #[default]
     Fjall,
+    /// Process memory only (nothing survives a restart)
+    Memory,
     // Future: Redis, Postgres, etc.
 }
 
+/// When per-session inflight/queue state is checkpointed to storage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionCheckpoint {
+    /// After every change to inflight messages, queued messages or subscriptions
+    EveryChange,
+    /// At most once per `session_checkpoint_interval` while the session has changes
+    Interval,
+    /// Only when the client disconnects
+    #[default]
+    Disconnect,
+}
+
 fn default_flush_interval() -> Duration {
     Duration::from_millis(100)
 }
 
+fn default_session_checkpoint_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
 /// Persistence configuration
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
@@ -38,6 +57,21 @@ pub struct PersistenceConfig {
 
     /// Maximum batch size before forced flush
     pub max_batch_size: usize,
+
+    /// When session state is checkpointed: "every_change", "interval" or "disconnect".
+    /// Sessions are always checkpointed on disconnect.
+    pub session_checkpoint: SessionCheckpoint,
+
+    /// Checkpoint interval for `session_checkpoint = "interval"` (e.g., "500ms", "5s")
+    #[serde(
+        default = "default_session_checkpoint_interval",
+        with = "humantime_serde"
+    )]
+    pub session_checkpoint_interval: Duration,
+
+    /// Copy the data directory aside (`<path>.v<N>.backup`) before
+    /// migrating it to a newer format version
+    pub backup_before_migrate: bool,
 }
 
 impl Default for PersistenceConfig {
@@ -48,6 +82,9 @@ impl Default for PersistenceConfig {
             path: PathBuf::from("./data"),
             flush_interval: Duration::from_millis(100),
             max_batch_size: 100,
+            session_checkpoint: SessionCheckpoint::Disconnect,
+            session_checkpoint_interval: default_session_checkpoint_interval(),
+            backup_before_migrate: true,
         }
     }
 }
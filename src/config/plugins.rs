@@ -0,0 +1,142 @@
+//! Plugin Configuration
+//!
+//! Configuration for external hook plugins: HTTP endpoints consulted on
+//! connects, publishes and subscribes, or told about client events. The
+//! instances listed here are loaded at startup; the admin API adds, replaces
+//! and removes them at runtime.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// A hook a plugin is called for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginEvent {
+    /// CONNECT credentials; a deny refuses the client
+    Authenticate,
+    /// Publishes (and wills); a deny refuses the message
+    PublishCheck,
+    /// Subscriptions; a deny refuses the filter
+    SubscribeCheck,
+    ClientConnected,
+    ClientDisconnected,
+    MessagePublished,
+}
+
+impl PluginEvent {
+    /// Whether the plugin's answer decides something
+    pub fn is_check(self) -> bool {
+        matches!(
+            self,
+            PluginEvent::Authenticate | PluginEvent::PublishCheck | PluginEvent::SubscribeCheck
+        )
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PluginEvent::Authenticate => "authenticate",
+            PluginEvent::PublishCheck => "publish_check",
+            PluginEvent::SubscribeCheck => "subscribe_check",
+            PluginEvent::ClientConnected => "client_connected",
+            PluginEvent::ClientDisconnected => "client_disconnected",
+            PluginEvent::MessagePublished => "message_published",
+        }
+    }
+}
+
+/// What a check does when the plugin can't be reached, times out, answers
+/// with an unexpected status, or its circuit breaker is open
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginFailure {
+    /// Refuse, as if the plugin had denied
+    #[default]
+    Deny,
+    /// Carry on as if the plugin had allowed
+    Allow,
+}
+
+/// An external hook plugin
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PluginConfig {
+    /// Unique name, used by the admin API
+    pub name: String,
+    /// Endpoint each call is POSTed to as JSON (http:// only)
+    pub url: String,
+    /// Hooks the plugin is called for
+    pub events: Vec<PluginEvent>,
+    /// Time allowed for each call
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    /// Higher priorities are consulted first
+    pub priority: i32,
+    /// Topic filters the plugin applies to (empty: every topic)
+    pub topics: Vec<String>,
+    /// Listeners the plugin applies to (empty: every listener)
+    pub listeners: Vec<String>,
+    /// Extra request headers (e.g. an Authorization token)
+    pub headers: HashMap<String, String>,
+    pub on_failure: PluginFailure,
+    /// `on_failure` for particular checks, e.g. fail open on
+    /// `publish_check` but closed on `authenticate`
+    pub on_failure_by_event: HashMap<PluginEvent, PluginFailure>,
+    /// Consecutive failed calls (errors or timeouts) that open the circuit
+    /// breaker (0 = never)
+    pub circuit_failures: u32,
+    /// How long an open breaker answers calls without the plugin before
+    /// trying it again
+    #[serde(with = "humantime_serde")]
+    pub circuit_cooldown: Duration,
+}
+
+impl PluginConfig {
+    /// What a failed `event` check does
+    pub fn failure_mode(&self, event: PluginEvent) -> PluginFailure {
+        self.on_failure_by_event
+            .get(&event)
+            .copied()
+            .unwrap_or(self.on_failure)
+    }
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            url: String::new(),
+            events: Vec::new(),
+            timeout: Duration::from_secs(2),
+            priority: 0,
+            topics: Vec::new(),
+            listeners: Vec::new(),
+            headers: HashMap::new(),
+            on_failure: PluginFailure::Deny,
+            on_failure_by_event: HashMap::new(),
+            circuit_failures: 5,
+            circuit_cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Plugin configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PluginsConfig {
+    /// How long removing or replacing a plugin waits for its calls in
+    /// flight to finish
+    #[serde(with = "humantime_serde")]
+    pub drain_timeout: Duration,
+    pub instances: Vec<PluginConfig>,
+}
+
+impl Default for PluginsConfig {
+    fn default() -> Self {
+        Self {
+            drain_timeout: Duration::from_secs(10),
+            instances: Vec::new(),
+        }
+    }
+}
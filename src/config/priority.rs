@@ -0,0 +1,32 @@
+//! Priority Topic Configuration
+//!
+//! Configuration for latency-critical topics (device commands, control
+//! messages), which are delivered ahead of ordinary traffic.
+
+use serde::Deserialize;
+
+/// Priority topic configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PriorityConfig {
+    /// Topic filters whose messages take the priority path (empty = none)
+    pub topics: Vec<String>,
+    /// Messages each connection's priority channel holds, on top of its
+    /// ordinary `outbound_channel_capacity` (default: 64)
+    pub headroom: usize,
+}
+
+impl Default for PriorityConfig {
+    fn default() -> Self {
+        Self {
+            topics: Vec::new(),
+            headroom: 64,
+        }
+    }
+}
+
+impl PriorityConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.topics.is_empty()
+    }
+}
@@ -0,0 +1,77 @@
+//! Configuration Profiles
+//!
+//! `profile = "<name>"` at the top of the config file starts from a bundled
+//! set of defaults for a kind of deployment instead of the built-in ones.
+//! Settings are layered, each layer overriding the ones before it:
+//!
+//! 1. built-in defaults
+//! 2. the profile
+//! 3. the config file
+//! 4. `VIBEMQ__*` environment variables (`VIBEMQ__PROFILE` selects the
+//!    profile too)
+//!
+//! Tables merge key by key, so setting `limits.max_connections` in the file
+//! keeps the rest of the profile's `[limits]`; arrays are replaced whole.
+//!
+//! Bundled profiles:
+//! - `edge`: small memory footprint (small queues and buffers, idle
+//!   connections hibernated, memory pressure eviction at 128 MiB) and
+//!   session state persisted on every change
+//! - `cloud`: high concurrency (a million connections, deep queues) with
+//!   periodic checkpoints and metrics enabled
+//! - `test`: ephemeral everything: in-memory persistence, short session
+//!   expiry, loopback listener
+
+use toml::Table;
+
+use super::ConfigError;
+
+/// Bundled profiles by name, in the order they are documented
+pub const PROFILES: &[(&str, &str)] = &[
+    ("edge", include_str!("profiles/edge.toml")),
+    ("cloud", include_str!("profiles/cloud.toml")),
+    ("test", include_str!("profiles/test.toml")),
+];
+
+/// Environment variable selecting a profile, over the file's `profile`
+const PROFILE_VAR: &str = "VIBEMQ__PROFILE";
+
+/// The settings of a bundled profile, as TOML
+pub fn profile(name: &str) -> Result<&'static str, ConfigError> {
+    let Some((_, content)) = PROFILES.iter().find(|(n, _)| *n == name) else {
+        let names: Vec<_> = PROFILES.iter().map(|(n, _)| *n).collect();
+        return Err(ConfigError::Validation(format!(
+            "unknown profile '{}' (expected one of: {})",
+            name,
+            names.join(", ")
+        )));
+    };
+    Ok(content)
+}
+
+/// Name of the profile `table` (a config file) selects
+pub(crate) fn selected(table: &Table) -> Option<&str> {
+    table.get("profile").and_then(|p| p.as_str())
+}
+
+/// Profile selected by the environment, then by the file's contents (which
+/// may not parse yet; the error is reported when the file is loaded)
+pub(crate) fn selected_by(content: Option<&str>) -> Option<String> {
+    if let Ok(name) = std::env::var(PROFILE_VAR) {
+        return Some(name);
+    }
+    let table: Table = toml::from_str(content?).ok()?;
+    selected(&table).map(str::to_string)
+}
+
+/// Layer `overlay` over `base`, merging tables key by key
+pub(crate) fn merge(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Profile History Configuration
+//!
+//! Configuration for continuous profiling: short CPU profiles collected on
+//! an interval and kept on disk, so the profile from the time of an
+//! incident is still there afterwards. Needs a build with the `pprof`
+//! feature.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Continuous profiling configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ProfileHistoryConfig {
+    pub enabled: bool,
+    /// Directory the profiles are kept in (default: "profiles")
+    pub dir: PathBuf,
+    /// How often a profile is collected (default: 1m)
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    /// How long each profile samples (default: 10s)
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+    /// Sampling frequency in Hz, low to keep the overhead down (default: 19)
+    pub frequency: i32,
+    /// Profiles kept; the oldest is deleted for each new one (default: 360)
+    pub keep: usize,
+}
+
+impl Default for ProfileHistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: PathBuf::from("profiles"),
+            interval: Duration::from_secs(60),
+            duration: Duration::from_secs(10),
+            frequency: 19,
+            keep: 360,
+        }
+    }
+}
@@ -2,7 +2,9 @@
 //!
 //! Configuration types for HAProxy PROXY protocol v1/v2 support.
 
-use serde::Deserialize;
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer};
+use std::net::IpAddr;
 use std::time::Duration;
 
 /// PROXY protocol configuration for a listener
@@ -20,6 +22,23 @@ pub struct ProxyProtocolConfig {
     /// Default: 5s
     #[serde(default = "default_timeout", with = "humantime_serde")]
     pub timeout: Duration,
+
+    /// Peers allowed to send PROXY headers (CIDR ranges or single IPs,
+    /// parsed when the config is loaded). Connections from other peers are
+    /// treated as direct and keep their socket address. Empty = trust every
+    /// peer.
+    #[serde(deserialize_with = "deserialize_networks")]
+    pub trusted_networks: Vec<IpNet>,
+
+    /// Accept connections both with and without a PROXY header.
+    /// Connections without one are treated as direct. Requires
+    /// `trusted_networks`, or any direct client could claim another address.
+    pub optional: bool,
+
+    /// Stamp the proxied client's identity on its wills and retained
+    /// messages as an `mqtt-forwarded-for` user property, replacing any
+    /// the client set itself
+    pub forwarded_for: bool,
 }
 
 fn default_timeout() -> Duration {
@@ -32,6 +51,75 @@ impl Default for ProxyProtocolConfig {
             enabled: false,
             tls_termination: false,
             timeout: Duration::from_secs(5),
+            trusted_networks: Vec::new(),
+            optional: false,
+            forwarded_for: false,
+        }
+    }
+}
+
+impl ProxyProtocolConfig {
+    /// Whether a PROXY header from this peer should be honored
+    ///
+    /// v4-mapped IPv6 peers match IPv4 networks.
+    pub fn is_trusted(&self, peer: IpAddr) -> bool {
+        let peer = peer.to_canonical();
+        self.trusted_networks.is_empty()
+            || self.trusted_networks.iter().any(|net| net.contains(&peer))
+    }
+
+    /// Check that a listener taking direct clients only trusts its proxies
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.enabled && self.optional && self.trusted_networks.is_empty() {
+            return Err(
+                "optional requires trusted_networks (direct clients could spoof their address)"
+                    .to_string(),
+            );
         }
+        Ok(())
+    }
+}
+
+/// Parse a CIDR range, or a single IP as a host range
+fn parse_network(s: &str) -> Option<IpNet> {
+    s.parse()
+        .ok()
+        .or_else(|| s.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+fn deserialize_networks<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<IpNet>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| {
+            parse_network(s)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid trusted network '{}'", s)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trusted_networks() {
+        let mut config = ProxyProtocolConfig::default();
+        assert!(config.is_trusted("203.0.113.7".parse().unwrap()));
+
+        config.trusted_networks = ["10.0.0.0/8", "192.168.1.5"]
+            .into_iter()
+            .filter_map(parse_network)
+            .collect();
+        assert_eq!(config.trusted_networks.len(), 2);
+        assert!(config.is_trusted("10.1.2.3".parse().unwrap()));
+        assert!(config.is_trusted("192.168.1.5".parse().unwrap()));
+        assert!(!config.is_trusted("192.168.1.6".parse().unwrap()));
+        assert!(!config.is_trusted("203.0.113.7".parse().unwrap()));
+        // Proxies reaching a dual-stack listener over IPv4
+        assert!(config.is_trusted("::ffff:10.1.2.3".parse().unwrap()));
+
+        assert!(parse_network("10.0.0.0/33").is_none());
     }
 }
@@ -0,0 +1,35 @@
+//! Publisher Backpressure Configuration
+//!
+//! Configuration for slowing down MQTT v5 publishers whose subscribers
+//! can't keep up, with flow control and Quota exceeded acknowledgements
+//! instead of pausing their connection.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Publisher backpressure configuration (`[limits.publisher_backpressure]`)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PublisherBackpressureConfig {
+    /// Hold the acknowledgements of QoS 1/2 messages from MQTT v5 clients
+    /// routed to congested subscribers, and reject them past `max_delay`
+    pub enabled: bool,
+    /// Longest an acknowledgement is held for congested subscribers to
+    /// catch up before the message is rejected (default: 500ms)
+    #[serde(with = "humantime_serde")]
+    pub max_delay: Duration,
+    /// Pause advised to a publisher whose message was rejected (default: 1s)
+    #[serde(with = "humantime_serde")]
+    pub retry_after: Duration,
+}
+
+impl Default for PublisherBackpressureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_delay: Duration::from_millis(500),
+            retry_after: Duration::from_secs(1),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! QUIC Listener Configuration
+//!
+//! `[server.quic]` tunes the experimental MQTT-over-QUIC listener enabled
+//! by `[server] quic_bind`. It uses the `[server.tls]` certificate and the
+//! TLS listener's settings (`tls_allow_mqtt31`, `tls_max_qos`,
+//! `tls_capabilities`, `tls_error_detail`).
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Settings of the QUIC listener
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct QuicConfig {
+    /// Let resuming clients send their CONNECT as 0-RTT data. Early data
+    /// can be replayed by an attacker, so only enable this where a
+    /// repeated CONNECT (or the packets after it) is harmless
+    pub zero_rtt: bool,
+    /// ALPN protocols offered; clients must ask for one of them
+    pub alpn: Vec<String>,
+    /// Keep connections across client address changes (e.g. a phone
+    /// switching networks)
+    pub migration: bool,
+    /// Close connections silent for this long
+    #[serde(with = "humantime_serde")]
+    pub idle_timeout: Duration,
+    /// Send QUIC pings this often, so idle MQTT sessions don't hit
+    /// `idle_timeout` (0 = never)
+    #[serde(with = "humantime_serde")]
+    pub keep_alive_interval: Duration,
+    /// Bidirectional streams a client may open, one MQTT connection each
+    pub max_streams: u32,
+}
+
+impl Default for QuicConfig {
+    fn default() -> Self {
+        Self {
+            zero_rtt: false,
+            alpn: vec!["mqtt".to_string()],
+            migration: true,
+            idle_timeout: Duration::from_secs(60),
+            keep_alive_interval: Duration::from_secs(15),
+            max_streams: 1,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Client Quota Configuration
+//!
+//! Per-connection limits on what a client may do: publish rate (messages
+//! and bytes per second), in-flight QoS 2 messages, subscription count and
+//! payload size. Limits are set broker-wide, per listener, per ACL role
+//! (`session.quota`) and per username, each level overriding the fields it
+//! sets. A client exceeding a limit is disconnected with reason Quota
+//! exceeded (0x97).
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// One level of quota limits; unset fields inherit, 0 = unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct QuotaLimits {
+    /// PUBLISH packets per second (bursts up to one second's worth)
+    pub messages_per_sec: Option<u32>,
+    /// PUBLISH payload bytes per second (bursts up to one second's worth)
+    pub bytes_per_sec: Option<u64>,
+    /// QoS 2 messages awaiting PUBREL; also caps the Receive Maximum
+    /// advertised in CONNACK and the messages in flight to the client
+    pub max_inflight: Option<u16>,
+    /// Subscriptions held by the client's session
+    pub max_subscriptions: Option<usize>,
+    /// Payload size of a single PUBLISH
+    pub max_payload_size: Option<usize>,
+}
+
+impl QuotaLimits {
+    /// Layer `other` over these limits (fields set in `other` win)
+    pub fn merge(self, other: &QuotaLimits) -> QuotaLimits {
+        QuotaLimits {
+            messages_per_sec: other.messages_per_sec.or(self.messages_per_sec),
+            bytes_per_sec: other.bytes_per_sec.or(self.bytes_per_sec),
+            max_inflight: other.max_inflight.or(self.max_inflight),
+            max_subscriptions: other.max_subscriptions.or(self.max_subscriptions),
+            max_payload_size: other.max_payload_size.or(self.max_payload_size),
+        }
+    }
+
+    /// Whether no limit applies
+    pub fn is_unlimited(&self) -> bool {
+        self.messages_per_sec.unwrap_or(0) == 0
+            && self.bytes_per_sec.unwrap_or(0) == 0
+            && self.max_inflight.unwrap_or(0) == 0
+            && self.max_subscriptions.unwrap_or(0) == 0
+            && self.max_payload_size.unwrap_or(0) == 0
+    }
+}
+
+/// Quota limits with their per-listener and per-user overrides
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    /// Limits for every client
+    pub default: QuotaLimits,
+    /// Overrides by listener ("tcp", "tls", "ws", "wss", "unix", "quic")
+    pub listeners: HashMap<String, QuotaLimits>,
+    /// Overrides by username, applied over the listener's
+    pub users: HashMap<String, QuotaLimits>,
+}
+
+impl QuotaConfig {
+    /// Limits for a client on `listener`, authenticated as `username`
+    pub fn resolve(&self, listener: &str, username: Option<&str>) -> QuotaLimits {
+        self.resolve_with_role(listener, &QuotaLimits::default(), username)
+    }
+
+    /// Limits for a client on `listener` with its ACL role's quota (see
+    /// `SessionPolicy`), authenticated as `username`
+    pub fn resolve_with_role(
+        &self,
+        listener: &str,
+        role: &QuotaLimits,
+        username: Option<&str>,
+    ) -> QuotaLimits {
+        let mut limits = self.default;
+        if let Some(overrides) = self.listeners.get(listener) {
+            limits = limits.merge(overrides);
+        }
+        limits = limits.merge(role);
+        if let Some(overrides) = username.and_then(|u| self.users.get(u)) {
+            limits = limits.merge(overrides);
+        }
+        limits
+    }
+}
@@ -0,0 +1,69 @@
+//! Publish Rate Limit Configuration
+//!
+//! Token-bucket limits on the messages a client may publish. Buckets are
+//! keyed by identity (username, TLS certificate CN, or client ID), so
+//! reconnecting doesn't restore a client's quota.
+//!
+//! Selected identities can instead be governed by an external quota service
+//! through the `on_rate_limit` hook, with the local buckets as a fallback
+//! when the service doesn't answer in time.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Publish rate limit configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PublishRateConfig {
+    /// Messages per second allowed per identity (0 = disabled)
+    pub messages_per_sec: u32,
+    /// Messages an identity may publish in a burst (default: 100)
+    pub burst: u32,
+    /// Persist buckets that aren't full so limits survive a restart
+    pub persist: bool,
+    /// How often changed buckets are persisted and shared with cluster
+    /// peers (e.g., "5s")
+    #[serde(with = "humantime_serde")]
+    pub sync_interval: Duration,
+    /// Identities whose limits are decided by the `on_rate_limit` hook
+    /// (e.g., "user:alice", or "cn:fleet-*" with a trailing wildcard)
+    pub external: Vec<String>,
+    /// How long to wait for the hook before using the local bucket
+    #[serde(with = "humantime_serde")]
+    pub external_timeout: Duration,
+    /// Report the limit to v5 clients as CONNACK user properties
+    /// (`rate-limit-messages-per-sec`, `rate-limit-burst`)
+    pub advertise: bool,
+}
+
+impl Default for PublishRateConfig {
+    fn default() -> Self {
+        Self {
+            messages_per_sec: 0,
+            burst: 100,
+            persist: true,
+            sync_interval: Duration::from_secs(5),
+            external: Vec::new(),
+            external_timeout: Duration::from_millis(100),
+            advertise: false,
+        }
+    }
+}
+
+impl PublishRateConfig {
+    /// Whether publish rate limiting is enabled
+    pub fn enabled(&self) -> bool {
+        self.messages_per_sec > 0
+    }
+
+    /// Whether the identity's limit is delegated to the `on_rate_limit` hook
+    pub fn is_external(&self, identity: &str) -> bool {
+        self.external
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => identity.starts_with(prefix),
+                None => identity == pattern,
+            })
+    }
+}
@@ -0,0 +1,164 @@
+//! Configuration Diffing
+//!
+//! On reload the running config is compared with the newly loaded one,
+//! section by section, to report which changes took effect and which only
+//! apply after a restart.
+
+use std::fmt::Debug;
+
+use super::Config;
+
+/// Config sections (and fields) that changed between two configs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigDiff {
+    /// Changed sections applied on reload
+    pub applied: Vec<&'static str>,
+    /// Changed sections and fields only read at startup
+    pub restart_required: Vec<&'static str>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.restart_required.is_empty()
+    }
+}
+
+/// Whether two values differ (configs are compared by their Debug output)
+fn changed<T: Debug>(old: &T, new: &T) -> bool {
+    format!("{:?}", old) != format!("{:?}", new)
+}
+
+impl Config {
+    /// Compare with a newly loaded config
+    ///
+    /// Reloadable sections are reported as applied when anything in them
+    /// other than their startup-only fields changed; those fields, and every
+    /// other section, are reported as requiring a restart.
+    pub fn diff(&self, new: &Config) -> ConfigDiff {
+        let mut diff = ConfigDiff::default();
+
+        let mut server = self.server.clone();
+        server.tls = new.server.tls.clone();
+        server.quic = new.server.quic.clone();
+        server.workers = new.server.workers;
+        let mut limits = self.limits.clone();
+        limits.flapping_detect = new.limits.flapping_detect.clone();
+        limits.connection_limit = new.limits.connection_limit.clone();
+        limits.publish_rate = new.limits.publish_rate.clone();
+        let mut session = self.session.clone();
+        session.expiry_check_interval = new.session.expiry_check_interval;
+        session.compress_idle_after = new.session.compress_idle_after;
+        let mut mqtt = self.mqtt.clone();
+        mqtt.shared_subscription_strategy = new.mqtt.shared_subscription_strategy;
+        mqtt.sys_topics = new.mqtt.sys_topics;
+        mqtt.sys_interval = new.mqtt.sys_interval;
+        mqtt.max_local_hops = new.mqtt.max_local_hops;
+        mqtt.routing_ids = new.mqtt.routing_ids;
+
+        for (name, differs) in [
+            ("log", changed(&self.log, &new.log)),
+            ("server", changed(&server, &new.server)),
+            ("limits", changed(&limits, &new.limits)),
+            ("session", changed(&session, &new.session)),
+            ("mqtt", changed(&mqtt, &new.mqtt)),
+            ("auth", changed(&self.auth, &new.auth)),
+            ("acl", changed(&self.acl, &new.acl)),
+            ("batch", changed(&self.batch, &new.batch)),
+            ("transaction", changed(&self.transaction, &new.transaction)),
+            ("handover", changed(&self.handover, &new.handover)),
+            ("shutdown", changed(&self.shutdown, &new.shutdown)),
+        ] {
+            if differs {
+                diff.applied.push(name);
+            }
+        }
+
+        for (name, differs) in [
+            ("server.tls", changed(&self.server.tls, &new.server.tls)),
+            ("server.quic", changed(&self.server.quic, &new.server.quic)),
+            ("server.workers", self.server.workers != new.server.workers),
+            (
+                "limits.flapping_detect",
+                changed(&self.limits.flapping_detect, &new.limits.flapping_detect),
+            ),
+            (
+                "limits.connection_limit",
+                changed(&self.limits.connection_limit, &new.limits.connection_limit),
+            ),
+            (
+                "limits.publish_rate",
+                changed(&self.limits.publish_rate, &new.limits.publish_rate),
+            ),
+            (
+                "session.expiry_check_interval",
+                self.session.expiry_check_interval != new.session.expiry_check_interval,
+            ),
+            (
+                "session.compress_idle_after",
+                self.session.compress_idle_after != new.session.compress_idle_after,
+            ),
+            (
+                "mqtt.shared_subscription_strategy",
+                self.mqtt.shared_subscription_strategy != new.mqtt.shared_subscription_strategy,
+            ),
+            (
+                "mqtt.sys_topics",
+                self.mqtt.sys_topics != new.mqtt.sys_topics,
+            ),
+            (
+                "mqtt.sys_interval",
+                self.mqtt.sys_interval != new.mqtt.sys_interval,
+            ),
+            (
+                "mqtt.max_local_hops",
+                self.mqtt.max_local_hops != new.mqtt.max_local_hops,
+            ),
+            (
+                "mqtt.routing_ids",
+                self.mqtt.routing_ids != new.mqtt.routing_ids,
+            ),
+            ("bridge", changed(&self.bridge, &new.bridge)),
+            ("cluster", changed(&self.cluster, &new.cluster)),
+            ("metrics", changed(&self.metrics, &new.metrics)),
+            ("admin", changed(&self.admin, &new.admin)),
+            ("persistence", changed(&self.persistence, &new.persistence)),
+            ("id", changed(&self.id, &new.id)),
+            ("stomp", changed(&self.stomp, &new.stomp)),
+            ("ocpp", changed(&self.ocpp, &new.ocpp)),
+            (
+                "retained_feed",
+                changed(&self.retained_feed, &new.retained_feed),
+            ),
+            (
+                "retained_cache",
+                changed(&self.retained_cache, &new.retained_cache),
+            ),
+            ("delayed", changed(&self.delayed, &new.delayed)),
+            ("health", changed(&self.health, &new.health)),
+            ("topic_tree", changed(&self.topic_tree, &new.topic_tree)),
+            ("plugins", changed(&self.plugins, &new.plugins)),
+            (
+                "profile_history",
+                changed(&self.profile_history, &new.profile_history),
+            ),
+            (
+                "topic_schema",
+                changed(&self.topic_schema, &new.topic_schema),
+            ),
+            ("sequence", changed(&self.sequence, &new.sequence)),
+            ("priority", changed(&self.priority, &new.priority)),
+            ("schedule", changed(&self.schedule, &new.schedule)),
+            ("maintenance", changed(&self.maintenance, &new.maintenance)),
+            ("aggregate", changed(&self.aggregate, &new.aggregate)),
+            ("geofence", changed(&self.geofence, &new.geofence)),
+            ("enrich", changed(&self.enrich, &new.enrich)),
+            ("rule", changed(&self.rule, &new.rule)),
+        ] {
+            if differs {
+                diff.restart_required.push(name);
+            }
+        }
+
+        diff
+    }
+}
@@ -0,0 +1,27 @@
+//! Retained Frame Cache Configuration
+//!
+//! Configuration for caching the encoded PUBLISH frames of retained
+//! messages, so a retained message delivered to every connecting client
+//! (a device configuration topic, say) isn't encoded again for each one.
+
+use serde::Deserialize;
+
+/// Retained frame cache configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RetainedCacheConfig {
+    /// Cache the encoded frames of delivered retained messages
+    pub enabled: bool,
+    /// Retained topics whose frames are cached at most; topics delivered
+    /// after the cache is full are encoded per client (default: 1000)
+    pub max_topics: usize,
+}
+
+impl Default for RetainedCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_topics: 1000,
+        }
+    }
+}
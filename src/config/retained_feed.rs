@@ -0,0 +1,38 @@
+//! Retained Change Feed Configuration
+//!
+//! Configuration for the feed of retained message changes, which lets a
+//! service mirror the retained store incrementally instead of scanning it.
+
+use serde::Deserialize;
+
+/// Retained change feed configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RetainedFeedConfig {
+    /// Publish a change message whenever a retained message is stored or
+    /// deleted
+    pub enabled: bool,
+    /// Topic prefix; the change of retained topic "a/b" is published to
+    /// "{topic}/a/b" (default: "$SYS/retained")
+    pub topic: String,
+    /// Include the stored message (payload and properties) in created and
+    /// updated changes
+    pub payload: bool,
+}
+
+impl Default for RetainedFeedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            topic: "$SYS/retained".to_string(),
+            payload: true,
+        }
+    }
+}
+
+impl RetainedFeedConfig {
+    /// Topic the change of retained topic `topic` is published to
+    pub fn change_topic(&self, topic: &str) -> String {
+        format!("{}/{}", self.topic, topic)
+    }
+}
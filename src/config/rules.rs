@@ -0,0 +1,146 @@
+//! Rule Configuration
+//!
+//! `[[rule]]` entries route the messages of a topic filter to several
+//! actions at once (republish to another topic, POST to a webhook). Each
+//! `[[rule.actions]]` entry has its own queue, retries and dead-letter
+//! topic, so one failing action doesn't hold up the others. A rule can
+//! also name an `ack_action` that QoS 1 publishers' PUBACKs wait for, and
+//! `user_properties` that narrow it to tagged messages.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use super::UserPropertyMatch;
+
+/// What an action does with a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionKind {
+    /// Publish the message to `topic`
+    #[default]
+    Republish,
+    /// POST the message as JSON to `url` (e.g. a Kafka REST proxy)
+    Webhook,
+}
+
+impl ActionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActionKind::Republish => "republish",
+            ActionKind::Webhook => "webhook",
+        }
+    }
+}
+
+/// What a QoS 1 publisher is told when its message's `ack_action` didn't
+/// succeed within `ack_timeout`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AckFallback {
+    /// PUBACK with an error (MQTT 5) or disconnect (3.1.1), so the client
+    /// sends the message again
+    #[default]
+    Reject,
+    /// PUBACK as usual; the message is only as durable as the broker
+    Puback,
+}
+
+/// One action of a rule
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RuleActionConfig {
+    /// Name unique within the rule (metrics label); unset = the action's
+    /// type, numbered from the second action of a type on
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: ActionKind,
+    /// Republish: topic template; `{topic}` is the message's topic,
+    /// `{name}` the rule's name
+    pub topic: Option<String>,
+    /// Republish: QoS of the published messages (0-2)
+    pub qos: u8,
+    /// Republish: publish as retained messages
+    pub retain: bool,
+    /// Webhook: URL (http:// only)
+    pub url: Option<String>,
+    /// Webhook: extra request headers (e.g. an Authorization token)
+    pub headers: HashMap<String, String>,
+    /// Webhook: time allowed for each request
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    /// Attempts after the first failed one
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each further one
+    #[serde(with = "humantime_serde")]
+    pub retry_backoff: Duration,
+    /// Messages waiting for the action; when full, new messages are
+    /// dead-lettered
+    pub queue_size: usize,
+    /// Topic template messages the action gave up on are published to, as
+    /// JSON with the error; `{name}` is the rule's name, `{action}` the
+    /// action's. Unset = they're dropped
+    pub dead_letter: Option<String>,
+}
+
+impl Default for RuleActionConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            kind: ActionKind::Republish,
+            topic: None,
+            qos: 0,
+            retain: false,
+            url: None,
+            headers: HashMap::new(),
+            timeout: Duration::from_secs(5),
+            retries: 3,
+            retry_backoff: Duration::from_secs(1),
+            queue_size: 1024,
+            dead_letter: None,
+        }
+    }
+}
+
+/// One routing rule
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RuleConfig {
+    /// Unique name (client ID "rule:<name>" for ACLs)
+    pub name: String,
+    /// Enable this rule
+    pub enabled: bool,
+    /// Topic filter whose messages are routed
+    pub filter: String,
+    /// User properties messages must carry to be routed (all of them);
+    /// others are ignored by the rule
+    pub user_properties: Vec<UserPropertyMatch>,
+    /// Username ACLs are checked for
+    pub username: Option<String>,
+    pub actions: Vec<RuleActionConfig>,
+    /// Action whose success QoS 1 PUBACKs of matching messages wait for,
+    /// for backend rather than broker-only durability
+    pub ack_action: Option<String>,
+    /// Longest a PUBACK waits for `ack_action`
+    #[serde(with = "humantime_serde")]
+    pub ack_timeout: Duration,
+    /// Answer when `ack_action` fails or times out
+    pub ack_fallback: AckFallback,
+}
+
+impl Default for RuleConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            filter: String::new(),
+            user_properties: Vec::new(),
+            username: None,
+            actions: Vec::new(),
+            ack_action: None,
+            ack_timeout: Duration::from_secs(5),
+            ack_fallback: AckFallback::Reject,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Scheduled Publish Configuration
+//!
+//! `[[schedule]]` entries publish a templated message whenever their cron
+//! expression fires, e.g. hourly heartbeats or nightly commands to a device
+//! group's command topic.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// One scheduled publish
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    /// Unique name, also the key its last run is persisted under
+    pub name: String,
+    /// Enable this schedule
+    pub enabled: bool,
+    /// Five-field cron expression in UTC, or a macro such as "@hourly"
+    pub cron: String,
+    /// Topic template
+    pub topic: String,
+    /// Payload template
+    pub payload: String,
+    /// QoS of the published message (0-2)
+    pub qos: u8,
+    /// Publish as a retained message
+    pub retain: bool,
+    /// Username ACLs are checked for (the client ID is "schedule:<name>")
+    pub username: Option<String>,
+    /// A firing time missed while the broker was down still publishes at
+    /// startup if it is at most this old; older ones are skipped
+    #[serde(with = "humantime_serde")]
+    pub misfire_grace: Duration,
+    /// Evaluation steps rendering the topic and payload may take (see
+    /// [`crate::template`])
+    pub budget: u32,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            cron: String::new(),
+            topic: String::new(),
+            payload: String::new(),
+            qos: 0,
+            retain: false,
+            username: None,
+            misfire_grace: Duration::from_secs(300),
+            budget: crate::template::DEFAULT_BUDGET,
+        }
+    }
+}
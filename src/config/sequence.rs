@@ -0,0 +1,94 @@
+//! Message Sequence Configuration
+//!
+//! Configuration for broker-assigned per-topic sequence numbers, stamped on
+//! routed messages as a user property so subscribers can spot gaps, and for
+//! the replay requests that fill them.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Message sequence number configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SequenceConfig {
+    pub enabled: bool,
+    /// User property carrying the number (replaces one set by the publisher)
+    pub property: String,
+    /// Topic filters whose messages are numbered
+    pub topics: Vec<String>,
+    /// Numbers reserved with each persistence write; an unclean shutdown
+    /// skips at most this many on a topic
+    pub reserve: u64,
+    /// Replay of missed messages on request
+    pub replay: ReplayConfig,
+}
+
+impl Default for SequenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            property: "x-seq".to_string(),
+            topics: vec!["#".to_string()],
+            reserve: 1000,
+            replay: ReplayConfig::default(),
+        }
+    }
+}
+
+/// Replay request configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ReplayConfig {
+    /// Keep numbered messages and handle replay requests
+    pub enabled: bool,
+    /// Control topic prefix. Publishing to "{topic}/request" asks for a
+    /// replay; the status goes to the request's Response Topic or else to
+    /// "{topic}/response" (default: "$replay")
+    pub topic: String,
+    /// Topic filters whose messages may be replayed (also subject to the
+    /// requester's subscribe ACL)
+    pub topics: Vec<String>,
+    /// Messages kept per topic (default: 1000)
+    pub retain: usize,
+    /// How long a message is kept (default: 10m)
+    #[serde(with = "humantime_serde")]
+    pub max_age: Duration,
+    /// Most messages replayed per request; the rest is left for a follow-up
+    /// request (default: 1000)
+    pub max_messages: usize,
+    /// Requests a client may make per `window` (default: 10, 0 = unlimited)
+    pub max_requests: u32,
+    #[serde(with = "humantime_serde")]
+    pub window: Duration,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            topic: "$replay".to_string(),
+            topics: vec!["#".to_string()],
+            retain: 1000,
+            max_age: Duration::from_secs(600),
+            max_messages: 1000,
+            max_requests: 10,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ReplayConfig {
+    /// Whether `topic` is a replay request, if it's under the control topic
+    ///
+    /// Returns `Some(false)` for an unknown command.
+    pub fn control(&self, topic: &str) -> Option<bool> {
+        let command = topic.strip_prefix(self.topic.as_str())?.strip_prefix('/')?;
+        Some(command == "request")
+    }
+
+    /// Topic the status is sent to when the request names no response topic
+    pub fn response_topic(&self) -> String {
+        format!("{}/response", self.topic)
+    }
+}
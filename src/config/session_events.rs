@@ -0,0 +1,27 @@
+//! Session Event Configuration
+//!
+//! Configuration for the events the broker publishes when it garbage
+//! collects a persistent session, so provisioning systems learn of expiry
+//! without inferring it from silence.
+
+use serde::Deserialize;
+
+/// Session expiry event configuration (`[session.expiry_events]`)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SessionExpiryEventsConfig {
+    /// Publish an event for each persistent session that expires
+    pub enabled: bool,
+    /// Topic the events are published to (default:
+    /// "$SYS/broker/sessions/expired")
+    pub topic: String,
+}
+
+impl Default for SessionExpiryEventsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            topic: "$SYS/broker/sessions/expired".to_string(),
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! Graceful Shutdown Configuration
+//!
+//! How the broker drains its clients on SIGTERM or Ctrl+C before exiting.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Graceful shutdown configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// Longest wait for connected clients' QoS 1/2 flows to complete
+    /// (default: 10s, 0 = disconnect immediately)
+    #[serde(with = "humantime_serde")]
+    pub drain_timeout: Duration,
+    /// Send MQTT 5 clients DISCONNECT "Server shutting down" instead of
+    /// just closing their connections
+    pub disconnect: bool,
+    /// Server Reference sent with the DISCONNECT, e.g. a failover broker
+    /// ("host:port")
+    pub server_reference: Option<String>,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            drain_timeout: Duration::from_secs(10),
+            disconnect: true,
+            server_reference: None,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! STOMP Listener Configuration
+//!
+//! Configuration for the STOMP 1.2 compatibility listener and the mapping
+//! from STOMP destinations to MQTT topics.
+
+use serde::Deserialize;
+use std::net::SocketAddr;
+
+/// Maps STOMP destinations with a given prefix onto an MQTT topic prefix
+#[derive(Debug, Clone, Deserialize)]
+pub struct StompDestination {
+    /// STOMP destination prefix (e.g., "/topic/")
+    pub prefix: String,
+    /// MQTT topic prefix that replaces it (e.g., "" or "legacy/")
+    #[serde(default)]
+    pub topic: String,
+}
+
+/// STOMP listener configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StompConfig {
+    /// Enable the STOMP listener
+    pub enabled: bool,
+    /// TCP bind address (default: 0.0.0.0:61613)
+    pub bind: SocketAddr,
+    /// STOMP over WebSocket bind address (optional)
+    pub ws_bind: Option<SocketAddr>,
+    /// WebSocket path (default: "/stomp")
+    pub ws_path: String,
+    /// Treat '.' as the level separator in destinations (ActiveMQ/RabbitMQ style),
+    /// mapping "*" to "+" and ">" to "#" in subscriptions
+    pub dot_separator: bool,
+    /// Maximum frame size in bytes, headers included (default: 1MB)
+    pub max_frame_size: usize,
+    /// Destination mappings (longest prefix wins). Destinations that match no
+    /// mapping are rejected with an ERROR frame.
+    pub destinations: Vec<StompDestination>,
+}
+
+impl Default for StompConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "0.0.0.0:61613".parse().unwrap(),
+            ws_bind: None,
+            ws_path: "/stomp".to_string(),
+            dot_separator: false,
+            max_frame_size: 1024 * 1024,
+            destinations: vec![StompDestination {
+                prefix: "/topic/".to_string(),
+                topic: String::new(),
+            }],
+        }
+    }
+}
@@ -68,6 +68,61 @@ workers = ${TEST_WORKERS:-4}
     std::env::remove_var("TEST_BIND_PORT");
 }
 
+#[test]
+fn test_profiles() {
+    for (name, _) in profile::PROFILES {
+        let config = Config::parse(&format!("profile = \"{}\"", name)).unwrap();
+        assert_eq!(config.profile.as_deref(), Some(*name));
+    }
+
+    // The file overrides the profile key by key
+    let config = Config::parse(
+        r#"
+profile = "edge"
+
+[limits]
+max_connections = 50
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.limits.max_connections, 50);
+    assert_eq!(config.limits.max_inflight, 16);
+    assert_eq!(
+        config.persistence.session_checkpoint,
+        SessionCheckpoint::EveryChange
+    );
+    // Settings the profile leaves alone keep the built-in defaults
+    assert_eq!(config.limits.max_awaiting_rel, 100);
+
+    let config = Config::parse("profile = \"test\"").unwrap();
+    assert_eq!(config.persistence.backend, BackendType::Memory);
+    assert!(config.server.bind.ip().is_loopback());
+
+    assert!(Config::parse("profile = \"staging\"").is_err());
+}
+
+#[test]
+fn test_load_config_with_profile() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("vibemq.toml");
+    std::fs::write(
+        &config_path,
+        r#"
+profile = "cloud"
+
+[persistence]
+path = "/var/lib/vibemq"
+"#,
+    )
+    .unwrap();
+
+    let config = Config::load(&config_path).unwrap();
+    assert_eq!(config.limits.max_connections, 1_000_000);
+    assert!(config.metrics.enabled);
+    assert_eq!(config.persistence.path, PathBuf::from("/var/lib/vibemq"));
+    assert_eq!(config.persistence.max_batch_size, 1000);
+}
+
 #[test]
 fn test_default_config() {
     let config = Config::default();
@@ -136,6 +191,7 @@ role = "device"
 
 [acl]
 enabled = true
+on_revoke = "disconnect"
 
 [[acl.roles]]
 name = "admin"
@@ -165,6 +221,7 @@ subscribe = ["$SYS/broker/+"]
     assert!(config.auth.users[1].password_hash.is_some());
     assert!(config.acl.enabled);
     assert_eq!(config.acl.roles.len(), 2);
+    assert_eq!(config.acl.on_revoke, AclRevocation::Disconnect);
 }
 
 #[test]
@@ -208,6 +265,93 @@ enabled = true
     assert!(result.is_err());
 }
 
+#[test]
+fn test_acl_ttl_rules() {
+    let toml = r##"
+[acl]
+enabled = true
+
+[[acl.roles]]
+name = "guest"
+publish = ["guest/#"]
+
+[[acl.roles.ttl]]
+topic = "guest/#"
+max_message_expiry = "10m"
+max_retained_lifetime = "1h"
+
+[[acl.default.ttl]]
+topic = "#"
+max_message_expiry = "1d"
+"##;
+
+    let config = Config::parse(toml).unwrap();
+    let rule = &config.acl.roles[0].ttl[0];
+    assert_eq!(rule.topic, "guest/#");
+    assert_eq!(rule.max_message_expiry, Some(Duration::from_secs(600)));
+    assert_eq!(rule.max_retained_lifetime, Some(Duration::from_secs(3600)));
+
+    let rule = &config.acl.default.ttl[0];
+    assert_eq!(rule.max_message_expiry, Some(Duration::from_secs(86400)));
+    assert_eq!(rule.max_retained_lifetime, None);
+}
+
+#[test]
+fn test_user_property_conditions() {
+    let toml = r##"
+[acl]
+enabled = true
+
+[[acl.default.publish_properties]]
+topic = "prod/#"
+user_properties = [{ name = "env", value = "prod" }]
+
+[[rule]]
+name = "audit"
+filter = "orders/#"
+user_properties = [{ name = "tenant", prefix = "acme-" }, { name = "trace" }]
+
+[[rule.actions]]
+topic = "audit/{topic}"
+
+[[bridge]]
+name = "cloud"
+address = "cloud:1883"
+
+[[bridge.forwards]]
+local_topic = "sensors/#"
+remote_topic = "sensors/#"
+user_properties = [{ name = "env", value = "prod" }]
+"##;
+
+    let config = Config::parse(toml).unwrap();
+    let rule = &config.acl.default.publish_properties[0];
+    assert_eq!(rule.topic, "prod/#");
+    assert_eq!(
+        rule.user_properties,
+        [UserPropertyMatch::exact("env", "prod")]
+    );
+    assert_eq!(
+        config.rule[0].user_properties,
+        [
+            UserPropertyMatch::prefix("tenant", "acme-"),
+            UserPropertyMatch {
+                name: "trace".to_string(),
+                ..Default::default()
+            }
+        ]
+    );
+    assert_eq!(
+        config.bridge[0].forwards[0].user_properties,
+        [UserPropertyMatch::exact("env", "prod")]
+    );
+
+    let invalid = toml.replace(r#"value = "prod" }]"#, r#"value = "prod", prefix = "p" }]"#);
+    assert!(Config::parse(&invalid).is_err());
+    let invalid = toml.replace(r#"{ name = "trace" }"#, r#"{ name = "" }"#);
+    assert!(Config::parse(&invalid).is_err());
+}
+
 #[test]
 fn test_build_role_map() {
     let toml = r##"
@@ -422,3 +566,1523 @@ qos = 1
 
     std::fs::remove_file(&config_path).ok();
 }
+
+#[test]
+fn test_id_config() {
+    let config = Config::parse(
+        r#"
+[id]
+generator = "snowflake"
+node_id = 12
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.id.generator, IdGeneratorKind::Snowflake);
+    assert_eq!(config.id.node_id, Some(12));
+
+    // Node ID must fit in 10 bits
+    assert!(Config::parse("[id]\nnode_id = 4096\n").is_err());
+}
+
+#[test]
+fn test_ocpp_config() {
+    let config = Config::parse(
+        r#"
+[ocpp]
+enabled = true
+uplink_topic = "fleet/{cp_id}/in"
+csms_clients = ["csms"]
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.ocpp.uplink_topic, "fleet/{cp_id}/in");
+    assert_eq!(config.ocpp.downlink_topic, "ocpp/{cp_id}/down");
+    assert!(config.ocpp.enforce_identity);
+
+    // Templates need a {cp_id} level
+    assert!(Config::parse("[ocpp]\nenabled = true\nuplink_topic = \"ocpp/up\"\n").is_err());
+}
+
+#[test]
+fn test_session_checkpoint_config() {
+    let config = Config::parse(
+        r#"
+[persistence]
+session_checkpoint = "interval"
+session_checkpoint_interval = "250ms"
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.persistence.session_checkpoint,
+        SessionCheckpoint::Interval
+    );
+    assert_eq!(
+        config.persistence.session_checkpoint_interval,
+        Duration::from_millis(250)
+    );
+    assert_eq!(
+        Config::default().persistence.session_checkpoint,
+        SessionCheckpoint::Disconnect
+    );
+
+    assert!(Config::parse(
+        "[persistence]\nsession_checkpoint = \"interval\"\nsession_checkpoint_interval = \"0s\"\n"
+    )
+    .is_err());
+}
+
+#[test]
+fn test_cluster_observer_config() {
+    let config = Config::parse(
+        r#"
+[[cluster]]
+enabled = true
+role = "observer"
+observer_api_bind = "127.0.0.1:9081"
+
+[admin]
+token = "secret"
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.cluster[0].role, ClusterRole::Observer);
+    assert!(config.cluster[0].is_observer());
+    assert_eq!(
+        config.cluster[0].observer_api_bind,
+        "127.0.0.1:9081".parse().unwrap()
+    );
+
+    assert!(Config::parse("[[cluster]]\nrole = \"replica\"\n").is_err());
+
+    // Retained payloads are never served without the admin token
+    let err = Config::parse("[[cluster]]\nenabled = true\nrole = \"observer\"\n").unwrap_err();
+    assert!(err.to_string().contains("admin.token"));
+}
+
+#[test]
+fn test_cluster_metrics_config() {
+    let config = Config::parse("[[cluster]]\nenabled = true\n").unwrap();
+    assert!(!config.cluster[0].metrics.enabled);
+    assert_eq!(config.cluster[0].metrics.interval, Duration::from_secs(15));
+
+    let config = Config::parse(
+        r#"
+[[cluster]]
+enabled = true
+
+[cluster.metrics]
+enabled = true
+interval = "5s"
+"#,
+    )
+    .unwrap();
+    assert!(config.cluster[0].metrics.enabled);
+    assert_eq!(config.cluster[0].metrics.interval, Duration::from_secs(5));
+
+    assert!(Config::parse(
+        "[[cluster]]\nenabled = true\n[cluster.metrics]\nenabled = true\ninterval = \"0s\"\n"
+    )
+    .is_err());
+}
+
+#[test]
+fn test_link_compression_config() {
+    let config = Config::parse("[[cluster]]\nenabled = true\n").unwrap();
+    assert!(!config.cluster[0].compression.enabled());
+    assert!(config.mqtt.bridge_compression.is_empty());
+
+    let config = Config::parse(
+        r#"
+[mqtt]
+bridge_compression = ["lz4"]
+
+[[cluster]]
+enabled = true
+
+[cluster.compression]
+codecs = ["zstd", "lz4"]
+min_size = 1024
+level = 9
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.mqtt.bridge_compression, [CompressionCodec::Lz4]);
+    let compression = &config.cluster[0].compression;
+    assert_eq!(
+        compression.codecs,
+        [CompressionCodec::Zstd, CompressionCodec::Lz4]
+    );
+    assert_eq!(compression.min_size, 1024);
+    assert_eq!(compression.level, 9);
+
+    assert!(Config::parse(
+        "[[cluster]]\nenabled = true\n[cluster.compression]\ncodecs = [\"zstd\"]\nlevel = 30\n"
+    )
+    .is_err());
+    assert!(Config::parse("[mqtt]\nbridge_compression = [\"brotli\"]\n").is_err());
+}
+
+#[test]
+fn test_tls_handshake_pool_config() {
+    let config = Config::parse(
+        r#"
+[server]
+tls_bind = "0.0.0.0:8883"
+
+[server.tls]
+cert = "server.crt"
+key = "server.key"
+handshake_threads = 2
+"#,
+    )
+    .unwrap();
+    let tls = config.server.tls.unwrap();
+    assert_eq!(tls.handshake_threads, 2);
+    assert_eq!(tls.handshake_queue_size, 1024);
+    assert_eq!(tls.handshake_timeout, Duration::from_secs(10));
+
+    assert!(Config::parse(
+        "[server]\ntls_bind = \"0.0.0.0:8883\"\n[server.tls]\ncert = \"c\"\nkey = \"k\"\nhandshake_threads = 2\nhandshake_queue_size = 0\n"
+    )
+    .is_err());
+}
+
+#[test]
+fn test_wss_listener_config() {
+    let config = Config::parse(
+        r#"
+[server]
+wss_bind = "0.0.0.0:8884"
+ws_max_frame_size = 65536
+
+[server.tls]
+cert = "server.crt"
+key = "server.key"
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.server.wss_bind,
+        Some("0.0.0.0:8884".parse().unwrap())
+    );
+    assert_eq!(config.server.ws_max_frame_size, Some(65536));
+
+    // WebSocket/TLS needs [server.tls]
+    assert!(Config::parse("[server]\nwss_bind = \"0.0.0.0:8884\"\n").is_err());
+    assert!(Config::parse("[server]\nws_max_frame_size = 0\n").is_err());
+}
+
+#[test]
+fn test_quic_listener_config() {
+    let config = Config::parse(
+        r#"
+[server]
+quic_bind = "0.0.0.0:14567"
+
+[server.tls]
+cert = "server.crt"
+key = "server.key"
+
+[server.quic]
+zero_rtt = true
+idle_timeout = "30s"
+max_streams = 4
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.server.quic_bind,
+        Some("0.0.0.0:14567".parse().unwrap())
+    );
+    assert!(config.server.quic.zero_rtt);
+    assert!(config.server.quic.migration);
+    assert_eq!(config.server.quic.alpn, ["mqtt"]);
+    assert_eq!(config.server.quic.idle_timeout, Duration::from_secs(30));
+    assert_eq!(config.server.quic.max_streams, 4);
+
+    // UDP, so it may share its port with a TCP listener
+    assert!(Config::parse(
+        "[server]\nbind = \"0.0.0.0:1883\"\nquic_bind = \"0.0.0.0:1883\"\n[server.tls]\ncert = \"c\"\nkey = \"k\"\n"
+    )
+    .is_ok());
+    // QUIC needs [server.tls]
+    assert!(Config::parse("[server]\nquic_bind = \"0.0.0.0:14567\"\n").is_err());
+    assert!(Config::parse(
+        "[server]\nquic_bind = \"0.0.0.0:14567\"\n[server.tls]\ncert = \"c\"\nkey = \"k\"\n[server.quic]\nalpn = []\n"
+    )
+    .is_err());
+}
+
+#[test]
+fn test_user_tenant() {
+    let toml = r#"
+[auth]
+enabled = true
+
+[[auth.users]]
+username = "alice"
+password = "secret"
+tenant = "acme"
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.auth.users[0].tenant.as_deref(), Some("acme"));
+
+    assert!(Config::parse(&toml.replace("\"acme\"", "\"acme/eu\"")).is_err());
+    assert!(Config::parse(&toml.replace("\"acme\"", "\"\"")).is_err());
+}
+
+#[test]
+fn test_auth_metadata_fields() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.auth.metadata_fields, AuthMetadataField::ALL);
+
+    let toml = r#"
+[auth]
+metadata_fields = ["client_ip", "tls_version", "listener"]
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(
+        config.auth.metadata_fields,
+        vec![
+            AuthMetadataField::ClientIp,
+            AuthMetadataField::TlsVersion,
+            AuthMetadataField::Listener,
+        ]
+    );
+    assert!(Config::parse("[auth]\nmetadata_fields = [\"mac_address\"]\n").is_err());
+}
+
+#[test]
+fn test_auth_backends() {
+    let config = Config::parse("").unwrap();
+    assert!(config.auth.password_file.is_none());
+    assert!(config.auth.http.is_none());
+
+    let toml = r#"
+[auth]
+password_file = "/etc/vibemq/passwd"
+
+[auth.http]
+url = "http://127.0.0.1:8081/auth"
+timeout = "2s"
+cache_ttl = "0s"
+headers = { Authorization = "Bearer t0k3n" }
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(
+        config.auth.password_file.as_deref(),
+        Some(std::path::Path::new("/etc/vibemq/passwd"))
+    );
+    let http = config.auth.http.as_ref().unwrap();
+    assert_eq!(http.url, "http://127.0.0.1:8081/auth");
+    assert_eq!(http.timeout, Duration::from_secs(2));
+    assert!(http.cache_ttl.is_zero());
+    assert_eq!(http.cache_size, 10_000);
+    assert_eq!(http.headers["Authorization"], "Bearer t0k3n");
+
+    assert!(Config::parse(&toml.replace("http://", "https://")).is_err());
+    assert!(Config::parse(&toml.replace("\"2s\"", "\"0s\"")).is_err());
+}
+
+#[test]
+fn test_session_compress_idle_after() {
+    let config = Config::parse("").unwrap();
+    assert!(config.session.compress_idle_after.is_none());
+
+    let config = Config::parse("[session]\ncompress_idle_after = \"10m\"\n").unwrap();
+    assert_eq!(
+        config.session.compress_idle_after,
+        Some(Duration::from_secs(600))
+    );
+}
+
+#[test]
+fn test_session_expiry_events() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.session.expiry_events.enabled);
+    assert_eq!(
+        config.session.expiry_events.topic,
+        "$SYS/broker/sessions/expired"
+    );
+
+    let toml = r#"
+[session.expiry_events]
+enabled = true
+topic = "provisioning/expired"
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert!(config.session.expiry_events.enabled);
+    assert_eq!(config.session.expiry_events.topic, "provisioning/expired");
+
+    assert!(Config::parse(&toml.replace("expired\"", "#\"")).is_err());
+}
+
+#[test]
+fn test_backlog_drain_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.session.backlog_drain.fair);
+    assert_eq!(config.session.backlog_drain.concurrency, 4);
+
+    let toml = r##"
+[session.backlog_drain]
+fair = true
+concurrency = 8
+batch_size = 50
+
+[acl]
+enabled = true
+
+[[acl.roles]]
+name = "gold"
+publish = []
+subscribe = ["#"]
+
+[acl.roles.session]
+drain_weight = 4
+"##;
+    let config = Config::parse(toml).unwrap();
+    assert!(config.session.backlog_drain.fair);
+    assert_eq!(config.session.backlog_drain.batch_size, 50);
+    assert_eq!(
+        config.session.backlog_drain.turn_timeout,
+        Duration::from_secs(1)
+    );
+    assert_eq!(config.acl.roles[0].session.drain_weight, Some(4));
+    assert_eq!(config.acl.default.session.drain_weight, None);
+
+    assert!(Config::parse(&toml.replace("drain_weight = 4", "drain_weight = 0")).is_err());
+    assert!(Config::parse(&toml.replace("concurrency = 8", "concurrency = 0")).is_err());
+    assert!(Config::parse(&toml.replace("batch_size = 50", "turn_timeout = \"0s\"")).is_err());
+}
+
+#[test]
+fn test_memory_pressure_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.limits.memory_pressure.is_enabled());
+
+    let toml = r#"
+[limits.memory_pressure]
+high_watermark = 1000
+check_interval = "500ms"
+low_priority_topics = ["telemetry/debug/#"]
+"#;
+    let config = Config::parse(toml).unwrap();
+    let memory = &config.limits.memory_pressure;
+    assert!(memory.is_enabled());
+    assert_eq!(memory.low_watermark(), 800);
+    assert_eq!(memory.check_interval, Duration::from_millis(500));
+    assert_eq!(memory.low_priority_topics, ["telemetry/debug/#"]);
+
+    let low = toml.replace(
+        "high_watermark = 1000",
+        "high_watermark = 1000\nlow_watermark = 1000",
+    );
+    assert!(Config::parse(&low).is_err());
+    assert!(Config::parse(&toml.replace("debug/#", "#/debug")).is_err());
+}
+
+#[test]
+fn test_publisher_backpressure_config() {
+    let config = Config::parse("").unwrap();
+    let backpressure = &config.limits.publisher_backpressure;
+    assert!(!backpressure.enabled);
+    assert_eq!(backpressure.max_delay, Duration::from_millis(500));
+
+    let toml = r#"
+[limits.publisher_backpressure]
+enabled = true
+max_delay = "100ms"
+retry_after = "3s"
+"#;
+    let config = Config::parse(toml).unwrap();
+    let backpressure = &config.limits.publisher_backpressure;
+    assert!(backpressure.enabled);
+    assert_eq!(backpressure.max_delay, Duration::from_millis(100));
+    assert_eq!(backpressure.retry_after, Duration::from_secs(3));
+
+    assert!(Config::parse(&toml.replace("\"3s\"", "\"0s\"")).is_err());
+}
+
+#[test]
+fn test_hibernate_after() {
+    let config = Config::parse("").unwrap();
+    assert!(config.limits.hibernate_after.is_none());
+
+    let config = Config::parse("[limits]\nhibernate_after = \"30s\"\n").unwrap();
+    assert_eq!(config.limits.hibernate_after, Some(Duration::from_secs(30)));
+}
+
+#[test]
+fn test_ack_alarm_after() {
+    let config = Config::parse("").unwrap();
+    assert!(config.limits.ack_alarm_after.is_none());
+
+    let config = Config::parse("[limits]\nack_alarm_after = \"2m\"\n").unwrap();
+    assert_eq!(
+        config.limits.ack_alarm_after,
+        Some(Duration::from_secs(120))
+    );
+}
+
+#[test]
+fn test_retained_feed_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.retained_feed.enabled);
+    assert_eq!(
+        config.retained_feed.change_topic("shadow/1"),
+        "$SYS/retained/shadow/1"
+    );
+
+    let config =
+        Config::parse("[retained_feed]\nenabled = true\ntopic = \"mirror\"\npayload = false\n")
+            .unwrap();
+    assert_eq!(config.retained_feed.topic, "mirror");
+    assert!(!config.retained_feed.payload);
+
+    assert!(Config::parse("[retained_feed]\nenabled = true\ntopic = \"mirror/#\"\n").is_err());
+}
+
+#[test]
+fn test_retained_cache_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.retained_cache.enabled);
+    assert_eq!(config.retained_cache.max_topics, 1000);
+
+    let config = Config::parse("[retained_cache]\nenabled = true\nmax_topics = 50\n").unwrap();
+    assert!(config.retained_cache.enabled);
+    assert_eq!(config.retained_cache.max_topics, 50);
+
+    assert!(Config::parse("[retained_cache]\nenabled = true\nmax_topics = 0\n").is_err());
+}
+
+#[test]
+fn test_delayed_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.delayed.enabled);
+    assert_eq!(
+        config.delayed.split("$delayed/30/cmd/reboot"),
+        Some(Some((Duration::from_secs(30), "cmd/reboot")))
+    );
+    assert_eq!(config.delayed.split("$delayed/soon/cmd"), Some(None));
+    assert_eq!(config.delayed.split("$delayed/30"), Some(None));
+    assert_eq!(config.delayed.split("$delayedx/30/cmd"), None);
+    assert_eq!(config.delayed.split("cmd/reboot"), None);
+
+    let toml = r#"
+[delayed]
+enabled = true
+topic = "later"
+user_property = "delay"
+max_delay = "1h"
+max_messages = 10
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.delayed.user_property.as_deref(), Some("delay"));
+    assert_eq!(config.delayed.max_delay, Duration::from_secs(3600));
+    assert_eq!(config.delayed.max_messages, 10);
+
+    assert!(Config::parse("[delayed]\nenabled = true\ntopic = \"later/+\"\n").is_err());
+    assert!(Config::parse("[delayed]\nenabled = true\nmax_delay = \"0s\"\n").is_err());
+}
+
+#[test]
+fn test_health_config() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.health.bind, None);
+    assert_eq!(config.health.min_cluster_peers, 1);
+
+    let config =
+        Config::parse("[health]\nbind = \"127.0.0.1:8081\"\nmin_cluster_peers = 0\n").unwrap();
+    assert_eq!(config.health.bind, Some("127.0.0.1:8081".parse().unwrap()));
+    assert_eq!(config.health.min_cluster_peers, 0);
+}
+
+#[test]
+fn test_topic_tree_config() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.topic_tree.recent_topics, 0);
+    assert_eq!(config.topic_tree.recent_window, Duration::from_secs(600));
+
+    let config =
+        Config::parse("[topic_tree]\nrecent_topics = 500\nrecent_window = \"1m\"\n").unwrap();
+    assert_eq!(config.topic_tree.recent_topics, 500);
+    assert_eq!(config.topic_tree.recent_window, Duration::from_secs(60));
+}
+
+#[test]
+fn test_topic_schema_config() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.topic_schema.mode, TopicSchemaMode::Off);
+
+    let toml = r#"
+[topic_schema]
+mode = "reject"
+
+[[topic_schema.templates]]
+pattern = "sensors/{site}/{device:int}"
+owner = "team-iot"
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.topic_schema.mode, TopicSchemaMode::Reject);
+    assert_eq!(
+        config.topic_schema.templates[0].pattern,
+        "sensors/{site}/{device:int}"
+    );
+    assert_eq!(
+        config.topic_schema.templates[0].owner.as_deref(),
+        Some("team-iot")
+    );
+
+    let err = Config::parse(&toml.replace("{device:int}", "{device:float}")).unwrap_err();
+    assert!(err.to_string().contains("topic_schema"));
+    assert!(Config::parse("[topic_schema]\nmode = \"warn\"\n").is_err());
+}
+
+#[test]
+fn test_priority_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.priority.is_enabled());
+    assert_eq!(config.priority.headroom, 64);
+
+    let config = Config::parse("[priority]\ntopics = [\"devices/+/cmd/#\"]\n").unwrap();
+    assert!(config.priority.is_enabled());
+    assert!(Config::parse("[priority]\ntopics = [\"cmd/#/x\"]\n").is_err());
+    assert!(Config::parse("[priority]\ntopics = [\"cmd/#\"]\nheadroom = 0\n").is_err());
+}
+
+#[test]
+fn test_sequence_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.sequence.enabled);
+    assert_eq!(config.sequence.property, "x-seq");
+    assert_eq!(config.sequence.topics, ["#"]);
+
+    let toml = "[sequence]\nenabled = true\ntopics = [\"sensors/#\"]\nreserve = 50\n";
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.sequence.topics, ["sensors/#"]);
+    assert_eq!(config.sequence.reserve, 50);
+
+    assert!(Config::parse(&toml.replace("50", "0")).is_err());
+    assert!(Config::parse(&toml.replace("sensors/#", "sensors/#/x")).is_err());
+    assert!(Config::parse("[sequence]\nenabled = true\nproperty = \"\"\n").is_err());
+}
+
+#[test]
+fn test_sequence_replay_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.sequence.replay.enabled);
+    assert_eq!(
+        config.sequence.replay.control("$replay/request"),
+        Some(true)
+    );
+    assert_eq!(config.sequence.replay.control("$replay/other"), Some(false));
+    assert_eq!(config.sequence.replay.control("sensors/1"), None);
+
+    let toml = r#"
+[sequence]
+enabled = true
+
+[sequence.replay]
+enabled = true
+retain = 50
+max_age = "30s"
+window = "10s"
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.sequence.replay.retain, 50);
+    assert_eq!(config.sequence.replay.max_age, Duration::from_secs(30));
+    assert_eq!(config.sequence.replay.window, Duration::from_secs(10));
+
+    assert!(Config::parse(&toml.replace("retain = 50", "retain = 0")).is_err());
+    // Nothing to replay without numbers
+    assert!(Config::parse(&toml.replacen("enabled = true", "enabled = false", 1)).is_err());
+}
+
+#[test]
+fn test_plugins_config() {
+    let config = Config::parse("").unwrap();
+    assert!(config.plugins.instances.is_empty());
+
+    let toml = r#"
+[plugins]
+drain_timeout = "5s"
+
+[[plugins.instances]]
+name = "audit"
+url = "http://127.0.0.1:9000/hooks"
+events = ["publish_check", "client_connected"]
+priority = 10
+topics = ["factory/#"]
+on_failure = "allow"
+on_failure_by_event = { authenticate = "deny" }
+circuit_failures = 3
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.plugins.drain_timeout, Duration::from_secs(5));
+    let plugin = &config.plugins.instances[0];
+    assert_eq!(
+        plugin.events,
+        vec![PluginEvent::PublishCheck, PluginEvent::ClientConnected]
+    );
+    assert_eq!(plugin.on_failure, PluginFailure::Allow);
+    assert_eq!(
+        plugin.failure_mode(PluginEvent::Authenticate),
+        PluginFailure::Deny
+    );
+    assert_eq!(
+        plugin.failure_mode(PluginEvent::SubscribeCheck),
+        PluginFailure::Allow
+    );
+    assert_eq!(plugin.circuit_failures, 3);
+    assert_eq!(plugin.circuit_cooldown, Duration::from_secs(30));
+    assert_eq!(plugin.timeout, Duration::from_secs(2));
+
+    assert!(Config::parse(&toml.replace("http://", "https://")).is_err());
+    assert!(Config::parse(&toml.replace("factory/#", "factory/#/x")).is_err());
+    let twice = format!("{}{}", toml, &toml[toml.find("[[").unwrap()..]);
+    assert!(Config::parse(&twice).is_err());
+}
+
+#[test]
+fn test_profile_history_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.profile_history.enabled);
+    assert_eq!(config.profile_history.keep, 360);
+
+    let toml = r#"
+[profile_history]
+enabled = true
+dir = "/var/lib/vibemq/profiles"
+interval = "5m"
+duration = "30s"
+keep = 288
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(
+        config.profile_history.dir,
+        std::path::PathBuf::from("/var/lib/vibemq/profiles")
+    );
+    assert_eq!(config.profile_history.interval, Duration::from_secs(300));
+    assert_eq!(config.profile_history.duration, Duration::from_secs(30));
+    assert_eq!(config.profile_history.frequency, 19);
+
+    assert!(Config::parse(&toml.replace("30s", "10m")).is_err());
+    assert!(Config::parse(&toml.replace("288", "0")).is_err());
+}
+
+#[test]
+fn test_listener_limits_config() {
+    let config = Config::parse("").unwrap();
+    assert!(config.limits.listeners.is_empty());
+
+    let toml = r#"
+[limits.listeners.ws]
+max_connections = 100
+max_outbound_messages = 50
+slow_client = "pause_publishers"
+pause_timeout = "2s"
+"#;
+    let config = Config::parse(toml).unwrap();
+    let ws = config.limits.listeners["ws"];
+    assert_eq!(ws.max_connections, 100);
+    assert_eq!(ws.max_outbound_bytes, 0);
+    assert_eq!(ws.slow_client, SlowClientPolicy::PausePublishers);
+    assert_eq!(ws.pause_timeout, Duration::from_secs(2));
+    assert!(ws.is_backlogged(51, 0));
+    assert!(!ws.is_backlogged(50, 1 << 30));
+
+    assert!(Config::parse("[limits.listeners.http]\nmax_connections = 1\n").is_err());
+    assert!(Config::parse("[limits.listeners.tcp]\nslow_client = \"block\"\n").is_err());
+}
+
+#[test]
+fn test_publish_rate_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.limits.publish_rate.enabled());
+    assert_eq!(config.limits.publish_rate.burst, 100);
+
+    let toml = r#"
+[limits.publish_rate]
+messages_per_sec = 50
+burst = 10
+persist = false
+sync_interval = "1s"
+external = ["user:billing", "cn:fleet-*"]
+external_timeout = "20ms"
+"#;
+    let config = Config::parse(toml).unwrap();
+    let publish_rate = &config.limits.publish_rate;
+    assert!(publish_rate.enabled());
+    assert_eq!(publish_rate.messages_per_sec, 50);
+    assert_eq!(publish_rate.burst, 10);
+    assert!(!publish_rate.persist);
+    assert_eq!(publish_rate.sync_interval, Duration::from_secs(1));
+    assert_eq!(publish_rate.external_timeout, Duration::from_millis(20));
+    assert!(publish_rate.is_external("user:billing"));
+    assert!(!publish_rate.is_external("user:billing2"));
+    assert!(publish_rate.is_external("cn:fleet-0042"));
+    assert!(!publish_rate.is_external("client:fleet-0042"));
+}
+
+#[test]
+fn test_quota_config() {
+    let config = Config::parse("").unwrap();
+    assert!(config.limits.quota.resolve("tcp", None).is_unlimited());
+
+    let toml = r#"
+[limits.quota.default]
+messages_per_sec = 100
+max_subscriptions = 10
+
+[limits.quota.listeners.ws]
+max_subscriptions = 2
+max_payload_size = 1024
+
+[limits.quota.users.Ingest]
+messages_per_sec = 0
+"#;
+    let quota = Config::parse(toml).unwrap().limits.quota;
+    assert_eq!(
+        quota.resolve("tcp", Some("alice")),
+        QuotaLimits {
+            messages_per_sec: Some(100),
+            max_subscriptions: Some(10),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        quota.resolve("ws", Some("Ingest")),
+        QuotaLimits {
+            messages_per_sec: Some(0),
+            max_subscriptions: Some(2),
+            max_payload_size: Some(1024),
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_error_detail_config() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.server.error_detail, ErrorDetail::Codes);
+    assert!(config.server.reason_map.is_empty());
+
+    let toml = r#"
+[server]
+error_detail = "generic"
+ws_error_detail = "generic"
+tls_error_detail = "full"
+
+[server.reason_map]
+"0x87" = "0x80"
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.server.error_detail, ErrorDetail::Generic);
+    assert_eq!(config.server.ws_error_detail, ErrorDetail::Generic);
+    assert_eq!(config.server.tls_error_detail, ErrorDetail::Full);
+    assert_eq!(config.server.reason_map["0x87"], "0x80");
+
+    // Success codes cannot be mapped to
+    let toml = "[server.reason_map]\n\"0x87\" = \"0x00\"\n";
+    assert!(Config::parse(toml).is_err());
+}
+
+#[test]
+fn test_proxy_trusted_networks() {
+    let toml = r#"
+[server.proxy_protocol]
+enabled = true
+trusted_networks = ["10.0.0.0/8", "127.0.0.1"]
+"#;
+    let config = Config::parse(toml).unwrap();
+    let proxy = &config.server.proxy_protocol;
+    assert!(proxy.is_trusted("10.20.30.40".parse().unwrap()));
+    assert!(proxy.is_trusted("127.0.0.1".parse().unwrap()));
+    assert!(!proxy.is_trusted("198.51.100.1".parse().unwrap()));
+
+    let toml = r#"
+[server.ws_proxy_protocol]
+trusted_networks = ["not-a-network"]
+"#;
+    assert!(Config::parse(toml).is_err());
+}
+
+#[test]
+fn test_shared_subscription_strategy() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(
+        config.mqtt.shared_subscription_strategy,
+        SharedSubscriptionStrategy::RoundRobin
+    );
+
+    let config = Config::parse("[mqtt]\nshared_subscription_strategy = \"sticky\"\n").unwrap();
+    assert_eq!(
+        config.mqtt.shared_subscription_strategy,
+        SharedSubscriptionStrategy::Sticky
+    );
+    assert!(Config::parse("[mqtt]\nshared_subscription_strategy = \"hash\"\n").is_err());
+}
+
+#[test]
+fn test_unix_listener() {
+    let config = Config::parse("").unwrap();
+    assert!(config.server.unix_bind.is_none());
+
+    let toml = r#"
+[server]
+unix_bind = "/run/vibemq/mqtt.sock"
+
+[server.unix_proxy_protocol]
+enabled = true
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(
+        config.server.unix_bind.as_deref(),
+        Some(Path::new("/run/vibemq/mqtt.sock"))
+    );
+    assert!(config.server.unix_proxy_protocol.enabled);
+}
+
+#[test]
+fn test_proxy_optional() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.server.proxy_protocol.optional);
+
+    let toml = r#"
+[server.tls_proxy_protocol]
+enabled = true
+optional = true
+trusted_networks = ["10.0.0.0/8"]
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert!(config.server.tls_proxy_protocol.optional);
+
+    // Direct clients could otherwise send a header of their own
+    let toml = toml.replace("trusted_networks = [\"10.0.0.0/8\"]\n", "");
+    let err = Config::parse(&toml).unwrap_err().to_string();
+    assert!(err.contains("server.tls_proxy_protocol"), "{}", err);
+    assert!(err.contains("trusted_networks"), "{}", err);
+}
+
+#[test]
+fn test_proxy_forwarded_for() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.server.ws_proxy_protocol.forwarded_for);
+
+    let toml = r#"
+[server.ws_proxy_protocol]
+enabled = true
+forwarded_for = true
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert!(config.server.ws_proxy_protocol.forwarded_for);
+}
+
+#[test]
+fn test_persistence_backend() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.persistence.backend, BackendType::Fjall);
+
+    let toml = r#"
+[persistence]
+backend = "memory"
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.persistence.backend, BackendType::Memory);
+    assert!(config.persistence.backup_before_migrate);
+
+    let config = Config::parse("[persistence]\nbackup_before_migrate = false\n").unwrap();
+    assert!(!config.persistence.backup_before_migrate);
+}
+
+#[test]
+fn test_max_qos_caps() {
+    let toml = r#"
+[server]
+max_qos = 1
+ws_max_qos = 0
+
+[acl]
+enabled = true
+
+[[acl.roles]]
+name = "public"
+max_qos = 0
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.server.max_qos, Some(1));
+    assert_eq!(config.server.tls_max_qos, None);
+    assert_eq!(config.server.ws_max_qos, Some(0));
+    assert_eq!(config.acl.roles[0].max_qos, Some(0));
+
+    assert!(Config::parse("[server]\ntls_max_qos = 3").is_err());
+    assert!(Config::parse("[[acl.roles]]\nname = \"r\"\nmax_qos = 3").is_err());
+}
+
+#[test]
+fn test_acl_session_policy() {
+    let toml = r#"
+[[acl.roles]]
+name = "gold"
+max_qos = 2
+
+[acl.roles.session]
+max_queued_messages = 10000
+max_session_expiry = "7d"
+max_keep_alive = 1200
+
+[acl.roles.session.quota]
+messages_per_sec = 500
+
+[acl.default.session]
+max_queued_messages = 100
+min_keep_alive = 10
+"#;
+    let config = Config::parse(toml).unwrap();
+    let gold = config.acl.roles[0].session;
+    assert_eq!(gold.max_queued_messages, Some(10000));
+    assert_eq!(
+        gold.max_session_expiry,
+        Some(Duration::from_secs(7 * 86400))
+    );
+    assert_eq!(gold.max_keep_alive, Some(1200));
+    assert_eq!(gold.quota.messages_per_sec, Some(500));
+    assert_eq!(config.acl.default.session.min_keep_alive, Some(10));
+
+    // The role's settings win over the defaults'
+    let policy = config.acl.default.session.merge(&gold);
+    assert_eq!(policy.max_queued_messages, Some(10000));
+    assert_eq!(policy.min_keep_alive, Some(10));
+
+    let inverted = "[acl.default.session]\nmin_keep_alive = 60\nmax_keep_alive = 30";
+    assert!(Config::parse(inverted).is_err());
+}
+
+#[test]
+fn test_listener_capabilities() {
+    let toml = r#"
+[server.ws_capabilities]
+retain_available = false
+shared_subscriptions = false
+topic_alias_maximum = 8
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.server.capabilities, ListenerCapabilities::default());
+    assert_eq!(
+        config.server.tls_capabilities,
+        ListenerCapabilities::default()
+    );
+    let ws = config.server.ws_capabilities;
+    assert!(!ws.retain_available);
+    assert!(ws.wildcard_subscriptions);
+    assert!(ws.subscription_identifiers);
+    assert!(!ws.shared_subscriptions);
+    assert_eq!(ws.topic_alias_maximum, Some(8));
+    assert_eq!(config.server.capabilities.topic_alias_maximum, None);
+}
+
+#[test]
+fn test_dual_stack_binds() {
+    let config = Config::parse("").unwrap();
+    assert!(config.server.extra_binds.is_empty());
+    assert_eq!(config.server.ipv6_only, None);
+
+    let toml = r#"
+[server]
+bind = "0.0.0.0:1883"
+extra_binds = ["[::]:1883"]
+ipv6_only = true
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(
+        config.server.extra_binds,
+        vec!["[::]:1883".parse::<SocketAddr>().unwrap()]
+    );
+    assert_eq!(config.server.ipv6_only, Some(true));
+
+    // A dual-stack wildcard would claim the IPv4 bind's port too
+    assert!(Config::parse(&toml.replace("true", "false")).is_err());
+    assert!(Config::parse("[server]\nbind = \"[::]:1883\"\nipv6_only = false\n").is_ok());
+    assert!(
+        Config::parse("[server]\nbind = \"0.0.0.0:1883\"\nextra_binds = [\"0.0.0.0:1883\"]\n")
+            .is_err()
+    );
+}
+
+#[test]
+fn test_acl_rule_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("acl.toml");
+    std::fs::write(
+        &path,
+        r##"
+[[roles]]
+name = "device"
+publish = ["devices/%c/#"]
+subscribe = ["commands/%u/#"]
+
+[default]
+subscribe = ["public/#"]
+"##,
+    )
+    .unwrap();
+
+    // Users may reference roles that only the rule file defines
+    let toml = format!(
+        r#"
+[auth]
+enabled = true
+
+[[auth.users]]
+username = "alice"
+password = "secret"
+role = "device"
+
+[acl]
+enabled = true
+rule_file = "{}"
+
+[[acl.roles]]
+name = "inline"
+"#,
+        path.display()
+    );
+    let config = Config::parse(&toml).unwrap();
+    assert_eq!(config.acl.roles[0].name, "inline");
+
+    let rules = config.acl.load_rules().unwrap();
+    assert!(rules.enabled);
+    assert_eq!(rules.roles.len(), 1);
+    assert_eq!(rules.roles[0].name, "device");
+    assert_eq!(rules.roles[0].publish, vec!["devices/%c/#"]);
+    assert_eq!(rules.default.subscribe, vec!["public/#"]);
+
+    // Without a rule file the inline rules apply
+    let inline = AclConfig {
+        rule_file: None,
+        ..config.acl.clone()
+    };
+    assert_eq!(inline.load_rules().unwrap().roles[0].name, "inline");
+
+    std::fs::write(&path, "[[roles]]\nname = \"device\"\nmax_qos = 3\n").unwrap();
+    assert!(config.acl.load_rules().is_err());
+    std::fs::write(&path, "[[rules]]\nname = \"device\"\n").unwrap();
+    assert!(config.acl.load_rules().is_err());
+    std::fs::remove_file(&path).unwrap();
+    assert!(config.acl.load_rules().is_err());
+}
+
+#[test]
+fn test_queue_overflow_config() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.limits.max_queued_bytes, 0);
+    assert!(config.limits.queue_qos0);
+    assert_eq!(config.limits.queue_overflow, QueueOverflow::DropOldest);
+
+    let config = Config::parse(
+        "[limits]\nmax_queued_bytes = 4096\nqueue_qos0 = false\nqueue_overflow = \"disconnect\"\n",
+    )
+    .unwrap();
+    assert_eq!(config.limits.max_queued_bytes, 4096);
+    assert!(!config.limits.queue_qos0);
+    assert_eq!(config.limits.queue_overflow, QueueOverflow::Disconnect);
+
+    assert!(Config::parse("[limits]\nqueue_overflow = \"block\"\n").is_err());
+}
+
+#[test]
+fn test_admin_requires_token() {
+    let toml = r#"
+[admin]
+enabled = true
+"#;
+    assert!(Config::parse(toml).is_err());
+
+    let toml = r#"
+[admin]
+enabled = true
+bind = "127.0.0.1:9090"
+token = "secret"
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.admin.bind.port(), 9090);
+    assert_eq!(config.admin.token.as_deref(), Some("secret"));
+}
+
+#[test]
+fn test_schedules() {
+    let toml = r#"
+[[schedule]]
+name = "heartbeat"
+cron = "*/5 * * * *"
+topic = "broker/{name}"
+payload = "alive"
+qos = 1
+misfire_grace = "1m"
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.schedule.len(), 1);
+    assert!(config.schedule[0].enabled);
+    assert_eq!(config.schedule[0].misfire_grace, Duration::from_secs(60));
+
+    for invalid in [
+        "name = \"a\"\ncron = \"61 * * * *\"\ntopic = \"t\"",
+        "name = \"a\"\ncron = \"@daily\"\ntopic = \"t/#\"",
+        "name = \"\"\ncron = \"@daily\"\ntopic = \"t\"",
+        "name = \"a\"\ncron = \"@daily\"\ntopic = \"t\"\npayload = \"{upper(nme)}\"",
+        "name = \"a\"\ncron = \"@daily\"\ntopic = \"t/{lower(name)}\"\nbudget = 0",
+    ] {
+        assert!(Config::parse(&format!("[[schedule]]\n{}", invalid)).is_err());
+    }
+
+    let duplicate = r#"
+[[schedule]]
+name = "a"
+cron = "@daily"
+topic = "t"
+
+[[schedule]]
+name = "a"
+cron = "@hourly"
+topic = "t"
+"#;
+    assert!(Config::parse(duplicate).is_err());
+}
+
+#[test]
+fn test_maintenance_windows() {
+    let toml = r#"
+[[maintenance]]
+name = "patch-night"
+cron = "0 2 * * sun"
+duration = "2h"
+pause_bridges = true
+log_level = "debug"
+reconnect_rate_factor = 10
+
+[[bridge]]
+name = "cloud"
+address = "cloud.example.com:1883"
+critical = true
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.maintenance.len(), 1);
+    let window = &config.maintenance[0];
+    assert!(window.enabled && window.pause_bridges && !window.suppress_wills);
+    assert_eq!(window.duration, Duration::from_secs(7200));
+    assert_eq!(window.reconnect_rate_factor, 10.0);
+    assert!(config.bridge[0].critical);
+
+    for invalid in [
+        "name = \"a\"\ncron = \"61 * * * *\"",
+        "name = \"\"\ncron = \"@daily\"",
+        "name = \"a\"\ncron = \"@daily\"\nduration = \"0s\"",
+        "name = \"a\"\ncron = \"@daily\"\nduration = \"8d\"",
+        "name = \"a\"\ncron = \"@daily\"\nreconnect_rate_factor = 0.5",
+        "name = \"a\"\ncron = \"@daily\"\nlog_level = \"vibemq=loud\"",
+    ] {
+        assert!(Config::parse(&format!("[[maintenance]]\n{}", invalid)).is_err());
+    }
+}
+
+#[test]
+fn test_aggregates() {
+    let toml = r#"
+[[aggregate]]
+name = "temp-avg"
+filter = "sensors/+/temperature"
+key_level = 1
+field = "reading.celsius"
+function = "avg"
+window = "5m"
+slide = "1m"
+output = "analytics/{key}/temperature"
+"#;
+    let config = Config::parse(toml).unwrap();
+    let aggregate = &config.aggregate[0];
+    assert_eq!(aggregate.function, AggregateFunction::Avg);
+    assert_eq!(aggregate.window, Duration::from_secs(300));
+    assert_eq!(aggregate.slide(), Duration::from_secs(60));
+    assert!(aggregate.alert.is_none());
+
+    // 7s doesn't divide 5m
+    let bad = toml.replace("slide = \"1m\"", "slide = \"7s\"");
+    assert!(Config::parse(&bad).is_err());
+
+    let alerting = format!(
+        "{}{}",
+        toml,
+        r#"
+[aggregate.alert]
+above = 30.0
+baseline_factor = 3.0
+topic = "alerts/{key}"
+webhook = "http://127.0.0.1:9000/alerts"
+"#
+    );
+    let config = Config::parse(&alerting).unwrap();
+    let alert = config.aggregate[0].alert.as_ref().unwrap();
+    assert_eq!(alert.above, Some(30.0));
+    assert_eq!(alert.baseline_alpha, 0.2);
+    assert_eq!(alert.qos, 1);
+
+    // An alert needs something to alert on
+    let no_condition = alerting.replace("above = 30.0\nbaseline_factor = 3.0\n", "");
+    assert!(Config::parse(&no_condition).is_err());
+}
+
+#[test]
+fn test_config_diff() {
+    let old = Config::parse(
+        r#"
+[server]
+bind = "0.0.0.0:1883"
+
+[acl]
+enabled = true
+"#,
+    )
+    .unwrap();
+    assert!(old.diff(&old.clone()).is_empty());
+
+    let new = Config::parse(
+        r#"
+[server]
+bind = "0.0.0.0:1883"
+ws_bind = "0.0.0.0:8080"
+workers = 4
+
+[acl]
+enabled = false
+
+[metrics]
+enabled = true
+"#,
+    )
+    .unwrap();
+    let diff = old.diff(&new);
+    assert_eq!(diff.applied, ["server", "acl"]);
+    assert_eq!(diff.restart_required, ["server.workers", "metrics"]);
+
+    // A startup-only field alone leaves its section unapplied
+    let mut workers = old.clone();
+    workers.server.workers = 8;
+    let diff = old.diff(&workers);
+    assert!(diff.applied.is_empty());
+    assert_eq!(diff.restart_required, ["server.workers"]);
+}
+
+#[test]
+fn test_shutdown_config() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.shutdown.drain_timeout, Duration::from_secs(10));
+    assert!(config.shutdown.disconnect);
+
+    let toml = r#"
+[shutdown]
+drain_timeout = "30s"
+server_reference = "mqtt-b.example.com:1883"
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.shutdown.drain_timeout, Duration::from_secs(30));
+    assert_eq!(
+        config.shutdown.server_reference.as_deref(),
+        Some("mqtt-b.example.com:1883")
+    );
+}
+
+#[test]
+fn test_geofences() {
+    let toml = r#"
+[[geofence]]
+name = "depot"
+filter = "fleet/+/position"
+key_level = 1
+polygon = [[52.52, 13.40], [52.52, 13.42], [52.53, 13.42]]
+output = "geofence/{name}/{key}"
+"#;
+    let config = Config::parse(toml).unwrap();
+    let geofence = &config.geofence[0];
+    assert_eq!(geofence.lat_field, "lat");
+    assert_eq!(geofence.polygon.len(), 3);
+
+    // Two vertices don't make a polygon
+    let invalid = toml.replace(", [52.53, 13.42]]", "]");
+    assert!(Config::parse(&invalid).is_err());
+
+    let cbor = format!(
+        "{}\n[geofence.payload]\nformat = \"cbor\"\noutput_format = \"msgpack\"\n",
+        toml
+    );
+    let config = Config::parse(&cbor).unwrap();
+    assert_eq!(config.geofence[0].payload.format, PayloadFormat::Cbor);
+    assert_eq!(
+        config.geofence[0].payload.output_format,
+        PayloadFormat::Msgpack
+    );
+
+    // Protobuf needs its descriptors
+    let protobuf = format!("{}\n[geofence.payload]\nformat = \"protobuf\"\n", toml);
+    assert!(Config::parse(&protobuf).is_err());
+}
+
+#[test]
+fn test_enrich() {
+    let toml = r#"
+[[enrich]]
+name = "sites"
+filter = "sensors/+/temp"
+key_field = "device.id"
+output = "enriched/{topic}"
+on_miss = "drop"
+
+[enrich.table]
+command = ["sqlite3", "-json", "sites.db", "SELECT id, site FROM devices"]
+format = "json"
+ttl = "5m"
+"#;
+    let config = Config::parse(toml).unwrap();
+    let enrich = &config.enrich[0];
+    assert_eq!(enrich.on_miss, EnrichMiss::Drop);
+    assert_eq!(enrich.table.format(), Some(TableFormat::Json));
+    assert_eq!(enrich.table.key_column, "id");
+    assert_eq!(enrich.table.ttl, Duration::from_secs(300));
+
+    // The format comes from the file name
+    let toml = r#"
+[[enrich]]
+name = "sites"
+filter = "sensors/+/temp"
+key_level = 1
+output = "enriched/{topic}"
+table = { path = "/etc/vibemq/sites.csv" }
+"#;
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.enrich[0].table.format(), Some(TableFormat::Csv));
+
+    let toml = r#"
+[[enrich]]
+name = "sites"
+filter = "sensors/+/temp"
+output = "enriched/{topic}"
+table = { path = "/etc/vibemq/sites.csv" }
+"#;
+    assert!(Config::parse(toml).is_err());
+}
+
+#[test]
+fn test_rules() {
+    let toml = r#"
+[[rule]]
+name = "telemetry"
+filter = "sensors/#"
+ack_action = "kafka"
+ack_fallback = "puback"
+
+[[rule.actions]]
+topic = "archive/{topic}"
+qos = 1
+
+[[rule.actions]]
+name = "kafka"
+type = "webhook"
+url = "http://kafka-rest:8082/topics/telemetry"
+retries = 0
+dead_letter = "dlq/{name}/{action}"
+"#;
+    let config = Config::parse(toml).unwrap();
+    let rule = &config.rule[0];
+    assert!(rule.enabled);
+    assert_eq!(rule.actions[0].kind, ActionKind::Republish);
+    assert_eq!(rule.actions[0].retries, 3);
+    assert_eq!(rule.actions[1].kind, ActionKind::Webhook);
+    assert_eq!(rule.actions[1].timeout, Duration::from_secs(5));
+    assert_eq!(rule.actions[1].queue_size, 1024);
+    assert_eq!(rule.ack_fallback, AckFallback::Puback);
+    assert_eq!(rule.ack_timeout, Duration::from_secs(5));
+
+    // A webhook action needs a URL
+    let toml = r#"
+[[rule]]
+name = "telemetry"
+filter = "sensors/#"
+actions = [{ type = "webhook" }]
+"#;
+    assert!(Config::parse(toml).is_err());
+
+    let toml = r#"
+[[rule]]
+name = "telemetry"
+filter = "sensors/#"
+actions = [{ topic = "a/{topic}" }]
+
+[[rule]]
+name = "telemetry"
+filter = "alerts/#"
+actions = [{ topic = "b/{topic}" }]
+"#;
+    assert!(Config::parse(toml).is_err());
+}
+
+#[test]
+fn test_transaction_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.transaction.enabled);
+    assert_eq!(config.transaction.max_messages, 1000);
+
+    let toml = r#"
+[transaction]
+enabled = true
+topic = "$tx/v1"
+"#;
+    let transaction = Config::parse(toml).unwrap().transaction;
+    assert_eq!(
+        transaction.control("$tx/v1/begin"),
+        Some(Some(TransactionControl::Begin))
+    );
+    assert_eq!(
+        transaction.control("$tx/v1/abort"),
+        Some(Some(TransactionControl::Abort))
+    );
+    assert_eq!(transaction.control("$tx/v1/rollback"), Some(None));
+    assert_eq!(transaction.control("$tx/v1"), None);
+    assert_eq!(transaction.control("$tx/v10/commit"), None);
+
+    let toml = r#"
+[transaction]
+enabled = true
+topic = "$txn/+"
+"#;
+    assert!(Config::parse(toml).is_err());
+}
+
+#[test]
+fn test_handover_config() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.handover.enabled);
+    assert_eq!(config.handover.token_ttl, Duration::from_secs(60));
+
+    let toml = r#"
+[handover]
+enabled = true
+topic = "$failover"
+token_ttl = "5m"
+"#;
+    let handover = Config::parse(toml).unwrap().handover;
+    assert_eq!(handover.token_ttl, Duration::from_secs(300));
+    assert_eq!(
+        handover.control("$failover/prepare"),
+        Some(Some(HandoverControl::Prepare))
+    );
+    assert_eq!(handover.control("$failover/token"), Some(None));
+    assert_eq!(handover.control("$handover/prepare"), None);
+    assert_eq!(handover.token_topic(), "$failover/token");
+
+    let toml = r#"
+[handover]
+enabled = true
+token_ttl = "0s"
+"#;
+    assert!(Config::parse(toml).is_err());
+}
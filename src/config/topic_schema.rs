@@ -0,0 +1,43 @@
+//! Topic Schema Configuration
+//!
+//! Configuration for the declared topic namespace: the templates client
+//! publishes are expected to match, and what to do with those that match
+//! none.
+
+use serde::Deserialize;
+
+/// What to do with a publish that matches no template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TopicSchemaMode {
+    /// Don't check topics
+    #[default]
+    Off,
+    /// Route the message, but log and count it
+    Warn,
+    /// Refuse the message (PUBACK/PUBREC "Topic Name invalid")
+    Reject,
+}
+
+/// A declared topic template
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(default)]
+pub struct TopicTemplateConfig {
+    /// Template such as "sensors/{site}/{device:int}/{metric:temp|humidity}",
+    /// see [`crate::topic::TopicSchema`]
+    pub pattern: String,
+    /// What the topics are for
+    pub description: Option<String>,
+    /// Who owns the namespace (team, service)
+    pub owner: Option<String>,
+}
+
+/// Topic schema configuration
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(default)]
+pub struct TopicSchemaConfig {
+    pub mode: TopicSchemaMode,
+    /// Templates; a topic is valid if it matches any of them. Topics
+    /// starting with "$" are never checked
+    pub templates: Vec<TopicTemplateConfig>,
+}
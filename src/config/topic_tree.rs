@@ -0,0 +1,30 @@
+//! Topic Tree Configuration
+//!
+//! Configuration for the topic tree export (`GET /api/v1/topics/tree`),
+//! which shows the concrete topics in use and the subscription filters.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Topic tree export configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct TopicTreeConfig {
+    /// Distinct topics of recent traffic remembered for the export, on top
+    /// of the retained ones (0 = retained topics only)
+    pub recent_topics: usize,
+    /// How long a topic nobody publishes to stays in the export
+    /// (default: 10m)
+    #[serde(with = "humantime_serde")]
+    pub recent_window: Duration,
+}
+
+impl Default for TopicTreeConfig {
+    fn default() -> Self {
+        Self {
+            recent_topics: 0,
+            recent_window: Duration::from_secs(600),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Transactional Publish Configuration
+//!
+//! Configuration for publish transactions, which let a client publish a
+//! batch of messages that subscribers see all at once or not at all.
+
+use serde::Deserialize;
+
+/// Publish transaction configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TransactionConfig {
+    /// Handle the transaction control topics
+    pub enabled: bool,
+    /// Control topic prefix. Publishing to "{topic}/begin" opens a
+    /// transaction, "{topic}/commit" routes its messages and "{topic}/abort"
+    /// discards them (default: "$txn")
+    pub topic: String,
+    /// Maximum messages per transaction; a transaction growing past it is
+    /// aborted (default: 1000, 0 = unlimited)
+    pub max_messages: usize,
+}
+
+impl Default for TransactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            topic: "$txn".to_string(),
+            max_messages: 1000,
+        }
+    }
+}
+
+/// A transaction control message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionControl {
+    Begin,
+    Commit,
+    Abort,
+}
+
+impl TransactionConfig {
+    /// Get the control message if `topic` is under the control topic
+    ///
+    /// Returns `Some(None)` for an unknown command.
+    pub fn control(&self, topic: &str) -> Option<Option<TransactionControl>> {
+        let command = topic.strip_prefix(self.topic.as_str())?.strip_prefix('/')?;
+        Some(match command {
+            "begin" => Some(TransactionControl::Begin),
+            "commit" => Some(TransactionControl::Commit),
+            "abort" => Some(TransactionControl::Abort),
+            _ => None,
+        })
+    }
+}
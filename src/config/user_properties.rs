@@ -0,0 +1,59 @@
+//! User Property Condition Configuration
+//!
+//! Conditions on a message's MQTT 5 user properties, shared by routing
+//! rules, ACL publish rules and bridge forwards:
+//!
+//! ```toml
+//! user_properties = [
+//!     { name = "env", value = "prod" },       # exact value
+//!     { name = "tenant", prefix = "acme-" },  # value prefix
+//!     { name = "trace" },                     # present, any value
+//! ]
+//! ```
+//!
+//! A list of conditions matches when every entry does; an entry matches
+//! when any property of that name does (names may repeat in MQTT 5).
+
+use serde::Deserialize;
+
+/// One condition on a message's user properties
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(default)]
+pub struct UserPropertyMatch {
+    /// Property name (case-sensitive)
+    pub name: String,
+    /// The property's value must be exactly this
+    pub value: Option<String>,
+    /// The property's value must start with this
+    pub prefix: Option<String>,
+}
+
+impl UserPropertyMatch {
+    /// Property present with the exact value
+    pub fn exact(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: Some(value.to_string()),
+            prefix: None,
+        }
+    }
+
+    /// Property present with a value starting with `prefix`
+    pub fn prefix(name: &str, prefix: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: None,
+            prefix: Some(prefix.to_string()),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.name.is_empty() {
+            return Err("user property name must not be empty");
+        }
+        if self.value.is_some() && self.prefix.is_some() {
+            return Err("user property condition sets both value and prefix");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+//! Lookup-Table Enrichment
+//!
+//! Adds operator-maintained metadata to messages in flight: each
+//! `[[enrich]]` entry subscribes to a topic filter, takes a key from every
+//! message (a topic level or a payload field, e.g. the device ID), looks
+//! it up in a table (see [`table`]) and republishes the payload with the
+//! row's fields added:
+//!
+//! ```json
+//! {"temp": 21.5, "site": "berlin-2", "region": "eu-central"}
+//! ```
+//!
+//! Payloads are decoded and encoded as configured (see [`crate::payload`])
+//! and must be objects. The table is reloaded in the background, so a
+//! changed file or query result applies without a restart.
+
+pub mod table;
+
+pub use table::{LookupTable, TableError, TableSource};
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde_json::{Map, Value};
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+use crate::broker::{Broker, LocalPublish};
+use crate::config::{EnrichConfig, EnrichMiss, LookupTableConfig, TableFormat};
+use crate::payload::{PayloadCodec, PayloadError};
+use crate::protocol::QoS;
+use crate::topic::{validate_topic_filter, validate_topic_name};
+
+/// Why an enrichment rule is invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnrichError {
+    InvalidFilter(&'static str),
+    InvalidOutput(&'static str),
+    /// Neither or both of `key_level` and `key_field`
+    InvalidKey,
+    InvalidTable(&'static str),
+    InvalidQos(u8),
+    InvalidPayload(PayloadError),
+}
+
+impl fmt::Display for EnrichError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnrichError::InvalidFilter(e) => write!(f, "invalid filter: {}", e),
+            EnrichError::InvalidOutput(e) => write!(f, "invalid output topic: {}", e),
+            EnrichError::InvalidKey => write!(f, "set exactly one of key_level and key_field"),
+            EnrichError::InvalidTable(e) => write!(f, "invalid table: {}", e),
+            EnrichError::InvalidQos(qos) => write!(f, "qos must be 0, 1, or 2, got {}", qos),
+            EnrichError::InvalidPayload(e) => write!(f, "invalid payload: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EnrichError {}
+
+/// Where a message's key is
+#[derive(Debug, Clone)]
+enum Key {
+    Level(usize),
+    Field(Vec<String>),
+}
+
+/// A validated `[[enrich]]` entry
+#[derive(Debug, Clone)]
+pub struct Enrichment {
+    name: String,
+    filter: String,
+    key: Key,
+    payload: PayloadCodec,
+    table: LookupTableConfig,
+    format: TableFormat,
+    into: Option<Vec<String>>,
+    on_miss: EnrichMiss,
+    output: String,
+    qos: QoS,
+    retain: bool,
+    username: Option<String>,
+}
+
+impl Enrichment {
+    pub fn new(config: &EnrichConfig) -> Result<Self, EnrichError> {
+        validate_topic_filter(&config.filter).map_err(EnrichError::InvalidFilter)?;
+        let path = |field: &str| field.split('.').map(String::from).collect();
+        let key = match (config.key_level, &config.key_field) {
+            (Some(level), None) => Key::Level(level),
+            (None, Some(field)) => Key::Field(path(field)),
+            _ => return Err(EnrichError::InvalidKey),
+        };
+        let table = &config.table;
+        if table.path.is_some() != table.command.is_empty() {
+            return Err(EnrichError::InvalidTable(
+                "set exactly one of path and command",
+            ));
+        }
+        let format = table.format().ok_or(EnrichError::InvalidTable(
+            "format is needed for commands and files not named .csv or .json",
+        ))?;
+        if table.key_column.is_empty() {
+            return Err(EnrichError::InvalidTable("key_column must not be empty"));
+        }
+        if table.ttl.is_zero() {
+            return Err(EnrichError::InvalidTable("ttl must be positive"));
+        }
+        let qos = QoS::from_u8(config.qos).ok_or(EnrichError::InvalidQos(config.qos))?;
+        let enrichment = Self {
+            name: config.name.clone(),
+            filter: config.filter.clone(),
+            key,
+            payload: PayloadCodec::new(&config.payload).map_err(EnrichError::InvalidPayload)?,
+            table: table.clone(),
+            format,
+            into: config.into.as_deref().map(path),
+            on_miss: config.on_miss,
+            output: config.output.clone(),
+            qos,
+            retain: config.retain,
+            username: config.username.clone(),
+        };
+        validate_topic_name(&enrichment.output_topic("topic", "key"))
+            .map_err(EnrichError::InvalidOutput)?;
+        Ok(enrichment)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn output_topic(&self, topic: &str, key: &str) -> String {
+        self.output
+            .replace("{topic}", topic)
+            .replace("{key}", key)
+            .replace("{name}", &self.name)
+    }
+
+    /// A message's key (`None` if it has none)
+    fn key(&self, topic: &str, payload: &Map<String, Value>) -> Option<String> {
+        match self.key {
+            Key::Level(level) => topic
+                .split('/')
+                .nth(level)
+                .filter(|key| !key.is_empty())
+                .map(String::from),
+            Key::Field(ref path) => {
+                let (first, rest) = path.split_first()?;
+                let value = rest
+                    .iter()
+                    .try_fold(payload.get(first)?, |v, key| v.get(key))?;
+                table::key_string(value)
+            }
+        }
+    }
+
+    /// The payload with the row added (`None` for a miss to drop)
+    fn enrich(
+        &self,
+        mut payload: Map<String, Value>,
+        row: Option<&Map<String, Value>>,
+    ) -> Option<Map<String, Value>> {
+        let Some(row) = row else {
+            return match self.on_miss {
+                EnrichMiss::Forward => Some(payload),
+                EnrichMiss::Drop => None,
+            };
+        };
+        match self.into {
+            Some(ref path) => {
+                let (last, parents) = path.split_last()?;
+                let mut object = &mut payload;
+                for key in parents {
+                    let entry = object
+                        .entry(key.clone())
+                        .or_insert_with(|| Value::Object(Map::new()));
+                    if !entry.is_object() {
+                        *entry = Value::Object(Map::new());
+                    }
+                    object = entry.as_object_mut()?;
+                }
+                object.insert(last.clone(), Value::Object(row.clone()));
+            }
+            None => {
+                for (column, value) in row {
+                    payload
+                        .entry(column.clone())
+                        .or_insert_with(|| value.clone());
+                }
+            }
+        }
+        Some(payload)
+    }
+
+    /// Subscribe and republish enriched messages until the broker shuts down
+    pub async fn run(self, broker: Arc<Broker>) {
+        let mut publisher = broker.local_publisher(&format!("enrich:{}", self.name));
+        if let Some(ref username) = self.username {
+            publisher = publisher.with_username(username.clone());
+        }
+        let mut subscription = match publisher.subscribe(&self.filter, QoS::AtMostOnce).await {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                warn!("Enrich '{}' cannot subscribe: {}", self.name, e);
+                return;
+            }
+        };
+        let (tx, table) = watch::channel(Arc::new(LookupTable::default()));
+        let source = TableSource::new(self.table.clone(), self.format);
+        tokio::spawn(source.run(self.name.clone(), tx));
+        let mut shutdown = broker.subscribe_shutdown();
+
+        loop {
+            tokio::select! {
+                message = subscription.recv() => {
+                    let Some(publish) = message else {
+                        warn!("Enrich '{}' lost its subscription", self.name);
+                        return;
+                    };
+                    let Some(Value::Object(payload)) = self.payload.decode(&publish.payload) else {
+                        debug!("Enrich '{}': {} is not an object", self.name, publish.topic);
+                        continue;
+                    };
+                    let Some(key) = self.key(&publish.topic, &payload) else {
+                        debug!("Enrich '{}': no key in {}", self.name, publish.topic);
+                        continue;
+                    };
+                    let table = table.borrow().clone();
+                    let Some(payload) = self.enrich(payload, table.get(&key)) else {
+                        continue;
+                    };
+                    let message = LocalPublish::new(
+                        self.output_topic(&publish.topic, &key),
+                        self.payload.encode(&Value::Object(payload)),
+                    )
+                    .with_qos(self.qos)
+                    .with_retain(self.retain)
+                    .caused_by(&publish);
+                    if let Err(e) = publisher.publish(message).await {
+                        warn!("Enrich '{}' failed to publish: {}", self.name, e);
+                    }
+                }
+                _ = shutdown.recv() => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> EnrichConfig {
+        EnrichConfig {
+            name: "sites".to_string(),
+            filter: "sensors/+/temp".to_string(),
+            key_level: Some(1),
+            table: LookupTableConfig {
+                path: Some("/etc/vibemq/sites.csv".into()),
+                ..Default::default()
+            },
+            output: "enriched/{topic}".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_key() {
+        let by_level = Enrichment::new(&config()).unwrap();
+        let payload = object(json!({"meta": {"device": 42}}));
+        assert_eq!(
+            by_level.key("sensors/dev1/temp", &payload),
+            Some("dev1".to_string())
+        );
+        assert_eq!(by_level.key("sensors", &payload), None);
+        assert_eq!(
+            by_level.output_topic("sensors/dev1/temp", "dev1"),
+            "enriched/sensors/dev1/temp"
+        );
+
+        let mut by_field = config();
+        by_field.key_level = None;
+        by_field.key_field = Some("meta.device".to_string());
+        let by_field = Enrichment::new(&by_field).unwrap();
+        assert_eq!(
+            by_field.key("sensors/dev1/temp", &payload),
+            Some("42".to_string())
+        );
+        assert_eq!(by_field.key("sensors/dev1/temp", &Map::new()), None);
+    }
+
+    #[test]
+    fn test_enrich() {
+        let row = object(json!({"site": "berlin", "temp": "row"}));
+        let merged = Enrichment::new(&config()).unwrap();
+        assert_eq!(
+            merged.enrich(object(json!({"temp": 21.5})), Some(&row)),
+            Some(object(json!({"temp": 21.5, "site": "berlin"})))
+        );
+        assert_eq!(
+            merged.enrich(object(json!({"temp": 21.5})), None),
+            Some(object(json!({"temp": 21.5})))
+        );
+
+        let mut nested = config();
+        nested.into = Some("meta.location".to_string());
+        nested.on_miss = EnrichMiss::Drop;
+        let nested = Enrichment::new(&nested).unwrap();
+        assert_eq!(
+            nested.enrich(object(json!({"temp": 21.5, "meta": 1})), Some(&row)),
+            Some(object(
+                json!({"temp": 21.5, "meta": {"location": {"site": "berlin", "temp": "row"}}})
+            ))
+        );
+        assert_eq!(nested.enrich(object(json!({"temp": 21.5})), None), None);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let new = |change: fn(&mut EnrichConfig)| {
+            let mut config = config();
+            change(&mut config);
+            Enrichment::new(&config).unwrap_err()
+        };
+        assert_eq!(
+            new(|c| c.key_field = Some("id".to_string())),
+            EnrichError::InvalidKey
+        );
+        assert!(matches!(
+            new(|c| c.table.command = vec!["sqlite3".to_string()]),
+            EnrichError::InvalidTable(_)
+        ));
+        assert!(matches!(
+            new(|c| c.table.path = Some("/etc/vibemq/sites.txt".into())),
+            EnrichError::InvalidTable(_)
+        ));
+        assert!(matches!(
+            new(|c| c.output = "enriched/#".to_string()),
+            EnrichError::InvalidOutput(_)
+        ));
+    }
+}
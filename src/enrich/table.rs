@@ -0,0 +1,345 @@
+//! Lookup Tables
+//!
+//! A table maps keys to rows of fields. It's read from a CSV or JSON file
+//! or from what a command prints, and kept fresh by a [`TableSource`]
+//! task: files are re-read when their modification time changes, commands
+//! re-run every `ttl`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use serde_json::{Map, Value};
+use tokio::process::Command;
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+use crate::config::{LookupTableConfig, TableFormat};
+
+/// Why a table couldn't be loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableError {
+    Io(String),
+    /// The command failed or timed out
+    Command(String),
+    Parse(String),
+    /// The CSV header has no key column
+    MissingKeyColumn(String),
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::Io(e) => write!(f, "cannot read table: {}", e),
+            TableError::Command(e) => write!(f, "table command failed: {}", e),
+            TableError::Parse(e) => write!(f, "invalid table: {}", e),
+            TableError::MissingKeyColumn(column) => {
+                write!(f, "table has no '{}' column", column)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TableError {}
+
+/// Rows by key
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LookupTable {
+    rows: HashMap<String, Map<String, Value>>,
+}
+
+impl LookupTable {
+    /// Parse a table; rows without a key are skipped
+    ///
+    /// CSV cells are strings. JSON tables are an array of objects, or an
+    /// object whose keys are the keys and values the rows.
+    pub fn parse(text: &str, format: TableFormat, key_column: &str) -> Result<Self, TableError> {
+        let mut rows = HashMap::new();
+        match format {
+            TableFormat::Csv => {
+                let mut records = parse_csv(text)?.into_iter();
+                let header = records.next().unwrap_or_default();
+                let key = header
+                    .iter()
+                    .position(|column| column == key_column)
+                    .ok_or_else(|| TableError::MissingKeyColumn(key_column.to_string()))?;
+                for record in records {
+                    let Some(id) = record.get(key).filter(|id| !id.is_empty()) else {
+                        continue;
+                    };
+                    let row = header
+                        .iter()
+                        .zip(&record)
+                        .map(|(column, cell)| (column.clone(), Value::String(cell.clone())))
+                        .collect();
+                    rows.insert(id.clone(), row);
+                }
+            }
+            TableFormat::Json => {
+                let value: Value =
+                    serde_json::from_str(text).map_err(|e| TableError::Parse(e.to_string()))?;
+                match value {
+                    Value::Array(entries) => {
+                        for entry in entries {
+                            let Value::Object(row) = entry else {
+                                return Err(TableError::Parse("rows must be objects".to_string()));
+                            };
+                            if let Some(id) = row.get(key_column).and_then(key_string) {
+                                rows.insert(id, row);
+                            }
+                        }
+                    }
+                    Value::Object(entries) => {
+                        for (id, entry) in entries {
+                            let Value::Object(row) = entry else {
+                                return Err(TableError::Parse("rows must be objects".to_string()));
+                            };
+                            rows.insert(id, row);
+                        }
+                    }
+                    _ => {
+                        return Err(TableError::Parse(
+                            "expected an array or an object".to_string(),
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(Self { rows })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Map<String, Value>> {
+        self.rows.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+/// A key as a string (strings and numbers only)
+pub(super) fn key_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Records of an RFC 4180 CSV document (quoted fields may hold commas,
+/// quotes as `""` and line breaks)
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, TableError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.strip_prefix('\u{feff}').unwrap_or(text).chars();
+    let mut quoted = false;
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') => match chars.clone().next() {
+                Some('"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                _ => quoted = false,
+            },
+            (true, c) => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                if record.len() > 1 || !record[0].is_empty() {
+                    records.push(std::mem::take(&mut record));
+                } else {
+                    record.clear();
+                }
+                line += 1;
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(TableError::Parse(format!(
+            "unterminated quote on line {}",
+            line
+        )));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Loads a table and publishes each new version
+pub struct TableSource {
+    config: LookupTableConfig,
+    format: TableFormat,
+    /// Modification time of the file last read
+    modified: Option<SystemTime>,
+}
+
+impl TableSource {
+    /// `format` is the config's (validated) table format
+    pub fn new(config: LookupTableConfig, format: TableFormat) -> Self {
+        Self {
+            config,
+            format,
+            modified: None,
+        }
+    }
+
+    /// The table, unless the file hasn't changed since the last load
+    pub async fn load(&mut self) -> Result<Option<LookupTable>, TableError> {
+        let text = match self.config.path {
+            Some(ref path) => {
+                let modified = tokio::fs::metadata(path)
+                    .await
+                    .and_then(|m| m.modified())
+                    .map_err(|e| TableError::Io(format!("{}: {}", path.display(), e)))?;
+                if self.modified == Some(modified) {
+                    return Ok(None);
+                }
+                let text = tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|e| TableError::Io(format!("{}: {}", path.display(), e)))?;
+                self.modified = Some(modified);
+                text
+            }
+            None => self.run_command().await?,
+        };
+        LookupTable::parse(&text, self.format, &self.config.key_column).map(Some)
+    }
+
+    async fn run_command(&self) -> Result<String, TableError> {
+        let (program, args) = self
+            .config
+            .command
+            .split_first()
+            .ok_or_else(|| TableError::Command("no command".to_string()))?;
+        let output = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(self.config.timeout, output)
+            .await
+            .map_err(|_| TableError::Command(format!("timed out after {:?}", self.config.timeout)))?
+            .map_err(|e| TableError::Command(format!("{}: {}", program, e)))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(TableError::Command(format!(
+                "{} exited with {}: {}",
+                program,
+                output.status,
+                stderr.trim()
+            )));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|_| TableError::Command("output is not UTF-8".to_string()))
+    }
+
+    /// Reload every `ttl` until every receiver is gone
+    ///
+    /// A table that fails to load keeps the previous one in use.
+    pub async fn run(mut self, name: String, tx: watch::Sender<Arc<LookupTable>>) {
+        let mut ttl = tokio::time::interval(self.config.ttl);
+        ttl.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = ttl.tick() => {}
+                _ = tx.closed() => return,
+            }
+            match self.load().await {
+                Ok(Some(table)) => {
+                    debug!("Enrich '{}' loaded {} table rows", name, table.len());
+                    if tx.send(Arc::new(table)).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Enrich '{}': {}", name, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_csv() {
+        let text = "\u{feff}device_id,site,note\r\n\
+                    dev1,berlin,\"line, \"\"one\"\"\"\r\n\
+                    \r\n\
+                    dev2,hamburg,\"two\nlines\"\n\
+                    ,orphan,x\n\
+                    dev3,munich";
+        let table = LookupTable::parse(text, TableFormat::Csv, "device_id").unwrap();
+        assert_eq!(table.len(), 3);
+        assert_eq!(
+            Value::Object(table.get("dev1").unwrap().clone()),
+            json!({"device_id": "dev1", "site": "berlin", "note": "line, \"one\""})
+        );
+        assert_eq!(table.get("dev2").unwrap()["note"], "two\nlines");
+        assert_eq!(table.get("dev3").unwrap()["site"], "munich");
+
+        assert_eq!(
+            LookupTable::parse(text, TableFormat::Csv, "id"),
+            Err(TableError::MissingKeyColumn("id".to_string()))
+        );
+        assert!(LookupTable::parse("id\n\"dev1", TableFormat::Csv, "id").is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        let array = r#"[{"id": 7, "site": "berlin"}, {"site": "nowhere"}, {"id": "dev2"}]"#;
+        let table = LookupTable::parse(array, TableFormat::Json, "id").unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get("7").unwrap()["site"], "berlin");
+        assert!(table.get("dev2").is_some());
+
+        let object = r#"{"dev1": {"site": "berlin", "floor": 3}}"#;
+        let table = LookupTable::parse(object, TableFormat::Json, "id").unwrap();
+        assert_eq!(table.get("dev1").unwrap()["floor"], 3);
+
+        assert!(LookupTable::parse("[1, 2]", TableFormat::Json, "id").is_err());
+        assert!(LookupTable::parse("\"x\"", TableFormat::Json, "id").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_command_source() {
+        let mut source = TableSource::new(
+            LookupTableConfig {
+                command: vec![
+                    "sh".to_string(),
+                    "-c".to_string(),
+                    "printf 'id,site\\ndev1,berlin\\n'".to_string(),
+                ],
+                ..Default::default()
+            },
+            TableFormat::Csv,
+        );
+        let table = source.load().await.unwrap().unwrap();
+        assert_eq!(table.get("dev1").unwrap()["site"], "berlin");
+
+        source.config.command = vec!["sh".to_string(), "-c".to_string(), "exit 3".to_string()];
+        assert!(matches!(source.load().await, Err(TableError::Command(_))));
+    }
+}
@@ -9,6 +9,7 @@
 
 use std::net::IpAddr;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use dashmap::DashMap;
@@ -16,6 +17,8 @@ use ipnet::IpNet;
 use serde::Deserialize;
 use tracing::{debug, info, warn};
 
+use crate::broker::Limiters;
+
 /// Reason for rejecting a connection
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RejectionReason {
@@ -224,11 +227,22 @@ pub struct FlappingDetector {
     allowed_cidrs: Vec<IpNet>,
     /// Tracker start time for relative timestamps
     start_time: Instant,
+    /// Per-IP limit boosts (see [`crate::broker::Limiters`])
+    limiters: Option<Arc<Limiters>>,
 }
 
 impl FlappingDetector {
     /// Create a new flapping detector
-    pub fn new(flapping_config: FlappingConfig, limit_config: ConnectionLimitConfig) -> Self {
+    pub fn new(flapping_config: FlappingConfig, mut limit_config: ConnectionLimitConfig) -> Self {
+        // Match v4-mapped entries against the IPv4 addresses peers resolve to
+        for ip in limit_config
+            .banned_ips
+            .iter_mut()
+            .chain(limit_config.allowed_ips.iter_mut())
+        {
+            *ip = ip.to_canonical();
+        }
+
         // Parse CIDR ranges
         let banned_cidrs: Vec<IpNet> = limit_config
             .banned_cidrs
@@ -277,9 +291,21 @@ impl FlappingDetector {
             banned_cidrs,
             allowed_cidrs,
             start_time: Instant::now(),
+            limiters: None,
         }
     }
 
+    /// Apply the limit boosts of `ip:<address>` identities
+    pub fn with_limiters(mut self, limiters: Arc<Limiters>) -> Self {
+        self.limiters = Some(limiters);
+        self
+    }
+
+    /// Rate, burst and connection cap multiplier of an IP
+    fn boost(&self, ip: IpAddr) -> f64 {
+        self.limiters.as_ref().map_or(1.0, |l| l.boost_ip(ip))
+    }
+
     /// Get current time in milliseconds since start
     fn now_ms(&self) -> u64 {
         self.start_time.elapsed().as_millis() as u64
@@ -335,6 +361,7 @@ impl FlappingDetector {
     /// Check if a connection should be allowed
     /// Returns Ok(()) if allowed, Err(reason) if rejected
     pub fn check_connection(&self, ip: IpAddr) -> Result<(), RejectionReason> {
+        let ip = ip.to_canonical();
         // Allowed IPs bypass all checks
         if self.is_allowed(ip) {
             return Ok(());
@@ -359,12 +386,13 @@ impl FlappingDetector {
             .ip_state
             .entry(ip)
             .or_insert_with(|| IpState::new(self.limit_config.rate_burst, now_ms));
+        let boost = self.boost(ip);
 
         // Check rate limit
         if self.limit_config.rate_limit > 0
             && !state.try_consume_token(
-                self.limit_config.rate_limit,
-                self.limit_config.rate_burst,
+                (self.limit_config.rate_limit as f64 * boost) as u32,
+                (self.limit_config.rate_burst as f64 * boost) as u32,
                 now_ms,
             )
         {
@@ -375,7 +403,8 @@ impl FlappingDetector {
         // Check max connections per IP
         if self.limit_config.max_connections_per_ip > 0 {
             let count = state.connection_count.load(Ordering::Relaxed) as usize;
-            if count >= self.limit_config.max_connections_per_ip {
+            let max = (self.limit_config.max_connections_per_ip as f64 * boost) as usize;
+            if count >= max {
                 debug!(
                     "Connection from {} rejected: max connections ({}) exceeded",
                     ip, count
@@ -389,6 +418,7 @@ impl FlappingDetector {
 
     /// Record a successful connection
     pub fn record_connection(&self, ip: IpAddr) {
+        let ip = ip.to_canonical();
         if self.is_allowed(ip) {
             return;
         }
@@ -403,6 +433,7 @@ impl FlappingDetector {
 
     /// Record a disconnection and check for flapping
     pub fn record_disconnection(&self, ip: IpAddr) {
+        let ip = ip.to_canonical();
         if self.is_allowed(ip) {
             return;
         }
@@ -420,8 +451,8 @@ impl FlappingDetector {
             // Check for flapping if enabled
             if self.flapping_config.enabled {
                 let window_ms = self.flapping_config.window_time.as_millis() as u64;
-                let should_ban =
-                    state.record_disconnect(self.flapping_config.max_count, window_ms, now_ms);
+                let max_count = (self.flapping_config.max_count as f64 * self.boost(ip)) as u32;
+                let should_ban = state.record_disconnect(max_count, window_ms, now_ms);
 
                 if should_ban {
                     let ban_expiry_ms = now_ms + self.flapping_config.ban_time.as_millis() as u64;
@@ -430,7 +461,7 @@ impl FlappingDetector {
                         "IP {} banned for {:?} due to flapping ({} disconnects in {:?})",
                         ip,
                         self.flapping_config.ban_time,
-                        self.flapping_config.max_count,
+                        max_count,
                         self.flapping_config.window_time
                     );
                 }
@@ -440,6 +471,7 @@ impl FlappingDetector {
 
     /// Manually ban an IP for a specified duration
     pub fn ban_ip(&self, ip: IpAddr, duration: Duration) {
+        let ip = ip.to_canonical();
         let now_ms = self.now_ms();
         let expiry_ms = now_ms + duration.as_millis() as u64;
         self.temp_bans.insert(ip, expiry_ms);
@@ -448,11 +480,51 @@ impl FlappingDetector {
 
     /// Unban an IP
     pub fn unban_ip(&self, ip: IpAddr) {
+        let ip = ip.to_canonical();
         if self.temp_bans.remove(&ip).is_some() {
             info!("IP {} unbanned", ip);
         }
     }
 
+    /// IP, tokens, burst and rate of every connection rate bucket, boosts
+    /// included (empty when the rate isn't limited)
+    pub fn bucket_levels(&self) -> Vec<(IpAddr, f64, f64, f64)> {
+        if self.limit_config.rate_limit == 0 {
+            return Vec::new();
+        }
+        let now_ms = self.now_ms();
+        self.ip_state
+            .iter()
+            .map(|entry| {
+                let boost = self.boost(*entry.key());
+                let rate = (self.limit_config.rate_limit as f64 * boost).floor();
+                let burst = (self.limit_config.rate_burst as f64 * boost).floor();
+                let elapsed_ms =
+                    now_ms.saturating_sub(entry.last_refill_ms.load(Ordering::Relaxed));
+                let refill = ((elapsed_ms as f64 * rate) / 1000.0).floor();
+                let tokens = (entry.tokens.load(Ordering::Relaxed) as f64 + refill).min(burst);
+                (*entry.key(), tokens, burst, rate)
+            })
+            .collect()
+    }
+
+    /// Refill an IP's connection rate bucket, forget its recent
+    /// disconnects and lift a temporary ban; false if nothing was tracked
+    pub fn reset_ip(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        let unbanned = self.temp_bans.remove(&ip).is_some();
+        let Some(state) = self.ip_state.get(&ip) else {
+            return unbanned;
+        };
+        let burst = (self.limit_config.rate_burst as f64 * self.boost(ip)) as u32;
+        let now_ms = self.now_ms();
+        state.tokens.store(burst, Ordering::Relaxed);
+        state.last_refill_ms.store(now_ms, Ordering::Relaxed);
+        state.disconnect_count.store(0, Ordering::Relaxed);
+        state.window_start_ms.store(now_ms, Ordering::Relaxed);
+        true
+    }
+
     /// Cleanup expired entries
     pub fn cleanup(&self) {
         let now_ms = self.now_ms();
@@ -610,6 +682,65 @@ mod tests {
         assert!(detector.check_connection(ip).is_ok());
     }
 
+    #[test]
+    fn test_boost_and_reset() {
+        let flapping = FlappingConfig::default();
+        let mut limits = ConnectionLimitConfig::default();
+        limits.rate_limit = 1;
+        limits.rate_burst = 2;
+
+        let limiters = Arc::new(Limiters::default());
+        let detector = FlappingDetector::new(flapping, limits).with_limiters(limiters.clone());
+        let ip: IpAddr = "192.168.1.9".parse().unwrap();
+
+        assert!(detector.check_connection(ip).is_ok());
+        assert!(detector.check_connection(ip).is_ok());
+        assert_eq!(
+            detector.check_connection(ip),
+            Err(RejectionReason::RateLimited)
+        );
+        assert_eq!(detector.bucket_levels(), vec![(ip, 0.0, 2.0, 1.0)]);
+
+        // A boosted IP's bucket holds more once refilled
+        limiters
+            .set_boost("ip:192.168.1.9", 3.0, Duration::from_secs(60))
+            .unwrap();
+        detector.ban_ip(ip, Duration::from_secs(60));
+        assert!(detector.reset_ip(ip));
+        assert_eq!(detector.bucket_levels(), vec![(ip, 6.0, 6.0, 3.0)]);
+        for _ in 0..6 {
+            assert!(detector.check_connection(ip).is_ok());
+        }
+        assert!(!detector.reset_ip("192.168.1.10".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_reconnect_boost() {
+        let flapping = FlappingConfig {
+            enabled: true,
+            max_count: 2,
+            ..Default::default()
+        };
+        let limits = ConnectionLimitConfig::default();
+        let limiters = Arc::new(Limiters::default());
+        let detector = FlappingDetector::new(flapping, limits).with_limiters(limiters.clone());
+        let ip: IpAddr = "192.168.1.11".parse().unwrap();
+
+        // Relaxed, three disconnects stay under the threshold
+        limiters.set_reconnect_boost(2.0);
+        for _ in 0..3 {
+            detector.record_connection(ip);
+            detector.record_disconnection(ip);
+        }
+        assert!(detector.check_connection(ip).is_ok());
+
+        limiters.set_reconnect_boost(1.0);
+        assert_eq!(limiters.reconnect_boost(), 1.0);
+        detector.record_connection(ip);
+        detector.record_disconnection(ip);
+        assert_eq!(detector.check_connection(ip), Err(RejectionReason::Banned));
+    }
+
     #[test]
     fn test_cidr_matching() {
         let flapping = FlappingConfig::default();
@@ -665,4 +796,38 @@ mod tests {
         // Now should be banned
         assert_eq!(detector.check_connection(ip), Err(RejectionReason::Banned));
     }
+
+    #[test]
+    fn test_v4_mapped_addresses() {
+        let limits = ConnectionLimitConfig {
+            banned_ips: vec!["::ffff:10.0.0.9".parse().unwrap()],
+            banned_cidrs: vec!["192.168.0.0/16".to_string()],
+            ..Default::default()
+        };
+        let detector = FlappingDetector::new(FlappingConfig::default(), limits);
+
+        // Bans match whichever form the address arrives in
+        for ip in ["10.0.0.9", "::ffff:10.0.0.9", "::ffff:192.168.1.1"] {
+            assert_eq!(
+                detector.check_connection(ip.parse().unwrap()),
+                Err(RejectionReason::Banned),
+                "{}",
+                ip
+            );
+        }
+
+        let ip: IpAddr = "172.16.0.1".parse().unwrap();
+        detector.ban_ip(
+            "::ffff:172.16.0.1".parse().unwrap(),
+            Duration::from_secs(60),
+        );
+        assert_eq!(detector.check_connection(ip), Err(RejectionReason::Banned));
+        detector.unban_ip(ip);
+        assert!(detector.check_connection(ip).is_ok());
+
+        // Genuine IPv6 addresses are left alone
+        assert!(detector
+            .check_connection("2001:db8::1".parse().unwrap())
+            .is_ok());
+    }
 }
@@ -0,0 +1,338 @@
+//! Geofencing
+//!
+//! Asset tracking without an external processor: each `[[geofence]]` entry
+//! subscribes to a topic filter, reads a latitude and longitude from every
+//! message's payload (JSON or a binary format, see [`crate::payload`]), and tests the point against the fence's polygon.
+//! When an asset (a topic level, e.g. the vehicle ID) crosses the fence an
+//! event is published to the output topic:
+//!
+//! ```json
+//! {"geofence": "depot", "key": "truck7", "event": "enter",
+//!  "lat": 52.5201, "lon": 13.4049, "timestamp": 1714566900}
+//! ```
+//!
+//! An asset's first position counts as an entry when it's inside; after
+//! that only crossings are reported. Positions are tested on a flat
+//! latitude/longitude plane, so fences must not span the antimeridian.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::{debug, warn};
+
+use crate::broker::{Broker, LocalPublish, LocalPublisher};
+use crate::config::GeofenceConfig;
+use crate::payload::{self, PayloadCodec, PayloadError};
+use crate::protocol::QoS;
+use crate::topic::{validate_topic_filter, validate_topic_name};
+
+/// Why a geofence is invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeofenceError {
+    InvalidFilter(&'static str),
+    InvalidOutput(&'static str),
+    /// `{key}` in the output without a `key_level`
+    MissingKeyLevel,
+    InvalidPolygon(&'static str),
+    InvalidQos(u8),
+    InvalidPayload(PayloadError),
+}
+
+impl fmt::Display for GeofenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeofenceError::InvalidFilter(e) => write!(f, "invalid filter: {}", e),
+            GeofenceError::InvalidOutput(e) => write!(f, "invalid output topic: {}", e),
+            GeofenceError::MissingKeyLevel => write!(f, "output uses {{key}} without key_level"),
+            GeofenceError::InvalidPolygon(e) => write!(f, "invalid polygon: {}", e),
+            GeofenceError::InvalidQos(qos) => write!(f, "qos must be 0, 1, or 2, got {}", qos),
+            GeofenceError::InvalidPayload(e) => write!(f, "invalid payload: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GeofenceError {}
+
+/// A closed polygon of `[latitude, longitude]` vertices
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    vertices: Vec<[f64; 2]>,
+}
+
+impl Polygon {
+    pub fn new(vertices: Vec<[f64; 2]>) -> Result<Self, GeofenceError> {
+        if vertices.len() < 3 {
+            return Err(GeofenceError::InvalidPolygon("needs at least 3 vertices"));
+        }
+        if vertices
+            .iter()
+            .any(|[lat, lon]| !(-90.0..=90.0).contains(lat) || !(-180.0..=180.0).contains(lon))
+        {
+            return Err(GeofenceError::InvalidPolygon(
+                "latitudes must be within ±90 and longitudes within ±180",
+            ));
+        }
+        Ok(Self { vertices })
+    }
+
+    /// Whether a point is inside (even-odd rule; points on an edge may
+    /// fall either way)
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        let mut inside = false;
+        let mut previous = self.vertices[self.vertices.len() - 1];
+        for &vertex in &self.vertices {
+            let ([lat_a, lon_a], [lat_b, lon_b]) = (vertex, previous);
+            if (lat_a > lat) != (lat_b > lat)
+                && lon < (lon_b - lon_a) * (lat - lat_a) / (lat_b - lat_a) + lon_a
+            {
+                inside = !inside;
+            }
+            previous = vertex;
+        }
+        inside
+    }
+}
+
+/// A fence crossing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crossing {
+    Enter,
+    Exit,
+}
+
+impl Crossing {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Crossing::Enter => "enter",
+            Crossing::Exit => "exit",
+        }
+    }
+}
+
+/// Whether each asset was last seen inside the fence
+#[derive(Debug, Default)]
+pub struct Assets {
+    inside: HashMap<String, bool>,
+}
+
+impl Assets {
+    /// Record an asset's position; returns the crossing, if it made one
+    pub fn update(&mut self, key: &str, inside: bool) -> Option<Crossing> {
+        let was_inside = self.inside.insert(key.to_string(), inside);
+        match (was_inside, inside) {
+            (None | Some(false), true) => Some(Crossing::Enter),
+            (Some(true), false) => Some(Crossing::Exit),
+            _ => None,
+        }
+    }
+}
+
+/// A validated `[[geofence]]` entry
+#[derive(Debug, Clone)]
+pub struct Geofence {
+    name: String,
+    filter: String,
+    key_level: Option<usize>,
+    lat_field: Vec<String>,
+    lon_field: Vec<String>,
+    payload: PayloadCodec,
+    polygon: Polygon,
+    output: String,
+    qos: QoS,
+    retain: bool,
+    username: Option<String>,
+}
+
+impl Geofence {
+    pub fn new(config: &GeofenceConfig) -> Result<Self, GeofenceError> {
+        validate_topic_filter(&config.filter).map_err(GeofenceError::InvalidFilter)?;
+        if config.output.contains("{key}") && config.key_level.is_none() {
+            return Err(GeofenceError::MissingKeyLevel);
+        }
+        let qos = QoS::from_u8(config.qos).ok_or(GeofenceError::InvalidQos(config.qos))?;
+        let path = |field: &str| field.split('.').map(String::from).collect();
+        let geofence = Self {
+            name: config.name.clone(),
+            filter: config.filter.clone(),
+            key_level: config.key_level,
+            lat_field: path(&config.lat_field),
+            lon_field: path(&config.lon_field),
+            payload: PayloadCodec::new(&config.payload).map_err(GeofenceError::InvalidPayload)?,
+            polygon: Polygon::new(config.polygon.clone())?,
+            output: config.output.clone(),
+            qos,
+            retain: config.retain,
+            username: config.username.clone(),
+        };
+        validate_topic_name(&geofence.output_topic("key")).map_err(GeofenceError::InvalidOutput)?;
+        Ok(geofence)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Asset of a topic (`None` if it has too few levels)
+    fn key<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        match self.key_level {
+            Some(level) => topic.split('/').nth(level),
+            None => Some(""),
+        }
+    }
+
+    /// Latitude and longitude of a payload, if it has both
+    fn position(&self, payload: &[u8]) -> Option<(f64, f64)> {
+        let decoded = self.payload.decode(payload)?;
+        Some((
+            payload::number(&decoded, &self.lat_field)?,
+            payload::number(&decoded, &self.lon_field)?,
+        ))
+    }
+
+    fn output_topic(&self, key: &str) -> String {
+        self.output
+            .replace("{key}", key)
+            .replace("{name}", &self.name)
+    }
+
+    /// Event message for an asset's crossing at `(lat, lon)`
+    fn message(&self, key: &str, crossing: Crossing, lat: f64, lon: f64) -> LocalPublish {
+        let payload = serde_json::json!({
+            "geofence": self.name,
+            "key": key,
+            "event": crossing.as_str(),
+            "lat": lat,
+            "lon": lon,
+            "timestamp": unix_secs(),
+        });
+        LocalPublish::new(self.output_topic(key), self.payload.encode(&payload))
+            .with_qos(self.qos)
+            .with_retain(self.retain)
+    }
+
+    /// Subscribe and publish crossings until the broker shuts down
+    pub async fn run(self, broker: Arc<Broker>) {
+        let mut publisher = broker.local_publisher(&format!("geofence:{}", self.name));
+        if let Some(ref username) = self.username {
+            publisher = publisher.with_username(username.clone());
+        }
+        let mut subscription = match publisher.subscribe(&self.filter, QoS::AtMostOnce).await {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                warn!("Geofence '{}' cannot subscribe: {}", self.name, e);
+                return;
+            }
+        };
+        let mut shutdown = broker.subscribe_shutdown();
+        let mut assets = Assets::default();
+
+        loop {
+            tokio::select! {
+                message = subscription.recv() => {
+                    let Some(publish) = message else {
+                        warn!("Geofence '{}' lost its subscription", self.name);
+                        return;
+                    };
+                    let Some(key) = self.key(&publish.topic) else {
+                        debug!("Geofence '{}': {} has no key level", self.name, publish.topic);
+                        continue;
+                    };
+                    let Some((lat, lon)) = self.position(&publish.payload) else {
+                        debug!("Geofence '{}': no position in {}", self.name, publish.topic);
+                        continue;
+                    };
+                    if let Some(crossing) = assets.update(key, self.polygon.contains(lat, lon)) {
+                        self.publish(&publisher, self.message(key, crossing, lat, lon)).await;
+                    }
+                }
+                _ = shutdown.recv() => return,
+            }
+        }
+    }
+
+    async fn publish(&self, publisher: &LocalPublisher, message: LocalPublish) {
+        if let Err(e) = publisher.publish(message).await {
+            warn!("Geofence '{}' failed to publish: {}", self.name, e);
+        }
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> Polygon {
+        Polygon::new(vec![[0.0, 0.0], [0.0, 10.0], [10.0, 10.0], [10.0, 0.0]]).unwrap()
+    }
+
+    #[test]
+    fn test_contains() {
+        let square = square();
+        assert!(square.contains(5.0, 5.0));
+        assert!(!square.contains(15.0, 5.0));
+        assert!(!square.contains(5.0, -1.0));
+
+        // Concave: an L without its top-right quarter
+        let l = Polygon::new(vec![
+            [0.0, 0.0],
+            [10.0, 0.0],
+            [10.0, 5.0],
+            [5.0, 5.0],
+            [5.0, 10.0],
+            [0.0, 10.0],
+        ])
+        .unwrap();
+        assert!(l.contains(2.0, 8.0));
+        assert!(l.contains(8.0, 2.0));
+        assert!(!l.contains(8.0, 8.0));
+    }
+
+    #[test]
+    fn test_invalid_polygon() {
+        assert!(Polygon::new(vec![[0.0, 0.0], [1.0, 1.0]]).is_err());
+        assert!(Polygon::new(vec![[0.0, 0.0], [91.0, 0.0], [0.0, 1.0]]).is_err());
+    }
+
+    #[test]
+    fn test_crossings() {
+        let mut assets = Assets::default();
+        // First sighting inside counts as an entry, outside as nothing
+        assert_eq!(assets.update("a", true), Some(Crossing::Enter));
+        assert_eq!(assets.update("b", false), None);
+        assert_eq!(assets.update("a", true), None);
+        assert_eq!(assets.update("a", false), Some(Crossing::Exit));
+        assert_eq!(assets.update("a", false), None);
+        assert_eq!(assets.update("b", true), Some(Crossing::Enter));
+    }
+
+    #[test]
+    fn test_position_and_key() {
+        let geofence = Geofence::new(&GeofenceConfig {
+            name: "depot".to_string(),
+            filter: "fleet/+/position".to_string(),
+            key_level: Some(1),
+            lat_field: "gps.lat".to_string(),
+            polygon: vec![[0.0, 0.0], [0.0, 10.0], [10.0, 10.0]],
+            output: "geofence/{name}/{key}".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(geofence.key("fleet/truck7/position"), Some("truck7"));
+        assert_eq!(geofence.output_topic("truck7"), "geofence/depot/truck7");
+        assert_eq!(
+            geofence.position(br#"{"gps": {"lat": 1.5}, "lon": "2.5"}"#),
+            Some((1.5, 2.5))
+        );
+        assert_eq!(geofence.position(br#"{"gps": {"lat": 1.5}}"#), None);
+        assert_eq!(geofence.position(b"1.5,2.5"), None);
+    }
+}
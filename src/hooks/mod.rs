@@ -2,12 +2,29 @@
 //!
 //! Provides extensibility points for authentication, authorization,
 //! and custom event handling in VibeMQ.
+//!
+//! Applications embedding the broker implement [`Hooks`] and register
+//! their implementations with [`CompositeHooks`], then build the broker
+//! with `Broker::with_hooks`. Besides checks and events, hooks can
+//! intercept messages: `on_publish` rewrites or rejects what clients
+//! publish, `on_deliver` decides what each subscriber receives.
+//!
+//! Authorization can be dry-run with `explain_access`, which reports each
+//! hook consulted and the rule it decided by (admin API
+//! `/api/v1/acl/check`).
 
 use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 
-use crate::protocol::QoS;
+use crate::config::{AuthMetadataField, SessionPolicy};
+use crate::protocol::{Publish, QoS};
+use crate::proxy::ProxyIdentity;
+use crate::topic::topic_matches_filter;
 
 #[cfg(test)]
 mod tests;
@@ -21,6 +38,10 @@ pub enum HookError {
     AuthenticationFailed,
     /// Authorization denied
     AuthorizationDenied,
+    /// Client is banned
+    Banned,
+    /// A backend the hook depends on is unreachable or timed out
+    Unavailable(String),
 }
 
 impl fmt::Display for HookError {
@@ -29,6 +50,8 @@ impl fmt::Display for HookError {
             HookError::Internal(msg) => write!(f, "Internal error: {}", msg),
             HookError::AuthenticationFailed => write!(f, "Authentication failed"),
             HookError::AuthorizationDenied => write!(f, "Authorization denied"),
+            HookError::Banned => write!(f, "Banned"),
+            HookError::Unavailable(msg) => write!(f, "Backend unavailable: {}", msg),
         }
     }
 }
@@ -38,6 +61,159 @@ impl std::error::Error for HookError {}
 /// Hook result type
 pub type HookResult<T> = Result<T, HookError>;
 
+/// Publish rate decision made by an external quota service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitDecision {
+    /// The publish is within quota
+    Allow,
+    /// Over quota; `retry_after` is reported to MQTT v5 clients if known
+    Deny { retry_after: Option<Duration> },
+}
+
+/// Upper bounds on how long a published message lives in the broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PublishTtl {
+    /// Cap on the message expiry interval (also applied when the client sets none)
+    pub max_message_expiry: Option<Duration>,
+    /// Cap on how long a retained copy is kept
+    pub max_retained_lifetime: Option<Duration>,
+}
+
+impl PublishTtl {
+    /// The tighter of two sets of bounds
+    pub fn min(self, other: PublishTtl) -> PublishTtl {
+        fn tighter(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+        PublishTtl {
+            max_message_expiry: tighter(self.max_message_expiry, other.max_message_expiry),
+            max_retained_lifetime: tighter(self.max_retained_lifetime, other.max_retained_lifetime),
+        }
+    }
+}
+
+/// Access checked by a dry run (see `Hooks::explain_access`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessAction {
+    Publish,
+    Subscribe,
+}
+
+/// A hypothetical publish or subscribe to authorize
+#[derive(Debug, Clone, Copy)]
+pub struct AccessRequest<'a> {
+    pub client_id: &'a str,
+    pub username: Option<&'a str>,
+    pub action: AccessAction,
+    /// Topic published to, or filter subscribed to
+    pub topic: &'a str,
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+/// Outcome of one hook's check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessDecision {
+    Allow,
+    Deny,
+    /// The hook failed, which denies the access
+    Error,
+}
+
+/// One hook's part in a dry-run decision
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccessStep {
+    /// Hook consulted (see `Hooks::hook_name`)
+    pub hook: String,
+    pub decision: AccessDecision,
+    /// Rule the hook decided by, if it names its rules
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AccessStep {
+    /// Step for a check's result
+    pub fn new(hook: String, result: HookResult<bool>, rule: Option<String>) -> Self {
+        let (decision, error) = match result {
+            Ok(true) => (AccessDecision::Allow, None),
+            Ok(false) => (AccessDecision::Deny, None),
+            Err(e) => (AccessDecision::Error, Some(e.to_string())),
+        };
+        Self {
+            hook,
+            decision,
+            rule,
+            error,
+        }
+    }
+}
+
+/// Network and TLS details of a connecting client
+///
+/// Passed to `on_authenticate_with_metadata` so external authorizers can
+/// apply network-aware policies. Fields not selected in
+/// `auth.metadata_fields` are left empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConnectionMetadata {
+    /// Client IP, as reported by the PROXY header when behind a proxy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<IpAddr>,
+    /// TLS Server Name Indication
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sni: Option<String>,
+    /// Negotiated TLS version, e.g. "TLSv1.3"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_version: Option<String>,
+    /// Negotiated TLS cipher suite
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_cipher: Option<String>,
+    /// Verified client certificate CN
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cert_cn: Option<String>,
+    /// Listener the client connected to ("tcp", "tls", "ws", "wss", "unix", "quic")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listener: Option<String>,
+    /// Tenant the client belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl ConnectionMetadata {
+    /// Clear every field not listed in `fields`
+    pub fn retain(mut self, fields: &[AuthMetadataField]) -> Self {
+        let keep = |field| fields.contains(&field);
+        if !keep(AuthMetadataField::ClientIp) {
+            self.client_ip = None;
+        }
+        if !keep(AuthMetadataField::Sni) {
+            self.sni = None;
+        }
+        if !keep(AuthMetadataField::TlsVersion) {
+            self.tls_version = None;
+        }
+        if !keep(AuthMetadataField::TlsCipher) {
+            self.tls_cipher = None;
+        }
+        if !keep(AuthMetadataField::ClientCertCn) {
+            self.client_cert_cn = None;
+        }
+        if !keep(AuthMetadataField::Listener) {
+            self.listener = None;
+        }
+        if !keep(AuthMetadataField::Tenant) {
+            self.tenant = None;
+        }
+        self
+    }
+}
+
 /// Broker hooks trait
 ///
 /// Implement this trait to customize authentication, authorization,
@@ -65,6 +241,22 @@ pub trait Hooks: Send + Sync {
         Ok(true) // Default: allow all
     }
 
+    /// Called when a client attempts to authenticate, with its connection
+    /// details
+    ///
+    /// The broker calls this rather than `on_authenticate`. The default
+    /// ignores the metadata and defers to `on_authenticate`; override it for
+    /// policies such as "factory credentials only from the plant network".
+    async fn on_authenticate_with_metadata(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        password: Option<&[u8]>,
+        _metadata: &ConnectionMetadata,
+    ) -> HookResult<bool> {
+        self.on_authenticate(client_id, username, password).await
+    }
+
     /// Called when a client attempts to publish a message
     ///
     /// # Arguments
@@ -89,6 +281,52 @@ pub trait Hooks: Send + Sync {
         Ok(true) // Default: allow all
     }
 
+    /// Called when a client attempts to publish a message (or to set its
+    /// will), with the client's identity behind a PROXY header
+    ///
+    /// `identity` is `None` for clients that connected directly. Defaults
+    /// to `on_publish_check`.
+    async fn on_publish_check_with_identity(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        _identity: Option<&ProxyIdentity>,
+    ) -> HookResult<bool> {
+        self.on_publish_check(client_id, username, topic, qos, retain)
+            .await
+    }
+
+    /// Called after a publish is authorized, before it is retained and
+    /// routed, to rewrite or reject it
+    ///
+    /// Hooks may change the message's topic, payload and properties in
+    /// place, e.g. to prefix every topic with the publisher's tenant. QoS,
+    /// retain flag and packet identifier are already settled with the
+    /// client; changes to them are undone. A rewritten topic must still be
+    /// a valid topic name. Entries of a batch frame are intercepted one by
+    /// one.
+    ///
+    /// # Arguments
+    /// * `client_id` - The publishing client
+    /// * `username` - The username used for authentication (if any)
+    /// * `publish` - The message as published (or as rewritten by earlier hooks)
+    ///
+    /// # Returns
+    /// * `Ok(true)` - Route the (possibly rewritten) message
+    /// * `Ok(false)` - Reject it like a denied publish
+    /// * `Err(_)` - Internal error occurred (the message is rejected)
+    async fn on_publish(
+        &self,
+        _client_id: &str,
+        _username: Option<&str>,
+        _publish: &mut Publish,
+    ) -> HookResult<bool> {
+        Ok(true) // Default: route as published
+    }
+
     /// Called when a client attempts to subscribe to a topic filter
     ///
     /// # Arguments
@@ -111,6 +349,164 @@ pub trait Hooks: Send + Sync {
         Ok(true) // Default: allow all
     }
 
+    /// Name the hook is reported under by `explain_access`
+    fn hook_name(&self) -> String {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name).to_string()
+    }
+
+    /// Dry-run an authorization check, reporting how it was decided
+    ///
+    /// The default runs the check (`on_publish_check_with_identity`
+    /// without a PROXY identity, or `on_subscribe_check`) and reports it as
+    /// one step. Hooks with rules override it to name the rule that
+    /// decided; chains report a step for each hook they consult. The access
+    /// is allowed if every step allows it.
+    async fn explain_access(&self, request: &AccessRequest<'_>) -> Vec<AccessStep> {
+        let result = match request.action {
+            AccessAction::Publish => {
+                self.on_publish_check_with_identity(
+                    request.client_id,
+                    request.username,
+                    request.topic,
+                    request.qos,
+                    request.retain,
+                    None,
+                )
+                .await
+            }
+            AccessAction::Subscribe => {
+                self.on_subscribe_check(
+                    request.client_id,
+                    request.username,
+                    request.topic,
+                    request.qos,
+                )
+                .await
+            }
+        };
+        vec![AccessStep::new(self.hook_name(), result, None)]
+    }
+
+    /// Called before a message is sent to a subscriber
+    ///
+    /// Called when a message is routed to a connected client, and when a
+    /// message queued for a client is sent; not for retransmissions.
+    ///
+    /// # Arguments
+    /// * `client_id` - The receiving client
+    /// * `publish` - The message about to be sent
+    ///
+    /// # Returns
+    /// * `Ok(true)` - Send it
+    /// * `Ok(false)` - Skip it for this client
+    /// * `Err(_)` - Internal error occurred (the message is sent)
+    async fn on_deliver(&self, _client_id: &str, _publish: &Publish) -> HookResult<bool> {
+        Ok(true) // Default: deliver everything
+    }
+
+    /// Called to rate-limit publishes from identities listed in
+    /// `limits.publish_rate.external`
+    ///
+    /// # Arguments
+    /// * `identity` - The rate limit identity ("user:...", "cn:..." or "client:...")
+    /// * `count` - Number of messages being published
+    ///
+    /// # Returns
+    /// * `Ok(Some(_))` - The external service's decision
+    /// * `Ok(None)` - No opinion (local bucket applies)
+    /// * `Err(_)` - Service unavailable (local bucket applies)
+    async fn on_rate_limit(
+        &self,
+        _identity: &str,
+        _count: u32,
+    ) -> HookResult<Option<RateLimitDecision>> {
+        Ok(None) // Default: use local buckets
+    }
+
+    /// Called after a publish is authorized to bound how long it lives
+    ///
+    /// # Arguments
+    /// * `client_id` - The publishing client
+    /// * `username` - The username used for authentication (if any)
+    /// * `topic` - The topic being published to
+    ///
+    /// # Returns
+    /// * `Ok(ttl)` - Bounds applied to the message (defaults are unbounded)
+    /// * `Err(_)` - Internal error occurred (the message is left as is)
+    async fn on_publish_ttl(
+        &self,
+        _client_id: &str,
+        _username: Option<&str>,
+        _topic: &str,
+    ) -> HookResult<PublishTtl> {
+        Ok(PublishTtl::default()) // Default: unbounded
+    }
+
+    /// Called before authentication to find the tenant a client belongs to
+    ///
+    /// Client IDs are unique per tenant: the broker replaces the client ID
+    /// with `session::tenant_client_id(tenant, client_id)` for authentication,
+    /// sessions, persistence and every later hook.
+    ///
+    /// # Arguments
+    /// * `client_id` - The client identifier sent in CONNECT
+    /// * `username` - The username sent in CONNECT (if any)
+    ///
+    /// # Returns
+    /// * `Ok(Some(tenant))` - The client belongs to `tenant`
+    /// * `Ok(None)` - The client is in the shared, untenanted namespace
+    /// * `Err(_)` - Internal error occurred (the connection is refused)
+    async fn on_client_tenant(
+        &self,
+        _client_id: &str,
+        _username: Option<&str>,
+    ) -> HookResult<Option<String>> {
+        Ok(None) // Default: no tenants
+    }
+
+    /// Called after authentication to cap the QoS a client may use
+    ///
+    /// The cap applies to granted subscriptions and incoming publishes, and
+    /// is advertised as Maximum QoS in CONNACK.
+    ///
+    /// # Arguments
+    /// * `client_id` - The client identifier
+    /// * `username` - The username used for authentication (if any)
+    ///
+    /// # Returns
+    /// * `Ok(Some(qos))` - Highest QoS the client may use
+    /// * `Ok(None)` - No cap beyond the broker and listener limits
+    /// * `Err(_)` - Internal error occurred (no cap is applied)
+    async fn on_max_qos(
+        &self,
+        _client_id: &str,
+        _username: Option<&str>,
+    ) -> HookResult<Option<QoS>> {
+        Ok(None) // Default: no cap
+    }
+
+    /// Called after authentication for the client's session settings
+    ///
+    /// Set fields replace the broker's queue depth, session expiry cap and
+    /// keep-alive bounds for the client, and its quota is layered over the
+    /// listener's (see `SessionPolicy`).
+    ///
+    /// # Arguments
+    /// * `client_id` - The client identifier
+    /// * `username` - The username used for authentication (if any)
+    ///
+    /// # Returns
+    /// * `Ok(policy)` - Settings for the client (default: broker settings)
+    /// * `Err(_)` - Internal error occurred (broker settings apply)
+    async fn on_session_policy(
+        &self,
+        _client_id: &str,
+        _username: Option<&str>,
+    ) -> HookResult<SessionPolicy> {
+        Ok(SessionPolicy::default())
+    }
+
     /// Called after a client successfully connects
     ///
     /// This is called after authentication succeeds and CONNACK is sent.
@@ -164,6 +560,18 @@ impl<T: Hooks + ?Sized> Hooks for std::sync::Arc<T> {
             .await
     }
 
+    async fn on_authenticate_with_metadata(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        password: Option<&[u8]>,
+        metadata: &ConnectionMetadata,
+    ) -> HookResult<bool> {
+        (**self)
+            .on_authenticate_with_metadata(client_id, username, password, metadata)
+            .await
+    }
+
     async fn on_publish_check(
         &self,
         client_id: &str,
@@ -177,6 +585,29 @@ impl<T: Hooks + ?Sized> Hooks for std::sync::Arc<T> {
             .await
     }
 
+    async fn on_publish_check_with_identity(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        identity: Option<&ProxyIdentity>,
+    ) -> HookResult<bool> {
+        (**self)
+            .on_publish_check_with_identity(client_id, username, topic, qos, retain, identity)
+            .await
+    }
+
+    async fn on_publish(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        publish: &mut Publish,
+    ) -> HookResult<bool> {
+        (**self).on_publish(client_id, username, publish).await
+    }
+
     async fn on_subscribe_check(
         &self,
         client_id: &str,
@@ -189,6 +620,55 @@ impl<T: Hooks + ?Sized> Hooks for std::sync::Arc<T> {
             .await
     }
 
+    fn hook_name(&self) -> String {
+        (**self).hook_name()
+    }
+
+    async fn explain_access(&self, request: &AccessRequest<'_>) -> Vec<AccessStep> {
+        (**self).explain_access(request).await
+    }
+
+    async fn on_deliver(&self, client_id: &str, publish: &Publish) -> HookResult<bool> {
+        (**self).on_deliver(client_id, publish).await
+    }
+
+    async fn on_rate_limit(
+        &self,
+        identity: &str,
+        count: u32,
+    ) -> HookResult<Option<RateLimitDecision>> {
+        (**self).on_rate_limit(identity, count).await
+    }
+
+    async fn on_publish_ttl(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        topic: &str,
+    ) -> HookResult<PublishTtl> {
+        (**self).on_publish_ttl(client_id, username, topic).await
+    }
+
+    async fn on_client_tenant(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+    ) -> HookResult<Option<String>> {
+        (**self).on_client_tenant(client_id, username).await
+    }
+
+    async fn on_max_qos(&self, client_id: &str, username: Option<&str>) -> HookResult<Option<QoS>> {
+        (**self).on_max_qos(client_id, username).await
+    }
+
+    async fn on_session_policy(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+    ) -> HookResult<SessionPolicy> {
+        (**self).on_session_policy(client_id, username).await
+    }
+
     async fn on_client_connected(&self, client_id: &str, username: Option<&str>) {
         (**self).on_client_connected(client_id, username).await;
     }
@@ -202,13 +682,123 @@ impl<T: Hooks + ?Sized> Hooks for std::sync::Arc<T> {
     }
 }
 
+tokio::task_local! {
+    /// Listener of the client whose task is calling the hooks
+    static LISTENER: &'static str;
+}
+
+/// Run `fut` with the hooks it calls attributed to `listener`
+pub(crate) async fn scope_listener<F: Future>(listener: &'static str, fut: F) -> F::Output {
+    LISTENER.scope(listener, fut).await
+}
+
+/// Listener ("tcp", "tls", "ws", "wss", "unix", "quic", "stomp") of the
+/// client a hook is being called for
+///
+/// `None` outside a client connection: admin API publishes, delayed and
+/// scheduled publishes, bridges and embedded local clients.
+pub fn current_listener() -> Option<&'static str> {
+    LISTENER.try_with(|listener| *listener).ok()
+}
+
+/// How a hooks implementation is registered with [`CompositeHooks`]
+///
+/// Topic filters limit the calls that concern a topic: publish checks and
+/// rewrites, TTLs, deliveries and published events by the message topic,
+/// subscribe checks by whether the subscribed filter overlaps them. Calls
+/// without a topic (authentication, tenants, QoS caps, rate limits,
+/// connect and disconnect events) still reach the hook. Listener filters
+/// limit every call; a hook with listener filters is skipped for calls made
+/// outside a client connection (see [`current_listener`]).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HookOptions {
+    /// Higher priorities run first; equal ones in registration order
+    pub priority: i32,
+    /// Topic filters the hook applies to (empty: every topic)
+    pub topics: Vec<String>,
+    /// Listeners the hook applies to (empty: every listener)
+    pub listeners: Vec<String>,
+}
+
+impl HookOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Only consult the hook for topics matching `filter`
+    pub fn with_topic(mut self, filter: impl Into<String>) -> Self {
+        self.topics.push(filter.into());
+        self
+    }
+
+    /// Only consult the hook for clients of `listener`
+    pub fn with_listener(mut self, listener: impl Into<String>) -> Self {
+        self.listeners.push(listener.into());
+        self
+    }
+
+    fn applies_to_listener(&self, listener: Option<&str>) -> bool {
+        self.listeners.is_empty()
+            || listener.is_some_and(|listener| self.listeners.iter().any(|l| l == listener))
+    }
+
+    fn applies_to_topic(&self, topic: &str) -> bool {
+        self.topics.is_empty()
+            || self
+                .topics
+                .iter()
+                .any(|filter| topic_matches_filter(topic, filter))
+    }
+
+    fn applies_to_filter(&self, filter: &str) -> bool {
+        self.topics.is_empty() || self.topics.iter().any(|f| filters_overlap(filter, f))
+    }
+}
+
+/// Whether some topic matches both filters
+fn filters_overlap(a: &str, b: &str) -> bool {
+    let mut a = a.split('/');
+    let mut b = b.split('/');
+    loop {
+        match (a.next(), b.next()) {
+            // `#` also matches its parent level, so it overlaps from here
+            (Some("#"), _) | (_, Some("#")) => return true,
+            (None, None) => return true,
+            (None, Some(_)) | (Some(_), None) => return false,
+            (Some(x), Some(y)) if x == "+" || y == "+" || x == y => {}
+            _ => return false,
+        }
+    }
+}
+
+struct Registered {
+    hooks: Box<dyn Hooks>,
+    options: HookOptions,
+}
+
 /// Composite hooks that chains multiple hook implementations
 ///
+/// Hooks run by priority, then in registration order, skipping those
+/// whose [`HookOptions`] filters don't cover the call. A deny or an error
+/// stops the chain: later hooks aren't consulted.
+///
 /// For authentication: all hooks must return `Ok(true)` for success
 /// For authorization: all hooks must return `Ok(true)` for permission
+/// For publish rewrites: hooks are called in order, each seeing the
+/// previous one's changes, until one rejects the message
+/// For deliveries: all hooks must return `Ok(true)` for the message to be sent
+/// For rate limits: the first hook with a decision wins
+/// For message TTLs: the tightest bound wins
+/// For QoS caps: the lowest cap wins
+/// For tenants: the first hook naming one wins
 /// For events: all hooks are called in order
 pub struct CompositeHooks {
-    hooks: Vec<Box<dyn Hooks>>,
+    hooks: Vec<Registered>,
 }
 
 impl CompositeHooks {
@@ -219,7 +809,24 @@ impl CompositeHooks {
 
     /// Add a hooks implementation
     pub fn add<H: Hooks + 'static>(&mut self, hooks: H) {
-        self.hooks.push(Box::new(hooks));
+        self.add_with_options(hooks, HookOptions::default());
+    }
+
+    /// Add a hooks implementation with a priority and filters
+    pub fn add_with_options<H: Hooks + 'static>(&mut self, hooks: H, options: HookOptions) {
+        // After every hook of the same or a higher priority
+        let at = self
+            .hooks
+            .iter()
+            .position(|r| r.options.priority < options.priority)
+            .unwrap_or(self.hooks.len());
+        self.hooks.insert(
+            at,
+            Registered {
+                hooks: Box::new(hooks),
+                options,
+            },
+        );
     }
 
     /// Add a hooks implementation and return self for chaining
@@ -227,6 +834,40 @@ impl CompositeHooks {
         self.add(hooks);
         self
     }
+
+    /// Add a hooks implementation with a priority and filters, and return
+    /// self for chaining
+    pub fn with_options<H: Hooks + 'static>(mut self, hooks: H, options: HookOptions) -> Self {
+        self.add_with_options(hooks, options);
+        self
+    }
+
+    /// Hooks that apply to the calling client's listener
+    fn for_listener(&self) -> impl Iterator<Item = &Registered> {
+        let listener = current_listener();
+        self.hooks
+            .iter()
+            .filter(move |r| r.options.applies_to_listener(listener))
+    }
+
+    /// Hooks for the calling client's listener and `topic`
+    fn for_topic<'a>(&'a self, topic: &'a str) -> impl Iterator<Item = &'a dyn Hooks> {
+        self.for_listener()
+            .filter(move |r| r.options.applies_to_topic(topic))
+            .map(|r| r.hooks.as_ref())
+    }
+
+    /// Hooks for the calling client's listener whose topics overlap `filter`
+    fn for_filter<'a>(&'a self, filter: &'a str) -> impl Iterator<Item = &'a dyn Hooks> {
+        self.for_listener()
+            .filter(move |r| r.options.applies_to_filter(filter))
+            .map(|r| r.hooks.as_ref())
+    }
+
+    /// Hooks for the calling client's listener, regardless of topic
+    fn all(&self) -> impl Iterator<Item = &dyn Hooks> {
+        self.for_listener().map(|r| r.hooks.as_ref())
+    }
 }
 
 impl Default for CompositeHooks {
@@ -243,7 +884,7 @@ impl Hooks for CompositeHooks {
         username: Option<&str>,
         password: Option<&[u8]>,
     ) -> HookResult<bool> {
-        for hooks in &self.hooks {
+        for hooks in self.all() {
             if !hooks.on_authenticate(client_id, username, password).await? {
                 return Ok(false);
             }
@@ -251,6 +892,24 @@ impl Hooks for CompositeHooks {
         Ok(true)
     }
 
+    async fn on_authenticate_with_metadata(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        password: Option<&[u8]>,
+        metadata: &ConnectionMetadata,
+    ) -> HookResult<bool> {
+        for hooks in self.all() {
+            if !hooks
+                .on_authenticate_with_metadata(client_id, username, password, metadata)
+                .await?
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     async fn on_publish_check(
         &self,
         client_id: &str,
@@ -259,7 +918,7 @@ impl Hooks for CompositeHooks {
         qos: QoS,
         retain: bool,
     ) -> HookResult<bool> {
-        for hooks in &self.hooks {
+        for hooks in self.for_topic(topic) {
             if !hooks
                 .on_publish_check(client_id, username, topic, qos, retain)
                 .await?
@@ -270,6 +929,48 @@ impl Hooks for CompositeHooks {
         Ok(true)
     }
 
+    async fn on_publish_check_with_identity(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        identity: Option<&ProxyIdentity>,
+    ) -> HookResult<bool> {
+        for hooks in self.for_topic(topic) {
+            if !hooks
+                .on_publish_check_with_identity(client_id, username, topic, qos, retain, identity)
+                .await?
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn on_publish(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        publish: &mut Publish,
+    ) -> HookResult<bool> {
+        for registered in self.for_listener() {
+            // The topic as rewritten by earlier hooks
+            if !registered.options.applies_to_topic(&publish.topic) {
+                continue;
+            }
+            if !registered
+                .hooks
+                .on_publish(client_id, username, publish)
+                .await?
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     async fn on_subscribe_check(
         &self,
         client_id: &str,
@@ -277,7 +978,7 @@ impl Hooks for CompositeHooks {
         filter: &str,
         qos: QoS,
     ) -> HookResult<bool> {
-        for hooks in &self.hooks {
+        for hooks in self.for_filter(filter) {
             if !hooks
                 .on_subscribe_check(client_id, username, filter, qos)
                 .await?
@@ -288,20 +989,118 @@ impl Hooks for CompositeHooks {
         Ok(true)
     }
 
+    fn hook_name(&self) -> String {
+        "hooks".to_string()
+    }
+
+    /// Each consulted hook's steps, up to the first that doesn't allow
+    async fn explain_access(&self, request: &AccessRequest<'_>) -> Vec<AccessStep> {
+        let hooks: Vec<_> = match request.action {
+            AccessAction::Publish => self.for_topic(request.topic).collect(),
+            AccessAction::Subscribe => self.for_filter(request.topic).collect(),
+        };
+        let mut steps = Vec::new();
+        for hooks in hooks {
+            let explained = hooks.explain_access(request).await;
+            let decided = explained
+                .iter()
+                .any(|step| step.decision != AccessDecision::Allow);
+            steps.extend(explained);
+            if decided {
+                break;
+            }
+        }
+        steps
+    }
+
+    async fn on_deliver(&self, client_id: &str, publish: &Publish) -> HookResult<bool> {
+        for hooks in self.for_topic(&publish.topic) {
+            if !hooks.on_deliver(client_id, publish).await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn on_rate_limit(
+        &self,
+        identity: &str,
+        count: u32,
+    ) -> HookResult<Option<RateLimitDecision>> {
+        for hooks in self.all() {
+            if let Some(decision) = hooks.on_rate_limit(identity, count).await? {
+                return Ok(Some(decision));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn on_publish_ttl(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        topic: &str,
+    ) -> HookResult<PublishTtl> {
+        let mut ttl = PublishTtl::default();
+        for hooks in self.for_topic(topic) {
+            ttl = ttl.min(hooks.on_publish_ttl(client_id, username, topic).await?);
+        }
+        Ok(ttl)
+    }
+
+    async fn on_client_tenant(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+    ) -> HookResult<Option<String>> {
+        for hooks in self.all() {
+            if let Some(tenant) = hooks.on_client_tenant(client_id, username).await? {
+                return Ok(Some(tenant));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn on_max_qos(&self, client_id: &str, username: Option<&str>) -> HookResult<Option<QoS>> {
+        let mut cap = None;
+        for hooks in self.all() {
+            if let Some(qos) = hooks.on_max_qos(client_id, username).await? {
+                cap = Some(cap.map_or(qos, |cap: QoS| cap.min(qos)));
+            }
+        }
+        Ok(cap)
+    }
+
+    async fn on_session_policy(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+    ) -> HookResult<SessionPolicy> {
+        // Earlier hooks' settings win
+        let mut policy = SessionPolicy::default();
+        for hooks in self.all() {
+            policy = hooks
+                .on_session_policy(client_id, username)
+                .await?
+                .merge(&policy);
+        }
+        Ok(policy)
+    }
+
     async fn on_client_connected(&self, client_id: &str, username: Option<&str>) {
-        for hooks in &self.hooks {
+        for hooks in self.all() {
             hooks.on_client_connected(client_id, username).await;
         }
     }
 
     async fn on_client_disconnected(&self, client_id: &str, graceful: bool) {
-        for hooks in &self.hooks {
+        for hooks in self.all() {
             hooks.on_client_disconnected(client_id, graceful).await;
         }
     }
 
     async fn on_message_published(&self, topic: &str, payload: &[u8], qos: QoS) {
-        for hooks in &self.hooks {
+        for hooks in self.for_topic(topic) {
             hooks.on_message_published(topic, payload, qos).await;
         }
     }
@@ -101,6 +101,14 @@ impl Hooks for DenyHooks {
     ) -> HookResult<bool> {
         Ok(false)
     }
+
+    async fn on_rate_limit(
+        &self,
+        _identity: &str,
+        _count: u32,
+    ) -> HookResult<Option<RateLimitDecision>> {
+        Ok(Some(RateLimitDecision::Deny { retry_after: None }))
+    }
 }
 
 #[tokio::test]
@@ -153,6 +161,422 @@ async fn test_composite_hooks_subscribe_check() {
     assert!(!result, "One hook denies subscribe, should be denied");
 }
 
+#[tokio::test]
+async fn test_composite_hooks_explain_access() {
+    let hooks = CompositeHooks::new()
+        .with(AllowHooks)
+        .with_options(DenyHooks, HookOptions::new().with_topic("secret/#"))
+        .with(AllowHooks);
+    let request = AccessRequest {
+        client_id: "client1",
+        username: Some("user"),
+        action: AccessAction::Publish,
+        topic: "public/news",
+        qos: QoS::AtMostOnce,
+        retain: false,
+    };
+
+    // Hooks filtered out aren't consulted
+    let steps = hooks.explain_access(&request).await;
+    assert_eq!(steps.len(), 2);
+    assert!(steps.iter().all(|s| s.decision == AccessDecision::Allow));
+    assert_eq!(steps[0].hook, "AllowHooks");
+
+    // The chain stops at the first deny
+    let steps = hooks
+        .explain_access(&AccessRequest {
+            action: AccessAction::Subscribe,
+            topic: "secret/+",
+            ..request
+        })
+        .await;
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[1].hook, "DenyHooks");
+    assert_eq!(steps[1].decision, AccessDecision::Deny);
+}
+
+#[tokio::test]
+async fn test_composite_hooks_rate_limit() {
+    // Hooks without an opinion defer to the next one
+    let hooks = CompositeHooks::new().with(AllowHooks).with(DenyHooks);
+    let decision = hooks.on_rate_limit("user:alice", 1).await.unwrap();
+    assert_eq!(
+        decision,
+        Some(RateLimitDecision::Deny { retry_after: None })
+    );
+
+    let hooks = CompositeHooks::new().with(AllowHooks);
+    assert_eq!(hooks.on_rate_limit("user:alice", 1).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_composite_hooks_publish_ttl() {
+    struct Ttl(PublishTtl);
+
+    #[async_trait]
+    impl Hooks for Ttl {
+        async fn on_publish_ttl(
+            &self,
+            _client_id: &str,
+            _username: Option<&str>,
+            _topic: &str,
+        ) -> HookResult<PublishTtl> {
+            Ok(self.0)
+        }
+    }
+
+    let hooks = CompositeHooks::new()
+        .with(AllowHooks)
+        .with(Ttl(PublishTtl {
+            max_message_expiry: Some(Duration::from_secs(60)),
+            max_retained_lifetime: Some(Duration::from_secs(600)),
+        }))
+        .with(Ttl(PublishTtl {
+            max_message_expiry: Some(Duration::from_secs(30)),
+            max_retained_lifetime: None,
+        }));
+    let ttl = hooks.on_publish_ttl("client1", None, "a/b").await.unwrap();
+    assert_eq!(ttl.max_message_expiry, Some(Duration::from_secs(30)));
+    assert_eq!(ttl.max_retained_lifetime, Some(Duration::from_secs(600)));
+
+    let ttl = DefaultHooks.on_publish_ttl("client1", None, "a/b").await;
+    assert_eq!(ttl.unwrap(), PublishTtl::default());
+}
+
+#[tokio::test]
+async fn test_composite_hooks_max_qos() {
+    struct Cap(Option<QoS>);
+
+    #[async_trait]
+    impl Hooks for Cap {
+        async fn on_max_qos(
+            &self,
+            _client_id: &str,
+            _username: Option<&str>,
+        ) -> HookResult<Option<QoS>> {
+            Ok(self.0)
+        }
+    }
+
+    let hooks = CompositeHooks::new()
+        .with(Cap(Some(QoS::AtLeastOnce)))
+        .with(Cap(None))
+        .with(Cap(Some(QoS::ExactlyOnce)));
+    assert_eq!(
+        hooks.on_max_qos("client1", None).await.unwrap(),
+        Some(QoS::AtLeastOnce)
+    );
+    assert_eq!(
+        DefaultHooks.on_max_qos("client1", None).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_composite_hooks_session_policy() {
+    struct Policy(SessionPolicy);
+
+    #[async_trait]
+    impl Hooks for Policy {
+        async fn on_session_policy(
+            &self,
+            _client_id: &str,
+            _username: Option<&str>,
+        ) -> HookResult<SessionPolicy> {
+            Ok(self.0)
+        }
+    }
+
+    // Earlier hooks win field by field
+    let hooks = CompositeHooks::new()
+        .with(Policy(SessionPolicy {
+            max_keep_alive: Some(300),
+            ..Default::default()
+        }))
+        .with(Policy(SessionPolicy {
+            max_keep_alive: Some(60),
+            max_queued_messages: Some(10),
+            ..Default::default()
+        }));
+    let policy = hooks.on_session_policy("client1", None).await.unwrap();
+    assert_eq!(policy.max_keep_alive, Some(300));
+    assert_eq!(policy.max_queued_messages, Some(10));
+
+    let policy = DefaultHooks.on_session_policy("client1", None).await;
+    assert_eq!(policy.unwrap(), SessionPolicy::default());
+}
+
+#[tokio::test]
+async fn test_composite_hooks_client_tenant() {
+    struct Tenant(Option<&'static str>);
+
+    #[async_trait]
+    impl Hooks for Tenant {
+        async fn on_client_tenant(
+            &self,
+            _client_id: &str,
+            _username: Option<&str>,
+        ) -> HookResult<Option<String>> {
+            Ok(self.0.map(str::to_string))
+        }
+    }
+
+    let hooks = CompositeHooks::new()
+        .with(Tenant(None))
+        .with(Tenant(Some("acme")))
+        .with(Tenant(Some("beta")));
+    assert_eq!(
+        hooks
+            .on_client_tenant("client1", None)
+            .await
+            .unwrap()
+            .as_deref(),
+        Some("acme")
+    );
+    assert_eq!(
+        DefaultHooks
+            .on_client_tenant("client1", None)
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_composite_hooks_authenticate_with_metadata() {
+    /// Factory credentials are only accepted from the plant network
+    struct FactoryNetwork;
+
+    #[async_trait]
+    impl Hooks for FactoryNetwork {
+        async fn on_authenticate_with_metadata(
+            &self,
+            _client_id: &str,
+            username: Option<&str>,
+            _password: Option<&[u8]>,
+            metadata: &ConnectionMetadata,
+        ) -> HookResult<bool> {
+            let internal = metadata.client_ip.is_some_and(|ip| match ip {
+                IpAddr::V4(v4) => v4.is_private(),
+                IpAddr::V6(_) => false,
+            });
+            Ok(username != Some("factory") || internal)
+        }
+    }
+
+    let plant = ConnectionMetadata {
+        client_ip: Some("10.1.2.3".parse().unwrap()),
+        listener: Some("tls".to_string()),
+        ..Default::default()
+    };
+    let internet = ConnectionMetadata {
+        client_ip: Some("198.51.100.7".parse().unwrap()),
+        ..plant.clone()
+    };
+
+    let hooks = CompositeHooks::new().with(AllowHooks).with(FactoryNetwork);
+    assert!(hooks
+        .on_authenticate_with_metadata("c1", Some("factory"), None, &plant)
+        .await
+        .unwrap());
+    assert!(!hooks
+        .on_authenticate_with_metadata("c1", Some("factory"), None, &internet)
+        .await
+        .unwrap());
+
+    // Hooks that only implement on_authenticate are still consulted
+    let hooks = CompositeHooks::new().with(DenyHooks).with(FactoryNetwork);
+    assert!(!hooks
+        .on_authenticate_with_metadata("c1", Some("operator"), None, &plant)
+        .await
+        .unwrap());
+}
+
+/// Prefixes topics with the publisher's tenant and hides other tenants'
+/// messages from subscribers
+struct TenantHooks;
+
+#[async_trait]
+impl Hooks for TenantHooks {
+    async fn on_publish(
+        &self,
+        _client_id: &str,
+        username: Option<&str>,
+        publish: &mut Publish,
+    ) -> HookResult<bool> {
+        let Some(tenant) = username else {
+            return Ok(false);
+        };
+        publish.topic = format!("{}/{}", tenant, publish.topic);
+        Ok(true)
+    }
+
+    async fn on_deliver(&self, client_id: &str, publish: &Publish) -> HookResult<bool> {
+        Ok(publish.topic.starts_with(&format!("{}/", client_id)))
+    }
+}
+
+#[tokio::test]
+async fn test_composite_hooks_intercept() {
+    let publish = || Publish {
+        dup: false,
+        qos: QoS::AtMostOnce,
+        retain: false,
+        topic: "sensors/1".to_string(),
+        packet_id: None,
+        payload: bytes::Bytes::from_static(b"21.5"),
+        properties: Default::default(),
+    };
+
+    // Each hook sees the previous one's rewrite
+    let hooks = CompositeHooks::new().with(TenantHooks).with(TenantHooks);
+    let mut message = publish();
+    assert!(hooks
+        .on_publish("c1", Some("acme"), &mut message)
+        .await
+        .unwrap());
+    assert_eq!(message.topic, "acme/acme/sensors/1");
+    assert!(hooks.on_deliver("acme", &message).await.unwrap());
+    assert!(!hooks.on_deliver("globex", &message).await.unwrap());
+
+    // A rejection stops the chain
+    let mut message = publish();
+    assert!(!hooks.on_publish("c1", None, &mut message).await.unwrap());
+    assert_eq!(message.topic, "sensors/1");
+
+    // Default hooks pass messages through unchanged
+    let mut message = publish();
+    assert!(DefaultHooks
+        .on_publish("c1", None, &mut message)
+        .await
+        .unwrap());
+    assert_eq!(message.topic, "sensors/1");
+    assert!(DefaultHooks.on_deliver("c1", &message).await.unwrap());
+}
+
+/// Records which hooks were consulted, allowing or denying as set up
+struct Recorder {
+    name: &'static str,
+    allow: bool,
+    calls: std::sync::Arc<parking_lot::Mutex<Vec<&'static str>>>,
+}
+
+#[async_trait]
+impl Hooks for Recorder {
+    async fn on_publish_check(
+        &self,
+        _client_id: &str,
+        _username: Option<&str>,
+        _topic: &str,
+        _qos: QoS,
+        _retain: bool,
+    ) -> HookResult<bool> {
+        self.calls.lock().push(self.name);
+        Ok(self.allow)
+    }
+
+    async fn on_subscribe_check(
+        &self,
+        _client_id: &str,
+        _username: Option<&str>,
+        _filter: &str,
+        _qos: QoS,
+    ) -> HookResult<bool> {
+        self.calls.lock().push(self.name);
+        Ok(self.allow)
+    }
+}
+
+#[tokio::test]
+async fn test_composite_hooks_priority_and_filters() {
+    let calls = std::sync::Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let recorder = |name, allow| Recorder {
+        name,
+        allow,
+        calls: calls.clone(),
+    };
+    let hooks = CompositeHooks::new()
+        .with(recorder("default", true))
+        .with_options(
+            recorder("sensors", true),
+            HookOptions::new().with_priority(10).with_topic("sensors/#"),
+        )
+        .with_options(
+            recorder("ws-deny", false),
+            HookOptions::new().with_priority(5).with_listener("ws"),
+        )
+        .with(recorder("last", true));
+    let check =
+        |topic: &'static str| hooks.on_publish_check("c1", None, topic, QoS::AtMostOnce, false);
+    let take = || std::mem::take(&mut *calls.lock());
+
+    // Priorities first, then registration order; filters skip hooks
+    assert!(check("sensors/1").await.unwrap());
+    assert_eq!(take(), vec!["sensors", "default", "last"]);
+    assert!(check("other").await.unwrap());
+    assert_eq!(take(), vec!["default", "last"]);
+
+    // On its listener the deny applies and stops the chain
+    assert!(!scope_listener("ws", check("sensors/1")).await.unwrap());
+    assert_eq!(take(), vec!["sensors", "ws-deny"]);
+    assert!(scope_listener("tcp", check("sensors/1")).await.unwrap());
+    assert_eq!(take(), vec!["sensors", "default", "last"]);
+
+    // Subscribe checks by overlapping filters
+    for (filter, consulted) in [
+        ("sensors/+/temp", true),
+        ("#", true),
+        ("+/1", true),
+        ("sensors", true),
+        ("other/#", false),
+    ] {
+        assert!(hooks
+            .on_subscribe_check("c1", None, filter, QoS::AtMostOnce)
+            .await
+            .unwrap());
+        assert_eq!(take().contains(&"sensors"), consulted, "{}", filter);
+    }
+}
+
+#[test]
+fn test_filters_overlap() {
+    assert!(filters_overlap("a/b", "a/b"));
+    assert!(filters_overlap("a/+", "+/b"));
+    assert!(filters_overlap("a/#", "a"));
+    assert!(filters_overlap("a/b/c", "a/#"));
+    assert!(!filters_overlap("a/b", "a/c"));
+    assert!(!filters_overlap("a/b", "a/b/c"));
+    assert!(!filters_overlap("a/+", "a"));
+}
+
+#[test]
+fn test_connection_metadata_retain() {
+    let metadata = ConnectionMetadata {
+        client_ip: Some("10.1.2.3".parse().unwrap()),
+        sni: Some("broker.example.com".to_string()),
+        tls_version: Some("TLSv1.3".to_string()),
+        tls_cipher: Some("TLS13_AES_128_GCM_SHA256".to_string()),
+        client_cert_cn: Some("device-1".to_string()),
+        listener: Some("tls".to_string()),
+        tenant: Some("acme".to_string()),
+    };
+    assert_eq!(metadata.clone().retain(&AuthMetadataField::ALL), metadata);
+
+    let limited = metadata.retain(&[AuthMetadataField::ClientIp, AuthMetadataField::Listener]);
+    assert_eq!(
+        limited,
+        ConnectionMetadata {
+            client_ip: Some("10.1.2.3".parse().unwrap()),
+            listener: Some("tls".to_string()),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        serde_json::to_string(&limited).unwrap(),
+        r#"{"client_ip":"10.1.2.3","listener":"tls"}"#
+    );
+}
+
 #[tokio::test]
 async fn test_hook_error_display() {
     let internal = HookError::Internal("test error".to_string());
@@ -163,4 +587,7 @@ async fn test_hook_error_display() {
 
     let auth_denied = HookError::AuthorizationDenied;
     assert_eq!(format!("{}", auth_denied), "Authorization denied");
+
+    let unavailable = HookError::Unavailable("timed out".to_string());
+    assert_eq!(format!("{}", unavailable), "Backend unavailable: timed out");
 }
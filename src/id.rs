@@ -0,0 +1,297 @@
+//! Broker-wide ID Generation
+//!
+//! Provides sortable, collision-free identifiers for message IDs, trace IDs
+//! and audit records. Two strategies are available:
+//! - **UUIDv7** (default): 48-bit millisecond timestamp + randomness, no coordination needed
+//! - **Snowflake**: 41-bit timestamp, 10-bit node ID, 12-bit sequence; the node ID is
+//!   taken from config or derived from the cluster node name. Derived IDs can
+//!   collide, so cluster nodes gossip theirs and refuse to peer with a node
+//!   using the same one (see `cluster::ClusterManager`)
+//!
+//! Both generators are monotonic within a process, so IDs sort by creation time.
+
+use std::hash::Hasher;
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+
+use crate::config::{IdConfig, IdGeneratorKind};
+
+/// Snowflake epoch (2024-01-01T00:00:00Z, in unix milliseconds)
+pub const SNOWFLAKE_EPOCH_MS: u64 = 1_704_067_200_000;
+
+/// Maximum Snowflake node ID (10 bits)
+pub const MAX_NODE_ID: u16 = (1 << 10) - 1;
+
+const SNOWFLAKE_SEQUENCE_BITS: u32 = 12;
+const SNOWFLAKE_MAX_SEQUENCE: u64 = (1 << SNOWFLAKE_SEQUENCE_BITS) - 1;
+const UUIDV7_MAX_COUNTER: u16 = (1 << 12) - 1;
+
+/// Trait for broker-wide ID generators
+pub trait IdGenerator: Send + Sync {
+    /// Generate a new identifier
+    fn next_id(&self) -> String;
+
+    /// Name of the strategy (for logging)
+    fn name(&self) -> &'static str;
+
+    /// Node ID embedded in the IDs, for generators that need one unique
+    /// across the cluster
+    fn node_id(&self) -> Option<u16> {
+        None
+    }
+}
+
+/// Current unix time in milliseconds
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// UUIDv7 generator (RFC 9562)
+///
+/// The 12-bit `rand_a` field is used as a counter within the same millisecond,
+/// so IDs generated by one process are strictly increasing.
+pub struct UuidV7Generator {
+    /// (last timestamp ms, counter within that ms)
+    state: Mutex<(u64, u16)>,
+}
+
+impl UuidV7Generator {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new((0, 0)),
+        }
+    }
+
+    /// Generate the raw 128-bit UUID value
+    pub fn next_u128(&self) -> u128 {
+        let (ms, counter) = {
+            let mut state = self.state.lock();
+            let now = now_ms();
+            if now > state.0 {
+                // Start each millisecond at a random point in the lower half
+                *state = (now, rand::random::<u16>() & (UUIDV7_MAX_COUNTER >> 1));
+            } else if state.1 < UUIDV7_MAX_COUNTER {
+                state.1 += 1;
+            } else {
+                // Counter exhausted (or clock went backwards): borrow the next millisecond
+                *state = (state.0 + 1, 0);
+            }
+            *state
+        };
+
+        let rand_b = rand::random::<u64>() & 0x3FFF_FFFF_FFFF_FFFF;
+
+        ((ms as u128 & 0xFFFF_FFFF_FFFF) << 80)
+            | (0x7 << 76)
+            | ((counter as u128) << 64)
+            | (0b10 << 62)
+            | rand_b as u128
+    }
+}
+
+impl Default for UuidV7Generator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdGenerator for UuidV7Generator {
+    fn next_id(&self) -> String {
+        let v = self.next_u128();
+        format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            (v >> 96) as u32,
+            (v >> 80) as u16,
+            (v >> 64) as u16,
+            (v >> 48) as u16,
+            v & 0xFFFF_FFFF_FFFF
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        "uuidv7"
+    }
+}
+
+/// Snowflake-style 64-bit generator
+pub struct SnowflakeGenerator {
+    node_id: u16,
+    /// (last timestamp ms since epoch, sequence within that ms)
+    state: Mutex<(u64, u64)>,
+}
+
+impl SnowflakeGenerator {
+    /// Create a generator for the given node ID (masked to 10 bits)
+    pub fn new(node_id: u16) -> Self {
+        Self {
+            node_id: node_id & MAX_NODE_ID,
+            state: Mutex::new((0, 0)),
+        }
+    }
+
+    /// Create a generator with the node ID derived from a cluster node name
+    pub fn from_node_name(name: &str) -> Self {
+        Self::new(node_id_from_name(name))
+    }
+
+    /// Generate the raw 64-bit ID
+    pub fn next_u64(&self) -> u64 {
+        let (ms, seq) = {
+            let mut state = self.state.lock();
+            let now = now_ms().saturating_sub(SNOWFLAKE_EPOCH_MS);
+            if now > state.0 {
+                *state = (now, 0);
+            } else if state.1 < SNOWFLAKE_MAX_SEQUENCE {
+                state.1 += 1;
+            } else {
+                *state = (state.0 + 1, 0);
+            }
+            *state
+        };
+
+        ((ms & 0x1FF_FFFF_FFFF) << 22) | ((self.node_id as u64) << SNOWFLAKE_SEQUENCE_BITS) | seq
+    }
+}
+
+impl IdGenerator for SnowflakeGenerator {
+    fn next_id(&self) -> String {
+        // Fixed width so string ordering matches numeric ordering
+        format!("{:016x}", self.next_u64())
+    }
+
+    fn name(&self) -> &'static str {
+        "snowflake"
+    }
+
+    fn node_id(&self) -> Option<u16> {
+        Some(self.node_id)
+    }
+}
+
+/// Derive a 10-bit node ID from a cluster node name (FNV-1a)
+///
+/// Different names can map to the same ID; clusters that must not rely on
+/// collisions being caught at peering should set `id.node_id` per node.
+pub fn node_id_from_name(name: &str) -> u16 {
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(name.as_bytes());
+    (hasher.finish() % (MAX_NODE_ID as u64 + 1)) as u16
+}
+
+/// Build a generator from configuration
+///
+/// `cluster_node` is the cluster node name, used for the Snowflake node ID
+/// when none is configured explicitly.
+pub fn from_config(config: &IdConfig, cluster_node: Option<&str>) -> Arc<dyn IdGenerator> {
+    match config.generator {
+        IdGeneratorKind::Uuidv7 => Arc::new(UuidV7Generator::new()),
+        IdGeneratorKind::Snowflake => {
+            let node_id = config
+                .node_id
+                .or_else(|| cluster_node.map(node_id_from_name))
+                .unwrap_or(0);
+            Arc::new(SnowflakeGenerator::new(node_id))
+        }
+    }
+}
+
+/// Global ID generator instance
+static GLOBAL_GENERATOR: OnceLock<Arc<dyn IdGenerator>> = OnceLock::new();
+
+/// Install the global ID generator
+///
+/// Returns false if a generator was already installed (or one was already used).
+pub fn install(generator: Arc<dyn IdGenerator>) -> bool {
+    GLOBAL_GENERATOR.set(generator).is_ok()
+}
+
+/// Get the global ID generator (UUIDv7 unless another one was installed)
+pub fn global() -> &'static Arc<dyn IdGenerator> {
+    GLOBAL_GENERATOR.get_or_init(|| Arc::new(UuidV7Generator::new()))
+}
+
+/// Generate a new ID from the global generator
+#[inline]
+pub fn next_id() -> String {
+    global().next_id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uuidv7_format() {
+        let gen = UuidV7Generator::new();
+        let id = gen.next_id();
+        assert_eq!(id.len(), 36);
+        // Version nibble
+        assert_eq!(&id[14..15], "7");
+        // Variant bits (10xx)
+        assert!(matches!(&id[19..20], "8" | "9" | "a" | "b"));
+    }
+
+    #[test]
+    fn test_uuidv7_monotonic() {
+        let gen = UuidV7Generator::new();
+        let mut prev = gen.next_id();
+        for _ in 0..10_000 {
+            let id = gen.next_id();
+            assert!(id > prev, "{} should sort after {}", id, prev);
+            prev = id;
+        }
+    }
+
+    #[test]
+    fn test_snowflake_layout() {
+        let gen = SnowflakeGenerator::new(42);
+        let id = gen.next_u64();
+        assert_eq!((id >> 12) & MAX_NODE_ID as u64, 42);
+        assert_eq!(gen.next_id().len(), 16);
+    }
+
+    #[test]
+    fn test_snowflake_monotonic() {
+        let gen = SnowflakeGenerator::new(1);
+        let mut prev = gen.next_u64();
+        for _ in 0..10_000 {
+            let id = gen.next_u64();
+            assert!(id > prev);
+            prev = id;
+        }
+    }
+
+    #[test]
+    fn test_snowflake_node_ids_differ() {
+        let a = SnowflakeGenerator::new(1).next_u64();
+        let b = SnowflakeGenerator::new(2).next_u64();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_node_id_from_name() {
+        let id = node_id_from_name("node-a");
+        assert!(id <= MAX_NODE_ID);
+        assert_eq!(id, node_id_from_name("node-a"));
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = IdConfig {
+            generator: IdGeneratorKind::Snowflake,
+            node_id: None,
+        };
+        let gen = from_config(&config, Some("node-a"));
+        assert_eq!(gen.name(), "snowflake");
+        assert_eq!(gen.node_id(), Some(node_id_from_name("node-a")));
+
+        let gen = from_config(&IdConfig::default(), None);
+        assert_eq!(gen.name(), "uuidv7");
+        assert_eq!(gen.node_id(), None);
+    }
+}
@@ -4,6 +4,8 @@
 //! designed for maximum performance and full protocol compliance.
 
 pub mod acl;
+pub mod admin;
+pub mod aggregate;
 pub mod auth;
 pub mod bridge;
 pub mod broker;
@@ -11,18 +13,32 @@ pub mod buffer_pool;
 pub mod cluster;
 pub mod codec;
 pub mod config;
+pub mod enrich;
 pub mod flapping;
+pub mod geofence;
 pub mod hooks;
+pub mod id;
+pub mod logging;
 pub mod metrics;
+pub mod ocpp;
+pub mod payload;
 pub mod persistence;
+pub mod plugin;
 #[cfg(feature = "pprof")]
 pub mod profiling;
 pub mod protocol;
 pub mod proxy;
+pub mod reload;
 pub mod remote;
+pub mod rules;
+pub mod schedule;
+pub mod selftest;
 pub mod session;
+pub mod stomp;
+pub mod template;
 pub mod topic;
 pub mod transport;
+pub mod user_properties;
 
 pub use acl::AclProvider;
 pub use auth::AuthProvider;
@@ -31,7 +47,10 @@ pub use broker::Broker;
 pub use cluster::{ClusterConfig, ClusterManager};
 pub use config::Config;
 pub use flapping::{ConnectionLimitConfig, FlappingConfig, FlappingDetector};
-pub use hooks::{CompositeHooks, DefaultHooks, Hooks};
+pub use hooks::{
+    CompositeHooks, ConnectionMetadata, DefaultHooks, HookOptions, Hooks, PublishTtl,
+    RateLimitDecision,
+};
 pub use metrics::{Metrics, MetricsServer};
 pub use persistence::{FjallBackend, PersistenceManager, StorageBackend};
 pub use protocol::{ProtocolVersion, QoS};
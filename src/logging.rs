@@ -0,0 +1,114 @@
+//! Runtime Log Filter
+//!
+//! The process's tracing filter can change while the broker runs: a config
+//! reload applies a new `[log] level`, and the diagnostics server's
+//! `/debug/log` takes any `EnvFilter` string, e.g.
+//! `warn,vibemq::broker::connection=debug` to follow one module during an
+//! incident. `LogFilter` applies them through the subscriber's reload
+//! handle and remembers the one in force.
+
+use std::fmt;
+
+use parking_lot::Mutex;
+use tracing_subscriber::EnvFilter;
+
+/// Applies a filter to the installed subscriber
+pub type ApplyFilter = Box<dyn Fn(EnvFilter) -> Result<(), String> + Send + Sync>;
+
+/// Why a filter couldn't be applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogFilterError {
+    /// Not a valid `EnvFilter` string
+    Invalid(String),
+    /// The subscriber refused the filter (e.g. it is gone)
+    Apply(String),
+}
+
+impl fmt::Display for LogFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFilterError::Invalid(e) => write!(f, "invalid filter: {}", e),
+            LogFilterError::Apply(e) => write!(f, "cannot apply filter: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LogFilterError {}
+
+/// The tracing filter in force, changeable at runtime
+pub struct LogFilter {
+    current: Mutex<String>,
+    apply: ApplyFilter,
+}
+
+impl LogFilter {
+    /// `initial` is the filter the subscriber was installed with
+    pub fn new(initial: &EnvFilter, apply: ApplyFilter) -> Self {
+        Self {
+            current: Mutex::new(initial.to_string()),
+            apply,
+        }
+    }
+
+    /// The filter in force, as an `EnvFilter` string
+    pub fn current(&self) -> String {
+        self.current.lock().clone()
+    }
+
+    /// Parse and apply an `EnvFilter` string (e.g. `info,vibemq::bridge=debug`)
+    pub fn set_directives(&self, directives: &str) -> Result<(), LogFilterError> {
+        let filter = EnvFilter::try_new(directives.trim())
+            .map_err(|e| LogFilterError::Invalid(e.to_string()))?;
+        self.set(filter)
+    }
+
+    pub fn set(&self, filter: EnvFilter) -> Result<(), LogFilterError> {
+        // Hold the lock so concurrent changes land in the order recorded
+        let mut current = self.current.lock();
+        let text = filter.to_string();
+        (self.apply)(filter).map_err(LogFilterError::Apply)?;
+        *current = text;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_log_filter() {
+        let applied = Arc::new(Mutex::new(Vec::new()));
+        let record = applied.clone();
+        let filter = LogFilter::new(
+            &EnvFilter::new("warn"),
+            Box::new(move |filter| {
+                record.lock().push(filter.to_string());
+                Ok(())
+            }),
+        );
+        assert_eq!(filter.current(), "warn");
+
+        filter
+            .set_directives("warn,vibemq::broker=debug\n")
+            .unwrap();
+        assert_eq!(filter.current(), "vibemq::broker=debug,warn");
+        assert!(matches!(
+            filter.set_directives("vibemq=loud"),
+            Err(LogFilterError::Invalid(_))
+        ));
+        assert_eq!(filter.current(), "vibemq::broker=debug,warn");
+        assert_eq!(applied.lock().len(), 1);
+
+        let refusing = LogFilter::new(
+            &EnvFilter::new("info"),
+            Box::new(|_| Err("subscriber dropped".to_string())),
+        );
+        assert!(matches!(
+            refusing.set_directives("debug"),
+            Err(LogFilterError::Apply(_))
+        ));
+        assert_eq!(refusing.current(), "info");
+    }
+}
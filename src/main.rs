@@ -2,6 +2,10 @@
 //!
 //! Usage:
 //!   vibemq [OPTIONS]
+//!   vibemq config import --from <FILE> [--format mosquitto|emqx] [-o <FILE>]
+//!   vibemq data import --from <mosquitto.db> [--path <DIR>]
+//!   vibemq data migrate [--path <DIR>] [--check-only]
+//!   vibemq selftest [--username <USER> --password <PASS>] [--topic <TOPIC>]
 //!
 //! Options:
 //!   -c, --config <FILE>    Configuration file path
@@ -21,24 +25,32 @@ static ALLOC: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 #[cfg(feature = "pprof")]
 #[allow(non_upper_case_globals)]
 #[export_name = "_rjem_malloc_conf"]
-pub static malloc_conf: &[u8] = b"prof:true,prof_active:true,lg_prof_sample:19\0";
+pub static malloc_conf: &[u8] = b"prof:true,prof_active:true,prof_accum:true,lg_prof_sample:19\0";
 
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
 
-use clap::{Parser, ValueEnum};
-use tracing::{info, Level};
-use tracing_subscriber::FmtSubscriber;
+use clap::{Parser, Subcommand, ValueEnum};
+use tracing::{info, warn, Level};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
 use vibemq::acl::AclProvider;
-use vibemq::auth::AuthProvider;
-use vibemq::broker::{Broker, BrokerConfig, RetainedMessage, TlsConfig};
-use vibemq::config::Config;
+use vibemq::auth::{AuthProvider, HttpAuthenticator, PasswordFileAuthenticator};
+use vibemq::broker::{parse_retained_seed, Broker, BrokerConfig, RetainedMessage, TlsConfig};
+use vibemq::config::import::{self, ImportSource};
+use vibemq::config::{parse_reason_map, BackendType, Config, SessionCheckpoint, TopicSchemaMode};
 use vibemq::hooks::CompositeHooks;
-use vibemq::persistence::{FjallBackend, PersistenceManager};
+use vibemq::ocpp::OcppProvider;
+use vibemq::persistence::{
+    parse_mosquitto_db, FjallBackend, MemoryBackend, PersistenceManager, PersistenceOp,
+    StorageBackend, StoredRetainedMessage, FORMAT_VERSION,
+};
 use vibemq::protocol::{Properties, QoS};
+use vibemq::reload::ConfigReloader;
+use vibemq::selftest::SelfTest;
 
 /// Log level for CLI
 #[derive(Debug, Clone, Copy, ValueEnum, Default)]
@@ -66,6 +78,22 @@ impl LogLevel {
             LogLevel::Trace => Level::TRACE,
         }
     }
+
+    /// Level named by `[log] level` (warn if unrecognized)
+    fn from_config(level: &str) -> Self {
+        match level.to_lowercase().as_str() {
+            "error" => LogLevel::Error,
+            "warn" => LogLevel::Warn,
+            "info" => LogLevel::Info,
+            "debug" => LogLevel::Debug,
+            "trace" => LogLevel::Trace,
+            _ => LogLevel::Warn,
+        }
+    }
+
+    fn to_filter(self) -> EnvFilter {
+        EnvFilter::default().add_directive(LevelFilter::from_level(self.to_tracing_level()).into())
+    }
 }
 
 /// VibeMQ - High-performance MQTT broker
@@ -132,56 +160,375 @@ struct Args {
     #[cfg(feature = "pprof")]
     #[arg(long)]
     profile_output: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+/// Subcommands (the broker runs when none is given)
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Configuration utilities
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Persistent data utilities
+    Data {
+        #[command(subcommand)]
+        action: DataCommand,
+    },
+    /// Boot the configured broker on loopback ports, run a smoke suite of
+    /// MQTT flows against it and exit nonzero if any check fails
+    Selftest {
+        /// Username the test clients connect with
+        #[arg(long)]
+        username: Option<String>,
 
-    // Load configuration file if specified, otherwise use env vars + defaults
-    let file_config = if let Some(config_path) = &args.config {
-        match Config::load(config_path) {
-            Ok(cfg) => cfg,
-            Err(e) => {
-                eprintln!("Error loading config file: {}", e);
-                std::process::exit(1);
+        /// Password the test clients connect with
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Topic the test messages are published under
+        #[arg(long, default_value = "vibemq/selftest")]
+        topic: String,
+
+        /// Seconds to wait for each response from the broker
+        #[arg(long, default_value_t = 5)]
+        timeout: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Translate a Mosquitto or EMQX config file into VibeMQ TOML
+    Import {
+        /// Source configuration file
+        #[arg(long)]
+        from: PathBuf,
+
+        /// Source format (detected from the file name if omitted)
+        #[arg(long, value_enum)]
+        format: Option<ImportFormat>,
+
+        /// Output file (default: stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print the settings of a bundled profile (`profile = "<name>"`), or
+    /// list the profiles
+    Profile {
+        /// Profile name
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DataCommand {
+    /// Load retained messages and persistent sessions from a Mosquitto `mosquitto.db`
+    Import {
+        /// Mosquitto persistence file
+        #[arg(long)]
+        from: PathBuf,
+
+        /// Persistence directory to write to (default: `persistence.path` from the config)
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+    /// Store the retained messages of a seed file (JSON Lines, one exported
+    /// entry per line) in a stopped broker's persistence store
+    Seed {
+        /// Seed file
+        #[arg(long)]
+        from: PathBuf,
+
+        /// Persistence directory to write to (default: `persistence.path` from the config)
+        #[arg(long)]
+        path: Option<PathBuf>,
+
+        /// Validate the file and report what would change, without writing
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Migrate a persistence store to this release's format (the broker
+    /// also does so on startup)
+    Migrate {
+        /// Persistence directory (default: `persistence.path` from the config)
+        #[arg(long)]
+        path: Option<PathBuf>,
+
+        /// Report the store's format version and pending migrations, without
+        /// changing anything
+        #[arg(long)]
+        check_only: bool,
+    },
+}
+
+/// Source broker format for `config import`
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ImportFormat {
+    Mosquitto,
+    Emqx,
+}
+
+impl From<ImportFormat> for ImportSource {
+    fn from(format: ImportFormat) -> Self {
+        match format {
+            ImportFormat::Mosquitto => ImportSource::Mosquitto,
+            ImportFormat::Emqx => ImportSource::Emqx,
+        }
+    }
+}
+
+/// Run `vibemq config import`, printing the unsupported-options report to stderr
+fn run_config_profile(name: Option<&str>) -> i32 {
+    let Some(name) = name else {
+        for (name, _) in vibemq::config::profile::PROFILES {
+            println!("{}", name);
+        }
+        return 0;
+    };
+    match vibemq::config::profile::profile(name) {
+        Ok(settings) => {
+            print!("{}", settings);
+            0
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+fn run_config_import(
+    from: &std::path::Path,
+    format: Option<ImportFormat>,
+    output: Option<&std::path::Path>,
+) -> i32 {
+    let report = match import::import_file(from, format.map(Into::into)) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+
+    let toml = report.to_toml();
+    if let Err(e) = Config::parse(&toml) {
+        eprintln!("Warning: imported config does not validate: {}", e);
+    }
+
+    match output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, &toml) {
+                eprintln!("Error writing {}: {}", path.display(), e);
+                return 1;
             }
+            eprintln!("Wrote {}", path.display());
         }
+        None => print!("{}", toml),
+    }
+
+    if report.notes.is_empty() {
+        eprintln!("All options imported.");
     } else {
-        Config::from_env().unwrap_or_default()
-    };
+        eprintln!("{} option(s) not imported:", report.notes.len());
+        for note in &report.notes {
+            eprintln!("  {}", note);
+        }
+    }
+    0
+}
 
-    // Setup logging - CLI overrides config, config overrides default (warn)
-    let log_level = args.log_level.unwrap_or_else(|| {
-        // Parse from config string
-        match file_config.log.level.to_lowercase().as_str() {
-            "error" => LogLevel::Error,
-            "warn" => LogLevel::Warn,
-            "info" => LogLevel::Info,
-            "debug" => LogLevel::Debug,
-            "trace" => LogLevel::Trace,
-            _ => LogLevel::Warn,
+/// Run `vibemq data import`, writing Mosquitto state into the persistence store
+async fn run_data_import(from: &std::path::Path, path: &std::path::Path) -> i32 {
+    let data = match std::fs::read(from) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", from.display(), e);
+            return 1;
         }
-    });
+    };
+    let imported = match parse_mosquitto_db(&data) {
+        Ok(imported) => imported,
+        Err(e) => {
+            eprintln!("Error parsing {}: {}", from.display(), e);
+            return 1;
+        }
+    };
+    let backend = match FjallBackend::open(path) {
+        Ok(backend) => backend,
+        Err(e) => {
+            eprintln!("Error opening {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    if let Err(e) = imported.write_to(&backend).await {
+        eprintln!("Error writing to {}: {}", path.display(), e);
+        return 1;
+    }
 
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(log_level.to_tracing_level())
-        .with_target(false)
-        .with_thread_ids(true)
-        .with_file(false)
-        .with_line_number(false)
-        .compact()
-        .finish();
+    let subscriptions: usize = imported
+        .sessions
+        .iter()
+        .map(|s| s.subscriptions.len())
+        .sum();
+    eprintln!(
+        "Imported {} retained message(s), {} session(s) with {} subscription(s) from mosquitto.db v{} into {}",
+        imported.retained.len(),
+        imported.sessions.len(),
+        subscriptions,
+        imported.db_version,
+        path.display()
+    );
+    if imported.skipped > 0 {
+        eprintln!(
+            "Skipped {} entries (expired, unreferenced or unsupported)",
+            imported.skipped
+        );
+    }
+    0
+}
 
-    tracing::subscriber::set_global_default(subscriber)?;
+/// Run `vibemq data seed`, writing a seed file's retained messages into the
+/// persistence store in one batch
+async fn run_data_seed(from: &std::path::Path, path: &std::path::Path, dry_run: bool) -> i32 {
+    let seed = match std::fs::read_to_string(from) {
+        Ok(seed) => seed,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", from.display(), e);
+            return 1;
+        }
+    };
+    let messages = match parse_retained_seed(&seed) {
+        Ok(messages) => messages,
+        Err(e) => {
+            eprintln!("Error in {}: {}", from.display(), e);
+            return 1;
+        }
+    };
+    let backend = match FjallBackend::open(path) {
+        Ok(backend) => backend,
+        Err(e) => {
+            eprintln!("Error opening {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    let existing: std::collections::HashSet<String> = match backend.list_retained().await {
+        Ok(retained) => retained.into_iter().map(|(topic, _)| topic).collect(),
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    let updated = messages
+        .iter()
+        .filter(|m| existing.contains(&m.topic))
+        .count();
+    let created = messages.len() - updated;
 
-    if args.config.is_some() {
-        info!(
-            "Loaded configuration from {:?}",
-            args.config.as_ref().unwrap()
+    if dry_run {
+        eprintln!(
+            "Would seed {} retained message(s) into {} ({} new, {} replaced)",
+            messages.len(),
+            path.display(),
+            created,
+            updated
+        );
+        return 0;
+    }
+    let ops = messages
+        .iter()
+        .map(|message| PersistenceOp::SetRetained {
+            topic: message.topic.clone(),
+            message: StoredRetainedMessage::from(message),
+        })
+        .collect();
+    if let Err(e) = backend.batch_write(ops).await {
+        eprintln!("Error writing to {}: {}", path.display(), e);
+        return 1;
+    }
+    if let Err(e) = backend.flush().await {
+        eprintln!("Error writing to {}: {}", path.display(), e);
+        return 1;
+    }
+    eprintln!(
+        "Seeded {} retained message(s) into {} ({} new, {} replaced)",
+        messages.len(),
+        path.display(),
+        created,
+        updated
+    );
+    0
+}
+
+/// Run `vibemq data migrate`; fails if the store can't be brought to the
+/// current format
+fn run_data_migrate(path: &std::path::Path, check_only: bool, backup: bool) -> i32 {
+    let status = match FjallBackend::check_format(path) {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("Error checking {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    let Some(version) = status.version else {
+        eprintln!("No data in {}", path.display());
+        return 0;
+    };
+    if status.is_current() {
+        eprintln!(
+            "{} is in format version {} (current)",
+            path.display(),
+            version
         );
+        return 0;
+    }
+    eprintln!(
+        "{} is in format version {}; {} migration(s) to version {}:",
+        path.display(),
+        version,
+        status.pending.len(),
+        FORMAT_VERSION
+    );
+    for migration in &status.pending {
+        eprintln!("  {}", migration);
+    }
+    if check_only {
+        return 0;
     }
+    match FjallBackend::open_migrating(path, backup) {
+        Ok(_) => {
+            eprintln!("Migrated {}", path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("Error migrating {}: {}", path.display(), e);
+            1
+        }
+    }
+}
+
+/// Run `vibemq selftest`, printing one line per check
+async fn run_selftest(selftest: SelfTest) -> i32 {
+    match selftest.run().await {
+        Ok(report) => {
+            println!("{}", report);
+            if report.passed() {
+                0
+            } else {
+                1
+            }
+        }
+        Err(e) => {
+            eprintln!("Error starting the broker: {}", e);
+            1
+        }
+    }
+}
 
+/// Broker settings for a config, with command-line overrides applied
+fn build_broker_config(file_config: &Config, args: &Args) -> BrokerConfig {
     // CLI args override file config
     let bind_addr = args.bind.unwrap_or(file_config.server.bind);
     let tls_bind_addr = file_config.server.tls_bind;
@@ -190,6 +537,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         key_path: tls.key.clone(),
         ca_cert_path: tls.ca_cert.clone(),
         require_client_cert: tls.require_client_cert,
+        handshake_threads: tls.handshake_threads,
+        handshake_queue_size: tls.handshake_queue_size,
+        handshake_timeout: tls.handshake_timeout,
     });
     let ws_bind_addr = args.ws_bind.or(file_config.server.ws_bind);
     let max_connections = args
@@ -221,20 +571,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .wildcard_subs
         .unwrap_or(file_config.mqtt.wildcard_subscriptions);
 
-    // Parse max QoS
-    let max_qos_value = args.max_qos.unwrap_or(file_config.mqtt.max_qos);
-    let max_qos = match max_qos_value {
-        0 => QoS::AtMostOnce,
-        1 => QoS::AtLeastOnce,
-        2 => QoS::ExactlyOnce,
-        _ => {
-            eprintln!(
-                "Invalid max-qos value: {}. Must be 0, 1, or 2.",
-                max_qos_value
-            );
-            std::process::exit(1);
-        }
-    };
+    // Checked at startup (and by config validation)
+    let max_qos =
+        QoS::from_u8(args.max_qos.unwrap_or(file_config.mqtt.max_qos)).unwrap_or(QoS::ExactlyOnce);
 
     // Determine worker count
     let workers = args.workers.unwrap_or(file_config.server.workers);
@@ -246,28 +585,40 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         workers
     };
 
-    // Build broker configuration
-    let broker_config = BrokerConfig {
+    BrokerConfig {
         bind_addr,
+        extra_bind_addrs: file_config.server.extra_binds.clone(),
+        ipv6_only: file_config.server.ipv6_only,
         tls_bind_addr,
         tls_config,
         ws_bind_addr,
+        wss_bind_addr: file_config.server.wss_bind,
+        quic_bind_addr: file_config.server.quic_bind,
+        quic: file_config.server.quic.clone(),
         ws_path: file_config.server.ws_path.clone(),
+        ws_max_frame_size: file_config.server.ws_max_frame_size,
+        unix_bind_path: file_config.server.unix_bind.clone(),
         max_connections,
         max_packet_size,
         default_keep_alive: keep_alive,
         max_keep_alive,
         session_expiry_check_interval: file_config.session.expiry_check_interval,
+        session_compress_idle_after: file_config.session.compress_idle_after,
+        max_session_expiry: file_config.session.max_expiry,
         receive_maximum,
         max_qos,
         retain_available,
         wildcard_subscription_available: wildcard_subs,
         subscription_identifiers_available: file_config.mqtt.subscription_identifiers,
         shared_subscriptions_available: file_config.mqtt.shared_subscriptions,
+        shared_subscription_strategy: file_config.mqtt.shared_subscription_strategy,
         max_topic_alias,
         num_workers,
         sys_topics_enabled: file_config.mqtt.sys_topics,
         sys_topics_interval: file_config.mqtt.sys_interval,
+        max_local_hops: file_config.mqtt.max_local_hops,
+        routing_ids: file_config.mqtt.routing_ids,
+        bridge_compression: file_config.mqtt.bridge_compression.clone(),
         // 0 = unbounded for all limits
         max_inflight: if file_config.limits.max_inflight == 0 {
             u16::MAX
@@ -279,12 +630,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         } else {
             file_config.limits.max_queued_messages
         },
+        max_queued_bytes: file_config.limits.max_queued_bytes,
+        queue_qos0: file_config.limits.queue_qos0,
+        queue_overflow: file_config.limits.queue_overflow,
         max_awaiting_rel: if file_config.limits.max_awaiting_rel == 0 {
             usize::MAX
         } else {
             file_config.limits.max_awaiting_rel
         },
         retry_interval: file_config.limits.retry_interval,
+        ack_alarm_after: file_config.limits.ack_alarm_after,
         outbound_channel_capacity: if file_config.limits.outbound_channel_capacity == 0 {
             // tokio mpsc channel max is ~2^61, use a large but safe value
             1_000_000
@@ -292,19 +647,161 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             file_config.limits.outbound_channel_capacity
         },
         max_topic_levels: file_config.limits.max_topic_levels,
+        hibernate_after: file_config.limits.hibernate_after,
         proxy_protocol: file_config.server.proxy_protocol.clone(),
         tls_proxy_protocol: file_config.server.tls_proxy_protocol.clone(),
         ws_proxy_protocol: file_config.server.ws_proxy_protocol.clone(),
+        unix_proxy_protocol: file_config.server.unix_proxy_protocol.clone(),
+        allow_mqtt31: file_config.server.allow_mqtt31,
+        tls_allow_mqtt31: file_config.server.tls_allow_mqtt31,
+        ws_allow_mqtt31: file_config.server.ws_allow_mqtt31,
+        listener_max_qos: file_config.server.max_qos.and_then(QoS::from_u8),
+        tls_listener_max_qos: file_config.server.tls_max_qos.and_then(QoS::from_u8),
+        ws_listener_max_qos: file_config.server.ws_max_qos.and_then(QoS::from_u8),
+        capabilities: file_config.server.capabilities,
+        tls_capabilities: file_config.server.tls_capabilities,
+        ws_capabilities: file_config.server.ws_capabilities,
+        error_detail: file_config.server.error_detail,
+        tls_error_detail: file_config.server.tls_error_detail,
+        ws_error_detail: file_config.server.ws_error_detail,
+        // Validated when the config was loaded
+        reason_map: parse_reason_map(&file_config.server.reason_map).unwrap_or_default(),
+        batch: file_config.batch.clone(),
+        transaction: file_config.transaction.clone(),
+        handover: file_config.handover.clone(),
+        retained_feed: file_config.retained_feed.clone(),
+        retained_cache: file_config.retained_cache.clone(),
+        delayed: file_config.delayed.clone(),
+        session_expiry_events: file_config.session.expiry_events.clone(),
+        backlog_drain: file_config.session.backlog_drain.clone(),
+        health: file_config.health.clone(),
+        topic_tree: file_config.topic_tree.clone(),
+        topic_schema: file_config.topic_schema.clone(),
+        sequence: file_config.sequence.clone(),
+        priority: file_config.priority.clone(),
+        publish_rate: file_config.limits.publish_rate.clone(),
+        quota: file_config.limits.quota.clone(),
+        memory_pressure: file_config.limits.memory_pressure.clone(),
+        publisher_backpressure: file_config.limits.publisher_backpressure.clone(),
+        listener_limits: file_config.limits.listeners.clone(),
+        auth_metadata_fields: file_config.auth.metadata_fields.clone(),
+        shutdown: file_config.shutdown.clone(),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Arc::new(Args::parse());
+
+    if let Some(Command::Config { action }) = &args.command {
+        match action {
+            ConfigCommand::Import {
+                from,
+                format,
+                output,
+            } => std::process::exit(run_config_import(from, *format, output.as_deref())),
+            ConfigCommand::Profile { name } => {
+                std::process::exit(run_config_profile(name.as_deref()))
+            }
+        }
+    }
+
+    // Load configuration file if specified, otherwise use env vars + defaults
+    let file_config = if let Some(config_path) = &args.config {
+        match Config::load(config_path) {
+            Ok(cfg) => cfg,
+            Err(e) => {
+                eprintln!("Error loading config file: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        Config::from_env().unwrap_or_default()
     };
 
+    if let Some(Command::Data { action }) = &args.command {
+        match action {
+            DataCommand::Import { from, path } => {
+                let path = path.as_deref().unwrap_or(&file_config.persistence.path);
+                std::process::exit(run_data_import(from, path).await)
+            }
+            DataCommand::Seed {
+                from,
+                path,
+                dry_run,
+            } => {
+                let path = path.as_deref().unwrap_or(&file_config.persistence.path);
+                std::process::exit(run_data_seed(from, path, *dry_run).await)
+            }
+            DataCommand::Migrate { path, check_only } => {
+                let path = path.as_deref().unwrap_or(&file_config.persistence.path);
+                let backup = file_config.persistence.backup_before_migrate;
+                std::process::exit(run_data_migrate(path, *check_only, backup))
+            }
+        }
+    }
+
+    // Setup logging - CLI overrides config, config overrides default (warn)
+    let log_level = args
+        .log_level
+        .unwrap_or_else(|| LogLevel::from_config(&file_config.log.level));
+
+    let subscriber = FmtSubscriber::builder()
+        .with_env_filter(log_level.to_filter())
+        .with_target(false)
+        .with_thread_ids(true)
+        .with_file(false)
+        .with_line_number(false)
+        .compact()
+        .with_filter_reloading();
+    let reload_handle = subscriber.reload_handle();
+    let log_filter = Arc::new(vibemq::logging::LogFilter::new(
+        &log_level.to_filter(),
+        Box::new(move |filter| reload_handle.reload(filter).map_err(|e| e.to_string())),
+    ));
+
+    tracing::subscriber::set_global_default(subscriber.finish())?;
+
+    if let Some(ref path) = args.config {
+        info!("Loaded configuration from {:?}", path);
+    }
+    if let Some(ref profile) = file_config.profile {
+        info!("Using the {} profile", profile);
+    }
+
+    // Parse max QoS
+    let max_qos_value = args.max_qos.unwrap_or(file_config.mqtt.max_qos);
+    if QoS::from_u8(max_qos_value).is_none() {
+        eprintln!(
+            "Invalid max-qos value: {}. Must be 0, 1, or 2.",
+            max_qos_value
+        );
+        std::process::exit(1);
+    }
+
+    // Build broker configuration (CLI args override file config)
+    let broker_config = build_broker_config(&file_config, &args);
+
     info!("Starting VibeMQ MQTT Broker");
     info!("  Bind address: {}", broker_config.bind_addr);
+    for addr in &broker_config.extra_bind_addrs {
+        info!("  Bind address: {}", addr);
+    }
     if let Some(tls_addr) = &broker_config.tls_bind_addr {
         info!("  TLS address: {}", tls_addr);
     }
     if let Some(ws_addr) = &broker_config.ws_bind_addr {
         info!("  WebSocket address: {}", ws_addr);
     }
+    if let Some(wss_addr) = &broker_config.wss_bind_addr {
+        info!("  WebSocket/TLS address: {}", wss_addr);
+    }
+    if let Some(quic_addr) = &broker_config.quic_bind_addr {
+        info!("  QUIC address: {} (experimental)", quic_addr);
+    }
+    if let Some(unix_path) = &broker_config.unix_bind_path {
+        info!("  Unix socket: {}", unix_path.display());
+    }
     info!("  Workers: {}", broker_config.num_workers);
     info!("  Max connections: {}", broker_config.max_connections);
     info!("  Max packet size: {} bytes", broker_config.max_packet_size);
@@ -313,11 +810,45 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "  Max queued messages: {}",
         broker_config.max_queued_messages
     );
+    if let Some(idle_after) = broker_config.session_compress_idle_after {
+        info!("  Idle session compression: after {:?}", idle_after);
+    }
+    if let Some(idle_after) = broker_config.hibernate_after {
+        info!("  Idle connection hibernation: after {:?}", idle_after);
+    }
+    if let Some(alarm_after) = broker_config.ack_alarm_after {
+        info!("  Unacknowledged message alarm: after {:?}", alarm_after);
+    }
+    if broker_config.publish_rate.enabled() {
+        info!(
+            "  Publish rate limit: {}/s per identity (burst {})",
+            broker_config.publish_rate.messages_per_sec, broker_config.publish_rate.burst
+        );
+    }
     info!(
         "  Outbound channel capacity: {}",
         broker_config.outbound_channel_capacity
     );
     info!("  Max QoS: {:?}", broker_config.max_qos);
+    for (name, max_qos) in [
+        ("TCP", broker_config.listener_max_qos),
+        ("TLS", broker_config.tls_listener_max_qos),
+        ("WebSocket", broker_config.ws_listener_max_qos),
+    ] {
+        if let Some(max_qos) = max_qos {
+            info!("  Max QoS on {} listener: {:?}", name, max_qos);
+        }
+    }
+
+    // Install the broker-wide ID generator (node ID from cluster membership)
+    let cluster_node_id = file_config
+        .cluster
+        .iter()
+        .find(|c| c.enabled)
+        .map(|c| c.get_node_id());
+    let id_generator = vibemq::id::from_config(&file_config.id, cluster_node_id.as_deref());
+    info!("  ID generator: {}", id_generator.name());
+    vibemq::id::install(id_generator);
 
     // Log auth/ACL status
     if file_config.auth.enabled {
@@ -328,11 +859,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     } else {
         info!("  Authentication: disabled");
     }
-    if file_config.acl.enabled {
+    if file_config.acl.enabled && file_config.acl.rule_file.is_none() {
         info!(
             "  ACL: enabled ({} roles configured)",
             file_config.acl.roles.len()
         );
+    } else if file_config.acl.enabled {
+        info!("  ACL: enabled");
     } else {
         info!("  ACL: disabled");
     }
@@ -348,6 +881,59 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         );
     }
+    let mqtt31_refused: Vec<&str> = [
+        ("TCP", broker_config.allow_mqtt31),
+        ("TLS", broker_config.tls_allow_mqtt31),
+        ("WebSocket", broker_config.ws_allow_mqtt31),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| (!enabled).then_some(name))
+    .collect();
+    if broker_config.batch.enabled {
+        info!(
+            "  Message batching: {} (max {} messages)",
+            broker_config.batch.topic, broker_config.batch.max_messages
+        );
+    }
+    if broker_config.transaction.enabled {
+        info!(
+            "  Publish transactions: {}/{{begin,commit,abort}} (max {} messages)",
+            broker_config.transaction.topic, broker_config.transaction.max_messages
+        );
+    }
+    if broker_config.handover.enabled {
+        info!(
+            "  Session handover: {}/{{prepare,cancel}} (token valid {:?})",
+            broker_config.handover.topic, broker_config.handover.token_ttl
+        );
+    }
+    if broker_config.retained_feed.enabled {
+        info!(
+            "  Retained change feed: {}/#",
+            broker_config.retained_feed.topic
+        );
+    }
+    if broker_config.delayed.enabled {
+        info!(
+            "  Delayed publish: {}/<seconds>/<topic> (max {:?}, {} messages)",
+            broker_config.delayed.topic,
+            broker_config.delayed.max_delay,
+            broker_config.delayed.max_messages
+        );
+    }
+    if broker_config.topic_schema.mode != TopicSchemaMode::Off {
+        info!(
+            "  Topic schema: {} templates ({:?} on mismatch)",
+            broker_config.topic_schema.templates.len(),
+            broker_config.topic_schema.mode
+        );
+    }
+    if !mqtt31_refused.is_empty() {
+        info!(
+            "  MQTT 3.1 (MQIsdp): refused on {}",
+            mqtt31_refused.join(", ")
+        );
+    }
     if broker_config.tls_proxy_protocol.enabled {
         info!(
             "  PROXY protocol (TLS): enabled{}",
@@ -368,39 +954,185 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         );
     }
+    if broker_config.unix_proxy_protocol.enabled {
+        info!(
+            "  PROXY protocol (Unix): enabled{}",
+            if broker_config.unix_proxy_protocol.tls_termination {
+                " (TLS termination)"
+            } else {
+                ""
+            }
+        );
+    }
+    // Unix socket peers are always trusted, so only the network listeners
+    let cluster_proxies = file_config
+        .cluster
+        .iter()
+        .filter(|c| c.enabled)
+        .map(|c| ("cluster", &c.proxy_protocol));
+    for (name, proxy) in [
+        ("TCP", &broker_config.proxy_protocol),
+        ("TLS", &broker_config.tls_proxy_protocol),
+        ("WebSocket", &broker_config.ws_proxy_protocol),
+    ]
+    .into_iter()
+    .chain(cluster_proxies)
+    {
+        if proxy.enabled && proxy.trusted_networks.is_empty() {
+            warn!(
+                "PROXY protocol ({}) has no trusted_networks: any peer can claim any client address",
+                name
+            );
+        }
+    }
 
     // Create auth and ACL providers
-    let auth_provider = Arc::new(AuthProvider::new(&file_config.auth));
-    let acl_provider = Arc::new(AclProvider::new(&file_config.acl, auth_provider.clone()));
+    let mut auth_provider = AuthProvider::new(&file_config.auth);
+    if let Some(ref path) = file_config.auth.password_file {
+        let passwords = match PasswordFileAuthenticator::load(path) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("Error loading password file: {}", e);
+                std::process::exit(1);
+            }
+        };
+        info!(
+            "  Password file: {} ({} users)",
+            path.display(),
+            passwords.len()
+        );
+        auth_provider = auth_provider.with_authenticator(Arc::new(passwords));
+    }
+    if let Some(ref http) = file_config.auth.http {
+        let webhook = match HttpAuthenticator::new(http) {
+            Ok(h) => h,
+            Err(e) => {
+                eprintln!("Error configuring HTTP authentication: {}", e);
+                std::process::exit(1);
+            }
+        };
+        info!("  HTTP authentication: {}", http.url);
+        auth_provider = auth_provider.with_authenticator(Arc::new(webhook));
+    }
+    let auth_provider = Arc::new(auth_provider);
+    let acl_rules = match file_config.acl.load_rules() {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("Error loading ACL rules: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(ref path) = file_config.acl.rule_file {
+        info!(
+            "  ACL rules: {} ({} roles)",
+            path.display(),
+            acl_rules.roles.len()
+        );
+    }
+    let acl_provider = Arc::new(AclProvider::new(&acl_rules, auth_provider.clone()));
+
+    let plugin_host = match vibemq::plugin::PluginHost::new(&file_config.plugins) {
+        Ok(host) => Arc::new(host),
+        Err(e) => {
+            eprintln!("Error loading plugins: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if !file_config.plugins.instances.is_empty() {
+        info!("  Plugins: {}", file_config.plugins.instances.len());
+    }
+
+    // Compose hooks: auth first, then ACL, then plugins, then OCPP topic
+    // conventions
+    let mut hooks = CompositeHooks::new()
+        .with(auth_provider.clone())
+        .with(acl_provider.clone())
+        .with(plugin_host.clone());
+    let mut ocpp_provider = None;
+    if file_config.ocpp.enabled {
+        let provider = match OcppProvider::new(&file_config.ocpp) {
+            Ok(p) => Arc::new(p),
+            Err(e) => {
+                eprintln!("Error configuring OCPP: {}", e);
+                std::process::exit(1);
+            }
+        };
+        info!(
+            "  OCPP: uplink {}, downlink {}",
+            file_config.ocpp.uplink_topic, file_config.ocpp.downlink_topic
+        );
+        hooks.add(provider.clone());
+        ocpp_provider = Some(provider);
+    }
+    let hooks = Arc::new(hooks);
 
-    // Compose hooks: auth first, then ACL
-    let hooks = Arc::new(CompositeHooks::new().with(auth_provider).with(acl_provider));
+    if let Some(Command::Selftest {
+        username,
+        password,
+        topic,
+        timeout,
+    }) = &args.command
+    {
+        let selftest = SelfTest::new(broker_config, hooks)
+            .with_auth_enabled(file_config.auth.enabled)
+            .with_credentials(username.clone(), password.clone())
+            .with_topic(topic)
+            .with_timeout(std::time::Duration::from_secs(*timeout));
+        std::process::exit(run_selftest(selftest).await);
+    }
 
     // Create broker with hooks
     let mut broker = Broker::with_hooks(broker_config, hooks);
 
     // Initialize persistence if enabled
-    let persistence_manager = if file_config.persistence.enabled {
-        info!(
-            "  Persistence: enabled ({:?})",
-            file_config.persistence.path
-        );
+    // Last runs of scheduled publishes, restored once the broker is shared
+    let mut schedule_runs = Vec::new();
 
-        // Open the fjall backend
-        let backend = match FjallBackend::open(&file_config.persistence.path) {
-            Ok(b) => Arc::new(b),
-            Err(e) => {
-                eprintln!("Error opening persistence backend: {}", e);
-                std::process::exit(1);
+    let persistence_manager = if file_config.persistence.enabled {
+        // Open the configured backend
+        let backend: Arc<dyn StorageBackend> = match file_config.persistence.backend {
+            BackendType::Fjall => {
+                info!(
+                    "  Persistence: enabled ({:?})",
+                    file_config.persistence.path
+                );
+                match FjallBackend::open_migrating(
+                    &file_config.persistence.path,
+                    file_config.persistence.backup_before_migrate,
+                ) {
+                    Ok(b) => Arc::new(b),
+                    Err(e) => {
+                        eprintln!("Error opening persistence backend: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            BackendType::Memory => {
+                info!("  Persistence: enabled (memory, not kept across restarts)");
+                Arc::new(MemoryBackend::new())
             }
         };
 
         // Create the persistence manager
-        let manager = Arc::new(PersistenceManager::new(
-            backend,
-            file_config.persistence.flush_interval,
-            file_config.persistence.max_batch_size,
-        ));
+        let manager = Arc::new(
+            PersistenceManager::new(
+                backend,
+                file_config.persistence.flush_interval,
+                file_config.persistence.max_batch_size,
+            )
+            .with_session_checkpoint(
+                file_config.persistence.session_checkpoint,
+                file_config.persistence.session_checkpoint_interval,
+            ),
+        );
+        match file_config.persistence.session_checkpoint {
+            SessionCheckpoint::EveryChange => info!("  Session checkpoint: every change"),
+            SessionCheckpoint::Interval => info!(
+                "  Session checkpoint: every {:?}",
+                file_config.persistence.session_checkpoint_interval
+            ),
+            SessionCheckpoint::Disconnect => info!("  Session checkpoint: on disconnect"),
+        }
 
         // Load existing data
         let loaded = match manager.load_all().await {
@@ -425,10 +1157,50 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 qos: QoS::from_u8(stored.qos).unwrap_or_default(),
                 properties: Properties::from(stored.properties),
                 timestamp: Instant::now(), // Approximate - original timestamp lost
+                proxy_identity: None,
             };
             broker.retained().insert(topic, msg);
         }
 
+        // Restore publish rate buckets so limits survive the restart
+        if let Some(limiter) = broker.rate_limits() {
+            if file_config.limits.publish_rate.persist {
+                for (identity, stored) in loaded.rate_buckets {
+                    limiter.merge(stored.into_bucket(identity));
+                }
+            }
+        }
+
+        schedule_runs = loaded.schedule_runs;
+
+        // Continue sequence numbers after their stored reservations
+        if broker.sequencer().is_enabled() {
+            for (topic, stored) in loaded.sequences {
+                broker.sequencer().restore(topic, stored);
+            }
+        }
+
+        // Restore delayed publishes; those due while the broker was down go
+        // out right away
+        if !loaded.delayed.is_empty() {
+            if file_config.delayed.enabled {
+                info!("  Delayed messages: {} restored", loaded.delayed.len());
+                for (id, stored) in loaded.delayed {
+                    broker.delayed().restore(id, stored.into());
+                }
+            } else {
+                warn!(
+                    "{} delayed messages are stored but delayed publish is disabled; \
+                     they are kept until it is enabled",
+                    loaded.delayed.len()
+                );
+            }
+        }
+
+        // Session expiry events stored but not yet published go out once the
+        // broker runs
+        broker.restore_session_events(loaded.session_events);
+
         // TODO: Restore sessions when session store supports it
         // For now, sessions will be recreated on client reconnect
 
@@ -460,6 +1232,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("  DoS protection: disabled");
     }
 
+    // Setup STOMP compatibility listener if enabled
+    if file_config.stomp.enabled {
+        info!(
+            "  STOMP: {} destination mapping(s){}",
+            file_config.stomp.destinations.len(),
+            if file_config.stomp.dot_separator {
+                " ('.' separator)"
+            } else {
+                ""
+            }
+        );
+        broker.set_stomp(file_config.stomp.clone());
+    }
+
     // Setup bridges if configured
     let enabled_bridges = file_config.bridge.iter().filter(|b| b.enabled).count();
     info!(
@@ -491,7 +1277,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 );
             }
         }
-        let bridge_manager = broker.create_bridge_manager(file_config.bridge);
+        let bridge_manager = broker.create_bridge_manager(file_config.bridge.clone());
         broker.set_bridge_manager(bridge_manager);
     }
 
@@ -507,6 +1293,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             info!("    Seeds: {}", cluster_cfg.seeds.join(", "));
         }
 
+        if cluster_cfg.is_observer() {
+            info!(
+                "    Role: observer (read-only, API on http://{})",
+                cluster_cfg.observer_api_bind
+            );
+        }
+
         match broker.create_cluster_manager(cluster_cfg.clone()).await {
             Ok(cluster_manager) => {
                 broker.set_cluster_manager(cluster_manager);
@@ -520,31 +1313,80 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("  Cluster: disabled");
     }
 
-    // Setup metrics if configured
+    // Serve read-only queries from observer nodes
+    if let Some(cluster_manager) = broker.cluster_manager().filter(|c| c.is_observer()) {
+        let observer_api = vibemq::cluster::ObserverApi::new(
+            file_config.cluster[0].observer_api_bind,
+            file_config.admin.token.clone().unwrap_or_default(),
+            cluster_manager.clone(),
+            broker.retained().clone(),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = observer_api.run().await {
+                tracing::error!("Observer API error: {}", e);
+            }
+        });
+    }
+
+    // Setup metrics if configured (the server starts once the broker is
+    // shared, as it also answers the readiness probe)
+    let mut metrics_server = None;
     if file_config.metrics.enabled {
         let metrics = Arc::new(vibemq::Metrics::new());
         broker.set_metrics(metrics.clone());
+        if let Err(e) = plugin_host.register_metrics(&metrics) {
+            tracing::warn!("Failed to register plugin metrics: {}", e);
+        }
         info!("  Metrics: enabled (http://{})", file_config.metrics.bind);
-
-        // Spawn metrics server
-        let metrics_server = vibemq::MetricsServer::new(metrics, file_config.metrics.bind);
-        tokio::spawn(async move {
-            if let Err(e) = metrics_server.run().await {
-                tracing::error!("Metrics server error: {}", e);
-            }
-        });
+        metrics_server = Some(vibemq::MetricsServer::new(
+            metrics,
+            file_config.metrics.bind,
+        ));
+    } else if file_config.mqtt.sys_topics {
+        // $SYS topics are fed from the same counters
+        broker.set_metrics(Arc::new(vibemq::Metrics::new()));
+        info!("  Metrics: disabled (collected for $SYS topics only)");
     } else {
         info!("  Metrics: disabled");
     }
 
+    #[cfg(not(feature = "pprof"))]
+    if file_config.profile_history.enabled {
+        warn!("profile_history is enabled, but this build has no pprof feature; ignoring");
+    }
+
     // Start profiling server if feature is enabled
     #[cfg(feature = "pprof")]
     let continuous_profiler = {
         vibemq::profiling::check_jemalloc_profiling();
         let pprof_addr: std::net::SocketAddr = vibemq::profiling::DEFAULT_BIND.parse().unwrap();
         info!("  Profiling: enabled (http://{})", pprof_addr);
+        let metrics = broker.metrics().cloned();
+        let log_filter = log_filter.clone();
+        let history = if file_config.profile_history.enabled {
+            match vibemq::profiling::ProfileHistory::new(&file_config.profile_history) {
+                Ok(history) => {
+                    let history = Arc::new(history);
+                    history.clone().spawn();
+                    Some(history)
+                }
+                Err(e) => {
+                    tracing::error!(
+                        "Failed to open profile history {:?}: {}",
+                        file_config.profile_history.dir,
+                        e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
         tokio::spawn(async move {
-            if let Err(e) = vibemq::profiling::start_server(pprof_addr).await {
+            if let Err(e) =
+                vibemq::profiling::start_server(pprof_addr, metrics, Some(log_filter), history)
+                    .await
+            {
                 tracing::error!("Profiling server error: {}", e);
             }
         });
@@ -564,6 +1406,171 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    let broker = Arc::new(broker);
+
+    // Spawn metrics server
+    if let Some(metrics_server) = metrics_server {
+        let metrics_server = metrics_server.with_health(broker.clone());
+        tokio::spawn(async move {
+            if let Err(e) = metrics_server.run().await {
+                tracing::error!("Metrics server error: {}", e);
+            }
+        });
+    }
+
+    // Serve the health probes on their own listener
+    if let Some(bind) = file_config.health.bind {
+        info!("  Health probes: http://{}/readyz", bind);
+        let health_server = vibemq::broker::HealthServer::new(broker.clone(), bind);
+        tokio::spawn(async move {
+            if let Err(e) = health_server.run().await {
+                tracing::error!("Health probe server error: {}", e);
+            }
+        });
+    }
+
+    // Maintenance windows may change the log filter for a while
+    let maintenance_log_filter = log_filter.clone();
+
+    // Reload the config on SIGHUP and from the admin API
+    let reload_args = args.clone();
+    let mut reloader = ConfigReloader::new(
+        args.config.clone(),
+        file_config.clone(),
+        broker.clone(),
+        auth_provider,
+        acl_provider,
+        Box::new(move |config| build_broker_config(config, &reload_args)),
+    );
+    // A level given on the command line stays in force
+    if args.log_level.is_none() {
+        reloader = reloader.with_log_level(Box::new(move |level| {
+            if let Err(e) = log_filter.set(LogLevel::from_config(level).to_filter()) {
+                tracing::warn!("Cannot change the log level: {}", e);
+            }
+        }));
+    }
+    let reloader = Arc::new(reloader);
+    #[cfg(unix)]
+    spawn_config_reload(reloader.clone());
+
+    // Serve the admin API (token presence is checked by config validation)
+    if file_config.admin.enabled {
+        let token = file_config.admin.token.clone().unwrap_or_default();
+        info!("  Admin API: enabled (http://{})", file_config.admin.bind);
+        let mut admin_api =
+            vibemq::admin::AdminApi::new(file_config.admin.bind, token, broker.clone())
+                .with_reloader(reloader)
+                .with_plugins(plugin_host.clone());
+        if let Some(ocpp) = ocpp_provider {
+            admin_api = admin_api.with_ocpp(ocpp);
+        }
+        tokio::spawn(async move {
+            if let Err(e) = admin_api.run().await {
+                tracing::error!("Admin API error: {}", e);
+            }
+        });
+    }
+
+    // Run scheduled publishes (validated with the config)
+    if file_config.schedule.iter().any(|s| s.enabled) {
+        match vibemq::schedule::Scheduler::new(broker.clone(), &file_config.schedule) {
+            Ok(scheduler) => {
+                info!("  Schedules: {} enabled", scheduler.len());
+                for schedule in file_config.schedule.iter().filter(|s| s.enabled) {
+                    info!(
+                        "    - {} [{}] -> {}",
+                        schedule.name, schedule.cron, schedule.topic
+                    );
+                }
+                tokio::spawn(scheduler.with_last_runs(schedule_runs).run());
+            }
+            Err(e) => tracing::error!("Scheduled publishes disabled: {}", e),
+        }
+    }
+
+    // Open and close maintenance windows (validated with the config)
+    if file_config.maintenance.iter().any(|m| m.enabled) {
+        match vibemq::broker::MaintenanceScheduler::new(broker.clone(), &file_config.maintenance) {
+            Ok(scheduler) => {
+                info!("  Maintenance windows: {} enabled", scheduler.len());
+                for window in file_config.maintenance.iter().filter(|m| m.enabled) {
+                    info!(
+                        "    - {} [{}] for {:?}",
+                        window.name, window.cron, window.duration
+                    );
+                }
+                tokio::spawn(scheduler.with_log_filter(maintenance_log_filter).run());
+            }
+            Err(e) => tracing::error!("Maintenance windows disabled: {}", e),
+        }
+    }
+
+    // Run aggregation windows (validated with the config)
+    for aggregate in file_config.aggregate.iter().filter(|a| a.enabled) {
+        match vibemq::aggregate::Aggregation::new(aggregate) {
+            Ok(aggregation) => {
+                info!(
+                    "  Aggregate: {} ({} of {} over {:?}) -> {}",
+                    aggregate.name,
+                    aggregate.function.as_str(),
+                    aggregate.filter,
+                    aggregate.window,
+                    aggregate.output
+                );
+                tokio::spawn(aggregation.run(broker.clone()));
+            }
+            Err(e) => tracing::error!("Aggregate '{}' disabled: {}", aggregate.name, e),
+        }
+    }
+
+    // Run geofences (validated with the config)
+    for geofence in file_config.geofence.iter().filter(|g| g.enabled) {
+        match vibemq::geofence::Geofence::new(geofence) {
+            Ok(fence) => {
+                info!(
+                    "  Geofence: {} ({} vertices, {}) -> {}",
+                    geofence.name,
+                    geofence.polygon.len(),
+                    geofence.filter,
+                    geofence.output
+                );
+                tokio::spawn(fence.run(broker.clone()));
+            }
+            Err(e) => tracing::error!("Geofence '{}' disabled: {}", geofence.name, e),
+        }
+    }
+
+    // Run enrichment rules (validated with the config)
+    for enrich in file_config.enrich.iter().filter(|e| e.enabled) {
+        match vibemq::enrich::Enrichment::new(enrich) {
+            Ok(enrichment) => {
+                info!(
+                    "  Enrich: {} ({}) -> {}",
+                    enrich.name, enrich.filter, enrich.output
+                );
+                tokio::spawn(enrichment.run(broker.clone()));
+            }
+            Err(e) => tracing::error!("Enrich '{}' disabled: {}", enrich.name, e),
+        }
+    }
+
+    // Run routing rules (validated with the config)
+    for rule_config in file_config.rule.iter().filter(|r| r.enabled) {
+        match vibemq::rules::Rule::new(rule_config) {
+            Ok(rule) => {
+                info!(
+                    "  Rule: {} ({}) -> {}",
+                    rule_config.name,
+                    rule_config.filter,
+                    rule.actions().collect::<Vec<_>>().join(", ")
+                );
+                tokio::spawn(rule.run(broker.clone()));
+            }
+            Err(e) => tracing::error!("Rule '{}' disabled: {}", rule_config.name, e),
+        }
+    }
+
     // Run the broker (it handles Ctrl+C internally via the shutdown signal)
     let result = broker.run().await;
 
@@ -586,3 +1593,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     result?;
     Ok(())
 }
+
+/// Reload the configuration on SIGHUP
+///
+/// If the config file (or an ACL rule or password file it names) fails to
+/// load, the current configuration stays in place.
+#[cfg(unix)]
+fn spawn_config_reload(reloader: Arc<ConfigReloader>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            tracing::warn!("Cannot listen for SIGHUP, config reload disabled: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            if let Err(e) = reloader.reload().await {
+                tracing::error!("Config reload failed, keeping current configuration: {}", e);
+            }
+        }
+    });
+}
@@ -2,14 +2,26 @@
 //!
 //! Exposes metrics at /metrics endpoint for monitoring and observability.
 //! Useful for Grafana dashboards, alerts, and capacity planning.
+//! In a cluster with `[cluster.metrics]` enabled, the leader also serves
+//! every node's metrics at /cluster/metrics (see `cluster::metrics`).
+//!
+//! `Metrics::registry` is the crate-wide registry: other subsystems can add
+//! their own collectors with `Metrics::register`, and both the metrics
+//! server and the profiling server export everything in it.
+
+use std::time::Instant;
 
+use prometheus::core::Collector;
 use prometheus::{
-    Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    Encoder, Gauge, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
 };
 
+use crate::protocol::QoS;
+
 mod server;
 
-pub use server::MetricsServer;
+pub use server::{metrics_response, MetricsServer};
 
 /// All VibeMQ metrics in one place
 #[derive(Clone)]
@@ -21,9 +33,19 @@ pub struct Metrics {
     pub connections_current: IntGauge,
     pub connections_maximum: IntGauge,
     pub connections_by_protocol: IntGaugeVec,
+    pub connections_hibernated: IntGauge,
+    pub hibernations_total: IntCounter,
+
+    // TLS handshake pool metrics
+    pub tls_handshake_queue_depth: IntGauge,
+    pub tls_handshakes_rejected: IntCounter,
+    pub tls_handshake_duration: Histogram,
 
     // Session metrics
+    pub sessions_current: IntGauge,
     pub sessions_expired_total: IntCounter,
+    pub session_checkpoints_total: IntCounter,
+    pub session_checkpoint_duration: Histogram,
 
     // Message metrics (all packet types)
     pub messages_total_received: IntCounter,
@@ -39,20 +61,38 @@ pub struct Metrics {
     pub publish_messages_received: IntCounter,
     pub publish_messages_sent: IntCounter,
     pub publish_messages_dropped: IntCounter,
+    pub messages_dropped_total: IntCounterVec,
+    pub publish_received_per_second: Gauge,
+    pub publish_sent_per_second: Gauge,
+
+    // Batch frame metrics
+    pub batches_received: IntCounter,
+    pub batch_messages_received: IntCounter,
+    pub batches_rejected: IntCounter,
+    pub publishes_rate_limited: IntCounter,
+    pub rate_limit_fallbacks: IntCounter,
+    pub quota_exceeded_total: IntCounterVec,
+    pub publisher_backpressure_total: IntCounterVec,
+    pub schedule_fired_total: IntCounterVec,
+    pub rule_actions_total: IntCounterVec,
 
     // Subscription metrics
     pub subscriptions_current: IntGauge,
     pub subscriptions_total: IntCounter,
     pub unsubscriptions_total: IntCounter,
+    pub subscriptions_revoked: IntCounter,
 
     // Retained messages
     pub retained_messages_current: IntGauge,
     pub retained_bytes_current: IntGauge,
+    pub retained_frame_cache_hits: IntCounter,
+    pub retained_frame_cache_misses: IntCounter,
 
     // QoS metrics
     pub inflight_messages: IntGaugeVec,
     pub qos1_retransmits: IntCounter,
     pub qos2_retransmits: IntCounter,
+    pub ack_stalls_total: IntCounter,
 
     // Cluster metrics
     pub cluster_peers_current: IntGauge,
@@ -65,6 +105,7 @@ pub struct Metrics {
 
     // DoS protection metrics
     pub connections_rejected_total: IntCounterVec,
+    pub proxy_protocol_errors_total: IntCounterVec,
     pub ips_banned_current: IntGauge,
     pub ips_tracked_current: IntGauge,
 }
@@ -101,13 +142,72 @@ impl Metrics {
         ))
         .unwrap();
 
+        let connections_hibernated = IntGauge::with_opts(Opts::new(
+            "vibemq_connections_hibernated",
+            "Idle connections currently hibernated (buffers released)",
+        ))
+        .unwrap();
+
+        let hibernations_total = IntCounter::with_opts(Opts::new(
+            "vibemq_hibernations_total",
+            "Total times an idle connection was hibernated",
+        ))
+        .unwrap();
+
+        // TLS handshake pool metrics
+        let tls_handshake_queue_depth = IntGauge::with_opts(Opts::new(
+            "vibemq_tls_handshake_queue_depth",
+            "TLS handshakes queued or in progress on the handshake pool",
+        ))
+        .unwrap();
+
+        let tls_handshakes_rejected = IntCounter::with_opts(Opts::new(
+            "vibemq_tls_handshakes_rejected_total",
+            "TLS connections dropped because the handshake queue was full",
+        ))
+        .unwrap();
+
+        let tls_handshake_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "vibemq_tls_handshake_duration_seconds",
+                "Time from queueing a TLS handshake to completion",
+            )
+            .buckets(vec![
+                0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+            ]),
+        )
+        .unwrap();
+
         // Session metrics
+        let sessions_current = IntGauge::with_opts(Opts::new(
+            "vibemq_sessions_current",
+            "Current number of sessions (connected and persisted)",
+        ))
+        .unwrap();
+
         let sessions_expired_total = IntCounter::with_opts(Opts::new(
             "vibemq_sessions_expired_total",
             "Total sessions expired since startup",
         ))
         .unwrap();
 
+        let session_checkpoints_total = IntCounter::with_opts(Opts::new(
+            "vibemq_session_checkpoints_total",
+            "Total session state checkpoints written to storage",
+        ))
+        .unwrap();
+
+        let session_checkpoint_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "vibemq_session_checkpoint_duration_seconds",
+                "Time to write a storage batch carrying session checkpoints",
+            )
+            .buckets(vec![
+                0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0,
+            ]),
+        )
+        .unwrap();
+
         // Message metrics (all packet types)
         let messages_total_received = IntCounter::with_opts(Opts::new(
             "vibemq_messages_total_received",
@@ -140,6 +240,27 @@ impl Metrics {
         ))
         .unwrap();
 
+        let messages_dropped_total = IntCounterVec::new(
+            Opts::new(
+                "vibemq_messages_dropped_total",
+                "Total messages dropped by reason",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+
+        let publish_received_per_second = Gauge::with_opts(Opts::new(
+            "vibemq_publish_received_per_second",
+            "PUBLISH packets received per second over the last sample interval",
+        ))
+        .unwrap();
+
+        let publish_sent_per_second = Gauge::with_opts(Opts::new(
+            "vibemq_publish_sent_per_second",
+            "PUBLISH packets sent per second over the last sample interval",
+        ))
+        .unwrap();
+
         // Message metrics (by type, for Prometheus labels)
         let messages_received_total = IntCounterVec::new(
             Opts::new(
@@ -168,6 +289,73 @@ impl Metrics {
         ))
         .unwrap();
 
+        // Batch frame metrics
+        let batches_received = IntCounter::with_opts(Opts::new(
+            "vibemq_batches_received_total",
+            "Total batch frames unpacked",
+        ))
+        .unwrap();
+
+        let batch_messages_received = IntCounter::with_opts(Opts::new(
+            "vibemq_batch_messages_received_total",
+            "Total messages unpacked from batch frames",
+        ))
+        .unwrap();
+
+        let batches_rejected = IntCounter::with_opts(Opts::new(
+            "vibemq_batches_rejected_total",
+            "Total batch frames rejected (malformed or over the size limit)",
+        ))
+        .unwrap();
+
+        let publishes_rate_limited = IntCounter::with_opts(Opts::new(
+            "vibemq_publishes_rate_limited_total",
+            "Total publishes rejected by the per-identity publish rate limit",
+        ))
+        .unwrap();
+
+        let rate_limit_fallbacks = IntCounter::with_opts(Opts::new(
+            "vibemq_rate_limit_fallbacks_total",
+            "Total external rate limit checks that failed or timed out and used local buckets",
+        ))
+        .unwrap();
+
+        let quota_exceeded_total = IntCounterVec::new(
+            Opts::new(
+                "vibemq_quota_exceeded_total",
+                "Total clients disconnected for exceeding a quota, by limit",
+            ),
+            &["limit"],
+        )
+        .unwrap();
+
+        let publisher_backpressure_total = IntCounterVec::new(
+            Opts::new(
+                "vibemq_publisher_backpressure_total",
+                "Total MQTT v5 publishes held or rejected for congested subscribers, by action",
+            ),
+            &["action"],
+        )
+        .unwrap();
+
+        let schedule_fired_total = IntCounterVec::new(
+            Opts::new(
+                "vibemq_schedule_fired_total",
+                "Total messages published by scheduled publishes, by schedule",
+            ),
+            &["schedule"],
+        )
+        .unwrap();
+
+        let rule_actions_total = IntCounterVec::new(
+            Opts::new(
+                "vibemq_rule_actions_total",
+                "Total messages handled by rule actions, by rule, action and result",
+            ),
+            &["rule", "action", "result"],
+        )
+        .unwrap();
+
         // Subscription metrics
         let subscriptions_current = IntGauge::with_opts(Opts::new(
             "vibemq_subscriptions_current",
@@ -187,6 +375,12 @@ impl Metrics {
         ))
         .unwrap();
 
+        let subscriptions_revoked = IntCounter::with_opts(Opts::new(
+            "vibemq_subscriptions_revoked_total",
+            "Total subscriptions removed because an ACL change revoked them",
+        ))
+        .unwrap();
+
         // Retained messages
         let retained_messages_current = IntGauge::with_opts(Opts::new(
             "vibemq_retained_messages_current",
@@ -200,6 +394,18 @@ impl Metrics {
         ))
         .unwrap();
 
+        let retained_frame_cache_hits = IntCounter::with_opts(Opts::new(
+            "vibemq_retained_frame_cache_hits_total",
+            "Retained messages delivered from a cached frame",
+        ))
+        .unwrap();
+
+        let retained_frame_cache_misses = IntCounter::with_opts(Opts::new(
+            "vibemq_retained_frame_cache_misses_total",
+            "Retained messages encoded because no cached frame matched",
+        ))
+        .unwrap();
+
         // QoS metrics
         let inflight_messages = IntGaugeVec::new(
             Opts::new(
@@ -222,6 +428,12 @@ impl Metrics {
         ))
         .unwrap();
 
+        let ack_stalls_total = IntCounter::with_opts(Opts::new(
+            "vibemq_ack_stalls_total",
+            "Times a live client left a message unacknowledged past limits.ack_alarm_after",
+        ))
+        .unwrap();
+
         // Cluster metrics
         let cluster_peers_current = IntGauge::with_opts(Opts::new(
             "vibemq_cluster_peers_current",
@@ -274,6 +486,15 @@ impl Metrics {
         )
         .unwrap();
 
+        let proxy_protocol_errors_total = IntCounterVec::new(
+            Opts::new(
+                "vibemq_proxy_protocol_errors_total",
+                "Total connections dropped because the PROXY header could not be parsed",
+            ),
+            &["listener"],
+        )
+        .unwrap();
+
         let ips_banned_current = IntGauge::with_opts(Opts::new(
             "vibemq_ips_banned_current",
             "Current number of IPs banned by flapping detection",
@@ -299,9 +520,33 @@ impl Metrics {
         registry
             .register(Box::new(connections_by_protocol.clone()))
             .unwrap();
+        registry
+            .register(Box::new(connections_hibernated.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(hibernations_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(tls_handshake_queue_depth.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(tls_handshakes_rejected.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(tls_handshake_duration.clone()))
+            .unwrap();
         registry
             .register(Box::new(sessions_expired_total.clone()))
             .unwrap();
+        registry
+            .register(Box::new(sessions_current.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(session_checkpoints_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(session_checkpoint_duration.clone()))
+            .unwrap();
         registry
             .register(Box::new(messages_total_received.clone()))
             .unwrap();
@@ -317,6 +562,42 @@ impl Metrics {
         registry
             .register(Box::new(publish_messages_dropped.clone()))
             .unwrap();
+        registry
+            .register(Box::new(messages_dropped_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(publish_received_per_second.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(publish_sent_per_second.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(batches_received.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(batch_messages_received.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(batches_rejected.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(publishes_rate_limited.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(rate_limit_fallbacks.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(quota_exceeded_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(publisher_backpressure_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(schedule_fired_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(rule_actions_total.clone()))
+            .unwrap();
         registry
             .register(Box::new(messages_received_total.clone()))
             .unwrap();
@@ -338,12 +619,21 @@ impl Metrics {
         registry
             .register(Box::new(unsubscriptions_total.clone()))
             .unwrap();
+        registry
+            .register(Box::new(subscriptions_revoked.clone()))
+            .unwrap();
         registry
             .register(Box::new(retained_messages_current.clone()))
             .unwrap();
         registry
             .register(Box::new(retained_bytes_current.clone()))
             .unwrap();
+        registry
+            .register(Box::new(retained_frame_cache_hits.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(retained_frame_cache_misses.clone()))
+            .unwrap();
         registry
             .register(Box::new(inflight_messages.clone()))
             .unwrap();
@@ -353,6 +643,9 @@ impl Metrics {
         registry
             .register(Box::new(qos2_retransmits.clone()))
             .unwrap();
+        registry
+            .register(Box::new(ack_stalls_total.clone()))
+            .unwrap();
         registry
             .register(Box::new(cluster_peers_current.clone()))
             .unwrap();
@@ -371,6 +664,9 @@ impl Metrics {
         registry
             .register(Box::new(connections_rejected_total.clone()))
             .unwrap();
+        registry
+            .register(Box::new(proxy_protocol_errors_total.clone()))
+            .unwrap();
         registry
             .register(Box::new(ips_banned_current.clone()))
             .unwrap();
@@ -384,7 +680,15 @@ impl Metrics {
             connections_current,
             connections_maximum,
             connections_by_protocol,
+            connections_hibernated,
+            hibernations_total,
+            tls_handshake_queue_depth,
+            tls_handshakes_rejected,
+            tls_handshake_duration,
+            sessions_current,
             sessions_expired_total,
+            session_checkpoints_total,
+            session_checkpoint_duration,
             messages_total_received,
             messages_total_sent,
             messages_received_total,
@@ -394,25 +698,54 @@ impl Metrics {
             publish_messages_received,
             publish_messages_sent,
             publish_messages_dropped,
+            messages_dropped_total,
+            publish_received_per_second,
+            publish_sent_per_second,
+            batches_received,
+            batch_messages_received,
+            batches_rejected,
+            publishes_rate_limited,
+            rate_limit_fallbacks,
+            quota_exceeded_total,
+            publisher_backpressure_total,
+            schedule_fired_total,
+            rule_actions_total,
             subscriptions_current,
             subscriptions_total,
             unsubscriptions_total,
+            subscriptions_revoked,
             retained_messages_current,
             retained_bytes_current,
+            retained_frame_cache_hits,
+            retained_frame_cache_misses,
             inflight_messages,
             qos1_retransmits,
             qos2_retransmits,
+            ack_stalls_total,
             cluster_peers_current,
             cluster_messages_forwarded,
             cluster_messages_received,
             publish_latency,
             connect_duration,
             connections_rejected_total,
+            proxy_protocol_errors_total,
             ips_banned_current,
             ips_tracked_current,
         }
     }
 
+    /// Add a collector to the registry (e.g. a subsystem's own metrics)
+    pub fn register(&self, collector: Box<dyn Collector>) -> prometheus::Result<()> {
+        self.registry.register(collector)
+    }
+
+    /// Encode everything in the registry in the Prometheus text format
+    pub fn encode_text(&self) -> prometheus::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(buffer)
+    }
+
     // Helper methods for common operations
 
     pub fn client_connected(&self, protocol: &str) {
@@ -460,6 +793,10 @@ impl Metrics {
         self.unsubscriptions_total.inc();
     }
 
+    pub fn subscription_revoked(&self) {
+        self.subscriptions_revoked.inc();
+    }
+
     pub fn retained_message_stored(&self, bytes: usize) {
         self.retained_messages_current.inc();
         self.retained_bytes_current.add(bytes as i64);
@@ -470,6 +807,14 @@ impl Metrics {
         self.retained_bytes_current.sub(bytes as i64);
     }
 
+    pub fn retained_frame_hit(&self) {
+        self.retained_frame_cache_hits.inc();
+    }
+
+    pub fn retained_frame_miss(&self) {
+        self.retained_frame_cache_misses.inc();
+    }
+
     pub fn cluster_peer_connected(&self) {
         self.cluster_peers_current.inc();
     }
@@ -502,6 +847,59 @@ impl Metrics {
 
     pub fn publish_dropped(&self) {
         self.publish_messages_dropped.inc();
+        self.message_dropped("queue_full");
+    }
+
+    pub fn message_dropped(&self, reason: &str) {
+        self.messages_dropped_total
+            .with_label_values(&[reason])
+            .inc();
+    }
+
+    // Batch frame helpers
+
+    pub fn batch_received(&self, messages: usize) {
+        self.batches_received.inc();
+        self.batch_messages_received.inc_by(messages as u64);
+    }
+
+    pub fn batch_rejected(&self) {
+        self.batches_rejected.inc();
+    }
+
+    pub fn publish_rate_limited(&self) {
+        self.publishes_rate_limited.inc();
+        self.message_dropped("rate_limited");
+    }
+
+    pub fn rate_limit_fallback(&self) {
+        self.rate_limit_fallbacks.inc();
+    }
+
+    pub fn quota_exceeded(&self, limit: &str) {
+        self.quota_exceeded_total.with_label_values(&[limit]).inc();
+    }
+
+    /// A publish held ("held") or rejected ("rejected") for congested
+    /// subscribers
+    pub fn publisher_backpressure(&self, action: &str) {
+        self.publisher_backpressure_total
+            .with_label_values(&[action])
+            .inc();
+    }
+
+    pub fn schedule_fired(&self, schedule: &str) {
+        self.schedule_fired_total
+            .with_label_values(&[schedule])
+            .inc();
+    }
+
+    /// `result` is "success", "failure" (an attempt failed),
+    /// "dead_lettered" or "dropped"
+    pub fn rule_action(&self, rule: &str, action: &str, result: &str) {
+        self.rule_actions_total
+            .with_label_values(&[rule, action, result])
+            .inc();
     }
 
     // Packet helpers (for total message counts)
@@ -520,6 +918,57 @@ impl Metrics {
         self.sessions_expired_total.inc();
     }
 
+    pub fn update_session_count(&self, sessions: usize) {
+        self.sessions_current.set(sessions as i64);
+    }
+
+    // QoS helpers
+
+    pub fn message_retransmitted(&self, qos: QoS) {
+        match qos {
+            QoS::ExactlyOnce => self.qos2_retransmits.inc(),
+            _ => self.qos1_retransmits.inc(),
+        }
+    }
+
+    pub fn ack_stalled(&self) {
+        self.ack_stalls_total.inc();
+    }
+
+    // Hibernation helpers
+
+    pub fn connection_hibernated(&self) {
+        self.connections_hibernated.inc();
+        self.hibernations_total.inc();
+    }
+
+    pub fn connection_woken(&self) {
+        self.connections_hibernated.dec();
+    }
+
+    // TLS handshake pool helpers
+    pub fn tls_handshake_queued(&self) {
+        self.tls_handshake_queue_depth.inc();
+    }
+
+    pub fn tls_handshake_finished(&self, duration: std::time::Duration) {
+        self.tls_handshake_queue_depth.dec();
+        self.tls_handshake_duration.observe(duration.as_secs_f64());
+    }
+
+    pub fn tls_handshake_rejected(&self) {
+        self.tls_handshakes_rejected.inc();
+    }
+
+    pub fn session_checkpointed(&self) {
+        self.session_checkpoints_total.inc();
+    }
+
+    pub fn session_checkpoint_written(&self, duration: std::time::Duration) {
+        self.session_checkpoint_duration
+            .observe(duration.as_secs_f64());
+    }
+
     // DoS protection helpers
 
     pub fn connection_rejected(&self, reason: &str) {
@@ -528,6 +977,12 @@ impl Metrics {
             .inc();
     }
 
+    pub fn proxy_protocol_error(&self, listener: &str) {
+        self.proxy_protocol_errors_total
+            .with_label_values(&[listener])
+            .inc();
+    }
+
     pub fn update_flapping_stats(&self, banned_ips: usize, tracked_ips: usize) {
         self.ips_banned_current.set(banned_ips as i64);
         self.ips_tracked_current.set(tracked_ips as i64);
@@ -539,3 +994,74 @@ impl Default for Metrics {
         Self::new()
     }
 }
+
+/// Turns the PUBLISH counters into per-second gauges between samples
+pub struct ThroughputSampler {
+    last_sample: Instant,
+    received: u64,
+    sent: u64,
+}
+
+impl ThroughputSampler {
+    pub fn new(metrics: &Metrics) -> Self {
+        Self {
+            last_sample: Instant::now(),
+            received: metrics.publish_messages_received.get(),
+            sent: metrics.publish_messages_sent.get(),
+        }
+    }
+
+    /// Update the per-second gauges from the counters since the last sample
+    pub fn sample(&mut self, metrics: &Metrics) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_sample).as_secs_f64();
+        if elapsed <= 0.0 {
+            return;
+        }
+        let received = metrics.publish_messages_received.get();
+        let sent = metrics.publish_messages_sent.get();
+        metrics
+            .publish_received_per_second
+            .set(received.saturating_sub(self.received) as f64 / elapsed);
+        metrics
+            .publish_sent_per_second
+            .set(sent.saturating_sub(self.sent) as f64 / elapsed);
+        self.last_sample = now;
+        self.received = received;
+        self.sent = sent;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_text_includes_registered_collectors() {
+        let metrics = Metrics::new();
+        metrics.publish_dropped();
+        metrics.proxy_protocol_error("tcp");
+
+        let custom = IntCounter::new("vibemq_custom_total", "A subsystem counter").unwrap();
+        metrics.register(Box::new(custom.clone())).unwrap();
+        custom.inc();
+
+        let text = String::from_utf8(metrics.encode_text().unwrap()).unwrap();
+        assert!(text.contains("vibemq_messages_dropped_total{reason=\"queue_full\"} 1"));
+        assert!(text.contains("vibemq_proxy_protocol_errors_total{listener=\"tcp\"} 1"));
+        assert!(text.contains("vibemq_custom_total 1"));
+        assert!(text.contains("vibemq_sessions_current 0"));
+    }
+
+    #[test]
+    fn test_throughput_sampler() {
+        let metrics = Metrics::new();
+        let mut sampler = ThroughputSampler::new(&metrics);
+        metrics.publish_received(10);
+        metrics.publish_received(10);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        sampler.sample(&metrics);
+        assert!(metrics.publish_received_per_second.get() > 0.0);
+        assert_eq!(metrics.publish_sent_per_second.get(), 0.0);
+    }
+}
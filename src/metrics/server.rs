@@ -1,6 +1,7 @@
 //! HTTP server for Prometheus metrics endpoint
 
 use super::Metrics;
+use crate::broker::{health_response, readiness_response, Broker};
 use http_body_util::Full;
 use hyper::body::Bytes;
 use hyper::server::conn::http1;
@@ -18,11 +19,23 @@ use tracing::{error, info};
 pub struct MetricsServer {
     metrics: Arc<Metrics>,
     addr: SocketAddr,
+    broker: Option<Arc<Broker>>,
 }
 
 impl MetricsServer {
     pub fn new(metrics: Arc<Metrics>, addr: SocketAddr) -> Self {
-        Self { metrics, addr }
+        Self {
+            metrics,
+            addr,
+            broker: None,
+        }
+    }
+
+    /// Answer `/readyz` from the broker's readiness checks (without a
+    /// broker it always passes)
+    pub fn with_health(mut self, broker: Arc<Broker>) -> Self {
+        self.broker = Some(broker);
+        self
     }
 
     pub async fn run(self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -33,11 +46,13 @@ impl MetricsServer {
             let (stream, _) = listener.accept().await?;
             let io = TokioIo::new(stream);
             let metrics = self.metrics.clone();
+            let broker = self.broker.clone();
 
             tokio::spawn(async move {
                 let service = service_fn(move |req| {
                     let metrics = metrics.clone();
-                    async move { handle_request(req, metrics).await }
+                    let broker = broker.clone();
+                    async move { handle_request(req, metrics, broker).await }
                 });
 
                 if let Err(err) = http1::Builder::new().serve_connection(io, service).await {
@@ -51,36 +66,16 @@ impl MetricsServer {
 async fn handle_request(
     req: Request<hyper::body::Incoming>,
     metrics: Arc<Metrics>,
+    broker: Option<Arc<Broker>>,
 ) -> Result<Response<Full<Bytes>>, Infallible> {
     let response = match req.uri().path() {
-        "/metrics" => {
-            let encoder = TextEncoder::new();
-            let metric_families = metrics.registry.gather();
-            let mut buffer = Vec::new();
-
-            match encoder.encode(&metric_families, &mut buffer) {
-                Ok(_) => Response::builder()
-                    .status(StatusCode::OK)
-                    .header("Content-Type", encoder.format_type())
-                    .body(Full::new(Bytes::from(buffer)))
-                    .unwrap(),
-                Err(e) => {
-                    error!("Failed to encode metrics: {}", e);
-                    Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(Full::new(Bytes::from("Failed to encode metrics")))
-                        .unwrap()
-                }
-            }
-        }
-        "/health" | "/healthz" => Response::builder()
-            .status(StatusCode::OK)
-            .body(Full::new(Bytes::from("OK")))
-            .unwrap(),
-        "/ready" | "/readyz" => Response::builder()
-            .status(StatusCode::OK)
-            .body(Full::new(Bytes::from("OK")))
-            .unwrap(),
+        "/metrics" => metrics_response(&metrics),
+        "/cluster/metrics" => cluster_metrics_response(&metrics, broker.as_deref()),
+        "/health" | "/healthz" => health_response(),
+        "/ready" | "/readyz" => match broker {
+            Some(ref broker) => readiness_response(broker),
+            None => health_response(),
+        },
         _ => Response::builder()
             .status(StatusCode::NOT_FOUND)
             .body(Full::new(Bytes::from("Not Found")))
@@ -89,3 +84,64 @@ async fn handle_request(
 
     Ok(response)
 }
+
+/// Federated metrics of the whole cluster, served by the leader (see
+/// `cluster::metrics`)
+fn cluster_metrics_response(metrics: &Metrics, broker: Option<&Broker>) -> Response<Full<Bytes>> {
+    let Some(cluster) = broker
+        .and_then(|b| b.cluster_manager())
+        .filter(|c| c.metrics_report_interval().is_some())
+    else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Full::new(Bytes::from(
+                "Cluster metrics federation is disabled",
+            )))
+            .unwrap();
+    };
+    if !cluster.is_leader() {
+        let leader = cluster.leader_id().unwrap_or_default();
+        return Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header("X-VibeMQ-Cluster-Leader", leader.as_str())
+            .body(Full::new(Bytes::from(format!(
+                "Not the cluster leader; the leader is '{}'",
+                leader
+            ))))
+            .unwrap();
+    }
+
+    let mut buffer = Vec::new();
+    match TextEncoder::new().encode(&cluster.federated_metrics(metrics), &mut buffer) {
+        Ok(()) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", prometheus::TEXT_FORMAT)
+            .body(Full::new(Bytes::from(buffer)))
+            .unwrap(),
+        Err(e) => {
+            error!("Failed to encode cluster metrics: {}", e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Full::new(Bytes::from("Failed to encode metrics")))
+                .unwrap()
+        }
+    }
+}
+
+/// Prometheus text exposition of the registry (also served by the profiler)
+pub fn metrics_response(metrics: &Metrics) -> Response<Full<Bytes>> {
+    match metrics.encode_text() {
+        Ok(buffer) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", prometheus::TEXT_FORMAT)
+            .body(Full::new(Bytes::from(buffer)))
+            .unwrap(),
+        Err(e) => {
+            error!("Failed to encode metrics: {}", e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Full::new(Bytes::from("Failed to encode metrics")))
+                .unwrap()
+        }
+    }
+}
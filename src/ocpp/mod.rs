@@ -0,0 +1,364 @@
+//! OCPP-over-MQTT Topic Conventions
+//!
+//! Optional helper for EV-charging fleets that carry OCPP-J messages over MQTT.
+//! Each charge point has an uplink topic (charge point to CSMS) and a downlink
+//! topic (CSMS to charge point), both derived from templates containing a
+//! `{cp_id}` level:
+//!
+//! - Charge-point ID extraction from topics and subscription filters
+//! - Response topic templating (a reply to an uplink message goes downlink, and vice versa)
+//! - Identity enforcement: a charge point may only use its own topics
+//! - Per-charge-point presence tracking
+
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+
+use crate::config::OcppConfig;
+use crate::hooks::{HookResult, Hooks};
+use crate::protocol::QoS;
+
+#[cfg(test)]
+mod tests;
+
+/// Placeholder for the charge-point ID in topic templates
+pub const CHARGE_POINT_PLACEHOLDER: &str = "{cp_id}";
+
+/// Direction of an OCPP topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OcppDirection {
+    /// Charge point to CSMS
+    Uplink,
+    /// CSMS to charge point
+    Downlink,
+}
+
+/// Charge points addressed by a subscription filter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterTarget<'a> {
+    /// A single charge point
+    One(&'a str),
+    /// Any charge point (wildcard at the charge-point level)
+    Any,
+}
+
+/// A topic template with a single `{cp_id}` level
+#[derive(Debug, Clone)]
+pub struct TopicTemplate {
+    levels: Vec<String>,
+    cp_index: usize,
+}
+
+impl TopicTemplate {
+    /// Parse a template such as "ocpp/{cp_id}/up"
+    pub fn parse(template: &str) -> Result<Self, String> {
+        if template.contains(['+', '#']) {
+            return Err(format!(
+                "template '{}' must not contain wildcards",
+                template
+            ));
+        }
+        let levels: Vec<String> = template.split('/').map(String::from).collect();
+        let mut positions = levels
+            .iter()
+            .enumerate()
+            .filter(|(_, level)| level.contains(CHARGE_POINT_PLACEHOLDER));
+        let cp_index = match (positions.next(), positions.next()) {
+            (Some((i, level)), None) if level == CHARGE_POINT_PLACEHOLDER => i,
+            (Some(_), None) => {
+                return Err(format!(
+                    "template '{}' must use {} as a whole topic level",
+                    template, CHARGE_POINT_PLACEHOLDER
+                ))
+            }
+            _ => {
+                return Err(format!(
+                    "template '{}' must contain {} exactly once",
+                    template, CHARGE_POINT_PLACEHOLDER
+                ))
+            }
+        };
+        Ok(Self { levels, cp_index })
+    }
+
+    /// Extract the charge-point ID from a topic name
+    pub fn match_topic<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        let mut cp_id = None;
+        let mut count = 0;
+        for (i, level) in topic.split('/').enumerate() {
+            match self.levels.get(i) {
+                Some(_) if i == self.cp_index => cp_id = Some(level),
+                Some(literal) if literal == level => {}
+                _ => return None,
+            }
+            count += 1;
+        }
+        if count != self.levels.len() {
+            return None;
+        }
+        cp_id.filter(|id| !id.is_empty())
+    }
+
+    /// Determine which charge points a subscription filter addresses
+    pub fn match_filter<'a>(&self, filter: &'a str) -> Option<FilterTarget<'a>> {
+        let mut target = None;
+        let mut count = 0;
+        for (i, level) in filter.split('/').enumerate() {
+            if level == "#" {
+                // Multi-level wildcard covers the charge-point level unless it came after it
+                return Some(target.unwrap_or(FilterTarget::Any));
+            }
+            match self.levels.get(i) {
+                Some(_) if i == self.cp_index => {
+                    target = Some(if level == "+" {
+                        FilterTarget::Any
+                    } else {
+                        FilterTarget::One(level)
+                    });
+                }
+                Some(literal) if level == "+" || literal == level => {}
+                _ => return None,
+            }
+            count += 1;
+        }
+        if count != self.levels.len() {
+            return None;
+        }
+        target
+    }
+
+    /// Render the topic for a charge point
+    pub fn render(&self, cp_id: &str) -> String {
+        let mut levels = self.levels.clone();
+        levels[self.cp_index] = cp_id.to_string();
+        levels.join("/")
+    }
+}
+
+/// Routes OCPP topics between charge points and the CSMS
+#[derive(Debug, Clone)]
+pub struct OcppRouter {
+    uplink: TopicTemplate,
+    downlink: TopicTemplate,
+}
+
+impl OcppRouter {
+    /// Create a router from configuration
+    pub fn new(config: &OcppConfig) -> Result<Self, String> {
+        Ok(Self {
+            uplink: TopicTemplate::parse(&config.uplink_topic)?,
+            downlink: TopicTemplate::parse(&config.downlink_topic)?,
+        })
+    }
+
+    /// Extract the charge-point ID and direction from a topic name
+    pub fn charge_point_id<'a>(&self, topic: &'a str) -> Option<(&'a str, OcppDirection)> {
+        if let Some(cp_id) = self.uplink.match_topic(topic) {
+            return Some((cp_id, OcppDirection::Uplink));
+        }
+        self.downlink
+            .match_topic(topic)
+            .map(|cp_id| (cp_id, OcppDirection::Downlink))
+    }
+
+    /// Topic on which a response to a message on `topic` should be published
+    pub fn response_topic(&self, topic: &str) -> Option<String> {
+        match self.charge_point_id(topic)? {
+            (cp_id, OcppDirection::Uplink) => Some(self.downlink.render(cp_id)),
+            (cp_id, OcppDirection::Downlink) => Some(self.uplink.render(cp_id)),
+        }
+    }
+
+    /// Uplink topic for a charge point
+    pub fn uplink_topic(&self, cp_id: &str) -> String {
+        self.uplink.render(cp_id)
+    }
+
+    /// Downlink topic for a charge point
+    pub fn downlink_topic(&self, cp_id: &str) -> String {
+        self.downlink.render(cp_id)
+    }
+}
+
+/// Presence of a charge point
+#[derive(Debug, Clone)]
+pub struct ChargePointPresence {
+    /// Client currently (or last) acting as the charge point
+    pub client_id: String,
+    /// Whether that client is still connected
+    pub online: bool,
+    /// When the charge point was first seen on this connection
+    pub connected_at: SystemTime,
+    /// Last uplink message or disconnect
+    pub last_seen: SystemTime,
+}
+
+/// OCPP hooks: topic validation and presence tracking
+pub struct OcppProvider {
+    router: OcppRouter,
+    enforce_identity: bool,
+    csms_clients: Vec<String>,
+    max_charge_point_id_len: usize,
+    /// Charge-point ID -> presence
+    presence: DashMap<String, ChargePointPresence>,
+    /// Client ID -> charge-point ID
+    clients: DashMap<String, String>,
+}
+
+impl OcppProvider {
+    /// Create a new OCPP provider from configuration
+    pub fn new(config: &OcppConfig) -> Result<Self, String> {
+        Ok(Self {
+            router: OcppRouter::new(config)?,
+            enforce_identity: config.enforce_identity,
+            csms_clients: config.csms_clients.clone(),
+            max_charge_point_id_len: config.max_charge_point_id_len,
+            presence: DashMap::new(),
+            clients: DashMap::new(),
+        })
+    }
+
+    /// Get the topic router
+    pub fn router(&self) -> &OcppRouter {
+        &self.router
+    }
+
+    /// Get the presence of a charge point
+    pub fn presence(&self, cp_id: &str) -> Option<ChargePointPresence> {
+        self.presence.get(cp_id).map(|p| p.clone())
+    }
+
+    /// IDs of charge points that are currently online
+    pub fn online_charge_points(&self) -> Vec<String> {
+        self.presence
+            .iter()
+            .filter(|p| p.online)
+            .map(|p| p.key().clone())
+            .collect()
+    }
+
+    fn is_csms(&self, client_id: &str) -> bool {
+        self.csms_clients.iter().any(|c| c == client_id)
+    }
+
+    fn valid_charge_point_id(&self, cp_id: &str) -> bool {
+        !cp_id.is_empty() && cp_id.len() <= self.max_charge_point_id_len
+    }
+
+    /// Whether a client may act as the given charge point
+    fn may_act_as(&self, client_id: &str, username: Option<&str>, cp_id: &str) -> bool {
+        !self.enforce_identity || cp_id == client_id || username == Some(cp_id)
+    }
+
+    /// Record that a client is acting as a charge point
+    fn mark_online(&self, client_id: &str, cp_id: &str) {
+        let now = SystemTime::now();
+        self.clients
+            .insert(client_id.to_string(), cp_id.to_string());
+        self.presence
+            .entry(cp_id.to_string())
+            .and_modify(|p| {
+                if !p.online || p.client_id != client_id {
+                    p.client_id = client_id.to_string();
+                    p.connected_at = now;
+                }
+                p.online = true;
+                p.last_seen = now;
+            })
+            .or_insert_with(|| ChargePointPresence {
+                client_id: client_id.to_string(),
+                online: true,
+                connected_at: now,
+                last_seen: now,
+            });
+    }
+}
+
+#[async_trait]
+impl Hooks for OcppProvider {
+    fn hook_name(&self) -> String {
+        "ocpp".to_string()
+    }
+
+    async fn on_publish_check(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        topic: &str,
+        _qos: QoS,
+        _retain: bool,
+    ) -> HookResult<bool> {
+        let Some((cp_id, direction)) = self.router.charge_point_id(topic) else {
+            return Ok(true);
+        };
+        if !self.valid_charge_point_id(cp_id) {
+            return Ok(false);
+        }
+        if self.is_csms(client_id) {
+            return Ok(true);
+        }
+
+        match direction {
+            OcppDirection::Uplink => {
+                if !self.may_act_as(client_id, username, cp_id) {
+                    return Ok(false);
+                }
+                self.mark_online(client_id, cp_id);
+                Ok(true)
+            }
+            OcppDirection::Downlink => Ok(self.csms_clients.is_empty()),
+        }
+    }
+
+    async fn on_subscribe_check(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        filter: &str,
+        _qos: QoS,
+    ) -> HookResult<bool> {
+        if self.is_csms(client_id) {
+            return Ok(true);
+        }
+
+        if let Some(target) = self.router.downlink.match_filter(filter) {
+            return Ok(match target {
+                FilterTarget::One(cp_id) => {
+                    let allowed = self.valid_charge_point_id(cp_id)
+                        && self.may_act_as(client_id, username, cp_id);
+                    if allowed {
+                        self.mark_online(client_id, cp_id);
+                    }
+                    allowed
+                }
+                FilterTarget::Any => !self.enforce_identity,
+            });
+        }
+        if self.router.uplink.match_filter(filter).is_some() {
+            return Ok(self.csms_clients.is_empty());
+        }
+        Ok(true)
+    }
+
+    async fn on_client_disconnected(&self, client_id: &str, _graceful: bool) {
+        let Some((_, cp_id)) = self.clients.remove(client_id) else {
+            return;
+        };
+        if let Some(mut p) = self.presence.get_mut(&cp_id) {
+            // A newer connection may have taken over the charge point
+            if p.client_id == client_id {
+                p.online = false;
+                p.last_seen = SystemTime::now();
+            }
+        }
+    }
+
+    async fn on_message_published(&self, topic: &str, _payload: &[u8], _qos: QoS) {
+        if let Some(cp_id) = self.router.uplink.match_topic(topic) {
+            if let Some(mut p) = self.presence.get_mut(cp_id) {
+                p.last_seen = SystemTime::now();
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use super::*;
+
+fn config() -> OcppConfig {
+    OcppConfig {
+        enabled: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_template_parse() {
+    assert!(TopicTemplate::parse("ocpp/{cp_id}/up").is_ok());
+    assert!(TopicTemplate::parse("{cp_id}").is_ok());
+    assert!(TopicTemplate::parse("ocpp/up").is_err());
+    assert!(TopicTemplate::parse("ocpp/{cp_id}/{cp_id}").is_err());
+    assert!(TopicTemplate::parse("ocpp/cp-{cp_id}/up").is_err());
+    assert!(TopicTemplate::parse("ocpp/+/{cp_id}").is_err());
+}
+
+#[test]
+fn test_charge_point_extraction_and_response_topic() {
+    let router = OcppRouter::new(&config()).unwrap();
+
+    assert_eq!(
+        router.charge_point_id("ocpp/CP001/up"),
+        Some(("CP001", OcppDirection::Uplink))
+    );
+    assert_eq!(
+        router.charge_point_id("ocpp/CP001/down"),
+        Some(("CP001", OcppDirection::Downlink))
+    );
+    assert_eq!(router.charge_point_id("ocpp/CP001"), None);
+    assert_eq!(router.charge_point_id("ocpp/CP001/up/extra"), None);
+    assert_eq!(router.charge_point_id("ocpp//up"), None);
+    assert_eq!(router.charge_point_id("other/CP001/up"), None);
+
+    assert_eq!(
+        router.response_topic("ocpp/CP001/up").as_deref(),
+        Some("ocpp/CP001/down")
+    );
+    assert_eq!(
+        router.response_topic("ocpp/CP001/down").as_deref(),
+        Some("ocpp/CP001/up")
+    );
+    assert_eq!(router.response_topic("sensors/temp"), None);
+}
+
+#[test]
+fn test_filter_target() {
+    let template = TopicTemplate::parse("ocpp/{cp_id}/down").unwrap();
+
+    assert_eq!(
+        template.match_filter("ocpp/CP001/down"),
+        Some(FilterTarget::One("CP001"))
+    );
+    assert_eq!(
+        template.match_filter("ocpp/CP001/#"),
+        Some(FilterTarget::One("CP001"))
+    );
+    assert_eq!(
+        template.match_filter("ocpp/+/down"),
+        Some(FilterTarget::Any)
+    );
+    assert_eq!(template.match_filter("ocpp/#"), Some(FilterTarget::Any));
+    assert_eq!(template.match_filter("#"), Some(FilterTarget::Any));
+    assert_eq!(
+        template.match_filter("+/CP001/+"),
+        Some(FilterTarget::One("CP001"))
+    );
+    assert_eq!(template.match_filter("ocpp/CP001/up"), None);
+    assert_eq!(template.match_filter("ocpp/+"), None);
+}
+
+#[tokio::test]
+async fn test_identity_enforcement() {
+    let provider = OcppProvider::new(&config()).unwrap();
+
+    // Charge point publishing on its own uplink
+    assert!(provider
+        .on_publish_check("CP001", None, "ocpp/CP001/up", QoS::AtLeastOnce, false)
+        .await
+        .unwrap());
+    // Spoofing another charge point
+    assert!(!provider
+        .on_publish_check("CP001", None, "ocpp/CP002/up", QoS::AtLeastOnce, false)
+        .await
+        .unwrap());
+    // Username may carry the identity
+    assert!(provider
+        .on_publish_check(
+            "random",
+            Some("CP002"),
+            "ocpp/CP002/up",
+            QoS::AtLeastOnce,
+            false
+        )
+        .await
+        .unwrap());
+
+    // Own downlink only; no wildcard eavesdropping
+    assert!(provider
+        .on_subscribe_check("CP001", None, "ocpp/CP001/down", QoS::AtLeastOnce)
+        .await
+        .unwrap());
+    assert!(!provider
+        .on_subscribe_check("CP001", None, "ocpp/CP002/down", QoS::AtLeastOnce)
+        .await
+        .unwrap());
+    assert!(!provider
+        .on_subscribe_check("CP001", None, "ocpp/+/down", QoS::AtLeastOnce)
+        .await
+        .unwrap());
+
+    // Unrelated topics are untouched
+    assert!(provider
+        .on_publish_check("CP001", None, "sensors/temp", QoS::AtMostOnce, false)
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn test_csms_clients() {
+    let provider = OcppProvider::new(&OcppConfig {
+        csms_clients: vec!["csms".to_string()],
+        ..config()
+    })
+    .unwrap();
+
+    assert!(provider
+        .on_subscribe_check("csms", None, "ocpp/+/up", QoS::AtLeastOnce)
+        .await
+        .unwrap());
+    assert!(provider
+        .on_publish_check("csms", None, "ocpp/CP001/down", QoS::AtLeastOnce, false)
+        .await
+        .unwrap());
+
+    // Only the CSMS may talk downlink or listen uplink
+    assert!(!provider
+        .on_publish_check("CP002", None, "ocpp/CP001/down", QoS::AtLeastOnce, false)
+        .await
+        .unwrap());
+    assert!(!provider
+        .on_subscribe_check("CP002", None, "ocpp/CP002/up", QoS::AtLeastOnce)
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn test_presence_tracking() {
+    let provider = OcppProvider::new(&config()).unwrap();
+    assert!(provider.presence("CP001").is_none());
+
+    provider
+        .on_subscribe_check("CP001", None, "ocpp/CP001/down", QoS::AtLeastOnce)
+        .await
+        .unwrap();
+    let presence = provider.presence("CP001").unwrap();
+    assert!(presence.online);
+    assert_eq!(presence.client_id, "CP001");
+    assert_eq!(provider.online_charge_points(), vec!["CP001".to_string()]);
+
+    // Unrelated client disconnecting doesn't affect the charge point
+    provider.on_client_disconnected("other", true).await;
+    assert!(provider.presence("CP001").unwrap().online);
+
+    provider.on_client_disconnected("CP001", false).await;
+    assert!(!provider.presence("CP001").unwrap().online);
+    assert!(provider.online_charge_points().is_empty());
+}
@@ -0,0 +1,273 @@
+//! Payload Formats
+//!
+//! Aggregations and geofences read fields out of message payloads. Besides
+//! JSON they understand the binary formats constrained devices tend to
+//! publish: CBOR, MessagePack, and Protobuf (given the compiled descriptor
+//! set of the messages). Every format is decoded into a JSON value, so
+//! dot-separated field paths work the same for all of them:
+//!
+//! - byte strings become base64 strings
+//! - CBOR tags are dropped, keeping the tagged value
+//! - Protobuf fields are named as in the `.proto` file, enums by their
+//!   value names; absent proto3 scalars read as their zero value
+//!
+//! Published results and events can be encoded as JSON, CBOR or
+//! MessagePack.
+
+pub mod msgpack;
+pub mod protobuf;
+
+use std::fmt;
+use std::sync::Arc;
+
+use base64ct::{Base64, Encoding};
+use serde_json::Value;
+
+use crate::config::{PayloadConfig, PayloadFormat};
+
+pub use protobuf::Schema;
+
+/// Why a payload config is invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayloadError {
+    /// `format = "protobuf"` without a descriptor or message
+    MissingSchema,
+    /// The descriptor set can't be read or parsed
+    InvalidDescriptor(String),
+    /// The message isn't in the descriptor set
+    UnknownMessage(String),
+    /// Outputs can't be encoded in this format
+    UnsupportedOutput(PayloadFormat),
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadError::MissingSchema => {
+                write!(f, "protobuf payloads need a descriptor and a message")
+            }
+            PayloadError::InvalidDescriptor(e) => write!(f, "invalid descriptor: {}", e),
+            PayloadError::UnknownMessage(name) => {
+                write!(f, "message '{}' is not in the descriptor set", name)
+            }
+            PayloadError::UnsupportedOutput(format) => {
+                write!(f, "output_format cannot be {}", format.as_str())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PayloadError {}
+
+/// Decodes payloads and encodes outputs as configured
+#[derive(Debug, Clone, Default)]
+pub struct PayloadCodec {
+    format: PayloadFormat,
+    output: PayloadFormat,
+    /// Descriptors and message type, for protobuf
+    schema: Option<(Arc<Schema>, String)>,
+}
+
+impl PayloadCodec {
+    pub fn new(config: &PayloadConfig) -> Result<Self, PayloadError> {
+        if config.output_format == PayloadFormat::Protobuf {
+            return Err(PayloadError::UnsupportedOutput(config.output_format));
+        }
+        let schema = match config.format {
+            PayloadFormat::Protobuf => {
+                let (Some(ref path), Some(ref message)) = (&config.descriptor, &config.message)
+                else {
+                    return Err(PayloadError::MissingSchema);
+                };
+                let bytes = std::fs::read(path).map_err(|e| {
+                    PayloadError::InvalidDescriptor(format!("{}: {}", path.display(), e))
+                })?;
+                Some(Self::schema(Schema::decode(&bytes)?, message)?)
+            }
+            _ => None,
+        };
+        Ok(Self {
+            format: config.format,
+            output: config.output_format,
+            schema,
+        })
+    }
+
+    fn schema(schema: Schema, message: &str) -> Result<(Arc<Schema>, String), PayloadError> {
+        let message = match message.strip_prefix('.') {
+            Some(_) => message.to_string(),
+            None => format!(".{}", message),
+        };
+        if !schema.contains(&message) {
+            return Err(PayloadError::UnknownMessage(message[1..].to_string()));
+        }
+        Ok((Arc::new(schema), message))
+    }
+
+    pub fn format(&self) -> PayloadFormat {
+        self.format
+    }
+
+    /// A payload as JSON (`None` if it isn't valid in the format)
+    pub fn decode(&self, payload: &[u8]) -> Option<Value> {
+        match self.format {
+            PayloadFormat::Json => serde_json::from_slice(payload).ok(),
+            PayloadFormat::Cbor => {
+                let value: ciborium::Value = ciborium::de::from_reader(payload).ok()?;
+                from_cbor(value)
+            }
+            PayloadFormat::Msgpack => msgpack::decode(payload),
+            PayloadFormat::Protobuf => {
+                let (ref schema, ref message) = *self.schema.as_ref()?;
+                schema.decode_message(message, payload)
+            }
+        }
+    }
+
+    /// An output in the output format
+    pub fn encode(&self, value: &Value) -> Vec<u8> {
+        match self.output {
+            PayloadFormat::Cbor => {
+                let mut buf = Vec::new();
+                // Writing to a Vec can't fail
+                let _ = ciborium::ser::into_writer(value, &mut buf);
+                buf
+            }
+            PayloadFormat::Msgpack => msgpack::encode(value),
+            // Protobuf outputs are rejected by new()
+            PayloadFormat::Json | PayloadFormat::Protobuf => value.to_string().into_bytes(),
+        }
+    }
+}
+
+/// Number at a field path of a decoded payload (numeric strings included)
+pub fn number(value: &Value, path: &[String]) -> Option<f64> {
+    match path.iter().try_fold(value, |v, key| v.get(key))? {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn from_cbor(value: ciborium::Value) -> Option<Value> {
+    use ciborium::Value as Cbor;
+    Some(match value {
+        Cbor::Integer(i) => {
+            let i = i128::from(i);
+            match (u64::try_from(i), i64::try_from(i)) {
+                (Ok(u), _) => u.into(),
+                (_, Ok(s)) => s.into(),
+                _ => (i as f64).into(),
+            }
+        }
+        Cbor::Float(f) => serde_json::Number::from_f64(f).map_or(Value::Null, Value::Number),
+        Cbor::Bytes(bytes) => Base64::encode_string(&bytes).into(),
+        Cbor::Text(text) => text.into(),
+        Cbor::Bool(b) => b.into(),
+        Cbor::Null => Value::Null,
+        Cbor::Tag(_, value) => from_cbor(*value)?,
+        Cbor::Array(values) => values
+            .into_iter()
+            .map(from_cbor)
+            .collect::<Option<Vec<_>>>()?
+            .into(),
+        Cbor::Map(entries) => {
+            let mut map = serde_json::Map::with_capacity(entries.len());
+            for (key, value) in entries {
+                let key = match key {
+                    Cbor::Text(text) => text,
+                    key => from_cbor(key)?.to_string(),
+                };
+                map.insert(key, from_cbor(value)?);
+            }
+            map.into()
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn codec(format: PayloadFormat, output_format: PayloadFormat) -> PayloadCodec {
+        PayloadCodec::new(&PayloadConfig {
+            format,
+            output_format,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cbor() {
+        let codec = codec(PayloadFormat::Cbor, PayloadFormat::Cbor);
+        let value = json!({"reading": {"temp": 21.5, "seq": 7}, "ok": true, "tags": ["a"]});
+        let encoded = codec.encode(&value);
+        assert_eq!(codec.decode(&encoded), Some(value.clone()));
+        assert_eq!(
+            number(
+                &codec.decode(&encoded).unwrap(),
+                &["reading".into(), "temp".into()]
+            ),
+            Some(21.5)
+        );
+
+        // Byte strings, tags and integer keys
+        let cbor = ciborium::Value::Map(vec![
+            (
+                ciborium::Value::Integer(1.into()),
+                ciborium::Value::Bytes(vec![0xde, 0xad]),
+            ),
+            (
+                ciborium::Value::Text("t".into()),
+                ciborium::Value::Tag(1, Box::new(ciborium::Value::Integer(1714566900.into()))),
+            ),
+        ]);
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&cbor, &mut buf).unwrap();
+        assert_eq!(
+            codec.decode(&buf),
+            Some(json!({"1": "3q0=", "t": 1714566900}))
+        );
+        assert_eq!(codec.decode(b"\xff\xff"), None);
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let value = json!({"key": "dev1", "value": 1.5});
+        let json = codec(PayloadFormat::Json, PayloadFormat::Json);
+        assert_eq!(json.encode(&value), value.to_string().into_bytes());
+        let msgpack = codec(PayloadFormat::Msgpack, PayloadFormat::Msgpack);
+        assert_eq!(msgpack.decode(&msgpack.encode(&value)), Some(value));
+    }
+
+    #[test]
+    fn test_invalid_configs() {
+        let new = |config: PayloadConfig| PayloadCodec::new(&config).unwrap_err();
+        assert_eq!(
+            new(PayloadConfig {
+                format: PayloadFormat::Protobuf,
+                ..Default::default()
+            }),
+            PayloadError::MissingSchema
+        );
+        assert_eq!(
+            new(PayloadConfig {
+                output_format: PayloadFormat::Protobuf,
+                ..Default::default()
+            }),
+            PayloadError::UnsupportedOutput(PayloadFormat::Protobuf)
+        );
+        assert!(matches!(
+            new(PayloadConfig {
+                format: PayloadFormat::Protobuf,
+                descriptor: Some("/nonexistent/telemetry.desc".into()),
+                message: Some("telemetry.Reading".to_string()),
+                ..Default::default()
+            }),
+            PayloadError::InvalidDescriptor(_)
+        ));
+    }
+}
@@ -0,0 +1,313 @@
+//! MessagePack
+//!
+//! Decoding to and encoding from JSON values. Binary and extension
+//! values decode to base64 strings of their data.
+
+use base64ct::{Base64, Encoding};
+use serde_json::{Map, Number, Value};
+
+/// Deepest nesting decoded
+const MAX_DEPTH: usize = 64;
+
+/// A complete MessagePack value (`None` if malformed or followed by more
+/// bytes)
+pub fn decode(payload: &[u8]) -> Option<Value> {
+    let mut reader = Reader { buf: payload };
+    let value = reader.value(0)?;
+    reader.buf.is_empty().then_some(value)
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < n {
+            return None;
+        }
+        let (taken, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Some(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    /// Big-endian length of `n` bytes
+    fn len(&mut self, n: usize) -> Option<usize> {
+        let bytes = self.take(n)?;
+        Some(bytes.iter().fold(0usize, |len, &b| (len << 8) | b as usize))
+    }
+
+    fn str(&mut self, len: usize) -> Option<Value> {
+        let bytes = self.take(len)?;
+        Some(std::str::from_utf8(bytes).ok()?.into())
+    }
+
+    fn bin(&mut self, len: usize) -> Option<Value> {
+        Some(Base64::encode_string(self.take(len)?).into())
+    }
+
+    /// Extension data (the type byte is skipped)
+    fn ext(&mut self, len: usize) -> Option<Value> {
+        self.take(1)?;
+        self.bin(len)
+    }
+
+    fn seq(&mut self, len: usize, depth: usize) -> Option<Value> {
+        // Every element takes at least a byte
+        if len > self.buf.len() {
+            return None;
+        }
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            values.push(self.value(depth + 1)?);
+        }
+        Some(values.into())
+    }
+
+    fn map(&mut self, len: usize, depth: usize) -> Option<Value> {
+        if len > self.buf.len() / 2 {
+            return None;
+        }
+        let mut map = Map::with_capacity(len);
+        for _ in 0..len {
+            let key = match self.value(depth + 1)? {
+                Value::String(key) => key,
+                key => key.to_string(),
+            };
+            map.insert(key, self.value(depth + 1)?);
+        }
+        Some(map.into())
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let float = |f: f64| Number::from_f64(f).map_or(Value::Null, Value::Number);
+        let marker = self.take(1)?[0];
+        Some(match marker {
+            0x00..=0x7f => marker.into(),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize, depth)?,
+            0x90..=0x9f => self.seq((marker & 0x0f) as usize, depth)?,
+            0xa0..=0xbf => self.str((marker & 0x1f) as usize)?,
+            0xc0 => Value::Null,
+            0xc2 => false.into(),
+            0xc3 => true.into(),
+            0xc4 => {
+                let len = self.len(1)?;
+                self.bin(len)?
+            }
+            0xc5 => {
+                let len = self.len(2)?;
+                self.bin(len)?
+            }
+            0xc6 => {
+                let len = self.len(4)?;
+                self.bin(len)?
+            }
+            0xc7 => {
+                let len = self.len(1)?;
+                self.ext(len)?
+            }
+            0xc8 => {
+                let len = self.len(2)?;
+                self.ext(len)?
+            }
+            0xc9 => {
+                let len = self.len(4)?;
+                self.ext(len)?
+            }
+            0xca => float(f32::from_be_bytes(self.array()?) as f64),
+            0xcb => float(f64::from_be_bytes(self.array()?)),
+            0xcc => self.take(1)?[0].into(),
+            0xcd => u16::from_be_bytes(self.array()?).into(),
+            0xce => u32::from_be_bytes(self.array()?).into(),
+            0xcf => u64::from_be_bytes(self.array()?).into(),
+            0xd0 => (self.take(1)?[0] as i8).into(),
+            0xd1 => i16::from_be_bytes(self.array()?).into(),
+            0xd2 => i32::from_be_bytes(self.array()?).into(),
+            0xd3 => i64::from_be_bytes(self.array()?).into(),
+            0xd4 => self.ext(1)?,
+            0xd5 => self.ext(2)?,
+            0xd6 => self.ext(4)?,
+            0xd7 => self.ext(8)?,
+            0xd8 => self.ext(16)?,
+            0xd9 => {
+                let len = self.len(1)?;
+                self.str(len)?
+            }
+            0xda => {
+                let len = self.len(2)?;
+                self.str(len)?
+            }
+            0xdb => {
+                let len = self.len(4)?;
+                self.str(len)?
+            }
+            0xdc => {
+                let len = self.len(2)?;
+                self.seq(len, depth)?
+            }
+            0xdd => {
+                let len = self.len(4)?;
+                self.seq(len, depth)?
+            }
+            0xde => {
+                let len = self.len(2)?;
+                self.map(len, depth)?
+            }
+            0xdf => {
+                let len = self.len(4)?;
+                self.map(len, depth)?
+            }
+            0xe0..=0xff => (marker as i8).into(),
+            // 0xc1 is never used
+            _ => return None,
+        })
+    }
+}
+
+/// A JSON value as MessagePack (integers and lengths in their smallest
+/// encoding)
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write(&mut buf, value);
+    buf
+}
+
+fn write(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => buf.push(0xc0),
+        Value::Bool(b) => buf.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                write_uint(buf, u);
+            } else if let Some(i) = n.as_i64() {
+                write_int(buf, i);
+            } else if let Some(f) = n.as_f64() {
+                buf.push(0xcb);
+                buf.extend_from_slice(&f.to_be_bytes());
+            }
+        }
+        Value::String(s) => {
+            let len = s.len();
+            match len {
+                0..=31 => buf.push(0xa0 | len as u8),
+                32..=0xff => buf.extend_from_slice(&[0xd9, len as u8]),
+                0x100..=0xffff => {
+                    buf.push(0xda);
+                    buf.extend_from_slice(&(len as u16).to_be_bytes());
+                }
+                _ => {
+                    buf.push(0xdb);
+                    buf.extend_from_slice(&(len as u32).to_be_bytes());
+                }
+            }
+            buf.extend_from_slice(s.as_bytes());
+        }
+        Value::Array(values) => {
+            write_len(buf, values.len(), 0x90, 0xdc);
+            for value in values {
+                write(buf, value);
+            }
+        }
+        Value::Object(map) => {
+            write_len(buf, map.len(), 0x80, 0xde);
+            for (key, value) in map {
+                write(buf, &Value::String(key.clone()));
+                write(buf, value);
+            }
+        }
+    }
+}
+
+/// Array or map header: fix form, then the 16- and 32-bit markers
+fn write_len(buf: &mut Vec<u8>, len: usize, fix: u8, marker16: u8) {
+    match len {
+        0..=15 => buf.push(fix | len as u8),
+        16..=0xffff => {
+            buf.push(marker16);
+            buf.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            buf.push(marker16 + 1);
+            buf.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+}
+
+fn write_uint(buf: &mut Vec<u8>, u: u64) {
+    match u {
+        0..=0x7f => buf.push(u as u8),
+        0x80..=0xff => buf.extend_from_slice(&[0xcc, u as u8]),
+        0x100..=0xffff => {
+            buf.push(0xcd);
+            buf.extend_from_slice(&(u as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            buf.push(0xce);
+            buf.extend_from_slice(&(u as u32).to_be_bytes());
+        }
+        _ => {
+            buf.push(0xcf);
+            buf.extend_from_slice(&u.to_be_bytes());
+        }
+    }
+}
+
+/// A negative integer
+fn write_int(buf: &mut Vec<u8>, i: i64) {
+    if i >= -32 {
+        buf.push(i as i8 as u8);
+    } else if i >= i8::MIN as i64 {
+        buf.extend_from_slice(&[0xd0, i as i8 as u8]);
+    } else if i >= i16::MIN as i64 {
+        buf.push(0xd1);
+        buf.extend_from_slice(&(i as i16).to_be_bytes());
+    } else if i >= i32::MIN as i64 {
+        buf.push(0xd2);
+        buf.extend_from_slice(&(i as i32).to_be_bytes());
+    } else {
+        buf.push(0xd3);
+        buf.extend_from_slice(&i.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let value = json!({
+            "small": 5, "byte": 200, "wide": 70000, "huge": u64::MAX,
+            "neg": -3, "neg8": -100, "neg32": -100000, "float": 21.5,
+            "text": "x".repeat(40), "list": [null, true, false], "empty": {},
+        });
+        assert_eq!(decode(&encode(&value)), Some(value));
+    }
+
+    #[test]
+    fn test_decode() {
+        // {"t": 21.5 (float32), "id": bin [1, 2]}
+        let payload = [
+            0x82, 0xa1, b't', 0xca, 0x41, 0xac, 0x00, 0x00, 0xa2, b'i', b'd', 0xc4, 0x02, 1, 2,
+        ];
+        assert_eq!(decode(&payload), Some(json!({"t": 21.5, "id": "AQI="})));
+        // Truncated, trailing bytes, huge claimed length, reserved marker
+        assert_eq!(decode(&payload[..payload.len() - 1]), None);
+        assert_eq!(decode(&[0x01, 0x02]), None);
+        assert_eq!(decode(&[0xdd, 0xff, 0xff, 0xff, 0xff]), None);
+        assert_eq!(decode(&[0xc1]), None);
+        // Nesting beyond the limit
+        let mut nested = vec![0x91; 100];
+        nested.push(0xc0);
+        assert_eq!(decode(&nested), None);
+        assert!(decode(&nested[90..]).is_some());
+    }
+}
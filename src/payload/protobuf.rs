@@ -0,0 +1,471 @@
+//! Protobuf
+//!
+//! Payloads are decoded against the messages of a compiled descriptor set
+//! (`protoc --include_imports --descriptor_set_out=...`), without
+//! generated code. Unknown fields are skipped; groups aren't supported.
+
+use std::collections::HashMap;
+
+use base64ct::{Base64, Encoding};
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use serde_json::{Map, Number, Value};
+
+use super::PayloadError;
+
+/// Deepest message nesting decoded
+const MAX_DEPTH: usize = 64;
+
+/// A message type with its file's syntax
+#[derive(Debug)]
+struct MessageType {
+    descriptor: DescriptorProto,
+    proto3: bool,
+}
+
+/// Message and enum types by fully qualified name (".package.Message")
+#[derive(Debug, Default)]
+pub struct Schema {
+    messages: HashMap<String, MessageType>,
+    enums: HashMap<String, EnumDescriptorProto>,
+}
+
+/// A field's encoded value
+enum Wire<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    Bytes(&'a [u8]),
+}
+
+impl Schema {
+    /// Parse a serialized `FileDescriptorSet`
+    pub fn decode(bytes: &[u8]) -> Result<Self, PayloadError> {
+        let set = FileDescriptorSet::decode(bytes)
+            .map_err(|e| PayloadError::InvalidDescriptor(e.to_string()))?;
+        Ok(Self::new(&set))
+    }
+
+    pub fn new(set: &FileDescriptorSet) -> Self {
+        let mut schema = Self::default();
+        for file in &set.file {
+            let prefix = match file.package() {
+                "" => String::new(),
+                package => format!(".{}", package),
+            };
+            let proto3 = file.syntax() == "proto3";
+            schema.add(&prefix, &file.message_type, &file.enum_type, proto3);
+        }
+        schema
+    }
+
+    fn add(
+        &mut self,
+        prefix: &str,
+        messages: &[DescriptorProto],
+        enums: &[EnumDescriptorProto],
+        proto3: bool,
+    ) {
+        for descriptor in enums {
+            self.enums.insert(
+                format!("{}.{}", prefix, descriptor.name()),
+                descriptor.clone(),
+            );
+        }
+        for descriptor in messages {
+            let name = format!("{}.{}", prefix, descriptor.name());
+            self.add(
+                &name,
+                &descriptor.nested_type,
+                &descriptor.enum_type,
+                proto3,
+            );
+            let descriptor = descriptor.clone();
+            self.messages
+                .insert(name, MessageType { descriptor, proto3 });
+        }
+    }
+
+    /// Whether the set has a message type (".package.Message")
+    pub fn contains(&self, message: &str) -> bool {
+        self.messages.contains_key(message)
+    }
+
+    /// A payload of a message type as a JSON object
+    pub fn decode_message(&self, message: &str, payload: &[u8]) -> Option<Value> {
+        self.message(message, payload, 0).map(Value::Object)
+    }
+
+    fn message(&self, name: &str, mut buf: &[u8], depth: usize) -> Option<Map<String, Value>> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let message = self.messages.get(name)?;
+        let mut fields = Map::new();
+        while !buf.is_empty() {
+            let key = varint(&mut buf)?;
+            let wire = match key & 7 {
+                0 => Wire::Varint(varint(&mut buf)?),
+                1 => Wire::Fixed64(u64::from_le_bytes(take(&mut buf)?)),
+                2 => {
+                    let len = usize::try_from(varint(&mut buf)?).ok()?;
+                    if len > buf.len() {
+                        return None;
+                    }
+                    let (bytes, rest) = buf.split_at(len);
+                    buf = rest;
+                    Wire::Bytes(bytes)
+                }
+                5 => Wire::Fixed32(u32::from_le_bytes(take(&mut buf)?)),
+                _ => return None,
+            };
+            let number = (key >> 3) as i32;
+            let Some(field) = message
+                .descriptor
+                .field
+                .iter()
+                .find(|f| f.number() == number)
+            else {
+                continue;
+            };
+            let values = self.values(field, wire, depth)?;
+            if field.label() != Label::Repeated {
+                if let Some(value) = values.into_iter().last() {
+                    fields.insert(field.name().to_string(), value);
+                }
+                continue;
+            }
+            if self.is_map(field) {
+                let entries = fields
+                    .entry(field.name())
+                    .or_insert_with(|| Map::new().into());
+                for entry in values {
+                    let key = match entry.get("key") {
+                        Some(Value::String(key)) => key.clone(),
+                        Some(key) => key.to_string(),
+                        None => String::new(),
+                    };
+                    let value = entry.get("value").cloned().unwrap_or(Value::Null);
+                    entries.as_object_mut()?.insert(key, value);
+                }
+            } else {
+                let list = fields
+                    .entry(field.name())
+                    .or_insert_with(|| Vec::<Value>::new().into());
+                list.as_array_mut()?.extend(values);
+            }
+        }
+
+        // Absent fields
+        for field in &message.descriptor.field {
+            if fields.contains_key(field.name()) {
+                continue;
+            }
+            let value = if field.label() == Label::Repeated {
+                match self.is_map(field) {
+                    true => Map::new().into(),
+                    false => Vec::<Value>::new().into(),
+                }
+            } else if message.proto3 && field.oneof_index.is_none() {
+                match self.zero(field) {
+                    Some(value) => value,
+                    None => continue,
+                }
+            } else {
+                continue;
+            };
+            fields.insert(field.name().to_string(), value);
+        }
+        Some(fields)
+    }
+
+    fn is_map(&self, field: &FieldDescriptorProto) -> bool {
+        field.r#type() == Type::Message
+            && self
+                .messages
+                .get(field.type_name())
+                .is_some_and(|m| m.descriptor.options.as_ref().is_some_and(|o| o.map_entry()))
+    }
+
+    /// Decoded value(s) of one occurrence of a field (several when packed)
+    fn values(&self, field: &FieldDescriptorProto, wire: Wire, depth: usize) -> Option<Vec<Value>> {
+        let kind = field.r#type();
+        Some(match wire {
+            Wire::Bytes(bytes) => match kind {
+                Type::String => vec![std::str::from_utf8(bytes).ok()?.into()],
+                Type::Bytes => vec![Base64::encode_string(bytes).into()],
+                Type::Message => {
+                    vec![self.message(field.type_name(), bytes, depth + 1)?.into()]
+                }
+                Type::Group => return None,
+                // Packed repeated scalars
+                _ => {
+                    let mut buf = bytes;
+                    let mut values = Vec::new();
+                    while !buf.is_empty() {
+                        let wire = match kind {
+                            Type::Double | Type::Fixed64 | Type::Sfixed64 => {
+                                Wire::Fixed64(u64::from_le_bytes(take(&mut buf)?))
+                            }
+                            Type::Float | Type::Fixed32 | Type::Sfixed32 => {
+                                Wire::Fixed32(u32::from_le_bytes(take(&mut buf)?))
+                            }
+                            _ => Wire::Varint(varint(&mut buf)?),
+                        };
+                        values.push(self.scalar(field, wire)?);
+                    }
+                    values
+                }
+            },
+            wire => vec![self.scalar(field, wire)?],
+        })
+    }
+
+    fn scalar(&self, field: &FieldDescriptorProto, wire: Wire) -> Option<Value> {
+        let float = |f: f64| Number::from_f64(f).map_or(Value::Null, Value::Number);
+        Some(match (field.r#type(), wire) {
+            (Type::Int32, Wire::Varint(v)) => (v as i32).into(),
+            (Type::Int64, Wire::Varint(v)) => (v as i64).into(),
+            (Type::Uint32, Wire::Varint(v)) => (v as u32).into(),
+            (Type::Uint64, Wire::Varint(v)) => v.into(),
+            (Type::Sint32, Wire::Varint(v)) => {
+                (((v as u32) >> 1) as i32 ^ -((v & 1) as i32)).into()
+            }
+            (Type::Sint64, Wire::Varint(v)) => ((v >> 1) as i64 ^ -((v & 1) as i64)).into(),
+            (Type::Bool, Wire::Varint(v)) => (v != 0).into(),
+            (Type::Enum, Wire::Varint(v)) => self.enum_value(field.type_name(), v as i32),
+            (Type::Double, Wire::Fixed64(v)) => float(f64::from_bits(v)),
+            (Type::Fixed64, Wire::Fixed64(v)) => v.into(),
+            (Type::Sfixed64, Wire::Fixed64(v)) => (v as i64).into(),
+            (Type::Float, Wire::Fixed32(v)) => float(f32::from_bits(v) as f64),
+            (Type::Fixed32, Wire::Fixed32(v)) => v.into(),
+            (Type::Sfixed32, Wire::Fixed32(v)) => (v as i32).into(),
+            // Wire type doesn't match the field
+            _ => return None,
+        })
+    }
+
+    /// An enum value's name, or its number if it isn't known
+    fn enum_value(&self, name: &str, number: i32) -> Value {
+        self.enums
+            .get(name)
+            .and_then(|e| e.value.iter().find(|v| v.number() == number))
+            .map_or_else(|| number.into(), |v| v.name().into())
+    }
+
+    /// Value of an absent proto3 scalar (`None` for messages)
+    fn zero(&self, field: &FieldDescriptorProto) -> Option<Value> {
+        Some(match field.r#type() {
+            Type::Message | Type::Group => return None,
+            // proto3 `optional` fields have presence
+            _ if field.proto3_optional() => return None,
+            Type::String | Type::Bytes => "".into(),
+            Type::Bool => false.into(),
+            Type::Double | Type::Float => 0.0.into(),
+            Type::Enum => self.enum_value(field.type_name(), 0),
+            _ => 0.into(),
+        })
+    }
+}
+
+fn varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (i, &byte) in buf.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            *buf = &buf[i + 1..];
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn take<const N: usize>(buf: &mut &[u8]) -> Option<[u8; N]> {
+    if buf.len() < N {
+        return None;
+    }
+    let (bytes, rest) = buf.split_at(N);
+    *buf = rest;
+    bytes.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::{
+        EnumValueDescriptorProto, FileDescriptorProto, MessageOptions, OneofDescriptorProto,
+    };
+    use serde_json::json;
+
+    fn field(name: &str, number: i32, kind: Type, label: Label) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            r#type: Some(kind as i32),
+            label: Some(label as i32),
+            ..Default::default()
+        }
+    }
+
+    fn typed(mut field: FieldDescriptorProto, type_name: &str) -> FieldDescriptorProto {
+        field.type_name = Some(type_name.to_string());
+        field
+    }
+
+    /// telemetry.proto:
+    ///
+    /// ```proto
+    /// syntax = "proto3";
+    /// package telemetry;
+    /// enum Status { OK = 0; FAULT = 1; }
+    /// message Reading {
+    ///   message Gps { double lat = 1; double lon = 2; }
+    ///   string device = 1;
+    ///   float temp = 2;
+    ///   sint32 offset = 3;
+    ///   Status status = 4;
+    ///   Gps gps = 5;
+    ///   repeated uint32 samples = 6;
+    ///   map<string, int64> counters = 7;
+    ///   oneof trigger { bool manual = 8; }
+    ///   uint64 seq = 9;
+    /// }
+    /// ```
+    fn schema() -> Schema {
+        let optional = Label::Optional;
+        let gps = DescriptorProto {
+            name: Some("Gps".to_string()),
+            field: vec![
+                field("lat", 1, Type::Double, optional),
+                field("lon", 2, Type::Double, optional),
+            ],
+            ..Default::default()
+        };
+        let counters = DescriptorProto {
+            name: Some("CountersEntry".to_string()),
+            field: vec![
+                field("key", 1, Type::String, optional),
+                field("value", 2, Type::Int64, optional),
+            ],
+            options: Some(MessageOptions {
+                map_entry: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut manual = field("manual", 8, Type::Bool, optional);
+        manual.oneof_index = Some(0);
+        let reading = DescriptorProto {
+            name: Some("Reading".to_string()),
+            field: vec![
+                field("device", 1, Type::String, optional),
+                field("temp", 2, Type::Float, optional),
+                field("offset", 3, Type::Sint32, optional),
+                typed(
+                    field("status", 4, Type::Enum, optional),
+                    ".telemetry.Status",
+                ),
+                typed(
+                    field("gps", 5, Type::Message, optional),
+                    ".telemetry.Reading.Gps",
+                ),
+                field("samples", 6, Type::Uint32, Label::Repeated),
+                typed(
+                    field("counters", 7, Type::Message, Label::Repeated),
+                    ".telemetry.Reading.CountersEntry",
+                ),
+                manual,
+                field("seq", 9, Type::Uint64, optional),
+            ],
+            nested_type: vec![gps, counters],
+            oneof_decl: vec![OneofDescriptorProto {
+                name: Some("trigger".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let status = EnumDescriptorProto {
+            name: Some("Status".to_string()),
+            value: ["OK", "FAULT"]
+                .iter()
+                .enumerate()
+                .map(|(number, name)| EnumValueDescriptorProto {
+                    name: Some(name.to_string()),
+                    number: Some(number as i32),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("telemetry.proto".to_string()),
+                package: Some("telemetry".to_string()),
+                syntax: Some("proto3".to_string()),
+                message_type: vec![reading],
+                enum_type: vec![status],
+                ..Default::default()
+            }],
+        };
+        Schema::decode(&set.encode_to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_decode() {
+        let schema = schema();
+        assert!(schema.contains(".telemetry.Reading.Gps"));
+        let mut payload = vec![0x0a, 4];
+        payload.extend_from_slice(b"dev1");
+        payload.push(0x15); // temp: 21.5
+        payload.extend_from_slice(&21.5f32.to_le_bytes());
+        payload.extend_from_slice(&[0x18, 0x03]); // offset: -2
+        payload.extend_from_slice(&[0x20, 0x01]); // status: FAULT
+        payload.extend_from_slice(&[0x2a, 18, 0x09]); // gps.lat
+        payload.extend_from_slice(&52.5f64.to_le_bytes());
+        payload.push(0x11); // gps.lon
+        payload.extend_from_slice(&13.25f64.to_le_bytes());
+        payload.extend_from_slice(&[0x32, 3, 1, 0x96, 0x01]); // samples: packed [1, 150]
+        payload.extend_from_slice(&[0x30, 7]); // samples: unpacked 7
+        payload.extend_from_slice(&[0x3a, 5, 0x0a, 1, b'a', 0x10, 9]); // counters: {a: 9}
+        payload.extend_from_slice(&[0x50, 1]); // unknown field 10
+
+        assert_eq!(
+            schema.decode_message(".telemetry.Reading", &payload),
+            Some(json!({
+                "device": "dev1",
+                "temp": 21.5,
+                "offset": -2,
+                "status": "FAULT",
+                "gps": {"lat": 52.5, "lon": 13.25},
+                "samples": [1, 150, 7],
+                "counters": {"a": 9},
+                "seq": 0,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_defaults_and_malformed() {
+        let schema = schema();
+        // Absent proto3 scalars read as zero; oneof members and messages stay absent
+        assert_eq!(
+            schema.decode_message(".telemetry.Reading", &[]),
+            Some(json!({
+                "device": "", "temp": 0.0, "offset": 0, "status": "OK",
+                "samples": [], "counters": {}, "seq": 0,
+            }))
+        );
+        // Truncated string, wrong wire type, group
+        assert_eq!(
+            schema.decode_message(".telemetry.Reading", &[0x0a, 4, b'd']),
+            None
+        );
+        assert_eq!(
+            schema.decode_message(".telemetry.Reading", &[0x08, 1]),
+            None
+        );
+        assert_eq!(schema.decode_message(".telemetry.Reading", &[0x0b]), None);
+        assert!(Schema::decode(b"\xff").is_err());
+    }
+}
@@ -6,7 +6,10 @@
 use async_trait::async_trait;
 
 use super::error::Result;
-use super::models::{LoadedData, StoredRetainedMessage, StoredRole, StoredSession, StoredUser};
+use super::models::{
+    LoadedData, StoredDelayedMessage, StoredRateBucket, StoredRetainedMessage, StoredRole,
+    StoredScheduleRun, StoredSequence, StoredSession, StoredSessionEvent, StoredUser,
+};
 
 /// Persistence operation for batch writes
 #[derive(Debug, Clone)]
@@ -33,6 +36,37 @@ pub enum PersistenceOp {
     SetRole { name: String, role: StoredRole },
     /// Delete a role
     DeleteRole { name: String },
+    /// Set a publish rate bucket
+    SetRateBucket {
+        identity: String,
+        bucket: StoredRateBucket,
+    },
+    /// Delete a publish rate bucket
+    DeleteRateBucket { identity: String },
+    /// Set a scheduled publish's last run
+    SetScheduleRun {
+        name: String,
+        run: StoredScheduleRun,
+    },
+    /// Set a topic's message sequence counter
+    SetSequence {
+        topic: String,
+        sequence: StoredSequence,
+    },
+    /// Set a delayed publish
+    SetDelayed {
+        id: u64,
+        message: StoredDelayedMessage,
+    },
+    /// Delete a delayed publish (delivered)
+    DeleteDelayed { id: u64 },
+    /// Set a session expiry event
+    SetSessionEvent {
+        event_id: String,
+        event: StoredSessionEvent,
+    },
+    /// Delete a session expiry event (published)
+    DeleteSessionEvent { event_id: String },
 }
 
 /// Storage backend trait for persistence
@@ -102,6 +136,65 @@ pub trait StorageBackend: Send + Sync {
     /// List all roles
     async fn list_roles(&self) -> Result<Vec<(String, StoredRole)>>;
 
+    // ========================================================================
+    // Publish rate buckets
+    // ========================================================================
+
+    /// Set a publish rate bucket
+    async fn set_rate_bucket(&self, identity: &str, bucket: &StoredRateBucket) -> Result<()>;
+
+    /// Delete a publish rate bucket
+    async fn delete_rate_bucket(&self, identity: &str) -> Result<()>;
+
+    /// List all publish rate buckets
+    async fn list_rate_buckets(&self) -> Result<Vec<(String, StoredRateBucket)>>;
+
+    // ========================================================================
+    // Scheduled publishes
+    // ========================================================================
+
+    /// Set a scheduled publish's last run
+    async fn set_schedule_run(&self, name: &str, run: &StoredScheduleRun) -> Result<()>;
+
+    /// List the last runs of all scheduled publishes
+    async fn list_schedule_runs(&self) -> Result<Vec<(String, StoredScheduleRun)>>;
+
+    // ========================================================================
+    // Message sequence numbers
+    // ========================================================================
+
+    /// Set a topic's sequence counter
+    async fn set_sequence(&self, topic: &str, sequence: &StoredSequence) -> Result<()>;
+
+    /// List the sequence counters of all numbered topics
+    async fn list_sequences(&self) -> Result<Vec<(String, StoredSequence)>>;
+
+    // ========================================================================
+    // Delayed publishes
+    // ========================================================================
+
+    /// Set a delayed publish
+    async fn set_delayed(&self, id: u64, message: &StoredDelayedMessage) -> Result<()>;
+
+    /// Delete a delayed publish
+    async fn delete_delayed(&self, id: u64) -> Result<()>;
+
+    /// List all delayed publishes not yet delivered
+    async fn list_delayed(&self) -> Result<Vec<(u64, StoredDelayedMessage)>>;
+
+    // ========================================================================
+    // Session expiry events
+    // ========================================================================
+
+    /// Set a session expiry event
+    async fn set_session_event(&self, event_id: &str, event: &StoredSessionEvent) -> Result<()>;
+
+    /// Delete a session expiry event
+    async fn delete_session_event(&self, event_id: &str) -> Result<()>;
+
+    /// List all session expiry events not yet published
+    async fn list_session_events(&self) -> Result<Vec<(String, StoredSessionEvent)>>;
+
     // ========================================================================
     // Batch operations
     // ========================================================================
@@ -125,12 +218,22 @@ pub trait StorageBackend: Send + Sync {
         let sessions = self.list_sessions().await?;
         let users = self.list_users().await?;
         let roles = self.list_roles().await?;
+        let rate_buckets = self.list_rate_buckets().await?;
+        let schedule_runs = self.list_schedule_runs().await?;
+        let sequences = self.list_sequences().await?;
+        let delayed = self.list_delayed().await?;
+        let session_events = self.list_session_events().await?;
 
         Ok(LoadedData {
             retained,
             sessions,
             users,
             roles,
+            rate_buckets,
+            schedule_runs,
+            sequences,
+            delayed,
+            session_events,
         })
     }
 }
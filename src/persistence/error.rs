@@ -15,6 +15,8 @@ pub enum PersistenceError {
     Storage(String),
     /// Data corruption detected
     Corruption(String),
+    /// Data in a format version this release can't read
+    Format(String),
 }
 
 impl fmt::Display for PersistenceError {
@@ -25,6 +27,7 @@ impl fmt::Display for PersistenceError {
             Self::Deserialize(e) => write!(f, "deserialization error: {}", e),
             Self::Storage(e) => write!(f, "storage error: {}", e),
             Self::Corruption(e) => write!(f, "data corruption: {}", e),
+            Self::Format(e) => write!(f, "unsupported data format: {}", e),
         }
     }
 }
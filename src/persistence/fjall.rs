@@ -1,15 +1,21 @@
 //! Fjall-based storage backend implementation.
 //!
 //! Uses fjall (an LSM-tree based embedded database) for local persistence.
+//! Stored records are versioned and migrated on open (see `format`).
 
 use std::path::Path;
 
 use async_trait::async_trait;
 use fjall::{Config, Keyspace, PartitionCreateOptions, PartitionHandle, PersistMode};
+use tracing::info;
 
 use super::backend::{PersistenceOp, StorageBackend};
 use super::error::{PersistenceError, Result};
-use super::models::{StoredRetainedMessage, StoredRole, StoredSession, StoredUser};
+use super::format::{self, FormatStatus, FORMAT_VERSION, VERSION_KEY};
+use super::models::{
+    StoredDelayedMessage, StoredRateBucket, StoredRetainedMessage, StoredRole, StoredScheduleRun,
+    StoredSequence, StoredSession, StoredSessionEvent, StoredUser,
+};
 
 /// Fjall-based storage backend
 pub struct FjallBackend {
@@ -18,17 +24,98 @@ pub struct FjallBackend {
     sessions: PartitionHandle,
     users: PartitionHandle,
     roles: PartitionHandle,
+    rate_buckets: PartitionHandle,
+    schedule_runs: PartitionHandle,
+    sequences: PartitionHandle,
+    delayed: PartitionHandle,
+    session_events: PartitionHandle,
+    /// Format version of the store
+    meta: PartitionHandle,
 }
 
 impl FjallBackend {
-    /// Open a fjall backend at the given path
+    /// Open a fjall backend at the given path, migrating its data to the
+    /// current format (after backing it up)
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_migrating(path, true)
+    }
+
+    /// Open a fjall backend, migrating its data to the current format; with
+    /// `backup` the data directory is copied aside first
+    pub fn open_migrating<P: AsRef<Path>>(path: P, backup: bool) -> Result<Self> {
+        let path = path.as_ref();
+        let mut backend = Self::open_keyspace(path)?;
+        let Some(version) = format::stored_version(&backend.meta, &backend.records())? else {
+            backend.meta.insert(VERSION_KEY, [FORMAT_VERSION])?;
+            return Ok(backend);
+        };
+        let pending = format::pending(version)?;
+        if pending.is_empty() {
+            return Ok(backend);
+        }
+
+        if backup {
+            // Copy the files of the closed keyspace
+            drop(backend);
+            let backup = format::back_up(path, version)?;
+            info!(
+                "Backed up persistence data in format version {} to {}",
+                version,
+                backup.display()
+            );
+            backend = Self::open_keyspace(path)?;
+        }
+        for migration in pending {
+            let records = format::migrate(
+                &backend.keyspace,
+                &backend.meta,
+                &backend.records(),
+                migration,
+            )?;
+            info!(
+                "Migrated {} persistence record(s) to format version {}: {}",
+                records, migration.to, migration.description
+            );
+        }
+        backend.keyspace.persist(PersistMode::SyncAll)?;
+        Ok(backend)
+    }
+
+    /// Format version of the data at `path` and the migrations opening it
+    /// would run, without changing it
+    pub fn check_format<P: AsRef<Path>>(path: P) -> Result<FormatStatus> {
+        let path = path.as_ref();
+        if !format::exists(path) {
+            return Ok(FormatStatus {
+                version: None,
+                pending: Vec::new(),
+            });
+        }
+        let backend = Self::open_keyspace(path)?;
+        let version = format::stored_version(&backend.meta, &backend.records())?;
+        let pending = format::pending(version.unwrap_or(FORMAT_VERSION))?;
+        Ok(FormatStatus {
+            version,
+            pending: pending.iter().map(|m| m.description).collect(),
+        })
+    }
+
+    fn open_keyspace(path: &Path) -> Result<Self> {
         let keyspace = Config::new(path).open()?;
 
         let retained = keyspace.open_partition("retained", PartitionCreateOptions::default())?;
         let sessions = keyspace.open_partition("sessions", PartitionCreateOptions::default())?;
         let users = keyspace.open_partition("users", PartitionCreateOptions::default())?;
         let roles = keyspace.open_partition("roles", PartitionCreateOptions::default())?;
+        let rate_buckets =
+            keyspace.open_partition("rate_buckets", PartitionCreateOptions::default())?;
+        let schedule_runs =
+            keyspace.open_partition("schedule_runs", PartitionCreateOptions::default())?;
+        let sequences = keyspace.open_partition("sequences", PartitionCreateOptions::default())?;
+        let delayed = keyspace.open_partition("delayed", PartitionCreateOptions::default())?;
+        let session_events =
+            keyspace.open_partition("session_events", PartitionCreateOptions::default())?;
+        let meta = keyspace.open_partition("meta", PartitionCreateOptions::default())?;
 
         Ok(Self {
             keyspace,
@@ -36,17 +123,40 @@ impl FjallBackend {
             sessions,
             users,
             roles,
+            rate_buckets,
+            schedule_runs,
+            sequences,
+            delayed,
+            session_events,
+            meta,
         })
     }
 
-    /// Serialize a value using bincode
+    /// Partitions holding records
+    fn records(&self) -> [&PartitionHandle; 9] {
+        [
+            &self.retained,
+            &self.sessions,
+            &self.users,
+            &self.roles,
+            &self.rate_buckets,
+            &self.schedule_runs,
+            &self.sequences,
+            &self.delayed,
+            &self.session_events,
+        ]
+    }
+
+    /// Serialize a value using bincode, behind the format version header
     fn serialize<T: bincode::Encode>(value: &T) -> Result<Vec<u8>> {
-        bincode::encode_to_vec(value, bincode::config::standard()).map_err(PersistenceError::from)
+        bincode::encode_to_vec(value, bincode::config::standard())
+            .map(format::with_header)
+            .map_err(PersistenceError::from)
     }
 
-    /// Deserialize a value using bincode
+    /// Deserialize a value using bincode, checking its format version
     fn deserialize<T: bincode::Decode<()>>(bytes: &[u8]) -> Result<T> {
-        bincode::decode_from_slice(bytes, bincode::config::standard())
+        bincode::decode_from_slice(format::body(bytes)?, bincode::config::standard())
             .map(|(value, _)| value)
             .map_err(PersistenceError::from)
     }
@@ -186,6 +296,128 @@ impl StorageBackend for FjallBackend {
         Ok(result)
     }
 
+    // ========================================================================
+    // Publish rate buckets
+    // ========================================================================
+
+    async fn set_rate_bucket(&self, identity: &str, bucket: &StoredRateBucket) -> Result<()> {
+        let bytes = Self::serialize(bucket)?;
+        self.rate_buckets.insert(identity, bytes)?;
+        Ok(())
+    }
+
+    async fn delete_rate_bucket(&self, identity: &str) -> Result<()> {
+        self.rate_buckets.remove(identity)?;
+        Ok(())
+    }
+
+    async fn list_rate_buckets(&self) -> Result<Vec<(String, StoredRateBucket)>> {
+        let mut result = Vec::new();
+        for item in self.rate_buckets.iter() {
+            let (key, value) = item?;
+            let identity = String::from_utf8_lossy(&key).to_string();
+            let bucket: StoredRateBucket = Self::deserialize(&value)?;
+            result.push((identity, bucket));
+        }
+        Ok(result)
+    }
+
+    // ========================================================================
+    // Scheduled publishes
+    // ========================================================================
+
+    async fn set_schedule_run(&self, name: &str, run: &StoredScheduleRun) -> Result<()> {
+        let bytes = Self::serialize(run)?;
+        self.schedule_runs.insert(name, bytes)?;
+        Ok(())
+    }
+
+    async fn list_schedule_runs(&self) -> Result<Vec<(String, StoredScheduleRun)>> {
+        let mut result = Vec::new();
+        for item in self.schedule_runs.iter() {
+            let (key, value) = item?;
+            let name = String::from_utf8_lossy(&key).to_string();
+            let run: StoredScheduleRun = Self::deserialize(&value)?;
+            result.push((name, run));
+        }
+        Ok(result)
+    }
+
+    // ========================================================================
+    // Message sequence numbers
+    // ========================================================================
+
+    async fn set_sequence(&self, topic: &str, sequence: &StoredSequence) -> Result<()> {
+        let bytes = Self::serialize(sequence)?;
+        self.sequences.insert(topic, bytes)?;
+        Ok(())
+    }
+
+    async fn list_sequences(&self) -> Result<Vec<(String, StoredSequence)>> {
+        let mut result = Vec::new();
+        for item in self.sequences.iter() {
+            let (key, value) = item?;
+            let topic = String::from_utf8_lossy(&key).to_string();
+            let sequence: StoredSequence = Self::deserialize(&value)?;
+            result.push((topic, sequence));
+        }
+        Ok(result)
+    }
+
+    // ========================================================================
+    // Delayed publishes
+    // ========================================================================
+
+    async fn set_delayed(&self, id: u64, message: &StoredDelayedMessage) -> Result<()> {
+        let bytes = Self::serialize(message)?;
+        self.delayed.insert(id.to_be_bytes(), bytes)?;
+        Ok(())
+    }
+
+    async fn delete_delayed(&self, id: u64) -> Result<()> {
+        self.delayed.remove(id.to_be_bytes())?;
+        Ok(())
+    }
+
+    async fn list_delayed(&self) -> Result<Vec<(u64, StoredDelayedMessage)>> {
+        let mut result = Vec::new();
+        for item in self.delayed.iter() {
+            let (key, value) = item?;
+            let Ok(id) = <[u8; 8]>::try_from(&*key) else {
+                continue;
+            };
+            let message: StoredDelayedMessage = Self::deserialize(&value)?;
+            result.push((u64::from_be_bytes(id), message));
+        }
+        Ok(result)
+    }
+
+    // ========================================================================
+    // Session expiry events
+    // ========================================================================
+
+    async fn set_session_event(&self, event_id: &str, event: &StoredSessionEvent) -> Result<()> {
+        let bytes = Self::serialize(event)?;
+        self.session_events.insert(event_id, bytes)?;
+        Ok(())
+    }
+
+    async fn delete_session_event(&self, event_id: &str) -> Result<()> {
+        self.session_events.remove(event_id)?;
+        Ok(())
+    }
+
+    async fn list_session_events(&self) -> Result<Vec<(String, StoredSessionEvent)>> {
+        let mut result = Vec::new();
+        for item in self.session_events.iter() {
+            let (key, value) = item?;
+            let event_id = String::from_utf8_lossy(&key).to_string();
+            let event: StoredSessionEvent = Self::deserialize(&value)?;
+            result.push((event_id, event));
+        }
+        Ok(result)
+    }
+
     // ========================================================================
     // Batch operations
     // ========================================================================
@@ -223,6 +455,35 @@ impl StorageBackend for FjallBackend {
                 PersistenceOp::DeleteRole { name } => {
                     batch.remove(&self.roles, name);
                 }
+                PersistenceOp::SetRateBucket { identity, bucket } => {
+                    let bytes = Self::serialize(&bucket)?;
+                    batch.insert(&self.rate_buckets, identity, bytes);
+                }
+                PersistenceOp::DeleteRateBucket { identity } => {
+                    batch.remove(&self.rate_buckets, identity);
+                }
+                PersistenceOp::SetScheduleRun { name, run } => {
+                    let bytes = Self::serialize(&run)?;
+                    batch.insert(&self.schedule_runs, name, bytes);
+                }
+                PersistenceOp::SetSequence { topic, sequence } => {
+                    let bytes = Self::serialize(&sequence)?;
+                    batch.insert(&self.sequences, topic, bytes);
+                }
+                PersistenceOp::SetDelayed { id, message } => {
+                    let bytes = Self::serialize(&message)?;
+                    batch.insert(&self.delayed, id.to_be_bytes(), bytes);
+                }
+                PersistenceOp::DeleteDelayed { id } => {
+                    batch.remove(&self.delayed, id.to_be_bytes());
+                }
+                PersistenceOp::SetSessionEvent { event_id, event } => {
+                    let bytes = Self::serialize(&event)?;
+                    batch.insert(&self.session_events, event_id, bytes);
+                }
+                PersistenceOp::DeleteSessionEvent { event_id } => {
+                    batch.remove(&self.session_events, event_id);
+                }
             }
         }
 
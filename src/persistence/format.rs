@@ -0,0 +1,208 @@
+//! On-disk format versions and migrations.
+//!
+//! Every record the fjall backend stores starts with a header byte naming
+//! the format version it was written in, and the keyspace's `meta`
+//! partition holds the version all of its records are in. (The journal and
+//! segment files are fjall's own, versioned by fjall.)
+//!
+//! A release that changes a stored format raises `FORMAT_VERSION` and adds
+//! a migration to `MIGRATIONS` rewriting the records of the previous
+//! version. Opening a store runs the migrations it needs in order, after
+//! copying the data directory aside (`<path>.v<N>.backup`), so data written
+//! by any earlier release keeps loading. Each migration commits atomically
+//! together with the version it leads to, so an interrupted migration is
+//! simply run again. A store written by a newer release is refused rather
+//! than misread.
+//!
+//! Version 0 is the format of releases before versioning: the same records
+//! without the header.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use fjall::{Keyspace, PartitionHandle};
+
+use super::error::{PersistenceError, Result};
+
+/// Format version this release writes
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Key of the store's format version in the `meta` partition
+pub(super) const VERSION_KEY: &str = "format_version";
+
+/// fjall's marker file, present in every keyspace directory
+const FJALL_MARKER: &str = "version";
+
+/// A rewrite of every record from the previous format version
+pub(super) struct Migration {
+    /// Version the records are in afterwards
+    pub to: u8,
+    pub description: &'static str,
+    /// New value of a record, from the previous version's bytes
+    pub rewrite: fn(&[u8]) -> Vec<u8>,
+}
+
+pub(super) const MIGRATIONS: &[Migration] = &[Migration {
+    to: 1,
+    description: "add format version headers to records",
+    rewrite: add_header,
+}];
+
+fn add_header(value: &[u8]) -> Vec<u8> {
+    let mut headed = Vec::with_capacity(value.len() + 1);
+    headed.push(1);
+    headed.extend_from_slice(value);
+    headed
+}
+
+/// Prefix an encoded record with the current version header
+pub(super) fn with_header(body: Vec<u8>) -> Vec<u8> {
+    let mut record = Vec::with_capacity(body.len() + 1);
+    record.push(FORMAT_VERSION);
+    record.extend_from_slice(&body);
+    record
+}
+
+/// The encoded body of a record, checking its header
+pub(super) fn body(record: &[u8]) -> Result<&[u8]> {
+    match record.split_first() {
+        Some((&FORMAT_VERSION, body)) => Ok(body),
+        Some((version, _)) => Err(PersistenceError::Format(format!(
+            "record in format version {}, expected {}",
+            version, FORMAT_VERSION
+        ))),
+        None => Err(PersistenceError::Corruption("empty record".to_string())),
+    }
+}
+
+/// Format version of an open store: the recorded one, 0 for a store of a
+/// release before versioning, `None` for a new (empty) store
+pub(super) fn stored_version(
+    meta: &PartitionHandle,
+    partitions: &[&PartitionHandle],
+) -> Result<Option<u8>> {
+    if let Some(value) = meta.get(VERSION_KEY)? {
+        return match *value {
+            [version] => Ok(Some(version)),
+            _ => Err(PersistenceError::Corruption(
+                "unreadable format version".to_string(),
+            )),
+        };
+    }
+    for partition in partitions {
+        if !partition.is_empty()? {
+            return Ok(Some(0));
+        }
+    }
+    Ok(None)
+}
+
+/// Migrations a store of `version` needs, refusing one from a newer release
+pub(super) fn pending(version: u8) -> Result<Vec<&'static Migration>> {
+    if version > FORMAT_VERSION {
+        return Err(PersistenceError::Format(format!(
+            "data is in format version {}, written by a newer VibeMQ (this one reads up to {})",
+            version, FORMAT_VERSION
+        )));
+    }
+    Ok(MIGRATIONS.iter().filter(|m| m.to > version).collect())
+}
+
+/// Run a migration over every record of the store; the rewritten records
+/// and the new version are committed in one batch
+pub(super) fn migrate(
+    keyspace: &Keyspace,
+    meta: &PartitionHandle,
+    partitions: &[&PartitionHandle],
+    migration: &Migration,
+) -> Result<usize> {
+    let mut batch = keyspace.batch();
+    let mut rewritten = 0;
+    for partition in partitions {
+        for item in partition.iter() {
+            let (key, value) = item?;
+            batch.insert(partition, key, (migration.rewrite)(&value));
+            rewritten += 1;
+        }
+    }
+    batch.insert(meta, VERSION_KEY, [migration.to]);
+    batch.commit()?;
+    Ok(rewritten)
+}
+
+/// Whether a directory holds a store
+pub(super) fn exists(path: &Path) -> bool {
+    path.join(FJALL_MARKER).exists()
+}
+
+/// Copy a store's directory aside before migrating it from `version`
+pub(super) fn back_up(path: &Path, version: u8) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "data".to_string());
+    let mut backup = path.with_file_name(format!("{}.v{}.backup", name, version));
+    if backup.exists() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        backup = path.with_file_name(format!("{}.v{}.backup-{}", name, version, now));
+    }
+    copy_dir(path, &backup)?;
+    Ok(backup)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Format of a store as found by [`super::FjallBackend::check_format`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatStatus {
+    /// Version the store is in (`None` if there is no store yet)
+    pub version: Option<u8>,
+    /// Migrations opening it will run, by description
+    pub pending: Vec<&'static str>,
+}
+
+impl FormatStatus {
+    pub fn is_current(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_headers() {
+        let record = with_header(vec![7, 8]);
+        assert_eq!(record, [FORMAT_VERSION, 7, 8]);
+        assert_eq!(body(&record).unwrap(), [7, 8]);
+
+        assert!(matches!(
+            body(&[FORMAT_VERSION + 1, 7]),
+            Err(PersistenceError::Format(_))
+        ));
+        assert!(matches!(body(&[]), Err(PersistenceError::Corruption(_))));
+
+        // Headers are what the first migration adds
+        assert_eq!((MIGRATIONS[0].rewrite)(&[7, 8]), record);
+        assert_eq!(pending(0).unwrap().len(), MIGRATIONS.len());
+        assert!(pending(FORMAT_VERSION).unwrap().is_empty());
+        assert!(pending(FORMAT_VERSION + 1).is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! In-memory storage backend implementation.
+//!
+//! Keeps everything in process memory, so nothing survives a restart. Useful
+//! for tests and ephemeral deployments that want the persistence code paths
+//! without touching disk.
+
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use parking_lot::RwLock;
+
+use super::backend::{PersistenceOp, StorageBackend};
+use super::error::Result;
+use super::models::{
+    StoredDelayedMessage, StoredRateBucket, StoredRetainedMessage, StoredRole, StoredScheduleRun,
+    StoredSequence, StoredSession, StoredSessionEvent, StoredUser,
+};
+
+/// In-memory storage backend
+#[derive(Default)]
+pub struct MemoryBackend {
+    retained: RwLock<BTreeMap<String, StoredRetainedMessage>>,
+    sessions: RwLock<BTreeMap<String, StoredSession>>,
+    users: RwLock<BTreeMap<String, StoredUser>>,
+    roles: RwLock<BTreeMap<String, StoredRole>>,
+    rate_buckets: RwLock<BTreeMap<String, StoredRateBucket>>,
+    schedule_runs: RwLock<BTreeMap<String, StoredScheduleRun>>,
+    sequences: RwLock<BTreeMap<String, StoredSequence>>,
+    delayed: RwLock<BTreeMap<u64, StoredDelayedMessage>>,
+    session_events: RwLock<BTreeMap<String, StoredSessionEvent>>,
+}
+
+impl MemoryBackend {
+    /// Create an empty in-memory backend
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn list<T: Clone>(map: &RwLock<BTreeMap<String, T>>) -> Vec<(String, T)> {
+        map.read()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryBackend {
+    // ========================================================================
+    // Retained messages
+    // ========================================================================
+
+    async fn get_retained(&self, topic: &str) -> Result<Option<StoredRetainedMessage>> {
+        Ok(self.retained.read().get(topic).cloned())
+    }
+
+    async fn set_retained(&self, topic: &str, message: &StoredRetainedMessage) -> Result<()> {
+        self.retained
+            .write()
+            .insert(topic.to_string(), message.clone());
+        Ok(())
+    }
+
+    async fn delete_retained(&self, topic: &str) -> Result<()> {
+        self.retained.write().remove(topic);
+        Ok(())
+    }
+
+    async fn list_retained(&self) -> Result<Vec<(String, StoredRetainedMessage)>> {
+        Ok(Self::list(&self.retained))
+    }
+
+    // ========================================================================
+    // Sessions
+    // ========================================================================
+
+    async fn get_session(&self, client_id: &str) -> Result<Option<StoredSession>> {
+        Ok(self.sessions.read().get(client_id).cloned())
+    }
+
+    async fn set_session(&self, client_id: &str, session: &StoredSession) -> Result<()> {
+        self.sessions
+            .write()
+            .insert(client_id.to_string(), session.clone());
+        Ok(())
+    }
+
+    async fn delete_session(&self, client_id: &str) -> Result<()> {
+        self.sessions.write().remove(client_id);
+        Ok(())
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<(String, StoredSession)>> {
+        Ok(Self::list(&self.sessions))
+    }
+
+    // ========================================================================
+    // Users
+    // ========================================================================
+
+    async fn get_user(&self, username: &str) -> Result<Option<StoredUser>> {
+        Ok(self.users.read().get(username).cloned())
+    }
+
+    async fn set_user(&self, username: &str, user: &StoredUser) -> Result<()> {
+        self.users
+            .write()
+            .insert(username.to_string(), user.clone());
+        Ok(())
+    }
+
+    async fn delete_user(&self, username: &str) -> Result<()> {
+        self.users.write().remove(username);
+        Ok(())
+    }
+
+    async fn list_users(&self) -> Result<Vec<(String, StoredUser)>> {
+        Ok(Self::list(&self.users))
+    }
+
+    // ========================================================================
+    // Roles
+    // ========================================================================
+
+    async fn get_role(&self, name: &str) -> Result<Option<StoredRole>> {
+        Ok(self.roles.read().get(name).cloned())
+    }
+
+    async fn set_role(&self, name: &str, role: &StoredRole) -> Result<()> {
+        self.roles.write().insert(name.to_string(), role.clone());
+        Ok(())
+    }
+
+    async fn delete_role(&self, name: &str) -> Result<()> {
+        self.roles.write().remove(name);
+        Ok(())
+    }
+
+    async fn list_roles(&self) -> Result<Vec<(String, StoredRole)>> {
+        Ok(Self::list(&self.roles))
+    }
+
+    // ========================================================================
+    // Publish rate buckets
+    // ========================================================================
+
+    async fn set_rate_bucket(&self, identity: &str, bucket: &StoredRateBucket) -> Result<()> {
+        self.rate_buckets
+            .write()
+            .insert(identity.to_string(), bucket.clone());
+        Ok(())
+    }
+
+    async fn delete_rate_bucket(&self, identity: &str) -> Result<()> {
+        self.rate_buckets.write().remove(identity);
+        Ok(())
+    }
+
+    async fn list_rate_buckets(&self) -> Result<Vec<(String, StoredRateBucket)>> {
+        Ok(Self::list(&self.rate_buckets))
+    }
+
+    // ========================================================================
+    // Scheduled publishes
+    // ========================================================================
+
+    async fn set_schedule_run(&self, name: &str, run: &StoredScheduleRun) -> Result<()> {
+        self.schedule_runs
+            .write()
+            .insert(name.to_string(), run.clone());
+        Ok(())
+    }
+
+    async fn list_schedule_runs(&self) -> Result<Vec<(String, StoredScheduleRun)>> {
+        Ok(Self::list(&self.schedule_runs))
+    }
+
+    // ========================================================================
+    // Message sequence numbers
+    // ========================================================================
+
+    async fn set_sequence(&self, topic: &str, sequence: &StoredSequence) -> Result<()> {
+        self.sequences
+            .write()
+            .insert(topic.to_string(), sequence.clone());
+        Ok(())
+    }
+
+    async fn list_sequences(&self) -> Result<Vec<(String, StoredSequence)>> {
+        Ok(Self::list(&self.sequences))
+    }
+
+    // ========================================================================
+    // Delayed publishes
+    // ========================================================================
+
+    async fn set_delayed(&self, id: u64, message: &StoredDelayedMessage) -> Result<()> {
+        self.delayed.write().insert(id, message.clone());
+        Ok(())
+    }
+
+    async fn delete_delayed(&self, id: u64) -> Result<()> {
+        self.delayed.write().remove(&id);
+        Ok(())
+    }
+
+    async fn list_delayed(&self) -> Result<Vec<(u64, StoredDelayedMessage)>> {
+        Ok(self
+            .delayed
+            .read()
+            .iter()
+            .map(|(id, message)| (*id, message.clone()))
+            .collect())
+    }
+
+    // ========================================================================
+    // Session expiry events
+    // ========================================================================
+
+    async fn set_session_event(&self, event_id: &str, event: &StoredSessionEvent) -> Result<()> {
+        self.session_events
+            .write()
+            .insert(event_id.to_string(), event.clone());
+        Ok(())
+    }
+
+    async fn delete_session_event(&self, event_id: &str) -> Result<()> {
+        self.session_events.write().remove(event_id);
+        Ok(())
+    }
+
+    async fn list_session_events(&self) -> Result<Vec<(String, StoredSessionEvent)>> {
+        Ok(Self::list(&self.session_events))
+    }
+
+    // ========================================================================
+    // Batch operations
+    // ========================================================================
+
+    async fn batch_write(&self, ops: Vec<PersistenceOp>) -> Result<()> {
+        for op in ops {
+            match op {
+                PersistenceOp::SetRetained { topic, message } => {
+                    self.retained.write().insert(topic, message);
+                }
+                PersistenceOp::DeleteRetained { topic } => {
+                    self.retained.write().remove(&topic);
+                }
+                PersistenceOp::SetSession { client_id, session } => {
+                    self.sessions.write().insert(client_id, session);
+                }
+                PersistenceOp::DeleteSession { client_id } => {
+                    self.sessions.write().remove(&client_id);
+                }
+                PersistenceOp::SetUser { username, user } => {
+                    self.users.write().insert(username, user);
+                }
+                PersistenceOp::DeleteUser { username } => {
+                    self.users.write().remove(&username);
+                }
+                PersistenceOp::SetRole { name, role } => {
+                    self.roles.write().insert(name, role);
+                }
+                PersistenceOp::DeleteRole { name } => {
+                    self.roles.write().remove(&name);
+                }
+                PersistenceOp::SetRateBucket { identity, bucket } => {
+                    self.rate_buckets.write().insert(identity, bucket);
+                }
+                PersistenceOp::DeleteRateBucket { identity } => {
+                    self.rate_buckets.write().remove(&identity);
+                }
+                PersistenceOp::SetScheduleRun { name, run } => {
+                    self.schedule_runs.write().insert(name, run);
+                }
+                PersistenceOp::SetSequence { topic, sequence } => {
+                    self.sequences.write().insert(topic, sequence);
+                }
+                PersistenceOp::SetDelayed { id, message } => {
+                    self.delayed.write().insert(id, message);
+                }
+                PersistenceOp::DeleteDelayed { id } => {
+                    self.delayed.write().remove(&id);
+                }
+                PersistenceOp::SetSessionEvent { event_id, event } => {
+                    self.session_events.write().insert(event_id, event);
+                }
+                PersistenceOp::DeleteSessionEvent { event_id } => {
+                    self.session_events.write().remove(&event_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // ========================================================================
+    // Lifecycle
+    // ========================================================================
+
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -4,36 +4,63 @@
 //! - Retained messages
 //! - Sessions (with inflight QoS 1/2 messages)
 //! - Users and ACL roles (for future HTTP API)
+//! - Publish rate buckets (so limits survive a restart)
+//! - Last runs of scheduled publishes (so a restart doesn't repeat one)
+//! - Delayed publishes not yet delivered
+//! - Session expiry events not yet published
+//!
+//! Mosquitto's `mosquitto.db` can be imported with [`parse_mosquitto_db`].
+//!
+//! Stored records carry a format version; opening a store from an earlier
+//! release migrates it (see [`FORMAT_VERSION`]).
 //!
 //! Uses a trait-based design allowing different backends:
 //! - `FjallBackend` (default) - Local LSM-tree storage
+//! - `MemoryBackend` - Process memory only (nothing survives a restart)
 //! - Future: Redis, PostgreSQL, etc.
 
 mod backend;
 mod error;
 mod fjall;
+mod format;
+mod memory;
 mod models;
+mod mosquitto;
 
 pub use backend::{PersistenceOp, StorageBackend};
 pub use error::{PersistenceError, Result};
 pub use fjall::FjallBackend;
+pub use format::{FormatStatus, FORMAT_VERSION};
+pub use memory::MemoryBackend;
 pub use models::{
-    LoadedData, StoredInflightMessage, StoredPendingMessage, StoredProperties, StoredPublish,
-    StoredRetainedMessage, StoredRole, StoredSession, StoredSubscription, StoredUser,
-    StoredWillMessage,
+    LoadedData, StoredDelayedMessage, StoredInflightMessage, StoredPendingMessage,
+    StoredProperties, StoredPublish, StoredRateBucket, StoredRetainedMessage, StoredRole,
+    StoredScheduleRun, StoredSequence, StoredSession, StoredSessionEvent, StoredSubscription,
+    StoredUser, StoredWillMessage,
 };
+pub use mosquitto::{parse_mosquitto_db, MosquittoImport, MOSQUITTO_DB_MAGIC};
 
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 use tokio::sync::mpsc;
 use tracing::{debug, error, info, warn};
 
+use crate::config::SessionCheckpoint;
+use crate::metrics::Metrics;
+
 /// Persistence manager that handles background writes
 pub struct PersistenceManager {
     backend: Arc<dyn StorageBackend>,
     tx: mpsc::Sender<PersistenceOp>,
     shutdown_tx: mpsc::Sender<()>,
+    /// Whether the last batch failed to write
+    failing: Arc<AtomicBool>,
+    /// Broker metrics, once set (batches with session state are timed)
+    metrics: Arc<OnceLock<Arc<Metrics>>>,
+    session_checkpoint: SessionCheckpoint,
+    session_checkpoint_interval: Duration,
 }
 
 impl PersistenceManager {
@@ -50,10 +77,14 @@ impl PersistenceManager {
 
         // Spawn background writer task
         let backend_clone = backend.clone();
+        let failing = Arc::new(AtomicBool::new(false));
+        let metrics = Arc::new(OnceLock::new());
         tokio::spawn(Self::writer_loop(
             backend_clone,
             rx,
             shutdown_rx,
+            failing.clone(),
+            metrics.clone(),
             flush_interval,
             max_batch_size,
         ));
@@ -62,9 +93,35 @@ impl PersistenceManager {
             backend,
             tx,
             shutdown_tx,
+            failing,
+            metrics,
+            session_checkpoint: SessionCheckpoint::Disconnect,
+            session_checkpoint_interval: Duration::from_secs(1),
         }
     }
 
+    /// Set when connected sessions are checkpointed (default: on disconnect only)
+    pub fn with_session_checkpoint(mut self, mode: SessionCheckpoint, interval: Duration) -> Self {
+        self.session_checkpoint = mode;
+        self.session_checkpoint_interval = interval;
+        self
+    }
+
+    /// Report session checkpoint write times to `metrics` (first call wins)
+    pub fn set_metrics(&self, metrics: Arc<Metrics>) {
+        let _ = self.metrics.set(metrics);
+    }
+
+    /// Session checkpoint mode
+    pub fn session_checkpoint(&self) -> SessionCheckpoint {
+        self.session_checkpoint
+    }
+
+    /// Session checkpoint interval (for [`SessionCheckpoint::Interval`])
+    pub fn session_checkpoint_interval(&self) -> Duration {
+        self.session_checkpoint_interval
+    }
+
     /// Fire-and-forget write operation (non-blocking for hot path)
     ///
     /// If the channel is full, the operation is dropped (backpressure).
@@ -74,6 +131,23 @@ impl PersistenceManager {
         }
     }
 
+    /// Queue write operations, waiting for room in the channel instead of
+    /// dropping any (for bulk changes off the hot path)
+    pub async fn write_all(&self, ops: Vec<PersistenceOp>) {
+        for op in ops {
+            if self.tx.send(op).await.is_err() {
+                warn!("Persistence writer stopped, dropping operations");
+                return;
+            }
+        }
+    }
+
+    /// Whether writes reach the backend: the writer is running and its
+    /// last batch was written
+    pub fn is_healthy(&self) -> bool {
+        !self.tx.is_closed() && !self.failing.load(Ordering::Relaxed)
+    }
+
     /// Load all data at startup
     pub async fn load_all(&self) -> Result<LoadedData> {
         self.backend.load_all().await
@@ -104,6 +178,8 @@ impl PersistenceManager {
         backend: Arc<dyn StorageBackend>,
         mut rx: mpsc::Receiver<PersistenceOp>,
         mut shutdown_rx: mpsc::Receiver<()>,
+        failing: Arc<AtomicBool>,
+        metrics: Arc<OnceLock<Arc<Metrics>>>,
         flush_interval: Duration,
         max_batch_size: usize,
     ) {
@@ -120,7 +196,7 @@ impl PersistenceManager {
 
                             // Flush immediately if batch is large
                             if batch.len() >= max_batch_size {
-                                if let Err(e) = backend.batch_write(std::mem::take(&mut batch)).await {
+                                if let Err(e) = Self::write_batch(&*backend, std::mem::take(&mut batch), &failing, &metrics).await {
                                     error!("Failed to write batch: {}", e);
                                 } else {
                                     debug!("Flushed {} operations (max batch)", batch.capacity());
@@ -130,7 +206,7 @@ impl PersistenceManager {
                         None => {
                             // Channel closed, flush remaining and exit
                             if !batch.is_empty() {
-                                if let Err(e) = backend.batch_write(std::mem::take(&mut batch)).await {
+                                if let Err(e) = Self::write_batch(&*backend, std::mem::take(&mut batch), &failing, &metrics).await {
                                     error!("Failed to write final batch: {}", e);
                                 }
                             }
@@ -143,7 +219,7 @@ impl PersistenceManager {
                 _ = interval.tick() => {
                     if !batch.is_empty() {
                         let count = batch.len();
-                        if let Err(e) = backend.batch_write(std::mem::take(&mut batch)).await {
+                        if let Err(e) = Self::write_batch(&*backend, std::mem::take(&mut batch), &failing, &metrics).await {
                             error!("Failed to write batch: {}", e);
                         } else {
                             debug!("Flushed {} operations (interval)", count);
@@ -157,7 +233,7 @@ impl PersistenceManager {
                     // Flush remaining operations
                     if !batch.is_empty() {
                         let count = batch.len();
-                        if let Err(e) = backend.batch_write(std::mem::take(&mut batch)).await {
+                        if let Err(e) = Self::write_batch(&*backend, std::mem::take(&mut batch), &failing, &metrics).await {
                             error!("Failed to write final batch on shutdown: {}", e);
                         } else {
                             info!("Flushed {} operations on shutdown", count);
@@ -170,6 +246,26 @@ impl PersistenceManager {
 
         info!("Persistence writer loop exited");
     }
+
+    /// Write a batch, recording whether the backend took it, and how long
+    /// it took if it carried session state
+    async fn write_batch(
+        backend: &dyn StorageBackend,
+        batch: Vec<PersistenceOp>,
+        failing: &AtomicBool,
+        metrics: &OnceLock<Arc<Metrics>>,
+    ) -> Result<()> {
+        let sessions = batch
+            .iter()
+            .any(|op| matches!(op, PersistenceOp::SetSession { .. }));
+        let start = Instant::now();
+        let result = backend.batch_write(batch).await;
+        failing.store(result.is_err(), Ordering::Relaxed);
+        if let Some(metrics) = metrics.get().filter(|_| sessions && result.is_ok()) {
+            metrics.session_checkpoint_written(start.elapsed());
+        }
+        result
+    }
 }
 
 #[cfg(test)]
@@ -244,4 +340,229 @@ mod tests {
         let retained = backend.list_retained().await.unwrap();
         assert_eq!(retained.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_fjall_backend_rate_buckets() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let backend = FjallBackend::open(temp_dir.path()).unwrap();
+
+        let bucket = StoredRateBucket {
+            tokens: 2.5,
+            updated_ms: 1_700_000_000_000,
+        };
+        backend
+            .set_rate_bucket("user:alice", &bucket)
+            .await
+            .unwrap();
+        backend
+            .batch_write(vec![PersistenceOp::SetRateBucket {
+                identity: "user:bob".to_string(),
+                bucket: bucket.clone(),
+            }])
+            .await
+            .unwrap();
+
+        let loaded = backend.load_all().await.unwrap();
+        assert_eq!(loaded.rate_buckets.len(), 2);
+        assert_eq!(loaded.rate_buckets[0].0, "user:alice");
+        assert_eq!(loaded.rate_buckets[0].1.tokens, 2.5);
+
+        backend.delete_rate_bucket("user:alice").await.unwrap();
+        assert_eq!(backend.list_rate_buckets().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fjall_backend_schedule_runs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        {
+            let backend = FjallBackend::open(temp_dir.path()).unwrap();
+            backend
+                .batch_write(vec![PersistenceOp::SetScheduleRun {
+                    name: "heartbeat".to_string(),
+                    run: StoredScheduleRun {
+                        last_run_secs: 1_714_564_800,
+                    },
+                }])
+                .await
+                .unwrap();
+            backend.close().await.unwrap();
+        }
+
+        let backend = FjallBackend::open(temp_dir.path()).unwrap();
+        let loaded = backend.load_all().await.unwrap();
+        assert_eq!(loaded.schedule_runs.len(), 1);
+        assert_eq!(loaded.schedule_runs[0].0, "heartbeat");
+        assert_eq!(loaded.schedule_runs[0].1.last_run_secs, 1_714_564_800);
+    }
+
+    #[tokio::test]
+    async fn test_fjall_backend_delayed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let message = |topic: &str| StoredDelayedMessage {
+            publisher: "device-1".to_string(),
+            publish: StoredPublish {
+                topic: topic.to_string(),
+                payload: b"retry".to_vec(),
+                qos: 1,
+                retain: false,
+                dup: false,
+                packet_id: None,
+                properties: StoredProperties::default(),
+            },
+            received_ms: 1_714_564_800_000,
+            deadline_ms: 1_714_564_830_000,
+        };
+        {
+            let backend = FjallBackend::open(temp_dir.path()).unwrap();
+            backend.set_delayed(7, &message("cmd/a")).await.unwrap();
+            backend
+                .batch_write(vec![
+                    PersistenceOp::SetDelayed {
+                        id: 300,
+                        message: message("cmd/b"),
+                    },
+                    PersistenceOp::DeleteDelayed { id: 7 },
+                ])
+                .await
+                .unwrap();
+            backend.close().await.unwrap();
+        }
+
+        let backend = FjallBackend::open(temp_dir.path()).unwrap();
+        let loaded = backend.load_all().await.unwrap();
+        assert_eq!(loaded.delayed.len(), 1);
+        assert_eq!(loaded.delayed[0].0, 300);
+        assert_eq!(loaded.delayed[0].1.publish.topic, "cmd/b");
+        assert_eq!(loaded.delayed[0].1.deadline_ms, 1_714_564_830_000);
+    }
+
+    #[tokio::test]
+    async fn test_fjall_backend_session_events() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let event = |client_id: &str| StoredSessionEvent {
+            client_id: client_id.to_string(),
+            username: Some("fleet".to_string()),
+            expired_at_ms: 1_714_564_800_000,
+            expiry_interval_secs: 3600,
+            overflowed: false,
+            discarded_messages: 12,
+            discarded_inflight: 1,
+        };
+        {
+            let backend = FjallBackend::open(temp_dir.path()).unwrap();
+            backend
+                .set_session_event("event-1", &event("device-1"))
+                .await
+                .unwrap();
+            backend
+                .batch_write(vec![
+                    PersistenceOp::SetSessionEvent {
+                        event_id: "event-2".to_string(),
+                        event: event("device-2"),
+                    },
+                    PersistenceOp::DeleteSessionEvent {
+                        event_id: "event-1".to_string(),
+                    },
+                ])
+                .await
+                .unwrap();
+            backend.close().await.unwrap();
+        }
+
+        let backend = FjallBackend::open(temp_dir.path()).unwrap();
+        let loaded = backend.load_all().await.unwrap();
+        assert_eq!(loaded.session_events.len(), 1);
+        assert_eq!(loaded.session_events[0].0, "event-2");
+        assert_eq!(loaded.session_events[0].1.client_id, "device-2");
+        assert_eq!(loaded.session_events[0].1.discarded_messages, 12);
+    }
+
+    #[tokio::test]
+    async fn test_fjall_format_migration() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("data");
+        let message = StoredRetainedMessage {
+            topic: "legacy/topic".to_string(),
+            payload: vec![1, 2, 3],
+            qos: 1,
+            properties: StoredProperties::default(),
+            timestamp_secs: 0,
+        };
+        assert_eq!(FjallBackend::check_format(&path).unwrap().version, None);
+
+        // A store of a release before versioning: unheaded records
+        {
+            let keyspace = ::fjall::Config::new(&path).open().unwrap();
+            let retained = keyspace
+                .open_partition("retained", Default::default())
+                .unwrap();
+            let legacy = bincode::encode_to_vec(&message, bincode::config::standard()).unwrap();
+            retained.insert("legacy/topic", legacy).unwrap();
+            keyspace.persist(::fjall::PersistMode::SyncAll).unwrap();
+        }
+        let status = FjallBackend::check_format(&path).unwrap();
+        assert_eq!(status.version, Some(0));
+        assert_eq!(status.pending.len(), 1);
+
+        // Opening backs the data up and migrates it
+        {
+            let backend = FjallBackend::open(&path).unwrap();
+            let loaded = backend.get_retained("legacy/topic").await.unwrap().unwrap();
+            assert_eq!(loaded.payload, vec![1, 2, 3]);
+        }
+        assert!(root.path().join("data.v0.backup").join("version").exists());
+        let status = FjallBackend::check_format(&path).unwrap();
+        assert_eq!(status.version, Some(FORMAT_VERSION));
+        assert!(status.is_current());
+
+        // A store of a newer release is refused
+        {
+            let keyspace = ::fjall::Config::new(&path).open().unwrap();
+            let meta = keyspace.open_partition("meta", Default::default()).unwrap();
+            meta.insert("format_version", [FORMAT_VERSION + 1]).unwrap();
+            keyspace.persist(::fjall::PersistMode::SyncAll).unwrap();
+        }
+        assert!(matches!(
+            FjallBackend::open(&path),
+            Err(PersistenceError::Format(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_memory_backend() {
+        let backend = MemoryBackend::new();
+        let message = StoredRetainedMessage {
+            topic: "test/topic".to_string(),
+            payload: vec![1, 2, 3],
+            qos: 1,
+            properties: StoredProperties::default(),
+            timestamp_secs: 0,
+        };
+        backend.set_retained("test/topic", &message).await.unwrap();
+        backend
+            .batch_write(vec![
+                PersistenceOp::SetRetained {
+                    topic: "test/other".to_string(),
+                    message: message.clone(),
+                },
+                PersistenceOp::DeleteRetained {
+                    topic: "test/topic".to_string(),
+                },
+                PersistenceOp::SetRateBucket {
+                    identity: "user:alice".to_string(),
+                    bucket: StoredRateBucket {
+                        tokens: 1.0,
+                        updated_ms: 0,
+                    },
+                },
+            ])
+            .await
+            .unwrap();
+
+        let loaded = backend.load_all().await.unwrap();
+        assert_eq!(loaded.retained.len(), 1);
+        assert_eq!(loaded.retained[0].0, "test/other");
+        assert_eq!(loaded.rate_buckets.len(), 1);
+        assert!(backend.get_retained("test/topic").await.unwrap().is_none());
+    }
 }
@@ -122,6 +122,54 @@ pub struct StoredRole {
     pub subscribe: Vec<String>,
 }
 
+/// Stored publish rate bucket (keyed by identity)
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct StoredRateBucket {
+    pub tokens: f64,
+    /// Unix timestamp in milliseconds of the last update
+    pub updated_ms: u64,
+}
+
+/// Stored last run of a scheduled publish (keyed by schedule name)
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct StoredScheduleRun {
+    /// Unix timestamp in seconds of the firing time last handled
+    pub last_run_secs: u64,
+}
+
+/// Stored message sequence counter (keyed by topic)
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct StoredSequence {
+    /// Highest number the broker may have assigned on the topic
+    pub reserved: u64,
+}
+
+/// Stored delayed publish (keyed by its ID)
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct StoredDelayedMessage {
+    /// Client that published the message
+    pub publisher: String,
+    pub publish: StoredPublish,
+    /// Unix timestamp in milliseconds when the message was received
+    pub received_ms: u64,
+    /// Unix timestamp in milliseconds when the message is due
+    pub deadline_ms: u64,
+}
+
+/// Stored session expiry event not yet published (keyed by event ID)
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct StoredSessionEvent {
+    pub client_id: String,
+    pub username: Option<String>,
+    /// Unix timestamp in milliseconds when the session was removed
+    pub expired_at_ms: u64,
+    pub expiry_interval_secs: u32,
+    /// Dropped for overflowing its queue rather than for expiring
+    pub overflowed: bool,
+    pub discarded_messages: u64,
+    pub discarded_inflight: u64,
+}
+
 // ============================================================================
 // Conversion implementations
 // ============================================================================
@@ -241,6 +289,7 @@ impl From<StoredWillMessage> for WillMessage {
             qos: QoS::from_u8(stored.qos).unwrap_or_default(),
             retain: stored.retain,
             properties: Properties::from(stored.properties),
+            proxy_identity: None,
         }
     }
 }
@@ -323,6 +372,7 @@ impl From<StoredInflightMessage> for InflightMessage {
             publish: Publish::from(stored.publish),
             qos2_state,
             sent_at: unix_secs_to_instant(stored.sent_at_secs),
+            first_sent_at: unix_secs_to_instant(stored.sent_at_secs),
             retry_count: stored.retry_count,
         }
     }
@@ -381,6 +431,48 @@ impl From<&crate::broker::RetainedMessage> for StoredRetainedMessage {
     }
 }
 
+impl From<&crate::broker::DelayedMessage> for StoredDelayedMessage {
+    fn from(message: &crate::broker::DelayedMessage) -> Self {
+        Self {
+            publisher: message.publisher.to_string(),
+            publish: StoredPublish::from(&message.publish),
+            received_ms: message.received_ms,
+            deadline_ms: message.deadline_ms,
+        }
+    }
+}
+
+impl From<StoredDelayedMessage> for crate::broker::DelayedMessage {
+    fn from(stored: StoredDelayedMessage) -> Self {
+        Self {
+            publisher: stored.publisher.into(),
+            publish: Publish::from(stored.publish),
+            received_ms: stored.received_ms,
+            deadline_ms: stored.deadline_ms,
+        }
+    }
+}
+
+impl From<&crate::session::RateBucket> for StoredRateBucket {
+    fn from(bucket: &crate::session::RateBucket) -> Self {
+        Self {
+            tokens: bucket.tokens,
+            updated_ms: bucket.updated_ms,
+        }
+    }
+}
+
+impl StoredRateBucket {
+    /// Convert back to a rate bucket for the given identity
+    pub fn into_bucket(self, identity: String) -> crate::session::RateBucket {
+        crate::session::RateBucket {
+            identity,
+            tokens: self.tokens,
+            updated_ms: self.updated_ms,
+        }
+    }
+}
+
 /// Data loaded from persistence at startup
 #[derive(Debug, Default)]
 pub struct LoadedData {
@@ -388,4 +480,9 @@ pub struct LoadedData {
     pub sessions: Vec<(String, StoredSession)>,
     pub users: Vec<(String, StoredUser)>,
     pub roles: Vec<(String, StoredRole)>,
+    pub rate_buckets: Vec<(String, StoredRateBucket)>,
+    pub schedule_runs: Vec<(String, StoredScheduleRun)>,
+    pub sequences: Vec<(String, StoredSequence)>,
+    pub delayed: Vec<(u64, StoredDelayedMessage)>,
+    pub session_events: Vec<(String, StoredSessionEvent)>,
 }
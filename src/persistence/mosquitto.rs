@@ -0,0 +1,583 @@
+//! Mosquitto persistence file import.
+//!
+//! Reads `mosquitto.db` (database versions 5 and 6, written by Mosquitto
+//! 1.6 and 2.x) and converts retained messages, persistent sessions with
+//! their subscriptions, and queued outgoing messages into VibeMQ's stored
+//! models, so migrations don't lose device state.
+//!
+//! The file starts with the magic, a CRC (always written as 0 and not
+//! checked) and the big-endian database version, followed by a sequence of
+//! chunks, each with a big-endian `(type, length)` header. Most integer fields are big-endian; Mosquitto writes database IDs
+//! and 64-bit timestamps in host byte order, which is assumed to be
+//! little-endian (x86_64, aarch64).
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::protocol::{Properties, ProtocolVersion};
+
+use super::backend::{PersistenceOp, StorageBackend};
+use super::error::{PersistenceError, Result};
+use super::models::{
+    StoredPendingMessage, StoredProperties, StoredPublish, StoredRetainedMessage, StoredSession,
+    StoredSubscription,
+};
+
+/// File magic: `\0` 0xB5 `\0` "mosquitto db"
+pub const MOSQUITTO_DB_MAGIC: &[u8; 15] = b"\x00\xb5\x00mosquitto db";
+
+const CHUNK_CFG: u32 = 1;
+const CHUNK_MSG_STORE: u32 = 2;
+const CHUNK_CLIENT_MSG: u32 = 3;
+const CHUNK_RETAIN: u32 = 4;
+const CHUNK_SUB: u32 = 5;
+const CHUNK_CLIENT: u32 = 6;
+
+/// Fixed-size part of a message store chunk (PF_msg_store)
+const MSG_STORE_FIXED_LEN: usize = 32;
+/// Fixed-size part of a client chunk (PF_client in v6, PF_client_v5 in v5)
+const CLIENT_FIXED_LEN_V6: usize = 24;
+const CLIENT_FIXED_LEN_V5: usize = 16;
+/// Fixed-size part of a client message chunk (PF_client_msg)
+const CLIENT_MSG_FIXED_LEN: usize = 16;
+/// Fixed-size part of a subscription chunk (PF_sub)
+const SUB_FIXED_LEN: usize = 16;
+
+/// Outgoing direction for client messages (mosq_md_out)
+const DIRECTION_OUT: u8 = 1;
+
+/// Subscription option bits
+const SUB_OPT_NO_LOCAL: u8 = 0x04;
+const SUB_OPT_RETAIN_AS_PUBLISHED: u8 = 0x08;
+
+/// Data read from a Mosquitto persistence file
+#[derive(Debug, Default)]
+pub struct MosquittoImport {
+    /// Database format version
+    pub db_version: u32,
+    /// Retained messages
+    pub retained: Vec<StoredRetainedMessage>,
+    /// Persistent sessions with subscriptions and queued messages
+    pub sessions: Vec<StoredSession>,
+    /// Entries skipped (expired messages, dangling references, unknown chunks)
+    pub skipped: usize,
+}
+
+impl MosquittoImport {
+    /// Write the imported data to a storage backend in one batch
+    pub async fn write_to(&self, backend: &dyn StorageBackend) -> Result<()> {
+        let mut ops = Vec::with_capacity(self.retained.len() + self.sessions.len());
+        for message in &self.retained {
+            ops.push(PersistenceOp::SetRetained {
+                topic: message.topic.clone(),
+                message: message.clone(),
+            });
+        }
+        for session in &self.sessions {
+            ops.push(PersistenceOp::SetSession {
+                client_id: session.client_id.clone(),
+                session: session.clone(),
+            });
+        }
+        backend.batch_write(ops).await?;
+        backend.flush().await
+    }
+}
+
+/// A stored message referenced by retain and client message chunks
+struct StoredMsg {
+    topic: String,
+    payload: Vec<u8>,
+    qos: u8,
+    retain: bool,
+    /// Unix expiry time in seconds (0 = never)
+    expiry_time: i64,
+    properties: StoredProperties,
+}
+
+/// Bounds-checked reader over a chunk body
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.buf.len())
+            .ok_or_else(|| PersistenceError::Corruption("truncated mosquitto.db chunk".into()))?;
+        let slice = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16_be(&mut self) -> Result<u16> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32_be(&mut self) -> Result<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64_le(&mut self) -> Result<u64> {
+        let b = self.take(8)?;
+        Ok(u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn string(&mut self, len: usize) -> Result<String> {
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| PersistenceError::Corruption("invalid UTF-8 in mosquitto.db".into()))
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let slice = &self.buf[self.pos..];
+        self.pos = self.buf.len();
+        slice
+    }
+}
+
+fn now_unix_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Parse a Mosquitto persistence file
+pub fn parse_mosquitto_db(data: &[u8]) -> Result<MosquittoImport> {
+    if data.len() < MOSQUITTO_DB_MAGIC.len() + 8 || &data[..15] != MOSQUITTO_DB_MAGIC {
+        return Err(PersistenceError::Corruption(
+            "not a mosquitto persistence file".into(),
+        ));
+    }
+    // data[15..19] is the unused CRC
+    let db_version = u32::from_be_bytes(data[19..23].try_into().unwrap());
+    if !matches!(db_version, 5 | 6) {
+        return Err(PersistenceError::Deserialize(format!(
+            "unsupported mosquitto.db version {} (only 5 and 6 are supported)",
+            db_version
+        )));
+    }
+
+    let now = now_unix_secs();
+    let mut import = MosquittoImport {
+        db_version,
+        ..Default::default()
+    };
+    let mut messages: HashMap<u64, StoredMsg> = HashMap::new();
+    let mut retain_ids: Vec<u64> = Vec::new();
+    let mut sessions: Vec<StoredSession> = Vec::new();
+    let mut session_index: HashMap<String, usize> = HashMap::new();
+    let mut subs: Vec<(String, StoredSubscription)> = Vec::new();
+    let mut client_msgs: Vec<(String, u64, u8)> = Vec::new();
+
+    let mut file = Reader::new(&data[23..]);
+    while file.pos < file.buf.len() {
+        let chunk_type = file.u32_be()?;
+        let length = file.u32_be()? as usize;
+        let mut chunk = Reader::new(file.take(length)?);
+
+        match chunk_type {
+            CHUNK_CFG => {}
+            CHUNK_MSG_STORE => {
+                if length < MSG_STORE_FIXED_LEN {
+                    return Err(PersistenceError::Corruption("short message chunk".into()));
+                }
+                let store_id = chunk.u64_le()?;
+                let expiry_time = chunk.u64_le()? as i64;
+                let payload_len = chunk.u32_be()? as usize;
+                let _source_mid = chunk.u16_be()?;
+                let source_id_len = chunk.u16_be()? as usize;
+                let source_username_len = chunk.u16_be()? as usize;
+                let topic_len = chunk.u16_be()? as usize;
+                let _source_port = chunk.u16_be()?;
+                let qos = chunk.u8()?;
+                let retain = chunk.u8()? != 0;
+                chunk.take(MSG_STORE_FIXED_LEN - chunk.pos)?;
+
+                chunk.take(source_id_len + source_username_len)?;
+                let topic = chunk.string(topic_len)?;
+                let payload = chunk.take(payload_len)?.to_vec();
+                let rest = chunk.rest();
+                let properties = if rest.is_empty() {
+                    StoredProperties::default()
+                } else {
+                    match Properties::decode(rest) {
+                        Ok((props, _)) => StoredProperties::from(&props),
+                        Err(_) => StoredProperties::default(),
+                    }
+                };
+
+                messages.insert(
+                    store_id,
+                    StoredMsg {
+                        topic,
+                        payload,
+                        qos: qos.min(2),
+                        retain,
+                        expiry_time,
+                        properties,
+                    },
+                );
+            }
+            CHUNK_RETAIN => retain_ids.push(chunk.u64_le()?),
+            CHUNK_CLIENT => {
+                let fixed_len = if db_version == 6 {
+                    CLIENT_FIXED_LEN_V6
+                } else {
+                    CLIENT_FIXED_LEN_V5
+                };
+                if length < fixed_len {
+                    return Err(PersistenceError::Corruption("short client chunk".into()));
+                }
+                let _session_expiry_time = chunk.u64_le()?;
+                let session_expiry_interval = chunk.u32_be()?;
+                let last_mid = chunk.u16_be()?;
+                let id_len = chunk.u16_be()? as usize;
+                let username_len = if db_version == 6 {
+                    let _listener_port = chunk.u16_be()?;
+                    chunk.u16_be()? as usize
+                } else {
+                    0
+                };
+                chunk.take(fixed_len - chunk.pos)?;
+                let client_id = chunk.string(id_len)?;
+                chunk.take(username_len)?;
+
+                // Mosquitto uses u32::MAX for MQTT 3.1.1 persistent (clean_session=false) sessions
+                let protocol_version = if session_expiry_interval == u32::MAX {
+                    ProtocolVersion::V311
+                } else {
+                    ProtocolVersion::V5
+                };
+
+                session_index.insert(client_id.clone(), sessions.len());
+                sessions.push(StoredSession {
+                    client_id,
+                    protocol_version: protocol_version as u8,
+                    session_expiry_interval,
+                    keep_alive: 0,
+                    subscriptions: Vec::new(),
+                    pending_messages: Vec::new(),
+                    inflight_outgoing: Vec::new(),
+                    inflight_incoming: Vec::new(),
+                    will: None,
+                    disconnected_at_secs: Some(now as u64),
+                    next_packet_id: last_mid.wrapping_add(1).max(1),
+                });
+            }
+            CHUNK_SUB => {
+                if length < SUB_FIXED_LEN {
+                    return Err(PersistenceError::Corruption(
+                        "short subscription chunk".into(),
+                    ));
+                }
+                let identifier = chunk.u32_be()?;
+                let id_len = chunk.u16_be()? as usize;
+                let topic_len = chunk.u16_be()? as usize;
+                let qos = chunk.u8()?;
+                let options = chunk.u8()?;
+                chunk.take(SUB_FIXED_LEN - chunk.pos)?;
+                let client_id = chunk.string(id_len)?;
+                let filter = chunk.string(topic_len)?;
+
+                subs.push((
+                    client_id,
+                    StoredSubscription {
+                        filter,
+                        qos: qos.min(2),
+                        no_local: options & SUB_OPT_NO_LOCAL != 0,
+                        retain_as_published: options & SUB_OPT_RETAIN_AS_PUBLISHED != 0,
+                        retain_handling: 0,
+                        subscription_id: (identifier != 0).then_some(identifier),
+                    },
+                ));
+            }
+            CHUNK_CLIENT_MSG => {
+                if length < CLIENT_MSG_FIXED_LEN {
+                    return Err(PersistenceError::Corruption(
+                        "short client message chunk".into(),
+                    ));
+                }
+                let store_id = chunk.u64_le()?;
+                let _mid = chunk.u16_be()?;
+                let id_len = chunk.u16_be()? as usize;
+                let qos = chunk.u8()?;
+                let _state = chunk.u8()?;
+                let _retain_dup = chunk.u8()?;
+                let direction = chunk.u8()?;
+                chunk.take(CLIENT_MSG_FIXED_LEN - chunk.pos)?;
+                let client_id = chunk.string(id_len)?;
+
+                if direction == DIRECTION_OUT {
+                    client_msgs.push((client_id, store_id, qos.min(2)));
+                } else {
+                    // Incoming QoS 2 state cannot be resumed across brokers
+                    import.skipped += 1;
+                }
+            }
+            _ => import.skipped += 1,
+        }
+    }
+
+    // Remaining message expiry, or None if the message has already expired
+    let remaining_expiry = |msg: &StoredMsg| -> Option<Option<u32>> {
+        if msg.expiry_time == 0 {
+            Some(msg.properties.message_expiry_interval)
+        } else if msg.expiry_time > now {
+            Some(Some((msg.expiry_time - now).min(u32::MAX as i64) as u32))
+        } else {
+            None
+        }
+    };
+
+    for store_id in retain_ids {
+        let Some(msg) = messages.get(&store_id) else {
+            import.skipped += 1;
+            continue;
+        };
+        let Some(expiry) = remaining_expiry(msg) else {
+            import.skipped += 1;
+            continue;
+        };
+        let mut properties = msg.properties.clone();
+        properties.message_expiry_interval = expiry;
+        import.retained.push(StoredRetainedMessage {
+            topic: msg.topic.clone(),
+            payload: msg.payload.clone(),
+            qos: msg.qos,
+            properties,
+            timestamp_secs: now as u64,
+        });
+    }
+
+    for (client_id, sub) in subs {
+        match session_index.get(&client_id) {
+            Some(&idx) => sessions[idx].subscriptions.push(sub),
+            None => import.skipped += 1,
+        }
+    }
+
+    for (client_id, store_id, qos) in client_msgs {
+        let (Some(&idx), Some(msg)) = (session_index.get(&client_id), messages.get(&store_id))
+        else {
+            import.skipped += 1;
+            continue;
+        };
+        let Some(expiry) = remaining_expiry(msg) else {
+            import.skipped += 1;
+            continue;
+        };
+        let mut properties = msg.properties.clone();
+        properties.message_expiry_interval = expiry;
+        sessions[idx].pending_messages.push(StoredPendingMessage {
+            publish: StoredPublish {
+                topic: msg.topic.clone(),
+                payload: msg.payload.clone(),
+                qos,
+                retain: msg.retain,
+                dup: false,
+                packet_id: None,
+                properties,
+            },
+            queued_at_secs: now as u64,
+        });
+    }
+
+    import.sessions = sessions;
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::FjallBackend;
+
+    /// Builds mosquitto.db files chunk by chunk
+    struct DbWriter {
+        buf: Vec<u8>,
+    }
+
+    impl DbWriter {
+        fn new(version: u32) -> Self {
+            let mut buf = MOSQUITTO_DB_MAGIC.to_vec();
+            buf.extend_from_slice(&0u32.to_be_bytes()); // CRC
+            buf.extend_from_slice(&version.to_be_bytes());
+            Self { buf }
+        }
+
+        fn chunk(&mut self, chunk_type: u32, body: &[u8]) {
+            self.buf.extend_from_slice(&chunk_type.to_be_bytes());
+            self.buf
+                .extend_from_slice(&(body.len() as u32).to_be_bytes());
+            self.buf.extend_from_slice(body);
+        }
+
+        fn msg_store(&mut self, id: u64, expiry: i64, topic: &str, payload: &[u8], qos: u8) {
+            let mut b = Vec::new();
+            b.extend_from_slice(&id.to_le_bytes());
+            b.extend_from_slice(&expiry.to_le_bytes());
+            b.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            b.extend_from_slice(&0u16.to_be_bytes()); // source_mid
+            b.extend_from_slice(&3u16.to_be_bytes()); // source_id_len
+            b.extend_from_slice(&0u16.to_be_bytes()); // source_username_len
+            b.extend_from_slice(&(topic.len() as u16).to_be_bytes());
+            b.extend_from_slice(&1883u16.to_be_bytes());
+            b.push(qos);
+            b.push(1);
+            b.extend_from_slice(b"pub");
+            b.extend_from_slice(topic.as_bytes());
+            b.extend_from_slice(payload);
+            self.chunk(CHUNK_MSG_STORE, &b);
+        }
+
+        fn client(&mut self, client_id: &str, expiry_interval: u32) {
+            let mut b = Vec::new();
+            b.extend_from_slice(&0i64.to_le_bytes());
+            b.extend_from_slice(&expiry_interval.to_be_bytes());
+            b.extend_from_slice(&41u16.to_be_bytes());
+            b.extend_from_slice(&(client_id.len() as u16).to_be_bytes());
+            b.extend_from_slice(&1883u16.to_be_bytes());
+            b.extend_from_slice(&0u16.to_be_bytes());
+            b.extend_from_slice(&[0; 4]);
+            b.extend_from_slice(client_id.as_bytes());
+            self.chunk(CHUNK_CLIENT, &b);
+        }
+
+        fn sub(&mut self, client_id: &str, filter: &str, qos: u8, options: u8) {
+            let mut b = Vec::new();
+            b.extend_from_slice(&7u32.to_be_bytes());
+            b.extend_from_slice(&(client_id.len() as u16).to_be_bytes());
+            b.extend_from_slice(&(filter.len() as u16).to_be_bytes());
+            b.push(qos);
+            b.push(options);
+            b.extend_from_slice(&[0; 6]);
+            b.extend_from_slice(client_id.as_bytes());
+            b.extend_from_slice(filter.as_bytes());
+            self.chunk(CHUNK_SUB, &b);
+        }
+
+        fn client_msg(&mut self, client_id: &str, store_id: u64, qos: u8) {
+            let mut b = Vec::new();
+            b.extend_from_slice(&store_id.to_le_bytes());
+            b.extend_from_slice(&5u16.to_be_bytes());
+            b.extend_from_slice(&(client_id.len() as u16).to_be_bytes());
+            b.extend_from_slice(&[qos, 0, 0, DIRECTION_OUT]);
+            b.extend_from_slice(client_id.as_bytes());
+            self.chunk(CHUNK_CLIENT_MSG, &b);
+        }
+    }
+
+    fn sample_db() -> Vec<u8> {
+        let mut db = DbWriter::new(6);
+        db.chunk(CHUNK_CFG, &[0; 16]);
+        db.msg_store(1, 0, "status/device1", b"online", 1);
+        db.msg_store(2, 1, "status/expired", b"old", 0);
+        db.msg_store(3, 0, "commands/device1", b"reboot", 1);
+        db.chunk(CHUNK_RETAIN, &1u64.to_le_bytes());
+        db.chunk(CHUNK_RETAIN, &2u64.to_le_bytes());
+        db.client("device1", u32::MAX);
+        db.sub("device1", "commands/device1/#", 1, SUB_OPT_NO_LOCAL);
+        db.sub("ghost", "orphan/#", 0, 0);
+        db.client_msg("device1", 3, 1);
+        db.buf
+    }
+
+    #[test]
+    fn test_parse_mosquitto_db() {
+        let import = parse_mosquitto_db(&sample_db()).unwrap();
+        assert_eq!(import.db_version, 6);
+
+        // Expired retained message is skipped
+        assert_eq!(import.retained.len(), 1);
+        assert_eq!(import.retained[0].topic, "status/device1");
+        assert_eq!(import.retained[0].payload, b"online");
+        assert_eq!(import.retained[0].qos, 1);
+
+        assert_eq!(import.sessions.len(), 1);
+        let session = &import.sessions[0];
+        assert_eq!(session.client_id, "device1");
+        assert_eq!(session.protocol_version, ProtocolVersion::V311 as u8);
+        assert_eq!(session.next_packet_id, 42);
+        assert_eq!(session.subscriptions.len(), 1);
+        assert_eq!(session.subscriptions[0].filter, "commands/device1/#");
+        assert!(session.subscriptions[0].no_local);
+        assert_eq!(session.subscriptions[0].subscription_id, Some(7));
+        assert_eq!(session.pending_messages.len(), 1);
+        assert_eq!(session.pending_messages[0].publish.payload, b"reboot");
+
+        // Expired retained + orphan subscription
+        assert_eq!(import.skipped, 2);
+    }
+
+    /// A mosquitto.db written by Mosquitto 2.0 on shutdown with no retained
+    /// messages or sessions: magic, CRC, version, then the config chunk
+    #[test]
+    fn test_parse_mosquitto_2_header() {
+        let db: &[u8] = &[
+            0x00, 0xb5, 0x00, b'm', b'o', b's', b'q', b'u', b'i', b't', b't', b'o', b' ', b'd',
+            b'b', // magic
+            0x00, 0x00, 0x00, 0x00, // CRC
+            0x00, 0x00, 0x00, 0x06, // version
+            0x00, 0x00, 0x00, 0x01, // CHUNK_CFG
+            0x00, 0x00, 0x00, 0x10, // length
+            0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // last_db_id
+            0x01, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // shutdown, dbid_size
+        ];
+        let import = parse_mosquitto_db(db).unwrap();
+        assert_eq!(import.db_version, 6);
+        assert!(import.retained.is_empty());
+        assert!(import.sessions.is_empty());
+
+        // The header alone, without room for the version
+        assert!(parse_mosquitto_db(&db[..20]).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_files() {
+        assert!(parse_mosquitto_db(b"not a database").is_err());
+
+        let mut old = MOSQUITTO_DB_MAGIC.to_vec();
+        old.extend_from_slice(&0u32.to_be_bytes());
+        old.extend_from_slice(&4u32.to_be_bytes());
+        assert!(matches!(
+            parse_mosquitto_db(&old),
+            Err(PersistenceError::Deserialize(_))
+        ));
+
+        // Truncated chunk
+        let mut db = sample_db();
+        db.truncate(db.len() - 3);
+        assert!(matches!(
+            parse_mosquitto_db(&db),
+            Err(PersistenceError::Corruption(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_import_into_backend() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let backend = FjallBackend::open(temp_dir.path()).unwrap();
+
+        let import = parse_mosquitto_db(&sample_db()).unwrap();
+        import.write_to(&backend).await.unwrap();
+
+        let loaded = backend.load_all().await.unwrap();
+        assert_eq!(loaded.retained.len(), 1);
+        assert_eq!(loaded.sessions.len(), 1);
+        assert_eq!(loaded.sessions[0].1.subscriptions.len(), 1);
+    }
+}
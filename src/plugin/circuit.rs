@@ -0,0 +1,131 @@
+//! Plugin Circuit Breaker
+//!
+//! After `threshold` consecutive failed calls (errors or timeouts) the
+//! breaker opens: calls are answered without asking the plugin until
+//! `cooldown` has passed. Then one trial call goes out; if it succeeds the
+//! breaker closes, otherwise it stays open for another cooldown. A trial
+//! that never finishes (its caller went away) is retried after a cooldown.
+
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    /// Calls are refused until `until`, when one trial goes out
+    Open {
+        until: Instant,
+    },
+}
+
+pub(super) struct CircuitBreaker {
+    /// Consecutive failures that open it (0 = never)
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub(super) fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether a call may go out now
+    pub(super) fn admit(&self) -> bool {
+        let mut state = self.state.lock();
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { until } if Instant::now() >= until => {
+                // The trial; others wait out another cooldown meanwhile
+                *state = State::Open {
+                    until: Instant::now() + self.cooldown,
+                };
+                true
+            }
+            State::Open { .. } => false,
+        }
+    }
+
+    /// Record a successful call; true if that closed the breaker
+    pub(super) fn succeeded(&self) -> bool {
+        let mut state = self.state.lock();
+        let was_open = matches!(*state, State::Open { .. });
+        *state = State::Closed { failures: 0 };
+        was_open
+    }
+
+    /// Record a failed call; true if that opened the breaker
+    pub(super) fn failed(&self) -> bool {
+        let mut state = self.state.lock();
+        let open = State::Open {
+            until: Instant::now() + self.cooldown,
+        };
+        match *state {
+            State::Closed { failures } => {
+                let failures = failures + 1;
+                if self.threshold > 0 && failures >= self.threshold {
+                    *state = open;
+                    true
+                } else {
+                    *state = State::Closed { failures };
+                    false
+                }
+            }
+            // A failed trial (or a call admitted before it opened)
+            State::Open { .. } => {
+                *state = open;
+                false
+            }
+        }
+    }
+
+    pub(super) fn is_open(&self) -> bool {
+        matches!(*self.state.lock(), State::Open { .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(3, Duration::from_millis(50));
+        assert!(!breaker.failed());
+        assert!(!breaker.failed());
+        // A success resets the count
+        assert!(!breaker.succeeded());
+        assert!(!breaker.failed());
+        assert!(!breaker.failed());
+        assert!(breaker.failed());
+        assert!(breaker.is_open());
+        assert!(!breaker.admit());
+
+        // One trial after the cooldown; failing it keeps the breaker open
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.admit());
+        assert!(!breaker.admit());
+        assert!(!breaker.failed());
+        assert!(!breaker.admit());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.admit());
+        assert!(breaker.succeeded());
+        assert!(!breaker.is_open());
+        assert!(breaker.admit());
+
+        // A threshold of 0 never opens
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(1));
+        for _ in 0..100 {
+            assert!(!breaker.failed());
+        }
+        assert!(breaker.admit());
+    }
+}
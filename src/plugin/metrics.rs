@@ -0,0 +1,120 @@
+//! Plugin Metrics
+//!
+//! Prometheus collectors for hook plugins, labelled by plugin name. A plugin
+//! runs in its own process, so its CPU and memory are its own; what the
+//! broker meters is what each call costs it: latency, request and reply
+//! bytes, and outcomes.
+
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts};
+
+use crate::metrics::Metrics;
+
+/// Prometheus collectors for all plugins of a host
+#[derive(Clone)]
+pub struct PluginMetrics {
+    calls: IntCounterVec,
+    duration: HistogramVec,
+    bytes: IntCounterVec,
+    circuit_open: IntGaugeVec,
+    circuit_trips: IntCounterVec,
+}
+
+impl PluginMetrics {
+    /// Create the (unregistered) collectors
+    pub fn new() -> Self {
+        Self {
+            calls: IntCounterVec::new(
+                Opts::new(
+                    "vibemq_plugin_calls_total",
+                    "Plugin hook calls, by plugin, event and result",
+                ),
+                &["plugin", "event", "result"],
+            )
+            .unwrap(),
+            duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "vibemq_plugin_call_duration_seconds",
+                    "Time plugin hook calls took, timeouts included",
+                )
+                .buckets(vec![
+                    0.0005, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+                ]),
+                &["plugin", "event"],
+            )
+            .unwrap(),
+            bytes: IntCounterVec::new(
+                Opts::new(
+                    "vibemq_plugin_bytes_total",
+                    "Bytes of plugin requests and replies, by direction",
+                ),
+                &["plugin", "direction"],
+            )
+            .unwrap(),
+            circuit_open: IntGaugeVec::new(
+                Opts::new(
+                    "vibemq_plugin_circuit_open",
+                    "Whether the plugin's circuit breaker is open",
+                ),
+                &["plugin"],
+            )
+            .unwrap(),
+            circuit_trips: IntCounterVec::new(
+                Opts::new(
+                    "vibemq_plugin_circuit_trips_total",
+                    "Times the plugin's circuit breaker opened",
+                ),
+                &["plugin"],
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Add the collectors to the crate-wide registry
+    pub fn register(&self, metrics: &Metrics) -> prometheus::Result<()> {
+        metrics.register(Box::new(self.calls.clone()))?;
+        metrics.register(Box::new(self.duration.clone()))?;
+        metrics.register(Box::new(self.bytes.clone()))?;
+        metrics.register(Box::new(self.circuit_open.clone()))?;
+        metrics.register(Box::new(self.circuit_trips.clone()))
+    }
+
+    /// `result` is "allow", "deny", "ok" (an event was delivered),
+    /// "failure", "timeout" or "short_circuited"
+    pub(super) fn call(&self, plugin: &str, event: &str, result: &str) {
+        self.calls.with_label_values(&[plugin, event, result]).inc();
+    }
+
+    pub(super) fn call_finished(
+        &self,
+        plugin: &str,
+        event: &str,
+        duration: std::time::Duration,
+        sent: usize,
+        received: usize,
+    ) {
+        self.duration
+            .with_label_values(&[plugin, event])
+            .observe(duration.as_secs_f64());
+        self.bytes
+            .with_label_values(&[plugin, "sent"])
+            .inc_by(sent as u64);
+        self.bytes
+            .with_label_values(&[plugin, "received"])
+            .inc_by(received as u64);
+    }
+
+    pub(super) fn circuit(&self, plugin: &str, open: bool) {
+        self.circuit_open
+            .with_label_values(&[plugin])
+            .set(open as i64);
+        if open {
+            self.circuit_trips.with_label_values(&[plugin]).inc();
+        }
+    }
+}
+
+impl Default for PluginMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,783 @@
+//! External Hook Plugins
+//!
+//! A plugin is an HTTP endpoint the broker consults on the hooks listed in
+//! its [`PluginConfig`]: each call is POSTed as JSON,
+//!
+//! ```json
+//! {"event": "publish_check", "client_id": "sensor-1", "username": "alice",
+//!  "topic": "sensors/1/temp", "qos": 1, "retain": false, "listener": "tls"}
+//! ```
+//!
+//! and for checks the reply decides: 200 or 204 allows (a 200 body of
+//! `{"result": "deny"}` denies), 403 denies, and anything else, a timeout
+//! or a connection failure is handled as `on_failure` says. Replies to
+//! event notifications are ignored.
+//!
+//! The [`PluginHost`] registers as one hooks implementation and runs its
+//! plugins by priority, with their topic and listener filters (see
+//! [`crate::hooks::HookOptions`]). Adding, replacing or removing plugins at
+//! runtime swaps in a new generation of the chain: calls that already
+//! started finish on the old one, which is drained before the change is
+//! reported done.
+//!
+//! Each plugin is metered (see [`PluginMetrics`]) and has a circuit breaker:
+//! after `circuit_failures` consecutive failed calls, checks are answered
+//! as `on_failure` says for `circuit_cooldown` without calling the plugin,
+//! so a plugin that hangs can't stall every connect and publish.
+
+use std::io;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::auth::HttpEndpoint;
+use crate::config::{PluginConfig, PluginEvent, PluginFailure, PluginsConfig};
+use crate::hooks::{
+    current_listener, AccessRequest, AccessStep, CompositeHooks, ConnectionMetadata, HookOptions,
+    HookResult, Hooks,
+};
+use crate::metrics::Metrics;
+use crate::protocol::QoS;
+use crate::topic::validate_topic_filter;
+
+mod circuit;
+mod metrics;
+
+use circuit::CircuitBreaker;
+pub use metrics::PluginMetrics;
+
+/// Request body sent to a plugin
+#[derive(Serialize)]
+struct PluginRequest<'a> {
+    event: PluginEvent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<&'a str>,
+    /// Omitted when absent or not valid UTF-8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<&'a str>,
+    /// Topic, or topic filter for subscribe checks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    topic: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    qos: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retain: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    graceful: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    listener: Option<&'a str>,
+}
+
+impl<'a> PluginRequest<'a> {
+    fn new(event: PluginEvent, client_id: Option<&'a str>) -> Self {
+        Self {
+            event,
+            client_id,
+            username: None,
+            password: None,
+            topic: None,
+            qos: None,
+            retain: None,
+            graceful: None,
+            listener: current_listener(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ReplyResult {
+    Allow,
+    Deny,
+}
+
+/// Optional decision in a 200 reply body
+#[derive(Deserialize)]
+struct Reply {
+    result: Option<ReplyResult>,
+}
+
+/// Why a call got no answer from the plugin
+enum CallError {
+    /// The circuit breaker is open
+    ShortCircuited,
+    TimedOut,
+    Failed(io::Error),
+}
+
+/// A loaded plugin
+pub struct Plugin {
+    config: PluginConfig,
+    endpoint: HttpEndpoint,
+    circuit: CircuitBreaker,
+    metrics: Option<PluginMetrics>,
+    calls: AtomicU64,
+    failures: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+impl Plugin {
+    pub fn new(config: PluginConfig) -> Result<Self, String> {
+        if config.name.is_empty() {
+            return Err("plugin name must not be empty".to_string());
+        }
+        let invalid = |msg: String| format!("plugin '{}': {}", config.name, msg);
+        if config.events.is_empty() {
+            return Err(invalid("events must not be empty".to_string()));
+        }
+        if config.timeout.is_zero() {
+            return Err(invalid("timeout must be greater than 0".to_string()));
+        }
+        for filter in &config.topics {
+            validate_topic_filter(filter)
+                .map_err(|e| invalid(format!("topic filter '{}': {}", filter, e)))?;
+        }
+        let endpoint = HttpEndpoint::parse(&config.url, &config.headers)
+            .map_err(|msg| invalid(format!("url '{}': {}", config.url, msg)))?;
+        Ok(Self {
+            circuit: CircuitBreaker::new(config.circuit_failures, config.circuit_cooldown),
+            config,
+            endpoint,
+            metrics: None,
+            calls: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+        })
+    }
+
+    /// Record the plugin's calls in these collectors
+    pub fn with_metrics(mut self, metrics: PluginMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn options(&self) -> HookOptions {
+        HookOptions {
+            priority: self.config.priority,
+            topics: self.config.topics.clone(),
+            listeners: self.config.listeners.clone(),
+        }
+    }
+
+    fn handles(&self, event: PluginEvent) -> bool {
+        self.config.events.contains(&event)
+    }
+
+    /// POST the request, through the circuit breaker; for checks, whether
+    /// the plugin allowed it
+    async fn call(&self, request: &PluginRequest<'_>) -> Result<bool, CallError> {
+        let event = request.event.as_str();
+        if !self.circuit.admit() {
+            self.record(event, "short_circuited");
+            return Err(CallError::ShortCircuited);
+        }
+        self.calls.fetch_add(1, Ordering::Relaxed);
+
+        let body = serde_json::to_vec(request).map_err(|e| CallError::Failed(e.into()))?;
+        let started = Instant::now();
+        let reply = tokio::time::timeout(self.config.timeout, self.endpoint.post_json(&body)).await;
+        let received = match reply {
+            Ok(Ok((_, ref reply))) => reply.len(),
+            _ => 0,
+        };
+        if let Some(ref metrics) = self.metrics {
+            metrics.call_finished(self.name(), event, started.elapsed(), body.len(), received);
+        }
+
+        let result = match reply {
+            Err(_) => Err(CallError::TimedOut),
+            Ok(Err(e)) => Err(CallError::Failed(e)),
+            Ok(Ok((200, body))) => Ok(!matches!(
+                serde_json::from_slice::<Reply>(&body)
+                    .ok()
+                    .and_then(|r| r.result),
+                Some(ReplyResult::Deny)
+            )),
+            Ok(Ok((204, _))) => Ok(true),
+            Ok(Ok((403, _))) => Ok(false),
+            Ok(Ok((status, _))) => Err(CallError::Failed(io::Error::other(format!(
+                "unexpected status {}",
+                status
+            )))),
+        };
+
+        match result {
+            Ok(allowed) => {
+                let outcome = match (request.event.is_check(), allowed) {
+                    (false, _) => "ok",
+                    (true, true) => "allow",
+                    (true, false) => "deny",
+                };
+                self.record(event, outcome);
+                if self.circuit.succeeded() {
+                    info!("Plugin '{}' answers again, circuit closed", self.name());
+                    if let Some(ref metrics) = self.metrics {
+                        metrics.circuit(self.name(), false);
+                    }
+                }
+            }
+            Err(ref e) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                match e {
+                    CallError::TimedOut => {
+                        self.timeouts.fetch_add(1, Ordering::Relaxed);
+                        self.record(event, "timeout");
+                        warn!("Plugin '{}' {} timed out", self.name(), event);
+                    }
+                    CallError::Failed(e) => {
+                        self.record(event, "failure");
+                        warn!("Plugin '{}' {} failed: {}", self.name(), event, e);
+                    }
+                    // Returned before the call
+                    CallError::ShortCircuited => {}
+                }
+                if self.circuit.failed() {
+                    warn!(
+                        "Plugin '{}' failed {} calls in a row, circuit open for {:?}",
+                        self.name(),
+                        self.config.circuit_failures,
+                        self.config.circuit_cooldown
+                    );
+                    if let Some(ref metrics) = self.metrics {
+                        metrics.circuit(self.name(), true);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn record(&self, event: &str, result: &str) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.call(self.name(), event, result);
+        }
+    }
+
+    async fn check(&self, request: PluginRequest<'_>) -> HookResult<bool> {
+        if !self.handles(request.event) {
+            return Ok(true);
+        }
+        match self.call(&request).await {
+            Ok(allowed) => Ok(allowed),
+            Err(_) => Ok(self.config.failure_mode(request.event) == PluginFailure::Allow),
+        }
+    }
+
+    async fn notify(&self, request: PluginRequest<'_>) {
+        if self.handles(request.event) {
+            let _ = self.call(&request).await;
+        }
+    }
+}
+
+#[async_trait]
+impl Hooks for Plugin {
+    fn hook_name(&self) -> String {
+        format!("plugin:{}", self.config.name)
+    }
+
+    async fn on_authenticate_with_metadata(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        password: Option<&[u8]>,
+        _metadata: &ConnectionMetadata,
+    ) -> HookResult<bool> {
+        self.check(PluginRequest {
+            username,
+            password: password.and_then(|p| std::str::from_utf8(p).ok()),
+            ..PluginRequest::new(PluginEvent::Authenticate, Some(client_id))
+        })
+        .await
+    }
+
+    async fn on_publish_check(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+    ) -> HookResult<bool> {
+        self.check(PluginRequest {
+            username,
+            topic: Some(topic),
+            qos: Some(qos as u8),
+            retain: Some(retain),
+            ..PluginRequest::new(PluginEvent::PublishCheck, Some(client_id))
+        })
+        .await
+    }
+
+    async fn on_subscribe_check(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        filter: &str,
+        qos: QoS,
+    ) -> HookResult<bool> {
+        self.check(PluginRequest {
+            username,
+            topic: Some(filter),
+            qos: Some(qos as u8),
+            ..PluginRequest::new(PluginEvent::SubscribeCheck, Some(client_id))
+        })
+        .await
+    }
+
+    async fn on_client_connected(&self, client_id: &str, username: Option<&str>) {
+        self.notify(PluginRequest {
+            username,
+            ..PluginRequest::new(PluginEvent::ClientConnected, Some(client_id))
+        })
+        .await;
+    }
+
+    async fn on_client_disconnected(&self, client_id: &str, graceful: bool) {
+        self.notify(PluginRequest {
+            graceful: Some(graceful),
+            ..PluginRequest::new(PluginEvent::ClientDisconnected, Some(client_id))
+        })
+        .await;
+    }
+
+    async fn on_message_published(&self, topic: &str, _payload: &[u8], qos: QoS) {
+        self.notify(PluginRequest {
+            topic: Some(topic),
+            qos: Some(qos as u8),
+            ..PluginRequest::new(PluginEvent::MessagePublished, None)
+        })
+        .await;
+    }
+}
+
+/// A plugin as listed by the admin API (headers are left out: they may
+/// hold credentials)
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginStatus {
+    pub name: String,
+    pub url: String,
+    pub events: Vec<PluginEvent>,
+    pub priority: i32,
+    pub topics: Vec<String>,
+    pub listeners: Vec<String>,
+    pub calls: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    pub circuit_open: bool,
+}
+
+/// Outcome of a runtime change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PluginChange {
+    /// A plugin of that name was replaced (or removed)
+    pub replaced: bool,
+    /// Calls in flight on the previous chain finished within the drain
+    /// timeout
+    pub drained: bool,
+}
+
+/// The plugin chain at one point in time
+struct Generation {
+    /// In registration order
+    plugins: Vec<Arc<Plugin>>,
+    chain: CompositeHooks,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+impl Generation {
+    fn new(plugins: Vec<Arc<Plugin>>) -> Self {
+        let mut chain = CompositeHooks::new();
+        for plugin in &plugins {
+            chain.add_with_options(plugin.clone(), plugin.options());
+        }
+        Self {
+            plugins,
+            chain,
+            in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+        }
+    }
+}
+
+/// A hook call in progress on a generation
+struct Call(Arc<Generation>);
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// The loaded plugins, changeable at runtime (see the module docs)
+pub struct PluginHost {
+    current: RwLock<Arc<Generation>>,
+    drain_timeout: Duration,
+    metrics: PluginMetrics,
+}
+
+impl PluginHost {
+    /// Load the configured instances
+    pub fn new(config: &PluginsConfig) -> Result<Self, String> {
+        let metrics = PluginMetrics::new();
+        let plugins = config
+            .instances
+            .iter()
+            .map(|instance| {
+                Plugin::new(instance.clone()).map(|p| Arc::new(p.with_metrics(metrics.clone())))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            current: RwLock::new(Arc::new(Generation::new(plugins))),
+            drain_timeout: config.drain_timeout,
+            metrics,
+        })
+    }
+
+    /// Export the plugins' metrics in the crate-wide registry
+    pub fn register_metrics(&self, metrics: &Metrics) -> prometheus::Result<()> {
+        self.metrics.register(metrics)
+    }
+
+    /// Loaded plugins, in registration order
+    pub fn list(&self) -> Vec<PluginStatus> {
+        self.current
+            .read()
+            .plugins
+            .iter()
+            .map(|plugin| PluginStatus {
+                name: plugin.config.name.clone(),
+                url: plugin.config.url.clone(),
+                events: plugin.config.events.clone(),
+                priority: plugin.config.priority,
+                topics: plugin.config.topics.clone(),
+                listeners: plugin.config.listeners.clone(),
+                calls: plugin.calls.load(Ordering::Relaxed),
+                failures: plugin.failures.load(Ordering::Relaxed),
+                timeouts: plugin.timeouts.load(Ordering::Relaxed),
+                circuit_open: plugin.circuit.is_open(),
+            })
+            .collect()
+    }
+
+    /// Add a plugin, or replace the one with the same name
+    pub async fn upsert(&self, config: PluginConfig) -> Result<PluginChange, String> {
+        let plugin = Arc::new(Plugin::new(config)?.with_metrics(self.metrics.clone()));
+        let (old, replaced) = {
+            let mut current = self.current.write();
+            let mut plugins = current.plugins.clone();
+            let replaced = match plugins.iter().position(|p| p.name() == plugin.name()) {
+                Some(i) => {
+                    plugins[i] = plugin.clone();
+                    true
+                }
+                None => {
+                    plugins.push(plugin.clone());
+                    false
+                }
+            };
+            let old = std::mem::replace(&mut *current, Arc::new(Generation::new(plugins)));
+            (old, replaced)
+        };
+        info!(
+            "Plugin '{}' {} ({})",
+            plugin.name(),
+            if replaced { "replaced" } else { "added" },
+            plugin.config.url
+        );
+        Ok(PluginChange {
+            replaced,
+            drained: self.drain(old).await,
+        })
+    }
+
+    /// Remove a plugin; `None` if there is none of that name
+    pub async fn remove(&self, name: &str) -> Option<PluginChange> {
+        let old = {
+            let mut current = self.current.write();
+            let i = current.plugins.iter().position(|p| p.name() == name)?;
+            let mut plugins = current.plugins.clone();
+            plugins.remove(i);
+            std::mem::replace(&mut *current, Arc::new(Generation::new(plugins)))
+        };
+        info!("Plugin '{}' removed", name);
+        Some(PluginChange {
+            replaced: true,
+            drained: self.drain(old).await,
+        })
+    }
+
+    /// Start a hook call on the current generation
+    fn enter(&self) -> Call {
+        let current = self.current.read();
+        // Counted under the lock, so a change swapping the generation out
+        // sees every call that will use it
+        current.in_flight.fetch_add(1, Ordering::AcqRel);
+        Call(current.clone())
+    }
+
+    /// Wait for the calls in flight on a replaced generation
+    async fn drain(&self, old: Arc<Generation>) -> bool {
+        let drained = tokio::time::timeout(self.drain_timeout, async {
+            loop {
+                let idle = old.idle.notified();
+                if old.in_flight.load(Ordering::Acquire) == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        })
+        .await
+        .is_ok();
+        if !drained {
+            warn!(
+                "Plugin change: {} hook calls still in flight after {:?}",
+                old.in_flight.load(Ordering::Acquire),
+                self.drain_timeout
+            );
+        }
+        drained
+    }
+}
+
+#[async_trait]
+impl Hooks for PluginHost {
+    fn hook_name(&self) -> String {
+        "plugins".to_string()
+    }
+
+    async fn explain_access(&self, request: &AccessRequest<'_>) -> Vec<AccessStep> {
+        let call = self.enter();
+        call.0.chain.explain_access(request).await
+    }
+
+    async fn on_authenticate_with_metadata(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        password: Option<&[u8]>,
+        metadata: &ConnectionMetadata,
+    ) -> HookResult<bool> {
+        let call = self.enter();
+        call.0
+            .chain
+            .on_authenticate_with_metadata(client_id, username, password, metadata)
+            .await
+    }
+
+    async fn on_publish_check(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+    ) -> HookResult<bool> {
+        let call = self.enter();
+        call.0
+            .chain
+            .on_publish_check(client_id, username, topic, qos, retain)
+            .await
+    }
+
+    async fn on_subscribe_check(
+        &self,
+        client_id: &str,
+        username: Option<&str>,
+        filter: &str,
+        qos: QoS,
+    ) -> HookResult<bool> {
+        let call = self.enter();
+        call.0
+            .chain
+            .on_subscribe_check(client_id, username, filter, qos)
+            .await
+    }
+
+    async fn on_client_connected(&self, client_id: &str, username: Option<&str>) {
+        let call = self.enter();
+        call.0.chain.on_client_connected(client_id, username).await;
+    }
+
+    async fn on_client_disconnected(&self, client_id: &str, graceful: bool) {
+        let call = self.enter();
+        call.0
+            .chain
+            .on_client_disconnected(client_id, graceful)
+            .await;
+    }
+
+    async fn on_message_published(&self, topic: &str, payload: &[u8], qos: QoS) {
+        let call = self.enter();
+        call.0.chain.on_message_published(topic, payload, qos).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Deny publishes to topics containing "blocked", after `delay`
+    async fn plugin_service(delay: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 4096];
+                    // The body ends with the JSON object
+                    while !buf.ends_with(b"}") {
+                        let n = stream.read(&mut chunk).await.unwrap();
+                        if n == 0 {
+                            return;
+                        }
+                        buf.extend_from_slice(&chunk[..n]);
+                    }
+                    tokio::time::sleep(delay).await;
+                    let status = if String::from_utf8_lossy(&buf).contains("blocked") {
+                        "403 Forbidden"
+                    } else {
+                        "204 No Content"
+                    };
+                    let reply = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                    let _ = stream.write_all(reply.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}/hook", addr)
+    }
+
+    fn plugin(name: &str, url: &str) -> PluginConfig {
+        PluginConfig {
+            name: name.to_string(),
+            url: url.to_string(),
+            events: vec![PluginEvent::PublishCheck],
+            ..Default::default()
+        }
+    }
+
+    async fn publish(host: &PluginHost, topic: &str) -> bool {
+        host.on_publish_check("c1", None, topic, QoS::AtMostOnce, false)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_plugin_host_runtime_changes() {
+        let url = plugin_service(Duration::ZERO).await;
+        let host = PluginHost::new(&PluginsConfig::default()).unwrap();
+        assert!(publish(&host, "blocked/1").await);
+
+        let change = host.upsert(plugin("acl", &url)).await.unwrap();
+        assert_eq!(
+            change,
+            PluginChange {
+                replaced: false,
+                drained: true
+            }
+        );
+        assert!(!publish(&host, "blocked/1").await);
+        assert!(publish(&host, "open/1").await);
+
+        // Replaced with one that only covers other topics
+        let mut scoped = plugin("acl", &url);
+        scoped.topics = vec!["sensors/#".to_string()];
+        assert!(host.upsert(scoped).await.unwrap().replaced);
+        assert!(publish(&host, "blocked/1").await);
+        assert_eq!(host.list().len(), 1);
+
+        assert!(host.remove("acl").await.is_some());
+        assert!(host.remove("acl").await.is_none());
+        assert!(host.list().is_empty());
+
+        // Unreachable plugins fail closed unless told otherwise
+        let mut down = plugin("down", "http://127.0.0.1:1/hook");
+        host.upsert(down.clone()).await.unwrap();
+        assert!(!publish(&host, "open/1").await);
+        down.on_failure = PluginFailure::Allow;
+        host.upsert(down).await.unwrap();
+        assert!(publish(&host, "open/1").await);
+        assert_eq!(host.list()[0].failures, 1);
+
+        assert!(host.upsert(plugin("", &url)).await.is_err());
+        assert!(host
+            .upsert(plugin("bad", "https://example.com"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_plugin_circuit_breaker() {
+        let metrics = Metrics::new();
+        let host = PluginHost::new(&PluginsConfig::default()).unwrap();
+        host.register_metrics(&metrics).unwrap();
+        let mut down = plugin("down", "http://127.0.0.1:1/hook");
+        down.events.push(PluginEvent::Authenticate);
+        down.circuit_failures = 2;
+        down.on_failure_by_event = [(PluginEvent::PublishCheck, PluginFailure::Allow)].into();
+        host.upsert(down).await.unwrap();
+
+        let metadata = ConnectionMetadata::default();
+        let authenticate = || host.on_authenticate_with_metadata("c1", None, None, &metadata);
+        assert!(publish(&host, "a").await);
+        assert!(!authenticate().await.unwrap());
+        // Open: answered as on_failure says without calling the plugin
+        assert!(publish(&host, "a").await);
+        assert!(!authenticate().await.unwrap());
+        let status = &host.list()[0];
+        assert_eq!((status.calls, status.failures), (2, 2));
+        assert!(status.circuit_open);
+
+        let text = String::from_utf8(metrics.encode_text().unwrap()).unwrap();
+        assert!(text.contains(
+            r#"vibemq_plugin_calls_total{event="publish_check",plugin="down",result="short_circuited"} 1"#
+        ));
+        assert!(text.contains(r#"vibemq_plugin_circuit_open{plugin="down"} 1"#));
+        assert!(text.contains("vibemq_plugin_call_duration_seconds_count"));
+    }
+
+    #[tokio::test]
+    async fn test_plugin_host_drains_calls() {
+        let url = plugin_service(Duration::from_millis(200)).await;
+        let host = Arc::new(
+            PluginHost::new(&PluginsConfig {
+                instances: vec![plugin("slow", &url)],
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+
+        let call = tokio::spawn({
+            let host = host.clone();
+            async move { publish(&host, "blocked/1").await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Removal waits for the call in flight, which still gets its answer
+        let started = std::time::Instant::now();
+        let change = host.remove("slow").await.unwrap();
+        assert!(change.drained);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert!(!call.await.unwrap());
+        assert!(publish(&host, "blocked/1").await);
+    }
+}
@@ -13,11 +13,34 @@
 //!   # View CPU profile in browser with Speedscope UI
 //!   open http://localhost:6060/debug/pprof/profile/ui?seconds=30
 //!
-//!   # Dump heap profile
-//!   curl http://localhost:6060/debug/pprof/heap > heap.pb
+//!   # Live heap and cumulative allocation profiles (pprof protobuf)
+//!   go tool pprof http://localhost:6060/debug/pprof/heap
+//!   go tool pprof -sample_index=alloc_space http://localhost:6060/debug/pprof/allocs
+//!
+//!   # Sample every 2^17 bytes allocated instead (resets both profiles)
+//!   curl http://localhost:6060/debug/pprof/heap/sampling?lg_sample=17
 //!
 //!   # View memory stats
 //!   curl http://localhost:6060/debug/pprof/heap/stats
+//!
+//!   # Tokio runtime metrics over a 5s window (worker busy ratio, queue depths)
+//!   curl http://localhost:6060/debug/pprof/tasks?seconds=5
+//!
+//!   # ... plus blocking pool usage and a dump of where each task is parked
+//!   RUSTFLAGS="--cfg tokio_unstable -C force-frame-pointers=yes" \
+//!     cargo build --release --features pprof
+//!
+//!   # Profiles kept by continuous profiling ([profile_history]): list them,
+//!   # then fetch the one covering a time (Unix seconds or RFC 3339)
+//!   curl http://localhost:6060/debug/pprof/history
+//!   curl http://localhost:6060/debug/pprof/history?ts=1760434200 > profile.pb
+//!
+//!   # Broker metrics in Prometheus text format (when metrics are enabled)
+//!   curl http://localhost:6060/metrics
+//!
+//!   # Read and change the log filter (an EnvFilter string) without a restart
+//!   curl http://localhost:6060/debug/log
+//!   curl -X PUT -d 'warn,vibemq::broker::connection=debug' http://localhost:6060/debug/log
 
 use std::collections::HashMap;
 use std::ffi::CString;
@@ -25,7 +48,7 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
-use http_body_util::Full;
+use http_body_util::{BodyExt, Full, Limited};
 use hyper::body::Bytes;
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
@@ -35,9 +58,13 @@ use parking_lot::RwLock;
 use pprof::protos::Message;
 use tikv_jemalloc_ctl::{epoch, stats};
 use tokio::net::TcpListener;
-use tracing::{error, info};
+use tracing::{debug, error, info};
 use uuid::Uuid;
 
+use crate::config::ProfileHistoryConfig;
+use crate::logging::LogFilter;
+use crate::metrics::{metrics_response, Metrics};
+
 // Embedded Speedscope assets
 static SPEEDSCOPE_INDEX: &str = include_str!("../assets/speedscope/index.html");
 static SPEEDSCOPE_JS: &[u8] = include_bytes!("../assets/speedscope/speedscope.6f107512.js");
@@ -62,9 +89,19 @@ type ProfileStore = Arc<RwLock<HashMap<String, Vec<u8>>>>;
 /// Default profiling server bind address
 pub const DEFAULT_BIND: &str = "127.0.0.1:6060";
 
+/// Largest filter string `/debug/log` accepts
+const MAX_FILTER_SIZE: usize = 4096;
+
 /// Start the profiling HTTP server
+///
+/// With `metrics`, the registry is also served at `/metrics`; with
+/// `log_filter`, the log filter is read and changed at `/debug/log`; with
+/// `history`, the kept profiles are served at `/debug/pprof/history`.
 pub async fn start_server(
     bind: SocketAddr,
+    metrics: Option<Arc<Metrics>>,
+    log_filter: Option<Arc<LogFilter>>,
+    history: Option<Arc<ProfileHistory>>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let listener = TcpListener::bind(bind).await?;
     let profile_store: ProfileStore = Arc::new(RwLock::new(HashMap::new()));
@@ -78,12 +115,23 @@ pub async fn start_server(
         let (stream, _) = listener.accept().await?;
         let io = TokioIo::new(stream);
         let store = profile_store.clone();
+        let metrics = metrics.clone();
+        let log_filter = log_filter.clone();
+        let history = history.clone();
 
         tokio::spawn(async move {
             if let Err(e) = http1::Builder::new()
                 .serve_connection(
                     io,
-                    service_fn(move |req| handle_request(req, store.clone())),
+                    service_fn(move |req| {
+                        handle_request(
+                            req,
+                            store.clone(),
+                            metrics.clone(),
+                            log_filter.clone(),
+                            history.clone(),
+                        )
+                    }),
                 )
                 .await
             {
@@ -96,10 +144,25 @@ pub async fn start_server(
 async fn handle_request(
     req: Request<hyper::body::Incoming>,
     store: ProfileStore,
+    metrics: Option<Arc<Metrics>>,
+    log_filter: Option<Arc<LogFilter>>,
+    history: Option<Arc<ProfileHistory>>,
 ) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    if req.uri().path() == "/debug/log" {
+        return Ok(match log_filter {
+            Some(log_filter) => log_filter_response(req, &log_filter).await,
+            None => not_found_response(),
+        });
+    }
     let path = req.uri().path();
 
     let response = match (req.method(), path) {
+        // Prometheus metrics
+        (&Method::GET, "/metrics") => match metrics {
+            Some(ref metrics) => metrics_response(metrics),
+            None => not_found_response(),
+        },
+
         // Raw profile download (protobuf)
         (&Method::GET, "/debug/pprof/profile") => {
             let seconds = parse_seconds(req.uri().query());
@@ -114,6 +177,12 @@ async fn handle_request(
             }
         }
 
+        // Profiles kept by continuous profiling
+        (&Method::GET, "/debug/pprof/history") => match history {
+            Some(ref history) => history_response(history, req.uri().query()),
+            None => not_found_response(),
+        },
+
         // Profile UI - collect and view in Speedscope
         (&Method::GET, "/debug/pprof/profile/ui") => {
             let seconds = parse_seconds(req.uri().query());
@@ -185,8 +254,20 @@ async fn handle_request(
             }
         }
 
-        // Heap profile dump (jemalloc)
-        (&Method::GET, "/debug/pprof/heap") => match dump_heap_profile() {
+        // Tokio runtime metrics and (with tokio_unstable) a task dump
+        (&Method::GET, "/debug/pprof/tasks") => {
+            let seconds = parse_seconds_or(req.uri().query(), 1);
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(Full::new(Bytes::from(
+                    collect_tasks(Duration::from_secs(seconds)).await,
+                )))
+                .unwrap()
+        }
+
+        // Live heap profile (pprof protobuf, for `go tool pprof`)
+        (&Method::GET, "/debug/pprof/heap") => match collect_heap_pprof(HeapView::InUse) {
             Ok(data) => Response::builder()
                 .status(StatusCode::OK)
                 .header("Content-Type", "application/octet-stream")
@@ -196,6 +277,40 @@ async fn handle_request(
             Err(e) => error_response(&format!("Heap profile error: {}", e)),
         },
 
+        // Allocations since startup or the last sampling reset (pprof protobuf)
+        (&Method::GET, "/debug/pprof/allocs") => match collect_heap_pprof(HeapView::Allocs) {
+            Ok(data) => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "application/octet-stream")
+                .header("Content-Disposition", "attachment; filename=\"allocs.pb\"")
+                .body(Full::new(Bytes::from(data)))
+                .unwrap(),
+            Err(e) => error_response(&format!("Allocation profile error: {}", e)),
+        },
+
+        // Raw jemalloc heap dump (for jeprof)
+        (&Method::GET, "/debug/pprof/heap/raw") => match dump_heap_profile() {
+            Ok(data) => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "application/octet-stream")
+                .header("Content-Disposition", "attachment; filename=\"heap.prof\"")
+                .body(Full::new(Bytes::from(data)))
+                .unwrap(),
+            Err(e) => error_response(&format!("Heap profile error: {}", e)),
+        },
+
+        // Show or change the allocation sampling rate (?lg_sample=N resets profiles)
+        (&Method::GET, "/debug/pprof/heap/sampling") => {
+            match heap_sampling(parse_lg_sample(req.uri().query())) {
+                Ok(text) => Response::builder()
+                    .status(StatusCode::OK)
+                    .header("Content-Type", "text/plain; charset=utf-8")
+                    .body(Full::new(Bytes::from(text)))
+                    .unwrap(),
+                Err(e) => error_response(&format!("Heap sampling error: {}", e)),
+            }
+        }
+
         // Heap stats (plaintext)
         (&Method::GET, "/debug/pprof/heap/stats") => match get_heap_stats() {
             Ok(text) => Response::builder()
@@ -385,9 +500,20 @@ curl http://localhost:6060/debug/pprof/heap/stats
 # Heap: Top allocation sites
 curl http://localhost:6060/debug/pprof/heap/top
 
+# Tokio runtime: worker busy ratio, queue depths (and task dump)
+curl http://localhost:6060/debug/pprof/tasks?seconds=5
+
 # Download protobuf for go tool pprof
 curl http://localhost:6060/debug/pprof/profile?seconds=30 -o profile.pb
-go tool pprof -http=:8080 profile.pb</pre>
+go tool pprof -http=:8080 profile.pb
+
+# Continuous profiling (profile_history): list, then fetch by time
+curl http://localhost:6060/debug/pprof/history
+curl "http://localhost:6060/debug/pprof/history?ts=2025-10-14T09:30:00Z" -o profile.pb
+
+# Log filter: read, and change without a restart
+curl http://localhost:6060/debug/log
+curl -X PUT -d 'warn,vibemq::broker=debug' http://localhost:6060/debug/log</pre>
   </div>
 
   <script>
@@ -441,6 +567,10 @@ go tool pprof -http=:8080 profile.pb</pre>
 }
 
 fn parse_seconds(query: Option<&str>) -> u64 {
+    parse_seconds_or(query, 30)
+}
+
+fn parse_seconds_or(query: Option<&str>, default: u64) -> u64 {
     query
         .and_then(|q| {
             q.split('&')
@@ -448,7 +578,48 @@ fn parse_seconds(query: Option<&str>) -> u64 {
                 .and_then(|p| p.strip_prefix("seconds="))
                 .and_then(|s| s.parse().ok())
         })
-        .unwrap_or(30)
+        .unwrap_or(default)
+}
+
+/// GET: the log filter in force; PUT: apply the body as the new filter
+async fn log_filter_response(
+    req: Request<hyper::body::Incoming>,
+    log_filter: &LogFilter,
+) -> Response<Full<Bytes>> {
+    let text = |status: StatusCode, body: String| {
+        Response::builder()
+            .status(status)
+            .header("Content-Type", "text/plain")
+            .body(Full::new(Bytes::from(body)))
+            .unwrap()
+    };
+    match *req.method() {
+        Method::GET => text(StatusCode::OK, log_filter.current() + "\n"),
+        Method::PUT => {
+            let body = match Limited::new(req.into_body(), MAX_FILTER_SIZE)
+                .collect()
+                .await
+            {
+                Ok(body) => body.to_bytes(),
+                Err(_) => return text(StatusCode::PAYLOAD_TOO_LARGE, "Body too large\n".into()),
+            };
+            let Ok(directives) = std::str::from_utf8(&body) else {
+                return text(StatusCode::BAD_REQUEST, "Filter is not UTF-8\n".into());
+            };
+            match log_filter.set_directives(directives) {
+                Ok(()) => {
+                    info!("Log filter changed to '{}'", log_filter.current());
+                    text(StatusCode::OK, log_filter.current() + "\n")
+                }
+                Err(e) => text(StatusCode::BAD_REQUEST, format!("{}\n", e)),
+            }
+        }
+        _ => Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("Allow", "GET, PUT")
+            .body(Full::new(Bytes::new()))
+            .unwrap(),
+    }
 }
 
 fn error_response(msg: &str) -> Response<Full<Bytes>> {
@@ -646,6 +817,104 @@ async fn collect_top(seconds: u64) -> Result<String, Box<dyn std::error::Error +
     Ok(output)
 }
 
+/// Tokio runtime metrics, with worker activity sampled over `window`
+///
+/// Async stalls don't show up in CPU profiles: a worker blocked on a
+/// synchronous call, or tasks piling up in the queues, look idle there.
+async fn collect_tasks(window: Duration) -> String {
+    use std::fmt::Write;
+
+    let handle = tokio::runtime::Handle::current();
+    let metrics = handle.metrics();
+    let workers = metrics.num_workers();
+    let sample = |worker| {
+        (
+            metrics.worker_total_busy_duration(worker),
+            metrics.worker_park_count(worker),
+        )
+    };
+    let before: Vec<_> = (0..workers).map(sample).collect();
+    tokio::time::sleep(window).await;
+    let after: Vec<_> = (0..workers).map(sample).collect();
+
+    let mut output = String::new();
+    let _ = writeln!(
+        output,
+        "Runtime: {:?}, {} workers",
+        handle.runtime_flavor(),
+        workers
+    );
+    let _ = writeln!(output, "Alive tasks: {}", metrics.num_alive_tasks());
+    let _ = writeln!(
+        output,
+        "Global queue depth: {}",
+        metrics.global_queue_depth()
+    );
+    #[cfg(tokio_unstable)]
+    let _ = writeln!(
+        output,
+        "Blocking pool: {} threads ({} idle), queue depth {}",
+        metrics.num_blocking_threads(),
+        metrics.num_idle_blocking_threads(),
+        metrics.blocking_queue_depth()
+    );
+
+    let _ = writeln!(output, "\nWorkers over {:?}:", window);
+    let _ = write!(output, "{:>6} {:>7} {:>7}", "worker", "busy", "parks");
+    #[cfg(tokio_unstable)]
+    let _ = write!(output, " {:>11}", "local queue");
+    output.push('\n');
+    for (worker, ((busy0, parks0), (busy1, parks1))) in before.iter().zip(&after).enumerate() {
+        let busy = (*busy1 - *busy0).as_secs_f64() / window.as_secs_f64().max(f64::EPSILON);
+        let _ = write!(
+            output,
+            "{:>6} {:>6.1}% {:>7}",
+            worker,
+            (busy * 100.0).min(100.0),
+            parks1 - parks0
+        );
+        #[cfg(tokio_unstable)]
+        let _ = write!(output, " {:>11}", metrics.worker_local_queue_depth(worker));
+        output.push('\n');
+    }
+
+    output.push('\n');
+    output.push_str(&task_dump(&handle).await);
+    output
+}
+
+/// Where each task is parked
+#[cfg(all(
+    tokio_unstable,
+    target_os = "linux",
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")
+))]
+async fn task_dump(handle: &tokio::runtime::Handle) -> String {
+    use std::fmt::Write;
+
+    // Tasks that never yield keep the dump from completing
+    let Ok(dump) = tokio::time::timeout(Duration::from_secs(5), handle.dump()).await else {
+        return "Task dump timed out: a task may be blocking its worker\n".to_string();
+    };
+    let mut output = String::new();
+    let _ = writeln!(output, "Task dump ({} tasks):", dump.tasks().iter().count());
+    for task in dump.tasks().iter() {
+        let _ = writeln!(output, "\nTask {}:\n{}", task.id(), task.trace());
+    }
+    output
+}
+
+#[cfg(not(all(
+    tokio_unstable,
+    target_os = "linux",
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")
+)))]
+async fn task_dump(_handle: &tokio::runtime::Handle) -> String {
+    "Task dump unavailable: build with RUSTFLAGS=\"--cfg tokio_unstable\" \
+     (Linux on x86, x86_64 or aarch64)\n"
+        .to_string()
+}
+
 fn dump_heap_profile() -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
     // Create temp file for jemalloc to write to
     let path = std::env::temp_dir().join(format!("vibemq_heap_{}.prof", std::process::id()));
@@ -830,8 +1099,8 @@ fn get_heap_top() -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
             "No allocation site data available.\n\n\
              To see allocation call stacks:\n\
              1. Set MALLOC_CONF=prof:true,lg_prof_sample:17 before running\n\
-             2. Use: jeprof --text /path/to/binary /debug/pprof/heap\n\
-             3. Or:  jeprof --svg /path/to/binary /debug/pprof/heap > heap.svg\n",
+             2. Use: jeprof --text /path/to/binary /debug/pprof/heap/raw\n\
+             3. Or:  go tool pprof -top http://localhost:6060/debug/pprof/heap\n",
         );
     } else {
         output.push_str(&format!(
@@ -872,13 +1141,346 @@ fn get_heap_top() -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
 
         output.push_str(
             "\nNote: Stack traces shown as addresses. For symbolicated output:\n\
-             jeprof --text $(which vibemq) http://localhost:6060/debug/pprof/heap\n",
+             go tool pprof -top http://localhost:6060/debug/pprof/heap\n",
         );
     }
 
     Ok(output)
 }
 
+/// Parse `lg_sample=N` from query string
+fn parse_lg_sample(query: Option<&str>) -> Option<usize> {
+    query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("lg_sample="))
+        .and_then(|value| value.parse().ok())
+}
+
+/// Report the allocation sampling rate, changing it first if requested
+///
+/// jemalloc can only change the rate through `prof.reset`, which also
+/// discards the samples collected so far.
+fn heap_sampling(
+    lg_sample: Option<usize>,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(mut lg_sample) = lg_sample {
+        if lg_sample > 40 {
+            return Err("lg_sample must be at most 40".into());
+        }
+        // SAFETY: prof.reset takes an optional size_t
+        let ret = unsafe {
+            let name = CString::new("prof.reset")?;
+            tikv_jemalloc_sys::mallctl(
+                name.as_ptr(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &mut lg_sample as *mut usize as *mut _,
+                std::mem::size_of::<usize>(),
+            )
+        };
+        if ret != 0 {
+            return Err(format!("prof.reset failed (code {})", ret).into());
+        }
+        info!("Heap profiles reset, sampling every 2^{} bytes", lg_sample);
+    }
+
+    let mut current: usize = 0;
+    let mut len = std::mem::size_of::<usize>();
+    // SAFETY: prof.lg_sample is a readable size_t
+    let ret = unsafe {
+        let name = CString::new("prof.lg_sample")?;
+        tikv_jemalloc_sys::mallctl(
+            name.as_ptr(),
+            &mut current as *mut usize as *mut _,
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(format!("jemalloc heap profiling not available (code {})", ret).into());
+    }
+
+    Ok(format!(
+        "lg_sample: {} (one sample per {} allocated on average)\n",
+        current,
+        format_bytes(1u64 << current)
+    ))
+}
+
+/// Which counts of a jemalloc heap profile to export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HeapView {
+    /// Memory still allocated
+    InUse,
+    /// Everything allocated since the profile was last reset (needs `prof_accum`)
+    Allocs,
+}
+
+/// One sampled allocation stack from a jemalloc `heap_v2` dump
+#[derive(Debug, Default, PartialEq)]
+struct HeapSite {
+    /// Return addresses, innermost first
+    stack: Vec<u64>,
+    live_objects: u64,
+    live_bytes: u64,
+    alloc_objects: u64,
+    alloc_bytes: u64,
+}
+
+/// A parsed jemalloc `heap_v2` dump
+#[derive(Debug, Default, PartialEq)]
+struct JemallocHeap {
+    /// Average bytes between samples
+    sample_period: u64,
+    sites: Vec<HeapSite>,
+}
+
+/// A symbolized stack frame
+struct Frame {
+    function: String,
+    file: String,
+    line: i64,
+}
+
+/// Parse the text dump written by jemalloc's `prof.dump`
+fn parse_jemalloc_heap(text: &str) -> Result<JemallocHeap, String> {
+    let mut lines = text.lines();
+    let header = lines.next().unwrap_or_default();
+    let sample_period = header
+        .strip_prefix("heap_v2/")
+        .and_then(|period| period.trim().parse().ok())
+        .ok_or_else(|| format!("unsupported heap profile header: {:?}", header))?;
+
+    let mut heap = JemallocHeap {
+        sample_period,
+        sites: Vec::new(),
+    };
+    let mut current: Option<HeapSite> = None;
+    for line in lines {
+        if line.starts_with("MAPPED_LIBRARIES") {
+            break;
+        }
+        if let Some(addrs) = line.strip_prefix('@') {
+            heap.sites.extend(current.take());
+            let stack = addrs
+                .split_whitespace()
+                .filter_map(|addr| u64::from_str_radix(addr.trim_start_matches("0x"), 16).ok())
+                .collect();
+            current = Some(HeapSite {
+                stack,
+                ..Default::default()
+            });
+        } else if let Some(counts) = line.trim().strip_prefix("t*:") {
+            // "t*: <live objects>: <live bytes> [<alloc objects>: <alloc bytes>]"
+            // The first one (before any stack) is the process total.
+            let Some(site) = current.as_mut() else {
+                continue;
+            };
+            let numbers: Vec<u64> = counts
+                .split([':', '[', ']'])
+                .filter_map(|n| n.trim().parse().ok())
+                .collect();
+            if let [live_objects, live_bytes, alloc_objects, alloc_bytes] = numbers[..] {
+                site.live_objects = live_objects;
+                site.live_bytes = live_bytes;
+                site.alloc_objects = alloc_objects;
+                site.alloc_bytes = alloc_bytes;
+            }
+        }
+    }
+    heap.sites.extend(current);
+    Ok(heap)
+}
+
+/// Estimate real counts from sampled ones (same correction as jeprof)
+fn unsample(objects: u64, bytes: u64, sample_period: u64) -> (i64, i64) {
+    if objects == 0 || sample_period == 0 {
+        return (objects as i64, bytes as i64);
+    }
+    let ratio = bytes as f64 / objects as f64;
+    let scale = 1.0 / (1.0 - (-ratio / sample_period as f64).exp());
+    (
+        (objects as f64 * scale).round() as i64,
+        (bytes as f64 * scale).round() as i64,
+    )
+}
+
+/// Build a pprof profile from a jemalloc heap dump
+fn heap_pprof(
+    heap: &JemallocHeap,
+    view: HeapView,
+    mut resolve: impl FnMut(u64) -> Vec<Frame>,
+) -> pprof::protos::Profile {
+    use pprof::protos::{Function, Line, Location, Profile, Sample, ValueType};
+
+    let mut strings: Vec<String> = vec![String::new()];
+    let mut string_ids: HashMap<String, i64> = HashMap::new();
+    let mut intern = |s: &str| -> i64 {
+        if let Some(&id) = string_ids.get(s) {
+            return id;
+        }
+        let id = strings.len() as i64;
+        strings.push(s.to_string());
+        string_ids.insert(s.to_string(), id);
+        id
+    };
+
+    let (objects, space) = match view {
+        HeapView::InUse => ("inuse_objects", "inuse_space"),
+        HeapView::Allocs => ("alloc_objects", "alloc_space"),
+    };
+    let sample_type = vec![
+        ValueType {
+            ty: intern(objects),
+            unit: intern("count"),
+        },
+        ValueType {
+            ty: intern(space),
+            unit: intern("bytes"),
+        },
+    ];
+    let period_type = Some(ValueType {
+        ty: intern("space"),
+        unit: intern("bytes"),
+    });
+
+    let mut locations: Vec<Location> = Vec::new();
+    let mut location_ids: HashMap<u64, Option<u64>> = HashMap::new();
+    let mut functions: Vec<Function> = Vec::new();
+    let mut function_ids: HashMap<(i64, i64), u64> = HashMap::new();
+    let mut samples = Vec::new();
+
+    for site in &heap.sites {
+        let (count, bytes) = match view {
+            HeapView::InUse => (site.live_objects, site.live_bytes),
+            HeapView::Allocs => (site.alloc_objects, site.alloc_bytes),
+        };
+        if bytes == 0 {
+            continue;
+        }
+        let (count, bytes) = unsample(count, bytes, heap.sample_period);
+
+        let mut location_id = Vec::with_capacity(site.stack.len());
+        for (depth, &addr) in site.stack.iter().enumerate() {
+            if let Some(&id) = location_ids.get(&addr) {
+                location_id.extend(id);
+                continue;
+            }
+            // Return addresses point after the call; look up the call itself
+            let lookup = if depth == 0 {
+                addr
+            } else {
+                addr.saturating_sub(1)
+            };
+            let frames = resolve(lookup);
+            // Leave out the allocator's own frames
+            if frames.iter().any(|f| is_allocator_frame(&f.function)) {
+                location_ids.insert(addr, None);
+                continue;
+            }
+            let line = frames
+                .into_iter()
+                .map(|frame| {
+                    let key = (intern(&frame.function), intern(&frame.file));
+                    let function_id = *function_ids.entry(key).or_insert_with(|| {
+                        let id = functions.len() as u64 + 1;
+                        functions.push(Function {
+                            id,
+                            name: key.0,
+                            system_name: key.0,
+                            filename: key.1,
+                            start_line: 0,
+                        });
+                        id
+                    });
+                    Line {
+                        function_id,
+                        line: frame.line,
+                    }
+                })
+                .collect();
+            let id = locations.len() as u64 + 1;
+            locations.push(Location {
+                id,
+                mapping_id: 0,
+                address: addr,
+                line,
+                is_folded: false,
+            });
+            location_ids.insert(addr, Some(id));
+            location_id.push(id);
+        }
+
+        samples.push(Sample {
+            location_id,
+            value: vec![count, bytes],
+            label: Vec::new(),
+        });
+    }
+
+    let default_sample_type = sample_type[1].ty;
+    Profile {
+        sample_type,
+        sample: samples,
+        location: locations,
+        function: functions,
+        string_table: strings,
+        period_type,
+        period: heap.sample_period as i64,
+        default_sample_type,
+        ..Default::default()
+    }
+}
+
+/// Whether a symbol belongs to jemalloc itself
+fn is_allocator_frame(function: &str) -> bool {
+    function.contains("jemalloc") || function.contains("_rjem_") || function.starts_with("je_")
+}
+
+/// Resolve an address to its (possibly inlined) frames, innermost first
+fn resolve_frames(addr: u64) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    backtrace::resolve(addr as *mut std::ffi::c_void, |symbol| {
+        frames.push(Frame {
+            function: symbol
+                .name()
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("{:#x}", addr)),
+            file: symbol
+                .filename()
+                .map(|file| file.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            line: symbol.lineno().map_or(0, i64::from),
+        });
+    });
+    if frames.is_empty() {
+        frames.push(Frame {
+            function: format!("{:#x}", addr),
+            file: String::new(),
+            line: 0,
+        });
+    }
+    frames
+}
+
+/// Dump the jemalloc heap profile and convert it to pprof protobuf
+fn collect_heap_pprof(view: HeapView) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let raw = dump_heap_profile()?;
+    let heap = parse_jemalloc_heap(&String::from_utf8_lossy(&raw))?;
+    let profile = heap_pprof(&heap, view, resolve_frames);
+
+    let mut buf = Vec::new();
+    profile.encode(&mut buf)?;
+    info!(
+        "{:?} heap profile converted ({} sites, {} bytes)",
+        view,
+        profile.sample.len(),
+        buf.len()
+    );
+    Ok(buf)
+}
+
 fn format_bytes(bytes: u64) -> String {
     if bytes >= 1024 * 1024 * 1024 {
         format!("{:.1} GB", bytes as f64 / 1024.0 / 1024.0 / 1024.0)
@@ -936,6 +1538,195 @@ fn symbolicate_addresses(
     symbols
 }
 
+// ============================================================================
+// Profile History (profile_history config section)
+// ============================================================================
+
+/// Short CPU profiles collected on an interval, kept on disk as a ring
+///
+/// Each profile is `cpu-<unix seconds it started>.pb` in the configured
+/// directory; once there are more than `keep`, the oldest are deleted.
+/// While a round samples, the pprof profiler is taken, so an on-demand
+/// `/debug/pprof/profile` fails until the round ends (and a round is
+/// skipped while an on-demand profile or `--profile-output` holds it).
+pub struct ProfileHistory {
+    config: ProfileHistoryConfig,
+}
+
+impl ProfileHistory {
+    /// Open the history, creating its directory
+    pub fn new(config: &ProfileHistoryConfig) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&config.dir)?;
+        Ok(Self {
+            config: config.clone(),
+        })
+    }
+
+    /// Collect a profile every interval, for as long as the process runs
+    pub fn spawn(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        info!(
+            "Continuous profiling: {:?} every {:?} at {}Hz, keeping {} in {:?}",
+            self.config.duration,
+            self.config.interval,
+            self.config.frequency,
+            self.config.keep,
+            self.config.dir
+        );
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let started = unix_now();
+                match self.collect().await {
+                    Ok(Some(data)) => {
+                        if let Err(e) = self.store(started, &data) {
+                            error!("Failed to store profile in {:?}: {}", self.config.dir, e);
+                        }
+                    }
+                    // Idle the whole round
+                    Ok(None) => {}
+                    Err(e) => debug!("Profile round skipped: {}", e),
+                }
+            }
+        })
+    }
+
+    async fn collect(&self) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error + Send + Sync>> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(self.config.frequency)
+            .build()?;
+
+        tokio::time::sleep(self.config.duration).await;
+
+        let report = guard.report().build()?;
+        if report.data.is_empty() {
+            return Ok(None);
+        }
+        let mut buf = Vec::new();
+        report.pprof()?.encode(&mut buf)?;
+        Ok(Some(buf))
+    }
+
+    /// Write a profile, then delete the oldest beyond `keep`
+    fn store(&self, started: u64, data: &[u8]) -> std::io::Result<()> {
+        let path = self.path(started);
+        // Readers never see a partial file
+        let tmp = path.with_extension("pb.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, &path)?;
+
+        let kept = self.list();
+        let excess = kept.len().saturating_sub(self.config.keep);
+        for ts in &kept[..excess] {
+            std::fs::remove_file(self.path(*ts))?;
+        }
+        Ok(())
+    }
+
+    fn path(&self, started: u64) -> std::path::PathBuf {
+        self.config.dir.join(format!("cpu-{}.pb", started))
+    }
+
+    /// Start times (Unix seconds) of the profiles kept, oldest first
+    pub fn list(&self) -> Vec<u64> {
+        let Ok(entries) = std::fs::read_dir(&self.config.dir) else {
+            return Vec::new();
+        };
+        let mut kept: Vec<u64> = entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name();
+                name.to_str()?
+                    .strip_prefix("cpu-")?
+                    .strip_suffix(".pb")?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        kept.sort_unstable();
+        kept
+    }
+
+    /// The profile covering `ts`: the newest started at or before it, if
+    /// `ts` is within an interval of its start
+    pub fn find(&self, ts: u64) -> Option<(u64, Vec<u8>)> {
+        let started = self.list().into_iter().rev().find(|&s| s <= ts)?;
+        if ts - started >= self.config.interval.as_secs().max(1) {
+            return None;
+        }
+        let data = std::fs::read(self.path(started)).ok()?;
+        Some((started, data))
+    }
+}
+
+fn format_rfc3339(secs: u64) -> String {
+    humantime_serde::re::humantime::format_rfc3339_seconds(
+        std::time::UNIX_EPOCH + Duration::from_secs(secs),
+    )
+    .to_string()
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// `ts` from the query, as Unix seconds or an RFC 3339 timestamp
+fn parse_ts(query: Option<&str>) -> Option<Result<u64, String>> {
+    let value = query?.split('&').find_map(|p| p.strip_prefix("ts="))?;
+    if let Ok(secs) = value.parse() {
+        return Some(Ok(secs));
+    }
+    // `:` arrives percent-encoded from most clients
+    let value = value.replace("%3A", ":").replace("%3a", ":");
+    Some(
+        humantime_serde::re::humantime::parse_rfc3339_weak(&value)
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .ok_or_else(|| format!("Invalid ts '{}': expected Unix seconds or RFC 3339", value)),
+    )
+}
+
+/// GET /debug/pprof/history: the profiles kept, or with `ts`, the one
+/// covering that time
+fn history_response(history: &ProfileHistory, query: Option<&str>) -> Response<Full<Bytes>> {
+    let text = |status: StatusCode, body: String| {
+        Response::builder()
+            .status(status)
+            .header("Content-Type", "text/plain")
+            .body(Full::new(Bytes::from(body)))
+            .unwrap()
+    };
+    match parse_ts(query) {
+        None => {
+            let mut body = String::new();
+            for ts in history.list() {
+                body.push_str(&format!("{}  {}\n", ts, format_rfc3339(ts)));
+            }
+            text(StatusCode::OK, body)
+        }
+        Some(Err(e)) => text(StatusCode::BAD_REQUEST, e + "\n"),
+        Some(Ok(ts)) => match history.find(ts) {
+            Some((started, data)) => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "application/octet-stream")
+                .header(
+                    "Content-Disposition",
+                    format!("attachment; filename=\"cpu-{}.pb\"", started),
+                )
+                .body(Full::new(Bytes::from(data)))
+                .unwrap(),
+            None => text(
+                StatusCode::NOT_FOUND,
+                format!("No profile covers {}\n", format_rfc3339(ts)),
+            ),
+        },
+    }
+}
+
 // ============================================================================
 // Continuous Profiling (for --profile-output flag)
 // ============================================================================
@@ -1317,3 +2108,122 @@ pub fn check_jemalloc_profiling() {
         info!("jemalloc config.prof = {} (ret={})", cfg_val, cfg_ret);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "heap_v2/524288\n\
+  t*: 3: 3072 [5: 8192]\n\
+  t0: 3: 3072 [5: 8192]\n\
+@ 0x10 0x20 0x30\n\
+  t*: 2: 2048 [4: 4096]\n\
+  t0: 2: 2048 [4: 4096]\n\
+@ 0x10 0x40\n\
+  t*: 0: 0 [1: 4096]\n\
+\n\
+MAPPED_LIBRARIES:\n\
+00400000-00500000 r-xp 00000000 08:01 1 /usr/bin/vibemq\n";
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_collect_tasks() {
+        let output = collect_tasks(Duration::from_millis(20)).await;
+        assert!(output.starts_with("Runtime: MultiThread, 2 workers\n"));
+        assert!(output.contains("Workers over 20ms:"));
+        assert!(output.contains("\n     1 "));
+        assert!(output.contains("Task dump"));
+    }
+
+    #[test]
+    fn test_parse_jemalloc_heap() {
+        let heap = parse_jemalloc_heap(DUMP).unwrap();
+        assert_eq!(heap.sample_period, 524288);
+        assert_eq!(heap.sites.len(), 2);
+        assert_eq!(heap.sites[0].stack, vec![0x10, 0x20, 0x30]);
+        assert_eq!(
+            (heap.sites[0].live_objects, heap.sites[0].live_bytes),
+            (2, 2048)
+        );
+        assert_eq!(
+            (heap.sites[1].alloc_objects, heap.sites[1].alloc_bytes),
+            (1, 4096)
+        );
+        assert!(parse_jemalloc_heap("heap_v1/1\n").is_err());
+    }
+
+    #[test]
+    fn test_heap_pprof_views() {
+        let heap = parse_jemalloc_heap(DUMP).unwrap();
+        let resolve = |addr: u64| {
+            vec![Frame {
+                function: format!("f{:x}", addr),
+                file: "lib.rs".to_string(),
+                line: 1,
+            }]
+        };
+
+        let inuse = heap_pprof(&heap, HeapView::InUse, resolve);
+        // The second site has nothing live
+        assert_eq!(inuse.sample.len(), 1);
+        assert_eq!(inuse.sample[0].location_id.len(), 3);
+        assert_eq!(
+            inuse.string_table[inuse.sample_type[1].ty as usize],
+            "inuse_space"
+        );
+        // Sampled counts are scaled up to estimates
+        assert!(inuse.sample[0].value[1] >= 2048);
+
+        let allocs = heap_pprof(&heap, HeapView::Allocs, resolve);
+        assert_eq!(allocs.sample.len(), 2);
+        // The shared frame is one location
+        assert_eq!(allocs.location.len(), 4);
+        assert_eq!(
+            allocs.string_table[allocs.sample_type[0].ty as usize],
+            "alloc_objects"
+        );
+    }
+
+    #[test]
+    fn test_parse_lg_sample() {
+        assert_eq!(parse_lg_sample(Some("lg_sample=17")), Some(17));
+        assert_eq!(parse_lg_sample(Some("x=1&lg_sample=20")), Some(20));
+        assert_eq!(parse_lg_sample(Some("lg_sample=x")), None);
+        assert_eq!(parse_lg_sample(None), None);
+    }
+
+    #[test]
+    fn test_profile_history_ring() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = ProfileHistory::new(&ProfileHistoryConfig {
+            enabled: true,
+            dir: dir.path().to_path_buf(),
+            interval: Duration::from_secs(60),
+            keep: 3,
+            ..Default::default()
+        })
+        .unwrap();
+
+        for ts in [1000, 1060, 1120, 1180] {
+            history.store(ts, format!("p{}", ts).as_bytes()).unwrap();
+        }
+        // The oldest went
+        assert_eq!(history.list(), vec![1060, 1120, 1180]);
+
+        assert_eq!(history.find(1130), Some((1120, b"p1120".to_vec())));
+        assert_eq!(history.find(1060).unwrap().0, 1060);
+        assert!(history.find(1010).is_none());
+        // Past the interval of the newest
+        assert!(history.find(1240).is_none());
+    }
+
+    #[test]
+    fn test_parse_ts() {
+        assert_eq!(parse_ts(Some("ts=1760434200")), Some(Ok(1760434200)));
+        assert_eq!(
+            parse_ts(Some("x=1&ts=2025-10-14T09%3A30%3A00Z")),
+            Some(Ok(1760434200))
+        );
+        assert!(matches!(parse_ts(Some("ts=yesterday")), Some(Err(_))));
+        assert_eq!(parse_ts(None), None);
+    }
+}
@@ -0,0 +1,148 @@
+//! Client identity behind a proxy
+//!
+//! The parts of a PROXY header that say who a message really came from:
+//! the original client address, the SNI it asked for and the CN of a
+//! certificate the proxy verified. Wills and retained messages keep the
+//! identity of their publisher, publish ACL checks see it, and listeners
+//! can stamp it on messages as an `mqtt-forwarded-for` user property.
+
+use std::fmt::Write;
+
+use crate::protocol::Properties;
+
+use super::ProxyInfo;
+
+/// User property carrying a message's proxied origin
+pub const FORWARDED_FOR_PROPERTY: &str = "mqtt-forwarded-for";
+
+/// Who a proxied client is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyIdentity {
+    /// Original client address
+    pub client_addr: String,
+    /// Server Name Indication the client sent
+    pub sni: Option<String>,
+    /// CN of the client certificate, when the proxy verified it
+    pub client_cert_cn: Option<String>,
+}
+
+impl ProxyIdentity {
+    pub fn from_info(info: &ProxyInfo) -> Self {
+        let tls = info.tls_info.as_ref();
+        Self {
+            client_addr: info.client_addr.to_string(),
+            sni: tls.and_then(|tls| tls.sni.clone()),
+            client_cert_cn: tls
+                .filter(|tls| tls.client_cert_verified)
+                .and_then(|tls| tls.client_cert_cn.clone()),
+        }
+    }
+
+    /// The identity as a `Forwarded` header value (RFC 7239 style)
+    ///
+    /// e.g. `for="203.0.113.7:51234";sni="sensors.example.com";cn="dev-42"`
+    pub fn forwarded_for(&self) -> String {
+        let mut value = String::new();
+        let mut pair = |key: &str, v: &str| {
+            if !value.is_empty() {
+                value.push(';');
+            }
+            let _ = write!(value, "{}=\"", key);
+            for c in v.chars() {
+                if c == '"' || c == '\\' {
+                    value.push('\\');
+                }
+                value.push(c);
+            }
+            value.push('"');
+        };
+        pair("for", &self.client_addr);
+        if let Some(ref sni) = self.sni {
+            pair("sni", sni);
+        }
+        if let Some(ref cn) = self.client_cert_cn {
+            pair("cn", cn);
+        }
+        value
+    }
+
+    /// Stamp the identity on a message
+    ///
+    /// Replaces any `mqtt-forwarded-for` the client set itself, so
+    /// consumers can trust the property.
+    pub fn stamp(&self, properties: &mut Properties) {
+        properties
+            .user_properties
+            .retain(|(key, _)| key != FORWARDED_FOR_PROPERTY);
+        properties
+            .user_properties
+            .push((FORWARDED_FOR_PROPERTY.to_string(), self.forwarded_for()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::{ProxyTlsInfo, ProxyVersion};
+
+    fn info(tls_info: Option<ProxyTlsInfo>) -> ProxyInfo {
+        ProxyInfo {
+            client_addr: "203.0.113.7:51234"
+                .parse::<std::net::SocketAddr>()
+                .unwrap()
+                .into(),
+            server_addr: None,
+            tls_info,
+            version: ProxyVersion::V2,
+            tlvs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_forwarded_for() {
+        let identity = ProxyIdentity::from_info(&info(None));
+        assert_eq!(identity.forwarded_for(), "for=\"203.0.113.7:51234\"");
+
+        let mut tls = ProxyTlsInfo {
+            sni: Some("sensors.example.com".to_string()),
+            client_cert_cn: Some("dev \"42\"".to_string()),
+            ..Default::default()
+        };
+        // Unverified CNs aren't an identity
+        let identity = ProxyIdentity::from_info(&info(Some(tls.clone())));
+        assert_eq!(identity.client_cert_cn, None);
+
+        tls.client_cert_verified = true;
+        let identity = ProxyIdentity::from_info(&info(Some(tls)));
+        assert_eq!(
+            identity.forwarded_for(),
+            "for=\"203.0.113.7:51234\";sni=\"sensors.example.com\";cn=\"dev \\\"42\\\"\""
+        );
+    }
+
+    #[test]
+    fn test_stamp_replaces_client_value() {
+        let identity = ProxyIdentity::from_info(&info(None));
+        let mut properties = Properties {
+            user_properties: vec![
+                (
+                    FORWARDED_FOR_PROPERTY.to_string(),
+                    "for=\"10.0.0.1\"".to_string(),
+                ),
+                ("unit".to_string(), "c".to_string()),
+            ],
+            ..Default::default()
+        };
+        identity.stamp(&mut properties);
+        assert_eq!(
+            properties.user_properties,
+            vec![
+                ("unit".to_string(), "c".to_string()),
+                (
+                    FORWARDED_FOR_PROPERTY.to_string(),
+                    "for=\"203.0.113.7:51234\"".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -2,8 +2,20 @@
 //!
 //! Handles HAProxy PROXY protocol v1/v2 header parsing for all listeners.
 //! Supports auto-detection of protocol version and extraction of TLS
-//! termination information from PROXY v2 TLVs.
+//! termination information and other TLVs (ALPN, unique ID, network
+//! namespace, custom types) from PROXY v2 headers. The writer emits the
+//! same headers on outgoing bridge connections. The identity a header gives
+//! a client travels with its wills and retained messages.
 
+mod identity;
 mod parser;
+mod writer;
 
-pub use parser::{parse_proxy_header, ProxyError, ProxyInfo, ProxyTlsInfo, ProxyVersion};
+pub use identity::{ProxyIdentity, FORWARDED_FOR_PROPERTY};
+pub use parser::{
+    parse_optional_proxy_header, parse_proxy_header, ProxyError, ProxyInfo, ProxyTlsInfo,
+    ProxyVersion, PP2_TYPE_ALPN, PP2_TYPE_CUSTOM, PP2_TYPE_NETNS, PP2_TYPE_UNIQUE_ID,
+};
+pub use writer::{
+    encode_proxy_header_v1, encode_proxy_header_v2, write_proxy_header_v1, write_proxy_header_v2,
+};
@@ -5,10 +5,12 @@
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::time::Duration;
 
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio::time::timeout;
 
+use crate::transport::PeerAddr;
+
 /// PROXY v1 signature: "PROXY "
 const PROXY_V1_SIGNATURE: &[u8] = b"PROXY ";
 
@@ -18,24 +20,74 @@ const PROXY_V2_SIGNATURE: &[u8] = b"\r\n\r\n\x00\r\nQUIT\n";
 /// Maximum PROXY header size
 const MAX_HEADER_SIZE: usize = 536;
 
+/// PP2_TYPE_ALPN: application protocol negotiated by the proxy
+pub const PP2_TYPE_ALPN: u8 = 0x01;
+
+/// PP2_TYPE_UNIQUE_ID: opaque connection ID assigned by the proxy
+pub const PP2_TYPE_UNIQUE_ID: u8 = 0x05;
+
+/// PP2_TYPE_NETNS: network namespace the connection was accepted in
+pub const PP2_TYPE_NETNS: u8 = 0x30;
+
+/// TLV types reserved for application-specific (vendor) data
+pub const PP2_TYPE_CUSTOM: std::ops::RangeInclusive<u8> = 0xE0..=0xEF;
+
 /// Information extracted from a PROXY protocol header
 #[derive(Debug, Clone)]
 pub struct ProxyInfo {
     /// Original client address (source from PROXY header)
-    pub client_addr: SocketAddr,
+    pub client_addr: PeerAddr,
 
     /// Server address the client connected to (destination from PROXY header)
-    pub server_addr: Option<SocketAddr>,
+    pub server_addr: Option<PeerAddr>,
 
     /// TLS termination info from PROXY v2 TLVs (if present and trusted)
     pub tls_info: Option<ProxyTlsInfo>,
 
     /// Protocol version used (v1 or v2)
     pub version: ProxyVersion,
+
+    /// ALPN, UNIQUE_ID, NETNS and custom TLVs from a PROXY v2 header,
+    /// in header order
+    pub tlvs: Vec<(u8, Bytes)>,
 }
 
-/// TLS termination information from PROXY v2 TLVs
-#[derive(Debug, Clone)]
+impl ProxyInfo {
+    /// Value of the first TLV of the given type
+    pub fn tlv(&self, kind: u8) -> Option<&Bytes> {
+        self.tlvs.iter().find(|(k, _)| *k == kind).map(|(_, v)| v)
+    }
+
+    /// Application protocol negotiated by the proxy (PP2_TYPE_ALPN)
+    pub fn alpn(&self) -> Option<&str> {
+        self.tlv(PP2_TYPE_ALPN)
+            .and_then(|v| std::str::from_utf8(v).ok())
+    }
+
+    /// Network namespace the proxy accepted the connection in (PP2_TYPE_NETNS)
+    pub fn netns(&self) -> Option<&str> {
+        self.tlv(PP2_TYPE_NETNS)
+            .and_then(|v| std::str::from_utf8(v).ok())
+    }
+
+    /// Proxy-assigned connection ID (PP2_TYPE_UNIQUE_ID), for log correlation
+    ///
+    /// Printable ASCII IDs are returned as-is, anything else hex-encoded.
+    pub fn unique_id(&self) -> Option<String> {
+        let id = self.tlv(PP2_TYPE_UNIQUE_ID)?;
+        if id.iter().all(|b| b.is_ascii_graphic()) {
+            Some(String::from_utf8_lossy(id).into_owned())
+        } else {
+            Some(id.iter().map(|b| format!("{:02x}", b)).collect())
+        }
+    }
+}
+
+/// TLS termination information
+///
+/// Filled from PROXY v2 TLVs when TLS is terminated upstream, or from the
+/// handshake when a listener terminates TLS itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct ProxyTlsInfo {
     /// Server Name Indication (SNI) from PP2_TYPE_AUTHORITY
     pub sni: Option<String>,
@@ -43,8 +95,18 @@ pub struct ProxyTlsInfo {
     /// Client certificate Common Name (CN) from PP2_SUBTYPE_SSL_CN
     pub client_cert_cn: Option<String>,
 
+    /// Client certificate Subject Alternative Names ("DNS:...", "email:...",
+    /// "URI:...", "IP:..."); only known when TLS is terminated locally
+    pub client_cert_sans: Vec<String>,
+
     /// Whether client provided a verified certificate
     pub client_cert_verified: bool,
+
+    /// Negotiated protocol version, e.g. "TLSv1.3" (PP2_SUBTYPE_SSL_VERSION)
+    pub version: Option<String>,
+
+    /// Negotiated cipher suite (PP2_SUBTYPE_SSL_CIPHER)
+    pub cipher: Option<String>,
 }
 
 /// PROXY protocol version
@@ -103,6 +165,23 @@ pub async fn parse_proxy_header<S: AsyncRead + Unpin>(
     timeout_duration: Duration,
     parse_tls_info: bool,
 ) -> Result<(ProxyInfo, BytesMut), ProxyError> {
+    match parse_optional_proxy_header(stream, timeout_duration, parse_tls_info).await? {
+        (Some(info), remaining) => Ok((info, remaining)),
+        (None, _) => Err(ProxyError::NotProxyProtocol),
+    }
+}
+
+/// Parse a PROXY protocol header if the stream starts with one
+///
+/// Detection stops at the first byte that can't belong to a PROXY
+/// signature, so direct clients aren't delayed. Without a header, returns
+/// `None` and the bytes read so far, which must be replayed to the
+/// protocol layer (see [`crate::transport::Rewind`]).
+pub async fn parse_optional_proxy_header<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    timeout_duration: Duration,
+    parse_tls_info: bool,
+) -> Result<(Option<ProxyInfo>, BytesMut), ProxyError> {
     let mut buf = BytesMut::with_capacity(MAX_HEADER_SIZE);
 
     // Read initial bytes with timeout
@@ -114,39 +193,54 @@ pub async fn parse_proxy_header<S: AsyncRead + Unpin>(
 
     match result {
         Ok(Ok(())) => {}
+        Ok(Err(ProxyError::NotProxyProtocol)) => return Ok((None, buf)),
         Ok(Err(e)) => return Err(e),
         Err(_) => return Err(ProxyError::Timeout),
     }
 
     // Detect version and parse
-    if buf.len() >= 12 && buf[..12] == *PROXY_V2_SIGNATURE {
-        parse_v2_header(&buf, parse_tls_info)
-    } else if buf.len() >= 6 && buf[..6] == *PROXY_V1_SIGNATURE {
-        parse_v1_header(&buf)
+    let (info, remaining) = if buf.len() >= 12 && buf[..12] == *PROXY_V2_SIGNATURE {
+        parse_v2_header(&buf, parse_tls_info)?
     } else {
-        Err(ProxyError::NotProxyProtocol)
-    }
+        parse_v1_header(&buf)?
+    };
+    Ok((Some(info), remaining))
+}
+
+/// Whether the bytes read so far could still start a PROXY header
+fn may_be_proxy_header(buf: &[u8]) -> bool {
+    let prefix_of = |signature: &[u8]| {
+        let len = buf.len().min(signature.len());
+        buf[..len] == signature[..len]
+    };
+    prefix_of(PROXY_V1_SIGNATURE) || prefix_of(PROXY_V2_SIGNATURE)
 }
 
 /// Read bytes until we have a complete PROXY header
+///
+/// On `NotProxyProtocol`, `buf` holds the bytes that were read.
 async fn read_until_header_complete<S: AsyncRead + Unpin>(
     stream: &mut S,
     buf: &mut BytesMut,
 ) -> Result<(), ProxyError> {
-    // Initial read - need at least 16 bytes to detect version and read v2 length
-    buf.resize(16, 0);
-    let mut total_read = 0;
-
-    while total_read < 16 {
-        let n = stream.read(&mut buf[total_read..16]).await?;
+    // Need 6 bytes to detect v1, or 16 to detect v2 and read its length
+    let mut chunk = [0u8; 16];
+    loop {
+        if !may_be_proxy_header(buf) {
+            return Err(ProxyError::NotProxyProtocol);
+        }
+        if buf.starts_with(PROXY_V1_SIGNATURE) || buf.len() >= 16 {
+            break;
+        }
+        let n = stream.read(&mut chunk[..16 - buf.len()]).await?;
         if n == 0 {
             return Err(ProxyError::ConnectionClosed);
         }
-        total_read += n;
+        buf.extend_from_slice(&chunk[..n]);
     }
 
     // Detect version from signature
-    if buf[..12] == *PROXY_V2_SIGNATURE {
+    if buf.starts_with(PROXY_V2_SIGNATURE) {
         // V2: Read the full header based on length field
         // Length is in bytes 14-15 (big-endian u16)
         let header_len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
@@ -159,6 +253,7 @@ async fn read_until_header_complete<S: AsyncRead + Unpin>(
             )));
         }
 
+        let mut total_read = buf.len();
         buf.resize(total_len, 0);
         while total_read < total_len {
             let n = stream.read(&mut buf[total_read..total_len]).await?;
@@ -167,10 +262,8 @@ async fn read_until_header_complete<S: AsyncRead + Unpin>(
             }
             total_read += n;
         }
-    } else if buf[..6] == *PROXY_V1_SIGNATURE {
+    } else {
         // V1: Read until CRLF (max 107 bytes)
-        buf.truncate(total_read);
-
         loop {
             if buf.windows(2).any(|w| w == b"\r\n") {
                 break;
@@ -185,8 +278,6 @@ async fn read_until_header_complete<S: AsyncRead + Unpin>(
             }
             buf.extend_from_slice(&tmp[..n]);
         }
-    } else {
-        return Err(ProxyError::NotProxyProtocol);
     }
 
     Ok(())
@@ -212,7 +303,7 @@ fn parse_v1_header(buf: &[u8]) -> Result<(ProxyInfo, BytesMut), ProxyError> {
                         IpAddr::V4(addrs.destination_address),
                         addrs.destination_port,
                     );
-                    (client, Some(server))
+                    (client.into(), Some(server.into()))
                 }
                 ppp::v1::Addresses::Tcp6(addrs) => {
                     let client =
@@ -221,12 +312,12 @@ fn parse_v1_header(buf: &[u8]) -> Result<(ProxyInfo, BytesMut), ProxyError> {
                         IpAddr::V6(addrs.destination_address),
                         addrs.destination_port,
                     );
-                    (client, Some(server))
+                    (client.into(), Some(server.into()))
                 }
                 ppp::v1::Addresses::Unknown => {
                     // UNKNOWN protocol - use placeholder
                     let client = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
-                    (client, None)
+                    (client.into(), None)
                 }
             };
 
@@ -238,6 +329,7 @@ fn parse_v1_header(buf: &[u8]) -> Result<(ProxyInfo, BytesMut), ProxyError> {
                     server_addr,
                     tls_info: None, // V1 doesn't support TLVs
                     version: ProxyVersion::V1,
+                    tlvs: Vec::new(),
                 },
                 remaining,
             ))
@@ -261,7 +353,7 @@ fn parse_v2_header(buf: &[u8], parse_tls_info: bool) -> Result<(ProxyInfo, Bytes
                         IpAddr::V4(addrs.destination_address),
                         addrs.destination_port,
                     );
-                    (client, Some(server))
+                    (client.into(), Some(server.into()))
                 }
                 ppp::v2::Addresses::IPv6(addrs) => {
                     let client =
@@ -270,17 +362,16 @@ fn parse_v2_header(buf: &[u8], parse_tls_info: bool) -> Result<(ProxyInfo, Bytes
                         IpAddr::V6(addrs.destination_address),
                         addrs.destination_port,
                     );
-                    (client, Some(server))
-                }
-                ppp::v2::Addresses::Unix(_) => {
-                    // Unix sockets - use placeholder IP
-                    let client = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
-                    (client, None)
+                    (client.into(), Some(server.into()))
                 }
+                ppp::v2::Addresses::Unix(addrs) => (
+                    PeerAddr::from_unix_bytes(&addrs.source),
+                    Some(PeerAddr::from_unix_bytes(&addrs.destination)),
+                ),
                 ppp::v2::Addresses::Unspecified => {
                     // LOCAL command or UNSPEC - use placeholder
                     let client = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
-                    (client, None)
+                    (client.into(), None)
                 }
             };
 
@@ -302,6 +393,7 @@ fn parse_v2_header(buf: &[u8], parse_tls_info: bool) -> Result<(ProxyInfo, Bytes
                     server_addr,
                     tls_info,
                     version: ProxyVersion::V2,
+                    tlvs: collect_tlvs(&header),
                 },
                 remaining,
             ))
@@ -313,11 +405,28 @@ fn parse_v2_header(buf: &[u8], parse_tls_info: bool) -> Result<(ProxyInfo, Bytes
     }
 }
 
+/// Collect the TLVs surfaced in `ProxyInfo::tlvs`
+///
+/// Unlike the TLS TLVs these don't grant anything, so they are kept
+/// regardless of `tls_termination`.
+fn collect_tlvs(header: &ppp::v2::Header) -> Vec<(u8, Bytes)> {
+    header
+        .tlvs()
+        .filter_map(Result::ok)
+        .filter(|tlv| {
+            matches!(
+                tlv.kind,
+                PP2_TYPE_ALPN | PP2_TYPE_UNIQUE_ID | PP2_TYPE_NETNS
+            ) || PP2_TYPE_CUSTOM.contains(&tlv.kind)
+        })
+        .map(|tlv| (tlv.kind, Bytes::copy_from_slice(&tlv.value)))
+        .collect()
+}
+
 /// Extract TLS information from PROXY v2 TLVs
 fn extract_tls_info(header: &ppp::v2::Header) -> Option<ProxyTlsInfo> {
     let mut sni = None;
-    let mut client_cert_cn = None;
-    let mut client_cert_verified = false;
+    let mut ssl = ProxyTlsInfo::default();
 
     // Iterate through TLVs
     for tlv_result in header.tlvs() {
@@ -337,34 +446,26 @@ fn extract_tls_info(header: &ppp::v2::Header) -> Option<ProxyTlsInfo> {
             0x20 => {
                 // Parse SSL sub-TLVs
                 if let Some(info) = parse_ssl_tlv(&tlv.value) {
-                    client_cert_cn = info.0;
-                    client_cert_verified = info.1;
+                    ssl = info;
                 }
             }
             // PP2_SUBTYPE_SSL_CN (0x22) - client cert CN as standalone TLV
             0x22 => {
                 if let Ok(s) = std::str::from_utf8(&tlv.value) {
-                    client_cert_cn = Some(s.to_string());
+                    ssl.client_cert_cn = Some(s.to_string());
                 }
             }
             _ => {}
         }
     }
 
-    if sni.is_some() || client_cert_cn.is_some() || client_cert_verified {
-        Some(ProxyTlsInfo {
-            sni,
-            client_cert_cn,
-            client_cert_verified,
-        })
-    } else {
-        None
-    }
+    let info = ProxyTlsInfo { sni, ..ssl };
+    (info != ProxyTlsInfo::default()).then_some(info)
 }
 
-/// Parse PP2_TYPE_SSL TLV value to extract client cert CN
-/// Returns (Option<CN>, client_verified)
-fn parse_ssl_tlv(value: &[u8]) -> Option<(Option<String>, bool)> {
+/// Parse PP2_TYPE_SSL TLV value: client cert CN, verification, protocol
+/// version and cipher
+fn parse_ssl_tlv(value: &[u8]) -> Option<ProxyTlsInfo> {
     // PP2_TYPE_SSL structure:
     // - 1 byte: client bitfield (bit 0 = PP2_CLIENT_SSL, bit 2 = PP2_CLIENT_CERT_CONN)
     // - 4 bytes: verify (0 = success, non-zero = error)
@@ -376,9 +477,10 @@ fn parse_ssl_tlv(value: &[u8]) -> Option<(Option<String>, bool)> {
 
     let client_flags = value[0];
     let _verify_result = u32::from_be_bytes([value[1], value[2], value[3], value[4]]);
-    let client_verified = (client_flags & 0x04) != 0; // PP2_CLIENT_CERT_CONN
-
-    let mut cn = None;
+    let mut info = ProxyTlsInfo {
+        client_cert_verified: (client_flags & 0x04) != 0, // PP2_CLIENT_CERT_CONN
+        ..Default::default()
+    };
 
     // Parse sub-TLVs starting at offset 5
     let mut offset = 5;
@@ -391,17 +493,22 @@ fn parse_ssl_tlv(value: &[u8]) -> Option<(Option<String>, bool)> {
             break;
         }
 
-        // PP2_SUBTYPE_SSL_CN = 0x02
-        if sub_type == 0x02 {
-            if let Ok(s) = std::str::from_utf8(&value[offset..offset + sub_len]) {
-                cn = Some(s.to_string());
+        if let Ok(s) = std::str::from_utf8(&value[offset..offset + sub_len]) {
+            match sub_type {
+                // PP2_SUBTYPE_SSL_VERSION
+                0x21 => info.version = Some(s.to_string()),
+                // PP2_SUBTYPE_SSL_CN is 0x22; some proxies send 0x02
+                0x22 | 0x02 => info.client_cert_cn = Some(s.to_string()),
+                // PP2_SUBTYPE_SSL_CIPHER
+                0x23 => info.cipher = Some(s.to_string()),
+                _ => {}
             }
         }
 
         offset += sub_len;
     }
 
-    Some((cn, client_verified))
+    Some(info)
 }
 
 #[cfg(test)]
@@ -429,14 +536,15 @@ mod tests {
 
         assert_eq!(info.version, ProxyVersion::V1);
         assert_eq!(
-            info.client_addr,
-            "192.168.1.1:12345".parse::<SocketAddr>().unwrap()
+            info.client_addr.socket_addr(),
+            Some("192.168.1.1:12345".parse::<SocketAddr>().unwrap())
         );
         assert_eq!(
-            info.server_addr,
+            info.server_addr.and_then(|addr| addr.socket_addr()),
             Some("10.0.0.1:80".parse::<SocketAddr>().unwrap())
         );
         assert!(info.tls_info.is_none());
+        assert!(info.tlvs.is_empty());
         assert!(remaining.is_empty());
     }
 
@@ -451,15 +559,14 @@ mod tests {
 
         assert_eq!(info.version, ProxyVersion::V1);
         assert_eq!(
-            info.client_addr,
-            "[::1]:12345".parse::<SocketAddr>().unwrap()
+            info.client_addr.socket_addr(),
+            Some("[::1]:12345".parse::<SocketAddr>().unwrap())
         );
     }
 
     #[tokio::test]
     async fn test_parse_v1_unknown() {
-        // UNKNOWN needs enough initial bytes (16) to be read
-        let header = b"PROXY UNKNOWN  \r\n";
+        let header = b"PROXY UNKNOWN\r\n";
         let mut cursor = std::io::Cursor::new(header.to_vec());
 
         let (info, _) = parse_proxy_header(&mut cursor, Duration::from_secs(5), false)
@@ -467,6 +574,122 @@ mod tests {
             .unwrap();
 
         assert_eq!(info.version, ProxyVersion::V1);
-        assert_eq!(info.client_addr.ip(), IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        assert_eq!(
+            info.client_addr.ip(),
+            Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_v1_keeps_trailing_bytes() {
+        // The first read can run past a short v1 header
+        let data = b"PROXY UNKNOWN\r\n\x10\x0c";
+        let mut cursor = std::io::Cursor::new(data.to_vec());
+
+        let (_, remaining) = parse_proxy_header(&mut cursor, Duration::from_secs(5), false)
+            .await
+            .unwrap();
+        let mut rest = remaining.to_vec();
+        rest.extend_from_slice(&data[cursor.position() as usize..]);
+        assert_eq!(rest, b"\x10\x0c");
+    }
+
+    #[tokio::test]
+    async fn test_optional_header_returns_consumed_bytes() {
+        // MQTT CONNECT fixed header: not a PROXY signature after one byte
+        let data = b"\x10\x0c\x00\x04MQTT";
+        let mut cursor = std::io::Cursor::new(data.to_vec());
+
+        let (info, consumed) =
+            parse_optional_proxy_header(&mut cursor, Duration::from_secs(5), false)
+                .await
+                .unwrap();
+        assert!(info.is_none());
+        assert_eq!(&consumed[..], &data[..cursor.position() as usize]);
+
+        // A header is still parsed in optional mode
+        let data = b"PROXY TCP4 192.168.1.1 10.0.0.1 12345 80\r\n";
+        let mut cursor = std::io::Cursor::new(data.to_vec());
+        let (info, _) = parse_optional_proxy_header(&mut cursor, Duration::from_secs(5), false)
+            .await
+            .unwrap();
+        assert_eq!(info.unwrap().version, ProxyVersion::V1);
+
+        // Required mode still rejects direct connections
+        let mut cursor = std::io::Cursor::new(data[..0].to_vec());
+        assert!(
+            parse_proxy_header(&mut cursor, Duration::from_secs(5), false)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_v2_tlvs() {
+        use ppp::v2::{Builder, Command, Protocol, Type, Version};
+
+        let addrs: (SocketAddr, SocketAddr) = (
+            "192.168.1.1:12345".parse().unwrap(),
+            "10.0.0.1:1883".parse().unwrap(),
+        );
+        let header =
+            Builder::with_addresses(Version::Two | Command::Proxy, Protocol::Stream, addrs)
+                .write_tlv(Type::ALPN, b"mqtt")
+                .unwrap()
+                .write_tlv(Type::Authority, b"broker.example.com")
+                .unwrap()
+                .write_tlv(Type::UniqueId, b"req-42")
+                .unwrap()
+                .write_tlv(Type::NetworkNamespace, b"tenant-a")
+                .unwrap()
+                .write_tlv(0xE3, &[1, 2, 3])
+                .unwrap()
+                .build()
+                .unwrap();
+        let mut cursor = std::io::Cursor::new(header);
+
+        let (info, _) = parse_proxy_header(&mut cursor, Duration::from_secs(5), false)
+            .await
+            .unwrap();
+
+        assert_eq!(info.version, ProxyVersion::V2);
+        assert_eq!(info.alpn(), Some("mqtt"));
+        assert_eq!(info.unique_id().as_deref(), Some("req-42"));
+        assert_eq!(info.netns(), Some("tenant-a"));
+        assert_eq!(info.tlv(0xE3).map(|v| &v[..]), Some(&[1u8, 2, 3][..]));
+        // AUTHORITY is TLS info, not collected (and not parsed without tls_termination)
+        assert_eq!(info.tlvs.len(), 4);
+        assert!(info.tls_info.is_none());
+
+        // Binary IDs are hex-encoded for logging
+        let info = ProxyInfo {
+            tlvs: vec![(PP2_TYPE_UNIQUE_ID, Bytes::from_static(&[0xde, 0xad]))],
+            ..info
+        };
+        assert_eq!(info.unique_id().as_deref(), Some("dead"));
+    }
+
+    #[tokio::test]
+    async fn test_parse_v2_unix() {
+        use ppp::v2::{Builder, Command, Protocol, Unix, Version};
+
+        let mut source = [0u8; 108];
+        source[..12].copy_from_slice(b"/run/app.sck");
+        let header = Builder::with_addresses(
+            Version::Two | Command::Proxy,
+            Protocol::Stream,
+            Unix::new(source, [0u8; 108]),
+        )
+        .build()
+        .unwrap();
+        let mut cursor = std::io::Cursor::new(header);
+
+        let (info, _) = parse_proxy_header(&mut cursor, Duration::from_secs(5), false)
+            .await
+            .unwrap();
+
+        assert_eq!(info.client_addr.to_string(), "unix:/run/app.sck");
+        assert_eq!(info.client_addr.ip(), None);
+        assert_eq!(info.server_addr, Some(PeerAddr::Unix(None)));
     }
 }
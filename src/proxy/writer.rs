@@ -0,0 +1,236 @@
+//! PROXY Protocol Writer
+//!
+//! Emits PROXY v1 (text) and v2 (binary) headers for outgoing connections,
+//! carrying the same fields the parser extracts on ingress.
+
+use std::io;
+use std::net::SocketAddr;
+
+use ppp::v2::{Builder, Command, Protocol, Type, Unix, Version};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::parser::ProxyInfo;
+use crate::transport::PeerAddr;
+
+/// PP2_TYPE_SSL client flags: connected over TLS
+const PP2_CLIENT_SSL: u8 = 0x01;
+
+/// PP2_TYPE_SSL client flags: client presented a certificate on this connection
+const PP2_CLIENT_CERT_CONN: u8 = 0x04;
+
+/// TCP source and destination when both are known and of the same family
+fn address_pair(info: &ProxyInfo) -> Option<(SocketAddr, SocketAddr)> {
+    let client = info.client_addr.socket_addr()?;
+    let server = info.server_addr.as_ref()?.socket_addr()?;
+    (client.is_ipv4() == server.is_ipv4()).then_some((client, server))
+}
+
+/// NUL-padded `sun_path` for a Unix peer (empty if unnamed)
+fn unix_path_bytes(addr: &PeerAddr) -> io::Result<[u8; 108]> {
+    let mut raw = [0u8; 108];
+    if let Some(path) = addr.unix_path() {
+        let path = path.as_os_str().as_encoded_bytes();
+        // Leave room for the terminating NUL
+        if path.len() >= raw.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unix socket path too long for PROXY header",
+            ));
+        }
+        raw[..path.len()].copy_from_slice(path);
+    }
+    Ok(raw)
+}
+
+/// Encode a PROXY v1 header
+///
+/// v1 has no TLVs, so only the addresses are sent. Without a destination
+/// address (or with mixed families or Unix peers) the header is
+/// `PROXY UNKNOWN`.
+pub fn encode_proxy_header_v1(info: &ProxyInfo) -> Vec<u8> {
+    match address_pair(info) {
+        Some((client, server)) => {
+            let family = if client.is_ipv4() { "TCP4" } else { "TCP6" };
+            format!(
+                "PROXY {} {} {} {} {}\r\n",
+                family,
+                client.ip(),
+                server.ip(),
+                client.port(),
+                server.port()
+            )
+            .into_bytes()
+        }
+        None => b"PROXY UNKNOWN\r\n".to_vec(),
+    }
+}
+
+/// Encode a PROXY v2 header
+///
+/// TLS info is sent as PP2_TYPE_AUTHORITY (SNI) and PP2_TYPE_SSL (version,
+/// client certificate CN, cipher), followed by `info.tlvs` in order.
+pub fn encode_proxy_header_v2(info: &ProxyInfo) -> io::Result<Vec<u8>> {
+    let version_command = Version::Two | Command::Proxy;
+    let mut builder = match (address_pair(info), &info.server_addr) {
+        (Some(addrs), _) => Builder::with_addresses(version_command, Protocol::Stream, addrs),
+        (None, Some(server)) if info.client_addr.is_unix() && server.is_unix() => {
+            Builder::with_addresses(
+                version_command,
+                Protocol::Stream,
+                Unix::new(
+                    unix_path_bytes(&info.client_addr)?,
+                    unix_path_bytes(server)?,
+                ),
+            )
+        }
+        _ => Builder::with_addresses(
+            version_command,
+            Protocol::Unspecified,
+            ppp::v2::Addresses::Unspecified,
+        ),
+    };
+
+    if let Some(ref tls) = info.tls_info {
+        if let Some(ref sni) = tls.sni {
+            builder = builder.write_tlv(Type::Authority, sni.as_bytes())?;
+        }
+
+        let mut flags = PP2_CLIENT_SSL;
+        if tls.client_cert_verified {
+            flags |= PP2_CLIENT_CERT_CONN;
+        }
+        // verify: 0 = certificate verified
+        let verify: u32 = if tls.client_cert_verified { 0 } else { 1 };
+        let mut ssl = vec![flags];
+        ssl.extend_from_slice(&verify.to_be_bytes());
+        let sub_tlvs = [
+            (Type::SSLVersion, &tls.version),
+            (Type::SSLCommonName, &tls.client_cert_cn),
+            (Type::SSLCipher, &tls.cipher),
+        ];
+        for (kind, value) in sub_tlvs {
+            if let Some(value) = value {
+                ssl.push(kind.into());
+                ssl.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                ssl.extend_from_slice(value.as_bytes());
+            }
+        }
+        builder = builder.write_tlv(Type::SSL, &ssl)?;
+    }
+
+    for (kind, value) in &info.tlvs {
+        builder = builder.write_tlv(*kind, value)?;
+    }
+
+    builder.build()
+}
+
+/// Write a PROXY v1 header to the stream
+pub async fn write_proxy_header_v1<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    info: &ProxyInfo,
+) -> io::Result<()> {
+    stream.write_all(&encode_proxy_header_v1(info)).await
+}
+
+/// Write a PROXY v2 header to the stream
+pub async fn write_proxy_header_v2<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    info: &ProxyInfo,
+) -> io::Result<()> {
+    stream.write_all(&encode_proxy_header_v2(info)?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::proxy::{parse_proxy_header, ProxyTlsInfo, ProxyVersion, PP2_TYPE_UNIQUE_ID};
+
+    fn info(client: &str, server: Option<&str>) -> ProxyInfo {
+        ProxyInfo {
+            client_addr: client.parse::<SocketAddr>().unwrap().into(),
+            server_addr: server.map(|s| s.parse::<SocketAddr>().unwrap().into()),
+            tls_info: None,
+            version: ProxyVersion::V2,
+            tlvs: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_v1_round_trip() {
+        let mut out = Vec::new();
+        write_proxy_header_v1(&mut out, &info("192.168.1.1:12345", Some("10.0.0.1:1883")))
+            .await
+            .unwrap();
+        assert_eq!(out, b"PROXY TCP4 192.168.1.1 10.0.0.1 12345 1883\r\n");
+
+        let (parsed, _) = parse_proxy_header(&mut Cursor::new(out), Duration::from_secs(1), false)
+            .await
+            .unwrap();
+        assert_eq!(parsed.client_addr.to_string(), "192.168.1.1:12345");
+
+        // Mixed families can't be expressed
+        let mixed = info("[::1]:12345", Some("10.0.0.1:1883"));
+        assert_eq!(encode_proxy_header_v1(&mixed), b"PROXY UNKNOWN\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_v2_round_trip_with_tlvs() {
+        let mut original = info("[2001:db8::1]:40000", Some("[2001:db8::2]:8883"));
+        original.tls_info = Some(ProxyTlsInfo {
+            sni: Some("broker.example.com".to_string()),
+            client_cert_cn: Some("device-42".to_string()),
+            client_cert_verified: true,
+            version: Some("TLSv1.3".to_string()),
+            cipher: Some("TLS_AES_128_GCM_SHA256".to_string()),
+            ..Default::default()
+        });
+        original.tlvs = vec![
+            (PP2_TYPE_UNIQUE_ID, Bytes::from_static(b"req-7")),
+            (0xE1, Bytes::from_static(&[9, 9])),
+        ];
+
+        let mut out = Vec::new();
+        write_proxy_header_v2(&mut out, &original).await.unwrap();
+        let (parsed, remaining) =
+            parse_proxy_header(&mut Cursor::new(out), Duration::from_secs(1), true)
+                .await
+                .unwrap();
+
+        assert!(remaining.is_empty());
+        assert_eq!(parsed.version, ProxyVersion::V2);
+        assert_eq!(parsed.client_addr, original.client_addr);
+        assert_eq!(parsed.server_addr, original.server_addr);
+        assert_eq!(parsed.tlvs, original.tlvs);
+        let tls = parsed.tls_info.unwrap();
+        assert_eq!(tls.sni.as_deref(), Some("broker.example.com"));
+        assert_eq!(tls.client_cert_cn.as_deref(), Some("device-42"));
+        assert!(tls.client_cert_verified);
+        assert_eq!(tls.version.as_deref(), Some("TLSv1.3"));
+        assert_eq!(tls.cipher.as_deref(), Some("TLS_AES_128_GCM_SHA256"));
+    }
+
+    #[tokio::test]
+    async fn test_v2_round_trip_unix() {
+        let original = ProxyInfo {
+            client_addr: PeerAddr::Unix(Some(std::path::Path::new("/run/app.sock").into())),
+            server_addr: Some(PeerAddr::Unix(None)),
+            ..info("127.0.0.1:1", None)
+        };
+
+        let out = encode_proxy_header_v2(&original).unwrap();
+        let (parsed, _) = parse_proxy_header(&mut Cursor::new(out), Duration::from_secs(1), false)
+            .await
+            .unwrap();
+        assert_eq!(parsed.client_addr, original.client_addr);
+        assert_eq!(parsed.server_addr, original.server_addr);
+
+        // No v1 form for Unix peers
+        assert_eq!(encode_proxy_header_v1(&original), b"PROXY UNKNOWN\r\n");
+    }
+}
@@ -0,0 +1,139 @@
+//! Runtime Configuration Reload
+//!
+//! [`ConfigReloader`] re-reads the config file (on SIGHUP or
+//! `POST /api/v1/reload`) and applies it without dropping connections:
+//!
+//! - auth users, password file and HTTP backend are replaced
+//! - ACL rules are replaced and existing subscriptions re-checked
+//! - limits, session, MQTT and listener settings apply to new connections
+//! - listeners no longer configured stop (closing only their connections)
+//!   and newly configured ones start
+//! - the log level changes (unless set on the command line)
+//!
+//! Everything else (bridges, cluster, persistence, TLS certificates, ...)
+//! is only read at startup; changes to it are reported as needing a
+//! restart. If the new config doesn't load or its rule or password files
+//! don't, nothing is applied.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::acl::AclProvider;
+use crate::auth::{config_authenticators, AuthProvider};
+use crate::broker::{Broker, BrokerConfig};
+use crate::config::{Config, ConfigError};
+
+/// Builds the broker settings for a config (command-line overrides included)
+pub type BrokerConfigFn = Box<dyn Fn(&Config) -> BrokerConfig + Send + Sync>;
+
+/// Applies a log level from the config
+pub type LogLevelFn = Box<dyn Fn(&str) + Send + Sync>;
+
+/// What a reload changed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReloadReport {
+    /// Changed sections that took effect
+    pub applied: Vec<&'static str>,
+    /// Sections and fields changed since startup that need a restart
+    pub restart_required: Vec<&'static str>,
+    pub listeners_started: Vec<String>,
+    pub listeners_stopped: Vec<String>,
+    /// Listeners that failed to start, with the error
+    pub listener_errors: Vec<String>,
+    /// Subscriptions the new ACL rules revoked
+    pub subscriptions_revoked: usize,
+}
+
+/// Reloads configuration into a running broker
+pub struct ConfigReloader {
+    /// Config file re-read on reload; without one the current config is
+    /// re-applied (re-reading its rule and password files)
+    path: Option<PathBuf>,
+    /// Config the broker started with
+    startup: Config,
+    /// Config last applied; the lock also serializes reloads
+    current: Mutex<Config>,
+    broker: Arc<Broker>,
+    auth: Arc<AuthProvider>,
+    acl: Arc<AclProvider>,
+    broker_config: BrokerConfigFn,
+    log_level: Option<LogLevelFn>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        path: Option<PathBuf>,
+        config: Config,
+        broker: Arc<Broker>,
+        auth: Arc<AuthProvider>,
+        acl: Arc<AclProvider>,
+        broker_config: BrokerConfigFn,
+    ) -> Self {
+        Self {
+            path,
+            startup: config.clone(),
+            current: Mutex::new(config),
+            broker,
+            auth,
+            acl,
+            broker_config,
+            log_level: None,
+        }
+    }
+
+    /// Apply `[log] level` on reload
+    pub fn with_log_level(mut self, log_level: LogLevelFn) -> Self {
+        self.log_level = Some(log_level);
+        self
+    }
+
+    /// Re-read the config and apply it
+    pub async fn reload(&self) -> Result<ReloadReport, ConfigError> {
+        let mut current = self.current.lock().await;
+        let new = match self.path {
+            Some(ref path) => Config::load(path)?,
+            None => current.clone(),
+        };
+
+        // Load everything that can fail before applying anything
+        let authenticators = config_authenticators(&new.auth).map_err(ConfigError::Validation)?;
+        let rules = new.acl.load_rules()?;
+
+        let mut report = ReloadReport {
+            applied: current.diff(&new).applied,
+            restart_required: self.startup.diff(&new).restart_required,
+            ..Default::default()
+        };
+
+        self.auth.reload(&new.auth, authenticators);
+        self.acl.reload(&rules);
+        report.subscriptions_revoked = self.broker.reevaluate_subscriptions(rules.on_revoke).await;
+
+        let changes = self.broker.apply_config((self.broker_config)(&new));
+        report.listeners_started = changes.started;
+        report.listeners_stopped = changes.stopped;
+        report.listener_errors = changes.errors;
+
+        if let Some(ref log_level) = self.log_level {
+            log_level(&new.log.level);
+        }
+
+        info!(
+            "Configuration reloaded: applied [{}], {} subscriptions revoked",
+            report.applied.join(", "),
+            report.subscriptions_revoked
+        );
+        if !report.restart_required.is_empty() {
+            warn!(
+                "Changes to [{}] take effect after a restart",
+                report.restart_required.join(", ")
+            );
+        }
+        *current = new;
+        Ok(report)
+    }
+}
@@ -0,0 +1,288 @@
+//! Link Compression
+//!
+//! Compression for cluster links and bridge connections, which often cross
+//! WANs billed by the byte. Each end lists the codecs it takes and the
+//! sender uses the first of its own that the receiver accepts, so links to
+//! brokers without compression (or older versions) stay uncompressed.
+//!
+//! Only messages of at least `min_size` bytes are compressed, and only
+//! when that makes them smaller. Bytes before and after compression and
+//! the CPU time spent are exported per link as
+//! `vibemq_link_uncompressed_bytes_total`,
+//! `vibemq_link_compressed_bytes_total` and
+//! `vibemq_link_compression_seconds_total`.
+
+use std::io::{self, Read};
+use std::time::Instant;
+
+use prometheus::{Counter, CounterVec, IntCounter, IntCounterVec, Opts};
+
+use crate::config::{CompressionCodec, LinkCompressionConfig};
+use crate::metrics::Metrics;
+
+/// The first of `offered` that `accepted` also lists
+pub fn negotiate<'a>(
+    offered: impl IntoIterator<Item = &'a str>,
+    accepted: &[CompressionCodec],
+) -> Option<CompressionCodec> {
+    offered
+        .into_iter()
+        .filter_map(|name| CompressionCodec::from_name(name.trim()))
+        .find(|codec| accepted.contains(codec))
+}
+
+/// Codec names as offered on the wire
+pub fn codec_names(codecs: &[CompressionCodec]) -> Vec<String> {
+    codecs.iter().map(|c| c.as_str().to_string()).collect()
+}
+
+/// Compress `data` with `codec` (`level` applies to zstd)
+pub fn compress(codec: CompressionCodec, data: &[u8], level: i32) -> io::Result<Vec<u8>> {
+    match codec {
+        CompressionCodec::Zstd => zstd::bulk::compress(data, level),
+        CompressionCodec::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+    }
+}
+
+/// Decompress `data`, failing if it would exceed `max_size` bytes
+pub fn decompress(codec: CompressionCodec, data: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+    let too_large = || io::Error::new(io::ErrorKind::InvalidData, "decompressed size too large");
+    match codec {
+        CompressionCodec::Zstd => {
+            // Read no further than the limit rather than trusting the header
+            let mut out = Vec::new();
+            zstd::stream::read::Decoder::new(data)?
+                .take((max_size as u64).saturating_add(1))
+                .read_to_end(&mut out)?;
+            if out.len() > max_size {
+                return Err(too_large());
+            }
+            Ok(out)
+        }
+        CompressionCodec::Lz4 => {
+            let invalid = |e: lz4_flex::block::DecompressError| {
+                io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+            };
+            let (size, _) = lz4_flex::block::uncompressed_size(data).map_err(invalid)?;
+            if size > max_size {
+                return Err(too_large());
+            }
+            lz4_flex::decompress_size_prepended(data).map_err(invalid)
+        }
+    }
+}
+
+/// Prometheus collectors for all compressed links
+#[derive(Clone)]
+pub struct CompressionMetrics {
+    uncompressed: IntCounterVec,
+    compressed: IntCounterVec,
+    seconds: CounterVec,
+}
+
+impl CompressionMetrics {
+    /// Create the (unregistered) collectors
+    pub fn new() -> Self {
+        Self {
+            uncompressed: IntCounterVec::new(
+                Opts::new(
+                    "vibemq_link_uncompressed_bytes_total",
+                    "Bytes of messages on compressed links before compression",
+                ),
+                &["link", "direction"],
+            )
+            .unwrap(),
+            compressed: IntCounterVec::new(
+                Opts::new(
+                    "vibemq_link_compressed_bytes_total",
+                    "Bytes of messages on compressed links as sent over the wire",
+                ),
+                &["link", "direction"],
+            )
+            .unwrap(),
+            seconds: CounterVec::new(
+                Opts::new(
+                    "vibemq_link_compression_seconds_total",
+                    "CPU time spent compressing and decompressing link messages",
+                ),
+                &["link", "direction"],
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Add the collectors to the crate-wide registry
+    pub fn register(&self, metrics: &Metrics) -> prometheus::Result<()> {
+        metrics.register(Box::new(self.uncompressed.clone()))?;
+        metrics.register(Box::new(self.compressed.clone()))?;
+        metrics.register(Box::new(self.seconds.clone()))
+    }
+
+    fn for_link(&self, link: &str, direction: &str) -> LinkMetrics {
+        LinkMetrics {
+            uncompressed: self.uncompressed.with_label_values(&[link, direction]),
+            compressed: self.compressed.with_label_values(&[link, direction]),
+            seconds: self.seconds.with_label_values(&[link, direction]),
+        }
+    }
+}
+
+impl Default for CompressionMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One link direction's children of the `CompressionMetrics` collectors
+struct LinkMetrics {
+    uncompressed: IntCounter,
+    compressed: IntCounter,
+    seconds: Counter,
+}
+
+impl LinkMetrics {
+    fn record(&self, uncompressed: usize, compressed: usize, started: Instant) {
+        self.uncompressed.inc_by(uncompressed as u64);
+        self.compressed.inc_by(compressed as u64);
+        self.seconds.inc_by(started.elapsed().as_secs_f64());
+    }
+}
+
+/// The negotiated codec of one link and its metrics
+pub struct LinkCompressor {
+    codec: CompressionCodec,
+    min_size: usize,
+    level: i32,
+    sent: Option<LinkMetrics>,
+    received: Option<LinkMetrics>,
+}
+
+impl LinkCompressor {
+    /// Compressor for `link` (the metrics label, e.g. "cluster:node-2")
+    pub fn new(
+        codec: CompressionCodec,
+        config: &LinkCompressionConfig,
+        link: &str,
+        metrics: Option<&CompressionMetrics>,
+    ) -> Self {
+        Self {
+            codec,
+            min_size: config.min_size,
+            level: config.level,
+            sent: metrics.map(|m| m.for_link(link, "out")),
+            received: metrics.map(|m| m.for_link(link, "in")),
+        }
+    }
+
+    /// The negotiated codec
+    pub fn codec(&self) -> CompressionCodec {
+        self.codec
+    }
+
+    /// Compress an outgoing message, if it's large enough and compression
+    /// makes it smaller (None = send it as is)
+    pub fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() < self.min_size {
+            return None;
+        }
+        let started = Instant::now();
+        let compressed = compress(self.codec, data, self.level)
+            .ok()
+            .filter(|c| c.len() < data.len());
+        if let Some(ref metrics) = self.sent {
+            let wire = compressed.as_ref().map_or(data.len(), Vec::len);
+            metrics.record(data.len(), wire, started);
+        }
+        compressed
+    }
+
+    /// Decompress an incoming message of at most `max_size` bytes
+    pub fn decompress(&self, data: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+        let started = Instant::now();
+        let decompressed = decompress(self.codec, data, max_size)?;
+        if let Some(ref metrics) = self.received {
+            metrics.record(decompressed.len(), data.len(), started);
+        }
+        Ok(decompressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn telemetry() -> Vec<u8> {
+        (0..200)
+            .flat_map(|i| format!("{{\"sensor\":\"temp-{}\",\"value\":21.5}}", i % 8).into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let data = telemetry();
+        for codec in [CompressionCodec::Zstd, CompressionCodec::Lz4] {
+            let compressed = compress(codec, &data, 3).unwrap();
+            assert!(compressed.len() < data.len());
+            assert_eq!(decompress(codec, &compressed, data.len()).unwrap(), data);
+            assert!(decompress(codec, &compressed, data.len() - 1).is_err());
+        }
+        assert!(decompress(CompressionCodec::Zstd, b"not zstd", 1024).is_err());
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let accepted = [CompressionCodec::Lz4];
+        assert_eq!(
+            negotiate(["brotli", "zstd", "lz4"], &accepted),
+            Some(CompressionCodec::Lz4)
+        );
+        assert_eq!(negotiate(["zstd"], &accepted), None);
+        assert_eq!(negotiate([], &accepted), None);
+        assert_eq!(
+            codec_names(&[CompressionCodec::Zstd, CompressionCodec::Lz4]),
+            ["zstd", "lz4"]
+        );
+    }
+
+    #[test]
+    fn test_link_compressor() {
+        let config = LinkCompressionConfig {
+            codecs: vec![CompressionCodec::Zstd],
+            min_size: 512,
+            level: 3,
+        };
+        let metrics = CompressionMetrics::new();
+        let link = LinkCompressor::new(
+            CompressionCodec::Zstd,
+            &config,
+            "bridge:cloud",
+            Some(&metrics),
+        );
+
+        // Small and incompressible messages go out as they are
+        assert!(link.compress(&[7; 100]).is_none());
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let noise: Vec<u8> = (0..1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 32) as u8
+            })
+            .collect();
+        assert!(link.compress(&noise).is_none());
+
+        let data = telemetry();
+        let compressed = link.compress(&data).unwrap();
+        assert_eq!(link.decompress(&compressed, usize::MAX).unwrap(), data);
+
+        let sent = metrics.for_link("bridge:cloud", "out");
+        assert_eq!(sent.uncompressed.get(), (noise.len() + data.len()) as u64);
+        assert_eq!(
+            sent.compressed.get(),
+            (noise.len() + compressed.len()) as u64
+        );
+        let received = metrics.for_link("bridge:cloud", "in");
+        assert_eq!(received.compressed.get(), compressed.len() as u64);
+    }
+}
@@ -2,9 +2,21 @@
 //!
 //! Messages exchanged between brokers for bridging and clustering.
 
+use std::sync::Arc;
+
 use bytes::Bytes;
 
 use crate::protocol::QoS;
+use crate::transport::PeerAddr;
+
+/// The local client that published a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishOrigin {
+    /// Publishing client ID
+    pub client_id: Arc<str>,
+    /// Client address (the PROXY-reported source when behind a proxy)
+    pub addr: PeerAddr,
+}
 
 /// A message to be forwarded to a remote broker
 #[derive(Debug, Clone)]
@@ -4,8 +4,10 @@
 //! the core traits and types used by both bridge connections (forwarding
 //! to external brokers) and cluster nodes (distributed broker instances).
 
+pub mod compression;
 mod message;
 mod peer;
 
-pub use message::{RemoteMessage, RemotePublish, RemoteSubscription};
+pub use compression::{CompressionMetrics, LinkCompressor};
+pub use message::{PublishOrigin, RemoteMessage, RemotePublish, RemoteSubscription};
 pub use peer::{RemoteError, RemotePeer, RemotePeerStatus, RemotePeers};
@@ -56,6 +56,9 @@ pub enum RemotePeerStatus {
     Backoff,
     /// Permanently failed, will not retry
     Failed,
+    /// Disconnected on purpose (a maintenance window), reconnects when
+    /// resumed
+    Paused,
 }
 
 /// Trait for remote broker communication
@@ -0,0 +1,307 @@
+//! Rule Actions
+//!
+//! Each action of a rule runs on its own task, fed through a bounded
+//! queue: a slow or failing action only backs up its own queue, and the
+//! rule's other actions keep going. A failed attempt is retried with
+//! exponential backoff; a message the action gives up on (or that finds
+//! its queue full) goes to the action's dead-letter topic, if it has one.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64ct::{Base64, Encoding};
+use serde_json::{json, Map, Value};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::auth::HttpEndpoint;
+use crate::broker::{routing_id, Confirm, LocalPublish, LocalPublishError, LocalPublisher};
+use crate::config::{ActionKind, RuleActionConfig};
+use crate::metrics::Metrics;
+use crate::protocol::{Publish, QoS};
+use crate::topic::validate_topic_name;
+
+/// What an action delivers messages to
+#[derive(Debug, Clone)]
+enum Target {
+    Republish {
+        topic: String,
+        qos: QoS,
+        retain: bool,
+    },
+    Webhook {
+        endpoint: HttpEndpoint,
+        timeout: Duration,
+    },
+}
+
+/// Why an attempt failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Failure {
+    error: String,
+    /// Retrying can't help (e.g. an ACL denial)
+    permanent: bool,
+}
+
+/// A validated `[[rule.actions]]` entry
+#[derive(Debug, Clone)]
+pub(super) struct Action {
+    pub(super) name: String,
+    target: Target,
+    retries: u32,
+    retry_backoff: Duration,
+    pub(super) queue_size: usize,
+    dead_letter: Option<String>,
+}
+
+impl Action {
+    pub(super) fn new(name: String, config: &RuleActionConfig) -> Result<Self, String> {
+        let template = |topic: &str| {
+            topic
+                .replace("{topic}", "topic")
+                .replace("{name}", "name")
+                .replace("{action}", "action")
+        };
+        let target = match config.kind {
+            ActionKind::Republish => {
+                let topic = config.topic.clone().ok_or("republish needs a topic")?;
+                validate_topic_name(&template(&topic)).map_err(|e| format!("topic: {}", e))?;
+                Target::Republish {
+                    topic,
+                    qos: QoS::from_u8(config.qos)
+                        .ok_or(format!("qos must be 0, 1, or 2, got {}", config.qos))?,
+                    retain: config.retain,
+                }
+            }
+            ActionKind::Webhook => {
+                let url = config.url.as_deref().ok_or("webhook needs a url")?;
+                Target::Webhook {
+                    endpoint: HttpEndpoint::parse(url, &config.headers)
+                        .map_err(|e| format!("url '{}': {}", url, e))?,
+                    timeout: config.timeout,
+                }
+            }
+        };
+        if config.queue_size == 0 {
+            return Err("queue_size must be positive".to_string());
+        }
+        if let Some(ref topic) = config.dead_letter {
+            validate_topic_name(&template(topic)).map_err(|e| format!("dead_letter: {}", e))?;
+        }
+        Ok(Self {
+            name,
+            target,
+            retries: config.retries,
+            retry_backoff: config.retry_backoff,
+            queue_size: config.queue_size,
+            dead_letter: config.dead_letter.clone(),
+        })
+    }
+
+    /// Deliver queued messages until the rule drops the queue, confirming
+    /// those whose publisher waits for it
+    pub(super) async fn run(
+        self,
+        rule: Arc<str>,
+        publisher: Arc<LocalPublisher>,
+        metrics: Option<Arc<Metrics>>,
+        mut queue: mpsc::Receiver<(Publish, Option<Confirm>)>,
+    ) {
+        let count = |result: &str| {
+            if let Some(ref metrics) = metrics {
+                metrics.rule_action(&rule, &self.name, result);
+            }
+        };
+        while let Some((publish, confirm)) = queue.recv().await {
+            let mut attempts = 0;
+            let failure = loop {
+                attempts += 1;
+                let Err(failure) = self.attempt(&rule, &publisher, &publish).await else {
+                    break None;
+                };
+                count("failure");
+                if failure.permanent || attempts > self.retries {
+                    break Some(failure);
+                }
+                debug!(
+                    "Rule '{}' action '{}' failed ({}), retrying",
+                    rule, self.name, failure.error
+                );
+                tokio::time::sleep(self.retry_backoff * 2u32.saturating_pow(attempts - 1)).await;
+            };
+            if let Some(confirm) = confirm {
+                confirm.confirm(failure.is_none());
+            }
+            match failure {
+                None => count("success"),
+                Some(failure) => {
+                    warn!(
+                        routing_id = routing_id(&publish.properties).unwrap_or_default(),
+                        "Rule '{}' action '{}' gave up on {} after {} attempts: {}",
+                        rule,
+                        self.name,
+                        publish.topic,
+                        attempts,
+                        failure.error
+                    );
+                    let result = self
+                        .dead_letter(&rule, &publisher, &publish, &failure.error, attempts)
+                        .await;
+                    count(result);
+                }
+            }
+        }
+    }
+
+    async fn attempt(
+        &self,
+        rule: &str,
+        publisher: &LocalPublisher,
+        publish: &Publish,
+    ) -> Result<(), Failure> {
+        match self.target {
+            Target::Republish {
+                ref topic,
+                qos,
+                retain,
+            } => {
+                let message = LocalPublish::new(
+                    self.render(topic, rule, &publish.topic),
+                    publish.payload.clone(),
+                )
+                .with_qos(qos)
+                .with_retain(retain)
+                .caused_by(publish);
+                publisher.publish(message).await.map_err(|e| Failure {
+                    permanent: !matches!(e, LocalPublishError::Hook(_)),
+                    error: e.to_string(),
+                })
+            }
+            Target::Webhook {
+                ref endpoint,
+                timeout,
+            } => {
+                let body = envelope(
+                    publish,
+                    [
+                        ("rule", rule.into()),
+                        ("qos", (publish.qos as u8).into()),
+                        ("retain", publish.retain.into()),
+                    ],
+                );
+                let error = match tokio::time::timeout(timeout, endpoint.post_json(body.as_bytes()))
+                    .await
+                {
+                    Ok(Ok((status, _))) if (200..300).contains(&status) => return Ok(()),
+                    Ok(Ok((status, _))) => format!("webhook returned {}", status),
+                    Ok(Err(e)) => format!("webhook failed: {}", e),
+                    Err(_) => "webhook timed out".to_string(),
+                };
+                Err(Failure {
+                    error,
+                    permanent: false,
+                })
+            }
+        }
+    }
+
+    /// Hand a message the action gave up on to its dead-letter topic;
+    /// returns the metrics result
+    pub(super) async fn dead_letter(
+        &self,
+        rule: &str,
+        publisher: &LocalPublisher,
+        publish: &Publish,
+        error: &str,
+        attempts: u32,
+    ) -> &'static str {
+        let Some(ref topic) = self.dead_letter else {
+            return "dropped";
+        };
+        let payload = envelope(
+            publish,
+            [
+                ("rule", rule.into()),
+                ("action", self.name.as_str().into()),
+                ("error", error.into()),
+                ("attempts", attempts.into()),
+            ],
+        );
+        let message = LocalPublish::new(self.render(topic, rule, &publish.topic), payload)
+            .with_qos(QoS::AtLeastOnce)
+            .caused_by(publish);
+        match publisher.publish(message).await {
+            Ok(()) => "dead_lettered",
+            Err(e) => {
+                warn!(
+                    "Rule '{}' action '{}' cannot dead-letter: {}",
+                    rule, self.name, e
+                );
+                "dropped"
+            }
+        }
+    }
+
+    fn render(&self, template: &str, rule: &str, topic: &str) -> String {
+        template
+            .replace("{topic}", topic)
+            .replace("{name}", rule)
+            .replace("{action}", &self.name)
+    }
+}
+
+/// A message as JSON: `fields`, its topic, its routing ID if it has one,
+/// and its payload as text (`payload`) or, if it isn't UTF-8, base64
+/// (`payload_base64`)
+fn envelope<const N: usize>(publish: &Publish, fields: [(&str, Value); N]) -> String {
+    let mut object: Map<String, Value> = fields
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+    object.insert("topic".to_string(), publish.topic.as_str().into());
+    if let Some(id) = routing_id(&publish.properties) {
+        object.insert("routing_id".to_string(), id.into());
+    }
+    match std::str::from_utf8(&publish.payload) {
+        Ok(text) => object.insert("payload".to_string(), text.into()),
+        Err(_) => object.insert(
+            "payload_base64".to_string(),
+            Base64::encode_string(&publish.payload).into(),
+        ),
+    };
+    json!(object).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::ROUTING_ID_PROPERTY;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_envelope() {
+        let mut publish = Publish {
+            topic: "sensors/1".to_string(),
+            payload: Bytes::from_static(b"21.5"),
+            ..Default::default()
+        };
+        let text: Value =
+            serde_json::from_str(&envelope(&publish, [("rule", "r".into())])).unwrap();
+        assert_eq!(
+            text,
+            json!({"rule": "r", "topic": "sensors/1", "payload": "21.5"})
+        );
+
+        publish.payload = Bytes::from_static(&[0xff, 0x00]);
+        let binary: Value = serde_json::from_str(&envelope(&publish, [])).unwrap();
+        assert_eq!(
+            binary,
+            json!({"topic": "sensors/1", "payload_base64": "/wA="})
+        );
+
+        publish.properties.user_properties =
+            vec![(ROUTING_ID_PROPERTY.to_string(), "0190-abc".to_string())];
+        let routed: Value = serde_json::from_str(&envelope(&publish, [])).unwrap();
+        assert_eq!(routed["routing_id"], "0190-abc");
+    }
+}
@@ -0,0 +1,324 @@
+//! Message Routing Rules
+//!
+//! Each `[[rule]]` subscribes to a topic filter and hands every message to
+//! all of its actions: republishing it to another topic, or POSTing it as
+//! JSON to a webhook (a Kafka REST proxy, a serverless function, ...):
+//!
+//! ```json
+//! {"rule": "telemetry", "topic": "sensors/1/temp", "qos": 1, "retain": false, "payload": "21.5"}
+//! ```
+//!
+//! Records and dead-letter entries of a message with a routing ID (see
+//! `mqtt.routing_ids`) carry it as `routing_id`.
+//!
+//! Actions are isolated from each other (see [`action`]): each has its own
+//! queue, retries and dead-letter topic, and its results are counted in
+//! `vibemq_rule_actions_total{rule, action, result}`.
+//!
+//! With `ack_action` set, QoS 1 publishers of matching messages get their
+//! PUBACK once that action has succeeded rather than when the broker has
+//! the message (see [`crate::broker::Confirmations`]).
+//!
+//! A rule with `user_properties` only routes messages carrying all of them
+//! (see [`crate::user_properties`]); it confirms the others right away, so
+//! their publishers aren't kept waiting by a rule that ignores them.
+
+mod action;
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::broker::Broker;
+use crate::config::{AckFallback, RuleConfig, UserPropertyMatch};
+use crate::protocol::QoS;
+use crate::topic::validate_topic_filter;
+use crate::user_properties;
+use action::Action;
+
+/// Why a routing rule is invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleError {
+    InvalidFilter(&'static str),
+    InvalidUserProperty(&'static str),
+    NoActions,
+    /// Two actions with the same name
+    DuplicateAction(String),
+    InvalidAction {
+        action: String,
+        reason: String,
+    },
+    /// `ack_action` names no action of the rule
+    UnknownAckAction(String),
+    InvalidAckTimeout,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::InvalidFilter(e) => write!(f, "invalid filter: {}", e),
+            RuleError::InvalidUserProperty(e) => write!(f, "{}", e),
+            RuleError::NoActions => write!(f, "needs at least one action"),
+            RuleError::DuplicateAction(name) => {
+                write!(f, "action '{}' is defined more than once", name)
+            }
+            RuleError::InvalidAction { action, reason } => {
+                write!(f, "action '{}': {}", action, reason)
+            }
+            RuleError::UnknownAckAction(name) => write!(f, "ack_action '{}' is not defined", name),
+            RuleError::InvalidAckTimeout => write!(f, "ack_timeout must be positive"),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+/// A validated `[[rule]]` entry
+#[derive(Debug, Clone)]
+pub struct Rule {
+    name: String,
+    filter: String,
+    user_properties: Vec<UserPropertyMatch>,
+    username: Option<String>,
+    actions: Vec<Action>,
+    /// Index of the action PUBACKs wait for, how long, and the fallback
+    ack: Option<(usize, Duration, AckFallback)>,
+}
+
+impl Rule {
+    pub fn new(config: &RuleConfig) -> Result<Self, RuleError> {
+        validate_topic_filter(&config.filter).map_err(RuleError::InvalidFilter)?;
+        for condition in &config.user_properties {
+            condition
+                .validate()
+                .map_err(RuleError::InvalidUserProperty)?;
+        }
+        if config.actions.is_empty() {
+            return Err(RuleError::NoActions);
+        }
+        let mut actions: Vec<Action> = Vec::with_capacity(config.actions.len());
+        for (i, action) in config.actions.iter().enumerate() {
+            let name = action.name.clone().unwrap_or_else(|| {
+                let kind = action.kind.as_str();
+                match config.actions[..i]
+                    .iter()
+                    .filter(|a| a.kind == action.kind)
+                    .count()
+                {
+                    0 => kind.to_string(),
+                    n => format!("{}{}", kind, n + 1),
+                }
+            });
+            if actions.iter().any(|a| a.name == name) {
+                return Err(RuleError::DuplicateAction(name));
+            }
+            let action =
+                Action::new(name.clone(), action).map_err(|reason| RuleError::InvalidAction {
+                    action: name,
+                    reason,
+                })?;
+            actions.push(action);
+        }
+        let ack = match config.ack_action {
+            Some(ref name) => {
+                let index = actions
+                    .iter()
+                    .position(|a| a.name == *name)
+                    .ok_or_else(|| RuleError::UnknownAckAction(name.clone()))?;
+                if config.ack_timeout.is_zero() {
+                    return Err(RuleError::InvalidAckTimeout);
+                }
+                Some((index, config.ack_timeout, config.ack_fallback))
+            }
+            None => None,
+        };
+        Ok(Self {
+            name: config.name.clone(),
+            filter: config.filter.clone(),
+            user_properties: config.user_properties.clone(),
+            username: config.username.clone(),
+            actions,
+            ack,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Names of the rule's actions, in order
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.actions.iter().map(|a| a.name.as_str())
+    }
+
+    /// Subscribe and feed the actions until the broker shuts down
+    pub async fn run(self, broker: Arc<Broker>) {
+        let mut publisher = broker.local_publisher(&format!("rule:{}", self.name));
+        if let Some(ref username) = self.username {
+            publisher = publisher.with_username(username.clone());
+        }
+        // Messages arrive at the QoS they were published with
+        let mut subscription = match publisher.subscribe(&self.filter, QoS::ExactlyOnce).await {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                warn!("Rule '{}' cannot subscribe: {}", self.name, e);
+                return;
+            }
+        };
+        let publisher = Arc::new(publisher);
+        let rule: Arc<str> = self.name.as_str().into();
+        let metrics = broker.metrics().cloned();
+        let queues: Vec<_> = self
+            .actions
+            .iter()
+            .map(|action| {
+                let (tx, rx) = mpsc::channel(action.queue_size);
+                let worker = action.clone();
+                tokio::spawn(worker.run(rule.clone(), publisher.clone(), metrics.clone(), rx));
+                tx
+            })
+            .collect();
+        let confirm_filter = self.ack.map(|(index, timeout, fallback)| {
+            let filter = broker
+                .confirmations()
+                .register(&self.filter, timeout, fallback);
+            (index, filter)
+        });
+        let mut shutdown = broker.subscribe_shutdown();
+
+        loop {
+            tokio::select! {
+                message = subscription.recv() => {
+                    let Some(publish) = message else {
+                        warn!("Rule '{}' lost its subscription", self.name);
+                        return;
+                    };
+                    let mut confirm = match confirm_filter {
+                        Some((index, ref filter)) => filter.take(&publish).map(|c| (index, c)),
+                        None => None,
+                    };
+                    if !user_properties::matches_all(
+                        &publish.properties.user_properties,
+                        &self.user_properties,
+                    ) {
+                        if let Some((_, confirm)) = confirm {
+                            confirm.confirm(true);
+                        }
+                        continue;
+                    }
+                    for (i, (action, queue)) in self.actions.iter().zip(&queues).enumerate() {
+                        let confirm = confirm.take_if(|(index, _)| *index == i).map(|(_, c)| c);
+                        // A dropped confirmation rejects the publish right away
+                        let Err(mpsc::error::TrySendError::Full((publish, _))) =
+                            queue.try_send((publish.clone(), confirm))
+                        else {
+                            continue;
+                        };
+                        let result = action
+                            .dead_letter(&rule, &publisher, &publish, "queue full", 0)
+                            .await;
+                        if let Some(ref metrics) = metrics {
+                            metrics.rule_action(&rule, &action.name, result);
+                        }
+                    }
+                }
+                _ = shutdown.recv() => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ActionKind, RuleActionConfig};
+
+    fn config() -> RuleConfig {
+        RuleConfig {
+            name: "telemetry".to_string(),
+            filter: "sensors/#".to_string(),
+            actions: vec![
+                RuleActionConfig {
+                    topic: Some("archive/{topic}".to_string()),
+                    ..Default::default()
+                },
+                RuleActionConfig {
+                    kind: ActionKind::Webhook,
+                    url: Some("http://127.0.0.1:8082/topics/telemetry".to_string()),
+                    ..Default::default()
+                },
+                RuleActionConfig {
+                    kind: ActionKind::Webhook,
+                    url: Some("http://127.0.0.1:9000/ingest".to_string()),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_action_names() {
+        let rule = Rule::new(&config()).unwrap();
+        assert_eq!(
+            rule.actions().collect::<Vec<_>>(),
+            ["republish", "webhook", "webhook2"]
+        );
+
+        let mut duplicate = config();
+        duplicate.actions[2].name = Some("republish".to_string());
+        assert_eq!(
+            Rule::new(&duplicate).unwrap_err(),
+            RuleError::DuplicateAction("republish".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let new = |change: fn(&mut RuleConfig)| {
+            let mut config = config();
+            change(&mut config);
+            Rule::new(&config).unwrap_err()
+        };
+        assert_eq!(new(|c| c.actions.clear()), RuleError::NoActions);
+        assert!(matches!(
+            new(|c| c.user_properties = vec![UserPropertyMatch::default()]),
+            RuleError::InvalidUserProperty(_)
+        ));
+        assert!(matches!(
+            new(|c| c.filter = "sensors/#/x".to_string()),
+            RuleError::InvalidFilter(_)
+        ));
+        let invalid = |error: RuleError| match error {
+            RuleError::InvalidAction { action, .. } => action,
+            e => panic!("unexpected error: {}", e),
+        };
+        assert_eq!(invalid(new(|c| c.actions[0].topic = None)), "republish");
+        assert_eq!(invalid(new(|c| c.actions[0].qos = 3)), "republish");
+        assert_eq!(
+            invalid(new(
+                |c| c.actions[1].url = Some("https://kafka/".to_string())
+            )),
+            "webhook"
+        );
+        assert_eq!(
+            invalid(new(|c| c.actions[2].dead_letter = Some("dlq/+".to_string()))),
+            "webhook2"
+        );
+        assert_eq!(invalid(new(|c| c.actions[2].queue_size = 0)), "webhook2");
+        assert_eq!(
+            new(|c| c.ack_action = Some("kafka".to_string())),
+            RuleError::UnknownAckAction("kafka".to_string())
+        );
+        assert_eq!(
+            new(|c| {
+                c.ack_action = Some("webhook".to_string());
+                c.ack_timeout = Duration::ZERO;
+            }),
+            RuleError::InvalidAckTimeout
+        );
+    }
+}
@@ -0,0 +1,338 @@
+//! Cron expressions
+//!
+//! The classic five fields (minute, hour, day of month, month, day of
+//! week), each `*`, a value, a range `a-b`, a step `*/n` or `a-b/n`, or a
+//! comma-separated list of those. Months and weekdays also take English
+//! abbreviations (`jan`, `mon`); weekday 0 and 7 are both Sunday. As in
+//! Vixie cron, when both day fields are restricted a day matching either
+//! one fires. The macros `@yearly`, `@monthly`, `@weekly`, `@daily` and
+//! `@hourly` stand for their usual expansions. Times are UTC.
+
+use std::fmt;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Searching further than this for a match means there is none (e.g. "0 0 30 2 *")
+const MAX_SEARCH_DAYS: i64 = 4 * 366 + 1;
+
+/// Invalid cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronError(String);
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cron expression: {}", self.0)
+    }
+}
+
+impl std::error::Error for CronError {}
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    /// Bit n set = value n matches
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    /// Whether the day-of-month / day-of-week fields were `*`
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// One field's allowed values and names
+struct Field {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    names: &'static [&'static str],
+}
+
+impl Field {
+    /// Parse a single value (number or name)
+    fn value(&self, s: &str) -> Result<u32, CronError> {
+        let lower = s.to_ascii_lowercase();
+        if let Some(i) = self.names.iter().position(|n| *n == lower) {
+            return Ok(self.min + i as u32);
+        }
+        match s.parse::<u32>() {
+            Ok(v) if (self.min..=self.max).contains(&v) => Ok(v),
+            _ => Err(CronError(format!(
+                "{} '{}' is not between {} and {}",
+                self.name, s, self.min, self.max
+            ))),
+        }
+    }
+
+    /// Parse a field into a bit mask, returning whether it was `*`
+    fn parse(&self, s: &str) -> Result<(u64, bool), CronError> {
+        let mut mask = 0u64;
+        for part in s.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => match step.parse::<u32>() {
+                    Ok(step) if step > 0 => (range, step),
+                    _ => {
+                        return Err(CronError(format!(
+                            "{} step '{}' must be a positive number",
+                            self.name, step
+                        )))
+                    }
+                },
+                None => (part, 1),
+            };
+            let (start, end) = match range {
+                "*" => (self.min, self.max),
+                _ => match range.split_once('-') {
+                    Some((a, b)) => (self.value(a)?, self.value(b)?),
+                    // "5/15" means 5 through the end in steps of 15
+                    None if step > 1 => (self.value(range)?, self.max),
+                    None => {
+                        let v = self.value(range)?;
+                        (v, v)
+                    }
+                },
+            };
+            if start > end {
+                return Err(CronError(format!(
+                    "{} range '{}' is backwards",
+                    self.name, range
+                )));
+            }
+            for v in (start..=end).step_by(step as usize) {
+                mask |= 1 << v;
+            }
+        }
+        Ok((mask, s == "*"))
+    }
+}
+
+const MINUTE: Field = Field {
+    name: "minute",
+    min: 0,
+    max: 59,
+    names: &[],
+};
+const HOUR: Field = Field {
+    name: "hour",
+    min: 0,
+    max: 23,
+    names: &[],
+};
+const DAY: Field = Field {
+    name: "day of month",
+    min: 1,
+    max: 31,
+    names: &[],
+};
+const MONTH: Field = Field {
+    name: "month",
+    min: 1,
+    max: 12,
+    names: &MONTHS,
+};
+const WEEKDAY: Field = Field {
+    name: "day of week",
+    min: 0,
+    max: 7,
+    names: &WEEKDAYS,
+};
+
+impl Cron {
+    /// Parse a five-field expression or macro
+    pub fn parse(expr: &str) -> Result<Self, CronError> {
+        let expanded = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other if other.starts_with('@') => {
+                return Err(CronError(format!("unknown macro '{}'", other)))
+            }
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(CronError(format!(
+                "expected 5 fields, got {} in '{}'",
+                fields.len(),
+                expr
+            )));
+        };
+
+        let (minutes, _) = MINUTE.parse(minute)?;
+        let (hours, _) = HOUR.parse(hour)?;
+        let (days, any_day) = DAY.parse(day)?;
+        let (months, _) = MONTH.parse(month)?;
+        let (mut weekdays, any_weekday) = WEEKDAY.parse(weekday)?;
+        // 7 is another name for Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & 0x7f;
+        }
+
+        Ok(Self {
+            minutes,
+            hours: hours as u32,
+            days: days as u32,
+            months: months as u16,
+            weekdays: weekdays as u8,
+            any_day,
+            any_weekday,
+        })
+    }
+
+    /// Whether the expression fires on this calendar day
+    fn matches_day(&self, day: u32, weekday: u32) -> bool {
+        let day_ok = self.days & (1 << day) != 0;
+        let weekday_ok = self.weekdays & (1 << weekday) != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day_ok || weekday_ok,
+            _ => day_ok && weekday_ok,
+        }
+    }
+
+    /// First firing time strictly after `after` (Unix seconds), or `None`
+    /// if the expression never fires (e.g. February 30th)
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        let start_minute = after / 60 + 1;
+        let mut day = (start_minute / 1440) as i64;
+        let mut minute_of_day = (start_minute % 1440) as u32;
+        let last_day = day + MAX_SEARCH_DAYS;
+
+        while day <= last_day {
+            let (_, month, dom) = civil_from_days(day);
+            // 1970-01-01 was a Thursday
+            let weekday = (day + 4).rem_euclid(7) as u32;
+            if self.months & (1 << month) != 0 && self.matches_day(dom, weekday) {
+                for m in minute_of_day..1440 {
+                    if self.hours & (1 << (m / 60)) != 0 && self.minutes & (1 << (m % 60)) != 0 {
+                        return Some((day as u64 * 1440 + m as u64) * 60);
+                    }
+                }
+            }
+            day += 1;
+            minute_of_day = 0;
+        }
+        None
+    }
+}
+
+/// (year, month 1-12, day 1-31) of a day count since 1970-01-01
+///
+/// Howard Hinnant's `civil_from_days` algorithm.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Day count since 1970-01-01 of (year, month 1-12, day 1-31)
+///
+/// Howard Hinnant's `days_from_civil` algorithm, the inverse of
+/// [`civil_from_days`].
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// RFC 3339 UTC timestamp ("2024-05-01T13:00:00Z") of Unix seconds
+pub(crate) fn format_rfc3339(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-05-01T12:34:56Z, a Wednesday
+    const NOW: u64 = 1_714_566_896;
+
+    fn next(expr: &str, after: u64) -> String {
+        format_rfc3339(Cron::parse(expr).unwrap().next_after(after).unwrap())
+    }
+
+    #[test]
+    fn test_format_rfc3339() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(NOW), "2024-05-01T12:34:56Z");
+        assert_eq!(format_rfc3339(951_782_400), "2000-02-29T00:00:00Z");
+    }
+
+    #[test]
+    fn test_days_from_civil() {
+        for days in [-719_468, -1, 0, 11_016, 19_844, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn test_next_after() {
+        assert_eq!(next("* * * * *", NOW), "2024-05-01T12:35:00Z");
+        assert_eq!(next("@hourly", NOW), "2024-05-01T13:00:00Z");
+        assert_eq!(next("*/15 * * * *", NOW), "2024-05-01T12:45:00Z");
+        assert_eq!(next("30 2 * * *", NOW), "2024-05-02T02:30:00Z");
+        assert_eq!(next("0 9-17/4 * * *", NOW), "2024-05-01T13:00:00Z");
+        assert_eq!(next("0 0 * * sat,sun", NOW), "2024-05-04T00:00:00Z");
+        assert_eq!(next("0 0 * * 7", NOW), "2024-05-05T00:00:00Z");
+        assert_eq!(next("0 0 29 feb *", NOW), "2028-02-29T00:00:00Z");
+        assert_eq!(next("@yearly", NOW), "2025-01-01T00:00:00Z");
+        // Exactly on a firing time: the next one
+        assert_eq!(next("0 * * * *", 1_714_568_400), "2024-05-01T14:00:00Z");
+    }
+
+    #[test]
+    fn test_day_fields_or() {
+        // The 15th or any Monday, whichever comes first
+        assert_eq!(next("0 0 15 * mon", NOW), "2024-05-06T00:00:00Z");
+        assert_eq!(next("0 0 2 * mon", NOW), "2024-05-02T00:00:00Z");
+    }
+
+    #[test]
+    fn test_never_fires() {
+        assert_eq!(Cron::parse("0 0 30 2 *").unwrap().next_after(NOW), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * * * funday",
+            "@often",
+        ] {
+            assert!(Cron::parse(expr).is_err(), "{} should not parse", expr);
+        }
+    }
+}
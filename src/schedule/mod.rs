@@ -0,0 +1,382 @@
+//! Scheduled Publishes
+//!
+//! `[[schedule]]` entries publish a templated message each time their cron
+//! expression fires. Topic and payload templates may contain:
+//!
+//! - `{name}`: the schedule's name
+//! - `{timestamp}`: the firing time in Unix seconds
+//! - `{datetime}`: the firing time in RFC 3339 UTC ("2024-05-01T13:00:00Z")
+//! - calls into the [template function library](crate::template::functions),
+//!   e.g. `{format_time(timestamp, "%F")}`, within the entry's `budget`
+//!
+//! Messages go through a [`LocalPublisher`](crate::broker::LocalPublisher)
+//! with client ID `schedule:<name>`, so ACLs apply.
+//!
+//! # Firing once
+//!
+//! In a cluster only the leader (see [`ClusterManager::is_leader`]) publishes;
+//! the other nodes keep time with it without publishing, so a node taking
+//! over doesn't repeat firing times already handled. Each node persists the
+//! last firing time it handled: a time missed while the broker was down is
+//! published once at startup if it is within `misfire_grace`, and one
+//! already handled is never published again after a restart.
+//!
+//! [`ClusterManager::is_leader`]: crate::cluster::ClusterManager::is_leader
+
+mod cron;
+
+pub(crate) use cron::{civil_from_days, days_from_civil};
+pub use cron::{Cron, CronError};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tracing::{debug, warn};
+
+use crate::broker::{Broker, LocalPublish, LocalPublisher};
+use crate::config::ScheduleConfig;
+use crate::metrics::Metrics;
+use crate::persistence::{PersistenceOp, StoredScheduleRun};
+use crate::protocol::QoS;
+use crate::template::{Template, TemplateError, Value};
+use crate::topic::validate_topic_name;
+
+/// Variables of schedule templates
+const VARS: &[&str] = &["name", "timestamp", "datetime"];
+
+/// Why a schedule is invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleError {
+    Cron(CronError),
+    InvalidTemplate(TemplateError),
+    InvalidTopic(&'static str),
+    InvalidQos(u8),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::Cron(e) => write!(f, "{}", e),
+            ScheduleError::InvalidTemplate(e) => write!(f, "invalid template: {}", e),
+            ScheduleError::InvalidTopic(e) => write!(f, "invalid topic template: {}", e),
+            ScheduleError::InvalidQos(qos) => write!(f, "qos must be 0, 1, or 2, got {}", qos),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+/// A validated `[[schedule]]` entry
+#[derive(Debug, Clone)]
+pub struct ScheduledPublish {
+    name: String,
+    cron: Cron,
+    topic: Template,
+    payload: Template,
+    qos: QoS,
+    retain: bool,
+    username: Option<String>,
+    misfire_grace: Duration,
+    budget: u32,
+}
+
+impl ScheduledPublish {
+    pub fn new(config: &ScheduleConfig) -> Result<Self, ScheduleError> {
+        let cron = Cron::parse(&config.cron).map_err(ScheduleError::Cron)?;
+        let qos = QoS::from_u8(config.qos).ok_or(ScheduleError::InvalidQos(config.qos))?;
+        let template =
+            |text: &str| Template::parse(text, VARS).map_err(ScheduleError::InvalidTemplate);
+        let schedule = Self {
+            name: config.name.clone(),
+            cron,
+            topic: template(&config.topic)?,
+            payload: template(&config.payload)?,
+            qos,
+            retain: config.retain,
+            username: config.username.clone(),
+            misfire_grace: config.misfire_grace,
+            budget: config.budget,
+        };
+        // A trial render catches bad arguments and tight budgets; topics
+        // are checked again on every publish
+        let topic = schedule
+            .render(&schedule.topic, 0)
+            .map_err(ScheduleError::InvalidTemplate)?;
+        validate_topic_name(&topic).map_err(ScheduleError::InvalidTopic)?;
+        schedule
+            .render(&schedule.payload, 0)
+            .map_err(ScheduleError::InvalidTemplate)?;
+        Ok(schedule)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Expand a template for the firing time `at` (Unix seconds)
+    fn render(&self, template: &Template, at: u64) -> Result<String, TemplateError> {
+        let values = [
+            Value::from(self.name.as_str()),
+            Value::Number(at as f64),
+            Value::from(cron::format_rfc3339(at)),
+        ];
+        template.render(&values, self.budget)
+    }
+
+    /// The message published for the firing time `at`
+    pub fn message(&self, at: u64) -> Result<LocalPublish, TemplateError> {
+        Ok(LocalPublish::new(
+            self.render(&self.topic, at)?,
+            self.render(&self.payload, at)?,
+        )
+        .with_qos(self.qos)
+        .with_retain(self.retain))
+    }
+
+    /// Next firing time to handle, given the last one handled
+    ///
+    /// A time missed since `last_run` is due now if it's within the
+    /// misfire grace; otherwise the next one after `now`.
+    fn next_run(&self, last_run: Option<u64>, now: u64) -> Option<u64> {
+        if let Some(missed) = last_run
+            .and_then(|last| self.cron.next_after(last))
+            .filter(|&at| at <= now)
+        {
+            // The latest missed time, not a burst of every one of them
+            let mut latest = missed;
+            while let Some(at) = self.cron.next_after(latest).filter(|&at| at <= now) {
+                latest = at;
+            }
+            if now - latest <= self.misfire_grace.as_secs() {
+                return Some(latest);
+            }
+        }
+        self.cron.next_after(now)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Runs the configured schedules against a broker
+pub struct Scheduler {
+    broker: Arc<Broker>,
+    schedules: Vec<(ScheduledPublish, LocalPublisher)>,
+    last_runs: HashMap<String, u64>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl Scheduler {
+    /// Scheduler for the enabled entries of `configs`
+    pub fn new(broker: Arc<Broker>, configs: &[ScheduleConfig]) -> Result<Self, ScheduleError> {
+        let mut schedules = Vec::new();
+        for config in configs.iter().filter(|c| c.enabled) {
+            let schedule = ScheduledPublish::new(config)?;
+            let mut publisher = broker.local_publisher(&format!("schedule:{}", schedule.name));
+            if let Some(ref username) = schedule.username {
+                publisher = publisher.with_username(username.clone());
+            }
+            schedules.push((schedule, publisher));
+        }
+        Ok(Self {
+            metrics: broker.metrics().cloned(),
+            broker,
+            schedules,
+            last_runs: HashMap::new(),
+        })
+    }
+
+    /// Restore the last runs loaded from persistence
+    pub fn with_last_runs(mut self, runs: Vec<(String, StoredScheduleRun)>) -> Self {
+        self.last_runs = runs
+            .into_iter()
+            .map(|(name, run)| (name, run.last_run_secs))
+            .collect();
+        self
+    }
+
+    /// Number of enabled schedules
+    pub fn len(&self) -> usize {
+        self.schedules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schedules.is_empty()
+    }
+
+    /// Fire schedules until the broker shuts down
+    pub async fn run(mut self) {
+        let mut shutdown = self.broker.subscribe_shutdown();
+        let now = unix_now();
+        let mut next: Vec<Option<u64>> = self
+            .schedules
+            .iter()
+            .map(|(s, _)| s.next_run(self.last_runs.get(&s.name).copied(), now))
+            .collect();
+
+        loop {
+            let Some(due) = next.iter().flatten().min().copied() else {
+                debug!("No scheduled publish will fire again");
+                return;
+            };
+            let wait = Duration::from_secs(due.saturating_sub(unix_now()));
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = shutdown.recv() => return,
+            }
+            // Sleeping can run a little short of the wall clock
+            let now = unix_now();
+            if now < due {
+                continue;
+            }
+
+            let leader = self.broker.cluster_manager().is_none_or(|c| c.is_leader());
+            for (i, next) in next.iter_mut().enumerate() {
+                let Some(at) = next.filter(|&at| at <= now) else {
+                    continue;
+                };
+                if leader {
+                    self.fire(i, at).await;
+                }
+                self.record_run(i, at);
+                *next = self.schedules[i].0.cron.next_after(now);
+            }
+        }
+    }
+
+    async fn fire(&self, index: usize, at: u64) {
+        let (ref schedule, ref publisher) = self.schedules[index];
+        let message = match schedule.message(at) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Schedule '{}' failed to render: {}", schedule.name, e);
+                return;
+            }
+        };
+        match publisher.publish(message).await {
+            Ok(()) => {
+                debug!("Schedule '{}' fired", schedule.name);
+                if let Some(ref metrics) = self.metrics {
+                    metrics.schedule_fired(&schedule.name);
+                }
+            }
+            Err(e) => warn!("Schedule '{}' failed to publish: {}", schedule.name, e),
+        }
+    }
+
+    /// Remember (and persist) `at` as the last handled firing time
+    fn record_run(&mut self, index: usize, at: u64) {
+        let name = &self.schedules[index].0.name;
+        self.last_runs.insert(name.clone(), at);
+        if let Some(persistence) = self.broker.persistence() {
+            persistence.write(PersistenceOp::SetScheduleRun {
+                name: name.clone(),
+                run: StoredScheduleRun { last_run_secs: at },
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-05-01T12:34:56Z
+    const NOW: u64 = 1_714_566_896;
+
+    fn schedule(cron: &str) -> ScheduledPublish {
+        ScheduledPublish::new(&ScheduleConfig {
+            name: "beat".to_string(),
+            cron: cron.to_string(),
+            topic: "heartbeat/{name}".to_string(),
+            payload: r#"{"at": "{datetime}", "ts": {timestamp}}"#.to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_templates() {
+        let s = schedule("@hourly");
+        assert_eq!(s.render(&s.topic, NOW).unwrap(), "heartbeat/beat");
+        assert_eq!(
+            s.render(&s.payload, NOW).unwrap(),
+            r#"{"at": "2024-05-01T12:34:56Z", "ts": 1714566896}"#
+        );
+
+        let s = ScheduledPublish::new(&ScheduleConfig {
+            name: "beat".to_string(),
+            cron: "@hourly".to_string(),
+            topic: "heartbeat/{upper(name)}/{format_time(timestamp, \"%Y/%m/%d\")}".to_string(),
+            payload: r#"{"id": {json(name)}, "hash": "{substr(sha256(timestamp), 0, 8)}"}"#
+                .to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            s.render(&s.topic, NOW).unwrap(),
+            "heartbeat/BEAT/2024/05/01"
+        );
+        assert_eq!(
+            s.render(&s.payload, NOW).unwrap(),
+            r#"{"id": "beat", "hash": "c9053581"}"#
+        );
+    }
+
+    #[test]
+    fn test_invalid_schedules() {
+        let config = |topic: &str, qos: u8| ScheduleConfig {
+            name: "x".to_string(),
+            cron: "@daily".to_string(),
+            topic: topic.to_string(),
+            qos,
+            ..Default::default()
+        };
+        assert!(matches!(
+            ScheduledPublish::new(&config("devices/+/cmd", 0)),
+            Err(ScheduleError::InvalidTopic(_))
+        ));
+        assert_eq!(
+            ScheduledPublish::new(&config("devices/cmd", 3)).unwrap_err(),
+            ScheduleError::InvalidQos(3)
+        );
+        // Functions can produce invalid topics, or take too long
+        assert!(matches!(
+            ScheduledPublish::new(&config("devices/{replace(name, \"x\", \"#\")}", 0)),
+            Err(ScheduleError::InvalidTopic(_))
+        ));
+        assert!(matches!(
+            ScheduledPublish::new(&ScheduleConfig {
+                budget: 10,
+                ..config("devices/{pad_left(name, 4096)}", 0)
+            }),
+            Err(ScheduleError::InvalidTemplate(
+                TemplateError::BudgetExceeded(10)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_next_run_misfire() {
+        let s = schedule("@hourly");
+        let noon = 1_714_564_800;
+
+        // Never run: wait for the next hour
+        assert_eq!(s.next_run(None, NOW), Some(noon + 3600));
+        // Noon already handled
+        assert_eq!(s.next_run(Some(noon), NOW), Some(noon + 3600));
+
+        // Down over noon, back within the grace: noon is due now
+        assert_eq!(s.next_run(Some(noon - 3600), noon + 60), Some(noon));
+        // Down for hours: only the latest missed time is due...
+        assert_eq!(s.next_run(Some(noon - 5 * 3600), noon + 60), Some(noon));
+        // ...and only if it's recent enough
+        assert_eq!(s.next_run(Some(noon - 3600), NOW), Some(noon + 3600));
+    }
+}
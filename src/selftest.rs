@@ -0,0 +1,854 @@
+//! Startup Self-Test
+//!
+//! `vibemq selftest` checks a deployment before traffic is cut over to it.
+//! It boots the configured broker on ephemeral loopback ports, with the
+//! configured TLS certificates, PROXY protocol settings and hooks (auth,
+//! ACL, plugins) but without persistence, bridges or clustering. Then it
+//! runs a smoke suite of MQTT flows against it:
+//!
+//! - CONNECT over MQTT 3.1.1 and 5.0 with the given credentials
+//! - refusal of unknown credentials, when auth is enabled
+//! - publish/subscribe round trips at each QoS up to the TCP listener's
+//!   maximum, with their acknowledgement flows
+//! - synthetic PROXY v1 and v2 headers parsed back, and a CONNECT behind
+//!   each on the TCP listener when it takes PROXY headers
+//! - a TLS handshake in which the broker must present the configured
+//!   certificate, and a CONNECT over it
+//!
+//! Checks that don't apply to the configuration are skipped. The self-test
+//! connects from loopback, so the ephemeral broker trusts PROXY headers
+//! from any peer.
+
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{
+    verify_tls12_signature, verify_tls13_signature, CryptoProvider,
+};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use crate::broker::{Broker, BrokerConfig};
+use crate::codec::{Decoder, Encoder};
+use crate::config::ProxyProtocolConfig;
+use crate::hooks::Hooks;
+use crate::protocol::{
+    ConnAck, Connect, Disconnect, Packet, Properties, ProtocolVersion, PubAck, PubComp, PubRec,
+    PubRel, Publish, QoS, RetainHandling, Subscribe, Subscription, SubscriptionOptions,
+};
+use crate::proxy::{
+    encode_proxy_header_v1, encode_proxy_header_v2, parse_proxy_header, ProxyInfo, ProxyTlsInfo,
+    ProxyVersion, PP2_TYPE_UNIQUE_ID,
+};
+use crate::transport::PeerAddr;
+
+/// Payload of the round-trip messages
+const PAYLOAD: &[u8] = b"vibemq selftest";
+
+/// How a check ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// Not applicable to the configuration
+    Skipped(String),
+    Failed(String),
+}
+
+impl From<Result<(), String>> for Outcome {
+    fn from(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => Outcome::Passed,
+            Err(reason) => Outcome::Failed(reason),
+        }
+    }
+}
+
+/// One check of the suite
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub outcome: Outcome,
+    pub elapsed: Duration,
+}
+
+/// Results of a self-test run
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn count(&self, matches: fn(&Outcome) -> bool) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| matches(&check.outcome))
+            .count()
+    }
+
+    /// Whether no check failed
+    pub fn passed(&self) -> bool {
+        self.failures() == 0
+    }
+
+    pub fn failures(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Failed(_)))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match &check.outcome {
+                Outcome::Passed => writeln!(f, "PASS  {} ({:?})", check.name, check.elapsed)?,
+                Outcome::Skipped(reason) => writeln!(f, "SKIP  {}: {}", check.name, reason)?,
+                Outcome::Failed(reason) => writeln!(f, "FAIL  {}: {}", check.name, reason)?,
+            }
+        }
+        write!(
+            f,
+            "{} passed, {} skipped, {} failed",
+            self.count(|outcome| *outcome == Outcome::Passed),
+            self.count(|outcome| matches!(outcome, Outcome::Skipped(_))),
+            self.failures()
+        )
+    }
+}
+
+/// A self-test of a broker configuration (see the module docs)
+pub struct SelfTest {
+    config: BrokerConfig,
+    hooks: Arc<dyn Hooks>,
+    auth_enabled: bool,
+    username: Option<String>,
+    password: Option<String>,
+    topic: String,
+    timeout: Duration,
+}
+
+impl SelfTest {
+    pub fn new(config: BrokerConfig, hooks: Arc<dyn Hooks>) -> Self {
+        Self {
+            config,
+            hooks,
+            auth_enabled: false,
+            username: None,
+            password: None,
+            topic: "vibemq/selftest".to_string(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Check that unknown credentials are refused
+    pub fn with_auth_enabled(mut self, enabled: bool) -> Self {
+        self.auth_enabled = enabled;
+        self
+    }
+
+    /// Credentials the test clients connect with
+    pub fn with_credentials(mut self, username: Option<String>, password: Option<String>) -> Self {
+        self.username = username;
+        self.password = password;
+        self
+    }
+
+    /// Topic the round-trip messages are published under
+    pub fn with_topic(mut self, topic: impl Into<String>) -> Self {
+        self.topic = topic.into();
+        self
+    }
+
+    /// How long to wait for each response from the broker
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Boot the broker, run the suite and shut the broker down; errors if
+    /// the broker doesn't start
+    pub async fn run(self) -> io::Result<Report> {
+        let config = ephemeral(&self.config)?;
+        let tcp = Endpoint {
+            addr: config.bind_addr,
+            proxy: config.proxy_protocol.clone(),
+        };
+        let tls = config
+            .tls_bind_addr
+            .zip(config.tls_config.clone())
+            .map(|(addr, tls)| {
+                let endpoint = Endpoint {
+                    addr,
+                    proxy: config.tls_proxy_protocol.clone(),
+                };
+                (endpoint, tls)
+            });
+        let max_qos = config
+            .listener_max_qos
+            .map_or(config.max_qos, |cap| cap.min(config.max_qos));
+
+        let broker = Arc::new(Broker::with_hooks(config, self.hooks.clone()));
+        let runner = {
+            let broker = broker.clone();
+            tokio::spawn(async move { broker.run().await })
+        };
+        let started = self.wait_started(tcp.addr, &runner).await;
+        if started.is_err() {
+            broker.shutdown();
+            runner.abort();
+        }
+        started?;
+
+        let mut report = Report::default();
+        let suite = Suite {
+            test: &self,
+            nonce: std::process::id(),
+        };
+        for version in [ProtocolVersion::V311, ProtocolVersion::V5] {
+            let name = format!("connect ({})", version_name(version));
+            report.run(name, suite.connect(&tcp, version, None)).await;
+        }
+        if self.auth_enabled {
+            report
+                .run("reject unknown credentials", suite.bad_credentials(&tcp))
+                .await;
+        } else {
+            report.skip("reject unknown credentials", "auth is disabled");
+        }
+        for qos in [QoS::AtMostOnce, QoS::AtLeastOnce, QoS::ExactlyOnce] {
+            let name = format!("publish/subscribe (QoS {})", qos as u8);
+            if qos > max_qos {
+                report.skip(
+                    name,
+                    format!("the TCP listener's maximum QoS is {}", max_qos as u8),
+                );
+            } else {
+                report.run(name, suite.round_trip(&tcp, qos)).await;
+            }
+        }
+        for version in [ProxyVersion::V1, ProxyVersion::V2] {
+            let name = format!("PROXY {:?} header parsing", version);
+            report.run(name, suite.proxy_parse(version)).await;
+        }
+        for version in [ProxyVersion::V1, ProxyVersion::V2] {
+            let name = format!("PROXY {:?} connect", version);
+            if tcp.proxy.enabled {
+                let header = proxy_header(version, tcp.addr)?;
+                let connect = suite.connect(&tcp, ProtocolVersion::V5, Some(header));
+                report.run(name, connect).await;
+            } else {
+                report.skip(name, "the TCP listener doesn't take PROXY headers");
+            }
+        }
+        match &tls {
+            None => report.skip("TLS handshake", "no TLS listener configured"),
+            Some((_, config)) if config.require_client_cert => report.skip(
+                "TLS handshake",
+                "the TLS listener requires client certificates",
+            ),
+            Some((endpoint, config)) => {
+                report
+                    .run("TLS handshake", suite.tls(endpoint, &config.cert_path))
+                    .await
+            }
+        }
+
+        broker.shutdown();
+        runner.abort();
+        Ok(report)
+    }
+
+    /// Wait until the TCP listener accepts connections
+    async fn wait_started(
+        &self,
+        addr: SocketAddr,
+        runner: &tokio::task::JoinHandle<io::Result<()>>,
+    ) -> io::Result<()> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if runner.is_finished() {
+                return Err(io::Error::other("broker stopped during startup"));
+            }
+            if TcpStream::connect(addr).await.is_ok() {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("broker not listening on {} in time", addr),
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+}
+
+impl Report {
+    async fn run(
+        &mut self,
+        name: impl Into<String>,
+        check: impl std::future::Future<Output = Result<(), String>>,
+    ) {
+        let started = Instant::now();
+        let outcome = check.await.into();
+        self.checks.push(Check {
+            name: name.into(),
+            outcome,
+            elapsed: started.elapsed(),
+        });
+    }
+
+    fn skip(&mut self, name: impl Into<String>, reason: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            outcome: Outcome::Skipped(reason.into()),
+            elapsed: Duration::ZERO,
+        });
+    }
+}
+
+/// The configuration on free loopback ports, with only the TCP and TLS
+/// listeners
+fn ephemeral(config: &BrokerConfig) -> io::Result<BrokerConfig> {
+    let mut config = config.clone();
+    config.bind_addr = free_port()?;
+    config.extra_bind_addrs.clear();
+    config.tls_bind_addr = match (config.tls_bind_addr, &config.tls_config) {
+        (Some(_), Some(_)) => Some(free_port()?),
+        _ => None,
+    };
+    config.ws_bind_addr = None;
+    config.wss_bind_addr = None;
+    config.quic_bind_addr = None;
+    config.unix_bind_path = None;
+    config.proxy_protocol.trusted_networks.clear();
+    config.tls_proxy_protocol.trusted_networks.clear();
+    Ok(config)
+}
+
+fn free_port() -> io::Result<SocketAddr> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?.local_addr()
+}
+
+fn version_name(version: ProtocolVersion) -> &'static str {
+    match version {
+        ProtocolVersion::V5 => "MQTT 5.0",
+        ProtocolVersion::V311 => "MQTT 3.1.1",
+    }
+}
+
+/// Synthetic identity a test proxy forwards
+fn proxy_info(version: ProxyVersion, server: SocketAddr) -> ProxyInfo {
+    let v2 = version == ProxyVersion::V2;
+    ProxyInfo {
+        client_addr: PeerAddr::Tcp(SocketAddr::from(([192, 0, 2, 10], 40000))),
+        server_addr: Some(PeerAddr::Tcp(server)),
+        tls_info: v2.then(|| ProxyTlsInfo {
+            sni: Some("selftest.invalid".to_string()),
+            client_cert_cn: Some("vibemq-selftest".to_string()),
+            version: Some("TLSv1.3".to_string()),
+            ..Default::default()
+        }),
+        version,
+        tlvs: if v2 {
+            vec![(PP2_TYPE_UNIQUE_ID, Bytes::from_static(b"selftest"))]
+        } else {
+            Vec::new()
+        },
+    }
+}
+
+fn proxy_header(version: ProxyVersion, server: SocketAddr) -> io::Result<Vec<u8>> {
+    let info = proxy_info(version, server);
+    match version {
+        ProxyVersion::V1 => Ok(encode_proxy_header_v1(&info)),
+        ProxyVersion::V2 => encode_proxy_header_v2(&info),
+    }
+}
+
+/// A listener under test
+struct Endpoint {
+    addr: SocketAddr,
+    proxy: ProxyProtocolConfig,
+}
+
+impl Endpoint {
+    /// A PROXY header for a connection, if the listener requires one
+    fn required_proxy_header(&self) -> Result<Option<Vec<u8>>, String> {
+        if !self.proxy.enabled || self.proxy.optional {
+            return Ok(None);
+        }
+        proxy_header(ProxyVersion::V2, self.addr)
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+}
+
+struct Suite<'a> {
+    test: &'a SelfTest,
+    nonce: u32,
+}
+
+impl Suite<'_> {
+    fn client_id(&self, role: &str) -> String {
+        format!("selftest-{}-{}", self.nonce, role)
+    }
+
+    /// Connect a client to the TCP listener, behind `proxy` if given
+    async fn client(
+        &self,
+        endpoint: &Endpoint,
+        version: ProtocolVersion,
+        proxy: Option<Vec<u8>>,
+    ) -> Result<Client, String> {
+        let mut stream = timeout(self.test.timeout, TcpStream::connect(endpoint.addr))
+            .await
+            .map_err(|_| "connect timed out".to_string())?
+            .map_err(|e| format!("connect failed: {}", e))?;
+        if let Some(header) = proxy.or(endpoint.required_proxy_header()?) {
+            stream
+                .write_all(&header)
+                .await
+                .map_err(|e| format!("write failed: {}", e))?;
+        }
+        Ok(Client::new(stream, version, self.test.timeout))
+    }
+
+    /// CONNECT with the credentials, expecting success
+    async fn session(
+        &self,
+        endpoint: &Endpoint,
+        role: &str,
+        version: ProtocolVersion,
+    ) -> Result<Client, String> {
+        let mut client = self.client(endpoint, version, None).await?;
+        let username = self.test.username.clone();
+        let password = self.test.password.clone();
+        expect_accepted(
+            client
+                .connect(self.client_id(role), username, password)
+                .await?,
+        )?;
+        Ok(client)
+    }
+
+    async fn connect(
+        &self,
+        endpoint: &Endpoint,
+        version: ProtocolVersion,
+        proxy: Option<Vec<u8>>,
+    ) -> Result<(), String> {
+        let mut client = self.client(endpoint, version, proxy).await?;
+        let role = match version {
+            ProtocolVersion::V5 => "v5",
+            _ => "v311",
+        };
+        let username = self.test.username.clone();
+        let password = self.test.password.clone();
+        expect_accepted(
+            client
+                .connect(self.client_id(role), username, password)
+                .await?,
+        )?;
+        client.disconnect().await
+    }
+
+    async fn bad_credentials(&self, endpoint: &Endpoint) -> Result<(), String> {
+        let mut client = self.client(endpoint, ProtocolVersion::V5, None).await?;
+        let username = format!("selftest-unknown-{}", self.nonce);
+        let password = format!("not-a-password-{}", self.nonce);
+        match client
+            .connect(self.client_id("auth"), Some(username), Some(password))
+            .await
+        {
+            Ok(ack) if ack.reason_code.is_success() => {
+                Err("the broker accepted unknown credentials".to_string())
+            }
+            // Refused, or disconnected without a CONNACK
+            _ => Ok(()),
+        }
+    }
+
+    async fn round_trip(&self, endpoint: &Endpoint, qos: QoS) -> Result<(), String> {
+        let topic = format!("{}/{}/qos{}", self.test.topic, self.nonce, qos as u8);
+        let role = |side| format!("qos{}-{}", qos as u8, side);
+
+        let mut subscriber = self
+            .session(endpoint, &role("sub"), ProtocolVersion::V5)
+            .await?;
+        subscriber
+            .send(&Packet::Subscribe(Subscribe {
+                packet_id: 1,
+                subscriptions: vec![Subscription {
+                    filter: topic.clone(),
+                    options: SubscriptionOptions {
+                        qos,
+                        no_local: false,
+                        retain_as_published: false,
+                        retain_handling: RetainHandling::DoNotSend,
+                    },
+                }],
+                properties: Properties::default(),
+            }))
+            .await?;
+        match subscriber.recv().await? {
+            Packet::SubAck(ack) => match ack.reason_codes.first() {
+                Some(code) if code.is_success() && *code as u8 >= qos as u8 => {}
+                Some(code) => return Err(format!("subscription refused: {:?}", code)),
+                None => return Err("empty SUBACK".to_string()),
+            },
+            other => return Err(unexpected("SUBACK", &other)),
+        }
+
+        let mut publisher = self
+            .session(endpoint, &role("pub"), ProtocolVersion::V5)
+            .await?;
+        let packet_id = (qos != QoS::AtMostOnce).then_some(1);
+        publisher
+            .send(&Packet::Publish(Publish {
+                dup: false,
+                qos,
+                retain: false,
+                topic: topic.clone(),
+                packet_id,
+                payload: Bytes::from_static(PAYLOAD),
+                properties: Properties::default(),
+            }))
+            .await?;
+        match qos {
+            QoS::AtMostOnce => {}
+            QoS::AtLeastOnce => match publisher.recv().await? {
+                Packet::PubAck(ack) if ack.reason_code.is_success() => {}
+                other => return Err(unexpected("PUBACK", &other)),
+            },
+            QoS::ExactlyOnce => {
+                match publisher.recv().await? {
+                    Packet::PubRec(rec) if rec.reason_code.is_success() => {}
+                    other => return Err(unexpected("PUBREC", &other)),
+                }
+                publisher.send(&Packet::PubRel(PubRel::new(1))).await?;
+                match publisher.recv().await? {
+                    Packet::PubComp(_) => {}
+                    other => return Err(unexpected("PUBCOMP", &other)),
+                }
+            }
+        }
+
+        let delivered = match subscriber.recv().await? {
+            Packet::Publish(publish) => publish,
+            other => return Err(unexpected("PUBLISH", &other)),
+        };
+        if delivered.topic != topic || delivered.payload != PAYLOAD {
+            return Err(format!(
+                "delivered a different message on {}",
+                delivered.topic
+            ));
+        }
+        if delivered.qos != qos {
+            return Err(format!("delivered at QoS {}", delivered.qos as u8));
+        }
+        let delivered_id = delivered.packet_id.unwrap_or_default();
+        match qos {
+            QoS::AtMostOnce => {}
+            QoS::AtLeastOnce => {
+                subscriber
+                    .send(&Packet::PubAck(PubAck::new(delivered_id)))
+                    .await?
+            }
+            QoS::ExactlyOnce => {
+                subscriber
+                    .send(&Packet::PubRec(PubRec::new(delivered_id)))
+                    .await?;
+                match subscriber.recv().await? {
+                    Packet::PubRel(_) => {}
+                    other => return Err(unexpected("PUBREL", &other)),
+                }
+                subscriber
+                    .send(&Packet::PubComp(PubComp::new(delivered_id)))
+                    .await?;
+            }
+        }
+
+        publisher.disconnect().await?;
+        subscriber.disconnect().await
+    }
+
+    async fn proxy_parse(&self, version: ProxyVersion) -> Result<(), String> {
+        let server = SocketAddr::from(([198, 51, 100, 1], 1883));
+        let expected = proxy_info(version, server);
+        let mut input = proxy_header(version, server).map_err(|e| e.to_string())?;
+        input.extend_from_slice(b"\x10MQTT");
+
+        let mut reader = input.as_slice();
+        let (info, rest) = parse_proxy_header(&mut reader, self.test.timeout, true)
+            .await
+            .map_err(|e| e.to_string())?;
+        if info.client_addr != expected.client_addr || info.server_addr != expected.server_addr {
+            return Err(format!(
+                "parsed addresses {:?} -> {:?}",
+                info.client_addr, info.server_addr
+            ));
+        }
+        let sni = |info: &ProxyInfo| info.tls_info.as_ref().and_then(|tls| tls.sni.clone());
+        if sni(&info) != sni(&expected) {
+            return Err(format!("parsed SNI {:?}", sni(&info)));
+        }
+        if info.tlv(PP2_TYPE_UNIQUE_ID) != expected.tlv(PP2_TYPE_UNIQUE_ID) {
+            return Err("unique ID TLV not parsed".to_string());
+        }
+        // The parser may read past the header; nothing after it is lost
+        let mut after = rest.to_vec();
+        after.extend_from_slice(reader);
+        if after != b"\x10MQTT" {
+            return Err("bytes after the header were lost".to_string());
+        }
+        Ok(())
+    }
+
+    async fn tls(&self, endpoint: &Endpoint, cert_path: &str) -> Result<(), String> {
+        let expected = CertificateDer::pem_file_iter(cert_path)
+            .and_then(|mut certs| certs.next().transpose())
+            .map_err(|e| format!("reading {}: {}", cert_path, e))?
+            .ok_or_else(|| format!("no certificate in {}", cert_path))?;
+        let mut tls = ClientConfig::builder()
+            .with_root_certificates(RootCertStore::empty())
+            .with_no_client_auth();
+        let provider = tls.crypto_provider().clone();
+        tls.dangerous()
+            .set_certificate_verifier(Arc::new(ConfiguredCert { expected, provider }));
+
+        let mut stream = timeout(self.test.timeout, TcpStream::connect(endpoint.addr))
+            .await
+            .map_err(|_| "connect timed out".to_string())?
+            .map_err(|e| format!("connect failed: {}", e))?;
+        if let Some(header) = endpoint.required_proxy_header()? {
+            stream
+                .write_all(&header)
+                .await
+                .map_err(|e| format!("write failed: {}", e))?;
+        }
+        let server_name = ServerName::try_from("localhost").expect("valid server name");
+        let stream = timeout(
+            self.test.timeout,
+            TlsConnector::from(Arc::new(tls)).connect(server_name, stream),
+        )
+        .await
+        .map_err(|_| "handshake timed out".to_string())?
+        .map_err(|e| format!("handshake failed: {}", e))?;
+
+        let mut client = Client::new(stream, ProtocolVersion::V5, self.test.timeout);
+        let username = self.test.username.clone();
+        let password = self.test.password.clone();
+        expect_accepted(
+            client
+                .connect(self.client_id("tls"), username, password)
+                .await?,
+        )?;
+        client.disconnect().await
+    }
+}
+
+fn expect_accepted(ack: ConnAck) -> Result<(), String> {
+    if ack.reason_code.is_success() {
+        Ok(())
+    } else {
+        Err(format!("connection refused: {:?}", ack.reason_code))
+    }
+}
+
+fn unexpected(expected: &str, packet: &Packet) -> String {
+    format!(
+        "expected {}, got packet type {}",
+        expected,
+        packet.packet_type()
+    )
+}
+
+/// Accepts the server certificate only if it is the configured one (the
+/// handshake signature proves the server holds its key)
+#[derive(Debug)]
+struct ConfiguredCert {
+    expected: CertificateDer<'static>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for ConfiguredCert {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if end_entity.as_ref() == self.expected.as_ref() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "the broker presented a certificate other than the configured one".to_string(),
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// A byte stream the test client talks MQTT over
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// Minimal MQTT client for the suite
+struct Client {
+    stream: Box<dyn Stream>,
+    encoder: Encoder,
+    decoder: Decoder,
+    read_buf: BytesMut,
+    timeout: Duration,
+}
+
+impl Client {
+    fn new(stream: impl Stream + 'static, version: ProtocolVersion, timeout: Duration) -> Self {
+        let mut decoder = Decoder::new();
+        decoder.set_protocol_version(version);
+        Self {
+            stream: Box::new(stream),
+            encoder: Encoder::new(version),
+            decoder,
+            read_buf: BytesMut::new(),
+            timeout,
+        }
+    }
+
+    async fn send(&mut self, packet: &Packet) -> Result<(), String> {
+        let mut buf = BytesMut::new();
+        self.encoder
+            .encode(packet, &mut buf)
+            .map_err(|e| format!("encode failed: {}", e))?;
+        self.stream
+            .write_all(&buf)
+            .await
+            .map_err(|e| format!("write failed: {}", e))
+    }
+
+    async fn recv(&mut self) -> Result<Packet, String> {
+        loop {
+            if let Some((packet, consumed)) = self
+                .decoder
+                .decode(&self.read_buf)
+                .map_err(|e| format!("malformed packet from the broker: {}", e))?
+            {
+                let _ = self.read_buf.split_to(consumed);
+                return Ok(packet);
+            }
+            let mut buf = [0u8; 4096];
+            match timeout(self.timeout, self.stream.read(&mut buf)).await {
+                Ok(Ok(0)) => return Err("the broker closed the connection".to_string()),
+                Ok(Ok(n)) => self.read_buf.extend_from_slice(&buf[..n]),
+                Ok(Err(e)) => return Err(format!("read failed: {}", e)),
+                Err(_) => return Err("no response from the broker in time".to_string()),
+            }
+        }
+    }
+
+    async fn connect(
+        &mut self,
+        client_id: String,
+        username: Option<String>,
+        password: Option<String>,
+    ) -> Result<ConnAck, String> {
+        let connect = Connect {
+            protocol_version: self.encoder.protocol_version(),
+            client_id,
+            clean_start: true,
+            keep_alive: 60,
+            username,
+            password: password.map(Bytes::from),
+            will: None,
+            properties: Properties::default(),
+        };
+        self.send(&Packet::Connect(Box::new(connect))).await?;
+        match self.recv().await? {
+            Packet::ConnAck(ack) => Ok(ack),
+            other => Err(unexpected("CONNACK", &other)),
+        }
+    }
+
+    async fn disconnect(&mut self) -> Result<(), String> {
+        self.send(&Packet::Disconnect(Disconnect::default())).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::DefaultHooks;
+
+    #[tokio::test]
+    async fn test_selftest_passes() {
+        let config = BrokerConfig {
+            bind_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+            proxy_protocol: ProxyProtocolConfig {
+                enabled: true,
+                optional: true,
+                trusted_networks: vec!["127.0.0.1/32".parse().unwrap()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let report = SelfTest::new(config, Arc::new(DefaultHooks))
+            .run()
+            .await
+            .unwrap();
+        assert!(report.passed(), "{}", report);
+        let skipped: Vec<_> = report
+            .checks
+            .iter()
+            .filter(|check| matches!(check.outcome, Outcome::Skipped(_)))
+            .map(|check| check.name.as_str())
+            .collect();
+        assert_eq!(skipped, ["reject unknown credentials", "TLS handshake"]);
+    }
+}
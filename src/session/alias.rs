@@ -0,0 +1,117 @@
+//! Server-to-client topic aliases
+//!
+//! Aliases are assigned to topics as messages go out, up to the Topic Alias
+//! Maximum the client advertised in CONNECT. Once all are taken, the least
+//! recently used alias is reassigned to the new topic, so a client with a
+//! small maximum still gets aliases for the topics it receives most often.
+//! Mappings belong to the network connection and start empty on each one.
+
+use std::collections::BTreeMap;
+
+use ahash::AHashMap;
+
+/// Topic alias table of one connection (server to client)
+#[derive(Debug, Default)]
+pub struct TopicAliases {
+    /// Topic to its alias and when it was last used
+    by_topic: AHashMap<String, (u16, u64)>,
+    /// Aliases in order of last use, with their topic
+    by_use: BTreeMap<u64, (u16, String)>,
+    /// Aliases given up by [`TopicAliases::forget`]
+    free: Vec<u16>,
+    /// Highest alias assigned so far
+    highest: u16,
+    /// Use counter
+    clock: u64,
+}
+
+impl TopicAliases {
+    /// Get the alias for `topic`, assigning one if it has none
+    ///
+    /// Returns the alias and whether it is new for the topic (so the topic
+    /// must go out along with it), or None if `maximum` is 0.
+    pub fn assign(&mut self, topic: &str, maximum: u16) -> Option<(u16, bool)> {
+        if maximum == 0 {
+            return None;
+        }
+        self.clock += 1;
+        let now = self.clock;
+
+        if let Some((alias, used)) = self.by_topic.get_mut(topic) {
+            let entry = self.by_use.remove(used).expect("alias in use order");
+            *used = now;
+            let alias = *alias;
+            self.by_use.insert(now, entry);
+            return Some((alias, false));
+        }
+
+        let alias = if let Some(alias) = self.free.pop() {
+            alias
+        } else if self.highest < maximum {
+            self.highest += 1;
+            self.highest
+        } else {
+            let (_, (alias, evicted)) = self.by_use.pop_first()?;
+            self.by_topic.remove(&evicted);
+            alias
+        };
+        self.by_topic.insert(topic.to_string(), (alias, now));
+        self.by_use.insert(now, (alias, topic.to_string()));
+        Some((alias, true))
+    }
+
+    /// Give up the alias of `topic`, e.g. when the message that would
+    /// have told the client about it was not sent
+    pub fn forget(&mut self, topic: &str) {
+        if let Some((alias, used)) = self.by_topic.remove(topic) {
+            self.by_use.remove(&used);
+            self.free.push(alias);
+        }
+    }
+
+    /// Number of aliases assigned
+    pub fn len(&self) -> usize {
+        self.by_topic.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_topic.is_empty()
+    }
+
+    /// Forget all aliases (new connection)
+    pub fn clear(&mut self) {
+        self.by_topic.clear();
+        self.by_use.clear();
+        self.free.clear();
+        self.highest = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_aliases_lru() {
+        let mut aliases = TopicAliases::default();
+        assert_eq!(aliases.assign("a", 0), None);
+
+        assert_eq!(aliases.assign("a", 2), Some((1, true)));
+        assert_eq!(aliases.assign("b", 2), Some((2, true)));
+        assert_eq!(aliases.assign("a", 2), Some((1, false)));
+
+        // Full: "b" is the least recently used
+        assert_eq!(aliases.assign("c", 2), Some((2, true)));
+        assert_eq!(aliases.assign("a", 2), Some((1, false)));
+        assert_eq!(aliases.assign("b", 2), Some((2, true)));
+        assert_eq!(aliases.assign("c", 2), Some((1, true)));
+        assert_eq!(aliases.len(), 2);
+
+        aliases.forget("b");
+        assert_eq!(aliases.assign("d", 2), Some((2, true)));
+
+        aliases.clear();
+        assert!(aliases.is_empty());
+        assert_eq!(aliases.assign("c", 2), Some((1, true)));
+    }
+}
@@ -0,0 +1,279 @@
+//! Idle session compression
+//!
+//! Persistent sessions of long-disconnected clients keep their subscription
+//! list and queued messages in memory. For fleets of mostly-idle devices
+//! that adds up, so after a configurable idle period the session store packs
+//! this state into an LZ4-compressed block. It is unpacked lazily when the
+//! client reconnects or the queue needs to drop its oldest message.
+//!
+//! Messages queued while a session is compressed are appended uncompressed
+//! and folded into the block on the next sweep.
+
+use std::time::{Duration, Instant};
+
+use bincode::{Decode, Encode};
+use tracing::error;
+
+use super::{PendingMessage, Session, SessionSubscription};
+use crate::persistence::{StoredPublish, StoredSubscription};
+use crate::protocol::Publish;
+
+/// Compressed subscription list and queued messages
+pub(super) struct CompressedState {
+    /// LZ4 block (size-prepended) of a bincode-encoded [`PackedState`]
+    data: Vec<u8>,
+    /// When the state was packed (queued_at is stored relative to this)
+    packed_at: Instant,
+    /// Number of queued messages in the block
+    pub(super) pending_count: usize,
+    /// Number of subscriptions in the block
+    subscription_count: usize,
+}
+
+impl CompressedState {
+    fn unpack(&self, client_id: &str) -> Option<PackedState> {
+        match lz4_flex::decompress_size_prepended(&self.data)
+            .map_err(|e| e.to_string())
+            .and_then(|decoded| {
+                bincode::decode_from_slice(&decoded, bincode::config::standard())
+                    .map(|(packed, _)| packed)
+                    .map_err(|e| e.to_string())
+            }) {
+            Ok(packed) => Some(packed),
+            Err(e) => {
+                error!("Failed to decompress session {}: {}", client_id, e);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Encode, Decode)]
+struct PackedState {
+    subscriptions: Vec<StoredSubscription>,
+    pending: Vec<PackedMessage>,
+}
+
+#[derive(Encode, Decode)]
+struct PackedMessage {
+    publish: StoredPublish,
+    /// Not part of StoredProperties, but needed for delivery
+    subscription_identifiers: Vec<u32>,
+    /// Time queued before packing, in milliseconds
+    age_ms: u64,
+}
+
+impl Session {
+    /// Whether the subscription list and queue are currently compressed
+    pub fn is_compressed(&self) -> bool {
+        self.compressed.is_some()
+    }
+
+    /// Size of the compressed block in bytes (0 if not compressed)
+    pub fn compressed_size(&self) -> usize {
+        self.compressed.as_ref().map_or(0, |c| c.data.len())
+    }
+
+    /// Number of subscriptions, packed ones included
+    pub fn subscription_count(&self) -> usize {
+        self.subscriptions.len() + self.compressed.as_ref().map_or(0, |c| c.subscription_count)
+    }
+
+    /// Subscriptions packed away in the compressed block, unpacked without
+    /// restoring them (empty if not compressed)
+    pub fn packed_subscriptions(&self) -> Vec<SessionSubscription> {
+        self.compressed
+            .as_ref()
+            .and_then(|c| c.unpack(&self.client_id))
+            .map(|packed| {
+                packed
+                    .subscriptions
+                    .into_iter()
+                    .map(SessionSubscription::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Compress subscriptions and queued messages
+    ///
+    /// Messages queued since the last compression are folded into the block.
+    /// Returns false if there was nothing to compress.
+    pub fn compress(&mut self) -> bool {
+        if self.subscriptions.is_empty() && self.pending_messages.is_empty() {
+            return false;
+        }
+        self.decompress();
+
+        let now = Instant::now();
+        let packed = PackedState {
+            subscriptions: self
+                .subscriptions
+                .values()
+                .map(StoredSubscription::from)
+                .collect(),
+            pending: self
+                .pending_messages
+                .iter()
+                .map(|pm| PackedMessage {
+                    publish: StoredPublish::from(&pm.publish),
+                    subscription_identifiers: pm
+                        .publish
+                        .properties
+                        .subscription_identifiers
+                        .clone(),
+                    age_ms: now.duration_since(pm.queued_at).as_millis() as u64,
+                })
+                .collect(),
+        };
+
+        let encoded = match bincode::encode_to_vec(&packed, bincode::config::standard()) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                error!("Failed to compress session {}: {}", self.client_id, e);
+                return false;
+            }
+        };
+
+        self.compressed = Some(CompressedState {
+            data: lz4_flex::compress_prepend_size(&encoded),
+            packed_at: now,
+            pending_count: packed.pending.len(),
+            subscription_count: packed.subscriptions.len(),
+        });
+        // Release the collections' allocations, not just their contents
+        self.subscriptions = Default::default();
+        self.pending_messages = Default::default();
+        true
+    }
+
+    /// Restore compressed subscriptions and queued messages (no-op if not compressed)
+    ///
+    /// Messages queued while compressed are kept after the restored ones.
+    pub fn decompress(&mut self) {
+        let Some(compressed) = self.compressed.take() else {
+            return;
+        };
+
+        let Some(packed) = compressed.unpack(&self.client_id) else {
+            return;
+        };
+
+        for sub in packed.subscriptions {
+            let sub = SessionSubscription::from(sub);
+            self.subscriptions
+                .entry(sub.filter.as_str().into())
+                .or_insert(sub);
+        }
+
+        let tail = std::mem::take(&mut self.pending_messages);
+        self.pending_messages = packed
+            .pending
+            .into_iter()
+            .map(|pm| {
+                let mut publish = Publish::from(pm.publish);
+                publish.properties.subscription_identifiers = pm.subscription_identifiers;
+                PendingMessage {
+                    publish,
+                    queued_at: compressed
+                        .packed_at
+                        .checked_sub(Duration::from_millis(pm.age_ms))
+                        .unwrap_or(compressed.packed_at),
+                }
+            })
+            .chain(tail)
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Properties, ProtocolVersion, QoS, SubscriptionOptions};
+    use crate::session::SessionLimits;
+
+    fn publish(payload: &'static str) -> Publish {
+        Publish {
+            topic: "devices/1/cmd".to_string(),
+            payload: bytes::Bytes::from(payload),
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            dup: false,
+            packet_id: None,
+            properties: Properties::default(),
+        }
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let mut session =
+            Session::new("dev1".into(), ProtocolVersion::V5, SessionLimits::default());
+        assert!(!session.compress());
+
+        session.add_subscription(
+            "devices/1/#".to_string(),
+            SubscriptionOptions {
+                qos: QoS::ExactlyOnce,
+                no_local: true,
+                ..Default::default()
+            },
+            Some(7),
+        );
+        let mut first = publish("first");
+        first.properties.subscription_identifiers.push(7);
+        first.properties.message_expiry_interval = Some(3600);
+        session.queue_message(first);
+
+        assert!(session.compress());
+        assert!(session.is_compressed());
+        assert!(session.compressed_size() > 0);
+        assert!(session.subscriptions.is_empty());
+        assert_eq!(session.subscription_count(), 1);
+        assert_eq!(session.packed_subscriptions()[0].filter, "devices/1/#");
+        assert!(session.is_compressed());
+        assert_eq!(session.pending_count(), 1);
+
+        // Queued while compressed: kept uncompressed, after the packed messages
+        session.queue_message(publish("second"));
+        assert_eq!(session.pending_count(), 2);
+
+        session.decompress();
+        assert!(!session.is_compressed());
+        let sub = &session.subscriptions["devices/1/#"];
+        assert_eq!(sub.options.qos, QoS::ExactlyOnce);
+        assert!(sub.options.no_local);
+        assert_eq!(sub.subscription_id, Some(7));
+
+        let messages = session.drain_pending_messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].payload, "first");
+        assert_eq!(messages[0].properties.subscription_identifiers, vec![7]);
+        assert!(messages[0].properties.message_expiry_interval.unwrap() > 3500);
+        assert_eq!(messages[1].payload, "second");
+    }
+
+    #[test]
+    fn test_queue_overflow_while_compressed() {
+        let limits = SessionLimits {
+            max_pending_messages: 2,
+            ..Default::default()
+        };
+        let mut session = Session::new("dev2".into(), ProtocolVersion::V5, limits);
+        session.queue_message(publish("a"));
+        session.queue_message(publish("b"));
+        assert!(session.compress());
+
+        // Full queue: the oldest (compressed) message is dropped
+        assert_eq!(
+            session.queue_message(publish("c")),
+            crate::session::QueueResult::DroppedOldest
+        );
+        assert!(!session.is_compressed());
+        let payloads: Vec<_> = session
+            .drain_pending_messages()
+            .into_iter()
+            .map(|p| p.payload)
+            .collect();
+        assert_eq!(payloads, vec!["b", "c"]);
+    }
+}
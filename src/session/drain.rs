@@ -0,0 +1,111 @@
+//! Fair draining of session backlogs
+//!
+//! A client that reconnects to a persistent session is sent its queued
+//! messages before anything else. After a restart many clients reconnect at
+//! once, and without coordination the first ones to arrive send their whole
+//! backlogs while later ones wait on bandwidth and disk.
+//!
+//! With `session.backlog_drain.fair`, at most `concurrency` sessions drain
+//! at a time, each sending one batch per turn: `batch_size` messages times
+//! the `drain_weight` of the client's ACL role. A session that has sent its
+//! batch queues again behind those waiting, so turns go round-robin in
+//! reconnect order and a role with weight 4 gets four times the share of a
+//! role with weight 1. Backlogs of a single batch go out without waiting,
+//! so ordinary reconnects aren't delayed.
+//!
+//! Connections wait for their turns in their packet loop, so a session
+//! waiting in line still answers its client. A turn lasts at most
+//! `turn_timeout`, so a client that stops reading can't hold one.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::BacklogDrainConfig;
+
+/// Turns for sessions draining their backlogs
+#[derive(Debug)]
+pub struct DrainScheduler {
+    turns: Arc<Semaphore>,
+    batch_size: usize,
+    turn_timeout: Duration,
+}
+
+impl DrainScheduler {
+    /// A scheduler if fair draining is enabled
+    pub fn new(config: &BacklogDrainConfig) -> Option<Self> {
+        config.fair.then(|| Self {
+            turns: Arc::new(Semaphore::new(config.concurrency)),
+            batch_size: config.batch_size,
+            turn_timeout: config.turn_timeout,
+        })
+    }
+
+    /// Messages a session of `weight` sends per turn
+    pub fn batch_size(&self, weight: u32) -> usize {
+        self.batch_size.saturating_mul(weight.max(1) as usize)
+    }
+
+    /// Longest a turn lasts
+    pub fn turn_timeout(&self) -> Duration {
+        self.turn_timeout
+    }
+
+    /// Wait for a turn, which lasts until the permit is dropped
+    ///
+    /// Waiters are served in order, so a session that queues again after
+    /// its turn goes behind the others. Dropping the future gives up the
+    /// place in line.
+    pub fn turn(&self) -> impl Future<Output = OwnedSemaphorePermit> + Send + Sync + 'static {
+        let turns = self.turns.clone();
+        // The semaphore is never closed
+        async move { turns.acquire_owned().await.expect("drain semaphore closed") }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_turns_go_round_robin() {
+        let config = BacklogDrainConfig {
+            fair: true,
+            concurrency: 1,
+            batch_size: 10,
+            ..Default::default()
+        };
+        let scheduler = Arc::new(DrainScheduler::new(&config).unwrap());
+        assert_eq!(scheduler.batch_size(3), 30);
+        assert!(DrainScheduler::new(&BacklogDrainConfig::default()).is_none());
+
+        // Two sessions with three turns each, the first holding the only turn
+        // while the second queues
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let first = scheduler.turn().await;
+        let mut tasks = Vec::new();
+        for session in ["a", "b"] {
+            let scheduler = scheduler.clone();
+            let order = order.clone();
+            tasks.push(tokio::spawn(async move {
+                for _ in 0..3 {
+                    let _turn = scheduler.turn().await;
+                    order.lock().push(session);
+                    tokio::task::yield_now().await;
+                }
+            }));
+            tokio::task::yield_now().await;
+        }
+        drop(first);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*order.lock(), ["a", "b", "a", "b", "a", "b"]);
+    }
+}
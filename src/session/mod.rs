@@ -11,6 +11,17 @@
 //! MQTT Compliance:
 //! - Implements message expiry per MQTT v5.0 spec [MQTT-3.3.2-5]
 
+mod alias;
+mod compress;
+mod drain;
+mod rate_limit;
+mod tenant;
+
+pub use alias::TopicAliases;
+pub use drain::DrainScheduler;
+pub use rate_limit::{RateBucket, RateLimiter};
+pub use tenant::{split_tenant, tenant_client_id, TENANT_SEPARATOR};
+
 use std::collections::VecDeque;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -20,7 +31,9 @@ use bytes::Bytes;
 use dashmap::DashMap;
 use parking_lot::RwLock;
 
+use crate::config::{BacklogDrainConfig, PublishRateConfig, QueueOverflow};
 use crate::protocol::{Properties, ProtocolVersion, Publish, QoS, SubscriptionOptions};
+use crate::proxy::ProxyIdentity;
 
 /// A pending message with timestamp for expiry tracking
 #[derive(Debug, Clone)]
@@ -53,6 +66,8 @@ pub struct InflightMessage {
     pub qos2_state: Option<Qos2State>,
     /// Timestamp when the message was sent
     pub sent_at: Instant,
+    /// When the message was first sent on the current connection
+    pub first_sent_at: Instant,
     /// Number of retransmission attempts
     pub retry_count: u32,
 }
@@ -81,6 +96,8 @@ pub struct SessionSubscription {
 pub struct Session {
     /// Client identifier
     pub client_id: Arc<str>,
+    /// Username the client last connected with (for ACL re-checks)
+    pub username: Option<Arc<str>>,
     /// Protocol version
     pub protocol_version: ProtocolVersion,
     /// Session state
@@ -99,12 +116,37 @@ pub struct Session {
     pub inflight_outgoing: AHashMap<u16, InflightMessage>,
     /// Inflight incoming messages (QoS 2) - stores the Publish packet until PUBREL
     pub inflight_incoming: AHashMap<u16, Publish>,
+    /// Outbound messages not acknowledged within the retry interval
+    /// (each retransmission counts)
+    pub ack_timeouts: u64,
+    /// The client keeps sending packets but stopped acknowledging messages
+    /// (see `limits.ack_alarm_after`)
+    pub ack_stalled: bool,
+    /// QoS 1/2 messages from the client whose acknowledgement was held for
+    /// congested subscribers (see `limits.publisher_backpressure`)
+    pub publishes_held: u64,
+    /// QoS 1/2 messages from the client rejected for congested subscribers
+    pub publishes_rejected: u64,
     /// Next packet identifier
     next_packet_id: u16,
     /// Pending messages (queued while disconnected) with expiry tracking
     pub pending_messages: VecDeque<PendingMessage>,
     /// Maximum pending messages
     pub max_pending_messages: usize,
+    /// Maximum topic and payload bytes of pending messages (0 = unlimited)
+    pub max_pending_bytes: usize,
+    /// Whether QoS 0 messages are queued
+    pub queue_qos0: bool,
+    /// What to do when the queue is full
+    pub queue_overflow: QueueOverflow,
+    /// Topic and payload bytes of pending messages, compressed ones included
+    pending_bytes: usize,
+    /// The queue overflowed under `QueueOverflow::Disconnect`; the session
+    /// ends once the client is gone
+    pub overflowed: bool,
+    /// QoS 0 messages evicted under memory pressure the client hasn't been
+    /// told of
+    evicted: u64,
     /// Maximum in-flight outgoing messages (QoS 1/2)
     pub max_inflight: u16,
     /// Maximum pending PUBREL (QoS 2 incoming)
@@ -117,10 +159,8 @@ pub struct Session {
     pub max_packet_size: u32,
     /// Topic aliases (client -> server) - uses AHashMap for faster lookup
     pub client_topic_aliases: AHashMap<u16, String>,
-    /// Topic aliases (server -> client) - uses AHashMap for faster lookup
-    pub server_topic_aliases: AHashMap<String, u16>,
-    /// Next server topic alias
-    next_server_alias: u16,
+    /// Topic aliases (server -> client)
+    pub server_topic_aliases: TopicAliases,
     /// Maximum topic alias
     pub topic_alias_maximum: u16,
     /// Will message
@@ -129,6 +169,10 @@ pub struct Session {
     pub will_delay_interval: u32,
     /// Disconnect timestamp
     pub disconnected_at: Option<Instant>,
+    /// Compressed subscriptions and queued messages (idle sessions only)
+    compressed: Option<compress::CompressedState>,
+    /// Handover under way; only its successor may connect (not persisted)
+    pub handover: Option<Handover>,
 }
 
 /// Will message
@@ -139,6 +183,30 @@ pub struct WillMessage {
     pub qos: QoS,
     pub retain: bool,
     pub properties: Properties,
+    /// Client's identity behind a PROXY header (not persisted)
+    pub proxy_identity: Option<Arc<ProxyIdentity>>,
+}
+
+/// CONNECT user property carrying a handover token
+pub const HANDOVER_TOKEN_PROPERTY: &str = "handover-token";
+
+/// A handover of the session to a successor connection (see
+/// [`crate::config::HandoverConfig`])
+#[derive(Debug, Clone)]
+pub struct Handover {
+    /// Token the successor presents as the `handover-token` CONNECT user
+    /// property
+    pub token: Arc<str>,
+    /// When the token stops being accepted
+    pub expires_at: Instant,
+}
+
+impl Handover {
+    /// Seconds until the token expires, rounded up
+    pub fn expires_in(&self) -> u64 {
+        let left = self.expires_at.saturating_duration_since(Instant::now());
+        left.as_millis().div_ceil(1000) as u64
+    }
 }
 
 /// Result of queueing a message
@@ -148,12 +216,71 @@ pub enum QueueResult {
     Queued,
     /// Message was queued but an older message was dropped due to queue overflow
     DroppedOldest,
+    /// Queue was full and the message was dropped
+    DroppedNewest,
+    /// Queue was full and the session is ending (`QueueOverflow::Disconnect`)
+    Overflow,
+    /// QoS 0 message not queued (`queue_qos0` is off)
+    Skipped,
+}
+
+impl QueueResult {
+    /// Whether a message was lost to queue overflow
+    pub fn dropped(self) -> bool {
+        matches!(
+            self,
+            QueueResult::DroppedOldest | QueueResult::DroppedNewest | QueueResult::Overflow
+        )
+    }
+}
+
+/// Queued messages of one session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueDepth {
+    pub client_id: Arc<str>,
+    pub messages: usize,
+    /// Topic and payload bytes
+    pub bytes: usize,
+    pub connected: bool,
+}
+
+/// A session removed by [`SessionStore::cleanup_expired`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpiredSession {
+    pub client_id: Arc<str>,
+    pub username: Option<Arc<str>>,
+    pub session_expiry_interval: u32,
+    /// Dropped for overflowing its queue rather than for expiring
+    pub overflowed: bool,
+    /// Queued messages discarded with the session, including compressed ones
+    pub pending_messages: usize,
+    /// Unacknowledged outgoing messages discarded with the session
+    pub inflight_messages: usize,
+}
+
+/// Queued messages evicted by [`SessionStore::evict_qos0`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Eviction {
+    pub messages: usize,
+    /// Topic and payload bytes
+    pub bytes: usize,
+    /// Sessions that lost messages
+    pub sessions: usize,
+}
+
+/// Bytes a queued message counts against `max_pending_bytes`
+fn message_size(publish: &Publish) -> usize {
+    publish.topic.len() + publish.payload.len()
 }
 
 /// Session limits configuration
 #[derive(Debug, Clone, Copy)]
 pub struct SessionLimits {
     pub max_pending_messages: usize,
+    /// 0 = unlimited
+    pub max_pending_bytes: usize,
+    pub queue_qos0: bool,
+    pub queue_overflow: QueueOverflow,
     pub max_inflight: u16,
     pub max_awaiting_rel: usize,
 }
@@ -162,6 +289,9 @@ impl Default for SessionLimits {
     fn default() -> Self {
         Self {
             max_pending_messages: 1000,
+            max_pending_bytes: 0,
+            queue_qos0: true,
+            queue_overflow: QueueOverflow::default(),
             max_inflight: 32,
             max_awaiting_rel: 100,
         }
@@ -176,6 +306,7 @@ impl Session {
     ) -> Self {
         Self {
             client_id,
+            username: None,
             protocol_version,
             state: SessionState::Connected,
             clean_start: true,
@@ -186,21 +317,32 @@ impl Session {
             subscriptions: AHashMap::new(),
             inflight_outgoing: AHashMap::new(),
             inflight_incoming: AHashMap::new(),
+            ack_timeouts: 0,
+            ack_stalled: false,
+            publishes_held: 0,
+            publishes_rejected: 0,
             next_packet_id: 1,
             pending_messages: VecDeque::new(),
             max_pending_messages: limits.max_pending_messages,
+            max_pending_bytes: limits.max_pending_bytes,
+            queue_qos0: limits.queue_qos0,
+            queue_overflow: limits.queue_overflow,
+            pending_bytes: 0,
+            overflowed: false,
+            evicted: 0,
             max_inflight: limits.max_inflight,
             max_awaiting_rel: limits.max_awaiting_rel,
             receive_maximum: 65535,
             send_quota: 65535,
             max_packet_size: 268_435_455,
             client_topic_aliases: AHashMap::new(),
-            server_topic_aliases: AHashMap::new(),
-            next_server_alias: 1,
+            server_topic_aliases: TopicAliases::default(),
             topic_alias_maximum: 0,
             will: None,
             will_delay_interval: 0,
             disconnected_at: None,
+            compressed: None,
+            handover: None,
         }
     }
 
@@ -222,6 +364,14 @@ impl Session {
         }
     }
 
+    /// When the oldest unacknowledged outbound message was first sent
+    pub fn oldest_unacked(&self) -> Option<Instant> {
+        self.inflight_outgoing
+            .values()
+            .map(|inflight| inflight.first_sent_at)
+            .min()
+    }
+
     /// Update last activity timestamp
     pub fn touch(&mut self) {
         self.last_activity = Instant::now();
@@ -233,7 +383,7 @@ impl Session {
             return false;
         }
 
-        if self.session_expiry_interval == 0 {
+        if self.session_expiry_interval == 0 || self.overflowed {
             return true;
         }
 
@@ -249,6 +399,34 @@ impl Session {
         false
     }
 
+    /// Hand the session over to whoever presents the returned token within
+    /// `ttl`
+    ///
+    /// The session now outlives this connection by at least `ttl`, queueing
+    /// messages for the successor, and until the token expires no other
+    /// connection may take it. A new token replaces a previous one.
+    pub fn prepare_handover(&mut self, ttl: Duration) -> Handover {
+        use rand::distributions::{Alphanumeric, DistString};
+
+        let token = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+        let ttl_secs = u32::try_from(ttl.as_secs()).unwrap_or(u32::MAX);
+        self.clean_start = false;
+        self.session_expiry_interval = self.session_expiry_interval.max(ttl_secs);
+        let handover = Handover {
+            token: token.into(),
+            expires_at: Instant::now() + ttl,
+        };
+        self.handover = Some(handover.clone());
+        handover
+    }
+
+    /// The handover under way, if its token hasn't expired
+    pub fn pending_handover(&self) -> Option<&Handover> {
+        self.handover
+            .as_ref()
+            .filter(|handover| handover.expires_at > Instant::now())
+    }
+
     /// Check if keep alive has timed out
     pub fn is_keep_alive_expired(&self) -> bool {
         if self.keep_alive == 0 {
@@ -261,15 +439,43 @@ impl Session {
     }
 
     /// Queue a message for later delivery
-    /// Returns QueueResult::DroppedOldest if the queue was full and an older message was dropped
+    ///
+    /// When the queue is over its message count or byte limit, the
+    /// `queue_overflow` policy decides what is lost (see [`QueueResult`]).
     pub fn queue_message(&mut self, publish: Publish) -> QueueResult {
-        let result = if self.pending_messages.len() >= self.max_pending_messages {
-            // Drop oldest message
-            self.pending_messages.pop_front();
-            QueueResult::DroppedOldest
-        } else {
-            QueueResult::Queued
-        };
+        if publish.qos == QoS::AtMostOnce && !self.queue_qos0 {
+            return QueueResult::Skipped;
+        }
+        if self.overflowed {
+            return QueueResult::Overflow;
+        }
+
+        let size = message_size(&publish);
+        // Dropping older messages can't make room for one over the byte limit
+        let fits = self.max_pending_bytes == 0 || size <= self.max_pending_bytes;
+        let mut result = QueueResult::Queued;
+        while self.queue_full(size) {
+            match self.queue_overflow {
+                QueueOverflow::DropOldest if fits && self.pending_count() > 0 => {
+                    // The oldest message may be in the compressed block
+                    self.decompress();
+                    if let Some(oldest) = self.pending_messages.pop_front() {
+                        self.pending_bytes -= message_size(&oldest.publish);
+                    }
+                    result = QueueResult::DroppedOldest;
+                }
+                QueueOverflow::DropOldest | QueueOverflow::DropNewest => {
+                    return QueueResult::DroppedNewest;
+                }
+                QueueOverflow::Disconnect => {
+                    self.overflowed = true;
+                    self.clear_pending();
+                    return QueueResult::Overflow;
+                }
+            }
+        }
+
+        self.pending_bytes += size;
         self.pending_messages.push_back(PendingMessage {
             publish,
             queued_at: Instant::now(),
@@ -277,11 +483,40 @@ impl Session {
         result
     }
 
+    /// Whether queueing `size` more bytes would exceed the queue limits
+    fn queue_full(&self, size: usize) -> bool {
+        self.pending_count() >= self.max_pending_messages
+            || (self.max_pending_bytes > 0 && self.pending_bytes + size > self.max_pending_bytes)
+    }
+
+    /// Drop all pending messages
+    fn clear_pending(&mut self) {
+        self.decompress();
+        self.pending_messages = Default::default();
+        self.pending_bytes = 0;
+    }
+
     /// Get and remove pending messages, filtering expired ones per MQTT-3.3.2-5
     /// Also updates message_expiry_interval to reflect time spent queued
     pub fn drain_pending_messages(&mut self) -> VecDeque<Publish> {
+        self.drain_pending(usize::MAX)
+    }
+
+    /// Get and remove up to `max` of the oldest pending messages, as
+    /// [`drain_pending_messages`](Self::drain_pending_messages)
+    pub fn drain_pending(&mut self, max: usize) -> VecDeque<Publish> {
+        self.decompress();
         let now = Instant::now();
-        let pending = std::mem::take(&mut self.pending_messages);
+        let count = max.min(self.pending_messages.len());
+        let pending: Vec<PendingMessage> = self.pending_messages.drain(..count).collect();
+        if self.pending_messages.is_empty() {
+            self.pending_bytes = 0;
+        } else {
+            self.pending_bytes -= pending
+                .iter()
+                .map(|pm| message_size(&pm.publish))
+                .sum::<usize>();
+        }
 
         pending
             .into_iter()
@@ -301,18 +536,74 @@ impl Session {
             .collect()
     }
 
+    /// Put messages taken from the queue back at its front, in order
+    pub fn requeue_front(&mut self, messages: Vec<Publish>) {
+        self.decompress();
+        let now = Instant::now();
+        for publish in messages.into_iter().rev() {
+            self.pending_bytes += message_size(&publish);
+            self.pending_messages.push_front(PendingMessage {
+                publish,
+                queued_at: now,
+            });
+        }
+    }
+
+    /// Evict queued QoS 0 messages on topics of eviction `tier`, oldest
+    /// first, until `bytes` are freed; returns the messages and bytes
+    /// evicted (compressed messages are left alone)
+    fn evict_qos0(&mut self, bytes: usize, tier: u8, rank: &dyn Fn(&str) -> u8) -> (usize, usize) {
+        let (mut messages, mut freed) = (0, 0);
+        self.pending_messages.retain(|pm| {
+            let evict = freed < bytes
+                && pm.publish.qos == QoS::AtMostOnce
+                && rank(&pm.publish.topic) == tier;
+            if evict {
+                messages += 1;
+                freed += message_size(&pm.publish);
+            }
+            !evict
+        });
+        self.pending_bytes -= freed;
+        self.evicted += messages as u64;
+        (messages, freed)
+    }
+
+    /// QoS 0 messages evicted under memory pressure since the client was
+    /// last told, resetting the count
+    pub fn take_evictions(&mut self) -> u64 {
+        std::mem::take(&mut self.evicted)
+    }
+
+    /// Number of queued messages, including compressed ones
+    pub fn pending_count(&self) -> usize {
+        self.pending_messages.len() + self.compressed.as_ref().map_or(0, |c| c.pending_count)
+    }
+
+    /// Topic and payload bytes of queued messages, including compressed ones
+    pub fn pending_bytes(&self) -> usize {
+        self.pending_bytes
+    }
+
     /// Remove expired messages from the pending queue
     /// Called periodically to clean up expired messages
+    /// (compressed messages are filtered when drained instead)
     pub fn cleanup_expired_messages(&mut self) {
         let now = Instant::now();
+        let mut freed = 0;
         self.pending_messages.retain(|pm| {
-            if let Some(expiry) = pm.publish.properties.message_expiry_interval {
+            let keep = if let Some(expiry) = pm.publish.properties.message_expiry_interval {
                 let elapsed = now.duration_since(pm.queued_at).as_secs() as u32;
                 elapsed < expiry
             } else {
                 true // No expiry, keep the message
+            };
+            if !keep {
+                freed += message_size(&pm.publish);
             }
+            keep
         });
+        self.pending_bytes -= freed;
     }
 
     /// Add a subscription
@@ -339,24 +630,18 @@ impl Session {
         self.subscriptions.remove(filter).is_some()
     }
 
-    /// Get a topic alias for server->client
-    pub fn get_or_create_topic_alias(&mut self, topic: &str) -> Option<u16> {
-        if self.topic_alias_maximum == 0 {
-            return None;
-        }
-
-        if let Some(&alias) = self.server_topic_aliases.get(topic) {
-            return Some(alias);
-        }
+    /// Get a topic alias for server->client, and whether the topic must
+    /// be sent with it
+    pub fn get_or_create_topic_alias(&mut self, topic: &str) -> Option<(u16, bool)> {
+        self.server_topic_aliases
+            .assign(topic, self.topic_alias_maximum)
+    }
 
-        if self.next_server_alias <= self.topic_alias_maximum {
-            let alias = self.next_server_alias;
-            self.next_server_alias += 1;
-            self.server_topic_aliases.insert(topic.to_string(), alias);
-            Some(alias)
-        } else {
-            None
-        }
+    /// Forget the topic aliases of the previous connection
+    pub fn reset_topic_aliases(&mut self) {
+        self.client_topic_aliases.clear();
+        self.server_topic_aliases.clear();
+        self.topic_alias_maximum = 0;
     }
 
     /// Resolve a client topic alias
@@ -390,15 +675,43 @@ impl Session {
 /// Thread-safe session store
 pub struct SessionStore {
     sessions: DashMap<Arc<str>, Arc<RwLock<Session>>>,
+    /// Publish rate buckets, kept across reconnects
+    rate_limits: Option<RateLimiter>,
+    /// Turns for draining backlogs, if shared fairly
+    backlog_drain: Option<DrainScheduler>,
 }
 
 impl SessionStore {
     pub fn new() -> Self {
         Self {
             sessions: DashMap::new(),
+            rate_limits: None,
+            backlog_drain: None,
         }
     }
 
+    /// Limit publish rates per client identity
+    pub fn with_rate_limits(mut self, config: &PublishRateConfig) -> Self {
+        self.rate_limits = RateLimiter::new(config);
+        self
+    }
+
+    /// Publish rate limiter (if enabled)
+    pub fn rate_limits(&self) -> Option<&RateLimiter> {
+        self.rate_limits.as_ref()
+    }
+
+    /// Share the draining of backlogs between sessions
+    pub fn with_backlog_drain(mut self, config: &BacklogDrainConfig) -> Self {
+        self.backlog_drain = DrainScheduler::new(config);
+        self
+    }
+
+    /// Backlog drain scheduler (if fair draining is enabled)
+    pub fn backlog_drain(&self) -> Option<&DrainScheduler> {
+        self.backlog_drain.as_ref()
+    }
+
     /// Get or create a session
     pub fn get_or_create(
         &self,
@@ -426,6 +739,7 @@ impl SessionStore {
                     s.state = SessionState::Connected;
                     s.protocol_version = protocol_version;
                     s.disconnected_at = None;
+                    s.decompress();
                     drop(s);
                     return (session.clone(), true);
                 }
@@ -447,6 +761,36 @@ impl SessionStore {
         self.sessions.get(client_id).map(|r| r.clone())
     }
 
+    /// Client IDs of all sessions, without locking them
+    pub fn client_ids(&self) -> Vec<Arc<str>> {
+        self.sessions.iter().map(|r| r.key().clone()).collect()
+    }
+
+    /// All sessions, for passes that may await between sessions
+    pub fn snapshot(&self) -> Vec<Arc<RwLock<Session>>> {
+        self.sessions.iter().map(|r| r.value().clone()).collect()
+    }
+
+    /// Sessions with queued messages, deepest queue first
+    pub fn queue_depths(&self) -> Vec<QueueDepth> {
+        let mut depths: Vec<_> = self
+            .sessions
+            .iter()
+            .filter_map(|r| {
+                let s = r.value().read();
+                let messages = s.pending_count();
+                (messages > 0).then(|| QueueDepth {
+                    client_id: s.client_id.clone(),
+                    messages,
+                    bytes: s.pending_bytes(),
+                    connected: s.state == SessionState::Connected,
+                })
+            })
+            .collect();
+        depths.sort_by_key(|d| std::cmp::Reverse(d.messages));
+        depths
+    }
+
     /// Remove a session
     pub fn remove(&self, client_id: &str) {
         self.sessions.remove(client_id);
@@ -469,16 +813,84 @@ impl SessionStore {
         }
     }
 
-    /// Clean up expired sessions and expired messages within sessions
+    /// Clean up expired sessions and expired messages within sessions,
+    /// returning the sessions removed
     /// Per MQTT v5.0 spec [MQTT-3.3.2-5]: expired messages MUST be deleted
-    pub fn cleanup_expired(&self) {
+    pub fn cleanup_expired(&self) -> Vec<ExpiredSession> {
+        let mut expired = Vec::new();
         self.sessions.retain(|_, session| {
             let mut s = session.write();
             // Clean up expired messages in this session
             s.cleanup_expired_messages();
-            // Return false to remove session if it's expired
-            !s.is_expired()
+            if !s.is_expired() {
+                return true;
+            }
+            expired.push(ExpiredSession {
+                client_id: s.client_id.clone(),
+                username: s.username.clone(),
+                session_expiry_interval: s.session_expiry_interval,
+                overflowed: s.overflowed,
+                pending_messages: s.pending_count(),
+                inflight_messages: s.inflight_outgoing.len(),
+            });
+            false
         });
+        expired
+    }
+
+    /// Evict queued QoS 0 messages until `bytes` are freed
+    ///
+    /// `rank` places each topic in an eviction tier below `tiers`; lower
+    /// tiers are evicted first, and within a tier the largest queues (by
+    /// bytes) lose their oldest messages first.
+    pub fn evict_qos0(&self, bytes: usize, tiers: u8, rank: impl Fn(&str) -> u8) -> Eviction {
+        let mut queues: Vec<_> = self
+            .sessions
+            .iter()
+            .filter_map(|entry| {
+                let size = entry.value().read().pending_bytes();
+                (size > 0).then(|| (size, entry.value().clone()))
+            })
+            .collect();
+        queues.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+
+        let mut eviction = Eviction::default();
+        let mut hit = vec![false; queues.len()];
+        for tier in 0..tiers {
+            for (i, (_, session)) in queues.iter().enumerate() {
+                if eviction.bytes >= bytes {
+                    break;
+                }
+                let (messages, freed) =
+                    session
+                        .write()
+                        .evict_qos0(bytes - eviction.bytes, tier, &rank);
+                eviction.messages += messages;
+                eviction.bytes += freed;
+                hit[i] |= messages > 0;
+            }
+        }
+        eviction.sessions = hit.iter().filter(|hit| **hit).count();
+        eviction
+    }
+
+    /// Compress sessions that have been disconnected for at least `idle_after`
+    ///
+    /// Returns the number of sessions compressed in this pass.
+    pub fn compress_idle(&self, idle_after: Duration) -> usize {
+        let mut compressed = 0;
+        for entry in self.sessions.iter() {
+            let mut s = entry.value().write();
+            let idle = s.state == SessionState::Disconnected
+                && s.disconnected_at
+                    .is_some_and(|at| at.elapsed() >= idle_after);
+            // Already-compressed sessions are only repacked if messages were queued since
+            let stale = !s.is_compressed() || !s.pending_messages.is_empty();
+            if idle && stale && s.compress() {
+                compressed += 1;
+            }
+        }
+        compressed
     }
 
     /// Get session count
@@ -509,7 +921,7 @@ impl SessionStore {
             .iter()
             .map(|entry| {
                 let session = entry.value().read();
-                session.pending_messages.len()
+                session.pending_count()
             })
             .sum()
     }
@@ -705,4 +1117,232 @@ mod tests {
             "no_expiry"
         );
     }
+
+    fn queued(topic: &str, payload: &'static str, qos: QoS) -> Publish {
+        Publish {
+            topic: topic.to_string(),
+            payload: bytes::Bytes::from(payload),
+            qos,
+            retain: false,
+            dup: false,
+            packet_id: None,
+            properties: Properties::default(),
+        }
+    }
+
+    fn payloads(session: &mut Session) -> Vec<Bytes> {
+        session
+            .drain_pending_messages()
+            .into_iter()
+            .map(|p| p.payload)
+            .collect()
+    }
+
+    #[test]
+    fn test_queue_byte_limit_drop_oldest() {
+        let limits = SessionLimits {
+            // Two messages of "t" + 4 bytes
+            max_pending_bytes: 10,
+            ..Default::default()
+        };
+        let mut session = Session::new("test".into(), ProtocolVersion::V5, limits);
+
+        assert_eq!(
+            session.queue_message(queued("t", "aaaa", QoS::AtLeastOnce)),
+            QueueResult::Queued
+        );
+        session.queue_message(queued("t", "bbbb", QoS::AtLeastOnce));
+        assert_eq!(session.pending_bytes(), 10);
+        assert_eq!(
+            session.queue_message(queued("t", "cccc", QoS::AtLeastOnce)),
+            QueueResult::DroppedOldest
+        );
+        assert_eq!(session.pending_bytes(), 10);
+
+        // Larger than the whole limit: dropped without emptying the queue
+        assert_eq!(
+            session.queue_message(queued("t", "dddddddddddd", QoS::AtLeastOnce)),
+            QueueResult::DroppedNewest
+        );
+        assert_eq!(payloads(&mut session), vec!["bbbb", "cccc"]);
+        assert_eq!(session.pending_bytes(), 0);
+    }
+
+    #[test]
+    fn test_queue_drop_newest() {
+        let limits = SessionLimits {
+            max_pending_messages: 2,
+            queue_overflow: QueueOverflow::DropNewest,
+            ..Default::default()
+        };
+        let mut session = Session::new("test".into(), ProtocolVersion::V5, limits);
+
+        session.queue_message(queued("t", "a", QoS::AtLeastOnce));
+        session.queue_message(queued("t", "b", QoS::AtLeastOnce));
+        let result = session.queue_message(queued("t", "c", QoS::AtLeastOnce));
+        assert_eq!(result, QueueResult::DroppedNewest);
+        assert!(result.dropped());
+        assert_eq!(payloads(&mut session), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_queue_overflow_ends_session() {
+        let limits = SessionLimits {
+            max_pending_messages: 1,
+            queue_overflow: QueueOverflow::Disconnect,
+            ..Default::default()
+        };
+        let mut session = Session::new("test".into(), ProtocolVersion::V5, limits);
+        session.session_expiry_interval = 3600;
+        session.state = SessionState::Disconnected;
+        session.disconnected_at = Some(Instant::now());
+
+        session.queue_message(queued("t", "a", QoS::AtLeastOnce));
+        assert!(!session.is_expired());
+        assert_eq!(
+            session.queue_message(queued("t", "b", QoS::AtLeastOnce)),
+            QueueResult::Overflow
+        );
+        assert_eq!(session.pending_count(), 0);
+        assert!(session.is_expired());
+    }
+
+    #[test]
+    fn test_drain_pending_in_batches() {
+        let mut session =
+            Session::new("test".into(), ProtocolVersion::V5, SessionLimits::default());
+        for topic in ["a", "b", "c", "d"] {
+            session.queue_message(queued(topic, "12345", QoS::AtLeastOnce));
+        }
+
+        let batch = session.drain_pending(3);
+        let topics: Vec<&str> = batch.iter().map(|p| p.topic.as_str()).collect();
+        assert_eq!(topics, ["a", "b", "c"]);
+        assert_eq!(session.pending_count(), 1);
+        assert_eq!(session.pending_bytes(), 6);
+
+        // Messages that couldn't be sent go back ahead of the rest
+        session.requeue_front(batch.into_iter().skip(1).collect());
+        assert_eq!(session.pending_bytes(), 18);
+        let topics: Vec<String> = session
+            .drain_pending_messages()
+            .into_iter()
+            .map(|p| p.topic)
+            .collect();
+        assert_eq!(topics, ["b", "c", "d"]);
+        assert_eq!(session.pending_bytes(), 0);
+    }
+
+    #[test]
+    fn test_evict_qos0_order() {
+        let store = SessionStore::new();
+        let queue = |client_id: &str, messages: &[(&str, QoS)]| {
+            let (session, _) = store.get_or_create(
+                client_id,
+                ProtocolVersion::V5,
+                true,
+                SessionLimits::default(),
+            );
+            let mut s = session.write();
+            for (topic, qos) in messages {
+                s.queue_message(queued(topic, "12345", *qos));
+            }
+        };
+        // Messages count 11 bytes (12 for debug topics)
+        queue(
+            "small",
+            &[("debug/a", QoS::AtMostOnce), ("data/a", QoS::AtMostOnce)],
+        );
+        queue(
+            "large",
+            &[
+                ("data/b", QoS::AtMostOnce),
+                ("data/c", QoS::AtLeastOnce),
+                ("debug/b", QoS::AtMostOnce),
+                ("data/d", QoS::AtMostOnce),
+            ],
+        );
+        let rank = |topic: &str| u8::from(!topic.starts_with("debug/"));
+
+        // Low-priority topics go first, from every queue
+        let eviction = store.evict_qos0(30, 2, rank);
+        assert_eq!(
+            eviction,
+            Eviction {
+                messages: 3,
+                bytes: 35,
+                sessions: 2,
+            }
+        );
+        let topics = |client_id: &str| -> Vec<String> {
+            let session = store.get(client_id).unwrap();
+            let s = session.read();
+            s.pending_messages
+                .iter()
+                .map(|pm| pm.publish.topic.clone())
+                .collect()
+        };
+        // Then the oldest message of the largest queue
+        assert_eq!(topics("large"), ["data/c", "data/d"]);
+        assert_eq!(topics("small"), ["data/a"]);
+        assert_eq!(store.get("large").unwrap().write().take_evictions(), 2);
+        assert_eq!(store.get("large").unwrap().write().take_evictions(), 0);
+
+        // QoS 1 messages stay whatever is asked for
+        store.evict_qos0(usize::MAX, 2, rank);
+        assert_eq!(topics("large"), ["data/c"]);
+        assert_eq!(store.get("large").unwrap().read().pending_bytes(), 11);
+    }
+
+    #[test]
+    fn test_cleanup_reports_expired_sessions() {
+        let store = SessionStore::new();
+        for client_id in ["gone", "kept"] {
+            let (session, _) = store.get_or_create(
+                client_id,
+                ProtocolVersion::V5,
+                true,
+                SessionLimits::default(),
+            );
+            let mut s = session.write();
+            s.session_expiry_interval = 60;
+            s.username = Some("fleet".into());
+            s.queue_message(queued("t", "a", QoS::AtLeastOnce));
+        }
+        store.disconnect("gone");
+        store.disconnect("kept");
+        store.get("gone").unwrap().write().disconnected_at =
+            Instant::now().checked_sub(Duration::from_secs(61));
+
+        let expired = store.cleanup_expired();
+        assert_eq!(
+            expired,
+            vec![ExpiredSession {
+                client_id: "gone".into(),
+                username: Some("fleet".into()),
+                session_expiry_interval: 60,
+                overflowed: false,
+                pending_messages: 1,
+                inflight_messages: 0,
+            }]
+        );
+        assert!(store.get("gone").is_none());
+        assert!(store.get("kept").is_some());
+        assert!(store.cleanup_expired().is_empty());
+    }
+
+    #[test]
+    fn test_queue_qos0_disabled() {
+        let limits = SessionLimits {
+            queue_qos0: false,
+            ..Default::default()
+        };
+        let mut session = Session::new("test".into(), ProtocolVersion::V5, limits);
+
+        let result = session.queue_message(queued("t", "a", QoS::AtMostOnce));
+        assert_eq!(result, QueueResult::Skipped);
+        assert!(!result.dropped());
+        session.queue_message(queued("t", "b", QoS::AtLeastOnce));
+        assert_eq!(payloads(&mut session), vec!["b"]);
+    }
 }
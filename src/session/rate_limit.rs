@@ -0,0 +1,313 @@
+//! Publish rate limiting by client identity
+//!
+//! Token buckets live in the session store rather than on the connection, so
+//! a client that reconnects keeps its remaining quota. Timestamps are wall
+//! clock milliseconds so buckets can be persisted and exchanged with cluster
+//! peers.
+//!
+//! Buckets that changed since the last sync are "dirty"; the broker
+//! periodically persists them and sends them to peers. When a bucket for the
+//! same identity arrives from elsewhere, the lower token count wins, so
+//! spreading connections across nodes doesn't multiply the quota.
+//!
+//! The rate and burst can change while the broker runs (see
+//! [`crate::broker::Tunables`]); buckets keep their tokens.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bincode::{Decode, Encode};
+use dashmap::DashMap;
+
+use crate::config::PublishRateConfig;
+
+/// Shared state of one identity's token bucket
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct RateBucket {
+    /// Identity the bucket belongs to (e.g. "user:alice")
+    pub identity: String,
+    /// Tokens left at `updated_ms`
+    pub tokens: f64,
+    /// Unix timestamp in milliseconds of the last update
+    pub updated_ms: u64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_ms: u64,
+    /// Changed since the last `take_dirty`
+    dirty: bool,
+}
+
+/// Publish rate limiter keyed by client identity
+pub struct RateLimiter {
+    /// Messages per second and burst, as f64 bits
+    rate: AtomicU64,
+    burst: AtomicU64,
+    buckets: DashMap<Arc<str>, Bucket>,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl RateLimiter {
+    /// Create a rate limiter, or `None` if the config disables it
+    pub fn new(config: &PublishRateConfig) -> Option<Self> {
+        config.enabled().then(|| Self {
+            rate: AtomicU64::new((config.messages_per_sec as f64).to_bits()),
+            burst: AtomicU64::new((config.burst.max(1) as f64).to_bits()),
+            buckets: DashMap::new(),
+        })
+    }
+
+    fn rate(&self) -> f64 {
+        f64::from_bits(self.rate.load(Ordering::Relaxed))
+    }
+
+    fn burst(&self) -> f64 {
+        f64::from_bits(self.burst.load(Ordering::Relaxed))
+    }
+
+    /// Change the limit; buckets above the new burst are cut down as they
+    /// refill
+    pub fn set_limits(&self, messages_per_sec: u32, burst: u32) {
+        self.rate.store(
+            (messages_per_sec.max(1) as f64).to_bits(),
+            Ordering::Relaxed,
+        );
+        self.burst
+            .store((burst.max(1) as f64).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Tokens after refilling from `updated_ms` to `now`
+    fn refilled(&self, tokens: f64, updated_ms: u64, now: u64) -> f64 {
+        self.refilled_boosted(tokens, updated_ms, now, 1.0)
+    }
+
+    /// Tokens after refilling with rate and burst times `boost`
+    fn refilled_boosted(&self, tokens: f64, updated_ms: u64, now: u64, boost: f64) -> f64 {
+        let elapsed = now.saturating_sub(updated_ms) as f64 / 1000.0;
+        (tokens + elapsed * self.rate() * boost).min(self.burst() * boost)
+    }
+
+    /// Take `count` tokens from the identity's bucket, with its rate and
+    /// burst times `boost` (see [`crate::broker::Limiters`])
+    ///
+    /// Returns how long until enough tokens are available if the bucket
+    /// is short; nothing is taken in that case.
+    pub fn try_acquire(&self, identity: &str, count: u32, boost: f64) -> Result<(), Duration> {
+        let now = now_ms();
+        let count = count as f64;
+        let mut bucket = self.buckets.entry(identity.into()).or_insert(Bucket {
+            tokens: self.burst(),
+            updated_ms: now,
+            dirty: false,
+        });
+        let tokens = self.refilled_boosted(bucket.tokens, bucket.updated_ms, now, boost);
+        bucket.updated_ms = now;
+        bucket.tokens = tokens;
+        if tokens < count {
+            return Err(Duration::from_secs_f64(
+                (count - tokens) / (self.rate() * boost),
+            ));
+        }
+        bucket.tokens -= count;
+        bucket.dirty = true;
+        Ok(())
+    }
+
+    /// Snapshot buckets changed since the last call
+    pub fn take_dirty(&self) -> Vec<RateBucket> {
+        let mut changed = Vec::new();
+        for mut entry in self.buckets.iter_mut() {
+            if entry.dirty {
+                entry.dirty = false;
+                changed.push(RateBucket {
+                    identity: entry.key().to_string(),
+                    tokens: entry.tokens,
+                    updated_ms: entry.updated_ms,
+                });
+            }
+        }
+        changed
+    }
+
+    /// Merge a bucket restored from storage or received from a peer
+    ///
+    /// Keeps whichever of the local and incoming bucket has fewer tokens.
+    /// Merged buckets aren't marked dirty, so they aren't echoed back.
+    pub fn merge(&self, bucket: RateBucket) {
+        let now = now_ms();
+        let tokens = self.refilled(bucket.tokens, bucket.updated_ms, now);
+        if tokens >= self.burst() {
+            return;
+        }
+        let mut local = self
+            .buckets
+            .entry(bucket.identity.into())
+            .or_insert(Bucket {
+                tokens,
+                updated_ms: now,
+                dirty: false,
+            });
+        let local_tokens = self.refilled(local.tokens, local.updated_ms, now);
+        local.tokens = local_tokens.min(tokens);
+        local.updated_ms = now;
+    }
+
+    /// Drop buckets that have refilled completely
+    ///
+    /// Returns the dropped identities so their persisted state can be deleted.
+    pub fn evict_full(&self) -> Vec<Arc<str>> {
+        let now = now_ms();
+        let mut evicted = Vec::new();
+        self.buckets.retain(|identity, bucket| {
+            let full = !bucket.dirty
+                && self.refilled(bucket.tokens, bucket.updated_ms, now) >= self.burst();
+            if full {
+                evicted.push(identity.clone());
+            }
+            !full
+        });
+        evicted
+    }
+
+    /// Refill the identity's bucket; false if it has none (it is full)
+    ///
+    /// The bucket is marked dirty so the refill is persisted.
+    pub fn fill(&self, identity: &str, boost: f64) -> bool {
+        let Some(mut bucket) = self.buckets.get_mut(identity) else {
+            return false;
+        };
+        bucket.tokens = self.burst() * boost;
+        bucket.updated_ms = now_ms();
+        bucket.dirty = true;
+        true
+    }
+
+    /// Identity, tokens, burst and rate of every tracked bucket, with the
+    /// boost `boost` returns for the identity
+    pub fn levels(&self, boost: impl Fn(&str) -> f64) -> Vec<(Arc<str>, f64, f64, f64)> {
+        let now = now_ms();
+        self.buckets
+            .iter()
+            .map(|entry| {
+                let boost = boost(entry.key());
+                let tokens = self.refilled_boosted(entry.tokens, entry.updated_ms, now, boost);
+                (
+                    entry.key().clone(),
+                    tokens,
+                    self.burst() * boost,
+                    self.rate() * boost,
+                )
+            })
+            .collect()
+    }
+
+    /// Number of tracked identities
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_limiter(messages_per_sec: u32, burst: u32) -> RateLimiter {
+        RateLimiter::new(&PublishRateConfig {
+            messages_per_sec,
+            burst,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_burst_then_limited() {
+        let limiter = new_limiter(1, 3);
+        for _ in 0..3 {
+            assert!(limiter.try_acquire("user:a", 1, 1.0).is_ok());
+        }
+        let retry = limiter.try_acquire("user:a", 1, 1.0).unwrap_err();
+        assert!(retry <= Duration::from_secs(1));
+        // Other identities have their own bucket
+        assert!(limiter.try_acquire("user:b", 3, 1.0).is_ok());
+        assert!(limiter.try_acquire("user:b", 1, 1.0).is_err());
+        assert!(RateLimiter::new(&PublishRateConfig::default()).is_none());
+    }
+
+    #[test]
+    fn test_set_limits() {
+        let limiter = new_limiter(1, 1);
+        assert!(limiter.try_acquire("user:a", 1, 1.0).is_ok());
+        assert!(limiter.try_acquire("user:a", 1, 1.0).is_err());
+
+        // A raised burst fills up at the new rate
+        limiter.set_limits(1000, 5);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(limiter.try_acquire("user:a", 5, 1.0).is_ok());
+        assert!(limiter.try_acquire("user:b", 6, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_boost_and_fill() {
+        let limiter = new_limiter(1, 2);
+        assert!(limiter.try_acquire("user:a", 2, 1.0).is_ok());
+        assert!(limiter.try_acquire("user:a", 1, 1.0).is_err());
+
+        // A refill is shared with peers and storage
+        assert!(limiter.fill("user:a", 3.0));
+        assert!(!limiter.fill("user:b", 1.0));
+        assert_eq!(limiter.take_dirty()[0].tokens, 6.0);
+        assert!(limiter.try_acquire("user:a", 6, 3.0).is_ok());
+
+        let levels = limiter.levels(|_| 3.0);
+        assert_eq!(levels.len(), 1);
+        assert_eq!(&*levels[0].0, "user:a");
+        assert_eq!((levels[0].2, levels[0].3), (6.0, 3.0));
+    }
+
+    #[test]
+    fn test_dirty_buckets_and_merge() {
+        let limiter = new_limiter(1, 10);
+        limiter.try_acquire("user:a", 4, 1.0).unwrap();
+        let dirty = limiter.take_dirty();
+        assert_eq!(dirty.len(), 1);
+        assert_eq!(dirty[0].identity, "user:a");
+        assert!(limiter.take_dirty().is_empty());
+
+        // A peer's emptier bucket wins and isn't re-shared
+        let peer = new_limiter(1, 10);
+        peer.merge(RateBucket {
+            tokens: 0.0,
+            updated_ms: now_ms(),
+            ..dirty[0].clone()
+        });
+        assert!(peer.try_acquire("user:a", 2, 1.0).is_err());
+        limiter.merge(dirty[0].clone());
+        assert!(limiter.take_dirty().is_empty());
+
+        // Full buckets are neither merged nor kept
+        peer.merge(RateBucket {
+            identity: "user:b".to_string(),
+            tokens: 10.0,
+            updated_ms: 0,
+        });
+        assert_eq!(peer.len(), 1);
+        let fresh = new_limiter(1, 10);
+        fresh.try_acquire("user:c", 0, 1.0).unwrap();
+        fresh.take_dirty();
+        assert_eq!(fresh.evict_full().len(), 1);
+        assert!(fresh.is_empty());
+    }
+}
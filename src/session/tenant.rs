@@ -0,0 +1,41 @@
+//! Tenant-scoped client IDs
+//!
+//! Clients belonging to a tenant are keyed as `<tenant>/<client_id>`, so
+//! two tenants can each have a `sensor-1` without taking over each other's
+//! session. Tenant names never contain the separator; the client ID may.
+
+/// Separator between the tenant and the client ID
+pub const TENANT_SEPARATOR: char = '/';
+
+/// Client ID qualified by its tenant
+pub fn tenant_client_id(tenant: &str, client_id: &str) -> String {
+    let mut qualified = String::with_capacity(tenant.len() + 1 + client_id.len());
+    qualified.push_str(tenant);
+    qualified.push(TENANT_SEPARATOR);
+    qualified.push_str(client_id);
+    qualified
+}
+
+/// Split a qualified client ID into tenant and client ID
+///
+/// Returns `None` when the ID has no tenant prefix. Whether the prefix
+/// names a configured tenant is up to the caller.
+pub fn split_tenant(client_id: &str) -> Option<(&str, &str)> {
+    client_id.split_once(TENANT_SEPARATOR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_client_id_roundtrip() {
+        let qualified = tenant_client_id("acme", "sensor-1");
+        assert_eq!(qualified, "acme/sensor-1");
+        assert_eq!(split_tenant(&qualified), Some(("acme", "sensor-1")));
+
+        let nested = tenant_client_id("acme", "site/sensor-1");
+        assert_eq!(split_tenant(&nested), Some(("acme", "site/sensor-1")));
+        assert_eq!(split_tenant("sensor-1"), None);
+    }
+}
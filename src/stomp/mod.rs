@@ -0,0 +1,320 @@
+//! STOMP 1.2 Protocol Support
+//!
+//! Frame codec and destination mapping for the STOMP compatibility listener.
+//! The listener itself lives in the broker, which maps SEND frames onto MQTT
+//! publishes and SUBSCRIBE frames onto MQTT subscriptions.
+//!
+//! Only the subset needed by publish/subscribe clients is supported:
+//! CONNECT/STOMP, SEND, SUBSCRIBE, UNSUBSCRIBE, ACK/NACK (accepted, no-op)
+//! and DISCONNECT. Transactions are rejected.
+
+#[cfg(test)]
+mod tests;
+
+use std::fmt;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::config::StompConfig;
+
+/// A STOMP frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Command (e.g., "SEND", "MESSAGE")
+    pub command: String,
+    /// Headers in wire order (the first occurrence of a header wins)
+    pub headers: Vec<(String, String)>,
+    /// Frame body
+    pub body: Bytes,
+}
+
+impl Frame {
+    /// Create a frame with no headers and an empty body
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            headers: Vec::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    /// Add a header
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the body
+    pub fn body(mut self, body: Bytes) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Get a header value
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Encode the frame, adding a content-length header for non-empty bodies
+    pub fn encode(&self, buf: &mut BytesMut) {
+        let escape = escapes_headers(&self.command);
+        buf.put_slice(self.command.as_bytes());
+        buf.put_u8(b'\n');
+        for (name, value) in &self.headers {
+            put_header_part(buf, name, escape);
+            buf.put_u8(b':');
+            put_header_part(buf, value, escape);
+            buf.put_u8(b'\n');
+        }
+        if !self.body.is_empty() && self.get("content-length").is_none() {
+            buf.put_slice(format!("content-length:{}\n", self.body.len()).as_bytes());
+        }
+        buf.put_u8(b'\n');
+        buf.put_slice(&self.body);
+        buf.put_u8(0);
+    }
+}
+
+/// STOMP protocol errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StompError {
+    /// Frame could not be parsed
+    Malformed(&'static str),
+    /// Frame exceeds the configured maximum size
+    FrameTooLarge,
+}
+
+impl fmt::Display for StompError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(msg) => write!(f, "malformed frame: {}", msg),
+            Self::FrameTooLarge => write!(f, "frame too large"),
+        }
+    }
+}
+
+impl std::error::Error for StompError {}
+
+/// CONNECT and CONNECTED frames don't use header escaping (STOMP 1.2, "Value Encoding")
+fn escapes_headers(command: &str) -> bool {
+    command != "CONNECT" && command != "CONNECTED"
+}
+
+fn put_header_part(buf: &mut BytesMut, s: &str, escape: bool) {
+    if !escape {
+        buf.put_slice(s.as_bytes());
+        return;
+    }
+    for c in s.chars() {
+        match c {
+            '\\' => buf.put_slice(b"\\\\"),
+            '\r' => buf.put_slice(b"\\r"),
+            '\n' => buf.put_slice(b"\\n"),
+            ':' => buf.put_slice(b"\\c"),
+            c => {
+                let mut tmp = [0u8; 4];
+                buf.put_slice(c.encode_utf8(&mut tmp).as_bytes());
+            }
+        }
+    }
+}
+
+fn unescape(s: &str) -> Result<String, StompError> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('r') => out.push('\r'),
+            Some('n') => out.push('\n'),
+            Some('c') => out.push(':'),
+            _ => return Err(StompError::Malformed("invalid header escape")),
+        }
+    }
+    Ok(out)
+}
+
+/// Decode a frame from the buffer, consuming it
+///
+/// Heart-beat EOLs between frames are skipped. Returns `Ok(None)` if more
+/// data is needed.
+pub fn decode_frame(
+    buf: &mut BytesMut,
+    max_frame_size: usize,
+) -> Result<Option<Frame>, StompError> {
+    // Skip heart-beats
+    let leading = buf
+        .iter()
+        .take_while(|&&b| b == b'\n' || b == b'\r')
+        .count();
+    buf.advance(leading);
+    if buf.is_empty() {
+        return Ok(None);
+    }
+
+    // Find the blank line that ends the headers
+    let Some(header_end) = find_header_end(buf) else {
+        if buf.len() > max_frame_size {
+            return Err(StompError::FrameTooLarge);
+        }
+        return Ok(None);
+    };
+    let (head_len, body_start) = header_end;
+
+    let head = std::str::from_utf8(&buf[..head_len])
+        .map_err(|_| StompError::Malformed("headers are not UTF-8"))?;
+    let mut lines = head.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l));
+    let command = lines
+        .next()
+        .filter(|c| !c.is_empty())
+        .ok_or(StompError::Malformed("missing command"))?
+        .to_string();
+    let escape = escapes_headers(&command);
+
+    let mut headers = Vec::new();
+    for line in lines {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(StompError::Malformed("header without ':'"))?;
+        if escape {
+            headers.push((unescape(name)?, unescape(value)?));
+        } else {
+            headers.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    let content_length = match headers.iter().find(|(k, _)| k == "content-length") {
+        Some((_, v)) => Some(
+            v.trim()
+                .parse::<usize>()
+                .map_err(|_| StompError::Malformed("invalid content-length"))?,
+        ),
+        None => None,
+    };
+
+    // Locate the NUL terminator
+    let body_end = match content_length {
+        Some(len) => {
+            if body_start + len > max_frame_size {
+                return Err(StompError::FrameTooLarge);
+            }
+            if buf.len() < body_start + len + 1 {
+                return Ok(None);
+            }
+            if buf[body_start + len] != 0 {
+                return Err(StompError::Malformed("body not terminated by NUL"));
+            }
+            body_start + len
+        }
+        None => match buf[body_start..].iter().position(|&b| b == 0) {
+            Some(pos) => body_start + pos,
+            None => {
+                if buf.len() > max_frame_size {
+                    return Err(StompError::FrameTooLarge);
+                }
+                return Ok(None);
+            }
+        },
+    };
+
+    let mut frame_bytes = buf.split_to(body_end + 1);
+    frame_bytes.truncate(body_end);
+    let body = frame_bytes.split_off(body_start).freeze();
+
+    Ok(Some(Frame {
+        command,
+        headers,
+        body,
+    }))
+}
+
+/// Find the end of the header block: returns (headers length, body offset)
+fn find_header_end(buf: &[u8]) -> Option<(usize, usize)> {
+    let mut i = 0;
+    while i < buf.len() {
+        if buf[i] == b'\n' {
+            let next = i + 1;
+            if buf.get(next) == Some(&b'\n') {
+                return Some((i, next + 1));
+            }
+            if buf.get(next) == Some(&b'\r') && buf.get(next + 1) == Some(&b'\n') {
+                return Some((i, next + 2));
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Maps STOMP destinations to MQTT topics and back
+#[derive(Debug, Clone)]
+pub struct DestinationMapper {
+    /// (STOMP prefix, MQTT topic prefix), longest STOMP prefix first
+    mappings: Vec<(String, String)>,
+    dot_separator: bool,
+}
+
+impl DestinationMapper {
+    pub fn new(config: &StompConfig) -> Self {
+        let mut mappings: Vec<(String, String)> = config
+            .destinations
+            .iter()
+            .map(|d| (d.prefix.clone(), d.topic.clone()))
+            .collect();
+        mappings.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Self {
+            mappings,
+            dot_separator: config.dot_separator,
+        }
+    }
+
+    /// Map a destination to an MQTT topic name or filter
+    ///
+    /// Returns the index of the matching mapping (for [`to_stomp`](Self::to_stomp))
+    /// and the MQTT topic.
+    pub fn to_mqtt(&self, destination: &str) -> Option<(usize, String)> {
+        let (index, (prefix, topic)) = self
+            .mappings
+            .iter()
+            .enumerate()
+            .find(|(_, (prefix, _))| destination.starts_with(prefix.as_str()))?;
+        let rest = &destination[prefix.len()..];
+        if rest.is_empty() {
+            return None;
+        }
+
+        let rest = if self.dot_separator {
+            rest.split('.')
+                .map(|level| match level {
+                    "*" => "+",
+                    ">" => "#",
+                    level => level,
+                })
+                .collect::<Vec<_>>()
+                .join("/")
+        } else {
+            rest.to_string()
+        };
+        Some((index, format!("{}{}", topic, rest)))
+    }
+
+    /// Map an MQTT topic back to a destination using a mapping from [`to_mqtt`](Self::to_mqtt)
+    pub fn to_stomp(&self, index: usize, topic: &str) -> String {
+        let Some((prefix, topic_prefix)) = self.mappings.get(index) else {
+            return topic.to_string();
+        };
+        let rest = topic.strip_prefix(topic_prefix.as_str()).unwrap_or(topic);
+        if self.dot_separator {
+            format!("{}{}", prefix, rest.replace('/', "."))
+        } else {
+            format!("{}{}", prefix, rest)
+        }
+    }
+}
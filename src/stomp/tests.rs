@@ -0,0 +1,121 @@
+//! STOMP codec and destination mapping tests
+
+use super::*;
+use crate::config::{StompConfig, StompDestination};
+
+fn decode_all(data: &[u8]) -> Vec<Frame> {
+    let mut buf = BytesMut::from(data);
+    let mut frames = Vec::new();
+    while let Some(frame) = decode_frame(&mut buf, 1024).unwrap() {
+        frames.push(frame);
+    }
+    assert!(buf.is_empty());
+    frames
+}
+
+#[test]
+fn test_decode_frames() {
+    let frames = decode_all(
+        b"CONNECT\naccept-version:1.2\nhost:broker\n\n\0\
+          \n\r\n\
+          SEND\r\ndestination:/topic/a\r\n\r\nhello\0\
+          SEND\ndestination:/topic/b\ncontent-length:3\n\na\0b\0",
+    );
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0].command, "CONNECT");
+    assert_eq!(frames[0].get("accept-version"), Some("1.2"));
+    assert_eq!(frames[1].get("destination"), Some("/topic/a"));
+    assert_eq!(frames[1].body, Bytes::from_static(b"hello"));
+    // content-length allows NUL in the body
+    assert_eq!(frames[2].body, Bytes::from_static(b"a\0b"));
+}
+
+#[test]
+fn test_decode_partial_and_errors() {
+    let mut buf = BytesMut::from(&b"SEND\ndestination:/topic/a\n\nhel"[..]);
+    assert_eq!(decode_frame(&mut buf, 1024), Ok(None));
+    buf.extend_from_slice(b"lo\0");
+    assert!(decode_frame(&mut buf, 1024).unwrap().is_some());
+
+    let mut buf = BytesMut::from(&b"SEND\nbad header\n\n\0"[..]);
+    assert!(matches!(
+        decode_frame(&mut buf, 1024),
+        Err(StompError::Malformed(_))
+    ));
+
+    let mut buf = BytesMut::from(&b"SEND\ncontent-length:4096\n\n"[..]);
+    assert_eq!(decode_frame(&mut buf, 1024), Err(StompError::FrameTooLarge));
+}
+
+#[test]
+fn test_header_escaping_round_trip() {
+    let frame = Frame::new("MESSAGE")
+        .header("destination", "/topic/a:b")
+        .header("note", "line1\nline2\\")
+        .body(Bytes::from_static(b"payload"));
+    let mut buf = BytesMut::new();
+    frame.encode(&mut buf);
+    assert!(buf.starts_with(b"MESSAGE\ndestination:/topic/a\\cb\n"));
+
+    let decoded = decode_frame(&mut buf, 1024).unwrap().unwrap();
+    assert_eq!(decoded.get("destination"), Some("/topic/a:b"));
+    assert_eq!(decoded.get("note"), Some("line1\nline2\\"));
+    assert_eq!(decoded.get("content-length"), Some("7"));
+    assert_eq!(decoded.body, Bytes::from_static(b"payload"));
+}
+
+#[test]
+fn test_destination_mapping() {
+    let config = StompConfig {
+        destinations: vec![
+            StompDestination {
+                prefix: "/topic/".to_string(),
+                topic: String::new(),
+            },
+            StompDestination {
+                prefix: "/topic/legacy/".to_string(),
+                topic: "legacy/".to_string(),
+            },
+        ],
+        ..Default::default()
+    };
+    let mapper = DestinationMapper::new(&config);
+
+    let (index, topic) = mapper.to_mqtt("/topic/sensors/temp").unwrap();
+    assert_eq!(topic, "sensors/temp");
+    assert_eq!(
+        mapper.to_stomp(index, "sensors/temp"),
+        "/topic/sensors/temp"
+    );
+
+    // Longest prefix wins
+    let (index, topic) = mapper.to_mqtt("/topic/legacy/device/1").unwrap();
+    assert_eq!(topic, "legacy/device/1");
+    assert_eq!(
+        mapper.to_stomp(index, "legacy/device/1"),
+        "/topic/legacy/device/1"
+    );
+
+    assert!(mapper.to_mqtt("/queue/jobs").is_none());
+    assert!(mapper.to_mqtt("/topic/").is_none());
+}
+
+#[test]
+fn test_destination_mapping_dot_separator() {
+    let config = StompConfig {
+        dot_separator: true,
+        ..Default::default()
+    };
+    let mapper = DestinationMapper::new(&config);
+
+    assert_eq!(
+        mapper.to_mqtt("/topic/sensors.*.temp").unwrap().1,
+        "sensors/+/temp"
+    );
+    assert_eq!(mapper.to_mqtt("/topic/sensors.>").unwrap().1, "sensors/#");
+    let (index, _) = mapper.to_mqtt("/topic/sensors.#").unwrap();
+    assert_eq!(
+        mapper.to_stomp(index, "sensors/kitchen/temp"),
+        "/topic/sensors.kitchen.temp"
+    );
+}
@@ -0,0 +1,715 @@
+//! Template Function Library
+//!
+//! Numbers:
+//! - `convert(x, from, to)`: unit conversion, rounded to 12 significant
+//!   digits, within temperature (`c`, `f`, `k`), length (`mm` to `mi`),
+//!   mass, pressure (`hpa`, `bar`, `psi`, ...), speed (`m/s`, `km/h`, `mph`,
+//!   `kn`), energy (`j`, `wh`, `kwh`, `kcal`, ...) or volume (`ml`, `l`,
+//!   `m3`, `gal`)
+//! - `round(x[, digits])`, `floor(x)`, `ceil(x)`, `abs(x)`, `min(x, ...)`,
+//!   `max(x, ...)`, `number(text)`
+//!
+//! Time (Unix seconds, UTC):
+//! - `format_time(ts[, format])`: RFC 3339 by default; the format takes
+//!   `%Y %m %d %H %M %S %j %s %F %T %%`
+//! - `parse_time(text)`: RFC 3339, a date-only `YYYY-MM-DD`, or a space
+//!   for the `T`; no offset means UTC
+//!
+//! Text:
+//! - `upper`, `lower`, `trim`, `len` (in characters)
+//! - `replace(text, from, to)`, `substr(text, start[, len])` (in
+//!   characters), `split(text, separator, index)`, `concat(x, ...)`
+//! - `pad_left(text, width[, fill])`, `pad_right(text, width[, fill])`
+//! - `json(x)`: `x` as a quoted, escaped JSON string
+//!
+//! Encoding:
+//! - `sha256(text)`: lowercase hex digest
+//! - `base64(text)`, `base64_decode(text)`
+//!
+//! Numeric arguments may be numeric text; every value renders as text.
+
+use std::ops::RangeInclusive;
+
+use base64ct::{Base64, Encoding};
+use sha2::{Digest, Sha256};
+
+use super::{TemplateError, Value, MAX_TEXT_LEN};
+use crate::schedule::{civil_from_days, days_from_civil};
+
+/// A library function
+#[derive(Debug)]
+pub struct Function {
+    pub name: &'static str,
+    /// Numbers of arguments taken
+    pub arity: RangeInclusive<usize>,
+    pub call: fn(&[Value]) -> Result<Value, TemplateError>,
+}
+
+/// Every library function
+pub static FUNCTIONS: &[Function] = &[
+    Function {
+        name: "convert",
+        arity: 3..=3,
+        call: convert,
+    },
+    Function {
+        name: "round",
+        arity: 1..=2,
+        call: round,
+    },
+    Function {
+        name: "floor",
+        arity: 1..=1,
+        call: |args| Ok(number("floor", &args[0])?.floor().into()),
+    },
+    Function {
+        name: "ceil",
+        arity: 1..=1,
+        call: |args| Ok(number("ceil", &args[0])?.ceil().into()),
+    },
+    Function {
+        name: "abs",
+        arity: 1..=1,
+        call: |args| Ok(number("abs", &args[0])?.abs().into()),
+    },
+    Function {
+        name: "min",
+        arity: 1..=16,
+        call: |args| fold("min", args, f64::min),
+    },
+    Function {
+        name: "max",
+        arity: 1..=16,
+        call: |args| fold("max", args, f64::max),
+    },
+    Function {
+        name: "number",
+        arity: 1..=1,
+        call: |args| Ok(number("number", &args[0])?.into()),
+    },
+    Function {
+        name: "format_time",
+        arity: 1..=2,
+        call: format_time,
+    },
+    Function {
+        name: "parse_time",
+        arity: 1..=1,
+        call: parse_time,
+    },
+    Function {
+        name: "upper",
+        arity: 1..=1,
+        call: |args| Ok(args[0].to_string().to_uppercase().into()),
+    },
+    Function {
+        name: "lower",
+        arity: 1..=1,
+        call: |args| Ok(args[0].to_string().to_lowercase().into()),
+    },
+    Function {
+        name: "trim",
+        arity: 1..=1,
+        call: |args| Ok(args[0].to_string().trim().into()),
+    },
+    Function {
+        name: "len",
+        arity: 1..=1,
+        call: |args| Ok((args[0].to_string().chars().count() as f64).into()),
+    },
+    Function {
+        name: "replace",
+        arity: 3..=3,
+        call: replace,
+    },
+    Function {
+        name: "substr",
+        arity: 2..=3,
+        call: substr,
+    },
+    Function {
+        name: "split",
+        arity: 3..=3,
+        call: split,
+    },
+    Function {
+        name: "concat",
+        arity: 1..=16,
+        call: concat,
+    },
+    Function {
+        name: "pad_left",
+        arity: 2..=3,
+        call: |args| pad("pad_left", args, true),
+    },
+    Function {
+        name: "pad_right",
+        arity: 2..=3,
+        call: |args| pad("pad_right", args, false),
+    },
+    Function {
+        name: "json",
+        arity: 1..=1,
+        call: |args| {
+            Ok(serde_json::Value::String(args[0].to_string())
+                .to_string()
+                .into())
+        },
+    },
+    Function {
+        name: "sha256",
+        arity: 1..=1,
+        call: sha256,
+    },
+    Function {
+        name: "base64",
+        arity: 1..=1,
+        call: |args| Ok(Base64::encode_string(args[0].to_string().as_bytes()).into()),
+    },
+    Function {
+        name: "base64_decode",
+        arity: 1..=1,
+        call: base64_decode,
+    },
+];
+
+/// A library function by name
+pub fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+fn invalid(function: &'static str, reason: impl Into<String>) -> TemplateError {
+    TemplateError::InvalidArgument(function, reason.into())
+}
+
+/// A numeric argument
+fn number(function: &'static str, value: &Value) -> Result<f64, TemplateError> {
+    let n = match value {
+        Value::Number(n) => *n,
+        Value::Text(text) => text
+            .trim()
+            .parse()
+            .map_err(|_| invalid(function, format!("'{}' is not a number", text)))?,
+    };
+    if !n.is_finite() {
+        return Err(invalid(function, "not a finite number"));
+    }
+    Ok(n)
+}
+
+/// A whole-number argument within `range`
+fn integer(
+    function: &'static str,
+    value: &Value,
+    range: RangeInclusive<i64>,
+) -> Result<i64, TemplateError> {
+    let n = number(function, value)?;
+    if n.fract() != 0.0 || !range.contains(&(n as i64)) {
+        return Err(invalid(
+            function,
+            format!(
+                "expected a whole number from {} to {}, got {}",
+                range.start(),
+                range.end(),
+                value
+            ),
+        ));
+    }
+    Ok(n as i64)
+}
+
+fn fold(
+    function: &'static str,
+    args: &[Value],
+    f: fn(f64, f64) -> f64,
+) -> Result<Value, TemplateError> {
+    let mut result = number(function, &args[0])?;
+    for arg in &args[1..] {
+        result = f(result, number(function, arg)?);
+    }
+    Ok(result.into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Temperature,
+    Length,
+    Mass,
+    Pressure,
+    Speed,
+    Energy,
+    Volume,
+}
+
+/// Units `convert` knows: (name, dimension, factor, offset), where a value
+/// in the unit is `value * factor + offset` in the dimension's base unit
+const UNITS: &[(&str, Dimension, f64, f64)] = &[
+    // Kelvin
+    ("c", Dimension::Temperature, 1.0, 273.15),
+    ("f", Dimension::Temperature, 5.0 / 9.0, 459.67 * 5.0 / 9.0),
+    ("k", Dimension::Temperature, 1.0, 0.0),
+    // Metres
+    ("mm", Dimension::Length, 0.001, 0.0),
+    ("cm", Dimension::Length, 0.01, 0.0),
+    ("m", Dimension::Length, 1.0, 0.0),
+    ("km", Dimension::Length, 1000.0, 0.0),
+    ("in", Dimension::Length, 0.0254, 0.0),
+    ("ft", Dimension::Length, 0.3048, 0.0),
+    ("yd", Dimension::Length, 0.9144, 0.0),
+    ("mi", Dimension::Length, 1609.344, 0.0),
+    // Kilograms
+    ("g", Dimension::Mass, 0.001, 0.0),
+    ("kg", Dimension::Mass, 1.0, 0.0),
+    ("t", Dimension::Mass, 1000.0, 0.0),
+    ("oz", Dimension::Mass, 0.028_349_523_125, 0.0),
+    ("lb", Dimension::Mass, 0.453_592_37, 0.0),
+    // Pascals
+    ("pa", Dimension::Pressure, 1.0, 0.0),
+    ("hpa", Dimension::Pressure, 100.0, 0.0),
+    ("kpa", Dimension::Pressure, 1000.0, 0.0),
+    ("mbar", Dimension::Pressure, 100.0, 0.0),
+    ("bar", Dimension::Pressure, 100_000.0, 0.0),
+    ("psi", Dimension::Pressure, 6_894.757_293_168, 0.0),
+    ("atm", Dimension::Pressure, 101_325.0, 0.0),
+    // Metres per second
+    ("m/s", Dimension::Speed, 1.0, 0.0),
+    ("km/h", Dimension::Speed, 1.0 / 3.6, 0.0),
+    ("mph", Dimension::Speed, 0.447_04, 0.0),
+    ("kn", Dimension::Speed, 1852.0 / 3600.0, 0.0),
+    // Joules
+    ("j", Dimension::Energy, 1.0, 0.0),
+    ("kj", Dimension::Energy, 1000.0, 0.0),
+    ("wh", Dimension::Energy, 3600.0, 0.0),
+    ("kwh", Dimension::Energy, 3_600_000.0, 0.0),
+    ("cal", Dimension::Energy, 4.184, 0.0),
+    ("kcal", Dimension::Energy, 4184.0, 0.0),
+    // Cubic metres
+    ("ml", Dimension::Volume, 0.000_001, 0.0),
+    ("l", Dimension::Volume, 0.001, 0.0),
+    ("m3", Dimension::Volume, 1.0, 0.0),
+    ("gal", Dimension::Volume, 0.003_785_411_784, 0.0),
+];
+
+fn unit(name: &Value) -> Result<(Dimension, f64, f64), TemplateError> {
+    let name = name.to_string().to_ascii_lowercase();
+    UNITS
+        .iter()
+        .find(|(unit, ..)| *unit == name)
+        .map(|&(_, dimension, factor, offset)| (dimension, factor, offset))
+        .ok_or_else(|| invalid("convert", format!("unknown unit '{}'", name)))
+}
+
+fn convert(args: &[Value]) -> Result<Value, TemplateError> {
+    let value = number("convert", &args[0])?;
+    let (from, from_factor, from_offset) = unit(&args[1])?;
+    let (to, to_factor, to_offset) = unit(&args[2])?;
+    if from != to {
+        return Err(invalid(
+            "convert",
+            format!("can't convert {} to {}", args[1], args[2]),
+        ));
+    }
+    let converted = (value * from_factor + from_offset - to_offset) / to_factor;
+    // Drop the float noise of the round trip through the base unit
+    let rounded = format!("{:.11e}", converted).parse().unwrap_or(converted);
+    Ok(Value::Number(rounded))
+}
+
+fn round(args: &[Value]) -> Result<Value, TemplateError> {
+    let value = number("round", &args[0])?;
+    let digits = match args.get(1) {
+        Some(digits) => integer("round", digits, 0..=12)?,
+        None => 0,
+    };
+    let scale = 10f64.powi(digits as i32);
+    Ok(((value * scale).round() / scale).into())
+}
+
+/// Default `format_time` format
+const RFC_3339: &str = "%FT%TZ";
+
+/// Unix seconds of 0001-01-01 and of 9999-12-31T23:59:59Z
+const TIME_RANGE: RangeInclusive<i64> = -62_135_596_800..=253_402_300_799;
+
+fn format_time(args: &[Value]) -> Result<Value, TemplateError> {
+    let secs = number("format_time", &args[0])?.floor();
+    if !TIME_RANGE.contains(&(secs as i64)) {
+        return Err(invalid("format_time", "timestamp out of range"));
+    }
+    let secs = secs as i64;
+    let format = args.get(1).map_or(RFC_3339.to_string(), Value::to_string);
+
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", year)),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('H') => out.push_str(&format!("{:02}", rem / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", rem % 3600 / 60)),
+            Some('S') => out.push_str(&format!("{:02}", rem % 60)),
+            Some('j') => out.push_str(&format!("{:03}", days - days_from_civil(year, 1, 1) + 1)),
+            Some('s') => out.push_str(&secs.to_string()),
+            Some('F') => out.push_str(&format!("{:04}-{:02}-{:02}", year, month, day)),
+            Some('T') => out.push_str(&format!(
+                "{:02}:{:02}:{:02}",
+                rem / 3600,
+                rem % 3600 / 60,
+                rem % 60
+            )),
+            Some('%') => out.push('%'),
+            Some(c) => return Err(invalid("format_time", format!("unknown format %{}", c))),
+            None => return Err(invalid("format_time", "format ends with %")),
+        }
+        if out.len() > MAX_TEXT_LEN {
+            return Err(TemplateError::TooLong);
+        }
+    }
+    Ok(out.into())
+}
+
+/// Reads a time's fields
+struct Cursor<'a>(&'a [u8]);
+
+impl Cursor<'_> {
+    /// Fixed-width decimal field
+    fn digits(&mut self, len: usize) -> Option<u32> {
+        let digits = self.0.get(..len)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        self.0 = &self.0[len..];
+        Some(digits.iter().fold(0, |n, d| n * 10 + u32::from(d - b'0')))
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        match self.0.split_first() {
+            Some((&b, rest)) if b == byte => {
+                self.0 = rest;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn parse_time(args: &[Value]) -> Result<Value, TemplateError> {
+    let text = args[0].to_string();
+    parse_rfc3339(text.trim().as_bytes())
+        .map(Value::Number)
+        .ok_or_else(|| invalid("parse_time", format!("'{}' is not an RFC 3339 time", text)))
+}
+
+fn parse_rfc3339(text: &[u8]) -> Option<f64> {
+    let mut cursor = Cursor(text);
+    let year = cursor.digits(4)?;
+    cursor.eat(b'-').then_some(())?;
+    let month = cursor.digits(2)?;
+    cursor.eat(b'-').then_some(())?;
+    let day = cursor.digits(2)?;
+    let days = days_from_civil(i64::from(year), month, day);
+    // Catches month 13, February 30th and the like
+    if !(1..=12).contains(&month) || civil_from_days(days) != (i64::from(year), month, day) {
+        return None;
+    }
+    if cursor.0.is_empty() {
+        return Some((days * 86_400) as f64);
+    }
+
+    if !(cursor.eat(b'T') || cursor.eat(b't') || cursor.eat(b' ')) {
+        return None;
+    }
+    let hour = cursor.digits(2)?;
+    cursor.eat(b':').then_some(())?;
+    let minute = cursor.digits(2)?;
+    let second = if cursor.eat(b':') {
+        cursor.digits(2)?
+    } else {
+        0
+    };
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let mut fraction = 0.0;
+    if cursor.0.starts_with(b".") {
+        let len = cursor.0[1..]
+            .iter()
+            .take_while(|d| d.is_ascii_digit())
+            .count();
+        if len == 0 {
+            return None;
+        }
+        fraction = std::str::from_utf8(&cursor.0[..=len]).ok()?.parse().ok()?;
+        cursor.0 = &cursor.0[len + 1..];
+    }
+    let offset = if cursor.0.is_empty() || cursor.eat(b'Z') || cursor.eat(b'z') {
+        0
+    } else {
+        let sign = if cursor.eat(b'+') {
+            1
+        } else if cursor.eat(b'-') {
+            -1
+        } else {
+            return None;
+        };
+        let hours = cursor.digits(2)?;
+        cursor.eat(b':');
+        let minutes = cursor.digits(2)?;
+        if hours > 23 || minutes > 59 {
+            return None;
+        }
+        sign * i64::from(hours * 3600 + minutes * 60)
+    };
+    if !cursor.0.is_empty() {
+        return None;
+    }
+    let seconds = i64::from(hour * 3600 + minute * 60 + second) - offset;
+    Some((days * 86_400 + seconds) as f64 + fraction)
+}
+
+fn replace(args: &[Value]) -> Result<Value, TemplateError> {
+    let (text, from, to) = (
+        args[0].to_string(),
+        args[1].to_string(),
+        args[2].to_string(),
+    );
+    if from.is_empty() {
+        return Err(invalid("replace", "nothing to replace"));
+    }
+    // Bound the result before building it
+    let matches = text.matches(from.as_str()).count();
+    if text.len() - matches * from.len() + matches * to.len() > MAX_TEXT_LEN {
+        return Err(TemplateError::TooLong);
+    }
+    Ok(text.replace(&from, &to).into())
+}
+
+fn substr(args: &[Value]) -> Result<Value, TemplateError> {
+    let text = args[0].to_string();
+    let start = integer("substr", &args[1], 0..=MAX_TEXT_LEN as i64)? as usize;
+    let len = match args.get(2) {
+        Some(len) => integer("substr", len, 0..=MAX_TEXT_LEN as i64)? as usize,
+        None => usize::MAX,
+    };
+    Ok(text
+        .chars()
+        .skip(start)
+        .take(len)
+        .collect::<String>()
+        .into())
+}
+
+fn split(args: &[Value]) -> Result<Value, TemplateError> {
+    let (text, separator) = (args[0].to_string(), args[1].to_string());
+    if separator.is_empty() {
+        return Err(invalid("split", "empty separator"));
+    }
+    let index = integer("split", &args[2], 0..=MAX_TEXT_LEN as i64)? as usize;
+    Ok(text
+        .split(separator.as_str())
+        .nth(index)
+        .unwrap_or("")
+        .into())
+}
+
+fn concat(args: &[Value]) -> Result<Value, TemplateError> {
+    let mut out = String::new();
+    for arg in args {
+        out.push_str(&arg.to_string());
+        if out.len() > MAX_TEXT_LEN {
+            return Err(TemplateError::TooLong);
+        }
+    }
+    Ok(out.into())
+}
+
+fn pad(function: &'static str, args: &[Value], left: bool) -> Result<Value, TemplateError> {
+    let text = args[0].to_string();
+    let width = integer(function, &args[1], 0..=MAX_TEXT_LEN as i64)? as usize;
+    let fill = match args.get(2) {
+        Some(fill) => {
+            let fill = fill.to_string();
+            let mut chars = fill.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(invalid(function, "fill must be one character")),
+            }
+        }
+        None => ' ',
+    };
+    let padding: String =
+        std::iter::repeat_n(fill, width.saturating_sub(text.chars().count())).collect();
+    if padding.len() + text.len() > MAX_TEXT_LEN {
+        return Err(TemplateError::TooLong);
+    }
+    Ok(if left {
+        padding + &text
+    } else {
+        text + &padding
+    }
+    .into())
+}
+
+fn sha256(args: &[Value]) -> Result<Value, TemplateError> {
+    let digest = Sha256::digest(args[0].to_string().as_bytes());
+    Ok(digest
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>()
+        .into())
+}
+
+fn base64_decode(args: &[Value]) -> Result<Value, TemplateError> {
+    let decoded = Base64::decode_vec(args[0].to_string().trim())
+        .map_err(|_| invalid("base64_decode", "invalid base64"))?;
+    String::from_utf8(decoded)
+        .map(Value::Text)
+        .map_err(|_| invalid("base64_decode", "decoded bytes are not UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[Value]) -> Result<String, TemplateError> {
+        (lookup(name).unwrap().call)(args).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn test_convert() {
+        let convert =
+            |x: f64, from: &str, to: &str| call("convert", &[x.into(), from.into(), to.into()]);
+        assert_eq!(convert(100.0, "c", "f").unwrap(), "212");
+        assert_eq!(convert(-40.0, "F", "C").unwrap(), "-40");
+        assert_eq!(convert(0.0, "c", "k").unwrap(), "273.15");
+        assert_eq!(convert(1.0, "mi", "km").unwrap(), "1.609344");
+        assert_eq!(convert(36.0, "km/h", "m/s").unwrap(), "10");
+        assert_eq!(convert(1.0, "kwh", "j").unwrap(), "3600000");
+        assert_eq!(convert(1013.25, "hpa", "atm").unwrap(), "1");
+        assert!(matches!(
+            convert(1.0, "kg", "m"),
+            Err(TemplateError::InvalidArgument("convert", _))
+        ));
+        assert!(convert(1.0, "parsec", "m").is_err());
+        assert_eq!(
+            call("convert", &["21.5".into(), "c".into(), "f".into()]).unwrap(),
+            "70.7"
+        );
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(call("round", &[2.345.into(), 2.0.into()]).unwrap(), "2.35");
+        assert_eq!(call("round", &[(-2.5).into()]).unwrap(), "-3");
+        assert!(call("round", &[1.0.into(), 13.0.into()]).is_err());
+        assert_eq!(
+            call("max", &[1.0.into(), "7".into(), 3.0.into()]).unwrap(),
+            "7"
+        );
+        assert_eq!(call("floor", &[(-1.5).into()]).unwrap(), "-2");
+        assert!(call("abs", &["warm".into()]).is_err());
+        assert!(call("number", &["inf".into()]).is_err());
+    }
+
+    #[test]
+    fn test_time() {
+        const NOW: f64 = 1_714_566_896.0;
+        assert_eq!(
+            call("format_time", &[NOW.into()]).unwrap(),
+            "2024-05-01T12:34:56Z"
+        );
+        assert_eq!(
+            call(
+                "format_time",
+                &[NOW.into(), "%j %Y%m%d %H.%M.%S %s%%".into()]
+            )
+            .unwrap(),
+            "122 20240501 12.34.56 1714566896%"
+        );
+        assert_eq!(
+            call("format_time", &[(-1.0).into()]).unwrap(),
+            "1969-12-31T23:59:59Z"
+        );
+        assert!(call("format_time", &[NOW.into(), "%q".into()]).is_err());
+        assert!(call("format_time", &[1e13.into()]).is_err());
+
+        let parse = |text: &str| call("parse_time", &[text.into()]);
+        assert_eq!(parse("2024-05-01T12:34:56Z").unwrap(), "1714566896");
+        assert_eq!(parse("2024-05-01 14:34:56+02:00").unwrap(), "1714566896");
+        assert_eq!(
+            parse("2024-05-01T07:04:56.25-0530").unwrap(),
+            "1714566896.25"
+        );
+        assert_eq!(parse("2024-05-01T12:34").unwrap(), "1714566840");
+        assert_eq!(parse("2024-05-01").unwrap(), "1714521600");
+        for bad in [
+            "2024-02-30",
+            "2024-13-01",
+            "2024-05-01T24:00",
+            "yesterday",
+            "2024-05-01T12:34:56+2",
+        ] {
+            assert!(parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(call("upper", &["déjà".into()]).unwrap(), "DÉJÀ");
+        assert_eq!(call("len", &["déjà".into()]).unwrap(), "4");
+        assert_eq!(
+            call("substr", &["déjà vu".into(), 2.0.into(), 2.0.into()]).unwrap(),
+            "jà"
+        );
+        assert_eq!(
+            call("split", &["a/b/c".into(), "/".into(), 1.0.into()]).unwrap(),
+            "b"
+        );
+        assert_eq!(
+            call("split", &["a/b/c".into(), "/".into(), 5.0.into()]).unwrap(),
+            ""
+        );
+        assert_eq!(
+            call("replace", &["a-b-c".into(), "-".into(), "/".into()]).unwrap(),
+            "a/b/c"
+        );
+        assert_eq!(
+            call("pad_left", &[7.0.into(), 3.0.into(), "0".into()]).unwrap(),
+            "007"
+        );
+        assert_eq!(
+            call("pad_right", &["ab".into(), 4.0.into()]).unwrap(),
+            "ab  "
+        );
+        assert!(call("pad_left", &["x".into(), 1e6.into()]).is_err());
+        assert_eq!(
+            call("json", &["say \"hi\"\n".into()]).unwrap(),
+            r#""say \"hi\"\n""#
+        );
+        let big = "x".repeat(MAX_TEXT_LEN / 2 + 1);
+        assert_eq!(
+            call("concat", &[big.as_str().into(), big.as_str().into()]),
+            Err(TemplateError::TooLong)
+        );
+    }
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(
+            call("sha256", &["abc".into()]).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(call("base64", &["hi!".into()]).unwrap(), "aGkh");
+        assert_eq!(call("base64_decode", &["aGkh".into()]).unwrap(), "hi!");
+        assert!(call("base64_decode", &["not base64".into()]).is_err());
+        assert!(call("base64_decode", &["/w==".into()]).is_err());
+    }
+}
@@ -0,0 +1,477 @@
+//! Templates
+//!
+//! Topic and payload templates expand `{placeholder}`s: a variable the
+//! template's user provides (`{name}`), or a call into the built-in
+//! function library (see [`functions`]):
+//!
+//! ```text
+//! {"temp_f": {convert(temp, "c", "f")}, "day": "{format_time(timestamp, "%F")}"}
+//! ```
+//!
+//! Arguments are variables, numbers, double-quoted strings (`\"`, `\\`,
+//! `\n` and `\t` escapes) and nested calls. Anything else in braces is
+//! literal text, so JSON payloads need no escaping. Unknown functions and
+//! malformed calls are errors when the template is parsed.
+//!
+//! Rendering is deterministic: the output depends only on the template and
+//! the variables (no clock, no randomness). Each render has a budget of
+//! evaluation steps: one per call plus one per 64 bytes it reads or
+//! produces, so a runaway template fails instead of hogging the CPU.
+
+pub mod functions;
+
+use std::fmt;
+
+pub use functions::FUNCTIONS;
+
+/// Default steps a render may take
+pub const DEFAULT_BUDGET: u32 = 10_000;
+
+/// Longest text a function may produce
+pub const MAX_TEXT_LEN: usize = 64 * 1024;
+
+/// Deepest nesting of calls
+const MAX_DEPTH: usize = 32;
+
+/// Why a template can't be parsed or rendered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// Malformed placeholder at a byte offset
+    Syntax(usize, &'static str),
+    UnknownFunction(String),
+    UnknownVariable(String),
+    /// Wrong number of arguments
+    Arity(&'static str),
+    /// A function rejected its arguments
+    InvalidArgument(&'static str, String),
+    /// The render took more steps than its budget
+    BudgetExceeded(u32),
+    TooLong,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Syntax(offset, e) => write!(f, "{} at offset {}", e, offset),
+            TemplateError::UnknownFunction(name) => write!(f, "unknown function '{}'", name),
+            TemplateError::UnknownVariable(name) => write!(f, "unknown variable '{}'", name),
+            TemplateError::Arity(name) => write!(f, "wrong number of arguments to {}()", name),
+            TemplateError::InvalidArgument(name, e) => write!(f, "{}(): {}", name, e),
+            TemplateError::BudgetExceeded(budget) => {
+                write!(f, "template exceeded its budget of {} steps", budget)
+            }
+            TemplateError::TooLong => write!(f, "text longer than {} bytes", MAX_TEXT_LEN),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// A variable, argument or result
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(f64),
+}
+
+impl Value {
+    /// Bytes the value takes as text, for the budget
+    fn cost_len(&self) -> usize {
+        match self {
+            Value::Text(text) => text.len(),
+            Value::Number(_) => 8,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Text(text) => f.write_str(text),
+            // Whole numbers without a fraction, others in their shortest
+            // exact form
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Value::Number(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Value::Text(text.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value::Text(text)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+/// Steps left in a render
+struct Budget {
+    limit: u32,
+    left: u32,
+}
+
+impl Budget {
+    fn new(limit: u32) -> Self {
+        Self { limit, left: limit }
+    }
+
+    /// Take a call's steps: one, plus one per 64 bytes
+    fn charge(&mut self, bytes: usize) -> Result<(), TemplateError> {
+        let steps = 1 + u32::try_from(bytes / 64).unwrap_or(u32::MAX);
+        self.left = self
+            .left
+            .checked_sub(steps)
+            .ok_or(TemplateError::BudgetExceeded(self.limit))?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    /// Index into the template's variables
+    Var(usize),
+    Call(&'static functions::Function, Vec<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Expr(Expr),
+}
+
+/// A parsed template
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parse a template whose variables are `vars`; `{x}` for a name not in
+    /// `vars` stays literal text
+    pub fn parse(template: &str, vars: &[&str]) -> Result<Self, TemplateError> {
+        let mut parser = Parser {
+            src: template,
+            pos: 0,
+            vars,
+        };
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        while let Some(open) = parser.src[parser.pos..].find('{') {
+            text.push_str(&parser.src[parser.pos..parser.pos + open]);
+            parser.pos += open + 1;
+            match parser.placeholder()? {
+                Some(expr) => {
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Expr(expr));
+                }
+                None => text.push('{'),
+            }
+        }
+        text.push_str(&parser.src[parser.pos..]);
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Whether the template has no placeholders
+    pub fn is_literal(&self) -> bool {
+        self.parts.iter().all(|part| matches!(part, Part::Text(_)))
+    }
+
+    /// Expand the template with `values` (in the order of the variables it
+    /// was parsed with), within `budget` steps
+    pub fn render(&self, values: &[Value], budget: u32) -> Result<String, TemplateError> {
+        let mut budget = Budget::new(budget);
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Expr(expr) => {
+                    let value = eval(expr, values, &mut budget)?;
+                    out.push_str(&value.to_string());
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn eval(expr: &Expr, values: &[Value], budget: &mut Budget) -> Result<Value, TemplateError> {
+    match expr {
+        Expr::Literal(value) => Ok(value.clone()),
+        Expr::Var(index) => Ok(values
+            .get(*index)
+            .cloned()
+            .unwrap_or(Value::Text(String::new()))),
+        Expr::Call(function, args) => {
+            let args = args
+                .iter()
+                .map(|arg| eval(arg, values, budget))
+                .collect::<Result<Vec<_>, _>>()?;
+            let result = (function.call)(&args)?;
+            if let Value::Text(ref text) = result {
+                if text.len() > MAX_TEXT_LEN {
+                    return Err(TemplateError::TooLong);
+                }
+            }
+            let bytes: usize = args.iter().chain([&result]).map(Value::cost_len).sum();
+            budget.charge(bytes)?;
+            Ok(result)
+        }
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    vars: &'a [&'a str],
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.src[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error(&self, e: &'static str) -> TemplateError {
+        TemplateError::Syntax(self.pos, e)
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        let rest = &self.src[self.pos..];
+        if !rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            return None;
+        }
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        Some(&rest[..len])
+    }
+
+    fn var(&self, name: &str) -> Option<usize> {
+        self.vars.iter().position(|var| *var == name)
+    }
+
+    /// The placeholder after a `{` (`None`, rewound, if it's literal text)
+    fn placeholder(&mut self) -> Result<Option<Expr>, TemplateError> {
+        let start = self.pos;
+        let Some(name) = self.ident() else {
+            return Ok(None);
+        };
+        let expr = match self.peek() {
+            Some('(') => self.call(name, 0)?,
+            Some('}') => match self.var(name) {
+                Some(index) => Expr::Var(index),
+                None => {
+                    self.pos = start;
+                    return Ok(None);
+                }
+            },
+            _ => {
+                self.pos = start;
+                return Ok(None);
+            }
+        };
+        self.skip_whitespace();
+        if self.peek() != Some('}') {
+            return Err(self.error("expected '}'"));
+        }
+        self.pos += 1;
+        Ok(Some(expr))
+    }
+
+    /// A call to `name`, at the opening parenthesis
+    fn call(&mut self, name: &str, depth: usize) -> Result<Expr, TemplateError> {
+        if depth >= MAX_DEPTH {
+            return Err(self.error("calls nested too deeply"));
+        }
+        let function = functions::lookup(name)
+            .ok_or_else(|| TemplateError::UnknownFunction(name.to_string()))?;
+        self.pos += 1;
+        let mut args = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(')') {
+            self.pos += 1;
+        } else {
+            loop {
+                args.push(self.arg(depth)?);
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(',') => self.pos += 1,
+                    Some(')') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return Err(self.error("expected ',' or ')'")),
+                }
+            }
+        }
+        if !function.arity.contains(&args.len()) {
+            return Err(TemplateError::Arity(function.name));
+        }
+        Ok(Expr::Call(function, args))
+    }
+
+    fn arg(&mut self, depth: usize) -> Result<Expr, TemplateError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"') => self.string(),
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '.' => self.number(),
+            _ => {
+                let start = self.pos;
+                let Some(name) = self.ident() else {
+                    return Err(self.error("expected an argument"));
+                };
+                if self.peek() == Some('(') {
+                    return self.call(name, depth + 1);
+                }
+                match self.var(name) {
+                    Some(index) => Ok(Expr::Var(index)),
+                    None => Err(TemplateError::UnknownVariable(
+                        self.src[start..self.pos].to_string(),
+                    )),
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<Expr, TemplateError> {
+        self.pos += 1;
+        let mut text = String::new();
+        let mut chars = self.src[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(Expr::Literal(Value::Text(text)));
+                }
+                '\\' => match chars.next() {
+                    Some((_, '"')) => text.push('"'),
+                    Some((_, '\\')) => text.push('\\'),
+                    Some((_, 'n')) => text.push('\n'),
+                    Some((_, 't')) => text.push('\t'),
+                    _ => {
+                        self.pos += i;
+                        return Err(self.error("invalid escape"));
+                    }
+                },
+                c => text.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn number(&mut self) -> Result<Expr, TemplateError> {
+        let rest = &self.src[self.pos..];
+        let len = rest
+            .char_indices()
+            .find(|&(i, c)| {
+                !(c.is_ascii_digit()
+                    || c == '.'
+                    || c == 'e'
+                    || c == 'E'
+                    || (c == '-' || c == '+') && (i == 0 || rest[..i].ends_with(['e', 'E'])))
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let n: f64 = rest[..len]
+            .parse()
+            .map_err(|_| self.error("invalid number"))?;
+        self.pos += len;
+        Ok(Expr::Literal(Value::Number(n)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str) -> Result<String, TemplateError> {
+        let vars = ["name", "temp"];
+        let values = [Value::from("dev-1"), Value::from(21.5)];
+        Template::parse(template, &vars)?.render(&values, DEFAULT_BUDGET)
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(render("sensors/{name}").unwrap(), "sensors/dev-1");
+        assert_eq!(
+            render(r#"{"id": "{upper(name)}", "t": {round(temp)}}"#).unwrap(),
+            r#"{"id": "DEV-1", "t": 22}"#
+        );
+        // Unknown variables and other braces are text
+        assert_eq!(
+            render("{other} {} { name } {1}").unwrap(),
+            "{other} {} { name } {1}"
+        );
+        assert_eq!(
+            render(r#"{concat("a\"b", " ", -1.5e1, upper(lower("X")))}"#).unwrap(),
+            r#"a"b -15X"#
+        );
+        assert!(Template::parse("{name}", &["name"])
+            .unwrap()
+            .render(&[], 1)
+            .is_ok());
+        assert!(Template::parse("plain", &[]).unwrap().is_literal());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            render("{uper(name)}").unwrap_err(),
+            TemplateError::UnknownFunction("uper".to_string())
+        );
+        assert_eq!(
+            render("{upper(nme)}").unwrap_err(),
+            TemplateError::UnknownVariable("nme".to_string())
+        );
+        assert_eq!(
+            render("{upper(name, name)}").unwrap_err(),
+            TemplateError::Arity("upper")
+        );
+        assert!(matches!(
+            render("{upper(name}"),
+            Err(TemplateError::Syntax(..))
+        ));
+        assert!(matches!(
+            render(r#"{upper("x)}"#),
+            Err(TemplateError::Syntax(_, "unterminated string"))
+        ));
+        let nested = format!("{{{}name{}}}", "lower(".repeat(40), ")".repeat(40));
+        assert!(matches!(render(&nested), Err(TemplateError::Syntax(..))));
+    }
+
+    #[test]
+    fn test_budget() {
+        let template = Template::parse(r#"{pad_left("", 60000, "x")}"#, &[]).unwrap();
+        assert_eq!(
+            template.render(&[], 100),
+            Err(TemplateError::BudgetExceeded(100))
+        );
+        assert_eq!(template.render(&[], DEFAULT_BUDGET).unwrap().len(), 60000);
+
+        // Every call costs a step
+        let template = Template::parse("{lower(lower(lower(name)))}", &["name"]).unwrap();
+        let values = [Value::from("A")];
+        assert_eq!(template.render(&values, 3).unwrap(), "a");
+        assert!(template.render(&values, 2).is_err());
+    }
+}
@@ -8,23 +8,29 @@
 //! - Uses callback-based matching to avoid intermediate allocations
 //! - Uses SmallVec for typical workloads (few matching subscriptions per topic)
 //! - Pre-allocates result vectors with reasonable capacity
+//! - Skips the cache and trie for topics no subscribed filter could match,
+//!   using per-filter counters (`has_subscribers`)
 
+mod schema;
 mod trie;
 pub mod validation;
 
+pub use schema::{SchemaSummary, TemplateSummary, TopicSchema};
 pub use trie::TopicTrie;
 pub use validation::{
     topic_matches_filter, validate_topic_filter, validate_topic_filter_with_max_levels,
     validate_topic_name, validate_topic_name_with_max_levels, TopicLevel,
 };
 
-use ahash::AHashMap;
+use ahash::{AHashMap, RandomState};
 use dashmap::DashMap;
 use parking_lot::RwLock;
+use rand::Rng;
 use smallvec::SmallVec;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use crate::config::SharedSubscriptionStrategy;
 use crate::protocol::QoS;
 
 /// Maximum number of entries in the topic cache
@@ -64,6 +70,19 @@ pub fn parse_shared_subscription(filter: &str) -> Option<(&str, &str)> {
     None
 }
 
+/// Literal levels of a wildcard filter before its first wildcard, or None
+/// for a filter without wildcards
+fn wildcard_prefix(filter: &str) -> Option<&str> {
+    let mut start = 0usize;
+    for level in filter.split('/') {
+        if level == "+" || level == "#" {
+            return Some(&filter[..start.saturating_sub(1)]);
+        }
+        start += level.len() + 1;
+    }
+    None
+}
+
 /// Cached topic match result
 struct CachedMatch {
     subscriptions: SmallVec<[Subscription; 16]>,
@@ -75,10 +94,18 @@ pub struct SubscriptionStore {
     trie: RwLock<TopicTrie<Vec<Subscription>>>,
     /// Round-robin counters for shared subscriptions, keyed by share group
     share_counters: DashMap<Arc<str>, AtomicUsize>,
+    /// How a share group member is picked for each message
+    share_strategy: SharedSubscriptionStrategy,
     /// Cache of topic -> matching subscriptions (invalidated on subscription changes)
     topic_cache: DashMap<String, CachedMatch>,
     /// Generation counter - incremented on any subscription change
     generation: AtomicU64,
+    /// Subscribed filters without wildcards
+    exact_filters: DashMap<String, usize, RandomState>,
+    /// Subscribed wildcard filters by the literal levels before their
+    /// first wildcard ("" for filters starting with one)
+    wildcard_prefixes: DashMap<String, usize, RandomState>,
+    wildcard_filters: AtomicUsize,
 }
 
 impl SubscriptionStore {
@@ -86,8 +113,53 @@ impl SubscriptionStore {
         Self {
             trie: RwLock::new(TopicTrie::new()),
             share_counters: DashMap::new(),
+            share_strategy: SharedSubscriptionStrategy::default(),
             topic_cache: DashMap::new(),
             generation: AtomicU64::new(0),
+            exact_filters: DashMap::with_hasher(RandomState::new()),
+            wildcard_prefixes: DashMap::with_hasher(RandomState::new()),
+            wildcard_filters: AtomicUsize::new(0),
+        }
+    }
+
+    /// Set the shared subscription member selection strategy
+    pub fn with_share_strategy(mut self, strategy: SharedSubscriptionStrategy) -> Self {
+        self.share_strategy = strategy;
+        self
+    }
+
+    /// Pick the share group member that receives a message
+    ///
+    /// Sticky selection uses rendezvous hashing with fixed seeds, so a
+    /// publisher maps to the same member on every node and only moves when
+    /// that member leaves the group. Messages without a publishing client
+    /// (published by the server, or bridged in from another broker) fall
+    /// back to round-robin.
+    fn select_member<'a>(
+        &self,
+        group: Arc<str>,
+        members: &'a [Subscription],
+        publisher: Option<&str>,
+    ) -> &'a Subscription {
+        match (self.share_strategy, publisher) {
+            (SharedSubscriptionStrategy::Random, _) => {
+                &members[rand::thread_rng().gen_range(0..members.len())]
+            }
+            (SharedSubscriptionStrategy::Sticky, Some(publisher)) => {
+                let hasher = RandomState::with_seeds(0x5eed, 0x5b5c, 0x7e57, 0x5a1e);
+                members
+                    .iter()
+                    .max_by_key(|member| hasher.hash_one((publisher, &*member.client_id)))
+                    .unwrap_or(&members[0])
+            }
+            _ => {
+                let counter = self
+                    .share_counters
+                    .entry(group)
+                    .or_insert_with(|| AtomicUsize::new(0));
+                let idx = counter.fetch_add(1, Ordering::Relaxed) % members.len();
+                &members[idx]
+            }
         }
     }
 
@@ -101,6 +173,60 @@ impl SubscriptionStore {
         }
     }
 
+    /// Count a filter added to the trie
+    fn filter_added(&self, filter: &str) {
+        match wildcard_prefix(filter) {
+            Some(prefix) => {
+                *self
+                    .wildcard_prefixes
+                    .entry(prefix.to_string())
+                    .or_insert(0) += 1;
+                self.wildcard_filters.fetch_add(1, Ordering::Relaxed);
+            }
+            None => *self.exact_filters.entry(filter.to_string()).or_insert(0) += 1,
+        }
+    }
+
+    /// Uncount a filter removed from the trie
+    fn filter_removed(&self, filter: &str) {
+        let (counts, key) = match wildcard_prefix(filter) {
+            Some(prefix) => {
+                self.wildcard_filters.fetch_sub(1, Ordering::Relaxed);
+                (&self.wildcard_prefixes, prefix)
+            }
+            None => (&self.exact_filters, filter),
+        };
+        counts.remove_if_mut(key, |_, n| {
+            *n -= 1;
+            *n == 0
+        });
+    }
+
+    /// Whether any subscription could match `topic`
+    ///
+    /// A check against the subscribed filters' counters rather than the
+    /// trie: one lookup for the topic itself, and while there are wildcard
+    /// subscriptions one per topic level. It may answer true for a topic
+    /// nothing matches (`a/+/b` for `a/c/d`), never false for one something
+    /// does, so a PUBLISH it answers false for has no one to be routed to.
+    pub fn has_subscribers(&self, topic: &str) -> bool {
+        if self.exact_filters.contains_key(topic) {
+            return true;
+        }
+        if self.wildcard_filters.load(Ordering::Relaxed) == 0 {
+            return false;
+        }
+        // $-topics don't match filters starting with a wildcard
+        if !topic.starts_with('$') && self.wildcard_prefixes.contains_key("") {
+            return true;
+        }
+        topic
+            .match_indices('/')
+            .map(|(i, _)| &topic[..i])
+            .chain([topic])
+            .any(|prefix| !prefix.is_empty() && self.wildcard_prefixes.contains_key(prefix))
+    }
+
     /// Add a subscription
     pub fn subscribe(&self, filter: &str, mut subscription: Subscription) {
         // Check if this is a shared subscription
@@ -125,6 +251,7 @@ impl SubscriptionStore {
             subs.push(subscription);
         } else {
             trie.insert(actual_filter, vec![subscription]);
+            self.filter_added(actual_filter);
         }
         drop(trie);
         self.invalidate_cache();
@@ -157,6 +284,7 @@ impl SubscriptionStore {
             let removed = subs.len() != len_before;
             if subs.is_empty() {
                 trie.remove(actual_filter);
+                self.filter_removed(actual_filter);
             }
             removed
         } else {
@@ -172,6 +300,15 @@ impl SubscriptionStore {
     /// Remove all subscriptions for a client
     pub fn unsubscribe_all(&self, client_id: &str) {
         let mut trie = self.trie.write();
+        let mut emptied = Vec::new();
+        trie.for_each_filter(|filter, subs| {
+            if subs.iter().all(|s| s.client_id.as_ref() == client_id) {
+                emptied.push(filter.to_string());
+            }
+        });
+        for filter in emptied {
+            self.filter_removed(&filter);
+        }
         trie.remove_by_predicate(|subs| {
             subs.retain(|s| s.client_id.as_ref() != client_id);
             subs.is_empty()
@@ -181,11 +318,29 @@ impl SubscriptionStore {
     }
 
     /// Find all matching subscriptions for a topic
-    /// For shared subscriptions, only one subscriber per share group is returned (round-robin)
+    /// For shared subscriptions, only one subscriber per share group is returned
     ///
     /// Performance: Uses topic cache for frequently-published topics (O(1) lookup)
     /// Cache is invalidated when subscriptions change.
     pub fn matches(&self, topic: &str) -> SmallVec<[Subscription; 16]> {
+        self.matches_from(topic, None)
+    }
+
+    /// Find all matching subscriptions for a message from `publisher`
+    ///
+    /// The publisher's client ID is only used by sticky share group selection.
+    pub fn matches_from(
+        &self,
+        topic: &str,
+        publisher: Option<&str>,
+    ) -> SmallVec<[Subscription; 16]> {
+        // Most topics of a fleet that publishes more than it consumes have
+        // no subscribers; they don't need the cache (which they would fill)
+        // or the trie
+        if !self.has_subscribers(topic) {
+            return SmallVec::new();
+        }
+
         let current_gen = self.generation.load(Ordering::Acquire);
 
         // Check cache first (only for non-shared subscriptions)
@@ -217,20 +372,15 @@ impl SubscriptionStore {
         });
         drop(trie);
 
-        // For each share group, pick one subscriber using round-robin
+        // For each share group, pick one subscriber
         for (group, subs) in share_groups {
             if subs.is_empty() {
                 continue;
             }
-            let counter = self
-                .share_counters
-                .entry(group)
-                .or_insert_with(|| AtomicUsize::new(0));
-            let idx = counter.fetch_add(1, Ordering::Relaxed) % subs.len();
-            result.push(subs[idx].clone());
+            result.push(self.select_member(group, &subs, publisher).clone());
         }
 
-        // Cache result only if no shared subscriptions (round-robin makes them uncacheable)
+        // Cache result only if no shared subscriptions (member selection makes them uncacheable)
         // and cache isn't too large
         if !has_shared && self.topic_cache.len() < TOPIC_CACHE_MAX_SIZE {
             self.topic_cache.insert(
@@ -246,15 +396,19 @@ impl SubscriptionStore {
     }
 
     /// Find all matching subscriptions using a callback to avoid allocation
-    /// For shared subscriptions, only one subscriber per share group is called (round-robin)
+    /// For shared subscriptions, only one subscriber per share group is
+    /// called, selected for `publisher` as in [`Self::matches_from`]
     ///
     /// Note: For shared subscriptions, this still needs to clone subscriptions temporarily
-    /// to handle the round-robin selection. For non-shared subscriptions, the callback
+    /// to handle the member selection. For non-shared subscriptions, the callback
     /// is invoked immediately without cloning.
-    pub fn matches_with_callback<F>(&self, topic: &str, mut callback: F)
+    pub fn matches_with_callback<F>(&self, topic: &str, publisher: Option<&str>, mut callback: F)
     where
         F: FnMut(&Subscription),
     {
+        if !self.has_subscribers(topic) {
+            return;
+        }
         let trie = self.trie.read();
         // Temporary storage for share group selection (must clone due to callback lifetime)
         let mut share_groups: AHashMap<Arc<str>, SmallVec<[Subscription; 4]>> =
@@ -275,20 +429,72 @@ impl SubscriptionStore {
             }
         });
 
-        // For each share group, pick one subscriber using round-robin
+        // For each share group, pick one subscriber
         for (group, subs) in share_groups {
             if subs.is_empty() {
                 continue;
             }
-            let counter = self
-                .share_counters
-                .entry(group)
-                .or_insert_with(|| AtomicUsize::new(0));
-            let idx = counter.fetch_add(1, Ordering::Relaxed) % subs.len();
-            callback(&subs[idx]);
+            callback(self.select_member(group, &subs, publisher));
         }
     }
 
+    /// A subscriber of `topic` other than `publisher` that `pred` holds for
+    ///
+    /// Unlike [`Self::matches_from`] no share group member is selected, so
+    /// a group only counts when `pred` holds for all its members.
+    pub fn find_subscriber(
+        &self,
+        topic: &str,
+        publisher: &str,
+        pred: impl Fn(&str) -> bool,
+    ) -> Option<Arc<str>> {
+        if !self.has_subscribers(topic) {
+            return None;
+        }
+        let trie = self.trie.read();
+        let mut found = None;
+        let mut share_groups: AHashMap<Arc<str>, Option<Arc<str>>> = AHashMap::new();
+        trie.matches(topic, |subs| {
+            for sub in subs {
+                if &*sub.client_id == publisher {
+                    continue;
+                }
+                let hit = pred(&sub.client_id).then(|| sub.client_id.clone());
+                match sub.share_group {
+                    Some(ref group) => {
+                        let member = share_groups.entry(group.clone()).or_insert(hit.clone());
+                        if hit.is_none() {
+                            *member = None;
+                        }
+                    }
+                    None => {
+                        if found.is_none() {
+                            found = hit;
+                        }
+                    }
+                }
+            }
+        });
+        found.or_else(|| share_groups.into_values().flatten().next())
+    }
+
+    /// Subscriptions per topic filter, in filter order; shared ones are
+    /// counted under "$share/{group}/{filter}"
+    pub fn filter_counts(&self) -> Vec<(String, usize)> {
+        let trie = self.trie.read();
+        let mut counts = std::collections::BTreeMap::new();
+        trie.for_each_filter(|filter, subs| {
+            for sub in subs {
+                let filter = match sub.share_group {
+                    Some(ref group) => format!("$share/{}/{}", group, filter),
+                    None => filter.to_string(),
+                };
+                *counts.entry(filter).or_insert(0) += 1;
+            }
+        });
+        counts.into_iter().collect()
+    }
+
     /// Count the number of shared subscriptions
     /// For $SYS/broker/shared_subscriptions/count
     pub fn shared_subscription_count(&self) -> usize {
@@ -306,3 +512,156 @@ impl Default for SubscriptionStore {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sub(client_id: &str) -> Subscription {
+        Subscription {
+            client_id: client_id.into(),
+            qos: QoS::AtMostOnce,
+            no_local: false,
+            retain_as_published: false,
+            subscription_id: None,
+            share_group: None,
+        }
+    }
+
+    fn store(strategy: SharedSubscriptionStrategy) -> SubscriptionStore {
+        let store = SubscriptionStore::new().with_share_strategy(strategy);
+        for member in ["w1", "w2", "w3"] {
+            store.subscribe("$share/workers/jobs/#", sub(member));
+        }
+        store.subscribe("jobs/#", sub("audit"));
+        store
+    }
+
+    /// Member of the share group that received each of `n` messages
+    fn receivers(store: &SubscriptionStore, n: usize, publisher: Option<&str>) -> Vec<String> {
+        (0..n)
+            .map(|_| {
+                let matches = store.matches_from("jobs/1", publisher);
+                assert_eq!(matches.len(), 2);
+                assert!(matches.iter().any(|s| &*s.client_id == "audit"));
+                matches
+                    .iter()
+                    .find(|s| s.share_group.is_some())
+                    .map(|s| s.client_id.to_string())
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_has_subscribers() {
+        let store = SubscriptionStore::new();
+        assert!(!store.has_subscribers("devices/1/telemetry"));
+
+        store.subscribe("devices/1/cmd", sub("d1"));
+        store.subscribe("$share/ops/alerts/+/high", sub("ops"));
+        assert!(store.has_subscribers("devices/1/cmd"));
+        assert!(store.has_subscribers("alerts/db/high"));
+        assert!(!store.has_subscribers("devices/1/telemetry"));
+        assert!(!store.has_subscribers("metrics/cpu"));
+
+        // Root wildcards match everything but $-topics
+        store.subscribe("#", sub("monitor"));
+        store.subscribe("sensors/#", sub("monitor"));
+        assert!(store.has_subscribers("metrics/cpu"));
+        assert!(!store.has_subscribers("$SYS/broker/uptime"));
+        assert!(store.unsubscribe("#", "monitor"));
+        assert!(!store.has_subscribers("metrics/cpu"));
+        assert!(store.has_subscribers("sensors"));
+
+        store.unsubscribe_all("monitor");
+        store.unsubscribe_all("ops");
+        assert!(!store.has_subscribers("sensors/1"));
+        assert!(!store.has_subscribers("alerts/db/high"));
+        assert!(store.has_subscribers("devices/1/cmd"));
+        assert!(store.unsubscribe("devices/1/cmd", "d1"));
+        assert!(!store.has_subscribers("devices/1/cmd"));
+    }
+
+    #[test]
+    fn test_find_subscriber() {
+        let store = store(SharedSubscriptionStrategy::RoundRobin);
+        let behind = |ids: &'static [&'static str]| move |id: &str| ids.contains(&id);
+
+        assert_eq!(
+            store
+                .find_subscriber("jobs/1", "pub", behind(&["audit"]))
+                .as_deref(),
+            Some("audit")
+        );
+        assert!(store
+            .find_subscriber("jobs/1", "audit", behind(&["audit"]))
+            .is_none());
+        assert!(store
+            .find_subscriber("other", "pub", behind(&["audit"]))
+            .is_none());
+
+        // A share group counts once every member does
+        assert!(store
+            .find_subscriber("jobs/1", "pub", behind(&["w1", "w2"]))
+            .is_none());
+        let member = store.find_subscriber("jobs/1", "pub", behind(&["w1", "w2", "w3"]));
+        assert!(member.is_some_and(|m| m.starts_with('w')));
+    }
+
+    #[test]
+    fn test_shared_round_robin() {
+        let store = store(SharedSubscriptionStrategy::RoundRobin);
+        let mut got = receivers(&store, 3, Some("pub"));
+        got.sort();
+        assert_eq!(got, vec!["w1", "w2", "w3"]);
+    }
+
+    #[test]
+    fn test_shared_random() {
+        let store = store(SharedSubscriptionStrategy::Random);
+        let got = receivers(&store, 200, None);
+        for member in ["w1", "w2", "w3"] {
+            assert!(got.iter().any(|c| c == member));
+        }
+    }
+
+    #[test]
+    fn test_shared_sticky() {
+        let store = store(SharedSubscriptionStrategy::Sticky);
+        let first = receivers(&store, 1, Some("pub-a")).remove(0);
+        assert!(receivers(&store, 10, Some("pub-a"))
+            .iter()
+            .all(|c| *c == first));
+
+        // Other members keep their publishers when one leaves
+        let publishers: Vec<String> = (0..20).map(|i| format!("pub-{}", i)).collect();
+        let before: Vec<String> = publishers
+            .iter()
+            .map(|p| receivers(&store, 1, Some(p)).remove(0))
+            .collect();
+        assert!(store.unsubscribe("$share/workers/jobs/#", "w1"));
+        for (publisher, member) in publishers.iter().zip(&before) {
+            let now = receivers(&store, 1, Some(publisher)).remove(0);
+            assert_ne!(now, "w1");
+            if member != "w1" {
+                assert_eq!(&now, member);
+            }
+        }
+
+        // The callback variant picks the same member
+        let mut called = Vec::new();
+        store.matches_with_callback("jobs/1", Some("pub-a"), |sub| {
+            if sub.share_group.is_some() {
+                called.push(sub.client_id.to_string());
+            }
+        });
+        assert_eq!(called, [receivers(&store, 1, Some("pub-a")).remove(0)]);
+
+        // Messages without a publishing client (published by the server or
+        // bridged in) fall back to round-robin
+        let mut got = receivers(&store, 2, None);
+        got.sort();
+        assert_eq!(got, vec!["w2", "w3"]);
+    }
+}
@@ -0,0 +1,295 @@
+//! Topic schema: templates with typed levels
+//!
+//! A template is a topic name whose levels may be placeholders:
+//! - `{name}` (or `+`) - any non-empty level
+//! - `{name:int}` - an integer, optionally negative
+//! - `{name:hex}` - hexadecimal digits
+//! - `{name:uuid}` - a UUID in its hyphenated form
+//! - `{name:a|b|c}` - one of the listed values
+//! - `#` - as the last level, any number of levels (including none)
+//!
+//! Any other level must appear literally. A topic is valid if it matches
+//! any template of the schema.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+use crate::config::{TopicSchemaConfig, TopicSchemaMode};
+
+/// A template level
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Any,
+    Int,
+    Hex,
+    Uuid,
+    OneOf(Vec<String>),
+    Rest,
+}
+
+impl Segment {
+    fn parse(level: &str) -> Result<Self, String> {
+        if level == "+" {
+            return Ok(Segment::Any);
+        }
+        if level == "#" {
+            return Ok(Segment::Rest);
+        }
+        let Some(inner) = level.strip_prefix('{').and_then(|l| l.strip_suffix('}')) else {
+            if level.contains(['{', '}']) {
+                return Err(format!(
+                    "'{}': a placeholder must be a whole topic level",
+                    level
+                ));
+            }
+            return Ok(Segment::Literal(level.to_string()));
+        };
+        let (name, kind) = inner.split_once(':').unwrap_or((inner, "string"));
+        if name.is_empty() {
+            return Err(format!("'{}': placeholder without a name", level));
+        }
+        Ok(match kind {
+            "string" => Segment::Any,
+            "int" => Segment::Int,
+            "hex" => Segment::Hex,
+            "uuid" => Segment::Uuid,
+            values if values.contains('|') => {
+                let values: Vec<String> = values.split('|').map(String::from).collect();
+                if values.iter().any(|v| v.is_empty()) {
+                    return Err(format!("'{}': empty value in the list", level));
+                }
+                Segment::OneOf(values)
+            }
+            other => {
+                return Err(format!(
+                    "'{}': unknown type '{}' (expected string, int, hex, uuid or a|b|...)",
+                    level, other
+                ))
+            }
+        })
+    }
+
+    fn matches(&self, level: &str) -> bool {
+        match self {
+            Segment::Literal(literal) => literal == level,
+            Segment::Any => !level.is_empty(),
+            Segment::Int => {
+                let digits = level.strip_prefix('-').unwrap_or(level);
+                !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+            }
+            Segment::Hex => !level.is_empty() && level.bytes().all(|b| b.is_ascii_hexdigit()),
+            Segment::Uuid => {
+                level.len() == 36
+                    && level.bytes().enumerate().all(|(i, b)| match i {
+                        8 | 13 | 18 | 23 => b == b'-',
+                        _ => b.is_ascii_hexdigit(),
+                    })
+            }
+            Segment::OneOf(values) => values.iter().any(|v| v == level),
+            Segment::Rest => true,
+        }
+    }
+}
+
+/// A compiled template
+#[derive(Debug)]
+struct Template {
+    pattern: String,
+    description: Option<String>,
+    owner: Option<String>,
+    segments: Vec<Segment>,
+    /// Topics published that matched it
+    matched: AtomicU64,
+}
+
+impl Template {
+    fn parse(pattern: &str) -> Result<Vec<Segment>, String> {
+        if pattern.is_empty() {
+            return Err("empty template".to_string());
+        }
+        let segments = pattern
+            .split('/')
+            .map(Segment::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("template '{}': {}", pattern, e))?;
+        if segments[..segments.len() - 1].contains(&Segment::Rest) {
+            return Err(format!("template '{}': # must be the last level", pattern));
+        }
+        Ok(segments)
+    }
+
+    fn matches(&self, topic: &str) -> bool {
+        let mut levels = topic.split('/');
+        for segment in &self.segments {
+            if *segment == Segment::Rest {
+                return true;
+            }
+            match levels.next() {
+                Some(level) if segment.matches(level) => {}
+                _ => return false,
+            }
+        }
+        levels.next().is_none()
+    }
+}
+
+/// A declared template with how often it matched, as listed by the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateSummary {
+    pub pattern: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    pub matched: u64,
+}
+
+/// The schema as listed by the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaSummary {
+    pub mode: &'static str,
+    pub templates: Vec<TemplateSummary>,
+    /// Topics published that matched no template
+    pub violations: u64,
+}
+
+/// The declared topic namespace (`topic_schema`)
+#[derive(Debug, Default)]
+pub struct TopicSchema {
+    mode: TopicSchemaMode,
+    templates: Vec<Template>,
+    violations: AtomicU64,
+}
+
+impl TopicSchema {
+    /// Compile the templates of `config`
+    pub fn new(config: &TopicSchemaConfig) -> Result<Self, String> {
+        let templates = config
+            .templates
+            .iter()
+            .map(|template| {
+                Ok(Template {
+                    pattern: template.pattern.clone(),
+                    description: template.description.clone(),
+                    owner: template.owner.clone(),
+                    segments: Template::parse(&template.pattern)?,
+                    matched: AtomicU64::new(0),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            mode: config.mode,
+            templates,
+            violations: AtomicU64::new(0),
+        })
+    }
+
+    pub fn mode(&self) -> TopicSchemaMode {
+        self.mode
+    }
+
+    /// Check a published topic, counting the template it matched or the
+    /// violation; true if it is valid (or not checked)
+    pub fn check(&self, topic: &str) -> bool {
+        if self.mode == TopicSchemaMode::Off || topic.starts_with('$') {
+            return true;
+        }
+        match self.templates.iter().find(|t| t.matches(topic)) {
+            Some(template) => {
+                template.matched.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            None => {
+                self.violations.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    /// The templates with their counts
+    pub fn summary(&self) -> SchemaSummary {
+        SchemaSummary {
+            mode: match self.mode {
+                TopicSchemaMode::Off => "off",
+                TopicSchemaMode::Warn => "warn",
+                TopicSchemaMode::Reject => "reject",
+            },
+            templates: self
+                .templates
+                .iter()
+                .map(|t| TemplateSummary {
+                    pattern: t.pattern.clone(),
+                    description: t.description.clone(),
+                    owner: t.owner.clone(),
+                    matched: t.matched.load(Ordering::Relaxed),
+                })
+                .collect(),
+            violations: self.violations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TopicTemplateConfig;
+
+    fn schema(patterns: &[&str]) -> Result<TopicSchema, String> {
+        TopicSchema::new(&TopicSchemaConfig {
+            mode: TopicSchemaMode::Reject,
+            templates: patterns
+                .iter()
+                .map(|pattern| TopicTemplateConfig {
+                    pattern: pattern.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn test_schema_matching() {
+        let schema = schema(&[
+            "sensors/{site}/{device:int}/{metric:temp|humidity}",
+            "devices/{id:uuid}/#",
+            "fw/{hash:hex}",
+            "plain/+",
+        ])
+        .unwrap();
+
+        assert!(schema.check("sensors/berlin/42/temp"));
+        assert!(schema.check("sensors/berlin/-1/humidity"));
+        assert!(!schema.check("sensors/berlin/x42/temp"));
+        assert!(!schema.check("sensors/berlin/42/pressure"));
+        assert!(!schema.check("sensors//42/temp"));
+        assert!(!schema.check("sensors/berlin/42/temp/extra"));
+
+        assert!(schema.check("devices/123e4567-e89b-12d3-a456-426614174000"));
+        assert!(schema.check("devices/123e4567-e89b-12d3-a456-426614174000/state/led"));
+        assert!(!schema.check("devices/123e4567/state"));
+
+        assert!(schema.check("fw/deadBEEF"));
+        assert!(!schema.check("fw/xyz"));
+        assert!(schema.check("plain/anything"));
+
+        // System topics aren't checked
+        assert!(schema.check("$SYS/broker/uptime"));
+
+        let summary = schema.summary();
+        assert_eq!(summary.templates[0].matched, 2);
+        assert_eq!(summary.templates[1].matched, 2);
+        assert_eq!(summary.violations, 6);
+    }
+
+    #[test]
+    fn test_schema_errors() {
+        assert!(schema(&[""]).is_err());
+        assert!(schema(&["a/#/b"]).is_err());
+        assert!(schema(&["a/x{id}"]).is_err());
+        assert!(schema(&["a/{:int}"]).is_err());
+        assert!(schema(&["a/{id:float}"]).is_err());
+        assert!(schema(&["a/{kind:a||b}"]).is_err());
+    }
+}
@@ -268,6 +268,45 @@ impl<V> TopicTrie<V> {
             Self::for_each_recursive(child, callback);
         }
     }
+
+    /// Iterate over all values with the filter each is stored under
+    pub fn for_each_filter<F>(&self, mut callback: F)
+    where
+        F: FnMut(&str, &V),
+    {
+        let mut levels = Vec::new();
+        Self::for_each_filter_recursive(&self.root, &mut levels, &mut callback);
+    }
+
+    fn for_each_filter_recursive<'a, F>(
+        node: &'a TrieNode<V>,
+        levels: &mut Vec<&'a str>,
+        callback: &mut F,
+    ) where
+        F: FnMut(&str, &V),
+    {
+        if let Some(ref v) = node.value {
+            callback(&levels.join("/"), v);
+        }
+
+        if let Some(ref v) = node.multi_wildcard {
+            levels.push("#");
+            callback(&levels.join("/"), v);
+            levels.pop();
+        }
+
+        if let Some(ref child) = node.single_wildcard {
+            levels.push("+");
+            Self::for_each_filter_recursive(child, levels, callback);
+            levels.pop();
+        }
+
+        for (level, child) in &node.children {
+            levels.push(level);
+            Self::for_each_filter_recursive(child, levels, callback);
+            levels.pop();
+        }
+    }
 }
 
 impl<V> Default for TopicTrie<V> {
@@ -344,4 +383,30 @@ mod tests {
         trie.matches("test/topic", |v| matches.push(*v));
         assert!(matches.is_empty());
     }
+
+    #[test]
+    fn test_for_each_filter() {
+        let mut trie = TopicTrie::new();
+        for (i, filter) in ["a/b", "a/+/c", "#", "a/#", "/x", "a//b"]
+            .iter()
+            .enumerate()
+        {
+            trie.insert(filter, i);
+        }
+
+        let mut filters = Vec::new();
+        trie.for_each_filter(|filter, v| filters.push((filter.to_string(), *v)));
+        filters.sort();
+        assert_eq!(
+            filters,
+            vec![
+                ("#".to_string(), 2),
+                ("/x".to_string(), 4),
+                ("a/#".to_string(), 3),
+                ("a/+/c".to_string(), 1),
+                ("a//b".to_string(), 5),
+                ("a/b".to_string(), 0),
+            ]
+        );
+    }
 }
@@ -1,9 +1,15 @@
 //! Transport Layer
 //!
-//! Handles TCP and WebSocket connections with a unified interface.
+//! Handles TCP, Unix socket, WebSocket and QUIC connections with a unified interface.
 
+mod peer;
+mod quic;
+mod rewind;
 mod websocket;
 
+pub use peer::PeerAddr;
+pub use quic::QuicStream;
+pub use rewind::Rewind;
 pub use websocket::WsStream;
 
 use tokio::net::TcpStream;
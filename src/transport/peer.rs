@@ -0,0 +1,120 @@
+//! Peer Addresses
+//!
+//! Connections arrive over TCP (plain, TLS, WebSocket) or Unix domain
+//! sockets, and a PROXY header can report either kind of source. `PeerAddr`
+//! keeps the distinction instead of squeezing Unix peers into a placeholder
+//! `SocketAddr`.
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Address of a connected client
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PeerAddr {
+    /// TCP peer (also used for TLS and WebSocket)
+    Tcp(SocketAddr),
+    /// Unix domain socket peer (`None` for unnamed or abstract sockets)
+    Unix(Option<Arc<Path>>),
+}
+
+impl PeerAddr {
+    /// IP address of a TCP peer
+    ///
+    /// IPv4 clients accepted on a dual-stack socket (or reported by a TCP6
+    /// PROXY header) appear as v4-mapped IPv6 addresses; they're returned as
+    /// IPv4 so bans, limits and hooks see one address per client.
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.socket_addr().map(|addr| addr.ip().to_canonical())
+    }
+
+    /// Socket address of a TCP peer
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            PeerAddr::Tcp(addr) => Some(*addr),
+            PeerAddr::Unix(_) => None,
+        }
+    }
+
+    /// Whether the peer is on a Unix domain socket
+    pub fn is_unix(&self) -> bool {
+        matches!(self, PeerAddr::Unix(_))
+    }
+
+    /// Path of a Unix domain socket peer
+    pub fn unix_path(&self) -> Option<&Path> {
+        match self {
+            PeerAddr::Unix(path) => path.as_deref(),
+            PeerAddr::Tcp(_) => None,
+        }
+    }
+
+    /// Unix peer from a NUL-padded `sun_path` (as carried in PROXY v2)
+    ///
+    /// Abstract socket names (leading NUL) and non-UTF-8 paths are treated
+    /// as unnamed.
+    pub fn from_unix_bytes(raw: &[u8]) -> Self {
+        let len = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+        let path = std::str::from_utf8(&raw[..len])
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(|path| Arc::from(Path::new(path)));
+        PeerAddr::Unix(path)
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddr::Tcp(addr)
+    }
+}
+
+#[cfg(unix)]
+impl From<tokio::net::unix::SocketAddr> for PeerAddr {
+    fn from(addr: tokio::net::unix::SocketAddr) -> Self {
+        PeerAddr::Unix(addr.as_pathname().map(Arc::from))
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => write!(f, "{}", addr),
+            PeerAddr::Unix(Some(path)) => write!(f, "unix:{}", path.display()),
+            PeerAddr::Unix(None) => write!(f, "unix:(unnamed)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_addr() {
+        let tcp = PeerAddr::from("10.0.0.1:1883".parse::<SocketAddr>().unwrap());
+        assert_eq!(tcp.ip(), Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(tcp.to_string(), "10.0.0.1:1883");
+
+        let path = b"/run/sidecar.sock";
+        let mut raw = [0u8; 108];
+        raw[..path.len()].copy_from_slice(path);
+        let unix = PeerAddr::from_unix_bytes(&raw);
+        assert!(unix.is_unix());
+        assert_eq!(unix.ip(), None);
+
+        let mapped = PeerAddr::from("[::ffff:10.0.0.1]:1883".parse::<SocketAddr>().unwrap());
+        assert_eq!(mapped.ip(), Some("10.0.0.1".parse().unwrap()));
+        // The socket address keeps its family (for PROXY headers we emit)
+        assert!(mapped.socket_addr().unwrap().is_ipv6());
+        let v6 = PeerAddr::from("[2001:db8::1]:1883".parse::<SocketAddr>().unwrap());
+        assert_eq!(v6.ip(), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(unix.unix_path(), Some(Path::new("/run/sidecar.sock")));
+        assert_eq!(unix.to_string(), "unix:/run/sidecar.sock");
+
+        raw[0] = 0;
+        assert_eq!(PeerAddr::from_unix_bytes(&raw), PeerAddr::Unix(None));
+        assert_eq!(PeerAddr::Unix(None).to_string(), "unix:(unnamed)");
+    }
+}
@@ -13,19 +13,25 @@ use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{Sink, Stream, StreamExt};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::TcpStream;
-use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::tungstenite::protocol::{Message, WebSocketConfig};
 use tokio_tungstenite::WebSocketStream;
 
+/// WebSocket subprotocols negotiated for MQTT
+const MQTT_SUBPROTOCOLS: &[&str] = &["mqtt", "mqttv3.1", "mqttv5"];
+
+/// WebSocket subprotocols negotiated for STOMP
+const STOMP_SUBPROTOCOLS: &[&str] = &["v12.stomp", "v11.stomp", "v10.stomp"];
+
 /// WebSocket stream wrapper that implements AsyncRead and AsyncWrite
 ///
 /// MQTT over WebSocket uses binary frames to transport MQTT packets.
 /// This wrapper buffers incoming binary messages and presents them
 /// as a continuous byte stream.
-pub struct WsStream {
+pub struct WsStream<S = TcpStream> {
     /// Split sink for writing
-    sink: SplitSink<WebSocketStream<TcpStream>, Message>,
+    sink: SplitSink<WebSocketStream<S>, Message>,
     /// Split stream for reading
-    stream: SplitStream<WebSocketStream<TcpStream>>,
+    stream: SplitStream<WebSocketStream<S>>,
     /// Read buffer for incomplete reads
     read_buffer: BytesMut,
     /// Write buffer for batching small writes
@@ -34,11 +40,13 @@ pub struct WsStream {
     pending_messages: VecDeque<Vec<u8>>,
     /// Whether the stream has been closed
     closed: bool,
+    /// Treat text messages as data (STOMP clients send text frames)
+    accept_text: bool,
 }
 
-impl WsStream {
+impl<S: AsyncRead + AsyncWrite + Unpin> WsStream<S> {
     /// Create a new WebSocket stream wrapper
-    pub fn new(ws: WebSocketStream<TcpStream>) -> Self {
+    pub fn new(ws: WebSocketStream<S>) -> Self {
         let (sink, stream) = ws.split();
         Self {
             sink,
@@ -47,23 +55,57 @@ impl WsStream {
             write_buffer: BytesMut::with_capacity(4096),
             pending_messages: VecDeque::new(),
             closed: false,
+            accept_text: false,
         }
     }
 
     /// Accept a WebSocket connection with MQTT subprotocol
-    pub async fn accept(stream: TcpStream) -> Result<Self, io::Error> {
+    pub async fn accept(stream: S) -> Result<Self, io::Error> {
         Self::accept_with_path(stream, "/mqtt").await
     }
 
     /// Accept a WebSocket connection with MQTT subprotocol and path validation
-    pub async fn accept_with_path(
-        stream: TcpStream,
+    pub async fn accept_with_path(stream: S, expected_path: &str) -> Result<Self, io::Error> {
+        Self::accept_handshake(stream, expected_path, MQTT_SUBPROTOCOLS, None).await
+    }
+
+    /// Accept a WebSocket connection with MQTT subprotocol, path validation
+    /// and an optional cap on incoming frame size (tungstenite's default
+    /// of 16 MiB otherwise)
+    pub async fn accept_with_limits(
+        stream: S,
         expected_path: &str,
+        max_frame_size: Option<usize>,
+    ) -> Result<Self, io::Error> {
+        let config = max_frame_size.map(|size| WebSocketConfig {
+            max_frame_size: Some(size),
+            ..Default::default()
+        });
+        Self::accept_handshake(stream, expected_path, MQTT_SUBPROTOCOLS, config).await
+    }
+
+    /// Accept a STOMP over WebSocket connection with path validation
+    ///
+    /// Text and binary messages are both read as STOMP data.
+    pub async fn accept_stomp(stream: S, expected_path: &str) -> Result<Self, io::Error> {
+        let mut ws =
+            Self::accept_handshake(stream, expected_path, STOMP_SUBPROTOCOLS, None).await?;
+        ws.accept_text = true;
+        Ok(ws)
+    }
+
+    // The handshake callback signature (and its ErrorResponse) is dictated by tungstenite
+    #[allow(clippy::result_large_err)]
+    async fn accept_handshake(
+        stream: S,
+        expected_path: &str,
+        subprotocols: &'static [&'static str],
+        config: Option<WebSocketConfig>,
     ) -> Result<Self, io::Error> {
         let expected_path = expected_path.to_string();
 
-        // Custom callback to check for MQTT subprotocol and validate path
-        let ws = tokio_tungstenite::accept_hdr_async(stream, move |req: &tokio_tungstenite::tungstenite::handshake::server::Request, mut response: tokio_tungstenite::tungstenite::handshake::server::Response| {
+        // Custom callback to negotiate the subprotocol and validate path
+        let ws = tokio_tungstenite::accept_hdr_async_with_config(stream, move |req: &tokio_tungstenite::tungstenite::handshake::server::Request, mut response: tokio_tungstenite::tungstenite::handshake::server::Response| {
             // Validate request path
             let request_path = req.uri().path();
             if request_path != expected_path {
@@ -73,11 +115,11 @@ impl WsStream {
                 ))));
             }
 
-            // Check for MQTT subprotocol
+            // Check for a supported subprotocol
             if let Some(protocols) = req.headers().get("sec-websocket-protocol") {
                 if let Ok(protocols_str) = protocols.to_str() {
                     for protocol in protocols_str.split(',').map(|s| s.trim()) {
-                        if protocol == "mqtt" || protocol == "mqttv3.1" || protocol == "mqttv5" {
+                        if subprotocols.contains(&protocol) {
                             response.headers_mut().insert(
                                 "sec-websocket-protocol",
                                 protocol.parse().unwrap(),
@@ -88,7 +130,7 @@ impl WsStream {
                 }
             }
             Ok(response)
-        })
+        }, config)
         .await
         .map_err(io::Error::other)?;
 
@@ -96,7 +138,7 @@ impl WsStream {
     }
 }
 
-impl AsyncRead for WsStream {
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for WsStream<S> {
     fn poll_read(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
@@ -142,6 +184,15 @@ impl AsyncRead for WsStream {
                         self.closed = true;
                         Poll::Ready(Ok(()))
                     }
+                    Message::Text(text) if self.accept_text => {
+                        let data = text.into_bytes();
+                        let to_copy = std::cmp::min(buf.remaining(), data.len());
+                        buf.put_slice(&data[..to_copy]);
+                        if to_copy < data.len() {
+                            self.read_buffer.extend_from_slice(&data[to_copy..]);
+                        }
+                        Poll::Ready(Ok(()))
+                    }
                     Message::Ping(_) | Message::Pong(_) | Message::Text(_) => {
                         // Ignore non-binary messages, try again
                         cx.waker().wake_by_ref();
@@ -164,7 +215,7 @@ impl AsyncRead for WsStream {
     }
 }
 
-impl AsyncWrite for WsStream {
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for WsStream<S> {
     fn poll_write(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
@@ -215,3 +266,34 @@ impl AsyncWrite for WsStream {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::SinkExt;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_max_frame_size() {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let server = tokio::spawn(async move {
+            let mut ws = WsStream::accept_with_limits(server, "/mqtt", Some(16))
+                .await
+                .unwrap();
+            let mut buf = [0u8; 64];
+            let n = ws.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"small");
+            assert!(ws.read(&mut buf).await.is_err());
+        });
+
+        let (mut client, _) = tokio_tungstenite::client_async("ws://localhost/mqtt", client)
+            .await
+            .unwrap();
+        client
+            .send(Message::Binary(b"small".to_vec()))
+            .await
+            .unwrap();
+        client.send(Message::Binary(vec![0; 64])).await.unwrap();
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+//! User Property Index
+//!
+//! Rules, ACL publish rules and bridge forwards can require MQTT 5 user
+//! properties on a message (see [`UserPropertyMatch`]). Checking many
+//! conditions against a message by scanning its property list each time
+//! costs conditions × properties comparisons, so a message is indexed once
+//! (its properties sorted by name) and every condition is then a binary
+//! search.
+
+use crate::config::UserPropertyMatch;
+
+/// A message's user properties, sorted by name for lookups
+#[derive(Debug, Clone, Default)]
+pub struct PropertyIndex<'a> {
+    /// (name, value), sorted by name; equal names keep message order
+    entries: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> PropertyIndex<'a> {
+    pub fn new(properties: &'a [(String, String)]) -> Self {
+        let mut entries: Vec<_> = properties
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        entries.sort_by_key(|&(name, _)| name);
+        Self { entries }
+    }
+
+    /// Values of the properties named `name`, in message order
+    pub fn values<'s>(&'s self, name: &'s str) -> impl Iterator<Item = &'a str> + 's {
+        let start = self.entries.partition_point(|&(n, _)| n < name);
+        self.entries[start..]
+            .iter()
+            .take_while(move |&&(n, _)| n == name)
+            .map(|&(_, value)| value)
+    }
+
+    /// Whether the message satisfies one condition
+    pub fn matches(&self, condition: &UserPropertyMatch) -> bool {
+        let mut values = self.values(&condition.name);
+        match (&condition.value, &condition.prefix) {
+            (Some(expected), _) => values.any(|v| v == expected),
+            (None, Some(prefix)) => values.any(|v| v.starts_with(prefix.as_str())),
+            (None, None) => values.next().is_some(),
+        }
+    }
+
+    /// Whether the message satisfies every condition (true for none)
+    pub fn matches_all(&self, conditions: &[UserPropertyMatch]) -> bool {
+        conditions.iter().all(|condition| self.matches(condition))
+    }
+}
+
+/// Check conditions without keeping an index around; conditions-free
+/// checks skip indexing altogether
+pub fn matches_all(properties: &[(String, String)], conditions: &[UserPropertyMatch]) -> bool {
+    conditions.is_empty() || PropertyIndex::new(properties).matches_all(conditions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_property_index() {
+        let properties = properties(&[
+            ("tenant", "acme-eu"),
+            ("env", "staging"),
+            ("env", "prod"),
+            ("trace", ""),
+        ]);
+        let index = PropertyIndex::new(&properties);
+        assert_eq!(index.values("env").collect::<Vec<_>>(), ["staging", "prod"]);
+        assert_eq!(index.values("region").count(), 0);
+
+        // Any property of the name may satisfy a condition
+        assert!(index.matches(&UserPropertyMatch::exact("env", "prod")));
+        assert!(!index.matches(&UserPropertyMatch::exact("env", "dev")));
+        assert!(!index.matches(&UserPropertyMatch::exact("Env", "prod")));
+        assert!(index.matches(&UserPropertyMatch::prefix("tenant", "acme-")));
+        assert!(!index.matches(&UserPropertyMatch::prefix("tenant", "globex-")));
+        assert!(index.matches(&UserPropertyMatch {
+            name: "trace".to_string(),
+            ..Default::default()
+        }));
+        assert!(!index.matches(&UserPropertyMatch {
+            name: "region".to_string(),
+            ..Default::default()
+        }));
+
+        assert!(index.matches_all(&[]));
+        assert!(index.matches_all(&[
+            UserPropertyMatch::exact("env", "prod"),
+            UserPropertyMatch::prefix("tenant", "acme")
+        ]));
+        assert!(!index.matches_all(&[
+            UserPropertyMatch::exact("env", "prod"),
+            UserPropertyMatch::exact("tenant", "acme")
+        ]));
+
+        assert!(matches_all(&[], &[]));
+        assert!(!matches_all(
+            &[],
+            &[UserPropertyMatch::exact("env", "prod")]
+        ));
+    }
+}
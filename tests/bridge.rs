@@ -8,17 +8,28 @@ use std::time::Duration;
 
 use bytes::{Bytes, BytesMut};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::time::timeout;
 
-use vibemq::bridge::{BridgeConfig, ForwardDirection, ForwardRule, LoopPrevention};
+use vibemq::bridge::{
+    BridgeConfig, BridgeHealthConfig, BridgeProxyConfig, EgressProxyConfig, EgressProxyType,
+    ForwardDirection, ForwardRule, LoopPrevention, BRIDGE_ENCODING_PROPERTY,
+};
 use vibemq::broker::{Broker, BrokerConfig};
 use vibemq::codec::{Decoder, Encoder};
-use vibemq::config::ProxyProtocolConfig;
+use vibemq::config::{
+    AuthMetadataField, BacklogDrainConfig, BatchConfig, CompressionCodec, DelayedConfig,
+    ErrorDetail, HandoverConfig, HealthConfig, ListenerCapabilities, MemoryPressureConfig,
+    PriorityConfig, ProxyProtocolConfig, PublishRateConfig, PublisherBackpressureConfig,
+    QueueOverflow, QuotaConfig, RetainedCacheConfig, RetainedFeedConfig, SequenceConfig,
+    SessionExpiryEventsConfig, SharedSubscriptionStrategy, ShutdownConfig, TopicSchemaConfig,
+    TopicTreeConfig, TransactionConfig,
+};
 use vibemq::protocol::{
-    Connect, Packet, Properties, ProtocolVersion, Publish, QoS, ReasonCode, Subscribe,
+    ConnAck, Connect, Packet, Properties, ProtocolVersion, Publish, QoS, ReasonCode, Subscribe,
     Subscription, SubscriptionOptions,
 };
+use vibemq::remote::RemotePeerStatus;
 
 // Atomic port counter to avoid port conflicts between tests
 static PORT_COUNTER: AtomicU16 = AtomicU16::new(21000);
@@ -31,34 +42,86 @@ fn next_port() -> u16 {
 fn test_broker_config(port: u16) -> BrokerConfig {
     BrokerConfig {
         bind_addr: SocketAddr::from(([127, 0, 0, 1], port)),
+        extra_bind_addrs: Vec::new(),
+        ipv6_only: None,
         tls_bind_addr: None,
         tls_config: None,
         ws_bind_addr: None,
+        wss_bind_addr: None,
+        quic_bind_addr: None,
+        quic: Default::default(),
         ws_path: "/mqtt".to_string(),
+        ws_max_frame_size: None,
+        unix_bind_path: None,
         max_connections: 100,
         max_packet_size: 1024 * 1024,
         default_keep_alive: 60,
         max_keep_alive: 300,
         session_expiry_check_interval: Duration::from_secs(60),
+        session_compress_idle_after: None,
+        max_session_expiry: None,
         receive_maximum: 65535,
         max_qos: QoS::ExactlyOnce,
         retain_available: true,
         wildcard_subscription_available: true,
         subscription_identifiers_available: true,
         shared_subscriptions_available: true,
+        shared_subscription_strategy: SharedSubscriptionStrategy::default(),
         max_topic_alias: 65535,
         num_workers: 2,
         sys_topics_enabled: false,
         sys_topics_interval: Duration::from_secs(10),
+        max_local_hops: 8,
+        routing_ids: false,
+        bridge_compression: Vec::new(),
         max_inflight: 32,
         max_queued_messages: 1000,
+        max_queued_bytes: 0,
+        queue_qos0: true,
+        queue_overflow: QueueOverflow::default(),
         max_awaiting_rel: 100,
         retry_interval: Duration::from_secs(30),
+        ack_alarm_after: None,
         outbound_channel_capacity: 1024,
         max_topic_levels: 0,
+        hibernate_after: None,
         proxy_protocol: ProxyProtocolConfig::default(),
         tls_proxy_protocol: ProxyProtocolConfig::default(),
         ws_proxy_protocol: ProxyProtocolConfig::default(),
+        unix_proxy_protocol: ProxyProtocolConfig::default(),
+        allow_mqtt31: true,
+        tls_allow_mqtt31: true,
+        ws_allow_mqtt31: true,
+        listener_max_qos: None,
+        tls_listener_max_qos: None,
+        ws_listener_max_qos: None,
+        capabilities: ListenerCapabilities::default(),
+        tls_capabilities: ListenerCapabilities::default(),
+        ws_capabilities: ListenerCapabilities::default(),
+        error_detail: ErrorDetail::default(),
+        tls_error_detail: ErrorDetail::default(),
+        ws_error_detail: ErrorDetail::default(),
+        reason_map: Default::default(),
+        batch: BatchConfig::default(),
+        transaction: TransactionConfig::default(),
+        handover: HandoverConfig::default(),
+        retained_feed: RetainedFeedConfig::default(),
+        retained_cache: RetainedCacheConfig::default(),
+        delayed: DelayedConfig::default(),
+        session_expiry_events: SessionExpiryEventsConfig::default(),
+        backlog_drain: BacklogDrainConfig::default(),
+        health: HealthConfig::default(),
+        topic_tree: TopicTreeConfig::default(),
+        topic_schema: TopicSchemaConfig::default(),
+        sequence: SequenceConfig::default(),
+        priority: PriorityConfig::default(),
+        publish_rate: PublishRateConfig::default(),
+        quota: QuotaConfig::default(),
+        memory_pressure: MemoryPressureConfig::default(),
+        publisher_backpressure: PublisherBackpressureConfig::default(),
+        listener_limits: Default::default(),
+        auth_metadata_fields: AuthMetadataField::ALL.to_vec(),
+        shutdown: ShutdownConfig::default(),
     }
 }
 
@@ -205,6 +268,7 @@ async fn test_bridge_manager_creation() {
             direction: ForwardDirection::Out,
             qos: 1,
             retain: true,
+            user_properties: vec![],
         }],
     )];
 
@@ -253,11 +317,15 @@ async fn test_broker_events_include_payload() {
                     payload,
                     qos,
                     retain,
+                    origin,
+                    user_properties,
                 })) => {
                     assert_eq!(topic, "test/topic");
                     assert_eq!(&payload[..], b"hello bridge");
                     assert_eq!(qos, QoS::AtMostOnce);
                     assert!(!retain);
+                    assert_eq!(&*origin.unwrap().client_id, "event-test-client");
+                    assert!(user_properties.is_empty());
                 }
                 other => panic!("Expected MessagePublished event, got {:?}", other),
             }
@@ -267,11 +335,15 @@ async fn test_broker_events_include_payload() {
             payload,
             qos,
             retain,
+            origin,
+            user_properties,
         })) => {
             assert_eq!(topic, "test/topic");
             assert_eq!(&payload[..], b"hello bridge");
             assert_eq!(qos, QoS::AtMostOnce);
             assert!(!retain);
+            assert_eq!(&*origin.unwrap().client_id, "event-test-client");
+            assert!(user_properties.is_empty());
         }
         other => panic!("Expected event, got {:?}", other),
     }
@@ -296,6 +368,7 @@ async fn test_inbound_callback_routing() {
             direction: ForwardDirection::In,
             qos: 1,
             retain: true,
+            user_properties: vec![],
         }],
     )];
 
@@ -354,6 +427,7 @@ async fn test_two_broker_bridge_outbound() {
             direction: ForwardDirection::Out,
             qos: 1,
             retain: true,
+            user_properties: vec![],
         }],
     )];
 
@@ -398,6 +472,231 @@ async fn test_two_broker_bridge_outbound() {
     broker2_handle.abort();
 }
 
+/// A bridge with a PROXY header configured can reach a PROXY-only listener
+#[tokio::test]
+async fn test_bridge_sends_proxy_header() {
+    let broker1_port = next_port();
+    let broker2_port = next_port();
+
+    // Remote broker rejects connections without a PROXY header
+    let mut config2 = test_broker_config(broker2_port);
+    config2.proxy_protocol = ProxyProtocolConfig {
+        enabled: true,
+        timeout: Duration::from_secs(1),
+        ..Default::default()
+    };
+    let broker2 = Broker::new(config2);
+    let mut events_rx = broker2.subscribe_events();
+    let broker2_handle = tokio::spawn(async move {
+        let _ = broker2.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut bridge = test_bridge_config(
+        "proxied",
+        broker2_port,
+        vec![ForwardRule {
+            local_topic: "sensors/#".to_string(),
+            remote_topic: "sensors/#".to_string(),
+            direction: ForwardDirection::Out,
+            qos: 1,
+            retain: false,
+            user_properties: vec![],
+        }],
+    );
+    bridge.proxy_protocol = Some(BridgeProxyConfig {
+        source_address: Some("203.0.113.7:1883".parse().unwrap()),
+        unique_id: Some("edge-1".to_string()),
+        ..Default::default()
+    });
+
+    let mut broker1 = Broker::new(test_broker_config(broker1_port));
+    let bridge_manager = broker1.create_bridge_manager(vec![bridge]);
+    broker1.set_bridge_manager(bridge_manager);
+    let broker1_handle = tokio::spawn(async move {
+        let _ = broker1.run().await;
+    });
+
+    let connected = timeout(Duration::from_secs(5), async {
+        loop {
+            if let Ok(vibemq::broker::BrokerEvent::ClientConnected { client_id, .. }) =
+                events_rx.recv().await
+            {
+                if &*client_id == "bridge-proxied" {
+                    return;
+                }
+            }
+        }
+    })
+    .await;
+    assert!(connected.is_ok(), "bridge should connect through PROXY");
+
+    broker1_handle.abort();
+    broker2_handle.abort();
+}
+
+/// A bridge with `forward_origin` tags messages with the local publisher
+#[tokio::test]
+async fn test_bridge_forwards_origin() {
+    let broker1_port = next_port();
+    let broker2_port = next_port();
+
+    let broker2 = Broker::new(test_broker_config(broker2_port));
+    let mut events_rx = broker2.subscribe_events();
+    let broker2_handle = tokio::spawn(async move {
+        let _ = broker2.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut bridge = test_bridge_config(
+        "origin",
+        broker2_port,
+        vec![ForwardRule {
+            local_topic: "sensors/#".to_string(),
+            remote_topic: "sensors/#".to_string(),
+            direction: ForwardDirection::Out,
+            qos: 1,
+            retain: false,
+            user_properties: vec![],
+        }],
+    );
+    bridge.forward_origin = true;
+
+    let mut broker1 = Broker::new(test_broker_config(broker1_port));
+    let bridge_manager = broker1.create_bridge_manager(vec![bridge]);
+    broker1.set_bridge_manager(bridge_manager);
+    let broker1_handle = tokio::spawn(async move {
+        let _ = broker1.run().await;
+    });
+
+    let connected = timeout(Duration::from_secs(5), async {
+        loop {
+            if let Ok(vibemq::broker::BrokerEvent::ClientConnected { client_id, .. }) =
+                events_rx.recv().await
+            {
+                if &*client_id == "bridge-origin" {
+                    return;
+                }
+            }
+        }
+    })
+    .await;
+    assert!(connected.is_ok(), "bridge should connect");
+
+    let addr1 = SocketAddr::from(([127, 0, 0, 1], broker1_port));
+    let addr2 = SocketAddr::from(([127, 0, 0, 1], broker2_port));
+
+    let mut subscriber = TestClient::connect(addr2, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("remote-subscriber").await;
+    subscriber.subscribe(1, "sensors/#", QoS::AtMostOnce).await;
+
+    let mut publisher = TestClient::connect(addr1, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("local-publisher").await;
+    publisher
+        .publish("sensors/temp", b"21", QoS::AtMostOnce, false)
+        .await;
+
+    match subscriber.recv().await {
+        Some(Packet::Publish(publish)) => {
+            let props = &publish.properties.user_properties;
+            let get = |key: &str| {
+                props
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.as_str())
+            };
+            assert_eq!(get("x-forwarded-client-id"), Some("local-publisher"));
+            assert_eq!(get("x-forwarded-for"), Some("127.0.0.1"));
+            assert!(get("x-forwarded-port").is_some());
+        }
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    broker1_handle.abort();
+    broker2_handle.abort();
+}
+
+/// A bridge to a broker accepting compression sends large payloads
+/// compressed, and subscribers on the remote get them back unpacked
+#[tokio::test]
+async fn test_bridge_compression() {
+    let broker1_port = next_port();
+    let broker2_port = next_port();
+
+    let mut config2 = test_broker_config(broker2_port);
+    config2.bridge_compression = vec![CompressionCodec::Lz4];
+    let broker2 = Broker::new(config2);
+    let mut events_rx = broker2.subscribe_events();
+    let broker2_handle = tokio::spawn(async move {
+        let _ = broker2.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut bridge = test_bridge_config(
+        "compressed",
+        broker2_port,
+        vec![ForwardRule {
+            local_topic: "sensors/#".to_string(),
+            remote_topic: "sensors/#".to_string(),
+            direction: ForwardDirection::Out,
+            qos: 1,
+            retain: false,
+            user_properties: vec![],
+        }],
+    );
+    bridge.compression.codecs = vec![CompressionCodec::Zstd, CompressionCodec::Lz4];
+
+    let mut broker1 = Broker::new(test_broker_config(broker1_port));
+    let bridge_manager = broker1.create_bridge_manager(vec![bridge]);
+    broker1.set_bridge_manager(bridge_manager);
+    let broker1_handle = tokio::spawn(async move {
+        let _ = broker1.run().await;
+    });
+
+    let connected = timeout(Duration::from_secs(5), async {
+        loop {
+            if let Ok(vibemq::broker::BrokerEvent::ClientConnected { client_id, .. }) =
+                events_rx.recv().await
+            {
+                if &*client_id == "bridge-compressed" {
+                    return;
+                }
+            }
+        }
+    })
+    .await;
+    assert!(connected.is_ok(), "bridge should connect");
+
+    let addr1 = SocketAddr::from(([127, 0, 0, 1], broker1_port));
+    let addr2 = SocketAddr::from(([127, 0, 0, 1], broker2_port));
+
+    let mut subscriber = TestClient::connect(addr2, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("remote-subscriber").await;
+    subscriber.subscribe(1, "sensors/#", QoS::AtMostOnce).await;
+
+    let payload = b"{\"sensor\":\"temp\",\"value\":21.5}".repeat(64);
+    let mut publisher = TestClient::connect(addr1, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("local-publisher").await;
+    publisher
+        .publish("sensors/temp", &payload, QoS::AtMostOnce, false)
+        .await;
+
+    match subscriber.recv().await {
+        Some(Packet::Publish(publish)) => {
+            assert_eq!(&publish.payload[..], &payload[..]);
+            assert!(publish
+                .properties
+                .user_properties
+                .iter()
+                .all(|(k, _)| k != BRIDGE_ENCODING_PROPERTY));
+        }
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    broker1_handle.abort();
+    broker2_handle.abort();
+}
+
 // =============================================================================
 // Loop Prevention Tests
 // =============================================================================
@@ -486,6 +785,7 @@ async fn test_bridge_manager_status() {
                 direction: ForwardDirection::Out,
                 qos: 1,
                 retain: true,
+                user_properties: vec![],
             }],
         ),
         test_bridge_config(
@@ -497,6 +797,7 @@ async fn test_bridge_manager_status() {
                 direction: ForwardDirection::Out,
                 qos: 1,
                 retain: true,
+                user_properties: vec![],
             }],
         ),
     ];
@@ -510,3 +811,352 @@ async fn test_bridge_manager_status() {
     assert!(status.iter().any(|(name, _)| name == "bridge1"));
     assert!(status.iter().any(|(name, _)| name == "bridge2"));
 }
+
+/// An unreachable primary address fails over to the next configured one,
+/// and the manager reports which endpoint is in use
+#[tokio::test]
+async fn test_bridge_endpoint_failover() {
+    let dead_port = next_port();
+    let remote_port = next_port();
+
+    let remote = Broker::new(test_broker_config(remote_port));
+    let remote_handle = tokio::spawn(async move {
+        let _ = remote.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let local = Broker::new(test_broker_config(next_port()));
+    let bridge_config = BridgeConfig {
+        failover_addresses: vec![format!("localhost:{}", remote_port)],
+        ..test_bridge_config("failover", dead_port, Vec::new())
+    };
+    let bridge_manager = local.create_bridge_manager(vec![bridge_config]);
+
+    let endpoint = timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some((_, Some(endpoint))) = bridge_manager.endpoints().pop() {
+                return endpoint;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("bridge should fail over to the second address");
+
+    assert_eq!(endpoint.address, format!("localhost:{}", remote_port));
+    assert_eq!(endpoint.addr.port(), remote_port);
+    assert!(endpoint.addr.ip().is_loopback());
+    assert_eq!(bridge_manager.connected_count(), 1);
+
+    remote_handle.abort();
+}
+
+/// Read one packet from a raw connection, buffering partial reads
+async fn read_raw_packet(
+    stream: &mut TcpStream,
+    decoder: &mut Decoder,
+    buf: &mut BytesMut,
+) -> Packet {
+    loop {
+        if let Some((packet, consumed)) = decoder.decode(buf).expect("decode") {
+            let _ = buf.split_to(consumed);
+            return packet;
+        }
+        let n = timeout(Duration::from_secs(5), stream.read_buf(buf))
+            .await
+            .expect("timed out waiting for packet")
+            .expect("read");
+        assert!(n > 0, "connection closed");
+    }
+}
+
+/// Accept a bridge connection on a mock remote broker and answer its CONNECT
+async fn accept_bridge(
+    listener: &TcpListener,
+    session_present: bool,
+) -> (TcpStream, Decoder, BytesMut, Box<Connect>) {
+    let (mut stream, _) = timeout(Duration::from_secs(5), listener.accept())
+        .await
+        .expect("bridge should connect")
+        .unwrap();
+    let mut decoder = Decoder::new();
+    let mut buf = BytesMut::new();
+    let connect = match read_raw_packet(&mut stream, &mut decoder, &mut buf).await {
+        Packet::Connect(connect) => connect,
+        other => panic!("Expected CONNECT, got {:?}", other),
+    };
+    decoder.set_protocol_version(ProtocolVersion::V5);
+
+    let mut out = BytesMut::new();
+    Encoder::new(ProtocolVersion::V5)
+        .encode(
+            &Packet::ConnAck(ConnAck {
+                session_present,
+                reason_code: ReasonCode::Success,
+                properties: Properties::default(),
+            }),
+            &mut out,
+        )
+        .unwrap();
+    stream.write_all(&out).await.unwrap();
+    (stream, decoder, buf, connect)
+}
+
+/// Pausing disconnects non-critical bridges until they are resumed
+#[tokio::test]
+async fn test_bridge_pause() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let remote_port = listener.local_addr().unwrap().port();
+
+    let local = Broker::new(test_broker_config(next_port()));
+    let critical = BridgeConfig {
+        critical: true,
+        ..test_bridge_config("critical", next_port(), Vec::new())
+    };
+    let bridge_manager = local.create_bridge_manager(vec![
+        test_bridge_config("pausable", remote_port, Vec::new()),
+        critical,
+    ]);
+    let (mut stream, mut decoder, mut buf, _) = accept_bridge(&listener, false).await;
+
+    assert_eq!(
+        bridge_manager.set_noncritical_paused(true),
+        vec!["pausable"]
+    );
+    assert!(bridge_manager.set_noncritical_paused(true).is_empty());
+    loop {
+        match read_raw_packet(&mut stream, &mut decoder, &mut buf).await {
+            Packet::Disconnect(_) => break,
+            Packet::Subscribe(_) | Packet::PingReq => continue,
+            other => panic!("Expected DISCONNECT, got {:?}", other),
+        }
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(bridge_manager.paused(), vec!["pausable"]);
+    assert!(bridge_manager
+        .status()
+        .contains(&("pausable".to_string(), RemotePeerStatus::Paused)));
+
+    // Resumed, it connects again
+    assert_eq!(
+        bridge_manager.set_noncritical_paused(false),
+        vec!["pausable"]
+    );
+    accept_bridge(&listener, false).await;
+    assert!(bridge_manager.paused().is_empty());
+}
+
+/// A publish left unacknowledged by a dropped connection is retransmitted
+/// once the bridge has resumed its session
+#[tokio::test]
+async fn test_bridge_session_resumption() {
+    let remote_port = next_port();
+    let listener = TcpListener::bind(("127.0.0.1", remote_port)).await.unwrap();
+
+    let local_port = next_port();
+    let bridge = BridgeConfig {
+        clean_start: false,
+        session_expiry: Duration::from_secs(3600),
+        ..test_bridge_config(
+            "resume",
+            remote_port,
+            vec![ForwardRule {
+                local_topic: "sensors/#".to_string(),
+                remote_topic: "edge/sensors/#".to_string(),
+                direction: ForwardDirection::Out,
+                qos: 1,
+                retain: false,
+                user_properties: vec![],
+            }],
+        )
+    };
+    let mut local = Broker::new(test_broker_config(local_port));
+    let bridge_manager = local.create_bridge_manager(vec![bridge]);
+    local.set_bridge_manager(bridge_manager);
+    let local_handle = tokio::spawn(async move {
+        let _ = local.run().await;
+    });
+
+    // First connection: take the publish but never acknowledge it
+    let (mut conn, mut decoder, mut buf, connect) = accept_bridge(&listener, false).await;
+    assert!(!connect.clean_start);
+    assert_eq!(connect.properties.session_expiry_interval, Some(3600));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut publisher = TestClient::connect(
+        SocketAddr::from(([127, 0, 0, 1], local_port)),
+        ProtocolVersion::V5,
+    )
+    .await;
+    publisher.mqtt_connect("resume-publisher").await;
+    publisher
+        .publish("sensors/temp", b"21.5", QoS::AtLeastOnce, false)
+        .await;
+
+    let first = match read_raw_packet(&mut conn, &mut decoder, &mut buf).await {
+        Packet::Publish(publish) => publish,
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    };
+    assert_eq!(first.topic, "edge/sensors/temp");
+    assert_eq!(first.qos, QoS::AtLeastOnce);
+    assert!(!first.dup);
+    drop(conn);
+
+    // Second connection: the remote kept the session, so the message comes
+    // back as a duplicate with the same packet ID
+    let (mut conn, mut decoder, mut buf, _) = accept_bridge(&listener, true).await;
+    let again = match read_raw_packet(&mut conn, &mut decoder, &mut buf).await {
+        Packet::Publish(publish) => publish,
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    };
+    assert!(again.dup);
+    assert_eq!(again.packet_id, first.packet_id);
+    assert_eq!(again.payload, first.payload);
+
+    local_handle.abort();
+}
+
+/// Relaying SOCKS5 proxy (no auth) that reports the requested targets
+async fn socks5_relay(listener: TcpListener, targets: tokio::sync::mpsc::UnboundedSender<String>) {
+    loop {
+        let Ok((mut client, _)) = listener.accept().await else {
+            return;
+        };
+        let targets = targets.clone();
+        tokio::spawn(async move {
+            let mut greeting = [0u8; 2];
+            client.read_exact(&mut greeting).await.unwrap();
+            let mut methods = vec![0u8; greeting[1] as usize];
+            client.read_exact(&mut methods).await.unwrap();
+            client.write_all(&[0x05, 0x00]).await.unwrap();
+
+            let mut head = [0u8; 5];
+            client.read_exact(&mut head).await.unwrap();
+            assert_eq!(head[3], 0x03, "hostname should reach the proxy unresolved");
+            let mut target = vec![0u8; head[4] as usize + 2];
+            client.read_exact(&mut target).await.unwrap();
+            let port = u16::from_be_bytes([target[target.len() - 2], target[target.len() - 1]]);
+            let host = String::from_utf8(target[..target.len() - 2].to_vec()).unwrap();
+            let _ = targets.send(format!("{}:{}", host, port));
+
+            let mut upstream = TcpStream::connect((host.as_str(), port)).await.unwrap();
+            client
+                .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+            let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+        });
+    }
+}
+
+/// A bridge with an egress proxy reaches its remote through the tunnel
+#[tokio::test]
+async fn test_bridge_egress_socks5() {
+    let remote_port = next_port();
+    let remote = Broker::new(test_broker_config(remote_port));
+    let remote_handle = tokio::spawn(async move {
+        let _ = remote.run().await;
+    });
+
+    let proxy_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = proxy_listener.local_addr().unwrap();
+    let (targets_tx, mut targets_rx) = tokio::sync::mpsc::unbounded_channel();
+    let proxy_handle = tokio::spawn(socks5_relay(proxy_listener, targets_tx));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let local = Broker::new(test_broker_config(next_port()));
+    let bridge_config = BridgeConfig {
+        address: format!("localhost:{}", remote_port),
+        egress_proxy: Some(EgressProxyConfig {
+            proxy_type: EgressProxyType::Socks5,
+            address: proxy_addr.to_string(),
+            username: None,
+            password: None,
+        }),
+        ..test_bridge_config("egress", remote_port, Vec::new())
+    };
+    let bridge_manager = local.create_bridge_manager(vec![bridge_config]);
+
+    let endpoint = timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some((_, Some(endpoint))) = bridge_manager.endpoints().pop() {
+                return endpoint;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("bridge should connect through the proxy");
+
+    assert_eq!(endpoint.addr, proxy_addr);
+    assert_eq!(
+        targets_rx.recv().await.unwrap(),
+        format!("localhost:{}", remote_port)
+    );
+    assert_eq!(bridge_manager.connected_count(), 1);
+
+    proxy_handle.abort();
+    remote_handle.abort();
+}
+
+/// A remote that stops answering PINGREQ is abandoned for the next address,
+/// and the round trips to the healthy one are measured
+#[tokio::test]
+async fn test_bridge_health_failover() {
+    let silent_port = next_port();
+    let listener = TcpListener::bind(("127.0.0.1", silent_port)).await.unwrap();
+    let silent = tokio::spawn(async move {
+        let (mut stream, _, mut buf, _) = accept_bridge(&listener, false).await;
+        // Swallow everything, PINGREQ included
+        while stream.read_buf(&mut buf).await.is_ok_and(|n| n > 0) {
+            buf.clear();
+        }
+    });
+
+    let remote_port = next_port();
+    let remote = Broker::new(test_broker_config(remote_port));
+    let remote_handle = tokio::spawn(async move {
+        let _ = remote.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let local = Broker::new(test_broker_config(next_port()));
+    let bridge_config = BridgeConfig {
+        failover_addresses: vec![format!("localhost:{}", remote_port)],
+        health: BridgeHealthConfig {
+            interval: Duration::from_millis(100),
+            timeout: Duration::from_millis(200),
+            failure_threshold: 2,
+            ..Default::default()
+        },
+        ..test_bridge_config("health", silent_port, Vec::new())
+    };
+    let bridge_manager = local.create_bridge_manager(vec![bridge_config]);
+    let metrics = vibemq::Metrics::new();
+    bridge_manager.register_metrics(&metrics).unwrap();
+
+    let health = timeout(Duration::from_secs(10), async {
+        loop {
+            let endpoint = bridge_manager.endpoints().pop().and_then(|(_, e)| e);
+            let (_, health) = bridge_manager.health().pop().unwrap();
+            if endpoint.is_some_and(|e| e.addr.port() == remote_port) && health.last_rtt.is_some() {
+                return health;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("bridge should fail over to the healthy address");
+
+    assert_eq!(health.unhealthy_disconnects, 1);
+    assert_eq!(health.consecutive_failures, 0);
+    assert!(health.last_rtt.unwrap() < Duration::from_millis(200));
+
+    let text = String::from_utf8(metrics.encode_text().unwrap()).unwrap();
+    assert!(text.contains("vibemq_bridge_rtt_seconds{bridge=\"health\"}"));
+    assert!(text.contains("vibemq_bridge_unhealthy_total{bridge=\"health\"} 1"));
+    assert!(text.contains("vibemq_bridge_connected{bridge=\"health\"} 1"));
+
+    silent.abort();
+    remote_handle.abort();
+}
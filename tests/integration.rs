@@ -3,23 +3,54 @@
 //! These tests verify the broker's behavior by connecting actual MQTT clients
 //! and validating the protocol flows according to the MQTT specification.
 
-use std::net::SocketAddr;
-use std::sync::atomic::{AtomicU16, Ordering};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use bytes::{Bytes, BytesMut};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::time::timeout;
 
-use vibemq::broker::{Broker, BrokerConfig};
+use vibemq::acl::AclProvider;
+use vibemq::admin::AdminApi;
+use vibemq::aggregate::Aggregation;
+use vibemq::auth::{AuthDecision, AuthProvider, AuthRequest, Authenticator};
+use vibemq::broker::{
+    local_hops, routing_id, Broker, BrokerConfig, BrokerEvent, LocalPublish, LocalPublishError,
+    MaintenanceScheduler, TlsConfig, BACKPRESSURE_PROPERTY, EVICTED_PROPERTY, ROUTING_ID_PROPERTY,
+};
 use vibemq::codec::{Decoder, Encoder};
-use vibemq::config::ProxyProtocolConfig;
+use vibemq::config::{
+    AclConfig, AclPermissions, AclRevocation, AclRole, AclTtlRule, ActionKind,
+    AggregateAlertConfig, AggregateConfig, AggregateFunction, AuthConfig, AuthMetadataField,
+    BacklogDrainConfig, BatchConfig, ClusterConfig, DelayedConfig, EnrichConfig, ErrorDetail,
+    GeofenceConfig, HandoverConfig, HealthConfig, ListenerCapabilities, ListenerLimits,
+    LookupTableConfig, MaintenanceConfig, MemoryPressureConfig, PluginsConfig, PriorityConfig,
+    ProxyProtocolConfig, PublishRateConfig, PublisherBackpressureConfig, QueueOverflow,
+    QuotaConfig, QuotaLimits, ReplayConfig, RetainedCacheConfig, RetainedFeedConfig,
+    RuleActionConfig, RuleConfig, ScheduleConfig, SequenceConfig, SessionExpiryEventsConfig,
+    SessionPolicy, SharedSubscriptionStrategy, ShutdownConfig, SlowClientPolicy, TopicSchemaConfig,
+    TopicSchemaMode, TopicTemplateConfig, TopicTreeConfig, TransactionConfig, UserConfig,
+};
+use vibemq::enrich::Enrichment;
+use vibemq::flapping::{ConnectionLimitConfig, FlappingConfig, FlappingDetector};
+use vibemq::geofence::Geofence;
+use vibemq::hooks::{
+    CompositeHooks, ConnectionMetadata, HookError, HookResult, Hooks, RateLimitDecision,
+};
+use vibemq::persistence::StoredScheduleRun;
+use vibemq::plugin::PluginHost;
 use vibemq::protocol::{
-    ConnAck, Connect, Disconnect, Packet, Properties, ProtocolVersion, PubRel, Publish, QoS,
-    ReasonCode, RetainHandling, SubAck, Subscribe, Subscription, SubscriptionOptions, Unsubscribe,
-    Will,
+    ConnAck, Connect, Disconnect, Packet, Properties, ProtocolVersion, PubAck, PubRel, Publish,
+    QoS, ReasonCode, RetainHandling, SubAck, Subscribe, Subscription, SubscriptionOptions,
+    Unsubscribe, Will,
 };
+use vibemq::proxy::{encode_proxy_header_v2, ProxyIdentity, ProxyInfo, ProxyVersion};
+use vibemq::rules::Rule;
+use vibemq::schedule::Scheduler;
+use vibemq::transport::QuicStream;
 
 // Atomic port counter to avoid port conflicts between tests
 static PORT_COUNTER: AtomicU16 = AtomicU16::new(19000);
@@ -32,53 +63,118 @@ fn next_port() -> u16 {
 fn test_config(port: u16) -> BrokerConfig {
     BrokerConfig {
         bind_addr: SocketAddr::from(([127, 0, 0, 1], port)),
+        extra_bind_addrs: Vec::new(),
+        ipv6_only: None,
         tls_bind_addr: None,
         tls_config: None,
         ws_bind_addr: None,
+        wss_bind_addr: None,
+        quic_bind_addr: None,
+        quic: Default::default(),
         ws_path: "/mqtt".to_string(),
+        ws_max_frame_size: None,
+        unix_bind_path: None,
         max_connections: 100,
         max_packet_size: 1024 * 1024,
         default_keep_alive: 60,
         max_keep_alive: 300,
         session_expiry_check_interval: Duration::from_secs(60),
+        session_compress_idle_after: None,
+        max_session_expiry: None,
         receive_maximum: 65535,
         max_qos: QoS::ExactlyOnce,
         retain_available: true,
         wildcard_subscription_available: true,
         subscription_identifiers_available: true,
         shared_subscriptions_available: true,
+        shared_subscription_strategy: SharedSubscriptionStrategy::default(),
         max_topic_alias: 65535,
         num_workers: 2,
         sys_topics_enabled: false, // Disable in tests
         sys_topics_interval: Duration::from_secs(10),
+        max_local_hops: 8,
+        routing_ids: false,
+        bridge_compression: Vec::new(),
         max_inflight: 32,
         max_queued_messages: 1000,
+        max_queued_bytes: 0,
+        queue_qos0: true,
+        queue_overflow: QueueOverflow::default(),
         max_awaiting_rel: 100,
         retry_interval: Duration::from_secs(30),
+        ack_alarm_after: None,
         outbound_channel_capacity: 1024,
         max_topic_levels: 0,
+        hibernate_after: None,
         proxy_protocol: ProxyProtocolConfig::default(),
         tls_proxy_protocol: ProxyProtocolConfig::default(),
         ws_proxy_protocol: ProxyProtocolConfig::default(),
+        unix_proxy_protocol: ProxyProtocolConfig::default(),
+        allow_mqtt31: true,
+        tls_allow_mqtt31: true,
+        ws_allow_mqtt31: true,
+        listener_max_qos: None,
+        tls_listener_max_qos: None,
+        ws_listener_max_qos: None,
+        capabilities: ListenerCapabilities::default(),
+        tls_capabilities: ListenerCapabilities::default(),
+        ws_capabilities: ListenerCapabilities::default(),
+        error_detail: ErrorDetail::default(),
+        tls_error_detail: ErrorDetail::default(),
+        ws_error_detail: ErrorDetail::default(),
+        reason_map: Default::default(),
+        batch: BatchConfig::default(),
+        transaction: TransactionConfig::default(),
+        handover: HandoverConfig::default(),
+        retained_feed: RetainedFeedConfig::default(),
+        retained_cache: RetainedCacheConfig::default(),
+        delayed: DelayedConfig::default(),
+        session_expiry_events: SessionExpiryEventsConfig::default(),
+        backlog_drain: BacklogDrainConfig::default(),
+        health: HealthConfig::default(),
+        topic_tree: TopicTreeConfig::default(),
+        topic_schema: TopicSchemaConfig::default(),
+        sequence: SequenceConfig::default(),
+        priority: PriorityConfig::default(),
+        publish_rate: PublishRateConfig::default(),
+        quota: QuotaConfig::default(),
+        memory_pressure: MemoryPressureConfig::default(),
+        publisher_backpressure: PublisherBackpressureConfig::default(),
+        listener_limits: Default::default(),
+        auth_metadata_fields: AuthMetadataField::ALL.to_vec(),
+        shutdown: ShutdownConfig::default(),
     }
 }
 
 /// Helper struct for MQTT client operations in tests
+/// A byte stream a test client talks MQTT over
+trait TestStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> TestStream for T {}
+
 struct TestClient {
-    stream: TcpStream,
+    stream: Box<dyn TestStream>,
     encoder: Encoder,
     decoder: Decoder,
     protocol_version: ProtocolVersion,
+    /// Bytes read but not yet decoded (several packets may arrive in one read)
+    read_buf: BytesMut,
 }
 
 impl TestClient {
     async fn connect(addr: SocketAddr, version: ProtocolVersion) -> Self {
         let stream = TcpStream::connect(addr).await.expect("Failed to connect");
+        Self::over(stream, version)
+    }
+
+    /// A client on an already established transport
+    fn over(stream: impl TestStream + 'static, version: ProtocolVersion) -> Self {
         Self {
-            stream,
+            stream: Box::new(stream),
             encoder: Encoder::new(version),
             decoder: Decoder::new(),
             protocol_version: version,
+            read_buf: BytesMut::new(),
         }
     }
 
@@ -91,16 +187,21 @@ impl TestClient {
     }
 
     async fn recv(&mut self) -> Option<Packet> {
-        let mut buf = vec![0u8; 4096];
-        match timeout(Duration::from_secs(5), self.stream.read(&mut buf)).await {
-            Ok(Ok(n)) if n > 0 => {
-                self.decoder.set_protocol_version(self.protocol_version);
-                match self.decoder.decode(&buf[..n]) {
-                    Ok(Some((packet, _))) => Some(packet),
-                    _ => None,
+        self.decoder.set_protocol_version(self.protocol_version);
+        loop {
+            match self.decoder.decode(&self.read_buf) {
+                Ok(Some((packet, consumed))) => {
+                    let _ = self.read_buf.split_to(consumed);
+                    return Some(packet);
                 }
+                Ok(None) => {}
+                Err(_) => return None,
+            }
+            let mut buf = vec![0u8; 4096];
+            match timeout(Duration::from_secs(5), self.stream.read(&mut buf)).await {
+                Ok(Ok(n)) if n > 0 => self.read_buf.extend_from_slice(&buf[..n]),
+                _ => return None,
             }
-            _ => None,
         }
     }
 
@@ -123,6 +224,20 @@ impl TestClient {
         }
     }
 
+    /// Wait for the next packet, which must be a PUBACK
+    async fn recv_puback(&mut self) -> PubAck {
+        match self.recv().await {
+            Some(Packet::PubAck(ack)) => ack,
+            other => panic!("Expected PUBACK, got {:?}", other),
+        }
+    }
+
+    /// Publish at QoS 1 and wait for the PUBACK
+    async fn publish_acked(&mut self, topic: &str, payload: &[u8]) -> PubAck {
+        self.publish(topic, payload, QoS::AtLeastOnce, false).await;
+        self.recv_puback().await
+    }
+
     async fn subscribe(&mut self, packet_id: u16, filter: &str, qos: QoS) -> SubAck {
         let subscribe = Packet::Subscribe(Subscribe {
             packet_id,
@@ -792,6 +907,57 @@ async fn test_multiple_subscribers() {
     broker_handle.abort();
 }
 
+/// Large payloads, written from the shared buffer after their header, arrive
+/// intact at every subscriber
+#[tokio::test]
+async fn test_large_payload_fanout() {
+    let port = next_port();
+    let config = test_config(port);
+    let broker = Broker::new(config);
+
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+    let mut subscribers = Vec::new();
+    for (i, version) in [
+        ProtocolVersion::V311,
+        ProtocolVersion::V5,
+        ProtocolVersion::V5,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut sub = TestClient::connect(addr, version).await;
+        sub.mqtt_connect(&format!("large-sub{}", i), true).await;
+        sub.subscribe(1, "large/#", QoS::AtLeastOnce).await;
+        subscribers.push(sub);
+    }
+
+    let payload: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("large-pub", true).await;
+    publisher
+        .publish("large/blob", &payload, QoS::AtLeastOnce, false)
+        .await;
+
+    for sub in &mut subscribers {
+        match sub.recv().await {
+            Some(Packet::Publish(p)) => {
+                assert_eq!(p.topic, "large/blob");
+                assert_eq!(p.qos, QoS::AtLeastOnce);
+                assert!(p.payload[..] == payload[..], "payload corrupted");
+            }
+            other => panic!("Expected PUBLISH, got {:?}", other),
+        }
+    }
+
+    broker_handle.abort();
+}
+
 // ============================================================================
 // LIMITS Tests
 // ============================================================================
@@ -990,3 +1156,5679 @@ async fn test_max_connections_allows_takeover() {
 
     broker_handle.abort();
 }
+
+// ============================================================================
+// STOMP Compatibility Listener
+// ============================================================================
+
+/// Read STOMP frames until one with the given command arrives
+async fn read_stomp_frame(stream: &mut TcpStream, buf: &mut BytesMut, command: &str) -> String {
+    loop {
+        if let Some(end) = buf.iter().position(|&b| b == 0) {
+            let frame = String::from_utf8_lossy(&buf.split_to(end + 1)).to_string();
+            let frame = frame.trim_start_matches('\n').to_string();
+            if frame.starts_with(command) {
+                return frame;
+            }
+            continue;
+        }
+        let n = timeout(Duration::from_secs(5), stream.read_buf(buf))
+            .await
+            .expect("Timed out waiting for STOMP frame")
+            .unwrap();
+        assert!(n > 0, "STOMP connection closed");
+    }
+}
+
+#[tokio::test]
+async fn test_stomp_bridges_to_mqtt() {
+    let port = next_port();
+    let stomp_port = next_port();
+    let mut broker = Broker::new(test_config(port));
+    broker.set_stomp(vibemq::config::StompConfig {
+        enabled: true,
+        bind: SocketAddr::from(([127, 0, 0, 1], stomp_port)),
+        ..Default::default()
+    });
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut stomp = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], stomp_port)))
+        .await
+        .unwrap();
+    let mut buf = BytesMut::new();
+    stomp
+        .write_all(b"CONNECT\naccept-version:1.2\nhost:localhost\n\n\0")
+        .await
+        .unwrap();
+    let connected = read_stomp_frame(&mut stomp, &mut buf, "CONNECTED").await;
+    assert!(connected.contains("version:1.2"));
+
+    stomp
+        .write_all(b"SUBSCRIBE\nid:0\ndestination:/topic/sensors/#\nreceipt:r1\n\n\0")
+        .await
+        .unwrap();
+    read_stomp_frame(&mut stomp, &mut buf, "RECEIPT").await;
+
+    // MQTT -> STOMP
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let mut mqtt = TestClient::connect(addr, ProtocolVersion::V311).await;
+    mqtt.mqtt_connect("stomp-peer", true).await;
+    mqtt.subscribe(1, "alerts/#", QoS::AtMostOnce).await;
+    mqtt.publish("sensors/temp", b"21.5", QoS::AtMostOnce, false)
+        .await;
+
+    let message = read_stomp_frame(&mut stomp, &mut buf, "MESSAGE").await;
+    assert!(message.contains("subscription:0"));
+    assert!(message.contains("destination:/topic/sensors/temp"));
+    assert!(message.ends_with("21.5\0"));
+
+    // STOMP -> MQTT
+    stomp
+        .write_all(b"SEND\ndestination:/topic/alerts/fire\n\nevacuate\0")
+        .await
+        .unwrap();
+    match mqtt.recv().await {
+        Some(Packet::Publish(publish)) => {
+            assert_eq!(publish.topic, "alerts/fire");
+            assert_eq!(&publish.payload[..], b"evacuate");
+        }
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    // Unmapped destinations are rejected
+    stomp
+        .write_all(b"SEND\ndestination:/queue/jobs\n\nx\0")
+        .await
+        .unwrap();
+    let error = read_stomp_frame(&mut stomp, &mut buf, "ERROR").await;
+    assert!(error.contains("message:Unmapped destination"));
+
+    broker_handle.abort();
+}
+
+/// STOMP sessions count against max_connections like MQTT clients
+#[tokio::test]
+async fn test_stomp_max_connections() {
+    let port = next_port();
+    let stomp_port = next_port();
+    let mut config = test_config(port);
+    config.max_connections = 1;
+    let mut broker = Broker::new(config);
+    broker.set_stomp(vibemq::config::StompConfig {
+        enabled: true,
+        bind: SocketAddr::from(([127, 0, 0, 1], stomp_port)),
+        ..Default::default()
+    });
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let stomp_addr = SocketAddr::from(([127, 0, 0, 1], stomp_port));
+    let connect = b"CONNECT\naccept-version:1.2\nhost:localhost\n\n\0";
+    let mut first = TcpStream::connect(stomp_addr).await.unwrap();
+    let mut buf = BytesMut::new();
+    first.write_all(connect).await.unwrap();
+    read_stomp_frame(&mut first, &mut buf, "CONNECTED").await;
+
+    let mut mqtt = TestClient::connect(
+        SocketAddr::from(([127, 0, 0, 1], port)),
+        ProtocolVersion::V5,
+    )
+    .await;
+    let connack = mqtt.mqtt_connect("over-limit", true).await;
+    assert_eq!(connack.reason_code, ReasonCode::ServerUnavailable);
+
+    let mut second = TcpStream::connect(stomp_addr).await.unwrap();
+    let mut buf = BytesMut::new();
+    second.write_all(connect).await.unwrap();
+    let error = read_stomp_frame(&mut second, &mut buf, "ERROR").await;
+    assert!(
+        error.contains("message:Connection limit reached"),
+        "{}",
+        error
+    );
+
+    broker_handle.abort();
+}
+
+/// STOMP SEND takes from the publish rate limit of the login, like MQTT PUBLISH
+#[tokio::test]
+async fn test_stomp_send_rate_limited_by_login() {
+    let port = next_port();
+    let stomp_port = next_port();
+    let mut config = test_config(port);
+    config.publish_rate = PublishRateConfig {
+        messages_per_sec: 1,
+        burst: 1,
+        ..Default::default()
+    };
+    let mut broker = Broker::new(config);
+    broker.set_stomp(vibemq::config::StompConfig {
+        enabled: true,
+        bind: SocketAddr::from(([127, 0, 0, 1], stomp_port)),
+        ..Default::default()
+    });
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let stomp_addr = SocketAddr::from(([127, 0, 0, 1], stomp_port));
+    let connect = b"CONNECT\naccept-version:1.2\nhost:localhost\nlogin:alice\npasscode:x\n\n\0";
+    let send = b"SEND\ndestination:/topic/alerts/fire\nreceipt:r1\n\nevacuate\0";
+    let mut first = TcpStream::connect(stomp_addr).await.unwrap();
+    let mut buf = BytesMut::new();
+    first.write_all(connect).await.unwrap();
+    read_stomp_frame(&mut first, &mut buf, "CONNECTED").await;
+    first.write_all(send).await.unwrap();
+    read_stomp_frame(&mut first, &mut buf, "RECEIPT").await;
+    drop(first);
+
+    // A new session of the same login doesn't get a fresh bucket
+    let mut second = TcpStream::connect(stomp_addr).await.unwrap();
+    let mut buf = BytesMut::new();
+    second.write_all(connect).await.unwrap();
+    read_stomp_frame(&mut second, &mut buf, "CONNECTED").await;
+    second.write_all(send).await.unwrap();
+    let error = read_stomp_frame(&mut second, &mut buf, "ERROR").await;
+    assert!(
+        error.contains("message:Publish rate limit exceeded"),
+        "{}",
+        error
+    );
+
+    broker_handle.abort();
+}
+
+// ============================================================================
+// Message Batching Extension
+// ============================================================================
+
+#[tokio::test]
+async fn test_batch_frame_unpacked_and_routed() {
+    use vibemq::codec::{encode_batch, BatchEntry};
+
+    let port = next_port();
+    let mut config = test_config(port);
+    config.batch.enabled = true;
+    config.batch.max_messages = 3;
+    let broker = Broker::new(config);
+
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V311).await;
+    subscriber.mqtt_connect("batch-sub", true).await;
+    subscriber.subscribe(1, "sensors/#", QoS::AtLeastOnce).await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("batch-pub", true).await;
+
+    // QoS 1 batch with entries relative to "sensors/dev1"
+    let frame = encode_batch(&[
+        BatchEntry::new("temp", Bytes::from_static(b"21.5")),
+        BatchEntry::new("humidity", Bytes::from_static(b"40")),
+    ])
+    .unwrap();
+    publisher
+        .send(&Packet::Publish(Publish {
+            dup: false,
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            topic: "$batch/sensors/dev1".to_string(),
+            packet_id: Some(10),
+            payload: frame,
+            properties: Properties::default(),
+        }))
+        .await;
+    match publisher.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::Success),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+
+    let mut received = Vec::new();
+    for _ in 0..2 {
+        match subscriber.recv().await {
+            Some(Packet::Publish(msg)) => {
+                received.push((msg.topic, msg.payload));
+            }
+            other => panic!("Expected PUBLISH, got {:?}", other),
+        }
+    }
+    assert_eq!(
+        received,
+        vec![
+            ("sensors/dev1/temp".to_string(), Bytes::from_static(b"21.5")),
+            (
+                "sensors/dev1/humidity".to_string(),
+                Bytes::from_static(b"40")
+            ),
+        ]
+    );
+
+    // QoS 2 batch is routed on PUBREL
+    let frame = encode_batch(&[BatchEntry::new(
+        "sensors/dev2/temp",
+        Bytes::from_static(b"19"),
+    )])
+    .unwrap();
+    publisher
+        .send(&Packet::Publish(Publish {
+            dup: false,
+            qos: QoS::ExactlyOnce,
+            retain: false,
+            topic: "$batch".to_string(),
+            packet_id: Some(11),
+            payload: frame,
+            properties: Properties::default(),
+        }))
+        .await;
+    assert!(matches!(publisher.recv().await, Some(Packet::PubRec(_))));
+    publisher
+        .send(&Packet::PubRel(PubRel {
+            packet_id: 11,
+            reason_code: ReasonCode::Success,
+            properties: Properties::default(),
+        }))
+        .await;
+    assert!(matches!(publisher.recv().await, Some(Packet::PubComp(_))));
+    match subscriber.recv().await {
+        Some(Packet::Publish(msg)) => assert_eq!(msg.topic, "sensors/dev2/temp"),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    // Over the batch size limit
+    let entries: Vec<_> = (0..4)
+        .map(|i| BatchEntry::new(format!("n{}", i), Bytes::new()))
+        .collect();
+    publisher
+        .send(&Packet::Publish(Publish {
+            dup: false,
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            topic: "$batch/sensors".to_string(),
+            packet_id: Some(12),
+            payload: encode_batch(&entries).unwrap(),
+            properties: Properties::default(),
+        }))
+        .await;
+    match publisher.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::QuotaExceeded),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+
+    // Malformed frame
+    publisher
+        .send(&Packet::Publish(Publish {
+            dup: false,
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            topic: "$batch/sensors".to_string(),
+            packet_id: Some(13),
+            payload: Bytes::from_static(b"not a batch"),
+            properties: Properties::default(),
+        }))
+        .await;
+    match publisher.recv().await {
+        Some(Packet::PubAck(ack)) => {
+            assert_eq!(ack.reason_code, ReasonCode::PayloadFormatInvalid)
+        }
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+    assert!(timeout(Duration::from_millis(100), subscriber.recv())
+        .await
+        .ok()
+        .flatten()
+        .is_none());
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_publish_transaction() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.transaction.enabled = true;
+    config.transaction.max_messages = 2;
+    let broker = Broker::new(config);
+
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V311).await;
+    subscriber.mqtt_connect("txn-sub", true).await;
+    subscriber.subscribe(1, "state/#", QoS::AtLeastOnce).await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("txn-pub", true).await;
+
+    async fn send(client: &mut TestClient, topic: &str, qos: QoS, packet_id: u16) -> Packet {
+        client
+            .send(&Packet::Publish(Publish {
+                dup: false,
+                qos,
+                retain: false,
+                topic: topic.to_string(),
+                packet_id: (qos != QoS::AtMostOnce).then_some(packet_id),
+                payload: Bytes::from_static(b"on"),
+                properties: Properties::default(),
+            }))
+            .await;
+        client.recv().await.expect("acknowledgment")
+    }
+    async fn command(client: &mut TestClient, command: &str, packet_id: u16) -> ReasonCode {
+        match send(
+            client,
+            &format!("$txn/{}", command),
+            QoS::AtLeastOnce,
+            packet_id,
+        )
+        .await
+        {
+            Packet::PubAck(ack) => ack.reason_code,
+            other => panic!("Expected PUBACK, got {:?}", other),
+        }
+    }
+    async fn nothing_delivered(client: &mut TestClient) {
+        assert!(timeout(Duration::from_millis(100), client.recv())
+            .await
+            .ok()
+            .flatten()
+            .is_none());
+    }
+
+    // Held until commit, QoS 2 settled by the transaction
+    assert_eq!(
+        command(&mut publisher, "begin", 1).await,
+        ReasonCode::Success
+    );
+    assert!(matches!(
+        send(&mut publisher, "state/a", QoS::AtLeastOnce, 2).await,
+        Packet::PubAck(ack) if ack.reason_code == ReasonCode::Success
+    ));
+    assert!(matches!(
+        send(&mut publisher, "state/b", QoS::ExactlyOnce, 3).await,
+        Packet::PubRec(rec) if rec.reason_code == ReasonCode::Success
+    ));
+    publisher
+        .send(&Packet::PubRel(PubRel {
+            packet_id: 3,
+            reason_code: ReasonCode::Success,
+            properties: Properties::default(),
+        }))
+        .await;
+    assert!(matches!(publisher.recv().await, Some(Packet::PubComp(_))));
+    nothing_delivered(&mut subscriber).await;
+
+    assert_eq!(
+        command(&mut publisher, "commit", 4).await,
+        ReasonCode::Success
+    );
+    for topic in ["state/a", "state/b"] {
+        match subscriber.recv().await {
+            Some(Packet::Publish(msg)) => assert_eq!(msg.topic, topic),
+            other => panic!("Expected PUBLISH, got {:?}", other),
+        }
+    }
+    nothing_delivered(&mut subscriber).await;
+    assert_eq!(
+        command(&mut publisher, "commit", 5).await,
+        ReasonCode::ImplementationError
+    );
+    assert_eq!(
+        command(&mut publisher, "rollback", 5).await,
+        ReasonCode::ImplementationError
+    );
+
+    // Aborted explicitly, and by growing past max_messages
+    assert_eq!(
+        command(&mut publisher, "begin", 6).await,
+        ReasonCode::Success
+    );
+    send(&mut publisher, "state/c", QoS::AtLeastOnce, 7).await;
+    assert_eq!(
+        command(&mut publisher, "begin", 8).await,
+        ReasonCode::ImplementationError
+    );
+    assert_eq!(
+        command(&mut publisher, "abort", 9).await,
+        ReasonCode::Success
+    );
+    assert_eq!(
+        command(&mut publisher, "begin", 10).await,
+        ReasonCode::Success
+    );
+    send(&mut publisher, "state/d", QoS::AtLeastOnce, 11).await;
+    send(&mut publisher, "state/e", QoS::AtLeastOnce, 12).await;
+    assert!(matches!(
+        send(&mut publisher, "state/f", QoS::AtLeastOnce, 13).await,
+        Packet::PubAck(ack) if ack.reason_code == ReasonCode::QuotaExceeded
+    ));
+    assert_eq!(
+        command(&mut publisher, "commit", 14).await,
+        ReasonCode::ImplementationError
+    );
+    nothing_delivered(&mut subscriber).await;
+
+    // Discarded on disconnect
+    assert_eq!(
+        command(&mut publisher, "begin", 15).await,
+        ReasonCode::Success
+    );
+    send(&mut publisher, "state/g", QoS::AtLeastOnce, 16).await;
+    drop(publisher);
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("txn-pub", true).await;
+    assert_eq!(
+        command(&mut publisher, "commit", 1).await,
+        ReasonCode::ImplementationError
+    );
+    nothing_delivered(&mut subscriber).await;
+
+    // Outside a transaction, messages are routed at once
+    send(&mut publisher, "state/h", QoS::AtLeastOnce, 2).await;
+    match subscriber.recv().await {
+        Some(Packet::Publish(msg)) => assert_eq!(msg.topic, "state/h"),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+// ============================================================================
+// Session Checkpointing
+// ============================================================================
+
+#[tokio::test]
+async fn test_session_checkpoint_every_change() {
+    use std::sync::Arc;
+    use vibemq::config::SessionCheckpoint;
+    use vibemq::persistence::{FjallBackend, PersistenceManager, StorageBackend};
+
+    let dir = tempfile::tempdir().unwrap();
+    let backend = Arc::new(FjallBackend::open(dir.path()).unwrap());
+
+    let port = next_port();
+    let mut broker = Broker::new(test_config(port));
+    let metrics = Arc::new(vibemq::Metrics::new());
+    broker.set_metrics(metrics.clone());
+    broker.set_persistence(Arc::new(
+        PersistenceManager::new(backend.clone(), Duration::from_millis(10), 100)
+            .with_session_checkpoint(SessionCheckpoint::EveryChange, Duration::from_secs(1)),
+    ));
+
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V311).await;
+    client.mqtt_connect("checkpoint-client", false).await;
+    client.subscribe(1, "checkpoint/#", QoS::AtLeastOnce).await;
+
+    // Checkpointed while the client is still connected
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let stored = backend
+        .get_session("checkpoint-client")
+        .await
+        .unwrap()
+        .expect("session should be checkpointed before disconnect");
+    assert_eq!(stored.subscriptions.len(), 1);
+    assert_eq!(stored.subscriptions[0].filter, "checkpoint/#");
+
+    // The storage write is timed, not just the snapshot
+    assert!(metrics.session_checkpoints_total.get() > 0);
+    assert!(metrics.session_checkpoint_duration.get_sample_count() > 0);
+
+    broker_handle.abort();
+}
+
+/// Hibernated connections wake for both outbound deliveries and client packets
+#[tokio::test]
+async fn test_hibernated_connection_wakes() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.hibernate_after = Some(Duration::from_millis(50));
+
+    let addr = config.bind_addr;
+    let broker = Broker::new(config);
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("hibernate-sub", true).await;
+    subscriber
+        .subscribe(1, "idle/topic", QoS::AtLeastOnce)
+        .await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("hibernate-pub", true).await;
+
+    // Let both connections hibernate
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Publisher wakes on read, subscriber wakes on delivery
+    publisher
+        .publish("idle/topic", b"wake up", QoS::AtLeastOnce, false)
+        .await;
+    assert!(matches!(publisher.recv().await, Some(Packet::PubAck(_))));
+    match subscriber.recv().await {
+        Some(Packet::Publish(p)) => assert_eq!(&p.payload[..], b"wake up"),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    // Hibernate again and check the connection is still usable
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    subscriber.send(&Packet::PingReq).await;
+    assert!(matches!(subscriber.recv().await, Some(Packet::PingResp)));
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_ack_stall_alarm() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.retry_interval = Duration::from_millis(100);
+    config.ack_alarm_after = Some(Duration::from_millis(250));
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V311).await;
+    subscriber.mqtt_connect("stalled", true).await;
+    subscriber.subscribe(1, "jobs/#", QoS::AtLeastOnce).await;
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V311).await;
+    publisher.mqtt_connect("dispatcher", true).await;
+    publisher
+        .publish("jobs/1", b"run", QoS::AtLeastOnce, false)
+        .await;
+    let packet_id = match subscriber.recv().await {
+        Some(Packet::Publish(p)) => p.packet_id.unwrap(),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    };
+
+    // Pinging but not acknowledging: retransmissions, then the alarm
+    async fn keep_pinging(client: &mut TestClient, period: Duration) {
+        let until = tokio::time::Instant::now() + period;
+        while tokio::time::Instant::now() < until {
+            client.send(&Packet::PingReq).await;
+            let _ = timeout(Duration::from_millis(50), client.recv()).await;
+        }
+    }
+    keep_pinging(&mut subscriber, Duration::from_millis(600)).await;
+    let session = broker.sessions().get("stalled").unwrap();
+    {
+        let s = session.read();
+        assert!(s.ack_stalled);
+        assert!(s.ack_timeouts >= 2);
+        assert!(s
+            .oldest_unacked()
+            .is_some_and(|sent| sent.elapsed() >= Duration::from_millis(500)));
+    }
+
+    // The alarm clears once the message is acknowledged
+    subscriber
+        .send(&Packet::PubAck(PubAck::new(packet_id)))
+        .await;
+    keep_pinging(&mut subscriber, Duration::from_millis(300)).await;
+    assert!(!session.read().ack_stalled);
+    assert!(session.read().oldest_unacked().is_none());
+
+    broker_handle.abort();
+}
+
+/// Topic aliases in both directions: the client's are resolved and not passed
+/// on, the broker's follow the client's maximum with LRU reuse
+#[tokio::test]
+async fn test_topic_aliases() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.capabilities.topic_alias_maximum = Some(4);
+    let addr = config.bind_addr;
+    let broker = Broker::new(config);
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber
+        .send(&Packet::Connect(Box::new(Connect {
+            protocol_version: ProtocolVersion::V5,
+            client_id: "dashboard".to_string(),
+            clean_start: true,
+            keep_alive: 60,
+            username: None,
+            password: None,
+            will: None,
+            properties: Properties {
+                topic_alias_maximum: Some(2),
+                ..Default::default()
+            },
+        })))
+        .await;
+    match subscriber.recv().await {
+        Some(Packet::ConnAck(ack)) => assert_eq!(ack.properties.topic_alias_maximum, Some(4)),
+        other => panic!("Expected CONNACK, got {:?}", other),
+    }
+    subscriber.subscribe(1, "sensors/#", QoS::AtMostOnce).await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("sensor", true).await;
+    let aliased = |topic: &str, alias: u16| Publish {
+        topic: topic.to_string(),
+        payload: Bytes::from_static(b"21.5"),
+        properties: Properties {
+            topic_alias: Some(alias),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    publisher
+        .send(&Packet::Publish(aliased("sensors/a", 1)))
+        .await;
+    publisher.send(&Packet::Publish(aliased("", 1))).await;
+    for topic in ["sensors/b", "sensors/a", "sensors/c", "sensors/b"] {
+        publisher
+            .publish(topic, b"21.5", QoS::AtMostOnce, false)
+            .await;
+    }
+
+    // Once both are taken, the least recently used alias moves to the new
+    // topic: 2 from "sensors/b" to "sensors/c", then 1 from "sensors/a"
+    let expected = [
+        ("sensors/a", 1),
+        ("", 1),
+        ("sensors/b", 2),
+        ("", 1),
+        ("sensors/c", 2),
+        ("sensors/b", 1),
+    ];
+    for (topic, alias) in expected {
+        match subscriber.recv().await {
+            Some(Packet::Publish(p)) => {
+                assert_eq!(
+                    (p.topic.as_str(), p.properties.topic_alias),
+                    (topic, Some(alias))
+                )
+            }
+            other => panic!("Expected PUBLISH, got {:?}", other),
+        }
+    }
+
+    // Above the advertised maximum
+    publisher
+        .send(&Packet::Publish(aliased("sensors/d", 5)))
+        .await;
+    match publisher.recv().await {
+        Some(Packet::Disconnect(disconnect)) => {
+            assert_eq!(disconnect.reason_code, ReasonCode::TopicAliasInvalid)
+        }
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+/// Retained changes are published to the feed with their publisher
+#[tokio::test]
+async fn test_retained_change_feed() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.retained_feed.enabled = true;
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut mirror = TestClient::connect(addr, ProtocolVersion::V5).await;
+    mirror.mqtt_connect("mirror", true).await;
+    mirror
+        .subscribe(1, "$SYS/retained/#", QoS::AtMostOnce)
+        .await;
+    let mut lamp = TestClient::connect(addr, ProtocolVersion::V5).await;
+    lamp.mqtt_connect("lamp", true).await;
+
+    async fn next_change(mirror: &mut TestClient) -> serde_json::Value {
+        match mirror.recv().await {
+            Some(Packet::Publish(p)) => {
+                let change: serde_json::Value = serde_json::from_slice(&p.payload).unwrap();
+                assert_eq!(
+                    p.topic,
+                    format!("$SYS/retained/{}", change["topic"].as_str().unwrap())
+                );
+                change
+            }
+            other => panic!("Expected PUBLISH, got {:?}", other),
+        }
+    }
+
+    lamp.publish("shadow/lamp", b"on", QoS::AtMostOnce, true)
+        .await;
+    let change = next_change(&mut mirror).await;
+    assert_eq!(change["change"], "created");
+    assert_eq!(change["publisher"], "lamp");
+    assert_eq!(change["message"]["payload"], "on");
+
+    lamp.publish("shadow/lamp", b"off", QoS::AtMostOnce, true)
+        .await;
+    let change = next_change(&mut mirror).await;
+    assert_eq!(change["change"], "updated");
+    assert_eq!(change["message"]["payload"], "off");
+
+    // Non-retained messages and deleting nothing don't change the store
+    lamp.publish("shadow/lamp", b"blink", QoS::AtMostOnce, false)
+        .await;
+    assert!(!broker.delete_retained("shadow/none"));
+    assert!(broker.delete_retained("shadow/lamp"));
+    let change = next_change(&mut mirror).await;
+    assert_eq!(change["change"], "deleted");
+    assert!(change.get("publisher").is_none());
+    assert!(change.get("message").is_none());
+
+    broker_handle.abort();
+}
+
+/// Delayed publishes are acknowledged at once and routed to their target
+/// topic when the delay is up
+#[tokio::test]
+async fn test_delayed_publish() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.delayed.enabled = true;
+    config.delayed.user_property = Some("delay".to_string());
+    config.delayed.max_delay = Duration::from_secs(60);
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut device = TestClient::connect(addr, ProtocolVersion::V5).await;
+    device.mqtt_connect("device", true).await;
+    device.subscribe(1, "cmd/#", QoS::AtLeastOnce).await;
+    let mut service = TestClient::connect(addr, ProtocolVersion::V5).await;
+    service.mqtt_connect("service", true).await;
+
+    service
+        .publish("$delayed/1/cmd/reboot", b"now", QoS::AtLeastOnce, false)
+        .await;
+    match service.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::Success),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+    assert_eq!(broker.delayed().len(), 1);
+    assert!(
+        timeout(Duration::from_millis(500), device.recv())
+            .await
+            .is_err(),
+        "delivered before its delay"
+    );
+    match timeout(Duration::from_secs(2), device.recv()).await {
+        Ok(Some(Packet::Publish(p))) => {
+            assert_eq!(p.topic, "cmd/reboot");
+            assert_eq!(&p.payload[..], b"now");
+        }
+        other => panic!("Expected delayed PUBLISH, got {:?}", other),
+    }
+    assert!(broker.delayed().is_empty());
+
+    // The user property form, which doesn't reach subscribers
+    service
+        .send(&Packet::Publish(Publish {
+            topic: "cmd/update".to_string(),
+            payload: Bytes::from_static(b"v2"),
+            properties: Properties {
+                user_properties: vec![("delay".to_string(), "1".to_string())],
+                ..Default::default()
+            },
+            ..Default::default()
+        }))
+        .await;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(broker.delayed().len(), 1);
+    match timeout(Duration::from_secs(2), device.recv()).await {
+        Ok(Some(Packet::Publish(p))) => {
+            assert_eq!(p.topic, "cmd/update");
+            assert!(p.properties.user_properties.is_empty());
+        }
+        other => panic!("Expected delayed PUBLISH, got {:?}", other),
+    }
+
+    // Over max_delay, or without a topic
+    service
+        .publish("$delayed/3600/cmd/reboot", b"", QoS::AtLeastOnce, false)
+        .await;
+    match service.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::QuotaExceeded),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+    service
+        .publish("$delayed/5", b"", QoS::AtLeastOnce, false)
+        .await;
+    match service.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::TopicNameInvalid),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+    assert!(broker.delayed().is_empty());
+
+    broker_handle.abort();
+}
+
+/// A listener's connection limit refuses new clients but not takeovers, and
+/// a subscriber too far behind is disconnected
+#[tokio::test]
+async fn test_listener_limits() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.max_inflight = 1;
+    config.listener_limits.insert(
+        "tcp".to_string(),
+        ListenerLimits {
+            max_connections: 2,
+            max_outbound_messages: 2,
+            slow_client: SlowClientPolicy::Disconnect,
+            ..Default::default()
+        },
+    );
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("laggard", true).await;
+    subscriber.subscribe(1, "feed/#", QoS::AtLeastOnce).await;
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("feeder", true).await;
+
+    let mut extra = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = extra.mqtt_connect("extra", true).await;
+    assert_eq!(connack.reason_code, ReasonCode::ServerUnavailable);
+    assert_eq!(broker.listener_load().connections("tcp"), 2);
+
+    // Taking over an existing session is not a new connection
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = publisher.mqtt_connect("feeder", true).await;
+    assert_eq!(connack.reason_code, ReasonCode::Success);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(broker.listener_load().connections("tcp"), 2);
+
+    // One message inflight, the rest queue until the backlog is too long
+    for _ in 0..5 {
+        publisher
+            .publish("feed/tick", b"t", QoS::AtLeastOnce, false)
+            .await;
+    }
+    match subscriber.recv().await {
+        Some(Packet::Publish(p)) => assert_eq!(p.topic, "feed/tick"),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+    match subscriber.recv().await {
+        Some(Packet::Disconnect(disconnect)) => {
+            assert_eq!(disconnect.reason_code, ReasonCode::QuotaExceeded)
+        }
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(broker.listener_load().connections("tcp"), 1);
+
+    broker_handle.abort();
+}
+
+/// MQTT v5 publishers routing to a congested subscriber have their
+/// acknowledgements held, then get Quota exceeded with the pause to take
+#[tokio::test]
+async fn test_publisher_backpressure() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.max_inflight = 1;
+    config.listener_limits.insert(
+        "tcp".to_string(),
+        ListenerLimits {
+            max_outbound_messages: 2,
+            slow_client: SlowClientPolicy::PausePublishers,
+            pause_timeout: Duration::from_secs(5),
+            ..Default::default()
+        },
+    );
+    config.publisher_backpressure = PublisherBackpressureConfig {
+        enabled: true,
+        max_delay: Duration::from_millis(200),
+        retry_after: Duration::from_secs(2),
+    };
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("laggard", true).await;
+    subscriber.subscribe(1, "feed/#", QoS::AtLeastOnce).await;
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("feeder", true).await;
+
+    // The subscriber doesn't acknowledge, so its backlog grows until the
+    // publisher is told to back off instead of being paused
+    let mut rejected = None;
+    for _ in 0..10 {
+        let started = std::time::Instant::now();
+        let ack = publisher.publish_acked("feed/tick", b"t").await;
+        if ack.reason_code == ReasonCode::QuotaExceeded {
+            assert!(started.elapsed() >= Duration::from_millis(200));
+            rejected = Some(ack);
+            break;
+        }
+        assert_eq!(ack.reason_code, ReasonCode::Success);
+    }
+    let ack = rejected.expect("publish rejected");
+    assert!(ack
+        .properties
+        .user_properties
+        .contains(&(BACKPRESSURE_PROPERTY.to_string(), "2000".to_string())));
+    {
+        let session = broker.sessions().get("feeder").unwrap();
+        let s = session.read();
+        assert_eq!(s.publishes_rejected, 1);
+        assert_eq!(s.publishes_held, 0);
+    }
+
+    // Held until the subscriber is gone, then accepted; the publisher is
+    // served meanwhile
+    let leave = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let disconnect = Packet::Disconnect(Disconnect {
+            reason_code: ReasonCode::Success,
+            properties: Properties::default(),
+        });
+        subscriber.send(&disconnect).await;
+    });
+    publisher
+        .publish("feed/tick", b"t", QoS::AtLeastOnce, false)
+        .await;
+    publisher.send(&Packet::PingReq).await;
+    assert!(matches!(publisher.recv().await, Some(Packet::PingResp)));
+    match publisher.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::Success),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+    assert_eq!(
+        broker
+            .sessions()
+            .get("feeder")
+            .unwrap()
+            .read()
+            .publishes_held,
+        1
+    );
+    leave.await.unwrap();
+
+    broker_handle.abort();
+}
+
+/// Error detail policy controls SUBACK reason codes and reason strings
+#[tokio::test]
+async fn test_error_detail_policy() {
+    async fn suback_for(configure: impl FnOnce(&mut BrokerConfig)) -> SubAck {
+        let port = next_port();
+        let mut config = test_config(port);
+        configure(&mut config);
+
+        let addr = config.bind_addr;
+        let broker = Broker::new(config);
+        let broker_handle = tokio::spawn(async move {
+            let _ = broker.run().await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+        client.mqtt_connect("error-detail", true).await;
+        let suback = client.subscribe(1, "sensors/#/temp", QoS::AtMostOnce).await;
+        broker_handle.abort();
+        suback
+    }
+
+    let suback = suback_for(|c| c.error_detail = ErrorDetail::Full).await;
+    assert_eq!(suback.reason_codes, vec![ReasonCode::TopicFilterInvalid]);
+    let reason = suback.properties.reason_string.expect("reason string");
+    assert!(reason.contains("sensors/#/temp"), "{}", reason);
+
+    // Diagnostic user properties name the exceeded limit
+    let suback = suback_for(|c| {
+        c.error_detail = ErrorDetail::Full;
+        c.max_topic_levels = 2;
+    })
+    .await;
+    assert!(suback
+        .properties
+        .user_properties
+        .contains(&("limit".to_string(), "max_topic_levels=2".to_string())));
+
+    let suback = suback_for(|_| {}).await;
+    assert_eq!(suback.reason_codes, vec![ReasonCode::TopicFilterInvalid]);
+    assert!(suback.properties.reason_string.is_none());
+
+    let suback = suback_for(|c| c.error_detail = ErrorDetail::Generic).await;
+    assert_eq!(suback.reason_codes, vec![ReasonCode::UnspecifiedError]);
+    assert!(suback.properties.reason_string.is_none());
+
+    let suback = suback_for(|c| {
+        c.reason_map
+            .insert(ReasonCode::TopicFilterInvalid, ReasonCode::NotAuthorized);
+    })
+    .await;
+    assert_eq!(suback.reason_codes, vec![ReasonCode::NotAuthorized]);
+}
+
+/// Publish rate buckets outlive the connection, so reconnecting doesn't reset them
+#[tokio::test]
+async fn test_publish_rate_limit_survives_reconnect() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.error_detail = ErrorDetail::Full;
+    config.publish_rate = PublishRateConfig {
+        messages_per_sec: 1,
+        burst: 2,
+        ..Default::default()
+    };
+
+    let addr = config.bind_addr;
+    let broker = Broker::new(config);
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("rate-limited", true).await;
+    assert_eq!(
+        client.publish_acked("rate/limited", b"x").await.reason_code,
+        ReasonCode::Success
+    );
+    assert_eq!(
+        client.publish_acked("rate/limited", b"x").await.reason_code,
+        ReasonCode::Success
+    );
+    let ack = client.publish_acked("rate/limited", b"x").await;
+    assert_eq!(ack.reason_code, ReasonCode::QuotaExceeded);
+    assert!(ack
+        .properties
+        .user_properties
+        .iter()
+        .any(|(key, _)| key == "retry-after"));
+    drop(client);
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("rate-limited", true).await;
+    assert_eq!(
+        client.publish_acked("rate/limited", b"x").await.reason_code,
+        ReasonCode::QuotaExceeded
+    );
+
+    broker_handle.abort();
+}
+
+/// Delegated identities follow the hook, falling back to local buckets on timeout
+#[tokio::test]
+async fn test_publish_rate_external_hook() {
+    struct QuotaService;
+
+    #[async_trait::async_trait]
+    impl Hooks for QuotaService {
+        async fn on_rate_limit(
+            &self,
+            identity: &str,
+            _count: u32,
+        ) -> HookResult<Option<RateLimitDecision>> {
+            match identity {
+                "client:ext-allow" => Ok(Some(RateLimitDecision::Allow)),
+                "client:ext-deny" => Ok(Some(RateLimitDecision::Deny {
+                    retry_after: Some(Duration::from_secs(5)),
+                })),
+                _ => {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    Ok(Some(RateLimitDecision::Allow))
+                }
+            }
+        }
+    }
+
+    let port = next_port();
+    let mut config = test_config(port);
+    config.publish_rate = PublishRateConfig {
+        messages_per_sec: 1,
+        burst: 1,
+        external: vec!["client:ext-*".to_string()],
+        external_timeout: Duration::from_millis(50),
+        ..Default::default()
+    };
+
+    let addr = config.bind_addr;
+    let broker = Broker::with_hooks(config, Arc::new(QuotaService));
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The service's decision replaces the local bucket
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("ext-allow", true).await;
+    for _ in 0..3 {
+        assert_eq!(
+            client
+                .publish_acked("rate/external", b"x")
+                .await
+                .reason_code,
+            ReasonCode::Success
+        );
+    }
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("ext-deny", true).await;
+    assert_eq!(
+        client
+            .publish_acked("rate/external", b"x")
+            .await
+            .reason_code,
+        ReasonCode::QuotaExceeded
+    );
+
+    // No answer in time: the local bucket applies
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("ext-slow", true).await;
+    assert_eq!(
+        client
+            .publish_acked("rate/external", b"x")
+            .await
+            .reason_code,
+        ReasonCode::Success
+    );
+    assert_eq!(
+        client
+            .publish_acked("rate/external", b"x")
+            .await
+            .reason_code,
+        ReasonCode::QuotaExceeded
+    );
+
+    broker_handle.abort();
+}
+
+/// Quota violations end the connection; listener and user overrides layer
+/// over the defaults
+#[tokio::test]
+async fn test_client_quotas() {
+    async fn expect_quota_disconnect(client: &mut TestClient) {
+        match client.recv().await {
+            Some(Packet::Disconnect(disconnect)) => {
+                assert_eq!(disconnect.reason_code, ReasonCode::QuotaExceeded)
+            }
+            other => panic!("Expected DISCONNECT, got {:?}", other),
+        }
+        assert!(client.recv().await.is_none(), "connection should close");
+    }
+
+    let port = next_port();
+    let mut config = test_config(port);
+    config.quota = QuotaConfig {
+        default: QuotaLimits {
+            max_subscriptions: Some(1),
+            max_payload_size: Some(16),
+            ..Default::default()
+        },
+        listeners: [(
+            "tcp".to_string(),
+            QuotaLimits {
+                max_inflight: Some(4),
+                ..Default::default()
+            },
+        )]
+        .into(),
+        users: [(
+            "bulk".to_string(),
+            QuotaLimits {
+                max_subscriptions: Some(0),
+                ..Default::default()
+            },
+        )]
+        .into(),
+    };
+
+    let addr = config.bind_addr;
+    let broker = Broker::new(config);
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Defaults plus the listener's in-flight cap
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = client.mqtt_connect("quota-default", true).await;
+    assert_eq!(connack.properties.receive_maximum, Some(4));
+    client.subscribe(1, "quota/a", QoS::AtMostOnce).await;
+    // Re-subscribing to the same filter doesn't add a subscription
+    client.subscribe(2, "quota/a", QoS::AtLeastOnce).await;
+    client
+        .send(&Packet::Subscribe(Subscribe {
+            packet_id: 3,
+            subscriptions: vec![Subscription {
+                filter: "quota/b".to_string(),
+                options: SubscriptionOptions::default(),
+            }],
+            properties: Properties::default(),
+        }))
+        .await;
+    expect_quota_disconnect(&mut client).await;
+
+    // The user override lifts the subscription limit only
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client
+        .send(&Packet::Connect(Box::new(Connect {
+            protocol_version: ProtocolVersion::V5,
+            client_id: "quota-bulk".to_string(),
+            clean_start: true,
+            keep_alive: 60,
+            username: Some("bulk".to_string()),
+            password: None,
+            will: None,
+            properties: Properties::default(),
+        })))
+        .await;
+    assert!(matches!(client.recv().await, Some(Packet::ConnAck(_))));
+    for (id, filter) in [(1, "quota/a"), (2, "quota/b"), (3, "quota/c")] {
+        let suback = client.subscribe(id, filter, QoS::AtMostOnce).await;
+        assert_eq!(suback.reason_codes, vec![ReasonCode::Success]);
+    }
+    client
+        .publish("quota/a", &[0u8; 17], QoS::AtMostOnce, false)
+        .await;
+    expect_quota_disconnect(&mut client).await;
+
+    broker_handle.abort();
+}
+
+/// Local publishes skip client rate limits but not ACLs, and a chain of
+/// rules republishing each other's output stops at the hop budget
+#[tokio::test]
+async fn test_local_publisher_loop_budget() {
+    /// Confines the "rules" publisher to rules/#
+    struct Namespace;
+
+    #[async_trait::async_trait]
+    impl Hooks for Namespace {
+        async fn on_publish_check(
+            &self,
+            client_id: &str,
+            _username: Option<&str>,
+            topic: &str,
+            _qos: QoS,
+            _retain: bool,
+        ) -> HookResult<bool> {
+            Ok(client_id != "rules" || topic.starts_with("rules/"))
+        }
+    }
+
+    let port = next_port();
+    let mut config = test_config(port);
+    config.max_local_hops = 3;
+    config.publish_rate = PublishRateConfig {
+        messages_per_sec: 1,
+        burst: 1,
+        ..Default::default()
+    };
+
+    let addr = config.bind_addr;
+    let broker = Broker::with_hooks(config, Arc::new(Namespace));
+    let rules = broker.local_publisher("rules");
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("local-observer", true).await;
+    client.subscribe(1, "rules/#", QoS::AtMostOnce).await;
+
+    assert_eq!(
+        rules.publish(LocalPublish::new("other/topic", "x")).await,
+        Err(LocalPublishError::NotAuthorized)
+    );
+
+    // A rule that republishes everything it sees back onto its own topic
+    let mut subscription = rules.subscribe("rules/#", QoS::AtMostOnce).await.unwrap();
+    rules
+        .publish(LocalPublish::new("rules/echo", "x"))
+        .await
+        .unwrap();
+    let mut results = Vec::new();
+    while let Ok(Some(cause)) = timeout(Duration::from_secs(1), subscription.recv()).await {
+        let reaction = LocalPublish::new("rules/echo", "x").caused_by(&cause);
+        let result = rules.publish(reaction).await;
+        results.push(result.clone());
+        if result.is_err() {
+            break;
+        }
+    }
+    assert_eq!(
+        results,
+        vec![
+            Ok(()),
+            Ok(()),
+            Err(LocalPublishError::LoopDetected { hops: 4 })
+        ]
+    );
+
+    // The observer saw each hop, numbered, despite the 1 msg/s client limit
+    for expected in 1..=3u8 {
+        match client.recv().await {
+            Some(Packet::Publish(publish)) => {
+                assert_eq!(local_hops(&publish.properties), expected)
+            }
+            other => panic!("Expected PUBLISH, got {:?}", other),
+        }
+    }
+
+    broker_handle.abort();
+}
+
+/// A denied subscription names the ACL rule that denied it
+#[tokio::test]
+async fn test_acl_denial_names_rule() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.error_detail = ErrorDetail::Full;
+    let addr = config.bind_addr;
+    let acl = AclConfig {
+        enabled: true,
+        default: AclPermissions {
+            subscribe: vec!["sensors/#".to_string()],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let auth = Arc::new(AuthProvider::new(&AuthConfig::default()));
+    let broker = Broker::with_hooks(config, Arc::new(AclProvider::new(&acl, auth)));
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("acl-denied", true).await;
+    let suback = client.subscribe(1, "alarms/#", QoS::AtMostOnce).await;
+    assert_eq!(suback.reason_codes, vec![ReasonCode::NotAuthorized]);
+    assert!(
+        suback.properties.user_properties.contains(&(
+            "denied-by".to_string(),
+            "acl: no subscribe pattern of the defaults matches".to_string()
+        )),
+        "{:?}",
+        suback.properties.user_properties
+    );
+
+    broker_handle.abort();
+}
+
+/// Reloaded ACLs revoke existing subscriptions once re-evaluated
+#[tokio::test]
+async fn test_acl_reload_revokes_subscriptions() {
+    fn acl(subscribe: &[&str]) -> AclConfig {
+        AclConfig {
+            enabled: true,
+            default: AclPermissions {
+                publish: vec!["#".to_string()],
+                subscribe: subscribe.iter().map(|s| s.to_string()).collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let auth = Arc::new(AuthProvider::new(&AuthConfig::default()));
+    let provider = Arc::new(AclProvider::new(&acl(&["sensors/#"]), auth));
+    let broker = Arc::new(Broker::with_hooks(config, provider.clone()));
+    let mut events = broker.subscribe_events();
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("acl-sub", true).await;
+    let suback = subscriber.subscribe(1, "sensors/#", QoS::AtMostOnce).await;
+    assert_eq!(suback.reason_codes, vec![ReasonCode::Success]);
+
+    // Unchanged rules revoke nothing
+    assert_eq!(
+        broker
+            .reevaluate_subscriptions(AclRevocation::Unsubscribe)
+            .await,
+        0
+    );
+
+    provider.reload(&acl(&[]));
+    assert_eq!(
+        broker
+            .reevaluate_subscriptions(AclRevocation::Unsubscribe)
+            .await,
+        1
+    );
+    let revoked = timeout(Duration::from_secs(2), async {
+        loop {
+            if let Ok(BrokerEvent::SubscriptionRevoked { filter, client_id }) = events.recv().await
+            {
+                return (filter, client_id);
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(revoked.0, "sensors/#");
+    assert_eq!(&*revoked.1, "acl-sub");
+
+    // The subscriber stays connected but no longer receives messages
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("acl-pub", true).await;
+    publisher
+        .publish("sensors/temp", b"21", QoS::AtMostOnce, false)
+        .await;
+    subscriber.send(&Packet::PingReq).await;
+    assert!(matches!(subscriber.recv().await, Some(Packet::PingResp)));
+
+    // With the disconnect policy, the client is dropped
+    provider.reload(&acl(&["sensors/#"]));
+    subscriber.subscribe(2, "sensors/#", QoS::AtMostOnce).await;
+    provider.reload(&acl(&[]));
+    broker
+        .reevaluate_subscriptions(AclRevocation::Disconnect)
+        .await;
+    match subscriber.recv().await {
+        Some(Packet::Disconnect(disconnect)) => {
+            assert_eq!(disconnect.reason_code, ReasonCode::NotAuthorized)
+        }
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+/// Retained messages matched by a wildcard subscribe are filtered by the
+/// current read rules, topic by topic
+#[tokio::test]
+async fn test_retained_delivery_checks_acl_per_topic() {
+    struct PrivateAcl {
+        allow_private: AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl Hooks for PrivateAcl {
+        async fn on_subscribe_check(
+            &self,
+            _client_id: &str,
+            _username: Option<&str>,
+            filter: &str,
+            _qos: QoS,
+        ) -> HookResult<bool> {
+            Ok(
+                !filter.starts_with("sensors/private/")
+                    || self.allow_private.load(Ordering::SeqCst),
+            )
+        }
+    }
+
+    async fn retained_topics(client: &mut TestClient) -> Vec<String> {
+        let mut topics = Vec::new();
+        client.send(&Packet::PingReq).await;
+        loop {
+            match client.recv().await {
+                Some(Packet::Publish(publish)) => topics.push(publish.topic),
+                Some(Packet::PingResp) => break,
+                other => panic!("Expected PUBLISH or PINGRESP, got {:?}", other),
+            }
+        }
+        topics.sort();
+        topics
+    }
+
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let hooks = Arc::new(PrivateAcl {
+        allow_private: AtomicBool::new(true),
+    });
+    let broker = Broker::with_hooks(config, hooks.clone());
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("retained-pub", true).await;
+    publisher
+        .publish("sensors/public/temp", b"21", QoS::AtMostOnce, true)
+        .await;
+    publisher
+        .publish("sensors/private/key", b"secret", QoS::AtMostOnce, true)
+        .await;
+    publisher.send(&Packet::PingReq).await;
+    assert!(matches!(publisher.recv().await, Some(Packet::PingResp)));
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("retained-sub", true).await;
+    subscriber.subscribe(1, "sensors/#", QoS::AtMostOnce).await;
+    assert_eq!(
+        retained_topics(&mut subscriber).await,
+        vec!["sensors/private/key", "sensors/public/temp"]
+    );
+
+    // Rules narrowed after the messages were retained
+    hooks.allow_private.store(false, Ordering::SeqCst);
+    let suback = subscriber.subscribe(2, "sensors/#", QoS::AtMostOnce).await;
+    assert_eq!(suback.reason_codes, vec![ReasonCode::Success]);
+    assert_eq!(
+        retained_topics(&mut subscriber).await,
+        vec!["sensors/public/temp"]
+    );
+    subscriber
+        .subscribe(3, "sensors/+/key", QoS::AtMostOnce)
+        .await;
+    assert!(retained_topics(&mut subscriber).await.is_empty());
+
+    broker_handle.abort();
+}
+
+/// ACL TTL rules clamp the expiry of routed and retained messages
+#[tokio::test]
+async fn test_acl_ttl_clamps_message_expiry() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let acl = AclConfig {
+        enabled: true,
+        default: AclPermissions {
+            publish: vec!["#".to_string()],
+            subscribe: vec!["#".to_string()],
+            ttl: vec![AclTtlRule {
+                topic: "guest/#".to_string(),
+                max_message_expiry: Some(Duration::from_secs(300)),
+                max_retained_lifetime: Some(Duration::from_secs(60)),
+            }],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let auth = Arc::new(AuthProvider::new(&AuthConfig::default()));
+    let broker = Broker::with_hooks(config, Arc::new(AclProvider::new(&acl, auth)));
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("ttl-sub", true).await;
+    subscriber.subscribe(1, "#", QoS::AtMostOnce).await;
+
+    // No expiry given: the maximum applies
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("ttl-pub", true).await;
+    publisher
+        .publish("guest/note", b"hi", QoS::AtMostOnce, true)
+        .await;
+    match subscriber.recv().await {
+        Some(Packet::Publish(publish)) => {
+            assert_eq!(publish.properties.message_expiry_interval, Some(300))
+        }
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    // Topics without a rule are untouched
+    publisher
+        .publish("other/note", b"hi", QoS::AtMostOnce, false)
+        .await;
+    match subscriber.recv().await {
+        Some(Packet::Publish(publish)) => {
+            assert_eq!(publish.properties.message_expiry_interval, None)
+        }
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    // The retained copy is kept no longer than the retained lifetime
+    let mut late = TestClient::connect(addr, ProtocolVersion::V5).await;
+    late.mqtt_connect("ttl-late", true).await;
+    late.subscribe(1, "guest/#", QoS::AtMostOnce).await;
+    match late.recv().await {
+        Some(Packet::Publish(publish)) => {
+            assert!(publish.retain);
+            let expiry = publish.properties.message_expiry_interval.unwrap();
+            assert!(expiry <= 60, "retained expiry {} not clamped", expiry);
+        }
+        other => panic!("Expected retained PUBLISH, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+/// CONNACK reports the limits the broker actually applies
+#[tokio::test]
+async fn test_connack_reports_limits() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.max_awaiting_rel = 10;
+    config.publish_rate = PublishRateConfig {
+        messages_per_sec: 5,
+        burst: 20,
+        external: vec!["client:ext-*".to_string()],
+        advertise: true,
+        ..Default::default()
+    };
+    let addr = config.bind_addr;
+    let broker = Broker::new(config);
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = client.mqtt_connect("limits-client", true).await;
+    assert_eq!(connack.properties.receive_maximum, Some(10));
+    assert_eq!(
+        connack.properties.user_properties,
+        vec![
+            ("rate-limit-messages-per-sec".to_string(), "5".to_string()),
+            ("rate-limit-burst".to_string(), "20".to_string()),
+        ]
+    );
+
+    // Externally decided limits aren't known up front
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = client.mqtt_connect("ext-client", true).await;
+    assert!(connack.properties.user_properties.is_empty());
+
+    broker_handle.abort();
+}
+
+/// A listener QoS cap downgrades subscriptions and rejects higher publishes
+#[tokio::test]
+async fn test_listener_max_qos() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.listener_max_qos = Some(QoS::AtLeastOnce);
+    let addr = config.bind_addr;
+    let broker = Broker::new(config);
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = client.mqtt_connect("capped", true).await;
+    assert_eq!(connack.properties.maximum_qos, Some(1));
+
+    let suback = client.subscribe(1, "capped/#", QoS::ExactlyOnce).await;
+    assert_eq!(suback.reason_codes, vec![ReasonCode::GrantedQoS1]);
+
+    client
+        .publish("capped/a", b"x", QoS::ExactlyOnce, false)
+        .await;
+    match client.recv().await {
+        Some(Packet::Disconnect(disconnect)) => {
+            assert_eq!(disconnect.reason_code, ReasonCode::QoSNotSupported)
+        }
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+/// Tenants may reuse client IDs without taking over each other's sessions
+#[tokio::test]
+async fn test_tenant_client_id_namespace() {
+    async fn connect_as(
+        client: &mut TestClient,
+        client_id: &str,
+        username: Option<&str>,
+    ) -> ConnAck {
+        client
+            .send(&Packet::Connect(Box::new(Connect {
+                protocol_version: ProtocolVersion::V5,
+                client_id: client_id.to_string(),
+                clean_start: true,
+                keep_alive: 60,
+                username: username.map(str::to_string),
+                password: username.map(|_| Bytes::from_static(b"secret")),
+                will: None,
+                properties: Properties::default(),
+            })))
+            .await;
+        match client.recv().await {
+            Some(Packet::ConnAck(ack)) => ack,
+            other => panic!("Expected CONNACK, got {:?}", other),
+        }
+    }
+
+    let user = |username: &str, tenant: &str| UserConfig {
+        username: username.to_string(),
+        password: Some("secret".to_string()),
+        password_hash: None,
+        role: None,
+        tenant: Some(tenant.to_string()),
+    };
+    let auth = AuthConfig {
+        enabled: true,
+        allow_anonymous: true,
+        users: vec![user("alice", "acme"), user("bob", "beta")],
+        ..Default::default()
+    };
+
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let broker = Broker::with_hooks(config, Arc::new(AuthProvider::new(&auth)));
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut acme = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = connect_as(&mut acme, "sensor-1", Some("alice")).await;
+    assert_eq!(connack.reason_code, ReasonCode::Success);
+
+    let mut beta = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = connect_as(&mut beta, "sensor-1", Some("bob")).await;
+    assert_eq!(connack.reason_code, ReasonCode::Success);
+
+    // Untenanted clients can't claim a tenant's namespace
+    let mut intruder = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = connect_as(&mut intruder, "acme/sensor-1", None).await;
+    assert_eq!(connack.reason_code, ReasonCode::NotAuthorized);
+
+    // Neither session was taken over
+    let suback = acme.subscribe(1, "a", QoS::AtMostOnce).await;
+    assert_eq!(suback.reason_codes, vec![ReasonCode::Success]);
+    let suback = beta.subscribe(1, "b", QoS::AtMostOnce).await;
+    assert_eq!(suback.reason_codes, vec![ReasonCode::Success]);
+
+    broker_handle.abort();
+}
+
+/// ACL roles grant their clients' session settings at CONNECT
+#[tokio::test]
+async fn test_acl_session_policy() {
+    async fn connect_as(
+        client: &mut TestClient,
+        username: Option<&str>,
+        keep_alive: u16,
+    ) -> ConnAck {
+        client
+            .send(&Packet::Connect(Box::new(Connect {
+                protocol_version: ProtocolVersion::V5,
+                client_id: String::new(),
+                clean_start: true,
+                keep_alive,
+                username: username.map(str::to_string),
+                password: None,
+                will: None,
+                properties: Properties {
+                    session_expiry_interval: Some(u32::MAX),
+                    ..Default::default()
+                },
+            })))
+            .await;
+        match client.recv().await {
+            Some(Packet::ConnAck(ack)) => ack,
+            other => panic!("Expected CONNACK, got {:?}", other),
+        }
+    }
+
+    let auth = Arc::new(AuthProvider::new(&AuthConfig {
+        users: vec![UserConfig {
+            username: "gold-device".to_string(),
+            password: Some("secret".to_string()),
+            password_hash: None,
+            role: Some("gold".to_string()),
+            tenant: None,
+        }],
+        ..Default::default()
+    }));
+    let acl = AclConfig {
+        enabled: true,
+        roles: vec![AclRole {
+            name: "gold".to_string(),
+            publish: vec!["#".to_string()],
+            subscribe: vec!["#".to_string()],
+            ttl: vec![],
+            publish_properties: vec![],
+            max_qos: None,
+            session: SessionPolicy {
+                max_session_expiry: Some(Duration::from_secs(3600)),
+                min_keep_alive: Some(30),
+                max_keep_alive: Some(600),
+                ..Default::default()
+            },
+        }],
+        default: AclPermissions {
+            publish: vec!["#".to_string()],
+            subscribe: vec!["#".to_string()],
+            session: SessionPolicy {
+                max_session_expiry: Some(Duration::from_secs(60)),
+                max_keep_alive: Some(30),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let broker = Broker::with_hooks(config, Arc::new(AclProvider::new(&acl, auth)));
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Clients without a role get the default tier
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = connect_as(&mut client, None, 60).await;
+    assert_eq!(connack.reason_code, ReasonCode::Success);
+    assert_eq!(connack.properties.server_keep_alive, Some(30));
+    assert_eq!(connack.properties.session_expiry_interval, Some(60));
+
+    // The gold role is granted more, and its keep-alive floor applies
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = connect_as(&mut client, Some("gold-device"), 60).await;
+    assert_eq!(connack.properties.server_keep_alive, None);
+    assert_eq!(connack.properties.session_expiry_interval, Some(3600));
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = connect_as(&mut client, Some("gold-device"), 5).await;
+    assert_eq!(connack.properties.server_keep_alive, Some(30));
+
+    broker_handle.abort();
+}
+
+/// Authentication hooks see the client's address and listener, limited to
+/// the configured fields
+#[tokio::test]
+async fn test_auth_connection_metadata() {
+    struct Recorder(parking_lot::Mutex<Vec<ConnectionMetadata>>);
+
+    #[async_trait::async_trait]
+    impl Hooks for Recorder {
+        async fn on_authenticate_with_metadata(
+            &self,
+            _client_id: &str,
+            _username: Option<&str>,
+            _password: Option<&[u8]>,
+            metadata: &ConnectionMetadata,
+        ) -> HookResult<bool> {
+            self.0.lock().push(metadata.clone());
+            Ok(metadata.listener.as_deref() == Some("tcp"))
+        }
+    }
+
+    let port = next_port();
+    let mut config = test_config(port);
+    config.auth_metadata_fields = vec![AuthMetadataField::ClientIp, AuthMetadataField::Listener];
+    let addr = config.bind_addr;
+    let recorder = Arc::new(Recorder(parking_lot::Mutex::new(Vec::new())));
+    let broker = Broker::with_hooks(config, recorder.clone());
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = client.mqtt_connect("meta", true).await;
+    assert_eq!(connack.reason_code, ReasonCode::Success);
+
+    let seen = recorder.0.lock().clone();
+    assert_eq!(
+        seen,
+        vec![ConnectionMetadata {
+            client_ip: Some("127.0.0.1".parse().unwrap()),
+            listener: Some("tcp".to_string()),
+            ..Default::default()
+        }]
+    );
+
+    broker_handle.abort();
+}
+
+/// Authenticator decisions and failures map to CONNACK reason codes
+#[tokio::test]
+async fn test_authenticator_connack_codes() {
+    struct Backend;
+
+    #[async_trait::async_trait]
+    impl Authenticator for Backend {
+        fn name(&self) -> &str {
+            "backend"
+        }
+
+        async fn authenticate(&self, request: &AuthRequest<'_>) -> HookResult<AuthDecision> {
+            match request.username {
+                Some("alice") => Ok(AuthDecision::Allow),
+                Some("mallory") => Ok(AuthDecision::Banned),
+                Some("blocked") => Ok(AuthDecision::NotAuthorized),
+                Some("outage") => Err(HookError::Unavailable("timed out".into())),
+                _ => Ok(AuthDecision::Ignore),
+            }
+        }
+    }
+
+    async fn connect_as(addr: SocketAddr, version: ProtocolVersion, username: &str) -> ReasonCode {
+        let mut client = TestClient::connect(addr, version).await;
+        client
+            .send(&Packet::Connect(Box::new(Connect {
+                protocol_version: version,
+                client_id: format!("{}-client", username),
+                clean_start: true,
+                keep_alive: 60,
+                username: Some(username.to_string()),
+                password: Some(Bytes::from_static(b"secret")),
+                will: None,
+                properties: Properties::default(),
+            })))
+            .await;
+        match client.recv().await {
+            Some(Packet::ConnAck(ack)) => ack.reason_code,
+            other => panic!("Expected CONNACK, got {:?}", other),
+        }
+    }
+
+    let auth = AuthConfig {
+        enabled: true,
+        ..Default::default()
+    };
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let provider = AuthProvider::new(&auth).with_authenticator(Arc::new(Backend));
+    let broker = Broker::with_hooks(config, Arc::new(provider));
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let v5 = ProtocolVersion::V5;
+    assert_eq!(connect_as(addr, v5, "alice").await, ReasonCode::Success);
+    assert_eq!(
+        connect_as(addr, v5, "eve").await,
+        ReasonCode::BadUserNameOrPassword
+    );
+    assert_eq!(
+        connect_as(addr, v5, "blocked").await,
+        ReasonCode::NotAuthorized
+    );
+    assert_eq!(connect_as(addr, v5, "mallory").await, ReasonCode::Banned);
+    assert_eq!(
+        connect_as(addr, v5, "outage").await,
+        ReasonCode::ServerUnavailable
+    );
+
+    // v3.1.1 return codes: 4 bad credentials, 5 not authorized, 3 unavailable
+    let v3 = ProtocolVersion::V311;
+    assert_eq!(
+        connect_as(addr, v3, "eve").await,
+        ReasonCode::BadUserNameOrPassword
+    );
+    assert_eq!(
+        connect_as(addr, v3, "mallory").await,
+        ReasonCode::NotAuthorized
+    );
+    assert_eq!(
+        connect_as(addr, v3, "outage").await,
+        ReasonCode::ServerUnavailable
+    );
+
+    broker_handle.abort();
+}
+
+/// Features disabled on a listener are advertised as unavailable and refused
+#[tokio::test]
+async fn test_listener_capabilities() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.capabilities = ListenerCapabilities {
+        retain_available: false,
+        wildcard_subscriptions: false,
+        subscription_identifiers: true,
+        shared_subscriptions: false,
+        topic_alias_maximum: None,
+    };
+    let addr = config.bind_addr;
+    let broker = Broker::new(config);
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = client.mqtt_connect("lite", true).await;
+    assert_eq!(connack.properties.retain_available, Some(0));
+    assert_eq!(connack.properties.wildcard_subscription_available, Some(0));
+    assert_eq!(connack.properties.shared_subscription_available, Some(0));
+    assert_eq!(
+        connack.properties.subscription_identifier_available,
+        Some(1)
+    );
+
+    let suback = client.subscribe(1, "lite/#", QoS::AtMostOnce).await;
+    assert_eq!(
+        suback.reason_codes,
+        vec![ReasonCode::WildcardSubsNotSupported]
+    );
+    let suback = client.subscribe(2, "$share/g/lite", QoS::AtMostOnce).await;
+    assert_eq!(
+        suback.reason_codes,
+        vec![ReasonCode::SharedSubsNotSupported]
+    );
+    let suback = client.subscribe(3, "lite/a", QoS::AtMostOnce).await;
+    assert_eq!(suback.reason_codes, vec![ReasonCode::Success]);
+
+    client.publish("lite/a", b"x", QoS::AtMostOnce, true).await;
+    match client.recv().await {
+        Some(Packet::Disconnect(disconnect)) => {
+            assert_eq!(disconnect.reason_code, ReasonCode::RetainNotSupported)
+        }
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+/// PROXY headers are only expected from trusted peers; others connect directly
+#[tokio::test]
+async fn test_proxy_protocol_untrusted_peer_is_direct() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.proxy_protocol = ProxyProtocolConfig {
+        enabled: true,
+        trusted_networks: vec!["10.0.0.0/8".parse().unwrap()],
+        ..Default::default()
+    };
+
+    let addr = config.bind_addr;
+    let broker = Broker::new(config);
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // 127.0.0.1 is not trusted: no PROXY header is required
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = client.mqtt_connect("proxy-direct", true).await;
+    assert_eq!(connack.reason_code, ReasonCode::Success);
+
+    broker_handle.abort();
+}
+
+/// Optional mode accepts proxied and direct clients on the same listener
+#[tokio::test]
+async fn test_proxy_protocol_optional() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.proxy_protocol = ProxyProtocolConfig {
+        enabled: true,
+        optional: true,
+        trusted_networks: vec!["127.0.0.1/32".parse().unwrap()],
+        ..Default::default()
+    };
+
+    let addr = config.bind_addr;
+    let broker = Broker::new(config);
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Direct client: the bytes read while detecting are replayed as MQTT
+    let mut direct = TestClient::connect(addr, ProtocolVersion::V5).await;
+    let connack = direct.mqtt_connect("proxy-optional-direct", true).await;
+    assert_eq!(connack.reason_code, ReasonCode::Success);
+
+    // Proxied client
+    let mut proxied = TestClient::connect(addr, ProtocolVersion::V5).await;
+    proxied
+        .stream
+        .write_all(b"PROXY TCP4 192.0.2.10 127.0.0.1 40000 1883\r\n")
+        .await
+        .unwrap();
+    let connack = proxied.mqtt_connect("proxy-optional-proxied", true).await;
+    assert_eq!(connack.reason_code, ReasonCode::Success);
+
+    broker_handle.abort();
+}
+
+/// Records the proxied identity publish ACL checks see; denies wills from
+/// 192.0.2.66
+#[derive(Default)]
+struct IdentityRecorder(parking_lot::Mutex<Vec<(String, Option<String>)>>);
+
+#[async_trait::async_trait]
+impl Hooks for IdentityRecorder {
+    async fn on_publish_check_with_identity(
+        &self,
+        _client_id: &str,
+        _username: Option<&str>,
+        topic: &str,
+        _qos: QoS,
+        _retain: bool,
+        identity: Option<&ProxyIdentity>,
+    ) -> HookResult<bool> {
+        let addr = identity.map(|identity| identity.client_addr.clone());
+        let denied = topic.starts_with("status/") && addr.as_deref() == Some("192.0.2.66:40001");
+        self.0.lock().push((topic.to_string(), addr));
+        Ok(!denied)
+    }
+}
+
+fn forwarded_for(publish: &Publish) -> Vec<&str> {
+    publish
+        .properties
+        .user_properties
+        .iter()
+        .filter(|(key, _)| key == "mqtt-forwarded-for")
+        .map(|(_, value)| value.as_str())
+        .collect()
+}
+
+/// Wills and retained messages carry the proxied client's identity to ACL
+/// hooks and, with `forwarded_for`, to subscribers
+#[tokio::test]
+async fn test_proxy_identity_on_wills_and_retained() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.proxy_protocol = ProxyProtocolConfig {
+        enabled: true,
+        optional: true,
+        forwarded_for: true,
+        trusted_networks: vec!["127.0.0.1/32".parse().unwrap()],
+        ..Default::default()
+    };
+
+    let addr = config.bind_addr;
+    let recorder = Arc::new(IdentityRecorder::default());
+    let broker = Broker::with_hooks(config, recorder.clone());
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let will = |client_id: &str| {
+        Packet::Connect(Box::new(Connect {
+            protocol_version: ProtocolVersion::V5,
+            client_id: client_id.to_string(),
+            clean_start: true,
+            keep_alive: 60,
+            username: None,
+            password: None,
+            will: Some(Will {
+                topic: format!("status/{}", client_id),
+                payload: Bytes::from_static(b"offline"),
+                qos: QoS::AtMostOnce,
+                retain: true,
+                properties: Properties::default(),
+            }),
+            properties: Properties::default(),
+        }))
+    };
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher
+        .stream
+        .write_all(b"PROXY TCP4 192.0.2.10 127.0.0.1 40000 1883\r\n")
+        .await
+        .unwrap();
+    publisher.send(&will("dev1")).await;
+    match publisher.recv().await {
+        Some(Packet::ConnAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::Success),
+        other => panic!("Expected CONNACK, got {:?}", other),
+    }
+
+    // A client-set forwarded-for is replaced on retained messages
+    let properties = Properties {
+        user_properties: vec![(
+            "mqtt-forwarded-for".to_string(),
+            "for=\"10.9.9.9\"".to_string(),
+        )],
+        ..Default::default()
+    };
+    for (topic, retain) in [("sensors/dev1", true), ("live/dev1", false)] {
+        publisher
+            .send(&Packet::Publish(Publish {
+                dup: false,
+                qos: QoS::AtMostOnce,
+                retain,
+                topic: topic.to_string(),
+                packet_id: None,
+                payload: Bytes::from_static(b"21.5"),
+                properties: properties.clone(),
+            }))
+            .await;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("identity-sub", true).await;
+    subscriber.subscribe(1, "sensors/#", QoS::AtMostOnce).await;
+    match subscriber.recv().await {
+        Some(Packet::Publish(msg)) => {
+            assert_eq!(forwarded_for(&msg), vec!["for=\"192.0.2.10:40000\""])
+        }
+        other => panic!("Expected retained PUBLISH, got {:?}", other),
+    }
+    subscriber.subscribe(2, "status/#", QoS::AtMostOnce).await;
+
+    // The will goes out (and is retained) stamped
+    drop(publisher);
+    match subscriber.recv().await {
+        Some(Packet::Publish(msg)) => {
+            assert_eq!(msg.topic, "status/dev1");
+            assert_eq!(forwarded_for(&msg), vec!["for=\"192.0.2.10:40000\""]);
+        }
+        other => panic!("Expected will PUBLISH, got {:?}", other),
+    }
+
+    // Non-retained messages are left as published
+    let mut live = TestClient::connect(addr, ProtocolVersion::V5).await;
+    live.mqtt_connect("identity-live", true).await;
+    live.subscribe(1, "live/#", QoS::AtMostOnce).await;
+    live.publish("live/dev2", b"x", QoS::AtMostOnce, false)
+        .await;
+    match live.recv().await {
+        Some(Packet::Publish(msg)) => assert!(forwarded_for(&msg).is_empty()),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    // The ACL hook saw who set the will; wills it denies reject the CONNECT
+    let mut denied = TestClient::connect(addr, ProtocolVersion::V5).await;
+    denied
+        .stream
+        .write_all(b"PROXY TCP4 192.0.2.66 127.0.0.1 40001 1883\r\n")
+        .await
+        .unwrap();
+    denied.send(&will("dev2")).await;
+    match denied.recv().await {
+        Some(Packet::ConnAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::NotAuthorized),
+        other => panic!("Expected CONNACK, got {:?}", other),
+    }
+
+    let checks = recorder.0.lock().clone();
+    let proxied = Some("192.0.2.10:40000".to_string());
+    assert!(checks.contains(&("status/dev1".to_string(), proxied.clone())));
+    assert!(checks.contains(&("sensors/dev1".to_string(), proxied)));
+    assert!(checks.contains(&("live/dev2".to_string(), None)));
+
+    broker_handle.abort();
+}
+
+/// Records the client IP each authentication hook call sees
+#[derive(Default)]
+struct IpRecorder(parking_lot::Mutex<Vec<Option<IpAddr>>>);
+
+#[async_trait::async_trait]
+impl Hooks for IpRecorder {
+    async fn on_authenticate_with_metadata(
+        &self,
+        _client_id: &str,
+        _username: Option<&str>,
+        _password: Option<&[u8]>,
+        metadata: &ConnectionMetadata,
+    ) -> HookResult<bool> {
+        self.0.lock().push(metadata.client_ip);
+        Ok(true)
+    }
+}
+
+impl IpRecorder {
+    fn last(&self) -> Option<IpAddr> {
+        self.0.lock().last().copied().flatten()
+    }
+}
+
+/// One dual-stack socket serves both families; IPv4 clients are reported
+/// as IPv4 rather than v4-mapped IPv6
+#[tokio::test]
+async fn test_dual_stack_listener() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.bind_addr = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+    config.ipv6_only = Some(false);
+    let recorder = Arc::new(IpRecorder::default());
+    let broker = Broker::with_hooks(config, recorder.clone());
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for (ip, client_id) in [("127.0.0.1", "dual-v4"), ("::1", "dual-v6")] {
+        let ip: IpAddr = ip.parse().unwrap();
+        let mut client = TestClient::connect(SocketAddr::new(ip, port), ProtocolVersion::V5).await;
+        let connack = client.mqtt_connect(client_id, true).await;
+        assert_eq!(connack.reason_code, ReasonCode::Success);
+        assert_eq!(recorder.last(), Some(ip));
+    }
+
+    broker_handle.abort();
+}
+
+/// Separate IPv4 and IPv6 sockets on one port; the IPv6 wildcard is made
+/// IPv6-only so both can bind
+#[tokio::test]
+async fn test_separate_ipv4_ipv6_binds() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.extra_bind_addrs = vec![SocketAddr::from((Ipv6Addr::UNSPECIFIED, port))];
+    let recorder = Arc::new(IpRecorder::default());
+    let broker = Broker::with_hooks(config, recorder.clone());
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for (ip, client_id) in [("127.0.0.1", "split-v4"), ("::1", "split-v6")] {
+        let ip: IpAddr = ip.parse().unwrap();
+        let mut client = TestClient::connect(SocketAddr::new(ip, port), ProtocolVersion::V5).await;
+        let connack = client.mqtt_connect(client_id, true).await;
+        assert_eq!(connack.reason_code, ReasonCode::Success);
+        assert_eq!(recorder.last(), Some(ip));
+    }
+
+    broker_handle.abort();
+}
+
+/// PROXY v1/v2 headers whose source family differs from the connection's,
+/// on a dual-stack listener; v4-mapped sources match IPv4 trust and bans
+#[tokio::test]
+async fn test_proxy_protocol_mixed_families() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.bind_addr = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+    config.ipv6_only = Some(false);
+    config.proxy_protocol = ProxyProtocolConfig {
+        enabled: true,
+        // IPv4 proxies arrive as ::ffff:127.0.0.1 on the dual-stack socket
+        trusted_networks: vec!["127.0.0.1/32".parse().unwrap(), "::1/128".parse().unwrap()],
+        ..Default::default()
+    };
+    let recorder = Arc::new(IpRecorder::default());
+    let mut broker = Broker::with_hooks(config, recorder.clone());
+    broker.set_flapping_detector(FlappingDetector::new(
+        FlappingConfig::default(),
+        ConnectionLimitConfig {
+            banned_ips: vec!["192.0.2.66".parse().unwrap()],
+            ..Default::default()
+        },
+    ));
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let v4 = SocketAddr::from(([127, 0, 0, 1], port));
+    let v6 = SocketAddr::from((Ipv6Addr::LOCALHOST, port));
+    let v2_header = |source: &str| {
+        encode_proxy_header_v2(&ProxyInfo {
+            client_addr: source.parse::<SocketAddr>().unwrap().into(),
+            server_addr: Some(SocketAddr::from((Ipv6Addr::LOCALHOST, 1883)).into()),
+            tls_info: None,
+            version: ProxyVersion::V2,
+            tlvs: Vec::new(),
+        })
+        .unwrap()
+    };
+    let cases: Vec<(SocketAddr, Vec<u8>, &str)> = vec![
+        // IPv6 client behind an IPv4 proxy connection, and vice versa
+        (
+            v4,
+            b"PROXY TCP6 2001:db8::7 ::1 40000 1883\r\n".to_vec(),
+            "2001:db8::7",
+        ),
+        (
+            v6,
+            b"PROXY TCP4 192.0.2.10 127.0.0.1 40000 1883\r\n".to_vec(),
+            "192.0.2.10",
+        ),
+        (
+            v4,
+            b"PROXY TCP6 ::ffff:192.0.2.11 ::1 40000 1883\r\n".to_vec(),
+            "192.0.2.11",
+        ),
+        (v6, v2_header("[::ffff:192.0.2.20]:40000"), "192.0.2.20"),
+        (v4, v2_header("[2001:db8::8]:40000"), "2001:db8::8"),
+    ];
+    for (i, (addr, header, expected)) in cases.into_iter().enumerate() {
+        let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+        client.stream.write_all(&header).await.unwrap();
+        let connack = client.mqtt_connect(&format!("mixed-{}", i), true).await;
+        assert_eq!(connack.reason_code, ReasonCode::Success);
+        assert_eq!(recorder.last(), Some(expected.parse().unwrap()));
+    }
+
+    // A ban on an IPv4 address applies to its v4-mapped form
+    let mut banned = TestClient::connect(v6, ProtocolVersion::V5).await;
+    banned
+        .stream
+        .write_all(&v2_header("[::ffff:192.0.2.66]:40000"))
+        .await
+        .unwrap();
+    banned
+        .send(&Packet::Connect(Box::new(Connect {
+            protocol_version: ProtocolVersion::V5,
+            client_id: "mixed-banned".to_string(),
+            clean_start: true,
+            keep_alive: 60,
+            username: None,
+            password: None,
+            will: None,
+            properties: Properties::default(),
+        })))
+        .await;
+    assert!(banned.recv().await.is_none());
+
+    broker_handle.abort();
+}
+
+/// Unix socket listener accepts MQTT clients; PROXY headers from local
+/// peers are honored regardless of `trusted_networks`
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_socket_listener() {
+    use tokio::net::UnixStream;
+
+    async fn connack(prefix: &[u8], path: &std::path::Path, client_id: &str) -> Option<ConnAck> {
+        let mut stream = UnixStream::connect(path).await.unwrap();
+        let connect = Packet::Connect(Box::new(Connect {
+            protocol_version: ProtocolVersion::V5,
+            client_id: client_id.to_string(),
+            clean_start: true,
+            keep_alive: 60,
+            username: None,
+            password: None,
+            will: None,
+            properties: Properties::default(),
+        }));
+        let mut buf = BytesMut::from(prefix);
+        Encoder::new(ProtocolVersion::V5)
+            .encode(&connect, &mut buf)
+            .unwrap();
+        stream.write_all(&buf).await.unwrap();
+
+        let mut read_buf = vec![0u8; 256];
+        let n = match timeout(Duration::from_secs(2), stream.read(&mut read_buf)).await {
+            Ok(Ok(n)) if n > 0 => n,
+            _ => return None,
+        };
+        let mut decoder = Decoder::new();
+        decoder.set_protocol_version(ProtocolVersion::V5);
+        match decoder.decode(&read_buf[..n]) {
+            Ok(Some((Packet::ConnAck(ack), _))) => Some(ack),
+            other => panic!("Expected CONNACK, got {:?}", other),
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("vibemq-unix-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("mqtt.sock");
+
+    let port = next_port();
+    let mut config = test_config(port);
+    config.unix_bind_path = Some(path.clone());
+    config.unix_proxy_protocol = ProxyProtocolConfig {
+        enabled: true,
+        optional: true,
+        trusted_networks: vec!["10.0.0.0/8".parse().unwrap()],
+        ..Default::default()
+    };
+    let broker = Broker::new(config);
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let direct = connack(b"", &path, "unix-direct").await.unwrap();
+    assert_eq!(direct.reason_code, ReasonCode::Success);
+
+    let proxied = connack(
+        b"PROXY TCP4 192.0.2.10 127.0.0.1 40000 1883\r\n",
+        &path,
+        "unix-proxied",
+    )
+    .await
+    .unwrap();
+    assert_eq!(proxied.reason_code, ReasonCode::Success);
+
+    broker_handle.abort();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// ============================================================================
+// $SYS Topic Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_sys_topics_tree() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.sys_topics_enabled = true;
+    config.sys_topics_interval = Duration::from_secs(1);
+    let mut broker = Broker::new(config);
+    broker.set_metrics(Arc::new(vibemq::Metrics::new()));
+
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("sys-watcher", true).await;
+    client.subscribe(1, "$SYS/broker/#", QoS::AtMostOnce).await;
+
+    // Retained values arrive in no particular order
+    let mut expected = vec![
+        "$SYS/broker/version",
+        "$SYS/broker/uptime",
+        "$SYS/broker/clients/connected",
+        "$SYS/broker/subscriptions/count",
+        "$SYS/broker/bytes/received",
+        "$SYS/broker/messages/sent",
+        "$SYS/broker/load/messages/received/1min",
+        "$SYS/broker/load/messages/received/5min",
+        "$SYS/broker/load/messages/received/15min",
+    ];
+    #[cfg(target_os = "linux")]
+    expected.extend(["$SYS/broker/heap/current", "$SYS/broker/heap/maximum"]);
+    let mut values = std::collections::HashMap::new();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while tokio::time::Instant::now() < deadline
+        && !expected.iter().all(|topic| values.contains_key(*topic))
+    {
+        if let Some(Packet::Publish(publish)) = client.recv().await {
+            values.insert(
+                publish.topic.clone(),
+                String::from_utf8_lossy(&publish.payload).to_string(),
+            );
+        }
+    }
+
+    assert!(values["$SYS/broker/version"].starts_with("vibemq version "));
+    assert!(values["$SYS/broker/uptime"].ends_with(" seconds"));
+    assert!(values["$SYS/broker/clients/connected"]
+        .parse::<u64>()
+        .is_ok());
+    for topic in &expected[6..] {
+        assert!(
+            values[*topic].parse::<f64>().is_ok(),
+            "{} not numeric",
+            topic
+        );
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let current: u64 = values["$SYS/broker/heap/current"].parse().unwrap();
+        let maximum: u64 = values["$SYS/broker/heap/maximum"].parse().unwrap();
+        assert!(current > 0 && maximum >= current);
+    }
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_session_expiry_and_will_delay() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.max_session_expiry = Some(Duration::from_secs(60));
+    let broker = Broker::new(config);
+
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+    fn connect_packet(client_id: &str, session_expiry: u32, will_delay: Option<u32>) -> Packet {
+        Packet::Connect(Box::new(Connect {
+            protocol_version: ProtocolVersion::V5,
+            client_id: client_id.to_string(),
+            clean_start: false,
+            keep_alive: 60,
+            username: None,
+            password: None,
+            will: will_delay.map(|delay| Will {
+                topic: "expiry/will".to_string(),
+                payload: Bytes::from_static(b"gone"),
+                qos: QoS::AtMostOnce,
+                retain: false,
+                properties: Properties {
+                    will_delay_interval: Some(delay),
+                    ..Default::default()
+                },
+            }),
+            properties: Properties {
+                session_expiry_interval: Some(session_expiry),
+                ..Default::default()
+            },
+        }))
+    }
+    fn disconnect_with_expiry(interval: u32) -> Packet {
+        Packet::Disconnect(Disconnect {
+            reason_code: ReasonCode::Success,
+            properties: Properties {
+                session_expiry_interval: Some(interval),
+                ..Default::default()
+            },
+        })
+    }
+
+    // A session that would never expire is granted the configured maximum
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client
+        .send(&connect_packet("expiry-capped", u32::MAX, None))
+        .await;
+    match client.recv().await {
+        Some(Packet::ConnAck(ack)) => {
+            assert_eq!(ack.properties.session_expiry_interval, Some(60))
+        }
+        other => panic!("Expected CONNACK, got {:?}", other),
+    }
+
+    // Setting 0 on DISCONNECT ends the session
+    client.send(&disconnect_with_expiry(0)).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client
+        .send(&connect_packet("expiry-capped", 30, None))
+        .await;
+    match client.recv().await {
+        Some(Packet::ConnAck(ack)) => {
+            assert!(!ack.session_present);
+            // Within the maximum, nothing to report
+            assert_eq!(ack.properties.session_expiry_interval, None);
+        }
+        other => panic!("Expected CONNACK, got {:?}", other),
+    }
+
+    // A session that ends on disconnect can't be revived by DISCONNECT
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.send(&connect_packet("expiry-zero", 0, None)).await;
+    let _ = client.recv().await; // CONNACK
+    client.send(&disconnect_with_expiry(30)).await;
+    match client.recv().await {
+        Some(Packet::Disconnect(d)) => assert_eq!(d.reason_code, ReasonCode::ProtocolError),
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+
+    // The will is published when the session expires before its delay
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("expiry-sub", true).await;
+    subscriber
+        .subscribe(1, "expiry/will", QoS::AtMostOnce)
+        .await;
+
+    let mut will_client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    will_client
+        .send(&connect_packet("expiry-will", 1, Some(30)))
+        .await;
+    let _ = will_client.recv().await; // CONNACK
+    drop(will_client);
+
+    match subscriber.recv().await {
+        Some(Packet::Publish(msg)) => assert_eq!(&msg.payload[..], b"gone"),
+        other => panic!("Expected will PUBLISH, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_session_expiry_events() {
+    use vibemq::persistence::{MemoryBackend, PersistenceManager, StorageBackend};
+
+    let port = next_port();
+    let mut config = test_config(port);
+    config.session_expiry_check_interval = Duration::from_millis(100);
+    config.session_expiry_events = SessionExpiryEventsConfig {
+        enabled: true,
+        topic: "provisioning/expired".to_string(),
+    };
+    let backend = Arc::new(MemoryBackend::new());
+    let mut broker = Broker::new(config);
+    broker.set_persistence(Arc::new(PersistenceManager::new(
+        backend.clone(),
+        Duration::from_millis(10),
+        100,
+    )));
+
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+    let mut provisioning = TestClient::connect(addr, ProtocolVersion::V311).await;
+    provisioning.mqtt_connect("provisioning", true).await;
+    provisioning
+        .subscribe(1, "provisioning/expired", QoS::ExactlyOnce)
+        .await;
+
+    // A persistent session expiring a second after disconnect
+    let mut device = TestClient::connect(addr, ProtocolVersion::V5).await;
+    device
+        .send(&Packet::Connect(Box::new(Connect {
+            protocol_version: ProtocolVersion::V5,
+            client_id: "device-1".to_string(),
+            clean_start: false,
+            keep_alive: 60,
+            username: None,
+            password: None,
+            will: None,
+            properties: Properties {
+                session_expiry_interval: Some(1),
+                ..Default::default()
+            },
+        })))
+        .await;
+    let _ = device.recv().await; // CONNACK
+    device.subscribe(1, "cmd/device-1", QoS::AtLeastOnce).await;
+    device
+        .send(&Packet::Disconnect(Disconnect {
+            reason_code: ReasonCode::Success,
+            properties: Properties::default(),
+        }))
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Queued while the device is away, discarded when the session expires
+    provisioning
+        .publish("cmd/device-1", b"reboot", QoS::AtMostOnce, false)
+        .await;
+
+    let event: serde_json::Value = match provisioning.recv().await {
+        Some(Packet::Publish(publish)) => {
+            assert_eq!(publish.topic, "provisioning/expired");
+            assert_eq!(publish.qos, QoS::ExactlyOnce);
+            serde_json::from_slice(&publish.payload).unwrap()
+        }
+        other => panic!("Expected the expiry event, got {:?}", other),
+    };
+    assert_eq!(event["client_id"], "device-1");
+    assert_eq!(event["reason"], "expired");
+    assert_eq!(event["expiry_interval_secs"], 1);
+    assert_eq!(event["discarded_messages"], 1);
+    assert!(event["event_id"].as_str().is_some_and(|id| !id.is_empty()));
+
+    // The session is gone from storage and the published event with it
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(backend.get_session("device-1").await.unwrap().is_none());
+    assert!(backend.list_session_events().await.unwrap().is_empty());
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn test_memory_pressure_evicts_qos0() {
+    let port = next_port();
+    let mut config = test_config(port);
+    // Any broker is over a 2 byte watermark
+    config.memory_pressure = MemoryPressureConfig {
+        high_watermark: 2,
+        low_watermark: 1,
+        check_interval: Duration::from_millis(50),
+        low_priority_topics: vec!["telemetry/debug/#".to_string()],
+    };
+    let broker = Broker::new(config);
+
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+    let connect = |clean_start: bool| {
+        Packet::Connect(Box::new(Connect {
+            protocol_version: ProtocolVersion::V5,
+            client_id: "evicted-device".to_string(),
+            clean_start,
+            keep_alive: 60,
+            username: None,
+            password: None,
+            will: None,
+            properties: Properties {
+                session_expiry_interval: Some(60),
+                ..Default::default()
+            },
+        }))
+    };
+    let mut device = TestClient::connect(addr, ProtocolVersion::V5).await;
+    device.send(&connect(false)).await;
+    let _ = device.recv().await; // CONNACK
+    device.subscribe(1, "telemetry/#", QoS::AtLeastOnce).await;
+    device
+        .send(&Packet::Disconnect(Disconnect {
+            reason_code: ReasonCode::Success,
+            properties: Properties::default(),
+        }))
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V311).await;
+    publisher.mqtt_connect("evicting-publisher", true).await;
+    publisher
+        .publish("telemetry/debug/trace", b"a", QoS::AtMostOnce, false)
+        .await;
+    publisher
+        .publish("telemetry/temp", b"b", QoS::AtMostOnce, false)
+        .await;
+    publisher
+        .publish("telemetry/alarm", b"c", QoS::AtLeastOnce, false)
+        .await;
+    let _ = publisher.recv().await; // PUBACK
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Only the QoS 1 message survives, telling of the two evicted
+    let mut device = TestClient::connect(addr, ProtocolVersion::V5).await;
+    device.send(&connect(false)).await;
+    let _ = device.recv().await; // CONNACK
+    match device.recv().await {
+        Some(Packet::Publish(publish)) => {
+            assert_eq!(publish.topic, "telemetry/alarm");
+            assert!(publish
+                .properties
+                .user_properties
+                .contains(&(EVICTED_PROPERTY.to_string(), "2".to_string())));
+        }
+        other => panic!("Expected the QoS 1 message, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_offline_queue_overflow() {
+    async fn offline_subscriber(addr: SocketAddr, client_id: &str) {
+        let mut client = TestClient::connect(addr, ProtocolVersion::V311).await;
+        client.mqtt_connect(client_id, false).await;
+        client.subscribe(1, "queue/#", QoS::AtLeastOnce).await;
+        client
+            .send(&Packet::Disconnect(Disconnect::default()))
+            .await;
+    }
+
+    let port = next_port();
+    let mut config = test_config(port);
+    config.max_queued_messages = 3;
+    config.queue_qos0 = false;
+    config.queue_overflow = QueueOverflow::DropNewest;
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    offline_subscriber(addr, "queue-slow").await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V311).await;
+    publisher.mqtt_connect("queue-pub", true).await;
+    publisher
+        .publish("queue/a", b"qos0", QoS::AtMostOnce, false)
+        .await;
+    for payload in ["1", "2", "3", "4"] {
+        publisher
+            .publish("queue/a", payload.as_bytes(), QoS::AtLeastOnce, false)
+            .await;
+        assert!(matches!(publisher.recv().await, Some(Packet::PubAck(_))));
+    }
+
+    // QoS 0 wasn't queued and the fourth message found the queue full
+    let depths = broker.queue_depths();
+    assert_eq!(depths.len(), 1);
+    assert_eq!(&*depths[0].client_id, "queue-slow");
+    assert_eq!(depths[0].messages, 3);
+    assert_eq!(depths[0].bytes, 3 * "queue/a1".len());
+    assert!(!depths[0].connected);
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V311).await;
+    assert!(
+        client
+            .mqtt_connect("queue-slow", false)
+            .await
+            .session_present
+    );
+    let mut received = Vec::new();
+    for _ in 0..3 {
+        match client.recv().await {
+            Some(Packet::Publish(p)) => received.push(p.payload),
+            other => panic!("Expected PUBLISH, got {:?}", other),
+        }
+    }
+    assert_eq!(received, vec!["1", "2", "3"]);
+    assert!(broker.queue_depths().is_empty());
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_fair_backlog_drain() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.backlog_drain = BacklogDrainConfig {
+        fair: true,
+        concurrency: 1,
+        batch_size: 2,
+        ..Default::default()
+    };
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for client_id in ["drain-a", "drain-b"] {
+        let mut client = TestClient::connect(addr, ProtocolVersion::V311).await;
+        client.mqtt_connect(client_id, false).await;
+        client.subscribe(1, "drain/#", QoS::AtLeastOnce).await;
+        client
+            .send(&Packet::Disconnect(Disconnect::default()))
+            .await;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V311).await;
+    publisher.mqtt_connect("drain-pub", true).await;
+    let payloads: Vec<String> = (0..7).map(|i| i.to_string()).collect();
+    for payload in &payloads {
+        publisher
+            .publish("drain/a", payload.as_bytes(), QoS::AtLeastOnce, false)
+            .await;
+        assert!(matches!(publisher.recv().await, Some(Packet::PubAck(_))));
+    }
+
+    // Both backlogs span several batches and share the single turn; each
+    // still arrives whole and in order
+    let mut clients = Vec::new();
+    for client_id in ["drain-a", "drain-b"] {
+        let mut client = TestClient::connect(addr, ProtocolVersion::V311).await;
+        assert!(client.mqtt_connect(client_id, false).await.session_present);
+        clients.push(client);
+    }
+    for client in &mut clients {
+        let mut received = Vec::new();
+        for _ in 0..payloads.len() {
+            match client.recv().await {
+                Some(Packet::Publish(p)) => received.push(p.payload),
+                other => panic!("Expected PUBLISH, got {:?}", other),
+            }
+        }
+        assert_eq!(received, payloads);
+    }
+    assert!(broker.queue_depths().is_empty());
+
+    broker_handle.abort();
+}
+
+/// A client that stops reading mid-drain gives its turn up after
+/// `turn_timeout`, and a session waiting for a turn still answers its client
+#[tokio::test]
+async fn test_backlog_drain_turn_timeout() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.backlog_drain = BacklogDrainConfig {
+        fair: true,
+        concurrency: 1,
+        batch_size: 2,
+        turn_timeout: Duration::from_millis(200),
+    };
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for (client_id, filter) in [("drain-stuck", "stuck/#"), ("drain-waiting", "waiting/#")] {
+        let mut client = TestClient::connect(addr, ProtocolVersion::V311).await;
+        client.mqtt_connect(client_id, false).await;
+        client.subscribe(1, filter, QoS::AtLeastOnce).await;
+        client
+            .send(&Packet::Disconnect(Disconnect::default()))
+            .await;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // More than the socket buffers hold for the stuck client, a few
+    // batches for the waiting one
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V311).await;
+    publisher.mqtt_connect("drain-pub", true).await;
+    let large = vec![0u8; 512 * 1024];
+    for _ in 0..24 {
+        publisher
+            .publish("stuck/a", &large, QoS::AtMostOnce, false)
+            .await;
+    }
+    for payload in ["1", "2", "3"] {
+        publisher
+            .publish("waiting/a", payload.as_bytes(), QoS::AtLeastOnce, false)
+            .await;
+        assert!(matches!(publisher.recv().await, Some(Packet::PubAck(_))));
+    }
+
+    // The stuck client never reads past its CONNACK
+    let mut stuck = TestClient::connect(addr, ProtocolVersion::V311).await;
+    assert!(
+        stuck
+            .mqtt_connect("drain-stuck", false)
+            .await
+            .session_present
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut waiting = TestClient::connect(addr, ProtocolVersion::V311).await;
+    assert!(
+        waiting
+            .mqtt_connect("drain-waiting", false)
+            .await
+            .session_present
+    );
+    waiting.send(&Packet::PingReq).await;
+    let mut received = Vec::new();
+    let mut pinged = false;
+    while received.len() < 3 || !pinged {
+        match waiting.recv().await {
+            Some(Packet::Publish(p)) => received.push(p.payload),
+            Some(Packet::PingResp) => pinged = true,
+            other => panic!("Expected PUBLISH or PINGRESP, got {:?}", other),
+        }
+    }
+    assert_eq!(received, ["1", "2", "3"]);
+
+    drop(stuck);
+    broker_handle.abort();
+}
+
+/// Start a broker as cluster node `name`, joining the node gossiping on
+/// `seed`; returns its MQTT address, its gossip port and the broker
+async fn start_cluster_node(
+    name: &str,
+    seed: Option<u16>,
+    config: BrokerConfig,
+) -> (SocketAddr, u16, Arc<Broker>) {
+    let addr = config.bind_addr;
+    let (gossip_port, peer_port) = (next_port(), next_port());
+    let localhost = |port| SocketAddr::from(([127, 0, 0, 1], port));
+    let cluster = ClusterConfig {
+        enabled: true,
+        node_id: Some(name.to_string()),
+        gossip_addr: localhost(gossip_port),
+        gossip_advertise_addr: Some(localhost(gossip_port)),
+        peer_addr: localhost(peer_port),
+        peer_advertise_addr: Some(localhost(peer_port)),
+        seeds: seed
+            .map(|port| format!("127.0.0.1:{port}"))
+            .into_iter()
+            .collect(),
+        gossip_interval: Duration::from_millis(100),
+        ..Default::default()
+    };
+    let mut broker = Broker::new(config);
+    let manager = broker.create_cluster_manager(cluster).await.unwrap();
+    broker.set_cluster_manager(manager);
+    let broker = Arc::new(broker);
+    let runner = broker.clone();
+    tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    (addr, gossip_port, broker)
+}
+
+/// Wait up to 10s for `done`
+async fn eventually(done: impl Fn() -> bool) {
+    for _ in 0..100 {
+        if done() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("condition not reached within 10s");
+}
+
+/// A client connecting to another node takes its session over, even if the
+/// nodes were not linked yet when it connected, and a late announcement
+/// doesn't undo a newer connect
+#[tokio::test]
+async fn test_cluster_session_takeover() {
+    let (addr_a, gossip_a, node_a) =
+        start_cluster_node("takeover-a", None, test_config(next_port())).await;
+    let mut first = TestClient::connect(addr_a, ProtocolVersion::V5).await;
+    first.mqtt_connect("roamer", false).await;
+    first.subscribe(1, "roam/#", QoS::AtLeastOnce).await;
+
+    // Connects to the second node before the nodes have found each other
+    let (addr_b, _, node_b) =
+        start_cluster_node("takeover-b", Some(gossip_a), test_config(next_port())).await;
+    let mut second = TestClient::connect(addr_b, ProtocolVersion::V5).await;
+    second.mqtt_connect("roamer", false).await;
+    match first.recv().await {
+        Some(Packet::Disconnect(d)) => assert_eq!(d.reason_code, ReasonCode::SessionTakenOver),
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+    eventually(|| node_a.sessions().get("roamer").is_none()).await;
+
+    // Back to the first node, which keeps the session from then on
+    let mut third = TestClient::connect(addr_a, ProtocolVersion::V5).await;
+    third.mqtt_connect("roamer", false).await;
+    match second.recv().await {
+        Some(Packet::Disconnect(d)) => assert_eq!(d.reason_code, ReasonCode::SessionTakenOver),
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+    eventually(|| node_b.sessions().get("roamer").is_none()).await;
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    assert!(node_a.sessions().get("roamer").is_some());
+    third.send(&Packet::PingReq).await;
+    assert!(matches!(third.recv().await, Some(Packet::PingResp)));
+}
+
+/// Messages forwarded between cluster nodes keep their publisher, so a
+/// sticky share group on the other node sends them all to one member
+#[tokio::test]
+async fn test_cluster_sticky_shared_subscription() {
+    let sticky = || {
+        let mut config = test_config(next_port());
+        config.shared_subscription_strategy = SharedSubscriptionStrategy::Sticky;
+        config
+    };
+    let (addr_a, gossip_a, node_a) = start_cluster_node("sticky-a", None, sticky()).await;
+    let (addr_b, _, _node_b) = start_cluster_node("sticky-b", Some(gossip_a), sticky()).await;
+
+    let mut members = Vec::new();
+    for client_id in ["sticky-w1", "sticky-w2"] {
+        let mut member = TestClient::connect(addr_b, ProtocolVersion::V311).await;
+        member.mqtt_connect(client_id, true).await;
+        member
+            .subscribe(1, "$share/g/jobs/#", QoS::AtMostOnce)
+            .await;
+        members.push(member);
+    }
+    let cluster = node_a.cluster_manager().unwrap().clone();
+    eventually(|| {
+        cluster.connected_peer_count() == 1
+            && cluster
+                .peers()
+                .iter()
+                .any(|peer| !peer.remote_subscriptions().is_empty())
+    })
+    .await;
+
+    let mut publisher = TestClient::connect(addr_a, ProtocolVersion::V311).await;
+    publisher.mqtt_connect("sticky-pub", true).await;
+    for i in 0..6 {
+        publisher
+            .publish("jobs/1", i.to_string().as_bytes(), QoS::AtMostOnce, false)
+            .await;
+    }
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut counts = Vec::new();
+    for member in &mut members {
+        let mut count = 0;
+        while let Ok(Some(packet)) = timeout(Duration::from_millis(300), member.recv()).await {
+            assert!(matches!(packet, Packet::Publish(_)));
+            count += 1;
+        }
+        counts.push(count);
+    }
+    counts.sort();
+    assert_eq!(counts, [0, 6]);
+}
+
+/// Send one request to the admin API; returns the status and JSON body
+async fn admin_request(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    token: &str,
+    body: &str,
+) -> (u16, serde_json::Value) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: admin\r\nAuthorization: Bearer {token}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let status = response[9..12].parse().unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    (status, serde_json::from_str(body).unwrap_or_default())
+}
+
+/// Plugins added and removed through the admin API take effect at once
+#[tokio::test]
+async fn test_admin_plugins() {
+    // A plugin denying publishes to "blocked/..."
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            while !buf.ends_with(b"}") {
+                let mut chunk = [0u8; 4096];
+                match stream.read(&mut chunk).await.unwrap() {
+                    0 => break,
+                    n => buf.extend_from_slice(&chunk[..n]),
+                }
+            }
+            let reply: &[u8] = if String::from_utf8_lossy(&buf).contains("\"blocked/") {
+                b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n"
+            } else {
+                b"HTTP/1.1 204 No Content\r\n\r\n"
+            };
+            let _ = stream.write_all(reply).await;
+        }
+    });
+
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let plugins = Arc::new(PluginHost::new(&PluginsConfig::default()).unwrap());
+    let broker = Arc::new(Broker::with_hooks(
+        config,
+        Arc::new(CompositeHooks::new().with(plugins.clone())),
+    ));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker.clone()).with_plugins(plugins);
+    let admin_handle = tokio::spawn(admin.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("plugins", true).await;
+    assert_eq!(
+        client.publish_acked("blocked/1", b"1").await.reason_code,
+        ReasonCode::Success
+    );
+
+    let body = format!(r#"{{"url": "{}", "events": ["publish_check"]}}"#, url);
+    let (status, change) =
+        admin_request(admin_addr, "PUT", "/api/v1/plugins/acl", "secret", &body).await;
+    assert_eq!(status, 200);
+    assert_eq!(change["replaced"], false);
+    assert_eq!(change["drained"], true);
+    assert_eq!(
+        client.publish_acked("blocked/1", b"1").await.reason_code,
+        ReasonCode::NotAuthorized
+    );
+    assert_eq!(
+        client.publish_acked("open/1", b"1").await.reason_code,
+        ReasonCode::Success
+    );
+
+    let (status, list) = admin_request(admin_addr, "GET", "/api/v1/plugins", "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(list[0]["name"], "acl");
+    assert_eq!(list[0]["calls"], 2);
+
+    let (status, _) = admin_request(
+        admin_addr,
+        "PUT",
+        "/api/v1/plugins/bad",
+        "secret",
+        r#"{"url": "x"}"#,
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    let (status, _) =
+        admin_request(admin_addr, "DELETE", "/api/v1/plugins/acl", "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        client.publish_acked("blocked/1", b"1").await.reason_code,
+        ReasonCode::Success
+    );
+    let (status, _) =
+        admin_request(admin_addr, "DELETE", "/api/v1/plugins/acl", "secret", "").await;
+    assert_eq!(status, 404);
+
+    broker_handle.abort();
+    admin_handle.abort();
+}
+
+/// The readiness probe fails until the listeners are bound
+#[tokio::test]
+async fn test_health_probes() {
+    let port = next_port();
+    let config = test_config(port);
+    let broker = Arc::new(Broker::new(config));
+    let health_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let health = vibemq::broker::HealthServer::new(broker.clone(), health_addr);
+    let health_handle = tokio::spawn(health.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let (status, _) = admin_request(health_addr, "GET", "/healthz", "", "").await;
+    assert_eq!(status, 200);
+    let (status, body) = admin_request(health_addr, "GET", "/readyz", "", "").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["ready"], false);
+    assert_eq!(body["checks"][0]["name"], "listeners");
+    assert_eq!(body["checks"][0]["ok"], false);
+
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let (status, body) = admin_request(health_addr, "GET", "/readyz", "", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["ready"], true);
+
+    broker_handle.abort();
+    health_handle.abort();
+}
+
+/// The topic tree export counts retained topics, recent traffic and
+/// subscriptions per level
+#[tokio::test]
+async fn test_admin_topic_tree() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.topic_tree.recent_topics = 100;
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker.clone());
+    let admin_handle = tokio::spawn(admin.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("tree/sub", true).await;
+    client.subscribe(1, "plant/+/temp", QoS::AtMostOnce).await;
+    client
+        .publish("plant/a/temp", b"20", QoS::AtMostOnce, true)
+        .await;
+    client
+        .publish("plant/b/temp", b"21", QoS::AtMostOnce, false)
+        .await;
+    client
+        .publish("plant/b/temp", b"22", QoS::AtMostOnce, false)
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let (status, body) =
+        admin_request(admin_addr, "GET", "/api/v1/topics/tree", "secret", "").await;
+    assert_eq!(status, 200);
+    let plant = &body["topics"]["children"][0];
+    assert_eq!(plant["name"], "plant");
+    assert_eq!(plant["topics"], 2);
+    assert_eq!(plant["retained"], 1);
+    assert_eq!(plant["messages"], 3);
+    let b = &plant["children"][1];
+    assert_eq!(b["name"], "b");
+    assert_eq!(b["messages"], 2);
+    let filter = &body["subscriptions"]["children"][0];
+    assert_eq!(filter["name"], "plant");
+    assert_eq!(filter["total"], 1);
+    assert_eq!(filter["children"][0]["name"], "+");
+
+    // Cut off at a depth
+    let (_, body) = admin_request(
+        admin_addr,
+        "GET",
+        "/api/v1/topics/tree?depth=1",
+        "secret",
+        "",
+    )
+    .await;
+    assert!(body["topics"]["children"][0].get("children").is_none());
+    assert_eq!(body["topics"]["children"][0]["topics"], 2);
+
+    let (status, _) = admin_request(
+        admin_addr,
+        "GET",
+        "/api/v1/topics/tree?format=dot",
+        "secret",
+        "",
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (status, _) = admin_request(
+        admin_addr,
+        "GET",
+        "/api/v1/topics/tree?format=svg",
+        "secret",
+        "",
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    broker_handle.abort();
+    admin_handle.abort();
+}
+
+/// Tunables changed through the admin API apply to connected clients
+#[tokio::test]
+async fn test_admin_tunables() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.publish_rate = PublishRateConfig {
+        messages_per_sec: 1,
+        burst: 1,
+        ..Default::default()
+    };
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker.clone());
+    let admin_handle = tokio::spawn(admin.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("tuned", true).await;
+    assert_eq!(
+        client.publish_acked("rate/tuned", b"x").await.reason_code,
+        ReasonCode::Success
+    );
+    assert_eq!(
+        client.publish_acked("rate/tuned", b"x").await.reason_code,
+        ReasonCode::QuotaExceeded
+    );
+
+    // Out of bounds and unknown tunables are refused
+    let rate = "/api/v1/tunables/publish_rate.messages_per_sec";
+    let (status, body) = admin_request(admin_addr, "PUT", rate, "secret", r#"{"value":0}"#).await;
+    assert_eq!(status, 400);
+    assert!(body["error"].as_str().unwrap().contains("between 1 and"));
+    let (status, _) = admin_request(
+        admin_addr,
+        "PUT",
+        "/api/v1/tunables/limits.max_connections",
+        "secret",
+        r#"{"value":10}"#,
+    )
+    .await;
+    assert_eq!(status, 404);
+
+    // A raised rate refills the bucket of the connected client at once
+    let (status, body) =
+        admin_request(admin_addr, "PUT", rate, "secret", r#"{"value":1000}"#).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["previous"], 1);
+    assert_eq!(body["value"], 1000);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(
+        client.publish_acked("rate/tuned", b"x").await.reason_code,
+        ReasonCode::Success
+    );
+
+    let (status, body) = admin_request(admin_addr, "GET", "/api/v1/tunables", "secret", "").await;
+    assert_eq!(status, 200);
+    let tuned = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|t| t["name"] == "publish_rate.messages_per_sec")
+        .unwrap();
+    assert_eq!(tuned["configured"], 1);
+    assert_eq!(tuned["overridden"], true);
+
+    let (status, body) = admin_request(admin_addr, "DELETE", rate, "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["value"], 1);
+
+    broker_handle.abort();
+    admin_handle.abort();
+}
+
+/// Limiter levels are listed per identity, and an identity's buckets can
+/// be refilled and its limits boosted through the admin API
+#[tokio::test]
+async fn test_admin_limits() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.publish_rate = PublishRateConfig {
+        messages_per_sec: 1,
+        burst: 1,
+        ..Default::default()
+    };
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker.clone());
+    let admin_handle = tokio::spawn(admin.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("limited", true).await;
+    assert_eq!(
+        client.publish_acked("rate/limited", b"x").await.reason_code,
+        ReasonCode::Success
+    );
+    assert_eq!(
+        client.publish_acked("rate/limited", b"x").await.reason_code,
+        ReasonCode::QuotaExceeded
+    );
+
+    let (status, body) = admin_request(
+        admin_addr,
+        "GET",
+        "/api/v1/limits?identity=client%3Alimited",
+        "secret",
+        "",
+    )
+    .await;
+    assert_eq!(status, 200);
+    let buckets = body["buckets"].as_array().unwrap();
+    assert_eq!(buckets.len(), 1);
+    assert_eq!(buckets[0]["limiter"], "publish");
+    assert_eq!(buckets[0]["capacity"], 1.0);
+    assert!(buckets[0]["tokens"].as_f64().unwrap() < 1.0);
+
+    // A reset refills the bucket
+    let (status, body) = admin_request(
+        admin_addr,
+        "POST",
+        "/api/v1/limits/client:limited/reset",
+        "secret",
+        "",
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["reset"], 1);
+    assert_eq!(
+        client.publish_acked("rate/limited", b"x").await.reason_code,
+        ReasonCode::Success
+    );
+
+    // A boost raises the rate until it is ended
+    let boost = "/api/v1/limits/client:limited/boost";
+    let (status, _) = admin_request(
+        admin_addr,
+        "PUT",
+        boost,
+        "secret",
+        r#"{"factor":0.5,"duration":"1m"}"#,
+    )
+    .await;
+    assert_eq!(status, 400);
+    let (status, body) = admin_request(
+        admin_addr,
+        "PUT",
+        boost,
+        "secret",
+        r#"{"factor":500,"duration":"1m"}"#,
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["factor"], 500.0);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(
+        client.publish_acked("rate/limited", b"x").await.reason_code,
+        ReasonCode::Success
+    );
+    assert_eq!(
+        client.publish_acked("rate/limited", b"x").await.reason_code,
+        ReasonCode::Success
+    );
+
+    let (_, body) = admin_request(admin_addr, "GET", "/api/v1/limits", "secret", "").await;
+    assert_eq!(body["boosts"][0]["identity"], "client:limited");
+    let (status, _) = admin_request(admin_addr, "DELETE", boost, "secret", "").await;
+    assert_eq!(status, 200);
+    let (status, _) = admin_request(admin_addr, "DELETE", boost, "secret", "").await;
+    assert_eq!(status, 404);
+
+    broker_handle.abort();
+    admin_handle.abort();
+}
+
+/// The ACL check endpoint explains a decision without touching the broker
+#[tokio::test]
+async fn test_admin_acl_check() {
+    let port = next_port();
+    let broker = Arc::new(Broker::new(test_config(port)));
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker);
+    let admin_handle = tokio::spawn(admin.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let check = "/api/v1/acl/check";
+    let (status, body) = admin_request(
+        admin_addr,
+        "POST",
+        check,
+        "secret",
+        r#"{"client_id":"c1","action":"publish","topic":"a/b","qos":1}"#,
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["allowed"], true);
+    assert_eq!(body["steps"][0]["decision"], "allow");
+
+    // Wildcards are only valid in subscribe checks
+    let (status, _) = admin_request(
+        admin_addr,
+        "POST",
+        check,
+        "secret",
+        r#"{"client_id":"c1","action":"publish","topic":"a/#"}"#,
+    )
+    .await;
+    assert_eq!(status, 400);
+    let (status, _) = admin_request(
+        admin_addr,
+        "POST",
+        check,
+        "secret",
+        r#"{"client_id":"c1","action":"subscribe","topic":"a/#","listener":"nope"}"#,
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    admin_handle.abort();
+}
+
+/// In reject mode, publishes matching no template are refused
+#[tokio::test]
+async fn test_topic_schema() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.topic_schema = TopicSchemaConfig {
+        mode: TopicSchemaMode::Reject,
+        templates: vec![TopicTemplateConfig {
+            pattern: "sensors/{site}/{device:int}".to_string(),
+            description: Some("Plant sensors".to_string()),
+            ..Default::default()
+        }],
+    };
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker.clone());
+    let admin_handle = tokio::spawn(admin.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("schema", true).await;
+    client.subscribe(1, "#", QoS::AtLeastOnce).await;
+
+    client
+        .publish("sensors/berlin/x1", b"1", QoS::AtLeastOnce, false)
+        .await;
+    match client.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::TopicNameInvalid),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+
+    client
+        .publish("sensors/berlin/7", b"2", QoS::AtLeastOnce, false)
+        .await;
+    let mut delivered = false;
+    let mut acked = false;
+    while !(delivered && acked) {
+        match client.recv().await {
+            Some(Packet::PubAck(ack)) => {
+                assert_eq!(ack.reason_code, ReasonCode::Success);
+                acked = true;
+            }
+            Some(Packet::Publish(p)) => {
+                assert_eq!(p.topic, "sensors/berlin/7");
+                delivered = true;
+            }
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    let (status, body) =
+        admin_request(admin_addr, "GET", "/api/v1/topics/schema", "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["mode"], "reject");
+    assert_eq!(body["templates"][0]["description"], "Plant sensors");
+    assert_eq!(body["templates"][0]["matched"], 1);
+    assert_eq!(body["violations"], 1);
+
+    broker_handle.abort();
+    admin_handle.abort();
+}
+
+/// Priority messages reach a subscriber that is too far behind for
+/// ordinary QoS 0 messages
+#[tokio::test]
+async fn test_priority_topics() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.priority = PriorityConfig {
+        topics: vec!["devices/+/cmd/#".to_string()],
+        ..Default::default()
+    };
+    config.listener_limits.insert(
+        "tcp".to_string(),
+        ListenerLimits {
+            max_outbound_messages: 1,
+            ..Default::default()
+        },
+    );
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("device-7", true).await;
+    subscriber
+        .subscribe(1, "devices/7/#", QoS::AtMostOnce)
+        .await;
+
+    // Telemetry floods the channel before the connection drains it, and
+    // most of it is shed; the command in the middle of it isn't
+    for i in 0..200 {
+        let topic = match i {
+            100 => "devices/7/cmd/reboot",
+            _ => "devices/7/telemetry",
+        };
+        broker.publish(
+            topic.to_string(),
+            Bytes::from(i.to_string()),
+            QoS::AtMostOnce,
+            false,
+        );
+    }
+
+    loop {
+        match subscriber.recv().await {
+            Some(Packet::Publish(p)) if p.topic == "devices/7/cmd/reboot" => break,
+            Some(Packet::Publish(_)) => {}
+            other => panic!("Expected the command, got {:?}", other),
+        }
+    }
+
+    broker_handle.abort();
+}
+
+/// Subscribers get a retained message from its cached frame, with their
+/// own packet IDs, and the new message once it is replaced
+#[tokio::test]
+async fn test_retained_frame_cache() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.retained_cache = RetainedCacheConfig {
+        enabled: true,
+        ..Default::default()
+    };
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("config-pub", true).await;
+    publisher
+        .publish("devices/config", b"interval=30", QoS::AtLeastOnce, true)
+        .await;
+    assert!(matches!(publisher.recv().await, Some(Packet::PubAck(_))));
+
+    async fn fetch(addr: std::net::SocketAddr, client_id: &str) -> Publish {
+        let mut device = TestClient::connect(addr, ProtocolVersion::V5).await;
+        device.mqtt_connect(client_id, true).await;
+        device
+            .subscribe(1, "devices/config", QoS::AtLeastOnce)
+            .await;
+        match device.recv().await {
+            Some(Packet::Publish(p)) => p,
+            other => panic!("Expected the retained config, got {:?}", other),
+        }
+    }
+
+    let first = fetch(addr, "device-1").await;
+    assert_eq!(broker.retained_frames().len(), 1);
+    let second = fetch(addr, "device-2").await;
+    assert_eq!(&second.payload[..], b"interval=30");
+    assert!(second.retain);
+    assert_eq!(first.packet_id, second.packet_id);
+
+    // A second delivery to one device patches in its next packet ID
+    let mut device = TestClient::connect(addr, ProtocolVersion::V5).await;
+    device.mqtt_connect("device-4", true).await;
+    let mut packet_ids = Vec::new();
+    for (id, filter) in [(1, "devices/config"), (2, "devices/+")] {
+        device.subscribe(id, filter, QoS::AtLeastOnce).await;
+        match device.recv().await {
+            Some(Packet::Publish(p)) => {
+                assert_eq!(&p.payload[..], b"interval=30");
+                packet_ids.push(p.packet_id.unwrap());
+            }
+            other => panic!("Expected the retained config, got {:?}", other),
+        }
+    }
+    assert_ne!(packet_ids[0], packet_ids[1]);
+
+    // Replacing the message drops its frame
+    publisher
+        .publish("devices/config", b"interval=60", QoS::AtLeastOnce, true)
+        .await;
+    assert!(matches!(publisher.recv().await, Some(Packet::PubAck(_))));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(broker.retained_frames().is_empty());
+    let third = fetch(addr, "device-3").await;
+    assert_eq!(&third.payload[..], b"interval=60");
+
+    broker_handle.abort();
+}
+
+/// A client's message gets a routing ID of the broker's, and reactions to
+/// it keep that ID
+#[tokio::test]
+async fn test_routing_ids() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.routing_ids = true;
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let rule = broker.local_publisher("rule:archive");
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("routing-sub", true).await;
+    subscriber.subscribe(1, "archive/#", QoS::AtMostOnce).await;
+    let mut cause = rule.subscribe("sensors/#", QoS::AtMostOnce).await.unwrap();
+
+    // The publisher's own ID is replaced
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("routing-pub", true).await;
+    publisher
+        .send(&Packet::Publish(Publish {
+            dup: false,
+            qos: QoS::AtMostOnce,
+            retain: false,
+            topic: "sensors/1".to_string(),
+            packet_id: None,
+            payload: Bytes::from_static(b"21.5"),
+            properties: Properties {
+                user_properties: vec![(ROUTING_ID_PROPERTY.to_string(), "forged".to_string())],
+                ..Default::default()
+            },
+        }))
+        .await;
+    let routed = timeout(Duration::from_secs(2), cause.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let id = routing_id(&routed.properties).unwrap().to_string();
+    assert_ne!(id, "forged");
+
+    rule.publish(LocalPublish::new("archive/sensors/1", "21.5").caused_by(&routed))
+        .await
+        .unwrap();
+    match subscriber.recv().await {
+        Some(Packet::Publish(p)) => assert_eq!(routing_id(&p.properties), Some(id.as_str())),
+        other => panic!("Expected the archived message, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+/// Routed messages carry one per-topic number, whoever publishes them
+#[tokio::test]
+async fn test_sequence_numbers() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.sequence = SequenceConfig {
+        enabled: true,
+        topics: vec!["sensors/#".to_string()],
+        ..Default::default()
+    };
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V5).await;
+    subscriber.mqtt_connect("seq-sub", true).await;
+    subscriber.subscribe(1, "#", QoS::AtMostOnce).await;
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("seq-pub", true).await;
+
+    publisher
+        .publish("sensors/1", b"a", QoS::AtMostOnce, false)
+        .await;
+    publisher
+        .publish("other/1", b"b", QoS::AtMostOnce, false)
+        .await;
+    publisher
+        .publish("sensors/1", b"c", QoS::AtMostOnce, false)
+        .await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    broker.publish(
+        "sensors/1".to_string(),
+        Bytes::from_static(b"d"),
+        QoS::AtMostOnce,
+        false,
+    );
+
+    let mut received = Vec::new();
+    while received.len() < 4 {
+        match subscriber.recv().await {
+            Some(Packet::Publish(p)) => {
+                let seq = p
+                    .properties
+                    .user_properties
+                    .iter()
+                    .find(|(name, _)| name == "x-seq")
+                    .map(|(_, value)| value.clone());
+                received.push((p.topic, seq));
+            }
+            other => panic!("Expected PUBLISH, got {:?}", other),
+        }
+    }
+    let seq = |n: &str| Some(n.to_string());
+    assert_eq!(
+        received,
+        [
+            ("sensors/1".to_string(), seq("1")),
+            ("other/1".to_string(), None),
+            ("sensors/1".to_string(), seq("2")),
+            ("sensors/1".to_string(), seq("3")),
+        ]
+    );
+    assert_eq!(broker.sequencer().last("sensors/1"), Some(3));
+
+    broker_handle.abort();
+}
+
+/// A subscriber asks for numbers it missed and gets them from the log,
+/// within the replayable topics and its request budget
+#[tokio::test]
+async fn test_replay_requests() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.sequence = SequenceConfig {
+        enabled: true,
+        replay: ReplayConfig {
+            enabled: true,
+            topics: vec!["sensors/#".to_string()],
+            max_requests: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for payload in [&b"a"[..], b"b", b"c", &[0u8; 256]] {
+        broker.publish(
+            "sensors/1".to_string(),
+            Bytes::copy_from_slice(payload),
+            QoS::AtMostOnce,
+            false,
+        );
+    }
+
+    // Replayed messages respect the client's Maximum Packet Size
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client
+        .send(&Packet::Connect(Box::new(Connect {
+            protocol_version: ProtocolVersion::V5,
+            client_id: "replayer".to_string(),
+            clean_start: true,
+            keep_alive: 60,
+            username: None,
+            password: None,
+            will: None,
+            properties: Properties {
+                maximum_packet_size: Some(128),
+                ..Default::default()
+            },
+        })))
+        .await;
+    match client.recv().await {
+        Some(Packet::ConnAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::Success),
+        other => panic!("Expected CONNACK, got {:?}", other),
+    }
+    let request = |packet_id, body: &str| {
+        Packet::Publish(Publish {
+            qos: QoS::AtLeastOnce,
+            topic: "$replay/request".to_string(),
+            packet_id: Some(packet_id),
+            payload: Bytes::from(body.to_string()),
+            properties: Properties {
+                response_topic: Some("replayer/status".to_string()),
+                correlation_data: Some(Bytes::from_static(b"r1")),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    };
+
+    client
+        .send(&request(1, r#"{"topic": "sensors/1", "from": 2}"#))
+        .await;
+    match client.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::Success),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+    for (payload, seq) in [(b"b", "2"), (b"c", "3")] {
+        match client.recv().await {
+            Some(Packet::Publish(p)) => {
+                assert_eq!(p.topic, "sensors/1");
+                assert_eq!(&p.payload[..], payload);
+                assert_eq!(p.qos, QoS::AtMostOnce);
+                assert!(p
+                    .properties
+                    .user_properties
+                    .contains(&("x-seq".to_string(), seq.to_string())));
+            }
+            other => panic!("Expected replayed PUBLISH, got {:?}", other),
+        }
+    }
+    match client.recv().await {
+        Some(Packet::Publish(p)) => {
+            assert_eq!(p.topic, "replayer/status");
+            assert_eq!(p.properties.correlation_data.as_deref(), Some(&b"r1"[..]));
+            let status: serde_json::Value = serde_json::from_slice(&p.payload).unwrap();
+            assert_eq!(status["replayed"], 2);
+            assert_eq!(status["to"], 4);
+            assert_eq!(status["unavailable"], 0);
+        }
+        other => panic!("Expected status, got {:?}", other),
+    }
+
+    // Outside the replayable topics, then over the request budget
+    client
+        .send(&request(2, r#"{"topic": "other/1", "from": 1}"#))
+        .await;
+    match client.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::NotAuthorized),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+    client
+        .send(&request(3, r#"{"topic": "sensors/1", "from": 1}"#))
+        .await;
+    match client.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::QuotaExceeded),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_admin_api() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker.clone());
+    let admin_handle = tokio::spawn(admin.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let (status, _) = admin_request(admin_addr, "GET", "/api/v1/clients", "wrong", "").await;
+    assert_eq!(status, 401);
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("admin/sub", false).await;
+    client.subscribe(1, "admin/#", QoS::AtLeastOnce).await;
+
+    let (status, page) = admin_request(admin_addr, "GET", "/api/v1/clients", "secret", "").await;
+    assert_eq!(status, 200);
+    let clients = &page["clients"];
+    assert_eq!(clients[0]["client_id"], "admin/sub");
+    assert_eq!(clients[0]["connected"], true);
+    assert_eq!(clients[0]["subscription_count"], 1);
+    assert!(page["next"].is_null());
+
+    // Sessions are listed a page at a time
+    let mut other = TestClient::connect(addr, ProtocolVersion::V5).await;
+    other.mqtt_connect("admin/zzz", true).await;
+    let path = "/api/v1/clients?limit=1";
+    let (status, page) = admin_request(admin_addr, "GET", path, "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(page["clients"].as_array().unwrap().len(), 1);
+    assert_eq!(page["next"], "admin/sub");
+    let path = "/api/v1/clients?limit=1&after=admin%2Fsub";
+    let (status, page) = admin_request(admin_addr, "GET", path, "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(page["clients"][0]["client_id"], "admin/zzz");
+    assert!(page["next"].is_null());
+    let (status, _) =
+        admin_request(admin_addr, "GET", "/api/v1/clients?limit=0", "secret", "").await;
+    assert_eq!(status, 400);
+    drop(other);
+
+    let publish = r#"{"topic": "admin/x", "payload": "hi", "qos": 1, "retain": true}"#;
+    let (status, _) = admin_request(admin_addr, "POST", "/api/v1/publish", "secret", publish).await;
+    assert_eq!(status, 200);
+    match client.recv().await {
+        Some(Packet::Publish(p)) => assert_eq!(&p.payload[..], b"hi"),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+    let bad_topic = r#"{"topic": "admin/#", "payload": "hi"}"#;
+    let (status, _) =
+        admin_request(admin_addr, "POST", "/api/v1/publish", "secret", bad_topic).await;
+    assert_eq!(status, 400);
+
+    // The unacknowledged PUBLISH is in the inflight window
+    let (status, detail) = admin_request(
+        admin_addr,
+        "GET",
+        "/api/v1/clients/admin%2Fsub",
+        "secret",
+        "",
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(detail["subscriptions"][0]["filter"], "admin/#");
+    assert_eq!(detail["inflight"][0]["topic"], "admin/x");
+    assert_eq!(detail["inflight"][0]["awaiting"], "puback");
+
+    let path = "/api/v1/retained?topic=admin%2Fx";
+    assert_eq!(
+        admin_request(admin_addr, "DELETE", path, "secret", "")
+            .await
+            .0,
+        200
+    );
+    assert_eq!(
+        admin_request(admin_addr, "DELETE", path, "secret", "")
+            .await
+            .0,
+        404
+    );
+
+    // Disconnecting keeps the session until asked to discard it
+    let path = "/api/v1/clients/admin%2Fsub";
+    let (status, result) = admin_request(admin_addr, "DELETE", path, "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(result["disconnected"], true);
+    match client.recv().await {
+        Some(Packet::Disconnect(d)) => assert_eq!(d.reason_code, ReasonCode::AdministrativeAction),
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let (_, detail) = admin_request(admin_addr, "GET", path, "secret", "").await;
+    assert_eq!(detail["connected"], false);
+
+    let discard = "/api/v1/clients/admin%2Fsub?discard_session=true";
+    let (_, result) = admin_request(admin_addr, "DELETE", discard, "secret", "").await;
+    assert_eq!(result["session_discarded"], true);
+    assert_eq!(
+        admin_request(admin_addr, "GET", path, "secret", "").await.0,
+        404
+    );
+
+    admin_handle.abort();
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_admin_retained() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker.clone());
+    let admin_handle = tokio::spawn(admin.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let shadows: Vec<_> = (0..5)
+        .map(
+            |i| serde_json::json!({"topic": format!("shadow/dev{}", i), "payload": "{}", "qos": 1}),
+        )
+        .chain([serde_json::json!({"topic": "config/dev0", "payload_base64": "/wA="})])
+        .collect();
+    let body = serde_json::Value::from(shadows).to_string();
+    let (status, result) = admin_request(
+        admin_addr,
+        "POST",
+        "/api/v1/retained/import",
+        "secret",
+        &body,
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(result["imported"], 6);
+    let invalid =
+        r#"[{"topic": "shadow/new", "payload": "{}"}, {"topic": "shadow/+", "payload": "{}"}]"#;
+    let (status, _) = admin_request(
+        admin_addr,
+        "POST",
+        "/api/v1/retained/import",
+        "secret",
+        invalid,
+    )
+    .await;
+    assert_eq!(status, 400);
+    assert_eq!(broker.retained_count(), 6);
+
+    // Imported messages reach new subscriptions
+    let mut client = TestClient::connect(addr, ProtocolVersion::V311).await;
+    client.mqtt_connect("retained-sub", true).await;
+    client.subscribe(1, "config/#", QoS::AtMostOnce).await;
+    match client.recv().await {
+        Some(Packet::Publish(p)) => assert_eq!(&p.payload[..], [0xff, 0x00]),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    // Paged listing
+    let path = "/api/v1/retained?filter=shadow%2F%2B&limit=3";
+    let (status, page) = admin_request(admin_addr, "GET", path, "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(page["messages"].as_array().unwrap().len(), 3);
+    assert_eq!(page["messages"][0]["topic"], "shadow/dev0");
+    assert_eq!(page["next"], "shadow/dev2");
+    let path = "/api/v1/retained?filter=shadow%2F%2B&limit=3&after=shadow%2Fdev2";
+    let (_, page) = admin_request(admin_addr, "GET", path, "secret", "").await;
+    assert_eq!(page["messages"].as_array().unwrap().len(), 2);
+    assert_eq!(page["next"], serde_json::Value::Null);
+    let path = "/api/v1/retained?limit=0";
+    assert_eq!(
+        admin_request(admin_addr, "GET", path, "secret", "").await.0,
+        400
+    );
+
+    // Export, bulk delete, and restore from the export
+    let (_, exported) =
+        admin_request(admin_addr, "GET", "/api/v1/retained/export", "secret", "").await;
+    assert_eq!(exported.as_array().unwrap().len(), 6);
+    let path = "/api/v1/retained?filter=shadow%2F%23";
+    let (_, result) = admin_request(admin_addr, "DELETE", path, "secret", "").await;
+    assert_eq!(result["deleted"], 5);
+    assert_eq!(broker.retained_count(), 1);
+    let (_, result) = admin_request(
+        admin_addr,
+        "POST",
+        "/api/v1/retained/import",
+        "secret",
+        &exported.to_string(),
+    )
+    .await;
+    assert_eq!(result["imported"], 6);
+    let (_, restored) =
+        admin_request(admin_addr, "GET", "/api/v1/retained/export", "secret", "").await;
+    assert_eq!(restored, exported);
+
+    // Seeding from JSON Lines, with a dry run first
+    let seed = "{\"topic\": \"shadow/dev0\", \"payload\": \"off\"}\n\
+                {\"topic\": \"shadow/dev9\", \"payload\": \"off\"}\n";
+    let path = "/api/v1/retained/seed?dry_run=true";
+    let (status, report) = admin_request(admin_addr, "POST", path, "secret", seed).await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        (report["created"].as_u64(), report["updated"].as_u64()),
+        (Some(1), Some(1))
+    );
+    assert_eq!(broker.retained_count(), 6);
+    let (_, report) =
+        admin_request(admin_addr, "POST", "/api/v1/retained/seed", "secret", seed).await;
+    assert_eq!(report["dry_run"], false);
+    assert_eq!(broker.retained_count(), 7);
+    let (status, error) = admin_request(
+        admin_addr,
+        "POST",
+        "/api/v1/retained/seed",
+        "secret",
+        "{\"topic\": \"a\", \"payload\": \"x\"}\nnot json\n",
+    )
+    .await;
+    assert_eq!(status, 400);
+    assert!(error.to_string().contains("line 2"));
+
+    admin_handle.abort();
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_session_handover() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.handover.enabled = true;
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker.clone());
+    let admin_handle = tokio::spawn(admin.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    fn connect_with_token(token: Option<&str>) -> Packet {
+        Packet::Connect(Box::new(Connect {
+            protocol_version: ProtocolVersion::V5,
+            client_id: "worker".to_string(),
+            clean_start: false,
+            keep_alive: 60,
+            username: None,
+            password: None,
+            will: None,
+            properties: Properties {
+                user_properties: token
+                    .map(|t| vec![("handover-token".to_string(), t.to_string())])
+                    .unwrap_or_default(),
+                ..Default::default()
+            },
+        }))
+    }
+    async fn connack(client: &mut TestClient) -> ConnAck {
+        match client.recv().await {
+            Some(Packet::ConnAck(ack)) => ack,
+            other => panic!("Expected CONNACK, got {:?}", other),
+        }
+    }
+
+    // A clean 3.1.1 session asks for a handover and gets its token
+    let mut old = TestClient::connect(addr, ProtocolVersion::V311).await;
+    old.mqtt_connect("worker", true).await;
+    old.subscribe(1, "jobs/#", QoS::AtLeastOnce).await;
+    old.publish("$handover/prepare", b"", QoS::AtLeastOnce, false)
+        .await;
+    assert!(matches!(old.recv().await, Some(Packet::PubAck(_))));
+    let token = match old.recv().await {
+        Some(Packet::Publish(p)) if p.topic == "$handover/token" => {
+            let reply: serde_json::Value = serde_json::from_slice(&p.payload).unwrap();
+            assert_eq!(reply["expires_in"], 60);
+            reply["token"].as_str().unwrap().to_string()
+        }
+        other => panic!("Expected the token, got {:?}", other),
+    };
+    old.send(&Packet::Disconnect(Disconnect::default())).await;
+    drop(old);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The session outlives the connection and queues for the successor
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V311).await;
+    publisher.mqtt_connect("dispatcher", true).await;
+    publisher
+        .publish("jobs/1", b"run", QoS::AtLeastOnce, false)
+        .await;
+    assert!(matches!(publisher.recv().await, Some(Packet::PubAck(_))));
+
+    // Only the token gets in
+    for presented in [None, Some("wrong")] {
+        let mut rival = TestClient::connect(addr, ProtocolVersion::V5).await;
+        rival.send(&connect_with_token(presented)).await;
+        assert_eq!(
+            connack(&mut rival).await.reason_code,
+            ReasonCode::NotAuthorized
+        );
+    }
+    let mut successor = TestClient::connect(addr, ProtocolVersion::V5).await;
+    successor.send(&connect_with_token(Some(&token))).await;
+    let ack = connack(&mut successor).await;
+    assert_eq!(ack.reason_code, ReasonCode::Success);
+    assert!(ack.session_present);
+    match successor.recv().await {
+        Some(Packet::Publish(p)) => assert_eq!(p.topic, "jobs/1"),
+        other => panic!("Expected the queued message, got {:?}", other),
+    }
+
+    // The admin API hands over a session too; cancelling lets anyone in
+    let (status, result) = admin_request(
+        admin_addr,
+        "POST",
+        "/api/v1/clients/worker/handover",
+        "secret",
+        "",
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert!(result["token"].as_str().is_some_and(|t| t != token));
+    let mut rival = TestClient::connect(addr, ProtocolVersion::V5).await;
+    rival.send(&connect_with_token(Some(&token))).await;
+    assert_eq!(
+        connack(&mut rival).await.reason_code,
+        ReasonCode::NotAuthorized
+    );
+    let (status, result) = admin_request(
+        admin_addr,
+        "DELETE",
+        "/api/v1/clients/worker/handover",
+        "secret",
+        "",
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(result["cancelled"], true);
+    let mut rival = TestClient::connect(addr, ProtocolVersion::V5).await;
+    rival.send(&connect_with_token(None)).await;
+    assert_eq!(connack(&mut rival).await.reason_code, ReasonCode::Success);
+
+    let (status, _) = admin_request(
+        admin_addr,
+        "POST",
+        "/api/v1/clients/nobody/handover",
+        "secret",
+        "",
+    )
+    .await;
+    assert_eq!(status, 404);
+
+    admin_handle.abort();
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_admin_ocpp() {
+    use vibemq::config::OcppConfig;
+    use vibemq::ocpp::OcppProvider;
+
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let ocpp = Arc::new(
+        OcppProvider::new(&OcppConfig {
+            enabled: true,
+            ..Default::default()
+        })
+        .unwrap(),
+    );
+    let broker = Arc::new(Broker::with_hooks(config, ocpp.clone()));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker.clone()).with_ocpp(ocpp);
+    let admin_handle = tokio::spawn(admin.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let path = "/api/v1/ocpp/charge_points";
+    let (status, online) = admin_request(admin_addr, "GET", path, "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(online, serde_json::json!([]));
+
+    // A charge point comes online with its first uplink message
+    let mut cp = TestClient::connect(addr, ProtocolVersion::V5).await;
+    cp.mqtt_connect("CP001", true).await;
+    cp.publish(
+        "ocpp/CP001/up",
+        b"[2,\"1\",\"Heartbeat\",{}]",
+        QoS::AtMostOnce,
+        false,
+    )
+    .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let (status, online) = admin_request(admin_addr, "GET", path, "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(online[0]["cp_id"], "CP001");
+    assert_eq!(online[0]["client_id"], "CP001");
+    assert_eq!(online[0]["online"], true);
+
+    let cp_path = "/api/v1/ocpp/charge_points/CP001";
+    let (status, presence) = admin_request(admin_addr, "GET", cp_path, "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(presence["client_id"], "CP001");
+    assert_eq!(presence["online"], true);
+    let unknown = "/api/v1/ocpp/charge_points/CP999";
+    let (status, _) = admin_request(admin_addr, "GET", unknown, "secret", "").await;
+    assert_eq!(status, 404);
+
+    admin_handle.abort();
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_admin_traces() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker.clone());
+    let admin_handle = tokio::spawn(admin.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let (status, _) = admin_request(admin_addr, "POST", "/api/v1/traces", "secret", "{}").await;
+    assert_eq!(status, 400);
+    let start = r#"{"client_id": "traced", "duration": "1m"}"#;
+    let (status, trace) =
+        admin_request(admin_addr, "POST", "/api/v1/traces", "secret", start).await;
+    assert_eq!(status, 200);
+    let id = trace["id"].as_str().unwrap().to_string();
+    assert_eq!(trace["topic"], format!("$SYS/trace/{}", id));
+
+    let mut watcher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    watcher.mqtt_connect("trace-watcher", true).await;
+    watcher
+        .subscribe(1, &format!("$SYS/trace/{}", id), QoS::AtMostOnce)
+        .await;
+
+    // Stream the events over SSE as well
+    let mut sse = TcpStream::connect(admin_addr).await.unwrap();
+    let request = format!(
+        "GET /api/v1/traces/{id}/events HTTP/1.1\r\nHost: admin\r\n\
+         Authorization: Bearer secret\r\nConnection: close\r\n\r\n"
+    );
+    sse.write_all(request.as_bytes()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("traced", true).await;
+    let publish = Packet::Publish(Publish {
+        dup: false,
+        qos: QoS::AtLeastOnce,
+        retain: false,
+        topic: "traced/temp".to_string(),
+        packet_id: Some(3),
+        payload: Bytes::from_static(b"21.5"),
+        properties: Properties::default(),
+    });
+    client.send(&publish).await;
+    assert!(matches!(client.recv().await, Some(Packet::PubAck(_))));
+
+    let mut events = Vec::new();
+    while events.len() < 4 {
+        match watcher.recv().await {
+            Some(Packet::Publish(p)) => {
+                events.push(serde_json::from_slice::<serde_json::Value>(&p.payload).unwrap())
+            }
+            other => panic!("Expected trace event, got {:?}", other),
+        }
+    }
+    let packets: Vec<_> = events
+        .iter()
+        .map(|e| {
+            (
+                e["direction"].as_str().unwrap(),
+                e["packet"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        packets,
+        [
+            ("in", "CONNECT"),
+            ("out", "CONNACK"),
+            ("in", "PUBLISH"),
+            ("out", "PUBACK")
+        ]
+    );
+    assert_eq!(events[2]["client_id"], "traced");
+    assert_eq!(events[2]["topic"], "traced/temp");
+    assert_eq!(events[2]["packet_id"], 3);
+    assert!(events[2]["size"].as_u64().unwrap() > 0);
+
+    let (_, traces) = admin_request(admin_addr, "GET", "/api/v1/traces", "secret", "").await;
+    assert_eq!(traces[0]["client_id"], "traced");
+    let path = format!("/api/v1/traces/{}", id);
+    assert_eq!(
+        admin_request(admin_addr, "DELETE", &path, "secret", "")
+            .await
+            .0,
+        200
+    );
+    assert_eq!(
+        admin_request(admin_addr, "DELETE", &path, "secret", "")
+            .await
+            .0,
+        404
+    );
+
+    // The stream carried the events and ends with the trace
+    let mut response = String::new();
+    timeout(Duration::from_secs(5), sse.read_to_string(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(response.contains("text/event-stream"));
+    assert!(response.contains(r#"data: {"trace":"#));
+    assert!(response.contains(r#""packet":"PUBACK""#));
+
+    admin_handle.abort();
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_schedule_publishes_missed_run() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TestClient::connect(addr, ProtocolVersion::V5).await;
+    client.mqtt_connect("schedule-sub", true).await;
+    client.subscribe(1, "schedule/#", QoS::AtMostOnce).await;
+
+    // Last run two minutes ago: the minute just passed was missed and is
+    // still within the grace, so it publishes right away
+    let schedules = [ScheduleConfig {
+        name: "tick".to_string(),
+        cron: "* * * * *".to_string(),
+        topic: "schedule/{name}".to_string(),
+        payload: "{timestamp}".to_string(),
+        ..Default::default()
+    }];
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let last_run = StoredScheduleRun {
+        last_run_secs: now - 120,
+    };
+    let scheduler = Scheduler::new(broker.clone(), &schedules)
+        .unwrap()
+        .with_last_runs(vec![("tick".to_string(), last_run)]);
+    let scheduler_handle = tokio::spawn(scheduler.run());
+
+    match client.recv().await {
+        Some(Packet::Publish(p)) => {
+            assert_eq!(p.topic, "schedule/tick");
+            let fired_at: u64 = std::str::from_utf8(&p.payload).unwrap().parse().unwrap();
+            assert_eq!(fired_at, now / 60 * 60);
+        }
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    scheduler_handle.abort();
+    broker_handle.abort();
+}
+
+/// An open maintenance window shows in the admin API, relaxes connection
+/// limits and drops wills
+#[tokio::test]
+async fn test_maintenance_window() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    let admin_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let admin = AdminApi::new(admin_addr, "secret", broker.clone());
+    let admin_handle = tokio::spawn(admin.run());
+
+    // Opened every minute for an hour: open now
+    let windows = [MaintenanceConfig {
+        name: "drill".to_string(),
+        cron: "* * * * *".to_string(),
+        reconnect_rate_factor: 5.0,
+        suppress_wills: true,
+        ..Default::default()
+    }];
+    let scheduler = MaintenanceScheduler::new(broker.clone(), &windows).unwrap();
+    let scheduler_handle = tokio::spawn(scheduler.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let (status, body) =
+        admin_request(admin_addr, "GET", "/api/v1/maintenance", "secret", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["active"][0], "drill");
+    assert_eq!(body["windows"][0]["active"], true);
+    assert!(body["windows"][0]["ends_at"].as_u64().is_some());
+    assert_eq!(broker.limiters().reconnect_boost(), 5.0);
+
+    let mut subscriber = TestClient::connect(addr, ProtocolVersion::V311).await;
+    subscriber.mqtt_connect("will-sub", true).await;
+    subscriber
+        .subscribe(1, "client/status", QoS::AtMostOnce)
+        .await;
+    let mut will_client = TestClient::connect(addr, ProtocolVersion::V311).await;
+    will_client
+        .send(&Packet::Connect(Box::new(Connect {
+            protocol_version: ProtocolVersion::V311,
+            client_id: "will-client".to_string(),
+            clean_start: true,
+            keep_alive: 60,
+            username: None,
+            password: None,
+            will: Some(Will {
+                topic: "client/status".to_string(),
+                payload: Bytes::from_static(b"offline"),
+                qos: QoS::AtMostOnce,
+                retain: false,
+                properties: Properties::default(),
+            }),
+            properties: Properties::default(),
+        })))
+        .await;
+    let _ = will_client.recv().await; // CONNACK
+    drop(will_client);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The ping is answered with no will ahead of it
+    subscriber.send(&Packet::PingReq).await;
+    assert!(matches!(subscriber.recv().await, Some(Packet::PingResp)));
+
+    scheduler_handle.abort();
+    admin_handle.abort();
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_aggregate_window() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let aggregation = Aggregation::new(&AggregateConfig {
+        name: "avg".to_string(),
+        filter: "sensors/+/temp".to_string(),
+        key_level: Some(1),
+        field: Some("celsius".to_string()),
+        function: AggregateFunction::Avg,
+        window: Duration::from_secs(1),
+        output: "analytics/{key}/temp".to_string(),
+        alert: Some(AggregateAlertConfig {
+            above: Some(21.0),
+            topic: Some("alerts/{key}".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+    .unwrap();
+    let aggregate_handle = tokio::spawn(aggregation.run(broker.clone()));
+
+    let mut sub = TestClient::connect(addr, ProtocolVersion::V5).await;
+    sub.mqtt_connect("aggregate-sub", true).await;
+    sub.subscribe(1, "analytics/#", QoS::AtMostOnce).await;
+    sub.subscribe(2, "alerts/#", QoS::AtMostOnce).await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("aggregate-pub", true).await;
+
+    // Let the window's subscription settle, then publish right after a
+    // boundary so both values land in the same window
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    tokio::time::sleep(Duration::from_millis(1050 - now_ms % 1000)).await;
+
+    for value in [20, 23] {
+        let payload = format!(r#"{{"celsius": {}}}"#, value);
+        publisher
+            .publish(
+                "sensors/dev1/temp",
+                payload.as_bytes(),
+                QoS::AtMostOnce,
+                false,
+            )
+            .await;
+    }
+
+    let publish = match timeout(Duration::from_secs(3), sub.recv()).await {
+        Ok(Some(Packet::Publish(p))) => p,
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    };
+    assert_eq!(publish.topic, "analytics/dev1/temp");
+    let result: serde_json::Value = serde_json::from_slice(&publish.payload).unwrap();
+    assert_eq!(result["key"], "dev1");
+    assert_eq!(result["function"], "avg");
+    assert_eq!(result["value"], 21.5);
+    assert_eq!(result["count"], 2);
+
+    // 21.5 is above the alert threshold
+    let publish = match timeout(Duration::from_secs(3), sub.recv()).await {
+        Ok(Some(Packet::Publish(p))) => p,
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    };
+    assert_eq!(publish.topic, "alerts/dev1");
+    let alert: serde_json::Value = serde_json::from_slice(&publish.payload).unwrap();
+    assert_eq!(alert["state"], "raised");
+    assert_eq!(alert["reason"], "above");
+    assert_eq!(alert["limit"], 21.0);
+
+    aggregate_handle.abort();
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_reload_listeners() {
+    let (kept, removed, added) = (next_port(), next_port(), next_port());
+    let mut config = test_config(kept);
+    config.extra_bind_addrs = vec![SocketAddr::from(([127, 0, 0, 1], removed))];
+    let kept_addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config.clone()));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut sub = TestClient::connect(kept_addr, ProtocolVersion::V5).await;
+    sub.mqtt_connect("reload-sub", true).await;
+    sub.subscribe(1, "reload/#", QoS::AtMostOnce).await;
+    let removed_addr = SocketAddr::from(([127, 0, 0, 1], removed));
+    let mut dropped = TestClient::connect(removed_addr, ProtocolVersion::V5).await;
+    dropped.mqtt_connect("reload-dropped", true).await;
+
+    config.extra_bind_addrs = vec![SocketAddr::from(([127, 0, 0, 1], added))];
+    let changes = broker.apply_config(config);
+    assert_eq!(changes.started, [format!("tcp://127.0.0.1:{}", added)]);
+    assert_eq!(changes.stopped, [format!("tcp://127.0.0.1:{}", removed)]);
+    assert!(changes.errors.is_empty());
+
+    // Only the removed listener's connection closes
+    assert!(matches!(
+        timeout(Duration::from_secs(2), dropped.recv()).await,
+        Ok(None)
+    ));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(TcpStream::connect(removed_addr).await.is_err());
+
+    let mut publisher = TestClient::connect(
+        SocketAddr::from(([127, 0, 0, 1], added)),
+        ProtocolVersion::V5,
+    )
+    .await;
+    publisher.mqtt_connect("reload-pub", true).await;
+    publisher
+        .publish("reload/x", b"still here", QoS::AtMostOnce, false)
+        .await;
+    match sub.recv().await {
+        Some(Packet::Publish(p)) => assert_eq!(&p.payload[..], b"still here"),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_drain_waits_for_inflight() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.shutdown = ShutdownConfig {
+        drain_timeout: Duration::from_secs(5),
+        disconnect: true,
+        server_reference: Some("backup.example.com:1883".to_string()),
+    };
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut sub = TestClient::connect(addr, ProtocolVersion::V5).await;
+    sub.mqtt_connect("drain-sub", true).await;
+    sub.subscribe(1, "drain/#", QoS::AtLeastOnce).await;
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("drain-pub", true).await;
+    publisher
+        .publish("drain/x", b"inflight", QoS::AtLeastOnce, false)
+        .await;
+
+    // Leave the QoS 1 delivery unacknowledged
+    let packet_id = match sub.recv().await {
+        Some(Packet::Publish(p)) => p.packet_id.unwrap(),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    };
+
+    let drainer = broker.clone();
+    let drain = tokio::spawn(async move { drainer.drain().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(broker.is_draining());
+    assert!(TcpStream::connect(addr).await.is_err());
+    assert!(!drain.is_finished());
+
+    sub.send(&Packet::PubAck(PubAck::new(packet_id))).await;
+    match timeout(Duration::from_secs(2), sub.recv()).await {
+        Ok(Some(Packet::Disconnect(d))) => {
+            assert_eq!(d.reason_code, ReasonCode::ServerShuttingDown);
+            assert_eq!(
+                d.properties.server_reference.as_deref(),
+                Some("backup.example.com:1883")
+            );
+        }
+        other => panic!("Expected DISCONNECT, got {:?}", other),
+    }
+    timeout(Duration::from_secs(2), drain)
+        .await
+        .unwrap()
+        .unwrap();
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_geofence_events() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let geofence = Geofence::new(&GeofenceConfig {
+        name: "depot".to_string(),
+        filter: "fleet/+/position".to_string(),
+        key_level: Some(1),
+        polygon: vec![
+            [52.52, 13.40],
+            [52.52, 13.42],
+            [52.53, 13.42],
+            [52.53, 13.40],
+        ],
+        output: "geofence/{name}/{key}".to_string(),
+        ..Default::default()
+    })
+    .unwrap();
+    let geofence_handle = tokio::spawn(geofence.run(broker.clone()));
+
+    let mut sub = TestClient::connect(addr, ProtocolVersion::V5).await;
+    sub.mqtt_connect("geofence-sub", true).await;
+    sub.subscribe(1, "geofence/#", QoS::AtMostOnce).await;
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("geofence-pub", true).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Outside, inside twice, then outside: one entry and one exit
+    for (lat, lon) in [
+        (52.50, 13.41),
+        (52.525, 13.41),
+        (52.526, 13.41),
+        (52.54, 13.41),
+    ] {
+        let payload = format!(r#"{{"lat": {}, "lon": {}}}"#, lat, lon);
+        publisher
+            .publish(
+                "fleet/truck7/position",
+                payload.as_bytes(),
+                QoS::AtMostOnce,
+                false,
+            )
+            .await;
+    }
+
+    for (event, lat) in [("enter", 52.525), ("exit", 52.54)] {
+        let publish = match timeout(Duration::from_secs(2), sub.recv()).await {
+            Ok(Some(Packet::Publish(p))) => p,
+            other => panic!("Expected PUBLISH, got {:?}", other),
+        };
+        assert_eq!(publish.topic, "geofence/depot/truck7");
+        let payload: serde_json::Value = serde_json::from_slice(&publish.payload).unwrap();
+        assert_eq!(payload["event"], event);
+        assert_eq!(payload["key"], "truck7");
+        assert_eq!(payload["lat"], lat);
+    }
+    assert!(timeout(Duration::from_millis(200), sub.recv())
+        .await
+        .is_err());
+
+    geofence_handle.abort();
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_enrich_from_lookup_table() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sites.csv");
+    std::fs::write(&path, "device,site\ndev1,berlin\n").unwrap();
+    let enrichment = Enrichment::new(&EnrichConfig {
+        name: "sites".to_string(),
+        filter: "sensors/+/temp".to_string(),
+        key_level: Some(1),
+        table: LookupTableConfig {
+            path: Some(path.clone()),
+            key_column: "device".to_string(),
+            ttl: Duration::from_millis(50),
+            ..Default::default()
+        },
+        into: Some("meta".to_string()),
+        output: "enriched/{topic}".to_string(),
+        ..Default::default()
+    })
+    .unwrap();
+    let enrich_handle = tokio::spawn(enrichment.run(broker.clone()));
+
+    let mut sub = TestClient::connect(addr, ProtocolVersion::V5).await;
+    sub.mqtt_connect("enrich-sub", true).await;
+    sub.subscribe(1, "enriched/#", QoS::AtMostOnce).await;
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("enrich-pub", true).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    async fn enriched(
+        publisher: &mut TestClient,
+        sub: &mut TestClient,
+        device: &str,
+    ) -> serde_json::Value {
+        let topic = format!("sensors/{}/temp", device);
+        publisher
+            .publish(&topic, br#"{"temp": 21.5}"#, QoS::AtMostOnce, false)
+            .await;
+        let publish = match timeout(Duration::from_secs(2), sub.recv()).await {
+            Ok(Some(Packet::Publish(p))) => p,
+            other => panic!("Expected PUBLISH, got {:?}", other),
+        };
+        assert_eq!(publish.topic, format!("enriched/{}", topic));
+        serde_json::from_slice(&publish.payload).unwrap()
+    }
+
+    let payload = enriched(&mut publisher, &mut sub, "dev1").await;
+    assert_eq!(payload["temp"], 21.5);
+    assert_eq!(payload["meta"]["site"], "berlin");
+    // Unknown keys are forwarded as published
+    let payload = enriched(&mut publisher, &mut sub, "dev2").await;
+    assert!(payload.get("meta").is_none());
+
+    // A changed table applies without restarting the rule
+    std::fs::write(&path, "device,site\ndev1,munich\ndev2,hamburg\n").unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let payload = enriched(&mut publisher, &mut sub, "dev2").await;
+    assert_eq!(payload["meta"]["site"], "hamburg");
+
+    enrich_handle.abort();
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_rule_actions_fail_independently() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let metrics = Arc::new(vibemq::Metrics::new());
+    let mut broker = Broker::new(config);
+    broker.set_metrics(metrics.clone());
+    let broker = Arc::new(broker);
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // A webhook that accepts everything, sending the bodies it gets
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let webhook = format!("http://{}/ingest", listener.local_addr().unwrap());
+    let (bodies_tx, mut bodies) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            while !buf.ends_with(b"}") {
+                let mut chunk = [0u8; 4096];
+                match stream.read(&mut chunk).await.unwrap() {
+                    0 => break,
+                    n => buf.extend_from_slice(&chunk[..n]),
+                }
+            }
+            let _ = stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await;
+            let request = String::from_utf8_lossy(&buf).to_string();
+            let body = request.split("\r\n\r\n").nth(1).unwrap_or("").to_string();
+            let _ = bodies_tx.send(body);
+        }
+    });
+    // And one nobody listens on
+    let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let broken = format!("http://{}/ingest", closed.local_addr().unwrap());
+    drop(closed);
+
+    let rule = Rule::new(&RuleConfig {
+        name: "telemetry".to_string(),
+        filter: "sensors/#".to_string(),
+        actions: vec![
+            RuleActionConfig {
+                kind: ActionKind::Webhook,
+                url: Some(broken),
+                retries: 1,
+                retry_backoff: Duration::from_millis(10),
+                dead_letter: Some("dlq/{name}/{action}".to_string()),
+                ..Default::default()
+            },
+            RuleActionConfig {
+                topic: Some("archive/{topic}".to_string()),
+                ..Default::default()
+            },
+            RuleActionConfig {
+                kind: ActionKind::Webhook,
+                url: Some(webhook),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    })
+    .unwrap();
+    let rule_handle = tokio::spawn(rule.run(broker.clone()));
+
+    let mut sub = TestClient::connect(addr, ProtocolVersion::V5).await;
+    sub.mqtt_connect("rule-sub", true).await;
+    sub.subscribe(1, "archive/#", QoS::AtMostOnce).await;
+    sub.subscribe(2, "dlq/#", QoS::AtLeastOnce).await;
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("rule-pub", true).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    publisher
+        .publish("sensors/1/temp", b"21.5", QoS::AtMostOnce, false)
+        .await;
+
+    // The republish isn't held up by the failing webhook's retries
+    let archived = match timeout(Duration::from_secs(2), sub.recv()).await {
+        Ok(Some(Packet::Publish(p))) => p,
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    };
+    assert_eq!(archived.topic, "archive/sensors/1/temp");
+    assert_eq!(&archived.payload[..], b"21.5");
+
+    let body = timeout(Duration::from_secs(2), bodies.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["rule"], "telemetry");
+    assert_eq!(body["topic"], "sensors/1/temp");
+    assert_eq!(body["payload"], "21.5");
+
+    // The failing webhook's message ends up on its dead-letter topic
+    let dead = match timeout(Duration::from_secs(2), sub.recv()).await {
+        Ok(Some(Packet::Publish(p))) => p,
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    };
+    assert_eq!(dead.topic, "dlq/telemetry/webhook");
+    let dead: serde_json::Value = serde_json::from_slice(&dead.payload).unwrap();
+    assert_eq!(dead["action"], "webhook");
+    assert_eq!(dead["attempts"], 2);
+    assert_eq!(dead["topic"], "sensors/1/temp");
+    assert_eq!(dead["payload"], "21.5");
+
+    let metrics = String::from_utf8(metrics.encode_text().unwrap()).unwrap();
+    assert!(metrics.contains(
+        "vibemq_rule_actions_total{action=\"webhook\",result=\"dead_lettered\",rule=\"telemetry\"} 1"
+    ));
+    assert!(metrics.contains(
+        "vibemq_rule_actions_total{action=\"republish\",result=\"success\",rule=\"telemetry\"} 1"
+    ));
+
+    rule_handle.abort();
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_puback_waits_for_ack_action() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let broker = Arc::new(Broker::new(config));
+    let runner = broker.clone();
+    let broker_handle = tokio::spawn(async move {
+        let _ = runner.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // A backend that fails "bad" messages and stores others when released
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/topics/orders", listener.local_addr().unwrap());
+    let release = Arc::new(tokio::sync::Notify::new());
+    let backend_release = release.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            while !buf.ends_with(b"}") {
+                let mut chunk = [0u8; 4096];
+                match stream.read(&mut chunk).await.unwrap() {
+                    0 => break,
+                    n => buf.extend_from_slice(&chunk[..n]),
+                }
+            }
+            let reply: &[u8] = if String::from_utf8_lossy(&buf).contains("\"payload\":\"bad\"") {
+                b"HTTP/1.1 500 Internal Server Error\r\n\r\n"
+            } else {
+                backend_release.notified().await;
+                b"HTTP/1.1 204 No Content\r\n\r\n"
+            };
+            let _ = stream.write_all(reply).await;
+        }
+    });
+
+    let rule = Rule::new(&RuleConfig {
+        name: "orders".to_string(),
+        filter: "orders/#".to_string(),
+        actions: vec![RuleActionConfig {
+            name: Some("kafka".to_string()),
+            kind: ActionKind::Webhook,
+            url: Some(url),
+            retries: 0,
+            ..Default::default()
+        }],
+        ack_action: Some("kafka".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+    let rule_handle = tokio::spawn(rule.run(broker.clone()));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("ack-pub", true).await;
+    // Held until the backend has the message
+    publisher
+        .publish("orders/1", b"ok", QoS::AtLeastOnce, false)
+        .await;
+    assert!(
+        timeout(Duration::from_millis(300), publisher.recv())
+            .await
+            .is_err(),
+        "PUBACK sent before the backend confirmed"
+    );
+    release.notify_one();
+    let ack = publisher.recv_puback().await;
+    assert_eq!(ack.packet_id, 1);
+    assert_eq!(ack.reason_code, ReasonCode::Success);
+
+    // Rejected when the backend fails it, so the client resends
+    publisher
+        .publish("orders/2", b"bad", QoS::AtLeastOnce, false)
+        .await;
+    assert_eq!(
+        publisher.recv_puback().await.reason_code,
+        ReasonCode::ImplementationError
+    );
+
+    // Other topics are acknowledged right away
+    publisher
+        .publish("sensors/1", b"ok", QoS::AtLeastOnce, false)
+        .await;
+    assert_eq!(
+        publisher.recv_puback().await.reason_code,
+        ReasonCode::Success
+    );
+
+    rule_handle.abort();
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_mqtt_over_quic() {
+    use tokio_rustls::rustls;
+
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let cert_path = dir.path().join("cert.pem");
+    let key_path = dir.path().join("key.pem");
+    std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+    std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+
+    let port = next_port();
+    let quic_addr = SocketAddr::from(([127, 0, 0, 1], next_port()));
+    let mut config = test_config(port);
+    config.quic_bind_addr = Some(quic_addr);
+    config.tls_config = Some(TlsConfig {
+        cert_path: cert_path.display().to_string(),
+        key_path: key_path.display().to_string(),
+        ca_cert_path: None,
+        require_client_cert: false,
+        handshake_threads: 0,
+        handshake_queue_size: 0,
+        handshake_timeout: Duration::from_secs(10),
+    });
+    let addr = config.bind_addr;
+    let broker = Broker::new(config);
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client_config = |alpn: &[u8]| {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let mut tls = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        tls.alpn_protocols = vec![alpn.to_vec()];
+        quinn::ClientConfig::new(Arc::new(
+            quinn::crypto::rustls::QuicClientConfig::try_from(tls).unwrap(),
+        ))
+    };
+    let endpoint = quinn::Endpoint::client(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+
+    // The QUIC client subscribes, a TCP client publishes
+    let connection = endpoint
+        .connect_with(client_config(b"mqtt"), quic_addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+    let (send, recv) = connection.open_bi().await.unwrap();
+    let mut sub = TestClient::over(QuicStream::new(send, recv), ProtocolVersion::V5);
+    let connack = sub.mqtt_connect("quic-sub", true).await;
+    assert_eq!(connack.reason_code, ReasonCode::Success);
+    sub.subscribe(1, "quic/#", QoS::AtMostOnce).await;
+
+    let mut publisher = TestClient::connect(addr, ProtocolVersion::V5).await;
+    publisher.mqtt_connect("quic-pub", true).await;
+    publisher
+        .publish("quic/test", b"over quic", QoS::AtMostOnce, false)
+        .await;
+    match sub.recv().await {
+        Some(Packet::Publish(publish)) => {
+            assert_eq!(publish.topic, "quic/test");
+            assert_eq!(&publish.payload[..], b"over quic");
+        }
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    // Clients must ask for one of the offered ALPN protocols
+    let refused = endpoint
+        .connect_with(client_config(b"h3"), quic_addr, "localhost")
+        .unwrap()
+        .await;
+    assert!(refused.is_err());
+
+    broker_handle.abort();
+}
+
+/// Embedder hooks scoping topics by tenant: "<tenant>-<name>" clients
+/// publish under "<tenant>/" and only receive their tenant's messages
+struct TenantRewriter;
+
+fn tenant(client_id: &str) -> &str {
+    client_id.split('-').next().unwrap_or_default()
+}
+
+#[async_trait::async_trait]
+impl Hooks for TenantRewriter {
+    async fn on_publish(
+        &self,
+        client_id: &str,
+        _username: Option<&str>,
+        publish: &mut Publish,
+    ) -> HookResult<bool> {
+        if &publish.payload[..] == b"reject" {
+            return Ok(false);
+        }
+        publish.topic = format!("{}/{}", tenant(client_id), publish.topic);
+        // Not settled with the client; undone by the broker
+        publish.qos = QoS::AtMostOnce;
+        Ok(true)
+    }
+
+    async fn on_deliver(&self, client_id: &str, publish: &Publish) -> HookResult<bool> {
+        Ok(publish
+            .topic
+            .starts_with(&format!("{}/", tenant(client_id))))
+    }
+}
+
+#[tokio::test]
+async fn test_hooks_intercept_publish_and_delivery() {
+    let port = next_port();
+    let config = test_config(port);
+    let addr = config.bind_addr;
+    let hooks = CompositeHooks::new().with(TenantRewriter);
+    let broker = Broker::with_hooks(config, Arc::new(hooks));
+    let broker_handle = tokio::spawn(async move {
+        let _ = broker.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut acme_sub = TestClient::connect(addr, ProtocolVersion::V5).await;
+    acme_sub.mqtt_connect("acme-sub", true).await;
+    acme_sub.subscribe(1, "#", QoS::AtLeastOnce).await;
+    let mut globex_sub = TestClient::connect(addr, ProtocolVersion::V5).await;
+    globex_sub.mqtt_connect("globex-sub", true).await;
+    globex_sub.subscribe(1, "#", QoS::AtLeastOnce).await;
+    let mut acme_pub = TestClient::connect(addr, ProtocolVersion::V5).await;
+    acme_pub.mqtt_connect("acme-pub", true).await;
+
+    acme_pub
+        .publish("sensors/1", b"21.5", QoS::AtLeastOnce, true)
+        .await;
+    match acme_pub.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::Success),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+    match acme_sub.recv().await {
+        Some(Packet::Publish(publish)) => {
+            assert_eq!(publish.topic, "acme/sensors/1");
+            assert_eq!(publish.qos, QoS::AtLeastOnce);
+        }
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    // Rejected by the hook, like a denied publish
+    acme_pub
+        .publish("sensors/1", b"reject", QoS::AtLeastOnce, false)
+        .await;
+    match acme_pub.recv().await {
+        Some(Packet::PubAck(ack)) => assert_eq!(ack.reason_code, ReasonCode::NotAuthorized),
+        other => panic!("Expected PUBACK, got {:?}", other),
+    }
+
+    // globex-sub skipped acme's message: its first one is its tenant's
+    let mut globex_pub = TestClient::connect(addr, ProtocolVersion::V5).await;
+    globex_pub.mqtt_connect("globex-pub", true).await;
+    globex_pub
+        .publish("sensors/9", b"3", QoS::AtMostOnce, false)
+        .await;
+    match globex_sub.recv().await {
+        Some(Packet::Publish(publish)) => assert_eq!(publish.topic, "globex/sensors/9"),
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    // The message was retained under its rewritten topic, and retained
+    // messages pass on_deliver too
+    let mut late = TestClient::connect(addr, ProtocolVersion::V5).await;
+    late.mqtt_connect("acme-late", true).await;
+    late.subscribe(1, "+/sensors/1", QoS::AtMostOnce).await;
+    match late.recv().await {
+        Some(Packet::Publish(publish)) => {
+            assert_eq!(publish.topic, "acme/sensors/1");
+            assert!(publish.retain);
+        }
+        other => panic!("Expected retained PUBLISH, got {:?}", other),
+    }
+    let mut other_late = TestClient::connect(addr, ProtocolVersion::V5).await;
+    other_late.mqtt_connect("globex-late", true).await;
+    other_late
+        .subscribe(1, "+/sensors/1", QoS::AtMostOnce)
+        .await;
+    globex_pub
+        .publish("sensors/1", b"4", QoS::AtMostOnce, false)
+        .await;
+    match other_late.recv().await {
+        Some(Packet::Publish(publish)) => {
+            assert_eq!(publish.topic, "globex/sensors/1");
+            assert!(!publish.retain);
+        }
+        other => panic!("Expected PUBLISH, got {:?}", other),
+    }
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_selftest() {
+    use vibemq::selftest::{Outcome, Report, SelfTest};
+
+    fn outcome(report: &Report, name: &str) -> Option<Outcome> {
+        report
+            .checks
+            .iter()
+            .find(|check| check.name == name)
+            .map(|check| check.outcome.clone())
+    }
+
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let cert_path = dir.path().join("cert.pem");
+    let key_path = dir.path().join("key.pem");
+    std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+    std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+
+    // The self-test moves the listeners to ports of its own
+    let mut config = test_config(0);
+    config.tls_bind_addr = Some(SocketAddr::from(([127, 0, 0, 1], 0)));
+    config.tls_config = Some(TlsConfig {
+        cert_path: cert_path.display().to_string(),
+        key_path: key_path.display().to_string(),
+        ca_cert_path: None,
+        require_client_cert: false,
+        handshake_threads: 0,
+        handshake_queue_size: 0,
+        handshake_timeout: Duration::from_secs(10),
+    });
+    config.max_qos = QoS::AtLeastOnce;
+    let hooks: Arc<dyn Hooks> = Arc::new(AuthProvider::new(&AuthConfig {
+        enabled: true,
+        users: vec![UserConfig {
+            username: "deployer".to_string(),
+            password: Some("secret".to_string()),
+            password_hash: None,
+            role: None,
+            tenant: None,
+        }],
+        ..Default::default()
+    }));
+
+    let report = SelfTest::new(config.clone(), hooks.clone())
+        .with_auth_enabled(true)
+        .with_credentials(Some("deployer".to_string()), Some("secret".to_string()))
+        .run()
+        .await
+        .unwrap();
+    assert!(report.passed(), "{}", report);
+    assert_eq!(outcome(&report, "TLS handshake"), Some(Outcome::Passed));
+    assert_eq!(
+        outcome(&report, "reject unknown credentials"),
+        Some(Outcome::Passed)
+    );
+    assert!(matches!(
+        outcome(&report, "publish/subscribe (QoS 2)"),
+        Some(Outcome::Skipped(_))
+    ));
+
+    // Without valid credentials the connecting checks fail
+    let report = SelfTest::new(config, hooks)
+        .with_auth_enabled(true)
+        .with_credentials(Some("deployer".to_string()), Some("wrong".to_string()))
+        .run()
+        .await
+        .unwrap();
+    assert!(!report.passed());
+    assert!(matches!(
+        outcome(&report, "connect (MQTT 5.0)"),
+        Some(Outcome::Failed(_))
+    ));
+}
@@ -22,7 +22,14 @@ use tokio::net::TcpStream;
 use tokio::time::timeout;
 
 use vibemq::broker::{Broker, BrokerConfig};
-use vibemq::config::ProxyProtocolConfig;
+use vibemq::config::{
+    AuthMetadataField, BacklogDrainConfig, BatchConfig, DelayedConfig, ErrorDetail, HandoverConfig,
+    HealthConfig, ListenerCapabilities, MemoryPressureConfig, PriorityConfig, ProxyProtocolConfig,
+    PublishRateConfig, PublisherBackpressureConfig, QueueOverflow, QuotaConfig,
+    RetainedCacheConfig, RetainedFeedConfig, SequenceConfig, SessionExpiryEventsConfig,
+    SharedSubscriptionStrategy, ShutdownConfig, TopicSchemaConfig, TopicTreeConfig,
+    TransactionConfig,
+};
 use vibemq::protocol::QoS;
 
 static PORT_COUNTER: AtomicU16 = AtomicU16::new(21000);
@@ -34,34 +41,86 @@ pub fn next_port() -> u16 {
 pub fn test_config(port: u16) -> BrokerConfig {
     BrokerConfig {
         bind_addr: SocketAddr::from(([127, 0, 0, 1], port)),
+        extra_bind_addrs: Vec::new(),
+        ipv6_only: None,
         tls_bind_addr: None,
         tls_config: None,
         ws_bind_addr: None,
+        wss_bind_addr: None,
+        quic_bind_addr: None,
+        quic: Default::default(),
         ws_path: "/mqtt".to_string(),
+        ws_max_frame_size: None,
+        unix_bind_path: None,
         max_connections: 100,
         max_packet_size: 1024, // Small size for testing limits
         default_keep_alive: 60,
         max_keep_alive: 300,
         session_expiry_check_interval: Duration::from_secs(60),
+        session_compress_idle_after: None,
+        max_session_expiry: None,
         receive_maximum: 65535,
         max_qos: QoS::ExactlyOnce,
         retain_available: true,
         wildcard_subscription_available: true,
         subscription_identifiers_available: true,
         shared_subscriptions_available: true,
+        shared_subscription_strategy: SharedSubscriptionStrategy::default(),
         max_topic_alias: 65535,
         num_workers: 2,
         sys_topics_enabled: false,
         sys_topics_interval: Duration::from_secs(10),
+        max_local_hops: 8,
+        routing_ids: false,
+        bridge_compression: Vec::new(),
         max_inflight: 32,
         max_queued_messages: 1000,
+        max_queued_bytes: 0,
+        queue_qos0: true,
+        queue_overflow: QueueOverflow::default(),
         max_awaiting_rel: 100,
         retry_interval: Duration::from_secs(30),
+        ack_alarm_after: None,
         outbound_channel_capacity: 1024,
         max_topic_levels: 0,
+        hibernate_after: None,
         proxy_protocol: ProxyProtocolConfig::default(),
         tls_proxy_protocol: ProxyProtocolConfig::default(),
         ws_proxy_protocol: ProxyProtocolConfig::default(),
+        unix_proxy_protocol: ProxyProtocolConfig::default(),
+        allow_mqtt31: true,
+        tls_allow_mqtt31: true,
+        ws_allow_mqtt31: true,
+        listener_max_qos: None,
+        tls_listener_max_qos: None,
+        ws_listener_max_qos: None,
+        capabilities: ListenerCapabilities::default(),
+        tls_capabilities: ListenerCapabilities::default(),
+        ws_capabilities: ListenerCapabilities::default(),
+        error_detail: ErrorDetail::default(),
+        tls_error_detail: ErrorDetail::default(),
+        ws_error_detail: ErrorDetail::default(),
+        reason_map: Default::default(),
+        batch: BatchConfig::default(),
+        transaction: TransactionConfig::default(),
+        handover: HandoverConfig::default(),
+        retained_feed: RetainedFeedConfig::default(),
+        retained_cache: RetainedCacheConfig::default(),
+        delayed: DelayedConfig::default(),
+        session_expiry_events: SessionExpiryEventsConfig::default(),
+        backlog_drain: BacklogDrainConfig::default(),
+        health: HealthConfig::default(),
+        topic_tree: TopicTreeConfig::default(),
+        topic_schema: TopicSchemaConfig::default(),
+        sequence: SequenceConfig::default(),
+        priority: PriorityConfig::default(),
+        publish_rate: PublishRateConfig::default(),
+        quota: QuotaConfig::default(),
+        memory_pressure: MemoryPressureConfig::default(),
+        publisher_backpressure: PublisherBackpressureConfig::default(),
+        listener_limits: Default::default(),
+        auth_metadata_fields: AuthMetadataField::ALL.to_vec(),
+        shutdown: ShutdownConfig::default(),
     }
 }
 
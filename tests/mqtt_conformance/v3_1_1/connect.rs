@@ -121,6 +121,61 @@ async fn test_mqtt_3_1_2_2_unsupported_protocol_version() {
     broker_handle.abort();
 }
 
+// ============================================================================
+// Legacy MQTT 3.1 ("MQIsdp", level 3)
+// ============================================================================
+
+const CONNECT_MQTT31: [u8; 17] = [
+    0x10, 0x0F, 0x00, 0x06, b'M', b'Q', b'I', b's', b'd', b'p', 0x03, // MQIsdp level 3
+    0x00, 0x00, 0x3C, 0x00, 0x01, b'a', // CleanSession=0, client "a"
+];
+
+#[tokio::test]
+async fn test_mqtt31_rejected_when_disabled() {
+    let port = next_port();
+    let mut config = test_config(port);
+    config.allow_mqtt31 = false;
+    let broker_handle = start_broker(config).await;
+
+    let mut client = RawClient::connect(SocketAddr::from(([127, 0, 0, 1], port))).await;
+    client.send_raw(&CONNECT_MQTT31).await;
+
+    let response = client.recv_raw(1000).await.expect("Should receive CONNACK");
+    assert_eq!(response[0], 0x20, "Should receive CONNACK");
+    assert_eq!(response[3], 0x01, "Unacceptable protocol version");
+
+    broker_handle.abort();
+}
+
+#[tokio::test]
+async fn test_mqtt31_accepted_by_default() {
+    let port = next_port();
+    let config = test_config(port);
+    let broker_handle = start_broker(config).await;
+
+    // Persistent session: reconnect must not set the (reserved) session present bit
+    for _ in 0..2 {
+        let mut client = RawClient::connect(SocketAddr::from(([127, 0, 0, 1], port))).await;
+        client.send_raw(&CONNECT_MQTT31).await;
+
+        let response = client.recv_raw(1000).await.expect("Should receive CONNACK");
+        assert_eq!(response[..4], [0x20, 0x02, 0x00, 0x00]);
+
+        // PINGREQ works on the v3.1.1 session logic
+        client.send_raw(&[0xC0, 0x00]).await;
+        let response = client
+            .recv_raw(1000)
+            .await
+            .expect("Should receive PINGRESP");
+        assert_eq!(response[..2], [0xD0, 0x00]);
+
+        client.send_raw(&[0xE0, 0x00]).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    broker_handle.abort();
+}
+
 // ============================================================================
 // [MQTT-3.1.2-3] Reserved Flag Must Be Zero
 // ============================================================================
@@ -1337,3 +1392,25 @@ async fn test_mqtt_3_1_4_3_clean_session_processing() {
 
     broker_handle.abort();
 }
+
+#[tokio::test]
+async fn test_mqtt31_client_id_limit() {
+    let port = next_port();
+    let config = test_config(port);
+    let broker_handle = start_broker(config).await;
+
+    // MQTT 3.1 client IDs are 1 to 23 characters; longer ones get CONNACK 0x02
+    let client_id = [b'x'; 24];
+    let mut connect = vec![
+        0x10, 0x26, 0x00, 0x06, b'M', b'Q', b'I', b's', b'd', b'p', 0x03, // MQIsdp level 3
+        0x02, 0x00, 0x3C, 0x00, 0x18, // CleanSession=1, 24-byte client ID
+    ];
+    connect.extend_from_slice(&client_id);
+    let mut client = RawClient::connect(SocketAddr::from(([127, 0, 0, 1], port))).await;
+    client.send_raw(&connect).await;
+
+    let response = client.recv_raw(1000).await.expect("Should receive CONNACK");
+    assert_eq!(response[..4], [0x20, 0x02, 0x00, 0x02]);
+
+    broker_handle.abort();
+}